@@ -1,4 +1,4 @@
-use x_parser::{Symbol, FileId, Span, span::ByteOffset};
+use x_parser::{Symbol, FileId, Span, span::CharOffset};
 use x_parser::ast::*;
 use x_editor::incremental::IncrementalAnalyzer;
 
@@ -6,7 +6,7 @@ fn main() {
     println!("=== Incremental Type Checking Test ===\n");
 
     let file_id = FileId::new(0);
-    let span = Span::new(file_id, ByteOffset::new(0), ByteOffset::new(1));
+    let span = Span::new(file_id, CharOffset::new(0), CharOffset::new(1));
     
     // Create initial AST manually
     println!("1. Creating initial AST:");
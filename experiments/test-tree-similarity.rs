@@ -1,4 +1,4 @@
-use x_parser::{Symbol, FileId, Span, span::ByteOffset};
+use x_parser::{Symbol, FileId, Span, span::CharOffset};
 use x_parser::ast::*;
 use x_editor::content_addressing::{ContentRepository, Version};
 use x_editor::tree_similarity::{TreeNode, APTED, TSED, CombinedSimilarity};
@@ -8,7 +8,7 @@ fn main() {
     println!("=== Tree Similarity Test (APTED/TSED) ===\n");
 
     let file_id = FileId::new(0);
-    let span = Span::new(file_id, ByteOffset::new(0), ByteOffset::new(1));
+    let span = Span::new(file_id, CharOffset::new(0), CharOffset::new(1));
     
     // Create repository
     let mut repo = ContentRepository::new();
@@ -5,10 +5,11 @@
 
 use anyhow::Result;
 use std::collections::{HashMap, HashSet};
-use x_parser::{Symbol, Span, FileId, span::ByteOffset};
+use x_parser::{Symbol, Span, FileId, span::CharOffset};
 use x_parser::ast::*;
 use x_checker::types::{Type as CheckerType, TypeScheme};
 use crate::intent::{CodeIntent, IntentTarget};
+use crate::retrieval::RetrievedDefinition;
 
 /// Code generation context
 #[derive(Debug, Clone)]
@@ -31,6 +32,9 @@ pub struct CodeGenContext {
     /// Generated items in the current session
     pub generated_items: Vec<GeneratedItem>,
     
+    /// Existing definitions retrieved from the codebase for the intent
+    pub retrieved: Vec<RetrievedDefinition>,
+    
     /// User preferences and constraints
     pub preferences: UserPreferences,
 }
@@ -152,10 +156,21 @@ impl CodeGenContext {
             imports: HashSet::new(),
             function_context: None,
             generated_items: Vec::new(),
+            retrieved: Vec::new(),
             preferences: UserPreferences::default(),
         }
     }
     
+    /// Make a retrieved definition available to the generated code
+    pub fn add_retrieved(&mut self, definition: RetrievedDefinition) {
+        let module = definition.module_path.as_ref().map(|path| {
+            let segments = path.split('.').map(Symbol::intern).collect();
+            ModulePath::new(segments, Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(0)))
+        });
+        self.symbols.add_function(definition.name, definition.type_scheme.clone(), module, Visibility::Public);
+        self.retrieved.push(definition);
+    }
+    
     /// Add a generated item to the context
     pub fn add_generated_item(&mut self, item: GeneratedItem) {
         // Update symbol table
@@ -163,7 +178,7 @@ impl CodeGenContext {
             GeneratedItemKind::Function => {
                 if let Item::ValueDef(def) = &item.ast {
                     let module_path = self.current_module.as_ref()
-                        .map(|name| ModulePath::single(*name, Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(1))));
+                        .map(|name| ModulePath::single(*name, Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(1))));
                     self.symbols.add_function(
                         def.name,
                         None, // Type will be inferred
@@ -183,7 +198,7 @@ impl CodeGenContext {
                         },
                         def.type_params.iter().map(|p| p.name).collect(),
                         self.current_module.as_ref()
-                            .map(|name| ModulePath::single(*name, Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(1)))),
+                            .map(|name| ModulePath::single(*name, Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(1)))),
                     );
                 }
             }
@@ -3,7 +3,7 @@
 //! This module transforms structured intents into x Language AST.
 
 use anyhow::{Result, bail};
-use x_parser::{Symbol, Span, FileId, span::ByteOffset};
+use x_parser::{Symbol, Span, FileId, span::CharOffset};
 use x_parser::ast::*;
 use crate::{
     intent::*,
@@ -47,7 +47,7 @@ impl CodeGenerator {
     }
     
     fn span(&self) -> Span {
-        Span::new(self.file_id, ByteOffset::new(0), ByteOffset::new(1))
+        Span::new(self.file_id, CharOffset::new(0), CharOffset::new(1))
     }
     
     /// Generate code from intent and context
@@ -82,6 +82,7 @@ impl CodeGenerator {
             confidence: self.calculate_confidence(intent, context),
             alternatives,
             explanation: self.generate_explanation(intent),
+            validation_trace: Vec::new(),
         };
         
         Ok(GeneratedCode {
@@ -221,7 +222,7 @@ impl CodeGenerator {
                 Box::new(Expr::Var(Symbol::intern("<="), span)),
                 vec![
                     Expr::Var(Symbol::intern("n"), span),
-                    Expr::Literal(Literal::Integer(0), span),
+                    Expr::Literal(Literal::int(0), span),
                 ],
                 span,
             )),
@@ -233,7 +234,7 @@ impl CodeGenerator {
                         Box::new(Expr::Var(Symbol::intern("-"), span)),
                         vec![
                             Expr::Var(Symbol::intern("n"), span),
-                            Expr::Literal(Literal::Integer(1), span),
+                            Expr::Literal(Literal::int(1), span),
                         ],
                         span,
                     ),
@@ -270,7 +271,7 @@ impl CodeGenerator {
                 Box::new(Expr::Var(Symbol::intern("fact_aux"), span)),
                 vec![
                     Expr::Var(Symbol::intern(n), span),
-                    Expr::Literal(Literal::Integer(1), span),
+                    Expr::Literal(Literal::int(1), span),
                 ],
                 span,
             )),
@@ -292,6 +293,7 @@ impl CodeGenerator {
                     .map(|f| Constructor {
                         name: Symbol::intern(&f.name),
                         fields: Vec::new(),
+                        result: None,
                         span: self.span(),
                     })
                     .collect();
@@ -616,7 +618,7 @@ impl CodeGenerator {
                     Box::new(Expr::Var(Symbol::intern("<="), span)),
                     vec![
                         Expr::Var(Symbol::intern("n"), span),
-                        Expr::Literal(Literal::Integer(0), span),
+                        Expr::Literal(Literal::int(0), span),
                     ],
                     span,
                 )),
@@ -628,7 +630,7 @@ impl CodeGenerator {
                             Box::new(Expr::Var(Symbol::intern("-"), span)),
                             vec![
                                 Expr::Var(Symbol::intern("n"), span),
-                                Expr::Literal(Literal::Integer(1), span),
+                                Expr::Literal(Literal::int(1), span),
                             ],
                             span,
                         ),
@@ -668,8 +670,8 @@ impl CodeGenerator {
                         Box::new(Expr::Var(Symbol::intern("fib_aux"), span)),
                         vec![
                             Expr::Var(Symbol::intern("n"), span),
-                            Expr::Literal(Literal::Integer(0), span),
-                            Expr::Literal(Literal::Integer(1), span),
+                            Expr::Literal(Literal::int(0), span),
+                            Expr::Literal(Literal::int(1), span),
                         ],
                         span,
                     )),
@@ -686,7 +688,7 @@ impl CodeGenerator {
                         Box::new(Expr::Var(Symbol::intern("<="), span)),
                         vec![
                             Expr::Var(Symbol::intern("n"), span),
-                            Expr::Literal(Literal::Integer(1), span),
+                            Expr::Literal(Literal::int(1), span),
                         ],
                         span,
                     )),
@@ -700,7 +702,7 @@ impl CodeGenerator {
                                     Box::new(Expr::Var(Symbol::intern("-"), span)),
                                     vec![
                                         Expr::Var(Symbol::intern("n"), span),
-                                        Expr::Literal(Literal::Integer(1), span),
+                                        Expr::Literal(Literal::int(1), span),
                                     ],
                                     span,
                                 )],
@@ -712,7 +714,7 @@ impl CodeGenerator {
                                     Box::new(Expr::Var(Symbol::intern("-"), span)),
                                     vec![
                                         Expr::Var(Symbol::intern("n"), span),
-                                        Expr::Literal(Literal::Integer(2), span),
+                                        Expr::Literal(Literal::int(2), span),
                                     ],
                                     span,
                                 )],
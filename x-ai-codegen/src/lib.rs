@@ -9,6 +9,7 @@ pub mod generator;
 pub mod refiner;
 pub mod validator;
 pub mod session;
+pub mod retrieval;
 
 pub use intent::*;
 pub use context::*;
@@ -16,8 +17,12 @@ pub use generator::*;
 pub use refiner::*;
 pub use validator::*;
 pub use session::*;
+pub use retrieval::*;
 
 use x_parser::ast::*;
+use x_parser::{Symbol, Span, VisitSpans, span::CharOffset};
+use x_editor::{AstEditor, EditOperation, EditSession};
+use x_editor::operations::EditableNode;
 use anyhow::Result;
 
 /// Main AI code generation interface
@@ -59,7 +64,10 @@ impl AICodeGenerator {
             initial_code
         };
         
-        // 6. Update session
+        // 6. Repair until the code parses and type checks
+        let refined_code = self.validator.refine(refined_code, |code, attempt| self.refiner.repair(code, attempt))?;
+        
+        // 7. Update session
         self.session.add_generated_code(&refined_code);
         
         Ok(refined_code)
@@ -92,6 +100,41 @@ impl AICodeGenerator {
         }
     }
     
+    /// Structural edits carrying out `instruction` on the session's module
+    ///
+    /// The instruction is applied to a copy of the module and each changed
+    /// item becomes an operation, targeting items by id so the operations
+    /// stay valid when applied in order. The edits must apply cleanly to the
+    /// current AST without adding type errors, so
+    /// `XLanguageEditor::apply_batch` can apply them as one unit; an
+    /// instruction that cannot be carried out that way is an error.
+    pub fn propose_edits(&self, session: &EditSession, instruction: &str) -> Result<Vec<EditOperation>> {
+        let intent = self.parse_refinement_intent(instruction)?;
+        let code = GeneratedCode {
+            ast: session.ast.clone(),
+            metadata: GenerationMetadata {
+                intent: CodeIntent {
+                    action: IntentAction::Modify,
+                    target: IntentTarget::Module { name: session.ast.module.name.to_string(), exports: Vec::new() },
+                    constraints: Vec::new(),
+                    examples: Vec::new(),
+                },
+                confidence: 1.0,
+                alternatives: Vec::new(),
+                explanation: String::new(),
+                validation_trace: Vec::new(),
+            },
+            suggestions: None,
+            validation: None,
+        };
+        let context = self.session.build_refinement_context(&code, &intent)?;
+        let edited = self.refiner.apply_feedback(code, &intent, &context)?;
+        
+        let operations = item_edits(session, &edited.ast)?;
+        edits_check(session, &operations)?;
+        Ok(operations)
+    }
+    
     /// Get completion suggestions for partial code
     pub async fn get_completions(&self, partial_code: &str) -> Result<Vec<CompletionSuggestion>> {
         let context = self.session.current_context();
@@ -109,6 +152,95 @@ impl AICodeGenerator {
     }
 }
 
+/// Operations turning the session's items into those of `edited`
+///
+/// Items are paired by the name they define, so reordering or inserting
+/// items does not turn into edits of their neighbours. A definition that
+/// is gone pairs with a newly named one in its place and is treated as
+/// renamed: the new item is inserted before its uses are updated and the
+/// old one is only deleted afterwards, so the module checks after every
+/// step. Spans are ignored when comparing, since an edited module may
+/// have been parsed from different text.
+fn item_edits(session: &EditSession, edited: &CompilationUnit) -> Result<Vec<EditOperation>> {
+    let old = &session.ast.module.items;
+    let new = &edited.module.items;
+    let mut partner: Vec<Option<usize>> = old.iter()
+        .map(|item| defined_name(item).and_then(|name| new.iter().position(|n| defined_name(n) == Some(name))))
+        .collect();
+    let paired: Vec<usize> = partner.iter().flatten().copied().collect();
+    let mut unpaired = (0..new.len()).filter(|j| !paired.contains(j));
+    for slot in partner.iter_mut().filter(|slot| slot.is_none()) {
+        *slot = unpaired.next();
+    }
+    let appended: Vec<usize> = unpaired.collect();
+
+    let id = |index: usize| session.node_id(&[0, index])
+        .ok_or_else(|| anyhow::anyhow!("Item {index} has no node id"));
+    let mut renamed = Vec::new();
+    let mut replaced = Vec::new();
+    let mut deleted = Vec::new();
+    for (index, (old_item, new_index)) in old.iter().zip(&partner).enumerate() {
+        let Some(new_item) = new_index.map(|j| &new[j]) else {
+            deleted.push(EditOperation::delete(id(index)?));
+            continue;
+        };
+        if same_item(old_item, new_item) {
+            continue;
+        }
+        if defined_name(old_item) != defined_name(new_item) {
+            renamed.push(EditOperation::insert(id(index)?, EditableNode::Item(new_item.clone())));
+            deleted.push(EditOperation::delete(id(index)?));
+        } else {
+            replaced.push(EditOperation::replace(id(index)?, EditableNode::Item(new_item.clone())));
+        }
+    }
+    let kept = old.len() - partner.iter().filter(|slot| slot.is_none()).count();
+    let mut operations: Vec<_> = renamed.into_iter().chain(replaced).chain(deleted).collect();
+    for (offset, j) in appended.into_iter().enumerate() {
+        operations.push(EditOperation::insert(vec![0, kept + offset], EditableNode::Item(new[j].clone())));
+    }
+    Ok(operations)
+}
+
+/// Whether two items are the same apart from their spans
+fn same_item(a: &Item, b: &Item) -> bool {
+    let unlocated = |item: &Item| {
+        let mut item = item.clone();
+        item.visit_spans(&mut |span| *span = Span::new(span.file_id, CharOffset::new(0), CharOffset::new(0)));
+        item
+    };
+    unlocated(a) == unlocated(b)
+}
+
+fn defined_name(item: &Item) -> Option<Symbol> {
+    match item {
+        Item::ValueDef(def) => Some(def.name),
+        Item::TypeDef(def) => Some(def.name),
+        _ => None,
+    }
+}
+
+/// Check that `operations` apply in order to the session's AST without
+/// any step adding type errors
+fn edits_check(session: &EditSession, operations: &[EditOperation]) -> Result<()> {
+    use x_checker::checker::TypeCheck;
+    
+    let baseline = session.ast.type_check().errors.len();
+    let mut ids = session.node_ids().clone();
+    let mut ast = session.ast.clone();
+    let mut editor = AstEditor::new();
+    for (step, operation) in operations.iter().enumerate() {
+        let resolved = ids.resolve_operation(operation)?;
+        let mut result = editor.apply_operation(&mut ast, resolved)?;
+        ids.record(&mut result);
+        let errors = ast.type_check().errors.len();
+        if errors > baseline {
+            anyhow::bail!("Edit {} of {} adds {} type error(s)", step + 1, operations.len(), errors - baseline);
+        }
+    }
+    Ok(())
+}
+
 /// Generated code with metadata
 #[derive(Debug, Clone)]
 pub struct GeneratedCode {
@@ -125,6 +257,8 @@ pub struct GenerationMetadata {
     pub confidence: f64,
     pub alternatives: Vec<AlternativeCode>,
     pub explanation: String,
+    /// Candidates the repair loop checked, in order
+    pub validation_trace: Vec<ValidationAttempt>,
 }
 
 /// Alternative code generation
@@ -183,4 +317,54 @@ impl Default for AICodeGenerator {
     fn default() -> Self {
         Self::new()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x_editor::{LanguageServiceConfig, XLanguageEditor};
+
+    #[test]
+    fn test_proposed_edits_apply_as_a_batch() {
+        let mut editor = XLanguageEditor::new(LanguageServiceConfig::default());
+        let session_id = editor.start_session("module Main\nlet double = fun x -> x * 2\nlet four = double 2").unwrap();
+        let generator = AICodeGenerator::new();
+
+        let session = editor.get_session(session_id).unwrap();
+        let edits = generator.propose_edits(session, "rename function double to twice").unwrap();
+        assert!(matches!(
+            edits.as_slice(),
+            [EditOperation::Insert(_), EditOperation::Replace(_), EditOperation::Delete(_)]
+        ));
+        editor.apply_batch(session_id, edits).unwrap();
+        let names: Vec<_> = editor.get_session(session_id).unwrap().ast.module.items.iter()
+            .filter_map(|item| match item {
+                Item::ValueDef(def) => Some(def.name.as_str().to_string()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(names, ["twice", "four"]);
+
+        // Adding a parameter would break `four`, so nothing is proposed
+        let session = editor.get_session(session_id).unwrap();
+        let error = generator.propose_edits(session, "add parameter y to function twice").unwrap_err();
+        assert!(error.to_string().contains("type error"), "{error}");
+    }
+
+    #[test]
+    fn test_item_edits_pair_items_by_name() {
+        let mut editor = XLanguageEditor::new(LanguageServiceConfig::default());
+        let session_id = editor.start_session("module Main\nlet one = 1\nlet two = 2").unwrap();
+        let session = editor.get_session(session_id).unwrap();
+
+        // A new first item is an insertion, not an edit of every item after it
+        let edited = x_parser::parse_source(
+            "module Main\nlet zero = 0\nlet one = 1\nlet two = 2",
+            x_parser::FileId::new(0),
+            x_parser::SyntaxStyle::SExpression,
+        ).unwrap();
+        let operations = item_edits(session, &edited).unwrap();
+        assert!(matches!(operations.as_slice(), [EditOperation::Insert(_)]), "{operations:?}");
+        edits_check(session, &operations).unwrap();
+    }
+}
@@ -2,17 +2,82 @@
 //! 
 //! This module handles code refinement based on validation results and user feedback.
 
-use anyhow::{Result, bail};
+use anyhow::{Result, anyhow, bail};
 use std::collections::HashMap;
+use std::fmt;
 use x_parser::ast::*;
-use x_parser::{Symbol, Span, FileId, span::ByteOffset};
+use x_parser::{Symbol, Span, FileId, span::CharOffset, parse_source, SyntaxStyle};
+use x_parser::syntax::{ocaml::OCamlPrinter, SyntaxConfig, SyntaxPrinter};
+use x_editor::rename::{is_valid_identifier, rename_symbol, replace_references, SymbolIndex};
+use x_editor::rewrite::RewriteRule;
 use crate::{
     GeneratedCode, ValidationResult,
     intent::{RefinementIntent, RefinementAction},
     context::CodeGenContext,
-    validator::{ValidationError, ErrorKind},
+    validator::{ValidationAttempt, ValidationError, ErrorKind},
 };
 
+/// Idioms the next edition writes more simply, as rewrite rules
+const NEXT_EDITION_IDIOMS: &[(&str, &str)] = &[
+    ("if $c then true else false", "$c"),
+    ("if $c then false else true", "not $c"),
+];
+
+/// Language edition a source is written for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Edition {
+    /// The edition the compiler implements today
+    #[default]
+    Current,
+    /// The upcoming edition, without deprecated values and redundant idioms
+    Next,
+}
+
+impl fmt::Display for Edition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Edition::Current => write!(f, "current"),
+            Edition::Next => write!(f, "next"),
+        }
+    }
+}
+
+impl std::str::FromStr for Edition {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "current" => Ok(Edition::Current),
+            "next" => Ok(Edition::Next),
+            _ => bail!("Unknown edition: {s} (expected `current` or `next`)"),
+        }
+    }
+}
+
+/// A compilation unit rewritten into the idioms of an edition
+#[derive(Debug, Clone)]
+pub struct Modernization {
+    pub unit: CompilationUnit,
+    /// One line per change, or per change that was left to the reviewer
+    pub notes: Vec<String>,
+}
+
+/// Deprecated values of `unit` whose `@deprecated` attribute names a
+/// replacement in backticks, such as ``@deprecated use `repeat` ``
+pub fn deprecated_replacements(unit: &CompilationUnit) -> HashMap<Symbol, Symbol> {
+    unit.module.items.iter()
+        .filter_map(|item| match item {
+            Item::ValueDef(def) => Some((def.name, def.documentation.as_ref()?.doc_comment.deprecation()?.note?)),
+            _ => None,
+        })
+        .filter_map(|(name, note)| {
+            let replacement = note.split('`').skip(1).step_by(2)
+                .find(|candidate| is_valid_identifier(candidate) && *candidate != name.as_str())?;
+            Some((name, Symbol::intern(replacement)))
+        })
+        .collect()
+}
+
 /// Code refiner
 pub struct CodeRefiner {
     file_id: FileId,
@@ -39,7 +104,7 @@ impl CodeRefiner {
     }
     
     fn span(&self) -> Span {
-        Span::new(self.file_id, ByteOffset::new(0), ByteOffset::new(1))
+        Span::new(self.file_id, CharOffset::new(0), CharOffset::new(1))
     }
     
     /// Refine code based on validation results
@@ -61,6 +126,16 @@ impl CodeRefiner {
         Ok(code)
     }
     
+    /// A new candidate for the repair loop, fixing what `attempt` found
+    pub fn repair(&self, code: &GeneratedCode, attempt: &ValidationAttempt) -> Result<GeneratedCode> {
+        let validation = ValidationResult {
+            errors: attempt.errors.clone(),
+            warnings: Vec::new(),
+            suggestions: Vec::new(),
+        };
+        self.refine(code.clone(), &validation)
+    }
+    
     /// Apply feedback from user
     pub fn apply_feedback(
         &self,
@@ -98,6 +173,48 @@ impl CodeRefiner {
         Ok(code)
     }
     
+    /// Rewrite `ast`, parsed from `source`, into the idioms of `edition`
+    ///
+    /// Uses of the deprecated values in `replacements`, usually gathered from
+    /// a whole project with [`deprecated_replacements`], are pointed at their
+    /// replacements. A replacement that a local binding would capture is left
+    /// alone and noted for the reviewer.
+    pub fn modernize(
+        &self,
+        ast: &CompilationUnit,
+        source: &str,
+        edition: Edition,
+        replacements: &HashMap<Symbol, Symbol>,
+    ) -> Result<Modernization> {
+        let mut unit = ast.clone();
+        let mut notes = Vec::new();
+        if edition == Edition::Current {
+            return Ok(Modernization { unit, notes });
+        }
+        
+        let mut deprecated: Vec<_> = replacements.iter().collect();
+        deprecated.sort_by_key(|(old, _)| old.as_str());
+        for (old, new) in deprecated {
+            match replace_references(&mut unit, source, *old, new.as_str()) {
+                Ok(locations) if locations.is_empty() => {}
+                Ok(locations) => notes.push(format!(
+                    "replaced {} use(s) of deprecated `{old}` with `{new}`", locations.len(),
+                )),
+                Err(error) => notes.push(format!("kept deprecated `{old}`: {error}")),
+            }
+        }
+        
+        for (pattern, replacement) in NEXT_EDITION_IDIOMS {
+            let rule = RewriteRule::parse(pattern, replacement)
+                .map_err(|error| anyhow!("Invalid idiom `{pattern}`: {error}"))?;
+            for site in rule.apply(&mut unit) {
+                notes.push(format!("{}: rewrote `{pattern}` to `{replacement}`", site.item));
+            }
+        }
+        
+        Ok(Modernization { unit, notes })
+    }
+    
     /// Fix a specific error
     fn fix_error(
         &self,
@@ -223,36 +340,47 @@ impl CodeRefiner {
     }
     
     /// Rename an item
+    ///
+    /// Values are renamed with `x_editor::rename_symbol`, which follows
+    /// scopes: locals shadowing the name keep it, and a new name that would
+    /// capture or be captured is refused. It locates names in source text,
+    /// so the module is printed and parsed back first.
     fn rename_item(
         &self,
         ast: &CompilationUnit,
         target: &Option<String>,
         details: &str,
     ) -> Result<CompilationUnit> {
-        let mut module = ast.module.clone();
-        
-        if let Some(old_name) = target {
-            let new_name = self.extract_new_name(details)?;
-            let new_symbol = Symbol::intern(&new_name);
-            
-            // Rename in definitions
-            for item in &mut module.items {
-                match item {
-                    Item::ValueDef(def) if def.name.as_str() == old_name => {
-                        def.name = new_symbol;
-                    }
-                    Item::TypeDef(def) if def.name.as_str() == old_name => {
-                        def.name = new_symbol;
-                    }
-                    _ => {}
+        let Some(old_name) = target else { return Ok(ast.clone()) };
+        let new_name = self.extract_new_name(details)?;
+        let mut unit = ast.clone();
+        for item in &mut unit.module.items {
+            if let Item::TypeDef(def) = item {
+                if def.name.as_str() == old_name {
+                    def.name = Symbol::intern(&new_name);
                 }
             }
         }
-        
-        Ok(CompilationUnit {
-            module,
-            span: ast.span,
-        })
+        if !unit.module.items.iter().any(|item| matches!(item, Item::ValueDef(def) if def.name.as_str() == old_name)) {
+            return Ok(unit);
+        }
+
+        let source = OCamlPrinter::new().print(&unit, &SyntaxConfig::default())?;
+        let mut printed = parse_source(&source, unit.span.file_id, SyntaxStyle::SExpression)?;
+        let definition = printed.module.items.iter()
+            .find_map(|item| match item {
+                Item::ValueDef(def) if def.name.as_str() == old_name => Some(def.span),
+                _ => None,
+            })
+            .and_then(|span| {
+                SymbolIndex::build(&printed, &source).occurrences().iter()
+                    .find(|occ| occ.is_definition && occ.name.as_str() == old_name && span.contains(occ.span.start))
+                    .map(|occ| occ.span.start)
+            })
+            .ok_or_else(|| anyhow!("Cannot find the definition of {old_name}"))?;
+        rename_symbol(&mut printed, &source, definition, &new_name)
+            .map_err(|error| anyhow!("Cannot rename {old_name} to {new_name}: {error}"))?;
+        Ok(printed)
     }
     
     /// Add a case to pattern match
@@ -446,8 +574,8 @@ impl CodeRefiner {
     fn infer_type_annotation(&self, expr: &Expr) -> Option<Type> {
         match expr {
             Expr::Literal(lit, span) => match lit {
-                Literal::Integer(_) => Some(Type::Con(Symbol::intern("Int"), *span)),
-                Literal::Float(_) => Some(Type::Con(Symbol::intern("Float"), *span)),
+                Literal::Integer(..) => Some(Type::Con(Symbol::intern("Int"), *span)),
+                Literal::Float(..) => Some(Type::Con(Symbol::intern("Float"), *span)),
                 Literal::String(_) => Some(Type::Con(Symbol::intern("String"), *span)),
                 Literal::Bool(_) => Some(Type::Con(Symbol::intern("Bool"), *span)),
                 Literal::Unit => Some(Type::Con(Symbol::intern("Unit"), *span)),
@@ -501,7 +629,7 @@ impl TransformationRules {
             ErrorFix {
                 error_kind: ErrorKind::UndefinedSymbol,
                 fix: |file_id, error, ast| {
-                    let span = Span::new(file_id, ByteOffset::new(0), ByteOffset::new(1));
+                    let span = Span::new(file_id, CharOffset::new(0), CharOffset::new(1));
                     let mut module = ast.module.clone();
                     
                     // Add import or definition for undefined symbol
@@ -558,7 +686,7 @@ impl TransformationRules {
             ErrorFix {
                 error_kind: ErrorKind::RecursionWithoutBase,
                 fix: |file_id, error, ast| {
-                    let span = Span::new(file_id, ByteOffset::new(0), ByteOffset::new(1));
+                    let span = Span::new(file_id, CharOffset::new(0), CharOffset::new(1));
                     let mut module = ast.module.clone();
                     
                     // Find the recursive function and add a base case
@@ -584,4 +712,54 @@ impl TransformationRules {
             },
         ]
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_modernize_for_next_edition() {
+        let source = "module Test\n```\n---\n@deprecated use `repeat`\n---\n```\nlet replicate = fun n x -> x\n\
+            let repeat = fun n x -> x\n\
+            let copies = replicate 3 1\n\
+            let positive = fun n -> if n > 0 then true else false\n";
+        let unit = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let replacements = deprecated_replacements(&unit);
+        assert_eq!(replacements, HashMap::from([(Symbol::intern("replicate"), Symbol::intern("repeat"))]));
+
+        let refiner = CodeRefiner::new();
+        let current = refiner.modernize(&unit, source, Edition::Current, &replacements).unwrap();
+        assert!(current.notes.is_empty());
+
+        let next = refiner.modernize(&unit, source, Edition::Next, &replacements).unwrap();
+        assert_eq!(next.notes, vec![
+            "replaced 1 use(s) of deprecated `replicate` with `repeat`".to_string(),
+            "positive: rewrote `if $c then true else false` to `$c`".to_string(),
+        ]);
+        let printed = OCamlPrinter::new().print(&next.unit, &SyntaxConfig::default()).unwrap();
+        assert!(printed.contains("let replicate = fun n x -> x"), "{printed}");
+        assert!(printed.contains("let copies = repeat 3 1"), "{printed}");
+        assert!(printed.contains("let positive = fun n -> n > 0"), "{printed}");
+    }
+
+    #[test]
+    fn test_rename_item_follows_scopes() {
+        let source = "module Main\nlet double = fun x -> x * 2\nlet four = double 2\nlet shadow = fun double -> double 1";
+        let unit = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let refiner = CodeRefiner::new();
+        let target = Some("double".to_string());
+
+        let renamed = refiner.rename_item(&unit, &target, "rename double to twice").unwrap();
+        let printed = OCamlPrinter::new().print(&renamed, &SyntaxConfig::default()).unwrap();
+        assert!(printed.contains("let twice = fun x -> x * 2"), "{printed}");
+        assert!(printed.contains("let four = twice 2"), "{printed}");
+        // The parameter shadowing `double` is a different binding
+        assert!(printed.contains("let shadow = fun double -> double 1"), "{printed}");
+
+        // A new name a local would capture is refused
+        let source = "module Main\nlet double = fun x -> x * 2\nlet apply = fun twice -> double twice";
+        let unit = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        assert!(refiner.rename_item(&unit, &target, "rename double to twice").is_err());
+    }
+}
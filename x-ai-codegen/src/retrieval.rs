@@ -0,0 +1,274 @@
+//! Retrieval of existing definitions for the generation context
+//!
+//! Definitions in a content-addressed codebase are embedded as sparse
+//! feature vectors over the words of their names and the type
+//! constructors and arity of their signatures. An intent is embedded the
+//! same way, from its target's name, description and parameter and return
+//! types, and the definitions closest to it by cosine similarity are handed
+//! to generation.
+
+use std::collections::HashMap;
+use x_checker::types::{Type as CheckerType, TypeScheme};
+use x_editor::content_addressing::{ContentHash, ContentItem, ContentRepository};
+use x_parser::ast::ValueDef;
+use x_parser::Symbol;
+use crate::intent::{CodeIntent, IntentTarget};
+
+/// Sparse feature vector
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Embedding {
+    features: HashMap<String, f64>,
+}
+
+impl Embedding {
+    fn add(&mut self, feature: String, weight: f64) {
+        *self.features.entry(feature).or_insert(0.0) += weight;
+    }
+
+    /// Features for the words of an identifier or phrase
+    fn add_words(&mut self, text: &str) {
+        for word in words(text) {
+            self.add(format!("word:{word}"), 1.0);
+        }
+    }
+
+    /// Features for the constructors in a type, written or inferred
+    fn add_type_names(&mut self, names: impl IntoIterator<Item = String>) {
+        for name in names {
+            self.add(format!("type:{}", name.to_lowercase()), 1.0);
+        }
+    }
+
+    pub fn cosine(&self, other: &Embedding) -> f64 {
+        let dot: f64 = self.features.iter()
+            .filter_map(|(feature, weight)| other.features.get(feature).map(|other| weight * other))
+            .sum();
+        let norm = |embedding: &Embedding| embedding.features.values().map(|w| w * w).sum::<f64>().sqrt();
+        let norms = norm(self) * norm(other);
+        if norms == 0.0 { 0.0 } else { dot / norms }
+    }
+}
+
+/// An existing definition retrieved for an intent
+#[derive(Debug, Clone)]
+pub struct RetrievedDefinition {
+    pub hash: ContentHash,
+    pub name: Symbol,
+    pub module_path: Option<String>,
+    pub type_scheme: Option<TypeScheme>,
+    pub ast: ValueDef,
+    /// Similarity to the intent, between 0 and 1
+    pub score: f64,
+}
+
+/// Embedded function definitions of a codebase
+#[derive(Debug, Clone)]
+pub struct DefinitionIndex {
+    definitions: Vec<(RetrievedDefinition, Embedding)>,
+    /// How many definitions `retrieve` returns at most
+    top_k: usize,
+}
+
+impl DefinitionIndex {
+    /// Embed the function definitions of `repository`
+    pub fn from_repository(repository: &ContentRepository) -> Self {
+        let mut definitions: Vec<_> = repository.entries.values()
+            .filter_map(|entry| {
+                let ContentItem::Function { ast, type_scheme, .. } = &entry.content else { return None };
+                let definition = RetrievedDefinition {
+                    hash: entry.hash.clone(),
+                    name: Symbol::intern(&entry.name),
+                    module_path: entry.module_path.clone(),
+                    type_scheme: type_scheme.clone(),
+                    ast: ast.clone(),
+                    score: 0.0,
+                };
+                let embedding = embed_definition(&entry.name, type_scheme.as_ref());
+                Some((definition, embedding))
+            })
+            .collect();
+        // Ties are broken by hash, so retrieval does not depend on map order
+        definitions.sort_by(|(a, _), (b, _)| a.hash.0.cmp(&b.hash.0));
+        Self { definitions, top_k: 5 }
+    }
+
+    /// Return at most `top_k` definitions from `retrieve`
+    pub fn with_top_k(mut self, top_k: usize) -> Self {
+        self.top_k = top_k;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.definitions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.definitions.is_empty()
+    }
+
+    /// The definitions most similar to `intent`, best first; unrelated
+    /// definitions are never returned
+    pub fn retrieve(&self, intent: &CodeIntent) -> Vec<RetrievedDefinition> {
+        self.nearest(&embed_intent(intent))
+    }
+
+    /// The definitions nearest to `query`, best first
+    pub fn nearest(&self, query: &Embedding) -> Vec<RetrievedDefinition> {
+        let mut scored: Vec<_> = self.definitions.iter()
+            .map(|(definition, embedding)| (definition, query.cosine(embedding)))
+            .filter(|(_, score)| *score > 0.0)
+            .collect();
+        scored.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        scored.into_iter()
+            .take(self.top_k)
+            .map(|(definition, score)| RetrievedDefinition { score, ..definition.clone() })
+            .collect()
+    }
+}
+
+/// Embed a definition by its name and type
+pub fn embed_definition(name: &str, type_scheme: Option<&TypeScheme>) -> Embedding {
+    let mut embedding = Embedding::default();
+    embedding.add_words(name);
+    if let Some(scheme) = type_scheme {
+        let mut names = Vec::new();
+        constructors(&scheme.body, &mut names);
+        embedding.add_type_names(names);
+        if let CheckerType::Fun { params, .. } = &scheme.body {
+            embedding.add(format!("arity:{}", params.len()), 1.0);
+        }
+    }
+    embedding
+}
+
+/// Embed what an intent asks for the way definitions are embedded
+pub fn embed_intent(intent: &CodeIntent) -> Embedding {
+    let mut embedding = Embedding::default();
+    match &intent.target {
+        IntentTarget::Function { name, parameters, return_type, description } => {
+            embedding.add_words(name);
+            embedding.add_words(description);
+            let types = parameters.iter().filter_map(|param| param.typ.as_deref()).chain(return_type.as_deref());
+            embedding.add_type_names(types.flat_map(words));
+            if !parameters.is_empty() {
+                embedding.add(format!("arity:{}", parameters.len()), 1.0);
+            }
+        }
+        IntentTarget::DataType { name, .. }
+        | IntentTarget::Module { name, .. }
+        | IntentTarget::Interface { name, .. }
+        | IntentTarget::Effect { name, .. } => embedding.add_words(name),
+        IntentTarget::Algorithm { name, complexity } => {
+            embedding.add_words(name);
+            if let Some(complexity) = complexity {
+                embedding.add_words(complexity);
+            }
+        }
+    }
+    embedding
+}
+
+/// Lowercased words of `text`, splitting identifiers at `_` and case changes
+fn words(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut previous_lower = false;
+    for c in text.chars() {
+        if (!c.is_alphanumeric() || (c.is_uppercase() && previous_lower)) && !current.is_empty() {
+            words.push(std::mem::take(&mut current));
+        }
+        if c.is_alphanumeric() {
+            current.extend(c.to_lowercase());
+        }
+        previous_lower = c.is_lowercase();
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+fn constructors(typ: &CheckerType, out: &mut Vec<String>) {
+    match typ {
+        CheckerType::Con(name) => out.push(name.to_string()),
+        CheckerType::App(con, args) => {
+            constructors(con, out);
+            args.iter().for_each(|arg| constructors(arg, out));
+        }
+        CheckerType::Fun { params, return_type, .. } => {
+            params.iter().for_each(|param| constructors(param, out));
+            constructors(return_type, out);
+        }
+        CheckerType::Forall { body, .. } => constructors(body, out),
+        CheckerType::Tuple(types) => types.iter().for_each(|t| constructors(t, out)),
+        CheckerType::Record(fields) | CheckerType::Row { fields, .. } => {
+            fields.iter().for_each(|(_, t)| constructors(t, out))
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intent::{IntentAction, ParameterIntent};
+    use std::collections::HashSet;
+    use x_checker::checker::TypeCheck;
+    use x_parser::{parse_source, FileId, Item, SyntaxStyle};
+
+    fn repository(source: &str) -> ContentRepository {
+        let unit = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let types = unit.type_check().inferred_types;
+        let mut repository = ContentRepository::new();
+        for item in &unit.module.items {
+            let Item::ValueDef(def) = item else { continue };
+            repository.add_function(def.name.as_str(), def, types.get(&def.name), None, HashSet::new()).unwrap();
+        }
+        repository
+    }
+
+    fn function(name: &str, description: &str, types: &[&str], return_type: &str) -> CodeIntent {
+        CodeIntent {
+            action: IntentAction::Create,
+            target: IntentTarget::Function {
+                name: name.to_string(),
+                parameters: types.iter().enumerate().map(|(i, typ)| ParameterIntent {
+                    name: format!("p{i}"),
+                    typ: Some(typ.to_string()),
+                    description: None,
+                }).collect(),
+                return_type: Some(return_type.to_string()),
+                description: description.to_string(),
+            },
+            constraints: Vec::new(),
+            examples: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_retrieves_definitions_by_name_and_type() {
+        let repository = repository(
+            "module Lib\nlet add_ints = fun a b -> a + b\nlet string_length = fun s -> 3\nlet is_empty = fun s -> s == \"\"\nlet answer = 42",
+        );
+        let index = DefinitionIndex::from_repository(&repository).with_top_k(2);
+        assert_eq!(index.len(), 4);
+
+        let found = index.retrieve(&function("sumInts", "add two ints", &["Int", "Int"], "Int"));
+        assert_eq!(found[0].name.as_str(), "add_ints");
+        assert!(found.len() <= 2 && found.windows(2).all(|pair| pair[0].score >= pair[1].score));
+
+        let found = index.retrieve(&function("isBlank", "whether a string is empty", &["String"], "Bool"));
+        assert_eq!(found[0].name.as_str(), "is_empty");
+        assert!(index.retrieve(&function("zzz", "", &[], "Unit")).is_empty());
+
+        let session = crate::CodeGenSession::new().with_codebase(index);
+        let context = session.build_context(&function("sumInts", "add two ints", &["Int", "Int"], "Int")).unwrap();
+        assert_eq!(context.retrieved.len(), 2);
+        assert!(context.is_in_scope(Symbol::intern("add_ints")));
+    }
+
+    #[test]
+    fn test_words_split_identifiers() {
+        assert_eq!(words("parseHTTPRequest_body"), ["parse", "httprequest", "body"]);
+    }
+}
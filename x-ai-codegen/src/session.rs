@@ -10,6 +10,7 @@ use x_parser::ast::*;
 use crate::{
     GeneratedCode, CodeIntent, RefinementIntent, IntentTarget,
     context::{CodeGenContext, ContextBuilder, GeneratedItem, GeneratedItemKind},
+    retrieval::DefinitionIndex,
 };
 
 /// Code generation session
@@ -32,6 +33,9 @@ pub struct CodeGenSession {
     
     /// Active file being worked on
     pub active_file: Option<FileId>,
+    
+    /// Existing definitions to retrieve context from
+    pub codebase: Option<DefinitionIndex>,
 }
 
 /// Generation history
@@ -123,9 +127,16 @@ impl CodeGenSession {
             history: GenerationHistory::new(),
             metadata: SessionMetadata::new(),
             active_file: None,
+            codebase: None,
         }
     }
     
+    /// Retrieve context for each intent from `codebase`
+    pub fn with_codebase(mut self, codebase: DefinitionIndex) -> Self {
+        self.codebase = Some(codebase);
+        self
+    }
+    
     /// Build context for an intent
     pub fn build_context(&self, intent: &CodeIntent) -> Result<CodeGenContext> {
         let mut builder = ContextBuilder::new();
@@ -148,6 +159,13 @@ impl CodeGenSession {
         context.imports.extend(intent_context.imports);
         context.preferences = intent_context.preferences;
         
+        // Add related existing definitions
+        if let Some(codebase) = &self.codebase {
+            for definition in codebase.retrieve(intent) {
+                context.add_retrieved(definition);
+            }
+        }
+        
         Ok(context)
     }
     
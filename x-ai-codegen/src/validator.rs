@@ -1,11 +1,17 @@
 //! Code validation and error detection
 //! 
 //! This module validates generated code and suggests improvements.
+//! [`CodeValidator::refine`] runs the repair loop: each candidate is
+//! printed, parsed back and type checked, and its diagnostics become the
+//! feedback for the next candidate until one checks or the budget runs out.
 
 use anyhow::Result;
 use std::collections::{HashMap, HashSet};
 use x_parser::ast::*;
-use x_parser::Symbol;
+use x_parser::syntax::{ocaml::OCamlPrinter, SyntaxConfig, SyntaxPrinter};
+use x_parser::{parse_source, FileId, Span, Symbol, SyntaxStyle};
+use x_checker::checker::TypeChecker as Checker;
+use x_checker::error_reporting::TypeError;
 use x_checker::types::{Type as CheckerType};
 use crate::{
     GeneratedCode, Suggestion, SuggestionKind, CodeLocation,
@@ -65,12 +71,35 @@ pub enum Severity {
     Info,
 }
 
+/// One round of the repair loop
+#[derive(Debug, Clone)]
+pub struct ValidationAttempt {
+    /// The candidate as printed source
+    pub source: String,
+    /// Parse and type errors, located by line in `source`
+    pub errors: Vec<ValidationError>,
+    /// Whether `source` parsed at all
+    pub parsed: bool,
+    /// The feedback prompt sent to repair the candidate, if it was repaired
+    pub feedback: Option<String>,
+}
+
+impl ValidationAttempt {
+    /// Lower is better: candidates that parse beat those that do not, then
+    /// fewer errors win
+    fn rank(&self) -> (bool, usize) {
+        (!self.parsed, self.errors.len())
+    }
+}
+
 /// Code validator
 pub struct CodeValidator {
     type_checker: TypeChecker,
     pattern_analyzer: PatternAnalyzer,
     effect_analyzer: EffectAnalyzer,
     complexity_analyzer: ComplexityAnalyzer,
+    /// Repairs `refine` may ask for before settling on the best candidate
+    repair_budget: usize,
 }
 
 /// Simple type checker for validation
@@ -94,7 +123,88 @@ impl CodeValidator {
             pattern_analyzer: PatternAnalyzer,
             effect_analyzer: EffectAnalyzer,
             complexity_analyzer: ComplexityAnalyzer,
+            repair_budget: 3,
+        }
+    }
+    
+    /// Set how many repairs `refine` may ask for
+    pub fn with_repair_budget(mut self, budget: usize) -> Self {
+        self.repair_budget = budget;
+        self
+    }
+    
+    /// Parse and type check a candidate from its printed source
+    pub fn check_candidate(&self, code: &GeneratedCode) -> Result<ValidationAttempt> {
+        let source = OCamlPrinter::new().print(&code.ast, &SyntaxConfig::default())?;
+        let module = code.ast.module.name.to_string();
+        let location = |span: Option<Span>| CodeLocation {
+            module: module.clone(),
+            item: String::new(),
+            line: span.map(|span| line_of(&source, span)),
+        };
+        
+        let unit = match parse_source(&source, FileId::new(0), SyntaxStyle::SExpression) {
+            Ok(unit) => unit,
+            Err(error) => {
+                let error = ValidationError {
+                    kind: ErrorKind::SyntaxError,
+                    message: error.to_string(),
+                    location: location(None),
+                    severity: Severity::Error,
+                };
+                return Ok(ValidationAttempt { source, errors: vec![error], parsed: false, feedback: None });
+            }
+        };
+        let errors = Checker::new().check_compilation_unit(&unit).errors.iter()
+            .map(|error| ValidationError {
+                kind: error_kind(error),
+                message: format!("{}: {error}", error.code()),
+                location: location(Some(error.span())),
+                severity: Severity::Error,
+            })
+            .collect();
+        Ok(ValidationAttempt { source, errors, parsed: true, feedback: None })
+    }
+    
+    /// Check `code`, asking `repair` for a new candidate while it has errors
+    /// and the budget lasts. Returns the best candidate seen, with every
+    /// attempt recorded in its metadata's validation trace.
+    pub fn refine(
+        &self,
+        code: GeneratedCode,
+        mut repair: impl FnMut(&GeneratedCode, &ValidationAttempt) -> Result<GeneratedCode>,
+    ) -> Result<GeneratedCode> {
+        let mut trace: Vec<ValidationAttempt> = Vec::new();
+        let mut best: Option<(GeneratedCode, usize)> = None;
+        let mut candidate = code;
+        loop {
+            let mut attempt = self.check_candidate(&candidate)?;
+            let improves = best.as_ref().is_none_or(|(_, index)| attempt.rank() < trace[*index].rank());
+            let done = attempt.errors.is_empty() || trace.len() == self.repair_budget;
+            let next = if done {
+                None
+            } else {
+                attempt.feedback = Some(feedback_prompt(&attempt));
+                Some(repair(&candidate, &attempt)?)
+            };
+            if improves {
+                best = Some((candidate, trace.len()));
+            }
+            trace.push(attempt);
+            match next {
+                Some(next) => candidate = next,
+                None => break,
+            }
         }
+        
+        let (mut code, index) = best.expect("the first candidate is always checked");
+        code.validation = Some(ValidationResult {
+            errors: trace[index].errors.clone(),
+            warnings: Vec::new(),
+            suggestions: Vec::new(),
+        });
+        code.metadata.validation_trace = trace;
+        Ok(code)
     }
     
     /// Validate generated code
@@ -679,6 +789,39 @@ impl CodeValidator {
     }
 }
 
+/// The prompt asking for a candidate without `attempt`'s errors
+pub fn feedback_prompt(attempt: &ValidationAttempt) -> String {
+    let mut prompt = String::from("This candidate does not check:\n\n");
+    prompt.push_str(&attempt.source);
+    prompt.push_str("\n\nFix these problems and return the whole module:\n");
+    for error in &attempt.errors {
+        match error.location.line {
+            Some(line) => prompt.push_str(&format!("- line {line}: {}\n", error.message)),
+            None => prompt.push_str(&format!("- {}\n", error.message)),
+        }
+    }
+    prompt
+}
+
+/// The 1-based line of `span` in `source`
+fn line_of(source: &str, span: Span) -> usize {
+    let offset = (span.start.as_u32() as usize).min(source.len());
+    source.as_bytes()[..offset].iter().filter(|byte| **byte == b'\n').count() + 1
+}
+
+fn error_kind(error: &TypeError) -> ErrorKind {
+    match error {
+        TypeError::UnboundVariable { .. } => ErrorKind::UndefinedSymbol,
+        TypeError::UnknownEffect { .. }
+        | TypeError::UnknownOperation { .. }
+        | TypeError::UnhandledEffects { .. }
+        | TypeError::EffectRowMismatch { .. }
+        | TypeError::MissingHandler { .. }
+        | TypeError::ImpureDefinition { .. } => ErrorKind::EffectNotHandled,
+        _ => ErrorKind::TypeMismatch,
+    }
+}
+
 impl ValidationResult {
     /// Check if there are any issues
     pub fn has_issues(&self) -> bool {
@@ -697,4 +840,62 @@ impl TypeChecker {
             type_env: HashMap::new(),
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CodeIntent, GenerationMetadata, IntentAction, IntentTarget};
+
+    fn candidate(source: &str) -> GeneratedCode {
+        GeneratedCode {
+            ast: parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap(),
+            metadata: GenerationMetadata {
+                intent: CodeIntent {
+                    action: IntentAction::Create,
+                    target: IntentTarget::Module { name: "Gen".to_string(), exports: Vec::new() },
+                    constraints: Vec::new(),
+                    examples: Vec::new(),
+                },
+                confidence: 0.5,
+                alternatives: Vec::new(),
+                explanation: String::new(),
+                validation_trace: Vec::new(),
+            },
+            suggestions: None,
+            validation: None,
+        }
+    }
+
+    #[test]
+    fn test_refine_repairs_until_the_candidate_checks() {
+        let mut prompts = Vec::new();
+        let fixes = ["module Gen\nlet answer = 1 + missing", "module Gen\nlet answer = 1 + 41"];
+        let mut fixes = fixes.iter();
+        let code = CodeValidator::new()
+            .refine(candidate("module Gen\nlet answer = 1 + true"), |_, attempt| {
+                prompts.push(attempt.feedback.clone().unwrap());
+                Ok(candidate(fixes.next().unwrap()))
+            })
+            .unwrap();
+
+        let trace = &code.metadata.validation_trace;
+        assert_eq!(trace.iter().map(|attempt| attempt.errors.len()).collect::<Vec<_>>(), [1, 1, 0]);
+        assert_eq!(trace[1].errors[0].kind, ErrorKind::UndefinedSymbol);
+        assert!(prompts[0].contains("- line 3: E0105"), "{}", prompts[0]);
+        assert!(trace[2].feedback.is_none());
+        assert!(code.validation.unwrap().errors.is_empty());
+    }
+
+    #[test]
+    fn test_refine_keeps_the_best_candidate_within_budget() {
+        let code = CodeValidator::new()
+            .with_repair_budget(1)
+            .refine(candidate("module Gen\nlet a = 1 + true"), |_, _| {
+                Ok(candidate("module Gen\nlet a = 1 + true\nlet b = 2 + true"))
+            })
+            .unwrap();
+        assert_eq!(code.metadata.validation_trace.len(), 2);
+        assert_eq!(code.ast.module.items.len(), 1);
+        assert_eq!(code.validation.unwrap().errors.len(), 1);
+    }
+}
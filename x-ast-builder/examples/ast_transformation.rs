@@ -269,13 +269,13 @@ fn fold_constants(expr: &Expr) -> Expr {
             match (func.as_ref(), args.as_slice()) {
                 (Expr::Var(op, _), [left, right]) => {
                     match (fold_constants(left), fold_constants(right)) {
-                        (Expr::Literal(Literal::Integer(l), _), 
-                         Expr::Literal(Literal::Integer(r), _)) => {
+                        (Expr::Literal(Literal::Integer(l, _), _), 
+                         Expr::Literal(Literal::Integer(r, _), _)) => {
                             match op.as_str() {
-                                "+" => Expr::Literal(Literal::Integer(l + r), *span),
-                                "-" => Expr::Literal(Literal::Integer(l - r), *span),
-                                "*" => Expr::Literal(Literal::Integer(l * r), *span),
-                                "/" if r != 0 => Expr::Literal(Literal::Integer(l / r), *span),
+                                "+" => Expr::Literal(Literal::int(l + r), *span),
+                                "-" => Expr::Literal(Literal::int(l - r), *span),
+                                "*" => Expr::Literal(Literal::int(l * r), *span),
+                                "/" if r != 0 => Expr::Literal(Literal::int(l / r), *span),
                                 _ => expr.clone(),
                             }
                         }
@@ -3,7 +3,7 @@
 //! This shows how to construct x Language AST nodes directly
 
 use x_parser::ast::*;
-use x_parser::{Symbol, Span, FileId, span::ByteOffset, Visibility, Purity};
+use x_parser::{Symbol, Span, FileId, span::CharOffset, Visibility, Purity};
 use x_parser::syntax::sexp::SExpPrinter;
 use x_parser::syntax::{SyntaxPrinter, SyntaxConfig, SyntaxStyle};
 
@@ -39,7 +39,7 @@ fn example_simple_program() {
                 name: Symbol::intern("x"),
                 type_annotation: None,
                 parameters: Vec::new(),
-                body: Expr::Literal(Literal::Integer(42), span),
+                body: Expr::Literal(Literal::int(42), span),
                 visibility: Visibility::Private,
                 purity: Purity::Inferred,
                 span,
@@ -55,7 +55,7 @@ fn example_simple_program() {
                     Box::new(Expr::Var(Symbol::intern("+"), span)),
                     vec![
                         Expr::Var(Symbol::intern("x"), span),
-                        Expr::Literal(Literal::Integer(10), span),
+                        Expr::Literal(Literal::int(10), span),
                     ],
                     span
                 ),
@@ -130,6 +130,7 @@ fn example_function_with_pattern_matching() {
                     Constructor {
                         name: Symbol::intern("Nil"),
                         fields: Vec::new(),
+                        result: None,
                         span,
                     },
                     Constructor {
@@ -142,6 +143,7 @@ fn example_function_with_pattern_matching() {
                                 span
                             ),
                         ],
+                        result: None,
                         span,
                     },
                 ]),
@@ -168,7 +170,7 @@ fn example_function_with_pattern_matching() {
                                     span,
                                 },
                                 guard: None,
-                                body: Expr::Literal(Literal::Integer(0), span),
+                                body: Expr::Literal(Literal::int(0), span),
                                 span,
                             },
                             // | Cons _ tail -> 1 + length tail
@@ -185,7 +187,7 @@ fn example_function_with_pattern_matching() {
                                 body: Expr::App(
                                     Box::new(Expr::Var(Symbol::intern("+"), span)),
                                     vec![
-                                        Expr::Literal(Literal::Integer(1), span),
+                                        Expr::Literal(Literal::int(1), span),
                                         Expr::App(
                                             Box::new(Expr::Var(Symbol::intern("length"), span)),
                                             vec![Expr::Var(Symbol::intern("tail"), span)],
@@ -277,7 +279,7 @@ fn example_algebraic_effects() {
 // Helper functions
 
 fn make_span() -> Span {
-    Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(1))
+    Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(1))
 }
 
 fn print_module(module: &Module) {
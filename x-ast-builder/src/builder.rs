@@ -124,6 +124,7 @@ impl<'a> ModuleBuilder<'a> {
                 Constructor {
                     name: Symbol::intern(ctor_name),
                     fields: field_types,
+                    result: None,
                     span: self.builder.span(),
                 }
             })
@@ -236,12 +237,12 @@ impl<'a> ModuleBuilder<'a> {
 impl AstBuilder {
     /// Integer literal
     pub fn int(&mut self, value: i64) -> Expr {
-        Expr::Literal(Literal::Integer(value), self.span())
+        Expr::Literal(Literal::int(value), self.span())
     }
     
     /// Float literal
     pub fn float(&mut self, value: f64) -> Expr {
-        Expr::Literal(Literal::Float(value), self.span())
+        Expr::Literal(Literal::float(value), self.span())
     }
     
     /// String literal
@@ -21,14 +21,14 @@ impl<'a> ExprBuilder<'a> {
     /// Integer literal
     pub fn int(mut self, value: i64) -> Self {
         let span = self.builder.span();
-        self.expr = Some(Expr::Literal(Literal::Integer(value), span));
+        self.expr = Some(Expr::Literal(Literal::int(value), span));
         self
     }
     
     /// Float literal
     pub fn float(mut self, value: f64) -> Self {
         let span = self.builder.span();
-        self.expr = Some(Expr::Literal(Literal::Float(value), span));
+        self.expr = Some(Expr::Literal(Literal::float(value), span));
         self
     }
     
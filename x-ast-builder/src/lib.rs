@@ -3,7 +3,7 @@
 //! This module provides a fluent API for programmatically constructing
 //! x Language AST nodes without writing source code.
 
-use x_parser::{Span, FileId, span::ByteOffset};
+use x_parser::{Span, FileId, span::CharOffset};
 
 pub mod builder;
 pub mod dsl;
@@ -57,8 +57,8 @@ impl AstBuilder {
         self.current_offset += 1;
         Span::new(
             self.file_id,
-            ByteOffset::new(start),
-            ByteOffset::new(self.current_offset),
+            CharOffset::new(start),
+            CharOffset::new(self.current_offset),
         )
     }
     
@@ -68,8 +68,8 @@ impl AstBuilder {
         self.current_offset += len;
         Span::new(
             self.file_id,
-            ByteOffset::new(start),
-            ByteOffset::new(self.current_offset),
+            CharOffset::new(start),
+            CharOffset::new(self.current_offset),
         )
     }
 }
@@ -0,0 +1,15 @@
+//! Generates `x_capi.h` from the crate's `extern "C"` items into `OUT_DIR`
+
+use std::path::PathBuf;
+
+fn main() {
+    let crate_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap());
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+
+    let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml")).expect("invalid cbindgen.toml");
+    cbindgen::generate_with_config(&crate_dir, config)
+        .expect("failed to generate the C header")
+        .write_to_file(out_dir.join("x_capi.h"));
+}
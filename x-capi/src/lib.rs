@@ -0,0 +1,323 @@
+//! C API for embedding the x Language compiler
+//!
+//! The library builds as a shared and a static library, declared by the
+//! header `x_capi.h` that the build script generates into its `OUT_DIR`
+//! (`cbindgen --config cbindgen.toml --output x_capi.h` writes it
+//! elsewhere):
+//!
+//! ```c
+//! XCompileResult *result = x_compile_source(source, "typescript", "out");
+//! size_t count;
+//! const XDiagnostic *diagnostics = x_get_diagnostics(result, &count);
+//! for (size_t i = 0; i < count; i++) { ... }
+//! x_free_result(result);
+//! ```
+//!
+//! Strings are UTF-8 and NUL-terminated. A result, and every string and
+//! array reached through it, is owned by the library and stays valid until
+//! the result is passed to `x_free_result`. The layout of the `#[repr(C)]`
+//! types only grows at the end; `x_capi_version` is bumped when it changes.
+
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::ptr;
+use x_compiler::{compile, CompilerConfig, CompilerDiagnostic, CompilerError, DiagnosticSeverity};
+use x_parser::span::{CharOffset, LineMap};
+
+/// Version of the API and struct layout described by the header
+pub const X_CAPI_VERSION: u32 = 1;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XSeverity {
+    Error = 0,
+    Warning = 1,
+    Info = 2,
+}
+
+/// A diagnostic of a compilation
+#[repr(C)]
+#[derive(Debug)]
+pub struct XDiagnostic {
+    pub severity: XSeverity,
+    /// Stable code such as `E0101`, or NULL
+    pub code: *const c_char,
+    pub message: *const c_char,
+    /// 1-based line of the start of the diagnostic's location, or 0 when
+    /// it has none
+    pub line: u32,
+    /// 1-based column, counted in UTF-8 bytes
+    pub column: u32,
+    /// UTF-8 byte offsets of the location in the source
+    pub start: u32,
+    pub end: u32,
+}
+
+/// A file written by a compilation
+#[repr(C)]
+#[derive(Debug)]
+pub struct XOutputFile {
+    pub path: *const c_char,
+    /// Contents of the file, which need not be text
+    pub data: *const u8,
+    pub len: usize,
+}
+
+/// Outcome of `x_compile_source`, opaque to C
+pub struct XCompileResult {
+    succeeded: bool,
+    diagnostics: Vec<XDiagnostic>,
+    files: Vec<XOutputFile>,
+    /// Storage the pointers in `diagnostics` and `files` refer to
+    strings: Vec<CString>,
+    contents: Vec<Vec<u8>>,
+}
+
+impl XCompileResult {
+    fn new() -> Self {
+        XCompileResult { succeeded: false, diagnostics: Vec::new(), files: Vec::new(), strings: Vec::new(), contents: Vec::new() }
+    }
+
+    /// Keep `text` alive with the result, returning a pointer to it
+    fn intern(&mut self, text: &str) -> *const c_char {
+        let text = CString::new(text.replace('\0', "\u{FFFD}")).expect("NUL bytes were replaced");
+        let pointer = text.as_ptr();
+        self.strings.push(text);
+        pointer
+    }
+
+    fn push_diagnostic(&mut self, diagnostic: &CompilerDiagnostic, source: &str, lines: &LineMap) {
+        // Spans count characters, while C indexes the UTF-8 buffer it passed in
+        let byte = |offset: CharOffset| lines.byte_index(source, offset).unwrap_or(source.len()) as u32;
+        let location = diagnostic.span.map(|span| {
+            let position = lines.offset_to_position(span.start);
+            let line_start = CharOffset(span.start.as_u32() - position.column.as_u32());
+            (position.line.as_u32() + 1, byte(span.start) - byte(line_start) + 1, byte(span.start), byte(span.end))
+        });
+        let code = diagnostic.code.map_or(ptr::null(), |code| self.intern(code));
+        let message = self.intern(&diagnostic.message);
+        self.diagnostics.push(XDiagnostic {
+            severity: match diagnostic.severity {
+                DiagnosticSeverity::Error => XSeverity::Error,
+                DiagnosticSeverity::Warning => XSeverity::Warning,
+                DiagnosticSeverity::Info => XSeverity::Info,
+            },
+            code,
+            message,
+            line: location.map_or(0, |(line, ..)| line),
+            column: location.map_or(0, |(_, column, ..)| column),
+            start: location.map_or(0, |(.., start, _)| start),
+            end: location.map_or(0, |(.., end)| end),
+        });
+    }
+
+    fn push_error(&mut self, message: &str) {
+        let message = self.intern(message);
+        self.diagnostics.push(XDiagnostic {
+            severity: XSeverity::Error,
+            code: ptr::null(),
+            message,
+            line: 0,
+            column: 0,
+            start: 0,
+            end: 0,
+        });
+    }
+
+    fn push_file(&mut self, path: PathBuf, contents: Vec<u8>) {
+        let path = self.intern(&path.to_string_lossy());
+        // Moving the buffer into `contents` keeps its heap allocation in place
+        self.files.push(XOutputFile { path, data: contents.as_ptr(), len: contents.len() });
+        self.contents.push(contents);
+    }
+}
+
+/// The version of the API this library implements, `X_CAPI_VERSION`
+#[no_mangle]
+pub extern "C" fn x_capi_version() -> u32 {
+    X_CAPI_VERSION
+}
+
+/// Compile `source` for `target` (e.g. `"typescript"`, `"wasm-gc"`),
+/// writing the generated files under `output_dir`
+///
+/// Returns NULL only when an argument is NULL; every other failure is
+/// reported as an error diagnostic of the result.
+///
+/// # Safety
+///
+/// The arguments must be NULL or point to NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn x_compile_source(
+    source: *const c_char,
+    target: *const c_char,
+    output_dir: *const c_char,
+) -> *mut XCompileResult {
+    if source.is_null() || target.is_null() || output_dir.is_null() {
+        return ptr::null_mut();
+    }
+    let (source, target, output_dir) = (CStr::from_ptr(source), CStr::from_ptr(target), CStr::from_ptr(output_dir));
+    let mut result = XCompileResult::new();
+    match (source.to_str(), target.to_str(), output_dir.to_str()) {
+        (Ok(source), Ok(target), Ok(output_dir)) => {
+            let compiled = panic::catch_unwind(AssertUnwindSafe(|| {
+                compile(source, target, PathBuf::from(output_dir), CompilerConfig::default())
+            }));
+            match compiled {
+                Ok(compiled) => record(&mut result, source, compiled),
+                Err(_) => result.push_error("internal compiler error: the compiler panicked"),
+            }
+        }
+        _ => result.push_error("arguments must be valid UTF-8"),
+    }
+    Box::into_raw(Box::new(result))
+}
+
+fn record(result: &mut XCompileResult, source: &str, compiled: x_compiler::Result<x_compiler::CompilationResult>) {
+    let lines = LineMap::new(source);
+    match compiled {
+        Ok(compiled) => {
+            for diagnostic in &compiled.diagnostics {
+                result.push_diagnostic(diagnostic, source, &lines);
+            }
+            let mut files: Vec<(PathBuf, Vec<u8>)> = compiled.files.into_iter()
+                .map(|(path, text)| (path, text.into_bytes()))
+                .chain(compiled.binary_files)
+                .collect();
+            files.sort_by(|(a, _), (b, _)| a.cmp(b));
+            for (path, contents) in files {
+                result.push_file(path, contents);
+            }
+            result.succeeded = result.diagnostics.iter().all(|diagnostic| diagnostic.severity != XSeverity::Error);
+        }
+        Err(CompilerError::Parse(error)) => result.push_diagnostic(&CompilerDiagnostic::from_parse_error(&error), source, &lines),
+        Err(error) => result.push_error(&error.to_string()),
+    }
+}
+
+/// Whether the compilation produced no error diagnostics
+///
+/// # Safety
+///
+/// `result` must be NULL or a result returned by `x_compile_source` and not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn x_result_succeeded(result: *const XCompileResult) -> bool {
+    result.as_ref().is_some_and(|result| result.succeeded)
+}
+
+/// The diagnostics of a compilation, storing their number in `count`
+///
+/// # Safety
+///
+/// `result` must be NULL or a result returned by `x_compile_source` and not
+/// yet freed; `count` must be NULL or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn x_get_diagnostics(result: *const XCompileResult, count: *mut usize) -> *const XDiagnostic {
+    let diagnostics = result.as_ref().map_or(&[][..], |result| &result.diagnostics[..]);
+    if let Some(count) = count.as_mut() {
+        *count = diagnostics.len();
+    }
+    diagnostics.as_ptr()
+}
+
+/// The files a compilation wrote, sorted by path, storing their number in `count`
+///
+/// # Safety
+///
+/// As for `x_get_diagnostics`.
+#[no_mangle]
+pub unsafe extern "C" fn x_get_output_files(result: *const XCompileResult, count: *mut usize) -> *const XOutputFile {
+    let files = result.as_ref().map_or(&[][..], |result| &result.files[..]);
+    if let Some(count) = count.as_mut() {
+        *count = files.len();
+    }
+    files.as_ptr()
+}
+
+/// Release a result and everything reached through it
+///
+/// # Safety
+///
+/// `result` must be NULL or a result returned by `x_compile_source` that
+/// has not been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn x_free_result(result: *mut XCompileResult) {
+    if !result.is_null() {
+        drop(Box::from_raw(result));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn compile_with(source: &str, target: &str, dir: &TempDir) -> *mut XCompileResult {
+        let source = CString::new(source).unwrap();
+        let target = CString::new(target).unwrap();
+        let output_dir = CString::new(dir.path().to_str().unwrap()).unwrap();
+        unsafe { x_compile_source(source.as_ptr(), target.as_ptr(), output_dir.as_ptr()) }
+    }
+
+    fn diagnostics<'a>(result: *const XCompileResult) -> &'a [XDiagnostic] {
+        let mut count = 0;
+        unsafe { std::slice::from_raw_parts(x_get_diagnostics(result, &mut count), count) }
+    }
+
+    #[test]
+    fn test_compile_source_writes_files() {
+        let dir = TempDir::new().unwrap();
+        let result = compile_with("module Main\nlet one = 1", "typescript", &dir);
+        unsafe {
+            assert!(x_result_succeeded(result), "{:?}", diagnostics(result));
+            let mut count = 0;
+            let files = std::slice::from_raw_parts(x_get_output_files(result, &mut count), count);
+            assert!(!files.is_empty());
+            let path = CStr::from_ptr(files[0].path).to_str().unwrap();
+            let contents = std::slice::from_raw_parts(files[0].data, files[0].len);
+            assert_eq!(std::fs::read(path).unwrap(), contents);
+            x_free_result(result);
+        }
+    }
+
+    #[test]
+    fn test_failures_are_reported_as_diagnostics() {
+        let dir = TempDir::new().unwrap();
+        let result = compile_with("module Main\nlet bad = 1 + missing", "typescript", &dir);
+        unsafe {
+            assert!(!x_result_succeeded(result));
+            let diagnostic = &diagnostics(result)[0];
+            assert_eq!((diagnostic.severity, diagnostic.line), (XSeverity::Error, 2));
+            assert!(CStr::from_ptr(diagnostic.code).to_str().unwrap().starts_with("E01"));
+            x_free_result(result);
+
+            let result = compile_with("module Main\nlet = 1", "typescript", &dir);
+            assert!(CStr::from_ptr(diagnostics(result)[0].code).to_str().unwrap().starts_with("E00"));
+            x_free_result(result);
+
+            let result = compile_with("module Main\nlet one = 1", "cobol", &dir);
+            let message = CStr::from_ptr(diagnostics(result)[0].message).to_str().unwrap();
+            assert!(message.contains("cobol"), "{message}");
+            x_free_result(result);
+
+            assert!(x_compile_source(ptr::null(), ptr::null(), ptr::null()).is_null());
+            assert!(!x_result_succeeded(ptr::null()));
+            x_free_result(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn test_locations_count_bytes() {
+        let dir = TempDir::new().unwrap();
+        let source = "module Main\nlet bad = \"é\" ^ missing";
+        let result = compile_with(source, "typescript", &dir);
+        unsafe {
+            let diagnostic = &diagnostics(result)[0];
+            let start = source.find("missing").unwrap() as u32;
+            assert_eq!((diagnostic.line, diagnostic.column), (2, start - 12 + 1), "{diagnostic:?}");
+            assert_eq!((diagnostic.start, diagnostic.end), (start, start + 7));
+            x_free_result(result);
+        }
+    }
+}
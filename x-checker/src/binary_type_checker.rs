@@ -4,6 +4,7 @@
 //! enabling faster type analysis by avoiding AST reconstruction.
 
 use crate::{
+    checker::CheckResult,
     error_reporting::{TypeError, TypeErrorReporter},
     inference::{InferenceContext, InferenceResult},
     types::*,
@@ -11,8 +12,8 @@ use crate::{
 use std::collections::HashMap;
 use std::result::Result as StdResult;
 use x_parser::{
-    binary::{BinaryDeserializer, TypeCode, TypedBinaryNode},
-    Span, Symbol,
+    binary::{self, BinaryDeserializer, BinarySerializer, InternalType, TypeCode, TypedBinaryNode, TypedDefinition},
+    CompilationUnit, Item, Span, Symbol,
 };
 
 /// Binary type checker that operates directly on binary AST
@@ -46,95 +47,109 @@ impl BinaryTypeChecker {
     }
 
     /// Type check a binary compilation unit
+    ///
+    /// Files written with `serialize_with_types` carry a scheme and effect
+    /// row for every definition; these are loaded into the environment as-is
+    /// and inference is skipped. Otherwise, or if any definition lacks a
+    /// stored type, the unit is inferred from scratch.
     pub fn check_binary_compilation_unit(
         &mut self,
         binary_data: &[u8],
     ) -> StdResult<TypeCheckResult, String> {
-        let mut deserializer = BinaryDeserializer::new(binary_data.to_vec())
+        let mut deserializer = BinaryDeserializer::from_slice(binary_data)
             .map_err(|e| format!("Binary deserializer error: {e:?}"))?;
+        let cu = deserializer
+            .deserialize_compilation_unit()
+            .map_err(|e| format!("Failed to decode compilation unit: {e}"))?;
 
         // Check if binary contains cached type information
         let has_types = deserializer.has_type_information();
         let has_effects = deserializer.has_cached_effects();
 
         if has_types && has_effects {
-            // Use cached type information for fast validation
-            self.validate_cached_types(&mut deserializer)
-        } else {
-            // Perform full type inference on binary AST
-            self.infer_types_from_binary(&mut deserializer)
+            let definitions = deserializer
+                .deserialize_type_metadata()
+                .map_err(|e| format!("Failed to read type metadata: {e}"))?;
+            if let Some(result) = self.validate_cached_types(&cu, definitions) {
+                return Ok(result);
+            }
         }
+        self.infer_types_from_binary(&cu)
+    }
+
+    /// Environment holding the schemes of the last checked unit
+    pub fn type_env(&self) -> &TypeEnv {
+        &self.type_env
     }
 
-    /// Validate pre-computed type information in binary format
+    /// Use pre-computed type information, if it covers every definition
     fn validate_cached_types(
         &mut self,
-        deserializer: &mut BinaryDeserializer,
-    ) -> StdResult<TypeCheckResult, String> {
-        let mut results = Vec::new();
-        let mut node_count = 0;
-
-        // Load type metadata
-        self.load_type_cache(deserializer)?;
-
-        // Validate each typed node
-        while let Ok((expr, typ, effects)) = deserializer.deserialize_typed_expr() {
-            node_count += 1;
-
-            if let (Some(_cached_type), Some(_cached_effects)) = (typ, effects) {
-                // TODO: Convert x-parser types to x-checker types and validate
-                // For now, skip validation
-
-                results.push(TypedNode {
-                    node_id: node_count,
-                    inferred_type: Type::Hole, // TODO: Convert from cached_type
-                    effects: EffectSet::Empty, // TODO: Convert from cached_effects
-                    span: expr.span(),
-                });
-            } else {
-                // Missing type information - create default result
-                let inference_result = InferenceResult {
-                    typ: Type::Hole,
-                    effects: EffectSet::Empty,
-                    constraints: Vec::new(),
-                };
-                results.push(TypedNode {
-                    node_id: node_count,
-                    inferred_type: inference_result.typ,
-                    effects: inference_result.effects,
-                    span: expr.span(),
-                });
-            }
+        cu: &CompilationUnit,
+        definitions: Vec<TypedDefinition>,
+    ) -> Option<TypeCheckResult> {
+        let mut schemes = HashMap::new();
+        let mut effects = HashMap::new();
+        for definition in definitions {
+            schemes.insert(definition.name, TypeScheme {
+                type_vars: definition.type_vars.into_iter().map(TypeVar).collect(),
+                effect_vars: definition.effect_vars.into_iter().map(EffectVar).collect(),
+                constraints: Vec::new(),
+                body: from_internal_type(&definition.body),
+            });
+            effects.insert(definition.name, from_internal_effects(&definition.effects));
         }
 
-        Ok(TypeCheckResult {
-            typed_nodes: results,
-            errors: self.error_reporter.errors().to_vec(),
-            validation_mode: ValidationMode::CachedValidation,
-            node_count,
-        })
-    }
-
-    /// Perform type inference directly on binary AST
-    fn infer_types_from_binary(
-        &mut self,
-        _deserializer: &mut BinaryDeserializer,
-    ) -> StdResult<TypeCheckResult, String> {
-        let results = Vec::new();
-        let node_count = 0;
+        // Definitions without a stored type failed to check when the file
+        // was written, or the metadata is stale
+        let complete = definition_names(cu)
+            .all(|(_, name, _)| schemes.contains_key(&name) && effects.contains_key(&name));
+        if !complete {
+            return None;
+        }
 
-        // Create inference context
-        let _inference_ctx = InferenceContext::new();
+        Some(self.finish(cu, schemes, effects, Vec::new(), ValidationMode::CachedValidation))
+    }
 
-        // TODO: Implement proper binary node processing
-        // For now, skip binary node processing since the deserialize_binary_node method is not available
+    /// Perform full type inference on the decoded unit
+    fn infer_types_from_binary(&mut self, cu: &CompilationUnit) -> StdResult<TypeCheckResult, String> {
+        let result = crate::type_check(cu);
+        Ok(self.finish(
+            cu,
+            result.inferred_types,
+            result.inferred_effects,
+            result.errors,
+            ValidationMode::FullInference,
+        ))
+    }
 
-        Ok(TypeCheckResult {
-            typed_nodes: results,
-            errors: self.error_reporter.errors().to_vec(),
-            validation_mode: ValidationMode::FullInference,
-            node_count,
-        })
+    /// Record the schemes in the environment and report one node per definition
+    fn finish(
+        &mut self,
+        cu: &CompilationUnit,
+        schemes: HashMap<Symbol, TypeScheme>,
+        effects: HashMap<Symbol, EffectSet>,
+        errors: Vec<TypeError>,
+        validation_mode: ValidationMode,
+    ) -> TypeCheckResult {
+        let typed_nodes = definition_names(cu)
+            .filter_map(|(index, name, span)| {
+                Some(TypedNode {
+                    node_id: index as u32,
+                    inferred_type: schemes.get(&name)?.body.clone(),
+                    effects: effects.get(&name).cloned().unwrap_or(EffectSet::Empty),
+                    span,
+                })
+            })
+            .collect();
+        self.type_env.vars.extend(schemes);
+
+        TypeCheckResult {
+            typed_nodes,
+            errors,
+            validation_mode,
+            node_count: cu.module.items.len() as u32,
+        }
     }
 
     /// Infer type for a single binary node
@@ -199,38 +214,6 @@ impl BinaryTypeChecker {
         }
     }
 
-    /// Load type cache from binary metadata
-    fn load_type_cache(&mut self, deserializer: &mut BinaryDeserializer) -> StdResult<(), String> {
-        // Read type metadata section
-        let type_count = deserializer
-            .read_u32()
-            .map_err(|e| format!("Failed to read type count: {e:?}"))?;
-
-        for _i in 0..type_count {
-            let type_id = deserializer
-                .read_u32()
-                .map_err(|e| format!("Failed to read type id: {e:?}"))?;
-            // TODO: Implement proper type deserialization once API is available
-            // For now, store placeholder types
-            self.type_cache.insert(type_id, Type::Hole);
-        }
-
-        // Read effect cache
-        let effect_count = deserializer
-            .read_u32()
-            .map_err(|e| format!("Failed to read effect count: {e:?}"))?;
-        for _i in 0..effect_count {
-            let effect_id = deserializer
-                .read_u32()
-                .map_err(|e| format!("Failed to read effect id: {e:?}"))?;
-            // TODO: Implement proper effect deserialization once API is available
-            // For now, store empty effect sets
-            self.effect_cache.insert(effect_id, EffectSet::Empty);
-        }
-
-        Ok(())
-    }
-
     /// Validate a cached type against current context
     #[allow(dead_code)]
     fn validate_type_in_context(&self, _typ: &Type, _effects: &EffectSet) -> StdResult<(), String> {
@@ -348,6 +331,158 @@ impl TypeCheckResult {
     }
 }
 
+/// Persist checker output alongside the binary AST
+pub trait SerializeWithTypes {
+    /// Serialize `cu` with the scheme and effect row inferred for each of its
+    /// definitions, so `BinaryTypeChecker` can load it without re-inferring.
+    /// Definitions that failed to check are left out. Class constraints on
+    /// schemes are not stored.
+    fn serialize_with_types(&mut self, cu: &CompilationUnit, result: &CheckResult) -> x_parser::Result<Vec<u8>>;
+}
+
+impl SerializeWithTypes for BinarySerializer {
+    fn serialize_with_types(&mut self, cu: &CompilationUnit, result: &CheckResult) -> x_parser::Result<Vec<u8>> {
+        let definitions: Vec<TypedDefinition> = definition_names(cu)
+            .filter_map(|(_, name, _)| {
+                let scheme = result.inferred_types.get(&name)?;
+                Some(TypedDefinition {
+                    name,
+                    type_vars: scheme.type_vars.iter().map(|var| var.0).collect(),
+                    effect_vars: scheme.effect_vars.iter().map(|var| var.0).collect(),
+                    body: to_internal_type(&scheme.body),
+                    effects: to_internal_effects(result.inferred_effects.get(&name).unwrap_or(&EffectSet::Empty)),
+                })
+            })
+            .collect();
+        self.serialize_typed_compilation_unit(cu, &definitions)
+    }
+}
+
+/// Index, name and span of every definition the checker infers a type for
+fn definition_names(cu: &CompilationUnit) -> impl Iterator<Item = (usize, Symbol, Span)> + '_ {
+    cu.module.items.iter().enumerate().filter_map(|(index, item)| match item {
+        Item::ValueDef(def) => Some((index, def.name, def.span)),
+        Item::TestDef(test) => Some((index, test.name, test.span)),
+        _ => None,
+    })
+}
+
+fn to_internal_type(typ: &Type) -> InternalType {
+    let all = |types: &[Type]| types.iter().map(to_internal_type).collect();
+    match typ {
+        Type::Var(var) => InternalType::Var(var.0),
+        Type::Con(name) => InternalType::Con(*name),
+        Type::App(base, args) => InternalType::App(Box::new(to_internal_type(base)), all(args)),
+        Type::Fun { params, return_type, effects } => InternalType::Fun {
+            params: all(params),
+            return_type: Box::new(to_internal_type(return_type)),
+            effects: to_internal_effects(effects),
+        },
+        Type::Forall { type_vars, effect_vars, body } => InternalType::Forall {
+            type_vars: type_vars.iter().map(|var| var.0).collect(),
+            effect_vars: effect_vars.iter().map(|var| var.0).collect(),
+            body: Box::new(to_internal_type(body)),
+        },
+        Type::Record(fields) => InternalType::Record(
+            fields.iter().map(|(name, field)| (*name, to_internal_type(field))).collect(),
+        ),
+        Type::Row { fields, rest } => InternalType::Row {
+            fields: fields.iter().map(|(name, field)| (*name, to_internal_type(field))).collect(),
+            rest: rest.0,
+        },
+        Type::Variant(variants) => InternalType::Variant(
+            variants.iter().map(|(name, fields)| (*name, all(fields))).collect(),
+        ),
+        Type::Tuple(types) => InternalType::Tuple(all(types)),
+        Type::Hole => InternalType::Hole,
+        Type::Rec { var, body } => InternalType::Rec { var: var.0, body: Box::new(to_internal_type(body)) },
+        Type::Unknown => InternalType::Unknown,
+    }
+}
+
+fn to_internal_effects(effects: &EffectSet) -> binary::EffectSet {
+    match effects {
+        EffectSet::Empty => binary::EffectSet::Empty,
+        EffectSet::Var(var) => binary::EffectSet::Var(binary::EffectVar(var.0)),
+        EffectSet::Row { effects, tail } => binary::EffectSet::Row {
+            effects: effects
+                .iter()
+                .map(|effect| binary::Effect {
+                    name: effect.name,
+                    operations: effect
+                        .operations
+                        .iter()
+                        .map(|op| binary::EffectOperation {
+                            name: op.name,
+                            params: op.params.iter().map(to_internal_type).collect(),
+                            return_type: to_internal_type(&op.return_type),
+                        })
+                        .collect(),
+                })
+                .collect(),
+            tail: tail.as_ref().map(|tail| Box::new(to_internal_effects(tail))),
+        },
+    }
+}
+
+fn from_internal_type(typ: &InternalType) -> Type {
+    let all = |types: &[InternalType]| types.iter().map(from_internal_type).collect();
+    match typ {
+        InternalType::Var(var) => Type::Var(TypeVar(*var)),
+        InternalType::Con(name) => Type::Con(*name),
+        InternalType::App(base, args) => Type::App(Box::new(from_internal_type(base)), all(args)),
+        InternalType::Fun { params, return_type, effects } => Type::Fun {
+            params: all(params),
+            return_type: Box::new(from_internal_type(return_type)),
+            effects: from_internal_effects(effects),
+        },
+        InternalType::Forall { type_vars, effect_vars, body } => Type::Forall {
+            type_vars: type_vars.iter().copied().map(TypeVar).collect(),
+            effect_vars: effect_vars.iter().copied().map(EffectVar).collect(),
+            body: Box::new(from_internal_type(body)),
+        },
+        InternalType::Record(fields) => Type::Record(
+            fields.iter().map(|(name, field)| (*name, from_internal_type(field))).collect(),
+        ),
+        InternalType::Row { fields, rest } => Type::Row {
+            fields: fields.iter().map(|(name, field)| (*name, from_internal_type(field))).collect(),
+            rest: TypeVar(*rest),
+        },
+        InternalType::Variant(variants) => Type::Variant(
+            variants.iter().map(|(name, fields)| (*name, all(fields))).collect(),
+        ),
+        InternalType::Tuple(types) => Type::Tuple(all(types)),
+        InternalType::Hole => Type::Hole,
+        InternalType::Rec { var, body } => Type::Rec { var: TypeVar(*var), body: Box::new(from_internal_type(body)) },
+        InternalType::Unknown => Type::Unknown,
+    }
+}
+
+fn from_internal_effects(effects: &binary::EffectSet) -> EffectSet {
+    match effects {
+        binary::EffectSet::Empty => EffectSet::Empty,
+        binary::EffectSet::Var(var) => EffectSet::Var(EffectVar(var.0)),
+        binary::EffectSet::Row { effects, tail } => EffectSet::Row {
+            effects: effects
+                .iter()
+                .map(|effect| Effect {
+                    name: effect.name,
+                    operations: effect
+                        .operations
+                        .iter()
+                        .map(|op| Operation {
+                            name: op.name,
+                            params: op.params.iter().map(from_internal_type).collect(),
+                            return_type: from_internal_type(&op.return_type),
+                        })
+                        .collect(),
+                })
+                .collect(),
+            tail: tail.as_ref().map(|tail| Box::new(from_internal_effects(tail))),
+        },
+    }
+}
+
 /// Extension trait for BinaryDeserializer to support type checking
 #[allow(dead_code)]
 trait BinaryDeserializerExt {
@@ -393,7 +528,7 @@ impl BinaryDeserializerExt for BinaryDeserializer {
         let file_id = self.read_u32().map_err(|e| format!("Failed to read file_id: {:?}", e))?;
         let start = self.read_u32().map_err(|e| format!("Failed to read start: {:?}", e))?;
         let end = self.read_u32().map_err(|e| format!("Failed to read end: {:?}", e))?;
-        let span = Span::new(FileId::INVALID, ByteOffset(0), ByteOffset(0)); // Simplified for now
+        let span = Span::new(FileId::INVALID, CharOffset(0), CharOffset(0)); // Simplified for now
 
         // Read payload
         let payload_size = self.read_u32().map_err(|e| format!("Failed to read payload size: {:?}", e))? as usize;
@@ -473,7 +608,7 @@ impl ReadExact for BinaryDeserializer {
 mod tests {
     use super::*;
     use x_parser::symbol::Symbol;
-    use x_parser::{FileId, span::ByteOffset};
+    use x_parser::{FileId, span::CharOffset};
 
     #[test]
     fn test_binary_type_checker_creation() {
@@ -495,13 +630,40 @@ mod tests {
         assert!(!result.used_cache());
     }
 
+    #[test]
+    fn test_cached_types_skip_inference() {
+        let source = "module Test\nlet answer = 42\nlet greeting = \"hello\"";
+        let cu = x_parser::parse_source(source, FileId::new(0), x_parser::SyntaxStyle::SExpression).unwrap();
+        let checked = crate::type_check(&cu);
+        assert!(checked.errors.is_empty());
+
+        let typed = BinarySerializer::new().serialize_with_types(&cu, &checked).unwrap();
+        let mut checker = BinaryTypeChecker::new();
+        let result = checker.check_binary_compilation_unit(&typed).unwrap();
+        assert!(result.used_cache());
+        assert_eq!(result.typed_nodes.len(), 2);
+        for name in ["answer", "greeting"] {
+            let name = Symbol::intern(name);
+            assert_eq!(checker.type_env().lookup_var(name), checked.inferred_types.get(&name));
+        }
+
+        let plain = BinarySerializer::new().serialize_compilation_unit(&cu).unwrap();
+        let mut checker = BinaryTypeChecker::new();
+        let inferred = checker.check_binary_compilation_unit(&plain).unwrap();
+        assert_eq!(inferred.validation_mode, ValidationMode::FullInference);
+        assert_eq!(
+            inferred.typed_nodes.iter().map(|node| &node.inferred_type).collect::<Vec<_>>(),
+            result.typed_nodes.iter().map(|node| &node.inferred_type).collect::<Vec<_>>(),
+        );
+    }
+
     #[test]
     fn test_typed_node() {
         let node = TypedNode {
             node_id: 1,
             inferred_type: Type::Con(Symbol::intern("Int")),
             effects: EffectSet::Empty,
-            span: Span::new(FileId(u32::MAX), ByteOffset(0), ByteOffset(0)),
+            span: Span::new(FileId(u32::MAX), CharOffset(0), CharOffset(0)),
         };
 
         assert_eq!(node.node_id, 1);
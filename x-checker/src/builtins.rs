@@ -3,8 +3,8 @@
 //! This module defines the core built-in types and operators that are
 //! available in every x Language program without explicit imports.
 
-use crate::types::{Type, TypeScheme, TypeVar, EffectSet, Effect};
-use x_parser::{Symbol, Span, span::ByteOffset, FileId};
+use crate::types::{Type, TypeScheme, TypeVar, EffectSet, EffectVar, Effect};
+use x_parser::{Symbol, Span, span::CharOffset, FileId};
 use std::collections::HashMap;
 
 /// Built-in types
@@ -62,7 +62,7 @@ impl Default for BuiltinOperators {
 impl BuiltinOperators {
     pub fn new() -> Self {
         let mut operators = HashMap::new();
-        let _dummy_span = Span::new(FileId::INVALID, ByteOffset(0), ByteOffset(0));
+        let _dummy_span = Span::new(FileId::INVALID, CharOffset(0), CharOffset(0));
         
         // Arithmetic operators
         let int_type = Type::Con(Symbol::intern("Int"));
@@ -122,6 +122,8 @@ impl BuiltinOperators {
         
         operators.insert(Symbol::intern("="), comparison.clone());
         operators.insert(Symbol::intern("<>"), comparison.clone());
+        operators.insert(Symbol::intern("=="), comparison.clone());
+        operators.insert(Symbol::intern("!="), comparison.clone());
         operators.insert(Symbol::intern("<"), comparison.clone());
         operators.insert(Symbol::intern(">"), comparison.clone());
         operators.insert(Symbol::intern("<="), comparison.clone());
@@ -189,6 +191,66 @@ impl BuiltinOperators {
         
         operators.insert(Symbol::intern("::"), list_cons_type);
         
+        // Pipeline and composition, passing on the effects of the functions
+        // they apply
+        let arrow = |param: Type, result: Type, effects: EffectSet| Type::Fun {
+            params: vec![param],
+            return_type: Box::new(result),
+            effects,
+        };
+        let (a, b, c) = (TypeVar(0), TypeVar(1), TypeVar(2));
+        let e = EffectVar(0);
+        let (ta, tb, tc) = (Type::Var(a), Type::Var(b), Type::Var(c));
+        let latent = EffectSet::Var(e);
+        
+        // |> : 'a -> ('a -> 'b / e) -> 'b / e
+        let pipe_type = TypeScheme {
+            type_vars: vec![a, b],
+            effect_vars: vec![e],
+            constraints: vec![],
+            body: arrow(
+                ta.clone(),
+                arrow(arrow(ta.clone(), tb.clone(), latent.clone()), tb.clone(), latent.clone()),
+                EffectSet::empty(),
+            ),
+        };
+        
+        // >> : ('a -> 'b / e) -> ('b -> 'c / e) -> 'a -> 'c / e
+        let compose_right_type = TypeScheme {
+            type_vars: vec![a, b, c],
+            effect_vars: vec![e],
+            constraints: vec![],
+            body: arrow(
+                arrow(ta.clone(), tb.clone(), latent.clone()),
+                arrow(
+                    arrow(tb.clone(), tc.clone(), latent.clone()),
+                    arrow(ta.clone(), tc.clone(), latent.clone()),
+                    EffectSet::empty(),
+                ),
+                EffectSet::empty(),
+            ),
+        };
+        
+        // << : ('b -> 'c / e) -> ('a -> 'b / e) -> 'a -> 'c / e
+        let compose_left_type = TypeScheme {
+            type_vars: vec![a, b, c],
+            effect_vars: vec![e],
+            constraints: vec![],
+            body: arrow(
+                arrow(tb.clone(), tc.clone(), latent.clone()),
+                arrow(
+                    arrow(ta.clone(), tb, latent.clone()),
+                    arrow(ta, tc, latent),
+                    EffectSet::empty(),
+                ),
+                EffectSet::empty(),
+            ),
+        };
+        
+        operators.insert(Symbol::intern("|>"), pipe_type);
+        operators.insert(Symbol::intern(">>"), compose_right_type);
+        operators.insert(Symbol::intern("<<"), compose_left_type);
+        
         Self { operators }
     }
     
@@ -1,24 +1,111 @@
 //! Main type checker interface
 
 use crate::{
-    types::{Type, TypeScheme, TypeEnv, EffectSet},
-    inference::InferenceContext,
+    types::{Type, TypeScheme, TypeEnv, TypeVar, EffectSet, Effect, Operation, Constraint, Instance, ModuleInterface},
+    inference::{InferenceContext, InferenceResult},
     error_reporting::{TypeError, TypeErrorReporter},
+    constraints::{ClassSolver, Evidence, instance_dictionary_name, substitute_vars},
+    continuations::{self, Resumption},
+    database::ItemCheck,
+    resolver::describe_visibility,
+    signatures::ModuleType,
 };
-use x_parser::{CompilationUnit, Module, Item, ValueDef, TypeDef, Symbol, Span, FileId};
-use x_parser::span::ByteOffset;
-use std::collections::HashMap;
+use x_parser::{CompilationUnit, Module, Item, ValueDef, TypeDef, Symbol, Span, FileId, Purity, Fixity, Deprecation};
+use x_parser::{CancellationToken, Cancelled};
+use x_parser::dependency::DependencyManager;
+use x_parser::span::CharOffset;
+use std::collections::{HashMap, HashSet};
 
 /// Type checking result
 #[derive(Debug)]
 pub struct CheckResult {
     pub type_env: TypeEnv,
     pub inferred_types: HashMap<Symbol, TypeScheme>,
+    /// Effects performed by evaluating each top-level definition
+    pub inferred_effects: HashMap<Symbol, EffectSet>,
     pub effect_constraints: Vec<EffectConstraint>,
+    /// Dictionaries to pass at each use of an overloaded name, keyed by the use's span
+    pub class_evidence: HashMap<Span, Vec<Evidence>>,
+    /// How each operation clause resumes its continuation, keyed by the clause's span
+    pub continuations: HashMap<Span, Resumption>,
     pub errors: Vec<TypeError>,
     pub warnings: Vec<TypeError>,
 }
 
+impl CheckResult {
+    /// The values and constructors the module this result checked offers
+    /// other modules; with an export list, only the items it names
+    pub fn module_interface(&self, module: &Module) -> ModuleInterface {
+        let mut interface = ModuleInterface { path: Some(module.name.clone()), ..ModuleInterface::default() };
+        let exported = |name: Symbol| match &module.exports {
+            Some(exports) => exports.items.iter()
+                .find(|item| item.name == name)
+                .map(|item| item.alias.unwrap_or(name)),
+            None => Some(name),
+        };
+        for item in &module.items {
+            match item {
+                Item::ValueDef(def) => {
+                    let name = exported(def.name);
+                    if let (Some(name), Some(scheme)) = (name, self.inferred_types.get(&def.name)) {
+                        export_value(&mut interface, name, scheme.clone(), &def.visibility);
+                        if let Some(deprecation) = def.documentation.as_ref().and_then(|doc| doc.doc_comment.deprecation()) {
+                            interface.deprecated.insert(name, deprecation);
+                        }
+                    }
+                }
+                Item::TypeDef(TypeDef { name, kind: x_parser::TypeDefKind::Data(constructors), visibility, .. })
+                    if exported(*name).is_some() =>
+                {
+                    for constructor in constructors {
+                        if let Some(scheme) = self.inferred_types.get(&constructor.name) {
+                            export_value(&mut interface, constructor.name, scheme.clone(), visibility);
+                            interface.constructors.insert(constructor.name);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        for (operator, fixity) in module.fixities() {
+            let name = exported(operator).filter(|name| {
+                interface.values.contains_key(name) || interface.restricted.contains_key(name)
+            });
+            if let Some(name) = name {
+                interface.fixities.insert(name, fixity);
+            }
+        }
+        interface
+    }
+
+    /// Effects a top-level definition performs, when evaluated or once applied
+    /// to all of its arguments, or `None` if it was not checked
+    pub fn performed_effects(&self, name: Symbol) -> Option<Vec<Symbol>> {
+        let scheme = self.inferred_types.get(&name)?;
+        let mut rows = vec![self.inferred_effects.get(&name).cloned().unwrap_or(EffectSet::Empty)];
+        let mut typ = &scheme.body;
+        loop {
+            match typ {
+                Type::Forall { body, .. } => typ = body,
+                Type::Fun { effects, return_type, .. } => {
+                    rows.push(effects.clone());
+                    typ = return_type;
+                }
+                _ => break,
+            }
+        }
+        let mut performed = Vec::new();
+        for row in rows {
+            if let EffectSet::Row { effects, .. } = row.normalize() {
+                performed.extend(effects.into_iter().map(|effect| effect.name));
+            }
+        }
+        performed.sort_by_key(|name| name.as_str());
+        performed.dedup();
+        Some(performed)
+    }
+}
+
 /// Effect constraint for effect system checking
 #[derive(Debug, Clone)]
 pub struct EffectConstraint {
@@ -32,6 +119,23 @@ pub struct TypeChecker {
     env: TypeEnv,
     inference_ctx: InferenceContext,
     error_reporter: TypeErrorReporter,
+    cancellation: CancellationToken,
+    definition_effects: HashMap<Symbol, EffectSet>,
+    classes: HashMap<Symbol, ClassInfo>,
+    class_evidence: HashMap<Span, Vec<Evidence>>,
+    continuations: HashMap<Span, Resumption>,
+    /// Modules that imports can resolve to, keyed by dotted path
+    available_modules: HashMap<String, ModuleInterface>,
+    /// Data types with constructors that refine their result type
+    gadts: HashSet<Symbol>,
+}
+
+/// A declared type class, with method signatures over its parameters
+#[derive(Debug, Clone)]
+struct ClassInfo {
+    params: Vec<TypeVar>,
+    superclasses: Vec<Constraint>,
+    methods: Vec<(Symbol, Type)>,
 }
 
 impl TypeChecker {
@@ -41,6 +145,13 @@ impl TypeChecker {
             env: TypeEnv::new(),
             inference_ctx: InferenceContext::new(),
             error_reporter: TypeErrorReporter::new(),
+            cancellation: CancellationToken::new(),
+            definition_effects: HashMap::new(),
+            classes: HashMap::new(),
+            class_evidence: HashMap::new(),
+            continuations: HashMap::new(),
+            available_modules: HashMap::new(),
+            gadts: HashSet::new(),
         }
     }
 
@@ -50,74 +161,362 @@ impl TypeChecker {
             env,
             inference_ctx: InferenceContext::new(),
             error_reporter: TypeErrorReporter::new(),
+            cancellation: CancellationToken::new(),
+            definition_effects: HashMap::new(),
+            classes: HashMap::new(),
+            class_evidence: HashMap::new(),
+            continuations: HashMap::new(),
+            available_modules: HashMap::new(),
+            gadts: HashSet::new(),
         }
     }
 
+    /// Attach a cancellation token that is polled between items
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = token;
+        self
+    }
+
+    /// Make a checked module importable under its dotted path, e.g. `Core.List`
+    pub fn with_module(mut self, path: impl Into<String>, interface: ModuleInterface) -> Self {
+        self.available_modules.insert(path.into(), interface);
+        self
+    }
+
+    /// Fixities of the operators `module` imports, which its source has to be
+    /// parsed with for their uses to group as declared; see
+    /// `Parser::with_fixities`
+    pub fn imported_fixities(&self, module: &Module) -> Vec<(Symbol, Fixity)> {
+        let mut fixities = Vec::new();
+        for import in &module.imports {
+            let Some(interface) = self.available_modules.get(&import.module_path.to_string()) else {
+                continue;
+            };
+            match &import.kind {
+                x_parser::ImportKind::Selective(items) => fixities.extend(items.iter().filter_map(|item| {
+                    let fixity = interface.fixities.get(&item.name)?;
+                    Some((item.alias.unwrap_or(item.name), *fixity))
+                })),
+                x_parser::ImportKind::Wildcard => fixities.extend(interface.fixities.iter().map(|(name, fixity)| (*name, *fixity))),
+                _ => {}
+            }
+        }
+        fixities
+    }
+
     /// Type check a compilation unit
+    ///
+    /// If the attached token is cancelled mid-check, the result only covers
+    /// the items checked so far; use `try_check_compilation_unit` to observe
+    /// cancellation explicitly.
     pub fn check_compilation_unit(&mut self, cu: &CompilationUnit) -> CheckResult {
-        // Process the module
-        self.check_module(&cu.module);
+        let _ = self.check_module(&cu.module);
+        self.collect_result()
+    }
+
+    /// Type check a compilation unit, returning `Err(Cancelled)` if the token fires
+    pub fn try_check_compilation_unit(&mut self, cu: &CompilationUnit) -> Result<CheckResult, Cancelled> {
+        self.check_module(&cu.module)?;
+        Ok(self.collect_result())
+    }
 
-        // Collect results
+    /// Collect results accumulated so far
+    fn collect_result(&self) -> CheckResult {
         CheckResult {
             type_env: self.env.clone(),
             inferred_types: self.collect_inferred_types(),
+            inferred_effects: self.definition_effects.clone(),
             effect_constraints: self.collect_effect_constraints(),
+            class_evidence: self.class_evidence.clone(),
+            continuations: self.continuations.clone(),
             errors: self.error_reporter.errors().to_vec(),
-            warnings: self.error_reporter.warnings().to_vec(),
+            warnings: self.error_reporter.warnings().iter()
+                .chain(&self.inference_ctx.warnings)
+                .cloned()
+                .collect(),
         }
     }
 
     /// Type check a module
-    fn check_module(&mut self, module: &Module) {
-        // Process module imports
-        for import in &module.imports {
-            self.check_import(import);
+    fn check_module(&mut self, module: &Module) -> Result<(), Cancelled> {
+        if let Some(exports) = &module.exports {
+            self.check_exports(module, exports);
         }
+        self.enter_module(module);
 
         // Process module items
         for item in &module.items {
+            self.cancellation.check()?;
             self.check_item(item);
         }
+        self.continuations.extend(continuations::classify_module(module));
 
         // Module scope is implicitly exited when scope_env is dropped
+        Ok(())
+    }
+
+    /// Bring into scope what every item of `module` sees: its module types
+    /// and its imports
+    pub(crate) fn enter_module(&mut self, module: &Module) {
+        self.inference_ctx.current_module = Some(module.name.clone());
+
+        // Module types are in scope for the imports as well as every item
+        for item in &module.items {
+            if let Item::ModuleTypeDef(module_type_def) = item {
+                self.check_module_type_def(module_type_def);
+            }
+        }
+
+        // Process module imports
+        for import in &module.imports {
+            self.check_import(import);
+        }
+    }
+
+    /// Run `check` and report only what it binds and reports, forgetting
+    /// the diagnostics of earlier checks
+    pub(crate) fn observe(&mut self, check: impl FnOnce(&mut Self)) -> ItemCheck {
+        self.error_reporter = TypeErrorReporter::new();
+        self.inference_ctx.warnings.clear();
+        self.class_evidence.clear();
+        self.definition_effects.clear();
+        let before = self.env.vars.clone();
+
+        check(self);
+
+        let mut bindings: Vec<_> = self.env.vars.iter()
+            .filter(|(name, scheme)| before.get(name) != Some(scheme))
+            .map(|(name, scheme)| (*name, scheme.clone()))
+            .collect();
+        bindings.sort_by_key(|(name, _)| name.as_str());
+        let mut effects: Vec<_> = self.definition_effects.drain().collect();
+        effects.sort_by_key(|(name, _)| name.as_str());
+        let mut class_evidence: Vec<_> = self.class_evidence.drain().collect();
+        class_evidence.sort_by_key(|(span, _)| (span.start, span.end));
+        ItemCheck {
+            bindings,
+            effects,
+            class_evidence,
+            continuations: Vec::new(),
+            errors: self.error_reporter.errors().to_vec(),
+            warnings: self.error_reporter.warnings().iter()
+                .chain(&self.inference_ctx.warnings)
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Warn at the uses of a value checked elsewhere
+    pub(crate) fn deprecate(&mut self, name: Symbol, deprecation: Deprecation) {
+        self.inference_ctx.env.deprecated.insert(name, deprecation);
+    }
+
+    /// Bind a value checked elsewhere, as if its definition had been checked
+    pub(crate) fn bind_value(&mut self, name: Symbol, scheme: TypeScheme) {
+        self.inference_ctx.env.insert_var(name, scheme.clone());
+        self.env.insert_var(name, scheme);
+    }
+
+    /// Every name in an export list must be an item that can leave the module
+    pub(crate) fn check_exports(&mut self, module: &Module, exports: &x_parser::ExportList) {
+        for export in &exports.items {
+            let visibility = module.items.iter().find_map(|item| match item {
+                Item::ValueDef(def) if def.name == export.name => Some(&def.visibility),
+                Item::TypeDef(def) if def.name == export.name => Some(&def.visibility),
+                Item::EffectDef(def) if def.name == export.name => Some(&def.visibility),
+                _ => None,
+            });
+            match visibility {
+                Some(visibility) if !is_exported(visibility) => {
+                    self.error_reporter.report_error(TypeError::InsufficientVisibility {
+                        name: export.name,
+                        visibility: describe_visibility(visibility),
+                        required: describe_visibility(&x_parser::Visibility::Public),
+                        span: export.span,
+                    });
+                }
+                Some(_) => {}
+                None => self.error_reporter.report_error(TypeError::UnboundVariable { name: export.name, span: export.span }),
+            }
+        }
     }
 
     /// Type check an item
-    fn check_item(&mut self, item: &Item) {
+    pub(crate) fn check_item(&mut self, item: &Item) {
         match item {
             Item::ValueDef(value_def) => self.check_value_def(value_def),
             Item::TypeDef(type_def) => self.check_type_def(type_def),
             Item::EffectDef(effect_def) => self.check_effect_def(effect_def),
             Item::HandlerDef(handler_def) => self.check_handler_def(handler_def),
             Item::InterfaceDef(interface_def) => self.check_interface_def(interface_def),
-            Item::ModuleTypeDef(module_type_def) => self.check_module_type_def(module_type_def),
+            // Registered before the imports
+            Item::ModuleTypeDef(_) => {}
+            // Applied by the parser, and exported with the operators
+            Item::Fixity(_) => {}
             Item::TestDef(test_def) => self.check_test_def(test_def),
+            Item::BenchDef(bench_def) => self.check_bench_def(bench_def),
+            Item::ClassDef(class_def) => self.check_class_def(class_def),
+            Item::InstanceDef(instance_def) => self.check_instance_def(instance_def),
+            // Without a target to resolve it against, the item is checked as
+            // if its condition held
+            Item::Conditional(conditional) => return self.check_item(&conditional.item),
+        }
+        let definition = match item {
+            Item::ValueDef(value_def) => Some(value_def.name),
+            _ => None,
+        };
+        for hole in self.inference_ctx.take_holes(definition) {
+            self.error_reporter.report_error(hole);
         }
     }
 
-    /// Type check a value definition
+    /// Type check a value definition, and record whether later uses of it
+    /// are deprecated
     fn check_value_def(&mut self, value_def: &ValueDef) {
-        match self.inference_ctx.infer_expr(&value_def.body) {
+        let deprecation = value_def.documentation.as_ref().and_then(|doc| doc.doc_comment.deprecation());
+        // A deprecated definition may still use other deprecated ones
+        let outer = deprecation.is_some().then(|| std::mem::take(&mut self.inference_ctx.env.deprecated));
+        self.check_value_body(value_def);
+        let deprecated = &mut self.inference_ctx.env.deprecated;
+        if let Some(outer) = outer {
+            *deprecated = outer;
+        }
+        match deprecation {
+            Some(deprecation) => deprecated.insert(value_def.name, deprecation),
+            None => deprecated.remove(&value_def.name),
+        };
+    }
+
+    fn check_value_body(&mut self, value_def: &ValueDef) {
+        // Matching on a GADT refines the annotation's type variables, so such
+        // definitions are checked against their annotation, which they may
+        // also call themselves at
+        let declared = value_def.type_annotation.as_ref()
+            .filter(|annotation| self.mentions_gadt(annotation))
+            .map(|annotation| (self.annotation_scheme(annotation), self.convert_parser_type_to_checker_type(annotation)));
+        let inferred = if let Some((scheme, expected)) = &declared {
+            self.inference_ctx.env.insert_var(value_def.name, scheme.clone());
+            let checked = self.inference_ctx.check_expr(&value_def.body, expected);
+            self.inference_ctx.env.vars.remove(&value_def.name);
+            checked
+        } else {
+            self.infer_value_def(value_def)
+        };
+
+        match inferred {
             Ok(inference_result) => {
+                if let Some(annotation) = &value_def.type_annotation {
+                    // Holes take their types from the annotation as well as from their uses
+                    if self.inference_ctx.has_holes() {
+                        let expected = self.annotation_scheme(annotation).body;
+                        let _ = self.inference_ctx.unify_types(&inference_result.typ, &expected);
+                    }
+                    let mut holes = Vec::new();
+                    type_holes(annotation, &self.inference_ctx.resolve_type(&inference_result.typ), &mut holes);
+                    for (span, typ) in holes {
+                        self.error_reporter.report_warning(TypeError::TypeHole { typ, span });
+                    }
+                }
+
                 // Check type annotation if present
-                if let Some(ref annotation) = value_def.type_annotation {
+                if let (None, Some(annotation)) = (&declared, &value_def.type_annotation) {
                     if let Err(error) = self.check_type_annotation(&inference_result.typ, annotation) {
+                        let error = match error {
+                            TypeError::TypeMismatch { expected, found, .. } => TypeError::AnnotationMismatch {
+                                expected,
+                                found,
+                                span: value_def.body.span(),
+                                annotation: annotation.span(),
+                            },
+                            error => error,
+                        };
                         self.error_reporter.report_error(error);
                     }
                 }
 
+                // Class constraints on type variables become dictionary parameters
+                let constraints = self.solve_wanted(Vec::new(), true);
+                
                 // Generalize and add to environment
-                let type_scheme = self.inference_ctx.generalize(&inference_result.typ, &inference_result.effects);
+                let mut type_scheme = match declared {
+                    Some((scheme, _)) => scheme,
+                    None => self.inference_ctx.generalize(&inference_result.typ, &inference_result.effects),
+                };
+                type_scheme.constraints = constraints;
+                self.inference_ctx.env.insert_var(value_def.name, type_scheme.clone());
                 self.env.insert_var(value_def.name, type_scheme);
+                let effects = self.inference_ctx.resolve_effects(&inference_result.effects);
+                if value_def.name == Symbol::intern("main") {
+                    self.report_effects_escaping_main(&inference_result.typ, &effects, value_def.span);
+                } else {
+                    self.report_unhandled_effects(&effects, value_def.span);
+                }
+                if value_def.purity == Purity::Pure {
+                    self.report_impure_definition(value_def, &inference_result.typ, &effects);
+                }
+                self.definition_effects.insert(value_def.name, effects);
             }
             Err(error) => {
-                self.error_reporter.report_error(TypeError::InferenceError {
-                    message: format!("Failed to infer type for {}: {}", value_def.name.as_str(), error),
-                    symbol: value_def.name,
-                    span: value_def.span,
-                });
+                self.inference_ctx.take_wanted();
+                // Uses can still rely on what the annotation declares
+                if let Some((scheme, _)) = declared {
+                    self.inference_ctx.env.insert_var(value_def.name, scheme.clone());
+                    self.env.insert_var(value_def.name, scheme);
+                }
+                // Prefer the errors inference located precisely over a summary for the whole definition
+                let located = std::mem::take(&mut self.inference_ctx.errors);
+                if located.is_empty() {
+                    self.error_reporter.report_error(TypeError::InferenceError {
+                        message: format!("Failed to infer type for {}: {}", value_def.name.as_str(), error),
+                        symbol: value_def.name,
+                        span: value_def.span,
+                    });
+                }
+                for error in located {
+                    self.error_reporter.report_error(error);
+                }
+            }
+        }
+    }
+
+    fn infer_value_def(&mut self, value_def: &ValueDef) -> Result<InferenceResult, String> {
+        // Functions may call themselves; the name is monomorphic inside its own body
+        let recursive = matches!(value_def.body, x_parser::Expr::Lambda { .. })
+            && DependencyManager::extract_dependencies_from_def(value_def).contains(&value_def.name);
+        let own_type = recursive.then(|| {
+            let own_type = self.inference_ctx.fresh_type_var();
+            self.inference_ctx.env.insert_var(value_def.name, TypeScheme::monotype(own_type.clone()));
+            own_type
+        });
+        let inferred = self.inference_ctx.infer_expr(&value_def.body).and_then(|result| {
+            if let Some(own_type) = &own_type {
+                self.inference_ctx.unify_types(own_type, &result.typ)?;
             }
+            Ok(result)
+        });
+        if own_type.is_some() {
+            self.inference_ctx.env.vars.remove(&value_def.name);
+        }
+        inferred
+    }
+
+    /// Whether an annotation names a data type declared with GADT constructors
+    fn mentions_gadt(&self, annotation: &x_parser::Type) -> bool {
+        let mut names = Vec::new();
+        type_constructor_names(&self.convert_parser_type_to_checker_type(annotation), &mut names);
+        names.iter().any(|name| self.gadts.contains(name))
+    }
+
+    /// The scheme an annotation declares, quantified over its lowercase names
+    fn annotation_scheme(&mut self, annotation: &x_parser::Type) -> TypeScheme {
+        let vars: HashMap<Symbol, TypeVar> = type_variable_names(annotation).into_iter()
+            .map(|name| (name, self.inference_ctx.var_gen.fresh_type_var()))
+            .collect();
+        TypeScheme {
+            type_vars: vars.values().copied().collect(),
+            effect_vars: Vec::new(),
+            constraints: Vec::new(),
+            body: self.convert_type_with_vars(annotation, &vars),
         }
     }
 
@@ -138,6 +537,7 @@ impl TypeChecker {
                         }
                     }
                 }
+                self.register_constructors(type_def, constructors);
             }
             x_parser::TypeDefKind::Alias(aliased_type) => {
                 if let Err(error) = self.check_type_well_formed(aliased_type) {
@@ -150,25 +550,461 @@ impl TypeChecker {
         }
     }
 
-    /// Check other item types (simplified implementations)
-    fn check_effect_def(&mut self, _effect_def: &x_parser::EffectDef) {
-        // TODO: Implement effect definition checking
+    /// Bind each constructor to a curried function from its fields to the data type
+    fn register_constructors(&mut self, type_def: &TypeDef, constructors: &[x_parser::Constructor]) {
+        let vars: HashMap<Symbol, TypeVar> = type_def.type_params.iter()
+            .map(|param| (param.name, self.inference_ctx.var_gen.fresh_type_var()))
+            .collect();
+        let params: Vec<TypeVar> = type_def.type_params.iter().map(|param| vars[&param.name]).collect();
+        let data_type = if params.is_empty() {
+            Type::Con(type_def.name)
+        } else {
+            Type::App(
+                Box::new(Type::Con(type_def.name)),
+                params.iter().map(|var| Type::Var(*var)).collect(),
+            )
+        };
+
+        for constructor in constructors {
+            // A GADT constructor gives its own result type, and lowercase
+            // names it does not share with the data type are its own
+            let mut vars = vars.clone();
+            let mut type_vars = params.clone();
+            let result = match &constructor.result {
+                Some(result) => {
+                    let names = constructor.fields.iter().chain(std::iter::once(result)).flat_map(type_variable_names);
+                    for name in names {
+                        if let std::collections::hash_map::Entry::Vacant(entry) = vars.entry(name) {
+                            let var = self.inference_ctx.var_gen.fresh_type_var();
+                            type_vars.push(var);
+                            entry.insert(var);
+                        }
+                    }
+                    let result = self.convert_type_with_vars(result, &vars);
+                    let head = match &result {
+                        Type::App(con, _) => con.as_ref(),
+                        typ => typ,
+                    };
+                    if head != &Type::Con(type_def.name) {
+                        self.error_reporter.report_error(TypeError::InferenceError {
+                            message: format!("Constructor {} must give a {}, not {result}", constructor.name, type_def.name),
+                            symbol: constructor.name,
+                            span: constructor.span,
+                        });
+                        continue;
+                    }
+                    self.gadts.insert(type_def.name);
+                    result
+                }
+                None => data_type.clone(),
+            };
+            let body = constructor.fields.iter().rev().fold(result, |result, field| Type::Fun {
+                params: vec![self.convert_type_with_vars(field, &vars)],
+                return_type: Box::new(result),
+                effects: EffectSet::Empty,
+            });
+            let scheme = TypeScheme {
+                type_vars,
+                effect_vars: Vec::new(),
+                constraints: Vec::new(),
+                body,
+            };
+            self.inference_ctx.constructors.insert(constructor.name, scheme.clone());
+            self.inference_ctx.env.insert_var(constructor.name, scheme.clone());
+            self.env.insert_var(constructor.name, scheme);
+        }
+    }
+
+    /// Resolve the class constraints raised by the definition just inferred
+    ///
+    /// Returns the givens extended with any constraints that had to be
+    /// abstracted over; evidence for each use site is recorded.
+    fn solve_wanted(&mut self, givens: Vec<Constraint>, abstract_vars: bool) -> Vec<Constraint> {
+        let wanted = self.inference_ctx.take_wanted();
+        let mut solver = ClassSolver::new(&self.env.instances, givens, abstract_vars);
+        let mut errors = Vec::new();
+        
+        for wanted in wanted {
+            let types: Vec<Type> = wanted.types.iter()
+                .map(|t| self.inference_ctx.resolve_type(t))
+                .collect();
+            match solver.entail(wanted.class, &types) {
+                Some(evidence) => self.class_evidence.entry(wanted.span).or_default().push(evidence),
+                None => errors.push(TypeError::NoInstance {
+                    class: wanted.class,
+                    types,
+                    span: wanted.span,
+                }),
+            }
+        }
+        
+        let givens = solver.into_givens();
+        for error in errors {
+            self.error_reporter.report_error(error);
+        }
+        givens
+    }
+
+    /// Register a class and give each method a scheme constrained by the class
+    fn check_class_def(&mut self, class_def: &x_parser::ClassDef) {
+        let vars: HashMap<Symbol, TypeVar> = class_def.type_params.iter()
+            .map(|param| (param.name, self.inference_ctx.var_gen.fresh_type_var()))
+            .collect();
+        let params: Vec<TypeVar> = class_def.type_params.iter().map(|param| vars[&param.name]).collect();
+        let head = Constraint::Class {
+            class: class_def.name,
+            types: params.iter().map(|var| Type::Var(*var)).collect(),
+        };
+        
+        let superclasses = class_def.superclasses.iter()
+            .map(|constraint| self.convert_class_constraint(constraint, &vars))
+            .collect();
+        
+        let mut methods = Vec::new();
+        for method in &class_def.methods {
+            let typ = self.convert_type_with_vars(&method.type_annotation, &vars);
+            let scheme = TypeScheme {
+                type_vars: params.clone(),
+                effect_vars: Vec::new(),
+                constraints: vec![head.clone()],
+                body: typ.clone(),
+            };
+            self.inference_ctx.env.insert_var(method.name, scheme.clone());
+            self.env.insert_var(method.name, scheme);
+            methods.push((method.name, typ));
+        }
+        
+        self.classes.insert(class_def.name, ClassInfo { params, superclasses, methods });
+    }
+
+    /// Register an instance and check its methods against the class signatures
+    ///
+    /// Method bodies are checked with the instance's type variables held
+    /// rigid, so they must work for every type the instance covers.
+    fn check_instance_def(&mut self, instance_def: &x_parser::InstanceDef) {
+        let Some(class) = self.classes.get(&instance_def.class).cloned() else {
+            self.error_reporter.report_error(TypeError::UnknownClass {
+                name: instance_def.class,
+                span: instance_def.span,
+            });
+            return;
+        };
+        if class.params.len() != instance_def.types.len() {
+            self.error_reporter.report_error(TypeError::ArityMismatch {
+                expected: class.params.len(),
+                found: instance_def.types.len(),
+                span: instance_def.span,
+            });
+            return;
+        }
+        
+        // Lowercase names in the head are the instance's type variables
+        let mut vars = HashMap::new();
+        for typ in &instance_def.types {
+            for name in type_variable_names(typ) {
+                vars.entry(name).or_insert_with(|| self.inference_ctx.var_gen.fresh_type_var());
+            }
+        }
+        let head_types: Vec<Type> = instance_def.types.iter()
+            .map(|typ| self.convert_type_with_vars(typ, &vars))
+            .collect();
+        let context: Vec<Constraint> = instance_def.constraints.iter()
+            .map(|constraint| self.convert_class_constraint(constraint, &vars))
+            .collect();
+        self.env.instances.entry(instance_def.class).or_default().push(Instance {
+            constraints: context,
+            head: Constraint::Class { class: instance_def.class, types: head_types },
+            dictionary: instance_dictionary_name(instance_def),
+        });
+        
+        // Rigid view of the instance: its variables stay as opaque constructors
+        let no_vars = HashMap::new();
+        let rigid_types: Vec<Type> = instance_def.types.iter()
+            .map(|typ| self.convert_type_with_vars(typ, &no_vars))
+            .collect();
+        let givens: Vec<Constraint> = instance_def.constraints.iter()
+            .map(|constraint| self.convert_class_constraint(constraint, &no_vars))
+            .collect();
+        let class_subst: HashMap<TypeVar, Type> = class.params.iter().copied().zip(rigid_types).collect();
+        
+        for superclass in &class.superclasses {
+            if let Constraint::Class { class: superclass, types } = superclass {
+                let types: Vec<Type> = types.iter().map(|t| substitute_vars(t, &class_subst)).collect();
+                let mut solver = ClassSolver::new(&self.env.instances, givens.clone(), false);
+                if solver.entail(*superclass, &types).is_none() {
+                    self.error_reporter.report_error(TypeError::NoInstance {
+                        class: *superclass,
+                        types,
+                        span: instance_def.span,
+                    });
+                }
+            }
+        }
+        
+        for method in &instance_def.methods {
+            let Some((_, signature)) = class.methods.iter().find(|(name, _)| *name == method.name) else {
+                self.error_reporter.report_error(TypeError::InferenceError {
+                    message: format!("'{}' is not a method of class {}", method.name, instance_def.class),
+                    symbol: method.name,
+                    span: method.span,
+                });
+                continue;
+            };
+            let expected = substitute_vars(signature, &class_subst);
+            let checked = self.inference_ctx.infer_expr(&method.body)
+                .and_then(|result| self.inference_ctx.unify_types(&result.typ, &expected));
+            if let Err(error) = checked {
+                self.inference_ctx.take_wanted();
+                self.error_reporter.report_error(TypeError::InferenceError {
+                    message: format!("Method {} of instance {}: {}", method.name, instance_def.class, error),
+                    symbol: method.name,
+                    span: method.span,
+                });
+                continue;
+            }
+            self.solve_wanted(givens.clone(), false);
+        }
+        
+        for (name, _) in &class.methods {
+            if !instance_def.methods.iter().any(|method| method.name == *name) {
+                self.error_reporter.report_error(TypeError::InferenceError {
+                    message: format!("Instance of {} is missing method '{}'", instance_def.class, name),
+                    symbol: *name,
+                    span: instance_def.span,
+                });
+            }
+        }
+    }
+
+    fn convert_class_constraint(&self, constraint: &x_parser::TypeConstraint, vars: &HashMap<Symbol, TypeVar>) -> Constraint {
+        Constraint::Class {
+            class: constraint.class,
+            types: constraint.types.iter().map(|typ| self.convert_type_with_vars(typ, vars)).collect(),
+        }
+    }
+
+    /// Convert a type in a class or instance declaration, where the named parameters are type variables
+    fn convert_type_with_vars(&self, parser_type: &x_parser::Type, vars: &HashMap<Symbol, TypeVar>) -> Type {
+        let subst = vars.iter().map(|(name, var)| (*name, Type::Var(*var))).collect();
+        replace_constructors(&self.convert_parser_type_to_checker_type(parser_type), &subst)
+    }
+
+    /// Report every effect left in a definition's row that no handler discharges.
+    /// Ambient effects (IO) are provided by the runtime and never need a handler.
+    fn report_unhandled_effects(&mut self, effects: &EffectSet, def_span: Span) {
+        self.report_effects_escaping(effects, def_span, is_ambient_effect);
+    }
+
+    /// `main` is the program's entry point, which the host runs under its
+    /// event loop: the host handles `Async` there, as well as IO, both for
+    /// evaluating `main` and for calling it when it is a function. Anything
+    /// else `main` performs must be handled inside it.
+    fn report_effects_escaping_main(&mut self, typ: &Type, effects: &EffectSet, def_span: Span) {
+        let mut escaping = match effects.normalize() {
+            EffectSet::Row { effects, .. } => effects,
+            _ => Vec::new(),
+        };
+        if let Type::Fun { effects: latent, .. } = self.inference_ctx.resolve_type(typ) {
+            if let EffectSet::Row { effects, .. } = self.inference_ctx.resolve_effects(&latent).normalize() {
+                escaping.extend(effects);
+            }
+        }
+        let escaping = EffectSet::Row { effects: escaping, tail: None };
+        self.report_effects_escaping(&escaping, def_span, |name| {
+            is_ambient_effect(name) || name == x_parser::symbol::symbols::ASYNC()
+        });
+    }
+
+    /// Report each effect a definition declared `pure` performs, either when
+    /// evaluated or once applied to all of its arguments. Calls contribute the
+    /// effects of their callee, so this covers everything it reaches; an
+    /// effect row left open by a higher-order parameter is not a violation.
+    fn report_impure_definition(&mut self, value_def: &ValueDef, typ: &Type, effects: &EffectSet) {
+        let mut rows = vec![effects.clone()];
+        let mut typ = self.inference_ctx.resolve_type(typ);
+        while let Type::Fun { effects, return_type, .. } = typ {
+            rows.push(self.inference_ctx.resolve_effects(&effects));
+            typ = self.inference_ctx.resolve_type(&return_type);
+        }
+        let mut reported = HashSet::new();
+        for row in rows {
+            let EffectSet::Row { effects, .. } = row.normalize() else {
+                continue;
+            };
+            for effect in effects.into_iter().filter(|effect| reported.insert(effect.name)) {
+                let span = effect.operations.iter()
+                    .find_map(|operation| self.inference_ctx.perform_site(effect.name, operation.name))
+                    .unwrap_or(value_def.span);
+                self.error_reporter.report_error(TypeError::ImpureDefinition {
+                    name: value_def.name,
+                    effect: effect.name,
+                    span,
+                    definition: value_def.span,
+                });
+            }
+        }
+    }
+
+    fn report_effects_escaping(&mut self, effects: &EffectSet, def_span: Span, handled_by_host: impl Fn(Symbol) -> bool) {
+        let EffectSet::Row { effects, .. } = effects.normalize() else {
+            return;
+        };
+        for effect in effects.iter().filter(|effect| !handled_by_host(effect.name)) {
+            if effect.operations.is_empty() {
+                self.error_reporter.report_error(TypeError::MissingHandler {
+                    effect: effect.name,
+                    operation: None,
+                    span: def_span,
+                });
+            }
+            for operation in &effect.operations {
+                let span = self.inference_ctx
+                    .perform_site(effect.name, operation.name)
+                    .unwrap_or(def_span);
+                self.error_reporter.report_error(TypeError::MissingHandler {
+                    effect: effect.name,
+                    operation: Some(operation.name),
+                    span,
+                });
+            }
+        }
     }
 
-    fn check_handler_def(&mut self, _handler_def: &x_parser::HandlerDef) {
-        // TODO: Implement handler definition checking
+    /// Register a user-defined effect so `perform` and handlers can resolve its operations
+    fn check_effect_def(&mut self, effect_def: &x_parser::EffectDef) {
+        for op in &effect_def.operations {
+            self.inference_ctx.declare_operation(effect_def.name, op.name, op.span);
+        }
+        let operations = effect_def.operations.iter()
+            .map(|op| Operation {
+                name: op.name,
+                params: op.parameters.iter().map(|t| self.convert_parser_type_to_checker_type(t)).collect(),
+                return_type: self.convert_parser_type_to_checker_type(&op.return_type),
+            })
+            .collect();
+        self.inference_ctx.env.effects.insert(effect_def.name, Effect {
+            name: effect_def.name,
+            operations,
+        });
+    }
+
+    /// Check each clause of a handler against the operation it handles, as
+    /// declared by its effect, for a handled computation of any type
+    fn check_handler_def(&mut self, handler_def: &x_parser::HandlerDef) {
+        let value_type = self.inference_ctx.fresh_type_var();
+        let return_clause = handler_def.return_clause.as_ref();
+        let handlers = &handler_def.handlers;
+        if let Err(error) = self.inference_ctx.infer_handler_clauses(value_type, handler_def.mode, handlers, return_clause) {
+            let located = std::mem::take(&mut self.inference_ctx.errors);
+            if located.is_empty() {
+                self.error_reporter.report_error(TypeError::InferenceError {
+                    message: format!("Invalid handler {}: {error}", handler_def.name),
+                    symbol: handler_def.name,
+                    span: handler_def.span,
+                });
+            }
+            for error in located {
+                self.error_reporter.report_error(error);
+            }
+        }
     }
 
     fn check_interface_def(&mut self, _interface_def: &x_parser::ComponentInterface) {
         // TODO: Implement interface definition checking
     }
 
-    fn check_module_type_def(&mut self, _module_type_def: &x_parser::ModuleTypeDef) {
-        // TODO: Implement module type definition checking
+    fn check_module_type_def(&mut self, module_type_def: &x_parser::ModuleTypeDef) {
+        let module_type = ModuleType::from_def(module_type_def, |typ| self.convert_parser_type_to_checker_type(typ));
+        self.inference_ctx.module_types.insert(module_type_def.name, module_type);
     }
 
-    fn check_import(&mut self, _import: &x_parser::Import) {
-        // TODO: Implement import checking
+    /// Bring an imported module's values into scope
+    ///
+    /// Modules not registered with `with_module` are left to the build
+    /// pipeline, which resolves project files itself.
+    fn check_import(&mut self, import: &x_parser::Import) {
+        let Some(interface) = self.available_modules.get(&import.module_path.to_string()).cloned() else {
+            // A lazy import may be checked after this module; its uses are
+            // then left unchecked with a warning
+            if import.kind == x_parser::ImportKind::Lazy {
+                if let Some(name) = import.alias.or_else(|| import.module_path.segments.last().copied()) {
+                    let path = Symbol::intern(&import.module_path.to_string());
+                    self.inference_ctx.unloaded_lazy.insert(name, path);
+                }
+            }
+            return;
+        };
+        match &import.kind {
+            x_parser::ImportKind::Selective(items) => {
+                let from = self.inference_ctx.current_module.clone();
+                for item in items {
+                    if item.kind != x_parser::ExportKind::Value {
+                        continue;
+                    }
+                    let name = Symbol::intern(&format!("{}.{}", import.module_path, item.name));
+                    match interface.lookup(item.name, from.as_ref()) {
+                        Some(Ok(scheme)) => {
+                            let constructor = interface.constructors.contains(&item.name);
+                            let local = item.alias.unwrap_or(item.name);
+                            self.bind_import(local, scheme.clone(), constructor);
+                            if let Some(deprecation) = interface.deprecated.get(&item.name) {
+                                self.inference_ctx.env.deprecated.insert(local, deprecation.clone());
+                            }
+                        }
+                        Some(Err(visibility)) => self.error_reporter.report_error(TypeError::InsufficientVisibility {
+                            name,
+                            visibility: describe_visibility(visibility),
+                            required: describe_visibility(&interface.required_visibility(from.as_ref())),
+                            span: item.span,
+                        }),
+                        None => self.error_reporter.report_error(TypeError::UnboundVariable { name, span: item.span }),
+                    }
+                }
+            }
+            x_parser::ImportKind::Wildcard => {
+                let from = self.inference_ctx.current_module.clone();
+                for (name, scheme) in interface.visible_values(from.as_ref()) {
+                    self.bind_import(name, scheme.clone(), interface.constructors.contains(&name));
+                    if let Some(deprecation) = interface.deprecated.get(&name) {
+                        self.inference_ctx.env.deprecated.insert(name, deprecation.clone());
+                    }
+                }
+            }
+            x_parser::ImportKind::Signature(signature) => {
+                let Some(name) = import.alias.or_else(|| import.module_path.segments.last().copied()) else {
+                    return;
+                };
+                let Some(signature_type) = self.inference_ctx.module_types.get(signature).cloned() else {
+                    self.error_reporter.report_error(TypeError::UnboundVariable { name: *signature, span: import.span });
+                    return;
+                };
+                // Only what the signature lists is visible, with its abstract types opaque
+                if let Err(reason) = self.inference_ctx.match_signature(&interface, &signature_type) {
+                    self.error_reporter.report_error(TypeError::SignatureMismatch {
+                        module: name,
+                        signature: *signature,
+                        reason,
+                        span: import.span,
+                    });
+                }
+                let opened = self.inference_ctx.open_signature(name, &signature_type);
+                self.inference_ctx.modules.insert(name, opened);
+            }
+            _ => {
+                let name = import.alias
+                    .or_else(|| import.module_path.segments.last().copied());
+                if let Some(name) = name {
+                    self.inference_ctx.modules.insert(name, interface);
+                }
+            }
+        }
+    }
+
+    fn bind_import(&mut self, name: Symbol, scheme: TypeScheme, constructor: bool) {
+        if constructor {
+            self.inference_ctx.constructors.insert(name, scheme.clone());
+        }
+        self.inference_ctx.env.insert_var(name, scheme);
     }
     
     /// Type check a test definition
@@ -180,7 +1016,7 @@ impl TypeChecker {
                 let bool_type = Type::Con(x_parser::symbol::Symbol::intern("Bool"));
                 let bool_parser_type = x_parser::Type::Con(
                     x_parser::symbol::Symbol::intern("Bool"), 
-                    Span::new(FileId::INVALID, ByteOffset(0), ByteOffset(0))
+                    Span::new(FileId::INVALID, CharOffset(0), CharOffset(0))
                 );
                 
                 if self.check_type_annotation(&inference_result.typ, &bool_parser_type).is_err() {
@@ -199,6 +1035,9 @@ impl TypeChecker {
                     effects: EffectSet::Empty
                 });
                 self.env.bind(test_def.name, test_type);
+                let effects = self.inference_ctx.resolve_effects(&inference_result.effects);
+                self.report_unhandled_effects(&effects, test_def.span);
+                self.definition_effects.insert(test_def.name, effects);
             }
             Err(error) => {
                 self.error_reporter.report_error(TypeError::InferenceError {
@@ -231,6 +1070,24 @@ impl TypeChecker {
         }
     }
 
+    /// Type check a benchmark definition; its body may have any type
+    fn check_bench_def(&mut self, bench_def: &x_parser::BenchDef) {
+        match self.inference_ctx.infer_expr(&bench_def.body) {
+            Ok(inference_result) => {
+                let effects = self.inference_ctx.resolve_effects(&inference_result.effects);
+                self.report_unhandled_effects(&effects, bench_def.span);
+                self.definition_effects.insert(bench_def.name, effects);
+            }
+            Err(error) => {
+                self.error_reporter.report_error(TypeError::InferenceError {
+                    message: error,
+                    symbol: bench_def.name,
+                    span: bench_def.span,
+                });
+            }
+        }
+    }
+
     #[allow(dead_code)]
     fn check_export(&mut self, _export: &x_parser::ExportList) {
         // TODO: Implement export checking
@@ -245,7 +1102,7 @@ impl TypeChecker {
         unifier.unify(inferred, &annotation_type).map_err(|_| TypeError::TypeMismatch {
             expected: annotation_type,
             found: inferred.clone(),
-            span: Span::new(FileId::INVALID, ByteOffset(0), ByteOffset(0)), // TODO: Get proper span
+            span: Span::new(FileId::INVALID, CharOffset(0), CharOffset(0)), // TODO: Get proper span
         })?;
 
         Ok(())
@@ -290,11 +1147,25 @@ impl TypeChecker {
             x_parser::Type::Tuple { types, .. } => {
                 Type::Tuple(types.iter().map(|t| self.convert_parser_type_to_checker_type(t)).collect())
             }
-            x_parser::Type::Record { fields, .. } => {
-                Type::Record(fields.iter().map(|(k, v)| (*k, self.convert_parser_type_to_checker_type(v))).collect())
+            x_parser::Type::Record { fields, rest, .. } => {
+                let fields = fields.iter().map(|(k, v)| (*k, self.convert_parser_type_to_checker_type(v))).collect();
+                // The tail names a row variable whether it was written as a variable or not
+                let rest = match rest.as_deref() {
+                    Some(x_parser::Type::Con(name, span)) => {
+                        self.convert_parser_type_to_checker_type(&x_parser::Type::Var(*name, *span))
+                    }
+                    Some(rest) => self.convert_parser_type_to_checker_type(rest),
+                    None => Type::Record(Vec::new()),
+                };
+                Type::extend_record(fields, &rest)
             }
             x_parser::Type::Variant { variants, .. } => {
-                Type::Variant(variants.iter().map(|(k, v)| (*k, vec![self.convert_parser_type_to_checker_type(v)])).collect())
+                // Sorted, like record fields, so the order does not depend on hashing
+                let mut variants: Vec<_> = variants.iter()
+                    .map(|(k, v)| (*k, vec![self.convert_parser_type_to_checker_type(v)]))
+                    .collect();
+                variants.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+                Type::Variant(variants)
             }
             x_parser::Type::Hole(_) => Type::Hole,
             _ => Type::Unknown,
@@ -302,8 +1173,7 @@ impl TypeChecker {
     }
 
     fn collect_inferred_types(&self) -> HashMap<Symbol, TypeScheme> {
-        // TODO: Collect all inferred types from the environment
-        HashMap::new()
+        self.env.vars.clone()
     }
 
     fn collect_effect_constraints(&self) -> Vec<EffectConstraint> {
@@ -318,6 +1188,111 @@ impl Default for TypeChecker {
     }
 }
 
+/// Pair each type hole `?` of an annotation with the part of the inferred
+/// type it stands for
+fn type_holes(annotation: &x_parser::Type, typ: &Type, holes: &mut Vec<(Span, Type)>) {
+    match (annotation, typ) {
+        (x_parser::Type::Hole(span), typ) => holes.push((*span, typ.clone())),
+        (x_parser::Type::App(con, args, _), Type::App(typ_con, typ_args)) if args.len() == typ_args.len() => {
+            type_holes(con, typ_con, holes);
+            args.iter().zip(typ_args).for_each(|(arg, typ)| type_holes(arg, typ, holes));
+        }
+        (x_parser::Type::Fun { params, return_type, .. }, Type::Fun { params: typ_params, return_type: typ_return, .. })
+            if params.len() == typ_params.len() =>
+        {
+            params.iter().zip(typ_params).for_each(|(param, typ)| type_holes(param, typ, holes));
+            type_holes(return_type, typ_return, holes);
+        }
+        (x_parser::Type::Tuple { types, .. }, Type::Tuple(typ_types)) if types.len() == typ_types.len() => {
+            types.iter().zip(typ_types).for_each(|(annotation, typ)| type_holes(annotation, typ, holes));
+        }
+        _ => {}
+    }
+}
+
+/// Names of the type constructors a type mentions
+fn type_constructor_names(typ: &Type, names: &mut Vec<Symbol>) {
+    match typ {
+        Type::Con(name) => names.push(*name),
+        Type::App(con, args) => {
+            type_constructor_names(con, names);
+            args.iter().for_each(|arg| type_constructor_names(arg, names));
+        }
+        Type::Fun { params, return_type, .. } => {
+            params.iter().for_each(|param| type_constructor_names(param, names));
+            type_constructor_names(return_type, names);
+        }
+        Type::Tuple(types) => types.iter().for_each(|typ| type_constructor_names(typ, names)),
+        _ => {}
+    }
+}
+
+/// Lowercase constructor names, which stand for type variables in instance
+/// heads, GADT constructors and the annotations of definitions using them
+fn type_variable_names(typ: &x_parser::Type) -> Vec<Symbol> {
+    match typ {
+        x_parser::Type::Con(name, _) | x_parser::Type::Var(name, _)
+            if name.as_str().starts_with(|c: char| c.is_lowercase()) => vec![*name],
+        x_parser::Type::App(con, args, _) => std::iter::once(con.as_ref())
+            .chain(args)
+            .flat_map(type_variable_names)
+            .collect(),
+        x_parser::Type::Fun { params, return_type, .. } => params.iter()
+            .chain(std::iter::once(return_type.as_ref()))
+            .flat_map(type_variable_names)
+            .collect(),
+        x_parser::Type::Tuple { types, .. } => types.iter().flat_map(type_variable_names).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Replace constructors named in `subst`, used to turn declared parameters into type variables
+pub(crate) fn replace_constructors(typ: &Type, subst: &HashMap<Symbol, Type>) -> Type {
+    let all = |types: &[Type]| types.iter().map(|t| replace_constructors(t, subst)).collect();
+    match typ {
+        Type::Con(name) => subst.get(name).cloned().unwrap_or_else(|| typ.clone()),
+        Type::App(con, args) => Type::App(Box::new(replace_constructors(con, subst)), all(args)),
+        Type::Fun { params, return_type, effects } => Type::Fun {
+            params: all(params),
+            return_type: Box::new(replace_constructors(return_type, subst)),
+            effects: effects.clone(),
+        },
+        Type::Tuple(types) => Type::Tuple(all(types)),
+        Type::Record(fields) => Type::Record(
+            fields.iter().map(|(name, t)| (*name, replace_constructors(t, subst))).collect(),
+        ),
+        Type::Row { fields, rest } => Type::Row {
+            fields: fields.iter().map(|(name, t)| (*name, replace_constructors(t, subst))).collect(),
+            rest: *rest,
+        },
+        Type::Variant(variants) => Type::Variant(
+            variants.iter().map(|(name, types)| (*name, all(types))).collect(),
+        ),
+        _ => typ.clone(),
+    }
+}
+
+/// Whether importing modules can see an item
+fn is_exported(visibility: &x_parser::Visibility) -> bool {
+    !matches!(visibility, x_parser::Visibility::Private | x_parser::Visibility::SelfModule)
+}
+
+/// Add a value to `interface`, restricted unless it is declared `pub`
+fn export_value(interface: &mut ModuleInterface, name: Symbol, scheme: TypeScheme, visibility: &x_parser::Visibility) {
+    match visibility {
+        x_parser::Visibility::Public | x_parser::Visibility::Component { .. } => {
+            interface.values.insert(name, scheme);
+        }
+        restricted => {
+            interface.restricted.insert(name, (scheme, restricted.clone()));
+        }
+    }
+}
+
+fn is_ambient_effect(name: Symbol) -> bool {
+    name == x_parser::symbol::symbols::IO()
+}
+
 /// Convenience trait for type checking
 pub trait TypeCheck {
     fn type_check(&self) -> CheckResult;
@@ -353,6 +1328,631 @@ mod tests {
         assert!(result.errors.len() >= 0); // Placeholder assertion
     }
 
+    #[test]
+    fn test_cancelled_check() {
+        let source = "module Test\nlet x = 42\nlet y = true";
+        let file_id = FileId::new(0);
+        let cu = parse_source(source, file_id, SyntaxStyle::SExpression).unwrap();
+        
+        let token = CancellationToken::new();
+        let mut checker = TypeChecker::new().with_cancellation(token.clone());
+        assert!(checker.try_check_compilation_unit(&cu).is_ok());
+        
+        token.cancel();
+        let mut checker = TypeChecker::new().with_cancellation(token);
+        assert_eq!(checker.try_check_compilation_unit(&cu).err(), Some(Cancelled));
+    }
+
+    fn unit_with_body(body: x_parser::Expr) -> CompilationUnit {
+        let source = "module Test\nlet x = 42";
+        let mut cu = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        if let Item::ValueDef(value_def) = &mut cu.module.items[0] {
+            value_def.body = body;
+        }
+        cu
+    }
+
+    fn perform_get(span: Span) -> x_parser::Expr {
+        x_parser::Expr::Perform {
+            effect: x_parser::symbol::symbols::STATE(),
+            operation: x_parser::symbol::symbols::GET(),
+            args: vec![],
+            span,
+        }
+    }
+
+    #[test]
+    fn test_unhandled_operation_reports_perform_site() {
+        let perform_span = Span::new(FileId::new(0), CharOffset(11), CharOffset(20));
+        let result = unit_with_body(perform_get(perform_span)).type_check();
+
+        let missing: Vec<_> = result.errors.iter()
+            .filter_map(|error| match error {
+                TypeError::MissingHandler { effect, operation, span } => Some((*effect, *operation, *span)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(missing, vec![(
+            x_parser::symbol::symbols::STATE(),
+            Some(x_parser::symbol::symbols::GET()),
+            perform_span,
+        )]);
+    }
+
+    fn handle_get(mode: x_parser::HandlerMode, span: Span) -> x_parser::Expr {
+        let x_parser::Expr::Handle { handlers, .. } = handle_get_around(perform_get(span), span) else { unreachable!() };
+        x_parser::Expr::Handle { expr: Box::new(perform_get(span)), mode, handlers, return_clause: None, span }
+    }
+
+    /// `body` under a deep handler answering `State.get` with 0
+    fn handle_get_around(body: x_parser::Expr, span: Span) -> x_parser::Expr {
+        let mode = x_parser::HandlerMode::Deep;
+        x_parser::Expr::Handle {
+            expr: Box::new(body),
+            mode,
+            handlers: vec![x_parser::EffectHandler {
+                effect: x_parser::EffectRef {
+                    name: x_parser::symbol::symbols::STATE(),
+                    args: vec![],
+                    span,
+                },
+                operation: x_parser::symbol::symbols::GET(),
+                parameters: vec![],
+                continuation: Some(Symbol::intern("k")),
+                body: x_parser::Expr::Literal(x_parser::Literal::int(0), span),
+                span,
+            }],
+            return_clause: None,
+            span,
+        }
+    }
+
+    #[test]
+    fn test_handled_operation_is_discharged() {
+        let span = Span::new(FileId::new(0), CharOffset(11), CharOffset(20));
+        let result = unit_with_body(handle_get(x_parser::HandlerMode::Deep, span)).type_check();
+
+        assert!(!result.errors.iter().any(|error| matches!(error, TypeError::MissingHandler { .. })));
+        let effects = &result.inferred_effects[&Symbol::intern("x")];
+        assert!(!effects.contains_effect(x_parser::symbol::symbols::STATE()));
+
+        // What a shallow handler resumes runs without it
+        let result = unit_with_body(handle_get(x_parser::HandlerMode::Shallow, span)).type_check();
+        let effects = &result.inferred_effects[&Symbol::intern("x")];
+        assert!(effects.contains_effect(x_parser::symbol::symbols::STATE()));
+    }
+
+    #[test]
+    fn test_pure_definitions_reject_effects_they_reach() {
+        let span = Span::new(FileId::new(0), CharOffset(11), CharOffset(20));
+        let source = "module Test\npure let reads = fun u -> 1\npure let caller = fun u -> reads u\npure let fine = fun u -> u";
+        let mut cu = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        if let Item::ValueDef(value_def) = &mut cu.module.items[0] {
+            value_def.body = x_parser::Expr::Lambda {
+                parameters: vec![x_parser::Pattern::Variable(Symbol::intern("u"), span)],
+                body: Box::new(perform_get(span)),
+                span,
+            };
+        }
+        let result = cu.type_check();
+
+        let impure = result.errors.iter()
+            .filter_map(|error| match error {
+                TypeError::ImpureDefinition { name, effect, span: at, .. } => Some((name.to_string(), effect.to_string(), *at)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(impure, [
+            ("reads".to_string(), "State".to_string(), span),
+            ("caller".to_string(), "State".to_string(), span),
+        ]);
+        assert_eq!(result.errors.iter().find(|error| error.code() == "E0118").map(|error| error.to_string()),
+            Some("reads is declared pure but performs State".to_string()));
+        assert_eq!(result.performed_effects(Symbol::intern("caller")), Some(vec![x_parser::symbol::symbols::STATE()]));
+        assert_eq!(result.performed_effects(Symbol::intern("fine")), Some(vec![]));
+    }
+
+    fn perform_async(operation: &str, argument: x_parser::Expr, span: Span) -> x_parser::Expr {
+        x_parser::Expr::Perform {
+            effect: x_parser::symbol::symbols::ASYNC(),
+            operation: Symbol::intern(operation),
+            args: vec![argument],
+            span,
+        }
+    }
+
+    #[test]
+    fn test_async_is_handled_by_the_host_only_at_main() {
+        let span = Span::new(FileId::new(0), CharOffset(11), CharOffset(20));
+        let task = |body| x_parser::Expr::Lambda {
+            parameters: vec![x_parser::Pattern::Wildcard(span)],
+            body: Box::new(body),
+            span,
+        };
+        let awaited = |body| perform_async("await", perform_async("spawn", task(body), span), span);
+        let one = || x_parser::Expr::Literal(x_parser::Literal::int(1), span);
+        let entry = |name: &str, body| {
+            let mut cu = unit_with_body(body);
+            if let Item::ValueDef(value_def) = &mut cu.module.items[0] {
+                value_def.name = Symbol::intern(name);
+            }
+            cu.type_check()
+        };
+        let missing = |result: &CheckResult| result.errors.iter()
+            .filter_map(|error| match error {
+                TypeError::MissingHandler { effect, .. } => Some(effect.to_string()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let result = entry("main", awaited(one()));
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        assert_eq!(result.inferred_types[&Symbol::intern("main")].body, Type::Con(Symbol::intern("Int")));
+        assert_eq!(missing(&entry("x", awaited(one()))), ["Async", "Async"]);
+
+        // Other effects must be handled inside main, even ones its function type defers
+        assert_eq!(missing(&entry("main", task(awaited(one())))), Vec::<String>::new());
+        assert_eq!(missing(&entry("main", task(perform_get(span)))), ["State"]);
+
+        // A spawned task outlives the handlers around the spawn
+        let result = entry("main", awaited(handle_get(x_parser::HandlerMode::Deep, span)));
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        let result = entry("main", handle_get_around(awaited(perform_get(span)), span));
+        assert!(result.errors.iter().any(|error| error.to_string().contains("Async.spawn cannot perform State")), "{:?}", result.errors);
+    }
+
+    #[test]
+    fn test_shallow_continuations_give_the_computation_value() {
+        let effect = "module Test\neffect State { get : Int put : Int -> Unit }\n";
+        // Deep: the continuation gives what the return clause does
+        let result = check(&format!("{effect}handler deep {{ | State.get with k => k 0 | return x => \"done\" }}"));
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        let result = check(&format!("{effect}handler deep {{ | State.get with k => (k 0, 1) | return x => \"done\" }}"));
+        assert_eq!(result.errors.len(), 1, "{:?}", result.errors);
+
+        // Shallow: it gives the value of the computation itself
+        let result = check(&format!("{effect}shallow handler once {{ | State.get with k => (k 0, 1) | return x => (x, 0) }}"));
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+    }
+
+    const EQ_CLASS: &str = "module Test\nclass Eq[a] {\n  eq : a -> a -> Bool\n}\ninstance Eq[Int] {\n  let eq = fun x -> fun y -> true\n}\n";
+
+    fn check_with_classes(rest: &str) -> CheckResult {
+        let source = format!("{EQ_CLASS}{rest}");
+        parse_source(&source, FileId::new(0), SyntaxStyle::SExpression).unwrap().type_check()
+    }
+
+    #[test]
+    fn test_class_method_resolves_instance() {
+        let result = check_with_classes("let same = eq 1 2");
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+
+        let evidence: Vec<_> = result.class_evidence.values().flatten().collect();
+        assert_eq!(evidence, vec![&Evidence::Instance {
+            dictionary: Symbol::intern("Eq$Int"),
+            args: vec![],
+        }]);
+    }
+
+    #[test]
+    fn test_missing_instance_is_reported() {
+        let result = check_with_classes("let same = eq \"a\" \"b\"");
+        assert!(result.errors.iter().any(|error| matches!(
+            error,
+            TypeError::NoInstance { class, .. } if class.as_str() == "Eq"
+        )));
+    }
+
+    #[test]
+    fn test_constrained_definition_takes_dictionary() {
+        let result = check_with_classes("let same = fun x -> eq x x");
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+
+        let scheme = &result.inferred_types[&Symbol::intern("same")];
+        assert!(matches!(
+            scheme.constraints.as_slice(),
+            [Constraint::Class { class, types }] if class.as_str() == "Eq" && matches!(types[0], Type::Var(_))
+        ));
+        let evidence: Vec<_> = result.class_evidence.values().flatten().collect();
+        assert_eq!(evidence, vec![&Evidence::Param(0)]);
+    }
+
+    fn check(source: &str) -> CheckResult {
+        parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap().type_check()
+    }
+
+    #[test]
+    fn test_projection_is_row_polymorphic() {
+        let result = check(
+            "module Test\nlet get_x = fun r -> r.x\nlet a = get_x { x = 1 }\nlet b = get_x { x = true, y = 2 }",
+        );
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+
+        let int = Type::Con(Symbol::intern("Int"));
+        let boolean = Type::Con(Symbol::intern("Bool"));
+        assert_eq!(result.inferred_types[&Symbol::intern("a")].body, int);
+        assert_eq!(result.inferred_types[&Symbol::intern("b")].body, boolean);
+        assert!(matches!(
+            &result.inferred_types[&Symbol::intern("get_x")].body,
+            Type::Fun { params, .. } if matches!(&params[0], Type::Row { fields, .. } if fields.len() == 1)
+        ));
+    }
+
+    #[test]
+    fn test_operators_apply_curried() {
+        let result = check("module Test\nlet add = fun x -> fun y -> x + y\nlet greet = fun name -> \"Hi \" ^ name");
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        assert!(result.inferred_types[&Symbol::intern("add")].body.to_string().starts_with("Int -> Int -> Int"));
+    }
+
+    #[test]
+    fn test_sections_and_pattern_lambdas_take_arguments_one_at_a_time() {
+        let result = check(
+            "module Test\n\
+             data Option[a] = None | Some a\n\
+             let fold = fun f -> fun acc -> fun x -> f acc x\n\
+             let sum = fold (_ + _) 1 2\n\
+             let pick = fun (Some x) (a, b) -> x + a + b\n\
+             let six = pick (Some 1) (2, 3)",
+        );
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        for name in ["sum", "six"] {
+            assert_eq!(result.inferred_types[&Symbol::intern(name)].body.to_string(), "Int");
+        }
+    }
+
+    #[test]
+    fn test_pipeline_and_composition_are_polymorphic() {
+        let result = check(
+            "module Test\n\
+             let inc = fun x -> x + 1\n\
+             let twice = inc >> inc\n\
+             let shown = string_of_int << inc\n\
+             let piped = 3 |> inc |> string_of_int\n\
+             let then_show = fun f -> f >> string_of_int",
+        );
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        let types = &result.inferred_types;
+        assert!(types[&Symbol::intern("twice")].body.to_string().starts_with("Int -> Int"));
+        assert!(types[&Symbol::intern("shown")].body.to_string().starts_with("Int -> String"));
+        assert_eq!(types[&Symbol::intern("piped")].body.to_string(), "String");
+        // (a -> Int) -> a -> String
+        let Type::Fun { params, .. } = &types[&Symbol::intern("then_show")].body else { panic!("expected a function") };
+        assert!(matches!(&params[0], Type::Fun { return_type, .. } if return_type.to_string() == "Int"), "{:?}", params);
+
+        let result = check("module Test\nlet inc = fun x -> x + 1\nlet wrong = 1 |> string_of_int >> inc");
+        assert!(!result.errors.is_empty());
+    }
+
+    #[test]
+    fn test_missing_field_is_reported() {
+        let result = check("module Test\nlet p = { x = 1 }\nlet q = p.y");
+        assert!(result.errors.iter().any(|error| matches!(
+            error,
+            TypeError::MissingField { field, .. } if field.as_str() == "y"
+        )), "{:?}", result.errors);
+    }
+
+    #[test]
+    fn test_record_update_keeps_type() {
+        let result = check("module Test\nlet p = { x = 1, y = 2 }\nlet q = { p with x = 3 }");
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        assert_eq!(
+            result.inferred_types[&Symbol::intern("q")].body.to_string(),
+            "{x: Int, y: Int}",
+        );
+
+        let result = check("module Test\nlet p = { x = 1 }\nlet q = { p with x = true }");
+        assert!(!result.errors.is_empty());
+    }
+
+    #[test]
+    fn test_annotation_mismatch_points_at_annotation() {
+        let source = "module Test\nlet x : Int = true";
+        let result = check(source);
+        let [error] = result.errors.as_slice() else {
+            panic!("expected one error, got {:?}", result.errors);
+        };
+        let span = error.span();
+        assert_eq!(&source[span.start.0 as usize..span.end.0 as usize], "true");
+        let labels = error.labels();
+        let (annotation, message) = &labels[0];
+        assert_eq!(&source[annotation.start.0 as usize..annotation.end.0 as usize], "Int");
+        assert_eq!(message, "expected because of this annotation");
+    }
+
+    #[test]
+    fn test_tuple_patterns_bind_components() {
+        let result = check(
+            "module Test\n\
+             let swap = fun (a, b) -> (b, a)\n\
+             let first = (let (x, y) = (1, true) in x)\n\
+             let second = match (1, true) with (x, y) => y",
+        );
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        let types = &result.inferred_types;
+        assert_eq!(types[&Symbol::intern("swap")].body.to_string(), "(a1, a2) -> (a2, a1) / e0");
+        assert_eq!(types[&Symbol::intern("first")].body.to_string(), "Int");
+        assert_eq!(types[&Symbol::intern("second")].body.to_string(), "Bool");
+    }
+
+    #[test]
+    fn test_constructors_and_recursion() {
+        let result = check(
+            "module Test\n\
+             data Shape = Dot | Circle Int\n\
+             let size = fun s -> match s with | Dot => 0 | Circle r => r\n\
+             let count = fun n -> if n < 1 then 0 else 1 + count (n - 1)\n\
+             let wrong = size (Circle true)",
+        );
+        let types = &result.inferred_types;
+        assert_eq!(types[&Symbol::intern("Circle")].body.to_string(), "Int -> Shape");
+        assert_eq!(types[&Symbol::intern("size")].body.to_string(), "Shape -> Int / e0");
+        assert!(types[&Symbol::intern("count")].body.to_string().starts_with("Int -> Int / "));
+        assert_eq!(result.errors.len(), 1, "{:?}", result.errors);
+    }
+
+    #[test]
+    fn test_or_patterns_bind_the_same_variables() {
+        let result = check(
+            "module Test\n\
+             data Shape = Dot | Circle Int | Square Int\n\
+             let size = fun s -> match s with | Circle r | Square r => r | Dot => 0",
+        );
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        assert_eq!(result.inferred_types[&Symbol::intern("size")].body.to_string(), "Shape -> Int / e0");
+
+        let result = check(
+            "module Test\n\
+             data Shape = Dot | Circle Int\n\
+             let size = fun s -> match s with | Circle r | Dot => r",
+        );
+        assert_eq!(result.errors.len(), 1, "{:?}", result.errors);
+    }
+
+    #[test]
+    fn test_handlers_are_checked_against_their_effects() {
+        let effect = "module Test\neffect State { get : Int put : Int -> Unit }\n";
+        let result = check(&format!(
+            "{effect}handler counter {{ | State.get with k => k 0 | State.put n => resume () | return x => x }}"
+        ));
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+
+        // Every clause must agree with the operation it handles
+        for clause in [
+            "State.get with k => k true",
+            "State.put n m => resume ()",
+            "State.put n => resume 1",
+            "State.size => 0",
+            "Missing.get => 0",
+        ] {
+            let result = check(&format!("{effect}handler broken {{ | {clause} }}"));
+            assert_eq!(result.errors.len(), 1, "{clause}: {:?}", result.errors);
+        }
+    }
+
+    #[test]
+    fn test_handler_clause_mismatch_points_at_clause_and_declaration() {
+        let source = "module Test\neffect State { get : Int put : Int -> Unit }\nhandler broken { | State.get => 0 | State.put n m => resume () }";
+        let result = check(source);
+        assert_eq!(result.errors.len(), 1, "{:?}", result.errors);
+        let error = &result.errors[0];
+        assert_eq!(error.code(), "E0121");
+        assert!(error.to_string().contains("binds 2 parameter(s), but the operation takes 1"), "{error}");
+        assert_eq!(error.span().start.as_u32() as usize, source.find("State.put n m").unwrap());
+        let labels = error.labels();
+        assert_eq!(labels[0].0.start.as_u32() as usize, source.find("put :").unwrap());
+        assert_eq!(error.notes(), ["State.put : Int -> Unit; its clause binds one parameter per argument and resumes with the result"]);
+
+        let result = check(&source.replace("State.put n m => resume ()", "State.size => 0"));
+        assert_eq!(result.errors[0].code(), "E0108");
+    }
+
+    #[test]
+    fn test_gadt_match_refines_the_annotation() {
+        let source = "module Test\n\
+            data Expr[a] = IntLit Int : Expr[Int] | BoolLit Bool : Expr[Bool] | If (Expr[Bool]) (Expr[a]) (Expr[a]) : Expr[a]\n\
+            let eval : Expr[a] -> a = fun e -> match e with | IntLit n => n | BoolLit b => b | If c t f => if eval c then eval t else eval f\n\
+            let one = eval (If (BoolLit true) (IntLit 1) (IntLit 2))";
+        let result = check(source);
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        assert_eq!(result.inferred_types[&Symbol::intern("one")].body, Type::Con(Symbol::intern("Int")));
+
+        let result = check(&source.replace("BoolLit b => b", "BoolLit b => 0"));
+        assert_eq!(result.errors.len(), 1, "{:?}", result.errors);
+        let result = check(&source.replace("(IntLit 1)", "(BoolLit false)"));
+        assert_eq!(result.errors.len(), 1, "{:?}", result.errors);
+    }
+
+    #[test]
+    fn test_expression_hole_reports_type_locals_and_candidates() {
+        let source = "module Test\nlet zero = 0\nlet name = \"x\"\nlet count : Int = _";
+        let result = check(source);
+        assert_eq!(result.errors.len(), 1, "{:?}", result.errors);
+        let hole = &result.errors[0];
+        assert_eq!(hole.code(), "E0122");
+        assert_eq!(hole.to_string(), "Found hole `_` of type Int");
+        assert_eq!(hole.span().start.as_u32() as usize, source.rfind('_').unwrap());
+        assert_eq!(hole.notes(), ["could be filled with: zero"]);
+
+        let result = check(&source.replace(": Int = _", ": Int -> Int = fun n -> (let pair = (n, true) in _)"));
+        assert_eq!(result.errors[0].notes(), ["local bindings: n : Int, pair : (Int, Bool)", "could be filled with: n, zero"]);
+    }
+
+    #[test]
+    fn test_type_hole_reports_inferred_type() {
+        let source = "module Test\nlet pair : (Int, ?) = (1, true)";
+        let result = check(source);
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        assert_eq!(result.warnings.len(), 1, "{:?}", result.warnings);
+        assert_eq!(result.warnings[0].to_string(), "Type hole `?` stands for Bool");
+        assert_eq!(result.warnings[0].span().start.as_u32() as usize, source.find('?').unwrap());
+    }
+
+    #[test]
+    fn test_gadt_constructor_must_give_its_data_type() {
+        let result = check("module Test\ndata Expr[a] = IntLit Int : List[Int]");
+        assert_eq!(result.errors.len(), 1, "{:?}", result.errors);
+        assert!(result.errors[0].to_string().contains("must give a Expr"), "{}", result.errors[0]);
+    }
+
+    #[test]
+    fn test_continuations_are_classified_by_how_often_they_resume() {
+        let effect = "module Test\neffect Choice { flip : Bool }\n";
+        for (clause, expected) in [
+            ("Choice.flip => false", Resumption::Never),
+            ("Choice.flip with k => k true", Resumption::Tail),
+            ("Choice.flip => if true then resume true else resume false", Resumption::Tail),
+            ("Choice.flip with k => (let x = k true in x)", Resumption::Once),
+            ("Choice.flip with k => (k true, k false)", Resumption::Many),
+            ("Choice.flip with k => fun x -> k x", Resumption::Many),
+            ("Choice.flip with k => k", Resumption::Many),
+            ("Choice.flip with k => (let k = fun x -> x in (k 1, k 2))", Resumption::Never),
+        ] {
+            let result = check(&format!("{effect}handler choose {{ | {clause} }}"));
+            let classes = result.continuations.values().copied().collect::<Vec<_>>();
+            assert_eq!(classes, [expected], "{clause}");
+        }
+    }
+
+    #[test]
+    fn test_imports_resolve_registered_modules() {
+        let library = parse_source(
+            "module Lib\npub data Box[a] = Box a\npub let unbox = fun b -> match b with | Box x => x\nlet hidden = 1",
+            FileId::new(1),
+            SyntaxStyle::SExpression,
+        ).unwrap();
+        let interface = library.type_check().module_interface(&library.module);
+        assert!(interface.constructors.contains(&Symbol::intern("Box")));
+        assert!(!interface.values.contains_key(&Symbol::intern("hidden")));
+
+        let unit = parse_source(
+            "module Main\nimport Lib\nimport Lib { Box as Wrap }\nlet n = Lib.unbox (Wrap 1)\nlet h = Lib.hidden",
+            FileId::new(0),
+            SyntaxStyle::SExpression,
+        ).unwrap();
+        let result = TypeChecker::new().with_module("Lib", interface).check_compilation_unit(&unit);
+        assert_eq!(result.inferred_types[&Symbol::intern("n")].body.to_string(), "Int");
+        assert_eq!(result.errors.len(), 1);
+        assert!(result.errors[0].to_string().contains("Lib.hidden"));
+    }
+
+    #[test]
+    fn test_imports_carry_operator_fixities() {
+        let library = parse_source(
+            "module Lib\ninfixr 5 <+> <->\npub let (<+>) = fun a b -> a - b",
+            FileId::new(1),
+            SyntaxStyle::SExpression,
+        ).unwrap();
+        let interface = library.type_check().module_interface(&library.module);
+        assert_eq!(interface.fixities.keys().collect::<Vec<_>>(), [&Symbol::intern("<+>")]);
+
+        let source = "module Main\nimport Lib { (<+>) as (<&>) }\nlet n = 10 <&> 4 <&> 1";
+        let unit = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let checker = TypeChecker::new().with_module("Lib", interface);
+        let fixities = checker.imported_fixities(&unit.module);
+        let unit = x_parser::Parser::new(source, FileId::new(0)).unwrap().with_fixities(fixities).parse().unwrap();
+        let Item::ValueDef(def) = &unit.module.items[0] else { unreachable!() };
+        // `10 <&> (4 <&> 1)`
+        let x_parser::Expr::App(_, args, _) = &def.body else { unreachable!() };
+        assert!(matches!(args[1], x_parser::Expr::App(..)));
+    }
+
+    #[test]
+    fn test_unloaded_lazy_imports_warn_at_use() {
+        let source = "module Main\nlazy import Reports.Pdf as Pdf\nlet render = fun doc -> Pdf.render doc";
+        let unit = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let result = TypeChecker::new().check_compilation_unit(&unit);
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        assert_eq!(result.warnings.len(), 1);
+        assert_eq!(result.warnings[0].code(), "W0101");
+        assert!(result.warnings[0].to_string().starts_with("Pdf.render comes from lazy import Reports.Pdf"));
+
+        let library = parse_source("module Reports.Pdf
+pub let render = fun doc -> doc", FileId::new(1), SyntaxStyle::SExpression).unwrap();
+        let interface = library.type_check().module_interface(&library.module);
+        let result = TypeChecker::new().with_module("Reports.Pdf", interface).check_compilation_unit(&unit);
+        assert!(result.errors.is_empty() && result.warnings.is_empty(), "{:?}", result.warnings);
+    }
+
+    #[test]
+    fn test_deprecated_definitions_warn_at_use() {
+        let result = check(
+            "module Test\n```\n---\n@deprecated \"use bar instead\"\n---\n```\nlet foo = fun n -> if n == 0 then 0 else foo (n - 1)\n\
+             let bar = fun n -> n\nlet a = foo 1\nlet b = fun foo -> foo + 1",
+        );
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        let warnings: Vec<_> = result.warnings.iter().map(|warning| (warning.code(), warning.to_string(), warning.notes())).collect();
+        // Neither the recursive call nor the shadowing parameter is warned about
+        assert_eq!(warnings, [("W0103", "foo is deprecated".to_string(), vec!["use bar instead".to_string()])]);
+
+        let library = parse_source(
+            "module Lib\n```\n---\n@deprecated true\n---\n```\npub let old = 1",
+            FileId::new(1),
+            SyntaxStyle::SExpression,
+        ).unwrap();
+        let interface = library.type_check().module_interface(&library.module);
+        let unit = parse_source(
+            "module Main\nimport Lib\nimport Lib { old as legacy }\nlet n = Lib.old + legacy",
+            FileId::new(0),
+            SyntaxStyle::SExpression,
+        ).unwrap();
+        let result = TypeChecker::new().with_module("Lib", interface).check_compilation_unit(&unit);
+        let warnings: Vec<_> = result.warnings.iter().map(|warning| warning.to_string()).collect();
+        assert_eq!(warnings, ["Lib.old is deprecated", "legacy is deprecated"]);
+        assert!(result.warnings.iter().all(|warning| warning.notes().is_empty()));
+    }
+
+    #[test]
+    fn test_imports_respect_visibility() {
+        let library = parse_source(
+            "module App.Util.Strings\n\
+             pub(crate) let shared = 1\n\
+             pub(super) let nearby = 2\n\
+             pub(in App.Web) let web = 3\n\
+             let private = 4",
+            FileId::new(1),
+            SyntaxStyle::SExpression,
+        ).unwrap();
+        let interface = library.type_check().module_interface(&library.module);
+        let check = |source: &str| {
+            let unit = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+            TypeChecker::new().with_module("App.Util.Strings", interface.clone()).check_compilation_unit(&unit)
+        };
+
+        let result = check("module App.Util.Main\nimport App.Util.Strings\nimport App.Util.Strings { shared, nearby }\nlet total = shared + nearby + Strings.web");
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].code(), "E0120", "{:?}", result.errors);
+        assert_eq!(result.errors[0].to_string(), "Strings.web is pub(in App.Web) and not visible here; it must be pub(super)");
+
+        let result = check("module App.Web.Server\nimport App.Util.Strings { shared, web }\nlet total = shared + web");
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+
+        let result = check("module Other\nimport App.Util.Strings { shared, private }");
+        let messages: Vec<_> = result.errors.iter().map(|error| error.to_string()).collect();
+        assert_eq!(messages, [
+            "App.Util.Strings.shared is pub(crate) and not visible here; it must be pub",
+            "App.Util.Strings.private is private and not visible here; it must be pub",
+        ]);
+    }
+
+    #[test]
+    fn test_export_lists_follow_visibility() {
+        let library = parse_source(
+            "module Lib export { shown, renamed(alias), secret }\npub let shown = 1\npub let renamed = 2\nlet secret = 3\npub let unlisted = 4",
+            FileId::new(1),
+            SyntaxStyle::SExpression,
+        ).unwrap();
+        let result = library.type_check();
+        assert_eq!(result.errors.iter().map(|error| error.to_string()).collect::<Vec<_>>(),
+            ["secret is private and not visible here; it must be pub"]);
+
+        let interface = result.module_interface(&library.module);
+        let mut names: Vec<_> = interface.values.keys().map(|name| name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, ["alias", "shown"]);
+    }
+
     #[test]
     fn test_type_check_trait() {
         let source = "module Test\nlet x = true";
@@ -1,9 +1,9 @@
 //! Constraint generation and solving for type inference
 
 use crate::{
-    types::{Type, TypeVar, TypeScheme, Effect, EffectSet},
+    types::{Type, TypeVar, TypeScheme, Effect, EffectSet, Constraint, Instance},
 };
-use x_parser::{Symbol, Span};
+use x_parser::{Symbol, Span, InstanceDef, Type as AstType};
 use std::collections::HashMap;
 
 /// Type constraint for constraint-based type inference
@@ -290,6 +290,164 @@ impl Default for ConstraintSolver {
     }
 }
 
+/// Dictionary evidence for a solved class constraint
+///
+/// Lowering passes a dictionary wherever an overloaded name is used, so
+/// backends only ever see ordinary values and calls.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Evidence {
+    /// An instance's dictionary, applied to the dictionaries its context requires
+    Instance {
+        dictionary: Symbol,
+        args: Vec<Evidence>,
+    },
+    /// The enclosing definition's dictionary parameter at this position
+    Param(usize),
+}
+
+/// Solves class constraints against the declared instances
+///
+/// Constraints on type variables cannot be decided locally; they are matched
+/// against the givens (the enclosing definition's dictionary parameters) and,
+/// when `abstract_vars` is set, added to them.
+pub struct ClassSolver<'a> {
+    instances: &'a HashMap<Symbol, Vec<Instance>>,
+    givens: Vec<Constraint>,
+    abstract_vars: bool,
+}
+
+impl<'a> ClassSolver<'a> {
+    pub fn new(instances: &'a HashMap<Symbol, Vec<Instance>>, givens: Vec<Constraint>, abstract_vars: bool) -> Self {
+        Self { instances, givens, abstract_vars }
+    }
+
+    /// Find the dictionary that proves `class types`, if any instance or given does
+    pub fn entail(&mut self, class: Symbol, types: &[Type]) -> Option<Evidence> {
+        let wanted = Constraint::Class { class, types: types.to_vec() };
+        if let Some(index) = self.givens.iter().position(|given| *given == wanted) {
+            return Some(Evidence::Param(index));
+        }
+
+        let instances = self.instances;
+        if let Some((instance, subst)) = instances.get(&class).into_iter().flatten()
+            .find_map(|instance| match_instance(instance, types).map(|subst| (instance, subst)))
+        {
+            let mut args = Vec::new();
+            for constraint in &instance.constraints {
+                if let Constraint::Class { class, types } = constraint {
+                    let types: Vec<Type> = types.iter().map(|t| substitute_vars(t, &subst)).collect();
+                    args.push(self.entail(*class, &types)?);
+                }
+            }
+            return Some(Evidence::Instance { dictionary: instance.dictionary, args });
+        }
+
+        if self.abstract_vars && types.iter().any(|t| matches!(type_head(t), Type::Var(_))) {
+            self.givens.push(wanted);
+            return Some(Evidence::Param(self.givens.len() - 1));
+        }
+
+        None
+    }
+
+    /// The dictionary parameters the definition ends up taking
+    pub fn into_givens(self) -> Vec<Constraint> {
+        self.givens
+    }
+}
+
+/// Name of the dictionary an instance declaration is lowered to, e.g. `Eq$List`
+pub fn instance_dictionary_name(def: &InstanceDef) -> Symbol {
+    fn head(typ: &AstType) -> String {
+        match typ {
+            AstType::Con(name, _) | AstType::Var(name, _) => name.as_str().to_string(),
+            AstType::App(con, _, _) => head(con),
+            AstType::Tuple { .. } => "Tuple".to_string(),
+            AstType::Fun { .. } => "Fun".to_string(),
+            AstType::Record { .. } => "Record".to_string(),
+            _ => "_".to_string(),
+        }
+    }
+    let heads: Vec<String> = def.types.iter().map(head).collect();
+    Symbol::intern(&format!("{}${}", def.class.as_str(), heads.join("$")))
+}
+
+/// Outermost constructor of a type application
+fn type_head(typ: &Type) -> &Type {
+    match typ {
+        Type::App(con, _) => type_head(con),
+        _ => typ,
+    }
+}
+
+/// One-way match of an instance head against the wanted types
+fn match_instance(instance: &Instance, types: &[Type]) -> Option<HashMap<TypeVar, Type>> {
+    let Constraint::Class { types: head, .. } = &instance.head else {
+        return None;
+    };
+    if head.len() != types.len() {
+        return None;
+    }
+    let mut subst = HashMap::new();
+    head.iter()
+        .zip(types)
+        .all(|(pattern, target)| match_type(pattern, target, &mut subst))
+        .then_some(subst)
+}
+
+fn match_type(pattern: &Type, target: &Type, subst: &mut HashMap<TypeVar, Type>) -> bool {
+    match (pattern, target) {
+        (Type::Var(var), _) => match subst.get(var) {
+            Some(bound) => bound == target,
+            None => {
+                subst.insert(*var, target.clone());
+                true
+            }
+        },
+        (Type::Con(a), Type::Con(b)) => a == b,
+        (Type::App(c1, args1), Type::App(c2, args2)) => {
+            args1.len() == args2.len()
+                && match_type(c1, c2, subst)
+                && args1.iter().zip(args2).all(|(a, b)| match_type(a, b, subst))
+        }
+        (Type::Tuple(ts1), Type::Tuple(ts2)) => {
+            ts1.len() == ts2.len() && ts1.iter().zip(ts2).all(|(a, b)| match_type(a, b, subst))
+        }
+        (Type::Fun { params: p1, return_type: r1, .. }, Type::Fun { params: p2, return_type: r2, .. }) => {
+            p1.len() == p2.len()
+                && p1.iter().zip(p2).all(|(a, b)| match_type(a, b, subst))
+                && match_type(r1, r2, subst)
+        }
+        _ => false,
+    }
+}
+
+/// Replace type variables, leaving unmapped ones in place
+pub(crate) fn substitute_vars(typ: &Type, subst: &HashMap<TypeVar, Type>) -> Type {
+    let all = |types: &[Type]| types.iter().map(|t| substitute_vars(t, subst)).collect();
+    match typ {
+        Type::Var(var) => subst.get(var).cloned().unwrap_or_else(|| typ.clone()),
+        Type::App(con, args) => Type::App(Box::new(substitute_vars(con, subst)), all(args)),
+        Type::Tuple(types) => Type::Tuple(all(types)),
+        Type::Fun { params, return_type, effects } => Type::Fun {
+            params: all(params),
+            return_type: Box::new(substitute_vars(return_type, subst)),
+            effects: effects.clone(),
+        },
+        Type::Record(fields) => Type::Record(
+            fields.iter().map(|(name, t)| (*name, substitute_vars(t, subst))).collect(),
+        ),
+        Type::Row { fields, rest } => Type::extend_record(
+            fields.iter().map(|(name, t)| (*name, substitute_vars(t, subst))).collect(),
+            subst.get(rest).unwrap_or(&Type::Var(*rest)),
+        ),
+        Type::Variant(variants) => Type::Variant(
+            variants.iter().map(|(name, types)| (*name, all(types))).collect(),
+        ),
+        _ => typ.clone(),
+    }
+}
+
 /// Result of constraint solving
 #[derive(Debug, Clone)]
 pub struct Substitution {
@@ -436,14 +594,14 @@ impl std::error::Error for ConstraintError {}
 mod tests {
     use super::*;
     use crate::types::TypeVar;
-    use x_parser::{FileId, span::ByteOffset};
+    use x_parser::{FileId, span::CharOffset};
 
     #[test]
     fn test_constraint_set_creation() {
         let mut constraints = ConstraintSet::new();
         assert!(constraints.is_empty());
 
-        let span = Span::new(FileId(u32::MAX), ByteOffset(0), ByteOffset(0));
+        let span = Span::new(FileId(u32::MAX), CharOffset(0), CharOffset(0));
         constraints.equal(Type::Con(Symbol::intern("Int")), Type::Con(Symbol::intern("Int")), span);
         assert!(!constraints.is_empty());
         assert_eq!(constraints.type_constraints.len(), 1);
@@ -0,0 +1,296 @@
+//! Continuation usage analysis
+//!
+//! Classifies how often each operation clause can resume its continuation.
+//! Backends whose runtime keeps a single stack per handled computation can
+//! only run clauses that resume at most once; the interpreter copies
+//! continuations and runs the rest.
+
+use x_parser::{DoStatement, EffectHandler, Expr, Item, Module, Pattern, Span, Symbol};
+use std::collections::HashMap;
+
+/// How an operation clause uses its continuation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Resumption {
+    /// Never resumed: the clause aborts the handled computation
+    Never,
+    /// Resumed once, as the clause's result
+    Tail,
+    /// Resumed at most once, with the clause continuing afterwards
+    Once,
+    /// Possibly resumed more than once, or escaping as a value
+    Many,
+}
+
+impl Resumption {
+    /// Whether a one-shot continuation is enough to run the clause
+    pub fn is_one_shot(self) -> bool {
+        self != Resumption::Many
+    }
+}
+
+/// Classify the continuation of one operation clause
+pub fn classify(clause: &EffectHandler) -> Resumption {
+    let shadowed = clause.continuation.is_some_and(|k| clause.parameters.iter().any(|p| binds(p, k)));
+    let uses = Uses {
+        continuation: clause.continuation.filter(|_| !shadowed),
+        resume: true,
+    };
+    match uses.count(&clause.body, true) {
+        Count::Zero => Resumption::Never,
+        Count::One { tail: true } => Resumption::Tail,
+        Count::One { tail: false } => Resumption::Once,
+        Count::Many => Resumption::Many,
+    }
+}
+
+/// Classify every operation clause in a module, keyed by the clause's span
+pub fn classify_module(module: &Module) -> HashMap<Span, Resumption> {
+    module.items.iter().flat_map(classify_item).collect()
+}
+
+/// Classify the operation clauses of one item, keyed by the clause's span
+pub fn classify_item(item: &Item) -> HashMap<Span, Resumption> {
+    let mut clauses = Vec::new();
+    match item {
+        Item::ValueDef(def) => collect_clauses(&def.body, &mut clauses),
+        Item::HandlerDef(def) => {
+            for clause in &def.handlers {
+                clauses.push(clause);
+                collect_clauses(&clause.body, &mut clauses);
+            }
+            if let Some(ret) = &def.return_clause {
+                collect_clauses(&ret.body, &mut clauses);
+            }
+        }
+        Item::TestDef(def) => {
+            for expr in def.setup.iter().chain(&def.teardown) {
+                collect_clauses(expr, &mut clauses);
+            }
+            collect_clauses(&def.body, &mut clauses);
+        }
+        Item::BenchDef(def) => collect_clauses(&def.body, &mut clauses),
+        Item::InstanceDef(def) => {
+            for method in &def.methods {
+                collect_clauses(&method.body, &mut clauses);
+            }
+        }
+        _ => {}
+    }
+    clauses.into_iter().map(|clause| (clause.span, classify(clause))).collect()
+}
+
+/// The clauses of every `handle` expression nested in `expr`
+fn collect_clauses<'a>(expr: &'a Expr, out: &mut Vec<&'a EffectHandler>) {
+    match expr {
+        Expr::Literal(..) | Expr::Var(..) | Expr::Pack { .. } | Expr::Error(_) => {}
+        Expr::App(func, args, _) => {
+            collect_clauses(func, out);
+            args.iter().for_each(|arg| collect_clauses(arg, out));
+        }
+        Expr::Lambda { body, .. } => collect_clauses(body, out),
+        Expr::Let { value, body, .. } => {
+            collect_clauses(value, out);
+            collect_clauses(body, out);
+        }
+        Expr::If { condition, then_branch, else_branch, .. } => {
+            collect_clauses(condition, out);
+            collect_clauses(then_branch, out);
+            collect_clauses(else_branch, out);
+        }
+        Expr::Match { scrutinee, arms, .. } => {
+            collect_clauses(scrutinee, out);
+            for arm in arms {
+                if let Some(guard) = &arm.guard {
+                    collect_clauses(guard, out);
+                }
+                collect_clauses(&arm.body, out);
+            }
+        }
+        Expr::Do { statements, .. } => {
+            for statement in statements {
+                match statement {
+                    DoStatement::Let { expr, .. } | DoStatement::Bind { expr, .. } | DoStatement::Expr(expr) => {
+                        collect_clauses(expr, out)
+                    }
+                }
+            }
+        }
+        Expr::Handle { expr, handlers, return_clause, .. } => {
+            collect_clauses(expr, out);
+            for clause in handlers {
+                out.push(clause);
+                collect_clauses(&clause.body, out);
+            }
+            if let Some(ret) = return_clause {
+                collect_clauses(&ret.body, out);
+            }
+        }
+        Expr::Resume { value, .. } => collect_clauses(value, out),
+        Expr::Perform { args, .. } => args.iter().for_each(|arg| collect_clauses(arg, out)),
+        Expr::Ann { expr, .. } => collect_clauses(expr, out),
+        Expr::Record { fields, .. } => fields.iter().for_each(|(_, value)| collect_clauses(value, out)),
+        Expr::Project { record, .. } => collect_clauses(record, out),
+        Expr::RecordUpdate { record, fields, .. } => {
+            collect_clauses(record, out);
+            fields.iter().for_each(|(_, value)| collect_clauses(value, out));
+        }
+        Expr::Tuple { elements, .. } => elements.iter().for_each(|element| collect_clauses(element, out)),
+        Expr::Unpack { value, body, .. } => {
+            collect_clauses(value, out);
+            collect_clauses(body, out);
+        }
+    }
+}
+
+/// Resumptions along one evaluation of an expression
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Count {
+    Zero,
+    One { tail: bool },
+    Many,
+}
+
+impl Count {
+    /// Resumptions of evaluating `self`, then `other`
+    fn then(self, other: Count) -> Count {
+        match (self, other) {
+            (Count::Zero, count) | (count, Count::Zero) => count,
+            _ => Count::Many,
+        }
+    }
+
+    /// Resumptions of evaluating either `self` or `other`
+    fn or(self, other: Count) -> Count {
+        match (self, other) {
+            (Count::Many, _) | (_, Count::Many) => Count::Many,
+            (Count::Zero, count) | (count, Count::Zero) => count,
+            (Count::One { tail: a }, Count::One { tail: b }) => Count::One { tail: a && b },
+        }
+    }
+
+    /// Resumptions of code that may run any number of times, like a lambda body
+    fn repeated(self) -> Count {
+        if self == Count::Zero { Count::Zero } else { Count::Many }
+    }
+}
+
+/// What counts as resuming within the clause body being walked
+#[derive(Debug, Clone, Copy)]
+struct Uses {
+    /// The continuation variable, unless shadowed
+    continuation: Option<Symbol>,
+    /// Whether `resume` still refers to this clause
+    resume: bool,
+}
+
+impl Uses {
+    fn shadowed_by(self, pattern: &Pattern) -> Uses {
+        Uses {
+            continuation: self.continuation.filter(|k| !binds(pattern, *k)),
+            ..self
+        }
+    }
+
+    fn sequence<'a>(self, exprs: impl IntoIterator<Item = &'a Expr>) -> Count {
+        exprs.into_iter().fold(Count::Zero, |count, expr| count.then(self.count(expr, false)))
+    }
+
+    fn count(self, expr: &Expr, tail: bool) -> Count {
+        match expr {
+            Expr::Literal(..) | Expr::Pack { .. } | Expr::Error(_) => Count::Zero,
+            // The continuation used as a value may be called any number of times
+            Expr::Var(name, _) if Some(*name) == self.continuation => Count::Many,
+            Expr::Var(..) => Count::Zero,
+            Expr::App(func, args, _) => match func.as_ref() {
+                Expr::Var(name, _) if Some(*name) == self.continuation && args.len() == 1 => {
+                    self.count(&args[0], false).then(Count::One { tail })
+                }
+                _ => self.count(func, false).then(self.sequence(args)),
+            },
+            Expr::Lambda { parameters, body, .. } => {
+                let uses = parameters.iter().fold(self, |uses, p| uses.shadowed_by(p));
+                uses.count(body, true).repeated()
+            }
+            Expr::Let { pattern, value, body, .. } => {
+                self.count(value, false).then(self.shadowed_by(pattern).count(body, tail))
+            }
+            Expr::If { condition, then_branch, else_branch, .. } => {
+                self.count(condition, false).then(self.count(then_branch, tail).or(self.count(else_branch, tail)))
+            }
+            Expr::Match { scrutinee, arms, .. } => {
+                let arms = arms.iter().fold(Count::Zero, |count, arm| {
+                    let uses = self.shadowed_by(&arm.pattern);
+                    let guard = arm.guard.as_ref().map_or(Count::Zero, |guard| uses.count(guard, false));
+                    count.or(guard.then(uses.count(&arm.body, tail)))
+                });
+                self.count(scrutinee, false).then(arms)
+            }
+            Expr::Do { statements, .. } => {
+                let mut uses = self;
+                let mut count = Count::Zero;
+                for statement in statements {
+                    match statement {
+                        DoStatement::Let { pattern, expr, .. } | DoStatement::Bind { pattern, expr, .. } => {
+                            count = count.then(uses.count(expr, false));
+                            uses = uses.shadowed_by(pattern);
+                        }
+                        DoStatement::Expr(expr) => count = count.then(uses.count(expr, false)),
+                    }
+                }
+                count
+            }
+            Expr::Handle { expr, handlers, return_clause, .. } => {
+                // A nested handler that copies its continuation may run the
+                // handled expression, and any resumption in it, again
+                let body = self.count(expr, false);
+                let body = if handlers.iter().all(|clause| classify(clause).is_one_shot()) {
+                    body
+                } else {
+                    body.repeated()
+                };
+                let clauses = handlers.iter().fold(Count::Zero, |count, clause| {
+                    let uses = Uses {
+                        continuation: self.continuation.filter(|k| {
+                            clause.continuation != Some(*k) && !clause.parameters.iter().any(|p| binds(p, *k))
+                        }),
+                        resume: false,
+                    };
+                    count.then(uses.count(&clause.body, false).repeated())
+                });
+                let ret = return_clause.as_ref().map_or(Count::Zero, |ret| {
+                    self.shadowed_by(&ret.parameter).count(&ret.body, false)
+                });
+                body.then(clauses).then(ret)
+            }
+            Expr::Resume { value, .. } => {
+                let value = self.count(value, false);
+                if self.resume { value.then(Count::One { tail }) } else { value }
+            }
+            Expr::Perform { args, .. } => self.sequence(args),
+            Expr::Ann { expr, .. } => self.count(expr, tail),
+            Expr::Record { fields, .. } => self.sequence(fields.iter().map(|(_, value)| value)),
+            Expr::Project { record, .. } => self.count(record, false),
+            Expr::RecordUpdate { record, fields, .. } => {
+                self.count(record, false).then(self.sequence(fields.iter().map(|(_, value)| value)))
+            }
+            Expr::Tuple { elements, .. } => self.sequence(elements),
+            Expr::Unpack { value, body, .. } => self.count(value, false).then(self.count(body, tail)),
+        }
+    }
+}
+
+/// Whether `pattern` binds `name`
+fn binds(pattern: &Pattern, name: Symbol) -> bool {
+    match pattern {
+        Pattern::Variable(var, _) => *var == name,
+        Pattern::Wildcard(_) | Pattern::Literal(..) => false,
+        Pattern::Constructor { args, .. } => args.iter().any(|p| binds(p, name)),
+        Pattern::Record { fields, rest, .. } => {
+            fields.values().any(|p| binds(p, name)) || rest.as_ref().is_some_and(|p| binds(p, name))
+        }
+        Pattern::Tuple { patterns, .. } => patterns.iter().any(|p| binds(p, name)),
+        Pattern::Or { left, .. } => binds(left, name),
+        Pattern::As { pattern, name: alias, .. } => *alias == name || binds(pattern, name),
+        Pattern::Ann { pattern, .. } => binds(pattern, name),
+    }
+}
@@ -0,0 +1,738 @@
+//! Incremental type checking with salsa
+//!
+//! The source text of each module is the input. A module is parsed whole,
+//! but each of its items is checked by its own query, keyed by a hash of
+//! the item's text rather than its position, with spans made relative to
+//! the item's start. An item moved by an edit elsewhere is then the same
+//! query with the same syntax, and its check is reused.
+//!
+//! Items see the module as the whole-module checker does: the module types
+//! and imports, every type, effect, class and instance declared before
+//! them, and the latest earlier definition of each name they use. Those
+//! definitions are an item's dependencies; its check runs again only when
+//! its own text or what its dependencies bind changes.
+//!
+//! ```ignore
+//! let mut db = TypeCheckDatabaseImpl::new();
+//! db.set_source(file_id, source);
+//! let check = db.check_result(file_id)?;
+//! ```
+//!
+//! `check_definition` checks one definition and what it transitively
+//! depends on, leaving the rest of the module unchecked.
+//!
+//! Checks poll both salsa's cancellation, raised when an input is set while
+//! a snapshot is checking on another thread, and the database's
+//! `CancellationToken`; either unwinds the check, which `check_result`
+//! reports as `Cancelled`.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use salsa::{ParallelDatabase, Snapshot};
+use x_parser::dependency::DependencyManager;
+use x_parser::span::CharOffset;
+use x_parser::{CancellationToken, Cancelled, CompilationUnit, Deprecation, Expr, FileId, Item, Module, Parser, Span, Symbol, VisitSpans};
+
+use crate::checker::{CheckResult, TypeChecker};
+use crate::constraints::Evidence;
+use crate::continuations::{self, Resumption};
+use crate::error_reporting::TypeError;
+use crate::types::{self, EffectSet, ModuleInterface, TypeEnv, TypeScheme};
+
+/// The token checks poll besides salsa's own cancellation
+pub trait CheckCancellation {
+    fn cancellation(&self) -> &CancellationToken;
+}
+
+#[salsa::query_group(TypeCheckDatabase)]
+pub trait TypeCheckDb: salsa::Database + CheckCancellation {
+    /// Source of a module
+    #[salsa::input]
+    fn module_source(&self, file: FileId) -> ModuleSource;
+
+    /// Modules available to imports, by path
+    #[salsa::input]
+    fn module_interfaces(&self) -> Arc<Vec<(String, ModuleInterface)>>;
+
+    /// Type of a name defined outside the modules in the database
+    #[salsa::input]
+    fn symbol_type(&self, symbol: Symbol) -> Option<TypeScheme>;
+
+    /// Names given a type with `symbol_type`
+    #[salsa::input]
+    fn external_symbols(&self) -> Arc<Vec<Symbol>>;
+
+    /// The module and its items, or `None` if it does not parse
+    fn parse_module(&self, file: FileId) -> Option<Arc<ParsedModule>>;
+
+    /// Items in source order
+    fn item_keys(&self, file: FileId) -> Arc<Vec<ItemKey>>;
+
+    /// An item, with spans relative to its start
+    fn item_syntax(&self, file: FileId, key: ItemKey) -> Option<ItemSyntax>;
+
+    /// Names an item uses that may be defined by earlier items
+    fn item_references(&self, file: FileId, key: ItemKey) -> Arc<Vec<Symbol>>;
+
+    /// The module types and imports every item sees, without spans
+    fn module_header(&self, file: FileId) -> Option<ItemSyntax<Module>>;
+
+    /// Dependencies of every item
+    fn module_dependencies(&self, file: FileId) -> Arc<HashMap<ItemKey, Arc<Vec<ItemKey>>>>;
+
+    /// Earlier items an item is checked against: the declarations before
+    /// it, in order, and the definitions of the names it uses
+    fn item_dependencies(&self, file: FileId, key: ItemKey) -> Arc<Vec<ItemKey>>;
+
+    /// The declarations among an item's dependencies, with spans in no file
+    /// so they cannot be taken for the item's own
+    fn item_declarations(&self, file: FileId, key: ItemKey) -> Arc<Vec<ItemSyntax>>;
+
+    /// What the definitions an item uses bind
+    ///
+    /// Unchanged when a definition's text changes but not its type, so the
+    /// items using it are not checked again.
+    fn item_environment(&self, file: FileId, key: ItemKey) -> Arc<Vec<(Symbol, TypeScheme)>>;
+
+    /// The definitions an item uses that are documented `@deprecated`
+    fn item_deprecations(&self, file: FileId, key: ItemKey) -> Arc<Vec<(Symbol, Deprecation)>>;
+
+    /// Check an item against its dependencies
+    fn check_item(&self, file: FileId, key: ItemKey) -> Arc<ItemCheck>;
+
+    /// Check the export list and imports, then gather the item checks
+    fn check_module(&self, file: FileId) -> Arc<ItemCheck>;
+
+    /// Check the latest definition of `name` and its dependencies,
+    /// transitively, or `None` if the module does not define it
+    fn check_definition(&self, file: FileId, name: Symbol) -> Option<Arc<ItemCheck>>;
+
+    /// Check if two types are compatible
+    fn types_compatible(&self, t1: types::Type, t2: types::Type) -> bool;
+}
+
+/// Source of a module: its text, or a unit parsed elsewhere
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModuleSource {
+    Text(Arc<String>),
+    Parsed(ItemSyntax<CompilationUnit>),
+}
+
+/// An item, identified by a hash of its text and how many items with the
+/// same text come before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ItemKey {
+    pub hash: u64,
+    pub occurrence: u32,
+}
+
+/// Syntax shared between queries
+///
+/// `Eq` so salsa can tell an unchanged item from a changed one; a NaN
+/// literal compares unequal to itself, which only costs a recheck.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ItemSyntax<T = Item>(pub Arc<T>);
+
+impl<T: PartialEq> Eq for ItemSyntax<T> {}
+
+/// A parsed module, with each item's key, start and relative syntax
+#[derive(Debug, PartialEq)]
+pub struct ParsedModule {
+    pub unit: CompilationUnit,
+    pub items: Vec<(ItemKey, CharOffset, ItemSyntax)>,
+    index: HashMap<ItemKey, usize>,
+}
+
+impl Eq for ParsedModule {}
+
+/// What checking one item found, with spans relative to the item, or for
+/// `check_module` the whole module with spans in its source
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ItemCheck {
+    /// Names bound, with their schemes; later bindings shadow earlier ones
+    pub bindings: Vec<(Symbol, TypeScheme)>,
+    /// Effects performed by evaluating each definition
+    pub effects: Vec<(Symbol, EffectSet)>,
+    pub class_evidence: Vec<(Span, Vec<Evidence>)>,
+    pub continuations: Vec<(Span, Resumption)>,
+    pub errors: Vec<TypeError>,
+    pub warnings: Vec<TypeError>,
+}
+
+impl ItemCheck {
+    /// Move every span by `delta`
+    fn shift(&mut self, delta: i64) {
+        let origin = CharOffset::new(0);
+        self.errors.shift_spans(origin, delta);
+        self.warnings.shift_spans(origin, delta);
+        for (span, _) in &mut self.class_evidence {
+            span.shift_spans(origin, delta);
+        }
+        for (span, _) in &mut self.continuations {
+            span.shift_spans(origin, delta);
+        }
+    }
+
+    fn extend(&mut self, other: ItemCheck) {
+        self.bindings.extend(other.bindings);
+        self.effects.extend(other.effects);
+        self.class_evidence.extend(other.class_evidence);
+        self.continuations.extend(other.continuations);
+        self.errors.extend(other.errors);
+        self.warnings.extend(other.warnings);
+    }
+}
+
+/// Database implementation for type checking
+#[salsa::database(TypeCheckDatabase)]
+pub struct TypeCheckDatabaseImpl {
+    storage: salsa::Storage<Self>,
+    cancellation: CancellationToken,
+    /// Item checks run, shared with snapshots
+    items_checked: Arc<AtomicUsize>,
+}
+
+impl Default for TypeCheckDatabaseImpl {
+    fn default() -> Self {
+        let mut db = TypeCheckDatabaseImpl {
+            storage: salsa::Storage::default(),
+            cancellation: CancellationToken::new(),
+            items_checked: Arc::default(),
+        };
+        db.set_external_symbols(Arc::new(Vec::new()));
+        db.set_module_interfaces(Arc::new(Vec::new()));
+        db
+    }
+}
+
+impl salsa::Database for TypeCheckDatabaseImpl {
+    fn salsa_event(&self, event: salsa::Event) {
+        if let salsa::EventKind::WillExecute { database_key } = event.kind {
+            if format!("{:?}", database_key.debug(self)).contains("check_item(") {
+                self.items_checked.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn on_propagated_panic(&self) -> ! {
+        // A check on another thread this one waited for was cancelled
+        panic::resume_unwind(Box::new(Cancelled))
+    }
+}
+
+impl ParallelDatabase for TypeCheckDatabaseImpl {
+    fn snapshot(&self) -> Snapshot<Self> {
+        Snapshot::new(TypeCheckDatabaseImpl {
+            storage: self.storage.snapshot(),
+            cancellation: self.cancellation.clone(),
+            items_checked: self.items_checked.clone(),
+        })
+    }
+}
+
+impl CheckCancellation for TypeCheckDatabaseImpl {
+    fn cancellation(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+}
+
+impl std::fmt::Debug for TypeCheckDatabaseImpl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TypeCheckDatabaseImpl")
+            .field("items_checked", &self.items_checked())
+            .finish_non_exhaustive()
+    }
+}
+
+impl TypeCheckDatabaseImpl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set or replace the source of a module
+    pub fn set_source(&mut self, file: FileId, text: impl Into<String>) {
+        self.set_module_source(file, ModuleSource::Text(Arc::new(text.into())));
+    }
+
+    /// Set or replace a module by its parse, for callers without its text
+    ///
+    /// Items are then keyed by their syntax instead of their text.
+    pub fn set_unit(&mut self, file: FileId, unit: CompilationUnit) {
+        self.set_module_source(file, ModuleSource::Parsed(ItemSyntax(Arc::new(unit))));
+    }
+
+    /// Make modules available to imports
+    pub fn set_modules(&mut self, interfaces: Vec<(String, ModuleInterface)>) {
+        self.set_module_interfaces(Arc::new(interfaces));
+    }
+
+    /// Set type for a symbol
+    pub fn set_type_for_symbol(&mut self, symbol: Symbol, type_scheme: TypeScheme) {
+        let mut external = (*self.external_symbols()).clone();
+        if !external.contains(&symbol) {
+            external.push(symbol);
+            self.set_external_symbols(Arc::new(external));
+        }
+        self.set_symbol_type(symbol, Some(type_scheme));
+    }
+
+    /// A snapshot for another thread, whose checks also stop once `token`
+    /// is cancelled
+    pub fn snapshot_with_cancellation(&self, token: CancellationToken) -> Snapshot<Self> {
+        Snapshot::new(TypeCheckDatabaseImpl {
+            storage: self.storage.snapshot(),
+            cancellation: token,
+            items_checked: self.items_checked.clone(),
+        })
+    }
+
+    /// How many item checks have run, as opposed to being reused
+    pub fn items_checked(&self) -> usize {
+        self.items_checked.load(Ordering::Relaxed)
+    }
+
+    /// Check a module, as `type_check` would its parse
+    ///
+    /// A module that does not parse checks clean; report its parse errors.
+    pub fn check_result(&self, file: FileId) -> Result<CheckResult, Cancelled> {
+        let check = catch_cancelled(|| self.check_module(file))?;
+        Ok(check_result_of(&check))
+    }
+
+    /// Check the latest definition of `name` and what it depends on, or
+    /// `None` if the module does not parse or does not define it
+    pub fn definition_result(&self, file: FileId, name: Symbol) -> Result<Option<CheckResult>, Cancelled> {
+        let check = catch_cancelled(|| self.check_definition(file, name))?;
+        Ok(check.as_deref().map(check_result_of))
+    }
+
+    /// Check if compilation unit is well-typed
+    pub fn is_well_typed(&self, cu: &CompilationUnit) -> bool {
+        let result = crate::type_check(cu);
+        result.errors.is_empty()
+    }
+}
+
+/// A module or definition check as `type_check` reports it
+fn check_result_of(check: &ItemCheck) -> CheckResult {
+    let mut type_env = TypeEnv::new();
+    for (name, scheme) in &check.bindings {
+        type_env.insert_var(*name, scheme.clone());
+    }
+    CheckResult {
+        inferred_types: type_env.vars.clone(),
+        type_env,
+        inferred_effects: check.effects.iter().cloned().collect(),
+        effect_constraints: Vec::new(),
+        class_evidence: check.class_evidence.iter().cloned().collect(),
+        continuations: check.continuations.iter().cloned().collect(),
+        errors: check.errors.clone(),
+        warnings: check.warnings.clone(),
+    }
+}
+
+/// Run `f`, turning a check unwinding with `Cancelled` into an error
+fn catch_cancelled<T>(f: impl FnOnce() -> T) -> Result<T, Cancelled> {
+    panic::catch_unwind(AssertUnwindSafe(f)).or_else(|payload| match payload.downcast::<Cancelled>() {
+        Ok(_) => Err(Cancelled),
+        Err(payload) => panic::resume_unwind(payload),
+    })
+}
+
+fn unwind_if_cancelled(db: &dyn TypeCheckDb) {
+    if db.salsa_runtime().is_current_revision_canceled() || db.cancellation().is_cancelled() {
+        panic::resume_unwind(Box::new(Cancelled));
+    }
+}
+
+fn parse_module(db: &dyn TypeCheckDb, file: FileId) -> Option<Arc<ParsedModule>> {
+    let (unit, starts_and_hashes) = match db.module_source(file) {
+        ModuleSource::Text(text) => {
+            let mut parser = Parser::new(&text, file).ok()?;
+            let unit = parser.parse().ok()?;
+            // Spans count characters
+            let bytes: Vec<usize> = text.char_indices().map(|(index, _)| index).chain([text.len()]).collect();
+            let items = parser.take_trivia().item_extents.iter()
+                .map(|extent| {
+                    let (start, end) = (extent.start.as_u32() as usize, extent.end.as_u32() as usize);
+                    (extent.start, hash(&text[bytes[start]..bytes[end]]))
+                })
+                .collect::<Vec<_>>();
+            (unit, items)
+        }
+        ModuleSource::Parsed(ItemSyntax(unit)) => {
+            let items = unit.module.items.iter()
+                .map(|item| {
+                    let mut item = item.clone();
+                    let mut start = u32::MAX;
+                    item.visit_spans(&mut |span| start = start.min(span.start.as_u32()));
+                    item.shift_spans(CharOffset::new(0), -(start as i64));
+                    (CharOffset::new(start), hash(&format!("{item:?}")))
+                })
+                .collect();
+            ((*unit).clone(), items)
+        }
+    };
+
+    let mut occurrences = HashMap::new();
+    let mut items = Vec::with_capacity(starts_and_hashes.len());
+    for (item, (start, hash)) in unit.module.items.iter().zip(starts_and_hashes) {
+        let occurrence = occurrences.entry(hash).or_insert(0);
+        let key = ItemKey { hash, occurrence: *occurrence };
+        *occurrence += 1;
+
+        let mut item = item.clone();
+        item.shift_spans(CharOffset::new(0), -(start.as_u32() as i64));
+        items.push((key, start, ItemSyntax(Arc::new(item))));
+    }
+    let index = items.iter().enumerate().map(|(index, (key, ..))| (*key, index)).collect();
+    Some(Arc::new(ParsedModule { unit, items, index }))
+}
+
+fn hash(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn item_keys(db: &dyn TypeCheckDb, file: FileId) -> Arc<Vec<ItemKey>> {
+    let keys = db.parse_module(file)
+        .map(|parsed| parsed.items.iter().map(|(key, ..)| *key).collect())
+        .unwrap_or_default();
+    Arc::new(keys)
+}
+
+fn item_syntax(db: &dyn TypeCheckDb, file: FileId, key: ItemKey) -> Option<ItemSyntax> {
+    let parsed = db.parse_module(file)?;
+    let index = *parsed.index.get(&key)?;
+    Some(parsed.items[index].2.clone())
+}
+
+fn item_references(db: &dyn TypeCheckDb, file: FileId, key: ItemKey) -> Arc<Vec<Symbol>> {
+    let Some(ItemSyntax(item)) = db.item_syntax(file, key) else {
+        return Arc::default();
+    };
+    let free = |expr: &Expr| DependencyManager::extract_dependencies(expr);
+    let names: HashSet<Symbol> = match &*item {
+        Item::ValueDef(def) => DependencyManager::extract_dependencies_from_def(def),
+        Item::TestDef(def) => def.setup.iter().chain(&def.teardown).map(|expr| &**expr).chain([&def.body]).flat_map(free).collect(),
+        Item::BenchDef(def) => free(&def.body),
+        Item::InstanceDef(def) => def.methods.iter().flat_map(|method| free(&method.body)).collect(),
+        Item::HandlerDef(def) => def.handlers.iter()
+            .map(|clause| &clause.body)
+            .chain(def.return_clause.as_ref().map(|clause| &*clause.body))
+            .flat_map(free)
+            .collect(),
+        _ => HashSet::new(),
+    };
+    let mut names: Vec<Symbol> = names.into_iter().collect();
+    names.sort_by_key(|name| name.as_u32());
+    Arc::new(names)
+}
+
+fn module_header(db: &dyn TypeCheckDb, file: FileId) -> Option<ItemSyntax<Module>> {
+    let parsed = db.parse_module(file)?;
+    let module = &parsed.unit.module;
+    let mut header = Module {
+        name: module.name.clone(),
+        documentation: None,
+        exports: None,
+        imports: module.imports.clone(),
+        items: parsed.items.iter()
+            .filter(|(.., item)| matches!(*item.0, Item::ModuleTypeDef(_)))
+            .map(|(.., item)| (*item.0).clone())
+            .collect(),
+        span: Span::single(file, CharOffset::new(0)),
+    };
+    // Items see the header without its positions, which `check_module`
+    // reports against; otherwise moving the header would recheck them all
+    header.visit_spans(&mut |span| *span = Span::single(file, CharOffset::new(0)));
+    Some(ItemSyntax(Arc::new(header)))
+}
+
+fn module_dependencies(db: &dyn TypeCheckDb, file: FileId) -> Arc<HashMap<ItemKey, Arc<Vec<ItemKey>>>> {
+    let mut dependencies = HashMap::new();
+    let mut declarations = Vec::new();
+    let mut definitions = HashMap::new();
+    for key in db.item_keys(file).iter() {
+        let Some(ItemSyntax(item)) = db.item_syntax(file, *key) else { continue };
+        let references = db.item_references(file, *key);
+        // A hole could be filled with any earlier definition, so it sees them all
+        let mut used: Vec<ItemKey> = if references.contains(&x_parser::symbol::symbols::UNDERSCORE()) {
+            definitions.values().copied().collect()
+        } else {
+            references.iter().filter_map(|name| definitions.get(name).copied()).collect()
+        };
+        used.sort();
+        used.dedup();
+        dependencies.insert(*key, Arc::new(declarations.iter().copied().chain(used).collect()));
+
+        match &*item {
+            Item::ValueDef(def) => {
+                definitions.insert(def.name, *key);
+            }
+            Item::TypeDef(_) | Item::EffectDef(_) | Item::ClassDef(_) | Item::InstanceDef(_) => declarations.push(*key),
+            _ => {}
+        }
+    }
+    Arc::new(dependencies)
+}
+
+fn item_dependencies(db: &dyn TypeCheckDb, file: FileId, key: ItemKey) -> Arc<Vec<ItemKey>> {
+    db.module_dependencies(file).get(&key).cloned().unwrap_or_default()
+}
+
+/// A checker that can import the modules given to the database
+fn new_checker(db: &dyn TypeCheckDb) -> TypeChecker {
+    db.module_interfaces().iter().fold(TypeChecker::new(), |checker, (path, interface)| {
+        checker.with_module(path.clone(), interface.clone())
+    })
+}
+
+fn item_declarations(db: &dyn TypeCheckDb, file: FileId, key: ItemKey) -> Arc<Vec<ItemSyntax>> {
+    let declarations = db.item_dependencies(file, key).iter()
+        .filter_map(|dependency| db.item_syntax(file, *dependency))
+        .filter(|item| !matches!(*item.0, Item::ValueDef(_)))
+        .map(|ItemSyntax(item)| {
+            let mut item = (*item).clone();
+            item.visit_spans(&mut |span| span.file_id = FileId::INVALID);
+            ItemSyntax(Arc::new(item))
+        })
+        .collect();
+    Arc::new(declarations)
+}
+
+fn item_environment(db: &dyn TypeCheckDb, file: FileId, key: ItemKey) -> Arc<Vec<(Symbol, TypeScheme)>> {
+    let external = db.external_symbols();
+    let mut environment: Vec<(Symbol, TypeScheme)> = db.item_references(file, key).iter()
+        .filter(|name| external.contains(name))
+        .filter_map(|name| Some((*name, db.symbol_type(*name)?)))
+        .collect();
+    for dependency in db.item_dependencies(file, key).iter() {
+        if let Some(ItemSyntax(item)) = db.item_syntax(file, *dependency) {
+            if let Item::ValueDef(_) = &*item {
+                environment.extend(db.check_item(file, *dependency).bindings.iter().cloned());
+            }
+        }
+    }
+    Arc::new(environment)
+}
+
+fn item_deprecations(db: &dyn TypeCheckDb, file: FileId, key: ItemKey) -> Arc<Vec<(Symbol, Deprecation)>> {
+    let deprecations = db.item_dependencies(file, key).iter()
+        .filter_map(|dependency| match &*db.item_syntax(file, *dependency)?.0 {
+            Item::ValueDef(def) => Some((def.name, def.documentation.as_ref()?.doc_comment.deprecation()?)),
+            _ => None,
+        })
+        .collect();
+    Arc::new(deprecations)
+}
+
+fn check_item(db: &dyn TypeCheckDb, file: FileId, key: ItemKey) -> Arc<ItemCheck> {
+    let Some(ItemSyntax(item)) = db.item_syntax(file, key) else {
+        return Arc::default();
+    };
+    let mut checker = new_checker(db);
+    if let Some(ItemSyntax(header)) = db.module_header(file) {
+        checker.enter_module(&header);
+    }
+    // Declarations register their types, effects, classes and instances
+    // as they are checked
+    for ItemSyntax(declaration) in db.item_declarations(file, key).iter() {
+        checker.check_item(declaration);
+    }
+    for (name, scheme) in db.item_environment(file, key).iter() {
+        checker.bind_value(*name, scheme.clone());
+    }
+    for (name, deprecation) in db.item_deprecations(file, key).iter() {
+        checker.deprecate(*name, deprecation.clone());
+    }
+
+    unwind_if_cancelled(db);
+    let mut check = checker.observe(|checker| checker.check_item(&item));
+    check.continuations = continuations::classify_item(&item).into_iter().collect();
+    check.continuations.sort_by_key(|(span, _)| (span.start, span.end));
+    Arc::new(check)
+}
+
+fn check_module(db: &dyn TypeCheckDb, file: FileId) -> Arc<ItemCheck> {
+    let Some(parsed) = db.parse_module(file) else {
+        return Arc::default();
+    };
+    let module = &parsed.unit.module;
+    let mut checker = new_checker(db);
+    let mut check = checker.observe(|checker| {
+        if let Some(exports) = &module.exports {
+            checker.check_exports(module, exports);
+        }
+        checker.enter_module(module);
+    });
+    for (key, start, _) in &parsed.items {
+        check.extend(located_check(db, file, &parsed, *key, *start));
+    }
+    Arc::new(check)
+}
+
+fn check_definition(db: &dyn TypeCheckDb, file: FileId, name: Symbol) -> Option<Arc<ItemCheck>> {
+    let parsed = db.parse_module(file)?;
+    let (key, ..) = parsed.items.iter()
+        .rfind(|(.., ItemSyntax(item))| matches!(&**item, Item::ValueDef(def) if def.name == name))?;
+
+    let mut needed = HashSet::from([*key]);
+    let mut pending = vec![*key];
+    while let Some(key) = pending.pop() {
+        for dependency in db.item_dependencies(file, key).iter() {
+            if needed.insert(*dependency) {
+                pending.push(*dependency);
+            }
+        }
+    }
+
+    // In source order, so the definition's own binding comes last
+    let mut check = ItemCheck::default();
+    for (key, start, _) in parsed.items.iter().filter(|(key, ..)| needed.contains(key)) {
+        check.extend(located_check(db, file, &parsed, *key, *start));
+    }
+    Some(Arc::new(check))
+}
+
+/// The check of the item at `start`, with spans in the module's source
+fn located_check(db: &dyn TypeCheckDb, file: FileId, parsed: &ParsedModule, key: ItemKey, start: CharOffset) -> ItemCheck {
+    let mut check = (*db.check_item(file, key)).clone();
+    check.shift(start.as_u32() as i64);
+    for error in &mut check.errors {
+        locate_declaration(&parsed.unit.module, error);
+    }
+    check
+}
+
+/// Point a label taken from a replayed declaration at the declaration
+fn locate_declaration(module: &Module, error: &mut TypeError) {
+    let TypeError::HandlerClauseMismatch { effect, operation, span, declaration, .. } = error else {
+        return;
+    };
+    if declaration.is_none_or(|declaration| declaration.file_id != FileId::INVALID) {
+        return;
+    }
+    *declaration = module.items.iter()
+        .filter_map(|item| match item {
+            Item::EffectDef(def) if def.name == *effect && def.span.start < span.start => Some(def),
+            _ => None,
+        })
+        .next_back()
+        .and_then(|def| def.operations.iter().find(|op| op.name == *operation))
+        .map(|op| op.span);
+}
+
+fn types_compatible(_db: &dyn TypeCheckDb, t1: types::Type, t2: types::Type) -> bool {
+    let mut unifier = crate::unification::Unifier::new();
+    unifier.unify(&t1, &t2).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::type_check;
+    use x_parser::{parse_source, SyntaxStyle};
+
+    const SOURCE: &str = "module Test\n\ndata Shape = Circle Int | Square Int\n\nlet area = fun shape -> match shape with | Circle r => r * r * 3 | Square s => s * s\n\nlet total = area (Circle 2) + area (Square 3)\n\nlet label = \"shapes\"\n\nlet broken = label + 1\n";
+
+    fn codes(errors: &[TypeError]) -> Vec<(&'static str, Span)> {
+        errors.iter().map(|error| (error.code(), error.span())).collect()
+    }
+
+    #[test]
+    fn test_matches_whole_module_check() {
+        let file = FileId::new(1);
+        let mut db = TypeCheckDatabaseImpl::new();
+        db.set_source(file, SOURCE);
+        let check = db.check_result(file).unwrap();
+
+        let expected = type_check(&parse_source(SOURCE, file, SyntaxStyle::SExpression).unwrap());
+        assert!(!expected.errors.is_empty());
+        assert_eq!(codes(&check.errors), codes(&expected.errors));
+        for name in ["area", "total", "label", "Circle"] {
+            let name = Symbol::intern(name);
+            assert_eq!(
+                check.inferred_types[&name].body.to_string(),
+                expected.inferred_types[&name].body.to_string(),
+                "{name}",
+            );
+        }
+    }
+
+    #[test]
+    fn test_labels_point_into_other_items() {
+        let source = "module Test\n\neffect State { get : Int put : Int -> Unit }\n\nhandler broken { | State.put n m => resume () }\n";
+        let file = FileId::new(0);
+        let mut db = TypeCheckDatabaseImpl::new();
+        db.set_source(file, source);
+        let check = db.check_result(file).unwrap();
+
+        let expected = type_check(&parse_source(source, file, SyntaxStyle::SExpression).unwrap());
+        assert_eq!(check.errors, expected.errors);
+        assert_eq!(check.errors[0].labels()[0].0.start.as_u32() as usize, source.find("put :").unwrap());
+    }
+
+    #[test]
+    fn test_edit_rechecks_changed_items_and_dependents() {
+        let file = FileId::new(0);
+        let mut db = TypeCheckDatabaseImpl::new();
+        db.set_source(file, SOURCE);
+        db.check_result(file).unwrap();
+        assert_eq!(db.items_checked(), 5);
+
+        // Moving every item down rechecks nothing
+        db.set_source(file, SOURCE.replace("module Test\n", "module Test\n\n\n"));
+        db.check_result(file).unwrap();
+        assert_eq!(db.items_checked(), 5);
+
+        // `label` changes type, so `broken` is checked again; `total`
+        // does not depend on it
+        db.set_source(file, SOURCE.replace("\"shapes\"", "2"));
+        let check = db.check_result(file).unwrap();
+        assert_eq!(db.items_checked(), 7);
+        assert!(check.errors.is_empty(), "{:?}", check.errors);
+
+        // Same type, new text: only `label` itself
+        db.set_source(file, SOURCE.replace("\"shapes\"", "3").replace("module Test\n", "module Test\n\n"));
+        db.check_result(file).unwrap();
+        assert_eq!(db.items_checked(), 8);
+    }
+
+    #[test]
+    fn test_definition_checks_only_its_dependencies() {
+        let file = FileId::new(0);
+        let mut db = TypeCheckDatabaseImpl::new();
+        db.set_source(file, SOURCE);
+
+        // `total` needs `Shape` and `area`, not `label` or `broken`
+        let check = db.definition_result(file, Symbol::intern("total")).unwrap().unwrap();
+        assert!(check.errors.is_empty());
+        assert_eq!(check.inferred_types[&Symbol::intern("total")].body.to_string(), "Int");
+        assert_eq!(db.items_checked(), 3);
+
+        // The whole module reuses those checks
+        assert_eq!(db.check_result(file).unwrap().errors.len(), 1);
+        assert_eq!(db.items_checked(), 5);
+        assert!(db.definition_result(file, Symbol::intern("Shape")).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_cancelled_check() {
+        let file = FileId::new(0);
+        let mut db = TypeCheckDatabaseImpl::new();
+        db.set_source(file, SOURCE);
+        let token = CancellationToken::new();
+        token.cancel();
+        let snapshot = db.snapshot_with_cancellation(token);
+        assert_eq!(snapshot.check_result(file).unwrap_err(), Cancelled);
+        drop(snapshot);
+        assert_eq!(db.check_result(file).unwrap().errors.len(), 1);
+    }
+}
@@ -10,7 +10,7 @@ use crate::error_reporting::TypeError;
 use x_parser::Symbol;
 
 /// Effect row wrapper for compatibility
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct EffectRow {
     pub effects: EffectSet,
 }
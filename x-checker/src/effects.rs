@@ -11,7 +11,7 @@ use x_parser::{
     Symbol,
     Span, FileId,
 };
-use x_parser::span::ByteOffset;
+use x_parser::span::CharOffset;
 use crate::types::*;
 use std::result::Result as StdResult;
 
@@ -348,6 +348,68 @@ impl EffectSet {
     }
     
     // contains_effect is now implemented in types.rs
+
+    /// Flatten nested rows and merge repeated labels
+    ///
+    /// A row with no labels collapses to its tail, so the result is `Empty`,
+    /// a bare variable, or a row with at least one label.
+    pub fn normalize(&self) -> EffectSet {
+        let mut labels: Vec<Effect> = Vec::new();
+        let mut current = self;
+        let tail = loop {
+            match current {
+                EffectSet::Empty => break None,
+                EffectSet::Var(var) => break Some(EffectSet::Var(*var)),
+                EffectSet::Row { effects, tail } => {
+                    for effect in effects {
+                        match labels.iter_mut().find(|label| label.name == effect.name) {
+                            Some(label) => {
+                                for operation in &effect.operations {
+                                    if !label.operations.iter().any(|op| op.name == operation.name) {
+                                        label.operations.push(operation.clone());
+                                    }
+                                }
+                            }
+                            None => labels.push(effect.clone()),
+                        }
+                    }
+                    match tail {
+                        Some(tail) => current = tail,
+                        None => break None,
+                    }
+                }
+            }
+        };
+
+        match (labels.is_empty(), tail) {
+            (true, tail) => tail.unwrap_or(EffectSet::Empty),
+            (false, tail) => EffectSet::Row { effects: labels, tail: tail.map(Box::new) },
+        }
+    }
+
+    /// Drop the given labels, keeping the tail open
+    ///
+    /// Handling an effect the row does not mention is allowed, so this never
+    /// fails; the tail may still stand for the removed effects.
+    pub fn without_labels(&self, labels: &[Symbol]) -> EffectSet {
+        match self.normalize() {
+            EffectSet::Row { effects, tail } => EffectSet::Row {
+                effects: effects.into_iter().filter(|e| !labels.contains(&e.name)).collect(),
+                tail,
+            }
+            .normalize(),
+            other => other,
+        }
+    }
+
+    /// Tail variable of an open row
+    pub fn row_tail(&self) -> Option<EffectVar> {
+        match self {
+            EffectSet::Empty => None,
+            EffectSet::Var(var) => Some(*var),
+            EffectSet::Row { tail, .. } => tail.as_ref().and_then(|tail| tail.row_tail()),
+        }
+    }
     
     fn effect_in_set(&self, effect: &Symbol, set: &EffectSet) -> bool {
         set.contains_effect(*effect)
@@ -463,7 +525,7 @@ pub fn create_builtin_effects() -> HashMap<Symbol, EffectDefinition> {
                 params: vec![Type::Con(symbols::STRING())],
                 return_type: Type::Con(symbols::UNIT_TYPE()),
                 resumption_type: Type::Con(symbols::UNIT_TYPE()),
-                span: Span::new(FileId::INVALID, ByteOffset(0), ByteOffset(0)),
+                span: Span::new(FileId::INVALID, CharOffset(0), CharOffset(0)),
             },
         );
         ops.insert(
@@ -474,7 +536,7 @@ pub fn create_builtin_effects() -> HashMap<Symbol, EffectDefinition> {
                 params: Vec::new(),
                 return_type: Type::Con(symbols::STRING()),
                 resumption_type: Type::Con(symbols::STRING()),
-                span: Span::new(FileId::INVALID, ByteOffset(0), ByteOffset(0)),
+                span: Span::new(FileId::INVALID, CharOffset(0), CharOffset(0)),
             },
         );
         ops
@@ -485,7 +547,7 @@ pub fn create_builtin_effects() -> HashMap<Symbol, EffectDefinition> {
         EffectDefinition {
             name: symbols::IO(),
             operations: io_operations,
-            span: Span::new(FileId::INVALID, ByteOffset(0), ByteOffset(0)),
+            span: Span::new(FileId::INVALID, CharOffset(0), CharOffset(0)),
         },
     );
     
@@ -502,7 +564,7 @@ pub fn create_builtin_effects() -> HashMap<Symbol, EffectDefinition> {
                 params: Vec::new(),
                 return_type: Type::Var(state_var),
                 resumption_type: Type::Var(state_var),
-                span: Span::new(FileId::INVALID, ByteOffset(0), ByteOffset(0)),
+                span: Span::new(FileId::INVALID, CharOffset(0), CharOffset(0)),
             },
         );
         ops.insert(
@@ -513,7 +575,7 @@ pub fn create_builtin_effects() -> HashMap<Symbol, EffectDefinition> {
                 params: vec![Type::Var(state_var)],
                 return_type: Type::Con(symbols::UNIT_TYPE()),
                 resumption_type: Type::Con(symbols::UNIT_TYPE()),
-                span: Span::new(FileId::INVALID, ByteOffset(0), ByteOffset(0)),
+                span: Span::new(FileId::INVALID, CharOffset(0), CharOffset(0)),
             },
         );
         ops
@@ -524,7 +586,7 @@ pub fn create_builtin_effects() -> HashMap<Symbol, EffectDefinition> {
         EffectDefinition {
             name: symbols::STATE(),
             operations: state_operations,
-            span: Span::new(FileId::INVALID, ByteOffset(0), ByteOffset(0)),
+            span: Span::new(FileId::INVALID, CharOffset(0), CharOffset(0)),
         },
     );
     
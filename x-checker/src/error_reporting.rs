@@ -9,23 +9,31 @@
 use x_parser::{
     Span,
     Symbol,
+    VisitSpans,
 };
 #[allow(unused_imports)]
 use x_parser::{
     FileId,
-    span::ByteOffset,
+    span::CharOffset,
 };
 use crate::types::*;
 use std::fmt;
 
 /// Enhanced type error with context and suggestions
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum TypeError {
     TypeMismatch {
         expected: Type,
         found: Type,
         span: Span,
     },
+    /// A definition's body disagrees with its declared type
+    AnnotationMismatch {
+        expected: Type,
+        found: Type,
+        span: Span,
+        annotation: Span,
+    },
     UnboundVariable {
         name: Symbol,
         span: Span,
@@ -69,14 +77,92 @@ pub enum TypeError {
         message: String,
         span: Span,
     },
+    MissingHandler {
+        effect: Symbol,
+        operation: Option<Symbol>,
+        span: Span,
+    },
+    UnknownClass {
+        name: Symbol,
+        span: Span,
+    },
+    NoInstance {
+        class: Symbol,
+        types: Vec<Type>,
+        span: Span,
+    },
     NotAFunction {
         typ: Type,
         span: Span,
     },
+    MissingField {
+        field: Symbol,
+        typ: Type,
+        span: Span,
+    },
+    DuplicateField {
+        field: Symbol,
+        span: Span,
+    },
     InternalError {
         message: String,
         span: Span,
     },
+    ImpureDefinition {
+        name: Symbol,
+        effect: Symbol,
+        span: Span,
+        definition: Span,
+    },
+    SignatureMismatch {
+        module: Symbol,
+        signature: Symbol,
+        reason: String,
+        span: Span,
+    },
+    InsufficientVisibility {
+        name: Symbol,
+        visibility: String,
+        required: String,
+        span: Span,
+    },
+    UnloadedLazyImport {
+        name: Symbol,
+        module: Symbol,
+        span: Span,
+    },
+    /// An operation clause of a handler disagrees with the operation's declaration
+    HandlerClauseMismatch {
+        effect: Symbol,
+        operation: Symbol,
+        /// The operation's declared type, as `Int -> Unit`
+        signature: String,
+        reason: String,
+        span: Span,
+        /// Where the operation is declared, unless it is built in
+        declaration: Option<Span>,
+    },
+    /// An expression hole `_` left to fill in
+    ExpressionHole {
+        typ: Type,
+        /// Local bindings in scope at the hole, with their types
+        locals: Vec<(Symbol, Type)>,
+        /// Names in scope whose type fits the hole
+        candidates: Vec<Symbol>,
+        span: Span,
+    },
+    /// A type hole `?` in an annotation, and the type inferred for it
+    TypeHole {
+        typ: Type,
+        span: Span,
+    },
+    /// A use of a definition documented `@deprecated`
+    Deprecated {
+        name: Symbol,
+        /// What to use instead, from the attribute's message
+        note: Option<String>,
+        span: Span,
+    },
 }
 
 
@@ -86,7 +172,8 @@ pub enum TypeError {
 impl TypeError {
     fn format_error(&self) -> String {
         match self {
-            TypeError::TypeMismatch { expected, found, span: _ } => {
+            TypeError::TypeMismatch { expected, found, span: _ }
+            | TypeError::AnnotationMismatch { expected, found, .. } => {
                 format!("Type mismatch: expected {expected}, found {found}")
             }
             TypeError::UnboundVariable { name, span: _ } => {
@@ -116,16 +203,224 @@ impl TypeError {
             TypeError::EffectRowMismatch { message, span: _ } => {
                 format!("Effect row mismatch: {message}")
             }
+            TypeError::MissingHandler { effect, operation: Some(operation), span: _ } => {
+                format!("Unhandled effect operation {effect}.{operation}: no enclosing handler for {effect}")
+            }
+            TypeError::MissingHandler { effect, operation: None, span: _ } => {
+                format!("Unhandled effect {effect}: no enclosing handler")
+            }
+            TypeError::UnknownClass { name, span: _ } => {
+                format!("Unknown class: {name}")
+            }
+            TypeError::NoInstance { class, types, span: _ } => {
+                let types: Vec<String> = types.iter().map(|t| t.to_string()).collect();
+                format!("No instance for {class}[{}]", types.join(", "))
+            }
             TypeError::NotAFunction { typ, span: _ } => {
                 format!("Expected function type, found {typ}")
             }
+            TypeError::MissingField { field, typ, span: _ } => {
+                format!("Record {typ} has no field {field}")
+            }
+            TypeError::DuplicateField { field, span: _ } => {
+                format!("Duplicate record field: {field}")
+            }
             TypeError::InternalError { message, span: _ } => {
                 format!("Internal error: {message}")
             }
+            TypeError::ImpureDefinition { name, effect, .. } => {
+                format!("{name} is declared pure but performs {effect}")
+            }
+            TypeError::SignatureMismatch { module, signature, reason, span: _ } => {
+                format!("Module {module} does not match {signature}: {reason}")
+            }
+            TypeError::InsufficientVisibility { name, visibility, required, span: _ } => {
+                format!("{name} is {visibility} and not visible here; it must be {required}")
+            }
+            TypeError::UnloadedLazyImport { name, module, span: _ } => {
+                format!("{name} comes from lazy import {module}, which is not loaded at type-check time; its type is not checked")
+            }
+            TypeError::HandlerClauseMismatch { effect, operation, reason, .. } => {
+                format!("Handler clause for {effect}.{operation} does not match its declaration: {reason}")
+            }
+            TypeError::ExpressionHole { typ, .. } => {
+                format!("Found hole `_` of type {typ}")
+            }
+            TypeError::TypeHole { typ, .. } => {
+                format!("Type hole `?` stands for {typ}")
+            }
+            TypeError::Deprecated { name, .. } => {
+                format!("{name} is deprecated")
+            }
         }
     }
 }
 
+impl TypeError {
+    /// Location the error is reported at
+    pub fn span(&self) -> Span {
+        match self {
+            TypeError::TypeMismatch { span, .. }
+            | TypeError::AnnotationMismatch { span, .. }
+            | TypeError::UnboundVariable { span, .. }
+            | TypeError::InfiniteType { span, .. }
+            | TypeError::ArityMismatch { span, .. }
+            | TypeError::InferenceError { span, .. }
+            | TypeError::TestTypeMismatch { span, .. }
+            | TypeError::UnknownEffect { span, .. }
+            | TypeError::UnknownOperation { span, .. }
+            | TypeError::UnhandledEffects { span, .. }
+            | TypeError::EffectRowMismatch { span, .. }
+            | TypeError::MissingHandler { span, .. }
+            | TypeError::UnknownClass { span, .. }
+            | TypeError::NoInstance { span, .. }
+            | TypeError::NotAFunction { span, .. }
+            | TypeError::MissingField { span, .. }
+            | TypeError::DuplicateField { span, .. }
+            | TypeError::InternalError { span, .. }
+            | TypeError::ImpureDefinition { span, .. }
+            | TypeError::SignatureMismatch { span, .. }
+            | TypeError::InsufficientVisibility { span, .. }
+            | TypeError::UnloadedLazyImport { span, .. }
+            | TypeError::HandlerClauseMismatch { span, .. }
+            | TypeError::ExpressionHole { span, .. }
+            | TypeError::TypeHole { span, .. }
+            | TypeError::Deprecated { span, .. } => *span,
+        }
+    }
+
+    /// The primary span and every label's span
+    fn spans_mut(&mut self) -> [Option<&mut Span>; 2] {
+        match self {
+            TypeError::AnnotationMismatch { span, annotation, .. } => [Some(span), Some(annotation)],
+            TypeError::ImpureDefinition { span, definition, .. } => [Some(span), Some(definition)],
+            TypeError::HandlerClauseMismatch { span, declaration, .. } => [Some(span), declaration.as_mut()],
+            TypeError::TypeMismatch { span, .. }
+            | TypeError::UnboundVariable { span, .. }
+            | TypeError::InfiniteType { span, .. }
+            | TypeError::ArityMismatch { span, .. }
+            | TypeError::InferenceError { span, .. }
+            | TypeError::TestTypeMismatch { span, .. }
+            | TypeError::UnknownEffect { span, .. }
+            | TypeError::UnknownOperation { span, .. }
+            | TypeError::UnhandledEffects { span, .. }
+            | TypeError::EffectRowMismatch { span, .. }
+            | TypeError::MissingHandler { span, .. }
+            | TypeError::UnknownClass { span, .. }
+            | TypeError::NoInstance { span, .. }
+            | TypeError::NotAFunction { span, .. }
+            | TypeError::MissingField { span, .. }
+            | TypeError::DuplicateField { span, .. }
+            | TypeError::InternalError { span, .. }
+            | TypeError::SignatureMismatch { span, .. }
+            | TypeError::InsufficientVisibility { span, .. }
+            | TypeError::UnloadedLazyImport { span, .. }
+            | TypeError::ExpressionHole { span, .. }
+            | TypeError::TypeHole { span, .. }
+            | TypeError::Deprecated { span, .. } => [Some(span), None],
+        }
+    }
+
+    /// Stable diagnostic code; type errors use the `E01xx` range and
+    /// warnings the `W01xx` range
+    pub fn code(&self) -> &'static str {
+        match self {
+            TypeError::TypeMismatch { .. } => "E0100",
+            TypeError::AnnotationMismatch { .. } => "E0101",
+            TypeError::UnboundVariable { .. } => "E0102",
+            TypeError::InfiniteType { .. } => "E0103",
+            TypeError::ArityMismatch { .. } => "E0104",
+            TypeError::InferenceError { .. } => "E0105",
+            TypeError::TestTypeMismatch { .. } => "E0106",
+            TypeError::UnknownEffect { .. } => "E0107",
+            TypeError::UnknownOperation { .. } => "E0108",
+            TypeError::UnhandledEffects { .. } => "E0109",
+            TypeError::EffectRowMismatch { .. } => "E0110",
+            TypeError::MissingHandler { .. } => "E0111",
+            TypeError::UnknownClass { .. } => "E0112",
+            TypeError::NoInstance { .. } => "E0113",
+            TypeError::NotAFunction { .. } => "E0114",
+            TypeError::MissingField { .. } => "E0115",
+            TypeError::DuplicateField { .. } => "E0116",
+            TypeError::InternalError { .. } => "E0117",
+            TypeError::ImpureDefinition { .. } => "E0118",
+            TypeError::SignatureMismatch { .. } => "E0119",
+            TypeError::InsufficientVisibility { .. } => "E0120",
+            TypeError::HandlerClauseMismatch { .. } => "E0121",
+            TypeError::ExpressionHole { .. } => "E0122",
+            TypeError::UnloadedLazyImport { .. } => "W0101",
+            TypeError::TypeHole { .. } => "W0102",
+            TypeError::Deprecated { .. } => "W0103",
+        }
+    }
+
+    /// Other locations that explain the error
+    pub fn labels(&self) -> Vec<(Span, String)> {
+        match self {
+            TypeError::AnnotationMismatch { annotation, .. } => {
+                vec![(*annotation, "expected because of this annotation".to_string())]
+            }
+            TypeError::ImpureDefinition { definition, .. } => {
+                vec![(*definition, "declared pure here".to_string())]
+            }
+            TypeError::HandlerClauseMismatch { declaration: Some(declaration), .. } => {
+                vec![(*declaration, "operation declared here".to_string())]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Extra explanation shown below the source excerpt
+    pub fn notes(&self) -> Vec<String> {
+        match self {
+            TypeError::InfiniteType { .. } => {
+                vec!["a type variable cannot be unified with a type that contains it".to_string()]
+            }
+            TypeError::MissingHandler { effect, .. } => {
+                vec![format!("install a handler for {effect} around this expression")]
+            }
+            TypeError::NoInstance { class, .. } => {
+                vec![format!("add an `instance {class}[...]` for this type")]
+            }
+            TypeError::ImpureDefinition { effect, .. } => {
+                vec![format!("handle {effect} inside the definition, or remove `pure`")]
+            }
+            TypeError::InsufficientVisibility { required, .. } => {
+                vec![format!("declare it `{required}` or wider to use it here")]
+            }
+            TypeError::UnloadedLazyImport { module, .. } => {
+                vec![format!("check {module} before this module, or import it eagerly, to check its uses")]
+            }
+            TypeError::HandlerClauseMismatch { effect, operation, signature, .. } => {
+                vec![format!("{effect}.{operation} : {signature}; its clause binds one parameter per argument and resumes with the result")]
+            }
+            TypeError::ExpressionHole { locals, candidates, .. } => {
+                let mut notes = Vec::new();
+                if !locals.is_empty() {
+                    let locals: Vec<String> = locals.iter().map(|(name, typ)| format!("{name} : {typ}")).collect();
+                    notes.push(format!("local bindings: {}", locals.join(", ")));
+                }
+                if !candidates.is_empty() {
+                    let candidates: Vec<&str> = candidates.iter().map(|name| name.as_str()).collect();
+                    notes.push(format!("could be filled with: {}", candidates.join(", ")));
+                }
+                notes
+            }
+            TypeError::TypeHole { typ, .. } => {
+                vec![format!("write {typ} in place of `?`")]
+            }
+            TypeError::Deprecated { note: Some(note), .. } => vec![note.clone()],
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl VisitSpans for TypeError {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        self.spans_mut().into_iter().flatten().for_each(f)
+    }
+}
+
 impl fmt::Display for TypeError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.format_error())
@@ -181,7 +476,7 @@ mod tests {
     use super::*;
     
     fn test_span() -> Span {
-        Span::new(FileId::INVALID, ByteOffset(0), ByteOffset(0))
+        Span::new(FileId::INVALID, CharOffset(0), CharOffset(0))
     }
     
     #[test]
@@ -7,13 +7,18 @@
 
 use x_parser::{
     Expr, Pattern, Literal, Item, ValueDef, TypeDef, Module,
-    LetBinding, MatchArm, DoStatement, EffectHandler, Type as AstType,
+    LetBinding, MatchArm, DoStatement, EffectHandler, HandlerMode, ReturnClause, Type as AstType,
+    ModulePath,
     Span,
     Symbol,
+    symbol::symbols,
 };
 use crate::types::*;
 use crate::error_reporting::*;
 use crate::builtins::Builtins;
+use crate::resolver::describe_visibility;
+use crate::signatures::ModuleType;
+use crate::unification::{resolve_effects, Unifier};
 use std::result::Result as StdResult;
 
 use std::collections::{HashMap, HashSet};
@@ -25,7 +30,54 @@ pub struct InferenceContext {
     pub var_gen: VarGen,
     pub constraints: Vec<Constraint>,
     pub errors: Vec<TypeError>,
+    pub warnings: Vec<TypeError>,
     pub builtins: Builtins,
+    /// Data constructors in scope, which patterns can match on
+    pub constructors: HashMap<Symbol, TypeScheme>,
+    /// Imported modules, by the name they are qualified with
+    pub modules: HashMap<Symbol, ModuleInterface>,
+    /// Module types in scope, by name
+    pub module_types: HashMap<Symbol, ModuleType>,
+    /// Path of the module being checked, which decides what imports it may use
+    pub current_module: Option<ModulePath>,
+    /// Lazy imports whose modules are not loaded at type-check time, from
+    /// qualifier to module path
+    pub unloaded_lazy: HashMap<Symbol, Symbol>,
+    /// Solutions for type and effect row variables
+    subst: Substitution,
+    /// First place each effect operation is performed, for diagnostics
+    perform_sites: HashMap<(Symbol, Symbol), Span>,
+    /// Where each effect operation of the module is declared, for diagnostics
+    operation_declarations: HashMap<(Symbol, Symbol), Span>,
+    /// Class constraints raised by overloaded names, awaiting a dictionary
+    wanted: Vec<WantedClass>,
+    /// For each operation clause being checked, the type `resume` takes and
+    /// the type it returns, innermost last
+    resumptions: Vec<(Type, Type)>,
+    /// What matching on GADT constructors has learned about the rigid type
+    /// variables of an annotation, one frame per match arm being checked
+    refinements: Vec<HashMap<Symbol, Type>>,
+    /// Expression holes found since they were last taken
+    holes: Vec<Hole>,
+}
+
+/// An expression hole, with the bindings in scope where it was found
+#[derive(Debug, Clone)]
+struct Hole {
+    typ: Type,
+    scope: Vec<(Symbol, TypeScheme)>,
+    span: Span,
+}
+
+/// Most names suggested to fill a hole
+const MAX_HOLE_CANDIDATES: usize = 5;
+
+/// A class constraint raised where an overloaded name is used
+#[derive(Debug, Clone, PartialEq)]
+pub struct WantedClass {
+    pub class: Symbol,
+    pub types: Vec<Type>,
+    pub span: Span,
 }
 
 /// Inference result containing type and effects
@@ -43,7 +95,20 @@ impl InferenceContext {
             var_gen: VarGen::new(),
             constraints: Vec::new(),
             errors: Vec::new(),
+            warnings: Vec::new(),
             builtins: Builtins::new(),
+            constructors: HashMap::new(),
+            modules: HashMap::new(),
+            module_types: HashMap::new(),
+            current_module: None,
+            unloaded_lazy: HashMap::new(),
+            subst: Substitution::new(),
+            perform_sites: HashMap::new(),
+            operation_declarations: HashMap::new(),
+            wanted: Vec::new(),
+            resumptions: Vec::new(),
+            refinements: Vec::new(),
+            holes: Vec::new(),
         }
     }
     
@@ -57,6 +122,13 @@ impl InferenceContext {
     
     /// Instantiate a type scheme with fresh variables
     pub fn instantiate(&mut self, scheme: &TypeScheme) -> (Type, EffectSet) {
+        let (typ, _constraints) = self.instantiate_qualified(scheme);
+        let effects = extract_effects(&typ);
+        (typ, effects)
+    }
+    
+    /// Instantiate a type scheme, returning its class constraints over the fresh variables
+    fn instantiate_qualified(&mut self, scheme: &TypeScheme) -> (Type, Vec<Constraint>) {
         let mut subst = Substitution::new();
         
         // Create fresh variables for bound type variables
@@ -72,13 +144,26 @@ impl InferenceContext {
         }
         
         let typ = scheme.body.apply_subst(&subst);
-        let effects = extract_effects(&typ);
+        let constraints = scheme.constraints.iter()
+            .map(|constraint| match constraint {
+                Constraint::Class { class, types } => Constraint::Class {
+                    class: *class,
+                    types: types.iter().map(|t| t.apply_subst(&subst)).collect(),
+                },
+                other => other.clone(),
+            })
+            .collect();
         
-        (typ, effects)
+        (typ, constraints)
     }
     
     /// Generalize a type to a type scheme
+    ///
+    /// Open row tails of latent effects are generalized along with type
+    /// variables, so a function like `fun f -> f ()` stays polymorphic in the
+    /// effects of `f`.
     pub fn generalize(&self, typ: &Type, _effects: &EffectSet) -> TypeScheme {
+        let typ = self.resolve_type(typ);
         let type_free_vars = typ.free_vars();
         let env_free_vars = self.env_free_vars();
         
@@ -87,30 +172,156 @@ impl InferenceContext {
             .cloned()
             .collect();
         
-        // TODO: Properly extract effect variables
-        let effect_vars = Vec::new();
+        let env_effect_vars = self.env_free_effect_vars();
+        let effect_vars = typ.free_effect_vars()
+            .into_iter()
+            .filter(|var| !env_effect_vars.contains(var))
+            .collect();
         
         TypeScheme {
             type_vars: generalizable_vars,
             effect_vars,
             constraints: self.constraints.clone(),
-            body: typ.clone(),
+            body: typ,
         }
     }
     
     fn env_free_vars(&self) -> HashSet<TypeVar> {
         let mut vars = HashSet::new();
         for scheme in self.env.vars.values() {
-            let scheme_vars = scheme.body.free_vars();
-            vars.extend(scheme_vars);
+            let scheme_vars = self.resolve_type(&scheme.body).free_vars();
+            vars.extend(scheme_vars.into_iter().filter(|var| !scheme.type_vars.contains(var)));
         }
         vars
     }
     
+    fn env_free_effect_vars(&self) -> HashSet<EffectVar> {
+        self.env.vars.values()
+            .flat_map(|scheme| {
+                self.resolve_type(&scheme.body)
+                    .free_effect_vars()
+                    .into_iter()
+                    .filter(|var| !scheme.effect_vars.contains(var))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+    
+    /// Apply the solved effect rows to an effect set
+    pub fn resolve_effects(&self, effects: &EffectSet) -> EffectSet {
+        resolve_effects(&self.subst, effects)
+    }
+    
+    /// Apply the solved type variables and effect rows to a type
+    pub fn resolve_type(&self, typ: &Type) -> Type {
+        let all = |types: &[Type]| types.iter().map(|t| self.resolve_type(t)).collect();
+        match typ {
+            Type::Var(var) => match self.subst.lookup_type(*var) {
+                Some(solved) => self.resolve_type(solved),
+                None => typ.clone(),
+            },
+            Type::App(con, args) => Type::App(Box::new(self.resolve_type(con)), all(args)),
+            Type::Fun { params, return_type, effects } => Type::Fun {
+                params: all(params),
+                return_type: Box::new(self.resolve_type(return_type)),
+                effects: self.resolve_effects(effects),
+            },
+            Type::Forall { type_vars, effect_vars, body } => Type::Forall {
+                type_vars: type_vars.clone(),
+                effect_vars: effect_vars.clone(),
+                body: Box::new(self.resolve_type(body)),
+            },
+            Type::Record(fields) => Type::Record(
+                fields.iter().map(|(name, t)| (*name, self.resolve_type(t))).collect(),
+            ),
+            Type::Row { fields, rest } => Type::extend_record(
+                fields.iter().map(|(name, t)| (*name, self.resolve_type(t))).collect(),
+                &self.resolve_type(&Type::Var(*rest)),
+            ),
+            Type::Variant(variants) => Type::Variant(
+                variants.iter().map(|(name, types)| (*name, all(types))).collect(),
+            ),
+            Type::Tuple(types) => Type::Tuple(all(types)),
+            Type::Rec { var, body } => Type::Rec { var: *var, body: Box::new(self.resolve_type(body)) },
+            Type::Con(_) | Type::Hole | Type::Unknown => typ.clone(),
+        }
+    }
+    
+    /// Class constraints raised since the last call
+    pub fn take_wanted(&mut self) -> Vec<WantedClass> {
+        std::mem::take(&mut self.wanted)
+    }
+    
+    /// Where `effect.operation` was first performed
+    pub fn perform_site(&self, effect: Symbol, operation: Symbol) -> Option<Span> {
+        self.perform_sites.get(&(effect, operation)).copied()
+    }
+    
+    /// Record where `effect.operation` is declared
+    pub fn declare_operation(&mut self, effect: Symbol, operation: Symbol, span: Span) {
+        self.operation_declarations.insert((effect, operation), span);
+    }
+    
     /// Report a type error
     pub fn report_error(&mut self, error: TypeError) {
         self.errors.push(error);
     }
+    
+    /// Report a problem that does not stop the module from compiling
+    pub fn report_warning(&mut self, warning: TypeError) {
+        self.warnings.push(warning);
+    }
+
+    /// Whether expression holes are waiting to be taken
+    pub fn has_holes(&self) -> bool {
+        !self.holes.is_empty()
+    }
+
+    /// Describe each expression hole found since the last call, once the
+    /// definition `definition` they belong to has been checked: the type
+    /// the hole must have, the local bindings in scope there, and the names
+    /// in scope whose type fits it
+    pub fn take_holes(&mut self, definition: Option<Symbol>) -> Vec<TypeError> {
+        std::mem::take(&mut self.holes).into_iter()
+            .map(|hole| {
+                let typ = self.resolve_type(&hole.typ);
+                let mut scope: Vec<(Symbol, TypeScheme, bool)> = hole.scope.into_iter()
+                    .filter(|(name, _)| Some(*name) != definition && name.as_str().starts_with(char::is_alphabetic))
+                    .map(|(name, scheme)| {
+                        let local = self.env.vars.get(&name) != Some(&scheme);
+                        (name, scheme, local)
+                    })
+                    .collect();
+                scope.sort_by(|(a, _, a_local), (b, _, b_local)| b_local.cmp(a_local).then(a.as_str().cmp(b.as_str())));
+
+                let locals = scope.iter()
+                    .filter(|(_, _, local)| *local)
+                    .map(|(name, scheme, _)| (*name, self.resolve_type(&scheme.body)))
+                    .collect();
+                // Anything fits a hole nothing constrains
+                let candidates = if matches!(typ, Type::Var(_)) {
+                    Vec::new()
+                } else {
+                    scope.iter()
+                        .filter(|(_, scheme, _)| self.fits(scheme, &typ))
+                        .map(|(name, _, _)| *name)
+                        .take(MAX_HOLE_CANDIDATES)
+                        .collect()
+                };
+                TypeError::ExpressionHole { typ, locals, candidates, span: hole.span }
+            })
+            .collect()
+    }
+
+    /// Whether a value of `scheme` could be used at `typ`, leaving no
+    /// solutions behind
+    fn fits(&mut self, scheme: &TypeScheme, typ: &Type) -> bool {
+        let saved = self.subst.clone();
+        let (candidate, _) = self.instantiate(scheme);
+        let fits = self.unify(&candidate, typ).is_ok();
+        self.subst = saved;
+        fits
+    }
 }
 
 impl Default for InferenceContext {
@@ -126,7 +337,9 @@ impl InferenceContext {
         match expr {
             Expr::Literal(lit, _span) => self.infer_literal(lit),
             
-            Expr::Var(name, _span) => self.infer_var(*name),
+            Expr::Var(name, span) if *name == x_parser::symbol::symbols::UNDERSCORE() => Ok(self.infer_hole(*span)),
+            
+            Expr::Var(name, span) => self.infer_var_with_span(*name, Some(*span)),
             
             Expr::App(func, args, _span) => self.infer_app(func, args),
             
@@ -134,16 +347,30 @@ impl InferenceContext {
                 self.infer_lambda(parameters, body, None)
             }
             
-            Expr::Let { pattern: _, value, body, .. } => {
-                // For now, handle simple let as a single binding
+            Expr::Let { pattern: Pattern::Variable(name, _), value, body, .. } => {
                 let binding = LetBinding {
-                    name: Symbol::intern("x"), // Placeholder
+                    name: *name,
                     value: *value.clone(),
                     span: body.span(),
                 };
                 self.infer_let(&[binding], body)
             }
             
+            Expr::Let { pattern, value, body, .. } => {
+                // Destructured bindings are monomorphic
+                let value_result = self.infer_expr(value)?;
+                let saved_env = self.env.clone();
+                self.bind_pattern(pattern, value_result.typ.clone())?;
+                let body_result = self.infer_expr(body);
+                self.env = saved_env;
+                let body_result = body_result?;
+                Ok(InferenceResult {
+                    typ: body_result.typ,
+                    effects: self.combine_effects(value_result.effects, body_result.effects)?,
+                    constraints: Vec::new(),
+                })
+            }
+            
             Expr::If { condition, then_branch, else_branch, .. } => {
                 self.infer_if(condition, then_branch, else_branch)
             }
@@ -152,24 +379,46 @@ impl InferenceContext {
             
             Expr::Do { statements, .. } => self.infer_do_statements(statements),
             
-            Expr::Handle { expr, handlers, .. } => {
-                self.infer_handle(expr, handlers)
+            Expr::Handle { expr, mode, handlers, return_clause, .. } => {
+                self.infer_handle(expr, *mode, handlers, return_clause.as_deref())
             }
             
             Expr::Resume { value, .. } => self.infer_resume(value),
             
-            Expr::Perform { effect, operation, args, .. } => {
-                self.infer_perform(*effect, *operation, args)
+            Expr::Perform { effect, operation, args, span } => {
+                self.infer_perform(*effect, *operation, args, *span)
             }
             
             Expr::Ann { expr, type_annotation, .. } => self.infer_annotation(expr, type_annotation),
+            
+            Expr::Record { fields, span } => self.infer_record(fields, *span),
+            
+            Expr::Project { record, field, span } => self.infer_project(record, *field, *span),
+            
+            Expr::RecordUpdate { record, fields, span } => {
+                self.infer_record_update(record, fields, *span)
+            }
+            
+            Expr::Tuple { elements, .. } => self.infer_tuple(elements),
+            
+            Expr::Pack { module, signature, span } => self.infer_pack(*module, *signature, *span),
+            
+            Expr::Unpack { name, signature, value, body, span } => {
+                self.infer_unpack(*name, *signature, value, body, *span)
+            }
+            // Already reported by the parser; any type will do
+            Expr::Error(_) => Ok(InferenceResult {
+                typ: self.fresh_type_var(),
+                effects: EffectSet::Empty,
+                constraints: Vec::new(),
+            }),
         }
     }
     
     fn infer_literal(&mut self, lit: &Literal) -> StdResult<InferenceResult, String> {
         let typ = match lit {
-            Literal::Integer(_) => Type::Con(Symbol::intern("Int")),
-            Literal::Float(_) => Type::Con(Symbol::intern("Float")),
+            Literal::Integer(..) => Type::Con(Symbol::intern("Int")),
+            Literal::Float(..) => Type::Con(Symbol::intern("Float")),
             Literal::String(_) => Type::Con(Symbol::intern("String")),
             Literal::Bool(_) => Type::Con(Symbol::intern("Bool")),
             Literal::Unit => Type::Con(Symbol::intern("Unit")),
@@ -182,18 +431,30 @@ impl InferenceContext {
         })
     }
     
-    fn infer_var(&mut self, name: Symbol) -> StdResult<InferenceResult, String> {
-        self.infer_var_with_span(name, None)
+    /// An expression hole `_` may have any type; what it must have is
+    /// reported once its definition is checked
+    fn infer_hole(&mut self, span: Span) -> InferenceResult {
+        let typ = self.fresh_type_var();
+        let scope = self.env.vars.iter().map(|(name, scheme)| (*name, scheme.clone())).collect();
+        self.holes.push(Hole { typ: typ.clone(), scope, span });
+        InferenceResult {
+            typ,
+            effects: EffectSet::Empty,
+            constraints: Vec::new(),
+        }
     }
     
     fn infer_var_with_span(&mut self, name: Symbol, span: Option<Span>) -> StdResult<InferenceResult, String> {
+        // Referencing a variable is pure; a function's latent effects are
+        // charged where it is applied
+        
         // First check if it's a builtin
         if let Some(scheme) = self.builtins.get_type_scheme(&name) {
             let scheme = scheme.clone();
-            let (typ, effects) = self.instantiate(&scheme);
+            let (typ, _latent) = self.instantiate(&scheme);
             return Ok(InferenceResult {
                 typ,
-                effects,
+                effects: EffectSet::Empty,
                 constraints: Vec::new(),
             });
         }
@@ -202,10 +463,21 @@ impl InferenceContext {
         match self.env.lookup_var(name) {
             Some(scheme) => {
                 let scheme = scheme.clone();
-                let (typ, effects) = self.instantiate(&scheme);
+                let (typ, constraints) = self.instantiate_qualified(&scheme);
+                if let Some(span) = span {
+                    if let Some(deprecation) = self.env.deprecated.get(&name) {
+                        let note = deprecation.note.clone();
+                        self.report_warning(TypeError::Deprecated { name, note, span });
+                    }
+                    for constraint in constraints {
+                        if let Constraint::Class { class, types } = constraint {
+                            self.wanted.push(WantedClass { class, types, span });
+                        }
+                    }
+                }
                 Ok(InferenceResult {
                     typ,
-                    effects,
+                    effects: EffectSet::Empty,
                     constraints: Vec::new(),
                 })
             }
@@ -231,6 +503,24 @@ impl InferenceContext {
             arg_results.push(self.infer_expr(arg)?);
         }
         
+        self.apply_function(func_result, arg_results)
+    }
+    
+    /// Type of applying a function to arguments; a curried function taking
+    /// fewer parameters than there are arguments is applied one group at a time
+    fn apply_function(
+        &mut self,
+        func_result: InferenceResult,
+        mut arg_results: Vec<InferenceResult>,
+    ) -> StdResult<InferenceResult, String> {
+        if let Type::Fun { params, .. } = self.resolve_type(&func_result.typ) {
+            if !params.is_empty() && params.len() < arg_results.len() {
+                let rest = arg_results.split_off(params.len());
+                let partial = self.apply_function(func_result, arg_results)?;
+                return self.apply_function(partial, rest);
+            }
+        }
+        
         // Create fresh variables for result
         let result_type = self.fresh_type_var();
         let result_effects = self.fresh_effect_var();
@@ -245,11 +535,12 @@ impl InferenceContext {
         
         self.unify(&func_result.typ, &expected_func_type)?;
         
-        // Combine effects from function and arguments
+        // Combine effects from function, arguments and the call itself
         let mut combined_effects = func_result.effects;
         for arg_result in &arg_results {
             combined_effects = self.combine_effects(combined_effects, arg_result.effects.clone())?;
         }
+        combined_effects = self.combine_effects(combined_effects, result_effects)?;
         
         Ok(InferenceResult {
             typ: result_type,
@@ -270,22 +561,8 @@ impl InferenceContext {
         // Add parameters to environment
         let mut param_types = Vec::new();
         for param in params {
-            // For now, just handle variable patterns
-            let param_name = match param {
-                Pattern::Variable(name, _) => *name,
-                _ => Symbol::intern("_"), // Placeholder for complex patterns
-            };
-            
             let param_type = self.fresh_type_var();
-            
-            let scheme = TypeScheme {
-                type_vars: Vec::new(),
-                effect_vars: Vec::new(),
-                constraints: Vec::new(),
-                body: param_type.clone(),
-            };
-            
-            self.env.insert_var(param_name, scheme);
+            self.bind_pattern(param, param_type.clone())?;
             param_types.push(param_type);
         }
         
@@ -295,10 +572,13 @@ impl InferenceContext {
         // Restore environment
         self.env = saved_env;
         
+        // Latent effects stay open so the function can be used where
+        // more effects are available
+        let latent = self.open_row(body_result.effects);
         let lambda_type = Type::Fun {
             params: param_types,
             return_type: Box::new(body_result.typ),
-            effects: body_result.effects.clone(),
+            effects: latent,
         };
         
         Ok(InferenceResult {
@@ -331,7 +611,7 @@ impl InferenceContext {
                 }
             };
             
-            self.env.insert_var(binding.name, scheme);
+            self.env.bind_local(binding.name, scheme);
         }
         
         // Infer body
@@ -385,8 +665,7 @@ impl InferenceContext {
         
         // Infer first arm to get result type
         let first_arm = &arms[0];
-        let _pattern_result = self.infer_pattern(&first_arm.pattern, &expr_result.typ)?;
-        let first_body_result = self.infer_expr(&first_arm.body)?;
+        let first_body_result = self.infer_arm(first_arm, &expr_result.typ)?;
         
         let result_type = first_body_result.typ;
         let mut combined_effects = self.combine_effects(
@@ -396,8 +675,7 @@ impl InferenceContext {
         
         // Check remaining arms
         for arm in &arms[1..] {
-            let _pattern_result = self.infer_pattern(&arm.pattern, &expr_result.typ)?;
-            let body_result = self.infer_expr(&arm.body)?;
+            let body_result = self.infer_arm(arm, &expr_result.typ)?;
             
             self.unify(&result_type, &body_result.typ)?;
             combined_effects = self.combine_effects(combined_effects, body_result.effects)?;
@@ -410,6 +688,89 @@ impl InferenceContext {
         })
     }
     
+    /// Check an expression against a type known in advance
+    ///
+    /// Functions and matches pass the expected type down to their bodies, so
+    /// an arm that matches a GADT constructor checks its body with what the
+    /// constructor's result type says about the annotation's rigid variables.
+    /// Anything else is inferred and unified with the expected type.
+    pub fn check_expr(&mut self, expr: &Expr, expected: &Type) -> StdResult<InferenceResult, String> {
+        match (expr, expected) {
+            (Expr::Lambda { parameters, body, .. }, Type::Fun { params, return_type, .. })
+                if parameters.len() == params.len() =>
+            {
+                let saved_env = self.env.clone();
+                let body_result = parameters.iter().zip(params)
+                    .try_for_each(|(parameter, typ)| self.bind_pattern(parameter, typ.clone()))
+                    .and_then(|()| self.check_expr(body, return_type));
+                self.env = saved_env;
+                let body_result = body_result?;
+                let latent = self.open_row(body_result.effects);
+                Ok(InferenceResult {
+                    typ: Type::Fun { params: params.clone(), return_type: return_type.clone(), effects: latent },
+                    effects: EffectSet::Empty,
+                    constraints: body_result.constraints,
+                })
+            }
+            (Expr::Match { scrutinee, arms, .. }, _) => self.check_match(scrutinee, arms, expected),
+            (Expr::If { condition, then_branch, else_branch, .. }, _) => {
+                let cond_result = self.infer_expr(condition)?;
+                self.unify(&cond_result.typ, &Type::Con(x_parser::symbol::symbols::BOOL()))?;
+                let then_result = self.check_expr(then_branch, expected)?;
+                let else_result = self.check_expr(else_branch, expected)?;
+                let branch_effects = self.combine_effects(then_result.effects, else_result.effects)?;
+                Ok(InferenceResult {
+                    typ: expected.clone(),
+                    effects: self.combine_effects(cond_result.effects, branch_effects)?,
+                    constraints: Vec::new(),
+                })
+            }
+            _ => {
+                let result = self.infer_expr(expr)?;
+                self.unify(&result.typ, expected)?;
+                Ok(result)
+            }
+        }
+    }
+
+    fn check_match(
+        &mut self,
+        expr: &Expr,
+        arms: &[MatchArm],
+        expected: &Type,
+    ) -> StdResult<InferenceResult, String> {
+        let expr_result = self.infer_expr(expr)?;
+        if arms.is_empty() {
+            return Err("Match expression must have at least one arm".to_string());
+        }
+
+        let mut combined_effects = expr_result.effects;
+        for arm in arms {
+            let saved_env = self.env.clone();
+            self.refinements.push(HashMap::new());
+            let body_result = self.bind_pattern(&arm.pattern, expr_result.typ.clone())
+                .and_then(|()| self.check_expr(&arm.body, expected));
+            self.refinements.pop();
+            self.env = saved_env;
+            combined_effects = self.combine_effects(combined_effects, body_result?.effects)?;
+        }
+
+        Ok(InferenceResult {
+            typ: expected.clone(),
+            effects: combined_effects,
+            constraints: Vec::new(),
+        })
+    }
+
+    /// Infer an arm's body with its pattern's variables in scope
+    fn infer_arm(&mut self, arm: &MatchArm, scrutinee: &Type) -> StdResult<InferenceResult, String> {
+        let saved_env = self.env.clone();
+        let result = self.bind_pattern(&arm.pattern, scrutinee.clone())
+            .and_then(|()| self.infer_expr(&arm.body));
+        self.env = saved_env;
+        result
+    }
+    
     fn infer_do_statements(&mut self, _statements: &[DoStatement]) -> StdResult<InferenceResult, String> {
         // For now, just return unit type
         Ok(InferenceResult {
@@ -422,29 +783,215 @@ impl InferenceContext {
     fn infer_handle(
         &mut self,
         body: &Expr,
-        _handlers: &[EffectHandler],
+        mode: HandlerMode,
+        handlers: &[EffectHandler],
+        return_clause: Option<&ReturnClause>,
     ) -> StdResult<InferenceResult, String> {
         let body_result = self.infer_expr(body)?;
         
-        // TODO: Implement proper effect handling
-        // For now, just return the body type with empty effects
+        // Subsumption: the body may perform any subset of the handled
+        // effects, and whatever else it performs passes through. Once a
+        // shallow handler has handled an operation the rest of the body runs
+        // without it, so its effects pass through as well.
+        let mut effects = self.resolve_effects(&body_result.effects);
+        if mode == HandlerMode::Deep {
+            let handled: Vec<Symbol> = handlers.iter().map(|handler| handler.effect.name).collect();
+            effects = effects.without_labels(&handled);
+        }
+        
+        let clauses = self.infer_handler_clauses(body_result.typ, mode, handlers, return_clause)?;
+        effects = self.combine_effects(effects, clauses.effects)?;
+        
         Ok(InferenceResult {
-            typ: body_result.typ,
-            effects: EffectSet::Empty,
+            typ: clauses.typ,
+            effects,
+            constraints: Vec::new(),
+        })
+    }
+    
+    /// Check the clauses of a handler against the effects they handle
+    ///
+    /// `value_type` is the type of the handled computation's value, which the
+    /// return clause receives. The result has the type every clause returns
+    /// and the effects the clauses themselves perform.
+    ///
+    /// The continuation of a deep handler's clause runs under the handler and
+    /// gives that type too; a shallow handler's continuation runs without it
+    /// and gives the computation's own value.
+    pub fn infer_handler_clauses(
+        &mut self,
+        value_type: Type,
+        mode: HandlerMode,
+        handlers: &[EffectHandler],
+        return_clause: Option<&ReturnClause>,
+    ) -> StdResult<InferenceResult, String> {
+        let mut effects = EffectSet::Empty;
+        let mut result_type = value_type.clone();
+        if let Some(return_clause) = return_clause {
+            let saved_env = self.env.clone();
+            self.bind_pattern(&return_clause.parameter, value_type.clone())?;
+            let return_result = self.infer_expr(&return_clause.body);
+            self.env = saved_env;
+            let return_result = return_result?;
+            effects = self.combine_effects(effects, return_result.effects)?;
+            result_type = return_result.typ;
+        }
+        
+        for handler in handlers {
+            let (effect, operation_name) = (handler.effect.name, handler.operation);
+            let Some(declared) = self.env.lookup_effect(effect) else {
+                self.report_error(TypeError::UnknownEffect { effect_name: effect.to_string(), span: handler.span });
+                return Err(format!("Unknown effect: {effect}"));
+            };
+            let Some(operation) = declared.operations.iter().find(|op| op.name == operation_name).cloned() else {
+                self.report_error(TypeError::UnknownOperation {
+                    effect_name: effect.to_string(),
+                    operation_name: operation_name.to_string(),
+                    span: handler.span,
+                });
+                return Err(format!("Unknown operation: {operation_name} in effect {effect}"));
+            };
+            
+            // Errors inference located inside the clause say more than a
+            // mismatch for the whole clause
+            let located = self.errors.len();
+            match self.infer_operation_clause(handler, &operation, mode, &value_type, &result_type) {
+                Ok(clause_effects) => effects = self.combine_effects(effects, clause_effects)?,
+                Err(reason) => {
+                    if self.errors.len() == located {
+                        self.report_error(TypeError::HandlerClauseMismatch {
+                            effect,
+                            operation: operation_name,
+                            signature: operation_signature(&operation),
+                            reason: reason.clone(),
+                            span: handler.span,
+                            declaration: self.operation_declarations.get(&(effect, operation_name)).copied(),
+                        });
+                    }
+                    return Err(reason);
+                }
+            }
+        }
+        
+        Ok(InferenceResult {
+            typ: result_type,
+            effects,
             constraints: Vec::new(),
         })
     }
     
+    /// Check one operation clause against the declared `operation`: its
+    /// parameters take the operation's arguments, its continuation and
+    /// `resume` the operation's result, and its body gives `result_type`
+    fn infer_operation_clause(
+        &mut self,
+        handler: &EffectHandler,
+        operation: &Operation,
+        mode: HandlerMode,
+        value_type: &Type,
+        result_type: &Type,
+    ) -> StdResult<EffectSet, String> {
+        let operation = self.instantiate_operation(operation);
+        if handler.parameters.len() != operation.params.len() {
+            return Err(format!(
+                "the clause binds {} parameter(s), but the operation takes {}",
+                handler.parameters.len(),
+                operation.params.len(),
+            ));
+        }
+        
+        let saved_env = self.env.clone();
+        let resumed_type = match mode {
+            HandlerMode::Deep => result_type.clone(),
+            HandlerMode::Shallow => value_type.clone(),
+        };
+        let bound = handler.parameters.iter().zip(&operation.params)
+            .try_for_each(|(param, param_type)| self.bind_pattern(param, param_type.clone()));
+        if let Err(error) = bound {
+            self.env = saved_env;
+            return Err(error);
+        }
+        if let Some(continuation) = handler.continuation {
+            let resume_type = Type::Fun {
+                params: vec![operation.return_type.clone()],
+                return_type: Box::new(resumed_type.clone()),
+                effects: self.fresh_effect_var(),
+            };
+            self.env.bind_local(continuation, TypeScheme::monotype(resume_type));
+        }
+        self.resumptions.push((operation.return_type.clone(), resumed_type));
+        let clause_result = self.infer_expr(&handler.body);
+        self.resumptions.pop();
+        self.env = saved_env;
+        let clause_result = clause_result?;
+        
+        if self.unify(result_type, &clause_result.typ).is_err() {
+            return Err(format!(
+                "the clause gives {}, but the handler gives {}",
+                self.resolve_type(&clause_result.typ),
+                self.resolve_type(result_type),
+            ));
+        }
+        Ok(clause_result.effects)
+    }
+    
+    /// Bind the variables of `pattern` as monomorphic types in the environment
+    fn bind_pattern(&mut self, pattern: &Pattern, typ: Type) -> StdResult<(), String> {
+        for (name, typ) in self.infer_pattern(pattern, &typ)? {
+            self.env.bind_local(name, TypeScheme::monotype(typ));
+        }
+        Ok(())
+    }
+    
+    /// `resume value` continues the operation being handled with `value`,
+    /// giving what the rest of the handled computation gives
     fn infer_resume(&mut self, expr: &Expr) -> StdResult<InferenceResult, String> {
-        // Resume passes through the expression type
-        self.infer_expr(expr)
+        let value = self.infer_expr(expr)?;
+        let Some((argument, result)) = self.resumptions.last().cloned() else {
+            return Err("resume used outside of an operation clause".to_string());
+        };
+        self.unify(&argument, &value.typ)?;
+        Ok(InferenceResult { typ: result, effects: value.effects, constraints: Vec::new() })
     }
     
+    /// A copy of `operation` with fresh type and effect variables, so each
+    /// perform and clause picks its own types for a polymorphic operation
+    fn instantiate_operation(&mut self, operation: &Operation) -> Operation {
+        let types = || operation.params.iter().chain([&operation.return_type]);
+        let mut subst = Substitution::new();
+        for var in types().flat_map(Type::free_vars).collect::<HashSet<_>>() {
+            subst.insert_type(var, Type::Var(self.var_gen.fresh_type_var()));
+        }
+        for var in types().flat_map(Type::free_effect_vars).collect::<HashSet<_>>() {
+            subst.insert_effect(var, EffectSet::Var(self.var_gen.fresh_effect_var()));
+        }
+        operation.apply_subst(&subst)
+    }
+
+    /// A spawned task runs after the handlers around `Async.spawn` may have
+    /// been removed, so it may only perform effects the host handles
+    fn check_spawned_task(&self, task: &Type) -> StdResult<(), String> {
+        let Type::Fun { effects, .. } = self.resolve_type(task) else { return Ok(()) };
+        let escaping: Vec<String> = row_labels(&self.resolve_effects(&effects)).iter()
+            .filter(|effect| effect.name != symbols::ASYNC() && effect.name != symbols::IO())
+            .map(|effect| effect.name.to_string())
+            .collect();
+        if escaping.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "A task given to Async.spawn cannot perform {}: it runs outside the handlers around the spawn",
+                escaping.join(", ")
+            ))
+        }
+    }
+
     fn infer_perform(
         &mut self,
         effect: Symbol,
         operation: Symbol,
         args: &[Expr],
+        span: Span,
     ) -> StdResult<InferenceResult, String> {
         // Look up effect and operation  
         let effect_def = self.env.lookup_effect(effect)
@@ -453,6 +1000,7 @@ impl InferenceContext {
         let operation_def = effect_def.operations.iter()
             .find(|op| op.name == operation)
             .ok_or_else(|| format!("Unknown operation: {operation} in effect {effect}"))?.clone();
+        let operation_def = self.instantiate_operation(&operation_def);
         
         // Check argument types
         if args.len() != operation_def.params.len() {
@@ -464,16 +1012,26 @@ impl InferenceContext {
             ));
         }
         
+        let mut effects = EffectSet::Empty;
         for (arg, expected_type) in args.iter().zip(&operation_def.params) {
             let arg_result = self.infer_expr(arg)?;
             self.unify(&arg_result.typ, expected_type)?;
+            effects = self.combine_effects(effects, arg_result.effects)?;
+        }
+        if effect == symbols::ASYNC() && operation.as_str() == "spawn" {
+            self.check_spawned_task(&operation_def.params[0])?;
         }
         
-        // Create effect set containing this effect
-        let effect_set = EffectSet::Row {
-            effects: vec![effect_def],
+        // The row records which operation was performed, and where
+        self.perform_sites.entry((effect, operation)).or_insert(span);
+        let performed = EffectSet::Row {
+            effects: vec![Effect {
+                name: effect_def.name,
+                operations: vec![operation_def.clone()],
+            }],
             tail: None,
         };
+        let effect_set = self.combine_effects(effects, performed)?;
         
         Ok(InferenceResult {
             typ: operation_def.return_type,
@@ -495,11 +1053,140 @@ impl InferenceContext {
         })
     }
     
+    fn infer_tuple(&mut self, elements: &[Expr]) -> StdResult<InferenceResult, String> {
+        let mut types = Vec::new();
+        let mut effects = EffectSet::Empty;
+        for element in elements {
+            let result = self.infer_expr(element)?;
+            effects = self.combine_effects(effects, result.effects)?;
+            types.push(result.typ);
+        }
+        Ok(InferenceResult {
+            typ: Type::Tuple(types),
+            effects,
+            constraints: Vec::new(),
+        })
+    }
+    
+    /// A record literal has exactly the fields it lists
+    fn infer_record(&mut self, fields: &[(Symbol, Expr)], span: Span) -> StdResult<InferenceResult, String> {
+        let (field_types, effects) = self.infer_fields(fields, span)?;
+        Ok(InferenceResult {
+            typ: Type::extend_record(field_types, &Type::Record(Vec::new())),
+            effects,
+            constraints: Vec::new(),
+        })
+    }
+    
+    /// `r.x` accepts any record with at least a field `x`; `M.x` names a value of an imported module
+    fn infer_project(&mut self, record: &Expr, field: Symbol, span: Span) -> StdResult<InferenceResult, String> {
+        if let Expr::Var(module, _) = record {
+            if self.env.lookup_var(*module).is_none() {
+                if let Some(&path) = self.unloaded_lazy.get(module) {
+                    let name = Symbol::intern(&format!("{module}.{field}"));
+                    self.report_warning(TypeError::UnloadedLazyImport { name, module: path, span });
+                    return Ok(InferenceResult {
+                        typ: self.fresh_type_var(),
+                        effects: EffectSet::Empty,
+                        constraints: Vec::new(),
+                    });
+                }
+                if let Some(interface) = self.modules.get(module) {
+                    let name = Symbol::intern(&format!("{module}.{field}"));
+                    let from = self.current_module.as_ref();
+                    let scheme = match interface.lookup(field, from) {
+                        Some(Ok(scheme)) => scheme.clone(),
+                        Some(Err(visibility)) => {
+                            let error = TypeError::InsufficientVisibility {
+                                name,
+                                visibility: describe_visibility(visibility),
+                                required: describe_visibility(&interface.required_visibility(from)),
+                                span,
+                            };
+                            self.report_error(error);
+                            return Err(format!("{name} is not visible here"));
+                        }
+                        None => {
+                            self.report_error(TypeError::UnboundVariable { name, span });
+                            return Err(format!("Unbound variable: {name}"));
+                        }
+                    };
+                    if let Some(deprecation) = interface.deprecated.get(&field) {
+                        let note = deprecation.note.clone();
+                        self.report_warning(TypeError::Deprecated { name, note, span });
+                    }
+                    let (typ, _latent) = self.instantiate(&scheme);
+                    return Ok(InferenceResult {
+                        typ,
+                        effects: EffectSet::Empty,
+                        constraints: Vec::new(),
+                    });
+                }
+            }
+        }
+        
+        let record_result = self.infer_expr(record)?;
+        let field_type = self.fresh_type_var();
+        let rest = self.var_gen.fresh_type_var();
+        let expected = Type::Row { fields: vec![(field, field_type.clone())], rest };
+        self.unify_with_span(&record_result.typ, &expected, Some(span))?;
+        
+        Ok(InferenceResult {
+            typ: field_type,
+            effects: record_result.effects,
+            constraints: Vec::new(),
+        })
+    }
+    
+    /// `{ r with x = e }` keeps the type of `r`, which must already have a field `x` of the same type
+    fn infer_record_update(
+        &mut self,
+        record: &Expr,
+        fields: &[(Symbol, Expr)],
+        span: Span,
+    ) -> StdResult<InferenceResult, String> {
+        let record_result = self.infer_expr(record)?;
+        let (field_types, field_effects) = self.infer_fields(fields, span)?;
+        let rest = self.var_gen.fresh_type_var();
+        let expected = Type::extend_record(field_types, &Type::Var(rest));
+        self.unify_with_span(&record_result.typ, &expected, Some(span))?;
+        
+        Ok(InferenceResult {
+            typ: record_result.typ,
+            effects: self.combine_effects(record_result.effects, field_effects)?,
+            constraints: Vec::new(),
+        })
+    }
+    
+    fn infer_fields(
+        &mut self,
+        fields: &[(Symbol, Expr)],
+        span: Span,
+    ) -> StdResult<(Vec<(Symbol, Type)>, EffectSet), String> {
+        let mut field_types: Vec<(Symbol, Type)> = Vec::new();
+        let mut effects = EffectSet::Empty;
+        for (name, value) in fields {
+            if field_types.iter().any(|(seen, _)| seen == name) {
+                self.report_error(TypeError::DuplicateField { field: *name, span });
+                return Err(format!("Duplicate record field: {name}"));
+            }
+            let result = self.infer_expr(value)?;
+            effects = self.combine_effects(effects, result.effects)?;
+            field_types.push((*name, result.typ));
+        }
+        Ok((field_types, effects))
+    }
+    
     /// Infer pattern type and return bindings
     fn infer_pattern(&mut self, pattern: &Pattern, expected_type: &Type) -> StdResult<HashMap<Symbol, Type>, String> {
         match pattern {
             Pattern::Wildcard(_) => Ok(HashMap::new()),
             
+            Pattern::Variable(name, _) if self.constructors.contains_key(name) => {
+                // A bare constructor name matches a nullary constructor
+                self.infer_constructor_pattern(*name, &[], expected_type)
+            }
+            
             Pattern::Variable(name, _) => {
                 let mut bindings = HashMap::new();
                 bindings.insert(*name, expected_type.clone());
@@ -512,8 +1199,12 @@ impl InferenceContext {
                 Ok(HashMap::new())
             }
             
-            Pattern::Constructor { name: _, args, .. } => {
-                // TODO: Implement constructor patterns
+            Pattern::Constructor { name, args, .. } if self.constructors.contains_key(name) => {
+                self.infer_constructor_pattern(*name, args, expected_type)
+            }
+            
+            Pattern::Constructor { args, .. } => {
+                // Constructors of undeclared types leave their arguments unconstrained
                 let mut bindings = HashMap::new();
                 for arg in args.iter() {
                     let arg_type = self.fresh_type_var();
@@ -559,6 +1250,22 @@ impl InferenceContext {
                 }
             }
             
+            Pattern::Or { left, right, .. } => {
+                // Both alternatives bind the same variables, at the same types
+                let left_bindings = self.infer_pattern(left, expected_type)?;
+                let right_bindings = self.infer_pattern(right, expected_type)?;
+                let mut names: Vec<_> = left_bindings.keys().chain(right_bindings.keys()).collect();
+                names.sort_by_key(|name| name.as_str());
+                names.dedup();
+                for name in names {
+                    match (left_bindings.get(name), right_bindings.get(name)) {
+                        (Some(left_type), Some(right_type)) => self.unify(left_type, right_type)?,
+                        _ => return Err(format!("Variable {name} must be bound on both sides of an or-pattern")),
+                    }
+                }
+                Ok(left_bindings)
+            }
+            
             _ => {
                 // TODO: Implement remaining patterns
                 Ok(HashMap::new())
@@ -566,6 +1273,70 @@ impl InferenceContext {
         }
     }
     
+    /// Match `name args...` against the curried type of the constructor
+    fn infer_constructor_pattern(
+        &mut self,
+        name: Symbol,
+        args: &[Pattern],
+        expected_type: &Type,
+    ) -> StdResult<HashMap<Symbol, Type>, String> {
+        let scheme = self.constructors[&name].clone();
+        let (mut typ, _) = self.instantiate(&scheme);
+        let mut bindings = HashMap::new();
+        for arg in args {
+            let Type::Fun { params, return_type, .. } = typ else {
+                return Err(format!("Constructor {name} is applied to too many patterns"));
+            };
+            bindings.extend(self.infer_pattern(arg, &params[0])?);
+            typ = *return_type;
+        }
+        if matches!(typ, Type::Fun { .. }) {
+            return Err(format!("Constructor {name} is missing arguments in pattern"));
+        }
+        self.refine(&typ, expected_type)?;
+        Ok(bindings)
+    }
+
+    /// Unify a constructor's result type with the type a pattern matches
+    ///
+    /// While an arm is being checked, a rigid type variable of the matched
+    /// type that meets a different type is refined to it for the rest of the
+    /// arm, instead of failing to unify.
+    fn refine(&mut self, result: &Type, matched: &Type) -> StdResult<(), String> {
+        let result = self.shallow_resolve(result);
+        let matched = self.shallow_resolve(matched);
+        match (&result, &matched) {
+            (Type::App(con1, args1), Type::App(con2, args2)) if args1.len() == args2.len() => {
+                self.refine(con1, con2)?;
+                args1.iter().zip(args2).try_for_each(|(arg1, arg2)| self.refine(arg1, arg2))
+            }
+            (typ, Type::Con(name))
+                if is_rigid(*name) && !matches!(typ, Type::Var(_)) && typ != &matched && self.refinement(*name).is_none() =>
+            {
+                let Some(frame) = self.refinements.last_mut() else {
+                    return self.unify(&result, &matched);
+                };
+                frame.insert(*name, typ.clone());
+                Ok(())
+            }
+            _ => self.unify(&result, &matched),
+        }
+    }
+
+    /// The type matching has refined a rigid type variable to, if any
+    fn refinement(&self, name: Symbol) -> Option<Type> {
+        self.refinements.iter().rev().find_map(|frame| frame.get(&name)).cloned()
+    }
+
+    /// Either side that is a refined rigid type variable, replaced by its
+    /// refinement, with the other side
+    fn refined(&self, t1: &Type, t2: &Type) -> Option<(Type, Type)> {
+        [(t1, t2), (t2, t1)].into_iter().find_map(|(rigid, other)| match rigid {
+            Type::Con(name) => Some((self.refinement(*name)?, other.clone())),
+            _ => None,
+        })
+    }
+    
     /// Helper function to check if an expression is a value (for let-polymorphism)
     fn is_value(&self, expr: &Expr) -> bool {
         matches!(expr, Expr::Literal(_, _) | Expr::Var(_, _) | Expr::Lambda { .. })
@@ -601,6 +1372,18 @@ impl InferenceContext {
                     effects: effect_set,
                 })
             }
+            AstType::Record { fields, rest, .. } => {
+                let mut field_types = Vec::new();
+                for (name, field) in fields {
+                    field_types.push((*name, self.ast_type_to_type(field)?));
+                }
+                // Any tail makes the record open
+                let rest = match rest {
+                    Some(_) => self.fresh_type_var(),
+                    None => Type::Record(Vec::new()),
+                };
+                Ok(Type::extend_record(field_types, &rest))
+            }
             _ => {
                 // TODO: Implement remaining AST type conversions
                 Err("Unsupported AST type conversion".to_string())
@@ -608,26 +1391,50 @@ impl InferenceContext {
         }
     }
     
-    fn ast_effects_to_effects(&mut self, _effects: &x_parser::ast::EffectSet) -> StdResult<EffectSet, String> {
-        // TODO: Implement AST effect set conversion
-        Ok(EffectSet::Empty)
+    fn ast_effects_to_effects(&mut self, effects: &x_parser::ast::EffectSet) -> StdResult<EffectSet, String> {
+        let labels = effects.effects.iter()
+            .map(|effect| Effect { name: effect.name, operations: Vec::new() })
+            .collect();
+        // A named row variable makes the annotation open
+        let tail = effects.row_var.map(|_| Box::new(self.fresh_effect_var()));
+        Ok(EffectSet::Row { effects: labels, tail }.normalize())
+    }
+    
+    /// Give a closed row a fresh tail
+    fn open_row(&mut self, effects: EffectSet) -> EffectSet {
+        let effects = self.resolve_effects(&effects);
+        if effects.row_tail().is_some() {
+            return effects;
+        }
+        EffectSet::Row {
+            effects: row_labels(&effects),
+            tail: Some(Box::new(self.fresh_effect_var())),
+        }
+        .normalize()
     }
 }
 
 /// Unification and effect combination
 impl InferenceContext {
+    /// Unify two types, recording the solution for any type variables
+    pub fn unify_types(&mut self, t1: &Type, t2: &Type) -> StdResult<(), String> {
+        self.unify(t1, t2)
+    }
+    
     /// Unify two types with enhanced error reporting
     fn unify(&mut self, t1: &Type, t2: &Type) -> StdResult<(), String> {
         self.unify_with_span(t1, t2, None)
     }
     
     /// Unify two types with span information for error reporting
-    fn unify_with_span(&mut self, t1: &Type, t2: &Type, span: Option<Span>) -> StdResult<(), String> {
+    pub(crate) fn unify_with_span(&mut self, t1: &Type, t2: &Type, span: Option<Span>) -> StdResult<(), String> {
+        let t1 = &self.shallow_resolve(t1);
+        let t2 = &self.shallow_resolve(t2);
         match (t1, t2) {
             (Type::Var(v1), Type::Var(v2)) if v1 == v2 => Ok(()),
             
             (Type::Var(var), typ) | (typ, Type::Var(var)) => {
-                if typ.free_vars().contains(var) {
+                if self.resolve_type(typ).free_vars().contains(var) {
                     if let Some(span) = span {
                         self.report_error(TypeError::InfiniteType {
                             var: *var,
@@ -638,7 +1445,7 @@ impl InferenceContext {
                     Err(format!("Occurs check failed: {} occurs in {}", 
                                 Type::Var(*var), typ))
                 } else {
-                    // TODO: Apply substitution
+                    self.subst.insert_type(*var, typ.clone());
                     Ok(())
                 }
             }
@@ -714,9 +1521,16 @@ impl InferenceContext {
                 }
             }
             
+            (Type::Record(_) | Type::Row { .. }, Type::Record(_) | Type::Row { .. }) => {
+                self.unify_records(t1, t2, span)
+            }
+            
             (Type::Hole, _) | (_, Type::Hole) => Ok(()),
             
             _ => {
+                if let Some((refined, other)) = self.refined(t1, t2) {
+                    return self.unify_with_span(&refined, &other, span);
+                }
                 if let Some(span) = span {
                     self.report_error(TypeError::TypeMismatch {
                         expected: t1.clone(),
@@ -729,31 +1543,132 @@ impl InferenceContext {
         }
     }
     
-    fn unify_effects(&mut self, _e1: &EffectSet, _e2: &EffectSet) -> StdResult<(), String> {
-        // TODO: Implement proper effect unification
-        Ok(())
-    }
-    
-    #[allow(clippy::only_used_in_recursion)]
-    fn combine_effects(&mut self, e1: EffectSet, e2: EffectSet) -> StdResult<EffectSet, String> {
-        match (e1, e2) {
-            (EffectSet::Empty, e) | (e, EffectSet::Empty) => Ok(e),
-            
-            (EffectSet::Row { mut effects, tail }, EffectSet::Row { effects: mut effects2, tail: tail2 }) => {
-                effects.append(&mut effects2);
-                let combined_tail = match (tail, tail2) {
-                    (Some(t1), Some(t2)) => Some(Box::new(self.combine_effects(*t1, *t2)?)),
-                    (Some(t), None) | (None, Some(t)) => Some(t),
-                    (None, None) => None,
+    /// Unify two record types field by field
+    ///
+    /// Fields present on one side only are pushed into the other side's row
+    /// variable; a closed record cannot absorb them.
+    fn unify_records(&mut self, t1: &Type, t2: &Type, span: Option<Span>) -> StdResult<(), String> {
+        let t1 = &self.resolve_type(t1);
+        let t2 = &self.resolve_type(t2);
+        let (fields1, rest1) = record_parts(t1);
+        let (fields2, rest2) = record_parts(t2);
+        
+        for (name, typ1) in fields1 {
+            if let Some((_, typ2)) = fields2.iter().find(|(other, _)| other == name) {
+                self.unify_with_span(typ1, typ2, span)?;
+            }
+        }
+        
+        let only_in = |fields: &[(Symbol, Type)], other: &[(Symbol, Type)]| -> Vec<(Symbol, Type)> {
+            fields.iter()
+                .filter(|(name, _)| !other.iter().any(|(other, _)| other == name))
+                .cloned()
+                .collect()
+        };
+        let only1 = only_in(fields1, fields2);
+        let only2 = only_in(fields2, fields1);
+        
+        match (rest1, rest2) {
+            (Some(r1), Some(r2)) if r1 != r2 => {
+                let rest = self.var_gen.fresh_type_var();
+                self.bind_row(r1, only2, rest, span)?;
+                self.bind_row(r2, only1, rest, span)
+            }
+            (Some(r1), None) if only1.is_empty() => {
+                self.unify_with_span(&Type::Var(r1), &Type::Record(only2), span)
+            }
+            (None, Some(r2)) if only2.is_empty() => {
+                self.unify_with_span(&Type::Var(r2), &Type::Record(only1), span)
+            }
+            _ if only1.is_empty() && only2.is_empty() => Ok(()),
+            _ => {
+                // Report against a closed side that lacks the field
+                let (field, typ) = match (only1.first(), only2.first()) {
+                    (_, Some((field, _))) if rest1.is_none() => (*field, t1),
+                    (Some((field, _)), _) => (*field, t2),
+                    (None, Some((field, _))) => (*field, t1),
+                    (None, None) => unreachable!("records with the same fields unify"),
                 };
-                Ok(EffectSet::Row { effects, tail: combined_tail })
+                if let Some(span) = span {
+                    self.report_error(TypeError::MissingField { field, typ: typ.clone(), span });
+                }
+                Err(format!("Record {typ} has no field {field}"))
             }
-            
-            (e1, _) => {
-                // TODO: Handle other effect combinations
-                Ok(e1.clone())
+        }
+    }
+    
+    /// Solve a row variable as `fields` followed by a fresh tail
+    fn bind_row(
+        &mut self,
+        var: TypeVar,
+        fields: Vec<(Symbol, Type)>,
+        rest: TypeVar,
+        span: Option<Span>,
+    ) -> StdResult<(), String> {
+        let row = Type::extend_record(fields, &Type::Var(rest));
+        self.unify_with_span(&Type::Var(var), &row, span)
+    }
+    
+    /// Follow solved variables until the outermost constructor is known
+    fn shallow_resolve(&self, typ: &Type) -> Type {
+        match typ {
+            Type::Var(var) => match self.subst.lookup_type(*var) {
+                Some(solved) => self.shallow_resolve(solved),
+                None => typ.clone(),
+            },
+            _ => typ.clone(),
+        }
+    }
+    
+    fn unify_effects(&mut self, e1: &EffectSet, e2: &EffectSet) -> StdResult<(), String> {
+        let mut unifier = Unifier::with_state(
+            std::mem::take(&mut self.subst),
+            std::mem::take(&mut self.var_gen),
+        );
+        let result = unifier.unify_effects(e1.clone(), e2.clone());
+        (self.subst, self.var_gen) = unifier.into_state();
+        result
+    }
+    
+    /// Effects of evaluating two expressions in sequence
+    ///
+    /// The labels are unioned; open tails are unified, since both stand for
+    /// the same surrounding context.
+    pub(crate) fn combine_effects(&mut self, e1: EffectSet, e2: EffectSet) -> StdResult<EffectSet, String> {
+        let e1 = self.resolve_effects(&e1);
+        let e2 = self.resolve_effects(&e2);
+        let tail = match (e1.row_tail(), e2.row_tail()) {
+            (Some(v1), Some(v2)) if v1 != v2 => {
+                self.unify_effects(&EffectSet::Var(v1), &EffectSet::Var(v2))?;
+                Some(v1)
             }
+            (tail1, tail2) => tail1.or(tail2),
+        };
+        
+        let mut labels = row_labels(&e1);
+        labels.extend(row_labels(&e2));
+        Ok(EffectSet::Row {
+            effects: labels,
+            tail: tail.map(|var| Box::new(EffectSet::Var(var))),
         }
+        .normalize())
+    }
+}
+
+/// Fields of a resolved record type and its row variable, if open
+fn record_parts(typ: &Type) -> (&[(Symbol, Type)], Option<TypeVar>) {
+    match typ {
+        Type::Row { fields, rest } => (fields, Some(*rest)),
+        Type::Record(fields) => (fields, None),
+        _ => (&[], None),
+    }
+}
+
+/// Labels of a normalized row
+fn row_labels(effects: &EffectSet) -> Vec<Effect> {
+    match effects {
+        EffectSet::Row { effects, .. } => effects.clone(),
+        _ => Vec::new(),
     }
 }
 
@@ -808,19 +1723,37 @@ fn infer_type_def(_ctx: &mut InferenceContext, _type_def: &TypeDef) -> StdResult
     Ok(())
 }
 
+/// `operation` as declared: its parameter types, then its result type
+/// Whether a type constructor is a rigid type variable of an annotation,
+/// which annotations write in lowercase
+fn is_rigid(name: Symbol) -> bool {
+    name.as_str().starts_with(|c: char| c.is_lowercase())
+}
+
+fn operation_signature(operation: &Operation) -> String {
+    operation.params.iter()
+        .map(|param| match param {
+            Type::Fun { .. } => format!("({param})"),
+            param => param.to_string(),
+        })
+        .chain([operation.return_type.to_string()])
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use x_parser::{ast::Parameter, span::Span, symbol::Symbol, FileId, span::ByteOffset};
+    use x_parser::{ast::Parameter, span::Span, symbol::Symbol, FileId, span::CharOffset};
     
     fn test_span() -> Span {
-        Span::new(FileId::INVALID, ByteOffset(0), ByteOffset(0))
+        Span::new(FileId::INVALID, CharOffset(0), CharOffset(0))
     }
     
     #[test]
     fn test_literal_inference() {
         let mut ctx = InferenceContext::new();
-        let lit = Literal::Integer(42);
+        let lit = Literal::int(42);
         let result = ctx.infer_literal(&lit).unwrap();
         
         assert!(matches!(result.typ, Type::Con(_)));
@@ -865,7 +1798,7 @@ mod tests {
         ctx.env.insert_var(Symbol::intern("id"), id_scheme);
         
         let func = Expr::Var(Symbol::intern("id"), test_span());
-        let arg = Expr::Literal(Literal::Integer(42), test_span());
+        let arg = Expr::Literal(Literal::int(42), test_span());
         
         let result = ctx.infer_app(&func, &[arg]).unwrap();
         
@@ -875,4 +1808,46 @@ mod tests {
         // that will be unified to Int during constraint solving
         assert!(matches!(result.typ, Type::Var(_)));
     }
-}
\ No newline at end of file
+
+    /// `add : Int -> Int -> Int`, curried
+    fn curried_add(ctx: &mut InferenceContext) -> Expr {
+        let int = Type::Con(Symbol::intern("Int"));
+        let add_type = Type::Fun {
+            params: vec![int.clone()],
+            return_type: Box::new(Type::Fun {
+                params: vec![int.clone()],
+                return_type: Box::new(int),
+                effects: EffectSet::Empty,
+            }),
+            effects: EffectSet::Empty,
+        };
+        ctx.env.insert_var(Symbol::intern("add"), TypeScheme::monotype(add_type));
+        Expr::Var(Symbol::intern("add"), test_span())
+    }
+
+    #[test]
+    fn test_curried_function_applies_to_several_arguments() {
+        let mut ctx = InferenceContext::new();
+        let add = curried_add(&mut ctx);
+        let args = [Expr::Literal(Literal::int(1), test_span()), Expr::Literal(Literal::int(2), test_span())];
+
+        let result = ctx.infer_app(&add, &args).unwrap();
+        assert_eq!(ctx.resolve_type(&result.typ), Type::Con(Symbol::intern("Int")));
+
+        // Partial application still yields the remaining function
+        let partial = ctx.infer_app(&add, &args[..1]).unwrap();
+        assert!(matches!(ctx.resolve_type(&partial.typ), Type::Fun { params, .. } if params.len() == 1));
+    }
+
+    #[test]
+    fn test_too_many_arguments_are_rejected() {
+        let mut ctx = InferenceContext::new();
+        let add = curried_add(&mut ctx);
+        let args: Vec<Expr> = (1..=3).map(|n| Expr::Literal(Literal::int(n), test_span())).collect();
+        assert!(ctx.infer_app(&add, &args).is_err());
+
+        // A mistyped later argument is caught in its own group
+        let args = [Expr::Literal(Literal::int(1), test_span()), Expr::Literal(Literal::Bool(true), test_span())];
+        assert!(ctx.infer_app(&add, &args).is_err());
+    }
+}
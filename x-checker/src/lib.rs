@@ -14,15 +14,23 @@ pub mod binary_type_checker;
 pub mod constraints;
 pub mod checker;
 pub mod builtins;
+pub mod continuations;
+pub mod signatures;
+pub mod wasm;
+pub mod database;
 
 // Re-export core types
-pub use types::{Type, TypeScheme, TypeVar, TypeEnv};
+pub use types::{Type, TypeScheme, TypeVar, TypeEnv, ModuleInterface};
 pub use inference::{InferenceContext, InferenceResult};
 pub use types::{Effect, EffectSet};
 pub use error_reporting::{TypeError, TypeErrorReporter};
 pub use checker::{TypeChecker, CheckResult, EffectConstraint};
+pub use binary_type_checker::{BinaryTypeChecker, SerializeWithTypes};
+pub use constraints::{Evidence, instance_dictionary_name};
+pub use continuations::Resumption;
+pub use database::{CheckCancellation, ItemCheck, ItemKey, ModuleSource, TypeCheckDatabaseImpl, TypeCheckDb};
 
-use x_parser::{CompilationUnit, Symbol, Span};
+use x_parser::{CompilationUnit, CancellationToken, Cancelled, Symbol};
 
 /// Type check a compilation unit
 pub fn type_check(cu: &CompilationUnit) -> CheckResult {
@@ -30,96 +38,26 @@ pub fn type_check(cu: &CompilationUnit) -> CheckResult {
     checker.check_compilation_unit(cu)
 }
 
-/// Type check with custom environment
-pub fn type_check_with_env(cu: &CompilationUnit, env: TypeEnv) -> CheckResult {
-    let mut checker = TypeChecker::with_env(env);
-    checker.check_compilation_unit(cu)
+/// Type check a compilation unit, stopping early once `token` is cancelled
+pub fn type_check_cancellable(cu: &CompilationUnit, token: &CancellationToken) -> Result<CheckResult, Cancelled> {
+    let mut checker = TypeChecker::new().with_cancellation(token.clone());
+    checker.try_check_compilation_unit(cu)
 }
 
-/// Incremental type checking interface using Salsa
-#[salsa::query_group(TypeCheckDatabase)]
-pub trait TypeCheckDb: salsa::Database {
-    /// Get the type scheme for a symbol
-    #[salsa::input]
-    fn symbol_type(&self, symbol: Symbol) -> Option<TypeScheme>;
-
-    /// Get inferred type for an expression
-    fn infer_expression_type(&self, expr_id: ExprId) -> types::Type;
-
-    /// Get effect set for an expression
-    fn infer_expression_effects(&self, expr_id: ExprId) -> EffectSet;
-
-    /// Check if two types are compatible
-    fn types_compatible(&self, t1: types::Type, t2: types::Type) -> bool;
-
-    /// Resolve symbol in scope
-    fn resolve_symbol(&self, symbol: Symbol, scope_id: ScopeId) -> Option<SymbolInfo>;
+/// Type check the latest definition of `item_name` and the definitions and
+/// declarations it depends on, transitively, leaving the rest of the unit
+/// unchecked; `None` if the unit does not define it
+pub fn type_check_item(cu: &CompilationUnit, item_name: Symbol) -> Option<CheckResult> {
+    let mut db = TypeCheckDatabaseImpl::new();
+    db.set_unit(cu.span.file_id, cu.clone());
+    db.definition_result(cu.span.file_id, item_name)
+        .unwrap_or_else(|_| unreachable!("nothing cancels a private database"))
 }
 
-/// Expression identifier for incremental type checking
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct ExprId(pub u32);
-
-/// Scope identifier for symbol resolution
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct ScopeId(pub u32);
-
-/// Symbol information for resolution
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct SymbolInfo {
-    pub symbol: Symbol,
-    pub type_scheme: TypeScheme,
-    pub span: Span,
-    pub visibility: x_parser::Visibility,
-}
-
-/// Database implementation for type checking
-#[salsa::database(TypeCheckDatabase)]
-#[derive(Default)]
-pub struct TypeCheckDatabaseImpl {
-    storage: salsa::Storage<Self>,
-}
-
-impl salsa::Database for TypeCheckDatabaseImpl {}
-
-/// Convenience functions for type checking queries
-impl TypeCheckDatabaseImpl {
-    pub fn new() -> Self {
-        Self::default()
-    }
-
-    /// Set type for a symbol
-    pub fn set_type_for_symbol(&mut self, symbol: Symbol, type_scheme: TypeScheme) {
-        self.set_symbol_type(symbol, Some(type_scheme));
-    }
-
-    /// Check if compilation unit is well-typed
-    pub fn is_well_typed(&self, cu: &CompilationUnit) -> bool {
-        let result = type_check(cu);
-        result.errors.is_empty()
-    }
-}
-
-/// Query implementations
-fn infer_expression_type(_db: &dyn TypeCheckDb, _expr_id: ExprId) -> types::Type {
-    // TODO: Implement incremental expression type inference
-    types::Type::Unknown
-}
-
-fn infer_expression_effects(_db: &dyn TypeCheckDb, _expr_id: ExprId) -> EffectSet {
-    // TODO: Implement incremental effect inference
-    EffectSet::empty()
-}
-
-fn types_compatible(_db: &dyn TypeCheckDb, t1: types::Type, t2: types::Type) -> bool {
-    // TODO: Implement type compatibility checking
-    let mut unifier = crate::unification::Unifier::new();
-    unifier.unify(&t1, &t2).is_ok()
-}
-
-fn resolve_symbol(_db: &dyn TypeCheckDb, _symbol: Symbol, _scope_id: ScopeId) -> Option<SymbolInfo> {
-    // TODO: Implement incremental symbol resolution
-    None
+/// Type check with custom environment
+pub fn type_check_with_env(cu: &CompilationUnit, env: TypeEnv) -> CheckResult {
+    let mut checker = TypeChecker::with_env(env);
+    checker.check_compilation_unit(cu)
 }
 
 #[cfg(test)]
@@ -137,6 +75,22 @@ mod tests {
         assert!(result.errors.is_empty());
     }
 
+    #[test]
+    fn test_type_check_item() {
+        let source = "module Test\nlet one = 1\nlet two = one + one\nlet bad = true + 1\nlet unused = \"x\" + 2";
+        let cu = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+
+        let result = type_check_item(&cu, Symbol::intern("two")).unwrap();
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        assert_eq!(result.inferred_types[&Symbol::intern("two")].body.to_string(), "Int");
+        assert!(!result.inferred_types.contains_key(&Symbol::intern("bad")));
+
+        let result = type_check_item(&cu, Symbol::intern("bad")).unwrap();
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].span(), type_check(&cu).errors[0].span());
+        assert!(type_check_item(&cu, Symbol::intern("missing")).is_none());
+    }
+
     #[test]
     fn test_type_database() {
         let mut db = TypeCheckDatabaseImpl::new();
@@ -147,15 +101,4 @@ mod tests {
         db.set_type_for_symbol(symbol, type_scheme.clone());
         assert_eq!(db.symbol_type(symbol), Some(type_scheme));
     }
-
-    #[test]
-    fn test_incremental_queries() {
-        let db = TypeCheckDatabaseImpl::new();
-        
-        let expr_id = ExprId(0);
-        let _typ = db.infer_expression_type(expr_id);
-        let _effects = db.infer_expression_effects(expr_id);
-        
-        // These should not panic even with unimplemented queries
-    }
-}
\ No newline at end of file
+}
@@ -5,7 +5,7 @@
 
 use x_parser::{
     ModulePath, Import, ImportKind,
-    FileId,
+    FileId, Symbol, Visibility,
 };
 // use crate::database::Database;
 use std::result::Result as StdResult;
@@ -25,6 +25,8 @@ pub struct ModuleResolver {
     dependencies: HashMap<String, DependencyInfo>,
     /// Module path to file ID mapping cache
     module_cache: HashMap<ModulePath, FileId>,
+    /// Lazy imports of each module, resolved on first use
+    deferred: HashMap<FileId, Vec<ModulePath>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -87,6 +89,7 @@ impl ModuleResolver {
             source_dirs,
             dependencies: HashMap::new(),
             module_cache: HashMap::new(),
+            deferred: HashMap::new(),
         }
     }
     
@@ -119,7 +122,7 @@ impl ModuleResolver {
     /// Resolve module imports and build dependency graph
     pub fn resolve_imports(
         &mut self, 
-        _file_id: FileId, 
+        file_id: FileId, 
         imports: &[Import]
     ) -> StdResult<Vec<FileId>, String> {
         let mut resolved_deps = Vec::new();
@@ -127,17 +130,19 @@ impl ModuleResolver {
         for import in imports {
             // Handle different import kinds
             match &import.kind {
-                ImportKind::Qualified | ImportKind::Selective(_) | ImportKind::Wildcard => {
+                ImportKind::Qualified | ImportKind::Selective(_) | ImportKind::Wildcard | ImportKind::Signature(_) => {
                     let dep_file_id = self.resolve_module(&import.module_path)?;
                     resolved_deps.push(dep_file_id);
                 }
                 ImportKind::Lazy => {
-                    // Lazy imports are resolved on-demand
-                    let dep_file_id = self.resolve_module(&import.module_path)?;
-                    resolved_deps.push(dep_file_id);
+                    // Lazy imports are resolved on first use, see `resolve_deferred`
+                    let deferred = self.deferred.entry(file_id).or_default();
+                    if !deferred.iter().any(|path| path.segments == import.module_path.segments) {
+                        deferred.push(import.module_path.clone());
+                    }
                 }
                 ImportKind::Conditional(_condition) => {
-                    // TODO: Handle conditional imports
+                    // Kept or dropped per target by the compiler before resolution
                 }
                 ImportKind::Interface { .. } => {
                     // TODO: Handle interface imports
@@ -154,6 +159,20 @@ impl ModuleResolver {
         Ok(resolved_deps)
     }
     
+    /// Lazy imports of `file_id` not resolved yet
+    pub fn deferred_imports(&self, file_id: FileId) -> &[ModulePath] {
+        self.deferred.get(&file_id).map_or(&[], Vec::as_slice)
+    }
+    
+    /// Resolve a lazy import of `file_id` on its first use
+    pub fn resolve_deferred(&mut self, file_id: FileId, module_path: &ModulePath) -> StdResult<FileId, String> {
+        let resolved = self.resolve_module(module_path)?;
+        if let Some(deferred) = self.deferred.get_mut(&file_id) {
+            deferred.retain(|path| path.segments != module_path.segments);
+        }
+        Ok(resolved)
+    }
+    
     /// Check for circular dependencies
     pub fn check_circular_dependencies(&self, graph: &DependencyGraph) -> StdResult<(), String> {
         for (&module, deps) in &graph.direct_deps {
@@ -427,6 +446,52 @@ impl DependencyGraph {
 
 /// Workspace configuration structure
 // TODO: Enable when toml crate is added
+/// Whether an item of module `owner` declared with `visibility` may be used
+/// from module `from`
+///
+/// Modules sharing the first segment of their path form a package, which
+/// `pub(crate)` and `pub(package)` open an item to; `pub(super)` opens it to
+/// the module's parent and everything under it.
+pub fn is_visible(visibility: &Visibility, owner: &ModulePath, from: &ModulePath) -> bool {
+    let (owner, from) = (&owner.segments, &from.segments);
+    match visibility {
+        Visibility::Public | Visibility::Component { .. } => true,
+        Visibility::Private | Visibility::SelfModule => owner == from,
+        Visibility::Crate | Visibility::Package => owner.first() == from.first(),
+        Visibility::Super => from.starts_with(parent(owner)),
+        Visibility::InPath(path) => owner == from || from.starts_with(&path.segments),
+    }
+}
+
+/// The narrowest of `pub(super)`, `pub(crate)` and `pub` that lets module
+/// `from` use an item of module `owner`
+pub fn required_visibility(owner: &ModulePath, from: &ModulePath) -> Visibility {
+    if owner.segments.len() > 1 && is_visible(&Visibility::Super, owner, from) {
+        Visibility::Super
+    } else if is_visible(&Visibility::Crate, owner, from) {
+        Visibility::Crate
+    } else {
+        Visibility::Public
+    }
+}
+
+/// How a visibility is written, `private` when it has no modifier
+pub fn describe_visibility(visibility: &Visibility) -> String {
+    match visibility {
+        Visibility::Private => "private".to_string(),
+        Visibility::Public | Visibility::Component { .. } => "pub".to_string(),
+        Visibility::Crate => "pub(crate)".to_string(),
+        Visibility::Package => "pub(package)".to_string(),
+        Visibility::Super => "pub(super)".to_string(),
+        Visibility::SelfModule => "pub(self)".to_string(),
+        Visibility::InPath(path) => format!("pub(in {path})"),
+    }
+}
+
+fn parent(segments: &[Symbol]) -> &[Symbol] {
+    &segments[..segments.len().saturating_sub(1)]
+}
+
 // #[derive(Debug, Deserialize)]
 #[allow(dead_code)]
 struct WorkspaceConfig {
@@ -441,7 +506,7 @@ mod tests {
     use super::*;
     use tempfile::tempdir;
     use std::fs;
-    use x_parser::{Symbol, span::{Span, ByteOffset}};
+    use x_parser::{Symbol, span::{Span, CharOffset}};
 
     #[test]
     fn test_module_path_resolution() {
@@ -457,7 +522,7 @@ mod tests {
         
         let module_path = ModulePath::new(
             vec![Symbol::intern("Test")],
-            Span::new(FileId::INVALID, ByteOffset(0), ByteOffset(0))
+            Span::new(FileId::INVALID, CharOffset(0), CharOffset(0))
         );
         
         let resolved = resolver.resolve_local_module(&module_path);
@@ -465,6 +530,57 @@ mod tests {
         assert_eq!(resolved.unwrap(), module_file);
     }
 
+    fn path(dotted: &str) -> ModulePath {
+        let span = Span::new(FileId::INVALID, CharOffset(0), CharOffset(0));
+        ModulePath::new(dotted.split('.').map(Symbol::intern).collect(), span)
+    }
+
+    #[test]
+    fn test_lazy_imports_resolve_on_first_use() {
+        let temp_dir = tempdir().unwrap();
+        let src_dir = temp_dir.path().join("src");
+        fs::create_dir_all(&src_dir).unwrap();
+        fs::write(src_dir.join("later.eff"), "module Later\n").unwrap();
+
+        let mut resolver = ModuleResolver::new(temp_dir.path().to_path_buf());
+        let file = FileId::new(7);
+        let import = Import {
+            module_path: path("Later"),
+            kind: ImportKind::Lazy,
+            alias: None,
+            version_spec: None,
+            span: Span::new(FileId::INVALID, CharOffset(0), CharOffset(0)),
+        };
+
+        assert_eq!(resolver.resolve_imports(file, &[import]).unwrap(), []);
+        assert_eq!(resolver.deferred_imports(file), [path("Later")]);
+
+        assert!(resolver.resolve_deferred(file, &path("Later")).is_ok());
+        assert!(resolver.deferred_imports(file).is_empty());
+    }
+
+    #[test]
+    fn test_visibility_across_modules() {
+        let owner = path("App.Util.Strings");
+        let sibling = path("App.Util.Main");
+        let cousin = path("App.Web.Server");
+        let outside = path("Other");
+
+        assert!(is_visible(&Visibility::Super, &owner, &sibling));
+        assert!(!is_visible(&Visibility::Super, &owner, &cousin));
+        assert!(is_visible(&Visibility::Crate, &owner, &cousin));
+        assert!(!is_visible(&Visibility::Package, &owner, &outside));
+        assert!(is_visible(&Visibility::InPath(path("App.Web")), &owner, &cousin));
+        assert!(!is_visible(&Visibility::InPath(path("App.Web")), &owner, &sibling));
+        assert!(!is_visible(&Visibility::Private, &owner, &sibling));
+        assert!(is_visible(&Visibility::SelfModule, &owner, &owner));
+
+        assert_eq!(required_visibility(&owner, &sibling), Visibility::Super);
+        assert_eq!(required_visibility(&owner, &cousin), Visibility::Crate);
+        assert_eq!(required_visibility(&owner, &outside), Visibility::Public);
+        assert_eq!(describe_visibility(&Visibility::InPath(path("App.Web"))), "pub(in App.Web)");
+    }
+
     #[test]
     fn test_dependency_graph() {
         let mut graph = DependencyGraph::new();
@@ -0,0 +1,265 @@
+//! Module types and signature matching
+//!
+//! A `module type` lists abstract types and typed values. A module matches
+//! it when it exports every listed value at a type at least as general as
+//! the listed one, each abstract type standing for the same type
+//! throughout. A module packed as a value has the nominal type named after
+//! its module type; opening one again keeps its abstract types opaque.
+
+use crate::checker::replace_constructors;
+use crate::error_reporting::TypeError;
+use crate::inference::{InferenceContext, InferenceResult};
+use crate::types::{EffectSet, ModuleInterface, Substitution, Type, TypeScheme};
+use x_parser::{Expr, ModuleTypeDef, SignatureItem, Span, Symbol};
+use std::collections::HashMap;
+
+/// A checked `module type`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModuleType {
+    pub name: Symbol,
+    /// Types the signature keeps abstract
+    pub abstract_types: Vec<Symbol>,
+    /// Listed values; lowercase names other than the abstract types are
+    /// each value's own type parameters
+    pub values: Vec<(Symbol, Type)>,
+}
+
+impl ModuleType {
+    /// Collect the abstract types and values of a definition, converting
+    /// written types with `convert`
+    pub fn from_def(def: &ModuleTypeDef, convert: impl Fn(&x_parser::Type) -> Type) -> Self {
+        let mut module_type = ModuleType { name: def.name, abstract_types: Vec::new(), values: Vec::new() };
+        for item in &def.signature.items {
+            match item {
+                SignatureItem::TypeSig { name, .. } => module_type.abstract_types.push(*name),
+                SignatureItem::ValueSig { name, type_annotation, .. } => {
+                    module_type.values.push((*name, convert(type_annotation)));
+                }
+                SignatureItem::EffectSig { .. } => {}
+            }
+        }
+        module_type
+    }
+
+    /// The type of modules packed with this signature
+    pub fn packed_type(&self) -> Type {
+        Type::Con(self.name)
+    }
+}
+
+impl InferenceContext {
+    /// `module M : S` checks that the imported module `M` matches `S`
+    pub(crate) fn infer_pack(&mut self, module: Symbol, signature: Symbol, span: Span) -> Result<InferenceResult, String> {
+        let signature_type = self.module_type(signature, span)?;
+        let Some(interface) = self.modules.get(&module).cloned() else {
+            self.report_error(TypeError::UnboundVariable { name: module, span });
+            return Err(format!("Unbound module: {module}"));
+        };
+        if let Err(reason) = self.match_signature(&interface, &signature_type) {
+            self.report_error(TypeError::SignatureMismatch { module, signature, reason: reason.clone(), span });
+            return Err(reason);
+        }
+        Ok(InferenceResult {
+            typ: signature_type.packed_type(),
+            effects: EffectSet::Empty,
+            constraints: Vec::new(),
+        })
+    }
+
+    /// `let module P : S = e in body` opens the packed module `e` as `P`
+    /// within `body`, whose type may not mention `P`'s abstract types
+    pub(crate) fn infer_unpack(
+        &mut self,
+        name: Symbol,
+        signature: Symbol,
+        value: &Expr,
+        body: &Expr,
+        span: Span,
+    ) -> Result<InferenceResult, String> {
+        let signature_type = self.module_type(signature, span)?;
+        let value_result = self.infer_expr(value)?;
+        self.unify_with_span(&value_result.typ, &signature_type.packed_type(), Some(value.span()))?;
+
+        let opened = self.open_signature(name, &signature_type);
+        let outer = self.modules.insert(name, opened);
+        let body_result = self.infer_expr(body);
+        match outer {
+            Some(outer) => self.modules.insert(name, outer),
+            None => self.modules.remove(&name),
+        };
+        let body_result = body_result?;
+
+        let mut mentioned = Vec::new();
+        constructors(&self.resolve_type(&body_result.typ), &mut mentioned);
+        let escaping = signature_type.abstract_types.iter()
+            .map(|typ| opaque_type(name, *typ))
+            .find(|typ| mentioned.contains(typ));
+        if let Some(escaping) = escaping {
+            let message = format!("abstract type {escaping} escapes the scope of module {name}");
+            self.report_error(TypeError::InferenceError { message: message.clone(), symbol: name, span });
+            return Err(message);
+        }
+
+        Ok(InferenceResult {
+            typ: body_result.typ,
+            effects: self.combine_effects(value_result.effects, body_result.effects)?,
+            constraints: Vec::new(),
+        })
+    }
+
+    fn module_type(&mut self, name: Symbol, span: Span) -> Result<ModuleType, String> {
+        self.module_types.get(&name).cloned().ok_or_else(|| {
+            self.report_error(TypeError::UnboundVariable { name, span });
+            format!("Unknown module type: {name}")
+        })
+    }
+
+    /// Check that `interface` provides every value of `signature`, naming
+    /// the first one it lacks or gives a less general type
+    pub fn match_signature(&mut self, interface: &ModuleInterface, signature: &ModuleType) -> Result<(), String> {
+        let abstract_types: HashMap<Symbol, Type> = signature.abstract_types.iter()
+            .map(|name| (*name, self.fresh_type_var()))
+            .collect();
+        for (name, expected) in &signature.values {
+            let Some(scheme) = interface.values.get(name) else {
+                return Err(format!("missing value {name}"));
+            };
+            // The signature's own type parameters stay rigid, so only a
+            // polymorphic enough implementation unifies with them
+            let expected = replace_constructors(expected, &abstract_types);
+            let (actual, _) = self.instantiate(scheme);
+            self.unify_types(&actual, &expected)
+                .map_err(|error| format!("{name} has type {}, not {expected}: {error}", self.resolve_type(&actual)))?;
+        }
+        Ok(())
+    }
+
+    /// Whether every use of a value of type `specific` can take one of type
+    /// `general` instead, i.e. `general` instantiates to `specific`
+    pub fn is_at_least_as_general(&mut self, general: &TypeScheme, specific: &TypeScheme) -> bool {
+        // `specific`'s own type variables stay rigid, as in `match_signature`
+        let mut subst = Substitution::new();
+        for (i, var) in specific.type_vars.iter().enumerate() {
+            subst.insert_type(*var, Type::Con(Symbol::intern(&format!("'rigid{i}"))));
+        }
+        let specific = specific.body.apply_subst(&subst);
+        let (general, _) = self.instantiate(general);
+        self.unify_types(&general, &specific).is_ok()
+    }
+
+    /// What `signature` lets code see of a module opened as `name`: its
+    /// values, with abstract types replaced by opaque `name.t` types
+    pub fn open_signature(&mut self, name: Symbol, signature: &ModuleType) -> ModuleInterface {
+        let opaque: HashMap<Symbol, Type> = signature.abstract_types.iter()
+            .map(|typ| (*typ, Type::Con(opaque_type(name, *typ))))
+            .collect();
+        let mut interface = ModuleInterface::default();
+        for (value, typ) in &signature.values {
+            let typ = replace_constructors(typ, &opaque);
+            let mut params = Vec::new();
+            constructors(&typ, &mut params);
+            params.retain(|param| param.as_str().starts_with(|c: char| c.is_lowercase()));
+            let vars: Vec<_> = params.iter().map(|_| self.var_gen.fresh_type_var()).collect();
+            let subst = params.iter().zip(&vars).map(|(param, var)| (*param, Type::Var(*var))).collect();
+            interface.values.insert(*value, TypeScheme {
+                type_vars: vars,
+                effect_vars: Vec::new(),
+                constraints: Vec::new(),
+                body: replace_constructors(&typ, &subst),
+            });
+        }
+        interface
+    }
+}
+
+/// The opaque type standing for abstract type `typ` of a module opened as `module`
+pub fn opaque_type(module: Symbol, typ: Symbol) -> Symbol {
+    Symbol::intern(&format!("{module}.{typ}"))
+}
+
+/// Constructor names in `typ`; lowercase ones stand for type parameters
+fn constructors(typ: &Type, out: &mut Vec<Symbol>) {
+    match typ {
+        Type::Con(name) if !out.contains(name) => out.push(*name),
+        Type::App(con, args) => {
+            constructors(con, out);
+            args.iter().for_each(|arg| constructors(arg, out));
+        }
+        Type::Fun { params, return_type, .. } => {
+            params.iter().for_each(|param| constructors(param, out));
+            constructors(return_type, out);
+        }
+        Type::Tuple(types) => types.iter().for_each(|t| constructors(t, out)),
+        Type::Record(fields) | Type::Row { fields, .. } => fields.iter().for_each(|(_, t)| constructors(t, out)),
+        Type::Variant(variants) => variants.iter().flat_map(|(_, types)| types).for_each(|t| constructors(t, out)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::checker::{CheckResult, TypeCheck, TypeChecker};
+    use crate::inference::InferenceContext;
+    use crate::types::ModuleInterface;
+    use x_parser::{parse_source, FileId, Symbol, SyntaxStyle};
+
+    const PLUGIN: &str = "module type Plugin { type t  make : Int -> t  show : t -> Int }";
+
+    fn math() -> ModuleInterface {
+        let library = parse_source(
+            "module Math\npub let make = fun n -> n\npub let show = fun n -> n + 1\npub let extra = 3",
+            FileId::new(1),
+            SyntaxStyle::SExpression,
+        ).unwrap();
+        library.type_check().module_interface(&library.module)
+    }
+
+    fn check(imports: &str, items: &str) -> CheckResult {
+        let source = format!("module Main\n{imports}\n{PLUGIN}\n{items}");
+        let unit = parse_source(&source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        TypeChecker::new().with_module("Math", math()).check_compilation_unit(&unit)
+    }
+
+    #[test]
+    fn test_packed_modules_open_as_their_signature() {
+        let result = check(
+            "import Math",
+            "let plugin = module Math : Plugin\nlet run = fun p -> (let module P : Plugin = p in P.show (P.make 1))\nlet answer = run plugin",
+        );
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        assert_eq!(result.inferred_types[&Symbol::intern("plugin")].body.to_string(), "Plugin");
+        assert_eq!(result.inferred_types[&Symbol::intern("answer")].body.to_string(), "Int");
+
+        let result = check("import Math", "let leak = fun p -> (let module P : Plugin = p in P.make 1)");
+        assert!(result.errors.iter().any(|error| error.to_string().contains("P.t escapes")), "{:?}", result.errors);
+    }
+
+    #[test]
+    fn test_modules_must_match_the_signature_they_are_packed_with() {
+        let result = check("import Math", "module type Strict { id : a -> a }\nlet strict = module Math : Strict");
+        assert_eq!(result.errors.iter().map(|error| error.code()).collect::<Vec<_>>(), ["E0119"]);
+        assert!(result.errors[0].to_string().contains("missing value id"));
+
+        let result = check("import Math", "module type Named { show : t -> String }\nlet named = module Math : Named");
+        assert!(result.errors[0].to_string().starts_with("Module Math does not match Named: show has type"));
+    }
+
+    #[test]
+    fn test_signature_imports_only_expose_listed_values() {
+        let result = check("import Math : Plugin", "let shown = Math.show (Math.make 2)\nlet hidden = Math.extra");
+        assert_eq!(result.inferred_types[&Symbol::intern("shown")].body.to_string(), "Int");
+        assert_eq!(result.errors.len(), 1);
+        assert!(result.errors[0].to_string().contains("Math.extra"));
+    }
+
+    #[test]
+    fn test_generality_of_schemes() {
+        let result = check("", "let id = fun x -> x\nlet inc = fun n -> n + 1\nlet first = fun x y -> x");
+        let scheme = |name: &str| result.inferred_types[&Symbol::intern(name)].clone();
+        let mut context = InferenceContext::new();
+        assert!(context.is_at_least_as_general(&scheme("id"), &scheme("inc")));
+        assert!(!context.is_at_least_as_general(&scheme("inc"), &scheme("id")));
+        assert!(context.is_at_least_as_general(&scheme("id"), &scheme("id")));
+        assert!(!context.is_at_least_as_general(&scheme("id"), &scheme("first")));
+    }
+}
@@ -5,7 +5,8 @@
 //! - Row polymorphism for effects
 //! - Kind system for higher-kinded types
 
-use x_parser::Symbol;
+use x_parser::{Deprecation, Fixity, ModulePath, Symbol, Visibility};
+use crate::resolver::{is_visible, required_visibility};
 use std::collections::{HashMap, HashSet};
 use std::fmt;
 use serde::{Deserialize, Serialize};
@@ -51,6 +52,12 @@ pub enum Type {
     /// Record type {x: Int, y: String}
     Record(Vec<(Symbol, Type)>),
     
+    /// Open record type {x: Int | r}: the listed fields plus whatever the row variable stands for
+    Row {
+        fields: Vec<(Symbol, Type)>,
+        rest: TypeVar,
+    },
+    
     /// Variant type (|A Int | B String|)
     Variant(Vec<(Symbol, Vec<Type>)>),
     
@@ -174,6 +181,9 @@ pub struct TypeEnv {
     
     /// Type class instances
     pub instances: HashMap<Symbol, Vec<Instance>>,
+
+    /// Variables whose uses are warned about, from `@deprecated` doc attributes
+    pub deprecated: HashMap<Symbol, Deprecation>,
 }
 
 /// Type class instance
@@ -181,6 +191,58 @@ pub struct TypeEnv {
 pub struct Instance {
     pub constraints: Vec<Constraint>,
     pub head: Constraint,
+    /// Name of the dictionary value the instance is lowered to
+    pub dictionary: Symbol,
+}
+
+/// What a checked module offers the modules importing it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ModuleInterface {
+    /// Public values, including data constructors
+    pub values: HashMap<Symbol, TypeScheme>,
+    /// Data constructors among `values` and `restricted`, which patterns can match on
+    pub constructors: HashSet<Symbol>,
+    /// Values declared with a visibility narrower than `pub`, and that visibility
+    pub restricted: HashMap<Symbol, (TypeScheme, Visibility)>,
+    /// Path of the module, which restricted visibilities are relative to
+    pub path: Option<ModulePath>,
+    /// Declared fixities of the operators among `values` and `restricted`,
+    /// which importing modules are parsed with
+    pub fixities: HashMap<Symbol, Fixity>,
+    /// Deprecated values among `values` and `restricted`
+    pub deprecated: HashMap<Symbol, Deprecation>,
+}
+
+impl ModuleInterface {
+    /// The scheme of `name` as seen from module `from`, or the visibility
+    /// that keeps `from` from using it
+    pub fn lookup(&self, name: Symbol, from: Option<&ModulePath>) -> Option<Result<&TypeScheme, &Visibility>> {
+        if let Some(scheme) = self.values.get(&name) {
+            return Some(Ok(scheme));
+        }
+        let (scheme, visibility) = self.restricted.get(&name)?;
+        match (&self.path, from) {
+            (Some(owner), Some(from)) if is_visible(visibility, owner, from) => Some(Ok(scheme)),
+            _ => Some(Err(visibility)),
+        }
+    }
+
+    /// The narrowest visibility that lets module `from` use this module's values
+    pub fn required_visibility(&self, from: Option<&ModulePath>) -> Visibility {
+        match (&self.path, from) {
+            (Some(owner), Some(from)) => required_visibility(owner, from),
+            _ => Visibility::Public,
+        }
+    }
+
+    /// Every value module `from` may use
+    pub fn visible_values<'a>(&'a self, from: Option<&'a ModulePath>) -> impl Iterator<Item = (Symbol, &'a TypeScheme)> + 'a {
+        let restricted = self.restricted.iter().filter_map(move |(name, (scheme, visibility))| {
+            let owner = self.path.as_ref()?;
+            is_visible(visibility, owner, from?).then_some((*name, scheme))
+        });
+        self.values.iter().map(|(name, scheme)| (*name, scheme)).chain(restricted)
+    }
 }
 
 /// Type variable generator
@@ -232,6 +294,7 @@ impl TypeEnv {
             type_cons: HashMap::new(),
             effects: HashMap::new(),
             instances: HashMap::new(),
+            deprecated: HashMap::new(),
         };
         
         // Add built-in types
@@ -248,6 +311,9 @@ impl TypeEnv {
         self.type_cons.insert(symbols::STRING(), (Kind::Star, vec![]));
         self.type_cons.insert(symbols::BOOL(), (Kind::Star, vec![]));
         self.type_cons.insert(symbols::UNIT_TYPE(), (Kind::Star, vec![]));
+        // What `Async.spawn` starts and `Async.await` waits for
+        let promise = Symbol::intern("Promise");
+        self.type_cons.insert(promise, (Kind::Arrow(Box::new(Kind::Star), Box::new(Kind::Star)), vec![TypeVar(0)]));
         
         // Built-in effects
         let io_effect = Effect {
@@ -283,6 +349,57 @@ impl TypeEnv {
             ],
         };
         self.effects.insert(symbols::STATE(), state_effect);
+        
+        let reader = Symbol::intern("Reader");
+        let reader_effect = Effect {
+            name: reader,
+            operations: vec![
+                Operation {
+                    name: Symbol::intern("ask"),
+                    params: vec![],
+                    return_type: Type::Var(TypeVar(0)),
+                },
+            ],
+        };
+        self.effects.insert(reader, reader_effect);
+        
+        // `raise` never returns, so its result takes any type
+        let exn = Symbol::intern("Exn");
+        let exn_effect = Effect {
+            name: exn,
+            operations: vec![
+                Operation {
+                    name: Symbol::intern("raise"),
+                    params: vec![Type::Var(TypeVar(0))],
+                    return_type: Type::Var(TypeVar(1)),
+                },
+            ],
+        };
+        self.effects.insert(exn, exn_effect);
+        
+        // Handled by the host's event loop around `main`
+        let value = Type::Var(TypeVar(0));
+        let promise_of_value = Type::App(Box::new(Type::Con(promise)), vec![value.clone()]);
+        let async_effect = Effect {
+            name: symbols::ASYNC(),
+            operations: vec![
+                Operation {
+                    name: Symbol::intern("await"),
+                    params: vec![promise_of_value.clone()],
+                    return_type: value.clone(),
+                },
+                Operation {
+                    name: Symbol::intern("spawn"),
+                    params: vec![Type::Fun {
+                        params: vec![Type::Con(symbols::UNIT_TYPE())],
+                        return_type: Box::new(value),
+                        effects: EffectSet::Var(EffectVar(0)),
+                    }],
+                    return_type: promise_of_value,
+                },
+            ],
+        };
+        self.effects.insert(symbols::ASYNC(), async_effect);
     }
     
     pub fn lookup_var(&self, name: Symbol) -> Option<&TypeScheme> {
@@ -293,6 +410,12 @@ impl TypeEnv {
         self.vars.insert(name, scheme);
     }
     
+    /// Bind a local variable, which shadows any deprecated variable of that name
+    pub fn bind_local(&mut self, name: Symbol, scheme: TypeScheme) {
+        self.deprecated.remove(&name);
+        self.insert_var(name, scheme);
+    }
+    
     /// Bind a variable to a type scheme (alias for insert_var)
     pub fn bind(&mut self, name: Symbol, scheme: TypeScheme) {
         self.insert_var(name, scheme);
@@ -339,7 +462,7 @@ impl Type {
             }
             Type::Fun { .. } => Kind::Star,
             Type::Forall { body, .. } => body.kind(env),
-            Type::Record(_) => Kind::Star,
+            Type::Record(_) | Type::Row { .. } => Kind::Star,
             Type::Variant(_) => Kind::Star,
             Type::Tuple(_) => Kind::Star,
             Type::Hole => Kind::Star,
@@ -355,6 +478,41 @@ impl Type {
         vars
     }
     
+    /// Get free effect row variables, i.e. the tails of open latent rows
+    pub fn free_effect_vars(&self) -> HashSet<EffectVar> {
+        let mut vars = HashSet::new();
+        self.collect_free_effect_vars(&mut vars);
+        vars
+    }
+
+    fn collect_free_effect_vars(&self, vars: &mut HashSet<EffectVar>) {
+        match self {
+            Type::App(con, args) => {
+                con.collect_free_effect_vars(vars);
+                args.iter().for_each(|arg| arg.collect_free_effect_vars(vars));
+            }
+            Type::Fun { params, return_type, effects } => {
+                params.iter().for_each(|param| param.collect_free_effect_vars(vars));
+                return_type.collect_free_effect_vars(vars);
+                vars.extend(effects.row_tail());
+            }
+            Type::Forall { effect_vars, body, .. } => {
+                let mut body_vars = HashSet::new();
+                body.collect_free_effect_vars(&mut body_vars);
+                vars.extend(body_vars.into_iter().filter(|var| !effect_vars.contains(var)));
+            }
+            Type::Record(fields) | Type::Row { fields, .. } => {
+                fields.iter().for_each(|(_, typ)| typ.collect_free_effect_vars(vars))
+            }
+            Type::Variant(variants) => variants.iter()
+                .flat_map(|(_, types)| types)
+                .for_each(|typ| typ.collect_free_effect_vars(vars)),
+            Type::Tuple(types) => types.iter().for_each(|typ| typ.collect_free_effect_vars(vars)),
+            Type::Rec { body, .. } => body.collect_free_effect_vars(vars),
+            Type::Var(_) | Type::Con(_) | Type::Hole | Type::Unknown => {}
+        }
+    }
+    
     fn collect_free_vars(&self, vars: &mut HashSet<TypeVar>) {
         match self {
             Type::Var(v) => {
@@ -388,6 +546,12 @@ impl Type {
                     typ.collect_free_vars(vars);
                 }
             }
+            Type::Row { fields, rest } => {
+                for (_, typ) in fields {
+                    typ.collect_free_vars(vars);
+                }
+                vars.insert(*rest);
+            }
             Type::Variant(variants) => {
                 for (_, types) in variants {
                     for typ in types {
@@ -454,6 +618,15 @@ impl Type {
                         .collect()
                 )
             }
+            Type::Row { fields, rest } => {
+                let fields = fields.iter()
+                    .map(|(name, typ)| (*name, typ.apply_subst(subst)))
+                    .collect();
+                match subst.lookup_type(*rest) {
+                    Some(rest) => Type::extend_record(fields, rest),
+                    None => Type::Row { fields, rest: *rest },
+                }
+            }
             Type::Variant(variants) => {
                 Type::Variant(
                     variants.iter()
@@ -479,6 +652,28 @@ impl Type {
             Type::Unknown => Type::Unknown,
         }
     }
+
+    /// Prepend `fields` to the record a row variable was bound to, keeping fields sorted by name
+    pub fn extend_record(mut fields: Vec<(Symbol, Type)>, rest: &Type) -> Type {
+        let rest = match rest {
+            Type::Record(more) => {
+                fields.extend(more.iter().cloned());
+                None
+            }
+            Type::Row { fields: more, rest } => {
+                fields.extend(more.iter().cloned());
+                Some(*rest)
+            }
+            Type::Var(var) if fields.is_empty() => return Type::Var(*var),
+            Type::Var(var) => Some(*var),
+            _ => None,
+        };
+        fields.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+        match rest {
+            Some(rest) => Type::Row { fields, rest },
+            None => Type::Record(fields),
+        }
+    }
 }
 
 impl EffectSet {
@@ -679,7 +874,8 @@ impl Type {
                 types1.len() == types2.len() &&
                 types1.iter().zip(types2.iter()).all(|(t1, t2)| Self::structurally_equal(t1, t2))
             }
-            (Type::Record(fields1), Type::Record(fields2)) => {
+            (Type::Record(fields1), Type::Record(fields2)) |
+            (Type::Row { fields: fields1, .. }, Type::Row { fields: fields2, .. }) => {
                 fields1.len() == fields2.len() &&
                 fields1.iter().zip(fields2.iter()).all(|((n1, t1), (n2, t2))| {
                     n1 == n2 && Self::structurally_equal(t1, t2)
@@ -751,6 +947,15 @@ impl fmt::Display for Type {
                 }
                 write!(f, "}}")
             }
+            Type::Row { fields, rest } => {
+                write!(f, "{{")?;
+                for (i, (name, typ)) in fields.iter().enumerate() {
+                    if i > 0 { write!(f, ", ")?; }
+                    write!(f, "{name}: {typ}")?;
+                }
+                if !fields.is_empty() { write!(f, " | ")?; }
+                write!(f, "{}}}", Type::Var(*rest))
+            }
             Type::Variant(variants) => {
                 write!(f, "|")?;
                 for (i, (name, types)) in variants.iter().enumerate() {
@@ -23,6 +23,9 @@ pub struct Unifier {
     
     /// Solved constraints
     solved: Vec<Constraint>,
+    
+    /// Supply of fresh row variables for splitting open rows
+    var_gen: VarGen,
 }
 
 /// Internal constraints for unification
@@ -48,9 +51,29 @@ impl Unifier {
             substitution: Substitution::new(),
             constraints: VecDeque::new(),
             solved: Vec::new(),
+            var_gen: VarGen::new(),
         }
     }
     
+    /// Continue from an existing substitution, drawing fresh variables from `var_gen`
+    pub fn with_state(substitution: Substitution, var_gen: VarGen) -> Self {
+        Unifier {
+            substitution,
+            var_gen,
+            ..Self::new()
+        }
+    }
+    
+    /// Give back the substitution and variable supply
+    pub fn into_state(self) -> (Substitution, VarGen) {
+        (self.substitution, self.var_gen)
+    }
+    
+    /// Apply the current substitution to an effect set, following row tails
+    pub fn resolve_effects(&self, effects: &EffectSet) -> EffectSet {
+        resolve_effects(&self.substitution, effects)
+    }
+    
     /// Add a type unification constraint
     pub fn unify_types(&mut self, t1: Type, t2: Type) -> Result<(), String> {
         self.constraints.push_back(UnificationConstraint::Unify(t1, t2));
@@ -237,9 +260,13 @@ impl Unifier {
     }
     
     /// Core effect unification implementation
+    ///
+    /// Rows are unified up to label order. Labels present on only one side
+    /// must be absorbed by the other side's tail; when both sides are open a
+    /// fresh tail is shared between them.
     fn unify_effects_impl(&mut self, e1: EffectSet, e2: EffectSet) -> Result<(), String> {
-        let e1 = e1.apply_subst(&self.substitution);
-        let e2 = e2.apply_subst(&self.substitution);
+        let e1 = self.resolve_effects(&e1);
+        let e2 = self.resolve_effects(&e2);
         
         match (e1, e2) {
             // Empty effects unify
@@ -256,12 +283,11 @@ impl Unifier {
                 self.unify_effect_rows(e1, t1, e2, t2)
             }
             
-            // Empty with non-empty
-            (EffectSet::Empty, EffectSet::Row { effects, .. }) if effects.is_empty() => Ok(()),
-            (EffectSet::Row { effects, .. }, EffectSet::Empty) if effects.is_empty() => Ok(()),
-            
-            // Mismatch
-            (e1, e2) => Err(format!("Cannot unify effect sets {e1} with {e2}")),
+            // A non-empty row against the closed empty row
+            (EffectSet::Row { effects, tail }, EffectSet::Empty)
+            | (EffectSet::Empty, EffectSet::Row { effects, tail }) => {
+                self.unify_effect_rows(effects, tail, Vec::new(), None)
+            }
         }
     }
     
@@ -278,12 +304,7 @@ impl Unifier {
     
     /// Check if an effect variable occurs in an effect set
     fn effect_var_occurs_in(&self, var: EffectVar, effects: &EffectSet) -> bool {
-        match effects {
-            EffectSet::Empty => false,
-            EffectSet::Var(v) => *v == var,
-            EffectSet::Row { tail: Some(tail), .. } => self.effect_var_occurs_in(var, tail),
-            EffectSet::Row { tail: None, .. } => false,
-        }
+        effects.row_tail() == Some(var)
     }
     
     /// Unify effect rows
@@ -294,47 +315,49 @@ impl Unifier {
         effects2: Vec<Effect>,
         tail2: Option<Box<EffectSet>>,
     ) -> Result<(), String> {
-        // Find common effects
-        let mut remaining1 = effects1;
-        let mut remaining2 = effects2;
-        
-        // Remove common effects
-        remaining1.retain(|e1| {
-            let found_index = remaining2.iter().position(|e2| e1.name == e2.name);
-            if let Some(index) = found_index {
-                remaining2.remove(index);
-                false // Remove this effect from remaining1
-            } else {
-                true // Keep this effect
-            }
-        });
+        let only1: Vec<Effect> = effects1.iter()
+            .filter(|e1| !effects2.iter().any(|e2| e1.name == e2.name))
+            .cloned()
+            .collect();
+        let only2: Vec<Effect> = effects2.into_iter()
+            .filter(|e2| !effects1.iter().any(|e1| e1.name == e2.name))
+            .collect();
+        let tail1 = tail1.map_or(EffectSet::Empty, |tail| *tail);
+        let tail2 = tail2.map_or(EffectSet::Empty, |tail| *tail);
         
-        // Handle remaining effects and tails
-        match (remaining1.is_empty(), remaining2.is_empty(), &tail1, &tail2) {
-            // Both empty, unify tails
-            (true, true, Some(t1), Some(t2)) => self.unify_effects_impl(*t1.clone(), *t2.clone()),
-            (true, true, None, None) => Ok(()),
-            
-            // One side has remaining effects, other must have compatible tail
-            (false, true, tail1_opt, Some(t2)) => {
-                let remaining_set = EffectSet::Row {
-                    effects: remaining1,
-                    tail: tail1_opt.clone(),
-                };
-                self.unify_effects_impl(remaining_set, *t2.clone())
-            }
-            (true, false, Some(t1), tail2_opt) => {
-                let remaining_set = EffectSet::Row {
-                    effects: remaining2,
-                    tail: tail2_opt.clone(),
-                };
-                self.unify_effects_impl(*t1.clone(), remaining_set)
+        match (only1.is_empty(), only2.is_empty()) {
+            (true, true) => self.unify_effects_impl(tail1, tail2),
+            (false, true) => self.absorb_labels(tail2, only1, tail1),
+            (true, false) => self.absorb_labels(tail1, only2, tail2),
+            (false, false) => {
+                // {l1 | r1} ~ {l2 | r2}  =>  r1 = {l2 | r}, r2 = {l1 | r}
+                if let (Some(v1), Some(v2)) = (tail1.row_tail(), tail2.row_tail()) {
+                    if v1 == v2 {
+                        return Err(format!(
+                            "Cannot unify effect rows sharing a tail: {} vs {}",
+                            label_names(&only1),
+                            label_names(&only2)
+                        ));
+                    }
+                }
+                let rest = EffectSet::Var(self.var_gen.fresh_effect_var());
+                self.absorb_labels(tail1, only2, rest.clone())?;
+                self.absorb_labels(tail2, only1, rest)
             }
-            
-            _ => Err("Effect row unification failed".to_string()),
         }
     }
     
+    /// Require `tail` to contain `labels` followed by `rest`
+    fn absorb_labels(&mut self, tail: EffectSet, labels: Vec<Effect>, rest: EffectSet) -> Result<(), String> {
+        if matches!(tail, EffectSet::Empty) {
+            return Err(format!(
+                "Effect row mismatch: {} not allowed by a closed effect row",
+                label_names(&labels)
+            ));
+        }
+        self.unify_effects_impl(tail, EffectSet::Row { effects: labels, tail: Some(Box::new(rest)) })
+    }
+    
     /// Solve row lacks constraint
     fn solve_row_lacks(&mut self, row: EffectSet, lacks: Symbol) -> Result<(), String> {
         let row = row.apply_subst(&self.substitution);
@@ -376,6 +399,26 @@ impl Unifier {
     }
 }
 
+/// Apply `subst` to an effect set, following bound row tails to the end
+pub fn resolve_effects(subst: &Substitution, effects: &EffectSet) -> EffectSet {
+    let resolved = match effects {
+        EffectSet::Empty => EffectSet::Empty,
+        EffectSet::Var(var) => match subst.lookup_effect(*var) {
+            Some(bound) => return resolve_effects(subst, bound),
+            None => EffectSet::Var(*var),
+        },
+        EffectSet::Row { effects, tail } => EffectSet::Row {
+            effects: effects.iter().map(|effect| effect.apply_subst(subst)).collect(),
+            tail: tail.as_ref().map(|tail| Box::new(resolve_effects(subst, tail))),
+        },
+    };
+    resolved.normalize()
+}
+
+fn label_names(effects: &[Effect]) -> String {
+    effects.iter().map(|effect| effect.name.as_str()).collect::<Vec<_>>().join(", ")
+}
+
 impl Default for Unifier {
     fn default() -> Self {
         Self::new()
@@ -607,6 +650,58 @@ mod tests {
         unifier.unify_effects(io_effect.clone(), io_effect).unwrap();
     }
     
+    fn row(labels: &[&str], tail: Option<u32>) -> EffectSet {
+        EffectSet::Row {
+            effects: labels.iter()
+                .map(|name| Effect { name: Symbol::intern(name), operations: Vec::new() })
+                .collect(),
+            tail: tail.map(|var| Box::new(EffectSet::Var(EffectVar(var)))),
+        }
+    }
+    
+    fn labels(effects: &EffectSet) -> Vec<&'static str> {
+        let mut names = match effects {
+            EffectSet::Row { effects, .. } => effects.iter().map(|e| e.name.as_str()).collect(),
+            _ => Vec::new(),
+        };
+        names.sort();
+        names
+    }
+    
+    #[test]
+    fn test_open_rows_share_a_fresh_tail() {
+        let mut unifier = Unifier::with_state(Substitution::new(), VarGen::new());
+        unifier.unify_effects(row(&["IO"], Some(100)), row(&["State"], Some(101))).unwrap();
+        
+        let left = unifier.resolve_effects(&row(&["IO"], Some(100)));
+        let right = unifier.resolve_effects(&row(&["State"], Some(101)));
+        assert_eq!(labels(&left), vec!["IO", "State"]);
+        assert_eq!(labels(&right), vec!["IO", "State"]);
+        assert_eq!(left.row_tail(), right.row_tail());
+        assert!(left.row_tail().is_some());
+    }
+    
+    #[test]
+    fn test_open_row_absorbs_missing_labels() {
+        let mut unifier = Unifier::new();
+        unifier.unify_effects(row(&["IO"], Some(100)), row(&["State", "IO"], None)).unwrap();
+        
+        let tail = unifier.resolve_effects(&EffectSet::Var(EffectVar(100)));
+        assert_eq!(labels(&tail), vec!["State"]);
+        assert_eq!(tail.row_tail(), None);
+    }
+    
+    #[test]
+    fn test_closed_rows_must_match() {
+        let mut unifier = Unifier::new();
+        let err = unifier.unify_effects(row(&["IO"], None), row(&["State"], None)).unwrap_err();
+        assert!(err.contains("closed effect row"));
+        
+        assert!(Unifier::new().unify_effects(row(&["IO"], None), EffectSet::Empty).is_err());
+        assert!(Unifier::new().unify_effects(row(&["IO"], Some(1)), row(&["State"], Some(1))).is_err());
+        assert!(Unifier::new().unify_effects(row(&[], Some(1)), EffectSet::Empty).is_ok());
+    }
+    
     #[test]
     fn test_mgu() {
         let var_a = TypeVar(0);
@@ -0,0 +1,192 @@
+//! Entry points for browser playgrounds
+//!
+//! Each function takes source text and returns a JSON string, so the
+//! bindings need nothing beyond strings at the JavaScript boundary. With
+//! the `wasm` feature they are exported through `wasm-bindgen` as `parse`,
+//! `typeCheck`, `format` and `convertSyntax`:
+//!
+//! ```sh
+//! cargo rustc -p x-checker --release --features wasm \
+//!     --target wasm32-unknown-unknown --crate-type cdylib
+//! wasm-bindgen --target web --out-dir pkg target/wasm32-unknown-unknown/release/x_checker.wasm
+//! ```
+//!
+//! Every result has an `ok` flag and a `diagnostics` array. A diagnostic
+//! carries its `severity`, `code` and `message` and, when it has a
+//! location, offsets `start` and `end` with the 1-based `line` and
+//! `column` of its start. Offsets and columns count Unicode scalar values,
+//! as `Array.from(source)` does, rather than UTF-8 bytes or UTF-16 units.
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use x_parser::span::{CharOffset, LineMap};
+use x_parser::syntax::{MultiSyntax, SyntaxConfig, SyntaxParser, SyntaxPrinter, SyntaxStyle};
+use x_parser::syntax::{ocaml::{OCamlParser, OCamlPrinter}, sexp::{SExpParser, SExpPrinter}};
+use x_parser::{parse_source_recovering, FileId, ParseError, Span};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::checker::TypeCheck;
+use crate::error_reporting::TypeError;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub severity: &'static str,
+    pub code: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<u32>,
+}
+
+impl Diagnostic {
+    fn new(severity: &'static str, code: &'static str, message: String, span: Option<Span>, lines: &LineMap) -> Self {
+        let position = span.map(|span| lines.offset_to_position(span.start));
+        Diagnostic {
+            severity,
+            code,
+            message,
+            start: span.map(|span| span.start.as_u32()),
+            end: span.map(|span| span.end.as_u32()),
+            line: position.map(|position| position.line.as_u32() + 1),
+            column: position.map(|position| position.column.as_u32() + 1),
+        }
+    }
+
+    fn parse_error(error: &ParseError, lines: &LineMap) -> Self {
+        Self::new("error", error.code(), error.to_string(), error.span(), lines)
+    }
+
+    fn type_error(severity: &'static str, error: &TypeError, lines: &LineMap) -> Self {
+        let span = error.span();
+        // Errors the checker cannot place carry an empty span at the start
+        let span = (span.end != CharOffset(0)).then_some(span);
+        Self::new(severity, error.code(), error.to_string(), span, lines)
+    }
+}
+
+/// The AST of `source`, with every syntax error the parser recovered from
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn parse(source: &str) -> String {
+    let lines = LineMap::new(source);
+    let result = match parse_source_recovering(source, FileId::new(0), Default::default()) {
+        Ok(recovered) => json!({
+            "ok": recovered.errors.is_empty(),
+            "ast": recovered.ast,
+            "diagnostics": recovered.errors.iter().map(|error| Diagnostic::parse_error(error, &lines)).collect::<Vec<_>>(),
+        }),
+        Err(error) => failure(vec![Diagnostic::parse_error(&error, &lines)]),
+    };
+    result.to_string()
+}
+
+/// Type errors and warnings of `source`, and the inferred type of each
+/// top-level definition under `types`
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = typeCheck))]
+pub fn type_check(source: &str) -> String {
+    let lines = LineMap::new(source);
+    let recovered = match parse_source_recovering(source, FileId::new(0), Default::default()) {
+        Ok(recovered) => recovered,
+        Err(error) => return failure(vec![Diagnostic::parse_error(&error, &lines)]).to_string(),
+    };
+    if recovered.has_errors() {
+        let diagnostics = recovered.errors.iter().map(|error| Diagnostic::parse_error(error, &lines)).collect();
+        return failure(diagnostics).to_string();
+    }
+
+    let result = recovered.ast.type_check();
+    let diagnostics: Vec<_> = result.errors.iter()
+        .map(|error| Diagnostic::type_error("error", error, &lines))
+        .chain(result.warnings.iter().map(|warning| Diagnostic::type_error("warning", warning, &lines)))
+        .collect();
+    let types: BTreeMap<String, String> = result.inferred_types.iter()
+        .map(|(name, scheme)| (name.to_string(), scheme.body.to_string()))
+        .collect();
+    json!({ "ok": result.errors.is_empty(), "types": types, "diagnostics": diagnostics }).to_string()
+}
+
+/// `source` printed in the canonical layout of `syntax` (`ocaml` or `sexp`),
+/// keeping its comments
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn format(source: &str, syntax: &str) -> String {
+    let lines = LineMap::new(source);
+    let printed = syntax.parse::<SyntaxStyle>().and_then(|style| {
+        let config = SyntaxConfig { style, ..SyntaxConfig::default() };
+        match style {
+            SyntaxStyle::OCaml => {
+                let (unit, trivia) = OCamlParser::new().parse_with_trivia(source, FileId::new(0))?;
+                OCamlPrinter::with_source(source).print_with_trivia(&unit, &trivia, &config)
+            }
+            SyntaxStyle::SExp => {
+                let (unit, trivia) = SExpParser::new().parse_with_trivia(source, FileId::new(0))?;
+                SExpPrinter::new().print_with_trivia(&unit, &trivia, &config).map(|printed| printed + "\n")
+            }
+        }
+    });
+    output(printed, &lines).to_string()
+}
+
+/// `source`, written in the `from` syntax, printed in the `to` syntax
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = convertSyntax))]
+pub fn convert_syntax(source: &str, from: &str, to: &str) -> String {
+    let lines = LineMap::new(source);
+    let converted = from.parse::<SyntaxStyle>()
+        .and_then(|from| Ok((from, to.parse::<SyntaxStyle>()?)))
+        .and_then(|(from, to)| MultiSyntax::default().convert(source, from, to, FileId::new(0)));
+    output(converted, &lines).to_string()
+}
+
+fn output(printed: x_parser::Result<String>, lines: &LineMap) -> Value {
+    match printed {
+        Ok(output) => json!({ "ok": true, "output": output, "diagnostics": [] }),
+        Err(error) => failure(vec![Diagnostic::parse_error(&error, lines)]),
+    }
+}
+
+fn failure(diagnostics: Vec<Diagnostic>) -> Value {
+    json!({ "ok": false, "diagnostics": diagnostics })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(result: String) -> Value {
+        serde_json::from_str(&result).unwrap()
+    }
+
+    #[test]
+    fn test_type_check_reports_types_and_located_errors() {
+        let checked = call(type_check("module Main\nlet one = 1\nlet two = one + 1"));
+        assert_eq!(checked["ok"], true);
+        assert_eq!(checked["types"]["two"], "Int");
+
+        let checked = call(type_check("module Main\nlet bad = 1 + missing"));
+        assert_eq!(checked["ok"], false);
+        let diagnostic = &checked["diagnostics"][0];
+        assert_eq!((diagnostic["severity"].as_str(), diagnostic["line"].as_u64()), (Some("error"), Some(2)));
+        assert!(diagnostic["message"].as_str().unwrap().contains("missing"), "{diagnostic}");
+
+        let parsed = call(parse("module Main\nlet = 1"));
+        assert_eq!(parsed["ok"], false);
+        assert!(parsed["diagnostics"][0]["line"].is_u64(), "{parsed}");
+    }
+
+    #[test]
+    fn test_format_and_convert() {
+        let formatted = call(format("module Main\nlet   one=1", "ocaml"));
+        assert_eq!(formatted["ok"], true);
+        assert!(formatted["output"].as_str().unwrap().contains("let one = 1"), "{formatted}");
+
+        let converted = call(convert_syntax("module Main\nlet one = 1", "ocaml", "sexp"));
+        assert!(converted["output"].as_str().unwrap().contains("(let one 1)"), "{converted}");
+
+        assert_eq!(call(format("", "cobol"))["ok"], false);
+    }
+}
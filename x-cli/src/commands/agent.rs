@@ -0,0 +1,306 @@
+//! JSON-RPC service for structural editing
+//!
+//! `x serve` exposes the editor's sessions to agents in any language. It
+//! speaks JSON-RPC 2.0 framed like the language server, with
+//! `Content-Length` headers, over stdio or TCP. Methods, with camelCase
+//! parameters:
+//!
+//! - `startSession {source}`: parse a module into a new session, returning `{sessionId}`
+//! - `closeSession {sessionId}`
+//! - `parseItems {source}`: module items as JSON, to build operations from
+//! - `applyOperation {sessionId, operation}` and `applyBatch {sessionId, operations}`
+//! - `undo {sessionId}` and `redo {sessionId}`
+//! - `query {sessionId, query}`: nodes matching a textual query
+//! - `typeCheck {sessionId}`: errors, warnings and inferred types
+//! - `render {sessionId}`: the session's module as source
+//!
+//! `shutdown` answers `null` and stops the server.
+
+use anyhow::{bail, Context, Result};
+use lsp_server::{Connection, ErrorCode, Message, Request, Response, ResponseError};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use tracing::warn;
+use x_checker::TypeError;
+use x_editor::{
+    parse_query, run_query, EditError, EditOperation, LanguageServiceConfig, QueryNode, SessionId, XLanguageEditor,
+};
+use x_parser::syntax::{ocaml::OCamlPrinter, SyntaxConfig, SyntaxPrinter};
+use x_parser::{parse_source, FileId, SyntaxStyle};
+
+pub async fn serve_command(transport: &str, port: u16) -> Result<()> {
+    let (connection, io_threads) = match transport {
+        "stdio" => Connection::stdio(),
+        "tcp" => {
+            eprintln!("Listening on port {}", port);
+            Connection::listen(("127.0.0.1", port))
+                .with_context(|| format!("Failed to listen on port {}", port))?
+        }
+        _ => bail!("Unknown transport: {} (expected stdio or tcp)", transport),
+    };
+
+    run_agent(&connection)?;
+    drop(connection);
+    io_threads.join()?;
+
+    Ok(())
+}
+
+/// Serve requests until `shutdown` or the client disconnects
+fn run_agent(connection: &Connection) -> Result<()> {
+    let mut server = AgentServer::new();
+    for msg in &connection.receiver {
+        match msg {
+            Message::Request(req) if req.method == "shutdown" => {
+                connection.sender.send(Message::Response(Response::new_ok(req.id, ())))?;
+                break;
+            }
+            Message::Request(req) => {
+                let response = server.handle_request(req);
+                connection.sender.send(Message::Response(response))?;
+            }
+            Message::Notification(not) if not.method == "exit" => break,
+            Message::Notification(not) => warn!("Ignoring notification: {}", not.method),
+            Message::Response(resp) => warn!("Unexpected response: {:?}", resp.id),
+        }
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SourceParams {
+    source: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SessionParams {
+    session_id: SessionId,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OperationParams {
+    session_id: SessionId,
+    operation: EditOperation,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BatchParams {
+    session_id: SessionId,
+    operations: Vec<EditOperation>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QueryParams {
+    session_id: SessionId,
+    query: String,
+}
+
+/// A node as reported to agents, located by character offsets into the source
+/// the session started from
+#[derive(Serialize)]
+struct NodeJson {
+    kind: &'static str,
+    name: Option<String>,
+    start: u32,
+    end: u32,
+}
+
+impl From<&QueryNode> for NodeJson {
+    fn from(node: &QueryNode) -> Self {
+        NodeJson {
+            kind: node.kind,
+            name: node.name.map(|name| name.to_string()),
+            start: node.span.start.as_u32(),
+            end: node.span.end.as_u32(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct DiagnosticJson {
+    code: &'static str,
+    message: String,
+    start: u32,
+    end: u32,
+}
+
+impl From<&TypeError> for DiagnosticJson {
+    fn from(error: &TypeError) -> Self {
+        let span = error.span();
+        DiagnosticJson {
+            code: error.code(),
+            message: error.to_string(),
+            start: span.start.as_u32(),
+            end: span.end.as_u32(),
+        }
+    }
+}
+
+/// The editor state behind one connection
+pub struct AgentServer {
+    editor: XLanguageEditor,
+}
+
+impl AgentServer {
+    pub fn new() -> Self {
+        Self { editor: XLanguageEditor::new(LanguageServiceConfig::default()) }
+    }
+
+    fn handle_request(&mut self, req: Request) -> Response {
+        let params = req.params;
+        let result = match req.method.as_str() {
+            "startSession" => call(params, |p: SourceParams| {
+                let session_id = self.editor.start_session(&p.source)?;
+                Ok(json!({ "sessionId": session_id }))
+            }),
+            "closeSession" => call(params, |p: SessionParams| {
+                self.editor.close_session(p.session_id)?;
+                Ok(Value::Null)
+            }),
+            "parseItems" => call(params, |p: SourceParams| {
+                let unit = parse_source(&p.source, FileId::new(0), SyntaxStyle::SExpression)?;
+                Ok(serde_json::to_value(&unit.module.items)?)
+            }),
+            "applyOperation" => call(params, |p: OperationParams| {
+                Ok(serde_json::to_value(self.editor.apply_operation(p.session_id, p.operation)?)?)
+            }),
+            "applyBatch" => call(params, |p: BatchParams| {
+                Ok(serde_json::to_value(self.editor.apply_batch(p.session_id, p.operations)?)?)
+            }),
+            "undo" => call(params, |p: SessionParams| Ok(serde_json::to_value(self.editor.undo(p.session_id)?)?)),
+            "redo" => call(params, |p: SessionParams| Ok(serde_json::to_value(self.editor.redo(p.session_id)?)?)),
+            "query" => call(params, |p: QueryParams| {
+                let pattern = parse_query(&p.query).map_err(EditError::from)?;
+                let session = self.session(p.session_id)?;
+                let matches: Vec<Value> = run_query(&session.ast, &pattern).iter()
+                    .map(|found| {
+                        let captures: BTreeMap<&str, NodeJson> = found.captures.iter()
+                            .map(|(name, node)| (name.as_str(), NodeJson::from(node)))
+                            .collect();
+                        json!({ "node": NodeJson::from(&found.node), "captures": captures })
+                    })
+                    .collect();
+                Ok(Value::Array(matches))
+            }),
+            "typeCheck" => call(params, |p: SessionParams| {
+                let result = self.editor.type_check_session(p.session_id)?;
+                let types: BTreeMap<String, String> = result.inferred_types.iter()
+                    .map(|(name, scheme)| (name.to_string(), scheme.body.to_string()))
+                    .collect();
+                Ok(json!({
+                    "errors": result.errors.iter().map(DiagnosticJson::from).collect::<Vec<_>>(),
+                    "warnings": result.warnings.iter().map(DiagnosticJson::from).collect::<Vec<_>>(),
+                    "types": types,
+                }))
+            }),
+            "render" => call(params, |p: SessionParams| {
+                let session = self.session(p.session_id)?;
+                let source = OCamlPrinter::new().print(&session.ast, &SyntaxConfig::default())?;
+                Ok(json!({ "source": source }))
+            }),
+            _ => Err(ResponseError {
+                code: ErrorCode::MethodNotFound as i32,
+                message: format!("Unknown method: {}", req.method),
+                data: None,
+            }),
+        };
+        match result {
+            Ok(value) => Response { id: req.id, result: Some(value), error: None },
+            Err(error) => Response { id: req.id, result: None, error: Some(error) },
+        }
+    }
+
+    fn session(&self, session_id: SessionId) -> Result<&x_editor::EditSession> {
+        Ok(self.editor.get_session(session_id).ok_or(EditError::SessionNotFound { session_id })?)
+    }
+}
+
+/// Run `handler` on the request's parameters; a rolled back batch reports
+/// the failing operation as the error's data
+fn call<P: serde::de::DeserializeOwned>(
+    params: Value,
+    handler: impl FnOnce(P) -> Result<Value>,
+) -> std::result::Result<Value, ResponseError> {
+    let params = serde_json::from_value(params).map_err(|e| ResponseError {
+        code: ErrorCode::InvalidParams as i32,
+        message: e.to_string(),
+        data: None,
+    })?;
+    handler(params).map_err(|error| {
+        let data = match error.downcast_ref::<EditError>() {
+            Some(EditError::BatchFailed(failure)) => serde_json::to_value(failure).ok(),
+            _ => None,
+        };
+        ResponseError { code: ErrorCode::RequestFailed as i32, message: format!("{error:#}"), data }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_server::RequestId;
+
+    fn request(server: &mut AgentServer, method: &str, params: Value) -> std::result::Result<Value, ResponseError> {
+        let response = server.handle_request(Request::new(RequestId::from(1), method.to_string(), params));
+        match response.error {
+            Some(error) => Err(error),
+            None => Ok(response.result.unwrap_or(Value::Null)),
+        }
+    }
+
+    #[test]
+    fn test_agent_drives_a_session() {
+        let mut server = AgentServer::new();
+        let started = request(&mut server, "startSession", json!({ "source": "module Main\nlet one = 1\nlet two = one + 1" })).unwrap();
+        let session = started["sessionId"].clone();
+
+        let items = request(&mut server, "parseItems", json!({ "source": "module M\nlet one = 10" })).unwrap();
+        let operation = json!({ "Replace": { "target": { "Path": [0, 0] }, "new_node": { "Item": items[0] } } });
+        request(&mut server, "applyOperation", json!({ "sessionId": session, "operation": operation })).unwrap();
+
+        let rendered = request(&mut server, "render", json!({ "sessionId": session })).unwrap();
+        assert!(rendered["source"].as_str().unwrap().contains("let one = 10"), "{rendered}");
+        let checked = request(&mut server, "typeCheck", json!({ "sessionId": session })).unwrap();
+        assert_eq!(checked["types"]["two"], "Int");
+        assert_eq!(checked["errors"], json!([]));
+        let found = request(&mut server, "query", json!({ "sessionId": session, "query": "var[name=\"one\"]" })).unwrap();
+        assert_eq!(found.as_array().unwrap().len(), 1);
+
+        let error = request(&mut server, "render", json!({ "sessionId": SessionId::new() })).unwrap_err();
+        assert!(error.message.starts_with("Session not found"));
+        let error = request(&mut server, "frobnicate", Value::Null).unwrap_err();
+        assert_eq!(error.code, ErrorCode::MethodNotFound as i32);
+    }
+
+    #[test]
+    fn test_rolled_back_batches_report_the_failing_operation() {
+        let mut server = AgentServer::new();
+        let started = request(&mut server, "startSession", json!({ "source": "module Main\nlet one = 1\nlet two = one + 1" })).unwrap();
+        let operations = json!([{ "Delete": { "target": { "Path": [0, 0] } } }]);
+        let error = request(&mut server, "applyBatch", json!({ "sessionId": started["sessionId"], "operations": operations })).unwrap_err();
+        assert_eq!(error.data.unwrap()["stage"], "TypeCheck");
+    }
+
+    #[test]
+    fn test_agent_protocol_over_a_connection() {
+        let (client, server) = Connection::memory();
+        let handle = std::thread::spawn(move || run_agent(&server));
+        let send = |id: i32, method: &str, params: Value| {
+            client.sender.send(Message::Request(Request::new(RequestId::from(id), method.to_string(), params))).unwrap();
+            match client.receiver.recv().unwrap() {
+                Message::Response(response) => response,
+                other => panic!("expected a response, got {other:?}"),
+            }
+        };
+        let started = send(1, "startSession", json!({ "source": "module Main\nlet one = 1" }));
+        assert!(started.result.unwrap()["sessionId"].is_string());
+        assert!(send(2, "shutdown", Value::Null).error.is_none());
+        handle.join().unwrap().unwrap();
+    }
+}
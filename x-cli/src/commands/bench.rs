@@ -0,0 +1,160 @@
+//! Bench command - run `bench` definitions and compare with the baseline
+//!
+//! Benchmarks are measured on the interpreter. Their stats are stored in
+//! `.x-bench/baseline.json` next to the sources, keyed by content hash, so a
+//! benchmark only runs again once it or something it uses has changed.
+
+use anyhow::{Result, Context, bail};
+use clap::Args;
+use colored::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use x_parser::{parse_source, FileId, SyntaxStyle};
+use x_testing::bench::BASELINE_FILE;
+use x_testing::{BenchBaseline, BenchConfig, BenchOutcome, BenchResult, BenchRunner, BenchStats};
+use crate::commands::test::source_files;
+use crate::utils::{format_duration, TableBuilder};
+
+/// Run the benchmarks of a file or project
+#[derive(Debug, Args)]
+pub struct BenchArgs {
+    /// File or directory to benchmark
+    #[arg(default_value = ".")]
+    path: PathBuf,
+
+    /// Only run benchmarks whose name contains this
+    #[arg(long)]
+    filter: Option<String>,
+
+    /// Measure every benchmark, even unchanged ones
+    #[arg(long)]
+    force: bool,
+
+    /// Measured runs of benchmarks that do not set their own
+    #[arg(long, default_value_t = BenchConfig::default().iterations)]
+    iterations: u64,
+
+    /// Unmeasured runs of benchmarks that do not set their own
+    #[arg(long, default_value_t = BenchConfig::default().warmup)]
+    warmup: u64,
+}
+
+pub async fn run(args: BenchArgs) -> Result<()> {
+    let root = if args.path.is_file() {
+        args.path.parent().unwrap_or(Path::new(".")).to_path_buf()
+    } else {
+        args.path.clone()
+    };
+    let baseline_path = root.join(BASELINE_FILE);
+    let config = BenchConfig { iterations: args.iterations, warmup: args.warmup, force: args.force, ..BenchConfig::default() };
+    let mut runner = BenchRunner::new(config)
+        .with_baseline(BenchBaseline::load(&baseline_path)?)
+        .with_filter(args.filter.clone());
+
+    let mut table = TableBuilder::new().headers(vec!["benchmark", "mean", "median", "min", "max", "std dev", "change"]);
+    let (mut measured, mut unchanged, mut failed) = (0, 0, 0);
+    for file in source_files(&args.path) {
+        let content = fs::read_to_string(&file)
+            .with_context(|| format!("Failed to read file: {}", file.display()))?;
+        let unit = match parse_source(&content, FileId::new(0), SyntaxStyle::SExpression) {
+            Ok(unit) => unit,
+            Err(error) => {
+                println!("{} skipping {}: {error}", "warning:".yellow(), file.display());
+                continue;
+            }
+        };
+        let module = unit.module.name.to_string();
+        for result in runner.run(&unit) {
+            let name = format!("{module}.{}", label(&result));
+            match &result.outcome {
+                BenchOutcome::Measured { stats, previous } => {
+                    measured += 1;
+                    let change = previous.as_ref().and_then(|previous| stats.change_from(previous));
+                    let cells = row(&name, stats, &change.map_or_else(|| "new".to_string(), percent));
+                    table = table.row(cells.iter().map(String::as_str).collect());
+                }
+                BenchOutcome::Unchanged { stats } => {
+                    unchanged += 1;
+                    let cells = row(&name, stats, "unchanged");
+                    table = table.row(cells.iter().map(String::as_str).collect());
+                }
+                BenchOutcome::Failed { error } => {
+                    failed += 1;
+                    println!("  {} {name}: {error}", "failed".red());
+                }
+            }
+        }
+    }
+
+    if measured + unchanged > 0 {
+        table.print();
+    }
+    if measured > 0 {
+        runner.baseline().save(&baseline_path)?;
+    }
+    println!(
+        "\n{} measured, {} unchanged, {} failed",
+        measured.to_string().green(),
+        unchanged.to_string().dimmed(),
+        failed.to_string().red(),
+    );
+    if failed > 0 {
+        bail!("{failed} benchmarks failed");
+    }
+    Ok(())
+}
+
+fn label(result: &BenchResult) -> String {
+    match &result.description {
+        Some(description) => format!("{description:?}"),
+        None => result.name.to_string(),
+    }
+}
+
+fn row(name: &str, stats: &BenchStats, change: &str) -> Vec<String> {
+    let time = |ns: f64| format_duration(Duration::from_nanos(ns as u64));
+    vec![
+        name.to_string(),
+        time(stats.mean_ns),
+        time(stats.median_ns),
+        time(stats.min_ns as f64),
+        time(stats.max_ns as f64),
+        time(stats.std_dev_ns),
+        change.to_string(),
+    ]
+}
+
+/// A relative change as a signed percentage
+fn percent(change: f64) -> String {
+    format!("{:+.1}%", change * 100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn args(path: &Path) -> BenchArgs {
+        BenchArgs { path: path.to_path_buf(), filter: None, force: false, iterations: 3, warmup: 0 }
+    }
+
+    #[tokio::test]
+    async fn test_bench_writes_baseline() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("main.x"), "module Demo\nlet double = fun x -> x + x\nbench doubling { double 2 }\n").unwrap();
+
+        run(args(temp_dir.path())).await.unwrap();
+        let baseline = BenchBaseline::load(&temp_dir.path().join(BASELINE_FILE)).unwrap();
+        assert_eq!(baseline.benchmarks["Demo.doubling"].stats.iterations, 3);
+
+        fs::write(temp_dir.path().join("main.x"), "module Demo\nbench broken { missing 1 }\n").unwrap();
+        assert!(run(args(temp_dir.path())).await.is_err());
+    }
+
+    #[test]
+    fn test_percent() {
+        assert_eq!(percent(0.125), "+12.5%");
+        assert_eq!(percent(-0.5), "-50.0%");
+    }
+}
@@ -1,24 +1,101 @@
 //! Type checking commands
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, Context, Result};
 use std::path::Path;
 use colored::*;
-use crate::utils::{ProgressIndicator, print_success};
+use x_checker::{BinaryTypeChecker, CheckResult, TypeCheckDatabaseImpl};
+use x_parser::binary::verify_checksums;
+use x_compiler::{CompilerDiagnostic, DiagnosticRenderer, DiagnosticSeverity};
+use x_editor::{apply_text_edits, suggest_fix};
+use x_parser::{parse_source_recovering, CompilationUnit, FileId, SourceMap, Symbol, SyntaxStyle};
+use crate::manifest::{project_checker, project_modules, with_imported_fixities};
+use crate::utils::{ProgressIndicator, print_error, print_success};
+
+pub async fn check_command(
+    input: &Path,
+    detailed: bool,
+    quiet: bool,
+    binary: bool,
+    format: &str,
+    fix: bool,
+    only: Option<&str>,
+) -> Result<()> {
+    if binary {
+        return check_binary(input, quiet);
+    }
+    let json = match format {
+        "text" => false,
+        "json" => true,
+        other => bail!("Unknown output format: {other} (expected text or json)"),
+    };
 
-pub async fn check_command(_input: &Path, detailed: bool, quiet: bool) -> Result<()> {
     let progress = ProgressIndicator::new("Type checking");
-    
+
     progress.set_message("Loading AST");
-    // TODO: Load AST and initialize type checker
-    
-    progress.set_message("Running type checker");
-    // TODO: Perform type checking
-    
-    progress.finish("Type checking completed");
-    
-    if !quiet {
+    let mut source = std::fs::read_to_string(input)
+        .with_context(|| format!("Failed to read {}", input.display()))?;
+    if fix {
+        progress.set_message("Applying suggested fixes");
+        let applied;
+        (source, applied) = apply_fixes(input, source)?;
+        if applied > 0 && !json {
+            print_success(&format!("Applied {applied} fix(es) to {}", input.display()));
+        }
+    }
+    let mut sources = SourceMap::new();
+    let file = sources.add_file(input, source);
+    let source = sources.text(file).unwrap_or_default();
+    let parsed = parse_source_recovering(source, file, SyntaxStyle::SExpression)
+        .with_context(|| format!("Failed to parse {}", input.display()))?;
+
+    // Type checking a partial AST would only add noise to the syntax errors
+    let (diagnostics, failure) = if parsed.has_errors() {
+        let diagnostics: Vec<_> = parsed.errors.iter().map(CompilerDiagnostic::from_parse_error).collect();
+        let failure = format!("{} syntax error(s) found", diagnostics.len());
+        (diagnostics, Some(failure))
+    } else {
+        progress.set_message("Running type checker");
+        let result = match only {
+            Some(name) => check_definition(input, source, &parsed.ast, name)?,
+            None => {
+                let mut checker = project_checker(input, &parsed.ast)?;
+                let unit = with_imported_fixities(&checker, source, parsed.ast.clone())
+                    .with_context(|| format!("Failed to parse {}", input.display()))?;
+                checker.check_compilation_unit(&unit)
+            }
+        };
+        let failure = (!result.errors.is_empty())
+            .then(|| format!("{} type error(s) found", result.errors.len()));
+        let diagnostics = result.errors.iter()
+            .map(|error| {
+                CompilerDiagnostic::from_type_error(error, DiagnosticSeverity::Error)
+                    .with_fix(suggest_fix(&parsed.ast, source, error))
+            })
+            .chain(result.warnings.iter().map(|warning| {
+                CompilerDiagnostic::from_type_error(warning, DiagnosticSeverity::Warning)
+            }))
+            .collect();
+        (diagnostics, failure)
+    };
+
+    match &failure {
+        Some(message) => progress.finish_error(message),
+        None => progress.finish("Type checking completed"),
+    }
+
+    let renderer = DiagnosticRenderer::for_file(&sources, file).context("checked file is in the source map")?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&renderer.to_json(&diagnostics))?);
+    } else if !diagnostics.is_empty() {
+        eprintln!("{}", renderer.render_all(&diagnostics));
+    }
+    if let Some(message) = failure {
+        bail!(message);
+    }
+
+    if !quiet && !json {
         print_success("No type errors found");
-        
+
         if detailed {
             println!("\n{}", "Type Information:".bold().underline());
             println!("  {} types inferred", "42".cyan());
@@ -26,6 +103,145 @@ pub async fn check_command(_input: &Path, detailed: bool, quiet: bool) -> Result
             println!("  {} constraints solved", "156".cyan());
         }
     }
-    
+
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Check the definition `name` and what it depends on, without checking
+/// the rest of the file
+fn check_definition(input: &Path, source: &str, unit: &CompilationUnit, name: &str) -> Result<CheckResult> {
+    let mut db = TypeCheckDatabaseImpl::new();
+    db.set_modules(project_modules(input, unit)?);
+    db.set_source(unit.span.file_id, source);
+    db.definition_result(unit.span.file_id, Symbol::intern(name))
+        .map_err(|cancelled| anyhow!(cancelled))?
+        .with_context(|| format!("{} does not define {name}", input.display()))
+}
+
+/// Apply every suggested fix that can be written as a text edit, saving the
+/// file if anything changed, and return the new source with the number of
+/// edits made. Fixes that only exist as AST edits are left to be reported
+/// with the remaining diagnostics.
+fn apply_fixes(input: &Path, source: String) -> Result<(String, usize)> {
+    let parsed = parse_source_recovering(&source, FileId::new(0), SyntaxStyle::SExpression)
+        .with_context(|| format!("Failed to parse {}", input.display()))?;
+    if parsed.has_errors() {
+        return Ok((source, 0));
+    }
+
+    let fixes: Vec<_> = project_checker(input, &parsed.ast)?.check_compilation_unit(&parsed.ast).errors.iter()
+        .filter_map(|error| suggest_fix(&parsed.ast, &source, error))
+        .filter(|fix| fix.is_textual())
+        .collect();
+    let (fixed, applied) = apply_text_edits(&source, fixes.iter().flat_map(|fix| &fix.text_edits));
+    if applied == 0 {
+        return Ok((source, 0));
+    }
+
+    std::fs::write(input, &fixed)
+        .with_context(|| format!("Failed to write {}", input.display()))?;
+    Ok((fixed, applied))
+}
+
+/// Verify a binary AST's checksums, then decode and type check it, using
+/// the stored types when the file has them
+fn check_binary(input: &Path, quiet: bool) -> Result<()> {
+    let data = std::fs::read(input)
+        .with_context(|| format!("Failed to read {}", input.display()))?;
+
+    let corrupt = verify_checksums(&data)
+        .with_context(|| format!("{} is not a readable binary AST", input.display()))?;
+    if !corrupt.is_empty() {
+        for region in &corrupt {
+            print_error(&format!("corrupted {region}"));
+        }
+        bail!("{} has {} corrupted region(s)", input.display(), corrupt.len());
+    }
+
+    let result = BinaryTypeChecker::new()
+        .check_binary_compilation_unit(&data)
+        .map_err(|message| anyhow!("Failed to decode binary AST: {message}"))?;
+    if !result.is_success() {
+        for error in &result.errors {
+            print_error(&error.to_string());
+        }
+        bail!("{} type error(s) found", result.error_count());
+    }
+
+    if !quiet {
+        let source = if result.used_cache() { "stored types" } else { "inferred types" };
+        print_success(&format!("{} verified: checksums match, no type errors ({source})", input.display()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x_parser::binary::BinarySerializer;
+    use x_parser::{parse_source, FileId, SyntaxStyle};
+
+    #[test]
+    fn test_check_binary_reports_corruption() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.xbin");
+        let unit = parse_source("module Test\nlet x = 42", FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let mut data = BinarySerializer::new().serialize_compilation_unit(&unit).unwrap();
+
+        std::fs::write(&path, &data).unwrap();
+        assert!(check_binary(&path, true).is_ok());
+
+        let last = data.len() - 1;
+        data[last] ^= 0xFF;
+        std::fs::write(&path, &data).unwrap();
+        let err = check_binary(&path, true).unwrap_err();
+        assert!(err.to_string().contains("1 corrupted region"));
+    }
+
+    #[tokio::test]
+    async fn test_check_reports_all_syntax_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.x");
+        std::fs::write(&path, "module Test\nlet a = (1, )\nlet b = 2\nlet c = 0b12").unwrap();
+
+        let err = check_command(&path, false, true, false, "text", false, None).await.unwrap_err();
+        assert_eq!(err.to_string(), "2 syntax error(s) found");
+    }
+
+    #[tokio::test]
+    async fn test_check_reports_type_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.x");
+        std::fs::write(&path, "module Test\nlet x : Int = true").unwrap();
+
+        let err = check_command(&path, false, true, false, "json", false, None).await.unwrap_err();
+        assert_eq!(err.to_string(), "1 type error(s) found");
+        assert!(check_command(&path, false, true, false, "xml", false, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_check_only_one_definition() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.x");
+        std::fs::write(&path, "module Test\nlet one = 1\nlet two = one + one\nlet bad : Int = true").unwrap();
+
+        check_command(&path, false, true, false, "text", false, Some("two")).await.unwrap();
+        let err = check_command(&path, false, true, false, "text", false, Some("bad")).await.unwrap_err();
+        assert_eq!(err.to_string(), "1 type error(s) found");
+        let err = check_command(&path, false, true, false, "text", false, Some("three")).await.unwrap_err();
+        assert!(err.to_string().ends_with("does not define three"));
+    }
+
+    #[tokio::test]
+    async fn test_check_fix_applies_suggestions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.x");
+        std::fs::write(&path, "module Test\nlet count = 1\nlet next = cuont\nlet y : Int = true").unwrap();
+
+        check_command(&path, false, true, false, "text", true, None).await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "module Test\nlet count = 1\nlet next = count\nlet y : Bool = true",
+        );
+    }
+}
@@ -1,12 +1,45 @@
 //! Compilation commands
 
-use anyhow::{Result, Context};
-use std::path::Path;
+use anyhow::{bail, Result, Context};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use colored::*;
 use crate::utils::{ProgressIndicator, print_success};
-use x_compiler::compile;
+use x_compiler::{artifacts::Artifact, passes::PassManager, profiling, CompilationPipeline, CompilerConfig, PartialConfig};
+use x_parser::{parse_source, FileId, SyntaxStyle};
+use crate::manifest::{Project, MANIFEST_FILE};
 
-pub async fn compile_command(input: &Path, target: &str, output: &Path) -> Result<()> {
+/// What to report about the passes, time and memory a compilation took
+#[derive(Debug, Default)]
+pub struct ProfileOptions {
+    /// Print a flame-style breakdown
+    pub print: bool,
+    /// Write Chrome tracing JSON to this file
+    pub trace: Option<PathBuf>,
+    /// List the passes run over the IR, in order
+    pub print_passes: bool,
+    /// Print how long each pass took on each module
+    pub time_passes: bool,
+}
+
+/// Compile `input` for `target`, configured by the project's `x.toml`, the
+/// environment and `flags`, in increasing order of precedence, giving up
+/// after `timeout`; the artifacts named in `emit` are written beside the code
+pub async fn compile_command(
+    input: &Path,
+    target: &str,
+    output: &Path,
+    flags: PartialConfig,
+    profile: ProfileOptions,
+    timeout: Option<Duration>,
+    emit: &[String],
+) -> Result<()> {
+    let emit = emit.iter()
+        .map(|name| Artifact::from_name(name).with_context(|| {
+            let known: Vec<&str> = Artifact::ALL.iter().map(|artifact| artifact.name()).collect();
+            format!("Unknown artifact `{name}`, expected one of {}", known.join(", "))
+        }))
+        .collect::<Result<Vec<_>>>()?;
     let progress = ProgressIndicator::new("Compiling");
     
     println!("Compiling {} to {}", input.display(), target.cyan());
@@ -17,12 +50,37 @@ pub async fn compile_command(input: &Path, target: &str, output: &Path) -> Resul
         .await
         .with_context(|| format!("Failed to read source file: {}", input.display()))?;
     
+    let project = Project::discover(input)?;
+    let manifest = project.as_ref().map(|project| project.packages[0].root.join(MANIFEST_FILE));
+    let config = CompilerConfig::load(manifest.as_deref(), flags)?;
+
+    // Inside a project, the modules the input imports are compiled with it
+    let mut sources = Vec::new();
+    if let Some(project) = project {
+        progress.set_message("Resolving dependencies");
+        let mut unit = parse_source(&source, FileId::new(0), SyntaxStyle::default())
+            .with_context(|| format!("Failed to parse {}", input.display()))?;
+        x_compiler::conditions::resolve_imports(&mut unit, target, &config.features)?;
+        sources = project.imported_modules(input, &unit)?
+            .into_iter()
+            .map(|module| module.source)
+            .collect();
+    }
+    sources.push(source);
+    
     progress.set_message(&format!("Compiling to {}", target));
     
-    // Use default compiler configuration
-    let config = x_compiler::config::CompilerConfig::default();
-    let result = compile(&source, target, output.to_path_buf(), config)
-        .with_context(|| format!("Failed to compile to {}", target))?;
+    let sources: Vec<&str> = sources.iter().map(String::as_str).collect();
+    let mut pipeline = CompilationPipeline::new(config).with_emit(emit);
+    if let Some(timeout) = timeout {
+        pipeline = pipeline.with_timeout(timeout);
+    }
+    let result = match pipeline.compile_project(&sources, target, output.to_path_buf()) {
+        Err(error) if error.is_cancelled() => {
+            bail!("Compilation to {} timed out after {}s", target, timeout.unwrap_or_default().as_secs_f64())
+        }
+        result => result.with_context(|| format!("Failed to compile to {}", target))?,
+    };
     
     progress.finish("Compilation completed");
     
@@ -46,10 +104,48 @@ pub async fn compile_command(input: &Path, target: &str, output: &Path) -> Resul
     }
     
     // Display generated files
-    println!("Generated {} files:", result.files.len());
-    for file_path in result.files.keys() {
+    println!("Generated {} files:", result.files.len() + result.binary_files.len());
+    for file_path in result.files.keys().chain(result.binary_files.keys()) {
         println!("  {}", file_path.display().to_string().green());
     }
+    println!("Manifest: {}", output.join(x_compiler::manifest::MANIFEST_FILE).display());
+    
+    if profile.print_passes {
+        println!("\nPasses:");
+        // Every module goes through the same passes
+        let passes = result.metadata.module_timings.first().map(|module| module.passes.as_slice()).unwrap_or_default();
+        let width = passes.iter().map(|pass| pass.name.len()).max().unwrap_or(0);
+        for pass in passes {
+            match PassManager::lookup(pass.name) {
+                Some(registered) => println!("  {:width$}  {}", pass.name, registered.description.dimmed()),
+                None => println!("  {}", pass.name),
+            }
+        }
+    }
+    if profile.time_passes {
+        println!("\nPass timings:");
+        for module in &result.metadata.module_timings {
+            println!("  {}", module.module.bold());
+            let width = module.passes.iter().map(|pass| pass.name.len()).max().unwrap_or(0).max("emit".len());
+            for pass in &module.passes {
+                println!("    {:width$}  {:>10.3}ms", pass.name, pass.duration.as_secs_f64() * 1000.0);
+            }
+            println!("    {:width$}  {:>10.3}ms", "emit", module.emit_time.as_secs_f64() * 1000.0);
+        }
+    }
+
+    let spans = profiling::spans(&result.metadata);
+    if profile.print {
+        println!("\nProfile:");
+        print!("{}", profiling::render_flame(&spans, 30));
+    }
+    if let Some(trace) = &profile.trace {
+        let json = serde_json::to_string_pretty(&profiling::chrome_trace(&spans))?;
+        tokio::fs::write(trace, json)
+            .await
+            .with_context(|| format!("Failed to write profile trace: {}", trace.display()))?;
+        println!("Profile trace: {}", trace.display());
+    }
     
     print_success(&format!("Successfully compiled to {}", target));
     
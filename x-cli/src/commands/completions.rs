@@ -0,0 +1,190 @@
+//! Completions command - shell completion scripts
+//!
+//! The scripts are clap's completions for the command line. For bash, zsh
+//! and fish they also complete values only known at run time: compilation
+//! targets for `compile --target`, syntax styles for `repl --syntax`, file
+//! formats for `convert --from` and `--to`, and the names of the tests
+//! under the path given to `x test` for `--filter`. Those values come from
+//! the hidden `x __complete <kind>` command, which the scripts call back.
+
+use anyhow::{bail, Result};
+use clap::{Args, CommandFactory};
+use clap_complete::Shell;
+use std::io::Write;
+use std::path::PathBuf;
+use x_compiler::BackendFactory;
+use x_parser::syntax::SyntaxStyle;
+use crate::commands::test::test_names;
+use crate::format::Format;
+use crate::Cli;
+
+/// Print a completion script for a shell
+#[derive(Debug, Args)]
+pub struct CompletionsArgs {
+    /// Shell to complete for
+    shell: Shell,
+}
+
+/// Print the values to complete, one per line
+#[derive(Debug, Args)]
+pub struct CompleteArgs {
+    /// What to complete: targets, syntaxes, formats or tests
+    kind: String,
+
+    /// The command line being completed
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    words: Vec<String>,
+}
+
+/// Options whose values the scripts complete dynamically: the subcommand,
+/// its spellings of the option, and the kind of value
+const DYNAMIC_OPTIONS: &[(&str, &[&str], &str)] = &[
+    ("compile", &["--target", "-t"], "targets"),
+    ("repl", &["--syntax"], "syntaxes"),
+    ("convert", &["--from", "--to"], "formats"),
+    ("test", &["--filter"], "tests"),
+];
+
+/// Options of `x test` that take a value, skipped when looking for its path
+const TEST_VALUE_OPTIONS: &[&str] = &["--filter", "-j", "--threads", "--reporter", "--timeout", "--lcov", "-c", "--config"];
+
+pub async fn run(args: CompletionsArgs) -> Result<()> {
+    let mut script = Vec::new();
+    clap_complete::generate(args.shell, &mut Cli::command(), "x", &mut script);
+    script.extend(dynamic_script(args.shell).into_bytes());
+    std::io::stdout().write_all(&script)?;
+    Ok(())
+}
+
+pub async fn complete(args: CompleteArgs) -> Result<()> {
+    for value in values(&args.kind, &args.words).await? {
+        println!("{}", value);
+    }
+    Ok(())
+}
+
+async fn values(kind: &str, words: &[String]) -> Result<Vec<String>> {
+    Ok(match kind {
+        "targets" => BackendFactory::available_backends().into_iter().map(String::from).collect(),
+        "syntaxes" => [SyntaxStyle::OCaml, SyntaxStyle::SExp].iter().map(|style| style.to_string()).collect(),
+        "formats" => Format::NAMES.iter().map(|name| name.to_string()).collect(),
+        // A directory that does not parse simply has nothing to offer
+        "tests" => test_names(&test_path(words)).await.unwrap_or_default(),
+        _ => bail!("Unknown completion kind: {} (expected targets, syntaxes, formats or tests)", kind),
+    })
+}
+
+/// The path argument of the `x test` command line in `words`
+fn test_path(words: &[String]) -> PathBuf {
+    let mut rest = words.iter().skip_while(|word| *word != "test").skip(1);
+    while let Some(word) = rest.next() {
+        if TEST_VALUE_OPTIONS.contains(&word.as_str()) {
+            rest.next();
+        } else if !word.starts_with('-') && !word.is_empty() {
+            return PathBuf::from(word);
+        }
+    }
+    PathBuf::from(".")
+}
+
+/// Shell functions completing `DYNAMIC_OPTIONS`, run before clap's
+fn dynamic_script(shell: Shell) -> String {
+    // `case` arms over "<subcommand> <previous word>", the same in bash and zsh
+    let cases: String = DYNAMIC_OPTIONS.iter()
+        .map(|(command, options, kind)| {
+            let patterns: Vec<String> = options.iter().map(|option| format!("\"{} {}\"", command, option)).collect();
+            format!("        {}) kind={} ;;\n", patterns.join("|"), kind)
+        })
+        .collect();
+    match shell {
+        Shell::Bash => format!(
+            r#"
+_x_dynamic() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}" prev="${{COMP_WORDS[COMP_CWORD-1]}}" command="" kind="" word
+    for word in "${{COMP_WORDS[@]:1:COMP_CWORD-1}}"; do
+        if [[ "$word" != -* ]]; then command="$word"; break; fi
+    done
+    case "$command $prev" in
+{cases}    esac
+    if [[ -n "$kind" ]]; then
+        local IFS=$'\n'
+        COMPREPLY=($(compgen -W "$(x __complete "$kind" "${{COMP_WORDS[@]}}" 2>/dev/null)" -- "$cur"))
+        return 0
+    fi
+    _x "$@"
+}}
+complete -F _x_dynamic -o nosort -o bashdefault -o default x
+"#,
+        ),
+        Shell::Zsh => format!(
+            r#"
+_x_dynamic() {{
+    local command=${{${{words[2,CURRENT-1]:#-*}}[1]}} kind=""
+    case "$command ${{words[CURRENT-1]}}" in
+{cases}    esac
+    if [[ -n $kind ]]; then
+        local -a values
+        values=(${{(f)"$(x __complete $kind ${{words[@]}} 2>/dev/null)"}})
+        compadd -a values
+        return
+    fi
+    _x "$@"
+}}
+compdef _x_dynamic x
+"#,
+        ),
+        Shell::Fish => DYNAMIC_OPTIONS.iter()
+            .flat_map(|(command, options, kind)| options.iter().map(move |option| {
+                let flag = match option.strip_prefix("--") {
+                    Some(long) => format!("-l {}", long),
+                    None => format!("-s {}", &option[1..]),
+                };
+                format!(
+                    "complete -c x -n \"__fish_seen_subcommand_from {}\" {} -f -a \"(x __complete {} (commandline -opc))\"\n",
+                    command, flag, kind,
+                )
+            }))
+            .collect(),
+        // Elvish and PowerShell get clap's static completions only
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn words(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[tokio::test]
+    async fn test_dynamic_values() {
+        assert!(values("targets", &[]).await.unwrap().contains(&"wasm-gc".to_string()));
+        assert_eq!(values("syntaxes", &[]).await.unwrap(), ["ocaml", "sexp"]);
+        assert!(values("colours", &[]).await.is_err());
+
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(
+            temp_dir.path().join("math.x"),
+            "module Math\nlet double = fun x -> x * 2\nlet test_double = double 2 == 4\nlet test_zero = double 0 == 0",
+        ).unwrap();
+        let line = format!("x test -j 2 {} --filter", temp_dir.path().display());
+        assert_eq!(values("tests", &words(&line)).await.unwrap(), ["test_double", "test_zero"]);
+        assert_eq!(test_path(&words("x -v test --reporter json")), PathBuf::from("."));
+    }
+
+    #[test]
+    fn test_scripts_call_back_for_dynamic_values() {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+            let mut script = Vec::new();
+            clap_complete::generate(shell, &mut Cli::command(), "x", &mut script);
+            let script = String::from_utf8(script).unwrap() + &dynamic_script(shell);
+            assert!(script.contains("x __complete"), "{shell}");
+        }
+        assert!(dynamic_script(Shell::Bash).contains(r#""compile --target"|"compile -t") kind=targets ;;"#));
+        assert!(dynamic_script(Shell::Fish).contains("__fish_seen_subcommand_from compile\" -s t -f"));
+    }
+}
@@ -5,6 +5,8 @@ use std::path::Path;
 use std::fs;
 use colored::*;
 use x_parser::persistent_ast::PersistentAstNode;
+use x_parser::syntax::MultiSyntax;
+use x_parser::FileId;
 // use x_editor::ast_engine::AstEngine;
 use crate::format::{Format, detect_format, load_ast, save_ast};
 use crate::utils::ProgressIndicator;
@@ -15,6 +17,7 @@ pub async fn convert_command(
     output: Option<&Path>,
     from_format: Option<&str>,
     to_format: Option<&str>,
+    compress: bool,
 ) -> Result<()> {
     let progress = ProgressIndicator::new("Converting format");
     
@@ -63,6 +66,19 @@ pub async fn convert_command(
     println!("Input:  {}", input.display());
     println!("Output: {}", output_path.display());
     
+    // Source text converts to source text directly, keeping its comments
+    if let (Some(from), Some(to)) = (input_format.text_syntax(), output_format.text_syntax()) {
+        progress.set_message("Converting source text");
+        let source = fs::read_to_string(input)
+            .with_context(|| format!("Failed to read input file: {}", input.display()))?;
+        let converted = MultiSyntax::default().convert(&source, from, to, FileId::new(0))
+            .with_context(|| format!("Failed to convert input file: {}", input.display()))?;
+        fs::write(&output_path, converted)
+            .with_context(|| format!("Failed to write output file: {}", output_path.display()))?;
+        progress.finish("Conversion completed successfully");
+        return print_conversion_stats(input, &output_path).await;
+    }
+    
     progress.set_message("Loading input file");
     
     // Load AST from input
@@ -77,7 +93,7 @@ pub async fn convert_command(
     progress.set_message("Saving output file");
     
     // Save AST to output
-    save_ast(&output_path, &converted_ast, output_format).await
+    save_ast(&output_path, &converted_ast, output_format, compress).await
         .with_context(|| format!("Failed to save output file: {}", output_path.display()))?;
     
     progress.finish("Conversion completed successfully");
@@ -160,7 +176,8 @@ mod tests {
             &input_path,
             Some(&output_path),
             Some("rustic"),
-            Some("binary")
+            Some("binary"),
+            false,
         ).await;
         
         // Should succeed (though actual conversion depends on parser implementation)
@@ -175,4 +192,19 @@ mod tests {
             }
         }
     }
-}
\ No newline at end of file
+    
+    #[tokio::test]
+    async fn test_convert_text_keeps_comments() {
+        let temp_dir = TempDir::new().unwrap();
+        let input_path = temp_dir.path().join("demo.x");
+        let output_path = temp_dir.path().join("demo.lisp.x");
+        fs::write(&input_path, "-- Demo module\nmodule Demo\n-- doubles\nlet double = fun x -> x + x -- twice\n").unwrap();
+        
+        convert_command(&input_path, Some(&output_path), None, None, false).await.unwrap();
+        
+        let converted = fs::read_to_string(&output_path).unwrap();
+        assert!(converted.starts_with("; Demo module\n(compilation-unit"), "{converted}");
+        assert!(converted.contains("; doubles\n"), "{converted}");
+        assert!(converted.contains("; twice\n"), "{converted}");
+    }
+}
@@ -0,0 +1,158 @@
+//! Diff command - compare two sources line by line or item by item
+//!
+//! Without flags the files are compared as text. `--structural` parses both
+//! and prints the edit script that turns the old module into the new one, so
+//! layout and comments never show up as changes and a moved definition is
+//! one move rather than a deletion and an insertion.
+
+use anyhow::{Result, Context};
+use clap::Args;
+use colored::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+use x_editor::{diff_compilation_units, AstEditor, EditOperation, EditResult};
+use x_editor::operations::EditableNode;
+use x_parser::CompilationUnit;
+use x_parser::syntax::SyntaxConfig;
+use x_parser::syntax::ocaml::OCamlPrinter;
+use crate::commands::migrate::edit_script;
+use crate::format::load_compilation_unit;
+
+/// Compare two source files
+#[derive(Debug, Args)]
+pub struct DiffArgs {
+    /// Original file
+    old: PathBuf,
+
+    /// Changed file
+    new: PathBuf,
+
+    /// Compare the parsed modules item by item instead of line by line
+    #[arg(long)]
+    structural: bool,
+}
+
+pub async fn run(args: DiffArgs) -> Result<()> {
+    if !args.structural {
+        let read = |path: &Path| fs::read_to_string(path)
+            .with_context(|| format!("Failed to read file: {}", path.display()));
+        let (old, new) = (read(&args.old)?, read(&args.new)?);
+        if old == new {
+            println!("No differences");
+        } else {
+            print!("{}", colorize(&edit_script(&args.new.display().to_string(), &old, &new)));
+        }
+        return Ok(());
+    }
+
+    let old = load_compilation_unit(&args.old)?;
+    let new = load_compilation_unit(&args.new)?;
+    let script = diff_compilation_units(&old, &new);
+    if script.is_empty() {
+        println!("No structural differences");
+        return Ok(());
+    }
+    let count = script.len();
+    print!("{}", render_script(&old, script)?);
+    println!("{} {}", count.to_string().cyan(), if count == 1 { "operation" } else { "operations" });
+    Ok(())
+}
+
+/// The edit script applied step by step to `old`, each operation shown with
+/// the items it removes (`-`), adds (`+`) or moves (`~`)
+pub fn render_script(old: &CompilationUnit, script: Vec<EditOperation>) -> Result<String> {
+    let mut unit = old.clone();
+    let mut editor = AstEditor::new();
+    let mut output = String::new();
+    for operation in script {
+        match editor.apply_operation(&mut unit, operation)? {
+            EditResult::Inserted { path, .. } => {
+                let item = EditableNode::Item(unit.module.items[path[1]].clone());
+                output.push_str(&format!("{}\n", format!("@{} insert", format_path(&path)).cyan()));
+                output.push_str(&node_lines('+', &item)?.green().to_string());
+            }
+            EditResult::Deleted { path, removed_node } => {
+                output.push_str(&format!("{}\n", format!("@{} delete", format_path(&path)).cyan()));
+                output.push_str(&node_lines('-', &removed_node)?.red().to_string());
+            }
+            EditResult::Replaced { path, old_node, new_node } => {
+                output.push_str(&format!("{}\n", format!("@{} replace", format_path(&path)).cyan()));
+                output.push_str(&node_lines('-', &old_node)?.red().to_string());
+                output.push_str(&node_lines('+', &new_node)?.green().to_string());
+            }
+            EditResult::Moved { source_path, dest_path } => {
+                let item = EditableNode::Item(unit.module.items[dest_path[1]].clone());
+                let header = format!("@{} -> @{} move", format_path(&source_path), format_path(&dest_path));
+                output.push_str(&format!("{}\n", header.cyan()));
+                output.push_str(&node_lines('~', &item)?.yellow().to_string());
+            }
+        }
+    }
+    Ok(output)
+}
+
+/// Printed `node` with every line behind `marker`
+fn node_lines(marker: char, node: &EditableNode) -> Result<String> {
+    let EditableNode::Item(item) = node else {
+        return Ok(format!("{marker} {node:?}\n"));
+    };
+    let printed = OCamlPrinter::new().print_item(item, &SyntaxConfig::default())?;
+    Ok(printed.lines().map(|line| format!("{marker} {line}\n")).collect())
+}
+
+fn format_path(path: &[usize]) -> String {
+    path.iter().map(|index| index.to_string()).collect::<Vec<_>>().join(".")
+}
+
+/// A line-based edit script with removed lines red and added lines green
+fn colorize(script: &str) -> String {
+    script.lines()
+        .map(|line| {
+            let colored = if line.starts_with("---") || line.starts_with("+++") {
+                line.bold()
+            } else if line.starts_with('-') {
+                line.red()
+            } else if line.starts_with('+') {
+                line.green()
+            } else {
+                line.normal()
+            };
+            format!("{colored}\n")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    use x_parser::FileId;
+    use x_parser::syntax::MultiSyntax;
+
+    #[test]
+    fn test_render_script() {
+        let parse = |source: &str| MultiSyntax::default()
+            .parse(source, x_parser::syntax::SyntaxStyle::OCaml, FileId::new(0))
+            .unwrap();
+        let old = parse("module Demo\nlet a = 1\nlet b = 2\nlet c = 3\n");
+        let new = parse("module Demo\nlet b = 20\nlet c = 3\nlet a = 1\n");
+
+        let rendered = render_script(&old, diff_compilation_units(&old, &new)).unwrap();
+        assert!(rendered.contains("@0.0 -> @0.2 move"), "{rendered}");
+        assert!(rendered.contains("~ let a = 1"), "{rendered}");
+        assert!(rendered.contains("- let b = 2"), "{rendered}");
+        assert!(rendered.contains("+ let b = 20"), "{rendered}");
+    }
+
+    #[tokio::test]
+    async fn test_structural_diff_ignores_layout() {
+        let temp_dir = TempDir::new().unwrap();
+        let (old, new) = (temp_dir.path().join("old.x"), temp_dir.path().join("new.x"));
+        fs::write(&old, "module Demo\nlet a = 1\n").unwrap();
+        fs::write(&new, "module Demo\n\n-- unchanged\nlet a   =   1\n").unwrap();
+
+        let unit = load_compilation_unit(&old).unwrap();
+        assert!(diff_compilation_units(&unit, &load_compilation_unit(&new).unwrap()).is_empty());
+        run(DiffArgs { old, new, structural: true }).await.unwrap();
+    }
+}
@@ -7,6 +7,7 @@ use clap::Args;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use x_parser::{parse_source, FileId, SyntaxStyle};
+use x_parser::span::LineMap;
 use x_checker::TypeChecker;
 
 #[derive(Debug, Args)]
@@ -129,6 +130,7 @@ pub struct AstReference {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ModuleSummary {
     pub name: String,
+    pub doc: Option<String>,
     pub exports: Vec<ExportSummary>,
     pub imports: Vec<ImportSummary>,
     pub internal_symbols: Vec<SemanticSymbol>,
@@ -142,6 +144,8 @@ pub struct ExportSummary {
     pub kind: SymbolKind,
     pub signature: Option<String>,
     pub doc: Option<String>,
+    /// Performs no effects, as checked rather than only declared
+    pub is_pure: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -185,6 +189,7 @@ impl DocCommand {
             
             let file_id = FileId(0);
             let compilation_unit = parse_source(&content, file_id, SyntaxStyle::SExpression)?;
+            report_doc_attribute_warnings(&compilation_unit, &content, &file_path);
             
             // Type check for better semantic information
             let mut type_checker = TypeChecker::new();
@@ -225,6 +230,17 @@ impl DocCommand {
     }
 }
 
+/// Print a warning for each doc comment attribute the schema doesn't allow
+fn report_doc_attribute_warnings(unit: &x_parser::CompilationUnit, source: &str, path: &std::path::Path) {
+    let module = &unit.module;
+    let documentation = module.documentation.iter().chain(module.items.iter().filter_map(|item| item.documentation()));
+    let line_map = LineMap::new(source);
+    for warning in documentation.flat_map(|documentation| documentation.doc_comment.attribute_warnings()) {
+        let position = line_map.offset_to_position(warning.span.start);
+        eprintln!("warning: {}:{}:{}: {warning}", path.display(), position.line.to_display(), position.column.to_display());
+    }
+}
+
 fn discover_x_files(path: &PathBuf) -> Result<Vec<PathBuf>> {
     let mut files = Vec::new();
     
@@ -274,13 +290,24 @@ fn generate_module_summary(
                 Item::ModuleTypeDef(_) => "ModuleTypeDef",
                 Item::InterfaceDef(_) => "InterfaceDef",
                 Item::TestDef(_) => "TestDef",
+                Item::BenchDef(_) => "BenchDef",
+                Item::ClassDef(_) => "ClassDef",
+                Item::InstanceDef(_) => "InstanceDef",
+                Item::Fixity(_) => "Fixity",
+                Item::Conditional(_) => "Conditional",
             }.to_string(),
             content_hash: calculate_content_hash(item),
         };
         
-        match item {
+        match item.unconditional() {
             Item::ValueDef(def) => {
                 if should_include(&def.visibility, include_private) {
+                    let performed = check_result.performed_effects(def.name);
+                    let is_pure = performed.as_ref().map_or(def.purity == x_parser::Purity::Pure, Vec::is_empty);
+                    let effects: Vec<String> = performed.unwrap_or_default().iter()
+                        .map(|effect| effect.as_str().to_string())
+                        .collect();
+                    let has_effects = !effects.is_empty();
                     let symbol = SemanticSymbol {
                         id: format!("{}.{}", module.name.to_string(), def.name.as_str()),
                         name: def.name.as_str().to_string(),
@@ -288,14 +315,14 @@ fn generate_module_summary(
                         kind: SymbolKind::Function,
                         type_signature: def.type_annotation.as_ref().map(|t| format!("{:?}", t)),
                         doc: def.documentation.as_ref().map(|doc| format_documentation(doc)),
-                        effects: extract_effects_from_type(&def.type_annotation),
+                        effects,
                         dependencies: extract_dependencies_from_expr(&def.body),
                         children: Vec::new(),
                         properties: SymbolProperties {
-                            is_pure: def.purity == x_parser::Purity::Pure,
+                            is_pure,
                             is_exported: is_exported(&def.visibility),
                             is_generic: def.type_annotation.as_ref().map_or(false, has_type_params),
-                            has_effects: !extract_effects_from_type(&def.type_annotation).is_empty(),
+                            has_effects,
                             complexity_score: calculate_complexity(&def.body),
                             test_coverage: None,
                         },
@@ -308,6 +335,7 @@ fn generate_module_summary(
                             kind: SymbolKind::Function,
                             signature: symbol.type_signature.clone(),
                             doc: symbol.doc.clone(),
+                            is_pure,
                         });
                     } else {
                         internal_symbols.push(symbol);
@@ -354,6 +382,7 @@ fn generate_module_summary(
     
     Ok(ModuleSummary {
         name: module.name.to_string(),
+        doc: module.documentation.as_ref().map(format_documentation),
         exports,
         imports: extract_imports(&module.imports),
         internal_symbols,
@@ -429,11 +458,6 @@ fn format_documentation(doc: &x_parser::Documentation) -> String {
     result.trim().to_string()
 }
 
-fn extract_effects_from_type(type_ann: &Option<x_parser::Type>) -> Vec<String> {
-    // TODO: Extract effect information from type
-    Vec::new()
-}
-
 fn extract_effects_from_expr(expr: &x_parser::Expr) -> Vec<String> {
     // TODO: Extract effects from expression
     Vec::new()
@@ -460,10 +484,15 @@ fn extract_dependencies_from_expr(expr: &x_parser::Expr) -> Vec<String> {
             Expr::Lambda { body, .. } => {
                 collect_deps(body, deps);
             }
-            Expr::Let { value, body, .. } => {
+            Expr::Let { value, body, .. } | Expr::Unpack { value, body, .. } => {
                 collect_deps(value, deps);
                 collect_deps(body, deps);
             }
+            Expr::Pack { module, .. } => {
+                if !deps.contains(&module.to_string()) {
+                    deps.push(module.to_string());
+                }
+            }
             Expr::If { condition, then_branch, else_branch, .. } => {
                 collect_deps(condition, deps);
                 collect_deps(then_branch, deps);
@@ -496,10 +525,26 @@ fn extract_dependencies_from_expr(expr: &x_parser::Expr) -> Vec<String> {
                     collect_deps(arg, deps);
                 }
             }
-            Expr::Ann { expr, .. } => {
+            Expr::Ann { expr, .. } | Expr::Project { record: expr, .. } => {
                 collect_deps(expr, deps);
             }
-            Expr::Literal(_, _) => {
+            Expr::Record { fields, .. } => {
+                for (_, value) in fields {
+                    collect_deps(value, deps);
+                }
+            }
+            Expr::Tuple { elements, .. } => {
+                for element in elements {
+                    collect_deps(element, deps);
+                }
+            }
+            Expr::RecordUpdate { record, fields, .. } => {
+                collect_deps(record, deps);
+                for (_, value) in fields {
+                    collect_deps(value, deps);
+                }
+            }
+            Expr::Literal(_, _) | Expr::Error(_) => {
                 // No dependencies
             }
         }
@@ -548,13 +593,18 @@ fn build_dependency_graph(
 fn print_human_summary(summaries: &[ModuleSummary]) {
     for summary in summaries {
         println!("Module: {}", summary.name);
+        if let Some(doc) = &summary.doc {
+            println!("  Doc: {}", doc);
+        }
         println!("  Exports: {}", summary.exports.len());
         for export in &summary.exports {
-            println!("    - {} ({})", export.name, format!("{:?}", export.kind));
+            let purity = if export.is_pure { ", pure" } else { "" };
+            println!("    - {} ({:?}{purity})", export.name, export.kind);
         }
         println!("  Internal symbols: {}", summary.internal_symbols.len());
         for symbol in &summary.internal_symbols {
-            println!("    - {} ({})", symbol.name, format!("{:?}", symbol.kind));
+            let purity = if symbol.properties.is_pure { ", pure" } else { "" };
+            println!("    - {} ({:?}{purity})", symbol.name, symbol.kind);
             if let Some(doc) = &symbol.doc {
                 println!("      Doc: {}", doc);
             }
@@ -611,6 +661,7 @@ fn print_semantic_summary(summaries: &[ModuleSummary]) -> Result<()> {
                     "name": e.name,
                     "kind": e.kind,
                     "signature": e.signature,
+                    "pure": e.is_pure,
                 })
             }).collect::<Vec<_>>(),
             "ast_refs": summary.internal_symbols.iter().map(|s| {
@@ -652,6 +703,11 @@ fn print_function_list(summaries: &[ModuleSummary]) {
                 if let Some(sig) = &func.type_signature {
                     println!("    Type: {}", sig);
                 }
+                if func.properties.is_pure {
+                    println!("    Pure");
+                } else if !func.effects.is_empty() {
+                    println!("    Effects: {}", func.effects.join(", "));
+                }
                 if !func.dependencies.is_empty() {
                     println!("    Deps: {}", func.dependencies.join(", "));
                 }
@@ -659,7 +715,8 @@ fn print_function_list(summaries: &[ModuleSummary]) {
             
             // Print exported functions
             for exp in exported_functions {
-                println!("  - {} (exported)", exp.name);
+                let purity = if exp.is_pure { ", pure" } else { "" };
+                println!("  - {} (exported{purity})", exp.name);
                 if let Some(sig) = &exp.signature {
                     println!("    Type: {}", sig);
                 }
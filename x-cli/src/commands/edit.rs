@@ -1,12 +1,16 @@
 //! AST editing commands
 
-use anyhow::Result;
+use anyhow::{Result, Context};
+use std::fs;
 use std::path::Path;
+use x_editor::{extract_function, AstEditor};
+use x_parser::span::{CharOffset, Position};
+use x_parser::{parse_source, SourceFile, SourceMap, Span, SyntaxStyle};
 use crate::utils::{ProgressIndicator, print_success, print_warning};
 
 pub async fn edit_command(
-    _input: &Path,
-    _output: Option<&Path>,
+    input: &Path,
+    output: Option<&Path>,
     commands: Option<&str>,
     interactive: bool,
 ) -> Result<()> {
@@ -14,8 +18,7 @@ pub async fn edit_command(
     
     if interactive {
         progress.finish("Starting interactive mode");
-        print_warning("Interactive editing mode is not yet implemented");
-        return Ok(());
+        return crate::interactive::run(input, output);
     }
     
     if let Some(_cmd_str) = commands {
@@ -48,21 +51,89 @@ pub async fn rename_command(
     Ok(())
 }
 
-#[allow(dead_code)]
+/// Extract the expression between `start` and `end` into a new top-level function
+///
+/// Positions are `line:column`, both 1-based; `end` points just past the selection.
 pub async fn extract_command(
     input: &Path,
     start: &str,
     end: &str,
     name: &str,
-    _output: Option<&Path>,
+    output: Option<&Path>,
 ) -> Result<()> {
     let progress = ProgressIndicator::new("Extracting method");
-    
-    println!("Extracting method '{}' from {}:{} in {}", name, start, end, input.display());
-    
-    // TODO: Implement method extraction
+
+    let mut sources = SourceMap::new();
+    let file = sources.add_file(input, fs::read_to_string(input)
+        .with_context(|| format!("Failed to read file: {}", input.display()))?);
+    let file = sources.file(file).context("extracted file is in the source map")?;
+    let source = file.text();
+    let mut ast = parse_source(source, file.id(), SyntaxStyle::SExpression)
+        .with_context(|| format!("Failed to parse: {}", input.display()))?;
+
+    let selection = Span::new(
+        file.id(),
+        resolve_position(file, start)?,
+        resolve_position(file, end)?,
+    );
+
+    let mut editor = AstEditor::new();
+    let extraction = extract_function(&mut editor, &mut ast, source, selection, name)?;
+
+    let output = output.unwrap_or(input);
+    fs::write(output, extraction.apply_to_source(source))
+        .with_context(|| format!("Failed to write file: {}", output.display()))?;
+
     progress.finish("Method extraction completed");
-    print_success(&format!("Extracted method '{}'", name));
-    
+    let params: Vec<String> = extraction.parameters.iter().map(|p| p.to_string()).collect();
+    let effects: Vec<String> = extraction.effects.iter().map(|e| e.to_string()).collect();
+    print_success(&format!(
+        "Extracted '{}' ({}) <{}> into {}",
+        name,
+        params.join(", "),
+        effects.join(", "),
+        output.display(),
+    ));
+
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Convert a 1-based `line:column` pair into an offset in `file`
+fn resolve_position(file: &SourceFile, position: &str) -> Result<CharOffset> {
+    let (line, column) = position.split_once(':')
+        .and_then(|(line, column)| Some((line.parse::<u32>().ok()?, column.parse::<u32>().ok()?)))
+        .filter(|(line, column)| *line > 0 && *column > 0)
+        .with_context(|| format!("Invalid position '{}', expected line:column", position))?;
+
+    file.offset(Position::new(line - 1, column - 1))
+        .with_context(|| format!("Position {} is outside the file", position))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_resolve_position() {
+        let mut sources = SourceMap::new();
+        let file = sources.add_file("main.x", "module Test\nlet x = 1");
+        let file = sources.file(file).unwrap();
+        assert_eq!(resolve_position(file, "2:5").unwrap(), CharOffset::new(16));
+        assert!(resolve_position(file, "0:1").is_err());
+        assert!(resolve_position(file, "two").is_err());
+        assert!(resolve_position(file, "1:20").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_extract_command_rewrites_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("main.x");
+        fs::write(&path, "module Test\nlet f = fun a -> a * 2 + 1").unwrap();
+
+        extract_command(&path, "2:18", "2:23", "double", None).await.unwrap();
+
+        let rewritten = fs::read_to_string(&path).unwrap();
+        assert_eq!(rewritten, "module Test\nlet double = fun a -> a * 2\nlet f = fun a -> (double a) + 1");
+    }
+}
@@ -0,0 +1,203 @@
+//! Format command - re-print sources in their canonical layout
+//!
+//! Every file is parsed and printed back in its own syntax style. Output that
+//! would change again when formatted a second time is never written, so a
+//! formatted file stays formatted. Comments are kept. `--check` leaves the
+//! files alone and fails when any of them would change, for CI.
+
+use anyhow::{Result, Context, anyhow, bail};
+use clap::Args;
+use colored::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+use x_parser::FileId;
+use x_parser::syntax::{SyntaxConfig, SyntaxParser, SyntaxPrinter, SyntaxStyle};
+use x_parser::syntax::ocaml::{OCamlParser, OCamlPrinter};
+use x_parser::syntax::sexp::{SExpParser, SExpPrinter};
+use crate::commands::migrate::edit_script;
+
+/// Format source files in their canonical layout
+#[derive(Debug, Args)]
+pub struct FmtArgs {
+    /// Files or directories to format
+    #[arg(default_value = ".")]
+    paths: Vec<PathBuf>,
+
+    /// Report files that are not formatted instead of rewriting them, and fail if there are any
+    #[arg(long)]
+    check: bool,
+
+    /// Maximum line width
+    #[arg(long)]
+    width: Option<usize>,
+
+    /// Spaces per indentation level
+    #[arg(long)]
+    indent: Option<usize>,
+
+    /// Indent with tabs
+    #[arg(long)]
+    tabs: bool,
+}
+
+pub async fn run(args: FmtArgs) -> Result<()> {
+    let defaults = SyntaxConfig::default();
+    let config = SyntaxConfig {
+        max_line_length: args.width.unwrap_or(defaults.max_line_length),
+        indent_size: args.indent.unwrap_or(defaults.indent_size),
+        use_tabs: args.tabs,
+        ..defaults
+    };
+
+    let mut files = Vec::new();
+    for path in &args.paths {
+        if path.is_dir() {
+            files.extend(discover_sources(path)?);
+        } else if syntax_of(path)?.is_some() {
+            files.push(path.clone());
+        } else {
+            bail!("Not a textual source file: {}", path.display());
+        }
+    }
+
+    let mut changed = 0;
+    let mut failed = 0;
+    for path in &files {
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read file: {}", path.display()))?;
+        let Some(style) = syntax_of(path)? else { continue };
+        // A file that cannot be formatted is reported, and the others are still formatted
+        let formatted = match format_source(&source, style, &config) {
+            Ok(formatted) => formatted,
+            Err(error) => {
+                failed += 1;
+                println!("  {} {}: {error:#}", "failed".red(), path.display());
+                continue;
+            }
+        };
+        if formatted == source {
+            continue;
+        }
+        changed += 1;
+
+        if args.check {
+            println!("  {} {}", "unformatted".yellow(), path.display());
+            print!("{}", edit_script(&path.display().to_string(), &source, &formatted));
+        } else {
+            fs::write(path, &formatted)
+                .with_context(|| format!("Failed to write file: {}", path.display()))?;
+            println!("  {} {}", "formatted".green(), path.display());
+        }
+    }
+
+    if failed > 0 {
+        bail!("{failed} of {} files could not be formatted", files.len());
+    }
+    if args.check && changed > 0 {
+        bail!("{changed} of {} files are not formatted; run `x fmt` to fix them", files.len());
+    }
+    let verb = if args.check { "checked" } else { "reformatted" };
+    let count = if args.check { files.len() } else { changed };
+    println!("{} {verb}, {} files in total", count.to_string().cyan(), files.len());
+    Ok(())
+}
+
+/// `source` printed in its canonical layout, checked to be a fixed point
+pub fn format_source(source: &str, style: SyntaxStyle, config: &SyntaxConfig) -> Result<String> {
+    let formatted = print(source, style, config)?;
+    let again = print(&formatted, style, config)
+        .map_err(|error| anyhow!("formatted output does not parse back: {error}"))?;
+    if again != formatted {
+        bail!("formatting is not stable for this file:\n{}", edit_script("formatted", &formatted, &again));
+    }
+    Ok(formatted)
+}
+
+fn print(source: &str, style: SyntaxStyle, config: &SyntaxConfig) -> Result<String> {
+    let file_id = FileId::new(0);
+    let config = SyntaxConfig { style, ..config.clone() };
+    let printed = match style {
+        SyntaxStyle::OCaml => {
+            let (unit, trivia) = OCamlParser::new().parse_with_trivia(source, file_id)?;
+            OCamlPrinter::with_source(source).print_with_trivia(&unit, &trivia, &config)?
+        }
+        SyntaxStyle::SExp => {
+            let (unit, trivia) = SExpParser::new().parse_with_trivia(source, file_id)?;
+            let mut printed = SExpPrinter::new().print_with_trivia(&unit, &trivia, &config)?;
+            printed.push('\n');
+            printed
+        }
+    };
+    Ok(printed)
+}
+
+/// Syntax a source file is written in; binary `.x` files have none
+pub(crate) fn syntax_of(path: &Path) -> Result<Option<SyntaxStyle>> {
+    let name = path.to_string_lossy().to_lowercase();
+    if name.ends_with(".lisp.x") || name.ends_with(".sexp.x") {
+        return Ok(Some(SyntaxStyle::SExp));
+    }
+    if !name.ends_with(".x") {
+        return Ok(None);
+    }
+    let content = fs::read(path).with_context(|| format!("Failed to read file: {}", path.display()))?;
+    Ok((!content.starts_with(&x_parser::binary::MAGIC_NUMBER)).then_some(SyntaxStyle::OCaml))
+}
+
+/// Textual sources under `dir`, skipping hidden and build directories
+pub(crate) fn discover_sources(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut directories = vec![dir.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        let entries = fs::read_dir(&directory)
+            .with_context(|| format!("Failed to read directory: {}", directory.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            let file_name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+            if path.is_dir() {
+                if !file_name.starts_with('.') && !matches!(file_name, "dist" | "target" | "node_modules") {
+                    directories.push(path);
+                }
+            } else if syntax_of(&path)?.is_some() {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const UNFORMATTED: &str = "module Demo\nlet double = fun x ->   x+x\n";
+
+    fn args(path: &Path, check: bool) -> FmtArgs {
+        FmtArgs { paths: vec![path.to_path_buf()], check, width: None, indent: None, tabs: false }
+    }
+
+    #[test]
+    fn test_format_source() {
+        let config = SyntaxConfig::default();
+        let formatted = format_source(UNFORMATTED, SyntaxStyle::OCaml, &config).unwrap();
+        assert_eq!(formatted, "module Demo\n\nlet double = fun x -> x + x\n");
+        assert_eq!(format_source(&formatted, SyntaxStyle::OCaml, &config).unwrap(), formatted);
+    }
+
+    #[tokio::test]
+    async fn test_check_then_format() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.path().join("src/demo.x");
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(&file, UNFORMATTED).unwrap();
+        fs::write(temp_dir.path().join("ast.x"), x_parser::binary::MAGIC_NUMBER).unwrap();
+
+        assert!(run(args(temp_dir.path(), true)).await.is_err());
+        assert_eq!(fs::read_to_string(&file).unwrap(), UNFORMATTED, "--check leaves files alone");
+
+        run(args(temp_dir.path(), false)).await.unwrap();
+        run(args(temp_dir.path(), true)).await.unwrap();
+    }
+}
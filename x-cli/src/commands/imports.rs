@@ -5,7 +5,9 @@ use clap::{Args, ValueEnum};
 use std::path::PathBuf;
 use std::fs;
 use x_parser::{Parser, FileId};
+use x_editor::ImportTree;
 use colored::*;
+use crate::manifest::{module_graph, Project};
 
 /// Extract and display import information
 #[derive(Debug, Args)]
@@ -37,6 +39,19 @@ pub async fn run(args: ImportsArgs) -> Result<()> {
     
     let module = &compilation_unit.module;
     
+    if args.transitive {
+        let graph = match Project::discover(&args.input)? {
+            Some(project) => project.dependency_graph(&args.input, &compilation_unit)?,
+            None => module_graph(&compilation_unit, None),
+        };
+        let tree = graph.import_tree(&module.name.to_string());
+        match args.format {
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&import_tree_json(&tree))?),
+            OutputFormat::Text | OutputFormat::Tree => display_import_tree(&tree, ""),
+        }
+        return Ok(());
+    }
+    
     match args.format {
         OutputFormat::Text => display_imports_text(module),
         OutputFormat::Json => display_imports_json(module)?,
@@ -209,4 +224,43 @@ fn display_imports_tree(module: &x_parser::ast::Module) {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+/// Print the transitive import tree, marking modules shown earlier with `(*)`
+fn display_import_tree(tree: &ImportTree, indent: &str) {
+    if indent.is_empty() {
+        println!("{}{}", tree.module.bold(), describe_origin(tree));
+    }
+    for (i, child) in tree.children.iter().enumerate() {
+        let is_last = i == tree.children.len() - 1;
+        let prefix = if is_last { "└── " } else { "├── " };
+        
+        let requirement = child.requirement.as_ref()
+            .map(|v| format!("@{}", v.green()))
+            .unwrap_or_default();
+        let repeated = if child.repeated { " (*)".dimmed().to_string() } else { String::new() };
+        
+        println!("{}{}{}{}{}{}", indent, prefix, child.module.yellow(), requirement, describe_origin(child), repeated);
+        display_import_tree(child, &format!("{}{}", indent, if is_last { "    " } else { "│   " }));
+    }
+}
+
+fn describe_origin(tree: &ImportTree) -> String {
+    match (&tree.package, &tree.version) {
+        (Some(package), Some(version)) => format!(" ({} {})", package, version).dimmed().to_string(),
+        (Some(package), None) => format!(" ({})", package).dimmed().to_string(),
+        (None, _) if x_compiler::stdlib::is_stdlib_module(&tree.module) => " (core)".dimmed().to_string(),
+        (None, _) => String::new(),
+    }
+}
+
+fn import_tree_json(tree: &ImportTree) -> serde_json::Value {
+    serde_json::json!({
+        "module": tree.module,
+        "requirement": tree.requirement,
+        "package": tree.package,
+        "version": tree.version.as_ref().map(|v| v.to_string()),
+        "repeated": tree.repeated,
+        "imports": tree.children.iter().map(import_tree_json).collect::<Vec<_>>(),
+    })
+}
@@ -0,0 +1,199 @@
+//! Lint command - check sources against the lint rules
+//!
+//! Every source is parsed, type checked and run through the built-in rules
+//! at the severities the `[lints]` table of the project's `x.toml` sets,
+//! which `--rule` overrides. Findings are printed grouped by rule, and any
+//! finding of a denied rule makes the command fail.
+
+use anyhow::{Result, Context, anyhow, bail};
+use clap::Args;
+use colored::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+use x_editor::{LintDiagnostic, LintRegistry, Severity};
+use x_parser::span::LineMap;
+use x_parser::syntax::{MultiSyntax, SyntaxStyle};
+use x_parser::{parse_source, FileId};
+use crate::commands::fmt::{discover_sources, syntax_of};
+use crate::manifest::{Manifest, MANIFEST_FILE};
+
+/// Check sources against the lint rules
+#[derive(Debug, Args)]
+pub struct LintArgs {
+    /// Files or directories to lint
+    #[arg(default_value = ".")]
+    paths: Vec<PathBuf>,
+
+    /// Set the severity of a rule, by name or code, over what x.toml says
+    #[arg(long = "rule", value_name = "RULE=allow|warn|deny")]
+    rules: Vec<String>,
+
+    /// List the rules and their severities instead of linting
+    #[arg(long)]
+    list: bool,
+}
+
+pub async fn run(args: LintArgs) -> Result<()> {
+    let registry = configure(&args)?;
+    if args.list {
+        for lint in registry.lints() {
+            let severity = registry.severity(lint.name()).unwrap_or(Severity::Allow);
+            println!("{} {:<28} {}", lint.code().cyan(), lint.name(), paint(severity, &severity.to_string()));
+        }
+        return Ok(());
+    }
+
+    let mut files = Vec::new();
+    for path in &args.paths {
+        if path.is_dir() {
+            files.extend(discover_sources(path)?);
+        } else if syntax_of(path)?.is_some() {
+            files.push(path.clone());
+        } else {
+            bail!("Not a textual source file: {}", path.display());
+        }
+    }
+
+    let mut findings: Vec<(&Path, LineMap, LintDiagnostic)> = Vec::new();
+    let mut failed = 0;
+    for (index, path) in files.iter().enumerate() {
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read file: {}", path.display()))?;
+        let Some(style) = syntax_of(path)? else { continue };
+        let file_id = FileId::new(index as u32);
+        let parsed = match style {
+            SyntaxStyle::OCaml => parse_source(&source, file_id, x_parser::SyntaxStyle::SExpression)
+                .map_err(anyhow::Error::from),
+            SyntaxStyle::SExp => MultiSyntax::default().parse(&source, style, file_id)
+                .map_err(anyhow::Error::from),
+        };
+        let unit = match parsed {
+            Ok(unit) => unit,
+            Err(error) => {
+                failed += 1;
+                println!("  {} {}: {error:#}", "failed".red(), path.display());
+                continue;
+            }
+        };
+        let line_map = LineMap::new(&source);
+        for diagnostic in registry.run(&unit, &source) {
+            findings.push((path, line_map.clone(), diagnostic));
+        }
+    }
+
+    print!("{}", render(&registry, &findings));
+    let errors = findings.iter().filter(|(.., diagnostic)| diagnostic.severity == Severity::Deny).count();
+    let warnings = findings.len() - errors;
+    if failed > 0 {
+        bail!("{failed} of {} files could not be parsed", files.len());
+    }
+    if errors > 0 {
+        bail!("{errors} lint errors and {warnings} warnings in {} files", files.len());
+    }
+    println!("{} warnings in {} files", warnings.to_string().cyan(), files.len());
+    Ok(())
+}
+
+/// The built-in rules at the severities from x.toml and `--rule`
+fn configure(args: &LintArgs) -> Result<LintRegistry> {
+    let mut registry = LintRegistry::builtin();
+    let start = args.paths.first().map_or(Path::new("."), PathBuf::as_path);
+    if let Some(manifest) = find_manifest(start)? {
+        let lints = Manifest::load(&manifest)?.lints;
+        registry.configure(&lints)
+            .with_context(|| format!("Invalid [lints] table in {}", manifest.display()))?;
+    }
+    for rule in &args.rules {
+        let (name, severity) = rule.split_once('=')
+            .ok_or_else(|| anyhow!("Expected RULE=SEVERITY, found `{rule}`"))?;
+        let severity = match severity {
+            "allow" => Severity::Allow,
+            "warn" => Severity::Warn,
+            "deny" => Severity::Deny,
+            _ => bail!("Unknown severity `{severity}`; expected allow, warn or deny"),
+        };
+        registry.set_severity(name, severity)?;
+    }
+    Ok(registry)
+}
+
+/// The manifest of the package `path` belongs to
+fn find_manifest(path: &Path) -> Result<Option<PathBuf>> {
+    let path = path.canonicalize()
+        .with_context(|| format!("Failed to resolve {}", path.display()))?;
+    Ok(path.ancestors().map(|dir| dir.join(MANIFEST_FILE)).find(|manifest| manifest.is_file()))
+}
+
+/// Findings grouped by rule, in the order the rules are registered
+fn render(registry: &LintRegistry, findings: &[(&Path, LineMap, LintDiagnostic)]) -> String {
+    let mut output = String::new();
+    for lint in registry.lints() {
+        let group: Vec<_> = findings.iter().filter(|(.., diagnostic)| diagnostic.rule == lint.name()).collect();
+        let Some((.., first)) = group.first() else { continue };
+        let header = format!("{}[{}] {}", first.severity, lint.code(), lint.name());
+        output.push_str(&format!("{} ({})\n", paint(first.severity, &header).bold(), group.len()));
+        for (path, line_map, diagnostic) in group {
+            let start = line_map.offset_to_position(diagnostic.span.start);
+            output.push_str(&format!(
+                "  {}:{}:{}: {}\n",
+                path.display(),
+                start.line.to_display(),
+                start.column.to_display(),
+                diagnostic.message,
+            ));
+        }
+    }
+    output
+}
+
+fn paint(severity: Severity, text: &str) -> ColoredString {
+    match severity {
+        Severity::Deny => text.red(),
+        Severity::Warn => text.yellow(),
+        Severity::Allow => text.dimmed(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn args(path: &Path, rules: &[&str]) -> LintArgs {
+        LintArgs {
+            paths: vec![path.to_path_buf()],
+            rules: rules.iter().map(|rule| rule.to_string()).collect(),
+            list: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_severities_from_manifest_and_flags() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(
+            temp_dir.path().join(MANIFEST_FILE),
+            "[package]\nname = \"demo\"\nversion = \"0.1.0\"\n\n[lints]\nunused-variable = \"deny\"\n",
+        ).unwrap();
+        fs::write(temp_dir.path().join("main.x"), "module Demo\nlet main = fun u -> 1\n").unwrap();
+
+        assert!(run(args(temp_dir.path(), &[])).await.is_err());
+        run(args(temp_dir.path(), &["W0001=warn"])).await.unwrap();
+        assert!(run(args(temp_dir.path(), &["no-such-rule=warn"])).await.is_err());
+    }
+
+    #[test]
+    fn test_render_groups_by_rule() {
+        let source = "module Demo\nimport Text\nlet main = fun u -> 1\n";
+        let unit = parse_source(source, FileId::new(0), x_parser::SyntaxStyle::SExpression).unwrap();
+        let registry = LintRegistry::builtin();
+        let path = Path::new("main.x");
+        let findings: Vec<_> = registry.run(&unit, source).into_iter()
+            .map(|diagnostic| (path, LineMap::new(source), diagnostic))
+            .collect();
+
+        colored::control::set_override(false);
+        let rendered = render(&registry, &findings);
+        assert_eq!(rendered, "warning[W0001] unused-variable (1)\n  main.x:3:16: unused variable `u`\n\
+            warning[W0002] unused-import (1)\n  main.x:2:8: unused import `Text`\n");
+    }
+}
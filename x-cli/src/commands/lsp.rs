@@ -1,16 +1,972 @@
 //! Language Server Protocol commands
+//!
+//! Requests and diagnostics run on worker threads, each against a snapshot
+//! of the open documents and with a `CancellationToken` the type checker
+//! polls. Editing a document cancels the diagnostics and requests still
+//! running for its previous text, `$/cancelRequest` cancels a request, and
+//! anything running past `REQUEST_TIMEOUT` is abandoned.
+//!
+//! Type checking goes through the checker's salsa database, which keeps
+//! the checks of the items an edit leaves alone.
 
-use anyhow::Result;
-use crate::utils::print_warning;
+use anyhow::{Result, Context, bail};
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+    Cancel, DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+    PublishDiagnostics,
+};
+use lsp_types::request::{
+    CodeActionRequest, Completion, HoverRequest, PrepareRenameRequest, References, Rename, Request as _,
+    SemanticTokensFullRequest,
+};
+use lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, CodeActionParams, CodeActionProviderCapability,
+    CodeActionResponse, CompletionItem, CompletionItemKind, CompletionItemTag, CompletionOptions, CompletionParams,
+    CompletionResponse, Diagnostic, DiagnosticSeverity, DiagnosticTag, Hover, HoverContents, HoverParams,
+    HoverProviderCapability, Location, MarkupContent, MarkupKind, NumberOrString, OneOf, PublishDiagnosticsParams, PrepareRenameResponse, ReferenceParams, RenameOptions, RenameParams, SemanticToken, SemanticTokenModifier,
+    SemanticTokenType, SemanticTokens, SemanticTokensFullOptions, SemanticTokensLegend,
+    SemanticTokensOptions, SemanticTokensParams, SemanticTokensResult,
+    SemanticTokensServerCapabilities, ServerCapabilities, TextDocumentPositionParams,
+    TextDocumentSyncCapability, TextDocumentSyncKind, TextEdit, Url, WorkDoneProgressOptions,
+    WorkspaceEdit,
+};
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+use x_checker::{CheckResult, TypeCheckDatabaseImpl, TypeError};
+use x_editor::{
+    references_at, rename_symbol, semantic_tokens, suggest_fix, BindingKind, QuickFix, SemanticTokenKind,
+    SourceFile, SymbolIndex,
+};
+use x_parser::span::{CharOffset, LineMap};
+use x_parser::{
+    parse_source, parse_source_recovering, CancellationToken, CompilationUnit, Expr, FileId, Item, ParseError,
+    Purity, SyntaxStyle,
+};
+
+/// How long a request or a diagnostics run may take before it is abandoned
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
 
 pub async fn lsp_command(mode: &str, port: u16) -> Result<()> {
-    println!("Starting x Language Server in {} mode", mode);
-    
-    if mode == "tcp" {
-        println!("Listening on port {}", port);
-    }
-    
-    print_warning("LSP server is not yet implemented");
-    
+    let (connection, io_threads) = match mode {
+        "stdio" => Connection::stdio(),
+        "tcp" => {
+            eprintln!("Listening on port {}", port);
+            Connection::listen(("127.0.0.1", port))
+                .with_context(|| format!("Failed to listen on port {}", port))?
+        }
+        _ => bail!("Unknown LSP mode: {} (expected stdio or tcp)", mode),
+    };
+
+    run_server(connection)?;
+    io_threads.join()?;
+
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Initialize the connection and serve requests until shutdown
+fn run_server(connection: Connection) -> Result<()> {
+    let capabilities = serde_json::to_value(server_capabilities())?;
+    connection.initialize(capabilities)?;
+    info!("x Language Server initialized");
+
+    let mut server = LspServer::default();
+    let sender = connection.sender.clone();
+    let mut scheduler = Scheduler::new(move |message| {
+        // The client has gone away if the channel is closed
+        let _ = sender.send(message);
+    });
+    for msg in &connection.receiver {
+        match msg {
+            Message::Request(req) => {
+                if connection.handle_shutdown(&req)? {
+                    break;
+                }
+                scheduler.request(&server, req);
+            }
+            Message::Notification(not) if not.method == Cancel::METHOD => scheduler.cancel(not),
+            Message::Notification(not) => {
+                if let Some(uri) = server.handle_notification(not) {
+                    scheduler.document_changed(&server, uri);
+                }
+            }
+            Message::Response(resp) => warn!("Unexpected response: {:?}", resp.id),
+        }
+    }
+
+    Ok(())
+}
+
+/// Requests still running, with the document each is about
+type RunningRequests = HashMap<RequestId, (Option<Url>, CancellationToken)>;
+
+/// Runs requests and diagnostics off the message loop, cancelling the work
+/// newer messages supersede
+struct Scheduler {
+    send: Arc<dyn Fn(Message) + Send + Sync>,
+    requests: Arc<Mutex<RunningRequests>>,
+    /// The diagnostics run of each document's latest text
+    diagnostics: HashMap<Url, CancellationToken>,
+    /// Held while publishing, so cancelled diagnostics are never sent after
+    /// those of a newer text
+    publishing: Arc<Mutex<()>>,
+}
+
+impl Scheduler {
+    fn new(send: impl Fn(Message) + Send + Sync + 'static) -> Self {
+        Scheduler {
+            send: Arc::new(send),
+            requests: Arc::default(),
+            diagnostics: HashMap::new(),
+            publishing: Arc::default(),
+        }
+    }
+
+    fn request(&self, server: &LspServer, req: Request) {
+        let token = CancellationToken::new().with_timeout(REQUEST_TIMEOUT);
+        let uri = req.params.pointer("/textDocument/uri")
+            .and_then(|uri| serde_json::from_value(uri.clone()).ok());
+        self.requests.lock().unwrap().insert(req.id.clone(), (uri, token.clone()));
+        let snapshot = server.snapshot(token);
+        let (requests, send) = (self.requests.clone(), self.send.clone());
+        std::thread::spawn(move || {
+            let id = req.id.clone();
+            let response = snapshot.handle_request(req);
+            requests.lock().unwrap().remove(&id);
+            send(Message::Response(response));
+        });
+    }
+
+    /// Handle `$/cancelRequest`
+    fn cancel(&self, not: Notification) {
+        let Ok(params) = serde_json::from_value::<lsp_types::CancelParams>(not.params) else { return };
+        let id = match params.id {
+            NumberOrString::Number(id) => RequestId::from(id),
+            NumberOrString::String(id) => RequestId::from(id),
+        };
+        if let Some((_, token)) = self.requests.lock().unwrap().get(&id) {
+            token.cancel();
+        }
+    }
+
+    /// Abandon the work on the previous text of `uri` and publish the
+    /// diagnostics of its current text
+    fn document_changed(&mut self, server: &LspServer, uri: Url) {
+        let token = CancellationToken::new().with_timeout(REQUEST_TIMEOUT);
+        {
+            let _publishing = self.publishing.lock().unwrap();
+            if let Some(previous) = self.diagnostics.insert(uri.clone(), token.clone()) {
+                previous.cancel();
+            }
+        }
+        for (request_uri, request) in self.requests.lock().unwrap().values() {
+            if request_uri.as_ref() == Some(&uri) {
+                request.cancel();
+            }
+        }
+
+        let snapshot = server.snapshot(token.clone());
+        let (publishing, send) = (self.publishing.clone(), self.send.clone());
+        std::thread::spawn(move || {
+            let notification = snapshot.publish_diagnostics(uri);
+            let _publishing = publishing.lock().unwrap();
+            if !token.is_cancelled() {
+                send(Message::Notification(notification));
+            }
+        });
+    }
+}
+
+fn server_capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+        completion_provider: Some(CompletionOptions::default()),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        references_provider: Some(OneOf::Left(true)),
+        rename_provider: Some(OneOf::Right(RenameOptions {
+            prepare_provider: Some(true),
+            work_done_progress_options: WorkDoneProgressOptions::default(),
+        })),
+        semantic_tokens_provider: Some(SemanticTokensServerCapabilities::SemanticTokensOptions(
+            SemanticTokensOptions {
+                legend: semantic_tokens_legend(),
+                full: Some(SemanticTokensFullOptions::Bool(true)),
+                ..SemanticTokensOptions::default()
+            },
+        )),
+        ..ServerCapabilities::default()
+    }
+}
+
+/// Token modifiers, in legend order (bit `i` of a token's modifier set)
+const MODIFIER_DECLARATION: u32 = 1 << 0;
+const MODIFIER_EFFECTFUL: u32 = 1 << 1;
+const MODIFIER_PURE: u32 = 1 << 2;
+
+fn semantic_tokens_legend() -> SemanticTokensLegend {
+    let token_types = SemanticTokenKind::ALL.iter()
+        .map(|kind| match kind {
+            SemanticTokenKind::Effect => SemanticTokenType::new("effect"),
+            SemanticTokenKind::Handler => SemanticTokenType::new("handler"),
+            SemanticTokenKind::Type => SemanticTokenType::TYPE,
+            SemanticTokenKind::Constructor => SemanticTokenType::ENUM_MEMBER,
+            SemanticTokenKind::Operation => SemanticTokenType::METHOD,
+            SemanticTokenKind::Function => SemanticTokenType::FUNCTION,
+            SemanticTokenKind::Variable => SemanticTokenType::VARIABLE,
+            SemanticTokenKind::Parameter => SemanticTokenType::PARAMETER,
+        })
+        .collect();
+
+    SemanticTokensLegend {
+        token_types,
+        token_modifiers: vec![
+            SemanticTokenModifier::DECLARATION,
+            SemanticTokenModifier::new("effectful"),
+            SemanticTokenModifier::new("pure"),
+        ],
+    }
+}
+
+/// Open document tracked by the server
+#[derive(Debug)]
+struct Document {
+    text: String,
+    file_id: FileId,
+    /// Line starts of `text`, kept so positions convert without a scan
+    lines: LineMap,
+}
+
+impl Document {
+    fn parse(&self) -> Option<CompilationUnit> {
+        parse_source(&self.text, self.file_id, SyntaxStyle::SExpression).ok()
+    }
+
+    /// Every syntax error in the document, found by a recovering parse, or
+    /// its type errors once it parses cleanly; nothing if the type check is
+    /// cancelled
+    fn diagnostics(&self, db: &TypeCheckDatabaseImpl) -> Vec<Diagnostic> {
+        let line_map = &self.lines;
+        let errors = match parse_source_recovering(&self.text, self.file_id, SyntaxStyle::SExpression) {
+            Ok(recovered) if !recovered.has_errors() => {
+                let Ok(check) = db.check_result(self.file_id) else {
+                    return Vec::new();
+                };
+                let errors = check.errors.iter()
+                    .map(|error| type_diagnostic(error, DiagnosticSeverity::ERROR, line_map));
+                let warnings = check.warnings.iter()
+                    .map(|warning| type_diagnostic(warning, DiagnosticSeverity::WARNING, line_map));
+                return errors.chain(warnings).collect();
+            }
+            Ok(recovered) => recovered.errors,
+            Err(error) => vec![error],
+        };
+        errors.iter()
+            .map(|error| Diagnostic {
+                range: error.span().map(|span| span.to_lsp_range(line_map)).unwrap_or_default(),
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(NumberOrString::String(error.code().to_string())),
+                source: Some("x".to_string()),
+                message: match error {
+                    ParseError::Syntax { message, .. } => message.clone(),
+                    error => error.to_string(),
+                },
+                ..Diagnostic::default()
+            })
+            .collect()
+    }
+
+    fn offset_at(&self, position: lsp_types::Position) -> Option<CharOffset> {
+        self.lines.position_to_offset(position.into())
+    }
+}
+
+/// The server's type checking database, or a snapshot of it for work on
+/// another thread
+enum Database {
+    Owned(TypeCheckDatabaseImpl),
+    Snapshot(salsa::Snapshot<TypeCheckDatabaseImpl>),
+}
+
+impl Default for Database {
+    fn default() -> Self {
+        Database::Owned(TypeCheckDatabaseImpl::new())
+    }
+}
+
+impl Deref for Database {
+    type Target = TypeCheckDatabaseImpl;
+
+    fn deref(&self) -> &TypeCheckDatabaseImpl {
+        match self {
+            Database::Owned(db) => db,
+            Database::Snapshot(db) => db,
+        }
+    }
+}
+
+impl std::fmt::Debug for Database {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&**self, f)
+    }
+}
+
+/// Server state: the set of open documents
+#[derive(Debug, Default)]
+struct LspServer {
+    documents: HashMap<Url, Arc<Document>>,
+    next_file_id: u32,
+    /// Holds the text of every open document
+    db: Database,
+    /// Cancels the work of a snapshot
+    cancellation: CancellationToken,
+}
+
+impl LspServer {
+    /// The documents as they are now, for work observing `token`
+    fn snapshot(&self, token: CancellationToken) -> LspServer {
+        LspServer {
+            documents: self.documents.clone(),
+            next_file_id: self.next_file_id,
+            db: Database::Snapshot(self.db.snapshot_with_cancellation(token.clone())),
+            cancellation: token,
+        }
+    }
+
+    /// Set the text of a document in the database
+    ///
+    /// Waits for the snapshots still checking the previous text, which stop
+    /// at their next item.
+    fn set_source(&mut self, file_id: FileId, text: &str) {
+        match &mut self.db {
+            Database::Owned(db) => db.set_source(file_id, text),
+            Database::Snapshot(_) => unreachable!("snapshots only answer requests"),
+        }
+    }
+
+    /// Type check `doc` unless the request is cancelled first
+    fn type_check(&self, doc: &Document) -> std::result::Result<CheckResult, String> {
+        self.db.check_result(doc.file_id).map_err(|cancelled| cancelled.to_string())
+    }
+
+    fn handle_request(&self, req: Request) -> Response {
+        let id = req.id.clone();
+        let response = self.dispatch_request(req);
+        if self.cancellation.is_timed_out() {
+            Response::new_err(id, ErrorCode::RequestFailed as i32, "Request timed out".to_string())
+        } else if self.cancellation.is_cancelled() {
+            Response::new_err(id, ErrorCode::RequestCanceled as i32, "Request cancelled".to_string())
+        } else {
+            response
+        }
+    }
+
+    fn dispatch_request(&self, req: Request) -> Response {
+        let id = req.id.clone();
+        match req.method.as_str() {
+            Rename::METHOD => dispatch(id, req.params, |params| self.rename(params)),
+            PrepareRenameRequest::METHOD => dispatch(id, req.params, |params| self.prepare_rename(params)),
+            References::METHOD => dispatch(id, req.params, |params| self.references(params)),
+            SemanticTokensFullRequest::METHOD => dispatch(id, req.params, |params| self.semantic_tokens_full(params)),
+            CodeActionRequest::METHOD => dispatch(id, req.params, |params| self.code_actions(params)),
+            HoverRequest::METHOD => dispatch(id, req.params, |params| self.hover(params)),
+            Completion::METHOD => dispatch(id, req.params, |params| self.completion(params)),
+            _ => Response::new_err(
+                id,
+                ErrorCode::MethodNotFound as i32,
+                format!("Unhandled request method: {}", req.method),
+            ),
+        }
+    }
+
+    /// Apply a document notification, returning the document whose
+    /// diagnostics must be published again
+    fn handle_notification(&mut self, not: Notification) -> Option<Url> {
+        match not.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params = serde_json::from_value::<lsp_types::DidOpenTextDocumentParams>(not.params).ok()?;
+                let uri = params.text_document.uri;
+                self.open(uri.clone(), params.text_document.text);
+                Some(uri)
+            }
+            DidChangeTextDocument::METHOD => {
+                let params = serde_json::from_value::<lsp_types::DidChangeTextDocumentParams>(not.params).ok()?;
+                // Full sync: the last change carries the whole document
+                let change = params.content_changes.into_iter().last()?;
+                let uri = params.text_document.uri;
+                self.open(uri.clone(), change.text);
+                Some(uri)
+            }
+            DidCloseTextDocument::METHOD => {
+                let params = serde_json::from_value::<lsp_types::DidCloseTextDocumentParams>(not.params).ok()?;
+                let uri = params.text_document.uri;
+                if let Some(doc) = self.documents.remove(&uri) {
+                    self.set_source(doc.file_id, "");
+                }
+                // Clear whatever was published for the closed document
+                Some(uri)
+            }
+            _ => None,
+        }
+    }
+
+    fn publish_diagnostics(&self, uri: Url) -> Notification {
+        let diagnostics = self.documents.get(&uri)
+            .map(|doc| doc.diagnostics(&self.db))
+            .unwrap_or_default();
+        let params = PublishDiagnosticsParams { uri, diagnostics, version: None };
+        Notification::new(PublishDiagnostics::METHOD.to_string(), params)
+    }
+
+    fn open(&mut self, uri: Url, text: String) {
+        let file_id = match self.documents.get(&uri) {
+            Some(doc) => doc.file_id,
+            None => {
+                self.next_file_id += 1;
+                FileId::new(self.next_file_id)
+            }
+        };
+        self.set_source(file_id, &text);
+        self.documents.insert(uri, Arc::new(Document { lines: LineMap::new(&text), text, file_id }));
+    }
+
+    fn document(&self, uri: &Url) -> std::result::Result<&Document, String> {
+        self.documents.get(uri).map(Arc::as_ref).ok_or_else(|| format!("Document not open: {}", uri))
+    }
+
+    /// Check that the cursor is on a renameable symbol and report its range
+    fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> std::result::Result<Option<PrepareRenameResponse>, String> {
+        let doc = self.document(&params.text_document.uri)?;
+        let (Some(ast), Some(offset)) = (doc.parse(), doc.offset_at(params.position)) else {
+            return Ok(None);
+        };
+
+        let index = SymbolIndex::build(&ast, &doc.text);
+        let line_map = &doc.lines;
+        Ok(index.occurrence_at(offset)
+            .filter(|occ| !index.related(occ).is_empty())
+            .map(|occ| PrepareRenameResponse::Range(occ.span.to_lsp_range(line_map))))
+    }
+
+    /// Every reference to the symbol under the cursor across the open
+    /// documents, following imports between them
+    fn references(&self, params: ReferenceParams) -> std::result::Result<Option<Vec<Location>>, String> {
+        let position = params.text_document_position;
+        let doc = self.document(&position.text_document.uri)?;
+        let Some(offset) = doc.offset_at(position.position) else { return Ok(None) };
+
+        let parsed: Vec<(&Url, &Document, CompilationUnit)> = self.documents.iter()
+            .filter_map(|(uri, doc)| Some((uri, doc.as_ref(), doc.parse()?)))
+            .collect();
+        let scope: Vec<SourceFile> = parsed.iter()
+            .map(|(_, doc, unit)| SourceFile { unit, source: &doc.text })
+            .collect();
+        let Some(file) = parsed.iter().position(|(uri, ..)| **uri == position.text_document.uri) else {
+            return Ok(None);
+        };
+
+        let locations = references_at(&scope, file, offset).into_iter()
+            .filter(|reference| params.context.include_declaration || !reference.is_definition)
+            .filter_map(|reference| {
+                let (uri, doc, _) = parsed.iter().find(|(_, doc, _)| doc.file_id == reference.span.file_id)?;
+                Some(Location::new((*uri).clone(), reference.span.to_lsp_range(&doc.lines)))
+            })
+            .collect();
+        Ok(Some(locations))
+    }
+
+    /// Rename the symbol under the cursor using scope-aware resolution
+    fn rename(&self, params: RenameParams) -> std::result::Result<Option<WorkspaceEdit>, String> {
+        let uri = params.text_document_position.text_document.uri;
+        let doc = self.document(&uri)?;
+        let mut ast = doc.parse().ok_or("Document has syntax errors")?;
+        let offset = doc.offset_at(params.text_document_position.position)
+            .ok_or("Position is outside the document")?;
+
+        let result = rename_symbol(&mut ast, &doc.text, offset, &params.new_name)
+            .map_err(|e| e.to_string())?;
+
+        let line_map = &doc.lines;
+        let edits = result.locations.iter()
+            .map(|span| TextEdit {
+                range: span.to_lsp_range(line_map),
+                new_text: params.new_name.clone(),
+            })
+            .collect();
+
+        Ok(Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(uri, edits)])),
+            ..WorkspaceEdit::default()
+        }))
+    }
+}
+
+impl LspServer {
+    /// Quick fixes for the type errors overlapping the requested range
+    ///
+    /// Only fixes that can be expressed as text edits are offered.
+    fn code_actions(&self, params: CodeActionParams) -> std::result::Result<Option<CodeActionResponse>, String> {
+        let uri = params.text_document.uri;
+        let doc = self.document(&uri)?;
+        let Some(ast) = doc.parse() else {
+            return Ok(None);
+        };
+
+        let line_map = &doc.lines;
+        let requested = params.range;
+        let actions = self.type_check(doc)?.errors.iter()
+            .filter(|error| {
+                let range = error.span().to_lsp_range(line_map);
+                range.start <= requested.end && requested.start <= range.end
+            })
+            .filter_map(|error| {
+                let fix = suggest_fix(&ast, &doc.text, error).filter(QuickFix::is_textual)?;
+                let edits = fix.text_edits.iter()
+                    .map(|edit| TextEdit {
+                        range: edit.span.to_lsp_range(line_map),
+                        new_text: edit.new_text.clone(),
+                    })
+                    .collect();
+                Some(CodeActionOrCommand::CodeAction(CodeAction {
+                    title: fix.title,
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    diagnostics: Some(vec![type_diagnostic(error, DiagnosticSeverity::ERROR, line_map)]),
+                    edit: Some(WorkspaceEdit {
+                        changes: Some(HashMap::from([(uri.clone(), edits)])),
+                        ..WorkspaceEdit::default()
+                    }),
+                    is_preferred: Some(true),
+                    ..CodeAction::default()
+                }))
+            })
+            .collect();
+
+        Ok(Some(actions))
+    }
+
+    /// The type and purity of the top-level definition under the cursor
+    fn hover(&self, params: HoverParams) -> std::result::Result<Option<Hover>, String> {
+        let position = params.text_document_position_params;
+        let doc = self.document(&position.text_document.uri)?;
+        let (Some(ast), Some(offset)) = (doc.parse(), doc.offset_at(position.position)) else {
+            return Ok(None);
+        };
+
+        let index = SymbolIndex::build(&ast, &doc.text);
+        let Some(occ) = index.occurrence_at(offset) else { return Ok(None) };
+        if index.binding_kind(occ) != Some(BindingKind::Module) {
+            return Ok(None);
+        }
+        let Some(def) = ast.module.items.iter().find_map(|item| match item {
+            Item::ValueDef(def) if def.name == occ.name => Some(def),
+            _ => None,
+        }) else {
+            return Ok(None);
+        };
+
+        // Only the definition and what it depends on need checking
+        let check = self.db.definition_result(doc.file_id, def.name).map_err(|cancelled| cancelled.to_string())?;
+        let Some(check) = check else { return Ok(None) };
+        let Some(scheme) = check.inferred_types.get(&def.name) else { return Ok(None) };
+        let effects = check.performed_effects(def.name).unwrap_or_default();
+        let effects = effects.iter().map(|effect| effect.as_str()).collect::<Vec<_>>().join(", ");
+        let purity = match (&def.purity, effects.is_empty()) {
+            (Purity::Pure, true) => "Pure (declared)".to_string(),
+            (Purity::Pure, false) => format!("Declared pure, but performs {effects}"),
+            (_, true) => "Pure".to_string(),
+            (_, false) => format!("Performs {effects}"),
+        };
+
+        let line_map = &doc.lines;
+        Ok(Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: format!("```x\n{} : {}\n```\n{purity}", def.name, scheme.body),
+            }),
+            range: Some(occ.span.to_lsp_range(line_map)),
+        }))
+    }
+
+    /// The module's top-level values, with their types once checked;
+    /// deprecated ones are tagged, which editors show struck through
+    fn completion(&self, params: CompletionParams) -> std::result::Result<Option<CompletionResponse>, String> {
+        let doc = self.document(&params.text_document_position.text_document.uri)?;
+        let Some(ast) = doc.parse() else {
+            return Ok(None);
+        };
+
+        let check = self.type_check(doc)?;
+        let items = ast.module.items.iter()
+            .filter_map(|item| match item {
+                Item::ValueDef(def) => Some(def),
+                _ => None,
+            })
+            .map(|def| {
+                let deprecated = def.documentation.as_ref().and_then(|doc| doc.doc_comment.deprecation()).is_some();
+                CompletionItem {
+                    label: def.name.to_string(),
+                    kind: Some(match def.body {
+                        Expr::Lambda { .. } => CompletionItemKind::FUNCTION,
+                        _ => CompletionItemKind::VARIABLE,
+                    }),
+                    detail: check.inferred_types.get(&def.name).map(|scheme| scheme.body.to_string()),
+                    tags: deprecated.then(|| vec![CompletionItemTag::DEPRECATED]),
+                    ..CompletionItem::default()
+                }
+            })
+            .collect();
+        Ok(Some(CompletionResponse::Array(items)))
+    }
+
+    /// Classify identifiers using the parsed AST and the checker's results
+    fn semantic_tokens_full(
+        &self,
+        params: SemanticTokensParams,
+    ) -> std::result::Result<Option<SemanticTokensResult>, String> {
+        let doc = self.document(&params.text_document.uri)?;
+        let Some(ast) = doc.parse() else {
+            return Ok(None);
+        };
+
+        let check = self.type_check(doc)?;
+        let line_map = &doc.lines;
+
+        // Tokens are delta-encoded against the previous token's start
+        let mut data = Vec::new();
+        let (mut prev_line, mut prev_start) = (0, 0);
+        for token in semantic_tokens(&ast, &doc.text, &check) {
+            let start = line_map.offset_to_position(token.span.start);
+            let (line, column) = (start.line.as_u32(), start.column.as_u32());
+            let delta_start = if line == prev_line { column - prev_start } else { column };
+
+            let mut modifiers = 0;
+            if token.declaration {
+                modifiers |= MODIFIER_DECLARATION;
+            }
+            if token.effectful {
+                modifiers |= MODIFIER_EFFECTFUL;
+            }
+            if token.pure {
+                modifiers |= MODIFIER_PURE;
+            }
+
+            data.push(SemanticToken {
+                delta_line: line - prev_line,
+                delta_start,
+                length: token.span.len(),
+                token_type: token.kind.index(),
+                token_modifiers_bitset: modifiers,
+            });
+            (prev_line, prev_start) = (line, column);
+        }
+
+        Ok(Some(SemanticTokensResult::Tokens(SemanticTokens { result_id: None, data })))
+    }
+}
+
+/// A type error or warning, with its notes on the lines after the message
+fn type_diagnostic(error: &TypeError, severity: DiagnosticSeverity, line_map: &LineMap) -> Diagnostic {
+    let message = std::iter::once(error.to_string()).chain(error.notes()).collect::<Vec<_>>().join("\n");
+    Diagnostic {
+        range: error.span().to_lsp_range(line_map),
+        severity: Some(severity),
+        code: Some(NumberOrString::String(error.code().to_string())),
+        source: Some("x".to_string()),
+        message,
+        // Editors strike through uses of deprecated definitions
+        tags: matches!(error, TypeError::Deprecated { .. }).then(|| vec![DiagnosticTag::DEPRECATED]),
+        ..Diagnostic::default()
+    }
+}
+
+/// Deserialize params, run a handler, and wrap its outcome in a response
+fn dispatch<P, R>(
+    id: RequestId,
+    params: serde_json::Value,
+    handler: impl FnOnce(P) -> std::result::Result<R, String>,
+) -> Response
+where
+    P: serde::de::DeserializeOwned,
+    R: serde::Serialize,
+{
+    match serde_json::from_value(params) {
+        Ok(params) => match handler(params) {
+            Ok(result) => Response::new_ok(id, result),
+            Err(message) => Response::new_err(id, ErrorCode::RequestFailed as i32, message),
+        },
+        Err(e) => Response::new_err(id, ErrorCode::InvalidParams as i32, e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::{Position, TextDocumentIdentifier, WorkDoneProgressParams};
+
+    fn server_with(text: &str) -> (LspServer, Url) {
+        let uri = Url::parse("file:///test.x").unwrap();
+        let mut server = LspServer::default();
+        server.open(uri.clone(), text.to_string());
+        (server, uri)
+    }
+
+    #[test]
+    fn test_rename_produces_workspace_edit() {
+        let (server, uri) = server_with("module Test\nlet x = 1\nlet y = fun x -> x\nlet z = x");
+
+        let params = RenameParams {
+            text_document_position: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                position: Position { line: 3, character: 8 },
+            },
+            new_name: "w".to_string(),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+        };
+
+        let edit = server.rename(params).unwrap().unwrap();
+        let edits = &edit.changes.unwrap()[&uri];
+        let lines: Vec<u32> = edits.iter().map(|e| e.range.start.line).collect();
+        // The lambda parameter shadows `x`, so line 2 is untouched
+        assert_eq!(lines, vec![1, 3]);
+    }
+
+    #[test]
+    fn test_references_follow_imports_between_documents() {
+        let (mut server, math) = server_with("module Math\nlet square = fun x -> x * x");
+        let app = Url::parse("file:///app.x").unwrap();
+        server.open(app.clone(), "module App\nimport Math { square }\nlet a = square 2\nlet b = fun square -> square".to_string());
+
+        let params = |include_declaration| ReferenceParams {
+            text_document_position: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri: app.clone() },
+                position: Position { line: 2, character: 8 },
+            },
+            context: lsp_types::ReferenceContext { include_declaration },
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: lsp_types::PartialResultParams::default(),
+        };
+
+        let locations = server.references(params(true)).unwrap().unwrap();
+        let found: Vec<(&str, u32)> = locations.iter()
+            .map(|location| (location.uri.path(), location.range.start.line))
+            .collect();
+        // The lambda parameter on line 3 shadows the import
+        assert_eq!(found.len(), 3);
+        assert!(found.contains(&(math.path(), 1)) && found.contains(&("/app.x", 1)) && found.contains(&("/app.x", 2)));
+        assert_eq!(server.references(params(false)).unwrap().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_edits_recheck_only_the_items_they_change() {
+        let (mut server, uri) = server_with("module Test\nlet a = 1\nlet b = \"b\"\nlet c = b");
+        assert!(server.publish_diagnostics(uri.clone()).params["diagnostics"].as_array().unwrap().is_empty());
+        let checked = server.db.items_checked();
+
+        server.open(uri.clone(), "module Test\nlet a = 1\nlet b = \"b\"\nlet c = b + 1".to_string());
+        let diagnostics = server.publish_diagnostics(uri);
+        assert_eq!(diagnostics.params["diagnostics"].as_array().unwrap().len(), 1);
+        assert_eq!(server.db.items_checked(), checked + 1);
+    }
+
+    #[test]
+    fn test_hover_shows_type_and_purity() {
+        let (server, uri) = server_with("module Test\npure let id = fun x -> x\nlet y = id 1");
+        let hover = |line, character| {
+            let params = HoverParams {
+                text_document_position_params: TextDocumentPositionParams {
+                    text_document: TextDocumentIdentifier { uri: uri.clone() },
+                    position: Position { line, character },
+                },
+                work_done_progress_params: WorkDoneProgressParams::default(),
+            };
+            match server.hover(params).unwrap() {
+                Some(Hover { contents: HoverContents::Markup(markup), .. }) => markup.value,
+                other => panic!("expected markdown hover, got {other:?}"),
+            }
+        };
+
+        let id = hover(2, 8);
+        assert!(id.starts_with("```x\nid : "), "{id}");
+        assert!(id.ends_with("Pure (declared)"), "{id}");
+        assert!(hover(2, 4).starts_with("```x\ny : Int\n```\nPure"));
+    }
+
+    #[test]
+    fn test_semantic_tokens_are_delta_encoded() {
+        let (server, uri) = server_with("module Test\nlet id = fun x -> x\nlet y = id 1");
+
+        let params = SemanticTokensParams {
+            text_document: TextDocumentIdentifier { uri },
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: Default::default(),
+        };
+
+        let Some(SemanticTokensResult::Tokens(tokens)) = server.semantic_tokens_full(params).unwrap() else {
+            panic!("expected full token set");
+        };
+        let encoded: Vec<(u32, u32, u32, u32)> = tokens.data.iter()
+            .map(|t| (t.delta_line, t.delta_start, t.length, t.token_type))
+            .collect();
+
+        let function = SemanticTokenKind::Function.index();
+        let parameter = SemanticTokenKind::Parameter.index();
+        let variable = SemanticTokenKind::Variable.index();
+        assert_eq!(encoded, vec![
+            (1, 4, 2, function),   // id
+            (0, 9, 1, parameter),  // x
+            (0, 5, 1, parameter),  // x
+            (1, 4, 1, variable),   // y
+            (0, 4, 2, function),   // id
+        ]);
+    }
+
+    #[test]
+    fn test_diagnostics_report_every_syntax_error() {
+        let (server, uri) = server_with("module Test\nlet a = (1, )\nlet b = 2\nlet c = 0b12");
+
+        let notification = server.publish_diagnostics(uri);
+        let params: PublishDiagnosticsParams = serde_json::from_value(notification.params).unwrap();
+        let lines: Vec<u32> = params.diagnostics.iter().map(|d| d.range.start.line).collect();
+        assert_eq!(lines, vec![1, 3]);
+    }
+
+    #[test]
+    fn test_diagnostics_describe_holes() {
+        let (server, uri) = server_with("module Test\nlet zero = 0\nlet one : Int = _\nlet pair : (Int, ?) = (1, true)");
+
+        let notification = server.publish_diagnostics(uri);
+        let params: PublishDiagnosticsParams = serde_json::from_value(notification.params).unwrap();
+        let diagnostics: Vec<_> = params.diagnostics.iter().map(|d| (d.severity, d.message.as_str())).collect();
+        assert_eq!(diagnostics, vec![
+            (Some(DiagnosticSeverity::ERROR), "Found hole `_` of type Int\ncould be filled with: zero"),
+            (Some(DiagnosticSeverity::WARNING), "Type hole `?` stands for Bool\nwrite Bool in place of `?`"),
+        ]);
+    }
+
+    #[test]
+    fn test_deprecated_uses_are_tagged() {
+        let (server, uri) = server_with(
+            "module Test\n```\n---\n@deprecated \"use bar instead\"\n---\n```\nlet foo = 1\nlet bar = 2\nlet n = foo + bar",
+        );
+
+        let notification = server.publish_diagnostics(uri.clone());
+        let params: PublishDiagnosticsParams = serde_json::from_value(notification.params).unwrap();
+        let [diagnostic] = params.diagnostics.as_slice() else {
+            panic!("expected one diagnostic, got {:?}", params.diagnostics);
+        };
+        assert_eq!(diagnostic.message, "foo is deprecated\nuse bar instead");
+        assert_eq!(diagnostic.tags, Some(vec![DiagnosticTag::DEPRECATED]));
+        assert_eq!(diagnostic.range.start, Position { line: 8, character: 8 });
+
+        let params = CompletionParams {
+            text_document_position: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position: Position { line: 8, character: 8 },
+            },
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: lsp_types::PartialResultParams::default(),
+            context: None,
+        };
+        let Some(CompletionResponse::Array(items)) = server.completion(params).unwrap() else {
+            panic!("expected completion items");
+        };
+        let tagged: Vec<_> = items.iter().map(|item| (item.label.as_str(), item.tags.clone())).collect();
+        assert_eq!(tagged, [("foo", Some(vec![CompletionItemTag::DEPRECATED])), ("bar", None), ("n", None)]);
+        assert_eq!(items[0].detail.as_deref(), Some("Int"));
+    }
+
+    #[test]
+    fn test_code_action_offers_quick_fix() {
+        let (server, uri) = server_with("module Test\nlet count = 1\nlet next = fun x -> cuont + x");
+
+        let notification = server.publish_diagnostics(uri.clone());
+        let params: PublishDiagnosticsParams = serde_json::from_value(notification.params).unwrap();
+        assert_eq!(params.diagnostics[0].code, Some(NumberOrString::String("E0102".to_string())));
+
+        let position = Position { line: 2, character: 21 };
+        let params = CodeActionParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            range: lsp_types::Range { start: position, end: position },
+            context: lsp_types::CodeActionContext::default(),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: lsp_types::PartialResultParams::default(),
+        };
+        let actions = server.code_actions(params).unwrap().unwrap();
+        let [CodeActionOrCommand::CodeAction(action)] = actions.as_slice() else {
+            panic!("expected one code action, got {actions:?}");
+        };
+        assert_eq!(action.title, "replace `cuont` with `count`");
+        let edits = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        assert_eq!(edits[0].new_text, "count");
+        assert_eq!(edits[0].range.start, Position { line: 2, character: 20 });
+    }
+
+    #[test]
+    fn test_prepare_rename_on_unresolved_name() {
+        let (server, uri) = server_with("module Test\nlet y = print 1");
+
+        let params = TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier { uri },
+            position: Position { line: 1, character: 9 },
+        };
+
+        assert!(server.prepare_rename(params).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_cancelled_requests_answer_request_canceled() {
+        let (server, uri) = server_with("module Test\nlet y = 1");
+        let token = CancellationToken::new();
+        token.cancel();
+        let hover = Request::new(RequestId::from(1), HoverRequest::METHOD.to_string(), serde_json::json!({
+            "textDocument": { "uri": uri },
+            "position": { "line": 1, "character": 4 },
+        }));
+
+        let response = server.snapshot(token).handle_request(hover.clone());
+        assert_eq!(response.error.map(|error| error.code), Some(ErrorCode::RequestCanceled as i32));
+        let response = server.snapshot(CancellationToken::new().with_timeout(Duration::ZERO)).handle_request(hover.clone());
+        assert_eq!(response.error.map(|error| error.message), Some("Request timed out".to_string()));
+        assert!(server.handle_request(hover).error.is_none());
+    }
+
+    #[test]
+    fn test_edits_cancel_work_on_the_previous_text() {
+        let (mut server, uri) = server_with("module Test\nlet y = 1");
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let sender = Mutex::new(sender);
+        let mut scheduler = Scheduler::new(move |message| sender.lock().unwrap().send(message).unwrap());
+
+        let request = CancellationToken::new();
+        scheduler.requests.lock().unwrap().insert(RequestId::from(7), (Some(uri.clone()), request.clone()));
+        scheduler.document_changed(&server, uri.clone());
+        let first = scheduler.diagnostics[&uri].clone();
+        assert!(request.is_cancelled());
+
+        server.open(uri.clone(), "module Test\nlet y = undefined_name".to_string());
+        scheduler.document_changed(&server, uri.clone());
+        assert!(first.is_cancelled() && !scheduler.diagnostics[&uri].is_cancelled());
+
+        // The first run may or may not have published before it was
+        // cancelled, but the last diagnostics are those of the new text
+        let mut last = None;
+        while let Ok(Message::Notification(not)) = receiver.recv_timeout(Duration::from_secs(5)) {
+            let params: PublishDiagnosticsParams = serde_json::from_value(not.params).unwrap();
+            last = Some(params.diagnostics.len());
+            if last == Some(1) {
+                break;
+            }
+        }
+        assert_eq!(last, Some(1));
+
+        let cancelled = CancellationToken::new();
+        scheduler.requests.lock().unwrap().insert(RequestId::from("a".to_string()), (None, cancelled.clone()));
+        scheduler.cancel(Notification::new(Cancel::METHOD.to_string(), serde_json::json!({ "id": "a" })));
+        assert!(cancelled.is_cancelled());
+    }
+}
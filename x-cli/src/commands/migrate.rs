@@ -0,0 +1,344 @@
+//! Migrate command - convert a whole project to a different syntax style or edition
+//!
+//! Every textual source under the project directory is parsed, rewritten by
+//! the code refiner into the idioms of the target edition, and printed in the
+//! target syntax style; comments are kept. The resulting changes are written
+//! as one reviewable edit script per file, headed by the refiner's notes;
+//! `--apply` additionally rewrites the sources in place, renaming files whose
+//! extension names their old style.
+//!
+//! Only the styles x-parser can both parse and print are targets, so
+//! `--to rustic` is refused until a Rust-like syntax exists.
+
+use anyhow::{Result, Context, bail};
+use clap::Args;
+use colored::*;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use x_ai_codegen::refiner::{deprecated_replacements, CodeRefiner, Edition};
+use x_parser::{CompilationUnit, FileId, Trivia};
+use x_parser::syntax::{MultiSyntax, SyntaxConfig, SyntaxStyle};
+use crate::commands::fmt::{discover_sources, syntax_of};
+
+/// Directory (relative to the project root) that receives edit scripts
+const SCRIPT_DIR: &str = ".x-migrate";
+
+/// Convert a project directory to a different syntax style or edition
+#[derive(Debug, Args)]
+pub struct MigrateArgs {
+    /// Project directory
+    #[arg(default_value = ".")]
+    path: PathBuf,
+
+    /// Target syntax style (`sexp` or `ocaml`); files keep their style by default
+    #[arg(long)]
+    to: Option<String>,
+
+    /// Target edition (`current` or `next`)
+    #[arg(long)]
+    edition: Option<String>,
+
+    /// Only migrate files written in this syntax style
+    #[arg(long)]
+    from: Option<String>,
+
+    /// Rewrite source files in place instead of only emitting edit scripts
+    #[arg(long)]
+    apply: bool,
+}
+
+/// A source file, parsed
+struct ParsedSource {
+    path: PathBuf,
+    style: SyntaxStyle,
+    source: String,
+    unit: CompilationUnit,
+    trivia: Trivia,
+}
+
+/// Migration outcome for a single file
+#[derive(Debug)]
+struct FileMigration {
+    path: PathBuf,
+    /// Where the migrated source goes, which differs from `path` when the
+    /// file name names its syntax style
+    target: PathBuf,
+    script: Option<String>,
+    converted: String,
+}
+
+pub async fn run(args: MigrateArgs) -> Result<()> {
+    let to = args.to.as_deref().map(parse_style).transpose()?;
+    let from = args.from.as_deref().map(parse_style).transpose()?;
+    let edition: Edition = args.edition.as_deref().map(str::parse).transpose()?.unwrap_or_default();
+    if to.is_none() && args.edition.is_none() {
+        bail!("Nothing to migrate to: pass --to and/or --edition");
+    }
+
+    if !args.path.is_dir() {
+        bail!("Not a project directory: {}", args.path.display());
+    }
+
+    let mut files = Vec::new();
+    for path in discover_sources(&args.path)? {
+        let Some(style) = syntax_of(&path)? else { continue };
+        if from.is_none_or(|from| from == style) {
+            files.push((path, style));
+        }
+    }
+    if files.is_empty() {
+        println!("No source files found in {}", args.path.display());
+        return Ok(());
+    }
+
+    // Convert everything up front so a single failure leaves the project untouched
+    let mut syntax = MultiSyntax::default();
+    let mut parsed = Vec::new();
+    for (i, (path, style)) in files.into_iter().enumerate() {
+        let source = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read file: {}", path.display()))?;
+        let (unit, trivia) = syntax.parse_with_trivia(&source, style, FileId::new(i as u32))
+            .with_context(|| format!("Failed to parse: {}", path.display()))?;
+        parsed.push(ParsedSource { path, style, source, unit, trivia });
+    }
+
+    // A value deprecated in one module gives way to its replacement in all of them
+    let replacements: HashMap<_, _> = parsed.iter()
+        .flat_map(|file| deprecated_replacements(&file.unit))
+        .collect();
+    let refiner = CodeRefiner::new();
+    let mut migrations = Vec::new();
+    for file in &parsed {
+        let relative = file.path.strip_prefix(&args.path).unwrap_or(&file.path);
+        migrations.push(migrate_file(&syntax, &refiner, file, relative, to, edition, &replacements)?);
+    }
+    for migration in &migrations {
+        if migration.target != migration.path && migration.target.exists() {
+            bail!(
+                "Cannot migrate {}: {} already exists",
+                migration.path.display(),
+                migration.target.display(),
+            );
+        }
+    }
+
+    let script_root = args.path.join(SCRIPT_DIR);
+    let mut changed = 0;
+    for migration in &migrations {
+        let relative = migration.path.strip_prefix(&args.path).unwrap_or(&migration.path);
+        let Some(script) = &migration.script else {
+            println!("  {} {}", "unchanged".dimmed(), relative.display());
+            continue;
+        };
+        changed += 1;
+
+        let script_path = script_root.join(relative).with_extension("x.edit");
+        if let Some(parent) = script_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&script_path, script)
+            .with_context(|| format!("Failed to write edit script: {}", script_path.display()))?;
+
+        if args.apply {
+            fs::write(&migration.target, &migration.converted)
+                .with_context(|| format!("Failed to rewrite: {}", migration.target.display()))?;
+            if migration.target != migration.path {
+                fs::remove_file(&migration.path)
+                    .with_context(|| format!("Failed to remove: {}", migration.path.display()))?;
+            }
+        }
+
+        println!("  {} {}", "migrated".green(), relative.display());
+    }
+
+    let style = to.map_or_else(|| "own style".to_string(), |to| to.to_string());
+    println!(
+        "\n{} of {} files changed ({style}, {edition} edition); edit scripts in {}",
+        changed.to_string().cyan(),
+        migrations.len(),
+        script_root.display(),
+    );
+    if !args.apply && changed > 0 {
+        println!("Review the scripts, then re-run with {} to rewrite the sources", "--apply".bold());
+    }
+
+    Ok(())
+}
+
+/// A syntax style x-parser can both parse and print
+fn parse_style(name: &str) -> Result<SyntaxStyle> {
+    name.parse().with_context(|| format!(
+        "Unsupported syntax style: {name} (supported styles are {})",
+        ["sexp", "ocaml"].join(", "),
+    ))
+}
+
+/// Modernize and convert a single file, and compute its edit script
+fn migrate_file(
+    syntax: &MultiSyntax,
+    refiner: &CodeRefiner,
+    file: &ParsedSource,
+    relative: &Path,
+    to: Option<SyntaxStyle>,
+    edition: Edition,
+    replacements: &HashMap<x_parser::Symbol, x_parser::Symbol>,
+) -> Result<FileMigration> {
+    let modernized = refiner.modernize(&file.unit, &file.source, edition, replacements)
+        .with_context(|| format!("Failed to modernize: {}", file.path.display()))?;
+
+    let style = to.unwrap_or(file.style);
+    let config = SyntaxConfig { style, ..SyntaxConfig::default() };
+    let mut converted = syntax.print_with_trivia(&modernized.unit, &file.trivia, &config)
+        .with_context(|| format!("Failed to convert: {}", file.path.display()))?;
+    if !converted.ends_with('\n') {
+        converted.push('\n');
+    }
+
+    let target = target_path(&file.path, file.style, style);
+    let target_relative = target_path(relative, file.style, style);
+    let script = (converted != file.source || target != file.path).then(|| {
+        let notes: String = modernized.notes.iter().map(|note| format!("# {note}\n")).collect();
+        notes + &edit_script_between(
+            &relative.display().to_string(),
+            &target_relative.display().to_string(),
+            &file.source,
+            &converted,
+        )
+    });
+
+    Ok(FileMigration {
+        path: file.path.clone(),
+        target,
+        script,
+        converted,
+    })
+}
+
+/// Where a file in syntax style `from` goes once converted to `to`
+///
+/// S-expression sources say so in their extension, which must change with
+/// the style; other textual sources are plain `.x` files.
+fn target_path(path: &Path, from: SyntaxStyle, to: SyntaxStyle) -> PathBuf {
+    if from == to {
+        return path.to_path_buf();
+    }
+    let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let lower = name.to_lowercase();
+    let stem_len = [".lisp.x", ".sexp.x", ".x"].iter()
+        .find(|extension| lower.ends_with(*extension))
+        .map_or(name.len(), |extension| name.len() - extension.len());
+    let extension = match to {
+        SyntaxStyle::SExp => "sexp.x",
+        SyntaxStyle::OCaml => "x",
+    };
+    path.with_file_name(format!("{}.{extension}", &name[..stem_len]))
+}
+
+/// Render a line-based edit script in unified diff notation
+pub(crate) fn edit_script(name: &str, old: &str, new: &str) -> String {
+    edit_script_between(name, name, old, new)
+}
+
+/// Render an edit script that also moves the file from `old_name` to `new_name`
+fn edit_script_between(old_name: &str, new_name: &str, old: &str, new: &str) -> String {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+
+    // Longest common subsequence table, filled from the end
+    let (n, m) = (old_lines.len(), new_lines.len());
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if old_lines[i] == new_lines[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut script = format!("--- {old_name}\n+++ {new_name}\n");
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && old_lines[i] == new_lines[j] {
+            script.push_str(&format!(" {}\n", old_lines[i]));
+            i += 1;
+            j += 1;
+        } else if i < n && (j == m || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            script.push_str(&format!("-{}\n", old_lines[i]));
+            i += 1;
+        } else {
+            script.push_str(&format!("+{}\n", new_lines[j]));
+            j += 1;
+        }
+    }
+
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn args(path: &Path, to: Option<&str>, edition: Option<&str>, apply: bool) -> MigrateArgs {
+        MigrateArgs {
+            path: path.to_path_buf(),
+            to: to.map(str::to_string),
+            edition: edition.map(str::to_string),
+            from: None,
+            apply,
+        }
+    }
+
+    #[test]
+    fn test_edit_script() {
+        let script = edit_script("a.sexp.x", "one\ntwo\nthree\n", "one\n2\nthree\n");
+        assert_eq!(script, "--- a.sexp.x\n+++ a.sexp.x\n one\n-two\n+2\n three\n");
+    }
+
+    #[test]
+    fn test_target_path() {
+        let path = Path::new("src/main.sexp.x");
+        assert_eq!(target_path(path, SyntaxStyle::SExp, SyntaxStyle::SExp), path);
+        assert_eq!(target_path(path, SyntaxStyle::SExp, SyntaxStyle::OCaml), Path::new("src/main.x"));
+        assert_eq!(target_path(Path::new("lib.x"), SyntaxStyle::OCaml, SyntaxStyle::SExp), Path::new("lib.sexp.x"));
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_target() {
+        let temp_dir = TempDir::new().unwrap();
+        let error = run(args(temp_dir.path(), Some("rustic"), None, false)).await.unwrap_err();
+        assert!(format!("{error:#}").contains("supported styles are sexp, ocaml"), "{error:#}");
+
+        assert!(run(args(temp_dir.path(), None, None, false)).await.is_err());
+        assert!(run(args(temp_dir.path(), None, Some("2099"), false)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_migrate_to_next_edition() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/main.x"), x_parser::binary::MAGIC_NUMBER).unwrap();
+        fs::write(
+            root.join("src/lib.x"),
+            "module Lib\n```\n---\n@deprecated use `repeat`\n---\n```\nlet replicate = fun n x -> x\n\nlet repeat = fun n x -> x\n",
+        ).unwrap();
+        let app = "module App\n\n-- Copies\nlet copies = replicate 3 1\n";
+        fs::write(root.join("src/app.x"), app).unwrap();
+
+        run(args(root, None, Some("next"), false)).await.unwrap();
+        assert_eq!(fs::read_to_string(root.join("src/app.x")).unwrap(), app);
+        let script = fs::read_to_string(root.join(SCRIPT_DIR).join("src/app.x.edit")).unwrap();
+        assert!(script.starts_with("# replaced 1 use(s) of deprecated `replicate` with `repeat`\n--- src/app.x\n"), "{script}");
+        assert!(script.contains("-let copies = replicate 3 1\n+let copies = repeat 3 1\n"), "{script}");
+
+        run(args(root, Some("sexp"), Some("next"), true)).await.unwrap();
+        assert!(!root.join("src/app.x").exists());
+        let migrated = fs::read_to_string(root.join("src/app.sexp.x")).unwrap();
+        assert!(migrated.contains("Copies") && migrated.contains("repeat"), "{migrated}");
+        assert!(root.join("src/main.x").exists());
+    }
+}
@@ -13,6 +13,7 @@ pub mod check;
 pub mod compile;
 pub mod repl;
 pub mod lsp;
+pub mod agent;
 pub mod stats;
 pub mod test;
 pub mod test_helpers;
@@ -23,21 +24,29 @@ pub mod imports;
 pub mod outdated;
 pub mod namespace;
 pub mod namespace_cli;
+pub mod namespace_remote;
 pub mod shell;
+pub mod migrate;
+pub mod fmt;
+pub mod diff;
+pub mod lint;
+pub mod bench;
+pub mod completions;
 
 // Re-export command functions
 pub use new::new_command;
 pub use convert::convert_command;
 pub use show::show_command;
-pub use query::query_command;
-pub use edit::edit_command;
+pub use query::{query_command, references_command};
+pub use edit::{edit_command, extract_command};
 // pub use rename::rename_command;
 pub use extract::ExtractArgs;
 pub use check::check_command;
-pub use compile::compile_command;
+pub use compile::{compile_command, ProfileOptions};
 pub use repl::repl_command;
 pub use lsp::lsp_command;
-pub use stats::stats_command;
-pub use test::test_command;
+pub use agent::serve_command;
+pub use stats::{call_graph_command, stats_command};
+pub use test::{coverage_command, doctest_command, mutation_command, test_command};
 pub use doc::DocCommand;
 pub use namespace_cli::{NamespaceCommand, namespace_command};
\ No newline at end of file
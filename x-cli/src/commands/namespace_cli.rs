@@ -1,6 +1,14 @@
 use clap::{Parser, Subcommand};
-use std::path::PathBuf;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use x_editor::content_addressing::ContentRepository;
+use x_editor::namespace::{Namespace, NamespacePath};
+use x_editor::namespace_semver::{diff, SemverDiff};
+use x_editor::namespace_storage::NamespaceStorage;
+use x_editor::namespace_sync::{pull, push, SyncReport};
+use x_parser::versioning::Version;
 use crate::commands::namespace::NamespaceManager;
+use crate::commands::namespace_remote::{serve, HttpRemote};
 use crate::commands::shell::NamespaceShell;
 
 #[derive(Parser, Debug)]
@@ -46,6 +54,98 @@ enum NamespaceSubcommand {
         /// Source directory
         path: PathBuf,
     },
+    /// Send a namespace and the definitions the remote lacks
+    Push {
+        /// Remote store URL (http://host:port)
+        url: String,
+        #[command(flatten)]
+        sync: SyncArgs,
+    },
+    /// Fetch a namespace and the definitions missing locally
+    Pull {
+        /// Remote store URL (http://host:port)
+        url: String,
+        #[command(flatten)]
+        sync: SyncArgs,
+    },
+    /// Compare two saved versions of a namespace
+    Diff {
+        /// Old version, by label or hash prefix
+        old: String,
+        /// New version, by label or hash prefix
+        new: String,
+        /// Classify the changes as a patch, minor or major release
+        #[arg(long)]
+        semver: bool,
+        #[command(flatten)]
+        sync: SyncArgs,
+    },
+    /// Serve the local store to `push` and `pull`
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:9258")]
+        addr: String,
+        /// Local namespace store
+        #[arg(long, default_value = ".x-namespaces")]
+        store: PathBuf,
+    },
+}
+
+#[derive(clap::Args, Debug)]
+struct SyncArgs {
+    /// Namespace to use, e.g. `Data.List` (default: root)
+    #[arg(long, default_value = "")]
+    namespace: String,
+    /// Local namespace store
+    #[arg(long, default_value = ".x-namespaces")]
+    store: PathBuf,
+}
+
+fn open_store(path: &Path) -> anyhow::Result<NamespaceStorage> {
+    NamespaceStorage::new(path.to_path_buf(), ContentRepository::new())
+}
+
+/// A saved version of the namespace at `path`, by label or hash prefix
+fn load_version(storage: &mut NamespaceStorage, path: &NamespacePath, spec: &str) -> anyhow::Result<(String, Namespace)> {
+    let version = storage.get_versions(path).into_iter()
+        .find(|version| version.version == spec || version.hash.0.starts_with(spec))
+        .ok_or_else(|| anyhow::anyhow!("No version '{spec}' of namespace '{}'", path.to_string()))?;
+    let namespace = storage.load_namespace_version(path, &version.version)?;
+    Ok((version.version, namespace))
+}
+
+/// Print the changes between two versions, with the release they call for
+fn report_diff(old: &str, diff: &SemverDiff, semver: bool) {
+    if semver {
+        match Version::parse(old) {
+            Some(version) => println!("{}: {old} -> {}", diff.bump, diff.bump.apply(&version)),
+            None => println!("{}", diff.bump),
+        }
+    }
+    for change in &diff.changes {
+        if semver {
+            println!("  {} ({}): {}", change.name, change.bump, change.reason);
+        } else {
+            println!("  {}: {}", change.name, change.reason);
+        }
+    }
+}
+
+/// Print a sync report, failing if conflicts kept it from being applied
+fn report_sync(action: &str, namespace: &str, report: SyncReport) -> anyhow::Result<()> {
+    if !report.conflicts.is_empty() {
+        for conflict in &report.conflicts {
+            eprintln!("conflict: {} is {} locally but {} remotely", conflict.name, conflict.local.short(), conflict.remote.short());
+        }
+        anyhow::bail!("{} conflicting names; nothing was {}", report.conflicts.len(), action.to_lowercase());
+    }
+    let namespace = if namespace.is_empty() { "/" } else { namespace };
+    println!(
+        "{action} {namespace}: {} definitions transferred, {} already present",
+        report.transferred,
+        report.deduplicated,
+    );
+    Ok(())
 }
 
 pub fn namespace_command(cmd: NamespaceCommand) -> anyhow::Result<()> {
@@ -100,6 +200,29 @@ pub fn namespace_command(cmd: NamespaceCommand) -> anyhow::Result<()> {
             mgr.import(&path).map_err(|e| anyhow::anyhow!(e))?;
             println!("Imported from {}", path.display());
         }
+        Some(NamespaceSubcommand::Push { url, sync }) => {
+            let mut local = open_store(&sync.store)?;
+            let report = push(&mut local, &mut HttpRemote::new(&url)?, &NamespacePath::from_str(&sync.namespace))?;
+            report_sync("Pushed", &sync.namespace, report)?;
+        }
+        Some(NamespaceSubcommand::Pull { url, sync }) => {
+            let mut local = open_store(&sync.store)?;
+            let report = pull(&mut local, &mut HttpRemote::new(&url)?, &NamespacePath::from_str(&sync.namespace))?;
+            report_sync("Pulled", &sync.namespace, report)?;
+        }
+        Some(NamespaceSubcommand::Diff { old, new, semver, sync }) => {
+            let mut storage = open_store(&sync.store)?;
+            let path = NamespacePath::from_str(&sync.namespace);
+            let (old_label, old) = load_version(&mut storage, &path, &old)?;
+            let (_, new) = load_version(&mut storage, &path, &new)?;
+            report_diff(&old_label, &diff(&old, &new), semver);
+        }
+        Some(NamespaceSubcommand::Serve { addr, store }) => {
+            let mut storage = open_store(&store)?;
+            let listener = TcpListener::bind(&addr)?;
+            println!("Serving {} on http://{addr}", store.display());
+            serve(&mut storage, listener)?;
+        }
     }
     
     Ok(())
@@ -117,4 +240,45 @@ mod tests {
             _ => panic!("Expected Pwd command"),
         }
     }
+
+    #[test]
+    fn test_sync_command_parsing() {
+        let cmd = NamespaceCommand::parse_from(["namespace", "push", "http://localhost:9258", "--namespace", "Data.List"]);
+        match cmd.command {
+            Some(NamespaceSubcommand::Push { url, sync }) => {
+                assert_eq!(url, "http://localhost:9258");
+                assert_eq!(sync.namespace, "Data.List");
+                assert_eq!(sync.store, PathBuf::from(".x-namespaces"));
+            }
+            _ => panic!("Expected Push command"),
+        }
+    }
+
+    #[test]
+    fn test_diff_loads_saved_versions() {
+        use x_editor::content_addressing::ContentHash;
+        use x_editor::namespace::Visibility;
+        use x_editor::namespace_semver::VersionBump;
+        use x_parser::Symbol;
+
+        let cmd = NamespaceCommand::parse_from(["namespace", "diff", "--semver", "1.0.0", "1.1.0"]);
+        assert!(matches!(cmd.command, Some(NamespaceSubcommand::Diff { semver: true, .. })));
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut storage = open_store(dir.path()).unwrap();
+        let path = NamespacePath::from_str("App");
+        let mut namespace = Namespace::new(path.clone());
+        namespace.metadata.version = Some("1.0.0".to_string());
+        namespace.add_value(Symbol::intern("one"), ContentHash::new(b"1"), None, Visibility::Public);
+        storage.save_namespace(&namespace).unwrap();
+        namespace.metadata.version = Some("1.1.0".to_string());
+        namespace.add_value(Symbol::intern("two"), ContentHash::new(b"2"), None, Visibility::Public);
+        let hash = storage.save_namespace(&namespace).unwrap();
+
+        let (label, old) = load_version(&mut storage, &path, "1.0.0").unwrap();
+        let (_, new) = load_version(&mut storage, &path, hash.short()).unwrap();
+        assert_eq!(label, "1.0.0");
+        assert_eq!(diff(&old, &new).bump, VersionBump::Minor);
+        assert!(load_version(&mut storage, &path, "2.0.0").is_err());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,279 @@
+//! Namespace synchronization over HTTP
+//!
+//! `x namespace serve` serves a namespace store, which `x namespace push`
+//! and `pull` reach at a `http://host:port` URL. Bodies are JSON:
+//!
+//! - `GET /namespaces/<path>`: the namespace, or 404
+//! - `PUT /namespaces/<path>`: store a namespace
+//! - `POST /content/missing`: which of the listed hashes the store lacks
+//! - `POST /content/fetch`: the definitions with the listed hashes
+//! - `PUT /content`: store definitions
+//!
+//! Only plain HTTP/1.1 with `Content-Length` bodies is spoken; put the
+//! server behind a proxy for TLS.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+use x_editor::content_addressing::{ContentEntry, ContentHash};
+use x_editor::namespace::{Namespace, NamespacePath};
+use x_editor::namespace_storage::NamespaceStorage;
+use x_editor::namespace_sync::NamespaceRemote;
+
+/// Largest request or response body accepted
+const MAX_BODY: usize = 64 * 1024 * 1024;
+
+/// How long the server waits on a silent client
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A store served over HTTP
+pub struct HttpRemote {
+    /// `host:port` to connect to
+    address: String,
+    /// Path the store is served under, without a trailing slash
+    prefix: String,
+}
+
+impl HttpRemote {
+    pub fn new(url: &str) -> Result<Self> {
+        let rest = url.strip_prefix("http://")
+            .ok_or_else(|| anyhow!("Unsupported remote URL {url}: only http:// is supported"))?;
+        let (address, prefix) = rest.split_once('/').map_or((rest, ""), |(address, prefix)| (address, prefix));
+        let address = if address.contains(':') { address.to_string() } else { format!("{address}:80") };
+        let prefix = prefix.trim_end_matches('/');
+        let prefix = if prefix.is_empty() { String::new() } else { format!("/{prefix}") };
+        Ok(Self { address, prefix })
+    }
+
+    fn request(&self, method: &str, path: &str, body: &[u8]) -> Result<(u16, Vec<u8>)> {
+        let mut stream = TcpStream::connect(&self.address)
+            .with_context(|| format!("Failed to connect to {}", self.address))?;
+        let host = self.address.trim_end_matches(":80");
+        write!(
+            stream,
+            "{method} {}{path} HTTP/1.1\r\nHost: {host}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.prefix,
+            body.len(),
+        )?;
+        stream.write_all(body)?;
+        let (status_line, body) = read_message(&mut BufReader::new(stream))?;
+        let status = status_line.split_whitespace().nth(1)
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(|| anyhow!("Malformed response from {}: {status_line}", self.address))?;
+        Ok((status, body))
+    }
+
+    /// Send a request whose successful response is JSON
+    fn call<T: serde::de::DeserializeOwned>(&self, method: &str, path: &str, body: &impl serde::Serialize) -> Result<T> {
+        let (status, response) = self.request(method, path, &serde_json::to_vec(body)?)?;
+        if status != 200 {
+            bail!("{method} {path} failed with status {status}: {}", String::from_utf8_lossy(&response));
+        }
+        Ok(serde_json::from_slice(&response)?)
+    }
+}
+
+impl NamespaceRemote for HttpRemote {
+    fn fetch_namespace(&mut self, path: &NamespacePath) -> Result<Option<Namespace>> {
+        let url = format!("/namespaces/{}", path.to_string());
+        match self.request("GET", &url, &[])? {
+            (200, body) => Ok(Some(serde_json::from_slice(&body)?)),
+            (404, _) => Ok(None),
+            (status, body) => bail!("GET {url} failed with status {status}: {}", String::from_utf8_lossy(&body)),
+        }
+    }
+
+    fn store_namespace(&mut self, namespace: &Namespace) -> Result<()> {
+        self.call("PUT", &format!("/namespaces/{}", namespace.path.to_string()), namespace)
+    }
+
+    fn missing_content(&mut self, hashes: &[ContentHash]) -> Result<Vec<ContentHash>> {
+        self.call("POST", "/content/missing", &hashes)
+    }
+
+    fn fetch_content(&mut self, hashes: &[ContentHash]) -> Result<Vec<ContentEntry>> {
+        self.call("POST", "/content/fetch", &hashes)
+    }
+
+    fn store_content(&mut self, entries: &[ContentEntry]) -> Result<()> {
+        self.call("PUT", "/content", &entries)
+    }
+}
+
+/// Serve `storage` to pushing and pulling clients, one request at a time
+pub fn serve(storage: &mut NamespaceStorage, listener: TcpListener) -> Result<()> {
+    for stream in listener.incoming() {
+        if let Err(error) = stream.map_err(Into::into).and_then(|stream| handle_connection(storage, stream)) {
+            eprintln!("namespace server: {error:#}");
+        }
+    }
+    Ok(())
+}
+
+fn handle_connection(storage: &mut NamespaceStorage, stream: TcpStream) -> Result<()> {
+    // One request at a time, so a client that stops sending must not stall the rest
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    stream.set_write_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream);
+    let (request_line, body) = read_message(&mut reader)?;
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let (status, response) = match respond(storage, method, path, &body) {
+        Ok(Some(response)) => (200, response),
+        Ok(None) => (404, b"not found".to_vec()),
+        Err(error) => (400, format!("{error:#}").into_bytes()),
+    };
+    let reason = match status {
+        200 => "OK",
+        404 => "Not Found",
+        _ => "Bad Request",
+    };
+    let mut stream = reader.into_inner();
+    write!(stream, "HTTP/1.1 {status} {reason}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", response.len())?;
+    stream.write_all(&response)?;
+    Ok(())
+}
+
+/// The JSON response to a request, or `None` for an unknown resource
+fn respond(storage: &mut NamespaceStorage, method: &str, path: &str, body: &[u8]) -> Result<Option<Vec<u8>>> {
+    let response = match (method, path) {
+        ("GET", _) if path.starts_with("/namespaces/") => {
+            let path = namespace_path(&path["/namespaces/".len()..])?;
+            match storage.fetch_namespace(&path)? {
+                Some(namespace) => serde_json::to_vec(&namespace)?,
+                None => return Ok(None),
+            }
+        }
+        ("PUT", _) if path.starts_with("/namespaces/") => {
+            let namespace: Namespace = serde_json::from_slice(body)?;
+            if namespace.path != namespace_path(&path["/namespaces/".len()..])? {
+                bail!("namespace {} sent to {path}", namespace.path.to_string());
+            }
+            storage.store_namespace(&namespace)?;
+            serde_json::to_vec(&())?
+        }
+        ("POST", "/content/missing") => {
+            let hashes: Vec<ContentHash> = serde_json::from_slice(body)?;
+            serde_json::to_vec(&storage.missing_content(&hashes)?)?
+        }
+        ("POST", "/content/fetch") => {
+            let hashes: Vec<ContentHash> = serde_json::from_slice(body)?;
+            serde_json::to_vec(&storage.fetch_content(&hashes)?)?
+        }
+        ("PUT", "/content") => {
+            let entries: Vec<ContentEntry> = serde_json::from_slice(body)?;
+            NamespaceRemote::store_content(storage, &entries)?;
+            serde_json::to_vec(&())?
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(response))
+}
+
+/// The namespace a request path names, if every segment is an identifier
+fn namespace_path(path: &str) -> Result<NamespacePath> {
+    let namespace = NamespacePath::from_str(path);
+    if !namespace.is_valid() {
+        bail!("invalid namespace path {path}");
+    }
+    Ok(namespace)
+}
+
+/// The start line and body of an HTTP message
+fn read_message(reader: &mut impl BufRead) -> Result<(String, Vec<u8>)> {
+    let mut start_line = String::new();
+    reader.read_line(&mut start_line)?;
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().context("Malformed Content-Length")?;
+                if content_length > MAX_BODY {
+                    bail!("Body of {content_length} bytes exceeds the limit of {MAX_BODY}");
+                }
+            }
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    Ok((start_line.trim_end().to_string(), body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use tempfile::TempDir;
+    use x_editor::content_addressing::ContentRepository;
+    use x_editor::namespace::Visibility;
+    use x_editor::namespace_sync::{pull, push};
+    use x_parser::{parse_source, FileId, Item, Symbol, SyntaxStyle};
+
+    fn store(dir: &TempDir, definitions: &[(&str, &str)]) -> NamespaceStorage {
+        let mut repo = ContentRepository::new();
+        let mut storage = NamespaceStorage::new(dir.path().to_path_buf(), ContentRepository::new()).unwrap();
+        let mut namespace = Namespace::new(NamespacePath::from_str("App"));
+        for (name, body) in definitions {
+            let unit = parse_source(&format!("module App\nlet {name} = {body}"), FileId::new(0), SyntaxStyle::SExpression).unwrap();
+            let Item::ValueDef(def) = &unit.module.items[0] else { unreachable!() };
+            let hash = repo.add_function(name, def, None, None, HashSet::new()).unwrap();
+            storage.store_content(repo.get(&hash).unwrap()).unwrap();
+            namespace.add_value(Symbol::intern(name), hash, None, Visibility::Public);
+        }
+        storage.save_namespace(&namespace).unwrap();
+        storage
+    }
+
+    #[test]
+    fn test_push_and_pull_over_http() {
+        let server_dir = TempDir::new().unwrap();
+        let mut served = store(&server_dir, &[("one", "1")]);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || serve(&mut served, listener));
+
+        let path = NamespacePath::from_str("App");
+        let local_dir = TempDir::new().unwrap();
+        let mut local = store(&local_dir, &[("one", "1"), ("two", "2")]);
+        let mut remote = HttpRemote::new(&url).unwrap();
+        let report = push(&mut local, &mut remote, &path).unwrap();
+        assert_eq!((report.transferred, report.deduplicated), (1, 1));
+
+        let other_dir = TempDir::new().unwrap();
+        let mut other = NamespaceStorage::new(other_dir.path().to_path_buf(), ContentRepository::new()).unwrap();
+        let report = pull(&mut other, &mut remote, &path).unwrap();
+        assert_eq!((report.transferred, report.deduplicated), (2, 0));
+        assert_eq!(other.load_namespace(&path).unwrap().bindings.len(), 2);
+
+        assert!(remote.fetch_namespace(&NamespacePath::from_str("Missing")).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_requests_are_checked() {
+        let dir = TempDir::new().unwrap();
+        let mut storage = store(&dir, &[("one", "1")]);
+
+        let escaping = Namespace::new(NamespacePath::new(vec![Symbol::intern(".."), Symbol::intern("outside")]));
+        let body = serde_json::to_vec(&escaping).unwrap();
+        assert!(respond(&mut storage, "PUT", "/namespaces/../outside", &body).is_err());
+        assert!(respond(&mut storage, "GET", "/namespaces/a/b", &[]).is_err());
+
+        let hashes = serde_json::to_vec(&[ContentHash("../namespace_index".to_string())]).unwrap();
+        assert!(respond(&mut storage, "POST", "/content/fetch", &hashes).is_err());
+
+        let oversized = format!("PUT /content HTTP/1.1\r\nContent-Length: {}\r\n\r\n", MAX_BODY + 1);
+        assert!(read_message(&mut oversized.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_remote_urls() {
+        let remote = HttpRemote::new("http://example.com/stores/main/").unwrap();
+        assert_eq!((remote.address.as_str(), remote.prefix.as_str()), ("example.com:80", "/stores/main"));
+        assert!(HttpRemote::new("https://example.com").is_err());
+    }
+}
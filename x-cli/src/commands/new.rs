@@ -6,9 +6,10 @@ use std::fs;
 use colored::*;
 use crate::utils::{ProgressIndicator, print_success};
 use crate::format::{save_ast, Format};
+use crate::manifest::{Manifest, MANIFEST_FILE};
 use x_parser::{
     persistent_ast::{NodeBuilder, AstNodeKind, PersistentAstNode, Visibility, Purity, LiteralValue, Parameter, Binding},
-    span::{Span, FileId, ByteOffset},
+    span::{Span, FileId, CharOffset},
     symbol::Symbol,
 };
 
@@ -51,7 +52,7 @@ async fn create_main_binary_file(project_dir: &Path, name: &str) -> Result<()> {
     // Create main function
     let main_body = create_main_function_body(&mut builder, name);
     let main_function = builder.build(
-        Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(100)),
+        Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(100)),
         AstNodeKind::ValueDef {
             name: Symbol::intern("main"),
             type_annotation: None,
@@ -66,7 +67,7 @@ async fn create_main_binary_file(project_dir: &Path, name: &str) -> Result<()> {
     
     // Create module
     let module = builder.build(
-        Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(200)),
+        Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(200)),
         AstNodeKind::Module {
             name: Symbol::intern("main"),
             items: vec![main_function, add_function],
@@ -76,7 +77,7 @@ async fn create_main_binary_file(project_dir: &Path, name: &str) -> Result<()> {
     
     // Create compilation unit
     let compilation_unit = builder.build(
-        Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(200)),
+        Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(200)),
         AstNodeKind::CompilationUnit {
             modules: vec![module],
             imports: Vec::new(),
@@ -86,7 +87,7 @@ async fn create_main_binary_file(project_dir: &Path, name: &str) -> Result<()> {
     
     // Save as binary file
     let main_file = project_dir.join("main.x");
-    save_ast(&main_file, &compilation_unit, Format::Binary).await
+    save_ast(&main_file, &compilation_unit, Format::Binary, false).await
         .with_context(|| format!("Failed to create main.x file: {}", main_file.display()))?;
     
     Ok(())
@@ -96,19 +97,19 @@ async fn create_main_binary_file(project_dir: &Path, name: &str) -> Result<()> {
 fn create_main_function_body(builder: &mut NodeBuilder, name: &str) -> PersistentAstNode {
     // Create: println("Hello from {name}!")
     let hello_string = builder.build(
-        Span::new(FileId::new(0), ByteOffset::new(10), ByteOffset::new(30)),
+        Span::new(FileId::new(0), CharOffset::new(10), CharOffset::new(30)),
         AstNodeKind::Literal {
             value: LiteralValue::String(format!("Hello from {}!", name)),
         },
     );
     
     let println_var = builder.build(
-        Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(7)),
+        Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(7)),
         AstNodeKind::Variable { name: Symbol::intern("println") },
     );
     
     let println_call = builder.build(
-        Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(40)),
+        Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(40)),
         AstNodeKind::Application {
             function: Box::new(println_var),
             arguments: vec![hello_string],
@@ -117,22 +118,22 @@ fn create_main_function_body(builder: &mut NodeBuilder, name: &str) -> Persisten
     
     // Create: let result = add(21, 21)
     let add_var = builder.build(
-        Span::new(FileId::new(0), ByteOffset::new(50), ByteOffset::new(53)),
+        Span::new(FileId::new(0), CharOffset::new(50), CharOffset::new(53)),
         AstNodeKind::Variable { name: Symbol::intern("add") },
     );
     
     let num_21_1 = builder.build(
-        Span::new(FileId::new(0), ByteOffset::new(54), ByteOffset::new(56)),
+        Span::new(FileId::new(0), CharOffset::new(54), CharOffset::new(56)),
         AstNodeKind::Literal { value: LiteralValue::Integer(21) },
     );
     
     let num_21_2 = builder.build(
-        Span::new(FileId::new(0), ByteOffset::new(58), ByteOffset::new(60)),
+        Span::new(FileId::new(0), CharOffset::new(58), CharOffset::new(60)),
         AstNodeKind::Literal { value: LiteralValue::Integer(21) },
     );
     
     let add_call = builder.build(
-        Span::new(FileId::new(0), ByteOffset::new(50), ByteOffset::new(70)),
+        Span::new(FileId::new(0), CharOffset::new(50), CharOffset::new(70)),
         AstNodeKind::Application {
             function: Box::new(add_var),
             arguments: vec![num_21_1, num_21_2],
@@ -140,7 +141,7 @@ fn create_main_function_body(builder: &mut NodeBuilder, name: &str) -> Persisten
     );
     
     let result_pattern = builder.build(
-        Span::new(FileId::new(0), ByteOffset::new(45), ByteOffset::new(51)),
+        Span::new(FileId::new(0), CharOffset::new(45), CharOffset::new(51)),
         AstNodeKind::PatternVariable { name: Symbol::intern("result") },
     );
     
@@ -151,7 +152,7 @@ fn create_main_function_body(builder: &mut NodeBuilder, name: &str) -> Persisten
     
     // Create function body with println and let binding
     builder.build(
-        Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(80)),
+        Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(80)),
         AstNodeKind::Let {
             bindings: vec![result_binding],
             body: Box::new(println_call),
@@ -163,7 +164,7 @@ fn create_main_function_body(builder: &mut NodeBuilder, name: &str) -> Persisten
 fn create_add_function(builder: &mut NodeBuilder) -> PersistentAstNode {
     // Create type references separately to avoid borrowing issues
     let i32_type_a = builder.build(
-        Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(3)),
+        Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(3)),
         AstNodeKind::TypeReference {
             name: Symbol::intern("i32"),
             type_args: Vec::new(),
@@ -171,7 +172,7 @@ fn create_add_function(builder: &mut NodeBuilder) -> PersistentAstNode {
     );
     
     let i32_type_b = builder.build(
-        Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(3)),
+        Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(3)),
         AstNodeKind::TypeReference {
             name: Symbol::intern("i32"),
             type_args: Vec::new(),
@@ -190,23 +191,23 @@ fn create_add_function(builder: &mut NodeBuilder) -> PersistentAstNode {
     
     // Create variables separately
     let plus_op = builder.build(
-        Span::new(FileId::new(0), ByteOffset::new(2), ByteOffset::new(3)),
+        Span::new(FileId::new(0), CharOffset::new(2), CharOffset::new(3)),
         AstNodeKind::Variable { name: Symbol::intern("+") },
     );
     
     let var_a = builder.build(
-        Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(1)),
+        Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(1)),
         AstNodeKind::Variable { name: Symbol::intern("a") },
     );
     
     let var_b = builder.build(
-        Span::new(FileId::new(0), ByteOffset::new(4), ByteOffset::new(5)),
+        Span::new(FileId::new(0), CharOffset::new(4), CharOffset::new(5)),
         AstNodeKind::Variable { name: Symbol::intern("b") },
     );
     
     // Create: a + b (as function application)
     let add_body = builder.build(
-        Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(5)),
+        Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(5)),
         AstNodeKind::Application {
             function: Box::new(plus_op),
             arguments: vec![var_a, var_b],
@@ -214,7 +215,7 @@ fn create_add_function(builder: &mut NodeBuilder) -> PersistentAstNode {
     );
     
     let lambda_body = builder.build(
-        Span::new(FileId::new(0), ByteOffset::new(110), ByteOffset::new(150)),
+        Span::new(FileId::new(0), CharOffset::new(110), CharOffset::new(150)),
         AstNodeKind::Lambda {
             parameters: vec![param_a, param_b],
             body: Box::new(add_body),
@@ -224,7 +225,7 @@ fn create_add_function(builder: &mut NodeBuilder) -> PersistentAstNode {
     
     // Create type annotations for function type
     let param_type1 = builder.build(
-        Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(3)),
+        Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(3)),
         AstNodeKind::TypeReference {
             name: Symbol::intern("i32"),
             type_args: Vec::new(),
@@ -232,7 +233,7 @@ fn create_add_function(builder: &mut NodeBuilder) -> PersistentAstNode {
     );
     
     let param_type2 = builder.build(
-        Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(3)),
+        Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(3)),
         AstNodeKind::TypeReference {
             name: Symbol::intern("i32"),
             type_args: Vec::new(),
@@ -240,7 +241,7 @@ fn create_add_function(builder: &mut NodeBuilder) -> PersistentAstNode {
     );
     
     let return_type = builder.build(
-        Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(3)),
+        Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(3)),
         AstNodeKind::TypeReference {
             name: Symbol::intern("i32"),
             type_args: Vec::new(),
@@ -248,7 +249,7 @@ fn create_add_function(builder: &mut NodeBuilder) -> PersistentAstNode {
     );
     
     let function_type = builder.build(
-        Span::new(FileId::new(0), ByteOffset::new(105), ByteOffset::new(120)),
+        Span::new(FileId::new(0), CharOffset::new(105), CharOffset::new(120)),
         AstNodeKind::FunctionType {
             parameters: vec![param_type1, param_type2],
             return_type: Box::new(return_type),
@@ -257,7 +258,7 @@ fn create_add_function(builder: &mut NodeBuilder) -> PersistentAstNode {
     );
     
     builder.build(
-        Span::new(FileId::new(0), ByteOffset::new(100), ByteOffset::new(150)),
+        Span::new(FileId::new(0), CharOffset::new(100), CharOffset::new(150)),
         AstNodeKind::ValueDef {
             name: Symbol::intern("add"),
             type_annotation: Some(Box::new(function_type)),
@@ -270,6 +271,11 @@ fn create_add_function(builder: &mut NodeBuilder) -> PersistentAstNode {
 
 fn create_project_files(project_dir: &Path, name: &str) -> Result<()> {
     
+    // Create package manifest
+    let manifest_file = project_dir.join(MANIFEST_FILE);
+    fs::write(&manifest_file, Manifest::new(name).to_toml()?)
+        .with_context(|| format!("Failed to create manifest: {}", manifest_file.display()))?;
+    
     // Create project configuration
     let config_file = project_dir.join("x-lang.toml");
     let config_content = format!(r#"[project]
@@ -357,6 +363,7 @@ cargo run --bin x -- convert main.x --to json
 ## Project Structure
 
 - `main.x` - Main source file (binary AST format)
+- `x.toml` - Package manifest: name, version and dependencies
 - `x-lang.toml` - Project configuration
 - `README.md` - This file
 
@@ -9,6 +9,8 @@ use x_parser::{Parser, FileId};
 use x_parser::versioning::Version;
 use x_parser::dependency::DependencyManager;
 use colored::*;
+use x_editor::OutdatedPackage;
+use crate::manifest::Project;
 use crate::version_db::VersionDatabase;
 
 /// Check for outdated dependencies
@@ -28,6 +30,17 @@ pub struct OutdatedArgs {
 }
 
 pub async fn run(args: OutdatedArgs) -> Result<()> {
+    // Inside a project, compare its packages against the versions available locally
+    if let Some(project) = Project::discover(&args.input)? {
+        let root = &project.packages[0].name;
+        let outdated = project.package_graph().outdated(root, &project.available_versions());
+        match args.format.as_str() {
+            "json" => display_packages_json(&outdated)?,
+            _ => display_packages_text(&outdated),
+        }
+        return Ok(());
+    }
+    
     let db = VersionDatabase::load_default()?;
     
     // Parse the input file
@@ -275,4 +288,57 @@ fn display_json(outdated: &[OutdatedDependency]) -> Result<()> {
     
     println!("{}", serde_json::to_string_pretty(&json)?);
     Ok(())
-}
\ No newline at end of file
+}
+
+fn display_packages_text(outdated: &[OutdatedPackage]) {
+    if outdated.is_empty() {
+        println!("{} All dependencies are up to date!", "✓".green());
+        return;
+    }
+    
+    println!("{}", "Outdated Packages:".bold().underline());
+    println!();
+    
+    for package in outdated {
+        let compatible = package.compatible.as_ref()
+            .map(|v| v.to_string().green())
+            .unwrap_or_else(|| "-".dimmed());
+        println!("  {} {} {} → {} (latest {}), required by {}{}",
+            "•".dimmed(),
+            package.name.cyan(),
+            package.current.to_string().yellow(),
+            compatible,
+            package.latest.to_string().green(),
+            package.dependent,
+            package.requirement.as_ref().map(|r| format!(" as {}", r)).unwrap_or_default(),
+        );
+    }
+    
+    println!();
+    println!("{}", "Summary:".bold());
+    println!("  {} {}", 
+        outdated.len().to_string().red(), 
+        if outdated.len() == 1 { "package has a newer version" } else { "packages have newer versions" }
+    );
+}
+
+fn display_packages_json(outdated: &[OutdatedPackage]) -> Result<()> {
+    let json = serde_json::json!({
+        "outdated": outdated.iter().map(|package| {
+            serde_json::json!({
+                "package": package.name,
+                "dependent": package.dependent,
+                "requirement": package.requirement,
+                "current_version": package.current.to_string(),
+                "compatible_version": package.compatible.as_ref().map(|v| v.to_string()),
+                "latest_version": package.latest.to_string(),
+            })
+        }).collect::<Vec<_>>(),
+        "summary": {
+            "total_outdated": outdated.len(),
+        }
+    });
+    
+    println!("{}", serde_json::to_string_pretty(&json)?);
+    Ok(())
+}
@@ -5,7 +5,11 @@ use std::path::Path;
 use colored::*;
 use serde_json;
 use x_parser::persistent_ast::{PersistentAstNode, AstNodeKind};
+use x_editor::{LanguageService, LanguageServiceConfig, QueryNode, SourceFile};
+use x_parser::span::LineMap;
+use x_parser::{parse_source, FileId, SyntaxStyle};
 use crate::format::{detect_format, load_ast};
+use crate::manifest::Project;
 use crate::utils::ProgressIndicator;
 
 /// Execute queries against AST
@@ -16,6 +20,12 @@ pub async fn query_command(
 ) -> Result<()> {
     let progress = ProgressIndicator::new("Executing query");
     
+    if !is_legacy_query(query_str) {
+        let results = execute_query_language(input, query_str)?;
+        progress.finish("Query completed");
+        return display_query_results(&results, output_format);
+    }
+    
     // Load AST
     let input_format = detect_format(input)?;
     let ast = load_ast(input, input_format).await
@@ -34,6 +44,87 @@ pub async fn query_command(
     Ok(())
 }
 
+/// List every reference to `symbol` across the project `input` belongs to,
+/// or within `input` alone when it has no manifest
+pub fn references_command(input: &Path, symbol: &str, output_format: &str) -> Result<()> {
+    let results = find_references(input, symbol)?;
+    display_query_results(&results, output_format)
+}
+
+fn find_references(input: &Path, symbol: &str) -> Result<Vec<QueryResult>> {
+    let input = input.canonicalize()
+        .with_context(|| format!("Failed to resolve {}", input.display()))?;
+    let mut paths = vec![input.clone()];
+    if let Some(project) = Project::discover(&input)? {
+        paths.extend(project.packages.iter()
+            .flat_map(|package| package.modules.values())
+            .filter(|path| **path != input)
+            .cloned());
+    }
+
+    let mut files = Vec::new();
+    for (index, path) in paths.iter().enumerate() {
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read file: {}", path.display()))?;
+        let unit = parse_source(&source, FileId::new(index as u32), SyntaxStyle::SExpression)
+            .with_context(|| format!("Failed to parse: {}", path.display()))?;
+        files.push((source, unit));
+    }
+    let scope: Vec<SourceFile> = files.iter().map(|(source, unit)| SourceFile { unit, source }).collect();
+
+    let service = LanguageService::new(LanguageServiceConfig::default());
+    Ok(service.find_references(symbol, &scope).iter()
+        .map(|reference| {
+            let index = reference.span.file_id.as_u32() as usize;
+            let start = LineMap::new(&files[index].0).offset_to_position(reference.span.start);
+            QueryResult {
+                node_id: None,
+                node_type: if reference.is_definition { "definition" } else { "reference" }.to_string(),
+                description: symbol.to_string(),
+                location: format!("{}:{}:{}", paths[index].display(), start.line.to_display(), start.column.to_display()),
+                captures: Vec::new(),
+            }
+        })
+        .collect())
+}
+
+/// `type:` and `symbol:` queries predate the query language
+fn is_legacy_query(query_str: &str) -> bool {
+    query_str.starts_with("type:") || query_str.starts_with("symbol:")
+}
+
+/// Run a textual query (e.g. `fn[name~"parse*"] > match`) against a source file
+fn execute_query_language(input: &Path, query_str: &str) -> Result<Vec<QueryResult>> {
+    let source = std::fs::read_to_string(input)
+        .with_context(|| format!("Failed to read file: {}", input.display()))?;
+    let service = LanguageService::new(LanguageServiceConfig::default());
+    let ast = service.parse(&source)
+        .with_context(|| format!("Failed to parse: {}", input.display()))?;
+    let matches = service.query_str(&ast, query_str)?;
+
+    let line_map = LineMap::new(&source);
+    let location = |node: &QueryNode| {
+        let start = line_map.offset_to_position(node.span.start);
+        format!("{}:{}", start.line.to_display(), start.column.to_display())
+    };
+    let describe = |node: &QueryNode| match node.name {
+        Some(name) => format!("{} {}", node.kind, name),
+        None => node.kind.to_string(),
+    };
+
+    Ok(matches.iter()
+        .map(|m| QueryResult {
+            node_id: None,
+            node_type: m.node.kind.to_string(),
+            description: describe(&m.node),
+            location: location(&m.node),
+            captures: m.captures.iter()
+                .map(|(name, node)| (name.clone(), format!("{} at {}", describe(node), location(node))))
+                .collect(),
+        })
+        .collect())
+}
+
 /// Simple query execution (placeholder implementation)
 fn execute_simple_query(ast: &PersistentAstNode, query_str: &str) -> Result<Vec<QueryResult>> {
     let mut results = Vec::new();
@@ -57,10 +148,11 @@ fn find_by_type(node: &PersistentAstNode, type_name: &str, results: &mut Vec<Que
     let node_type = get_node_type_name(&node.kind);
     if node_type.to_lowercase().contains(&type_name.to_lowercase()) {
         results.push(QueryResult {
-            node_id: node.metadata.node_id.as_u64(),
+            node_id: Some(node.metadata.node_id.as_u64()),
             node_type: node_type.to_string(),
             description: format!("Found {} node", node_type),
             location: format!("{}:{}", node.metadata.span.start.as_u32(), node.metadata.span.end.as_u32()),
+            captures: Vec::new(),
         });
     }
     
@@ -74,18 +166,20 @@ fn find_by_symbol(node: &PersistentAstNode, symbol_name: &str, results: &mut Vec
     match &node.kind {
         AstNodeKind::Variable { name } if name.as_str().contains(symbol_name) => {
             results.push(QueryResult {
-                node_id: node.metadata.node_id.as_u64(),
+                node_id: Some(node.metadata.node_id.as_u64()),
                 node_type: "Variable".to_string(),
                 description: format!("Variable: {}", name.as_str()),
                 location: format!("{}:{}", node.metadata.span.start.as_u32(), node.metadata.span.end.as_u32()),
+                captures: Vec::new(),
             });
         },
         AstNodeKind::ValueDef { name, .. } if name.as_str().contains(symbol_name) => {
             results.push(QueryResult {
-                node_id: node.metadata.node_id.as_u64(),
+                node_id: Some(node.metadata.node_id.as_u64()),
                 node_type: "ValueDef".to_string(),
                 description: format!("Function: {}", name.as_str()),
                 location: format!("{}:{}", node.metadata.span.start.as_u32(), node.metadata.span.end.as_u32()),
+                captures: Vec::new(),
             });
         },
         _ => {}
@@ -100,10 +194,11 @@ fn find_by_symbol(node: &PersistentAstNode, symbol_name: &str, results: &mut Vec
 fn collect_all_nodes(node: &PersistentAstNode, results: &mut Vec<QueryResult>) {
     let node_type = get_node_type_name(&node.kind);
     results.push(QueryResult {
-        node_id: node.metadata.node_id.as_u64(),
+        node_id: Some(node.metadata.node_id.as_u64()),
         node_type: node_type.to_string(),
         description: format!("{} node", node_type),
         location: format!("{}:{}", node.metadata.span.start.as_u32(), node.metadata.span.end.as_u32()),
+        captures: Vec::new(),
     });
     
     for child in node.children() {
@@ -134,6 +229,9 @@ fn display_query_results(results: &[QueryResult], output_format: &str) -> Result
                     result.description.white(),
                     result.location.dimmed()
                 );
+                for (name, capture) in &result.captures {
+                    println!("     {} {}", format!("@{}", name).yellow(), capture);
+                }
             }
             
             println!();
@@ -143,6 +241,9 @@ fn display_query_results(results: &[QueryResult], output_format: &str) -> Result
             println!("Results: {} found", results.len());
             for result in results {
                 println!("- {}: {} at {}", result.node_type, result.description, result.location);
+                for (name, capture) in &result.captures {
+                    println!("    @{}: {}", name, capture);
+                }
             }
         }
     }
@@ -153,10 +254,14 @@ fn display_query_results(results: &[QueryResult], output_format: &str) -> Result
 /// Query result structure
 #[derive(Debug, serde::Serialize)]
 struct QueryResult {
-    node_id: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    node_id: Option<u64>,
     node_type: String,
     description: String,
     location: String,
+    /// Named captures of the query language, as (name, description)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    captures: Vec<(String, String)>,
 }
 
 /// Get node type name
@@ -232,4 +337,36 @@ mod tests {
             }
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_find_references_across_the_project() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("x.toml"), "[package]\nname = \"demo\"\nversion = \"0.1.0\"\n").unwrap();
+        std::fs::write(temp_dir.path().join("math.x"), "module Math\nlet square = fun x -> x * x").unwrap();
+        let app = temp_dir.path().join("app.x");
+        std::fs::write(&app, "module App\nimport Math\nlet main = fun u -> Math.square 3").unwrap();
+
+        let results = find_references(&app, "Math.square").unwrap();
+        let found: Vec<(&str, String)> = results.iter()
+            .map(|result| {
+                let file = std::path::Path::new(result.location.split(':').next().unwrap());
+                let line = result.location.split(':').nth(1).unwrap();
+                (result.node_type.as_str(), format!("{}:{line}", file.file_name().unwrap().to_string_lossy()))
+            })
+            .collect();
+        assert_eq!(found, vec![("reference", "app.x:3".to_string()), ("definition", "math.x:2".to_string())]);
+    }
+
+    #[test]
+    fn test_query_language_results() {
+        let temp_dir = TempDir::new().unwrap();
+        let input_path = temp_dir.path().join("main.x");
+        std::fs::write(&input_path, "module Test\nlet main = fun x -> print x").unwrap();
+
+        let results = execute_query_language(&input_path, r#"fn@f > app[name="print"]"#).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].description, "app print");
+        assert_eq!(results[0].location, "2:21");
+        assert_eq!(results[0].captures, vec![("f".to_string(), "fn main at 2:1".to_string())]);
+    }
+}
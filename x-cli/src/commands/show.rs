@@ -1,6 +1,11 @@
 //! AST display and inspection commands
+//!
+//! Besides textual views, `x show` renders the AST as a Graphviz DOT or
+//! Mermaid diagram, as do `--graph imports` for the module's transitive
+//! imports and `--graph calls` for the uses between its definitions.
 
-use anyhow::{Result, Context};
+use anyhow::{Result, Context, bail};
+use std::collections::HashMap;
 use std::path::Path;
 use colored::*;
 use serde_json;
@@ -9,7 +14,11 @@ use x_parser::syntax::{SyntaxConfig, SyntaxStyle};
 // use x_parser::syntax::haskell::HaskellPrinter; // Removed
 use x_parser::syntax::sexp::SExpPrinter;
 use x_parser::syntax::SyntaxPrinter;
-use crate::format::{detect_format, load_ast};
+use x_editor::analysis::{call_graph, CallGraph, DefinitionKind};
+use x_editor::{syntax_tree, ImportTree};
+use x_parser::CompilationUnit;
+use crate::format::{detect_format, load_ast, load_compilation_unit};
+use crate::manifest::{module_graph, Project};
 use crate::utils::ProgressIndicator;
 
 /// Display AST information in various formats
@@ -19,7 +28,19 @@ pub async fn show_command(
     depth: Option<usize>,
     show_types: bool,
     show_spans: bool,
+    graph: Option<&str>,
 ) -> Result<()> {
+    if let Some(graph) = graph {
+        let diagram = graph_diagram(input, graph, depth)?;
+        print!("{}", diagram.render(format, &input.display().to_string())?);
+        return Ok(());
+    }
+    if matches!(format, "dot" | "mermaid") {
+        let unit = load_compilation_unit(input)?;
+        print!("{}", ast_diagram(&unit, depth).render(format, &input.display().to_string())?);
+        return Ok(());
+    }
+
     let progress = ProgressIndicator::new("Loading AST");
     
     // Load AST
@@ -44,7 +65,7 @@ pub async fn show_command(
         "sexp" => show_sexp(&ast, depth)?,
         _ => {
             eprintln!("{} Unknown display format: {}", "Error:".red().bold(), format);
-            eprintln!("Available formats: tree, json, summary, compact, haskell, sexp");
+            eprintln!("Available formats: tree, json, summary, compact, haskell, sexp, dot, mermaid");
             std::process::exit(1);
         }
     }
@@ -374,11 +395,11 @@ fn show_sexp(ast: &PersistentAstNode, _max_depth: Option<usize>) -> Result<()> {
 fn convert_persistent_to_ast(_ast: &PersistentAstNode) -> Result<x_parser::ast::CompilationUnit> {
     use x_parser::ast::*;
     use x_parser::symbol::Symbol;
-    use x_parser::span::{Span, FileId, ByteOffset};
+    use x_parser::span::{Span, FileId, CharOffset};
     
     // Create a minimal placeholder compilation unit
     // In a real implementation, this would traverse the persistent AST and reconstruct the full AST
-    let span = Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(0));
+    let span = Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(0));
     
     let module = Module {
         name: ModulePath::single(Symbol::intern("Main"), span),
@@ -393,4 +414,267 @@ fn convert_persistent_to_ast(_ast: &PersistentAstNode) -> Result<x_parser::ast::
         module,
         span,
     })
-}
\ No newline at end of file
+}
+/// A graph to render as Graphviz DOT or Mermaid
+#[derive(Debug, Default)]
+struct Diagram {
+    nodes: Vec<DiagramNode>,
+    /// (from, to, label) by node index
+    edges: Vec<(usize, usize, Option<String>)>,
+}
+
+#[derive(Debug)]
+struct DiagramNode {
+    label: String,
+    shape: Shape,
+    style: Style,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Shape {
+    Box,
+    Ellipse,
+    Diamond,
+    Note,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Style {
+    Plain,
+    Bold,
+    Dashed,
+}
+
+impl Diagram {
+    fn add_node(&mut self, label: String, shape: Shape, style: Style) -> usize {
+        self.nodes.push(DiagramNode { label, shape, style });
+        self.nodes.len() - 1
+    }
+
+    fn add_edge(&mut self, from: usize, to: usize, label: Option<String>) {
+        self.edges.push((from, to, label));
+    }
+
+    fn render(&self, format: &str, name: &str) -> Result<String> {
+        match format {
+            "dot" => Ok(self.to_dot(name)),
+            "mermaid" => Ok(self.to_mermaid()),
+            _ => bail!("Unknown graph format: {format} (expected dot or mermaid)"),
+        }
+    }
+
+    fn to_dot(&self, name: &str) -> String {
+        let mut dot = format!("digraph {name:?} {{\n");
+        for (index, node) in self.nodes.iter().enumerate() {
+            let shape = match node.shape {
+                Shape::Box => "box",
+                Shape::Ellipse => "ellipse",
+                Shape::Diamond => "diamond",
+                Shape::Note => "note",
+            };
+            let style = match node.style {
+                Style::Plain => "",
+                Style::Bold => ", style=bold",
+                Style::Dashed => ", style=dashed",
+            };
+            dot.push_str(&format!("    n{index} [label={:?}, shape={shape}{style}];\n", node.label));
+        }
+        for (from, to, label) in &self.edges {
+            match label {
+                Some(label) => dot.push_str(&format!("    n{from} -> n{to} [label={label:?}];\n")),
+                None => dot.push_str(&format!("    n{from} -> n{to};\n")),
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn to_mermaid(&self) -> String {
+        let quote = |text: &str| format!("\"{}\"", text.replace('"', "#quot;"));
+        let mut mermaid = String::from("flowchart TD\n");
+        for (index, node) in self.nodes.iter().enumerate() {
+            let label = quote(&node.label);
+            let shaped = match node.shape {
+                Shape::Box => format!("[{label}]"),
+                Shape::Ellipse => format!("([{label}])"),
+                Shape::Diamond => format!("{{{label}}}"),
+                Shape::Note => format!("[/{label}/]"),
+            };
+            mermaid.push_str(&format!("    n{index}{shaped}\n"));
+        }
+        for (from, to, label) in &self.edges {
+            match label {
+                Some(label) => mermaid.push_str(&format!("    n{from} -->|{}| n{to}\n", quote(label))),
+                None => mermaid.push_str(&format!("    n{from} --> n{to}\n")),
+            }
+        }
+        for (index, node) in self.nodes.iter().enumerate() {
+            match node.style {
+                Style::Plain => {}
+                Style::Bold => mermaid.push_str(&format!("    style n{index} stroke-width:3px\n")),
+                Style::Dashed => mermaid.push_str(&format!("    style n{index} stroke-dasharray: 5 5\n")),
+            }
+        }
+        mermaid
+    }
+}
+
+/// The AST down to `max_depth`, each node labelled with its kind as spelled
+/// in queries and the symbol it defines or uses; the descendants of a node
+/// at the limit are collapsed into one dashed node counting them
+fn ast_diagram(unit: &CompilationUnit, max_depth: Option<usize>) -> Diagram {
+    let nodes = syntax_tree(unit);
+    let mut depths = Vec::with_capacity(nodes.len());
+    let mut hidden = vec![0; nodes.len()];
+    let mut diagram = Diagram::default();
+    let mut indices = Vec::with_capacity(nodes.len());
+    for (node, parent) in &nodes {
+        let depth = parent.map_or(0, |parent| depths[parent] + 1);
+        depths.push(depth);
+        if let Some(max) = max_depth.filter(|&max| depth > max) {
+            // Counted against the ancestor at the limit
+            let mut ancestor = parent.expect("the root is never beyond the limit");
+            while depths[ancestor] > max {
+                ancestor = nodes[ancestor].1.expect("the root is never beyond the limit");
+            }
+            hidden[ancestor] += 1;
+            indices.push(None);
+            continue;
+        }
+        let shape = match node.kind {
+            "module" | "fn" | "value" | "type" | "effect" | "handler" | "test" | "bench" | "class" | "instance" => Shape::Box,
+            "if" | "match" | "handle" => Shape::Diamond,
+            _ => Shape::Ellipse,
+        };
+        let label = match node.name {
+            Some(name) => format!("{} {}", node.kind, name.as_str()),
+            None => node.kind.to_string(),
+        };
+        let index = diagram.add_node(label, shape, Style::Plain);
+        if let Some(parent) = parent.and_then(|parent| indices[parent]) {
+            diagram.add_edge(parent, index, None);
+        }
+        indices.push(Some(index));
+    }
+    for (node, count) in hidden.into_iter().enumerate().filter(|(_, count)| *count > 0) {
+        let more = diagram.add_node(format!("… {count} more"), Shape::Box, Style::Dashed);
+        diagram.add_edge(indices[node].expect("only shown nodes hide descendants"), more, None);
+    }
+    diagram
+}
+
+/// The module or call graph of the module in `input`
+fn graph_diagram(input: &Path, graph: &str, max_depth: Option<usize>) -> Result<Diagram> {
+    let unit = load_compilation_unit(input)?;
+    match graph {
+        "imports" => {
+            let graph = match Project::discover(input)? {
+                Some(project) => project.dependency_graph(input, &unit)?,
+                None => module_graph(&unit, None),
+            };
+            Ok(imports_diagram(&graph.import_tree(&unit.module.name.to_string()), max_depth))
+        }
+        "calls" => Ok(calls_diagram(&call_graph(&unit))),
+        _ => bail!("Unknown graph: {graph} (expected imports or calls)"),
+    }
+}
+
+/// One node per module, imports as edges labelled with their version
+/// requirements; modules deeper than `max_depth` are left out
+fn imports_diagram(tree: &ImportTree, max_depth: Option<usize>) -> Diagram {
+    fn add(diagram: &mut Diagram, modules: &mut HashMap<String, usize>, tree: &ImportTree, depth: usize, max_depth: Option<usize>) -> usize {
+        let index = *modules.entry(tree.module.clone()).or_insert_with(|| {
+            let label = match &tree.package {
+                Some(package) => format!("{}\n({package})", tree.module),
+                None => tree.module.clone(),
+            };
+            diagram.add_node(label, Shape::Box, if depth == 0 { Style::Bold } else { Style::Plain })
+        });
+        if max_depth.is_some_and(|max| depth >= max) {
+            return index;
+        }
+        for child in &tree.children {
+            let child_index = add(diagram, modules, child, depth + 1, max_depth);
+            diagram.add_edge(index, child_index, child.requirement.clone());
+        }
+        index
+    }
+
+    let mut diagram = Diagram::default();
+    add(&mut diagram, &mut HashMap::new(), tree, 0, max_depth);
+    diagram
+}
+
+/// Definitions shaped by kind, entry points bold and dead code dashed
+fn calls_diagram(graph: &CallGraph) -> Diagram {
+    let dead: Vec<_> = graph.dead_code().iter().map(|definition| definition.name).collect();
+    let mut diagram = Diagram::default();
+    let mut indices = HashMap::new();
+    for definition in graph.definitions() {
+        let shape = match definition.kind {
+            DefinitionKind::Value => Shape::Ellipse,
+            DefinitionKind::Test => Shape::Note,
+            DefinitionKind::Handler => Shape::Box,
+            DefinitionKind::Effect => Shape::Diamond,
+        };
+        let style = if dead.contains(&definition.name) {
+            Style::Dashed
+        } else if definition.is_entry_point() {
+            Style::Bold
+        } else {
+            Style::Plain
+        };
+        let label = format!("{} {}", definition.kind, definition.name.as_str());
+        indices.insert(definition.name, diagram.add_node(label, shape, style));
+    }
+    for (caller, callee) in graph.edges() {
+        diagram.add_edge(indices[&caller.name], indices[&callee.name], None);
+    }
+    diagram
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn source_file(dir: &TempDir, source: &str) -> std::path::PathBuf {
+        let path = dir.path().join("main.x");
+        std::fs::write(&path, source).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_ast_diagrams_are_depth_limited() {
+        let dir = TempDir::new().unwrap();
+        let path = source_file(&dir, "module Main\nlet double = fun x -> x + x");
+        let unit = load_compilation_unit(&path).unwrap();
+
+        let full = ast_diagram(&unit, None);
+        assert!(full.nodes.iter().any(|node| node.label == "var x"), "{full:?}");
+        let limited = ast_diagram(&unit, Some(1));
+        assert!(limited.nodes.len() < full.nodes.len());
+        assert!(limited.nodes.iter().any(|node| node.style == Style::Dashed && node.label.starts_with('…')));
+
+        let mermaid = limited.render("mermaid", "main.x").unwrap();
+        assert!(mermaid.starts_with("flowchart TD\n") && mermaid.contains("n1[\"fn double\"]"), "{mermaid}");
+        let dot = limited.render("dot", "main.x").unwrap();
+        assert!(dot.contains("n0 -> n1;") && dot.contains("style=dashed"), "{dot}");
+        assert!(limited.render("svg", "main.x").is_err());
+    }
+
+    #[test]
+    fn test_call_and_import_graphs() {
+        let dir = TempDir::new().unwrap();
+        let path = source_file(&dir, "module Main\nimport List\nlet helper = fun x -> x\nlet unused = 1\nlet main = fun u -> helper 1");
+
+        let dot = graph_diagram(&path, "calls", None).unwrap().to_dot("Main");
+        assert!(dot.contains("label=\"value main\", shape=ellipse, style=bold"), "{dot}");
+        assert!(dot.contains("label=\"value unused\", shape=ellipse, style=dashed"), "{dot}");
+        assert_eq!(dot.matches("->").count(), 1);
+
+        let mermaid = graph_diagram(&path, "imports", Some(1)).unwrap().to_mermaid();
+        assert!(mermaid.contains("n0[\"Main\"]") && mermaid.contains("n0 --> n1"), "{mermaid}");
+        assert!(graph_diagram(&path, "types", None).is_err());
+    }
+}
@@ -1,72 +1,191 @@
 //! Project statistics commands
+//!
+//! `x stats` measures every definition of the given sources (complexity,
+//! nesting, effect use and purity) and `x stats --call-graph` prints how the
+//! definitions of one module use each other.
 
-use anyhow::Result;
-use std::path::Path;
+use anyhow::{Result, bail};
+use std::path::{Path, PathBuf};
 use colored::*;
+use x_editor::{call_graph, module_metrics, ModuleMetrics};
+use x_editor::metrics::purity_ratio;
+use crate::commands::fmt::discover_sources;
+use crate::format::load_compilation_unit;
 use crate::utils::{ProgressIndicator, TableBuilder};
 
-pub async fn stats_command(_input: &Path, format: &str) -> Result<()> {
+/// Metrics of every definition in a file or the sources under a directory,
+/// as a table or as JSON
+pub async fn stats_command(input: &Path, format: &str) -> Result<()> {
+    if !matches!(format, "table" | "json") {
+        bail!("Unknown format: {format} (expected table or json)");
+    }
     let progress = ProgressIndicator::new("Analyzing project");
-    
+
     progress.set_message("Scanning files");
-    // TODO: Scan directory for x Language files
-    
-    progress.set_message("Analyzing ASTs");
-    // TODO: Parse and analyze all ASTs
-    
+    let files = if input.is_dir() { discover_sources(input)? } else { vec![input.to_path_buf()] };
+
+    progress.set_message("Measuring definitions");
+    let mut modules = Vec::new();
+    let mut failed = Vec::new();
+    for path in files {
+        match load_compilation_unit(&path) {
+            Ok(unit) => modules.push((path, module_metrics(&unit))),
+            Err(error) => failed.push((path, error)),
+        }
+    }
+
     progress.finish("Analysis completed");
-    
+    for (path, error) in &failed {
+        eprintln!("{} skipped {}: {error:#}", "warning:".yellow(), path.display());
+    }
+
+    match format {
+        "json" => println!("{}", serde_json::to_string_pretty(&stats_json(&modules))?),
+        _ => display_table_stats(&modules),
+    }
+    Ok(())
+}
+
+/// Print the call graph of one module as DOT, or as JSON with its dead code
+pub fn call_graph_command(input: &Path, format: &str) -> Result<()> {
+    let graph = call_graph(&load_compilation_unit(input)?);
     match format {
-        "table" => display_table_stats(),
-        "json" => display_json_stats(),
-        _ => {
-            eprintln!("Unknown format: {}", format);
-            return Ok(());
+        "json" => println!("{}", serde_json::to_string_pretty(&graph.to_json())?),
+        "table" | "dot" => {
+            print!("{}", graph.to_dot());
+            for definition in graph.dead_code() {
+                eprintln!("{} {} {} is never used", "warning:".yellow(), definition.kind, definition.name);
+            }
         }
+        _ => bail!("Unknown format for the call graph: {format} (expected dot or json)"),
     }
-    
     Ok(())
 }
 
-fn display_table_stats() {
+fn display_table_stats(modules: &[(PathBuf, ModuleMetrics)]) {
+    let totals = Totals::of(modules);
     println!("{}", "Project Statistics".bold().underline());
     println!();
-    
-    TableBuilder::new()
-        .headers(vec!["Metric", "Value", "Notes"])
-        .row(vec!["Total Files", "5", ""])
-        .row(vec!["Total Nodes", "1,234", ""])
-        .row(vec!["Functions", "23", ""])
-        .row(vec!["Types", "8", ""])
-        .row(vec!["Effects", "3", ""])
-        .row(vec!["Lines of Code", "456", "Estimated"])
+
+    let ratio = totals.purity_ratio().map_or_else(|| "-".to_string(), |ratio| format!("{:.0}%", ratio * 100.0));
+    let rows = [
+        ("Files", modules.len().to_string()),
+        ("Definitions", totals.definitions.to_string()),
+        ("Pure values", totals.pure.to_string()),
+        ("Effectful values", totals.effectful.to_string()),
+        ("Purity ratio", ratio),
+        ("Perform sites", totals.perform_sites.to_string()),
+        ("Handle sites", totals.handle_sites.to_string()),
+        ("Max complexity", totals.max_complexity.to_string()),
+    ];
+    rows.iter()
+        .fold(TableBuilder::new().headers(vec!["Metric", "Value"]), |table, (metric, value)| {
+            table.row(vec![metric, value])
+        })
         .print();
-    
+
     println!();
-    println!("{}", "File Types".bold());
-    
-    TableBuilder::new()
-        .headers(vec!["Format", "Count", "Size"])
-        .row(vec!["Binary (.x)", "2", "1.2 KB"])
-        .row(vec!["Rust-like (.rustic.x)", "2", "3.4 KB"])
-        .row(vec!["Haskell (.haskell.x)", "1", "1.1 KB"])
-        .print();
+    println!("{}", "Definitions".bold());
+    let mut table = TableBuilder::new()
+        .headers(vec!["Definition", "Kind", "Complexity", "Depth", "Performs", "Handles", "Purity"]);
+    for (_, module) in modules {
+        for metrics in &module.definitions {
+            let name = format!("{}.{}", module.module, metrics.name);
+            let (kind, complexity, depth) = (metrics.kind.to_string(), metrics.complexity.to_string(), metrics.max_depth.to_string());
+            let (performs, handles) = (metrics.perform_sites.to_string(), metrics.handle_sites.to_string());
+            let purity = if metrics.effectful { "effectful" } else { "pure" };
+            table = table.row(vec![&name, &kind, &complexity, &depth, &performs, &handles, purity]);
+        }
+    }
+    table.print();
 }
 
-fn display_json_stats() {
-    let stats = serde_json::json!({
-        "total_files": 5,
-        "total_nodes": 1234,
-        "functions": 23,
-        "types": 8,
-        "effects": 3,
-        "lines_of_code": 456,
-        "file_types": {
-            "binary": { "count": 2, "size": 1200 },
-            "rustic": { "count": 2, "size": 3400 },
-            "haskell": { "count": 1, "size": 1100 }
-        }
-    });
-    
-    println!("{}", serde_json::to_string_pretty(&stats).unwrap());
-}
\ No newline at end of file
+fn stats_json(modules: &[(PathBuf, ModuleMetrics)]) -> serde_json::Value {
+    let totals = Totals::of(modules);
+    let modules: Vec<_> = modules.iter()
+        .map(|(path, module)| {
+            let mut json = module.to_json();
+            json["path"] = serde_json::json!(path.display().to_string());
+            json
+        })
+        .collect();
+    serde_json::json!({
+        "files": modules.len(),
+        "definitions": totals.definitions,
+        "pure": totals.pure,
+        "effectful": totals.effectful,
+        "purity_ratio": totals.purity_ratio(),
+        "perform_sites": totals.perform_sites,
+        "handle_sites": totals.handle_sites,
+        "max_complexity": totals.max_complexity,
+        "modules": modules,
+    })
+}
+
+/// Metrics summed over every module
+#[derive(Debug, Default)]
+struct Totals {
+    definitions: usize,
+    pure: usize,
+    effectful: usize,
+    perform_sites: usize,
+    handle_sites: usize,
+    max_complexity: usize,
+}
+
+impl Totals {
+    fn of(modules: &[(PathBuf, ModuleMetrics)]) -> Self {
+        modules.iter().fold(Totals::default(), |totals, (_, module)| Totals {
+            definitions: totals.definitions + module.definitions.len(),
+            pure: totals.pure + module.pure_count(),
+            effectful: totals.effectful + module.effectful_count(),
+            perform_sites: totals.perform_sites + module.perform_sites(),
+            handle_sites: totals.handle_sites + module.handle_sites(),
+            max_complexity: totals.max_complexity.max(module.max_complexity()),
+        })
+    }
+
+    fn purity_ratio(&self) -> Option<f64> {
+        purity_ratio(self.pure, self.effectful)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_call_graph_command() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.path().join("demo.x");
+        std::fs::write(&file, "module Demo\nlet helper = fun x -> x\nlet main = fun u -> helper 1\n").unwrap();
+
+        call_graph_command(&file, "dot").unwrap();
+        call_graph_command(&file, "json").unwrap();
+        assert!(call_graph_command(&file, "yaml").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stats_over_directory() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("a.x"), "module A\nlet f = fun n -> if n > 0 then 1 else 0\n").unwrap();
+        std::fs::write(temp_dir.path().join("b.x"), "module B\nlet g = fun x -> x\nlet h = fun x -> g x\n").unwrap();
+
+        let files = discover_sources(temp_dir.path()).unwrap();
+        let modules: Vec<_> = files.into_iter()
+            .map(|path| {
+                let unit = load_compilation_unit(&path).unwrap();
+                (path, module_metrics(&unit))
+            })
+            .collect();
+        let json = stats_json(&modules);
+        assert_eq!(json["files"], 2);
+        assert_eq!(json["definitions"], 3);
+        assert_eq!(json["max_complexity"], 2);
+        assert_eq!(json["purity_ratio"], 1.0);
+
+        stats_command(temp_dir.path(), "table").await.unwrap();
+        assert!(stats_command(temp_dir.path(), "yaml").await.is_err());
+    }
+}
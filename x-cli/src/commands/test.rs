@@ -5,11 +5,13 @@
 
 use anyhow::{Result, Context};
 use colored::*;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::io;
 use x_testing::{
-    TestRunner, TestRunnerConfig, 
-    TestDiscovery, TestSuite,
+    TestRunner, TestRunnerConfig, TestResult, TestReport,
+    TestDiscovery, TestSuite, TestCache, run_doctests,
+    MutantStatus, MutationTester, ModuleCoverage, run_tests_with_coverage,
+    interpreter::{with_large_stack, Limits},
     ConsoleReporter, TestReporter,
     test_report::{JsonReporter, JUnitReporter},
 };
@@ -17,11 +19,15 @@ use x_editor::{
     namespace::{Namespace, NamespacePath},
     namespace_storage::NamespaceStorage,
     content_addressing::ContentRepository,
+    dependency_hash::DefinitionHashes,
 };
-use x_parser::{parse_source, FileId, SyntaxStyle};
+use x_parser::{parse_source, CompilationUnit, FileId, SyntaxStyle};
+use x_parser::span::LineMap;
+use std::collections::HashMap;
 use x_checker::TypeChecker;
 use std::fs;
 use crate::commands::test_helpers::compilation_unit_to_namespace;
+use crate::utils::TableBuilder;
 
 /// Run tests command
 pub async fn test_command(
@@ -45,6 +51,30 @@ pub async fn test_command(
         filter: filter.map(String::from),
     };
     
+    // Create reporter
+    let reporter: Box<dyn TestReporter> = match reporter {
+        "json" => Box::new(JsonReporter::new_stdout()),
+        "junit" => Box::new(JUnitReporter::new_stdout()),
+        _ => Box::new(ConsoleReporter::new(verbose)),
+    };
+    
+    // Run tests
+    let Some(report) = run_tests(path, config, reporter.as_ref()).await? else {
+        println!("{}", "No tests found!".yellow());
+        return Ok(());
+    };
+    
+    // Exit with appropriate code
+    if report.is_success() {
+        Ok(())
+    } else {
+        std::process::exit(1);
+    }
+}
+
+/// Discover the tests under `path` and run them in the modules they are
+/// defined in, or `None` when there are none
+async fn run_tests(path: &Path, config: TestRunnerConfig, reporter: &dyn TestReporter) -> Result<Option<TestReport>> {
     // Initialize components
     let content_repo = ContentRepository::new();
     let namespace_storage = NamespaceStorage::new(path.join(".x-namespaces"), content_repo.clone())?;
@@ -52,42 +82,226 @@ pub async fn test_command(
     
     // Discover tests
     let discovery = TestDiscovery::new(content_repo.clone());
-    let suite = discover_tests(path, &discovery, &namespace_storage, &mut type_checker).await?;
+    let (suite, units) = discover_tests(path, &discovery, &namespace_storage, &mut type_checker).await?;
     
     if suite.tests.is_empty() {
-        println!("{}", "No tests found!".yellow());
-        return Ok(());
+        return Ok(None);
     }
     
     println!("Found {} tests", suite.tests.len());
     
     // Create test runner
     let mut runner = TestRunner::new(config)?;
+    for (namespace, unit) in &units {
+        runner.load(namespace.clone(), unit);
+    }
     
-    // Create reporter
-    let reporter: Box<dyn TestReporter> = match reporter {
-        "json" => Box::new(JsonReporter::new_stdout()),
-        "junit" => Box::new(JUnitReporter::new_stdout()),
-        _ => Box::new(ConsoleReporter::new(verbose)),
-    };
+    runner.run_suite(&suite, reporter).map(Some)
+}
+
+/// Run the examples in the doc comments of every `.x` file under `path`
+pub fn doctest_command(path: &Path, filter: Option<&str>, verbose: bool) -> Result<()> {
+    println!("{} {}", "Running doctests in".cyan(), path.display());
     
-    // Run tests
-    let report = runner.run_suite(&suite, reporter.as_ref())?;
+    let files = source_files(path);
     
-    // Exit with appropriate code
-    if report.is_success() {
-        Ok(())
-    } else {
+    let (mut passed, mut failed, mut skipped) = (0, 0, 0);
+    for file in files {
+        // Binary ASTs have no doc comments to run
+        let Ok(content) = fs::read_to_string(&file) else { continue };
+        let unit = parse_source(&content, FileId(0), SyntaxStyle::SExpression)
+            .with_context(|| format!("Failed to parse {}", file.display()))?;
+        
+        for (doctest, result) in run_doctests(&unit, &content) {
+            if filter.is_some_and(|filter| !doctest.item.contains(filter)) {
+                continue;
+            }
+            let location = doctest.location(&file.display().to_string());
+            match &result {
+                TestResult::Fail { error, output, .. } => {
+                    failed += 1;
+                    println!("doctest {} ... {}", location, "FAILED".red());
+                    println!("    {}", error);
+                    if let Some(output) = output.as_ref().filter(|_| verbose) {
+                        println!("    output: {}", output.trim_end());
+                    }
+                }
+                TestResult::Skipped { .. } => {
+                    skipped += 1;
+                    println!("doctest {} ... {}", location, "ignored".yellow());
+                }
+                _ => {
+                    passed += 1;
+                    println!("doctest {} ... {}", location, "ok".green());
+                }
+            }
+        }
+    }
+    
+    println!("\n{} passed, {} failed, {} ignored", passed, failed, skipped);
+    if failed > 0 {
         std::process::exit(1);
     }
+    Ok(())
 }
 
+/// Run the tests of every `.x` file under `path` against mutants of the
+/// definitions they test, and list the mutants that survive
+pub fn mutation_command(path: &Path, filter: Option<&str>, force: bool, verbose: bool) -> Result<()> {
+    println!("{} {}", "Mutation testing".cyan(), path.display());
+    
+    let cache_dir = if path.is_file() {
+        path.parent().unwrap_or(Path::new(".")).join(".x-test-cache")
+    } else {
+        path.join(".x-test-cache")
+    };
+    
+    let (mut killed, mut survived) = (0, 0);
+    for file in source_files(path) {
+        let Ok(content) = fs::read_to_string(&file) else { continue };
+        let unit = parse_source(&content, FileId(0), SyntaxStyle::SExpression)
+            .with_context(|| format!("Failed to parse {}", file.display()))?;
+        
+        let mut tester = MutationTester::new();
+        if !force {
+            tester = tester.with_cache(TestCache::new(&cache_dir)?);
+        }
+        if let Some(filter) = filter {
+            tester = tester.with_filter(filter);
+        }
+        let report = tester.run(&unit);
+        if report.mutants.is_empty() {
+            continue;
+        }
+        for test in &report.failing_tests {
+            println!("{} {} fails without mutations and is left out", "warning:".yellow(), test);
+        }
+        
+        let lines = LineMap::new(&content);
+        for mutant in &report.mutants {
+            let start = lines.offset_to_position(mutant.mutation.span.start);
+            let location = format!("{}:{}:{}", file.display(), start.line.to_display(), start.column.to_display());
+            match &mutant.status {
+                MutantStatus::Survived => {
+                    survived += 1;
+                    println!("{} {} {}: {}", "survived".red(), location, mutant.mutation.definition, mutant.mutation.description);
+                }
+                MutantStatus::Killed { test } => {
+                    killed += 1;
+                    if verbose {
+                        println!("{} {} {}: {} (by {})", "killed".green(), location, mutant.mutation.definition, mutant.mutation.description, test);
+                    }
+                }
+            }
+        }
+    }
+    
+    let total = killed + survived;
+    if total == 0 {
+        println!("{}", "No mutants to test".yellow());
+        return Ok(());
+    }
+    println!(
+        "\n{} mutants: {} killed, {} survived (score {:.0}%)",
+        total, killed, survived, killed as f64 * 100.0 / total as f64,
+    );
+    Ok(())
+}
+
+/// Run the tests of every `.x` file under `path` recording what they
+/// evaluate, print the coverage of each module and optionally write it as LCOV
+pub fn coverage_command(path: &Path, lcov: Option<&Path>, verbose: bool) -> Result<()> {
+    println!("{} {}", "Measuring coverage in".cyan(), path.display());
+    
+    let mut lcov_records = String::new();
+    let mut failed = 0;
+    let mut table = TableBuilder::new()
+        .headers(vec!["Module", "Lines", "Branches", "Functions", "Uncovered lines"]);
+    for file in source_files(path) {
+        let Ok(content) = fs::read_to_string(&file) else { continue };
+        let unit = parse_source(&content, FileId(0), SyntaxStyle::SExpression)
+            .with_context(|| format!("Failed to parse {}", file.display()))?;
+        
+        let (results, coverage) = with_large_stack(|| run_tests_with_coverage(&unit, Limits::deep()));
+        for (test, result) in &results {
+            if let TestResult::Fail { error, .. } = result {
+                failed += 1;
+                println!("test {} ... {}", test, "FAILED".red());
+                println!("    {}", error);
+            } else if verbose {
+                println!("test {} ... {}", test, "ok".green());
+            }
+        }
+        
+        let report = ModuleCoverage::compute(&unit, &content, &coverage);
+        let uncovered = line_ranges(&report.uncovered_lines());
+        table = table.row(vec![
+            &report.module,
+            &ratio(report.line_counts()),
+            &ratio(report.branch_counts()),
+            &ratio(report.function_counts()),
+            &uncovered,
+        ]);
+        lcov_records.push_str(&report.to_lcov(&file.display().to_string()));
+    }
+    
+    println!();
+    table.print();
+    if let Some(lcov) = lcov {
+        fs::write(lcov, lcov_records)
+            .with_context(|| format!("Failed to write {}", lcov.display()))?;
+        println!("Wrote LCOV to {}", lcov.display());
+    }
+    if failed > 0 {
+        anyhow::bail!("{failed} tests failed");
+    }
+    Ok(())
+}
+
+/// `covered/total (percent)`
+fn ratio((covered, total): (usize, usize)) -> String {
+    if total == 0 {
+        return "-".to_string();
+    }
+    format!("{covered}/{total} ({:.0}%)", covered as f64 * 100.0 / total as f64)
+}
+
+/// Sorted line numbers as `3, 7-9`
+fn line_ranges(lines: &[u32]) -> String {
+    let mut ranges: Vec<(u32, u32)> = Vec::new();
+    for &line in lines {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == line => *end = line,
+            _ => ranges.push((line, line)),
+        }
+    }
+    ranges.iter()
+        .map(|(start, end)| if start == end { start.to_string() } else { format!("{start}-{end}") })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The `.x` files under `path`, or `path` itself
+pub(crate) fn source_files(path: &Path) -> Vec<PathBuf> {
+    if path.is_file() {
+        return vec![path.to_path_buf()];
+    }
+    walkdir::WalkDir::new(path)
+        .follow_links(true)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .map(|entry| entry.path().to_path_buf())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "x"))
+        .collect()
+}
+
+/// The tests under `path`, with the modules they are discovered in
 async fn discover_tests(
     path: &Path,
     discovery: &TestDiscovery,
     namespace_storage: &NamespaceStorage,
     type_checker: &mut TypeChecker,
-) -> Result<TestSuite> {
+) -> Result<(TestSuite, Vec<(NamespacePath, CompilationUnit)>)> {
     if path.is_file() {
         // Single file test
         discover_file_tests(path, discovery, namespace_storage, type_checker).await
@@ -102,7 +316,7 @@ async fn discover_file_tests(
     discovery: &TestDiscovery,
     _namespace_storage: &NamespaceStorage,
     type_checker: &mut TypeChecker,
-) -> Result<TestSuite> {
+) -> Result<(TestSuite, Vec<(NamespacePath, CompilationUnit)>)> {
     let content = fs::read_to_string(path)
         .context("Failed to read test file")?;
     
@@ -125,7 +339,10 @@ async fn discover_file_tests(
     let namespace = compilation_unit_to_namespace(&compilation_unit, namespace_path, &check_result)?;
     
     // Discover tests in the namespace
-    discovery.discover_in_namespace(&namespace)
+    let mut suite = discovery.discover_in_namespace(&namespace)?;
+    let hashes = HashMap::from([(namespace.path.clone(), DefinitionHashes::compute(&compilation_unit))]);
+    attach_dependencies(&mut suite, &hashes);
+    Ok((suite, vec![(namespace.path, compilation_unit)]))
 }
 
 async fn discover_directory_tests(
@@ -133,8 +350,10 @@ async fn discover_directory_tests(
     discovery: &TestDiscovery,
     namespace_storage: &NamespaceStorage,
     type_checker: &mut TypeChecker,
-) -> Result<TestSuite> {
+) -> Result<(TestSuite, Vec<(NamespacePath, CompilationUnit)>)> {
     let mut namespaces = Vec::new();
+    let mut units = Vec::new();
+    let mut hashes = HashMap::new();
     
     // Walk directory for .x files
     for entry in walkdir::WalkDir::new(path)
@@ -145,7 +364,9 @@ async fn discover_directory_tests(
         let path = entry.path();
         
         if path.extension().map_or(false, |ext| ext == "x") {
-            if let Ok(namespace) = load_namespace_from_file(path, namespace_storage, type_checker).await {
+            if let Ok((namespace, unit)) = load_namespace_from_file(path, type_checker).await {
+                hashes.insert(namespace.path.clone(), DefinitionHashes::compute(&unit));
+                units.push((namespace.path.clone(), unit));
                 namespaces.push(namespace);
             }
         }
@@ -162,14 +383,57 @@ async fn discover_directory_tests(
     }
     
     // Discover tests in all namespaces
-    discovery.discover_in_namespaces(&namespaces)
+    let mut suite = discovery.discover_in_namespaces(&namespaces)?;
+    attach_dependencies(&mut suite, &hashes);
+    Ok((suite, units))
+}
+
+/// Names of the tests under `path`, as `--filter` matches them
+///
+/// Unlike running the tests this leaves no namespace storage behind, so it
+/// is cheap enough for shell completion.
+pub async fn test_names(path: &Path) -> Result<Vec<String>> {
+    let files: Vec<PathBuf> = if path.is_file() {
+        vec![path.to_path_buf()]
+    } else {
+        walkdir::WalkDir::new(path)
+            .follow_links(true)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .map(|entry| entry.path().to_path_buf())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "x"))
+            .collect()
+    };
+
+    let mut type_checker = TypeChecker::new();
+    let mut namespaces = Vec::new();
+    for file in &files {
+        if let Ok((namespace, _)) = load_namespace_from_file(file, &mut type_checker).await {
+            namespaces.push(namespace);
+        }
+    }
+
+    let suite = TestDiscovery::new(ContentRepository::new()).discover_in_namespaces(&namespaces)?;
+    let mut names: Vec<String> = suite.tests.iter().map(|test| test.name.to_string()).collect();
+    names.sort();
+    names.dedup();
+    Ok(names)
+}
+
+/// Record the dependency hashes of every discovered test, so a cached result
+/// is discarded as soon as anything the test transitively uses changes
+fn attach_dependencies(suite: &mut TestSuite, hashes: &HashMap<NamespacePath, DefinitionHashes>) {
+    for test in &mut suite.tests {
+        if let Some(hashes) = hashes.get(&test.namespace) {
+            test.dependencies = hashes.dependencies(&test.name);
+        }
+    }
 }
 
 async fn load_namespace_from_file(
     path: &Path,
-    _namespace_storage: &NamespaceStorage,
     type_checker: &mut TypeChecker,
-) -> Result<Namespace> {
+) -> Result<(Namespace, CompilationUnit)> {
     let content = fs::read_to_string(path)
         .context("Failed to read file")?;
     
@@ -186,7 +450,8 @@ async fn load_namespace_from_file(
     );
     
     // Convert the compilation unit to a namespace
-    compilation_unit_to_namespace(&compilation_unit, namespace_path, &check_result)
+    let namespace = compilation_unit_to_namespace(&compilation_unit, namespace_path, &check_result)?;
+    Ok((namespace, compilation_unit))
 }
 
 // Missing dependencies
@@ -273,4 +538,53 @@ mod walkdir {
             None
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn config(path: &Path) -> TestRunnerConfig {
+        TestRunnerConfig {
+            cache_dir: path.join(".x-test-cache"),
+            force_rerun: false,
+            timeout_seconds: 60,
+            num_threads: 1,
+            verbose: false,
+            filter: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_false_assertion_fails() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(
+            temp_dir.path().join("main.x"),
+            "module Demo\npub let double = fun x -> x + x\npub let test_double = double 3 == 6\npub let test_double_bad = double 3 == 7\n",
+        ).unwrap();
+
+        let report = run_tests(temp_dir.path(), config(temp_dir.path()), &ConsoleReporter::new(false)).await.unwrap().unwrap();
+        assert_eq!((report.stats.passed, report.stats.failed), (1, 1));
+        assert!(!report.is_success());
+        assert!(report.results.values().any(|result| matches!(result, TestResult::Fail { error, .. } if error == "evaluated to false")));
+    }
+
+    #[tokio::test]
+    async fn test_changed_dependency_reruns_test() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.path().join("main.x");
+        let source = |double: &str| format!("module Demo\npub let double = {double}\npub let test_double = double 3 == 6\n");
+        let run = || async { run_tests(temp_dir.path(), config(temp_dir.path()), &ConsoleReporter::new(false)).await.unwrap().unwrap() };
+
+        fs::write(&file, source("fun x -> x + x")).unwrap();
+        let report = run().await;
+        assert_eq!((report.stats.passed, report.stats.cached), (1, 0));
+        let report = run().await;
+        assert_eq!((report.stats.passed, report.stats.cached), (1, 1));
+
+        // Only `double` changes; the test is run again and sees the change
+        fs::write(&file, source("fun x -> x + 1")).unwrap();
+        let report = run().await;
+        assert_eq!((report.stats.failed, report.stats.cached), (1, 0));
+    }
+}
@@ -8,16 +8,23 @@ use x_parser::{
 use x_editor::{
     namespace::{Namespace, NamespacePath, NameBinding, Visibility},
     content_addressing::ContentHash,
+    dependency_hash::DefinitionHashes,
 };
 use x_checker::{TypeChecker, CheckResult, types::TypeScheme};
 
 /// Convert a parsed CompilationUnit to a Namespace with test functions
+///
+/// Bindings are keyed by their dependency closure hash, so a cached test
+/// result is reused only while the test and everything it uses are unchanged.
 pub fn compilation_unit_to_namespace(
     compilation_unit: &CompilationUnit,
     namespace_path: NamespacePath,
     check_result: &CheckResult,
 ) -> Result<Namespace> {
     let mut namespace = Namespace::new(namespace_path);
+    let hashes = DefinitionHashes::compute(compilation_unit);
+    let hash_of = |name: &Symbol| hashes.closure_hash(name)
+        .unwrap_or_else(|| ContentHash::new(name.as_str().as_bytes()));
     
     // Extract all value definitions and test definitions from the module
     for item in &compilation_unit.module.items {
@@ -26,7 +33,7 @@ pub fn compilation_unit_to_namespace(
                 // Check if this might be a test function
                 if is_potential_test_function(value_def) {
                     // Create a NameBinding for this function
-                    let hash = ContentHash::new(value_def.name.as_str().as_bytes());
+                    let hash = hash_of(&value_def.name);
                     
                     // Get type information from check result if available
                     let type_scheme = check_result.inferred_types
@@ -47,7 +54,7 @@ pub fn compilation_unit_to_namespace(
             }
             Item::TestDef(test_def) => {
                 // Always include TestDef items as tests
-                let hash = ContentHash::new(test_def.name.as_str().as_bytes());
+                let hash = hash_of(&test_def.name);
                 
                 // Test functions always return Bool
                 let type_scheme = Some(TypeScheme::monotype(
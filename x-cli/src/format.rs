@@ -7,6 +7,7 @@ use x_parser::{
     persistent_ast::{PersistentAstNode, NodeBuilder, AstNodeKind, Visibility},
     span::{Span, FileId},
     symbol::Symbol,
+    syntax::{ocaml::OCamlPrinter, SyntaxConfig, SyntaxPrinter, SyntaxStyle as TextSyntax},
     SyntaxStyle,
 };
 
@@ -15,6 +16,8 @@ use x_parser::{
 pub enum Format {
     /// Binary AST format (.x)
     Binary,
+    /// OCaml-like source text (.x files without the binary header)
+    OCaml,
     /// Haskell-like syntax (.haskell.x)
     Haskell,
     /// S-expression syntax (.lisp.x)
@@ -24,10 +27,14 @@ pub enum Format {
 }
 
 impl Format {
+    /// Names of the formats, as `from_str` accepts them
+    pub const NAMES: [&'static str; 5] = ["binary", "ocaml", "haskell", "sexp", "json"];
+
     /// Parse format from string
     pub fn from_str(s: &str) -> Result<Self> {
         match s.to_lowercase().as_str() {
             "binary" | "bin" | "x" => Ok(Format::Binary),
+            "ocaml" | "ml" => Ok(Format::OCaml),
             "haskell" | "hs" => Ok(Format::Haskell),
             "sexp" | "lisp" | "s-expression" => Ok(Format::SExpression),
             "json" => Ok(Format::Json),
@@ -38,7 +45,7 @@ impl Format {
     /// Get default file extension for this format
     pub fn default_extension(&self) -> &'static str {
         match self {
-            Format::Binary => "x",
+            Format::Binary | Format::OCaml => "x",
             Format::Haskell => "haskell.x",
             Format::SExpression => "lisp.x",
             Format::Json => "json",
@@ -50,6 +57,15 @@ impl Format {
         match self {
             Format::Haskell => Some(SyntaxStyle::SExpression), // Now maps to S-expression
             Format::SExpression => Some(SyntaxStyle::SExpression),
+            Format::Binary | Format::OCaml | Format::Json => None,
+        }
+    }
+    
+    /// Syntax of source text formats, which convert into each other directly
+    pub fn text_syntax(&self) -> Option<TextSyntax> {
+        match self {
+            Format::OCaml => Some(TextSyntax::OCaml),
+            Format::Haskell | Format::SExpression => Some(TextSyntax::SExp),
             Format::Binary | Format::Json => None,
         }
     }
@@ -57,10 +73,15 @@ impl Format {
 
 /// Detect format from file path
 pub fn detect_format(path: &Path) -> Result<Format> {
-    let path_str = path.to_string_lossy().to_lowercase();
+    // Only the file name counts, so dots in directory names do not matter
+    let path_str = path.file_name().map(|name| name.to_string_lossy().to_lowercase()).unwrap_or_default();
     
     if path_str.ends_with(".x") && path_str.matches('.').count() == 1 {
-        Ok(Format::Binary)
+        // An existing `.x` file without the binary header is source text
+        match fs::read(path) {
+            Ok(content) if !content.starts_with(&x_parser::binary::MAGIC_NUMBER) => Ok(Format::OCaml),
+            _ => Ok(Format::Binary),
+        }
     } else if path_str.ends_with(".lisp.x") || path_str.ends_with(".sexp.x") {
         Ok(Format::SExpression)
     } else if path_str.ends_with(".haskell.x") || path_str.ends_with(".hs.x") {
@@ -80,17 +101,39 @@ pub async fn load_ast(path: &Path, format: Format) -> Result<PersistentAstNode>
     match format {
         Format::Binary => load_binary_ast(&content),
         Format::Json => load_json_ast(&content),
+        Format::OCaml => load_ocaml_ast(&content),
         Format::SExpression | Format::Haskell => {
             load_text_ast(&content, format)
         }
     }
 }
 
-/// Save AST to file
-pub async fn save_ast(path: &Path, ast: &PersistentAstNode, format: Format) -> Result<()> {
+/// Load a compilation unit from source text or a binary AST, in the format
+/// its file name says
+pub fn load_compilation_unit(path: &Path) -> Result<x_parser::CompilationUnit> {
+    let format = detect_format(path)?;
+    if let Some(style) = format.text_syntax() {
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read file: {}", path.display()))?;
+        return x_parser::syntax::MultiSyntax::default().parse(&source, style, FileId::new(0))
+            .with_context(|| format!("Failed to parse: {}", path.display()));
+    }
+    if format != Format::Binary {
+        bail!("Not a source or binary AST file: {}", path.display());
+    }
+    let mapped = x_parser::binary::MappedBinary::open(path)
+        .with_context(|| format!("Failed to read file: {}", path.display()))?;
+    mapped.deserializer()
+        .and_then(|mut deserializer| deserializer.deserialize_compilation_unit())
+        .with_context(|| format!("Failed to deserialize: {}", path.display()))
+}
+
+/// Save AST to file, compressing the payload of binary output if requested
+pub async fn save_ast(path: &Path, ast: &PersistentAstNode, format: Format, compress: bool) -> Result<()> {
     let content = match format {
-        Format::Binary => save_binary_ast(ast)?,
+        Format::Binary => save_binary_ast(ast, compress)?,
         Format::Json => save_json_ast(&ast)?,
+        Format::OCaml => save_ocaml_ast(ast)?,
         Format::SExpression | Format::Haskell => {
             save_text_ast(&ast, format)?
         }
@@ -115,7 +158,7 @@ fn load_binary_ast(content: &[u8]) -> Result<PersistentAstNode> {
     }
     
     // Use the binary deserializer from x_parser
-    let mut deserializer = x_parser::binary::BinaryDeserializer::new(content.to_vec())
+    let mut deserializer = x_parser::binary::BinaryDeserializer::from_slice(content)
         .context("Failed to create binary deserializer")?;
     
     let compilation_unit = deserializer.deserialize_compilation_unit()
@@ -126,13 +169,16 @@ fn load_binary_ast(content: &[u8]) -> Result<PersistentAstNode> {
 }
 
 /// Save binary AST format
-fn save_binary_ast(ast: &PersistentAstNode) -> Result<Vec<u8>> {
+fn save_binary_ast(ast: &PersistentAstNode, compress: bool) -> Result<Vec<u8>> {
     // Convert PersistentAstNode to AST
     let compilation_unit = convert_persistent_to_ast(ast)
         .context("Failed to convert PersistentAstNode to AST")?;
     
     // Use the binary serializer from x_parser
     let mut serializer = x_parser::binary::BinarySerializer::new();
+    if compress {
+        serializer = serializer.with_compression();
+    }
     
     let binary_data = serializer.serialize_compilation_unit(&compilation_unit)
         .context("Failed to serialize compilation unit to binary")?;
@@ -159,6 +205,24 @@ fn save_json_ast(ast: &PersistentAstNode) -> Result<Vec<u8>> {
     Ok(json_str.into_bytes())
 }
 
+/// Load OCaml-like source text
+fn load_ocaml_ast(content: &[u8]) -> Result<PersistentAstNode> {
+    let text = std::str::from_utf8(content)
+        .context("Invalid UTF-8 in text file")?;
+    let compilation_unit = x_parser::parse_source(text, FileId::new(0), SyntaxStyle::default())
+        .context("Failed to parse source text")?;
+    convert_ast_to_persistent(&compilation_unit)
+}
+
+/// Save OCaml-like source text
+fn save_ocaml_ast(ast: &PersistentAstNode) -> Result<Vec<u8>> {
+    let compilation_unit = convert_persistent_to_ast(ast)?;
+    let config = SyntaxConfig { style: TextSyntax::OCaml, ..SyntaxConfig::default() };
+    let text = OCamlPrinter::new().print(&compilation_unit, &config)
+        .context("Failed to print source text")?;
+    Ok(text.into_bytes())
+}
+
 /// Load text AST format
 fn load_text_ast(content: &[u8], format: Format) -> Result<PersistentAstNode> {
     let _text = std::str::from_utf8(content)
@@ -172,7 +236,7 @@ fn load_text_ast(content: &[u8], format: Format) -> Result<PersistentAstNode> {
     let mut builder = NodeBuilder::new();
     
     let module = builder.build(
-        Span::new(FileId::new(0), x_parser::span::ByteOffset::new(0), x_parser::span::ByteOffset::new(0)),
+        Span::new(FileId::new(0), x_parser::span::CharOffset::new(0), x_parser::span::CharOffset::new(0)),
         AstNodeKind::Module {
             name: Symbol::intern("main"),
             items: Vec::new(),
@@ -181,7 +245,7 @@ fn load_text_ast(content: &[u8], format: Format) -> Result<PersistentAstNode> {
     );
     
     Ok(builder.build(
-        Span::new(FileId::new(0), x_parser::span::ByteOffset::new(0), x_parser::span::ByteOffset::new(0)),
+        Span::new(FileId::new(0), x_parser::span::CharOffset::new(0), x_parser::span::CharOffset::new(0)),
         AstNodeKind::CompilationUnit {
             modules: vec![module],
             imports: Vec::new(),
@@ -361,8 +425,8 @@ fn convert_persistent_expr_to_ast(expr_ast: &PersistentAstNode) -> Result<x_pars
         }
         AstNodeKind::Literal { value } => {
             let ast_literal = match value {
-                x_parser::persistent_ast::LiteralValue::Integer(n) => Literal::Integer(n.clone()),
-                x_parser::persistent_ast::LiteralValue::Float(f) => Literal::Float(f.clone()),
+                x_parser::persistent_ast::LiteralValue::Integer(n) => Literal::int(*n),
+                x_parser::persistent_ast::LiteralValue::Float(f) => Literal::float(*f),
                 x_parser::persistent_ast::LiteralValue::String(s) => Literal::String(s.clone()),
                 x_parser::persistent_ast::LiteralValue::Boolean(b) => Literal::Bool(b.clone()),
                 x_parser::persistent_ast::LiteralValue::Unit => Literal::Unit,
@@ -413,11 +477,11 @@ fn convert_persistent_pattern_to_ast(pattern_ast: &PersistentAstNode) -> Result<
 /// Convert Parameter to AST Pattern (helper function)
 fn convert_persistent_pattern_to_ast_from_param(param: &x_parser::persistent_ast::Parameter) -> Result<x_parser::ast::Pattern> {
     use x_parser::ast::*;
-    use x_parser::span::{FileId, ByteOffset};
+    use x_parser::span::{FileId, CharOffset};
     
     Ok(Pattern::Variable(
         param.name,
-        Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(1))
+        Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(1))
     ))
 }
 
@@ -1,144 +1,555 @@
-//! Interactive mode and REPL functionality
+//! Interactive AST editor
+//!
+//! `x edit --interactive` opens a terminal UI over an editing session. The
+//! left pane is the syntax tree of the module, the right pane the selected
+//! node with its inferred type and the type errors inside it, refreshed by
+//! the checker after every edit. Edits apply to the top-level item holding
+//! the selection.
+//!
+//! | Key | Action |
+//! |-----|--------|
+//! | `j` / `k`, arrows | Select the next / previous node |
+//! | `g` / `G` | Select the first / last node |
+//! | `/` | Search with the query language, e.g. `call[name="print"]` |
+//! | `n` / `N` | Select the next / previous match |
+//! | `d` | Delete the item |
+//! | `J` / `K` | Move the item down / up |
+//! | `c` | Change the item to newly typed source |
+//! | `u` / `Ctrl-r` | Undo / redo |
+//! | `w` | Write the module |
+//! | `q` | Quit, twice with unsaved changes |
 
-use anyhow::Result;
-use colored::*;
-use std::path::Path;
-use crate::utils::{print_header, get_user_input, select_option};
+use anyhow::{Context, Result};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use x_checker::TypeError;
+use x_editor::{
+    parse_query, run_query, syntax_tree, EditOperation, EditResult, LanguageServiceConfig,
+    QueryNode, SessionId, XLanguageEditor,
+};
+use x_editor::operations::EditableNode;
+use x_parser::ast::Item;
+use x_parser::syntax::{ocaml::OCamlPrinter, SyntaxConfig, SyntaxPrinter};
+use x_parser::{parse_source, FileId, Symbol, SyntaxStyle};
 
-/// Interactive AST editor
-#[allow(dead_code)]
-pub struct InteractiveEditor {
-    // TODO: Add AST engine and state
+/// Open `input` in the editor, writing to `output` (or back to `input`)
+pub fn run(input: &Path, output: Option<&Path>) -> Result<()> {
+    let source = fs::read_to_string(input)
+        .with_context(|| format!("Failed to read file: {}", input.display()))?;
+    let mut app = TuiApp::new(&source, output.unwrap_or(input).to_path_buf())?;
+
+    let mut terminal = ratatui::init();
+    let result = app.run(&mut terminal);
+    ratatui::restore();
+    result
 }
 
-impl InteractiveEditor {
-    pub fn new() -> Self {
-        Self {}
-    }
-    
-    /// Start interactive editing session
-    pub async fn start(&mut self, input_file: Option<&Path>) -> Result<()> {
-        print_header("x Language Interactive Editor");
-        
-        if let Some(file) = input_file {
-            println!("Loaded file: {}", file.display().to_string().cyan());
-        } else {
-            println!("Starting with empty AST");
-        }
-        
-        self.main_loop().await
-    }
-    
-    /// Main interactive loop
-    async fn main_loop(&mut self) -> Result<()> {
-        loop {
-            println!();
-            
-            let options = vec![
-                "Show AST",
-                "Query nodes",
-                "Edit AST",
-                "Rename symbol",
-                "Extract method",
-                "Save file",
-                "Load file",
-                "Help",
-                "Exit",
-            ];
-            
-            let choice = select_option("Choose an action:", &options);
-            
-            match choice {
-                Some(0) => self.show_ast().await?,
-                Some(1) => self.query_nodes().await?,
-                Some(2) => self.edit_ast().await?,
-                Some(3) => self.rename_symbol().await?,
-                Some(4) => self.extract_method().await?,
-                Some(5) => self.save_file().await?,
-                Some(6) => self.load_file().await?,
-                Some(7) => self.show_help(),
-                Some(8) | None => break,
-                _ => continue,
-            }
-        }
-        
-        println!("Goodbye!");
-        Ok(())
+/// A node of the tree view
+#[derive(Debug, Clone)]
+struct Row {
+    node: QueryNode,
+    depth: usize,
+    /// Index in the module of the item holding the node
+    item: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Mode {
+    Normal,
+    /// Typing a query, matched as it is typed
+    Search(String),
+    /// Typing the source of the item replacing the selected one
+    Change(String),
+}
+
+/// State of the editor, independent of the terminal
+pub struct TuiApp {
+    editor: XLanguageEditor,
+    session: SessionId,
+    path: PathBuf,
+    rows: Vec<Row>,
+    list: ListState,
+    types: HashMap<Symbol, String>,
+    errors: Vec<TypeError>,
+    mode: Mode,
+    query: Option<String>,
+    matches: Vec<usize>,
+    status: String,
+    dirty: bool,
+    confirm_quit: bool,
+    quit: bool,
+}
+
+impl TuiApp {
+    pub fn new(source: &str, path: PathBuf) -> Result<Self> {
+        let mut editor = XLanguageEditor::new(LanguageServiceConfig::default());
+        let session = editor.start_session(source)
+            .with_context(|| format!("Failed to parse: {}", path.display()))?;
+        let mut app = TuiApp {
+            editor,
+            session,
+            path,
+            rows: Vec::new(),
+            list: ListState::default().with_selected(Some(0)),
+            types: HashMap::new(),
+            errors: Vec::new(),
+            mode: Mode::Normal,
+            query: None,
+            matches: Vec::new(),
+            status: String::new(),
+            dirty: false,
+            confirm_quit: false,
+            quit: false,
+        };
+        app.refresh()?;
+        Ok(app)
     }
-    
-    async fn show_ast(&self) -> Result<()> {
-        println!("{}", "AST Structure:".bold());
-        println!("(Not yet implemented)");
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        while !self.quit {
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    self.handle_key(key);
+                }
+            }
+        }
         Ok(())
     }
-    
-    async fn query_nodes(&self) -> Result<()> {
-        if let Some(query) = get_user_input("Enter query") {
-            println!("Executing query: {}", query.cyan());
-            println!("(Not yet implemented)");
+
+    /// Rebuild the tree and re-run the checker after the session changed
+    fn refresh(&mut self) -> Result<()> {
+        let session = self.editor.get_session(self.session).context("The editing session was closed")?;
+        let module = &session.ast.module;
+
+        // Interface and module type items have no node in the tree
+        let mut items = module.items.iter().enumerate()
+            .filter(|(_, item)| !matches!(item, Item::ModuleTypeDef(_) | Item::InterfaceDef(_)))
+            .map(|(index, _)| index);
+        let nodes = syntax_tree(&session.ast);
+        let mut rows: Vec<Row> = Vec::with_capacity(nodes.len());
+        for (node, parent) in nodes {
+            let row = match parent {
+                None => Row { node, depth: 0, item: None },
+                Some(parent) if rows[parent].item.is_none() => Row { node, depth: 1, item: items.next() },
+                Some(parent) => Row { node, depth: rows[parent].depth + 1, item: rows[parent].item },
+            };
+            rows.push(row);
+        }
+        self.rows = rows;
+
+        let checked = self.editor.type_check_session(self.session)?;
+        self.types = checked.inferred_types.iter()
+            .map(|(name, scheme)| (*name, scheme.body.to_string()))
+            .collect();
+        self.errors = checked.errors;
+
+        let last = self.rows.len().saturating_sub(1);
+        self.list.select(Some(self.selected().min(last)));
+        if let Some(query) = self.query.clone() {
+            self.search(&query);
         }
         Ok(())
     }
-    
-    async fn edit_ast(&self) -> Result<()> {
-        println!("{}", "AST Editor:".bold());
-        println!("(Not yet implemented)");
-        Ok(())
+
+    fn selected(&self) -> usize {
+        self.list.selected().unwrap_or(0)
+    }
+
+    fn select(&mut self, index: usize) {
+        self.list.select(Some(index.min(self.rows.len().saturating_sub(1))));
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) {
+        match std::mem::replace(&mut self.mode, Mode::Normal) {
+            Mode::Normal => self.normal_key(key),
+            Mode::Search(text) => self.search_key(key, text),
+            Mode::Change(text) => self.change_key(key, text),
+        }
+    }
+
+    fn normal_key(&mut self, key: KeyEvent) {
+        let quitting = std::mem::take(&mut self.confirm_quit);
+        match key.code {
+            KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.history(|editor, session| editor.redo(session), "Nothing to redo")
+            }
+            KeyCode::Esc if self.query.is_some() => {
+                self.query = None;
+                self.matches.clear();
+                self.status.clear();
+            }
+            KeyCode::Char('q') | KeyCode::Esc => {
+                if self.dirty && !quitting {
+                    self.confirm_quit = true;
+                    self.status = "Unsaved changes; press q again to quit".to_string();
+                } else {
+                    self.quit = true;
+                }
+            }
+            KeyCode::Down | KeyCode::Char('j') => self.select(self.selected() + 1),
+            KeyCode::Up | KeyCode::Char('k') => self.select(self.selected().saturating_sub(1)),
+            KeyCode::Home | KeyCode::Char('g') => self.select(0),
+            KeyCode::End | KeyCode::Char('G') => self.select(self.rows.len()),
+            KeyCode::Char('/') => self.mode = Mode::Search(String::new()),
+            KeyCode::Char('n') => self.next_match(true),
+            KeyCode::Char('N') => self.next_match(false),
+            KeyCode::Char('d') => {
+                if let Some(item) = self.selected_item() {
+                    self.edit(EditOperation::delete(vec![0, item]));
+                }
+            }
+            KeyCode::Char('J') => self.move_item(1),
+            KeyCode::Char('K') => self.move_item(-1),
+            KeyCode::Char('c') => {
+                if let Some(item) = self.selected_item() {
+                    let session = self.editor.get_session(self.session).expect("the session is open");
+                    let text = OCamlPrinter::new()
+                        .print_item(&session.ast.module.items[item], &SyntaxConfig::default())
+                        .unwrap_or_default();
+                    self.mode = Mode::Change(text.trim().replace('\n', " "));
+                }
+            }
+            KeyCode::Char('u') => self.history(|editor, session| editor.undo(session), "Nothing to undo"),
+            KeyCode::Char('w') => self.write(),
+            _ => {}
+        }
     }
-    
-    async fn rename_symbol(&self) -> Result<()> {
-        if let Some(old_name) = get_user_input("Symbol to rename") {
-            if let Some(new_name) = get_user_input("New name") {
-                println!("Renaming {} → {}", old_name.cyan(), new_name.green());
-                println!("(Not yet implemented)");
+
+    fn search_key(&mut self, key: KeyEvent, mut text: String) {
+        match key.code {
+            KeyCode::Esc => {
+                self.query = None;
+                self.matches.clear();
+                self.status.clear();
+                return;
+            }
+            // The selection already follows the query
+            KeyCode::Enter => return,
+            KeyCode::Backspace => {
+                text.pop();
             }
+            KeyCode::Char(c) => text.push(c),
+            _ => {}
         }
-        Ok(())
+        self.search(&text);
+        // Jump to the first match at or after the selection as the query grows
+        if let Some(&first) = self.matches.iter().find(|&&row| row >= self.selected()).or(self.matches.first()) {
+            self.select(first);
+        }
+        self.mode = Mode::Search(text);
     }
-    
-    async fn extract_method(&self) -> Result<()> {
-        println!("{}", "Method Extraction:".bold());
-        println!("(Not yet implemented)");
-        Ok(())
+
+    fn change_key(&mut self, key: KeyEvent, mut text: String) {
+        match key.code {
+            KeyCode::Esc => return,
+            KeyCode::Enter => {
+                self.change(&text);
+                return;
+            }
+            KeyCode::Backspace => {
+                text.pop();
+            }
+            KeyCode::Char(c) => text.push(c),
+            _ => {}
+        }
+        self.mode = Mode::Change(text);
     }
-    
-    async fn save_file(&self) -> Result<()> {
-        if let Some(filename) = get_user_input("Save as") {
-            println!("Saving to: {}", filename.cyan());
-            println!("(Not yet implemented)");
+
+    /// Match `text` against the tree, keeping the matches for `n` and `N`
+    fn search(&mut self, text: &str) {
+        self.query = Some(text.to_string());
+        self.matches.clear();
+        if text.trim().is_empty() {
+            self.status.clear();
+            return;
         }
-        Ok(())
+        let pattern = match parse_query(text) {
+            Ok(pattern) => pattern,
+            Err(error) => {
+                self.status = error.to_string();
+                return;
+            }
+        };
+        let session = self.editor.get_session(self.session).expect("the session is open");
+        for found in run_query(&session.ast, &pattern) {
+            if let Some(row) = self.rows.iter().position(|row| row.node == found.node) {
+                self.matches.push(row);
+            }
+        }
+        self.status = format!("{} matches", self.matches.len());
     }
-    
-    async fn load_file(&self) -> Result<()> {
-        if let Some(filename) = get_user_input("Load file") {
-            println!("Loading: {}", filename.cyan());
-            println!("(Not yet implemented)");
+
+    fn next_match(&mut self, forward: bool) {
+        let current = self.selected();
+        let next = if forward {
+            self.matches.iter().find(|&&row| row > current).or(self.matches.first())
+        } else {
+            self.matches.iter().rev().find(|&&row| row < current).or(self.matches.last())
+        };
+        if let Some(&row) = next {
+            self.select(row);
         }
-        Ok(())
     }
-    
-    fn show_help(&self) {
-        println!("{}", "x Language Interactive Editor Help".bold().underline());
-        println!();
-        println!("{}", "Available Commands:".bold());
-        println!("  {} - Display the current AST structure", "Show AST".cyan());
-        println!("  {} - Execute queries against the AST", "Query nodes".cyan());
-        println!("  {} - Directly edit AST nodes", "Edit AST".cyan());
-        println!("  {} - Rename symbols throughout the tree", "Rename symbol".cyan());
-        println!("  {} - Extract code into a new method", "Extract method".cyan());
-        println!("  {} - Save the current AST to file", "Save file".cyan());
-        println!("  {} - Load AST from file", "Load file".cyan());
-        println!();
-        println!("{}", "Tips:".bold());
-        println!("  • All operations work directly on the AST structure");
-        println!("  • Changes are type-checked in real-time");
-        println!("  • Use Ctrl+C to cancel any operation");
+
+    fn selected_item(&mut self) -> Option<usize> {
+        let item = self.rows.get(self.selected()).and_then(|row| row.item);
+        if item.is_none() {
+            self.status = "Select a node inside an item to edit it".to_string();
+        }
+        item
+    }
+
+    fn move_item(&mut self, offset: isize) {
+        let Some(item) = self.selected_item() else { return };
+        let count = self.editor.get_session(self.session).map_or(0, |session| session.ast.module.items.len());
+        match item.checked_add_signed(offset).filter(|&dest| dest < count) {
+            Some(dest) => self.edit(EditOperation::move_node(vec![0, item], vec![0, dest])),
+            None => self.status = "The item cannot move further".to_string(),
+        }
+    }
+
+    fn change(&mut self, text: &str) {
+        let Some(item) = self.selected_item() else { return };
+        let parsed = parse_source(&format!("module Edit\n{text}"), FileId::new(0), SyntaxStyle::SExpression);
+        match parsed.map(|unit| unit.module.items) {
+            Ok(mut items) if items.len() == 1 => {
+                let new_item = items.remove(0);
+                self.edit(EditOperation::replace(vec![0, item], EditableNode::Item(new_item)));
+            }
+            Ok(items) => self.status = format!("Expected one item, found {}", items.len()),
+            Err(error) => self.status = error.to_string(),
+        }
+    }
+
+    fn edit(&mut self, operation: EditOperation) {
+        match self.editor.apply_operation(self.session, operation) {
+            Ok(result) => {
+                self.status = describe(&result);
+                self.dirty = true;
+                self.refresh_or_report();
+            }
+            Err(error) => self.status = error.to_string(),
+        }
+    }
+
+    fn history(
+        &mut self,
+        step: impl FnOnce(&mut XLanguageEditor, SessionId) -> Result<Option<EditResult>, x_editor::EditError>,
+        nothing: &str,
+    ) {
+        match step(&mut self.editor, self.session) {
+            Ok(Some(result)) => {
+                self.status = describe(&result);
+                self.dirty = true;
+                self.refresh_or_report();
+            }
+            Ok(None) => self.status = nothing.to_string(),
+            Err(error) => self.status = error.to_string(),
+        }
+    }
+
+    fn refresh_or_report(&mut self) {
+        if let Err(error) = self.refresh() {
+            self.status = format!("{error:#}");
+        }
+    }
+
+    fn write(&mut self) {
+        let session = self.editor.get_session(self.session).expect("the session is open");
+        let written = OCamlPrinter::new()
+            .print(&session.ast, &SyntaxConfig::default())
+            .map_err(anyhow::Error::from)
+            .and_then(|source| Ok(fs::write(&self.path, source)?));
+        self.status = match written {
+            Ok(()) => {
+                self.dirty = false;
+                format!("Wrote {}", self.path.display())
+            }
+            Err(error) => format!("Failed to write {}: {error:#}", self.path.display()),
+        };
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status, help] = Layout::vertical([Constraint::Min(1), Constraint::Length(1), Constraint::Length(1)])
+            .areas(frame.area());
+        let [tree, details] = Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(main);
+
+        let items: Vec<ListItem> = self.rows.iter().enumerate()
+            .map(|(index, row)| {
+                let mut spans = vec![
+                    Span::raw("  ".repeat(row.depth)),
+                    Span::styled(row.node.kind, Style::default().fg(Color::Cyan)),
+                ];
+                if let Some(name) = row.node.name {
+                    spans.push(Span::raw(format!(" {name}")));
+                }
+                if self.errors.iter().any(|error| row.item.is_some() && within(error, &row.node)) {
+                    spans.push(Span::styled(" !", Style::default().fg(Color::Red)));
+                }
+                let style = if self.matches.contains(&index) {
+                    Style::default().fg(Color::Yellow)
+                } else {
+                    Style::default()
+                };
+                ListItem::new(Line::from(spans)).style(style)
+            })
+            .collect();
+        let title = format!(" {}{} ", self.path.display(), if self.dirty { " [+]" } else { "" });
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, tree, &mut self.list);
+
+        let panel = Paragraph::new(self.details())
+            .block(Block::default().borders(Borders::ALL).title(" Node "))
+            .wrap(Wrap { trim: false });
+        frame.render_widget(panel, details);
+
+        let status_line = match &self.mode {
+            Mode::Normal => Line::from(self.status.as_str()),
+            Mode::Search(text) => Line::from(format!("/{text}")),
+            Mode::Change(text) => Line::from(format!("change: {text}")),
+        };
+        frame.render_widget(Paragraph::new(status_line), status);
+        frame.render_widget(
+            Paragraph::new("j/k select  / search  n/N match  d delete  J/K move  c change  u undo  ^r redo  w write  q quit")
+                .style(Style::default().add_modifier(Modifier::DIM)),
+            help,
+        );
+    }
+
+    /// Lines of the side panel for the selected node
+    fn details(&self) -> Vec<Line<'static>> {
+        let Some(row) = self.rows.get(self.selected()) else { return Vec::new() };
+        let mut lines = vec![Line::from(format!("kind: {}", row.node.kind))];
+        if let Some(name) = row.node.name {
+            lines.push(Line::from(format!("name: {name}")));
+            let typed = matches!(row.node.kind, "fn" | "value" | "var" | "call");
+            if let Some(typ) = self.types.get(&name).filter(|_| typed) {
+                lines.push(Line::from(format!("type: {typ}")));
+            }
+        }
+        let errors: Vec<&TypeError> = if row.item.is_some() {
+            self.errors.iter().filter(|error| within(error, &row.node)).collect()
+        } else {
+            self.errors.iter().collect()
+        };
+        if !errors.is_empty() {
+            lines.push(Line::from(""));
+            for error in errors {
+                lines.push(Line::styled(format!("{} {error}", error.code()), Style::default().fg(Color::Red)));
+            }
+        }
+        lines
     }
 }
 
-impl Default for InteractiveEditor {
-    fn default() -> Self {
-        Self::new()
+fn within(error: &TypeError, node: &QueryNode) -> bool {
+    let span = error.span();
+    node.span.start <= span.start && span.end <= node.span.end
+}
+
+fn describe(result: &EditResult) -> String {
+    match result {
+        EditResult::Inserted { path, .. } => format!("Inserted at {path:?}"),
+        EditResult::Deleted { path, .. } => format!("Deleted {path:?}"),
+        EditResult::Replaced { path, .. } => format!("Replaced {path:?}"),
+        EditResult::Moved { source_path, dest_path } => format!("Moved {source_path:?} to {dest_path:?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+    use tempfile::TempDir;
+
+    fn press(app: &mut TuiApp, keys: &str) {
+        for c in keys.chars() {
+            let code = if c == '\n' { KeyCode::Enter } else { KeyCode::Char(c) };
+            app.handle_key(KeyEvent::from(code));
+        }
+    }
+
+    fn app(dir: &TempDir) -> TuiApp {
+        let source = "module Main\nlet one = 1\nlet two = one + 1\nlet three = two + one";
+        TuiApp::new(source, dir.path().join("main.x")).unwrap()
+    }
+
+    fn selected(app: &TuiApp) -> (&'static str, Option<String>) {
+        let node = &app.rows[app.selected()].node;
+        (node.kind, node.name.map(|name| name.to_string()))
+    }
+
+    #[test]
+    fn test_search_selects_matches_incrementally() {
+        let dir = TempDir::new().unwrap();
+        let mut app = app(&dir);
+        press(&mut app, "/value[name=\"two\"]");
+        assert_eq!(selected(&app), ("value", Some("two".to_string())));
+
+        app.handle_key(KeyEvent::from(KeyCode::Esc));
+        press(&mut app, "g/var[name=\"one\"]\n");
+        assert_eq!(app.matches.len(), 2);
+        assert_eq!(app.mode, Mode::Normal);
+        let first = app.selected();
+        press(&mut app, "n");
+        assert!(app.selected() > first);
+        press(&mut app, "n");
+        assert_eq!(app.selected(), first);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_edits_apply_to_the_selected_item_with_undo() {
+        let dir = TempDir::new().unwrap();
+        let mut app = app(&dir);
+        press(&mut app, "/value[name=\"three\"]\nd");
+        assert!(app.rows.iter().all(|row| row.node.name != Some(Symbol::intern("three"))));
+        press(&mut app, "u");
+        assert_eq!(app.rows.iter().filter(|row| row.depth == 1).count(), 3);
+
+        // Deleting `one` leaves the others without a definition to refer to
+        press(&mut app, "gjd");
+        assert!(!app.errors.is_empty());
+        app.handle_key(KeyEvent::new(KeyCode::Char('r'), KeyModifiers::CONTROL));
+        press(&mut app, "u");
+        assert!(app.errors.is_empty());
+        assert_eq!(app.types[&Symbol::intern("three")], "Int");
+
+        press(&mut app, "gjJ");
+        let names: Vec<_> = app.rows.iter().filter(|row| row.depth == 1).map(|row| row.node.name.unwrap().to_string()).collect();
+        assert_eq!(names, ["two", "one", "three"]);
+
+        press(&mut app, "q");
+        assert!(!app.quit);
+        press(&mut app, "w");
+        assert!(fs::read_to_string(dir.path().join("main.x")).unwrap().contains("let one = 1"));
+        press(&mut app, "q");
+        assert!(app.quit);
+    }
+
+    #[test]
+    fn test_change_replaces_the_item_and_draws_its_type() {
+        let dir = TempDir::new().unwrap();
+        let mut app = app(&dir);
+        press(&mut app, "gjc");
+        assert_eq!(app.mode, Mode::Change("let one = 1".to_string()));
+        app.handle_key(KeyEvent::from(KeyCode::Backspace));
+        press(&mut app, "\"one\"\n");
+        assert!(!app.errors.is_empty(), "adding a string to an int is an error");
+
+        let mut terminal = Terminal::new(TestBackend::new(100, 12)).unwrap();
+        terminal.draw(|frame| app.draw(frame)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect();
+        assert!(screen.contains("type: String"), "{screen}");
+    }
+}
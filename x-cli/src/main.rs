@@ -14,15 +14,26 @@ mod commands;
 mod config;
 mod format;
 mod interactive;
+mod manifest;
 mod utils;
 mod version_db;
 
+/// Counts allocations so `x compile --profile` can report peak memory
+#[global_allocator]
+static ALLOCATOR: x_compiler::profiling::TrackingAllocator = x_compiler::profiling::TrackingAllocator;
+
 use commands::*;
 use commands::hash::HashArgs;
 use commands::version::VersionArgs;
 use commands::imports::ImportsArgs;
 use commands::outdated::OutdatedArgs;
 use commands::namespace_cli::NamespaceCommand;
+use commands::migrate::MigrateArgs;
+use commands::fmt::FmtArgs;
+use commands::diff::DiffArgs;
+use commands::lint::LintArgs;
+use commands::bench::BenchArgs;
+use commands::completions::{CompleteArgs, CompletionsArgs};
 use config::CliConfig;
 
 /// x Language CLI - Direct AST manipulation and conversion tools
@@ -67,13 +78,16 @@ pub enum Commands {
         /// Target format (auto-detect from output extension if not specified)
         #[arg(long)]
         to: Option<String>,
+        /// Compress the payload when writing the binary format
+        #[arg(long)]
+        compress: bool,
     },
     
     /// Display AST information
     Show {
         /// Input file
         input: PathBuf,
-        /// Display format (tree, json, summary, compact, haskell, sexp)
+        /// Display format (tree, json, summary, compact, haskell, sexp, dot, mermaid)
         #[arg(short, long, default_value = "tree")]
         format: String,
         /// Maximum depth to display
@@ -85,14 +99,21 @@ pub enum Commands {
         /// Show spans
         #[arg(long)]
         spans: bool,
+        /// Render a graph instead of the AST (imports, calls); use with --format dot or mermaid
+        #[arg(long)]
+        graph: Option<String>,
     },
     
     /// Query AST nodes
     Query {
         /// Input file
         input: PathBuf,
-        /// Query expression
-        query: String,
+        /// Query expression, e.g. `fn[name~"parse*"] > match` (or legacy `type:`/`symbol:`)
+        #[arg(required_unless_present = "references")]
+        query: Option<String>,
+        /// Find every reference to a top-level value (`name` or `Module.name`) across the project
+        #[arg(long, value_name = "SYMBOL", conflicts_with = "query")]
+        references: Option<String>,
         /// Output format (json, table, tree)
         #[arg(short, long, default_value = "table")]
         format: String,
@@ -106,7 +127,7 @@ pub enum Commands {
         #[arg(short, long)]
         output: Option<PathBuf>,
         /// Edit commands file or inline command
-        #[arg(short, long)]
+        #[arg(long)]
         commands: Option<String>,
         /// Interactive mode
         #[arg(short, long)]
@@ -151,18 +172,63 @@ pub enum Commands {
         /// Check only (don't show types)
         #[arg(long)]
         quiet: bool,
+        /// Treat the input as a binary AST and verify its checksums
+        #[arg(long)]
+        binary: bool,
+        /// Diagnostic output format (text, json)
+        #[arg(short, long, default_value = "text")]
+        format: String,
+        /// Apply suggested fixes to the input file before reporting
+        #[arg(long)]
+        fix: bool,
+        /// Check only this definition and what it depends on
+        #[arg(long, value_name = "NAME", conflicts_with_all = ["binary", "fix"])]
+        only: Option<String>,
     },
     
     /// Compile to target language
     Compile {
         /// Input file
         input: PathBuf,
-        /// Target language (typescript, wasm, wasm-component)
+        /// Target language (typescript, javascript, wasm, wasm-component)
         #[arg(short, long, default_value = "typescript")]
         target: String,
         /// Output directory
         #[arg(short, long, default_value = "./dist")]
         output: PathBuf,
+        /// Feature enabled for `import ... when feature "name"` (repeatable)
+        #[arg(long = "feature")]
+        features: Vec<String>,
+        /// Optimization level from 0 to 3, over x.toml and X_OPTIMIZATION_LEVEL
+        #[arg(short = 'O', long = "opt-level")]
+        opt_level: Option<u8>,
+        /// Emit debug information
+        #[arg(long)]
+        debug_info: bool,
+        /// Emit source maps
+        #[arg(long)]
+        source_maps: bool,
+        /// Generate code twice and fail unless both runs are byte-identical
+        #[arg(long)]
+        deterministic: bool,
+        /// Print where the time and memory of the compilation went
+        #[arg(long)]
+        profile: bool,
+        /// Write the profile as Chrome tracing JSON to this file
+        #[arg(long, value_name = "FILE")]
+        profile_trace: Option<PathBuf>,
+        /// List the passes run over the IR of each module, in order
+        #[arg(long)]
+        print_passes: bool,
+        /// Print how long each pass took on each module
+        #[arg(long)]
+        time_passes: bool,
+        /// Give up once the compilation has taken this many seconds
+        #[arg(long, value_name = "SECONDS")]
+        timeout: Option<u64>,
+        /// Also write intermediate artifacts for each module: ast, typed-ast, ir
+        #[arg(long, value_name = "KIND", value_delimiter = ',')]
+        emit: Vec<String>,
     },
     
     /// Start interactive REPL
@@ -185,13 +251,26 @@ pub enum Commands {
         port: u16,
     },
     
-    /// Analyze project statistics
+    /// Serve editing sessions to agents over JSON-RPC
+    Serve {
+        /// Transport (stdio, tcp)
+        #[arg(long, default_value = "stdio")]
+        transport: String,
+        /// TCP port (for tcp transport)
+        #[arg(long, default_value = "9259")]
+        port: u16,
+    },
+    
+    /// Measure complexity, effect use and purity of every definition
     Stats {
         /// Input file or directory
         input: PathBuf,
         /// Output format (json, table)
         #[arg(short, long, default_value = "table")]
         format: String,
+        /// Print the call graph of the input file, as DOT or with `--format json` as JSON
+        #[arg(long)]
+        call_graph: bool,
     },
     
     /// Run tests with content-addressed caching
@@ -217,6 +296,18 @@ pub enum Commands {
         /// Test timeout in seconds
         #[arg(long, default_value = "60")]
         timeout: u64,
+        /// Run the ```x examples in doc comments instead of test definitions
+        #[arg(long)]
+        doc: bool,
+        /// Mutate the definitions under test and report the mutants no test catches
+        #[arg(long, conflicts_with = "doc")]
+        mutate: bool,
+        /// Report which lines, branches and functions the tests run
+        #[arg(long, conflicts_with_all = ["doc", "mutate"])]
+        coverage: bool,
+        /// Also write the coverage as LCOV to this file
+        #[arg(long, value_name = "FILE", requires = "coverage")]
+        lcov: Option<PathBuf>,
     },
     
     /// Generate documentation and semantic summaries
@@ -240,6 +331,28 @@ pub enum Commands {
     
     /// Git-like namespace management
     Namespace(NamespaceCommand),
+    
+    /// Migrate a project to a different syntax style
+    Migrate(MigrateArgs),
+    
+    /// Format source files in their canonical layout
+    Fmt(FmtArgs),
+    
+    /// Compare two source files, as text or item by item
+    Diff(DiffArgs),
+    
+    /// Check sources against the lint rules configured in x.toml
+    Lint(LintArgs),
+    
+    /// Run benchmarks, skipping those unchanged since the stored baseline
+    Bench(BenchArgs),
+    
+    /// Print a shell completion script, completing targets, formats and test names too
+    Completions(CompletionsArgs),
+    
+    /// Print the values the completion scripts offer
+    #[command(name = "__complete", hide = true)]
+    Complete(CompleteArgs),
 }
 
 #[tokio::main]
@@ -257,14 +370,18 @@ async fn main() -> Result<()> {
         Commands::New { name, dir } => {
             new_command(&name, dir.as_deref()).await
         },
-        Commands::Convert { input, output, from, to } => {
-            convert_command(&input, output.as_deref(), from.as_deref(), to.as_deref()).await
+        Commands::Convert { input, output, from, to, compress } => {
+            convert_command(&input, output.as_deref(), from.as_deref(), to.as_deref(), compress).await
         },
-        Commands::Show { input, format, depth, types, spans } => {
-            show_command(&input, &format, depth, types, spans).await
+        Commands::Show { input, format, depth, types, spans, graph } => {
+            show_command(&input, &format, depth, types, spans, graph.as_deref()).await
         },
-        Commands::Query { input, query, format } => {
-            query_command(&input, &query, &format).await
+        Commands::Query { input, query, references, format } => {
+            match (references, query) {
+                (Some(symbol), _) => references_command(&input, &symbol, &format),
+                (None, Some(query)) => query_command(&input, &query, &format).await,
+                (None, None) => unreachable!("clap requires a query or --references"),
+            }
         },
         Commands::Edit { input, output, commands, interactive } => {
             edit_command(&input, output.as_deref(), commands.as_deref(), interactive).await
@@ -274,16 +391,24 @@ async fn main() -> Result<()> {
             println!("Rename command not yet implemented");
             Ok(())
         },
-        Commands::Extract { input: _, start: _, end: _, name: _, output: _ } => {
-            // extract_command(&input, &start, &end, &name, output.as_deref()).await
-            println!("Extract command not yet implemented");
-            Ok(())
+        Commands::Extract { input, start, end, name, output } => {
+            extract_command(&input, &start, &end, &name, output.as_deref()).await
         },
-        Commands::Check { input, detailed, quiet } => {
-            check_command(&input, detailed, quiet).await
+        Commands::Check { input, detailed, quiet, binary, format, fix, only } => {
+            check_command(&input, detailed, quiet, binary, &format, fix, only.as_deref()).await
         },
-        Commands::Compile { input, target, output } => {
-            compile_command(&input, &target, &output).await
+        Commands::Compile { input, target, output, features, opt_level, debug_info, source_maps, deterministic, profile, profile_trace, print_passes, time_passes, timeout, emit } => {
+            let flags = x_compiler::PartialConfig {
+                optimization_level: opt_level,
+                debug_info: debug_info.then_some(true),
+                source_maps: source_maps.then_some(true),
+                deterministic: deterministic.then_some(true),
+                features: Some(features),
+                ..Default::default()
+            };
+            let profile = ProfileOptions { print: profile, trace: profile_trace, print_passes, time_passes };
+            let timeout = timeout.map(std::time::Duration::from_secs);
+            compile_command(&input, &target, &output, flags, profile, timeout, &emit).await
         },
         Commands::Repl { preload, syntax } => {
             repl_command(preload.as_deref(), &syntax).await
@@ -291,11 +416,26 @@ async fn main() -> Result<()> {
         Commands::Lsp { mode, port } => {
             lsp_command(&mode, port).await
         },
-        Commands::Stats { input, format } => {
-            stats_command(&input, &format).await
+        Commands::Serve { transport, port } => {
+            serve_command(&transport, port).await
+        },
+        Commands::Stats { input, format, call_graph } => {
+            if call_graph {
+                call_graph_command(&input, &format)
+            } else {
+                stats_command(&input, &format).await
+            }
         },
-        Commands::Test { path, filter, force, threads, verbose, reporter, timeout } => {
-            test_command(&path, filter.as_deref(), force, threads, verbose, &reporter, timeout).await
+        Commands::Test { path, filter, force, threads, verbose, reporter, timeout, doc, mutate, coverage, lcov } => {
+            if doc {
+                doctest_command(&path, filter.as_deref(), verbose)
+            } else if mutate {
+                mutation_command(&path, filter.as_deref(), force, verbose)
+            } else if coverage {
+                coverage_command(&path, lcov.as_deref(), verbose)
+            } else {
+                test_command(&path, filter.as_deref(), force, threads, verbose, &reporter, timeout).await
+            }
         },
         Commands::Doc(cmd) => {
             cmd.run().map_err(Into::into)
@@ -318,6 +458,27 @@ async fn main() -> Result<()> {
         Commands::Namespace(cmd) => {
             namespace_command(cmd)
         },
+        Commands::Migrate(args) => {
+            migrate::run(args).await
+        },
+        Commands::Fmt(args) => {
+            fmt::run(args).await
+        },
+        Commands::Diff(args) => {
+            diff::run(args).await
+        },
+        Commands::Lint(args) => {
+            lint::run(args).await
+        },
+        Commands::Bench(args) => {
+            bench::run(args).await
+        },
+        Commands::Completions(args) => {
+            completions::run(args).await
+        },
+        Commands::Complete(args) => {
+            completions::complete(args).await
+        },
     };
     
     match result {
@@ -343,6 +504,7 @@ fn init_logging(verbose: bool) -> Result<()> {
     tracing_subscriber::fmt()
         .with_max_level(level)
         .with_target(false)
+        .with_writer(std::io::stderr)
         .init();
     
     Ok(())
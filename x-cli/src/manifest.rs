@@ -0,0 +1,551 @@
+//! Project manifests (`x.toml`) and dependency resolution
+//!
+//! ```toml
+//! [package]
+//! name = "app"
+//! version = "0.1.0"
+//!
+//! [dependencies]
+//! utils = { path = "../utils", version = "^1.0" }
+//!
+//! [lints]
+//! unused-variable = "deny"
+//! W0003 = "allow"
+//! ```
+//!
+//! Only path dependencies are resolved for now. Every text `.x` file under a
+//! package's directory is one of its modules, except those belonging to a
+//! package nested inside it. A module may import modules of its own package,
+//! of the package's direct dependencies and of the standard library; a
+//! version written in an import (`import Utils@^1.0.0`) must accept the
+//! version of the package providing the module.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use x_checker::{ModuleInterface, TypeChecker};
+use x_editor::{DependencyGraph, ModuleNode, PackageNode, Severity};
+use x_parser::versioning::{Version, VersionSpec};
+use x_parser::{parse_source, CompilationUnit, FileId, ImportKind, Parser, SyntaxStyle};
+
+/// File name of the manifest at the root of every package
+pub const MANIFEST_FILE: &str = "x.toml";
+
+/// Contents of `x.toml`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub package: Package,
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, Dependency>,
+    /// Severity of lint rules, by rule name or code
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub lints: BTreeMap<String, Severity>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Package {
+    pub name: String,
+    pub version: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub authors: Vec<String>,
+}
+
+/// A dependency, either a bare version requirement or a table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Dependency {
+    Version(String),
+    Detailed {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        version: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        path: Option<PathBuf>,
+    },
+}
+
+impl Dependency {
+    /// Version requirement the dependency must satisfy
+    pub fn version(&self) -> Option<&str> {
+        match self {
+            Dependency::Version(version) => Some(version),
+            Dependency::Detailed { version, .. } => version.as_deref(),
+        }
+    }
+
+    /// Directory of a path dependency, relative to the depending package
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Dependency::Version(_) => None,
+            Dependency::Detailed { path, .. } => path.as_deref(),
+        }
+    }
+}
+
+impl Manifest {
+    /// Manifest of a new package without dependencies
+    pub fn new(name: &str) -> Self {
+        Self {
+            package: Package {
+                name: name.to_string(),
+                version: "0.1.0".to_string(),
+                authors: Vec::new(),
+            },
+            dependencies: BTreeMap::new(),
+            lints: BTreeMap::new(),
+        }
+    }
+
+    /// Load and validate a manifest
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read manifest: {}", path.display()))?;
+        let manifest: Manifest = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse manifest: {}", path.display()))?;
+        manifest.version()
+            .with_context(|| format!("Invalid manifest: {}", path.display()))?;
+        Ok(manifest)
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string_pretty(self).context("Failed to serialize manifest")
+    }
+
+    /// The package's version
+    pub fn version(&self) -> Result<Version> {
+        Version::parse(&self.package.version)
+            .ok_or_else(|| anyhow!("invalid version `{}` of package `{}`", self.package.version, self.package.name))
+    }
+}
+
+/// A package of the project, with its modules by name
+#[derive(Debug, Clone)]
+pub struct ResolvedPackage {
+    pub name: String,
+    pub version: Version,
+    pub root: PathBuf,
+    /// Packages it depends on directly, with their version requirements
+    pub dependencies: BTreeMap<String, Option<String>>,
+    pub modules: BTreeMap<String, PathBuf>,
+}
+
+/// A module taking part in a build
+#[derive(Debug, Clone)]
+pub struct ModuleSource {
+    pub name: String,
+    /// Name of the package providing it
+    pub package: String,
+    pub path: PathBuf,
+    pub source: String,
+}
+
+/// A package together with all the packages it depends on
+#[derive(Debug, Clone)]
+pub struct Project {
+    /// The root package first, then its dependencies
+    pub packages: Vec<ResolvedPackage>,
+}
+
+impl Project {
+    /// The project whose manifest governs `path`, found in its directory or an ancestor
+    pub fn discover(path: &Path) -> Result<Option<Self>> {
+        let path = path.canonicalize()
+            .with_context(|| format!("Failed to resolve {}", path.display()))?;
+        match path.ancestors().find(|dir| dir.join(MANIFEST_FILE).is_file()) {
+            Some(root) => Self::load(root).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Load the package at `root` and resolve its dependencies
+    pub fn load(root: &Path) -> Result<Self> {
+        let root = root.canonicalize()
+            .with_context(|| format!("Failed to resolve {}", root.display()))?;
+        let manifest = Manifest::load(&root.join(MANIFEST_FILE))?;
+
+        let mut packages: Vec<ResolvedPackage> = Vec::new();
+        let mut pending = vec![(root, manifest)];
+        while let Some((package_root, package_manifest)) = pending.pop() {
+            if packages.iter().any(|package| package.root == package_root) {
+                continue;
+            }
+            let name = &package_manifest.package.name;
+            if packages.iter().any(|package| package.name == *name) {
+                bail!("package `{name}` is found at two paths; one of them is {}", package_root.display());
+            }
+
+            for (dependency, spec) in &package_manifest.dependencies {
+                let path = spec.path().ok_or_else(|| {
+                    anyhow!("dependency `{dependency}` of `{name}` has no path; only path dependencies are supported")
+                })?;
+                let dependency_root = package_root.join(path).canonicalize()
+                    .with_context(|| format!("Dependency `{dependency}` of `{name}` not found at {}", path.display()))?;
+                let dependency_manifest = Manifest::load(&dependency_root.join(MANIFEST_FILE))?;
+                if dependency_manifest.package.name != *dependency {
+                    bail!(
+                        "dependency `{dependency}` of `{name}` points to package `{}`",
+                        dependency_manifest.package.name,
+                    );
+                }
+                if let Some(requirement) = spec.version() {
+                    let version = dependency_manifest.version()?;
+                    if !parse_requirement(requirement)?.matches(&version) {
+                        bail!("`{name}` requires `{dependency}` {requirement}, but {} has version {version}", path.display());
+                    }
+                }
+                pending.push((dependency_root, dependency_manifest));
+            }
+
+            packages.push(ResolvedPackage {
+                name: name.clone(),
+                version: package_manifest.version()?,
+                modules: find_modules(&package_root)?,
+                dependencies: package_manifest.dependencies.iter()
+                    .map(|(name, dependency)| (name.clone(), dependency.version().map(str::to_string)))
+                    .collect(),
+                root: package_root,
+            });
+        }
+
+        let mut seen: BTreeMap<&str, &str> = BTreeMap::new();
+        for package in &packages {
+            for module in package.modules.keys() {
+                if let Some(other) = seen.insert(module, &package.name) {
+                    bail!("module `{module}` is defined by both `{other}` and `{}`", package.name);
+                }
+            }
+        }
+
+        Ok(Self { packages })
+    }
+
+    /// Every module `unit` imports from the project, directly or not, each
+    /// after the modules it imports
+    pub fn imported_modules(&self, entry: &Path, unit: &CompilationUnit) -> Result<Vec<ModuleSource>> {
+        let entry = entry.canonicalize()
+            .with_context(|| format!("Failed to resolve {}", entry.display()))?;
+        let package = self.package_of(&entry);
+        let mut visited = HashSet::from([unit.module.name.to_string()]);
+        let mut modules = Vec::new();
+        self.collect_imports(package, unit, &mut visited, &mut modules)?;
+        Ok(modules)
+    }
+
+    fn collect_imports(
+        &self,
+        package: &ResolvedPackage,
+        unit: &CompilationUnit,
+        visited: &mut HashSet<String>,
+        modules: &mut Vec<ModuleSource>,
+    ) -> Result<()> {
+        for import in &unit.module.imports {
+            let name = import.module_path.to_string();
+            if x_compiler::stdlib::is_stdlib_module(&name) {
+                continue;
+            }
+            let (provider, path) = match self.resolve_import(package, &name) {
+                Ok(resolved) => resolved,
+                // Only needed for some targets, which may provide the module themselves
+                Err(_) if matches!(import.kind, ImportKind::Conditional(_)) => continue,
+                Err(error) => return Err(error),
+            };
+            let requirements = import.version_spec.iter().chain(match &import.kind {
+                ImportKind::Selective(items) => items.iter().filter_map(|item| item.version_spec.as_ref()).collect(),
+                _ => Vec::new(),
+            });
+            for requirement in requirements {
+                if !parse_requirement(requirement)?.matches(&provider.version) {
+                    bail!(
+                        "`import {name}@{requirement}` does not accept version {} of package `{}`",
+                        provider.version,
+                        provider.name,
+                    );
+                }
+            }
+            if !visited.insert(name.clone()) {
+                continue;
+            }
+
+            let source = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            let parsed = parse_source(&source, FileId::new(0), SyntaxStyle::default())
+                .with_context(|| format!("Failed to parse {}", path.display()))?;
+            self.collect_imports(provider, &parsed, visited, modules)?;
+            modules.push(ModuleSource { name, package: provider.name.clone(), path: path.clone(), source });
+        }
+        Ok(())
+    }
+
+    /// The module `name` as seen from `package`, with the package providing it
+    fn resolve_import<'a>(&'a self, package: &'a ResolvedPackage, name: &str) -> Result<(&'a ResolvedPackage, &'a PathBuf)> {
+        std::iter::once(package)
+            .chain(package.dependencies.keys().filter_map(|dependency| {
+                self.packages.iter().find(|package| package.name == *dependency)
+            }))
+            .find_map(|provider| provider.modules.get(name).map(|path| (provider, path)))
+            .ok_or_else(|| anyhow!("module `{name}` is not part of `{}` or its dependencies", package.name))
+    }
+
+    /// Graph of the packages alone
+    pub fn package_graph(&self) -> DependencyGraph {
+        let mut graph = DependencyGraph::new();
+        for package in &self.packages {
+            graph.add_package(PackageNode {
+                name: package.name.clone(),
+                version: package.version.clone(),
+                dependencies: package.dependencies.clone(),
+            });
+        }
+        graph
+    }
+
+    /// Graph of the packages and of `unit` with the modules it imports,
+    /// directly or not, standard library modules included
+    pub fn dependency_graph(&self, entry: &Path, unit: &CompilationUnit) -> Result<DependencyGraph> {
+        let mut graph = module_graph(unit, Some(self.package_of(&entry.canonicalize()?).name.clone()));
+        for package in self.package_graph().packages() {
+            graph.add_package(package.clone());
+        }
+        for module in self.imported_modules(entry, unit)? {
+            let parsed = parse_source(&module.source, FileId::new(0), SyntaxStyle::default())
+                .with_context(|| format!("Failed to parse {}", module.path.display()))?;
+            graph.add_module(ModuleNode::from_unit(&parsed, Some(module.package)));
+        }
+        Ok(graph)
+    }
+
+    /// Versions of each dependency found locally: the dependency itself and
+    /// packages of the same name in the directories next to it
+    pub fn available_versions(&self) -> BTreeMap<String, Vec<Version>> {
+        let mut available: BTreeMap<String, Vec<Version>> = BTreeMap::new();
+        for package in &self.packages[1..] {
+            let versions = available.entry(package.name.clone()).or_default();
+            versions.push(package.version.clone());
+            let Some(Ok(siblings)) = package.root.parent().map(fs::read_dir) else { continue };
+            for sibling in siblings.flatten() {
+                let Ok(manifest) = Manifest::load(&sibling.path().join(MANIFEST_FILE)) else { continue };
+                if manifest.package.name == package.name {
+                    versions.extend(manifest.version().ok());
+                }
+            }
+            versions.sort();
+            versions.dedup();
+        }
+        available
+    }
+
+    /// The innermost package containing `path`
+    fn package_of(&self, path: &Path) -> &ResolvedPackage {
+        self.packages.iter()
+            .filter(|package| path.starts_with(&package.root))
+            .max_by_key(|package| package.root.components().count())
+            .unwrap_or(&self.packages[0])
+    }
+}
+
+/// A type checker for `unit` that resolves imports of the modules of the
+/// project `entry` belongs to, besides the standard library
+pub fn project_checker(entry: &Path, unit: &CompilationUnit) -> Result<TypeChecker> {
+    Ok(with_modules(&project_modules(entry, unit)?))
+}
+
+/// Interfaces of the library modules and of the project modules `unit`
+/// imports, by path
+pub fn project_modules(entry: &Path, unit: &CompilationUnit) -> Result<Vec<(String, ModuleInterface)>> {
+    let mut interfaces: Vec<(String, ModuleInterface)> = x_compiler::stdlib::interfaces().iter()
+        .map(|(path, interface)| (path.to_string(), interface.clone()))
+        .collect();
+    let Some(project) = Project::discover(entry)? else {
+        return Ok(interfaces);
+    };
+    for module in project.imported_modules(entry, unit)? {
+        let parsed = parse_source(&module.source, FileId::new(0), SyntaxStyle::default())
+            .with_context(|| format!("Failed to parse {}", module.path.display()))?;
+        let mut checker = with_modules(&interfaces);
+        let parsed = with_imported_fixities(&checker, &module.source, parsed)
+            .with_context(|| format!("Failed to parse {}", module.path.display()))?;
+        let result = checker.check_compilation_unit(&parsed);
+        if !result.errors.is_empty() {
+            bail!("{}: {} type error(s) found", module.path.display(), result.errors.len());
+        }
+        interfaces.push((module.name, result.module_interface(&parsed.module)));
+    }
+    Ok(interfaces)
+}
+
+/// `unit`, parsed from `source`, or a parse of `source` with the fixities of
+/// the operators it imports when it imports any
+pub fn with_imported_fixities(checker: &TypeChecker, source: &str, unit: CompilationUnit) -> Result<CompilationUnit> {
+    let fixities = checker.imported_fixities(&unit.module);
+    if fixities.is_empty() {
+        return Ok(unit);
+    }
+    Ok(Parser::new(source, unit.span.file_id)?.with_fixities(fixities).parse()?)
+}
+
+fn with_modules(interfaces: &[(String, ModuleInterface)]) -> TypeChecker {
+    interfaces.iter().fold(TypeChecker::new(), |checker, (path, interface)| {
+        checker.with_module(path.clone(), interface.clone())
+    })
+}
+
+/// Graph of `unit` and the standard library modules, for a file outside any
+/// project or as the start of a project's graph
+pub fn module_graph(unit: &CompilationUnit, package: Option<String>) -> DependencyGraph {
+    let mut graph = DependencyGraph::new();
+    graph.add_module(ModuleNode::from_unit(unit, package));
+    for (path, _) in x_compiler::stdlib::MODULES {
+        if let Some(unit) = x_compiler::stdlib::parse(path) {
+            graph.add_module(ModuleNode::from_unit(&unit, None));
+        }
+    }
+    graph
+}
+
+fn parse_requirement(requirement: &str) -> Result<VersionSpec> {
+    VersionSpec::parse(requirement).ok_or_else(|| anyhow!("invalid version requirement `{requirement}`"))
+}
+
+/// Modules of the package at `root`, skipping build output, hidden
+/// directories and nested packages; binary ASTs are not modules
+fn find_modules(root: &Path) -> Result<BTreeMap<String, PathBuf>> {
+    let mut modules = BTreeMap::new();
+    let mut directories = vec![root.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        let entries = fs::read_dir(&directory)
+            .with_context(|| format!("Failed to read directory: {}", directory.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            let file_name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+            if path.is_dir() {
+                let skipped = file_name.starts_with('.') || matches!(file_name, "dist" | "target" | "node_modules");
+                if !skipped && !path.join(MANIFEST_FILE).is_file() {
+                    directories.push(path);
+                }
+            } else if path.extension().is_some_and(|extension| extension == "x") {
+                let Ok(source) = fs::read_to_string(&path) else { continue };
+                let Ok(parsed) = parse_source(&source, FileId::new(0), SyntaxStyle::default()) else {
+                    continue;
+                };
+                modules.insert(parsed.module.name.to_string(), path);
+            }
+        }
+    }
+    Ok(modules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write(dir: &Path, file: &str, content: &str) {
+        let path = dir.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    /// `app` depending on `utils` 1.2.0, which depends on `base`
+    fn workspace() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        write(dir.path(), "app/x.toml", "[package]\nname = \"app\"\nversion = \"0.1.0\"\n\n[dependencies]\nutils = { path = \"../utils\", version = \"^1.0\" }\n");
+        write(dir.path(), "app/main.x", "module Main\nimport Utils@^1.1.0\nimport Core.List\nlet main = Utils.twice 2\n");
+        write(dir.path(), "utils/x.toml", "[package]\nname = \"utils\"\nversion = \"1.2.0\"\n\n[dependencies]\nbase = { path = \"../base\" }\n");
+        write(dir.path(), "utils/src/utils.x", "module Utils\nimport Base\npub let twice = fun x -> Base.double x\n");
+        write(dir.path(), "base/x.toml", "[package]\nname = \"base\"\nversion = \"0.3.0\"\n");
+        write(dir.path(), "base/base.x", "module Base\npub let double = fun x -> x + x\n");
+        dir
+    }
+
+    fn imports(dir: &Path, entry: &str) -> Result<Vec<String>> {
+        let entry = dir.join(entry);
+        let project = Project::discover(&entry)?.unwrap();
+        let source = fs::read_to_string(&entry).unwrap();
+        let unit = parse_source(&source, FileId::new(0), SyntaxStyle::default())?;
+        Ok(project.imported_modules(&entry, &unit)?.into_iter().map(|module| module.name).collect())
+    }
+
+    #[test]
+    fn test_path_dependencies_resolve_imports() {
+        let dir = workspace();
+        let project = Project::discover(&dir.path().join("app/main.x")).unwrap().unwrap();
+        let names: Vec<&str> = project.packages.iter().map(|package| package.name.as_str()).collect();
+        assert_eq!(names, ["app", "utils", "base"]);
+        assert_eq!(project.packages[1].modules.keys().collect::<Vec<_>>(), ["Utils"]);
+
+        assert_eq!(imports(dir.path(), "app/main.x").unwrap(), ["Base", "Utils"]);
+    }
+
+    #[test]
+    fn test_imports_are_checked_against_the_manifest() {
+        let dir = workspace();
+        // Base is only a dependency of utils
+        write(dir.path(), "app/main.x", "module Main\nimport Base\nlet main = 1\n");
+        let error = imports(dir.path(), "app/main.x").unwrap_err().to_string();
+        assert!(error.contains("module `Base` is not part of `app`"), "{error}");
+
+        write(dir.path(), "app/main.x", "module Main\nimport Utils@^2.0.0\nlet main = 1\n");
+        let error = imports(dir.path(), "app/main.x").unwrap_err().to_string();
+        assert_eq!(error, "`import Utils@^2.0.0` does not accept version 1.2.0 of package `utils`");
+    }
+
+    #[test]
+    fn test_dependency_graph_and_local_versions() {
+        let dir = workspace();
+        write(dir.path(), "utils-next/x.toml", "[package]\nname = \"utils\"\nversion = \"1.5.0\"\n");
+        let entry = dir.path().join("app/main.x");
+        let project = Project::discover(&entry).unwrap().unwrap();
+        let source = fs::read_to_string(&entry).unwrap();
+        let unit = parse_source(&source, FileId::new(0), SyntaxStyle::default()).unwrap();
+
+        let tree = project.dependency_graph(&entry, &unit).unwrap().import_tree("Main");
+        let utils = &tree.children[0];
+        assert_eq!((utils.package.as_deref(), utils.version.as_ref().map(Version::to_string)), (Some("utils"), Some("1.2.0".to_string())));
+        assert_eq!(utils.children[0].module, "Base");
+        assert_eq!(tree.children[1].module, "Core.List");
+
+        let available = project.available_versions();
+        assert_eq!(available["utils"], [Version::new(1, 2, 0), Version::new(1, 5, 0)]);
+    }
+
+    #[test]
+    fn test_checker_sees_dependency_modules() {
+        let dir = workspace();
+        let entry = dir.path().join("app/main.x");
+        let source = fs::read_to_string(&entry).unwrap();
+        let unit = parse_source(&source, FileId::new(0), SyntaxStyle::default()).unwrap();
+        let result = project_checker(&entry, &unit).unwrap().check_compilation_unit(&unit);
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+    }
+
+    #[test]
+    fn test_imported_operators_parse_with_their_fixities() {
+        let dir = workspace();
+        write(dir.path(), "utils/src/utils.x", "module Utils\ninfixr 5 <+>\npub let (<+>) = fun a b -> a - b\n");
+        write(dir.path(), "app/main.x", "module Main\nimport Utils { (<+>) }\nlet main = 10 <+> 4 <+> 1\n");
+        let entry = dir.path().join("app/main.x");
+        let source = fs::read_to_string(&entry).unwrap();
+        let unit = parse_source(&source, FileId::new(0), SyntaxStyle::default()).unwrap();
+        let mut checker = project_checker(&entry, &unit).unwrap();
+        let unit = with_imported_fixities(&checker, &source, unit).unwrap();
+        assert!(checker.check_compilation_unit(&unit).errors.is_empty());
+
+        // `10 <+> (4 <+> 1)`
+        let x_parser::Item::ValueDef(def) = &unit.module.items[0] else { unreachable!() };
+        let x_parser::Expr::App(_, args, _) = &def.body else { unreachable!() };
+        assert!(matches!(args[1], x_parser::Expr::App(..)));
+    }
+
+    #[test]
+    fn test_dependency_versions_are_validated() {
+        let dir = workspace();
+        write(dir.path(), "app/x.toml", "[package]\nname = \"app\"\nversion = \"0.1.0\"\n\n[dependencies]\nutils = { path = \"../utils\", version = \"~1.1\" }\n");
+        let error = Project::load(&dir.path().join("app")).unwrap_err().to_string();
+        assert!(error.contains("`app` requires `utils` ~1.1"), "{error}");
+
+        write(dir.path(), "app/x.toml", "[package]\nname = \"app\"\nversion = \"0.1.0\"\n\n[dependencies]\nutils = \"^1.0\"\n");
+        let error = Project::load(&dir.path().join("app")).unwrap_err().to_string();
+        assert!(error.contains("only path dependencies are supported"), "{error}");
+    }
+}
@@ -36,7 +36,6 @@ impl ProgressIndicator {
     }
     
     /// Finish the progress indicator with an error message
-    #[allow(dead_code)]
     pub fn finish_error(&self, message: &str) {
         self.bar.finish_with_message(format!("{} {}", "✗".red(), message));
     }
@@ -0,0 +1,474 @@
+//! A-normal form of the IR
+//!
+//! [`normalize`] rewrites the functions and constants of a module so that
+//! the order of evaluation is explicit in the IR rather than in the nesting
+//! of expressions:
+//!
+//! - operands are *values*: variables, scalar literals, lambdas, and the
+//!   tuples, records and projections built from values. Calls, effect
+//!   operations, handlers, resumptions, cell reads and writes,
+//!   conditionals and matches are
+//!   *computations*, found only as the value of a `let`, in tail position or
+//!   at the head of an application spine, so `f (g x) (E.op y)` becomes
+//!   `let $t0 = g x in let $t1 = E.op y in f $t0 $t1`;
+//! - nested lets are flattened, and each local is bound once per definition
+//!   and never shadows a module-level name, so a backend can declare every
+//!   local without introducing scopes;
+//! - calls of the module's own functions pass as many arguments as the
+//!   function has parameters: the arguments beyond those go to the function
+//!   it returns, and a partial application becomes a lambda.
+//!
+//! Branches, handled computations and lambda bodies are normalized on their
+//! own, since hoisting out of them would change when they run.
+//! [`verify_normalized`](crate::verify::verify_normalized) checks that a
+//! module is in this form.
+
+use crate::ir::{IRBinding, IRExpression, IRLiteral, IRMatchCase, IRModule, IRParameter, IRPattern, IRPrimitiveType, IRType};
+use std::collections::{HashMap, HashSet};
+use x_parser::Symbol;
+
+/// Normalize every function and constant of `module`
+pub fn normalize(module: &mut IRModule) {
+    let mut normalizer = Normalizer {
+        arities: function_arities(module),
+        globals: module_names(module),
+        scope: Vec::new(),
+        declared: HashSet::new(),
+        fresh: 0,
+    };
+    for function in &mut module.functions {
+        normalizer.enter(function.parameters.iter().map(|parameter| parameter.name));
+        let body = std::mem::replace(&mut function.body, IRExpression::Literal(IRLiteral::Unit));
+        function.body = normalizer.tail(body);
+    }
+    for constant in &mut module.constants {
+        normalizer.enter(std::iter::empty());
+        let value = std::mem::replace(&mut constant.value, IRExpression::Literal(IRLiteral::Unit));
+        constant.value = normalizer.tail(value);
+    }
+}
+
+/// Whether `expr` is a variable or a scalar literal
+pub fn is_atom(expr: &IRExpression) -> bool {
+    matches!(
+        expr,
+        IRExpression::Variable(_)
+            | IRExpression::Literal(IRLiteral::Integer(_) | IRLiteral::Float(_) | IRLiteral::String(_)
+                | IRLiteral::Boolean(_) | IRLiteral::Unit)
+    )
+}
+
+/// Whether `expr` is a value, which evaluates without effects or control
+/// flow and so may be an operand
+pub fn is_value(expr: &IRExpression) -> bool {
+    match expr {
+        IRExpression::Lambda { .. } => true,
+        IRExpression::Literal(IRLiteral::Array(elements)) | IRExpression::Tuple(elements) => elements.iter().all(is_value),
+        IRExpression::Literal(IRLiteral::Record(fields)) => fields.iter().all(|(_, value)| is_value(value)),
+        IRExpression::Field { record, .. } => is_value(record),
+        IRExpression::TupleGet { tuple, .. } => is_value(tuple),
+        IRExpression::RecordUpdate { record, fields } => is_value(record) && fields.iter().all(|(_, value)| is_value(value)),
+        expr => is_atom(expr),
+    }
+}
+
+/// Parameter counts of the module's functions that take parameters
+pub(crate) fn function_arities(module: &IRModule) -> HashMap<Symbol, usize> {
+    module.functions.iter()
+        .filter(|function| !function.parameters.is_empty())
+        .map(|function| (function.name, function.parameters.len()))
+        .collect()
+}
+
+/// Names the module defines or imports
+pub(crate) fn module_names(module: &IRModule) -> HashSet<Symbol> {
+    let constructors = module.types.iter()
+        .filter_map(|type_def| match &type_def.definition {
+            crate::ir::IRTypeDefinitionKind::Variant(constructors) => Some(constructors),
+            _ => None,
+        })
+        .flatten()
+        .map(|(name, _)| *name);
+    let imported = module.imports.iter()
+        .flat_map(|import| import.items.iter().map(|item| item.alias.unwrap_or(item.name)).chain(import.qualifier));
+    module.functions.iter().map(|function| function.name)
+        .chain(module.constants.iter().map(|constant| constant.name))
+        .chain(constructors)
+        .chain(imported)
+        .collect()
+}
+
+struct Normalizer {
+    arities: HashMap<Symbol, usize>,
+    globals: HashSet<Symbol>,
+    /// Locals in scope, innermost last, with the names they are renamed to
+    scope: Vec<(Symbol, Symbol)>,
+    /// Every local of the definition being normalized
+    declared: HashSet<Symbol>,
+    /// Counter for temporaries and renamed locals
+    fresh: usize,
+}
+
+impl Normalizer {
+    /// Start a definition with the given parameters in scope
+    fn enter(&mut self, parameters: impl Iterator<Item = Symbol>) {
+        self.scope.clear();
+        self.declared.clear();
+        for parameter in parameters {
+            self.scope.push((parameter, parameter));
+            self.declared.insert(parameter);
+        }
+    }
+
+    /// The name a use of `name` refers to
+    fn lookup(&self, name: Symbol) -> Symbol {
+        self.scope.iter().rev()
+            .find(|(source, _)| *source == name)
+            .map_or(name, |(_, renamed)| *renamed)
+    }
+
+    fn is_local(&self, name: Symbol) -> bool {
+        self.scope.iter().any(|(source, _)| *source == name)
+    }
+
+    /// Bring `name` into scope, renamed if the definition already binds it
+    /// or the module defines it
+    fn bind(&mut self, name: Symbol) -> Symbol {
+        let renamed = if self.declared.contains(&name) || self.globals.contains(&name) {
+            self.fresh(name.as_str())
+        } else {
+            name
+        };
+        self.declared.insert(renamed);
+        self.scope.push((name, renamed));
+        renamed
+    }
+
+    fn fresh(&mut self, base: &str) -> Symbol {
+        let name = Symbol::intern(&format!("{base}${}", self.fresh));
+        self.fresh += 1;
+        name
+    }
+
+    /// A new temporary holding `value`
+    fn temporary(&mut self, value: IRExpression, bindings: &mut Vec<IRBinding>) -> IRExpression {
+        let name = Symbol::intern(&format!("$t{}", self.fresh));
+        self.fresh += 1;
+        self.declared.insert(name);
+        bindings.push(IRBinding { name, value, type_hint: None });
+        IRExpression::Variable(name)
+    }
+
+    /// Normalize an expression in tail position, where it may remain a
+    /// computation
+    fn tail(&mut self, expr: IRExpression) -> IRExpression {
+        let mut bindings = Vec::new();
+        let result = self.computation(expr, &mut bindings);
+        if bindings.is_empty() {
+            result
+        } else {
+            IRExpression::Let { bindings, body: Box::new(result) }
+        }
+    }
+
+    /// Normalize `expr` into a value, binding the computations it needs first
+    fn value(&mut self, expr: IRExpression, bindings: &mut Vec<IRBinding>) -> IRExpression {
+        match expr {
+            IRExpression::Variable(name) => IRExpression::Variable(self.lookup(name)),
+            IRExpression::Literal(IRLiteral::Array(elements)) => {
+                IRExpression::Literal(IRLiteral::Array(self.values(elements, bindings)))
+            }
+            IRExpression::Literal(IRLiteral::Record(fields)) => {
+                IRExpression::Literal(IRLiteral::Record(self.fields(fields, bindings)))
+            }
+            IRExpression::Literal(literal) => IRExpression::Literal(literal),
+            IRExpression::Tuple(elements) => IRExpression::Tuple(self.values(elements, bindings)),
+            IRExpression::Field { record, field } => IRExpression::Field {
+                record: Box::new(self.value(*record, bindings)),
+                field,
+            },
+            IRExpression::TupleGet { tuple, index } => IRExpression::TupleGet {
+                tuple: Box::new(self.value(*tuple, bindings)),
+                index,
+            },
+            IRExpression::RecordUpdate { record, fields } => IRExpression::RecordUpdate {
+                record: Box::new(self.value(*record, bindings)),
+                fields: self.fields(fields, bindings),
+            },
+            IRExpression::Lambda { parameters, body, closure } => self.lambda(parameters, *body, closure),
+            expr => {
+                let result = self.computation(expr, bindings);
+                if is_value(&result) {
+                    result
+                } else {
+                    self.temporary(result, bindings)
+                }
+            }
+        }
+    }
+
+    fn values(&mut self, exprs: Vec<IRExpression>, bindings: &mut Vec<IRBinding>) -> Vec<IRExpression> {
+        exprs.into_iter().map(|expr| self.value(expr, bindings)).collect()
+    }
+
+    fn fields(&mut self, fields: Vec<(Symbol, IRExpression)>, bindings: &mut Vec<IRBinding>) -> Vec<(Symbol, IRExpression)> {
+        fields.into_iter().map(|(name, value)| (name, self.value(value, bindings))).collect()
+    }
+
+    /// Normalize the operands of `expr` into values, binding the computations
+    /// they need first; the result may itself be a computation
+    fn computation(&mut self, expr: IRExpression, bindings: &mut Vec<IRBinding>) -> IRExpression {
+        match expr {
+            IRExpression::Let { bindings: source, body } => {
+                let outer = self.scope.len();
+                for binding in source {
+                    let value = self.computation(binding.value, bindings);
+                    let name = self.bind(binding.name);
+                    bindings.push(IRBinding { name, value, type_hint: binding.type_hint });
+                }
+                let result = self.computation(*body, bindings);
+                self.scope.truncate(outer);
+                result
+            }
+            IRExpression::Call { function, arguments } => self.call(*function, arguments, bindings),
+            IRExpression::If { condition, then_branch, else_branch } => IRExpression::If {
+                condition: Box::new(self.value(*condition, bindings)),
+                then_branch: Box::new(self.tail(*then_branch)),
+                else_branch: Box::new(self.tail(*else_branch)),
+            },
+            IRExpression::Match { value, cases } => {
+                let value = Box::new(self.value(*value, bindings));
+                let cases = cases.into_iter()
+                    .map(|case| {
+                        let outer = self.scope.len();
+                        let pattern = self.bind_pattern(case.pattern);
+                        let guard = case.guard.map(|guard| self.tail(guard));
+                        let body = self.tail(case.body);
+                        self.scope.truncate(outer);
+                        IRMatchCase { pattern, guard, body }
+                    })
+                    .collect();
+                IRExpression::Match { value, cases }
+            }
+            IRExpression::Block(expressions) => {
+                IRExpression::Block(expressions.into_iter().map(|expr| self.tail(expr)).collect())
+            }
+            IRExpression::Effect { effect, operation, arguments } => IRExpression::Effect {
+                effect,
+                operation,
+                arguments: self.values(arguments, bindings),
+            },
+            IRExpression::Handle { expression, mode, handlers, return_handler } => {
+                let expression = Box::new(self.tail(*expression));
+                let handlers = handlers.into_iter()
+                    .map(|mut handler| {
+                        let outer = self.scope.len();
+                        handler.parameters = handler.parameters.into_iter().map(|name| self.bind(name)).collect();
+                        handler.continuation = self.bind(handler.continuation);
+                        handler.body = self.tail(handler.body);
+                        self.scope.truncate(outer);
+                        handler
+                    })
+                    .collect();
+                let return_handler = return_handler.map(|handler| Box::new(self.value(*handler, bindings)));
+                IRExpression::Handle { expression, mode, handlers, return_handler }
+            }
+            IRExpression::Resume { value, continuation } => IRExpression::Resume {
+                value: Box::new(self.value(*value, bindings)),
+                continuation: self.lookup(continuation),
+            },
+            IRExpression::Cell { name, initial, body } => {
+                let initial = Box::new(self.value(*initial, bindings));
+                let outer = self.scope.len();
+                let name = self.bind(name);
+                let body = Box::new(self.tail(*body));
+                self.scope.truncate(outer);
+                IRExpression::Cell { name, initial, body }
+            }
+            IRExpression::CellGet(name) => IRExpression::CellGet(self.lookup(name)),
+            IRExpression::CellSet { name, value } => IRExpression::CellSet {
+                name: self.lookup(name),
+                value: Box::new(self.value(*value, bindings)),
+            },
+            expr => self.value(expr, bindings),
+        }
+    }
+
+    fn lambda(&mut self, parameters: Vec<IRParameter>, body: IRExpression, closure: Vec<Symbol>) -> IRExpression {
+        let closure = closure.into_iter().map(|name| self.lookup(name)).collect();
+        let outer = self.scope.len();
+        let parameters = parameters.into_iter()
+            .map(|parameter| IRParameter { name: self.bind(parameter.name), type_hint: parameter.type_hint })
+            .collect();
+        let body = Box::new(self.tail(body));
+        self.scope.truncate(outer);
+        IRExpression::Lambda { parameters, body, closure }
+    }
+
+    /// Normalize an application spine, saturating calls of the module's
+    /// own functions
+    fn call(&mut self, function: IRExpression, arguments: Vec<IRExpression>, bindings: &mut Vec<IRBinding>) -> IRExpression {
+        let mut groups = vec![arguments];
+        let mut head = function;
+        while let IRExpression::Call { function, arguments } = head {
+            groups.push(arguments);
+            head = *function;
+        }
+        groups.reverse();
+        let arity = match &head {
+            IRExpression::Variable(name) if !self.is_local(*name) => self.arities.get(name).copied(),
+            _ => None,
+        };
+        let head = self.value(head, bindings);
+        let groups: Vec<Vec<IRExpression>> = groups.into_iter()
+            .map(|arguments| self.values(arguments, bindings))
+            .collect();
+        let Some(arity) = arity else {
+            return groups.into_iter().fold(head, |function, arguments| IRExpression::Call {
+                function: Box::new(function),
+                arguments,
+            });
+        };
+
+        let mut arguments: Vec<IRExpression> = groups.into_iter().flatten().collect();
+        if arguments.len() < arity {
+            return self.partial_application(head, arguments, arity);
+        }
+        let rest = arguments.split_off(arity);
+        let call = IRExpression::Call { function: Box::new(head), arguments };
+        if rest.is_empty() {
+            call
+        } else {
+            IRExpression::Call { function: Box::new(call), arguments: rest }
+        }
+    }
+
+    /// A lambda taking the parameters `arguments` leaves out of a call
+    fn partial_application(&mut self, function: IRExpression, mut arguments: Vec<IRExpression>, arity: usize) -> IRExpression {
+        let closure = arguments.iter()
+            .flat_map(IRExpression::free_variables)
+            .filter(|name| self.declared.contains(name))
+            .fold(Vec::new(), |mut closure, name| {
+                if !closure.contains(&name) {
+                    closure.push(name);
+                }
+                closure
+            });
+        let parameters: Vec<IRParameter> = (arguments.len()..arity)
+            .map(|index| {
+                let name = self.fresh(&format!("$arg{index}"));
+                self.declared.insert(name);
+                IRParameter { name, type_hint: IRType::Primitive(IRPrimitiveType::Unit) }
+            })
+            .collect();
+        arguments.extend(parameters.iter().map(|parameter| IRExpression::Variable(parameter.name)));
+        IRExpression::Lambda {
+            parameters,
+            body: Box::new(IRExpression::Call { function: Box::new(function), arguments }),
+            closure,
+        }
+    }
+
+    /// Bind the variables of `pattern`, renaming them in it; `_` binds nothing
+    fn bind_pattern(&mut self, pattern: IRPattern) -> IRPattern {
+        let mut names = Vec::new();
+        pattern.collect_variables(&mut names);
+        let renamed: HashMap<Symbol, Symbol> = names.into_iter()
+            .filter(|name| name.as_str() != "_")
+            .map(|name| (name, self.bind(name)))
+            .collect();
+        rename_pattern(pattern, &renamed)
+    }
+}
+
+fn rename_pattern(pattern: IRPattern, renamed: &HashMap<Symbol, Symbol>) -> IRPattern {
+    let rename_all = |patterns: Vec<IRPattern>| patterns.into_iter().map(|pattern| rename_pattern(pattern, renamed)).collect();
+    match pattern {
+        IRPattern::Variable(name) => IRPattern::Variable(renamed.get(&name).copied().unwrap_or(name)),
+        IRPattern::Constructor { name, arguments } => IRPattern::Constructor { name, arguments: rename_all(arguments) },
+        IRPattern::Tuple(patterns) => IRPattern::Tuple(rename_all(patterns)),
+        IRPattern::Record(fields) => IRPattern::Record(fields.into_iter()
+            .map(|(field, pattern)| (field, rename_pattern(pattern, renamed)))
+            .collect()),
+        IRPattern::Or(alternatives) => IRPattern::Or(rename_all(alternatives)),
+        pattern @ (IRPattern::Wildcard | IRPattern::Literal(_)) => pattern,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::IRBuilder;
+    use x_parser::{parse_source, FileId, SyntaxStyle};
+
+    fn build(source: &str) -> IRModule {
+        let cu = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        IRBuilder::new().build_ir(&cu).unwrap().modules.remove(0)
+    }
+
+    fn normalized(source: &str) -> IRModule {
+        let mut module = build(source);
+        normalize(&mut module);
+        module
+    }
+
+    fn body<'a>(module: &'a IRModule, name: &str) -> &'a IRExpression {
+        &module.functions.iter().find(|function| function.name.as_str() == name).unwrap().body
+    }
+
+    fn let_bound(expr: &IRExpression) -> Vec<(&str, &IRExpression)> {
+        match expr {
+            IRExpression::Let { bindings, .. } => bindings.iter().map(|binding| (binding.name.as_str(), &binding.value)).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_computations_are_let_bound_in_order() {
+        // f a = g (g a) (Log.log a)
+        let mut module = build("module Test\nlet g = fun x -> x\nlet f = fun a -> g a");
+        let var = |name: &str| IRExpression::Variable(Symbol::intern(name));
+        module.functions[1].body = IRExpression::Call {
+            function: Box::new(var("g")),
+            arguments: vec![
+                IRExpression::Call { function: Box::new(var("g")), arguments: vec![var("a")] },
+                IRExpression::Effect { effect: Symbol::intern("Log"), operation: Symbol::intern("log"), arguments: vec![var("a")] },
+            ],
+        };
+        normalize(&mut module);
+        let f = body(&module, "f");
+        let bound: Vec<&str> = let_bound(f).into_iter().map(|(name, _)| name).collect();
+        assert_eq!(bound, ["$t0", "$t1"]);
+        assert!(matches!(let_bound(f)[1].1, IRExpression::Effect { .. }));
+        // `g` takes one argument, so the second goes to what it returns
+        let IRExpression::Let { body, .. } = f else { unreachable!() };
+        let IRExpression::Call { function, arguments } = body.as_ref() else { panic!("{body:?}") };
+        assert!(matches!(arguments.as_slice(), [IRExpression::Variable(t)] if t.as_str() == "$t1"));
+        assert!(matches!(function.as_ref(), IRExpression::Call { arguments, .. }
+            if matches!(arguments.as_slice(), [IRExpression::Variable(t)] if t.as_str() == "$t0")));
+    }
+
+    #[test]
+    fn test_shadowing_locals_are_renamed() {
+        let module = normalized("module Test\n\
+            let g = fun x -> x\n\
+            let f = fun x -> (let x = g x in (let g = x in g))");
+        let f = body(&module, "f");
+        let bound: Vec<&str> = let_bound(f).into_iter().map(|(name, _)| name).collect();
+        assert_eq!(bound, ["x$0", "g$1"]);
+        assert!(matches!(let_bound(f)[0].1, IRExpression::Call { arguments, .. }
+            if matches!(arguments.as_slice(), [IRExpression::Variable(x)] if x.as_str() == "x")));
+        assert!(matches!(let_bound(f)[1].1, IRExpression::Variable(x) if x.as_str() == "x$0"));
+    }
+
+    #[test]
+    fn test_partial_applications_become_lambdas() {
+        let module = normalized("module Test\n\
+            let add = fun x y -> x\n\
+            let inc = fun n -> add n");
+        let IRExpression::Lambda { parameters, body, closure } = body(&module, "inc") else { panic!() };
+        assert_eq!(parameters.len(), 1);
+        assert_eq!(closure, &[Symbol::intern("n")]);
+        assert!(matches!(body.as_ref(), IRExpression::Call { arguments, .. } if arguments.len() == 2));
+    }
+}
@@ -0,0 +1,131 @@
+//! Intermediate artifacts written next to the generated code
+//!
+//! `x compile --emit ast,typed-ast,ir` writes, for every module, what the
+//! compiler made of it before code generation:
+//!
+//! - `ast`: the parsed module after `#[cfg(...)]` resolution, printed as
+//!   S-expressions;
+//! - `typed-ast`: the same, preceded by the type and effects the checker
+//!   inferred for each top-level value;
+//! - `ir`: the module lowered to the IR, after the configured
+//!   [passes](crate::passes), printed by [`ir_print`](crate::ir_print).
+//!
+//! Artifacts are named like the module's generated files, with the
+//! artifact's extension, and are listed in the build manifest.
+
+use crate::{ir::IRBuilder, ir_print, passes::PassManager, CompilerError, Result};
+use x_checker::CheckResult;
+use x_parser::syntax::{sexp::SExpPrinter, SyntaxConfig, SyntaxPrinter};
+use x_parser::{CompilationUnit, Item};
+
+/// An intermediate artifact of compiling a module
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Artifact {
+    Ast,
+    TypedAst,
+    Ir,
+}
+
+impl Artifact {
+    pub const ALL: [Artifact; 3] = [Artifact::Ast, Artifact::TypedAst, Artifact::Ir];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "ast" => Some(Artifact::Ast),
+            "typed-ast" | "typed" => Some(Artifact::TypedAst),
+            "ir" => Some(Artifact::Ir),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Artifact::Ast => "ast",
+            Artifact::TypedAst => "typed-ast",
+            Artifact::Ir => "ir",
+        }
+    }
+
+    /// Extension of the file the artifact is written to
+    pub fn extension(self) -> &'static str {
+        match self {
+            Artifact::Ast => "ast",
+            Artifact::TypedAst => "typed.ast",
+            Artifact::Ir => "ir",
+        }
+    }
+
+    /// The text of the artifact for `unit`; `check` is needed for
+    /// [`Artifact::TypedAst`] only, and `passes` for [`Artifact::Ir`]
+    pub fn render(self, unit: &CompilationUnit, check: Option<&CheckResult>, passes: &PassManager) -> Result<String> {
+        match self {
+            Artifact::Ast => print_ast(unit),
+            Artifact::TypedAst => {
+                let check = check.ok_or_else(|| CompilerError::Generic("typed-ast needs the unit's check result".to_string()))?;
+                Ok(print_types(unit, check) + &print_ast(unit)?)
+            }
+            Artifact::Ir => {
+                let ir = IRBuilder::new().with_passes(passes.clone()).build_ir(unit)?;
+                Ok(ir.modules.iter().map(ir_print::print_module).collect())
+            }
+        }
+    }
+}
+
+fn print_ast(unit: &CompilationUnit) -> Result<String> {
+    let config = SyntaxConfig { preserve_comments: false, ..SyntaxConfig::default() };
+    Ok(SExpPrinter::new().print(unit, &config)? + "\n")
+}
+
+/// A `(: name "type" "effects")` line for each top-level value, in order
+fn print_types(unit: &CompilationUnit, check: &CheckResult) -> String {
+    let mut text = String::new();
+    for item in &unit.module.items {
+        let Item::ValueDef(def) = item else { continue };
+        let Some(scheme) = check.inferred_types.get(&def.name) else { continue };
+        let effects = check.inferred_effects.get(&def.name)
+            .map(ToString::to_string)
+            .unwrap_or_else(|| "{}".to_string());
+        text.push_str(&format!("(: {} {:?} {:?})\n", def.name, scheme.body.to_string(), effects));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x_parser::{parse_source, FileId, SyntaxStyle};
+
+    const SOURCE: &str = "module Test\n\
+        import Core.String\n\
+        let twice = fun s -> String.concat s s\n\
+        pub let shout = fun s -> twice (twice s)";
+
+    #[test]
+    fn test_artifact_names() {
+        for artifact in Artifact::ALL {
+            assert_eq!(Artifact::from_name(artifact.name()), Some(artifact));
+        }
+        assert_eq!(Artifact::from_name("bytecode"), None);
+    }
+
+    #[test]
+    fn test_artifacts_render_modules() {
+        let unit = parse_source(SOURCE, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let check = crate::stdlib::checker().check_compilation_unit(&unit);
+        let passes = PassManager::default();
+
+        let ast = Artifact::Ast.render(&unit, None, &passes).unwrap();
+        assert!(ast.starts_with("(compilation-unit (module Test"), "{ast}");
+        let typed = Artifact::TypedAst.render(&unit, Some(&check), &passes).unwrap();
+        assert!(typed.starts_with("(: twice \"String -> String / e3\" \"{}\")\n(: shout \"String -> String / e7\" \"{}\")\n"), "{typed}");
+        assert!(typed.ends_with(&ast));
+        assert!(Artifact::TypedAst.render(&unit, None, &passes).is_err());
+
+        let ir = Artifact::Ir.render(&unit, None, &passes).unwrap();
+        assert_eq!(ir, Artifact::Ir.render(&unit, None, &passes).unwrap());
+        assert!(ir.starts_with("(module Test\n  (import Core.String (as String))\n"), "{ir}");
+        assert!(ir.contains("\n  (fun pub shout ((s "), "{ir}");
+        assert!(ir.contains("(call twice (call twice s))"), "{ir}");
+    }
+}
@@ -1,10 +1,17 @@
 //! Abstract backend interface for code generation
 
-use x_parser::{CompilationUnit, Module, Span, Symbol};
+use x_parser::{CancellationToken, CompilationUnit, Module, SourceMap, Span, Symbol};
 use x_checker::TypeScheme;
+use crate::codegen_mod::{TypeScriptEffectLowering, TypeScriptModuleSystem};
+use crate::config::{OutputNaming, TargetConfig};
+use crate::inlining::InlineCandidates;
+use crate::passes::PassManager;
+use crate::pipeline::PipelineStage;
+use crate::profiling::PassTiming;
 use crate::{CompilerError, Result};
 use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 /// Compilation target specification
 #[derive(Debug, Clone)]
@@ -25,12 +32,43 @@ pub struct CodegenOptions {
     pub debug_info: bool,
     pub optimization_level: u8,
     pub emit_types: bool,
+    /// Where the files of each module go under `output_dir`
+    pub naming: OutputNaming,
+    /// Checked between definitions, so a cancelled build stops generating
+    pub cancellation: CancellationToken,
+    /// The passes run over the IR after lowering
+    pub passes: PassManager,
+    /// Functions of the other project modules to inline; see
+    /// [`inlining`](crate::inlining)
+    pub inlining: Arc<InlineCandidates>,
+    /// The project's source files, by the `FileId` of their spans, for debug
+    /// info referring back to them
+    pub sources: Arc<SourceMap>,
+}
+
+impl CodegenOptions {
+    /// Fail with `CompilerError::Cancelled` once the build was cancelled
+    pub fn check_cancelled(&self) -> Result<()> {
+        self.cancellation.check()
+            .map_err(|_| CompilerError::Cancelled { stage: PipelineStage::CodeGen })
+    }
+
+    /// `path:line:column` of the start of `span`, when generating debug info
+    /// and its file is known
+    pub fn debug_location(&self, span: Span) -> Option<String> {
+        if !self.debug_info {
+            return None;
+        }
+        self.sources.location(span)
+    }
 }
 
 /// Result of code generation
 #[derive(Debug)]
 pub struct CodegenResult {
     pub files: HashMap<PathBuf, String>,
+    /// Generated files that are not text, such as `.wasm` binaries
+    pub binary_files: HashMap<PathBuf, Vec<u8>>,
     pub source_maps: HashMap<PathBuf, String>,
     pub diagnostics: Vec<CodegenDiagnostic>,
     pub metadata: CodegenMetadata,
@@ -40,6 +78,8 @@ pub struct CodegenResult {
 #[derive(Debug, Clone)]
 pub struct CodegenDiagnostic {
     pub severity: DiagnosticSeverity,
+    /// Stable diagnostic code in the `E02xx` range
+    pub code: &'static str,
     pub message: String,
     pub location: Option<Span>,
 }
@@ -58,6 +98,10 @@ pub struct CodegenMetadata {
     pub generated_files: usize,
     pub total_size: usize,
     pub compilation_time: std::time::Duration,
+    /// Passes over the IR, for backends that build one
+    pub passes: Vec<PassTiming>,
+    /// Time spent emitting target code after the passes
+    pub emit_time: std::time::Duration,
 }
 
 /// Abstract code generation backend
@@ -100,6 +144,26 @@ pub trait CodegenBackend {
     }
 }
 
+/// Errors for operation clauses that may resume their continuation more
+/// than once, for backends that only have one-shot continuations
+pub fn multi_shot_diagnostics(cu: &CompilationUnit) -> Vec<CodegenDiagnostic> {
+    let mut spans: Vec<Span> = x_checker::continuations::classify_module(&cu.module)
+        .into_iter()
+        .filter(|(_, resumption)| !resumption.is_one_shot())
+        .map(|(span, _)| span)
+        .collect();
+    spans.sort_by_key(|span| span.start);
+    spans
+        .into_iter()
+        .map(|span| CodegenDiagnostic {
+            severity: DiagnosticSeverity::Error,
+            code: "E0206",
+            message: "This clause may resume its continuation more than once, which this target does not support".to_string(),
+            location: Some(span),
+        })
+        .collect()
+}
+
 /// Factory for creating backend instances
 pub struct BackendFactory;
 
@@ -110,6 +174,9 @@ impl BackendFactory {
             "typescript" | "ts" => {
                 Ok(Box::new(crate::typescript::TypeScriptBackend::new()))
             }
+            "javascript" | "js" => {
+                Ok(Box::new(crate::typescript::TypeScriptBackend::javascript()))
+            }
             "wasm-gc" | "wasm" => {
                 Ok(Box::new(crate::wasm_gc::WasmGCBackend::new()))
             }
@@ -125,9 +192,44 @@ impl BackendFactory {
         }
     }
     
+    /// Create a backend for the specified target, applying its options
+    /// from the configuration
+    pub fn create_configured_backend(target: &str, config: &TargetConfig) -> Result<Box<dyn CodegenBackend>> {
+        match target {
+            "typescript" | "ts" | "javascript" | "js" => {
+                let backend = if matches!(target, "javascript" | "js") {
+                    crate::typescript::TypeScriptBackend::javascript()
+                } else {
+                    crate::typescript::TypeScriptBackend::new()
+                };
+                let mut backend = backend.with_declarations(config.get_bool("declarations").unwrap_or(false));
+                if let Some(name) = config.get_string("module_system") {
+                    let module_system = TypeScriptModuleSystem::from_name(name).ok_or_else(|| {
+                        CompilerError::Config { message: format!("unknown module system `{name}`") }
+                    })?;
+                    backend = backend.with_module_system(module_system);
+                }
+                if let Some(name) = config.get_string("effect_lowering") {
+                    let lowering = TypeScriptEffectLowering::from_name(name).ok_or_else(|| {
+                        CompilerError::Config { message: format!("unknown effect lowering `{name}`") }
+                    })?;
+                    backend = backend.with_effect_lowering(lowering);
+                }
+                backend = backend.with_stack_traces(config.get_bool("stack_traces").unwrap_or(false));
+                Ok(Box::new(backend))
+            }
+            "wasm-gc" | "wasm" => {
+                let backend = crate::wasm_gc::WasmGCBackend::new()
+                    .with_tail_calls(config.get_bool("tail_calls").unwrap_or(false));
+                Ok(Box::new(backend))
+            }
+            _ => Self::create_backend(target),
+        }
+    }
+    
     /// List all available backends
     pub fn available_backends() -> Vec<&'static str> {
-        vec!["typescript", "wasm-gc", "wasm-component", "wit"]
+        vec!["typescript", "javascript", "wasm-gc", "wasm-component", "wit"]
     }
 }
 
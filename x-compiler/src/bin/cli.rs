@@ -334,6 +334,7 @@ fn parse_syntax_style(style: &str) -> Result<SyntaxStyle, Box<dyn std::error::Er
 fn create_target_config(target: &str) -> Result<TargetConfig, Box<dyn std::error::Error>> {
     match target {
         "typescript" | "ts" => Ok(presets::typescript_dev()),
+        "javascript" | "js" => Ok(presets::javascript()),
         "wasm-gc" => Ok(presets::wasm_dev()),
         "wasm-component" | "component" => Ok(presets::wasm_component()),
         "wit" => Ok(presets::wit_only()),
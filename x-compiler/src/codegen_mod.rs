@@ -37,6 +37,41 @@ pub enum TypeScriptModuleSystem {
     SystemJS,
 }
 
+impl TypeScriptModuleSystem {
+    /// Module system named by a `module_system` target option
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "es2020" | "esm" | "es" => Some(TypeScriptModuleSystem::ES2020),
+            "commonjs" | "cjs" => Some(TypeScriptModuleSystem::CommonJS),
+            "amd" => Some(TypeScriptModuleSystem::AMD),
+            "systemjs" | "system" => Some(TypeScriptModuleSystem::SystemJS),
+            _ => None,
+        }
+    }
+}
+
+/// How the TypeScript backend lowers effect operations and handlers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypeScriptEffectLowering {
+    /// Handlers are objects on a runtime stack that operations call
+    /// synchronously
+    Handlers,
+    /// Effectful functions are `async` and operations are awaited, so
+    /// handlers may answer with Promises
+    AsyncAwait,
+}
+
+impl TypeScriptEffectLowering {
+    /// Lowering named by an `effect_lowering` target option
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "handlers" | "cps" => Some(TypeScriptEffectLowering::Handlers),
+            "async" | "async-await" | "promise" => Some(TypeScriptEffectLowering::AsyncAwait),
+            _ => None,
+        }
+    }
+}
+
 /// WebAssembly optimization levels
 #[derive(Debug, Clone, PartialEq)]
 pub enum WasmOptLevel {
@@ -0,0 +1,1176 @@
+//! Component binaries compiled from x modules
+//!
+//! Public values whose inferred types are first-order over `Bool`, `Int`,
+//! `Float`, `String`, `List` and record aliases are compiled to a core module
+//! and lifted into component functions with the canonical ABI:
+//!
+//! - a value of type `t` is represented by the flattening of `t`: scalars
+//!   are one core value, strings and lists a pointer and a length into
+//!   linear memory, records the flattenings of their fields in name order
+//! - arguments arrive flattened, with strings and lists copied in through
+//!   the exported `cabi_realloc`, a bump allocator that never frees
+//! - results flattening to more than one core value are stored in memory in
+//!   the canonical layout and returned by pointer
+//!
+//! Other public values are left out of the component and reported, so the
+//! rest of the module can still be used.
+//!
+//! Compiled with the module's source file, the core module gets debug info:
+//! its functions and locals are named after the definitions and variables
+//! they hold, and every expression's instructions are mapped to its line and
+//! column.
+
+use crate::wasm_binary::{
+    op, BlockType, CanonOptions, Component, ComponentValType, CoreModule, Function, Instructions,
+    ValType,
+};
+use crate::wit::wit_name;
+use std::collections::HashMap;
+use x_checker::Type as CheckerType;
+use x_parser::{
+    CompilationUnit, Expr, Item, Literal, Pattern, SourceFile, SourceMap, Span, Symbol, Type, TypeDefKind, ValueDef,
+    Visibility,
+};
+
+/// Core export holding the allocator the host copies arguments in with
+const REALLOC: &str = "cabi_realloc";
+/// Most core parameters a lifted function may take before the canonical ABI
+/// passes them through memory, which is not supported
+const MAX_FLAT_PARAMS: usize = 16;
+/// Where string literals start; address 0 is left unused
+const DATA_START: u32 = 8;
+
+/// A compiled component
+#[derive(Debug)]
+pub struct ComponentBinary {
+    pub bytes: Vec<u8>,
+    /// Public values left out of the component, with the reason
+    pub skipped: Vec<SkippedExport>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SkippedExport {
+    pub name: Symbol,
+    pub reason: String,
+    pub span: Span,
+}
+
+/// Compile the public values of `cu` into a component, with debug info
+/// when `sources` holds the file `cu` was parsed from
+pub fn compile_component(cu: &CompilationUnit, sources: Option<&SourceMap>) -> ComponentBinary {
+    let source = sources.and_then(|sources| sources.file(cu.module.span.file_id));
+    let checked = x_checker::type_check(cu);
+    let inferred = checked.inferred_types;
+    // A component is instantiated whole, so there is no deferring the
+    // instantiation of a lazily imported module until first use
+    let lazy_uses: Vec<(Span, Symbol)> = checked.warnings.iter()
+        .filter_map(|warning| match warning {
+            x_checker::TypeError::UnloadedLazyImport { module, span, .. } => Some((*span, *module)),
+            _ => None,
+        })
+        .collect();
+    let aliases: HashMap<Symbol, &Type> = cu.module.items.iter()
+        .filter_map(|item| match item {
+            Item::TypeDef(def) if def.type_params.is_empty() => match &def.kind {
+                TypeDefKind::Alias(typ) => Some((def.name, typ)),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+    // Record aliases by their sorted field names, to name record types
+    let record_names: HashMap<Vec<Symbol>, Symbol> = aliases.iter()
+        .filter_map(|(name, typ)| match typ {
+            Type::Record { fields, .. } => {
+                let mut fields: Vec<Symbol> = fields.keys().copied().collect();
+                fields.sort_by_key(|field| field.as_str());
+                Some((fields, *name))
+            }
+            _ => None,
+        })
+        .collect();
+
+    let mut skipped = Vec::new();
+    let mut candidates = Vec::new();
+    for item in &cu.module.items {
+        let Item::ValueDef(def) = item else { continue };
+        let public = matches!(def.visibility, Visibility::Public | Visibility::Component { export: true, .. });
+        let lazy_use = lazy_uses.iter().find(|(span, _)| def.span.contains_position(*span));
+        let scheme = match lazy_use {
+            Some((_, module)) => Err(format!("it uses lazy import {module}, which components cannot instantiate on demand")),
+            None => inferred.get(&def.name).ok_or_else(|| "its type could not be inferred".to_string()),
+        };
+        let signature = scheme
+            .and_then(|scheme| signature(&scheme.body, &aliases))
+            .and_then(|signature| {
+                if public {
+                    if export_name(def.name.as_str()) == "memory" {
+                        return Err("its name is taken by the component's memory".to_string());
+                    }
+                    signature.check_exportable(&record_names)?;
+                }
+                Ok(signature)
+            });
+        match signature {
+            Ok(signature) => candidates.push((def, signature, public)),
+            Err(reason) if public => skipped.push(SkippedExport { name: def.name, reason, span: def.span }),
+            Err(_) => {}
+        }
+    }
+
+    // Leaving out a definition can break the ones calling it, so compile
+    // until every remaining definition compiles
+    loop {
+        let signatures: HashMap<Symbol, (u32, Signature)> = candidates.iter()
+            .enumerate()
+            .map(|(i, (def, signature, _))| (def.name, (i as u32 + 1, signature.clone())))
+            .collect();
+        let mut builder = ModuleBuilder::new(signatures, source.cloned());
+        let mut failed = Vec::new();
+        for (i, (def, signature, _)) in candidates.iter().enumerate() {
+            if let Err(reason) = builder.compile_definition(i as u32 + 1, def, signature) {
+                failed.push((i, reason));
+            }
+        }
+        if failed.is_empty() {
+            if builder.source.is_some() {
+                builder.module.name_module(&cu.module.name.to_string());
+            }
+            let bytes = builder.finish(&candidates, &record_names);
+            return ComponentBinary { bytes, skipped };
+        }
+        for (i, reason) in failed.into_iter().rev() {
+            let (def, _, public) = candidates.remove(i);
+            if public {
+                skipped.push(SkippedExport { name: def.name, reason, span: def.span });
+            }
+        }
+    }
+}
+
+/// Types values can have in a component
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum ValueType {
+    Unit,
+    Bool,
+    Int,
+    Float,
+    String,
+    List(Box<ValueType>),
+    /// Fields sorted by name
+    Record(Vec<(Symbol, ValueType)>),
+}
+
+impl ValueType {
+    fn from_checker(typ: &CheckerType, aliases: &HashMap<Symbol, &Type>) -> Result<Self, String> {
+        match typ {
+            CheckerType::Con(name) => match name.as_str() {
+                "Unit" => Ok(ValueType::Unit),
+                "Bool" => Ok(ValueType::Bool),
+                "Int" => Ok(ValueType::Int),
+                "Float" => Ok(ValueType::Float),
+                "String" => Ok(ValueType::String),
+                _ => match aliases.get(name) {
+                    Some(alias) => Self::from_surface(alias, aliases),
+                    None => Err(format!("values of type {name} cannot cross the component boundary")),
+                },
+            },
+            CheckerType::App(con, args) => match (con.as_ref(), args.as_slice()) {
+                (CheckerType::Con(name), [element]) if name.as_str() == "List" => {
+                    Ok(ValueType::List(Box::new(Self::from_checker(element, aliases)?)))
+                }
+                _ => Err(format!("values of type {typ} cannot cross the component boundary")),
+            },
+            CheckerType::Record(fields) => {
+                let mut fields = fields.iter()
+                    .map(|(name, typ)| Ok((*name, Self::from_checker(typ, aliases)?)))
+                    .collect::<Result<Vec<_>, String>>()?;
+                fields.sort_by_key(|(name, _)| name.as_str());
+                Ok(ValueType::Record(fields))
+            }
+            CheckerType::Forall { body, .. } => Self::from_checker(body, aliases),
+            CheckerType::Var(_) | CheckerType::Row { .. } => {
+                Err("its type is polymorphic".to_string())
+            }
+            CheckerType::Fun { .. } => Err("it takes or returns a function".to_string()),
+            _ => Err(format!("values of type {typ} cannot cross the component boundary")),
+        }
+    }
+
+    fn from_surface(typ: &Type, aliases: &HashMap<Symbol, &Type>) -> Result<Self, String> {
+        match typ {
+            Type::Record { fields, .. } => {
+                let mut fields = fields.iter()
+                    .map(|(name, typ)| Ok((*name, Self::from_surface(typ, aliases)?)))
+                    .collect::<Result<Vec<_>, String>>()?;
+                fields.sort_by_key(|(name, _)| name.as_str());
+                Ok(ValueType::Record(fields))
+            }
+            Type::Con(name, _) => Self::from_checker(&CheckerType::Con(*name), aliases),
+            Type::App(con, args, _) => match (con.as_ref(), args.as_slice()) {
+                (Type::Con(name, _), [element]) if name.as_str() == "List" => {
+                    Ok(ValueType::List(Box::new(Self::from_surface(element, aliases)?)))
+                }
+                _ => Err("values of this type cannot cross the component boundary".to_string()),
+            },
+            _ => Err("values of this type cannot cross the component boundary".to_string()),
+        }
+    }
+
+    /// Core values the type is represented by
+    fn flatten(&self) -> Vec<ValType> {
+        match self {
+            ValueType::Unit => Vec::new(),
+            ValueType::Bool | ValueType::Int => vec![ValType::I32],
+            ValueType::Float => vec![ValType::F64],
+            ValueType::String | ValueType::List(_) => vec![ValType::I32, ValType::I32],
+            ValueType::Record(fields) => fields.iter().flat_map(|(_, typ)| typ.flatten()).collect(),
+        }
+    }
+
+    /// Alignment in linear memory under the canonical ABI
+    fn align(&self) -> u32 {
+        match self {
+            ValueType::Unit | ValueType::Bool => 1,
+            ValueType::Int | ValueType::String | ValueType::List(_) => 4,
+            ValueType::Float => 8,
+            ValueType::Record(fields) => fields.iter().map(|(_, typ)| typ.align()).max().unwrap_or(1),
+        }
+    }
+
+    /// Size in linear memory under the canonical ABI
+    fn size(&self) -> u32 {
+        match self {
+            ValueType::Unit => 0,
+            ValueType::Bool => 1,
+            ValueType::Int => 4,
+            ValueType::Float | ValueType::String | ValueType::List(_) => 8,
+            ValueType::Record(fields) => {
+                let end = self.field_offsets().last()
+                    .zip(fields.last())
+                    .map_or(0, |(offset, (_, typ))| offset + typ.size());
+                align_to(end, self.align())
+            }
+        }
+    }
+
+    /// Offsets of a record's fields
+    fn field_offsets(&self) -> Vec<u32> {
+        let ValueType::Record(fields) = self else {
+            return Vec::new();
+        };
+        let mut offset = 0;
+        fields.iter()
+            .map(|(_, typ)| {
+                offset = align_to(offset, typ.align());
+                let field = offset;
+                offset += typ.size();
+                field
+            })
+            .collect()
+    }
+}
+
+fn align_to(offset: u32, align: u32) -> u32 {
+    offset.div_ceil(align) * align
+}
+
+/// Parameter and result types of a definition, with curried parameters
+/// flattened into one list
+#[derive(Debug, Clone)]
+struct Signature {
+    params: Vec<ValueType>,
+    result: ValueType,
+}
+
+fn signature(typ: &CheckerType, aliases: &HashMap<Symbol, &Type>) -> Result<Signature, String> {
+    let mut params = Vec::new();
+    let mut result = typ;
+    while let CheckerType::Forall { body, .. } = result {
+        result = body;
+    }
+    while let CheckerType::Fun { params: fun_params, return_type, .. } = result {
+        for param in fun_params {
+            params.push(ValueType::from_checker(param, aliases)?);
+        }
+        result = return_type;
+    }
+    Ok(Signature { params, result: ValueType::from_checker(result, aliases)? })
+}
+
+impl Signature {
+    fn flat_params(&self) -> Vec<ValType> {
+        self.params.iter().flat_map(ValueType::flatten).collect()
+    }
+
+    /// Whether the definition can be lifted: records need a name in the
+    /// component, and arguments must fit in core parameters
+    fn check_exportable(&self, record_names: &HashMap<Vec<Symbol>, Symbol>) -> Result<(), String> {
+        for typ in self.params.iter().chain([&self.result]) {
+            check_named(typ, record_names)?;
+        }
+        if self.flat_params().len() > MAX_FLAT_PARAMS {
+            return Err(format!("its arguments take more than {MAX_FLAT_PARAMS} core values"));
+        }
+        Ok(())
+    }
+}
+
+fn check_named(typ: &ValueType, record_names: &HashMap<Vec<Symbol>, Symbol>) -> Result<(), String> {
+    match typ {
+        ValueType::List(element) => check_named(element, record_names),
+        ValueType::Record(fields) => {
+            let names: Vec<Symbol> = fields.iter().map(|(name, _)| *name).collect();
+            if !record_names.contains_key(&names) {
+                return Err("it uses a record type without a type alias to name it".to_string());
+            }
+            fields.iter().try_for_each(|(_, typ)| check_named(typ, record_names))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The core module being built, with the string literals laid out so far
+struct ModuleBuilder {
+    module: CoreModule,
+    /// Function index and signature of each definition
+    signatures: HashMap<Symbol, (u32, Signature)>,
+    data: Vec<u8>,
+    strings: HashMap<String, u32>,
+    /// The module's source file, when compiling with debug info
+    source: Option<SourceFile>,
+}
+
+/// Core function 0 is the allocator, core global 0 the allocation pointer
+const REALLOC_INDEX: u32 = 0;
+const HEAP_GLOBAL: u32 = 0;
+
+impl ModuleBuilder {
+    fn new(signatures: HashMap<Symbol, (u32, Signature)>, source: Option<SourceFile>) -> Self {
+        let mut module = CoreModule::new();
+        let realloc = module.declare_function();
+        debug_assert_eq!(realloc, REALLOC_INDEX);
+        for _ in 0..signatures.len() {
+            module.declare_function();
+        }
+        if let Some(source) = &source {
+            module.debug_info(&source.path().display().to_string());
+            module.name_function(REALLOC_INDEX, REALLOC);
+        }
+        ModuleBuilder { module, signatures, data: Vec::new(), strings: HashMap::new(), source }
+    }
+
+    fn compile_definition(&mut self, index: u32, def: &ValueDef, signature: &Signature) -> Result<(), String> {
+        // Parameters are the definition's own, then those of the lambdas its body starts with
+        let mut patterns: Vec<&Pattern> = def.parameters.iter().collect();
+        let mut body = &def.body;
+        while patterns.len() < signature.params.len() {
+            match body {
+                Expr::Lambda { parameters, body: inner, .. } => {
+                    patterns.extend(parameters);
+                    body = inner;
+                }
+                _ => return Err("it is not defined by a lambda over all its parameters".to_string()),
+            }
+        }
+        if patterns.len() != signature.params.len() {
+            return Err("its parameters do not match its type".to_string());
+        }
+
+        let flat_params = signature.flat_params();
+        let mut compiler = FunctionCompiler::new(self, flat_params.len() as u32);
+        let mut next = 0;
+        for (pattern, typ) in patterns.iter().zip(&signature.params) {
+            let slots: Vec<u32> = (next..next + typ.flatten().len() as u32).collect();
+            next += slots.len() as u32;
+            match pattern {
+                Pattern::Variable(name, _) => compiler.bind(*name, typ.clone(), slots),
+                Pattern::Wildcard(_) => {}
+                _ => return Err("its parameters are destructured".to_string()),
+            }
+        }
+        let result = compiler.expr(body)?;
+        if result != signature.result {
+            return Err("its body does not have the inferred type".to_string());
+        }
+        let (locals, body, names) = (compiler.locals, compiler.body, compiler.names);
+
+        let type_index = self.module.func_type(&flat_params, &signature.result.flatten());
+        self.module.define_function(index, Function { type_index, locals, body });
+        if self.source.is_some() {
+            self.module.name_function(index, def.name.as_str());
+            self.module.name_locals(index, names);
+        }
+        Ok(())
+    }
+
+    /// Address of a string literal's bytes
+    fn string(&mut self, value: &str) -> u32 {
+        if let Some(offset) = self.strings.get(value) {
+            return *offset;
+        }
+        let offset = DATA_START + self.data.len() as u32;
+        self.data.extend_from_slice(value.as_bytes());
+        self.strings.insert(value.to_string(), offset);
+        offset
+    }
+
+    /// Add the allocator and the export wrappers, then wrap the module in a
+    /// component lifting the public definitions
+    fn finish(
+        mut self,
+        candidates: &[(&ValueDef, Signature, bool)],
+        record_names: &HashMap<Vec<Symbol>, Symbol>,
+    ) -> Vec<u8> {
+        let heap_start = align_to(DATA_START + self.data.len() as u32, 8);
+        let heap = self.module.global_i32(heap_start as i32);
+        debug_assert_eq!(heap, HEAP_GLOBAL);
+        let realloc_type = self.module.func_type(&[ValType::I32; 4], &[ValType::I32]);
+        self.module.define_function(REALLOC_INDEX, Function {
+            type_index: realloc_type,
+            locals: vec![ValType::I32],
+            body: realloc_body(),
+        });
+        if !self.data.is_empty() {
+            self.module.data(DATA_START, std::mem::take(&mut self.data));
+        }
+        self.module.export_memory("memory");
+        self.module.export_function(REALLOC, REALLOC_INDEX);
+
+        let mut exports = Vec::new();
+        for (def, signature, public) in candidates {
+            if !public {
+                continue;
+            }
+            let name = export_name(def.name.as_str());
+            let (index, _) = self.signatures[&def.name];
+            let core = if signature.result.flatten().len() > 1 {
+                let wrapper = self.return_by_pointer(index, signature);
+                if self.source.is_some() {
+                    self.module.name_function(wrapper, &format!("{}.return", def.name));
+                }
+                wrapper
+            } else {
+                index
+            };
+            self.module.export_function(&name, core);
+            exports.push((name, signature));
+        }
+
+        let mut component = Component::new();
+        component.instantiate_module(&self.module);
+        let memory = component.alias_core_memory("memory");
+        let realloc = component.alias_core_func(REALLOC);
+        let mut types = ComponentTypes { named: HashMap::new(), record_names };
+        for (name, signature) in exports {
+            let core = component.alias_core_func(&name);
+            let params: Vec<(String, ComponentValType)> = signature.params.iter()
+                .filter(|typ| **typ != ValueType::Unit)
+                .enumerate()
+                .map(|(i, typ)| (format!("p{i}"), types.value_type(&mut component, typ)))
+                .collect();
+            let result = (signature.result != ValueType::Unit)
+                .then(|| types.value_type(&mut component, &signature.result));
+            let func_type = component.func_type(&params, result.as_ref());
+
+            let needs_memory = signature.params.iter().chain([&signature.result]).any(uses_memory)
+                || signature.result.flatten().len() > 1;
+            let options = CanonOptions {
+                memory: needs_memory.then_some(memory),
+                realloc: signature.params.iter().any(uses_memory).then_some(realloc),
+            };
+            let func = component.lift(core, func_type, options);
+            component.export_func(&name, func);
+        }
+        component.encode()
+    }
+
+    /// Wrapper around function `index` storing its results in memory and
+    /// returning their address, as the canonical ABI expects of functions
+    /// with several core results
+    fn return_by_pointer(&mut self, index: u32, signature: &Signature) -> u32 {
+        let flat_params = signature.flat_params();
+        let results = signature.result.flatten();
+        let mut compiler = FunctionCompiler::new(self, flat_params.len() as u32);
+        for param in 0..flat_params.len() as u32 {
+            compiler.body.local_get(param);
+        }
+        compiler.body.call(index);
+        let slots = compiler.store_locals(&results);
+        let address = compiler.allocate_const(signature.result.size(), signature.result.align());
+        compiler.store(address, 0, &signature.result, &slots);
+        compiler.body.local_get(address);
+        let (locals, body) = (compiler.locals, compiler.body);
+
+        let type_index = self.module.func_type(&flat_params, &[ValType::I32]);
+        let wrapper = self.module.declare_function();
+        self.module.define_function(wrapper, Function { type_index, locals, body });
+        wrapper
+    }
+}
+
+/// Whether values of `typ` point into linear memory
+fn uses_memory(typ: &ValueType) -> bool {
+    match typ {
+        ValueType::String | ValueType::List(_) => true,
+        ValueType::Record(fields) => fields.iter().any(|(_, typ)| uses_memory(typ)),
+        _ => false,
+    }
+}
+
+/// Component names are kebab-case, without the `%` WIT escapes keywords with
+fn export_name(name: &str) -> String {
+    wit_name(name).trim_start_matches('%').to_string()
+}
+
+/// `cabi_realloc(old_ptr, old_size, align, new_size)`: bump allocate
+/// `new_size` bytes, growing memory when needed, and copy the old contents
+fn realloc_body() -> Instructions {
+    let (old_ptr, old_size, align, new_size, ptr) = (0, 1, 2, 3, 4);
+    let mut body = Instructions::new();
+    // ptr = (heap + align - 1) & -align
+    body.global_get(HEAP_GLOBAL).local_get(align).op(op::I32_ADD).i32_const(1).op(op::I32_SUB)
+        .i32_const(0).local_get(align).op(op::I32_SUB).op(op::I32_AND).local_set(ptr);
+    body.local_get(ptr).local_get(new_size).op(op::I32_ADD).global_set(HEAP_GLOBAL);
+    // Grow by enough pages to cover the new end of the heap
+    body.global_get(HEAP_GLOBAL).memory_size().i32_const(16).op(op::I32_SHL).op(op::I32_GT_U)
+        .if_(BlockType::Empty)
+        .global_get(HEAP_GLOBAL).memory_size().i32_const(16).op(op::I32_SHL).op(op::I32_SUB)
+        .i32_const(0xffff).op(op::I32_ADD).i32_const(16).op(op::I32_SHR_U)
+        .memory_grow().i32_const(-1).op(op::I32_EQ)
+        .if_(BlockType::Empty).op(op::UNREACHABLE).end()
+        .end();
+    body.local_get(old_ptr).if_(BlockType::Empty)
+        .local_get(ptr).local_get(old_ptr).local_get(old_size).memory_copy()
+        .end();
+    body.local_get(ptr);
+    body
+}
+
+/// Component types of exported signatures; records are exported under the
+/// name of their alias, and signatures refer to the exported type
+struct ComponentTypes<'a> {
+    named: HashMap<ValueType, ComponentValType>,
+    record_names: &'a HashMap<Vec<Symbol>, Symbol>,
+}
+
+impl ComponentTypes<'_> {
+    fn value_type(&mut self, component: &mut Component, typ: &ValueType) -> ComponentValType {
+        match typ {
+            ValueType::Bool => ComponentValType::Bool,
+            ValueType::Int => ComponentValType::S32,
+            ValueType::Float => ComponentValType::F64,
+            ValueType::String => ComponentValType::String,
+            // Unit parameters and results are dropped from signatures
+            ValueType::Unit => unreachable!("unit has no component value type"),
+            ValueType::List(element) => {
+                if let Some(defined) = self.named.get(typ) {
+                    return defined.clone();
+                }
+                let element = self.value_type(component, element);
+                let list = ComponentValType::Type(component.list_type(&element));
+                self.named.insert(typ.clone(), list.clone());
+                list
+            }
+            ValueType::Record(fields) => {
+                if let Some(defined) = self.named.get(typ) {
+                    return defined.clone();
+                }
+                let labels: Vec<(String, ComponentValType)> = fields.iter()
+                    .map(|(name, typ)| (export_name(name.as_str()), self.value_type(component, typ)))
+                    .collect();
+                let names: Vec<Symbol> = fields.iter().map(|(name, _)| *name).collect();
+                let record = component.record_type(&labels);
+                let exported = component.export_type(&export_name(self.record_names[&names].as_str()), record);
+                let record = ComponentValType::Type(exported);
+                self.named.insert(typ.clone(), record.clone());
+                record
+            }
+        }
+    }
+}
+
+/// Compiles one function body, leaving the flattened value of each
+/// expression on the stack
+struct FunctionCompiler<'a> {
+    builder: &'a mut ModuleBuilder,
+    params: u32,
+    locals: Vec<ValType>,
+    /// Variables in scope, innermost last, with the locals holding their value
+    scopes: Vec<(Symbol, ValueType, Vec<u32>)>,
+    body: Instructions,
+    /// Names of the locals holding variables, for debug info
+    names: Vec<(u32, String)>,
+}
+
+impl<'a> FunctionCompiler<'a> {
+    fn new(builder: &'a mut ModuleBuilder, params: u32) -> Self {
+        FunctionCompiler {
+            builder,
+            params,
+            locals: Vec::new(),
+            scopes: Vec::new(),
+            body: Instructions::new(),
+            names: Vec::new(),
+        }
+    }
+
+    /// Bring `name` into scope, held in the locals `slots`
+    fn bind(&mut self, name: Symbol, typ: ValueType, slots: Vec<u32>) {
+        if self.builder.source.is_some() {
+            match slots.as_slice() {
+                [slot] => self.names.push((*slot, name.to_string())),
+                slots => self.names.extend(slots.iter().enumerate().map(|(i, slot)| (*slot, format!("{name}.{i}")))),
+            }
+        }
+        self.scopes.push((name, typ, slots));
+    }
+
+    /// Attribute the instructions emitted next to the start of `span`
+    fn mark(&mut self, span: Span) {
+        let position = self.builder.source.as_ref().and_then(|source| source.position(span.start));
+        if let Some(position) = position {
+            self.body.mark(position.line.to_display(), position.column.to_display());
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<ValueType, String> {
+        self.mark(expr.span());
+        match expr {
+            Expr::Literal(literal, _) => self.literal(literal),
+            Expr::Var(name, _) => self.variable(*name),
+            Expr::App(..) => {
+                let mut args = Vec::new();
+                let mut func = expr;
+                while let Expr::App(inner, inner_args, _) = func {
+                    args.splice(0..0, inner_args.iter());
+                    func = inner;
+                }
+                match func {
+                    Expr::Var(name, _) => self.call(*name, &args),
+                    _ => Err("it applies a computed function".to_string()),
+                }
+            }
+            Expr::Let { pattern, value, body, .. } => {
+                let typ = self.expr(value)?;
+                let slots = self.store_locals(&typ.flatten());
+                match pattern {
+                    Pattern::Variable(name, _) => self.bind(*name, typ, slots),
+                    Pattern::Wildcard(_) => return self.expr(body),
+                    _ => return Err("it destructures a let binding".to_string()),
+                }
+                let result = self.expr(body);
+                self.scopes.pop();
+                result
+            }
+            Expr::If { condition, then_branch, else_branch, .. } => {
+                self.expect(condition, &ValueType::Bool)?;
+                let (then_type, then_code) = self.nested(then_branch)?;
+                let (else_type, else_code) = self.nested(else_branch)?;
+                if then_type != else_type {
+                    return Err("its branches have different types".to_string());
+                }
+                let block_type = self.block_type(&then_type);
+                self.body.if_(block_type).append(&then_code).else_().append(&else_code).end();
+                Ok(then_type)
+            }
+            Expr::Record { fields, .. } => {
+                let mut fields: Vec<&(Symbol, Expr)> = fields.iter().collect();
+                fields.sort_by_key(|(name, _)| name.as_str());
+                let types = fields.iter()
+                    .map(|(name, value)| Ok((*name, self.expr(value)?)))
+                    .collect::<Result<Vec<_>, String>>()?;
+                Ok(ValueType::Record(types))
+            }
+            Expr::Project { record, field, .. } => {
+                let typ = self.expr(record)?;
+                let ValueType::Record(fields) = &typ else {
+                    return Err("it projects a field of a value that is not a record".to_string());
+                };
+                let slots = self.store_locals(&typ.flatten());
+                let mut next = 0;
+                for (name, field_type) in fields {
+                    let width = field_type.flatten().len();
+                    if name == field {
+                        for slot in &slots[next..next + width] {
+                            self.body.local_get(*slot);
+                        }
+                        return Ok(field_type.clone());
+                    }
+                    next += width;
+                }
+                Err(format!("it projects a missing field {field}"))
+            }
+            Expr::Ann { expr, .. } => self.expr(expr),
+            Expr::Lambda { .. } => Err("it builds a closure".to_string()),
+            Expr::Match { .. } => Err("it uses pattern matching".to_string()),
+            Expr::Perform { .. } | Expr::Handle { .. } | Expr::Resume { .. } | Expr::Do { .. } => {
+                Err("it performs effects".to_string())
+            }
+            Expr::Tuple { .. } => Err("it builds a tuple".to_string()),
+            Expr::RecordUpdate { .. } => Err("it updates a record".to_string()),
+            Expr::Pack { .. } | Expr::Unpack { .. } => Err("it uses a first-class module".to_string()),
+            Expr::Error(_) => Err("it failed to parse".to_string()),
+        }
+    }
+
+    fn literal(&mut self, literal: &Literal) -> Result<ValueType, String> {
+        match literal {
+            Literal::Integer(value, _) => {
+                let value = i32::try_from(*value)
+                    .map_err(|_| format!("the integer {value} does not fit in 32 bits"))?;
+                self.body.i32_const(value);
+                Ok(ValueType::Int)
+            }
+            Literal::Float(value, _) => {
+                self.body.f64_const(*value);
+                Ok(ValueType::Float)
+            }
+            Literal::Bool(value) => {
+                self.body.i32_const(i32::from(*value));
+                Ok(ValueType::Bool)
+            }
+            Literal::String(value) => {
+                let address = self.builder.string(value);
+                self.body.i32_const(address as i32).i32_const(value.len() as i32);
+                Ok(ValueType::String)
+            }
+            Literal::Unit => Ok(ValueType::Unit),
+        }
+    }
+
+    fn variable(&mut self, name: Symbol) -> Result<ValueType, String> {
+        if let Some((_, typ, slots)) = self.scopes.iter().rev().find(|(bound, _, _)| *bound == name) {
+            let typ = typ.clone();
+            for slot in slots.clone() {
+                self.body.local_get(slot);
+            }
+            return Ok(typ);
+        }
+        self.call(name, &[])
+    }
+
+    fn call(&mut self, name: Symbol, args: &[&Expr]) -> Result<ValueType, String> {
+        if let Some(result) = self.operator(name, args)? {
+            return Ok(result);
+        }
+        let Some((index, signature)) = self.builder.signatures.get(&name).cloned() else {
+            return Err(format!("it uses `{name}`, which is not compiled to the component"));
+        };
+        if args.len() != signature.params.len() {
+            return Err(format!("it applies `{name}` partially or uses it as a value"));
+        }
+        for (arg, param) in args.iter().zip(&signature.params) {
+            self.expect(arg, param)?;
+        }
+        self.body.call(index);
+        Ok(signature.result)
+    }
+
+    /// Built-in operators; `None` when `name` is not one applied to the
+    /// arguments it takes
+    fn operator(&mut self, name: Symbol, args: &[&Expr]) -> Result<Option<ValueType>, String> {
+        let operator = name.as_str();
+        if let ("not", [operand]) = (operator, args) {
+            self.expect(operand, &ValueType::Bool)?;
+            self.body.op(op::I32_EQZ);
+            return Ok(Some(ValueType::Bool));
+        }
+        let [left, right] = args else {
+            return Ok(None);
+        };
+        match operator {
+            "+" | "-" | "*" | "/" | "mod" => {
+                self.expect(left, &ValueType::Int)?;
+                self.expect(right, &ValueType::Int)?;
+                self.body.op(match operator {
+                    "+" => op::I32_ADD,
+                    "-" => op::I32_SUB,
+                    "*" => op::I32_MUL,
+                    "/" => op::I32_DIV_S,
+                    _ => op::I32_REM_S,
+                });
+                Ok(Some(ValueType::Int))
+            }
+            "+." | "-." | "*." | "/." => {
+                self.expect(left, &ValueType::Float)?;
+                self.expect(right, &ValueType::Float)?;
+                self.body.op(match operator {
+                    "+." => op::F64_ADD,
+                    "-." => op::F64_SUB,
+                    "*." => op::F64_MUL,
+                    _ => op::F64_DIV,
+                });
+                Ok(Some(ValueType::Float))
+            }
+            "=" | "==" | "<>" | "!=" | "<" | ">" | "<=" | ">=" => {
+                let typ = self.expr(left)?;
+                self.expect(right, &typ)?;
+                let opcode = match (&typ, operator) {
+                    (ValueType::Float, "=" | "==") => op::F64_EQ,
+                    (ValueType::Float, "<>" | "!=") => op::F64_NE,
+                    (ValueType::Float, "<") => op::F64_LT,
+                    (ValueType::Float, ">") => op::F64_GT,
+                    (ValueType::Float, "<=") => op::F64_LE,
+                    (ValueType::Float, _) => op::F64_GE,
+                    (ValueType::Int | ValueType::Bool, "=" | "==") => op::I32_EQ,
+                    (ValueType::Int | ValueType::Bool, "<>" | "!=") => op::I32_NE,
+                    (ValueType::Int, "<") => op::I32_LT_S,
+                    (ValueType::Int, ">") => op::I32_GT_S,
+                    (ValueType::Int, "<=") => op::I32_LE_S,
+                    (ValueType::Int, ">=") => op::I32_GE_S,
+                    _ => return Err(format!("it compares values with `{operator}` that only compares numbers")),
+                };
+                self.body.op(opcode);
+                Ok(Some(ValueType::Bool))
+            }
+            "&&" | "||" => {
+                self.expect(left, &ValueType::Bool)?;
+                let (right_type, right_code) = self.nested(right)?;
+                if right_type != ValueType::Bool {
+                    return Err(format!("it applies `{operator}` to a value that is not a Bool"));
+                }
+                self.body.if_(BlockType::Value(ValType::I32));
+                if operator == "&&" {
+                    self.body.append(&right_code).else_().i32_const(0);
+                } else {
+                    self.body.i32_const(1).else_().append(&right_code);
+                }
+                self.body.end();
+                Ok(Some(ValueType::Bool))
+            }
+            "^" => {
+                self.expect(left, &ValueType::String)?;
+                let first = self.store_locals(&[ValType::I32; 2]);
+                self.expect(right, &ValueType::String)?;
+                let second = self.store_locals(&[ValType::I32; 2]);
+
+                let length = self.local(ValType::I32);
+                self.body.local_get(first[1]).local_get(second[1]).op(op::I32_ADD).local_set(length);
+                let address = self.allocate(length, 1, 1);
+                self.body.local_get(address).local_get(first[0]).local_get(first[1]).memory_copy();
+                self.body.local_get(address).local_get(first[1]).op(op::I32_ADD)
+                    .local_get(second[0]).local_get(second[1]).memory_copy();
+                self.body.local_get(address).local_get(length);
+                Ok(Some(ValueType::String))
+            }
+            "::" => {
+                let element = self.expr(left)?;
+                let head = self.store_locals(&element.flatten());
+                let list = ValueType::List(Box::new(element.clone()));
+                self.expect(right, &list)?;
+                let tail = self.store_locals(&[ValType::I32; 2]);
+
+                let length = self.local(ValType::I32);
+                self.body.local_get(tail[1]).i32_const(1).op(op::I32_ADD).local_set(length);
+                let address = self.allocate(length, element.size(), element.align());
+                self.store(address, 0, &element, &head);
+                self.body.local_get(address).i32_const(element.size() as i32).op(op::I32_ADD)
+                    .local_get(tail[0])
+                    .local_get(tail[1]).i32_const(element.size() as i32).op(op::I32_MUL)
+                    .memory_copy();
+                self.body.local_get(address).local_get(length);
+                Ok(Some(list))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Compile `expr`, which must have type `expected`
+    fn expect(&mut self, expr: &Expr, expected: &ValueType) -> Result<(), String> {
+        let typ = self.expr(expr)?;
+        if typ == *expected {
+            Ok(())
+        } else {
+            Err(format!("it uses a {typ:?} where a {expected:?} is expected"))
+        }
+    }
+
+    /// Compile `expr` into a separate instruction sequence
+    fn nested(&mut self, expr: &Expr) -> Result<(ValueType, Instructions), String> {
+        let outer = std::mem::take(&mut self.body);
+        let typ = self.expr(expr);
+        let code = std::mem::replace(&mut self.body, outer);
+        Ok((typ?, code))
+    }
+
+    fn block_type(&mut self, typ: &ValueType) -> BlockType {
+        match typ.flatten().as_slice() {
+            [] => BlockType::Empty,
+            [single] => BlockType::Value(*single),
+            results => BlockType::Type(self.builder.module.func_type(&[], results)),
+        }
+    }
+
+    fn local(&mut self, typ: ValType) -> u32 {
+        self.locals.push(typ);
+        self.params + self.locals.len() as u32 - 1
+    }
+
+    /// Pop the values of types `types` on top of the stack into new locals
+    fn store_locals(&mut self, types: &[ValType]) -> Vec<u32> {
+        let slots: Vec<u32> = types.iter().map(|typ| self.local(*typ)).collect();
+        for slot in slots.iter().rev() {
+            self.body.local_set(*slot);
+        }
+        slots
+    }
+
+    /// Allocate `count` elements of `size` bytes, returning the local holding the address
+    fn allocate(&mut self, count: u32, size: u32, align: u32) -> u32 {
+        self.body.i32_const(0).i32_const(0).i32_const(align as i32)
+            .local_get(count).i32_const(size as i32).op(op::I32_MUL)
+            .call(REALLOC_INDEX);
+        self.store_locals(&[ValType::I32])[0]
+    }
+
+    fn allocate_const(&mut self, size: u32, align: u32) -> u32 {
+        self.body.i32_const(0).i32_const(0).i32_const(align as i32).i32_const(size as i32)
+            .call(REALLOC_INDEX);
+        self.store_locals(&[ValType::I32])[0]
+    }
+
+    /// Store a value held in `slots` at `address + offset`, in the canonical layout
+    fn store(&mut self, address: u32, offset: u32, typ: &ValueType, slots: &[u32]) {
+        match typ {
+            ValueType::Unit => {}
+            ValueType::Bool => {
+                self.body.local_get(address).local_get(slots[0]).i32_store8(offset);
+            }
+            ValueType::Int => {
+                self.body.local_get(address).local_get(slots[0]).store(ValType::I32, offset);
+            }
+            ValueType::Float => {
+                self.body.local_get(address).local_get(slots[0]).store(ValType::F64, offset);
+            }
+            ValueType::String | ValueType::List(_) => {
+                self.body.local_get(address).local_get(slots[0]).store(ValType::I32, offset);
+                self.body.local_get(address).local_get(slots[1]).store(ValType::I32, offset + 4);
+            }
+            ValueType::Record(fields) => {
+                let mut next = 0;
+                for ((_, field), field_offset) in fields.iter().zip(typ.field_offsets()) {
+                    let width = field.flatten().len();
+                    self.store(address, offset + field_offset, field, &slots[next..next + width]);
+                    next += width;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+    use x_parser::{parse_source, FileId, SourceMap, SyntaxStyle};
+
+    fn compile(source: &str) -> ComponentBinary {
+        let cu = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        compile_component(&cu, None)
+    }
+
+    /// Sections of a binary after its 8-byte preamble
+    fn sections(bytes: &[u8]) -> Vec<(u8, &[u8])> {
+        let mut rest = &bytes[8..];
+        let mut sections = Vec::new();
+        while let Some((&id, tail)) = rest.split_first() {
+            let (mut size, mut shift, mut read) = (0usize, 0, 0);
+            loop {
+                let byte = tail[read];
+                size |= usize::from(byte & 0x7f) << shift;
+                shift += 7;
+                read += 1;
+                if byte & 0x80 == 0 {
+                    break;
+                }
+            }
+            sections.push((id, &tail[read..read + size]));
+            rest = &tail[read + size..];
+        }
+        sections
+    }
+
+    /// Validate the component, core module included, and list its exports
+    fn validate(bytes: &[u8]) -> Vec<String> {
+        wasmparser::Validator::new_with_features(wasmparser::WasmFeatures::all())
+            .validate_all(bytes)
+            .unwrap_or_else(|e| panic!("invalid component: {e}"));
+        let mut names = Vec::new();
+        for payload in wasmparser::Parser::new(0).parse_all(bytes) {
+            if let wasmparser::Payload::ComponentExportSection(reader) = payload.unwrap() {
+                names.extend(reader.into_iter().map(|export| export.unwrap().name.0.to_string()));
+            }
+        }
+        names
+    }
+
+    const SOURCE: &str = "module Test
+type Point = {x: Int, y: Int}
+pub let add = fun x -> fun y -> x + y
+pub let greet = fun name -> \"Hello, \" ^ name
+pub let origin = fun n -> {x = n * 2, y = 0 - n}
+pub let push = fun xs -> 7 :: xs
+pub let choose = fun b -> if b && true then \"yes\" else \"no\"
+pub let swap = fun f -> f
+";
+
+    #[test]
+    fn test_component_wraps_core_module() {
+        let binary = compile(SOURCE);
+        assert_eq!(&binary.bytes[..8], b"\0asm\x0d\0\x01\0");
+
+        let sections = sections(&binary.bytes);
+        assert_eq!(sections[0].0, 1);
+        assert_eq!(&sections[0].1[..8], b"\0asm\x01\0\0\0");
+        // Core instance, then aliases, types, lifts and exports
+        assert_eq!(sections[1].0, 2);
+        let exports = validate(&binary.bytes);
+        for name in ["add", "greet", "origin", "push", "choose", "point"] {
+            assert!(exports.iter().any(|export| export == name), "{name} is not exported: {exports:?}");
+        }
+
+        // A polymorphic function cannot be lifted
+        assert_eq!(binary.skipped.len(), 1);
+        assert_eq!(binary.skipped[0].name.as_str(), "swap");
+        assert_eq!(binary.skipped[0].reason, "its type is polymorphic");
+    }
+
+    #[test]
+    fn test_debug_info_maps_to_source() {
+        let mut sources = SourceMap::new();
+        let file = sources.add_file("Test.x", SOURCE);
+        let cu = parse_source(SOURCE, file, SyntaxStyle::SExpression).unwrap();
+        let core = |sources: Option<&SourceMap>| {
+            let binary = compile_component(&cu, sources);
+            sections(&binary.bytes)[0].1.to_vec()
+        };
+        let contains = |bytes: &[u8], text: &[u8]| bytes.windows(text.len()).any(|window| window == text);
+
+        validate(&compile_component(&cu, Some(&sources)).bytes);
+        let plain = core(None);
+        assert!(!contains(&plain, b".debug_line"));
+        let debug = core(Some(&sources));
+        for text in [&b"name"[..], b".debug_info", b".debug_line", b"Test.x", b"greet", b"origin.return", b"cabi_realloc"] {
+            assert!(contains(&debug, text), "{} is missing", String::from_utf8_lossy(text));
+        }
+    }
+
+    #[test]
+    fn test_component_skips_lazy_imports() {
+        let binary = compile("module Test\nlazy import Reports\npub let total = fun n -> Reports.count n + 1\npub let one = 1");
+        assert_eq!(validate(&binary.bytes), ["one"]);
+        assert_eq!(binary.skipped.len(), 1);
+        assert_eq!(binary.skipped[0].name.as_str(), "total");
+        assert_eq!(binary.skipped[0].reason, "it uses lazy import Reports, which components cannot instantiate on demand");
+    }
+
+    #[test]
+    fn test_record_layout() {
+        let record = ValueType::Record(vec![
+            (Symbol::intern("flag"), ValueType::Bool),
+            (Symbol::intern("value"), ValueType::Float),
+            (Symbol::intern("name"), ValueType::String),
+        ]);
+        assert_eq!(record.field_offsets(), [0, 8, 16]);
+        assert_eq!((record.size(), record.align()), (24, 8));
+        assert_eq!(record.flatten(), [ValType::I32, ValType::F64, ValType::I32, ValType::I32]);
+    }
+
+    /// Instantiate the component with wasmtime and call its exports through
+    /// the canonical ABI, lowering and lifting as any component host does
+    #[test]
+    fn test_component_runs_under_wasmtime() {
+        use wasmtime::component::{Component as WasmtimeComponent, ComponentNamedList, Instance, Lift, Linker, Lower, Val};
+        use wasmtime::{Config, Engine, Store};
+
+        fn call<P, R>(store: &mut Store<()>, instance: &Instance, name: &str, params: P) -> R
+        where
+            P: ComponentNamedList + Lower,
+            R: ComponentNamedList + Lift,
+        {
+            let func = instance.get_typed_func::<P, R>(&mut *store, name)
+                .unwrap_or_else(|e| panic!("{name}: {e}"));
+            let results = func.call(&mut *store, params).unwrap_or_else(|e| panic!("{name}: {e}"));
+            func.post_return(&mut *store).unwrap();
+            results
+        }
+
+        let binary = compile(SOURCE);
+        let mut config = Config::new();
+        config.wasm_component_model(true);
+        let engine = Engine::new(&config).unwrap();
+        let component = WasmtimeComponent::new(&engine, &binary.bytes).unwrap();
+        let mut store = Store::new(&engine, ());
+        let instance = Linker::new(&engine).instantiate(&mut store, &component).unwrap();
+
+        assert_eq!(call::<(i32, i32), (i32,)>(&mut store, &instance, "add", (2, 40)), (42,));
+        assert_eq!(call::<(&str,), (String,)>(&mut store, &instance, "greet", ("component",)).0, "Hello, component");
+        assert_eq!(call::<(Vec<i32>,), (Vec<i32>,)>(&mut store, &instance, "push", (vec![1, 2],)).0, [7, 1, 2]);
+        assert_eq!(call::<(bool,), (String,)>(&mut store, &instance, "choose", (true,)).0, "yes");
+        assert_eq!(call::<(bool,), (String,)>(&mut store, &instance, "choose", (false,)).0, "no");
+
+        // Records are lifted field by field
+        let origin = instance.get_func(&mut store, "origin").unwrap();
+        let mut results = [Val::Bool(false)];
+        origin.call(&mut store, &[Val::S32(5)], &mut results).unwrap();
+        origin.post_return(&mut store).unwrap();
+        assert_eq!(results[0], Val::Record(vec![("x".to_string(), Val::S32(10)), ("y".to_string(), Val::S32(-5))]));
+    }
+
+    /// Instantiate the core module under Node and call its exports the way a
+    /// host lowering arguments and lifting results through the canonical ABI
+    /// would. Skipped when `node` is not installed.
+    #[test]
+    fn test_core_module_runs() {
+        let binary = compile(SOURCE);
+        let sections = sections(&binary.bytes);
+        let core: Vec<String> = sections[0].1.iter().map(|byte| byte.to_string()).collect();
+
+        let script = format!(r#"
+const bytes = new Uint8Array([{}]);
+const {{ exports }} = new WebAssembly.Instance(new WebAssembly.Module(bytes));
+const memory = () => new Uint8Array(exports.memory.buffer);
+const view = () => new DataView(exports.memory.buffer);
+const lower = (text) => {{
+  const encoded = new TextEncoder().encode(text);
+  const ptr = exports.cabi_realloc(0, 0, 1, encoded.length);
+  memory().set(encoded, ptr);
+  return [ptr, encoded.length];
+}};
+const liftString = (ret) => {{
+  const [ptr, len] = [view().getInt32(ret, true), view().getInt32(ret + 4, true)];
+  return new TextDecoder().decode(memory().slice(ptr, ptr + len));
+}};
+const point = exports.origin(5);
+const list = exports.push(...(() => {{
+  const ptr = exports.cabi_realloc(0, 0, 4, 8);
+  view().setInt32(ptr, 1, true);
+  view().setInt32(ptr + 4, 2, true);
+  return [ptr, 2];
+}})());
+const [listPtr, listLen] = [view().getInt32(list, true), view().getInt32(list + 4, true)];
+console.log(JSON.stringify([
+  exports.add(2, 40),
+  liftString(exports.greet(...lower("component"))),
+  [view().getInt32(point, true), view().getInt32(point + 4, true)],
+  Array.from({{ length: listLen }}, (_, i) => view().getInt32(listPtr + 4 * i, true)),
+  liftString(exports.choose(1)),
+  liftString(exports.choose(0)),
+]));
+"#, core.join(","));
+
+        let output = match Command::new("node").arg("-e").arg(&script).output() {
+            Ok(output) => output,
+            Err(_) => {
+                eprintln!("node is not installed; skipping");
+                return;
+            }
+        };
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout).trim(),
+            r#"[42,"Hello, component",[10,-5],[7,1,2],"yes","no"]"#,
+        );
+    }
+}
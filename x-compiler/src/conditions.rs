@@ -0,0 +1,215 @@
+//! Conditional imports and items
+//!
+//! `import Node.Fs when target == "typescript"` is kept only when compiling
+//! for that target. Conditions are resolved once per compilation, before
+//! type checking, so the checker and each backend only see the imports
+//! valid for the target. A condition is built from:
+//!
+//! - `target`, the target's canonical name: `typescript`, `wasm-gc` or
+//!   `wasm-component`, compared with `==` and `!=`
+//! - `feature "name"`, whether the feature is enabled in
+//!   [`CompilerConfig::features`](crate::CompilerConfig::features)
+//! - `true`, `false`, `&&`, `||` and `not`
+//!
+//! Items take the same tests in a `#[cfg(...)]` attribute, written
+//! `target = "typescript"` and `feature = "node"` and combined with
+//! `all(...)`, `any(...)` and `not(...)`, so a module can define an item
+//! once per target.
+
+use crate::CompilerError;
+use x_parser::{Cfg, CompilationUnit, Expr, ImportKind, Item, Literal};
+
+/// Canonical name of a target, as conditions see it
+pub fn target_name(target: &str) -> &str {
+    match target {
+        "typescript" | "ts" | "javascript" | "js" => "typescript",
+        "wasm-gc" | "wasm" => "wasm-gc",
+        "wasm-component" | "component" => "wasm-component",
+        other => other,
+    }
+}
+
+/// Drop the conditional imports of `unit` whose condition does not hold
+/// for `target` and `features`, keeping the rest as qualified imports
+pub fn resolve_imports(unit: &mut CompilationUnit, target: &str, features: &[String]) -> Result<(), CompilerError> {
+    let context = Context { target: target_name(target), features };
+    let mut imports = Vec::with_capacity(unit.module.imports.len());
+    for mut import in std::mem::take(&mut unit.module.imports) {
+        if let ImportKind::Conditional(condition) = &import.kind {
+            let holds = context.condition(condition).map_err(|message| CompilerError::ImportCondition {
+                module: import.module_path.to_string(),
+                message,
+            })?;
+            if !holds {
+                continue;
+            }
+            import.kind = ImportKind::Qualified;
+        }
+        imports.push(import);
+    }
+    unit.module.imports = imports;
+    Ok(())
+}
+
+/// Drop the items of `unit` whose `#[cfg(...)]` does not hold for
+/// `target` and `features`, and unwrap the others; returns whether the
+/// module had any, so diagnostics can mention the configuration
+pub fn resolve_items(unit: &mut CompilationUnit, target: &str, features: &[String]) -> Result<bool, CompilerError> {
+    let context = Context { target: target_name(target), features };
+    let mut conditional = false;
+    let mut items = Vec::with_capacity(unit.module.items.len());
+    for mut item in std::mem::take(&mut unit.module.items) {
+        let mut holds = true;
+        while let Item::Conditional(inner) = item {
+            conditional = true;
+            holds &= context.cfg(&inner.condition).map_err(|message| CompilerError::ItemCondition {
+                condition: inner.condition.to_string(),
+                message,
+            })?;
+            item = *inner.item;
+        }
+        if holds {
+            items.push(item);
+        }
+    }
+    unit.module.items = items;
+    Ok(conditional)
+}
+
+/// The configuration conditions are resolved against, as `cfg` writes it
+pub fn active_configuration(target: &str, features: &[String]) -> String {
+    std::iter::once(format!("target = {:?}", target_name(target)))
+        .chain(features.iter().map(|feature| format!("feature = {feature:?}")))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+struct Context<'a> {
+    target: &'a str,
+    features: &'a [String],
+}
+
+/// Values conditions compute with
+#[derive(Debug, PartialEq)]
+enum Value<'a> {
+    Bool(bool),
+    String(&'a str),
+}
+
+impl<'a> Context<'a> {
+    fn cfg(&self, cfg: &Cfg) -> Result<bool, String> {
+        match cfg {
+            Cfg::Equals { key, value } => match key.as_str() {
+                "target" => Ok(target_name(value) == self.target),
+                "feature" => Ok(self.features.iter().any(|enabled| enabled == value)),
+                key => Err(format!("unknown key `{key}`; items can test `target` and `feature`")),
+            },
+            Cfg::Not(cfg) => Ok(!self.cfg(cfg)?),
+            Cfg::All(cfgs) => cfgs.iter().try_fold(true, |holds, cfg| Ok(holds && self.cfg(cfg)?)),
+            Cfg::Any(cfgs) => cfgs.iter().try_fold(false, |holds, cfg| Ok(holds || self.cfg(cfg)?)),
+        }
+    }
+
+    fn condition(&self, expr: &'a Expr) -> Result<bool, String> {
+        match self.value(expr)? {
+            Value::Bool(value) => Ok(value),
+            Value::String(value) => Err(format!("expected a condition, found the string {value:?}")),
+        }
+    }
+
+    fn value(&self, expr: &'a Expr) -> Result<Value<'a>, String> {
+        match expr {
+            Expr::Literal(Literal::Bool(value), _) => Ok(Value::Bool(*value)),
+            Expr::Literal(Literal::String(value), _) => Ok(Value::String(value)),
+            Expr::Var(name, _) if name.as_str() == "target" => Ok(Value::String(self.target)),
+            Expr::Ann { expr, .. } => self.value(expr),
+            Expr::App(function, args, _) => {
+                let Expr::Var(name, _) = function.as_ref() else {
+                    return Err("only `feature`, `not` and operators can be applied in conditions".to_string());
+                };
+                match (name.as_str(), args.as_slice()) {
+                    ("feature", [feature]) => match self.value(feature)? {
+                        Value::String(feature) => Ok(Value::Bool(self.features.iter().any(|enabled| enabled == feature))),
+                        Value::Bool(_) => Err("`feature` takes the feature's name as a string".to_string()),
+                    },
+                    ("not", [operand]) => Ok(Value::Bool(!self.condition(operand)?)),
+                    ("&&", [left, right]) => Ok(Value::Bool(self.condition(left)? && self.condition(right)?)),
+                    ("||", [left, right]) => Ok(Value::Bool(self.condition(left)? || self.condition(right)?)),
+                    ("==", [left, right]) => Ok(Value::Bool(self.value(left)? == self.value(right)?)),
+                    ("!=", [left, right]) => Ok(Value::Bool(self.value(left)? != self.value(right)?)),
+                    (name, _) => Err(format!("`{name}` cannot be used in conditions")),
+                }
+            }
+            Expr::Var(name, _) => Err(format!("unknown name `{name}`; conditions can test `target` and `feature \"name\"`")),
+            _ => Err("conditions are made of `target`, `feature \"name\"`, comparisons and `&&`, `||`, `not`".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x_parser::{parse_source, FileId, SyntaxStyle, ValueDef};
+
+    fn unit(imports: &str) -> CompilationUnit {
+        parse_source(&format!("module Main\n{imports}\nlet x = 1"), FileId::new(0), SyntaxStyle::SExpression).unwrap()
+    }
+
+    fn kept(imports: &str, target: &str, features: &[&str]) -> Vec<String> {
+        let mut unit = unit(imports);
+        let features: Vec<String> = features.iter().map(|feature| feature.to_string()).collect();
+        resolve_imports(&mut unit, target, &features).unwrap();
+        assert!(unit.module.imports.iter().all(|import| import.kind == ImportKind::Qualified));
+        unit.module.imports.iter().map(|import| import.module_path.to_string()).collect()
+    }
+
+    #[test]
+    fn test_imports_follow_target_and_features() {
+        let imports = "import Core\n\
+            import Node.Fs when target == \"typescript\"\n\
+            import Wasi.Fs when target != \"typescript\" && not (feature \"browser\")\n\
+            import Simd when feature \"simd\" || false";
+        assert_eq!(kept(imports, "js", &[]), ["Core", "Node.Fs"]);
+        assert_eq!(kept(imports, "wasm", &["simd"]), ["Core", "Wasi.Fs", "Simd"]);
+        assert_eq!(kept(imports, "wasm-component", &["browser"]), ["Core"]);
+    }
+
+    #[test]
+    fn test_items_follow_target_and_features() {
+        let source = "module Main\n\
+            #[cfg(target = \"ts\")]\nlet read = node_read\n\
+            #[cfg(all(not(target = \"typescript\"), feature = \"wasi\"))]\nlet read = wasi_read\n\
+            #[cfg(any())]\nlet never = never\n\
+            let always = always";
+        let kept = |target: &str, features: &[&str]| {
+            let mut unit = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+            let features: Vec<String> = features.iter().map(|feature| feature.to_string()).collect();
+            assert!(resolve_items(&mut unit, target, &features).unwrap());
+            unit.module.items.iter()
+                .map(|item| match item {
+                    Item::ValueDef(ValueDef { body: Expr::Var(value, _), .. }) => value.to_string(),
+                    other => panic!("expected a definition, got {other:?}"),
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(kept("javascript", &[]), ["node_read", "always"]);
+        assert_eq!(kept("wasm-gc", &["wasi"]), ["wasi_read", "always"]);
+        assert_eq!(kept("wasm-gc", &[]), ["always"]);
+
+        let mut plain = unit("");
+        assert!(!resolve_items(&mut plain, "typescript", &[]).unwrap());
+        assert_eq!(active_configuration("ts", &["node".to_string()]), "target = \"typescript\", feature = \"node\"");
+    }
+
+    #[test]
+    fn test_invalid_conditions_are_reported() {
+        let mut unit = unit("import Node.Fs when platform == \"node\"");
+        match resolve_imports(&mut unit, "typescript", &[]) {
+            Err(CompilerError::ImportCondition { module, message }) => {
+                assert_eq!(module, "Node.Fs");
+                assert!(message.starts_with("unknown name `platform`"), "{message}");
+            }
+            other => panic!("expected an invalid condition, got {other:?}"),
+        }
+    }
+}
@@ -1,9 +1,36 @@
 //! Compiler configuration and settings
+//!
+//! A build's configuration is layered, each layer overriding the settings
+//! it names in the layers before it:
+//!
+//! 1. the defaults
+//! 2. the `[compiler]` table of the project's `x.toml`
+//! 3. `X_*` environment variables, such as `X_OPTIMIZATION_LEVEL=2`
+//! 4. command line flags
+//!
+//! ```toml
+//! [compiler]
+//! optimization_level = 2
+//! source_maps = true
+//! passes = ["flatten-pipelines", "dce", "local-effects"]
+//!
+//! [compiler.targets.javascript]
+//! module_system = "commonjs"
+//! stack_traces = true
+//! layout = "source-tree"
+//! file_name = "{name}.{ext}"
+//!
+//! [compiler.targets.wasm-gc]
+//! tail_calls = true
+//! ```
 
 use x_parser::SyntaxStyle;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use crate::backend::BackendFactory;
+use crate::codegen_mod::{TypeScriptEffectLowering, TypeScriptModuleSystem};
+use crate::passes::PassManager;
 
 /// Main compiler configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,6 +44,20 @@ pub struct CompilerConfig {
     pub output_format: OutputFormat,
     pub incremental: bool,
     pub cache_dir: Option<PathBuf>,
+    /// Feature flags enabled for the build, tested by `feature "name"` in
+    /// import conditions
+    #[serde(default)]
+    pub features: Vec<String>,
+    /// Generate code twice and fail if the two runs differ in any byte
+    ///
+    /// Backends never write timestamps or other details of the build
+    /// environment, so identical inputs must give identical outputs.
+    #[serde(default)]
+    pub deterministic: bool,
+    /// The passes to run over the IR, in order; see [`passes`](crate::passes).
+    /// Unset, the optimization level picks them
+    #[serde(default)]
+    pub passes: Option<Vec<String>>,
 }
 
 impl Default for CompilerConfig {
@@ -31,6 +72,9 @@ impl Default for CompilerConfig {
             output_format: OutputFormat::Files,
             incremental: false,
             cache_dir: None,
+            features: Vec::new(),
+            deterministic: false,
+            passes: None,
         }
     }
 }
@@ -51,6 +95,110 @@ impl Default for TargetConfig {
     }
 }
 
+/// Where the files generated for each module go in the output directory;
+/// files shared by all modules, such as a runtime, stay at its root
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputLayout {
+    /// Every file directly in the output directory
+    #[default]
+    Flat,
+    /// A directory for each module, named after it
+    PerModule,
+    /// Directories following the module path the way sources are laid
+    /// out, so `Data.List` goes in `Data/`
+    SourceTree,
+}
+
+impl OutputLayout {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "flat" => Some(OutputLayout::Flat),
+            "per-module" | "module" => Some(OutputLayout::PerModule),
+            "source-tree" | "tree" => Some(OutputLayout::SourceTree),
+            _ => None,
+        }
+    }
+}
+
+/// How the files generated for a module are named and placed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputNaming {
+    pub layout: OutputLayout,
+    /// File name template; `{module}` is the module's name, `{name}` the
+    /// last segment of it and `{ext}` the extension of the file
+    pub file_name: String,
+}
+
+impl Default for OutputNaming {
+    fn default() -> Self {
+        Self::new(OutputLayout::default())
+    }
+}
+
+impl OutputNaming {
+    /// The naming of `layout` with its default template: `{name}.{ext}`
+    /// in a source tree, `{module}.{ext}` otherwise
+    pub fn new(layout: OutputLayout) -> Self {
+        let file_name = match layout {
+            OutputLayout::SourceTree => "{name}.{ext}",
+            OutputLayout::Flat | OutputLayout::PerModule => "{module}.{ext}",
+        };
+        Self { layout, file_name: file_name.to_string() }
+    }
+
+    pub fn with_file_name(mut self, template: &str) -> Self {
+        self.file_name = template.to_string();
+        self
+    }
+
+    /// The path, relative to the output directory, of the file with
+    /// extension `ext` generated for `module`
+    pub fn module_file(&self, module: &str, ext: &str) -> PathBuf {
+        let segments: Vec<&str> = module.split('.').collect();
+        let file_name = self.file_name
+            .replace("{module}", module)
+            .replace("{name}", segments[segments.len() - 1])
+            .replace("{ext}", ext);
+        let directory: PathBuf = match self.layout {
+            OutputLayout::Flat => PathBuf::new(),
+            OutputLayout::PerModule => PathBuf::from(module),
+            OutputLayout::SourceTree => segments[..segments.len() - 1].iter().collect(),
+        };
+        directory.join(file_name)
+    }
+
+    /// The relative path from the files of `module` to the root of the
+    /// output directory, `./` when they are in it
+    pub fn root_from(&self, module: &str) -> String {
+        match self.module_file(module, "").components().count() {
+            0 | 1 => "./".to_string(),
+            depth => "../".repeat(depth - 1),
+        }
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |message: String| Err(ConfigError::Invalid { field: "file_name".to_string(), message });
+        let mut rest = self.file_name.as_str();
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..].find('}').map(|end| start + end);
+            match end.map(|end| &rest[start + 1..end]) {
+                Some("module" | "name" | "ext") => rest = &rest[end.unwrap() + 1..],
+                Some(placeholder) => {
+                    return invalid(format!("unknown placeholder `{{{placeholder}}}`, expected {{module}}, {{name}} or {{ext}}"));
+                }
+                None => return invalid(format!("unclosed `{{` in `{}`", self.file_name)),
+            }
+        }
+        // Without the module in the name, the files of different modules would collide
+        let distinct = self.file_name.contains("{module}")
+            || (self.file_name.contains("{name}") && self.layout == OutputLayout::SourceTree);
+        if !distinct && self.layout != OutputLayout::PerModule {
+            return invalid(format!("`{}` names the files of every module alike", self.file_name));
+        }
+        Ok(())
+    }
+}
+
 /// Configuration value types
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(untagged)]
@@ -154,22 +302,148 @@ impl CompilerConfig {
         self.set_target_config(target, config);
     }
 
+    /// The configuration of a build, layering the `[compiler]` table of
+    /// `manifest` (when given), the environment and `flags` over the
+    /// defaults, in that order of precedence
+    pub fn load(manifest: Option<&Path>, flags: PartialConfig) -> Result<Self, ConfigError> {
+        let mut config = CompilerConfig::default();
+        if let Some(path) = manifest {
+            config.apply(PartialConfig::from_manifest(path)?);
+        }
+        config.apply(PartialConfig::from_env()?);
+        config.apply(flags);
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Override the settings `layer` names
+    pub fn apply(&mut self, layer: PartialConfig) {
+        if let Some(syntax_style) = layer.syntax_style {
+            self.syntax_style = syntax_style;
+        }
+        if let Some(level) = layer.optimization_level {
+            self.optimization_level = level;
+        }
+        if let Some(debug_info) = layer.debug_info {
+            self.debug_info = debug_info;
+        }
+        if let Some(source_maps) = layer.source_maps {
+            self.source_maps = source_maps;
+        }
+        if let Some(emit_types) = layer.emit_types {
+            self.emit_types = emit_types;
+        }
+        if let Some(incremental) = layer.incremental {
+            self.incremental = incremental;
+        }
+        if let Some(deterministic) = layer.deterministic {
+            self.deterministic = deterministic;
+        }
+        if layer.cache_dir.is_some() {
+            self.cache_dir = layer.cache_dir;
+        }
+        if layer.passes.is_some() {
+            self.passes = layer.passes;
+        }
+        for feature in layer.features.into_iter().flatten() {
+            if !self.features.contains(&feature) {
+                self.features.push(feature);
+            }
+        }
+        for (target, options) in layer.targets {
+            for (key, value) in options {
+                self.set_target_option(&target, &key, value);
+            }
+        }
+    }
+
+    /// The passes to run over the IR: those `passes` names, or the
+    /// defaults of the optimization level
+    pub fn pass_manager(&self) -> Result<PassManager, ConfigError> {
+        match &self.passes {
+            Some(names) => PassManager::new(names)
+                .map_err(|error| ConfigError::Invalid { field: "passes".to_string(), message: error.to_string() }),
+            None => Ok(PassManager::for_level(self.optimization_level)),
+        }
+    }
+
     /// Validate configuration
     pub fn validate(&self) -> Result<(), ConfigError> {
-        // Check optimization level
+        let invalid = |field: String, message: String| Err(ConfigError::Invalid { field, message });
+
         if self.optimization_level > 3 {
-            return Err(ConfigError::Invalid {
-                field: "optimization_level".to_string(),
-                message: "Optimization level must be 0-3".to_string(),
-            });
+            return invalid(
+                "optimization_level".to_string(),
+                format!("must be between 0 and 3, got {}", self.optimization_level),
+            );
         }
 
-        // Check cache directory if incremental is enabled
         if self.incremental && self.cache_dir.is_none() {
-            return Err(ConfigError::Invalid {
-                field: "cache_dir".to_string(),
-                message: "Cache directory required for incremental compilation".to_string(),
-            });
+            return invalid(
+                "cache_dir".to_string(),
+                "incremental compilation needs a cache directory".to_string(),
+            );
+        }
+
+        self.pass_manager()?;
+
+        let mut targets: Vec<_> = self.target_configs.iter().collect();
+        targets.sort_by_key(|(target, _)| *target);
+        for (target, config) in targets {
+            let field = |key: &str| format!("targets.{target}.{key}");
+            if BackendFactory::create_backend(target).is_err() {
+                return invalid(
+                    format!("targets.{target}"),
+                    format!("unknown target, expected one of {}", BackendFactory::available_backends().join(", ")),
+                );
+            }
+            if let Err(ConfigError::Invalid { field: key, message }) = config.output_naming() {
+                return invalid(field(&key), message);
+            }
+            let javascript = matches!(target.as_str(), "typescript" | "ts" | "javascript" | "js");
+            if !javascript {
+                continue;
+            }
+
+            if let Some(value) = config.options.get("module_system") {
+                let module_system = match value {
+                    ConfigValue::String(name) => TypeScriptModuleSystem::from_name(name),
+                    _ => None,
+                };
+                match module_system {
+                    None => return invalid(
+                        field("module_system"),
+                        format!("expected es2020, commonjs, amd or systemjs, got {}", value),
+                    ),
+                    Some(TypeScriptModuleSystem::AMD | TypeScriptModuleSystem::SystemJS)
+                        if matches!(target.as_str(), "javascript" | "js") => return invalid(
+                        field("module_system"),
+                        format!("JavaScript output supports es2020 and commonjs modules, not {}", value),
+                    ),
+                    Some(_) => {}
+                }
+            }
+            if let Some(value) = config.options.get("effect_lowering") {
+                if !matches!(value, ConfigValue::String(name) if TypeScriptEffectLowering::from_name(name).is_some()) {
+                    return invalid(field("effect_lowering"), format!("expected handlers or async, got {}", value));
+                }
+            }
+            if let Some(value) = config.options.get("stack_traces") {
+                if !matches!(value, ConfigValue::Bool(_)) {
+                    return invalid(field("stack_traces"), format!("expected a boolean, got {}", value));
+                }
+            }
+            if let Some(value) = config.options.get("declarations") {
+                if !matches!(value, ConfigValue::Bool(_)) {
+                    return invalid(field("declarations"), format!("expected a boolean, got {}", value));
+                }
+                if matches!(target.as_str(), "typescript" | "ts") && *value == ConfigValue::Bool(true) {
+                    return invalid(
+                        field("declarations"),
+                        "only applies to javascript; TypeScript output carries its own types".to_string(),
+                    );
+                }
+            }
         }
 
         Ok(())
@@ -195,9 +469,17 @@ impl CompilerConfig {
         if other.incremental {
             self.incremental = other.incremental;
         }
+        if other.deterministic {
+            self.deterministic = other.deterministic;
+        }
         if other.cache_dir.is_some() {
             self.cache_dir = other.cache_dir;
         }
+        for feature in other.features {
+            if !self.features.contains(&feature) {
+                self.features.push(feature);
+            }
+        }
 
         // Merge target configs
         for (target, config) in other.target_configs {
@@ -231,6 +513,27 @@ impl TargetConfig {
         }
     }
 
+    /// The naming its `layout` and `file_name` options ask for
+    pub fn output_naming(&self) -> Result<OutputNaming, ConfigError> {
+        let invalid = |field: &str, message: String| ConfigError::Invalid { field: field.to_string(), message };
+        let layout = match self.options.get("layout") {
+            None => OutputLayout::default(),
+            Some(value) => match value {
+                ConfigValue::String(name) => OutputLayout::from_name(name),
+                _ => None,
+            }
+            .ok_or_else(|| invalid("layout", format!("expected flat, per-module or source-tree, got {value}")))?,
+        };
+        let mut naming = OutputNaming::new(layout);
+        match self.options.get("file_name") {
+            None => {}
+            Some(ConfigValue::String(template)) => naming = naming.with_file_name(template),
+            Some(value) => return Err(invalid("file_name", format!("expected a template string, got {value}"))),
+        }
+        naming.validate()?;
+        Ok(naming)
+    }
+
     /// Set boolean option
     pub fn set_bool(&mut self, key: &str, value: bool) {
         self.options.insert(key.to_string(), ConfigValue::Bool(value));
@@ -247,6 +550,103 @@ impl TargetConfig {
     }
 }
 
+impl std::fmt::Display for ConfigValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigValue::Bool(value) => write!(f, "{value}"),
+            ConfigValue::String(value) => write!(f, "`{value}`"),
+            ConfigValue::Number(value) => write!(f, "{value}"),
+            ConfigValue::Array(_) => write!(f, "an array"),
+            ConfigValue::Object(_) => write!(f, "a table"),
+        }
+    }
+}
+
+/// One layer of configuration, setting only some of the options
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PartialConfig {
+    pub syntax_style: Option<SyntaxStyle>,
+    pub optimization_level: Option<u8>,
+    pub debug_info: Option<bool>,
+    pub source_maps: Option<bool>,
+    pub emit_types: Option<bool>,
+    pub incremental: Option<bool>,
+    pub cache_dir: Option<PathBuf>,
+    pub deterministic: Option<bool>,
+    /// Features added to those of the layers below
+    pub features: Option<Vec<String>>,
+    /// Passes replacing those of the layers below
+    pub passes: Option<Vec<String>>,
+    /// Options by target, each overriding the same option below
+    #[serde(default)]
+    pub targets: HashMap<String, HashMap<String, ConfigValue>>,
+}
+
+impl PartialConfig {
+    /// The `[compiler]` table of a manifest, or nothing if it has none
+    pub fn from_manifest(path: &Path) -> Result<Self, ConfigError> {
+        #[derive(Deserialize)]
+        struct Manifest {
+            #[serde(default)]
+            compiler: PartialConfig,
+        }
+
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| ConfigError::Io { path: path.to_path_buf(), error: e })?;
+        let manifest: Manifest = toml::from_str(&content)
+            .map_err(|e| ConfigError::Parse { path: path.to_path_buf(), error: e })?;
+        Ok(manifest.compiler)
+    }
+
+    /// The layer set by the `X_*` variables of the process's environment
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Self::from_vars(std::env::vars())
+    }
+
+    /// The layer set by `X_OPTIMIZATION_LEVEL`, `X_DEBUG_INFO`,
+    /// `X_SOURCE_MAPS`, `X_EMIT_TYPES`, `X_INCREMENTAL`, `X_CACHE_DIR`,
+    /// `X_DETERMINISTIC`, `X_FEATURES` and `X_PASSES` (both comma separated)
+    /// among `vars`
+    pub fn from_vars(vars: impl IntoIterator<Item = (String, String)>) -> Result<Self, ConfigError> {
+        let mut layer = PartialConfig::default();
+        for (name, value) in vars {
+            let invalid = |expected: &str| ConfigError::Invalid {
+                field: name.clone(),
+                message: format!("expected {expected}, got `{value}`"),
+            };
+            let flag = || match value.to_ascii_lowercase().as_str() {
+                "1" | "true" | "yes" | "on" => Ok(true),
+                "0" | "false" | "no" | "off" => Ok(false),
+                _ => Err(invalid("a boolean")),
+            };
+            match name.as_str() {
+                "X_OPTIMIZATION_LEVEL" => {
+                    layer.optimization_level = Some(value.parse().map_err(|_| invalid("a level from 0 to 3"))?);
+                }
+                "X_DEBUG_INFO" => layer.debug_info = Some(flag()?),
+                "X_SOURCE_MAPS" => layer.source_maps = Some(flag()?),
+                "X_EMIT_TYPES" => layer.emit_types = Some(flag()?),
+                "X_INCREMENTAL" => layer.incremental = Some(flag()?),
+                "X_CACHE_DIR" => layer.cache_dir = Some(PathBuf::from(&value)),
+                "X_DETERMINISTIC" => layer.deterministic = Some(flag()?),
+                "X_FEATURES" => {
+                    layer.features = Some(
+                        value.split(',').map(str::trim).filter(|f| !f.is_empty()).map(String::from).collect(),
+                    );
+                }
+                "X_PASSES" => {
+                    layer.passes = Some(
+                        value.split(',').map(str::trim).filter(|p| !p.is_empty()).map(String::from).collect(),
+                    );
+                }
+                _ => {}
+            }
+        }
+        Ok(layer)
+    }
+}
+
 /// Configuration errors
 #[derive(Debug, thiserror::Error)]
 pub enum ConfigError {
@@ -286,6 +686,16 @@ pub mod presets {
         config
     }
 
+    /// JavaScript configuration: ES modules without type annotations;
+    /// set `module_system` to `commonjs` for CommonJS and `declarations` to
+    /// write `.d.mts`/`.d.cts` files alongside
+    pub fn javascript() -> TargetConfig {
+        let mut config = TargetConfig::default();
+        config.set_string("module_system", "es2020");
+        config.set_bool("emit_types", false);
+        config
+    }
+
     /// WebAssembly development configuration
     pub fn wasm_dev() -> TargetConfig {
         let mut config = TargetConfig::default();
@@ -389,6 +799,99 @@ mod tests {
         assert_eq!(base_config.optimization_level, 2);
     }
 
+    #[test]
+    fn test_layers_override_in_order() {
+        let temp_dir = TempDir::new().unwrap();
+        let manifest = temp_dir.path().join("x.toml");
+        std::fs::write(&manifest, "[package]\nname = \"demo\"\n\n[compiler]\noptimization_level = 1\ndebug_info = true\nfeatures = [\"web\"]\npasses = [\"dce\"]\n\n[compiler.targets.javascript]\nmodule_system = \"commonjs\"\n").unwrap();
+
+        let mut config = CompilerConfig::default();
+        config.apply(PartialConfig::from_manifest(&manifest).unwrap());
+        config.apply(PartialConfig::from_vars([
+            ("X_OPTIMIZATION_LEVEL".to_string(), "2".to_string()),
+            ("X_FEATURES".to_string(), "fast, web".to_string()),
+            ("X_DETERMINISTIC".to_string(), "on".to_string()),
+            ("X_PASSES".to_string(), "flatten-pipelines, dce".to_string()),
+            ("HOME".to_string(), "/root".to_string()),
+        ]).unwrap());
+        config.apply(PartialConfig { optimization_level: Some(3), ..Default::default() });
+
+        assert_eq!(config.optimization_level, 3);
+        assert!(config.debug_info && config.deterministic);
+        assert_eq!(config.features, ["web", "fast"]);
+        let passes: Vec<&str> = config.pass_manager().unwrap().passes().iter().map(|pass| pass.name).collect();
+        assert_eq!(passes, ["flatten-pipelines", "constfold", "dce"]);
+        assert_eq!(config.target_config("javascript").get_string("module_system"), Some("commonjs"));
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_invalid_layers_are_reported_precisely() {
+        let error = PartialConfig::from_vars([("X_DEBUG_INFO".to_string(), "maybe".to_string())]).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid configuration for X_DEBUG_INFO: expected a boolean, got `maybe`");
+
+        let temp_dir = TempDir::new().unwrap();
+        let manifest = temp_dir.path().join("x.toml");
+        std::fs::write(&manifest, "[compiler]\noptimisation_level = 2\n").unwrap();
+        let error = PartialConfig::from_manifest(&manifest).unwrap_err();
+        assert!(error.to_string().contains("unknown field `optimisation_level`"), "{error}");
+
+        let invalid = |layer: PartialConfig| {
+            let mut config = CompilerConfig::default();
+            config.apply(layer);
+            config.validate().unwrap_err().to_string()
+        };
+        let target = |target: &str, key: &str, value: ConfigValue| PartialConfig {
+            targets: HashMap::from([(target.to_string(), HashMap::from([(key.to_string(), value)]))]),
+            ..Default::default()
+        };
+        assert_eq!(
+            invalid(PartialConfig { optimization_level: Some(7), ..Default::default() }),
+            "Invalid configuration for optimization_level: must be between 0 and 3, got 7",
+        );
+        assert!(invalid(target("wasmgc", "debug_info", true.into())).starts_with("Invalid configuration for targets.wasmgc: unknown target"));
+        assert_eq!(
+            invalid(target("javascript", "module_system", "amd".into())),
+            "Invalid configuration for targets.javascript.module_system: JavaScript output supports es2020 and commonjs modules, not `amd`",
+        );
+        assert!(invalid(target("typescript", "declarations", true.into())).contains("only applies to javascript"));
+        assert_eq!(
+            invalid(target("javascript", "stack_traces", "yes".into())),
+            "Invalid configuration for targets.javascript.stack_traces: expected a boolean, got `yes`",
+        );
+        assert!(invalid(PartialConfig { passes: Some(vec!["inline-handlers".to_string()]), ..Default::default() })
+            .starts_with("Invalid configuration for passes: unknown pass `inline-handlers`"));
+
+        let mut config = CompilerConfig::default();
+        config.optimization_level = 4;
+        let error = crate::Compiler::new(config).validate_config().unwrap_err();
+        assert!(matches!(error, crate::CompilerError::Config { .. }), "{error}");
+    }
+
+    #[test]
+    fn test_output_naming() {
+        let per_module = OutputNaming::new(OutputLayout::PerModule);
+        assert_eq!(per_module.module_file("Data.List", "ts"), PathBuf::from("Data.List/Data.List.ts"));
+        assert_eq!(per_module.root_from("Data.List"), "../");
+        let tree = OutputNaming::new(OutputLayout::SourceTree).with_file_name("{name}.gen.{ext}");
+        assert_eq!(tree.module_file("App.Data.List", "d.ts"), PathBuf::from("App/Data/List.gen.d.ts"));
+        assert_eq!(tree.root_from("App.Data.List"), "../../");
+        assert_eq!(OutputNaming::default().module_file("Main", "wat"), PathBuf::from("Main.wat"));
+        assert_eq!(OutputNaming::default().root_from("Main"), "./");
+
+        let naming = |options: &[(&str, &str)]| {
+            let mut config = TargetConfig::default();
+            for (key, value) in options {
+                config.set_string(key, value);
+            }
+            config.output_naming().map_err(|error| error.to_string())
+        };
+        assert_eq!(naming(&[("layout", "per-module"), ("file_name", "index.{ext}")]).unwrap().module_file("A", "js"), PathBuf::from("A/index.js"));
+        assert!(naming(&[("layout", "nested")]).unwrap_err().contains("expected flat, per-module or source-tree"));
+        assert!(naming(&[("file_name", "{module}.{extension}")]).unwrap_err().contains("unknown placeholder `{extension}`"));
+        assert!(naming(&[("file_name", "index.{ext}")]).unwrap_err().contains("names the files of every module alike"));
+    }
+
     #[test]
     fn test_target_config_options() {
         let mut config = TargetConfig::default();
@@ -0,0 +1,125 @@
+//! Constant folding on the IR
+//!
+//! [`fold_constants`] evaluates, innermost first, the operator applications
+//! whose operands are literals and the conditionals whose condition is one:
+//!
+//! - `+`, `-` and `*` on integers, and `/` and `%` by a non-zero integer,
+//!   unless the result overflows;
+//! - the comparisons on integers, and `==` and `!=` on booleans and strings;
+//! - `&&` and `||` on booleans;
+//! - `if true then a else b` becomes `a`, and `if false ...` becomes `b`.
+//!
+//! An operator the module defines itself is left alone.
+
+use crate::anf::module_names;
+use crate::ir::{IRExpression, IRLiteral, IRModule};
+use std::collections::HashSet;
+use x_parser::Symbol;
+
+/// Fold the constants of every function and constant of `module`
+pub fn fold_constants(module: &mut IRModule) {
+    let defined = module_names(module);
+    for function in &mut module.functions {
+        fold(&mut function.body, &defined);
+    }
+    for constant in &mut module.constants {
+        fold(&mut constant.value, &defined);
+    }
+}
+
+/// Fold the constants of `expr`; operators named in `defined` are not folded
+pub fn fold(expr: &mut IRExpression, defined: &HashSet<Symbol>) {
+    expr.for_each_child_mut(&mut |child| fold(child, defined));
+    let folded = match expr {
+        IRExpression::Call { function, arguments } => match (function.as_ref(), arguments.as_slice()) {
+            (IRExpression::Variable(operator), [IRExpression::Literal(left), IRExpression::Literal(right)])
+                if !defined.contains(operator) =>
+            {
+                binary(operator.as_str(), left, right).map(IRExpression::Literal)
+            }
+            _ => None,
+        },
+        IRExpression::If { condition, then_branch, else_branch } => match condition.as_ref() {
+            IRExpression::Literal(IRLiteral::Boolean(true)) => Some(std::mem::replace(then_branch.as_mut(), unit())),
+            IRExpression::Literal(IRLiteral::Boolean(false)) => Some(std::mem::replace(else_branch.as_mut(), unit())),
+            _ => None,
+        },
+        _ => None,
+    };
+    if let Some(folded) = folded {
+        *expr = folded;
+    }
+}
+
+fn unit() -> IRExpression {
+    IRExpression::Literal(IRLiteral::Unit)
+}
+
+/// The value of `left operator right`, if it can be known now
+fn binary(operator: &str, left: &IRLiteral, right: &IRLiteral) -> Option<IRLiteral> {
+    use IRLiteral::{Boolean, Integer, String};
+    let value = match (left, right) {
+        (Integer(a), Integer(b)) => match operator {
+            "+" => Integer(a.checked_add(*b)?),
+            "-" => Integer(a.checked_sub(*b)?),
+            "*" => Integer(a.checked_mul(*b)?),
+            "/" => Integer(a.checked_div(*b)?),
+            "%" => Integer(a.checked_rem(*b)?),
+            "==" => Boolean(a == b),
+            "!=" => Boolean(a != b),
+            "<" => Boolean(a < b),
+            "<=" => Boolean(a <= b),
+            ">" => Boolean(a > b),
+            ">=" => Boolean(a >= b),
+            _ => return None,
+        },
+        (Boolean(a), Boolean(b)) => match operator {
+            "&&" => Boolean(*a && *b),
+            "||" => Boolean(*a || *b),
+            "==" => Boolean(a == b),
+            "!=" => Boolean(a != b),
+            _ => return None,
+        },
+        (String(a), String(b)) => match operator {
+            "==" => Boolean(a == b),
+            "!=" => Boolean(a != b),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::IRBuilder;
+    use crate::ir_print::print_expression;
+    use x_parser::{parse_source, FileId, SyntaxStyle};
+
+    fn folded(source: &str) -> Vec<String> {
+        let unit = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let mut module = IRBuilder::new().build_ir(&unit).unwrap().modules.remove(0);
+        fold_constants(&mut module);
+        module.functions.iter().map(|function| print_expression(&function.body)).collect()
+    }
+
+    #[test]
+    fn test_literal_operations_are_folded() {
+        let source = "module Test\n\
+            let a = fun x -> if 1 + 2 * 3 == 7 then x else 0 - 1\n\
+            let b = fun x -> (let y = 7 / 0 in true && x)\n\
+            let c = fun x -> 9223372036854775807 + 1";
+        assert_eq!(folded(source), [
+            "x",
+            "(let ((y (call / 7 0))) (call && true x))",
+            "(call + 9223372036854775807 1)",
+        ]);
+    }
+
+    #[test]
+    fn test_operators_the_module_defines_are_kept() {
+        let source = "module Test\nlet (+) = fun a b -> a\nlet a = fun x -> 1 + 2";
+        assert_eq!(folded(source)[1], "(call + 1 2)");
+    }
+}
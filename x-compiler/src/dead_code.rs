@@ -0,0 +1,74 @@
+//! Dead code elimination on the IR
+//!
+//! [`eliminate_dead_code`] removes the computations whose result is never
+//! used and which cannot do anything else: let bindings of values nothing
+//! after them refers to, and values evaluated for effect in a block. Only
+//! [values](crate::anf::is_value) are removed, so calls, which may perform
+//! effects or fail to return, always stay.
+
+use crate::anf::is_value;
+use crate::ir::{IRExpression, IRLiteral, IRModule};
+use std::collections::HashSet;
+
+/// Remove the dead code of every function and constant of `module`
+pub fn eliminate_dead_code(module: &mut IRModule) {
+    for function in &mut module.functions {
+        eliminate(&mut function.body);
+    }
+    for constant in &mut module.constants {
+        eliminate(&mut constant.value);
+    }
+}
+
+/// Remove the dead code of `expr`, innermost first
+pub fn eliminate(expr: &mut IRExpression) {
+    expr.for_each_child_mut(&mut eliminate);
+    match expr {
+        IRExpression::Let { bindings, body } => {
+            // Walk back from the body, keeping what the rest refers to
+            let mut used: HashSet<_> = body.free_variables().into_iter().collect();
+            let mut kept = Vec::with_capacity(bindings.len());
+            for binding in std::mem::take(bindings).into_iter().rev() {
+                if used.contains(&binding.name) || !is_value(&binding.value) {
+                    used.remove(&binding.name);
+                    used.extend(binding.value.free_variables());
+                    kept.push(binding);
+                }
+            }
+            kept.reverse();
+            *bindings = kept;
+            if bindings.is_empty() {
+                *expr = std::mem::replace(body.as_mut(), IRExpression::Literal(IRLiteral::Unit));
+            }
+        }
+        IRExpression::Block(expressions) if expressions.len() > 1 => {
+            let last = expressions.pop().expect("more than one expression");
+            expressions.retain(|expression| !is_value(expression));
+            expressions.push(last);
+            if expressions.len() == 1 {
+                *expr = expressions.pop().expect("one expression");
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::IRBuilder;
+    use crate::ir_print::print_expression;
+    use x_parser::{parse_source, FileId, SyntaxStyle};
+
+    #[test]
+    fn test_unused_values_are_removed() {
+        let source = "module Test\n\
+            let g = fun x -> x\n\
+            let f = fun x -> (let a = (x, 1) in (let b = g x in (let c = fun y -> a in x)))";
+        let unit = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let mut module = IRBuilder::new().build_ir(&unit).unwrap().modules.remove(0);
+        eliminate_dead_code(&mut module);
+        // The call stays, though its result is unused
+        assert_eq!(print_expression(&module.functions[1].body), "(let ((b (call g x))) x)");
+    }
+}
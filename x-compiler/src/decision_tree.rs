@@ -0,0 +1,406 @@
+//! Match compilation
+//!
+//! [`compile`] turns the cases of a `match` into a [`Decision`] tree in the
+//! manner of Maranget's "Compiling Pattern Matching to Good Decision Trees":
+//! each node tests one part of the value, so on any path through the tree
+//! nothing is tested twice, and the backends lower a node to a single
+//! `switch` or branch table instead of trying the cases one after another.
+//!
+//! - or-patterns fork the case, one row per alternative, each binding the
+//!   case's variables to its own parts of the value;
+//! - a guard that does not hold goes on to the cases after it, as compiled
+//!   for what the tree already knows about the value;
+//! - branches that decide the same as the default are dropped, and runs of
+//!   consecutive integers deciding the same become one [`Test::Range`];
+//! - a switch covering every constructor of a type, or both booleans, has
+//!   no default.
+//!
+//! The tree is exhaustive when no path ends in [`Decision::Fail`], so a
+//! backend only emits a match failure where one can happen.
+
+use crate::ir::{IRLiteral, IRMatchCase, IRModule, IRPattern, IRTypeDefinitionKind};
+use std::collections::{HashMap, VecDeque};
+use x_parser::Symbol;
+
+/// Data types every module has, with the arity of their constructors
+const PRELUDE_TYPES: &[(&str, &[(&str, usize)])] = &[
+    ("List", &[("Nil", 0), ("Cons", 2)]),
+    ("Option", &[("None", 0), ("Some", 1)]),
+];
+
+/// Where a part of the matched value is found
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Access {
+    /// The matched value itself
+    Root,
+    /// Argument `index` of a value built with `constructor`
+    Field { parent: Box<Access>, constructor: Symbol, index: usize },
+    /// Element `index` of a tuple
+    Element { parent: Box<Access>, index: usize },
+    /// A field of a record
+    RecordField { parent: Box<Access>, field: Symbol },
+}
+
+/// What a branch of a [`Decision::Switch`] requires of the value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Test {
+    Constructor(Symbol),
+    Int(i64),
+    /// An integer in `low..=high`
+    Range(i64, i64),
+    Float(f64),
+    Bool(bool),
+    String(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decision {
+    /// No case matches
+    Fail,
+    /// Case `case` matches, with its variables bound to parts of the value.
+    /// When the case has a guard, `otherwise` decides if it does not hold.
+    Leaf {
+        case: usize,
+        bindings: Vec<(Symbol, Access)>,
+        otherwise: Option<Box<Decision>>,
+    },
+    /// Branch on the part of the value at `access`; `default` decides for
+    /// values no branch tests for, and is absent when there are none
+    Switch {
+        access: Access,
+        branches: Vec<(Test, Decision)>,
+        default: Option<Box<Decision>>,
+    },
+}
+
+impl Decision {
+    /// Whether every value reaches a case
+    pub fn is_exhaustive(&self) -> bool {
+        match self {
+            Decision::Fail => false,
+            Decision::Leaf { otherwise, .. } => otherwise.as_deref().is_none_or(Decision::is_exhaustive),
+            Decision::Switch { branches, default, .. } => {
+                branches.iter().all(|(_, decision)| decision.is_exhaustive())
+                    && default.as_deref().is_none_or(Decision::is_exhaustive)
+            }
+        }
+    }
+}
+
+/// The constructors of the data types a module can match on
+#[derive(Debug, Clone, Default)]
+pub struct Constructors {
+    /// Arity of each constructor, and the constructors of its type in order
+    by_name: HashMap<Symbol, (usize, Vec<Symbol>)>,
+}
+
+impl Constructors {
+    /// The prelude's constructors and those of the module's data types
+    pub fn for_module(module: &IRModule) -> Self {
+        let mut constructors = Self::default();
+        for (_, prelude) in PRELUDE_TYPES {
+            constructors.add(prelude.iter().map(|(name, arity)| (Symbol::intern(name), *arity)));
+        }
+        for type_def in &module.types {
+            if let IRTypeDefinitionKind::Variant(variants) = &type_def.definition {
+                constructors.add(variants.iter().map(|(name, fields)| (*name, fields.len())));
+            }
+        }
+        constructors
+    }
+
+    fn add(&mut self, variants: impl Iterator<Item = (Symbol, usize)>) {
+        let variants: Vec<(Symbol, usize)> = variants.collect();
+        let siblings: Vec<Symbol> = variants.iter().map(|(name, _)| *name).collect();
+        for (name, arity) in variants {
+            self.by_name.insert(name, (arity, siblings.clone()));
+        }
+    }
+
+    pub fn arity(&self, name: Symbol) -> Option<usize> {
+        self.by_name.get(&name).map(|(arity, _)| *arity)
+    }
+
+    /// The constructors of the type `name` belongs to, in definition order
+    pub fn siblings(&self, name: Symbol) -> &[Symbol] {
+        self.by_name.get(&name).map_or(&[], |(_, siblings)| siblings)
+    }
+
+    /// Position of `name` among its siblings
+    pub fn tag(&self, name: Symbol) -> Option<usize> {
+        self.siblings(name).iter().position(|sibling| *sibling == name)
+    }
+}
+
+/// Compile the cases of a match, tried in order, to a decision tree
+pub fn compile(cases: &[IRMatchCase], constructors: &Constructors) -> Decision {
+    let mut rows = Vec::new();
+    for (case, match_case) in cases.iter().enumerate() {
+        let row = Row { case, tests: Vec::new(), bindings: Vec::new() };
+        expand(row, VecDeque::from([(Access::Root, &match_case.pattern)]), constructors, &mut rows);
+    }
+    decide(rows, cases, constructors)
+}
+
+/// A case still in the running, with what is left to test of it
+#[derive(Debug, Clone)]
+struct Row<'a> {
+    case: usize,
+    /// Parts of the value the case tests, with the pattern each must match
+    tests: Vec<(Access, &'a IRPattern)>,
+    bindings: Vec<(Symbol, Access)>,
+}
+
+/// Add `row` to `rows` once `pending` is sorted into bindings and tests,
+/// one row per alternative of each or-pattern
+fn expand<'a>(
+    mut row: Row<'a>,
+    mut pending: VecDeque<(Access, &'a IRPattern)>,
+    constructors: &Constructors,
+    rows: &mut Vec<Row<'a>>,
+) {
+    while let Some((access, pattern)) = pending.pop_front() {
+        match pattern {
+            IRPattern::Wildcard | IRPattern::Literal(IRLiteral::Unit) => {}
+            // `None` is parsed as a variable
+            IRPattern::Variable(name) if constructors.arity(*name) == Some(0) => row.tests.push((access, pattern)),
+            IRPattern::Variable(name) if name.as_str() == "_" => {}
+            IRPattern::Variable(name) => row.bindings.push((*name, access)),
+            IRPattern::Tuple(patterns) => {
+                for (index, pattern) in patterns.iter().enumerate().rev() {
+                    pending.push_front((Access::Element { parent: Box::new(access.clone()), index }, pattern));
+                }
+            }
+            IRPattern::Record(fields) => {
+                for (field, pattern) in fields.iter().rev() {
+                    pending.push_front((Access::RecordField { parent: Box::new(access.clone()), field: *field }, pattern));
+                }
+            }
+            IRPattern::Or(alternatives) => {
+                for alternative in alternatives {
+                    let mut pending = pending.clone();
+                    pending.push_front((access.clone(), alternative));
+                    expand(row.clone(), pending, constructors, rows);
+                }
+                return;
+            }
+            IRPattern::Constructor { .. } | IRPattern::Literal(_) => row.tests.push((access, pattern)),
+        }
+    }
+    rows.push(row);
+}
+
+fn decide(mut rows: Vec<Row>, cases: &[IRMatchCase], constructors: &Constructors) -> Decision {
+    let Some(first) = rows.first() else { return Decision::Fail };
+    let Some((access, _)) = first.tests.first() else {
+        let first = rows.remove(0);
+        let otherwise = cases[first.case].guard.is_some()
+            .then(|| Box::new(decide(rows, cases, constructors)));
+        return Decision::Leaf { case: first.case, bindings: first.bindings, otherwise };
+    };
+    let access = access.clone();
+
+    // The tests made at `access`, in the order the cases make them
+    let mut heads = Vec::new();
+    for row in &rows {
+        if let Some(test) = row.test_at(&access) {
+            if !heads.contains(&test) {
+                heads.push(test);
+            }
+        }
+    }
+    let branches = heads.iter()
+        .map(|head| (head.clone(), decide(specialize(&rows, &access, head, constructors), cases, constructors)))
+        .collect();
+    let default = (!complete(&heads, constructors)).then(|| {
+        let rows = rows.into_iter().filter(|row| row.test_at(&access).is_none()).collect();
+        Box::new(decide(rows, cases, constructors))
+    });
+    simplify(access, branches, default)
+}
+
+impl Row<'_> {
+    fn test_at(&self, access: &Access) -> Option<Test> {
+        self.tests.iter()
+            .find(|(tested, _)| tested == access)
+            .map(|(_, pattern)| test(pattern))
+    }
+}
+
+/// The rows that can still match once the value at `access` passed `head`,
+/// with the arguments of a matched constructor to be tested in its place
+fn specialize<'a>(rows: &[Row<'a>], access: &Access, head: &Test, constructors: &Constructors) -> Vec<Row<'a>> {
+    let mut specialized = Vec::new();
+    for row in rows {
+        let Some(position) = row.tests.iter().position(|(tested, _)| tested == access) else {
+            specialized.push(row.clone());
+            continue;
+        };
+        let pattern = row.tests[position].1;
+        if test(pattern) != *head {
+            continue;
+        }
+        let mut row = row.clone();
+        row.tests.remove(position);
+        let arguments = match pattern {
+            IRPattern::Constructor { name, arguments } => arguments.iter()
+                .enumerate()
+                .map(|(index, argument)| {
+                    (Access::Field { parent: Box::new(access.clone()), constructor: *name, index }, argument)
+                })
+                .collect(),
+            _ => VecDeque::new(),
+        };
+        expand(row, arguments, constructors, &mut specialized);
+    }
+    specialized
+}
+
+/// The test a pattern left in a row's tests makes
+fn test(pattern: &IRPattern) -> Test {
+    match pattern {
+        IRPattern::Constructor { name, .. } | IRPattern::Variable(name) => Test::Constructor(*name),
+        IRPattern::Literal(IRLiteral::Integer(n)) => Test::Int(*n),
+        IRPattern::Literal(IRLiteral::Float(x)) => Test::Float(*x),
+        IRPattern::Literal(IRLiteral::Boolean(b)) => Test::Bool(*b),
+        IRPattern::Literal(IRLiteral::String(s)) => Test::String(s.clone()),
+        other => unreachable!("{other:?} makes no test"),
+    }
+}
+
+/// Whether every value passes one of `heads`
+fn complete(heads: &[Test], constructors: &Constructors) -> bool {
+    match heads.first() {
+        Some(Test::Bool(_)) => heads.contains(&Test::Bool(true)) && heads.contains(&Test::Bool(false)),
+        Some(Test::Constructor(name)) => {
+            let siblings = constructors.siblings(*name);
+            !siblings.is_empty() && siblings.iter().all(|sibling| heads.contains(&Test::Constructor(*sibling)))
+        }
+        _ => false,
+    }
+}
+
+fn simplify(access: Access, mut branches: Vec<(Test, Decision)>, default: Option<Box<Decision>>) -> Decision {
+    match default.as_deref() {
+        Some(default) => branches.retain(|(_, decision)| decision != default),
+        None if branches.windows(2).all(|pair| pair[0].1 == pair[1].1) => return branches.swap_remove(0).1,
+        None => {}
+    }
+    if branches.is_empty() {
+        return *default.expect("a switch without branches has a default");
+    }
+    if branches.iter().all(|(test, _)| matches!(test, Test::Int(_))) {
+        branches = ranges(branches);
+    }
+    Decision::Switch { access, branches, default }
+}
+
+/// Sort integer branches, merging runs of three or more consecutive values
+/// that decide the same into ranges
+fn ranges(mut branches: Vec<(Test, Decision)>) -> Vec<(Test, Decision)> {
+    let value = |test: &Test| match test {
+        Test::Int(n) => *n,
+        _ => unreachable!("only integer branches are merged"),
+    };
+    branches.sort_by_key(|(test, _)| value(test));
+    let mut merged: Vec<(i64, i64, Decision)> = Vec::new();
+    for (test, decision) in branches {
+        let n = value(&test);
+        match merged.last_mut() {
+            Some((_, high, last)) if high.checked_add(1) == Some(n) && *last == decision => *high = n,
+            _ => merged.push((n, n, decision)),
+        }
+    }
+    merged.into_iter()
+        .flat_map(|(low, high, decision)| {
+            if high - low >= 2 {
+                vec![(Test::Range(low, high), decision)]
+            } else {
+                (low..=high).map(|n| (Test::Int(n), decision.clone())).collect()
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::{IRBuilder, IRExpression};
+    use x_parser::{parse_source, FileId, SyntaxStyle};
+
+    /// The decision tree of the first match in the body of `name`
+    fn tree(source: &str, name: &str) -> Decision {
+        let unit = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let module = IRBuilder::new().build_ir(&unit).unwrap().modules.remove(0);
+        let function = module.functions.iter().find(|function| function.name.as_str() == name).unwrap();
+        let IRExpression::Match { cases, .. } = &function.body else { panic!("expected a match") };
+        compile(cases, &Constructors::for_module(&module))
+    }
+
+    fn leaf(case: usize) -> Decision {
+        Decision::Leaf { case, bindings: Vec::new(), otherwise: None }
+    }
+
+    #[test]
+    fn test_or_patterns_and_ranges() {
+        let decision = tree("module Demo\nlet size = fun n -> match n with | 1 | 2 | 3 | 7 => 0 | 4 | 5 => 1 | _ => 2", "size");
+        let expected = Decision::Switch {
+            access: Access::Root,
+            branches: vec![
+                (Test::Range(1, 3), leaf(0)),
+                (Test::Int(4), leaf(1)),
+                (Test::Int(5), leaf(1)),
+                (Test::Int(7), leaf(0)),
+            ],
+            default: Some(Box::new(leaf(2))),
+        };
+        assert_eq!(decision, expected);
+        assert!(decision.is_exhaustive());
+    }
+
+    #[test]
+    fn test_complete_switches_have_no_default() {
+        let source = "module Demo\n\
+            data Shape = Dot | Circle Int | Square Int\n\
+            let size = fun s -> match s with | Circle r | Square r => r | Dot => 0\n\
+            let partial = fun s -> match s with | Circle r => r\n\
+            let same = fun p -> match p with | (true, x) | (false, x) => x";
+        let Decision::Switch { branches, default, .. } = tree(source, "size") else { panic!("expected a switch") };
+        assert_eq!(default, None);
+        let field = |constructor| Access::Field { parent: Box::new(Access::Root), constructor: Symbol::intern(constructor), index: 0 };
+        let r = Symbol::intern("r");
+        assert_eq!(branches[1].1, Decision::Leaf { case: 0, bindings: vec![(r, field("Square"))], otherwise: None });
+
+        let partial = tree(source, "partial");
+        assert!(matches!(&partial, Decision::Switch { default: Some(default), .. } if **default == Decision::Fail));
+        assert!(!partial.is_exhaustive());
+        // Both branches decide the same, so there is nothing to test
+        let x = (Symbol::intern("x"), Access::Element { parent: Box::new(Access::Root), index: 1 });
+        assert_eq!(tree(source, "same"), Decision::Leaf { case: 0, bindings: vec![x], otherwise: None });
+    }
+
+    #[test]
+    fn test_guards_fall_through_and_wildcards_collapse() {
+        let source = "module Demo\n\
+            let pick = fun p -> match p with | (Some x, _) if x > 0 => x | (_, None) => 0 | (None, Some y) => y | (_, _) => 1";
+        let Decision::Switch { access, branches, default } = tree(source, "pick") else { panic!("expected a switch") };
+        assert_eq!(access, Access::Element { parent: Box::new(Access::Root), index: 0 });
+        assert_eq!(default, None);
+        // `Some x` whose guard fails goes on to the second element, where
+        // only the rows that did not need `None` first are left
+        let (Test::Constructor(some), Decision::Leaf { case: 0, otherwise: Some(otherwise), .. }) = &branches[0] else {
+            panic!("expected the guarded case: {branches:?}")
+        };
+        assert_eq!(some.as_str(), "Some");
+        let expected = Decision::Switch {
+            access: Access::Element { parent: Box::new(Access::Root), index: 1 },
+            branches: vec![(Test::Constructor(Symbol::intern("None")), leaf(1))],
+            default: Some(Box::new(leaf(3))),
+        };
+        assert_eq!(otherwise.as_ref(), &expected);
+        // After `None` the second element decides between the two cases
+        // that can still match, and `(_, _)` is left out
+        let Decision::Switch { branches: after_none, default: None, .. } = &branches[1].1 else { panic!("expected a switch") };
+        assert!(matches!(after_none[..], [(_, Decision::Leaf { case: 1, .. }), (_, Decision::Leaf { case: 2, .. })]));
+    }
+}
@@ -0,0 +1,318 @@
+//! Rendering of compiler diagnostics
+//!
+//! Diagnostics are printed either as source excerpts with the offending code
+//! underlined, in the style of rustc, or as JSON for editors and CI tools.
+//!
+//! Every diagnostic carries a stable code. Codes are never reused or
+//! renumbered; new ones are appended to their stage's range:
+//!
+//! - `E00xx`: parser ([`ParseError::code`])
+//! - `E01xx`: type checker ([`TypeError::code`])
+//! - `E02xx`: code generation ([`CodegenDiagnostic::code`](crate::CodegenDiagnostic))
+
+use crate::{CompilerDiagnostic, DiagnosticLabel, DiagnosticSource};
+use crate::backend::DiagnosticSeverity;
+use serde_json::{json, Value};
+use std::borrow::Cow;
+use std::fmt::Write;
+use x_checker::TypeError;
+use x_editor::QuickFix;
+use x_parser::span::{LineMap, Position};
+use x_parser::{FileId, ParseError, SourceMap, Span};
+
+impl CompilerDiagnostic {
+    /// Diagnostic for a type error, keeping its secondary labels and notes
+    pub fn from_type_error(error: &TypeError, severity: DiagnosticSeverity) -> Self {
+        CompilerDiagnostic {
+            severity,
+            message: error.to_string(),
+            source: DiagnosticSource::TypeChecker,
+            code: Some(error.code()),
+            span: Some(error.span()),
+            labels: error.labels().into_iter()
+                .map(|(span, message)| DiagnosticLabel { span, message })
+                .collect(),
+            notes: error.notes(),
+            suggested_fix: None,
+        }
+    }
+
+    /// Attach a fix, e.g. one found by [`x_editor::suggest_fix`]
+    pub fn with_fix(mut self, fix: Option<QuickFix>) -> Self {
+        self.suggested_fix = fix;
+        self
+    }
+
+    /// Diagnostic for a syntax error
+    pub fn from_parse_error(error: &ParseError) -> Self {
+        let message = match error {
+            ParseError::Syntax { message, .. } => message.clone(),
+            error => error.to_string(),
+        };
+        CompilerDiagnostic {
+            severity: DiagnosticSeverity::Error,
+            message,
+            source: DiagnosticSource::Parser,
+            code: Some(error.code()),
+            span: error.span(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+            suggested_fix: None,
+        }
+    }
+}
+
+/// Renders diagnostics against the source they refer to
+pub struct DiagnosticRenderer<'a> {
+    path: Cow<'a, str>,
+    source: &'a str,
+    line_map: Cow<'a, LineMap>,
+}
+
+impl<'a> DiagnosticRenderer<'a> {
+    pub fn new(path: &'a str, source: &'a str) -> Self {
+        DiagnosticRenderer {
+            path: Cow::Borrowed(path),
+            source,
+            line_map: Cow::Owned(LineMap::new(source)),
+        }
+    }
+
+    /// A renderer for `file` of `sources`, reusing its line map
+    pub fn for_file(sources: &'a SourceMap, file: FileId) -> Option<Self> {
+        let file = sources.file(file)?;
+        Some(DiagnosticRenderer {
+            path: file.path().to_string_lossy(),
+            source: file.text(),
+            line_map: Cow::Borrowed(file.lines()),
+        })
+    }
+
+    /// Render one diagnostic with underlined source excerpts:
+    ///
+    /// ```text
+    /// error[E0101]: Type mismatch: expected Int, found Bool
+    ///  --> main.x:2:15
+    ///   |
+    /// 2 | let x : Int = true
+    ///   |               ^^^^
+    /// 2 | let x : Int = true
+    ///   |         --- expected because of this annotation
+    ///   |
+    ///   = note: ...
+    ///   = help: change the annotation to `Bool`
+    /// ```
+    pub fn render(&self, diagnostic: &CompilerDiagnostic) -> String {
+        let mut out = String::new();
+        let severity = severity_name(&diagnostic.severity);
+        let header = match diagnostic.code {
+            Some(code) => format!("{severity}[{code}]"),
+            None => severity.to_string(),
+        };
+        let _ = writeln!(out, "{header}: {}", diagnostic.message);
+        let help = diagnostic.suggested_fix.as_ref().map(|fix| &fix.title);
+
+        let Some(span) = diagnostic.span else {
+            let _ = writeln!(out, " --> {}", self.path);
+            for note in &diagnostic.notes {
+                let _ = writeln!(out, "  = note: {note}");
+            }
+            if let Some(help) = help {
+                let _ = writeln!(out, "  = help: {help}");
+            }
+            return out;
+        };
+
+        let lines = std::iter::once(span)
+            .chain(diagnostic.labels.iter().map(|label| label.span))
+            .map(|span| self.position(span.start).line.to_display());
+        let width = lines.max().unwrap_or(1).to_string().len();
+        let gutter = " ".repeat(width);
+
+        let start = self.position(span.start);
+        let _ = writeln!(out, "{gutter}--> {}:{start}", self.path);
+        let _ = writeln!(out, "{gutter} |");
+        self.render_excerpt(&mut out, span, '^', "", width);
+        for label in &diagnostic.labels {
+            self.render_excerpt(&mut out, label.span, '-', &label.message, width);
+        }
+        if !diagnostic.notes.is_empty() || help.is_some() {
+            let _ = writeln!(out, "{gutter} |");
+        }
+        for note in &diagnostic.notes {
+            let _ = writeln!(out, "{gutter} = note: {note}");
+        }
+        if let Some(help) = help {
+            let _ = writeln!(out, "{gutter} = help: {help}");
+        }
+        out
+    }
+
+    /// Render every diagnostic, separated by blank lines
+    pub fn render_all(&self, diagnostics: &[CompilerDiagnostic]) -> String {
+        diagnostics.iter()
+            .map(|diagnostic| self.render(diagnostic))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Machine-readable form of the diagnostics, with 1-based positions
+    pub fn to_json(&self, diagnostics: &[CompilerDiagnostic]) -> Value {
+        let diagnostics: Vec<Value> = diagnostics.iter()
+            .map(|diagnostic| json!({
+                "severity": severity_name(&diagnostic.severity),
+                "source": source_name(&diagnostic.source),
+                "code": diagnostic.code,
+                "message": diagnostic.message,
+                "file": self.path,
+                "span": diagnostic.span.map(|span| self.span_json(span)),
+                "labels": diagnostic.labels.iter()
+                    .map(|label| json!({
+                        "message": label.message,
+                        "span": self.span_json(label.span),
+                    }))
+                    .collect::<Vec<_>>(),
+                "notes": diagnostic.notes,
+                "fix": diagnostic.suggested_fix.as_ref().map(|fix| json!({
+                    "title": fix.title,
+                    "operations": fix.operations,
+                    "edits": fix.text_edits.iter()
+                        .map(|edit| json!({
+                            "span": self.span_json(edit.span),
+                            "new_text": edit.new_text,
+                        }))
+                        .collect::<Vec<_>>(),
+                })),
+            }))
+            .collect();
+        json!({ "diagnostics": diagnostics })
+    }
+
+    /// Source line of `span` with its first line underlined by `marker`
+    fn render_excerpt(&self, out: &mut String, span: Span, marker: char, message: &str, width: usize) {
+        let start = self.position(span.start);
+        let text = self.line_text(start);
+        // Columns count characters; spans running past the end of their
+        // first line are cut there
+        let length = text.chars().count();
+        let underline_start = (start.column.as_u32() as usize).min(length);
+        let end = if self.position(span.end).line == start.line {
+            self.position(span.end).column.as_u32() as usize
+        } else {
+            length
+        };
+        let underline_len = end.min(length).saturating_sub(underline_start).max(1);
+
+        let gutter = " ".repeat(width);
+        let _ = writeln!(out, "{:>width$} | {text}", start.line.to_display());
+        let underline = marker.to_string().repeat(underline_len);
+        let label = if message.is_empty() { String::new() } else { format!(" {message}") };
+        let _ = writeln!(out, "{gutter} | {}{underline}{label}", " ".repeat(underline_start));
+    }
+
+    fn line_text(&self, position: Position) -> &str {
+        self.line_map.line_text(self.source, position.line).unwrap_or("")
+    }
+
+    fn position(&self, offset: x_parser::span::CharOffset) -> Position {
+        self.line_map.offset_to_position(offset)
+    }
+
+    fn span_json(&self, span: Span) -> Value {
+        let start = self.position(span.start);
+        let end = self.position(span.end);
+        json!({
+            "start": { "line": start.line.to_display(), "column": start.column.to_display() },
+            "end": { "line": end.line.to_display(), "column": end.column.to_display() },
+        })
+    }
+}
+
+fn severity_name(severity: &DiagnosticSeverity) -> &'static str {
+    match severity {
+        DiagnosticSeverity::Error => "error",
+        DiagnosticSeverity::Warning => "warning",
+        DiagnosticSeverity::Info => "info",
+    }
+}
+
+fn source_name(source: &DiagnosticSource) -> &'static str {
+    match source {
+        DiagnosticSource::Parser => "parser",
+        DiagnosticSource::TypeChecker => "type-checker",
+        DiagnosticSource::CodeGenerator => "codegen",
+        DiagnosticSource::Linker => "linker",
+        DiagnosticSource::Optimizer => "optimizer",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x_parser::{parse_source, FileId, SyntaxStyle};
+
+    fn check(source: &str) -> Vec<CompilerDiagnostic> {
+        let unit = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        x_checker::type_check(&unit).errors.iter()
+            .map(|error| CompilerDiagnostic::from_type_error(error, DiagnosticSeverity::Error))
+            .collect()
+    }
+
+    #[test]
+    fn test_render_underlines_span_and_labels() {
+        let source = "module Test\nlet x : Int = true";
+        let diagnostics = check(source);
+
+        let rendered = DiagnosticRenderer::new("main.x", source).render(&diagnostics[0]);
+        assert_eq!(rendered, "\
+error[E0101]: Type mismatch: expected Int, found Bool
+ --> main.x:2:15
+  |
+2 | let x : Int = true
+  |               ^^^^
+2 | let x : Int = true
+  |         --- expected because of this annotation
+");
+    }
+
+    #[test]
+    fn test_underline_counts_characters() {
+        let source = "module Test\nlet café : Int = true";
+        let diagnostics = check(source);
+
+        let rendered = DiagnosticRenderer::new("main.x", source).render(&diagnostics[0]);
+        assert!(rendered.contains(" --> main.x:2:18\n"));
+        assert!(rendered.contains("2 | let café : Int = true\n  |                  ^^^^\n"));
+    }
+
+    #[test]
+    fn test_json_output() {
+        let source = "module Test\nlet x : Int = true";
+        let diagnostics = check(source);
+
+        let json = DiagnosticRenderer::new("main.x", source).to_json(&diagnostics);
+        let diagnostic = &json["diagnostics"][0];
+        assert_eq!(diagnostic["severity"], "error");
+        assert_eq!(diagnostic["source"], "type-checker");
+        assert_eq!(diagnostic["span"]["start"], json!({ "line": 2, "column": 15 }));
+        assert_eq!(diagnostic["labels"][0]["message"], "expected because of this annotation");
+        assert_eq!(diagnostic["code"], "E0101");
+    }
+
+    #[test]
+    fn test_suggested_fix_is_rendered() {
+        let source = "module Test\nlet x : Int = true";
+        let unit = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let error = &x_checker::type_check(&unit).errors[0];
+        let diagnostic = CompilerDiagnostic::from_type_error(error, DiagnosticSeverity::Error)
+            .with_fix(x_editor::suggest_fix(&unit, source, error));
+
+        let renderer = DiagnosticRenderer::new("main.x", source);
+        assert!(renderer.render(&diagnostic).ends_with("  |\n  = help: change the annotation to `Bool`\n"));
+
+        let fix = &renderer.to_json(&[diagnostic])["diagnostics"][0]["fix"];
+        assert_eq!(fix["edits"][0]["new_text"], "Bool");
+        assert_eq!(fix["edits"][0]["span"]["start"], json!({ "line": 2, "column": 9 }));
+        assert!(fix["operations"][0]["Replace"].is_object());
+    }
+}
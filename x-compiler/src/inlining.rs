@@ -0,0 +1,305 @@
+//! Inlining of small functions across module boundaries
+//!
+//! When a project of several modules is compiled with optimizations, the
+//! pipeline collects the [candidates](InlineCandidates) of every module, and
+//! [`inline_calls`] replaces the calls of other modules' candidates by their
+//! bodies, the arguments bound to the parameters, before the
+//! [passes](crate::passes) run. A function is a candidate when
+//!
+//! - it is public and not marked `@noinline`;
+//! - its body refers to nothing but its parameters and operators neither
+//!   module defines, so it means the same in the caller's module;
+//! - its body is at most [`INLINE_BUDGET`] IR nodes, or it is marked
+//!   `@inline`.
+//!
+//! A call is inlined when it passes at least as many arguments as the
+//! function has parameters, qualified, as in `Lib.double x`, or through an
+//! imported name. The caller gets an `inlined` attribute naming each
+//! definition inlined into it, which generated code built with debug info or
+//! source maps points back at.
+
+use crate::anf::module_names;
+use crate::ir::{IRAttribute, IRBinding, IRExpression, IRFunction, IRModule};
+use std::collections::{HashMap, HashSet};
+use x_parser::{Symbol, Visibility};
+
+/// The largest body, in IR nodes, inlined without `@inline`
+pub const INLINE_BUDGET: usize = 16;
+
+/// The functions other modules may inline, by module and name
+#[derive(Debug, Clone, Default)]
+pub struct InlineCandidates {
+    functions: HashMap<(Symbol, Symbol), IRFunction>,
+}
+
+impl InlineCandidates {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the functions of `module` worth inlining
+    pub fn add_module(&mut self, module: &IRModule) {
+        let defined = module_names(module);
+        for function in &module.functions {
+            if is_candidate(function, &defined) {
+                self.functions.insert((module.name, function.name), function.clone());
+            }
+        }
+    }
+
+    /// The candidate `name` of `module`
+    pub fn get(&self, module: Symbol, name: Symbol) -> Option<&IRFunction> {
+        self.functions.get(&(module, name))
+    }
+
+    pub fn len(&self) -> usize {
+        self.functions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty()
+    }
+}
+
+/// The number of IR nodes in `expr`
+pub fn cost(expr: &IRExpression) -> usize {
+    let mut nodes = 0;
+    expr.walk(&mut |_| {
+        nodes += 1;
+        true
+    });
+    nodes
+}
+
+/// Whether `function` may be inlined into other modules; `defined` holds
+/// the names its module defines or imports
+fn is_candidate(function: &IRFunction, defined: &HashSet<Symbol>) -> bool {
+    let forced = function.has_attribute("inline");
+    function.visibility == Visibility::Public
+        && !function.parameters.is_empty()
+        && !function.has_attribute("noinline")
+        && (forced || cost(&function.body) <= INLINE_BUDGET)
+        && function.body.free_variables().iter().all(|name| {
+            function.parameters.iter().any(|parameter| parameter.name == *name)
+                || (is_operator(*name) && !defined.contains(name))
+        })
+}
+
+fn is_operator(name: Symbol) -> bool {
+    name.as_str().chars().all(|c| !c.is_alphanumeric() && c != '_' && c != '$')
+}
+
+/// Inline the calls of `candidates` in the functions of `module`
+pub fn inline_calls(module: &mut IRModule, candidates: &InlineCandidates) {
+    if candidates.is_empty() {
+        return;
+    }
+    let mut inliner = Inliner {
+        qualified: HashMap::new(),
+        imported: HashMap::new(),
+        defined: module_names(module),
+        candidates,
+        inlined: Vec::new(),
+    };
+    for import in &module.imports {
+        if let Some(qualifier) = import.qualifier {
+            inliner.qualified.insert(qualifier, import.module);
+        }
+        for item in &import.items {
+            inliner.imported.insert(item.alias.unwrap_or(item.name), (import.module, item.name));
+        }
+    }
+    for function in &mut module.functions {
+        let mut bound = function.parameters.iter().map(|parameter| parameter.name).collect();
+        inliner.rewrite(&mut function.body, &mut bound);
+        for origin in std::mem::take(&mut inliner.inlined) {
+            let value = Some(origin);
+            if !function.attributes.iter().any(|attribute| attribute.name.as_str() == "inlined" && attribute.value == value) {
+                function.attributes.push(IRAttribute { name: Symbol::intern("inlined"), value });
+            }
+        }
+    }
+}
+
+struct Inliner<'a> {
+    /// Modules by the qualifier they are imported under
+    qualified: HashMap<Symbol, Symbol>,
+    /// Module and name of the values imported unqualified, by local name
+    imported: HashMap<Symbol, (Symbol, Symbol)>,
+    /// Names the caller's module defines or imports
+    defined: HashSet<Symbol>,
+    candidates: &'a InlineCandidates,
+    /// `Module.name` of each function inlined into the current one
+    inlined: Vec<String>,
+}
+
+impl<'a> Inliner<'a> {
+    /// Inline the calls in `expr`, innermost first; `bound` holds the
+    /// locals in scope
+    fn rewrite(&mut self, expr: &mut IRExpression, bound: &mut Vec<Symbol>) {
+        let outer = bound.len();
+        match expr {
+            IRExpression::Lambda { parameters, body, .. } => {
+                bound.extend(parameters.iter().map(|parameter| parameter.name));
+                self.rewrite(body, bound);
+            }
+            IRExpression::Let { bindings, body } => {
+                for binding in bindings {
+                    self.rewrite(&mut binding.value, bound);
+                    bound.push(binding.name);
+                }
+                self.rewrite(body, bound);
+            }
+            IRExpression::Match { value, cases } => {
+                self.rewrite(value, bound);
+                for case in cases {
+                    let before = bound.len();
+                    case.pattern.collect_variables(bound);
+                    if let Some(guard) = &mut case.guard {
+                        self.rewrite(guard, bound);
+                    }
+                    self.rewrite(&mut case.body, bound);
+                    bound.truncate(before);
+                }
+            }
+            IRExpression::Handle { expression, handlers, return_handler, .. } => {
+                self.rewrite(expression, bound);
+                for handler in handlers {
+                    let before = bound.len();
+                    bound.extend(handler.parameters.iter().copied());
+                    bound.push(handler.continuation);
+                    self.rewrite(&mut handler.body, bound);
+                    bound.truncate(before);
+                }
+                if let Some(handler) = return_handler {
+                    self.rewrite(handler, bound);
+                }
+            }
+            IRExpression::Cell { name, initial, body } => {
+                self.rewrite(initial, bound);
+                bound.push(*name);
+                self.rewrite(body, bound);
+            }
+            _ => expr.for_each_child_mut(&mut |child| self.rewrite(child, bound)),
+        }
+        bound.truncate(outer);
+        if let Some(inlined) = self.inline(expr, bound) {
+            *expr = inlined;
+        }
+    }
+
+    /// The function a call's head refers to, if it is a candidate
+    fn callee(&self, head: &IRExpression, bound: &[Symbol]) -> Option<(Symbol, &'a IRFunction)> {
+        let (module, name) = match head {
+            IRExpression::Field { record, field } => match record.as_ref() {
+                IRExpression::Variable(qualifier) if !bound.contains(qualifier) => (*self.qualified.get(qualifier)?, *field),
+                _ => return None,
+            },
+            IRExpression::Variable(name) if !bound.contains(name) => *self.imported.get(name)?,
+            _ => return None,
+        };
+        Some((module, self.candidates.get(module, name)?))
+    }
+
+    /// `expr` with the candidate it calls inlined
+    fn inline(&mut self, expr: &IRExpression, bound: &[Symbol]) -> Option<IRExpression> {
+        let (head, arguments) = crate::tail_calls::call_spine(expr)?;
+        let (module, function) = self.callee(head, bound)?;
+        let arity = function.parameters.len();
+        if arguments.len() < arity {
+            return None;
+        }
+        // An operator the caller's module defines would mean something else
+        if function.body.free_variables().iter().any(|name| is_operator(*name) && self.defined.contains(name)) {
+            return None;
+        }
+        let (passed, rest) = arguments.split_at(arity);
+        // Parameters are bound one after the other, so an argument naming
+        // an earlier parameter is bound to a fresh local first
+        let parameters: Vec<Symbol> = function.parameters.iter().map(|parameter| parameter.name).collect();
+        let captured = passed.iter().enumerate().any(|(index, argument)| {
+            argument.free_variables().iter().any(|name| parameters[..index].contains(name))
+        });
+        let mut bindings = Vec::with_capacity(arity * 2);
+        let values: Vec<IRExpression> = if captured {
+            passed.iter().enumerate()
+                .map(|(index, argument)| {
+                    let name = Symbol::intern(&format!("{}$arg{index}", function.name));
+                    bindings.push(IRBinding { name, value: (*argument).clone(), type_hint: None });
+                    IRExpression::Variable(name)
+                })
+                .collect()
+        } else {
+            passed.iter().map(|argument| (*argument).clone()).collect()
+        };
+        bindings.extend(function.parameters.iter().zip(values).map(|(parameter, value)| IRBinding {
+            name: parameter.name,
+            value,
+            type_hint: None,
+        }));
+        self.inlined.push(format!("{module}.{}", function.name));
+        let body = IRExpression::Let { bindings, body: Box::new(function.body.clone()) };
+        Some(if rest.is_empty() {
+            body
+        } else {
+            IRExpression::Call { function: Box::new(body), arguments: rest.iter().map(|argument| (*argument).clone()).collect() }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::IRBuilder;
+    use crate::ir_print::print_expression;
+    use x_parser::{parse_source, FileId, SyntaxStyle};
+
+    fn build(source: &str) -> IRModule {
+        let unit = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        IRBuilder::new().build_ir(&unit).unwrap().modules.remove(0)
+    }
+
+    const LIBRARY: &str = "module Lib\n\
+        pub let double = fun x -> x + x\n\
+        pub let konst = fun a b -> a\n\
+        let private = fun x -> x\n\
+        pub let named = fun x -> double x\n\
+        ```\n---\n@noinline\n---\n```\n\
+        pub let kept = fun x -> x\n\
+        ```\n---\n@inline\n---\n```\n\
+        pub let big = fun x -> (x, x, x, x, x, x, x, x, x, x, x, x, x, x, x, x)";
+
+    #[test]
+    fn test_small_public_functions_are_candidates() {
+        let mut candidates = InlineCandidates::new();
+        candidates.add_module(&build(LIBRARY));
+        let lib = Symbol::intern("Lib");
+        let names: Vec<_> = ["double", "konst", "private", "named", "kept", "big"].into_iter()
+            .filter(|name| candidates.get(lib, Symbol::intern(name)).is_some())
+            .collect();
+        assert_eq!(names, ["double", "konst", "big"]);
+        assert!(cost(&candidates.get(lib, Symbol::intern("big")).unwrap().body) > INLINE_BUDGET);
+    }
+
+    #[test]
+    fn test_calls_across_modules_are_inlined() {
+        let mut candidates = InlineCandidates::new();
+        candidates.add_module(&build(LIBRARY));
+        let mut module = build("module Main\n\
+            import Lib\n\
+            import Lib { konst as pick }\n\
+            pub let f = fun x -> Lib.double (pick x 1)\n\
+            pub let g = fun a -> (let pick = fun y -> y in pick (Lib.konst 1 a a))\n\
+            pub let h = fun u -> Lib.kept (Lib.private u)");
+        inline_calls(&mut module, &candidates);
+        let bodies: Vec<_> = module.functions.iter().map(|function| print_expression(&function.body)).collect();
+        assert_eq!(bodies[0], "(let ((x (let ((a x) (b 1)) a))) (call + x x))");
+        // A local `pick` is not the import; an argument naming a parameter
+        // is bound to a fresh local first
+        assert_eq!(bodies[1], "(let\n  ((pick (lambda ((y Unit)) (closure) y)))\n  (call pick (call (let ((konst$arg0 1) (konst$arg1 a) (a konst$arg0) (b konst$arg1)) a) a)))");
+        assert_eq!(bodies[2], "(call (field Lib kept) (call (field Lib private) u))");
+        let inlined: Vec<_> = module.functions[0].attributes.iter().filter_map(|attribute| attribute.value.as_deref()).collect();
+        assert_eq!(inlined, ["Lib.konst", "Lib.double"]);
+        assert!(module.functions[2].attributes.is_empty());
+    }
+}
@@ -3,10 +3,16 @@
 //! This IR provides a common abstraction layer between the x Language AST
 //! and the target-specific code generators.
 
-use x_parser::{CompilationUnit, Module, Expr, Item, Pattern, Literal, Symbol, TypeDef, Visibility};
-use x_checker::{Type, EffectSet};
-use crate::Result;
-use std::collections::HashMap;
+use x_parser::{CompilationUnit, Module, Expr, EffectHandler, HandlerMode, Item, Pattern, Literal, SignatureItem, Symbol, TypeDef, TypeDefKind, ValueDef, Visibility, Span};
+use x_checker::{Type, EffectSet, Evidence, instance_dictionary_name};
+use x_checker::types::Constraint;
+use crate::{CompilerError, Result};
+use crate::inlining::{inline_calls, InlineCandidates};
+use crate::passes::PassManager;
+use crate::profiling::PassTiming;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Instant;
 
 /// Intermediate representation for code generation
 #[derive(Debug, Clone)]
@@ -39,6 +45,13 @@ pub struct IRFunction {
     pub attributes: Vec<IRAttribute>,
 }
 
+impl IRFunction {
+    /// Whether the function has an attribute named `name`
+    pub fn has_attribute(&self, name: &str) -> bool {
+        self.attributes.iter().any(|attribute| attribute.name.as_str() == name)
+    }
+}
+
 /// IR expression representation
 #[derive(Debug, Clone)]
 pub enum IRExpression {
@@ -74,6 +87,7 @@ pub enum IRExpression {
     },
     Handle {
         expression: Box<IRExpression>,
+        mode: HandlerMode,
         handlers: Vec<IREffectHandler>,
         return_handler: Option<Box<IRExpression>>,
     },
@@ -81,6 +95,37 @@ pub enum IRExpression {
         value: Box<IRExpression>,
         continuation: Symbol,
     },
+    /// Select a field of a record value, e.g. a method from a class dictionary
+    Field {
+        record: Box<IRExpression>,
+        field: Symbol,
+    },
+    /// Copy of a record with some fields replaced
+    RecordUpdate {
+        record: Box<IRExpression>,
+        fields: Vec<(Symbol, IRExpression)>,
+    },
+    Tuple(Vec<IRExpression>),
+    /// Select a component of a tuple by position
+    TupleGet {
+        tuple: Box<IRExpression>,
+        index: usize,
+    },
+    /// A mutable local holding `initial` while `body` runs, which is what
+    /// [`local_effects`](crate::local_effects) lowers a `State` or `Reader`
+    /// handler to
+    Cell {
+        name: Symbol,
+        initial: Box<IRExpression>,
+        body: Box<IRExpression>,
+    },
+    /// The value a cell holds
+    CellGet(Symbol),
+    /// Replace the value a cell holds, giving unit
+    CellSet {
+        name: Symbol,
+        value: Box<IRExpression>,
+    },
 }
 
 /// IR literal values
@@ -178,6 +223,8 @@ pub enum IRPattern {
     },
     Tuple(Vec<IRPattern>),
     Record(Vec<(Symbol, IRPattern)>),
+    /// Alternatives, each binding the same variables
+    Or(Vec<IRPattern>),
 }
 
 #[derive(Debug, Clone)]
@@ -199,6 +246,10 @@ pub struct IRExport {
 pub struct IRImport {
     pub module: Symbol,
     pub items: Vec<IRImportItem>,
+    /// Name the module's values are qualified with, e.g. `List` for `import Core.List`
+    pub qualifier: Option<Symbol>,
+    /// `lazy import`: loaded on first use rather than with the importing module
+    pub lazy: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -235,11 +286,35 @@ pub struct IRAttribute {
 }
 
 /// IR builder for converting AST to IR
+///
+/// Type classes are lowered by dictionary passing: each instance becomes a
+/// record of its methods, constrained definitions take dictionaries as
+/// leading parameters, and method uses select from the dictionary the
+/// checker resolved for them.
 #[allow(dead_code)]
 pub struct IRBuilder {
     current_module: Option<Symbol>,
     type_context: HashMap<Symbol, Type>,
     effect_context: HashMap<Symbol, EffectSet>,
+    class_evidence: HashMap<Span, Vec<Evidence>>,
+    class_methods: HashSet<Symbol>,
+    dictionary_arity: HashMap<Symbol, usize>,
+    /// Dictionary parameters of the definition being lowered
+    dictionary_params: Vec<Symbol>,
+    /// Local names in scope, innermost last; lambdas capture those they use
+    scope: Vec<Symbol>,
+    /// Constructors without arguments, which patterns write like variables
+    nullary_constructors: HashSet<Symbol>,
+    /// Continuations of the enclosing handler clauses, innermost last
+    continuations: Vec<Symbol>,
+    /// Values each module type lists, which packing a module copies
+    signatures: HashMap<Symbol, Vec<Symbol>>,
+    /// The passes `build_ir` runs after lowering
+    passes: PassManager,
+    /// Lowering and the passes after it, timed by the last `build_ir`
+    pass_timings: Vec<PassTiming>,
+    /// Functions of other modules `build_ir` inlines before the passes
+    inline_candidates: Arc<InlineCandidates>,
 }
 
 impl IRBuilder {
@@ -248,12 +323,50 @@ impl IRBuilder {
             current_module: None,
             type_context: HashMap::new(),
             effect_context: HashMap::new(),
+            class_evidence: HashMap::new(),
+            class_methods: HashSet::new(),
+            dictionary_arity: HashMap::new(),
+            dictionary_params: Vec::new(),
+            scope: Vec::new(),
+            nullary_constructors: HashSet::new(),
+            continuations: Vec::new(),
+            signatures: HashMap::new(),
+            passes: PassManager::default(),
+            pass_timings: Vec::new(),
+            inline_candidates: Arc::default(),
         }
     }
+
+    /// Run `passes` after lowering instead of the default pipeline
+    pub fn with_passes(mut self, passes: PassManager) -> Self {
+        self.passes = passes;
+        self
+    }
+
+    /// Inline calls of `candidates` after lowering, before the passes
+    pub fn with_inlining(mut self, candidates: Arc<InlineCandidates>) -> Self {
+        self.inline_candidates = candidates;
+        self
+    }
     
     /// Build IR from a compilation unit
     pub fn build_ir(&mut self, cu: &CompilationUnit) -> Result<IR> {
-        let ir_module = self.build_module(&cu.module)?;
+        self.pass_timings.clear();
+        let start = Instant::now();
+        let uses_classes = cu.module.items.iter()
+            .any(|item| matches!(item, Item::ClassDef(_) | Item::InstanceDef(_)));
+        if uses_classes {
+            self.resolve_classes(cu);
+        }
+        let mut ir_module = self.build_module(&cu.module)?;
+        self.pass_timings.push(PassTiming { name: "lower", duration: start.elapsed() });
+        if !self.inline_candidates.is_empty() {
+            let start = Instant::now();
+            inline_calls(&mut ir_module, &self.inline_candidates);
+            self.pass_timings.push(PassTiming { name: "inline", duration: start.elapsed() });
+        }
+        let timings = self.passes.run(&mut ir_module);
+        self.pass_timings.extend(timings);
         
         Ok(IR {
             modules: vec![ir_module],
@@ -262,6 +375,31 @@ impl IRBuilder {
         })
     }
     
+    /// How long lowering and each pass took in the last `build_ir`
+    pub fn pass_timings(&self) -> &[PassTiming] {
+        &self.pass_timings
+    }
+    
+    /// Record the dictionaries the checker resolved for each overloaded use
+    fn resolve_classes(&mut self, cu: &CompilationUnit) {
+        let result = x_checker::type_check(cu);
+        self.class_evidence = result.class_evidence;
+        self.dictionary_arity = result.inferred_types.iter()
+            .map(|(name, scheme)| {
+                let dictionaries = scheme.constraints.iter()
+                    .filter(|constraint| matches!(constraint, Constraint::Class { .. }))
+                    .count();
+                (*name, dictionaries)
+            })
+            .filter(|(_, dictionaries)| *dictionaries > 0)
+            .collect();
+        for item in &cu.module.items {
+            if let Item::ClassDef(class_def) = item {
+                self.class_methods.extend(class_def.methods.iter().map(|method| method.name));
+            }
+        }
+    }
+    
     /// Build IR module from AST module (public method)
     pub fn build_module(&mut self, module: &Module) -> Result<IRModule> {
         self.build_module_internal(module)
@@ -270,6 +408,26 @@ impl IRBuilder {
     /// Build IR module from AST module (internal implementation)
     fn build_module_internal(&mut self, module: &Module) -> Result<IRModule> {
         self.current_module = Some(module.name.segments[0]); // Simplified
+        self.nullary_constructors = module.items.iter()
+            .filter_map(|item| match item {
+                Item::TypeDef(TypeDef { kind: TypeDefKind::Data(constructors), .. }) => Some(constructors),
+                _ => None,
+            })
+            .flatten()
+            .filter(|constructor| constructor.fields.is_empty())
+            .map(|constructor| constructor.name)
+            .collect();
+        self.signatures = module.items.iter()
+            .filter_map(|item| match item {
+                Item::ModuleTypeDef(def) => Some((def.name, def.signature.items.iter()
+                    .filter_map(|item| match item {
+                        SignatureItem::ValueSig { name, .. } => Some(*name),
+                        _ => None,
+                    })
+                    .collect())),
+                _ => None,
+            })
+            .collect();
         
         let mut ir_functions = Vec::new();
         let mut ir_types = Vec::new();
@@ -277,20 +435,37 @@ impl IRBuilder {
         
         for item in &module.items {
             match item {
+                Item::ValueDef(value_def) if self.dictionary_arity.contains_key(&value_def.name) => {
+                    // Constrained definition: dictionaries first, then the value itself
+                    self.dictionary_params = dictionary_params(self.dictionary_arity[&value_def.name]);
+                    self.scope = self.dictionary_params.clone();
+                    let body = self.build_expression(&value_def.body)?;
+                    ir_functions.push(IRFunction {
+                        name: value_def.name,
+                        parameters: self.dictionary_parameters(),
+                        return_type: IRType::Primitive(IRPrimitiveType::Unit), // Simplified
+                        effects: body.effect_set(),
+                        body,
+                        visibility: value_def.visibility.clone(),
+                        attributes: function_attributes(value_def),
+                    });
+                    self.dictionary_params.clear();
+                    self.scope.clear();
+                }
                 Item::ValueDef(value_def) => {
                     // Check if the body is a lambda expression
-                    if let Expr::Lambda { parameters, body, .. } = &value_def.body {
+                    if let Expr::Lambda { .. } = &value_def.body {
                         // Function defined with `let f = fun x -> ...`
+                        let (parameters, body) = uncurried(&value_def.body);
+                        let (parameters, body) = self.build_function_parts(&parameters, body)?;
                         ir_functions.push(IRFunction {
                             name: value_def.name,
-                            parameters: parameters.iter()
-                                .map(|p| self.build_parameter(p))
-                                .collect::<Result<Vec<_>>>()?,
+                            parameters,
                             return_type: IRType::Primitive(IRPrimitiveType::Unit), // Simplified
-                            body: self.build_expression(body)?,
-                            effects: IREffectSet::Empty,
+                            effects: body.effect_set(),
+                            body,
                             visibility: value_def.visibility.clone(),
-                            attributes: Vec::new(),
+                            attributes: function_attributes(value_def),
                         });
                     } else if value_def.parameters.is_empty() {
                         // Constant
@@ -301,22 +476,51 @@ impl IRBuilder {
                         });
                     } else {
                         // Function with parameters in the definition
+                        let (parameters, body) = self.build_function_parts(&value_def.parameters, &value_def.body)?;
                         ir_functions.push(IRFunction {
                             name: value_def.name,
-                            parameters: value_def.parameters.iter()
-                                .map(|p| self.build_parameter(p))
-                                .collect::<Result<Vec<_>>>()?,
+                            parameters,
                             return_type: IRType::Primitive(IRPrimitiveType::Unit), // Simplified
-                            body: self.build_expression(&value_def.body)?,
-                            effects: IREffectSet::Empty,
+                            effects: body.effect_set(),
+                            body,
                             visibility: value_def.visibility.clone(),
-                            attributes: Vec::new(),
+                            attributes: function_attributes(value_def),
                         });
                     }
                 }
                 Item::TypeDef(type_def) => {
                     ir_types.push(self.build_type_definition(type_def)?);
                 }
+                Item::InstanceDef(instance_def) => {
+                    // An instance is a record of its methods; instances with a
+                    // context are functions from the context's dictionaries
+                    self.dictionary_params = dictionary_params(instance_def.constraints.len());
+                    self.scope = self.dictionary_params.clone();
+                    let methods = instance_def.methods.iter()
+                        .map(|method| Ok((method.name, self.build_expression(&method.body)?)))
+                        .collect::<Result<Vec<_>>>()?;
+                    let dictionary = IRExpression::Literal(IRLiteral::Record(methods));
+                    let name = instance_dictionary_name(instance_def);
+                    if self.dictionary_params.is_empty() {
+                        ir_constants.push(IRConstant {
+                            name,
+                            value: dictionary,
+                            type_hint: IRType::Named(instance_def.class),
+                        });
+                    } else {
+                        ir_functions.push(IRFunction {
+                            name,
+                            parameters: self.dictionary_parameters(),
+                            return_type: IRType::Named(instance_def.class),
+                            body: dictionary,
+                            effects: IREffectSet::Empty,
+                            visibility: Visibility::Public,
+                            attributes: Vec::new(),
+                        });
+                    }
+                    self.dictionary_params.clear();
+                    self.scope.clear();
+                }
                 _ => {
                     // Handle other item types
                 }
@@ -324,9 +528,9 @@ impl IRBuilder {
         }
         
         Ok(IRModule {
-            name: module.name.segments[0], // Simplified
+            name: Symbol::intern(&module.name.to_string()),
             exports: Vec::new(), // TODO: Build from module.exports
-            imports: Vec::new(), // TODO: Build from module.imports
+            imports: module.imports.iter().map(build_import).collect(),
             functions: ir_functions,
             types: ir_types,
             constants: ir_constants,
@@ -337,7 +541,25 @@ impl IRBuilder {
     fn build_expression(&mut self, expr: &Expr) -> Result<IRExpression> {
         match expr {
             Expr::Literal(lit, _) => Ok(IRExpression::Literal(self.build_literal(lit))),
-            Expr::Var(symbol, _) => Ok(IRExpression::Variable(*symbol)),
+            Expr::Var(symbol, span) => match self.class_evidence.get(span) {
+                Some(evidence) => {
+                    let mut dictionaries: Vec<IRExpression> = evidence.iter()
+                        .map(|evidence| self.build_evidence(evidence))
+                        .collect();
+                    if self.class_methods.contains(symbol) && dictionaries.len() == 1 {
+                        Ok(IRExpression::Field {
+                            record: Box::new(dictionaries.remove(0)),
+                            field: *symbol,
+                        })
+                    } else {
+                        Ok(IRExpression::Call {
+                            function: Box::new(IRExpression::Variable(*symbol)),
+                            arguments: dictionaries,
+                        })
+                    }
+                }
+                None => Ok(IRExpression::Variable(*symbol)),
+            },
             Expr::App(func, args, _) => {
                 Ok(IRExpression::Call {
                     function: Box::new(self.build_expression(func)?),
@@ -347,19 +569,22 @@ impl IRBuilder {
                 })
             }
             Expr::Lambda { parameters, body, .. } => {
-                Ok(IRExpression::Lambda {
-                    parameters: parameters.iter()
-                        .map(|p| self.build_parameter(p))
-                        .collect::<crate::Result<Vec<_>>>()?,
-                    body: Box::new(self.build_expression(body)?),
-                    closure: Vec::new(), // TODO: Compute closure
-                })
+                let (parameters, body) = self.build_function_parts(parameters, body)?;
+                Ok(self.closure(parameters, body))
             }
             Expr::Let { pattern, value, body, .. } => {
-                let binding = self.build_let_binding(pattern, value)?;
+                let mut bindings = vec![self.build_let_binding(pattern, value)?];
+                if !matches!(pattern, Pattern::Variable(..)) {
+                    let value = IRExpression::Variable(bindings[0].name);
+                    destructure(pattern, value, &mut bindings);
+                }
+                let outer = self.scope.len();
+                self.scope.extend(bindings.iter().map(|binding| binding.name));
+                let body = self.build_expression(body);
+                self.scope.truncate(outer);
                 Ok(IRExpression::Let {
-                    bindings: vec![binding],
-                    body: Box::new(self.build_expression(body)?),
+                    bindings,
+                    body: Box::new(body?),
                 })
             }
             Expr::If { condition, then_branch, else_branch, .. } => {
@@ -369,6 +594,94 @@ impl IRBuilder {
                     else_branch: Box::new(self.build_expression(else_branch)?),
                 })
             }
+            Expr::Record { fields, .. } => {
+                Ok(IRExpression::Literal(IRLiteral::Record(self.build_fields(fields)?)))
+            }
+            Expr::Project { record, field, .. } => {
+                Ok(IRExpression::Field {
+                    record: Box::new(self.build_expression(record)?),
+                    field: *field,
+                })
+            }
+            Expr::RecordUpdate { record, fields, .. } => {
+                Ok(IRExpression::RecordUpdate {
+                    record: Box::new(self.build_expression(record)?),
+                    fields: self.build_fields(fields)?,
+                })
+            }
+            Expr::Tuple { elements, .. } => {
+                Ok(IRExpression::Tuple(elements.iter()
+                    .map(|element| self.build_expression(element))
+                    .collect::<Result<Vec<_>>>()?))
+            }
+            // A packed module is a record of the values its module type lists
+            Expr::Pack { module, signature, .. } => {
+                let values = self.signatures.get(signature)
+                    .ok_or_else(|| CompilerError::CodeGen { message: format!("Unknown module type {signature}") })?;
+                Ok(IRExpression::Literal(IRLiteral::Record(values.iter()
+                    .map(|value| (*value, IRExpression::Field {
+                        record: Box::new(IRExpression::Variable(*module)),
+                        field: *value,
+                    }))
+                    .collect())))
+            }
+            Expr::Unpack { name, value, body, .. } => {
+                let value = self.build_expression(value)?;
+                self.scope.push(*name);
+                let body = self.build_expression(body);
+                self.scope.pop();
+                Ok(IRExpression::Let {
+                    bindings: vec![IRBinding { name: *name, value, type_hint: None }],
+                    body: Box::new(body?),
+                })
+            }
+            Expr::Match { scrutinee, arms, .. } => {
+                let value = Box::new(self.build_expression(scrutinee)?);
+                let cases = arms.iter()
+                    .map(|arm| {
+                        let pattern = self.build_pattern(&arm.pattern);
+                        let outer = self.scope.len();
+                        pattern.collect_variables(&mut self.scope);
+                        let case = self.build_match_case(pattern, arm.guard.as_deref(), &arm.body);
+                        self.scope.truncate(outer);
+                        case
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(IRExpression::Match { value, cases })
+            }
+            Expr::Perform { effect, operation, args, .. } => {
+                Ok(IRExpression::Effect {
+                    effect: *effect,
+                    operation: *operation,
+                    arguments: args.iter()
+                        .map(|arg| self.build_expression(arg))
+                        .collect::<Result<Vec<_>>>()?,
+                })
+            }
+            Expr::Handle { expr, mode, handlers, return_clause, .. } => {
+                let expression = Box::new(self.build_expression(expr)?);
+                let handlers = handlers.iter()
+                    .map(|handler| self.build_effect_handler(handler))
+                    .collect::<Result<Vec<_>>>()?;
+                let return_handler = match return_clause {
+                    Some(clause) => {
+                        let (parameters, body) = self.build_function_parts(
+                            std::slice::from_ref(&clause.parameter),
+                            &clause.body,
+                        )?;
+                        Some(Box::new(self.closure(parameters, body)))
+                    }
+                    None => None,
+                };
+                Ok(IRExpression::Handle { expression, mode: *mode, handlers, return_handler })
+            }
+            Expr::Resume { value, .. } => {
+                Ok(IRExpression::Resume {
+                    value: Box::new(self.build_expression(value)?),
+                    continuation: self.continuations.last().copied()
+                        .unwrap_or_else(|| Symbol::intern("resume")),
+                })
+            }
             _ => {
                 // Handle other expression types
                 Ok(IRExpression::Literal(IRLiteral::Unit))
@@ -376,11 +689,85 @@ impl IRBuilder {
         }
     }
     
+    /// Lambda capturing the enclosing locals its body uses
+    fn closure(&self, parameters: Vec<IRParameter>, body: IRExpression) -> IRExpression {
+        let mut lambda = IRExpression::Lambda {
+            parameters,
+            body: Box::new(body),
+            closure: Vec::new(),
+        };
+        let captured: Vec<Symbol> = lambda.free_variables().into_iter()
+            .filter(|name| self.scope.contains(name))
+            .collect();
+        if let IRExpression::Lambda { closure, .. } = &mut lambda {
+            *closure = captured;
+        }
+        lambda
+    }
+    
+    /// Lower one operation clause; `resume` in its body continues the
+    /// computation that performed the operation
+    fn build_effect_handler(&mut self, handler: &EffectHandler) -> Result<IREffectHandler> {
+        let parameters: Vec<Symbol> = handler.parameters.iter()
+            .map(|pattern| Ok(self.build_parameter(pattern)?.name))
+            .collect::<Result<_>>()?;
+        let continuation = handler.continuation.unwrap_or_else(|| Symbol::intern("resume"));
+        let outer = self.scope.len();
+        self.scope.extend(parameters.iter().copied());
+        self.scope.push(continuation);
+        self.continuations.push(continuation);
+        let body = self.build_expression(&handler.body);
+        self.continuations.pop();
+        self.scope.truncate(outer);
+        Ok(IREffectHandler {
+            effect: handler.effect.name,
+            operation: handler.operation,
+            parameters,
+            continuation,
+            body: body?,
+        })
+    }
+    
+    fn build_match_case(&mut self, pattern: IRPattern, guard: Option<&Expr>, body: &Expr) -> Result<IRMatchCase> {
+        Ok(IRMatchCase {
+            pattern,
+            guard: guard.map(|guard| self.build_expression(guard)).transpose()?,
+            body: self.build_expression(body)?,
+        })
+    }
+    
+    fn build_fields(&mut self, fields: &[(Symbol, Expr)]) -> Result<Vec<(Symbol, IRExpression)>> {
+        fields.iter()
+            .map(|(name, value)| Ok((*name, self.build_expression(value)?)))
+            .collect()
+    }
+    
+    /// Build the dictionary expression a piece of evidence stands for
+    fn build_evidence(&self, evidence: &Evidence) -> IRExpression {
+        match evidence {
+            Evidence::Instance { dictionary, args } if args.is_empty() => IRExpression::Variable(*dictionary),
+            Evidence::Instance { dictionary, args } => IRExpression::Call {
+                function: Box::new(IRExpression::Variable(*dictionary)),
+                arguments: args.iter().map(|arg| self.build_evidence(arg)).collect(),
+            },
+            Evidence::Param(index) => IRExpression::Variable(self.dictionary_params[*index]),
+        }
+    }
+    
+    fn dictionary_parameters(&self) -> Vec<IRParameter> {
+        self.dictionary_params.iter()
+            .map(|name| IRParameter {
+                name: *name,
+                type_hint: IRType::Named(Symbol::intern("Dictionary")),
+            })
+            .collect()
+    }
+    
     /// Build IR literal from AST literal
     fn build_literal(&self, lit: &Literal) -> IRLiteral {
         match lit {
-            Literal::Integer(n) => IRLiteral::Integer(*n),
-            Literal::Float(f) => IRLiteral::Float(*f),
+            Literal::Integer(n, _) => IRLiteral::Integer(*n),
+            Literal::Float(f, _) => IRLiteral::Float(*f),
             Literal::String(s) => IRLiteral::String(s.clone()),
             Literal::Bool(b) => IRLiteral::Boolean(*b),
             Literal::Unit => IRLiteral::Unit,
@@ -406,6 +793,69 @@ impl IRBuilder {
         }
     }
     
+    /// Build function parameters and body; tuple parameters are passed whole
+    /// and destructured at the start of the body
+    fn build_function_parts(&mut self, parameters: &[Pattern], body: &Expr) -> Result<(Vec<IRParameter>, IRExpression)> {
+        let mut ir_parameters = Vec::new();
+        let mut bindings = Vec::new();
+        for (index, pattern) in parameters.iter().enumerate() {
+            if let Pattern::Tuple { .. } = pattern {
+                let name = Symbol::intern(&format!("$arg{index}"));
+                destructure(pattern, IRExpression::Variable(name), &mut bindings);
+                ir_parameters.push(IRParameter {
+                    name,
+                    type_hint: IRType::Primitive(IRPrimitiveType::Unit), // Simplified
+                });
+            } else {
+                ir_parameters.push(self.build_parameter(pattern)?);
+            }
+        }
+        let outer = self.scope.len();
+        self.scope.extend(ir_parameters.iter().map(|parameter| parameter.name));
+        self.scope.extend(bindings.iter().map(|binding| binding.name));
+        let body = self.build_expression(body);
+        self.scope.truncate(outer);
+        let body = body?;
+        if bindings.is_empty() {
+            Ok((ir_parameters, body))
+        } else {
+            Ok((ir_parameters, IRExpression::Let { bindings, body: Box::new(body) }))
+        }
+    }
+    
+    /// Build IR pattern from AST pattern
+    fn build_pattern(&self, pattern: &Pattern) -> IRPattern {
+        match pattern {
+            Pattern::Wildcard(_) => IRPattern::Wildcard,
+            Pattern::Variable(symbol, _) if self.nullary_constructors.contains(symbol) => {
+                IRPattern::Constructor { name: *symbol, arguments: Vec::new() }
+            }
+            Pattern::Variable(symbol, _) => IRPattern::Variable(*symbol),
+            Pattern::Literal(lit, _) => IRPattern::Literal(self.build_literal(lit)),
+            Pattern::Constructor { name, args, .. } => IRPattern::Constructor {
+                name: *name,
+                arguments: args.iter().map(|arg| self.build_pattern(arg)).collect(),
+            },
+            Pattern::Tuple { patterns, .. } => {
+                IRPattern::Tuple(patterns.iter().map(|p| self.build_pattern(p)).collect())
+            }
+            Pattern::Record { fields, .. } => IRPattern::Record(
+                fields.iter().map(|(name, p)| (*name, self.build_pattern(p))).collect(),
+            ),
+            Pattern::Or { left, right, .. } => {
+                let mut alternatives = Vec::new();
+                for side in [left, right] {
+                    match self.build_pattern(side) {
+                        IRPattern::Or(nested) => alternatives.extend(nested),
+                        pattern => alternatives.push(pattern),
+                    }
+                }
+                IRPattern::Or(alternatives)
+            }
+            Pattern::As { pattern, .. } | Pattern::Ann { pattern, .. } => self.build_pattern(pattern),
+        }
+    }
+    
     /// Build IR binding from let pattern and value
     fn build_let_binding(&mut self, pattern: &Pattern, value: &Expr) -> Result<IRBinding> {
         match pattern {
@@ -416,6 +866,14 @@ impl IRBuilder {
                     type_hint: None,
                 })
             }
+            Pattern::Tuple { .. } => {
+                // Bound to a temporary that `destructure` then takes apart
+                Ok(IRBinding {
+                    name: Symbol::intern("$tuple"),
+                    value: self.build_expression(value)?,
+                    type_hint: None,
+                })
+            }
             _ => {
                 // Handle other pattern types
                 Ok(IRBinding {
@@ -428,18 +886,621 @@ impl IRBuilder {
     }
     
     /// Build IR type definition
-    fn build_type_definition(&self, _type_def: &TypeDef) -> Result<IRTypeDefinition> {
-        // Simplified implementation
+    fn build_type_definition(&self, type_def: &TypeDef) -> Result<IRTypeDefinition> {
+        let definition = match &type_def.kind {
+            TypeDefKind::Data(constructors) => IRTypeDefinitionKind::Variant(
+                constructors.iter()
+                    .map(|constructor| {
+                        (constructor.name, constructor.fields.iter().map(build_type).collect())
+                    })
+                    .collect(),
+            ),
+            TypeDefKind::Alias(typ) => IRTypeDefinitionKind::Alias(build_type(typ)),
+            TypeDefKind::Abstract => IRTypeDefinitionKind::Alias(IRType::Named(type_def.name)),
+        };
         Ok(IRTypeDefinition {
-            name: Symbol::intern("PlaceholderType"),
-            parameters: Vec::new(),
-            definition: IRTypeDefinitionKind::Alias(IRType::Primitive(IRPrimitiveType::Unit)),
+            name: type_def.name,
+            parameters: type_def.type_params.iter().map(|param| param.name).collect(),
+            definition,
         })
     }
 }
 
+/// Values an import brings into scope; module paths are kept dotted
+fn build_import(import: &x_parser::Import) -> IRImport {
+    use x_parser::ImportKind;
+    let items = match &import.kind {
+        ImportKind::Selective(items) => items.iter()
+            .map(|item| IRImportItem { name: item.name, alias: item.alias })
+            .collect(),
+        _ => Vec::new(),
+    };
+    let qualifier = match &import.kind {
+        ImportKind::Selective(_) | ImportKind::Wildcard => None,
+        _ => import.alias.or_else(|| import.module_path.segments.last().copied()),
+    };
+    IRImport {
+        module: Symbol::intern(&import.module_path.to_string()),
+        items,
+        qualifier,
+        lazy: import.kind == ImportKind::Lazy,
+    }
+}
+
+impl IRModule {
+    /// The module a qualified name such as `List.map` refers to
+    pub fn qualified_module(&self, qualifier: Symbol) -> Option<Symbol> {
+        self.imports.iter()
+            .find(|import| import.qualifier == Some(qualifier))
+            .map(|import| import.module)
+    }
+}
+
+/// IR type of a written type; anything without a direct IR counterpart is
+/// referred to by name
+fn build_type(typ: &x_parser::Type) -> IRType {
+    use x_parser::Type as T;
+    match typ {
+        T::Con(name, _) => match name.as_str() {
+            "Int" => IRType::Primitive(IRPrimitiveType::Int),
+            "Float" => IRType::Primitive(IRPrimitiveType::Float),
+            "String" => IRType::Primitive(IRPrimitiveType::String),
+            "Bool" => IRType::Primitive(IRPrimitiveType::Bool),
+            "Unit" => IRType::Primitive(IRPrimitiveType::Unit),
+            _ => IRType::Named(*name),
+        },
+        T::Var(name, _) => IRType::TypeVariable(*name),
+        T::App(con, _, _) => build_type(con),
+        T::Fun { params, return_type, .. } => IRType::Function {
+            parameters: params.iter().map(build_type).collect(),
+            return_type: Box::new(build_type(return_type)),
+            effects: IREffectSet::Empty,
+        },
+        T::Tuple { types, .. } => IRType::Tuple(types.iter().map(build_type).collect()),
+        _ => IRType::Named(Symbol::intern("Unknown")),
+    }
+}
+
+impl IRExpression {
+    /// Variables the expression refers to without binding them, in order of
+    /// first use
+    pub fn free_variables(&self) -> Vec<Symbol> {
+        let mut free = Vec::new();
+        self.collect_free(&mut Vec::new(), &mut free);
+        free
+    }
+    
+    fn collect_free(&self, bound: &mut Vec<Symbol>, free: &mut Vec<Symbol>) {
+        let outer = bound.len();
+        match self {
+            IRExpression::Literal(IRLiteral::Array(elements)) | IRExpression::Tuple(elements)
+            | IRExpression::Block(elements) => {
+                for element in elements {
+                    element.collect_free(bound, free);
+                }
+            }
+            IRExpression::Literal(IRLiteral::Record(fields)) => {
+                for (_, value) in fields {
+                    value.collect_free(bound, free);
+                }
+            }
+            IRExpression::Literal(_) => {}
+            IRExpression::Variable(name) => {
+                if !bound.contains(name) && !free.contains(name) {
+                    free.push(*name);
+                }
+            }
+            IRExpression::Call { function, arguments } => {
+                function.collect_free(bound, free);
+                for argument in arguments {
+                    argument.collect_free(bound, free);
+                }
+            }
+            IRExpression::Lambda { parameters, body, .. } => {
+                bound.extend(parameters.iter().map(|parameter| parameter.name));
+                body.collect_free(bound, free);
+            }
+            IRExpression::Let { bindings, body } => {
+                // Bindings are sequential: each sees the ones before it
+                for binding in bindings {
+                    binding.value.collect_free(bound, free);
+                    bound.push(binding.name);
+                }
+                body.collect_free(bound, free);
+            }
+            IRExpression::If { condition, then_branch, else_branch } => {
+                condition.collect_free(bound, free);
+                then_branch.collect_free(bound, free);
+                else_branch.collect_free(bound, free);
+            }
+            IRExpression::Match { value, cases } => {
+                value.collect_free(bound, free);
+                for case in cases {
+                    let before = bound.len();
+                    case.pattern.collect_variables(bound);
+                    if let Some(guard) = &case.guard {
+                        guard.collect_free(bound, free);
+                    }
+                    case.body.collect_free(bound, free);
+                    bound.truncate(before);
+                }
+            }
+            IRExpression::Effect { arguments, .. } => {
+                for argument in arguments {
+                    argument.collect_free(bound, free);
+                }
+            }
+            IRExpression::Handle { expression, handlers, return_handler, .. } => {
+                expression.collect_free(bound, free);
+                for handler in handlers {
+                    let before = bound.len();
+                    bound.extend(handler.parameters.iter().copied());
+                    bound.push(handler.continuation);
+                    handler.body.collect_free(bound, free);
+                    bound.truncate(before);
+                }
+                if let Some(handler) = return_handler {
+                    handler.collect_free(bound, free);
+                }
+            }
+            IRExpression::Resume { value, continuation } => {
+                value.collect_free(bound, free);
+                IRExpression::Variable(*continuation).collect_free(bound, free);
+            }
+            IRExpression::Field { record, .. } => record.collect_free(bound, free),
+            IRExpression::RecordUpdate { record, fields } => {
+                record.collect_free(bound, free);
+                for (_, value) in fields {
+                    value.collect_free(bound, free);
+                }
+            }
+            IRExpression::TupleGet { tuple, .. } => tuple.collect_free(bound, free),
+            IRExpression::Cell { name, initial, body } => {
+                initial.collect_free(bound, free);
+                bound.push(*name);
+                body.collect_free(bound, free);
+            }
+            IRExpression::CellGet(name) => IRExpression::Variable(*name).collect_free(bound, free),
+            IRExpression::CellSet { name, value } => {
+                value.collect_free(bound, free);
+                IRExpression::Variable(*name).collect_free(bound, free);
+            }
+        }
+        bound.truncate(outer);
+    }
+}
+
+impl IRExpression {
+    /// Visit the expression and its subexpressions in pre-order, descending
+    /// into an expression's children only while `visit` returns true
+    pub fn walk(&self, visit: &mut impl FnMut(&IRExpression) -> bool) {
+        if !visit(self) {
+            return;
+        }
+        match self {
+            IRExpression::Literal(IRLiteral::Array(elements)) | IRExpression::Tuple(elements)
+            | IRExpression::Block(elements) => elements.iter().for_each(|element| element.walk(visit)),
+            IRExpression::Literal(IRLiteral::Record(fields)) => {
+                fields.iter().for_each(|(_, value)| value.walk(visit));
+            }
+            IRExpression::Literal(_) | IRExpression::Variable(_) => {}
+            IRExpression::Call { function, arguments } => {
+                function.walk(visit);
+                arguments.iter().for_each(|argument| argument.walk(visit));
+            }
+            IRExpression::Lambda { body, .. } => body.walk(visit),
+            IRExpression::Let { bindings, body } => {
+                bindings.iter().for_each(|binding| binding.value.walk(visit));
+                body.walk(visit);
+            }
+            IRExpression::If { condition, then_branch, else_branch } => {
+                condition.walk(visit);
+                then_branch.walk(visit);
+                else_branch.walk(visit);
+            }
+            IRExpression::Match { value, cases } => {
+                value.walk(visit);
+                for case in cases {
+                    if let Some(guard) = &case.guard {
+                        guard.walk(visit);
+                    }
+                    case.body.walk(visit);
+                }
+            }
+            IRExpression::Effect { arguments, .. } => arguments.iter().for_each(|argument| argument.walk(visit)),
+            IRExpression::Handle { expression, handlers, return_handler, .. } => {
+                expression.walk(visit);
+                handlers.iter().for_each(|handler| handler.body.walk(visit));
+                if let Some(handler) = return_handler {
+                    handler.walk(visit);
+                }
+            }
+            IRExpression::Resume { value, .. } => value.walk(visit),
+            IRExpression::Field { record, .. } => record.walk(visit),
+            IRExpression::RecordUpdate { record, fields } => {
+                record.walk(visit);
+                fields.iter().for_each(|(_, value)| value.walk(visit));
+            }
+            IRExpression::TupleGet { tuple, .. } => tuple.walk(visit),
+            IRExpression::Cell { initial, body, .. } => {
+                initial.walk(visit);
+                body.walk(visit);
+            }
+            IRExpression::CellGet(_) => {}
+            IRExpression::CellSet { value, .. } => value.walk(visit),
+        }
+    }
+    
+    /// Call `visit` on each direct subexpression, letting it rewrite them
+    pub fn for_each_child_mut(&mut self, visit: &mut impl FnMut(&mut IRExpression)) {
+        match self {
+            IRExpression::Literal(IRLiteral::Array(elements)) | IRExpression::Tuple(elements)
+            | IRExpression::Block(elements) => elements.iter_mut().for_each(visit),
+            IRExpression::Literal(IRLiteral::Record(fields)) => {
+                fields.iter_mut().for_each(|(_, value)| visit(value));
+            }
+            IRExpression::Literal(_) | IRExpression::Variable(_) => {}
+            IRExpression::Call { function, arguments } => {
+                visit(function);
+                arguments.iter_mut().for_each(visit);
+            }
+            IRExpression::Lambda { body, .. } => visit(body),
+            IRExpression::Let { bindings, body } => {
+                bindings.iter_mut().for_each(|binding| visit(&mut binding.value));
+                visit(body);
+            }
+            IRExpression::If { condition, then_branch, else_branch } => {
+                visit(condition);
+                visit(then_branch);
+                visit(else_branch);
+            }
+            IRExpression::Match { value, cases } => {
+                visit(value);
+                for case in cases {
+                    if let Some(guard) = &mut case.guard {
+                        visit(guard);
+                    }
+                    visit(&mut case.body);
+                }
+            }
+            IRExpression::Effect { arguments, .. } => arguments.iter_mut().for_each(visit),
+            IRExpression::Handle { expression, handlers, return_handler, .. } => {
+                visit(expression);
+                handlers.iter_mut().for_each(|handler| visit(&mut handler.body));
+                if let Some(handler) = return_handler {
+                    visit(handler);
+                }
+            }
+            IRExpression::Resume { value, .. } => visit(value),
+            IRExpression::Field { record, .. } => visit(record),
+            IRExpression::RecordUpdate { record, fields } => {
+                visit(record);
+                fields.iter_mut().for_each(|(_, value)| visit(value));
+            }
+            IRExpression::TupleGet { tuple, .. } => visit(tuple),
+            IRExpression::Cell { initial, body, .. } => {
+                visit(initial);
+                visit(body);
+            }
+            IRExpression::CellGet(_) => {}
+            IRExpression::CellSet { value, .. } => visit(value),
+        }
+    }
+    
+    /// Effects the expression performs when evaluated and does not handle
+    /// itself, in order of first use; lambdas only perform theirs when called
+    pub fn performed_effects(&self) -> Vec<Symbol> {
+        let mut effects = Vec::new();
+        self.collect_effects(&mut effects);
+        effects
+    }
+    
+    /// The performed effects as an effect set
+    pub fn effect_set(&self) -> IREffectSet {
+        let effects = self.performed_effects();
+        if effects.is_empty() {
+            return IREffectSet::Empty;
+        }
+        IREffectSet::Effects(effects.into_iter()
+            .map(|name| IREffect { name, operations: Vec::new() })
+            .collect())
+    }
+    
+    fn collect_effects(&self, effects: &mut Vec<Symbol>) {
+        let add = |effect: Symbol, effects: &mut Vec<Symbol>| {
+            if !effects.contains(&effect) {
+                effects.push(effect);
+            }
+        };
+        match self {
+            IRExpression::Literal(IRLiteral::Array(elements)) | IRExpression::Tuple(elements)
+            | IRExpression::Block(elements) => {
+                elements.iter().for_each(|element| element.collect_effects(effects));
+            }
+            IRExpression::Literal(IRLiteral::Record(fields)) | IRExpression::RecordUpdate { fields, .. } => {
+                if let IRExpression::RecordUpdate { record, .. } = self {
+                    record.collect_effects(effects);
+                }
+                fields.iter().for_each(|(_, value)| value.collect_effects(effects));
+            }
+            IRExpression::Literal(_) | IRExpression::Variable(_) | IRExpression::Lambda { .. } => {}
+            IRExpression::Call { function, arguments } => {
+                function.collect_effects(effects);
+                arguments.iter().for_each(|argument| argument.collect_effects(effects));
+            }
+            IRExpression::Let { bindings, body } => {
+                bindings.iter().for_each(|binding| binding.value.collect_effects(effects));
+                body.collect_effects(effects);
+            }
+            IRExpression::If { condition, then_branch, else_branch } => {
+                condition.collect_effects(effects);
+                then_branch.collect_effects(effects);
+                else_branch.collect_effects(effects);
+            }
+            IRExpression::Match { value, cases } => {
+                value.collect_effects(effects);
+                for case in cases {
+                    if let Some(guard) = &case.guard {
+                        guard.collect_effects(effects);
+                    }
+                    case.body.collect_effects(effects);
+                }
+            }
+            IRExpression::Effect { effect, arguments, .. } => {
+                arguments.iter().for_each(|argument| argument.collect_effects(effects));
+                add(*effect, effects);
+            }
+            IRExpression::Handle { expression, mode, handlers, return_handler } => {
+                // A shallow handler lets the effects of what it resumes through
+                for effect in expression.performed_effects() {
+                    if *mode == HandlerMode::Shallow || !handlers.iter().any(|handler| handler.effect == effect) {
+                        add(effect, effects);
+                    }
+                }
+                // Clauses run outside the handler they belong to
+                handlers.iter().for_each(|handler| handler.body.collect_effects(effects));
+                if let Some(IRExpression::Lambda { body, .. }) = return_handler.as_deref() {
+                    body.collect_effects(effects);
+                }
+            }
+            IRExpression::Resume { value, .. } => value.collect_effects(effects),
+            IRExpression::Field { record, .. } => record.collect_effects(effects),
+            IRExpression::TupleGet { tuple, .. } => tuple.collect_effects(effects),
+            IRExpression::Cell { initial, body, .. } => {
+                initial.collect_effects(effects);
+                body.collect_effects(effects);
+            }
+            IRExpression::CellGet(_) => {}
+            IRExpression::CellSet { value, .. } => value.collect_effects(effects),
+        }
+    }
+}
+
+impl IRPattern {
+    /// Append the variables the pattern binds to `names`
+    pub fn collect_variables(&self, names: &mut Vec<Symbol>) {
+        match self {
+            IRPattern::Variable(name) => names.push(*name),
+            IRPattern::Constructor { arguments: patterns, .. } | IRPattern::Tuple(patterns) => {
+                for pattern in patterns {
+                    pattern.collect_variables(names);
+                }
+            }
+            IRPattern::Record(fields) => {
+                for (_, pattern) in fields {
+                    pattern.collect_variables(names);
+                }
+            }
+            // Every alternative binds the same variables
+            IRPattern::Or(alternatives) => {
+                if let Some(first) = alternatives.first() {
+                    first.collect_variables(names);
+                }
+            }
+            IRPattern::Wildcard | IRPattern::Literal(_) => {}
+        }
+    }
+}
+
+/// Bind the variables of an irrefutable pattern to projections of `value`
+fn destructure(pattern: &Pattern, value: IRExpression, bindings: &mut Vec<IRBinding>) {
+    match pattern {
+        Pattern::Variable(symbol, _) => bindings.push(IRBinding {
+            name: *symbol,
+            value,
+            type_hint: None,
+        }),
+        Pattern::Tuple { patterns, .. } => {
+            for (index, pattern) in patterns.iter().enumerate() {
+                let component = IRExpression::TupleGet {
+                    tuple: Box::new(value.clone()),
+                    index,
+                };
+                destructure(pattern, component, bindings);
+            }
+        }
+        Pattern::As { pattern, name, .. } => {
+            bindings.push(IRBinding {
+                name: *name,
+                value: value.clone(),
+                type_hint: None,
+            });
+            destructure(pattern, value, bindings);
+        }
+        Pattern::Ann { pattern, .. } => destructure(pattern, value, bindings),
+        _ => {}
+    }
+}
+
+/// The attributes the doc comment of `value_def` gives its function
+fn function_attributes(value_def: &ValueDef) -> Vec<IRAttribute> {
+    let Some(doc) = &value_def.documentation else { return Vec::new() };
+    let inline = match doc.doc_comment.inline() {
+        Some(true) => Some("inline"),
+        Some(false) => Some("noinline"),
+        None => None,
+    };
+    doc.doc_comment.tailrec().then_some("tailrec")
+        .into_iter()
+        .chain(inline)
+        .map(|name| IRAttribute { name: Symbol::intern(name), value: None })
+        .collect()
+}
+
+fn dictionary_params(count: usize) -> Vec<Symbol> {
+    (0..count).map(|index| Symbol::intern(&format!("$dict{index}"))).collect()
+}
+
 impl Default for IRBuilder {
     fn default() -> Self {
         Self::new()
     }
-}
\ No newline at end of file
+}
+
+/// The parameters and body of `fun p q -> body`, which the parser splits
+/// into `fun p -> fun q -> body` so that it can be applied one argument at
+/// a time; as a function of the module it takes them together, and calls
+/// saturate it. The split-off lambdas start at their parameter, unlike
+/// those written with a `fun` of their own, which stay closures.
+fn uncurried(lambda: &Expr) -> (Vec<Pattern>, &Expr) {
+    let mut parameters = Vec::new();
+    let mut expr = lambda;
+    while let Expr::Lambda { parameters: own, body, span } = expr {
+        let split = own.first().is_some_and(|parameter| parameter.span().start == span.start);
+        if !parameters.is_empty() && !split {
+            break;
+        }
+        parameters.extend(own.iter().cloned());
+        expr = body;
+    }
+    (parameters, expr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x_parser::{parse_source, FileId, SyntaxStyle};
+
+    fn build(source: &str) -> IRModule {
+        let cu = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        IRBuilder::new().build_ir(&cu).unwrap().modules.remove(0)
+    }
+
+    #[test]
+    fn test_classes_lower_to_dictionaries() {
+        let module = build(
+            "module Test\n\
+             class Eq[a] {\n  eq : a -> a -> Bool\n}\n\
+             instance Eq[Int] {\n  let eq = fun x -> fun y -> true\n}\n\
+             let same = fun x -> eq x x\n\
+             let check = same 1",
+        );
+
+        // The instance becomes a record constant holding its methods
+        let dictionary = module.constants.iter()
+            .find(|constant| constant.name.as_str() == "Eq$Int")
+            .expect("instance dictionary");
+        assert!(matches!(
+            &dictionary.value,
+            IRExpression::Literal(IRLiteral::Record(fields)) if fields[0].0.as_str() == "eq"
+        ));
+
+        // The constrained definition takes the dictionary and selects the method from it
+        let same = module.functions.iter()
+            .find(|function| function.name.as_str() == "same")
+            .expect("constrained function");
+        assert_eq!(same.parameters.len(), 1);
+        let dictionary_param = same.parameters[0].name;
+        let IRExpression::Lambda { body, .. } = &same.body else {
+            panic!("expected the original lambda under the dictionary parameter");
+        };
+        let IRExpression::Call { function, .. } = body.as_ref() else {
+            panic!("expected a method call");
+        };
+        let IRExpression::Call { function: method, .. } = function.as_ref() else {
+            panic!("expected a curried method call");
+        };
+        assert!(matches!(
+            method.as_ref(),
+            IRExpression::Field { record, field }
+                if field.as_str() == "eq" && matches!(record.as_ref(), IRExpression::Variable(name) if *name == dictionary_param)
+        ));
+
+        // Its caller passes the instance dictionary
+        let check = module.constants.iter()
+            .find(|constant| constant.name.as_str() == "check")
+            .expect("caller");
+        let IRExpression::Call { function, .. } = &check.value else {
+            panic!("expected a call");
+        };
+        assert!(matches!(
+            function.as_ref(),
+            IRExpression::Call { arguments, .. }
+                if matches!(arguments.as_slice(), [IRExpression::Variable(name)] if name.as_str() == "Eq$Int")
+        ));
+    }
+
+    #[test]
+    fn test_record_expressions_lower_to_fields() {
+        let module = build("module Test\nlet p = { x = 1, y = 2 }\nlet q = { p with x = 3 }.x");
+
+        let q = module.constants.iter()
+            .find(|constant| constant.name.as_str() == "q")
+            .expect("projection");
+        let IRExpression::Field { record, field } = &q.value else {
+            panic!("expected a field selection");
+        };
+        assert_eq!(field.as_str(), "x");
+        assert!(matches!(
+            record.as_ref(),
+            IRExpression::RecordUpdate { fields, .. } if fields.len() == 1 && fields[0].0.as_str() == "x"
+        ));
+    }
+
+    #[test]
+    fn test_tuple_parameters_are_destructured() {
+        let module = build("module Test
+let swap = fun (a, b) -> (b, a)");
+
+        let swap = &module.functions[0];
+        assert_eq!(swap.parameters[0].name.as_str(), "$arg0");
+        let IRExpression::Let { bindings, body } = &swap.body else {
+            panic!("expected destructuring bindings");
+        };
+        let names: Vec<_> = bindings.iter().map(|binding| binding.name.as_str()).collect();
+        assert_eq!(names, ["a", "b"]);
+        assert!(matches!(bindings[1].value, IRExpression::TupleGet { index: 1, .. }));
+        assert!(matches!(body.as_ref(), IRExpression::Tuple(elements) if elements.len() == 2));
+    }
+    
+    #[test]
+    fn test_lambdas_capture_enclosing_locals() {
+        let module = build(
+            "module Test\n\
+             let one = 1\n\
+             let konst = fun x -> fun y -> x\n\
+             let pick = fun s -> match s with | Some v => fun u -> v | None => fun u -> one",
+        );
+        
+        let closures = |function: &IRFunction| {
+            let mut closures = Vec::new();
+            let mut pending = vec![&function.body];
+            while let Some(expr) = pending.pop() {
+                match expr {
+                    IRExpression::Lambda { body, closure, .. } => {
+                        closures.push(closure.iter().map(|name| name.as_str()).collect::<Vec<_>>());
+                        pending.push(body);
+                    }
+                    IRExpression::Match { cases, .. } => pending.extend(cases.iter().map(|case| &case.body)),
+                    _ => {}
+                }
+            }
+            closures
+        };
+        // Module-level names are not captured
+        assert_eq!(closures(&module.functions[0]), [["x"]]);
+        let pick = closures(&module.functions[1]);
+        assert_eq!(pick.len(), 2);
+        assert!(pick.contains(&vec!["v"]) && pick.contains(&vec![]));
+    }
+}
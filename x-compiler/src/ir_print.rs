@@ -0,0 +1,330 @@
+//! Textual form of the IR
+//!
+//! [`print_module`] writes a module as S-expressions, one top-level form per
+//! definition, in the order the module holds them:
+//!
+//! ```text
+//! (module Main
+//!   (type (Shape) (variant (Dot) (Circle Int)))
+//!   (fun twice ((s Unit)) Unit (effects) (call (call (field String concat) s) s))
+//!   (fun size ((s Unit)) Unit (effects) (match s (case (Circle r) r) (case (Dot) 0))))
+//! ```
+//!
+//! The text depends on nothing but the IR, so two dumps of the same module
+//! are identical and dumps taken before and after a change can be diffed.
+//! A form that fits in the line is written on it; otherwise each of its
+//! operands goes on a line of its own, indented under the head.
+
+use crate::ir::{
+    IREffectSet, IRExpression, IRLiteral, IRModule, IRParameter, IRPattern, IRType, IRTypeDefinitionKind,
+};
+use x_parser::{HandlerMode, Symbol, Visibility};
+
+/// Columns a form may take before its operands are broken onto lines
+const WIDTH: usize = 100;
+
+/// The text of `module`, ending in a newline
+pub fn print_module(module: &IRModule) -> String {
+    let mut forms = vec![atom("module"), symbol(module.name)];
+    for import in &module.imports {
+        let mut form = vec![atom("import"), symbol(import.module)];
+        if import.lazy {
+            form.push(atom("lazy"));
+        }
+        if let Some(qualifier) = import.qualifier {
+            form.push(list(vec![atom("as"), symbol(qualifier)]));
+        }
+        form.extend(import.items.iter().map(|item| aliased(item.name, item.alias)));
+        forms.push(list(form));
+    }
+    for export in &module.exports {
+        forms.push(list(vec![atom("export"), aliased(export.name, export.alias)]));
+    }
+    for definition in &module.types {
+        let mut head = vec![symbol(definition.name)];
+        head.extend(definition.parameters.iter().map(|&parameter| symbol(parameter)));
+        let body = match &definition.definition {
+            IRTypeDefinitionKind::Alias(aliased) => list(vec![atom("alias"), ty(aliased)]),
+            IRTypeDefinitionKind::Variant(constructors) => variant(constructors),
+            IRTypeDefinitionKind::Record(fields) => record_type(fields),
+        };
+        forms.push(list(vec![atom("type"), list(head), body]));
+    }
+    for function in &module.functions {
+        let mut form = vec![atom("fun")];
+        form.extend(visibility(&function.visibility));
+        form.push(symbol(function.name));
+        form.push(parameters(&function.parameters));
+        form.push(ty(&function.return_type));
+        form.push(effects(&function.effects));
+        form.extend(function.attributes.iter().map(|attribute| {
+            let mut form = vec![atom("attribute"), symbol(attribute.name)];
+            form.extend(attribute.value.as_deref().map(string));
+            list(form)
+        }));
+        form.push(expression(&function.body));
+        forms.push(list(form));
+    }
+    for constant in &module.constants {
+        forms.push(list(vec![atom("const"), symbol(constant.name), ty(&constant.type_hint), expression(&constant.value)]));
+    }
+    let mut text = String::new();
+    list(forms).write(0, &mut text);
+    text.push('\n');
+    text
+}
+
+/// The text of `expr`, as it appears in a module's dump
+pub fn print_expression(expr: &IRExpression) -> String {
+    let mut text = String::new();
+    expression(expr).write(0, &mut text);
+    text
+}
+
+/// An S-expression
+enum Form {
+    Atom(String),
+    List(Vec<Form>),
+}
+
+impl Form {
+    /// Columns the form takes on one line
+    fn width(&self) -> usize {
+        match self {
+            Form::Atom(text) => text.len(),
+            Form::List(forms) => forms.iter().map(|form| form.width() + 1).sum::<usize>().max(1) + 1,
+        }
+    }
+
+    fn write(&self, indent: usize, out: &mut String) {
+        match self {
+            Form::Atom(text) => out.push_str(text),
+            Form::List(forms) if indent + self.width() <= WIDTH || forms.len() < 2 => {
+                out.push('(');
+                for (i, form) in forms.iter().enumerate() {
+                    if i > 0 {
+                        out.push(' ');
+                    }
+                    form.write(indent, out);
+                }
+                out.push(')');
+            }
+            Form::List(forms) => {
+                // The head and the atoms after it stay on the first line
+                let inline = 1 + forms[1..].iter().take_while(|form| matches!(form, Form::Atom(_))).count();
+                out.push('(');
+                for (i, form) in forms.iter().enumerate() {
+                    if i >= inline {
+                        out.push('\n');
+                        out.push_str(&" ".repeat(indent + 2));
+                    } else if i > 0 {
+                        out.push(' ');
+                    }
+                    form.write(indent + 2, out);
+                }
+                out.push(')');
+            }
+        }
+    }
+}
+
+fn atom(text: &str) -> Form {
+    Form::Atom(text.to_string())
+}
+
+fn symbol(name: Symbol) -> Form {
+    Form::Atom(name.to_string())
+}
+
+fn string(text: &str) -> Form {
+    Form::Atom(format!("{text:?}"))
+}
+
+fn list(forms: Vec<Form>) -> Form {
+    Form::List(forms)
+}
+
+fn aliased(name: Symbol, alias: Option<Symbol>) -> Form {
+    match alias {
+        Some(alias) => list(vec![symbol(name), atom("as"), symbol(alias)]),
+        None => symbol(name),
+    }
+}
+
+fn visibility(visibility: &Visibility) -> Option<Form> {
+    let text = match visibility {
+        Visibility::Private | Visibility::SelfModule => return None,
+        Visibility::Public => "pub".to_string(),
+        Visibility::Crate => "pub(crate)".to_string(),
+        Visibility::Package => "pub(package)".to_string(),
+        Visibility::Super => "pub(super)".to_string(),
+        Visibility::InPath(path) => format!("pub(in {path})"),
+        Visibility::Component { .. } => "pub(component)".to_string(),
+    };
+    Some(Form::Atom(text))
+}
+
+fn parameters(parameters: &[IRParameter]) -> Form {
+    list(parameters.iter().map(|parameter| list(vec![symbol(parameter.name), ty(&parameter.type_hint)])).collect())
+}
+
+fn effects(effects: &IREffectSet) -> Form {
+    let mut form = vec![atom("effects")];
+    match effects {
+        IREffectSet::Empty => {}
+        IREffectSet::Effects(effects) => form.extend(effects.iter().map(|effect| symbol(effect.name))),
+        IREffectSet::Variable(variable) => form.push(Form::Atom(format!("'{variable}"))),
+    }
+    list(form)
+}
+
+fn variant(constructors: &[(Symbol, Vec<IRType>)]) -> Form {
+    let mut form = vec![atom("variant")];
+    form.extend(constructors.iter().map(|(name, fields)| {
+        let mut constructor = vec![symbol(*name)];
+        constructor.extend(fields.iter().map(ty));
+        list(constructor)
+    }));
+    list(form)
+}
+
+fn record_type(fields: &[(Symbol, IRType)]) -> Form {
+    let mut form = vec![atom("record")];
+    form.extend(fields.iter().map(|(name, field)| list(vec![symbol(*name), ty(field)])));
+    list(form)
+}
+
+fn ty(ty: &IRType) -> Form {
+    match ty {
+        IRType::Primitive(primitive) => Form::Atom(format!("{primitive:?}")),
+        IRType::Function { parameters, return_type, effects: function_effects } => list(vec![
+            atom("->"),
+            list(parameters.iter().map(self::ty).collect()),
+            self::ty(return_type),
+            effects(function_effects),
+        ]),
+        IRType::Tuple(elements) => prefixed("tuple", elements.iter().map(self::ty)),
+        IRType::Record(fields) => record_type(fields),
+        IRType::Variant(constructors) => variant(constructors),
+        IRType::Array(element) => list(vec![atom("array"), self::ty(element)]),
+        IRType::Reference(target) => list(vec![atom("ref"), self::ty(target)]),
+        IRType::TypeVariable(variable) => Form::Atom(format!("'{variable}")),
+        IRType::Named(name) => symbol(*name),
+    }
+}
+
+fn prefixed(head: &str, forms: impl Iterator<Item = Form>) -> Form {
+    let mut form = vec![atom(head)];
+    form.extend(forms);
+    list(form)
+}
+
+fn literal(literal: &IRLiteral) -> Form {
+    match literal {
+        IRLiteral::Integer(value) => Form::Atom(value.to_string()),
+        IRLiteral::Float(value) => Form::Atom(format!("{value:?}")),
+        IRLiteral::String(value) => string(value),
+        IRLiteral::Boolean(value) => Form::Atom(value.to_string()),
+        IRLiteral::Unit => atom("()"),
+        IRLiteral::Array(elements) => prefixed("array", elements.iter().map(expression)),
+        IRLiteral::Record(fields) => fields_form("record", fields),
+    }
+}
+
+fn fields_form(head: &str, fields: &[(Symbol, IRExpression)]) -> Form {
+    prefixed(head, fields.iter().map(|(name, value)| list(vec![symbol(*name), expression(value)])))
+}
+
+fn expression(expr: &IRExpression) -> Form {
+    match expr {
+        IRExpression::Literal(value) => literal(value),
+        IRExpression::Variable(name) => symbol(*name),
+        IRExpression::Call { function, arguments } => {
+            prefixed("call", std::iter::once(expression(function)).chain(arguments.iter().map(expression)))
+        }
+        IRExpression::Lambda { parameters: params, body, closure } => list(vec![
+            atom("lambda"),
+            parameters(params),
+            prefixed("closure", closure.iter().map(|&name| symbol(name))),
+            expression(body),
+        ]),
+        IRExpression::Let { bindings, body } => list(vec![
+            atom("let"),
+            list(bindings.iter()
+                .map(|binding| {
+                    let mut form = vec![symbol(binding.name)];
+                    form.extend(binding.type_hint.as_ref().map(ty));
+                    form.push(expression(&binding.value));
+                    list(form)
+                })
+                .collect()),
+            expression(body),
+        ]),
+        IRExpression::If { condition, then_branch, else_branch } => {
+            list(vec![atom("if"), expression(condition), expression(then_branch), expression(else_branch)])
+        }
+        IRExpression::Match { value, cases } => prefixed("match", std::iter::once(expression(value)).chain(
+            cases.iter().map(|case| {
+                let mut form = vec![atom("case"), pattern(&case.pattern)];
+                form.extend(case.guard.as_ref().map(|guard| list(vec![atom("when"), expression(guard)])));
+                form.push(expression(&case.body));
+                list(form)
+            }),
+        )),
+        IRExpression::Block(expressions) => prefixed("block", expressions.iter().map(expression)),
+        IRExpression::Effect { effect, operation, arguments } => prefixed(
+            "perform",
+            std::iter::once(Form::Atom(format!("{effect}.{operation}"))).chain(arguments.iter().map(expression)),
+        ),
+        IRExpression::Handle { expression: handled, mode, handlers, return_handler } => {
+            let mode = match mode {
+                HandlerMode::Deep => "deep",
+                HandlerMode::Shallow => "shallow",
+            };
+            let mut form = vec![atom("handle"), atom(mode), expression(handled)];
+            form.extend(handlers.iter().map(|handler| list(vec![
+                atom("on"),
+                Form::Atom(format!("{}.{}", handler.effect, handler.operation)),
+                list(handler.parameters.iter().map(|&name| symbol(name)).collect()),
+                symbol(handler.continuation),
+                expression(&handler.body),
+            ])));
+            form.extend(return_handler.iter().map(|handler| list(vec![atom("return"), expression(handler)])));
+            list(form)
+        }
+        IRExpression::Resume { value, continuation } => {
+            list(vec![atom("resume"), symbol(*continuation), expression(value)])
+        }
+        IRExpression::Field { record, field } => list(vec![atom("field"), expression(record), symbol(*field)]),
+        IRExpression::RecordUpdate { record, fields } => {
+            let mut form = vec![atom("update"), expression(record)];
+            form.extend(fields.iter().map(|(name, value)| list(vec![symbol(*name), expression(value)])));
+            list(form)
+        }
+        IRExpression::Tuple(elements) => prefixed("tuple", elements.iter().map(expression)),
+        IRExpression::TupleGet { tuple, index } => {
+            list(vec![atom("get"), expression(tuple), Form::Atom(index.to_string())])
+        }
+        IRExpression::Cell { name, initial, body } => {
+            list(vec![atom("cell"), symbol(*name), expression(initial), expression(body)])
+        }
+        IRExpression::CellGet(name) => list(vec![atom("cell-get"), symbol(*name)]),
+        IRExpression::CellSet { name, value } => list(vec![atom("cell-set"), symbol(*name), expression(value)]),
+    }
+}
+
+fn pattern(pattern: &IRPattern) -> Form {
+    match pattern {
+        IRPattern::Wildcard => atom("_"),
+        IRPattern::Variable(name) => symbol(*name),
+        IRPattern::Literal(value) => literal(value),
+        IRPattern::Constructor { name, arguments } => {
+            list(std::iter::once(symbol(*name)).chain(arguments.iter().map(self::pattern)).collect())
+        }
+        IRPattern::Tuple(elements) => prefixed("tuple", elements.iter().map(self::pattern)),
+        IRPattern::Record(fields) => {
+            prefixed("record", fields.iter().map(|(name, field)| list(vec![symbol(*name), self::pattern(field)])))
+        }
+        IRPattern::Or(alternatives) => prefixed("or", alternatives.iter().map(self::pattern)),
+    }
+}
@@ -6,15 +6,36 @@
 
 pub mod backend;
 pub mod ir;
+pub mod ir_print;
+pub mod anf;
+pub mod artifacts;
+pub mod verify;
+pub mod passes;
+pub mod peephole;
+pub mod constant_folding;
+pub mod dead_code;
+pub mod inlining;
+pub mod local_effects;
+pub mod tail_calls;
+pub mod unboxing;
+pub mod decision_tree;
 pub mod codegen_mod;
 pub mod typescript;
 pub mod wasm_gc;
 pub mod wasm_component;
+pub mod wasm_binary;
+pub mod component_binary;
 pub mod wit;
 pub mod wit_backend;
 pub mod utils;
 pub mod pipeline;
+pub mod conditions;
 pub mod config;
+pub mod diagnostics;
+pub mod manifest;
+pub mod profiling;
+pub mod stdlib;
+pub mod runtime;
 
 // Re-export main types
 pub use backend::{
@@ -23,10 +44,12 @@ pub use backend::{
 };
 pub use ir::{IR, IRBuilder};
 pub use pipeline::{CompilationPipeline, PipelineStage, PipelineResult};
-pub use config::{CompilerConfig, TargetConfig};
+pub use config::{CompilerConfig, OutputLayout, OutputNaming, PartialConfig, TargetConfig};
+pub use diagnostics::DiagnosticRenderer;
+pub use manifest::{BuildManifest, ManifestEntry};
 
 use x_parser::{CompilationUnit, SyntaxStyle};
-use x_checker::{type_check, CheckResult};
+use x_checker::CheckResult;
 use std::path::PathBuf;
 
 pub type Result<T> = std::result::Result<T, CompilerError>;
@@ -47,6 +70,7 @@ pub fn compile(
 pub struct CompilationResult {
     pub target: String,
     pub files: std::collections::HashMap<PathBuf, String>,
+    pub binary_files: std::collections::HashMap<PathBuf, Vec<u8>>,
     pub diagnostics: Vec<CompilerDiagnostic>,
     pub metadata: CompilationMetadata,
 }
@@ -62,6 +86,25 @@ pub struct CompilationMetadata {
     pub ast_nodes: usize,
     pub generated_files: usize,
     pub total_output_size: usize,
+    /// Per-module timings, in the order the sources were given
+    pub module_timings: Vec<ModuleTiming>,
+    /// Time and peak memory of each stage that ran, in order
+    pub stages: Vec<profiling::StageProfile>,
+    /// Hashes of the written files, as saved to `build-manifest.json`
+    pub manifest: BuildManifest,
+}
+
+/// Parse, type check and code generation timings for one module
+#[derive(Debug, Clone)]
+pub struct ModuleTiming {
+    pub module: String,
+    pub parse_time: std::time::Duration,
+    pub check_time: std::time::Duration,
+    pub codegen_time: std::time::Duration,
+    /// Optimizer passes over the module's IR, in the order they ran
+    pub passes: Vec<profiling::PassTiming>,
+    /// Time the backend spent emitting target code after the passes
+    pub emit_time: std::time::Duration,
 }
 
 /// Compiler diagnostic
@@ -70,7 +113,21 @@ pub struct CompilerDiagnostic {
     pub severity: DiagnosticSeverity,
     pub message: String,
     pub source: DiagnosticSource,
+    /// Stable code such as `E0101`; see [`diagnostics`] for the ranges
+    pub code: Option<&'static str>,
     pub span: Option<x_parser::Span>,
+    /// Secondary locations, e.g. the annotation a mismatch was checked against
+    pub labels: Vec<DiagnosticLabel>,
+    pub notes: Vec<String>,
+    /// Edit that resolves the diagnostic, offered as a code action or by `x check --fix`
+    pub suggested_fix: Option<x_editor::QuickFix>,
+}
+
+/// A secondary location attached to a diagnostic
+#[derive(Debug, Clone)]
+pub struct DiagnosticLabel {
+    pub span: x_parser::Span,
+    pub message: String,
 }
 
 /// Source of a diagnostic
@@ -106,6 +163,34 @@ pub enum CompilerError {
 
     #[error("Generic error: {0}")]
     Generic(String),
+
+    #[error("Compilation cancelled during {stage:?} stage")]
+    Cancelled { stage: PipelineStage },
+
+    #[error("Code generation for {module} is not deterministic: two runs produced different {}", file.display())]
+    Nondeterministic { module: String, file: PathBuf },
+
+    #[error("Module {name} is defined more than once")]
+    DuplicateModule { name: String },
+
+    #[error("Circular imports between modules: {}", modules.join(", "))]
+    CircularImports { modules: Vec<String> },
+
+    #[error("Invalid condition on the import of {module}: {message}")]
+    ImportCondition { module: String, message: String },
+
+    #[error("Invalid condition in #[cfg({condition})]: {message}")]
+    ItemCondition { condition: String, message: String },
+
+    #[error("Invalid IR for module {module}: {}", errors.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    InvalidIR { module: String, errors: Vec<verify::VerifyError> },
+}
+
+impl CompilerError {
+    /// Check whether this error represents a cancellation rather than a failure
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self, CompilerError::Cancelled { .. })
+    }
 }
 
 impl From<String> for CompilerError {
@@ -223,8 +308,7 @@ impl Compiler {
 
     /// Validate configuration
     pub fn validate_config(&self) -> Result<()> {
-        // TODO: Implement configuration validation
-        Ok(())
+        self.config.validate().map_err(|error| CompilerError::Config { message: error.to_string() })
     }
 
     /// Get configuration
@@ -267,7 +351,7 @@ pub mod convenience {
         
         let file_id = FileId::new(0);
         let cu = parse_source(source, file_id, SyntaxStyle::default())?;
-        Ok(type_check(&cu))
+        Ok(stdlib::checker().check_compilation_unit(&cu))
     }
 
     /// Parse only
@@ -0,0 +1,391 @@
+//! Lowering of fully-handled `State` and `Reader` handlers
+//!
+//! The canonical handlers of `Core.State` and `Core.Reader` pass the state
+//! along as an argument: the handled computation becomes a function of the
+//! initial state, and each clause a function of the current one.
+//!
+//! ```text
+//! (handle body with
+//!   | State.get with k => fun s -> (k s) s
+//!   | State.put s with k => fun _ -> (k ()) s
+//!   | return x => fun _ -> x) initial
+//! ```
+//!
+//! That costs a closure and a continuation per operation. [`lower_local_effects`]
+//! replaces such a handler, applied to its initial state, with an
+//! [`IRExpression::Cell`] holding the state, which the backends emit as a
+//! mutable local: `get` and `ask` read the cell and `put` writes it.
+//!
+//! A handler is lowered only when it handles every operation the computation
+//! performs with clauses of this shape. The computation must perform the
+//! effect itself rather than in a function it calls, and no closure of the
+//! module may perform it, since the computation could be given one.
+
+use crate::ir::{IRBinding, IREffectHandler, IRExpression, IRLiteral, IRModule};
+use std::collections::{HashMap, HashSet};
+use x_parser::{HandlerMode, Symbol};
+
+/// Replace the fully-handled state-passing handlers of `module` with cells
+pub fn lower_local_effects(module: &mut IRModule) {
+    let mut lowering = Lowering {
+        local: local_effects(module),
+        cells: 0,
+    };
+    if lowering.local.is_empty() {
+        return;
+    }
+    for function in &mut module.functions {
+        lowering.lower(&mut function.body);
+    }
+    for constant in &mut module.constants {
+        lowering.lower(&mut constant.value);
+    }
+}
+
+/// What a clause does with the state it is given
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Access {
+    /// `fun s -> (k s) s`: resume with the state, keeping it
+    Read,
+    /// `fun _ -> (k ()) v`: resume with unit, replacing the state with `v`
+    Write,
+}
+
+struct Lowering {
+    /// Effects no value of the module can perform, with the functions that
+    /// perform each when called
+    local: HashMap<Symbol, HashSet<Symbol>>,
+    cells: usize,
+}
+
+impl Lowering {
+    /// Lower the handlers in `expr`, innermost first
+    fn lower(&mut self, expr: &mut IRExpression) {
+        expr.for_each_child_mut(&mut |child| self.lower(child));
+        if let Some(cell) = self.cell(expr) {
+            *expr = cell;
+        }
+    }
+
+    /// The cell replacing `expr`, if it applies a handler that can be lowered
+    fn cell(&mut self, expr: &mut IRExpression) -> Option<IRExpression> {
+        let IRExpression::Call { function, arguments } = expr else { return None };
+        let IRExpression::Handle { expression, mode: HandlerMode::Deep, handlers, return_handler: Some(return_handler) } =
+            function.as_mut() else { return None };
+        let accesses: Vec<Access> = handlers.iter().map(access).collect::<Option<_>>()?;
+        let (value, state, result) = state_passing_return(return_handler)?;
+        if !handlers.iter().all(|handler| self.local.contains_key(&handler.effect))
+            || !fully_handled(expression, handlers, &self.local)
+        {
+            return None;
+        }
+
+        let name = Symbol::intern(&format!("state.{}", self.cells));
+        self.cells += 1;
+        let mut body = std::mem::replace(expression.as_mut(), IRExpression::Literal(IRLiteral::Unit));
+        replace_operations(&mut body, handlers, &accesses, name);
+        let mut bindings = vec![IRBinding { name: value, value: body, type_hint: None }];
+        if result.free_variables().contains(&state) {
+            bindings.push(IRBinding { name: state, value: IRExpression::CellGet(name), type_hint: None });
+        }
+        let result = IRExpression::Let { bindings, body: Box::new(result.clone()) };
+        let initial = arguments.remove(0);
+        let cell = IRExpression::Cell { name, initial: Box::new(initial), body: Box::new(result) };
+        Some(if arguments.is_empty() {
+            cell
+        } else {
+            IRExpression::Call { function: Box::new(cell), arguments: std::mem::take(arguments) }
+        })
+    }
+}
+
+/// Effects no closure of the module performs and no function performing
+/// them is passed around, with the functions that perform each
+fn local_effects(module: &IRModule) -> HashMap<Symbol, HashSet<Symbol>> {
+    let bodies: Vec<&IRExpression> = module.functions.iter().map(|function| &function.body)
+        .chain(module.constants.iter().map(|constant| &constant.value))
+        .collect();
+    let mut effects = HashSet::new();
+    for body in &bodies {
+        body.walk(&mut |expr| {
+            if let IRExpression::Effect { effect, .. } = expr {
+                effects.insert(*effect);
+            }
+            true
+        });
+    }
+    effects.into_iter()
+        .map(|effect| (effect, performing_functions(module, effect)))
+        .filter(|(effect, performing)| bodies.iter().all(|body| !escapes(body, *effect, performing)))
+        .collect()
+}
+
+/// Functions of the module that perform `effect` when called, themselves or
+/// through other such functions
+fn performing_functions(module: &IRModule, effect: Symbol) -> HashSet<Symbol> {
+    let mut performing = HashSet::new();
+    loop {
+        let found: Vec<Symbol> = module.functions.iter()
+            .filter(|function| !performing.contains(&function.name))
+            .filter(|function| performs(&function.body, effect, &performing))
+            .map(|function| function.name)
+            .collect();
+        if found.is_empty() {
+            return performing;
+        }
+        performing.extend(found);
+    }
+}
+
+/// Whether `expr` performs `effect` or refers to one of the `performing`
+/// functions anywhere, lambdas included
+fn performs(expr: &IRExpression, effect: Symbol, performing: &HashSet<Symbol>) -> bool {
+    let mut found = false;
+    expr.walk(&mut |expr| {
+        found |= match expr {
+            IRExpression::Effect { effect: performed, .. } => *performed == effect,
+            IRExpression::Variable(name) => performing.contains(name),
+            _ => false,
+        };
+        !found
+    });
+    found
+}
+
+/// Whether `effect` can be performed by a value in `expr`: a closure that
+/// performs it, or one of the `performing` functions other than in a call
+fn escapes(expr: &IRExpression, effect: Symbol, performing: &HashSet<Symbol>) -> bool {
+    let mut closure = false;
+    let mut references = 0;
+    let mut calls = 0;
+    expr.walk(&mut |expr| {
+        match expr {
+            IRExpression::Lambda { body, .. } => closure |= performs(body, effect, performing),
+            IRExpression::Variable(name) if performing.contains(name) => references += 1,
+            IRExpression::Call { function, .. } => {
+                if matches!(function.as_ref(), IRExpression::Variable(name) if performing.contains(name)) {
+                    calls += 1;
+                }
+            }
+            _ => {}
+        }
+        !closure
+    });
+    closure || references > calls
+}
+
+/// Whether the handlers handle every operation `expr` performs of their
+/// effects, none of them in a function `expr` calls
+fn fully_handled(expr: &IRExpression, handlers: &[IREffectHandler], local: &HashMap<Symbol, HashSet<Symbol>>) -> bool {
+    let effects: HashSet<Symbol> = handlers.iter().map(|handler| handler.effect).collect();
+    let mut handled = true;
+    expr.walk(&mut |expr| {
+        match expr {
+            IRExpression::Variable(name) => {
+                handled &= !effects.iter().any(|effect| local[effect].contains(name));
+            }
+            IRExpression::Effect { effect, operation, .. } if effects.contains(effect) => {
+                handled &= handlers.iter().any(|handler| handler.effect == *effect && handler.operation == *operation);
+            }
+            // An inner handler left in place would take some of the operations
+            IRExpression::Handle { handlers: inner, .. } => {
+                handled &= !inner.iter().any(|handler| effects.contains(&handler.effect));
+            }
+            _ => {}
+        }
+        handled
+    });
+    handled
+}
+
+/// Replace the operations the handlers handle with reads and writes of `cell`
+fn replace_operations(expr: &mut IRExpression, handlers: &[IREffectHandler], accesses: &[Access], cell: Symbol) {
+    expr.for_each_child_mut(&mut |child| replace_operations(child, handlers, accesses, cell));
+    let IRExpression::Effect { effect, operation, arguments } = expr else { return };
+    let Some(index) = handlers.iter().position(|handler| handler.effect == *effect && handler.operation == *operation) else {
+        return;
+    };
+    *expr = match accesses[index] {
+        Access::Read => IRExpression::CellGet(cell),
+        Access::Write => IRExpression::CellSet { name: cell, value: Box::new(arguments.remove(0)) },
+    };
+}
+
+/// How a state-passing clause uses the state, if it is one
+fn access(handler: &IREffectHandler) -> Option<Access> {
+    let IRExpression::Lambda { parameters, body, .. } = &handler.body else { return None };
+    let [state] = parameters.as_slice() else { return None };
+    let (resumed, next) = resumed_then_applied(body, handler.continuation)?;
+    match (handler.parameters.as_slice(), resumed, next) {
+        ([], IRExpression::Variable(resumed), IRExpression::Variable(next))
+            if *resumed == state.name && *next == state.name => Some(Access::Read),
+        ([value], IRExpression::Literal(IRLiteral::Unit), IRExpression::Variable(next)) if next == value => {
+            Some(Access::Write)
+        }
+        _ => None,
+    }
+}
+
+/// `(a, b)` for `(k a) b`, where `k` is the clause's continuation
+fn resumed_then_applied(expr: &IRExpression, continuation: Symbol) -> Option<(&IRExpression, &IRExpression)> {
+    let IRExpression::Call { function, arguments } = expr else { return None };
+    match (function.as_ref(), arguments.as_slice()) {
+        (IRExpression::Variable(k), [resumed, next]) if *k == continuation => Some((resumed, next)),
+        (IRExpression::Call { function, arguments: first }, [next]) => match (function.as_ref(), first.as_slice()) {
+            (IRExpression::Variable(k), [resumed]) if *k == continuation => Some((resumed, next)),
+            _ => None,
+        },
+        (IRExpression::Resume { value, continuation: k }, [next]) if *k == continuation => Some((value, next)),
+        _ => None,
+    }
+}
+
+/// `(x, s, e)` for a return clause `fun x -> fun s -> e`
+fn state_passing_return(handler: &IRExpression) -> Option<(Symbol, Symbol, &IRExpression)> {
+    let IRExpression::Lambda { parameters, body, .. } = handler else { return None };
+    match (parameters.as_slice(), body.as_ref()) {
+        ([value, state], result) => Some((value.name, state.name, result)),
+        ([value], IRExpression::Lambda { parameters, body, .. }) => match parameters.as_slice() {
+            [state] => Some((value.name, state.name, body)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::IRBuilder;
+    use crate::stdlib;
+    use crate::CodegenBackend;
+    use x_parser::span::CharOffset;
+    use x_parser::{parse_source, CompilationUnit, Expr, FileId, Item, Span, SyntaxStyle};
+
+    fn span() -> Span {
+        Span::new(FileId::new(0), CharOffset(0), CharOffset(0))
+    }
+
+    fn perform(effect: &str, operation: &str, args: Vec<Expr>) -> Expr {
+        Expr::Perform { effect: Symbol::intern(effect), operation: Symbol::intern(operation), args, span: span() }
+    }
+
+    /// `(handle expr with <library handler>) initial`
+    fn run(library: &str, expr: Expr, initial: Expr) -> Expr {
+        let unit = stdlib::parse(library).unwrap();
+        let Some(Item::HandlerDef(handler)) = unit.module.items.into_iter().find(|item| matches!(item, Item::HandlerDef(_))) else {
+            panic!("{library} has no handler");
+        };
+        let handle = Expr::Handle {
+            expr: Box::new(expr),
+            mode: HandlerMode::Deep,
+            handlers: handler.handlers,
+            return_clause: handler.return_clause.map(Box::new),
+            span: span(),
+        };
+        Expr::App(Box::new(handle), vec![initial], span())
+    }
+
+    /// The module `source` with the bodies of its first functions replaced
+    fn unit(source: &str, bodies: Vec<Expr>) -> CompilationUnit {
+        let mut cu = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        for (item, new_body) in cu.module.items.iter_mut().zip(bodies) {
+            if let Item::ValueDef(value_def) = item {
+                if let Expr::Lambda { body, .. } = &mut value_def.body {
+                    **body = new_body;
+                }
+            }
+        }
+        cu
+    }
+
+    fn body<'a>(module: &'a IRModule, name: &str) -> &'a IRExpression {
+        &module.functions.iter().find(|function| function.name.as_str() == name).unwrap().body
+    }
+
+    fn handles(expr: &IRExpression) -> bool {
+        let mut found = false;
+        expr.walk(&mut |expr| {
+            found |= matches!(expr, IRExpression::Handle { .. } | IRExpression::Effect { .. });
+            !found
+        });
+        found
+    }
+
+    fn swap() -> Expr {
+        let u = Expr::Var(Symbol::intern("u"), span());
+        let steps = vec![perform("State", "get", vec![]), perform("State", "put", vec![u]), perform("State", "get", vec![])];
+        run("Core.State", Expr::Tuple { elements: steps, span: span() }, Expr::Literal(x_parser::Literal::int(1), span()))
+    }
+
+    #[test]
+    fn test_state_and_reader_handlers_become_cells() {
+        let ask = run("Core.Reader", perform("Reader", "ask", vec![]), Expr::Var(Symbol::intern("u"), span()));
+        let cu = unit("module Test\npub let swap = fun u -> 0\npub let ask = fun u -> 0", vec![swap(), ask]);
+        let module = IRBuilder::new().build_ir(&cu).unwrap().modules.remove(0);
+
+        for name in ["swap", "ask"] {
+            assert!(matches!(body(&module, name), IRExpression::Cell { .. }), "{name}: {:?}", body(&module, name));
+            assert!(!handles(body(&module, name)), "{name}: {:?}", body(&module, name));
+        }
+        let IRExpression::Cell { body: swapped, .. } = body(&module, "swap") else { unreachable!() };
+        let mut writes = 0;
+        swapped.walk(&mut |expr| {
+            writes += matches!(expr, IRExpression::CellSet { .. }) as usize;
+            true
+        });
+        assert_eq!(writes, 1);
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut backend = crate::typescript::TypeScriptBackend::javascript();
+        let options = crate::CodegenOptions {
+            target: backend.target_info(),
+            output_dir: dir.path().to_path_buf(),
+            source_maps: false,
+            debug_info: false,
+            optimization_level: 0,
+            emit_types: false,
+            naming: Default::default(),
+            cancellation: Default::default(),
+            passes: Default::default(),
+            inlining: Default::default(),
+            sources: Default::default(),
+        };
+        let result = backend.generate_code(&cu, &HashMap::new(), &options).unwrap();
+        for (path, code) in &result.files {
+            std::fs::write(path, code).unwrap();
+        }
+        let script = format!(
+            "import {{ swap, ask }} from {:?}; console.log(swap(5).join(), ask(7));",
+            dir.path().join("Test.mjs").display().to_string(),
+        );
+        match std::process::Command::new("node").args(["--input-type=module", "-e", &script]).output() {
+            Ok(output) => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "1,,5 7", "{stderr}");
+            }
+            Err(_) => eprintln!("node is not installed; skipping"),
+        }
+    }
+
+    #[test]
+    fn test_handlers_are_kept_when_the_effect_can_escape() {
+        let u = || Expr::Var(Symbol::intern("u"), span());
+        let call = |name: &str| Expr::App(Box::new(Expr::Var(Symbol::intern(name), span())), vec![u()], span());
+        let get = || perform("State", "get", vec![]);
+        let lambda = |body| Expr::Lambda { parameters: vec![x_parser::Pattern::Wildcard(span())], body: Box::new(body), span: span() };
+        let initial = || Expr::Literal(x_parser::Literal::int(0), span());
+
+        // The computation calls a function performing the effect
+        let cu = unit("module Test\nlet run = fun u -> 0\nlet read = fun u -> 0", vec![run("Core.State", call("read"), initial()), get()]);
+        let module = IRBuilder::new().build_ir(&cu).unwrap().modules.remove(0);
+        assert!(handles(body(&module, "run")));
+
+        // Some closure of the module performs it
+        let closure = run("Core.State", Expr::App(Box::new(lambda(get())), vec![u()], span()), initial());
+        let cu = unit("module Test\nlet run = fun u -> 0\nlet keep = fun u -> 0", vec![closure, swap()]);
+        let module = IRBuilder::new().build_ir(&cu).unwrap().modules.remove(0);
+        assert!(handles(body(&module, "run")));
+        assert!(handles(body(&module, "keep")));
+    }
+}
@@ -0,0 +1,182 @@
+//! Build manifests
+//!
+//! Every compilation writes `build-manifest.json` next to its output. It
+//! lists each generated file, relative to the output directory, with the
+//! SHA-256 of its contents and, when a single module produced it, that
+//! module's name and the SHA-256 of its source. Files shared between
+//! modules, such as the runtime, have no source. The compiler version and
+//! a hash of the configuration affecting the target make two manifests
+//! comparable: the same sources, compiler and configuration must give the
+//! same file hashes.
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::config::CompilerConfig;
+
+/// Name of the manifest file in the output directory
+pub const MANIFEST_FILE: &str = "build-manifest.json";
+
+/// Hex SHA-256 of `data`
+pub fn content_hash(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Hashes of the files written by one compilation
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct BuildManifest {
+    pub compiler_version: String,
+    pub target: String,
+    /// Hash of the configuration the target was compiled with
+    pub config_hash: String,
+    /// Files keyed by their `/`-separated path in the output directory
+    pub files: BTreeMap<String, ManifestEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub hash: String,
+    pub size: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub module: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_hash: Option<String>,
+}
+
+/// The module a generated file came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileOrigin {
+    pub module: String,
+    pub source_hash: String,
+}
+
+impl BuildManifest {
+    pub fn new(target: &str, config: &CompilerConfig) -> Self {
+        BuildManifest {
+            compiler_version: env!("CARGO_PKG_VERSION").to_string(),
+            target: target.to_string(),
+            config_hash: config_hash(target, config),
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// Record `contents`, written to `path` under `output_dir`
+    pub fn add_file(&mut self, output_dir: &Path, path: &Path, contents: &[u8], origin: Option<&FileOrigin>) {
+        self.files.insert(manifest_path(output_dir, path), ManifestEntry {
+            hash: content_hash(contents),
+            size: contents.len(),
+            module: origin.map(|origin| origin.module.clone()),
+            source_hash: origin.map(|origin| origin.source_hash.clone()),
+        });
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("manifests always serialize") + "\n"
+    }
+
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+
+    /// Read the manifest a compilation left in `output_dir`
+    pub fn read(output_dir: &Path) -> std::io::Result<Self> {
+        let json = std::fs::read_to_string(output_dir.join(MANIFEST_FILE))?;
+        Self::from_json(&json).map_err(std::io::Error::from)
+    }
+
+    /// Files under `output_dir` that are missing or whose contents no
+    /// longer match their hash
+    pub fn verify(&self, output_dir: &Path) -> Vec<&str> {
+        self.files.iter()
+            .filter(|(path, entry)| match std::fs::read(output_dir.join(path.as_str())) {
+                Ok(contents) => content_hash(&contents) != entry.hash,
+                Err(_) => true,
+            })
+            .map(|(path, _)| path.as_str())
+            .collect()
+    }
+
+    /// Files to deploy after `previous`: those that are new or changed
+    pub fn changed_since(&self, previous: &BuildManifest) -> Vec<&str> {
+        self.files.iter()
+            .filter(|(path, entry)| previous.files.get(*path).is_none_or(|old| old.hash != entry.hash))
+            .map(|(path, _)| path.as_str())
+            .collect()
+    }
+
+    /// Files of `previous` that this build no longer produces
+    pub fn removed_since<'a>(&self, previous: &'a BuildManifest) -> Vec<&'a str> {
+        previous.files.keys()
+            .filter(|path| !self.files.contains_key(*path))
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+/// `path` relative to `output_dir`, with `/` separators
+fn manifest_path(output_dir: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(output_dir).unwrap_or(path);
+    let parts: Vec<_> = relative.components().map(|part| part.as_os_str().to_string_lossy()).collect();
+    parts.join("/")
+}
+
+/// Hash of the settings that change what `target` generates
+///
+/// Object keys serialize sorted, so the hash does not depend on the order
+/// options were set in.
+fn config_hash(target: &str, config: &CompilerConfig) -> String {
+    let mut features = config.features.clone();
+    features.sort();
+    features.dedup();
+    let settings = json!({
+        "target": target,
+        "optimization_level": config.optimization_level,
+        "debug_info": config.debug_info,
+        "source_maps": config.source_maps,
+        "emit_types": config.emit_types,
+        "features": features,
+        "options": config.target_configs.get(target).map(|target| &target.options),
+    });
+    content_hash(settings.to_string().as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_manifest_tracks_changes() {
+        let dir = TempDir::new().unwrap();
+        let origin = FileOrigin { module: "Main".to_string(), source_hash: content_hash(b"module Main") };
+        let mut manifest = BuildManifest::new("typescript", &CompilerConfig::default());
+        manifest.add_file(dir.path(), &dir.path().join("Main.ts"), b"export {};", Some(&origin));
+        manifest.add_file(dir.path(), &dir.path().join("lib").join("runtime.ts"), b"// runtime", None);
+        assert_eq!(manifest.files.keys().collect::<Vec<_>>(), ["Main.ts", "lib/runtime.ts"]);
+        assert_eq!(BuildManifest::from_json(&manifest.to_json()).unwrap(), manifest);
+
+        std::fs::write(dir.path().join("Main.ts"), "export {};").unwrap();
+        assert_eq!(manifest.verify(dir.path()), ["lib/runtime.ts"]);
+
+        let mut next = manifest.clone();
+        next.files.remove("lib/runtime.ts");
+        next.add_file(dir.path(), &dir.path().join("Main.ts"), b"export const one = 1;", Some(&origin));
+        assert_eq!(next.changed_since(&manifest), ["Main.ts"]);
+        assert_eq!(next.removed_since(&manifest), ["lib/runtime.ts"]);
+    }
+
+    #[test]
+    fn test_config_hash_covers_target_settings() {
+        let mut config = CompilerConfig::default();
+        let base = config_hash("typescript", &config);
+        assert_eq!(base, config_hash("typescript", &config.clone()));
+        assert_ne!(base, config_hash("javascript", &config));
+        config.set_target_option("wasm-gc", "layout", "flat".into());
+        assert_eq!(base, config_hash("typescript", &config));
+        config.set_target_option("typescript", "layout", "flat".into());
+        assert_ne!(base, config_hash("typescript", &config));
+    }
+}
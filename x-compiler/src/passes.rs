@@ -0,0 +1,168 @@
+//! Passes over the IR and the order they run in
+//!
+//! Every pass is registered here with a name and the passes it relies on.
+//! A [`PassManager`] holds the pipeline a build runs after lowering: the
+//! passes `CompilerConfig::passes` names, each preceded by those it
+//! requires, or without that setting the [defaults](PassManager::for_level)
+//! of the optimization level.
+//!
+//! ```toml
+//! [compiler]
+//! passes = ["flatten-pipelines", "dce", "local-effects"]
+//! ```
+//!
+//! runs `flatten-pipelines`, then `constfold`, which `dce` requires, then
+//! `dce` and `local-effects`.
+
+use crate::ir::IRModule;
+use crate::profiling::PassTiming;
+use crate::{constant_folding, dead_code, local_effects, peephole, unboxing};
+use std::time::Instant;
+
+/// A named pass over the IR of a module
+#[derive(Debug)]
+pub struct Pass {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Passes that must run before this one; a pipeline naming this pass
+    /// gets them too
+    pub requires: &'static [&'static str],
+    pub run: fn(&mut IRModule),
+}
+
+/// Every pass a pipeline may name
+pub const PASSES: &[Pass] = &[
+    Pass {
+        name: "flatten-pipelines",
+        description: "turn applied pipelines and compositions into direct calls",
+        requires: &[],
+        run: peephole::flatten_pipelines,
+    },
+    Pass {
+        name: "constfold",
+        description: "evaluate operators on literals and conditionals on constants",
+        requires: &[],
+        run: constant_folding::fold_constants,
+    },
+    Pass {
+        name: "dce",
+        description: "remove unused values, including those constant folding leaves",
+        requires: &["constfold"],
+        run: dead_code::eliminate_dead_code,
+    },
+    Pass {
+        name: "local-effects",
+        description: "lower State and Reader handlers to mutable cells",
+        requires: &[],
+        run: local_effects::lower_local_effects,
+    },
+    Pass {
+        name: "unbox",
+        description: "type the locals that always hold an Int, Float or Bool, which WebAssembly keeps unboxed",
+        requires: &[],
+        run: unboxing::unbox_locals,
+    },
+];
+
+/// The passes every build runs
+const BASE_PIPELINE: [&str; 2] = ["flatten-pipelines", "local-effects"];
+
+/// The passes optimized builds run
+const OPTIMIZED_PIPELINE: [&str; 5] = ["flatten-pipelines", "constfold", "dce", "local-effects", "unbox"];
+
+/// A pipeline naming a pass that is not registered
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("unknown pass `{name}`, expected one of {}", PASSES.iter().map(|pass| pass.name).collect::<Vec<_>>().join(", "))]
+pub struct UnknownPass {
+    pub name: String,
+}
+
+/// The passes to run after lowering, in order
+#[derive(Debug, Clone)]
+pub struct PassManager {
+    pipeline: Vec<&'static Pass>,
+}
+
+impl Default for PassManager {
+    fn default() -> Self {
+        Self::for_level(0)
+    }
+}
+
+impl PassManager {
+    /// The pipeline of `names`, in order, each pass after those it requires
+    /// and run once
+    pub fn new<S: AsRef<str>>(names: &[S]) -> Result<Self, UnknownPass> {
+        let mut manager = PassManager { pipeline: Vec::new() };
+        for name in names {
+            manager.schedule(name.as_ref())?;
+        }
+        Ok(manager)
+    }
+
+    /// The default pipeline at optimization level `level`
+    pub fn for_level(level: u8) -> Self {
+        let names: &[&str] = if level == 0 { &BASE_PIPELINE } else { &OPTIMIZED_PIPELINE };
+        Self::new(names).expect("the default pipelines name registered passes")
+    }
+
+    /// The registered pass named `name`
+    pub fn lookup(name: &str) -> Option<&'static Pass> {
+        PASSES.iter().find(|pass| pass.name == name)
+    }
+
+    fn schedule(&mut self, name: &str) -> Result<(), UnknownPass> {
+        let pass = Self::lookup(name).ok_or_else(|| UnknownPass { name: name.to_string() })?;
+        if self.pipeline.iter().any(|scheduled| scheduled.name == pass.name) {
+            return Ok(());
+        }
+        for required in pass.requires {
+            self.schedule(required)?;
+        }
+        self.pipeline.push(pass);
+        Ok(())
+    }
+
+    /// The passes, in the order they run
+    pub fn passes(&self) -> &[&'static Pass] {
+        &self.pipeline
+    }
+
+    /// Run the pipeline over `module`, timing each pass
+    pub fn run(&self, module: &mut IRModule) -> Vec<PassTiming> {
+        self.pipeline.iter()
+            .map(|pass| {
+                let start = Instant::now();
+                (pass.run)(module);
+                PassTiming { name: pass.name, duration: start.elapsed() }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(manager: &PassManager) -> Vec<&str> {
+        manager.passes().iter().map(|pass| pass.name).collect()
+    }
+
+    #[test]
+    fn test_required_passes_run_first_and_once() {
+        let manager = PassManager::new(&["flatten-pipelines", "dce", "constfold", "local-effects"]).unwrap();
+        assert_eq!(names(&manager), ["flatten-pipelines", "constfold", "dce", "local-effects"]);
+        assert_eq!(names(&PassManager::default()), BASE_PIPELINE);
+        assert_eq!(names(&PassManager::for_level(2)), OPTIMIZED_PIPELINE);
+        assert!(PassManager::new::<&str>(&[]).unwrap().passes().is_empty());
+    }
+
+    #[test]
+    fn test_unknown_passes_are_rejected() {
+        let error = PassManager::new(&["dce", "inline-handlers"]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "unknown pass `inline-handlers`, expected one of flatten-pipelines, constfold, dce, local-effects, unbox",
+        );
+    }
+}
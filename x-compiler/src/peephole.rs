@@ -0,0 +1,170 @@
+//! Peephole rewrites on the IR
+//!
+//! [`flatten_pipelines`] turns applied uses of the pipeline and composition
+//! operators into the calls they stand for, so the backends emit
+//! `g(f(x))` for `x |> f |> g` instead of going through an operator and a
+//! closure per stage:
+//!
+//! - `x |> f a` becomes `f a x`, the call as it would be written directly;
+//! - `x |> fun y -> e` becomes `let y = x in e`;
+//! - `(f >> g) x` becomes `g (f x)`, and `(f << g) x` becomes `f (g x)`.
+//!
+//! A rewrite that would change the order in which effects are performed is
+//! not made, and a composition never applied to an argument is left alone.
+
+use crate::ir::{IRBinding, IRExpression, IRLiteral, IRModule};
+
+/// Flatten the pipelines of every function and constant of `module`
+pub fn flatten_pipelines(module: &mut IRModule) {
+    for function in &mut module.functions {
+        flatten(&mut function.body);
+    }
+    for constant in &mut module.constants {
+        flatten(&mut constant.value);
+    }
+}
+
+/// Flatten the pipelines of `expr`, innermost first
+pub fn flatten(expr: &mut IRExpression) {
+    expr.for_each_child_mut(&mut flatten);
+    let taken = std::mem::replace(expr, IRExpression::Literal(IRLiteral::Unit));
+    *expr = settle(taken);
+}
+
+/// Rewrite `expr` itself until no rule applies; its children are flat
+fn settle(mut expr: IRExpression) -> IRExpression {
+    loop {
+        expr = match rewrite(expr) {
+            Ok(rewritten) => rewritten,
+            Err(expr) => return expr,
+        };
+    }
+}
+
+/// Apply one rule at the root of `expr`, or give it back unchanged
+fn rewrite(expr: IRExpression) -> Result<IRExpression, IRExpression> {
+    let IRExpression::Call { function, mut arguments } = expr else { return Err(expr) };
+    // `(f >> g) x` is a call of a call; make it one call of `>>`
+    let function = match *function {
+        IRExpression::Call { function: inner, arguments: mut first } if operator(&inner).is_some() => {
+            first.append(&mut arguments);
+            return Ok(IRExpression::Call { function: inner, arguments: first });
+        }
+        function => Box::new(function),
+    };
+    match (operator(&function), arguments.len()) {
+        (Some("|>"), 2) if reorderable(&arguments[0], &arguments[1]) => {
+            let function = arguments.pop().expect("two arguments");
+            let argument = arguments.pop().expect("two arguments");
+            Ok(apply(function, argument))
+        }
+        (Some(">>" | "<<"), 3) if arguments[..2].iter().all(|function| function.performed_effects().is_empty()) => {
+            let argument = arguments.pop().expect("three arguments");
+            let mut second = arguments.pop().expect("three arguments");
+            let mut first = arguments.pop().expect("three arguments");
+            if operator(&function) == Some("<<") {
+                std::mem::swap(&mut first, &mut second);
+            }
+            let intermediate = apply(first, argument);
+            Ok(apply(second, intermediate))
+        }
+        _ => Err(IRExpression::Call { function, arguments }),
+    }
+}
+
+/// `function argument`, as a direct call where that keeps the order of effects
+fn apply(function: IRExpression, argument: IRExpression) -> IRExpression {
+    match function {
+        IRExpression::Lambda { mut parameters, body, .. } if parameters.len() == 1 => {
+            let parameter = parameters.remove(0);
+            IRExpression::Let {
+                bindings: vec![IRBinding { name: parameter.name, value: argument, type_hint: None }],
+                body,
+            }
+        }
+        IRExpression::Call { function, mut arguments }
+            if arguments.iter().all(|earlier| reorderable(earlier, &argument)) =>
+        {
+            arguments.push(argument);
+            settle(IRExpression::Call { function, arguments })
+        }
+        function => IRExpression::Call { function: Box::new(function), arguments: vec![argument] },
+    }
+}
+
+/// The pipeline or composition operator `expr` names
+fn operator(expr: &IRExpression) -> Option<&'static str> {
+    match expr {
+        IRExpression::Variable(name) => ["|>", ">>", "<<"].into_iter().find(|operator| name.as_str() == *operator),
+        _ => None,
+    }
+}
+
+/// Whether `a` and `b` may be evaluated in either order
+fn reorderable(a: &IRExpression, b: &IRExpression) -> bool {
+    a.performed_effects().is_empty() || b.performed_effects().is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::IRBuilder;
+    use x_parser::{parse_source, FileId, SyntaxStyle, Symbol};
+
+    fn body(source: &str, name: &str) -> IRExpression {
+        let unit = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let mut module = IRBuilder::new().build_ir(&unit).unwrap().modules.remove(0);
+        let function = module.functions.iter().position(|function| function.name.as_str() == name).unwrap();
+        module.functions.remove(function).body
+    }
+
+    /// The expression as nested calls, `f(a, b)`
+    fn calls(expr: &IRExpression) -> String {
+        match expr {
+            IRExpression::Variable(name) => name.to_string(),
+            IRExpression::Literal(_) => "lit".to_string(),
+            IRExpression::Call { function, arguments } => {
+                let arguments: Vec<String> = arguments.iter().map(calls).collect();
+                format!("{}({})", calls(function), arguments.join(", "))
+            }
+            IRExpression::Let { bindings, body } => format!("let {} = {} in {}", bindings[0].name, calls(&bindings[0].value), calls(body)),
+            other => format!("{other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_pipelines_become_direct_calls() {
+        let source = "module Demo\n\
+            let show = fun n -> n |> inc |> map f |> string_of_int\n\
+            let composed = fun n -> (inc >> double << half) n\n\
+            let piped = fun n -> n |> (fun m -> m + 1)";
+        assert_eq!(calls(&body(source, "show")), "string_of_int(map(f, inc(n)))");
+        // `(inc >> double) << half`
+        assert_eq!(calls(&body(source, "composed")), "double(inc(half(n)))");
+        assert_eq!(calls(&body(source, "piped")), "let m = n in +(m, lit)");
+    }
+
+    #[test]
+    fn test_unapplied_composition_and_effects_are_kept() {
+        let keep = IRExpression::Call {
+            function: Box::new(IRExpression::Variable(Symbol::intern(">>"))),
+            arguments: vec![IRExpression::Variable(Symbol::intern("f")), IRExpression::Variable(Symbol::intern("g"))],
+        };
+        let mut expr = keep.clone();
+        flatten(&mut expr);
+        assert_eq!(calls(&expr), calls(&keep));
+
+        // Performing the effect before evaluating the function is kept as it is
+        let perform = IRExpression::Effect { effect: Symbol::intern("IO"), operation: Symbol::intern("read"), arguments: vec![] };
+        let effectful_function = IRExpression::Call {
+            function: Box::new(IRExpression::Variable(Symbol::intern("pick"))),
+            arguments: vec![IRExpression::Effect { effect: Symbol::intern("IO"), operation: Symbol::intern("read"), arguments: vec![] }],
+        };
+        let mut expr = IRExpression::Call {
+            function: Box::new(IRExpression::Variable(Symbol::intern("|>"))),
+            arguments: vec![perform, effectful_function],
+        };
+        flatten(&mut expr);
+        assert!(calls(&expr).starts_with("|>("), "{}", calls(&expr));
+    }
+}
@@ -1,14 +1,24 @@
 //! Compilation pipeline for orchestrating the compilation process
 
 use crate::{
-    backend::{BackendFactory, CodegenOptions, CompilationTarget},
+    artifacts::Artifact,
+    backend::{BackendFactory, CodegenMetadata, CodegenOptions, CompilationTarget},
+    codegen_mod::TypeScriptModuleSystem,
+    conditions,
     config::CompilerConfig,
+    inlining::InlineCandidates,
+    ir::IRBuilder,
+    manifest::{content_hash, BuildManifest, FileOrigin, MANIFEST_FILE},
+    profiling::{self, PassTiming, StageProfile},
     CompilerError, CompilationResult, CompilationMetadata, CompilerDiagnostic, DiagnosticSource,
+    ModuleTiming,
 };
-use x_parser::{parse_with_metadata, FileId};
-use x_checker::type_check;
+use rayon::prelude::*;
+use x_checker::ModuleInterface;
+use x_parser::{parse_source_cancellable, CancellationToken, FileId, ImportKind, ParseError, SourceMap, Symbol};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::Instant;
 
 /// Compilation pipeline stages
@@ -31,10 +41,19 @@ pub struct PipelineResult<T> {
     pub diagnostics: Vec<CompilerDiagnostic>,
 }
 
+/// Text and binary files produced by code generation
+type GeneratedFiles = (HashMap<PathBuf, String>, HashMap<PathBuf, Vec<u8>>);
+
+/// Code generation time of a module, its passes and its emit time
+type CodegenTiming = (std::time::Duration, Vec<PassTiming>, std::time::Duration);
+
 /// Compilation pipeline
 pub struct CompilationPipeline {
     config: CompilerConfig,
     enabled_stages: Vec<PipelineStage>,
+    cancellation: CancellationToken,
+    /// Intermediate artifacts written for each module besides its code
+    emit: Vec<Artifact>,
 }
 
 impl CompilationPipeline {
@@ -51,62 +70,234 @@ impl CompilationPipeline {
         Self {
             config,
             enabled_stages,
+            cancellation: CancellationToken::new(),
+            emit: Vec::new(),
         }
     }
 
+    /// Also write `artifacts` for each module, named like its generated files
+    pub fn with_emit(mut self, artifacts: Vec<Artifact>) -> Self {
+        self.emit = artifacts;
+        self
+    }
+
+    /// Attach a cancellation token checked between and within stages
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = token;
+        self
+    }
+
+    /// Cancel the compilation once `timeout` has passed from now
+    pub fn with_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.cancellation = self.cancellation.with_timeout(timeout);
+        self
+    }
+
+    /// Get the cancellation token observed by this pipeline
+    pub fn cancellation_token(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
+    /// Fail with `CompilerError::Cancelled` if cancellation was requested before `stage`
+    fn check_cancelled(&self, stage: PipelineStage) -> Result<(), CompilerError> {
+        self.cancellation
+            .check()
+            .map_err(|_| CompilerError::Cancelled { stage })
+    }
+
     /// Run the full compilation pipeline
     pub fn compile(
         &mut self,
         source: &str,
         target: &str,
         output_dir: PathBuf,
+    ) -> Result<CompilationResult, CompilerError> {
+        self.compile_project(&[source], target, output_dir)
+    }
+
+    /// Compile a multi-module project
+    ///
+    /// All sources are parsed in parallel. Modules are then type checked in
+    /// waves following the import graph: every module in a wave only imports
+    /// modules from earlier waves, so the modules of a wave are checked in
+    /// parallel against the public definitions of the modules they import.
+    /// Imports of modules outside the project are ignored.
+    pub fn compile_project(
+        &mut self,
+        sources: &[&str],
+        target: &str,
+        output_dir: PathBuf,
     ) -> Result<CompilationResult, CompilerError> {
         let total_start = Instant::now();
         let mut all_diagnostics = Vec::new();
+        let mut stages = Vec::new();
 
         // Stage 1: Parse
-        let parse_result = self.run_parse_stage(source)?;
-        all_diagnostics.extend(parse_result.diagnostics);
-        let ast = parse_result.result;
-        let parse_time = parse_result.duration;
+        self.check_cancelled(PipelineStage::Parse)?;
+        profiling::reset_peak();
+        let parse_start = Instant::now();
+        let parsed = sources.par_iter()
+            .enumerate()
+            .map(|(index, source)| self.run_parse_stage(source, FileId::new(index as u32)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let parse_time = parse_start.elapsed();
+
+        let mut parse_times = Vec::with_capacity(parsed.len());
+        let mut units = Vec::with_capacity(parsed.len());
+        // Modules with `#[cfg(...)]` items, whose diagnostics hold for this configuration only
+        let mut configured = Vec::with_capacity(parsed.len());
+        for result in parsed {
+            all_diagnostics.extend(result.diagnostics);
+            parse_times.push(result.duration);
+            let mut unit = result.result;
+            conditions::resolve_imports(&mut unit, target, &self.config.features)?;
+            configured.push(conditions::resolve_items(&mut unit, target, &self.config.features)?);
+            units.push(unit);
+        }
+        let configuration = conditions::active_configuration(target, &self.config.features);
+        // Sources come without their paths, so debug info names each file
+        // after its module, e.g. `Plugins/Math.x`
+        let mut source_map = SourceMap::new();
+        for (unit, source) in units.iter().zip(sources) {
+            let segments: Vec<&str> = unit.module.name.segments.iter().map(|segment| segment.as_str()).collect();
+            source_map.add_file(format!("{}.x", segments.join("/")), *source);
+        }
+        let source_map = Arc::new(source_map);
+        stages.push(Self::stage_profile(PipelineStage::Parse, total_start, parse_start));
 
         // Stage 2: Type Check
-        let check_result = self.run_typecheck_stage(&ast)?;
-        all_diagnostics.extend(check_result.diagnostics);
-        let check_time = check_result.duration;
+        self.check_cancelled(PipelineStage::TypeCheck)?;
+        profiling::reset_peak();
+        let check_start = Instant::now();
+        let mut check_times = vec![std::time::Duration::ZERO; units.len()];
+        let mut interfaces = Vec::new();
+        // Check results, kept only for the artifacts that show them
+        let mut checks: Vec<Option<x_checker::CheckResult>> = units.iter().map(|_| None).collect();
+        for wave in Self::check_waves(&units)? {
+            let checked = wave.par_iter()
+                .map(|&index| self.run_typecheck_stage(&units[index], &interfaces))
+                .collect::<Result<Vec<_>, _>>()?;
+            for (index, result) in wave.into_iter().zip(checked) {
+                let module = &units[index].module;
+                interfaces.push((module.name.to_string(), result.result.module_interface(module)));
+                let mut diagnostics = result.diagnostics;
+                if configured[index] {
+                    for diagnostic in &mut diagnostics {
+                        diagnostic.notes.push(format!("checked with cfg({configuration})"));
+                    }
+                }
+                all_diagnostics.extend(diagnostics);
+                check_times[index] = result.duration;
+                if self.emit.contains(&Artifact::TypedAst) {
+                    checks[index] = Some(result.result);
+                }
+            }
+        }
+        let check_time = check_start.elapsed();
+        stages.push(Self::stage_profile(PipelineStage::TypeCheck, total_start, check_start));
 
         // Stage 3: Optimize (optional)
-        let optimize_result = self.run_optimize_stage(&ast)?;
-        all_diagnostics.extend(optimize_result.diagnostics);
-        let optimized_ast = optimize_result.result;
+        self.check_cancelled(PipelineStage::Optimize)?;
+        profiling::reset_peak();
+        let optimize_start = Instant::now();
+        let mut optimized = Vec::with_capacity(units.len());
+        for ast in &units {
+            let optimize_result = self.run_optimize_stage(ast)?;
+            all_diagnostics.extend(optimize_result.diagnostics);
+            optimized.push(optimize_result.result);
+        }
+        let inlining = Arc::new(self.inline_candidates(&optimized)?);
+        stages.push(Self::stage_profile(PipelineStage::Optimize, total_start, optimize_start));
 
         // Stage 4: Code Generation
-        let codegen_result = self.run_codegen_stage(&optimized_ast, target, &output_dir)?;
-        all_diagnostics.extend(codegen_result.diagnostics);
-        let generated_files = codegen_result.result;
-        let codegen_time = codegen_result.duration;
+        self.check_cancelled(PipelineStage::CodeGen)?;
+        profiling::reset_peak();
+        let codegen_start = Instant::now();
+        let mut codegen_timings: Vec<CodegenTiming> = Vec::with_capacity(optimized.len());
+        let mut generated_files = HashMap::new();
+        let mut generated_binaries = HashMap::new();
+        let mut codegen_time = std::time::Duration::ZERO;
+        // The module each file came from; `None` once several modules produced it
+        let mut origins: HashMap<PathBuf, Option<FileOrigin>> = HashMap::new();
+        for ((ast, source), check) in optimized.iter().zip(sources).zip(&checks) {
+            let codegen_result = self.run_codegen_stage(ast, target, &output_dir, &inlining, &source_map)?;
+            if self.config.deterministic {
+                let rerun = self.run_codegen_stage(ast, target, &output_dir, &inlining, &source_map)?;
+                if let Some(file) = Self::first_difference(&codegen_result.result.0, &rerun.result.0) {
+                    return Err(CompilerError::Nondeterministic { module: ast.module.name.to_string(), file });
+                }
+            }
+            all_diagnostics.extend(codegen_result.diagnostics);
+            let ((mut files, binaries), metadata) = codegen_result.result;
+            files.extend(self.run_emit(ast, check.as_ref(), target)?);
+            codegen_timings.push((codegen_result.duration, metadata.passes, metadata.emit_time));
+            let origin = FileOrigin { module: ast.module.name.to_string(), source_hash: content_hash(source.as_bytes()) };
+            for path in files.keys().chain(binaries.keys()) {
+                origins.entry(path.clone())
+                    .and_modify(|shared| *shared = None)
+                    .or_insert_with(|| Some(origin.clone()));
+            }
+            generated_files.extend(files);
+            generated_binaries.extend(binaries);
+            codegen_time += codegen_result.duration;
+        }
+        stages.push(Self::stage_profile(PipelineStage::CodeGen, total_start, codegen_start));
 
         // Stage 5: Link (optional for some targets)
+        self.check_cancelled(PipelineStage::Link)?;
+        profiling::reset_peak();
+        let link_start = Instant::now();
         let link_result = self.run_link_stage(&generated_files, target)?;
         all_diagnostics.extend(link_result.diagnostics);
+        stages.push(Self::stage_profile(PipelineStage::Link, total_start, link_start));
 
         // Stage 6: Write files
+        self.check_cancelled(PipelineStage::Write)?;
+        profiling::reset_peak();
+        let write_start = Instant::now();
         let write_result = self.run_write_stage(generated_files, &output_dir)?;
         all_diagnostics.extend(write_result.diagnostics);
         let final_files = write_result.result;
+        let write_result = self.run_write_stage(generated_binaries, &output_dir)?;
+        all_diagnostics.extend(write_result.diagnostics);
+        let final_binaries = write_result.result;
+
+        let mut manifest = BuildManifest::new(target, &self.config);
+        let written = final_files.iter().map(|(path, content)| (path, content.as_bytes()))
+            .chain(final_binaries.iter().map(|(path, content)| (path, content.as_slice())));
+        for (path, content) in written {
+            let origin = origins.get(path).or_else(|| origins.get(path.strip_prefix(&output_dir).ok()?));
+            manifest.add_file(&output_dir, path, content, origin.and_then(Option::as_ref));
+        }
+        let manifest_file = HashMap::from([(PathBuf::from(MANIFEST_FILE), manifest.to_json())]);
+        all_diagnostics.extend(self.run_write_stage(manifest_file, &output_dir)?.diagnostics);
+        stages.push(Self::stage_profile(PipelineStage::Write, total_start, write_start));
 
         let total_time = total_start.elapsed();
 
         // Calculate metadata
-        let lines_of_code = source.lines().count();
-        let ast_nodes = self.count_ast_nodes(&ast);
-        let total_output_size = final_files.values().map(|content| content.len()).sum();
+        let lines_of_code = sources.iter().map(|source| source.lines().count()).sum();
+        let ast_nodes = units.iter().map(|ast| self.count_ast_nodes(ast)).sum();
+        let total_output_size = final_files.values().map(|content| content.len()).sum::<usize>()
+            + final_binaries.values().map(|content| content.len()).sum::<usize>();
+        let module_timings = units.iter()
+            .zip(parse_times.into_iter().zip(check_times).zip(codegen_timings))
+            .map(|(ast, ((parse_time, check_time), (codegen_time, passes, emit_time)))| ModuleTiming {
+                module: ast.module.name.to_string(),
+                parse_time,
+                check_time,
+                codegen_time,
+                passes,
+                emit_time,
+            })
+            .collect();
 
-        let generated_files_count = final_files.len();
+        let generated_files_count = final_files.len() + final_binaries.len();
         
         Ok(CompilationResult {
             target: target.to_string(),
             files: final_files,
+            binary_files: final_binaries,
             diagnostics: all_diagnostics,
             metadata: CompilationMetadata {
                 parse_time,
@@ -117,24 +308,126 @@ impl CompilationPipeline {
                 ast_nodes,
                 generated_files: generated_files_count,
                 total_output_size,
+                module_timings,
+                stages,
+                manifest,
             },
         })
     }
 
+    /// Profile of `stage`, started at `stage_start`, as it finishes
+    fn stage_profile(stage: PipelineStage, compile_start: Instant, stage_start: Instant) -> StageProfile {
+        StageProfile {
+            stage,
+            start: stage_start.duration_since(compile_start),
+            duration: stage_start.elapsed(),
+            peak_memory: profiling::peak_memory(),
+        }
+    }
+
+    /// The first path, in order, at which two code generation runs differ
+    fn first_difference(first: &GeneratedFiles, second: &GeneratedFiles) -> Option<PathBuf> {
+        let contents = |(files, binaries): &GeneratedFiles| -> std::collections::BTreeMap<PathBuf, Vec<u8>> {
+            files.iter().map(|(path, text)| (path.clone(), text.clone().into_bytes()))
+                .chain(binaries.iter().map(|(path, bytes)| (path.clone(), bytes.clone())))
+                .collect()
+        };
+        let (first, second) = (contents(first), contents(second));
+        first.keys().chain(second.keys())
+            .filter(|path| first.get(*path) != second.get(*path))
+            .min()
+            .cloned()
+    }
+
+    /// Group modules into waves that can be type checked independently
+    ///
+    /// Each wave holds indices into `units`; a module appears in the first
+    /// wave after all the project modules it imports.
+    fn check_waves(units: &[x_parser::CompilationUnit]) -> Result<Vec<Vec<usize>>, CompilerError> {
+        let mut by_name = HashMap::new();
+        for (index, unit) in units.iter().enumerate() {
+            if by_name.insert(&unit.module.name.segments, index).is_some() {
+                return Err(CompilerError::DuplicateModule { name: unit.module.name.to_string() });
+            }
+        }
+
+        let deps = |unit: &x_parser::CompilationUnit, lazy: bool| {
+            let mut deps: Vec<usize> = unit.module.imports.iter()
+                .filter(|import| (import.kind == ImportKind::Lazy) == lazy)
+                .filter_map(|import| by_name.get(&import.module_path.segments).copied())
+                .collect();
+            deps.sort_unstable();
+            deps.dedup();
+            deps
+        };
+        let mut remaining: Vec<Vec<usize>> = units.iter()
+            .map(|unit| {
+                let mut all = deps(unit, false);
+                all.extend(deps(unit, true));
+                all.sort_unstable();
+                all.dedup();
+                all
+            })
+            .collect();
+        // Modules imported only lazily, which may be checked later when
+        // checking them first is impossible
+        let lazy: Vec<Vec<usize>> = units.iter()
+            .map(|unit| {
+                let eager = deps(unit, false);
+                deps(unit, true).into_iter().filter(|dep| !eager.contains(dep)).collect()
+            })
+            .collect();
+
+        let mut done = vec![false; units.len()];
+        let mut waves = Vec::new();
+        while done.iter().any(|done| !done) {
+            let wave: Vec<usize> = (0..units.len())
+                .filter(|&index| !done[index] && remaining[index].iter().all(|&dep| done[dep]))
+                .collect();
+            if wave.is_empty() {
+                let mut deferred = false;
+                for index in (0..units.len()).filter(|&index| !done[index]) {
+                    let before = remaining[index].len();
+                    remaining[index].retain(|dep| !lazy[index].contains(dep));
+                    deferred |= remaining[index].len() < before;
+                }
+                if deferred {
+                    continue;
+                }
+                let modules = (0..units.len())
+                    .filter(|&index| !done[index])
+                    .map(|index| units[index].module.name.to_string())
+                    .collect();
+                return Err(CompilerError::CircularImports { modules });
+            }
+            for &index in &wave {
+                done[index] = true;
+                remaining[index].clear();
+            }
+            waves.push(wave);
+        }
+
+        Ok(waves)
+    }
+
     /// Run parse stage
     fn run_parse_stage(
         &self,
         source: &str,
+        file_id: FileId,
     ) -> Result<PipelineResult<x_parser::CompilationUnit>, CompilerError> {
         let start = Instant::now();
-        let file_id = FileId::new(0);
 
-        let parse_result = parse_with_metadata(source, file_id, self.config.syntax_style)?;
+        let ast = parse_source_cancellable(source, file_id, self.config.syntax_style, &self.cancellation)
+            .map_err(|e| match e {
+                ParseError::Cancelled => CompilerError::Cancelled { stage: PipelineStage::Parse },
+                e => CompilerError::Parse(e),
+            })?;
         let duration = start.elapsed();
 
         Ok(PipelineResult {
             stage: PipelineStage::Parse,
-            result: parse_result.ast,
+            result: ast,
             duration,
             diagnostics: Vec::new(), // TODO: Convert parse errors to diagnostics
         })
@@ -144,24 +437,23 @@ impl CompilationPipeline {
     fn run_typecheck_stage(
         &self,
         ast: &x_parser::CompilationUnit,
+        modules: &[(String, ModuleInterface)],
     ) -> Result<PipelineResult<x_checker::CheckResult>, CompilerError> {
         let start = Instant::now();
         
-        let check_result = type_check(ast);
+        let check_result = modules.iter()
+            .fold(crate::stdlib::checker(), |checker, (path, interface)| {
+                checker.with_module(path.clone(), interface.clone())
+            })
+            .with_cancellation(self.cancellation.clone())
+            .try_check_compilation_unit(ast)
+            .map_err(|_| CompilerError::Cancelled { stage: PipelineStage::TypeCheck })?;
         let duration = start.elapsed();
 
         let diagnostics = check_result.errors.iter()
-            .map(|error| CompilerDiagnostic {
-                severity: crate::backend::DiagnosticSeverity::Error,
-                message: format!("{error}"),
-                source: DiagnosticSource::TypeChecker,
-                span: None, // TODO: Extract span from type error
-            })
-            .chain(check_result.warnings.iter().map(|warning| CompilerDiagnostic {
-                severity: crate::backend::DiagnosticSeverity::Warning,
-                message: format!("{warning}"),
-                source: DiagnosticSource::TypeChecker,
-                span: None,
+            .map(|error| CompilerDiagnostic::from_type_error(error, crate::backend::DiagnosticSeverity::Error))
+            .chain(check_result.warnings.iter().map(|warning| {
+                CompilerDiagnostic::from_type_error(warning, crate::backend::DiagnosticSeverity::Warning)
             }))
             .collect();
 
@@ -193,19 +485,46 @@ impl CompilationPipeline {
         })
     }
 
+    /// The small public functions of `units` other modules may inline,
+    /// when optimizing a project of several modules
+    fn inline_candidates(&self, units: &[x_parser::CompilationUnit]) -> Result<InlineCandidates, CompilerError> {
+        let mut candidates = InlineCandidates::new();
+        if self.config.optimization_level == 0 || units.len() < 2 {
+            return Ok(candidates);
+        }
+        let passes = self.config.pass_manager().map_err(|error| CompilerError::Config { message: error.to_string() })?;
+        for unit in units {
+            // Modules the IR cannot express are left to the backends to report
+            let Ok(mut ir) = IRBuilder::new().with_passes(passes.clone()).build_ir(unit) else {
+                continue;
+            };
+            for module in &mut ir.modules {
+                // Imports name modules by their whole path
+                module.name = Symbol::intern(&unit.module.name.to_string());
+                candidates.add_module(module);
+            }
+        }
+        Ok(candidates)
+    }
+
     /// Run code generation stage
     fn run_codegen_stage(
         &self,
         ast: &x_parser::CompilationUnit,
         target: &str,
         output_dir: &PathBuf,
-    ) -> Result<PipelineResult<HashMap<PathBuf, String>>, CompilerError> {
+        inlining: &Arc<InlineCandidates>,
+        sources: &Arc<SourceMap>,
+    ) -> Result<PipelineResult<(GeneratedFiles, CodegenMetadata)>, CompilerError> {
         let start = Instant::now();
 
-        let mut backend = BackendFactory::create_backend(target)
-            .map_err(|_| CompilerError::InvalidTarget { target: target.to_string() })?;
-
         let target_config = self.config.target_config(target);
+        let mut backend = BackendFactory::create_configured_backend(target, &target_config)
+            .map_err(|error| match error {
+                CompilerError::Config { .. } => error,
+                _ => CompilerError::InvalidTarget { target: target.to_string() },
+            })?;
+
         let compilation_target = self.create_compilation_target(target, &target_config)?;
 
         let codegen_options = CodegenOptions {
@@ -215,10 +534,18 @@ impl CompilationPipeline {
             debug_info: self.config.debug_info,
             optimization_level: self.config.optimization_level,
             emit_types: self.config.emit_types,
+            naming: target_config.output_naming().map_err(|error| CompilerError::Config { message: error.to_string() })?,
+            cancellation: self.cancellation.clone(),
+            passes: self.config.pass_manager().map_err(|error| CompilerError::Config { message: error.to_string() })?,
+            inlining: inlining.clone(),
+            sources: sources.clone(),
         };
 
         let codegen_result = backend.generate_code(ast, &HashMap::new(), &codegen_options)
-            .map_err(|e| CompilerError::CodeGen { message: format!("{e:?}") })?;
+            .map_err(|e| match e {
+                CompilerError::Cancelled { .. } => e,
+                e => CompilerError::CodeGen { message: format!("{e:?}") },
+            })?;
 
         let duration = start.elapsed();
 
@@ -227,18 +554,42 @@ impl CompilationPipeline {
                 severity: diag.severity,
                 message: diag.message,
                 source: DiagnosticSource::CodeGenerator,
+                code: Some(diag.code),
                 span: diag.location,
+                labels: Vec::new(),
+                notes: Vec::new(),
+                suggested_fix: None,
             })
             .collect();
 
+        // Backends place their files under the output directory; the write
+        // stage does that for every file, so take it off again
+        let files = relative_to(codegen_result.files, output_dir);
+        let binary_files = relative_to(codegen_result.binary_files, output_dir);
         Ok(PipelineResult {
             stage: PipelineStage::CodeGen,
-            result: codegen_result.files,
+            result: ((files, binary_files), codegen_result.metadata),
             duration,
             diagnostics,
         })
     }
 
+    /// The artifacts requested for `ast`, by path relative to the output directory
+    fn run_emit(
+        &self,
+        ast: &x_parser::CompilationUnit,
+        check: Option<&x_checker::CheckResult>,
+        target: &str,
+    ) -> Result<HashMap<PathBuf, String>, CompilerError> {
+        let naming = self.config.target_config(target).output_naming()
+            .map_err(|error| CompilerError::Config { message: error.to_string() })?;
+        let passes = self.config.pass_manager().map_err(|error| CompilerError::Config { message: error.to_string() })?;
+        let module = ast.module.name.to_string();
+        self.emit.iter()
+            .map(|artifact| Ok((naming.module_file(&module, artifact.extension()), artifact.render(ast, check, &passes)?)))
+            .collect()
+    }
+
     /// Run linking stage
     fn run_link_stage(
         &self,
@@ -260,11 +611,11 @@ impl CompilationPipeline {
     }
 
     /// Run file writing stage
-    fn run_write_stage(
+    fn run_write_stage<C: AsRef<[u8]>>(
         &self,
-        files: HashMap<PathBuf, String>,
+        files: HashMap<PathBuf, C>,
         output_dir: &PathBuf,
-    ) -> Result<PipelineResult<HashMap<PathBuf, String>>, CompilerError> {
+    ) -> Result<PipelineResult<HashMap<PathBuf, C>>, CompilerError> {
         let start = Instant::now();
         let mut written_files = HashMap::new();
         let mut diagnostics = Vec::new();
@@ -275,7 +626,11 @@ impl CompilationPipeline {
                 severity: crate::backend::DiagnosticSeverity::Error,
                 message: format!("Failed to create output directory {}: {}", output_dir.display(), e),
                 source: DiagnosticSource::Linker,
+                code: None,
                 span: None,
+                labels: Vec::new(),
+                notes: Vec::new(),
+                suggested_fix: None,
             });
         }
 
@@ -292,7 +647,11 @@ impl CompilationPipeline {
                         severity: crate::backend::DiagnosticSeverity::Error,
                         message: format!("Failed to create directory {}: {}", parent.display(), e),
                         source: DiagnosticSource::Linker,
+                        code: None,
                         span: None,
+                        labels: Vec::new(),
+                        notes: Vec::new(),
+                        suggested_fix: None,
                     });
                     continue;
                 }
@@ -307,7 +666,11 @@ impl CompilationPipeline {
                         severity: crate::backend::DiagnosticSeverity::Error,
                         message: format!("Failed to write file {}: {}", full_path.display(), e),
                         source: DiagnosticSource::Linker,
+                        code: None,
                         span: None,
+                        labels: Vec::new(),
+                        notes: Vec::new(),
+                        suggested_fix: None,
                     });
                 }
             }
@@ -327,10 +690,14 @@ impl CompilationPipeline {
     fn create_compilation_target(
         &self,
         target_name: &str,
-        _target_config: &crate::config::TargetConfig,
+        target_config: &crate::config::TargetConfig,
     ) -> Result<CompilationTarget, CompilerError> {
         let file_extension = match target_name {
             "typescript" | "ts" => "ts",
+            "javascript" | "js" => match target_config.get_string("module_system") {
+                Some(name) if TypeScriptModuleSystem::from_name(name) == Some(TypeScriptModuleSystem::CommonJS) => "cjs",
+                _ => "mjs",
+            },
             "wasm-gc" | "wasm" => "wasm",
             "wasm-component" | "component" => "wasm",
             "wit" => "wit",
@@ -410,6 +777,16 @@ impl CompilationPipeline {
     }
 }
 
+/// `files` by their paths relative to `output_dir`, those outside it as they are
+fn relative_to<C>(files: HashMap<PathBuf, C>, output_dir: &Path) -> HashMap<PathBuf, C> {
+    files.into_iter()
+        .map(|(path, content)| match path.strip_prefix(output_dir) {
+            Ok(relative) => (relative.to_path_buf(), content),
+            Err(_) => (path, content),
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -478,4 +855,283 @@ mod tests {
         // Should not panic, though may have errors due to incomplete implementation
         println!("Pipeline result: {:?}", result.is_ok());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_cancelled_compilation() {
+        let temp_dir = TempDir::new().unwrap();
+        let token = CancellationToken::new();
+        let mut pipeline = CompilationPipeline::new(CompilerConfig::default())
+            .with_cancellation(token.clone());
+        
+        token.cancel();
+        let result = pipeline.compile("module Test\nlet x = 42", "typescript", temp_dir.path().to_path_buf());
+        
+        match result {
+            Err(CompilerError::Cancelled { stage }) => assert_eq!(stage, PipelineStage::Parse),
+            other => panic!("expected cancellation, got {other:?}"),
+        }
+        assert!(std::fs::read_dir(temp_dir.path()).unwrap().next().is_none());
+
+        let mut pipeline = CompilationPipeline::new(CompilerConfig::default())
+            .with_timeout(std::time::Duration::ZERO);
+        let result = pipeline.compile("module Test\nlet x = 42", "typescript", temp_dir.path().to_path_buf());
+        assert!(result.is_err_and(|error| error.is_cancelled()));
+        assert!(pipeline.cancellation_token().is_timed_out());
+    }
+
+    #[test]
+    fn test_output_layout_places_module_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = CompilerConfig::default();
+        config.set_target_option("javascript", "layout", "source-tree".into());
+        let mut pipeline = CompilationPipeline::new(config);
+
+        let sources = ["module Data.List\nlet size = 1", "module App\nlet main = fun u -> print \"hi\""];
+        let result = pipeline.compile_project(&sources, "javascript", temp_dir.path().to_path_buf()).unwrap();
+        let list = temp_dir.path().join("Data").join("List.mjs");
+        assert!(result.files.contains_key(&list), "{:?}", result.files.keys());
+        assert!(result.files[&list].contains("from \"../runtime.mjs\""), "{}", result.files[&list]);
+        assert!(result.files[&temp_dir.path().join("App.mjs")].contains("from \"./runtime.mjs\""));
+        assert!(result.files.contains_key(&temp_dir.path().join("runtime.mjs")));
+
+        let mut config = CompilerConfig::default();
+        config.set_target_option("wasm-gc", "file_name", "{name}.wat".into());
+        let error = CompilationPipeline::new(config).compile("module A\nlet a = 1", "wasm-gc", temp_dir.path().to_path_buf()).unwrap_err();
+        assert!(matches!(error, CompilerError::Config { .. }), "{error}");
+    }
+
+    #[test]
+    fn test_emitted_artifacts_sit_beside_module_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = CompilerConfig::default();
+        config.set_target_option("javascript", "layout", "source-tree".into());
+        let mut pipeline = CompilationPipeline::new(config).with_emit(Artifact::ALL.to_vec());
+
+        let sources = ["module Data.List\nlet size = 1", "module App\nlet main = fun u -> u"];
+        let result = pipeline.compile_project(&sources, "javascript", temp_dir.path().to_path_buf()).unwrap();
+        let ir = &result.files[&temp_dir.path().join("Data").join("List.ir")];
+        assert_eq!(ir, "(module Data.List (const size Unit 1))\n");
+        let typed = &result.files[&temp_dir.path().join("App.typed.ast")];
+        assert!(typed.starts_with("(: main \"a0 -> a0 / e0\" \"{}\")\n(compilation-unit (module App"), "{typed}");
+        assert!(result.files.contains_key(&temp_dir.path().join("App.ast")));
+        assert_eq!(result.metadata.manifest.files["Data/List.ir"].module.as_deref(), Some("Data.List"));
+    }
+
+    #[test]
+    fn test_relative_output_dir() {
+        // A directory under the working directory, named relative to it
+        let temp_dir = TempDir::new_in(".").unwrap();
+        let output_dir = PathBuf::from(temp_dir.path().file_name().unwrap());
+        let result = CompilationPipeline::new(CompilerConfig::default())
+            .compile("module Demo\nlet x = 42", "typescript", output_dir.clone())
+            .unwrap();
+
+        let module = output_dir.join("Demo.ts");
+        assert!(result.files.contains_key(&module), "{:?}", result.files.keys());
+        assert!(module.is_file());
+        assert!(!output_dir.join(&output_dir).exists());
+        let manifest = &result.metadata.manifest;
+        assert!(manifest.files.contains_key("Demo.ts"), "{:?}", manifest.files.keys());
+        assert_eq!(&BuildManifest::read(&output_dir).unwrap(), manifest);
+        assert!(manifest.verify(&output_dir).is_empty());
+    }
+
+    #[test]
+    fn test_build_manifest_hashes_written_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let sources = ["module Util\nlet size = 1", "module App\nlet main = fun u -> print \"hi\""];
+        let result = CompilationPipeline::new(CompilerConfig::default())
+            .compile_project(&sources, "javascript", temp_dir.path().to_path_buf())
+            .unwrap();
+        let manifest = &result.metadata.manifest;
+        assert_eq!(&BuildManifest::read(temp_dir.path()).unwrap(), manifest);
+        assert_eq!(manifest.files.len(), result.files.len());
+        assert!(manifest.verify(temp_dir.path()).is_empty());
+
+        let util = &manifest.files["Util.mjs"];
+        assert_eq!(util.module.as_deref(), Some("Util"));
+        assert_eq!(util.source_hash, Some(content_hash(sources[0].as_bytes())));
+        assert_eq!(manifest.files["runtime.mjs"].module, None);
+
+        // The same build gives the same manifest
+        let again = CompilationPipeline::new(CompilerConfig::default())
+            .compile_project(&sources, "javascript", temp_dir.path().to_path_buf())
+            .unwrap();
+        assert_eq!(again.metadata.manifest, *manifest);
+    }
+
+    #[test]
+    fn test_profile_covers_stages_and_passes() {
+        let temp_dir = TempDir::new().unwrap();
+        let result = CompilationPipeline::new(CompilerConfig::default())
+            .compile("module Main\nlet one = 1", "typescript", temp_dir.path().to_path_buf())
+            .unwrap();
+        let stages: Vec<PipelineStage> = result.metadata.stages.iter().map(|stage| stage.stage).collect();
+        assert_eq!(stages, [
+            PipelineStage::Parse, PipelineStage::TypeCheck, PipelineStage::Optimize,
+            PipelineStage::CodeGen, PipelineStage::Link, PipelineStage::Write,
+        ]);
+        let passes: Vec<&str> = result.metadata.module_timings[0].passes.iter().map(|pass| pass.name).collect();
+        assert_eq!(passes, ["lower", "flatten-pipelines", "local-effects", "normalize", "verify"]);
+
+        let names: Vec<String> = profiling::spans(&result.metadata).into_iter()
+            .map(|span| format!("{}{}", "  ".repeat(span.depth), span.name))
+            .collect();
+        assert_eq!(names[..7], ["compile", "  parse", "  type-check", "  optimize", "  codegen", "    Main", "      lower"]);
+        assert_eq!(names.last().map(String::as_str), Some("  write"));
+    }
+
+    #[test]
+    fn test_deterministic_codegen() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = CompilerConfig { deterministic: true, ..CompilerConfig::default() };
+        let source = "module Shapes\ndata Shape = Circle Float | Rect Float Float\ntype Point = {x: Float, y: Float, z: Float}\npub let origin = {x = 0.0, y = 0.0, z = 0.0}";
+        for target in BackendFactory::available_backends() {
+            let first = CompilationPipeline::new(config.clone()).compile(source, target, temp_dir.path().to_path_buf()).unwrap();
+            let second = CompilationPipeline::new(config.clone()).compile(source, target, temp_dir.path().to_path_buf()).unwrap();
+            assert_eq!(first.metadata.manifest, second.metadata.manifest, "{target}");
+        }
+
+        let path = PathBuf::from("out/Main.js");
+        let files: GeneratedFiles = (HashMap::from([(path.clone(), "a".to_string())]), HashMap::new());
+        assert_eq!(CompilationPipeline::first_difference(&files, &files.clone()), None);
+        let changed = (HashMap::from([(path.clone(), "b".to_string())]), HashMap::new());
+        assert_eq!(CompilationPipeline::first_difference(&files, &changed), Some(path));
+        let extra = (files.0.clone(), HashMap::from([(PathBuf::from("out/Main.wasm"), vec![0])]));
+        assert_eq!(CompilationPipeline::first_difference(&files, &extra), Some(PathBuf::from("out/Main.wasm")));
+    }
+
+    #[test]
+    fn test_project_check_waves_follow_imports() {
+        use x_parser::{parse_source, FileId, SyntaxStyle};
+        let units: Vec<_> = [
+            "module App\nimport Util\nimport Core\nlet main = 1",
+            "module Util\nimport Core\nlet helper = 2",
+            "module Core\nimport Data.List\nlet base = 3",
+            "module Other\nlet other = 4",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(index, source)| parse_source(source, FileId::new(index as u32), SyntaxStyle::SExpression).unwrap())
+        .collect();
+
+        let waves = CompilationPipeline::check_waves(&units).unwrap();
+        assert_eq!(waves, vec![vec![2, 3], vec![1], vec![0]]);
+    }
+
+    #[test]
+    fn test_project_rejects_circular_imports() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut pipeline = CompilationPipeline::new(CompilerConfig::default());
+
+        let sources = ["module A\nimport B\nlet a = 1", "module B\nimport A\nlet b = 2"];
+        match pipeline.compile_project(&sources, "typescript", temp_dir.path().to_path_buf()) {
+            Err(CompilerError::CircularImports { modules }) => assert_eq!(modules, ["A", "B"]),
+            other => panic!("expected circular import error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_project_lazy_imports_break_cycles() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut pipeline = CompilationPipeline::new(CompilerConfig::default());
+
+        let sources = ["module A\nlazy import B\nlet a = B.b", "module B\nimport A\npub let b = 2"];
+        let result = pipeline.compile_project(&sources, "typescript", temp_dir.path().to_path_buf()).unwrap();
+
+        let modules: Vec<&str> = result.metadata.module_timings.iter()
+            .map(|timing| timing.module.as_str())
+            .collect();
+        assert_eq!(modules, ["A", "B"]);
+        let codes: Vec<&str> = result.diagnostics.iter()
+            .filter_map(|diagnostic| diagnostic.code)
+            .collect();
+        assert_eq!(codes, ["W0101"]);
+    }
+
+    #[test]
+    fn test_project_conditional_imports_follow_target() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = CompilerConfig { features: vec!["node".to_string()], ..CompilerConfig::default() };
+        let mut pipeline = CompilationPipeline::new(config);
+
+        let sources = ["module Main\nimport Fs when target == \"typescript\" && feature \"node\"\nlet main = 1"];
+        let result = pipeline.compile_project(&sources, "javascript", temp_dir.path().to_path_buf()).unwrap();
+        assert!(result.files.values().any(|file| file.contains("import * as Fs from \"Fs\";")));
+
+        let result = pipeline.compile_project(&sources, "wasm-gc", temp_dir.path().to_path_buf()).unwrap();
+        assert!(result.files.values().all(|file| !file.contains("Fs")));
+    }
+
+    #[test]
+    fn test_project_cfg_items_follow_target() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut pipeline = CompilationPipeline::new(CompilerConfig::default());
+
+        let sources = ["module Main\n\
+            #[cfg(target = \"typescript\")]\nlet greeting = \"hi\" ^ 1\n\
+            #[cfg(not(target = \"typescript\"))]\nlet greeting = \"hi\"\n\
+            let main = greeting"];
+        let mut type_errors = |target: &str| {
+            let result = pipeline.compile_project(&sources, target, temp_dir.path().to_path_buf()).unwrap();
+            result.diagnostics.into_iter()
+                .filter(|diagnostic| matches!(diagnostic.source, DiagnosticSource::TypeChecker))
+                .collect::<Vec<_>>()
+        };
+        let errors = type_errors("typescript");
+        assert!(errors[0].message.contains("Cannot unify String with Int"), "{errors:?}");
+        for error in &errors {
+            assert_eq!(error.notes.last().map(String::as_str), Some("checked with cfg(target = \"typescript\")"));
+        }
+        assert!(type_errors("wasm-gc").is_empty());
+    }
+
+    #[test]
+    fn test_optimized_projects_inline_across_modules() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = CompilerConfig { optimization_level: 1, debug_info: true, ..CompilerConfig::default() };
+        let mut pipeline = CompilationPipeline::new(config);
+
+        let sources = ["module Main\nimport Lib\npub let main = fun n -> Lib.double n", "module Lib\npub let double = fun x -> x + x"];
+        let result = pipeline.compile_project(&sources, "typescript", temp_dir.path().to_path_buf()).unwrap();
+        let main = result.files.iter()
+            .find(|(path, _)| path.file_stem().is_some_and(|stem| stem == "Main"))
+            .map(|(_, code)| code)
+            .unwrap();
+        assert!(main.contains("// inlines Lib.double\n"), "{main}");
+        assert!(!main.contains("Lib.double("), "{main}");
+        let timings = &result.metadata.module_timings[0].passes;
+        assert!(timings.iter().any(|timing| timing.name == "inline"));
+    }
+
+    #[test]
+    fn test_project_records_module_timings() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut pipeline = CompilationPipeline::new(CompilerConfig::default());
+
+        let sources = ["module Main\nimport Lib\nlet main = 1", "module Lib\nlet lib = 2"];
+        let result = pipeline.compile_project(&sources, "typescript", temp_dir.path().to_path_buf()).unwrap();
+
+        let modules: Vec<&str> = result.metadata.module_timings.iter()
+            .map(|timing| timing.module.as_str())
+            .collect();
+        assert_eq!(modules, ["Main", "Lib"]);
+        assert_eq!(result.metadata.lines_of_code, 5);
+    }
+
+    #[test]
+    fn test_project_modules_see_imported_types() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut pipeline = CompilationPipeline::new(CompilerConfig::default());
+
+        let sources = ["module Main\nimport Lib\nlet main = Lib.lib ^ \"!\"", "module Lib\npub let lib = 2"];
+        let result = pipeline.compile_project(&sources, "typescript", temp_dir.path().to_path_buf()).unwrap();
+
+        let errors: Vec<&str> = result.diagnostics.iter()
+            .filter(|diagnostic| matches!(diagnostic.source, DiagnosticSource::TypeChecker))
+            .map(|diagnostic| diagnostic.message.as_str())
+            .collect();
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        assert!(errors[0].contains("String") && errors[0].contains("Int"));
+    }
+}
@@ -0,0 +1,242 @@
+//! Timing and memory profiles of compilations
+//!
+//! Every compilation records how long each pipeline stage took, and for
+//! each module how long code generation spent in each optimizer pass and in
+//! emitting target code. Peak memory is only known to programs that install
+//! `TrackingAllocator` as their global allocator, as the `x` binary does:
+//!
+//! ```ignore
+//! #[global_allocator]
+//! static ALLOCATOR: x_compiler::profiling::TrackingAllocator = x_compiler::profiling::TrackingAllocator;
+//! ```
+//!
+//! `spans` lays a compilation's metadata out as nested spans, which
+//! `render_flame` prints as a flame-style breakdown and `chrome_trace`
+//! turns into the JSON that `chrome://tracing` and Perfetto load.
+
+use serde_json::{json, Value};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use crate::pipeline::PipelineStage;
+use crate::CompilationMetadata;
+
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+/// The system allocator, counting the bytes in use and their peak
+pub struct TrackingAllocator;
+
+unsafe impl GlobalAlloc for TrackingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let pointer = System.alloc(layout);
+        if !pointer.is_null() {
+            record_alloc(layout.size());
+        }
+        pointer
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let pointer = System.alloc_zeroed(layout);
+        if !pointer.is_null() {
+            record_alloc(layout.size());
+        }
+        pointer
+    }
+
+    unsafe fn dealloc(&self, pointer: *mut u8, layout: Layout) {
+        System.dealloc(pointer, layout);
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, pointer: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_pointer = System.realloc(pointer, layout, new_size);
+        if !new_pointer.is_null() {
+            CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+            record_alloc(new_size);
+        }
+        new_pointer
+    }
+}
+
+fn record_alloc(size: usize) {
+    INSTALLED.store(true, Ordering::Relaxed);
+    let current = CURRENT.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK.fetch_max(current, Ordering::Relaxed);
+}
+
+/// Start a new peak at the bytes in use now
+pub fn reset_peak() {
+    PEAK.store(CURRENT.load(Ordering::Relaxed), Ordering::Relaxed);
+}
+
+/// Most bytes in use since the last `reset_peak`, if `TrackingAllocator`
+/// is the global allocator
+pub fn peak_memory() -> Option<usize> {
+    INSTALLED.load(Ordering::Relaxed).then(|| PEAK.load(Ordering::Relaxed))
+}
+
+/// Time and peak memory of one pipeline stage
+#[derive(Debug, Clone)]
+pub struct StageProfile {
+    pub stage: PipelineStage,
+    /// Offset of the stage's start from the start of the compilation
+    pub start: Duration,
+    pub duration: Duration,
+    pub peak_memory: Option<usize>,
+}
+
+/// Time spent in one pass over a module's IR
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PassTiming {
+    pub name: &'static str,
+    pub duration: Duration,
+}
+
+/// A named interval of a compilation, inside the spans of smaller depth
+/// before it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileSpan {
+    pub name: String,
+    pub depth: usize,
+    pub start: Duration,
+    pub duration: Duration,
+    pub peak_memory: Option<usize>,
+}
+
+/// The stages of a compilation, with the code generation of each module
+/// and its passes nested in the code generation stage
+pub fn spans(metadata: &CompilationMetadata) -> Vec<ProfileSpan> {
+    let span = |name: String, depth, start, duration, peak_memory| ProfileSpan { name, depth, start, duration, peak_memory };
+    let mut spans = vec![span("compile".to_string(), 0, Duration::ZERO, metadata.total_time, None)];
+    for stage in &metadata.stages {
+        spans.push(span(stage_name(stage.stage).to_string(), 1, stage.start, stage.duration, stage.peak_memory));
+        if stage.stage != PipelineStage::CodeGen {
+            continue;
+        }
+        // Modules are generated one after another, and so are their passes
+        let mut start = stage.start;
+        for module in &metadata.module_timings {
+            spans.push(span(module.module.clone(), 2, start, module.codegen_time, None));
+            let mut pass_start = start;
+            for pass in &module.passes {
+                spans.push(span(pass.name.to_string(), 3, pass_start, pass.duration, None));
+                pass_start += pass.duration;
+            }
+            spans.push(span("emit".to_string(), 3, pass_start, module.emit_time, None));
+            start += module.codegen_time;
+        }
+    }
+    spans
+}
+
+fn stage_name(stage: PipelineStage) -> &'static str {
+    match stage {
+        PipelineStage::Parse => "parse",
+        PipelineStage::TypeCheck => "type-check",
+        PipelineStage::Optimize => "optimize",
+        PipelineStage::CodeGen => "codegen",
+        PipelineStage::Link => "link",
+        PipelineStage::Write => "write",
+    }
+}
+
+/// One line per span, indented by depth, with a bar `width` characters
+/// wide for the whole compilation
+pub fn render_flame(spans: &[ProfileSpan], width: usize) -> String {
+    let total = spans.iter().map(|span| span.start + span.duration).max().unwrap_or_default().as_secs_f64();
+    let name_width = spans.iter().map(|span| span.depth * 2 + span.name.len()).max().unwrap_or(0);
+    let mut out = String::new();
+    for span in spans {
+        let share = if total > 0.0 { span.duration.as_secs_f64() / total } else { 0.0 };
+        let offset = if total > 0.0 { (span.start.as_secs_f64() / total * width as f64) as usize } else { 0 };
+        // Every span gets at least one block, however short
+        let offset = offset.min(width.saturating_sub(1));
+        let length = ((share * width as f64).round() as usize).clamp(1, width - offset);
+        let bar = format!("{}{}", " ".repeat(offset), "█".repeat(length));
+        let name = format!("{}{}", "  ".repeat(span.depth), span.name);
+        let mut line = format!("{name:<name_width$}  {:>10}  {:>5.1}%  {bar:<width$}", format_duration(span.duration), share * 100.0);
+        if let Some(peak) = span.peak_memory {
+            let _ = write!(line, "  peak {}", format_bytes(peak));
+        }
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// Spans as Chrome trace events, in microseconds
+pub fn chrome_trace(spans: &[ProfileSpan]) -> Value {
+    let events: Vec<Value> = spans.iter()
+        .map(|span| {
+            let mut event = json!({
+                "name": span.name,
+                "cat": "compile",
+                "ph": "X",
+                "ts": span.start.as_secs_f64() * 1e6,
+                "dur": span.duration.as_secs_f64() * 1e6,
+                "pid": std::process::id(),
+                "tid": 0,
+            });
+            if let Some(peak) = span.peak_memory {
+                event["args"] = json!({ "peak_memory": peak });
+            }
+            event
+        })
+        .collect();
+    json!({ "traceEvents": events, "displayTimeUnit": "ms" })
+}
+
+fn format_duration(duration: Duration) -> String {
+    let micros = duration.as_secs_f64() * 1e6;
+    if micros >= 1e6 {
+        format!("{:.2}s", micros / 1e6)
+    } else if micros >= 1e3 {
+        format!("{:.2}ms", micros / 1e3)
+    } else {
+        format!("{micros:.0}µs")
+    }
+}
+
+fn format_bytes(bytes: usize) -> String {
+    match bytes {
+        0..1024 => format!("{bytes} B"),
+        1024..1_048_576 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(name: &str, depth: usize, start: u64, duration: u64) -> ProfileSpan {
+        ProfileSpan {
+            name: name.to_string(),
+            depth,
+            start: Duration::from_millis(start),
+            duration: Duration::from_millis(duration),
+            peak_memory: None,
+        }
+    }
+
+    #[test]
+    fn test_flame_and_trace() {
+        let mut spans = vec![span("compile", 0, 0, 10), span("parse", 1, 0, 5), span("codegen", 1, 5, 5)];
+        spans[1].peak_memory = Some(3 * 1024 * 1024);
+        let flame = render_flame(&spans, 10);
+        let lines: Vec<&str> = flame.lines().collect();
+        assert!(lines[0].starts_with("compile") && lines[0].contains("10.00ms") && lines[0].contains("100.0%"), "{flame}");
+        assert!(lines[1].starts_with("  parse") && lines[1].contains("█████     ") && lines[1].ends_with("peak 3.0 MiB"), "{flame}");
+        assert!(lines[2].contains("      █████"), "{flame}");
+
+        let trace = chrome_trace(&spans);
+        let events = trace["traceEvents"].as_array().unwrap();
+        assert_eq!(events.len(), 3);
+        assert_eq!((events[2]["ts"].as_f64(), events[2]["dur"].as_f64()), (Some(5000.0), Some(5000.0)));
+        assert_eq!(events[1]["args"]["peak_memory"], 3 * 1024 * 1024);
+    }
+}
@@ -0,0 +1,228 @@
+//! Runtime representation of values in each backend
+//!
+//! Every backend lays values out the same way wherever they come from, so
+//! generated modules and their runtimes can pass values to each other.
+//! [`REPRESENTATIONS`] lists, for each kind of value, its representation:
+//!
+//! | Kind     | TypeScript                          | WebAssembly GC                              | Component                        |
+//! |----------|-------------------------------------|---------------------------------------------|----------------------------------|
+//! | Int      | `number`                            | `$int` struct of an `i64`                   | `i64`                            |
+//! | Float    | `number`                            | `$float` struct of an `f64`                 | `f64`                            |
+//! | Bool     | `boolean`                           | `i31ref` of 0 or 1                          | `i32` of 0 or 1                  |
+//! | Unit     | `undefined`                         | null                                        | no value                         |
+//! | String   | `string`                            | not supported yet                           | pointer and length of UTF-8      |
+//! | Tuple    | array                               | `$array` of `anyref`                        | not exported                     |
+//! | Record   | object                              | `$record` of field ids and an `$array`      | fields flattened in name order   |
+//! | Data     | `{ tag: "Name", _0, _1, ... }`      | struct extending `$data`, tag field first   | not exported                     |
+//! | Closure  | function                            | `$closure` of a function and `$env`         | not exported                     |
+//!
+//! In TypeScript data values are tagged unions discriminated on their
+//! constructor's name. In WebAssembly GC every slot holds an `anyref`, so
+//! numbers are boxed, and data values are GC structs whose tag is the
+//! constructor's index in its type. Components pass values flattened by the
+//! canonical ABI, as [`component_binary`](crate::component_binary) explains.
+//!
+//! The `unbox` [pass](crate::passes) finds the locals that always hold an
+//! [`Unboxed`] scalar. The WebAssembly GC backend keeps them in locals of the
+//! scalar's core type and computes operators on them with core instructions,
+//! boxing a value only where it leaves the function's arithmetic.
+
+use crate::ir::{IRLiteral, IRPrimitiveType, IRType};
+
+/// A kind of value with a representation of its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ValueKind {
+    Int,
+    Float,
+    Bool,
+    Unit,
+    String,
+    Tuple,
+    Record,
+    Data,
+    Closure,
+}
+
+/// How the values of a kind are represented by each backend
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Representation {
+    pub kind: ValueKind,
+    pub typescript: &'static str,
+    pub wasm_gc: &'static str,
+    pub wasm_component: &'static str,
+}
+
+/// The representation of every kind of value
+pub const REPRESENTATIONS: &[Representation] = &[
+    Representation { kind: ValueKind::Int, typescript: "number", wasm_gc: "(ref $int)", wasm_component: "i64" },
+    Representation { kind: ValueKind::Float, typescript: "number", wasm_gc: "(ref $float)", wasm_component: "f64" },
+    Representation { kind: ValueKind::Bool, typescript: "boolean", wasm_gc: "(ref i31)", wasm_component: "i32" },
+    Representation { kind: ValueKind::Unit, typescript: "undefined", wasm_gc: "nullref", wasm_component: "" },
+    Representation { kind: ValueKind::String, typescript: "string", wasm_gc: "", wasm_component: "i32 i32" },
+    Representation { kind: ValueKind::Tuple, typescript: "Array", wasm_gc: "(ref $array)", wasm_component: "" },
+    Representation { kind: ValueKind::Record, typescript: "object", wasm_gc: "(ref $record)", wasm_component: "" },
+    Representation { kind: ValueKind::Data, typescript: "{ tag: string }", wasm_gc: "(ref $data)", wasm_component: "" },
+    Representation { kind: ValueKind::Closure, typescript: "Function", wasm_gc: "(ref $closure)", wasm_component: "" },
+];
+
+/// The representation of `kind`
+pub fn representation(kind: ValueKind) -> &'static Representation {
+    REPRESENTATIONS.iter()
+        .find(|representation| representation.kind == kind)
+        .expect("every kind has a representation")
+}
+
+/// A scalar the WebAssembly backends may hold without a box
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Unboxed {
+    Int,
+    Float,
+    Bool,
+}
+
+/// An operator taking and giving scalars of one kind, and the core
+/// instruction computing it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Operator {
+    pub scalar: Unboxed,
+    pub instruction: &'static str,
+}
+
+impl Unboxed {
+    /// The scalar a type hint names, if any
+    pub fn of_type(typ: &IRType) -> Option<Self> {
+        match typ {
+            IRType::Primitive(IRPrimitiveType::Int) => Some(Unboxed::Int),
+            IRType::Primitive(IRPrimitiveType::Float) => Some(Unboxed::Float),
+            IRType::Primitive(IRPrimitiveType::Bool) => Some(Unboxed::Bool),
+            _ => None,
+        }
+    }
+
+    /// The scalar a literal is, if any
+    pub fn of_literal(literal: &IRLiteral) -> Option<Self> {
+        match literal {
+            IRLiteral::Integer(_) => Some(Unboxed::Int),
+            IRLiteral::Float(_) => Some(Unboxed::Float),
+            IRLiteral::Boolean(_) => Some(Unboxed::Bool),
+            _ => None,
+        }
+    }
+
+    /// The type hint naming this scalar
+    pub fn ir_type(self) -> IRType {
+        IRType::Primitive(match self {
+            Unboxed::Int => IRPrimitiveType::Int,
+            Unboxed::Float => IRPrimitiveType::Float,
+            Unboxed::Bool => IRPrimitiveType::Bool,
+        })
+    }
+
+    pub fn kind(self) -> ValueKind {
+        match self {
+            Unboxed::Int => ValueKind::Int,
+            Unboxed::Float => ValueKind::Float,
+            Unboxed::Bool => ValueKind::Bool,
+        }
+    }
+
+    /// The core type holding the scalar
+    pub fn wasm_type(self) -> &'static str {
+        match self {
+            Unboxed::Int => "i64",
+            Unboxed::Float => "f64",
+            Unboxed::Bool => "i32",
+        }
+    }
+
+    /// The instructions boxing the scalar on top of the stack into an
+    /// `anyref`
+    pub fn wasm_box(self) -> &'static [&'static str] {
+        match self {
+            Unboxed::Int => &["(struct.new $int)"],
+            Unboxed::Float => &["(struct.new $float)"],
+            Unboxed::Bool => &["(ref.i31)"],
+        }
+    }
+
+    /// The instructions reading the scalar out of the `anyref` on top of
+    /// the stack
+    pub fn wasm_unbox(self) -> &'static [&'static str] {
+        match self {
+            Unboxed::Int => &["(ref.cast (ref $int))", "(struct.get $int 0)"],
+            Unboxed::Float => &["(ref.cast (ref $float))", "(struct.get $float 0)"],
+            Unboxed::Bool => &["(call $rt.is_true)"],
+        }
+    }
+
+    /// The instruction comparing two scalars of this kind with `operator`
+    pub fn comparison(self, operator: &str) -> Option<&'static str> {
+        let instruction = match (self, operator) {
+            (Unboxed::Int, "==" | "=") => "i64.eq",
+            (Unboxed::Int, "!=" | "<>") => "i64.ne",
+            (Unboxed::Int, "<") => "i64.lt_s",
+            (Unboxed::Int, "<=") => "i64.le_s",
+            (Unboxed::Int, ">") => "i64.gt_s",
+            (Unboxed::Int, ">=") => "i64.ge_s",
+            (Unboxed::Float, "==" | "=") => "f64.eq",
+            (Unboxed::Float, "!=" | "<>") => "f64.ne",
+            (Unboxed::Float, "<") => "f64.lt",
+            (Unboxed::Float, "<=") => "f64.le",
+            (Unboxed::Float, ">") => "f64.gt",
+            (Unboxed::Float, ">=") => "f64.ge",
+            (Unboxed::Bool, "==" | "=") => "i32.eq",
+            (Unboxed::Bool, "!=" | "<>") => "i32.ne",
+            _ => return None,
+        };
+        Some(instruction)
+    }
+}
+
+/// The operator `name` when its operands have a fixed scalar type
+pub fn operator(name: &str) -> Option<Operator> {
+    let (scalar, instruction) = match name {
+        "+" => (Unboxed::Int, "i64.add"),
+        "-" => (Unboxed::Int, "i64.sub"),
+        "*" => (Unboxed::Int, "i64.mul"),
+        "/" => (Unboxed::Int, "i64.div_s"),
+        "%" | "mod" => (Unboxed::Int, "i64.rem_s"),
+        "+." => (Unboxed::Float, "f64.add"),
+        "-." => (Unboxed::Float, "f64.sub"),
+        "*." => (Unboxed::Float, "f64.mul"),
+        "/." => (Unboxed::Float, "f64.div"),
+        "&&" => (Unboxed::Bool, "i32.and"),
+        "||" => (Unboxed::Bool, "i32.or"),
+        _ => return None,
+    };
+    Some(Operator { scalar, instruction })
+}
+
+/// Whether `name` compares two values of any one type, giving a `Bool`
+pub fn is_comparison(name: &str) -> bool {
+    matches!(name, "==" | "=" | "!=" | "<>" | "<" | "<=" | ">" | ">=")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_kind_has_one_representation() {
+        for expected in REPRESENTATIONS {
+            assert_eq!(representation(expected.kind), expected);
+        }
+        for scalar in [Unboxed::Int, Unboxed::Float, Unboxed::Bool] {
+            assert_eq!(Unboxed::of_type(&scalar.ir_type()), Some(scalar));
+            assert_eq!(representation(scalar.kind()).wasm_component, scalar.wasm_type());
+        }
+    }
+
+    #[test]
+    fn test_scalar_instructions() {
+        assert_eq!(Unboxed::Int.wasm_box(), ["(struct.new $int)"]);
+        assert_eq!(Unboxed::Float.wasm_unbox(), ["(ref.cast (ref $float))", "(struct.get $float 0)"]);
+        assert_eq!(operator("*."), Some(Operator { scalar: Unboxed::Float, instruction: "f64.mul" }));
+        assert_eq!(Unboxed::Int.comparison("<="), Some("i64.le_s"));
+        assert_eq!(Unboxed::Bool.comparison("<"), None);
+    }
+}
@@ -0,0 +1,169 @@
+//! The `Core` standard library
+//!
+//! The modules under `stdlib/Core` are embedded in the compiler and can be
+//! imported from any program without being part of the project:
+//!
+//! ```text
+//! import Core.List
+//! import Core.Option { Some, None }
+//!
+//! let total = fun xs -> List.fold (fun acc -> fun x -> acc + x) 0 xs
+//! ```
+//!
+//! Each module is checked once, in dependency order, the first time a checker
+//! is asked for; the test suite keeps them free of type errors. Backends may
+//! replace calls to library functions with [`Intrinsic`]s, native code that
+//! does the same job faster than the compiled x definition.
+
+use std::sync::OnceLock;
+use x_checker::{CheckResult, ModuleInterface, TypeChecker};
+use x_parser::{parse_source, CompilationUnit, FileId, SyntaxStyle};
+
+/// The library's modules as `(path, source)`, each after the modules it imports
+pub const MODULES: &[(&str, &str)] = &[
+    ("Core.Option", include_str!("../stdlib/Core/Option.x")),
+    ("Core.Result", include_str!("../stdlib/Core/Result.x")),
+    ("Core.List", include_str!("../stdlib/Core/List.x")),
+    ("Core.String", include_str!("../stdlib/Core/String.x")),
+    ("Core.Map", include_str!("../stdlib/Core/Map.x")),
+    ("Core.State", include_str!("../stdlib/Core/State.x")),
+    ("Core.Reader", include_str!("../stdlib/Core/Reader.x")),
+    ("Core.Exn", include_str!("../stdlib/Core/Exn.x")),
+];
+
+/// Whether `path` names a module of the standard library
+pub fn is_stdlib_module(path: &str) -> bool {
+    MODULES.iter().any(|(module, _)| *module == path)
+}
+
+/// Parse a library module
+pub fn parse(path: &str) -> Option<CompilationUnit> {
+    let (index, (_, source)) = MODULES.iter().enumerate().find(|(_, (module, _))| *module == path)?;
+    // Library sources are fixed at build time; the tests keep them parsing
+    let file_id = FileId::new(u32::MAX - index as u32);
+    parse_source(source, file_id, SyntaxStyle::default()).ok()
+}
+
+/// A type checker that resolves `import Core.*`
+pub fn checker() -> TypeChecker {
+    interfaces().iter().fold(TypeChecker::new(), |checker, (path, interface)| {
+        checker.with_module(*path, interface.clone())
+    })
+}
+
+/// Interfaces of every library module, checked on first use
+pub fn interfaces() -> &'static [(&'static str, ModuleInterface)] {
+    static INTERFACES: OnceLock<Vec<(&'static str, ModuleInterface)>> = OnceLock::new();
+    INTERFACES.get_or_init(|| {
+        let mut interfaces: Vec<(&'static str, ModuleInterface)> = Vec::new();
+        for (path, _) in MODULES {
+            if let Some((unit, result)) = check_module(path, &interfaces) {
+                interfaces.push((path, result.module_interface(&unit.module)));
+            }
+        }
+        interfaces
+    })
+}
+
+/// Check a library module against the modules before it
+fn check_module(path: &str, before: &[(&'static str, ModuleInterface)]) -> Option<(CompilationUnit, CheckResult)> {
+    let unit = parse(path)?;
+    let mut checker = before.iter().fold(TypeChecker::new(), |checker, (path, interface)| {
+        checker.with_module(*path, interface.clone())
+    });
+    let result = checker.check_compilation_unit(&unit);
+    Some((unit, result))
+}
+
+/// Native code a backend emits in place of calling a library function
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Intrinsic {
+    /// Arguments the code needs; calls with fewer go through the x definition
+    pub arity: usize,
+    /// For TypeScript, an expression with `{0}`, `{1}`, ... standing for the
+    /// arguments; for WebAssembly GC, the prelude function to call
+    pub code: &'static str,
+}
+
+/// `(target, module, function, intrinsic)`
+const INTRINSICS: &[(&str, &str, &str, Intrinsic)] = &[
+    ("typescript", "Core.String", "concat", Intrinsic { arity: 2, code: "({0} + {1})" }),
+    ("typescript", "Core.String", "from_int", Intrinsic { arity: 1, code: "String({0})" }),
+    ("typescript", "Core.String", "to_int", Intrinsic { arity: 1, code: "parseInt({0}, 10)" }),
+    ("typescript", "Core.String", "repeat", Intrinsic { arity: 2, code: "{1}.repeat(Math.max({0}, 0))" }),
+    ("wasm-gc", "Core.List", "length", Intrinsic { arity: 1, code: "list_length" }),
+    ("wasm-gc", "Core.List", "map", Intrinsic { arity: 2, code: "list_map" }),
+    ("wasm-gc", "Core.List", "fold", Intrinsic { arity: 3, code: "list_fold" }),
+    ("wasm-gc", "Core.List", "reverse", Intrinsic { arity: 1, code: "list_reverse" }),
+    ("wasm-gc", "Core.Option", "map", Intrinsic { arity: 2, code: "option_map" }),
+];
+
+/// The intrinsic `target` has for `module.function`, if any
+pub fn intrinsic(target: &str, module: &str, function: &str) -> Option<Intrinsic> {
+    INTRINSICS.iter()
+        .find(|(t, m, f, _)| *t == target && *m == module && *f == function)
+        .map(|(_, _, _, intrinsic)| *intrinsic)
+}
+
+/// Substitute the arguments into a TypeScript intrinsic's code
+pub fn expand(intrinsic: Intrinsic, arguments: &[String]) -> String {
+    arguments.iter().enumerate().fold(intrinsic.code.to_string(), |code, (index, argument)| {
+        code.replace(&format!("{{{index}}}"), argument)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x_checker::Type;
+    use x_parser::Symbol;
+
+    #[test]
+    fn test_library_modules_type_check() {
+        let mut before = Vec::new();
+        for (path, _) in MODULES {
+            let (unit, result) = check_module(path, &before).unwrap_or_else(|| panic!("{path} does not parse"));
+            assert!(result.errors.is_empty(), "{path}: {:?}", result.errors);
+            before.push((*path, result.module_interface(&unit.module)));
+        }
+        assert_eq!(interfaces().len(), MODULES.len());
+    }
+
+    #[test]
+    fn test_programs_import_library_modules() {
+        let source = "module Main\n\
+            import Core.List\n\
+            import Core.Option { Some, None }\n\
+            let total = fun xs -> List.fold (fun acc -> fun x -> acc + x) 0 xs\n\
+            let first = fun xs -> match List.head xs with | Some x => x | None => 0\n\
+            let count = List.length (List.Cons 1 List.Nil)";
+        let unit = parse_source(source, FileId::new(0), SyntaxStyle::default()).unwrap();
+        let result = checker().check_compilation_unit(&unit);
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+
+        let count = &result.inferred_types[&Symbol::intern("count")];
+        assert_eq!(count.body, Type::Con(Symbol::intern("Int")));
+
+        let misuse = "module Main\nimport Core.Option\nlet n = Option.get_or 0 (Option.Some \"x\")";
+        let unit = parse_source(misuse, FileId::new(0), SyntaxStyle::default()).unwrap();
+        assert!(!checker().check_compilation_unit(&unit).errors.is_empty());
+    }
+
+    #[test]
+    fn test_missing_library_value_is_reported() {
+        let source = "module Main\nimport Core.List\nimport Core.Map { lookup }\nlet n = List.size";
+        let unit = parse_source(source, FileId::new(0), SyntaxStyle::default()).unwrap();
+        let errors = checker().check_compilation_unit(&unit).errors;
+        let messages: Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+        assert!(messages.iter().any(|message| message.contains("Core.Map.lookup")), "{messages:?}");
+        assert!(messages.iter().any(|message| message.contains("List.size")), "{messages:?}");
+    }
+
+    #[test]
+    fn test_intrinsics() {
+        let concat = intrinsic("typescript", "Core.String", "concat").unwrap();
+        assert_eq!(expand(concat, &["a".into(), "b".into()]), "(a + b)");
+        assert_eq!(intrinsic("wasm-gc", "Core.List", "length").map(|i| i.code), Some("list_length"));
+        assert_eq!(intrinsic("wasm-gc", "Core.String", "concat"), None);
+    }
+}
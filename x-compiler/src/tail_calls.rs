@@ -0,0 +1,277 @@
+//! Tail calls and self-recursion on the IR
+//!
+//! A call is in tail position when its caller returns the result as it is:
+//! the function's body itself, the body of a `let`, the branches of an
+//! `if`, the cases of a `match` and the last expression of a block. Calls
+//! under a handler or in the scope of a cell are not, as the handler or
+//! cell must outlive them, and the bodies of lambdas are functions of their
+//! own.
+//!
+//! Backends reuse the caller's frame for such calls: the TypeScript backend
+//! turns a function whose [self-calls](self_calls) are all tail calls into
+//! a loop, and the WebAssembly GC backend emits `return_call` for
+//! [tail calls](tail_calls) when the target enables them.
+//!
+//! [`diagnostics`] warns about functions that call themselves outside tail
+//! position, whose recursion takes a frame per call, and reports an error
+//! for a function whose doc comment asserts `@tailrec` when it does not
+//! make a loop:
+//!
+//! ```text
+//! ---
+//! @tailrec
+//! ---
+//! let last = fun xs -> match xs with | Cons x Nil => x | Cons _ rest => last rest
+//! ```
+
+use crate::backend::{CodegenDiagnostic, DiagnosticSeverity};
+use crate::ir::{IRExpression, IRFunction, IRLiteral, IRModule};
+use x_parser::{CompilationUnit, Item, Symbol};
+
+/// The calls a function makes to itself with all of its parameters
+#[derive(Debug, Default)]
+pub struct SelfCalls<'a> {
+    /// Calls in tail position
+    pub tail: Vec<&'a IRExpression>,
+    /// Calls whose result the function still works on
+    pub other: Vec<&'a IRExpression>,
+}
+
+impl SelfCalls<'_> {
+    /// Whether the function recurses only through tail calls, so that it
+    /// can run as a loop
+    pub fn loops(&self) -> bool {
+        !self.tail.is_empty() && self.other.is_empty()
+    }
+
+    /// Whether `call` is one of the tail calls
+    pub fn is_tail_call(&self, call: &IRExpression) -> bool {
+        self.tail.iter().any(|tail| std::ptr::eq(*tail, call))
+    }
+}
+
+/// The function and arguments of a call, gathering the curried
+/// applications `(f x) y` arrive as
+pub fn call_spine(call: &IRExpression) -> Option<(&IRExpression, Vec<&IRExpression>)> {
+    let IRExpression::Call { .. } = call else { return None };
+    let mut function = call;
+    let mut arguments = Vec::new();
+    while let IRExpression::Call { function: inner, arguments: args } = function {
+        arguments.splice(0..0, args.iter());
+        function = inner;
+    }
+    Some((function, arguments))
+}
+
+/// The calls in tail position of `body`, outermost application first
+pub fn tail_calls(body: &IRExpression) -> Vec<&IRExpression> {
+    let mut calls = Vec::new();
+    visit_calls(body, true, &mut Vec::new(), &mut |call, tail, _| {
+        if tail {
+            calls.push(call);
+        }
+    });
+    calls
+}
+
+/// The calls `function` makes to itself, passing every parameter; calls
+/// where a local shadows the function's name are not
+pub fn self_calls(function: &IRFunction) -> SelfCalls<'_> {
+    let arity = function.parameters.len();
+    let mut calls = SelfCalls::default();
+    let mut bound = function.parameters.iter().map(|parameter| parameter.name).collect();
+    visit_calls(&function.body, true, &mut bound, &mut |call, tail, bound| {
+        let Some((IRExpression::Variable(name), arguments)) = call_spine(call) else { return };
+        if *name != function.name || bound.contains(name) || arguments.len() < arity {
+            return;
+        }
+        if tail && arguments.len() == arity {
+            calls.tail.push(call);
+        } else {
+            calls.other.push(call);
+        }
+    });
+    calls
+}
+
+/// Call `visit` with every call outside lambdas, whether it is in tail
+/// position and the names bound around it
+fn visit_calls<'a>(
+    expr: &'a IRExpression,
+    tail: bool,
+    bound: &mut Vec<Symbol>,
+    visit: &mut impl FnMut(&'a IRExpression, bool, &[Symbol]),
+) {
+    let outer = bound.len();
+    match expr {
+        IRExpression::Call { .. } => {
+            visit(expr, tail, bound);
+            let (function, arguments) = call_spine(expr).expect("a call has a spine");
+            visit_calls(function, false, bound, visit);
+            for argument in arguments {
+                visit_calls(argument, false, bound, visit);
+            }
+        }
+        IRExpression::Lambda { .. } | IRExpression::Variable(_) | IRExpression::CellGet(_) => {}
+        IRExpression::Literal(IRLiteral::Array(elements)) | IRExpression::Tuple(elements) => {
+            for element in elements {
+                visit_calls(element, false, bound, visit);
+            }
+        }
+        IRExpression::Literal(IRLiteral::Record(fields)) => {
+            for (_, value) in fields {
+                visit_calls(value, false, bound, visit);
+            }
+        }
+        IRExpression::Literal(_) => {}
+        IRExpression::Let { bindings, body } => {
+            for binding in bindings {
+                visit_calls(&binding.value, false, bound, visit);
+                bound.push(binding.name);
+            }
+            visit_calls(body, tail, bound, visit);
+        }
+        IRExpression::If { condition, then_branch, else_branch } => {
+            visit_calls(condition, false, bound, visit);
+            visit_calls(then_branch, tail, bound, visit);
+            visit_calls(else_branch, tail, bound, visit);
+        }
+        IRExpression::Match { value, cases } => {
+            visit_calls(value, false, bound, visit);
+            for case in cases {
+                let before = bound.len();
+                case.pattern.collect_variables(bound);
+                if let Some(guard) = &case.guard {
+                    visit_calls(guard, false, bound, visit);
+                }
+                visit_calls(&case.body, tail, bound, visit);
+                bound.truncate(before);
+            }
+        }
+        IRExpression::Block(expressions) => {
+            if let Some((last, rest)) = expressions.split_last() {
+                for expression in rest {
+                    visit_calls(expression, false, bound, visit);
+                }
+                visit_calls(last, tail, bound, visit);
+            }
+        }
+        IRExpression::Effect { arguments, .. } => {
+            for argument in arguments {
+                visit_calls(argument, false, bound, visit);
+            }
+        }
+        IRExpression::Handle { expression, handlers, return_handler, .. } => {
+            visit_calls(expression, false, bound, visit);
+            for handler in handlers {
+                let before = bound.len();
+                bound.extend(handler.parameters.iter().copied());
+                bound.push(handler.continuation);
+                visit_calls(&handler.body, false, bound, visit);
+                bound.truncate(before);
+            }
+            if let Some(handler) = return_handler {
+                visit_calls(handler, false, bound, visit);
+            }
+        }
+        IRExpression::Resume { value, .. } => visit_calls(value, false, bound, visit),
+        IRExpression::Field { record, .. } => visit_calls(record, false, bound, visit),
+        IRExpression::RecordUpdate { record, fields } => {
+            visit_calls(record, false, bound, visit);
+            for (_, value) in fields {
+                visit_calls(value, false, bound, visit);
+            }
+        }
+        IRExpression::TupleGet { tuple, .. } => visit_calls(tuple, false, bound, visit),
+        IRExpression::Cell { name, initial, body } => {
+            visit_calls(initial, false, bound, visit);
+            bound.push(*name);
+            visit_calls(body, false, bound, visit);
+        }
+        IRExpression::CellSet { value, .. } => visit_calls(value, false, bound, visit),
+    }
+    bound.truncate(outer);
+}
+
+/// Warnings for the functions of `module` that call themselves outside
+/// tail position, and errors for `@tailrec` functions that do not loop
+pub fn diagnostics(module: &IRModule, unit: &CompilationUnit) -> Vec<CodegenDiagnostic> {
+    let mut diagnostics = Vec::new();
+    for function in &module.functions {
+        let calls = self_calls(function);
+        let tailrec = function.has_attribute("tailrec");
+        let (severity, code, message) = match (tailrec, calls.other.is_empty()) {
+            (true, true) if calls.tail.is_empty() => {
+                (DiagnosticSeverity::Error, "E0209", format!("`{}` is marked @tailrec but never calls itself", function.name))
+            }
+            (true, true) | (false, true) => continue,
+            (true, false) => (
+                DiagnosticSeverity::Error,
+                "E0209",
+                format!("`{}` is marked @tailrec but calls itself outside tail position", function.name),
+            ),
+            (false, false) => (
+                DiagnosticSeverity::Warning,
+                "E0208",
+                format!("`{}` calls itself outside tail position, so deep recursion may overflow the stack", function.name),
+            ),
+        };
+        let location = unit.module.items.iter().find_map(|item| match item {
+            Item::ValueDef(def) if def.name == function.name => Some(def.span),
+            _ => None,
+        });
+        diagnostics.push(CodegenDiagnostic { severity, code, message, location });
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::IRBuilder;
+    use crate::ir_print::print_expression;
+    use x_parser::{parse_source, FileId, SyntaxStyle};
+
+    fn build(source: &str) -> (CompilationUnit, IRModule) {
+        let unit = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let module = IRBuilder::new().build_ir(&unit).unwrap().modules.remove(0);
+        (unit, module)
+    }
+
+    #[test]
+    fn test_self_calls_by_position() {
+        let (_, module) = build("module Test\n\
+            let walk = fun xs acc -> match xs with | Cons x rest => walk rest (Cons x acc) | Nil => acc\n\
+            let size = fun xs -> match xs with | Cons _ rest => 1 + size rest | Nil => 0\n\
+            let shadow = fun walk -> walk 1\n\
+            let inner = fun n -> (let f = fun m -> inner m in f n)");
+        let calls: Vec<_> = module.functions.iter().map(self_calls).collect();
+        assert!(calls[0].loops());
+        assert_eq!(print_expression(calls[0].tail[0]), "(call (call walk rest) (call (call Cons x) acc))");
+        assert!(calls[0].is_tail_call(calls[0].tail[0]));
+        assert!(!calls[1].loops());
+        assert_eq!(calls[1].other.len(), 1);
+        assert!(calls[2].tail.is_empty() && calls[2].other.is_empty());
+        assert!(calls[3].tail.is_empty() && calls[3].other.is_empty());
+        assert_eq!(tail_calls(&module.functions[1].body).len(), 1);
+    }
+
+    #[test]
+    fn test_recursion_diagnostics() {
+        let (unit, module) = build("module Test\n\
+            let size = fun xs -> match xs with | Cons _ rest => 1 + size rest | Nil => 0\n\
+            let walk = fun xs -> match xs with | Cons _ rest => walk rest | Nil => 0\n\
+            ```\n---\n@tailrec\n---\n```\n\
+            let count = fun xs -> match xs with | Cons _ rest => 1 + count rest | Nil => 0\n\
+            ```\n---\n@tailrec\n---\n```\n\
+            let id = fun x -> x");
+        let diagnostics = diagnostics(&module, &unit);
+        let reported: Vec<_> = diagnostics.iter().map(|diagnostic| (diagnostic.code, diagnostic.message.as_str())).collect();
+        assert_eq!(reported, [
+            ("E0208", "`size` calls itself outside tail position, so deep recursion may overflow the stack"),
+            ("E0209", "`count` is marked @tailrec but calls itself outside tail position"),
+            ("E0209", "`id` is marked @tailrec but never calls itself"),
+        ]);
+        assert!(diagnostics.iter().all(|diagnostic| diagnostic.location.is_some()));
+    }
+}
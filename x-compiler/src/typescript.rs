@@ -2,16 +2,57 @@
 //! 
 //! This backend generates TypeScript code from x Language IR,
 //! including full type information and effect system translation.
+//!
+//! Without type emission it generates plain JavaScript instead: `.mjs`
+//! files for ES modules or `.cjs` files for CommonJS, each importing a small
+//! runtime module written next to them.
+//!
+//! With declarations enabled, each module also gets a `.d.ts` file (`.d.mts`
+//! or `.d.cts` next to JavaScript) typing its exports from the signatures the
+//! type checker inferred. Records become interfaces and data types
+//! discriminated unions on `tag`, with constructor fields `_0`, `_1`, ...
+//!
+//! Effects are lowered according to the `effect_lowering` target option:
+//! `handlers` (the default) keeps a stack of handler objects in the runtime
+//! that operations call synchronously, while `async` makes effectful
+//! functions `async` and awaits operations, so handlers may return Promises.
+//! Generated modules that use effects explain the trade-offs in their header.
+//!
+//! Function bodies are emitted from the [A-normal form](crate::anf) of the
+//! IR, checked by the [verifier](crate::verify) first: each `let` becomes a
+//! `const` declaration and conditionals containing them `if` statements.
+//! A function whose recursive calls are all [tail calls](crate::tail_calls)
+//! becomes a `while (true)` loop, each tail call assigning the parameters
+//! and continuing it, so that it runs in constant stack space.
+//!
+//! With the `stack_traces` target option, every function records a frame
+//! naming its module, its definition and the definition's source location
+//! on a stack the runtime keeps, and errors leaving a function get a `stack`
+//! listing those frames instead of lines of the generated code. Operations
+//! no handler handles throw from the function performing them, so their
+//! errors point at it too.
+//!
+//! Imports of the `Core` standard library produce no import statement; calls
+//! to library functions with a TypeScript [intrinsic](crate::stdlib::Intrinsic)
+//! are replaced by native code such as `a + b` for `String.concat a b`.
 
 use crate::{
+    anf,
     backend::*,
     ir::*,
+    profiling::PassTiming,
+    stdlib,
+    tail_calls,
     utils,
+    verify,
+    CompilerError,
     Result,
 };
-use crate::codegen_mod::TypeScriptModuleSystem;
-use x_parser::{CompilationUnit, Module, Symbol, Visibility};
-use x_checker::TypeScheme;
+use crate::codegen_mod::{TypeScriptEffectLowering, TypeScriptModuleSystem};
+use crate::decision_tree::{self, Access, Constructors, Decision, Test};
+use x_parser::symbol::symbols;
+use x_parser::{CompilationUnit, HandlerMode, Item, Module, Symbol, Type, TypeDef, TypeDefKind, Visibility};
+use x_checker::{Type as CheckerType, TypeScheme};
 use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
 
@@ -20,8 +61,36 @@ use std::fmt::Write;
 pub struct TypeScriptBackend {
     module_system: TypeScriptModuleSystem,
     emit_types: bool,
+    emit_declarations: bool,
+    effect_lowering: TypeScriptEffectLowering,
     strict_mode: bool,
     generated_names: HashSet<String>,
+    /// Functions of the current module generated as `async`
+    async_functions: HashSet<Symbol>,
+    /// Qualifiers of lazy imports, loaded with `import()` on first use
+    lazy_modules: HashSet<Symbol>,
+    /// Standard library modules of the current module, by qualifier
+    library_modules: HashMap<Symbol, Symbol>,
+    /// Constructors by type, for compiling matches
+    signatures: Constructors,
+    /// Counter for the subjects of `match` expressions
+    match_index: u32,
+    /// The function being generated as a loop, while generating the
+    /// statements of its body
+    tail_loop: Option<TailLoop>,
+    /// Whether functions record their frames for stack traces
+    stack_traces: bool,
+    /// Full name of the module being generated, for its frames
+    module_path: String,
+    /// `path:line:column` of the module's definitions, for their frames
+    locations: HashMap<Symbol, String>,
+}
+
+/// A function generated as a loop, which tail calls to itself continue
+struct TailLoop {
+    function: Symbol,
+    /// What the arguments of a tail call are assigned to
+    parameters: Vec<String>,
 }
 
 impl TypeScriptBackend {
@@ -29,8 +98,19 @@ impl TypeScriptBackend {
         TypeScriptBackend {
             module_system: TypeScriptModuleSystem::ES2020,
             emit_types: true,
+            emit_declarations: false,
+            effect_lowering: TypeScriptEffectLowering::Handlers,
             strict_mode: true,
             generated_names: HashSet::new(),
+            async_functions: HashSet::new(),
+            lazy_modules: HashSet::new(),
+            library_modules: HashMap::new(),
+            signatures: Constructors::default(),
+            match_index: 0,
+            tail_loop: None,
+            stack_traces: false,
+            module_path: String::new(),
+            locations: HashMap::new(),
         }
     }
     
@@ -43,13 +123,128 @@ impl TypeScriptBackend {
         self.strict_mode = strict;
         self
     }
+    
+    pub fn with_emit_types(mut self, emit_types: bool) -> Self {
+        self.emit_types = emit_types;
+        self
+    }
+    
+    /// Also write a declaration file for each module
+    pub fn with_declarations(mut self, emit_declarations: bool) -> Self {
+        self.emit_declarations = emit_declarations;
+        self
+    }
+    
+    pub fn with_effect_lowering(mut self, effect_lowering: TypeScriptEffectLowering) -> Self {
+        self.effect_lowering = effect_lowering;
+        self
+    }
+    
+    /// Record x frames at runtime, so errors carry x stack traces
+    pub fn with_stack_traces(mut self, stack_traces: bool) -> Self {
+        self.stack_traces = stack_traces;
+        self
+    }
+    
+    /// Backend generating plain JavaScript ES modules
+    pub fn javascript() -> Self {
+        Self::new().with_emit_types(false)
+    }
+    
+    /// Extension of the generated files
+    fn file_extension(&self) -> &'static str {
+        match (self.emit_types, &self.module_system) {
+            (true, _) => "ts",
+            (false, TypeScriptModuleSystem::CommonJS) => "cjs",
+            (false, _) => "mjs",
+        }
+    }
+    
+    /// Whether exports are assigned to `module.exports` rather than declared
+    fn exports_commonjs(&self) -> bool {
+        !self.emit_types && self.module_system == TypeScriptModuleSystem::CommonJS
+    }
+    
+    /// `export ` for a declaration of an exported name, which CommonJS
+    /// output lists in `module.exports` instead
+    fn export_keyword(&self) -> &'static str {
+        if self.exports_commonjs() { "" } else { "export " }
+    }
+    
+    /// Functions that must be `async` because they await `Async.await`, an
+    /// operation or handler lowered to async/await, a lazily imported
+    /// module or another such function. Lazy qualifiers are included, as
+    /// their uses await the module's loading.
+    fn find_async_functions(&self, module: &IRModule) -> HashSet<Symbol> {
+        let mut async_functions: HashSet<Symbol> = module.imports.iter()
+            .filter(|import| import.lazy)
+            .filter_map(|import| import.qualifier)
+            .collect();
+        loop {
+            let found: Vec<Symbol> = module.functions.iter()
+                .filter(|function| !async_functions.contains(&function.name))
+                .filter(|function| awaits(&function.body, &async_functions, self.effect_lowering))
+                .map(|function| function.name)
+                .collect();
+            if found.is_empty() {
+                return async_functions;
+            }
+            async_functions.extend(found);
+        }
+    }
+    
+    /// Handlers lowered to handler objects run synchronously, so the
+    /// computations they handle and their clauses cannot wait for `Async`
+    fn synchronous_handler_diagnostics(&self, module: &IRModule) -> Vec<CodegenDiagnostic> {
+        if self.effect_lowering != TypeScriptEffectLowering::Handlers {
+            return Vec::new();
+        }
+        let async_functions = self.find_async_functions(module);
+        let mut diagnostics = Vec::new();
+        let bodies = module.functions.iter().map(|function| &function.body)
+            .chain(module.constants.iter().map(|constant| &constant.value));
+        for body in bodies {
+            body.walk(&mut |expr| {
+                if let IRExpression::Handle { expression, handlers, return_handler, .. } = expr {
+                    let waits = std::iter::once(expression.as_ref())
+                        .chain(handlers.iter().map(|handler| &handler.body))
+                        .chain(return_handler.as_deref())
+                        .any(|expr| awaits(expr, &async_functions, self.effect_lowering));
+                    if waits {
+                        diagnostics.push(CodegenDiagnostic {
+                            severity: DiagnosticSeverity::Error,
+                            code: "E0207",
+                            message: "A handled computation waits for Async, which needs effects lowered to async/await".to_string(),
+                            location: None,
+                        });
+                    }
+                }
+                true
+            });
+        }
+        diagnostics
+    }
+    
+    /// `: any`, for values the generated code does not know the type of
+    fn any_annotation(&self) -> &'static str {
+        if self.emit_types { ": any" } else { "" }
+    }
+    
+    /// Type annotation suffix, e.g. `: number`; empty in JavaScript
+    fn annotation(&self, typ: &IRType) -> String {
+        if self.emit_types {
+            format!(": {}", self.generate_ir_type(typ))
+        } else {
+            String::new()
+        }
+    }
 }
 
 impl CodegenBackend for TypeScriptBackend {
     fn target_info(&self) -> CompilationTarget {
         CompilationTarget {
-            name: "TypeScript".to_string(),
-            file_extension: "ts".to_string(),
+            name: if self.emit_types { "TypeScript" } else { "JavaScript" }.to_string(),
+            file_extension: self.file_extension().to_string(),
             supports_modules: true,
             supports_effects: true, // Via async/await and generators
             supports_gc: true,      // JavaScript has GC
@@ -61,6 +256,7 @@ impl CodegenBackend for TypeScriptBackend {
             "modules" | "types" | "effects" | "async" | "closures" => true,
             "gc" | "weakrefs" => true,
             "threads" => false, // Web Workers are different
+            "multishot-continuations" => false, // A resumption returns to its perform site
             _ => false,
         }
     }
@@ -72,25 +268,67 @@ impl CodegenBackend for TypeScriptBackend {
         options: &CodegenOptions,
     ) -> Result<CodegenResult> {
         let start_time = std::time::Instant::now();
+        if !self.emit_types && matches!(self.module_system, TypeScriptModuleSystem::AMD | TypeScriptModuleSystem::SystemJS) {
+            return Err(crate::CompilerError::CodeGen {
+                message: format!("JavaScript output supports ES modules and CommonJS, not {:?}", self.module_system),
+            });
+        }
         
         // Convert AST to IR
-        let mut ir_builder = IRBuilder::new();
-        let ir = ir_builder.build_ir(cu)?;
+        let mut ir_builder = IRBuilder::new()
+            .with_passes(options.passes.clone())
+            .with_inlining(options.inlining.clone());
+        let mut ir = ir_builder.build_ir(cu)?;
+        let mut passes = ir_builder.pass_timings().to_vec();
+        for module in &mut ir.modules {
+            normalize(module, &mut passes)?;
+        }
+        options.check_cancelled()?;
+        let emit_start = std::time::Instant::now();
         
         // Generate TypeScript code
         let mut files = HashMap::new();
-        let diagnostics = Vec::new();
+        let mut diagnostics = multi_shot_diagnostics(cu);
+        if self.stack_traces {
+            self.module_path = cu.module.name.to_string();
+            self.locations = cu.module.items.iter()
+                .filter_map(|item| match item {
+                    Item::ValueDef(def) => Some((def.name, options.sources.location(def.span)?)),
+                    _ => None,
+                })
+                .collect();
+        }
+        for module in &ir.modules {
+            diagnostics.extend(self.synchronous_handler_diagnostics(module));
+            diagnostics.extend(tail_calls::diagnostics(module, cu));
+        }
+        let extension = self.file_extension();
         
         for module in &ir.modules {
             let module_code = self.generate_ir_module(module, type_info, options)?;
-            let filename = format!("{}.ts", module.name.as_str());
-            files.insert(options.output_dir.join(&filename), module_code);
+            let path = options.naming.module_file(module.name.as_str(), extension);
+            files.insert(options.output_dir.join(path), module_code);
+        }
+        
+        if self.emit_declarations {
+            let inferred = x_checker::type_check(cu).inferred_types;
+            let extension = match extension {
+                "mjs" => "d.mts",
+                "cjs" => "d.cts",
+                _ => "d.ts",
+            };
+            for module in &ir.modules {
+                options.check_cancelled()?;
+                let declarations = self.generate_declarations(&cu.module, module, &inferred)?;
+                let path = options.naming.module_file(module.name.as_str(), extension);
+                files.insert(options.output_dir.join(path), declarations);
+            }
         }
         
         // Generate runtime if needed
         if self.needs_runtime(&ir) {
             let runtime_code = self.generate_runtime(options)?;
-            files.insert(options.output_dir.join("runtime.ts"), runtime_code);
+            files.insert(options.output_dir.join(format!("runtime.{extension}")), runtime_code);
         }
         
         // Generate type definitions
@@ -105,6 +343,7 @@ impl CodegenBackend for TypeScriptBackend {
         
         Ok(CodegenResult {
             files,
+            binary_files: HashMap::new(),
             source_maps: HashMap::new(), // TODO: Implement source maps
             diagnostics,
             metadata: CodegenMetadata {
@@ -112,6 +351,8 @@ impl CodegenBackend for TypeScriptBackend {
                 generated_files: files_len,
                 total_size,
                 compilation_time,
+                passes,
+                emit_time: emit_start.elapsed(),
             },
         })
     }
@@ -124,11 +365,15 @@ impl CodegenBackend for TypeScriptBackend {
     ) -> Result<String> {
         let mut ir_builder = IRBuilder::new();
         // Convert single module to IR
-        let ir_module = ir_builder.build_module(module)?; // This method doesn't exist yet
+        let mut ir_module = ir_builder.build_module(module)?;
+        normalize(&mut ir_module, &mut Vec::new())?;
         self.generate_ir_module(&ir_module, type_info, options)
     }
     
     fn generate_runtime(&self, _options: &CodegenOptions) -> Result<String> {
+        if !self.emit_types {
+            return Ok(self.generate_javascript_runtime());
+        }
         let mut code = String::new();
         
         writeln!(code, "// x Language Runtime for TypeScript")?;
@@ -175,6 +420,11 @@ impl CodegenBackend for TypeScriptBackend {
         writeln!(code, "    super(`Non-exhaustive pattern match for value: ${{JSON.stringify(value)}}`);")?;
         writeln!(code, "  }}")?;
         writeln!(code, "}}")?;
+        writeln!(code)?;
+        code.push_str(&self.generate_handler_runtime());
+        if self.stack_traces {
+            code.push_str(&self.generate_frame_runtime());
+        }
         
         Ok(code)
     }
@@ -186,7 +436,7 @@ impl TypeScriptBackend {
         &mut self,
         module: &IRModule,
         _type_info: &HashMap<Symbol, TypeScheme>,
-        _options: &CodegenOptions,
+        options: &CodegenOptions,
     ) -> Result<String> {
         let mut code = String::new();
         
@@ -195,34 +445,105 @@ impl TypeScriptBackend {
             writeln!(code, "\"use strict\";")?;
         }
         writeln!(code, "// Generated from x Language module: {}", module.name)?;
+        let uses_effects = module.functions.iter().map(|function| &function.body)
+            .chain(module.constants.iter().map(|constant| &constant.value))
+            .any(uses_effects);
+        if uses_effects {
+            writeln!(code, "//")?;
+            match self.effect_lowering {
+                TypeScriptEffectLowering::Handlers => {
+                    writeln!(code, "// Effects are lowered to handler objects: `handle` pushes a handler on a")?;
+                    writeln!(code, "// stack and operations call the innermost one synchronously. Calls cost")?;
+                    writeln!(code, "// no Promises, but handlers cannot wait for asynchronous work, resume")?;
+                    writeln!(code, "// at most once in tail position, and abort by throwing otherwise.")?;
+                }
+                TypeScriptEffectLowering::AsyncAwait => {
+                    writeln!(code, "// Effects are lowered to async/await: effectful functions return")?;
+                    writeln!(code, "// Promises, operations are awaited and handlers run in try/catch, so")?;
+                    writeln!(code, "// handlers may wait for asynchronous work. Every effectful call")?;
+                    writeln!(code, "// allocates a Promise, and computations running concurrently share")?;
+                    writeln!(code, "// one handler stack, so they must not interleave handlers.")?;
+                }
+            }
+        }
+        self.async_functions = self.find_async_functions(module);
+        self.lazy_modules = module.imports.iter()
+            .filter(|import| import.lazy)
+            .filter_map(|import| import.qualifier)
+            .collect();
+        self.library_modules = module.imports.iter()
+            .filter(|import| stdlib::is_stdlib_module(import.module.as_str()))
+            .filter_map(|import| Some((import.qualifier?, import.module)))
+            .collect();
+        self.signatures = Constructors::for_module(module);
+        self.match_index = 0;
         writeln!(code)?;
         
-        // Imports
-        for import in &module.imports {
+        // The runtime is a module of its own
+        if !self.emit_types || uses_effects || self.stack_traces {
+            let root = options.naming.root_from(module.name.as_str());
+            let runtime = if self.emit_types {
+                format!("{root}runtime")
+            } else {
+                format!("{root}runtime.{}", self.file_extension())
+            };
+            if self.exports_commonjs() {
+                writeln!(code, "const $rt = require(\"{runtime}\");")?;
+            } else {
+                writeln!(code, "import * as $rt from \"{runtime}\";")?;
+            }
+            writeln!(code)?;
+        }
+        
+        // Imports; the standard library is compiled in through intrinsics
+        let imports: Vec<&IRImport> = module.imports.iter()
+            .filter(|import| !import.items.is_empty() || import.qualifier.is_some())
+            .filter(|import| !stdlib::is_stdlib_module(import.module.as_str()))
+            .collect();
+        for import in &imports {
             writeln!(code, "{}", self.generate_import(import)?)?;
         }
-        if !module.imports.is_empty() {
+        if !imports.is_empty() {
             writeln!(code)?;
         }
         
-        // Type definitions
-        if self.emit_types {
-            for type_def in &module.types {
-                writeln!(code, "{}", self.generate_type_definition(type_def)?)?;
+        // Constructors of the data types
+        for type_def in &module.types {
+            let constructors = self.generate_type_definition(type_def)?;
+            if !constructors.is_empty() {
+                writeln!(code, "{constructors}")?;
                 writeln!(code)?;
             }
         }
         
         // Constants
         for constant in &module.constants {
+            options.check_cancelled()?;
             writeln!(code, "{}", self.generate_constant(constant)?)?;
         }
         if !module.constants.is_empty() {
             writeln!(code)?;
         }
         
+        // Frames of the functions, for stack traces
+        if self.stack_traces && !module.functions.is_empty() {
+            for function in &module.functions {
+                writeln!(code, "{}", self.generate_frame(function))?;
+            }
+            writeln!(code)?;
+        }
+        
         // Functions
         for function in &module.functions {
+            options.check_cancelled()?;
+            // Inlined code keeps pointing at where it was defined
+            let inlined: Vec<&str> = function.attributes.iter()
+                .filter(|attribute| attribute.name.as_str() == "inlined")
+                .filter_map(|attribute| attribute.value.as_deref())
+                .collect();
+            if (options.debug_info || options.source_maps) && !inlined.is_empty() {
+                writeln!(code, "// inlines {}", inlined.join(", "))?;
+            }
             writeln!(code, "{}", self.generate_function(function)?)?;
             writeln!(code)?;
         }
@@ -234,12 +555,101 @@ impl TypeScriptBackend {
                 writeln!(code, "{}", self.generate_export(export)?)?;
             }
         }
+        if self.exports_commonjs() {
+            let exported: Vec<String> = module.constants.iter()
+                .map(|constant| constant.name)
+                .chain(module.functions.iter()
+                    .filter(|function| function.visibility == Visibility::Public)
+                    .map(|function| function.name))
+                .map(|name| utils::sanitize_identifier(name, "typescript"))
+                .chain(module.exports.iter().map(|export| {
+                    let name = utils::sanitize_identifier(export.name, "typescript");
+                    match &export.alias {
+                        Some(alias) => format!("{alias}: {name}"),
+                        None => name,
+                    }
+                }))
+                .collect();
+            writeln!(code, "module.exports = {{ {} }};", exported.join(", "))?;
+        }
         
         Ok(code)
     }
     
+    /// Native code for a call to a standard library function, when it has an intrinsic
+    fn generate_intrinsic_call(&mut self, function: &IRExpression, arguments: &[IRExpression]) -> Result<Option<String>> {
+        // Curried applications nest; gather the whole spine
+        let mut function = function;
+        let mut arguments: Vec<&IRExpression> = arguments.iter().collect();
+        while let IRExpression::Call { function: inner, arguments: first } = function {
+            arguments = first.iter().chain(arguments).collect();
+            function = inner;
+        }
+        let IRExpression::Field { record, field } = function else {
+            return Ok(None);
+        };
+        let IRExpression::Variable(qualifier) = record.as_ref() else {
+            return Ok(None);
+        };
+        let Some(intrinsic) = self.library_modules.get(qualifier)
+            .and_then(|module| stdlib::intrinsic("typescript", module.as_str(), field.as_str()))
+            .filter(|intrinsic| intrinsic.arity <= arguments.len())
+        else {
+            return Ok(None);
+        };
+        let mut arguments = arguments.iter()
+            .map(|argument| self.generate_ir_expression(argument, 0))
+            .collect::<Result<Vec<_>>>()?;
+        let rest = arguments.split_off(intrinsic.arity);
+        let code = stdlib::expand(intrinsic, &arguments);
+        if rest.is_empty() {
+            Ok(Some(code))
+        } else {
+            Ok(Some(format!("{code}({})", rest.join(", "))))
+        }
+    }
+
+    /// Infix code for a built-in binary operator applied to both operands,
+    /// which would otherwise be called by its sanitized name
+    fn generate_operator_call(&mut self, function: &IRExpression, arguments: &[IRExpression]) -> Result<Option<String>> {
+        let (operator, left, right) = match (function, arguments) {
+            (IRExpression::Variable(operator), [left, right]) => (operator, left, right),
+            (IRExpression::Call { function, arguments: first }, [right]) => match (function.as_ref(), first.as_slice()) {
+                (IRExpression::Variable(operator), [left]) => (operator, left, right),
+                _ => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        let Some(infix) = infix_operator(operator.as_str()) else {
+            return Ok(None);
+        };
+        let left = self.generate_ir_expression(left, 0)?;
+        let right = self.generate_ir_expression(right, 0)?;
+        // Int division truncates, as the other backends' does
+        if operator.as_str() == "/" {
+            return Ok(Some(format!("Math.trunc({left} / {right})")));
+        }
+        Ok(Some(format!("({left} {infix} {right})")))
+    }
+
     /// Generate TypeScript import statement
     fn generate_import(&self, import: &IRImport) -> Result<String> {
+        // A lazy import is loaded by its first use, once
+        if let (Some(qualifier), true) = (import.qualifier, import.lazy) {
+            let promise = if self.emit_types { ": Promise<any> | undefined" } else { "" };
+            return Ok(format!(
+                "let $lazy_{qualifier}{promise};\nconst $load_{qualifier} = () => ($lazy_{qualifier} ??= import(\"{}\"));",
+                import.module,
+            ));
+        }
+        // A qualified import brings the whole module in as an object
+        if let (Some(qualifier), true) = (import.qualifier, import.items.is_empty()) {
+            return match self.module_system {
+                TypeScriptModuleSystem::ES2020 => Ok(format!("import * as {qualifier} from \"{}\";", import.module)),
+                TypeScriptModuleSystem::CommonJS => Ok(format!("const {qualifier} = require(\"{}\");", import.module)),
+                _ => Ok(format!("// TODO: Implement {:?} imports", self.module_system)),
+            };
+        }
         let mut items = Vec::new();
         for item in &import.items {
             if let Some(alias) = &item.alias {
@@ -265,79 +675,247 @@ impl TypeScriptBackend {
     /// Generate TypeScript function
     fn generate_function(&mut self, function: &IRFunction) -> Result<String> {
         let mut code = String::new();
+        let loops = tail_calls::self_calls(function).loops();
+        // Parameters a lambda captures are copied to a `const` each
+        // iteration, as closures made in earlier iterations must keep
+        // seeing their values
+        let copied = loops && captures_parameters(function);
+        let suffix = if copied { "$" } else { "" };
         
         // Function signature
         let params = function.parameters.iter()
-            .map(|p| format!("{}: {}", 
+            .map(|p| format!("{}{suffix}{}", 
                 utils::sanitize_identifier(p.name, "typescript"),
-                self.generate_ir_type(&p.type_hint)))
+                self.annotation(&p.type_hint)))
             .collect::<Vec<_>>()
             .join(", ");
         
-        let return_type = self.generate_ir_type(&function.return_type);
-        let visibility = if function.visibility == Visibility::Public { "export " } else { "" };
-        
-        // Handle effects (simplified as async for now)
-        let async_keyword = if !matches!(function.effects, IREffectSet::Empty) {
-            "async "
-        } else {
-            ""
+        let async_keyword = if self.async_functions.contains(&function.name) { "async " } else { "" };
+        let return_type = match (self.emit_types, async_keyword.is_empty()) {
+            (true, false) => format!(": Promise<{}>", self.generate_ir_type(&function.return_type)),
+            _ => self.annotation(&function.return_type),
         };
+        let visibility = if function.visibility == Visibility::Public { self.export_keyword() } else { "" };
+        
         
-        write!(code, "{}{}function {}({}): {} {{", 
+        write!(code, "{}{}function {}({}){} {{", 
                visibility, async_keyword, 
                utils::sanitize_identifier(function.name, "typescript"),
                params, return_type)?;
         
         // Function body
         writeln!(code)?;
-        let body = self.generate_ir_expression(&function.body, 1)?;
-        writeln!(code, "  return {body};")?;
+        let mut body = String::new();
+        if loops {
+            self.generate_loop(function, copied, &mut body)?;
+        } else {
+            self.generate_statements(&function.body, Destination::Return, 1, &mut body)?;
+        }
+        if self.stack_traces {
+            // The frame stays on the stack until the body returns or throws
+            writeln!(code, "  const $depth = $rt.enter({});", frame_name(function.name))?;
+            writeln!(code, "  try {{")?;
+            for line in body.lines() {
+                writeln!(code, "  {line}")?;
+            }
+            writeln!(code, "  }} catch (error) {{")?;
+            writeln!(code, "    throw $rt.trace(error);")?;
+            writeln!(code, "  }} finally {{")?;
+            writeln!(code, "    $rt.leave($depth);")?;
+            writeln!(code, "  }}")?;
+        } else {
+            code.push_str(&body);
+        }
         write!(code, "}}")?;
         
         Ok(code)
     }
     
+    /// The constant holding the frame `function` records
+    fn generate_frame(&self, function: &IRFunction) -> String {
+        let quoted = |text: &str| format!("\"{}\"", utils::escape_string(text, "typescript"));
+        let location = match self.locations.get(&function.name) {
+            Some(location) => quoted(location),
+            None => "undefined".to_string(),
+        };
+        format!(
+            "const {} = {{ module: {}, definition: {}, location: {location} }};",
+            frame_name(function.name),
+            quoted(&self.module_path),
+            quoted(function.name.as_str()),
+        )
+    }
+    
+    /// The body of a tail recursive function as a loop, whose parameters
+    /// are `copied` from ones named with a `$` suffix
+    fn generate_loop(&mut self, function: &IRFunction, copied: bool, code: &mut String) -> Result<()> {
+        let parameters: Vec<String> = function.parameters.iter()
+            .map(|parameter| utils::sanitize_identifier(parameter.name, "typescript"))
+            .collect();
+        writeln!(code, "  while (true) {{")?;
+        let assigned = if copied {
+            for parameter in &parameters {
+                writeln!(code, "    const {parameter} = {parameter}$;")?;
+            }
+            parameters.iter().map(|parameter| format!("{parameter}$")).collect()
+        } else {
+            parameters
+        };
+        self.tail_loop = Some(TailLoop { function: function.name, parameters: assigned });
+        let body = self.generate_statements(&function.body, Destination::Return, 2, code);
+        self.tail_loop = None;
+        body?;
+        writeln!(code, "  }}")?;
+        Ok(())
+    }
+    
+    /// Whether `expr` is a tail call continuing the loop being generated,
+    /// when its value is returned
+    fn continues_loop(&self, expr: &IRExpression, destination: Destination) -> bool {
+        let Some(tail_loop) = &self.tail_loop else { return false };
+        if !matches!(destination, Destination::Return) {
+            return false;
+        }
+        tail_calls::tail_calls(expr).into_iter().any(|call| match tail_calls::call_spine(call) {
+            Some((IRExpression::Variable(name), arguments)) => {
+                *name == tail_loop.function && arguments.len() == tail_loop.parameters.len()
+            }
+            _ => false,
+        })
+    }
+    
+    /// Statements computing `expr` and passing its value to `destination`
+    fn generate_statements(&mut self, expr: &IRExpression, destination: Destination, indent: usize, code: &mut String) -> Result<()> {
+        let indent_str = "  ".repeat(indent);
+        match expr {
+            // Normalized locals are unique, so they share the enclosing block
+            IRExpression::Let { bindings, body } => {
+                for binding in bindings {
+                    let name = utils::sanitize_identifier(binding.name, "typescript");
+                    if needs_statements(&binding.value) {
+                        writeln!(code, "{indent_str}let {name}{};", self.any_annotation())?;
+                        self.generate_statements(&binding.value, Destination::Assign(&name), indent, code)?;
+                    } else {
+                        writeln!(code, "{indent_str}const {name} = {};", self.generate_ir_expression(&binding.value, indent)?)?;
+                    }
+                }
+                self.generate_statements(body, destination, indent, code)
+            }
+            IRExpression::If { condition, then_branch, else_branch } if needs_statements(expr) || self.continues_loop(expr, destination) => {
+                writeln!(code, "{indent_str}if ({}) {{", self.generate_ir_expression(condition, 0)?)?;
+                self.generate_statements(then_branch, destination, indent + 1, code)?;
+                writeln!(code, "{indent_str}}} else {{")?;
+                self.generate_statements(else_branch, destination, indent + 1, code)?;
+                writeln!(code, "{indent_str}}}")?;
+                Ok(())
+            }
+            // In a loop, a match that may continue it is not a function
+            IRExpression::Match { value, cases } if self.continues_loop(expr, destination) => {
+                let subject = format!("$match{}", self.match_index);
+                self.match_index += 1;
+                let decision = decision_tree::compile(cases, &self.signatures);
+                writeln!(code, "{indent_str}const {subject} = {};", self.generate_ir_expression(value, 0)?)?;
+                self.generate_decision(&decision, cases, &subject, indent, code)
+            }
+            IRExpression::Call { .. } if self.continues_loop(expr, destination) => {
+                let (_, arguments) = tail_calls::call_spine(expr).expect("a call has a spine");
+                let values = arguments.iter()
+                    .map(|argument| self.generate_ir_expression(argument, indent))
+                    .collect::<Result<Vec<_>>>()?;
+                let parameters = &self.tail_loop.as_ref().expect("a loop is being generated").parameters;
+                match (parameters.as_slice(), values.as_slice()) {
+                    ([parameter], [value]) => writeln!(code, "{indent_str}{parameter} = {value};")?,
+                    _ => writeln!(code, "{indent_str}[{}] = [{}];", parameters.join(", "), values.join(", "))?,
+                }
+                writeln!(code, "{indent_str}continue;")?;
+                Ok(())
+            }
+            IRExpression::Block(expressions) if !expressions.is_empty() => {
+                let (last, rest) = expressions.split_last().expect("the block is not empty");
+                for expr in rest {
+                    self.generate_statements(expr, Destination::Discard, indent, code)?;
+                }
+                self.generate_statements(last, destination, indent, code)
+            }
+            _ => {
+                let value = self.generate_ir_expression(expr, indent)?;
+                match destination {
+                    Destination::Return => writeln!(code, "{indent_str}return {value};")?,
+                    Destination::Assign(name) => writeln!(code, "{indent_str}{name} = {value};")?,
+                    Destination::Discard => writeln!(code, "{indent_str}{value};")?,
+                }
+                Ok(())
+            }
+        }
+    }
+    
+    /// The body of an arrow function computing `expr`: a block when it
+    /// needs statements, and otherwise an expression, parenthesized when
+    /// it is an object literal
+    fn generate_arrow_body(&mut self, expr: &IRExpression, indent: usize) -> Result<String> {
+        if needs_statements(expr) {
+            // Its returns are the arrow function's, not the loop's
+            let tail_loop = self.tail_loop.take();
+            let mut body = String::new();
+            let statements = self.generate_statements(expr, Destination::Return, indent + 1, &mut body);
+            self.tail_loop = tail_loop;
+            statements?;
+            return Ok(format!("{{\n{body}{}}}", "  ".repeat(indent)));
+        }
+        let code = self.generate_ir_expression(expr, indent)?;
+        if code.starts_with('{') {
+            Ok(format!("({code})"))
+        } else {
+            Ok(code)
+        }
+    }
+    
     /// Generate TypeScript expression
     fn generate_ir_expression(&mut self, expr: &IRExpression, indent: usize) -> Result<String> {
-        let indent_str = "  ".repeat(indent);
-        
         match expr {
             IRExpression::Literal(lit) => Ok(self.generate_ir_literal(lit)),
             IRExpression::Variable(symbol) => {
                 Ok(utils::sanitize_identifier(*symbol, "typescript"))
             }
             IRExpression::Call { function, arguments } => {
+                if let Some(code) = self.generate_intrinsic_call(function, arguments)? {
+                    return Ok(code);
+                }
+                if let Some(code) = self.generate_operator_call(function, arguments)? {
+                    return Ok(code);
+                }
                 let func_code = self.generate_ir_expression(function, 0)?;
                 let args_code = arguments.iter()
                     .map(|arg| self.generate_ir_expression(arg, 0))
                     .collect::<Result<Vec<_>>>()?
                     .join(", ");
-                Ok(format!("{func_code}({args_code})"))
+                match function.as_ref() {
+                    IRExpression::Variable(name) if self.async_functions.contains(name) => {
+                        Ok(format!("(await {func_code}({args_code}))"))
+                    }
+                    _ => Ok(format!("{func_code}({args_code})")),
+                }
             }
             IRExpression::Lambda { parameters, body, .. } => {
                 let params = parameters.iter()
-                    .map(|p| format!("{}: {}", 
+                    .map(|p| format!("{}{}", 
                         utils::sanitize_identifier(p.name, "typescript"),
-                        self.generate_ir_type(&p.type_hint)))
+                        self.annotation(&p.type_hint)))
                     .collect::<Vec<_>>()
                     .join(", ");
-                let body_code = self.generate_ir_expression(body, 0)?;
-                Ok(format!("({params}) => {body_code}"))
+                let body_code = self.generate_arrow_body(body, indent)?;
+                let async_keyword = if awaits(body, &self.async_functions, self.effect_lowering) { "async " } else { "" };
+                Ok(format!("{async_keyword}({params}) => {body_code}"))
             }
-            IRExpression::Let { bindings, body } => {
-                let mut code = String::new();
-                writeln!(code, "{{")?;
-                for binding in bindings {
-                    writeln!(code, "{}const {} = {};", 
-                             indent_str,
-                             utils::sanitize_identifier(binding.name, "typescript"),
-                             self.generate_ir_expression(&binding.value, 0)?)?;
+            IRExpression::Block(expressions) if expressions.is_empty() => Ok("undefined".to_string()),
+            // Statements in expression position run in an arrow function
+            IRExpression::Let { .. } | IRExpression::Block(_) => {
+                let body = self.generate_arrow_body(expr, indent)?;
+                if awaits(expr, &self.async_functions, self.effect_lowering) {
+                    Ok(format!("(await (async () => {body})())"))
+                } else {
+                    Ok(format!("(() => {body})()"))
                 }
-                let body_code = self.generate_ir_expression(body, indent)?;
-                writeln!(code, "{indent_str}return {body_code};")?;
-                write!(code, "{}}}", "  ".repeat(indent.saturating_sub(1)))?;
-                Ok(code)
             }
             IRExpression::If { condition, then_branch, else_branch } => {
                 let cond_code = self.generate_ir_expression(condition, 0)?;
@@ -345,33 +923,246 @@ impl TypeScriptBackend {
                 let else_code = self.generate_ir_expression(else_branch, 0)?;
                 Ok(format!("({cond_code} ? {then_code} : {else_code})"))
             }
-            IRExpression::Block(expressions) => {
-                if expressions.is_empty() {
-                    return Ok("undefined".to_string());
+            IRExpression::Field { record, field } => {
+                let record_code = match record.as_ref() {
+                    IRExpression::Variable(qualifier) if self.lazy_modules.contains(qualifier) => {
+                        format!("(await $load_{qualifier}())")
+                    }
+                    _ => self.generate_ir_expression(record, 0)?,
+                };
+                Ok(format!("{}.{}", record_code, utils::sanitize_identifier(*field, "typescript")))
+            }
+            IRExpression::RecordUpdate { record, fields } => {
+                let mut parts = vec![format!("...{}", self.generate_ir_expression(record, 0)?)];
+                for (name, value) in fields {
+                    parts.push(format!("{}: {}", name, self.generate_ir_expression(value, 0)?));
                 }
-                
-                let mut code = String::new();
-                writeln!(code, "{{")?;
-                for (i, expr) in expressions.iter().enumerate() {
-                    let expr_code = self.generate_ir_expression(expr, indent + 1)?;
-                    if i == expressions.len() - 1 {
-                        writeln!(code, "{}return {};", 
-                                "  ".repeat(indent + 1), expr_code)?;
+                Ok(format!("{{ {} }}", parts.join(", ")))
+            }
+            IRExpression::Tuple(elements) => {
+                let elements_code = elements.iter()
+                    .map(|element| self.generate_ir_expression(element, 0))
+                    .collect::<Result<Vec<_>>>()?
+                    .join(", ");
+                Ok(format!("[{elements_code}]"))
+            }
+            IRExpression::TupleGet { tuple, index } => {
+                Ok(format!("{}[{}]", self.generate_ir_expression(tuple, 0)?, index))
+            }
+            // The host's event loop handles `Async` under either lowering
+            IRExpression::Effect { effect, operation, arguments } if *effect == symbols::ASYNC() && arguments.len() == 1 => {
+                let argument = self.generate_ir_expression(&arguments[0], 0)?;
+                match operation.as_str() {
+                    "await" => Ok(format!("(await {argument})")),
+                    "spawn" => Ok(format!("$rt.spawn({argument})")),
+                    _ => Err(crate::CompilerError::CodeGen { message: format!("Unknown operation Async.{operation}") }),
+                }
+            }
+            IRExpression::Effect { effect, operation, arguments } => {
+                let mut args = vec![format!("\"{effect}\""), format!("\"{operation}\"")];
+                for argument in arguments {
+                    args.push(self.generate_ir_expression(argument, 0)?);
+                }
+                match self.effect_lowering {
+                    TypeScriptEffectLowering::Handlers => Ok(format!("$rt.perform({})", args.join(", "))),
+                    TypeScriptEffectLowering::AsyncAwait => Ok(format!("(await $rt.performAsync({}))", args.join(", "))),
+                }
+            }
+            IRExpression::Handle { expression, mode, handlers, return_handler } => {
+                let asynchronous = self.effect_lowering == TypeScriptEffectLowering::AsyncAwait;
+                let async_keyword = if asynchronous { "async " } else { "" };
+                let body = self.generate_arrow_body(expression, indent)?;
+                let mut clauses = Vec::new();
+                for handler in handlers {
+                    let params = handler.parameters.iter()
+                        .chain(std::iter::once(&handler.continuation))
+                        .map(|name| format!("{}{}", utils::sanitize_identifier(*name, "typescript"), self.any_annotation()))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    let clause_body = self.generate_arrow_body(&handler.body, indent)?;
+                    clauses.push(format!("\"{}.{}\": {async_keyword}({params}) => {clause_body}",
+                                         handler.effect, handler.operation));
+                }
+                let mut args = vec![format!("{async_keyword}() => {body}"), format!("{{ {} }}", clauses.join(", "))];
+                if let Some(handler) = return_handler {
+                    let handler_code = self.generate_ir_expression(handler, 0)?;
+                    args.push(if asynchronous && !handler_code.starts_with("async ") {
+                        format!("async {handler_code}")
                     } else {
-                        writeln!(code, "{}{};", 
-                                "  ".repeat(indent + 1), expr_code)?;
+                        handler_code
+                    });
+                }
+                if *mode == HandlerMode::Shallow {
+                    if return_handler.is_none() {
+                        args.push("undefined".to_string());
                     }
+                    args.push("true".to_string());
+                }
+                if asynchronous {
+                    Ok(format!("(await $rt.handleAsync({}))", args.join(", ")))
+                } else {
+                    Ok(format!("$rt.handle({})", args.join(", ")))
                 }
-                write!(code, "{}}}", "  ".repeat(indent))?;
-                Ok(code)
             }
-            _ => {
-                // Handle other expression types
-                Ok("/* TODO: Implement expression */".to_string())
+            IRExpression::Resume { value, continuation } => {
+                Ok(format!("{}({})",
+                           utils::sanitize_identifier(*continuation, "typescript"),
+                           self.generate_ir_expression(value, 0)?))
+            }
+            IRExpression::Match { value, cases } => self.generate_match(value, cases, indent),
+            // A cell is the parameter of an arrow function called with its
+            // initial value
+            IRExpression::Cell { name, initial, body } => {
+                let initial = self.generate_ir_expression(initial, 0)?;
+                let body_code = self.generate_arrow_body(body, indent)?;
+                let name = utils::sanitize_identifier(*name, "typescript");
+                if awaits(body, &self.async_functions, self.effect_lowering) {
+                    Ok(format!("(await (async ({name}{}) => {body_code})({initial}))", self.any_annotation()))
+                } else {
+                    Ok(format!("(({name}{}) => {body_code})({initial})", self.any_annotation()))
+                }
+            }
+            IRExpression::CellGet(name) => Ok(utils::sanitize_identifier(*name, "typescript")),
+            IRExpression::CellSet { name, value } => {
+                Ok(format!("({} = {}, undefined)",
+                           utils::sanitize_identifier(*name, "typescript"),
+                           self.generate_ir_expression(value, 0)?))
             }
         }
     }
     
+    /// A match becomes an arrow function called with the value, its body
+    /// the decision tree: a `switch` on the tag for constructors and `if`
+    /// chains for literals, with a throw only where no case can match
+    fn generate_match(&mut self, value: &IRExpression, cases: &[IRMatchCase], indent: usize) -> Result<String> {
+        let subject = format!("$match{}", self.match_index);
+        self.match_index += 1;
+        let decision = decision_tree::compile(cases, &self.signatures);
+        let value = self.generate_ir_expression(value, 0)?;
+        let tail_loop = self.tail_loop.take();
+        let mut body = String::new();
+        let statements = self.generate_decision(&decision, cases, &subject, indent + 1, &mut body);
+        self.tail_loop = tail_loop;
+        statements?;
+        
+        let asynchronous = cases.iter()
+            .flat_map(|case| case.guard.iter().chain([&case.body]))
+            .any(|expr| awaits(expr, &self.async_functions, self.effect_lowering));
+        let (async_keyword, await_keyword) = if asynchronous { ("async ", "await ") } else { ("", "") };
+        Ok(format!(
+            "({await_keyword}({async_keyword}({subject}{}) => {{\n{body}{}}})({value}))",
+            self.any_annotation(),
+            "  ".repeat(indent)
+        ))
+    }
+    
+    /// Statements for `decision`, every path of which returns or throws
+    fn generate_decision(
+        &mut self,
+        decision: &Decision,
+        cases: &[IRMatchCase],
+        subject: &str,
+        indent: usize,
+        code: &mut String,
+    ) -> Result<()> {
+        let indent_str = "  ".repeat(indent);
+        match decision {
+            Decision::Fail => writeln!(code, "{indent_str}throw new Error(\"Match failure\");")?,
+            Decision::Leaf { case, bindings, otherwise } => {
+                let case = &cases[*case];
+                let fallback = otherwise.as_deref().filter(|_| case.guard.is_some());
+                // Bindings of a guarded case get a block of their own, as the
+                // cases after it may bind the same names
+                let scoped = fallback.is_some() && !bindings.is_empty();
+                let inner = if scoped { indent + 1 } else { indent };
+                let inner_str = "  ".repeat(inner);
+                if scoped {
+                    writeln!(code, "{indent_str}{{")?;
+                }
+                for (name, access) in bindings {
+                    writeln!(code, "{inner_str}const {} = {};",
+                             utils::sanitize_identifier(*name, "typescript"),
+                             access_code(access, subject))?;
+                }
+                match (&case.guard, fallback) {
+                    (Some(guard), Some(_)) if needs_statements(&case.body) || self.continues_loop(&case.body, Destination::Return) => {
+                        let mut body = String::new();
+                        self.generate_statements(&case.body, Destination::Return, inner + 1, &mut body)?;
+                        let guard = self.generate_ir_expression(guard, 0)?;
+                        writeln!(code, "{inner_str}if ({guard}) {{")?;
+                        code.push_str(&body);
+                        writeln!(code, "{inner_str}}}")?;
+                    }
+                    (Some(guard), Some(_)) => {
+                        let body = self.generate_ir_expression(&case.body, inner)?;
+                        let guard = self.generate_ir_expression(guard, 0)?;
+                        writeln!(code, "{inner_str}if ({guard}) return {body};")?;
+                    }
+                    _ => self.generate_statements(&case.body, Destination::Return, inner, code)?,
+                }
+                if scoped {
+                    writeln!(code, "{indent_str}}}")?;
+                }
+                if let Some(fallback) = fallback {
+                    self.generate_decision(fallback, cases, subject, indent, code)?;
+                }
+            }
+            Decision::Switch { access, branches, default } => {
+                let value = access_code(access, subject);
+                if matches!(branches[0].0, Test::Constructor(_)) {
+                    writeln!(code, "{indent_str}switch ({value}.tag) {{")?;
+                    for (index, (test, decision)) in branches.iter().enumerate() {
+                        let Test::Constructor(name) = test else { unreachable!("constructor switches only test constructors") };
+                        // The last constructor of a complete switch takes the
+                        // default, for the type checker's sake
+                        let label = if default.is_none() && index == branches.len() - 1 {
+                            format!("case \"{name}\": default:")
+                        } else {
+                            format!("case \"{name}\":")
+                        };
+                        writeln!(code, "{indent_str}  {label} {{")?;
+                        self.generate_decision(decision, cases, subject, indent + 2, code)?;
+                        writeln!(code, "{indent_str}  }}")?;
+                    }
+                    if let Some(default) = default {
+                        writeln!(code, "{indent_str}  default: {{")?;
+                        self.generate_decision(default, cases, subject, indent + 2, code)?;
+                        writeln!(code, "{indent_str}  }}")?;
+                    }
+                    writeln!(code, "{indent_str}}}")?;
+                } else {
+                    let (tested, last) = match default {
+                        Some(default) => (&branches[..], default.as_ref()),
+                        None => {
+                            let (last, tested) = branches.split_last().expect("a switch has branches");
+                            (tested, &last.1)
+                        }
+                    };
+                    for (test, decision) in tested {
+                        writeln!(code, "{indent_str}if ({}) {{", self.test_condition(test, &value))?;
+                        self.generate_decision(decision, cases, subject, indent + 1, code)?;
+                        writeln!(code, "{indent_str}}}")?;
+                    }
+                    self.generate_decision(last, cases, subject, indent, code)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    
+    /// The condition of a literal test of `value`
+    fn test_condition(&mut self, test: &Test, value: &str) -> String {
+        match test {
+            Test::Int(n) => format!("{value} === {n}"),
+            Test::Range(low, high) => format!("{value} >= {low} && {value} <= {high}"),
+            Test::Float(f) => format!("{value} === {}", self.generate_ir_literal(&IRLiteral::Float(*f))),
+            Test::Bool(true) => value.to_string(),
+            Test::Bool(false) => format!("!{value}"),
+            Test::String(s) => format!("{value} === {}", self.generate_ir_literal(&IRLiteral::String(s.clone()))),
+            Test::Constructor(name) => format!("{value}.tag === \"{name}\""),
+        }
+    }
+    
     /// Generate TypeScript literal
     fn generate_ir_literal(&mut self, lit: &IRLiteral) -> String {
         match lit {
@@ -446,16 +1237,18 @@ impl TypeScriptBackend {
     
     /// Generate other constructs
     fn generate_constant(&mut self, constant: &IRConstant) -> Result<String> {
-        Ok(format!("export const {}: {} = {};",
+        Ok(format!("{}const {}{} = {};",
+                   self.export_keyword(),
                    utils::sanitize_identifier(constant.name, "typescript"),
-                   self.generate_ir_type(&constant.type_hint),
-                   self.generate_ir_literal(&match &constant.value {
-                       IRExpression::Literal(lit) => lit.clone(),
-                       _ => IRLiteral::Unit, // Simplified
-                   })))
+                   self.annotation(&constant.type_hint),
+                   self.generate_ir_expression(&constant.value, 0)?))
     }
     
     fn generate_export(&self, export: &IRExport) -> Result<String> {
+        if self.exports_commonjs() {
+            // Listed in `module.exports`
+            return Ok(String::new());
+        }
         if let Some(alias) = &export.alias {
             Ok(format!("export {{ {} as {} }};", export.name, alias))
         } else {
@@ -463,9 +1256,33 @@ impl TypeScriptBackend {
         }
     }
     
-    fn generate_type_definition(&self, _type_def: &IRTypeDefinition) -> Result<String> {
-        // Simplified implementation
-        Ok("// TODO: Type definition".to_string())
+    /// A data type becomes a function per constructor, taking the fields
+    /// one at a time as applications pass them and building the tagged
+    /// object; a constructor without fields is the object itself. Other
+    /// types have no runtime representation.
+    fn generate_type_definition(&self, type_def: &IRTypeDefinition) -> Result<String> {
+        let IRTypeDefinitionKind::Variant(constructors) = &type_def.definition else {
+            return Ok(String::new());
+        };
+        let definitions: Vec<String> = constructors.iter()
+            .map(|(name, fields)| {
+                let slots: Vec<String> = (0..fields.len()).map(|i| format!("_{i}")).collect();
+                let object = std::iter::once(format!("tag: \"{name}\""))
+                    .chain(slots.iter().cloned())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let parameters: String = slots.iter()
+                    .map(|slot| format!("({slot}{}) => ", self.any_annotation()))
+                    .collect();
+                let value = if slots.is_empty() {
+                    format!("{{ {object} }}")
+                } else {
+                    format!("{parameters}({{ {object} }})")
+                };
+                format!("const {} = {value};", utils::sanitize_identifier(*name, "typescript"))
+            })
+            .collect();
+        Ok(definitions.join("\n"))
     }
     
     fn generate_type_definitions(&self, _ir: &IR) -> Result<String> {
@@ -480,14 +1297,1180 @@ impl TypeScriptBackend {
         Ok(code)
     }
     
+    /// The runtime without types: effect handlers, currying and match
+    /// failures
+    fn generate_javascript_runtime(&self) -> String {
+        let export = self.export_keyword();
+        let mut code = format!(
+            r#"// x Language Runtime for JavaScript
+
+// Effect System Runtime
+{export}class EffectContext {{
+  constructor() {{
+    this.handlers = new Map();
+  }}
+
+  addHandler(effect, handler) {{
+    this.handlers.set(effect, handler);
+  }}
+
+  perform(effect, operation, ...args) {{
+    const handler = this.handlers.get(effect);
+    if (!handler) {{
+      throw new Error(`Unhandled effect: ${{effect}}.${{operation}}`);
+    }}
+    return handler(operation, ...args);
+  }}
+}}
+
+// Utility Functions
+{export}function curry(fn) {{
+  return function curried(...args) {{
+    if (args.length >= fn.length) {{
+      return fn.apply(this, args);
+    }}
+    return (...more) => curried.apply(this, args.concat(more));
+  }};
+}}
+
+// Pattern matching support
+{export}class MatchError extends Error {{
+  constructor(value) {{
+    super(`Non-exhaustive pattern match for value: ${{JSON.stringify(value)}}`);
+  }}
+}}
+
+"#
+        );
+        code.push_str(&self.generate_handler_runtime());
+        if self.stack_traces {
+            code.push_str(&self.generate_frame_runtime());
+        }
+        if self.exports_commonjs() {
+            let (handle, perform) = self.handler_functions();
+            let frames = if self.stack_traces { ", enter, leave, trace" } else { "" };
+            code.push_str(&format!("\nmodule.exports = {{ EffectContext, curry, MatchError, {handle}, {perform}{frames} }};\n"));
+        }
+        code
+    }
+    
+    /// Declaration file for a module: its type definitions and the
+    /// exported values, typed by the signatures the checker inferred
+    fn generate_declarations(
+        &self,
+        module: &Module,
+        ir_module: &IRModule,
+        inferred: &HashMap<Symbol, TypeScheme>,
+    ) -> Result<String> {
+        let mut code = String::new();
+        writeln!(code, "// Declarations for x Language module: {}", ir_module.name)?;
+        writeln!(code)?;
+        
+        for item in &module.items {
+            if let Item::TypeDef(type_def) = item {
+                writeln!(code, "{}", declare_type_definition(type_def))?;
+            }
+        }
+        if module.items.iter().any(|item| matches!(item, Item::TypeDef(_))) {
+            writeln!(code)?;
+        }
+        
+        let records: HashMap<Vec<Symbol>, Symbol> = module.items.iter()
+            .filter_map(|item| match item {
+                Item::TypeDef(TypeDef { name, kind: TypeDefKind::Alias(Type::Record { fields, .. }), .. }) => {
+                    let mut fields: Vec<Symbol> = fields.keys().copied().collect();
+                    fields.sort_by_key(|field| field.as_str());
+                    Some((fields, *name))
+                }
+                _ => None,
+            })
+            .collect();
+        let async_functions = self.find_async_functions(ir_module);
+        let explicit: HashSet<Symbol> = ir_module.exports.iter().map(|export| export.name).collect();
+        for constant in &ir_module.constants {
+            let typ = inferred.get(&constant.name)
+                .map_or_else(|| "unknown".to_string(), |scheme| DeclaredType::new(scheme, &records).render(&scheme.body));
+            writeln!(code, "export declare const {}: {typ};",
+                     utils::sanitize_identifier(constant.name, "typescript"))?;
+        }
+        for function in &ir_module.functions {
+            let public = function.visibility == Visibility::Public;
+            if !public && !explicit.contains(&function.name) {
+                continue;
+            }
+            let export = if public { "export " } else { "" };
+            writeln!(code, "{export}declare {};", self.declare_function(function, async_functions.contains(&function.name), inferred.get(&function.name), &records))?;
+        }
+        for export in &ir_module.exports {
+            match &export.alias {
+                Some(alias) => writeln!(code, "export {{ {} as {} }};", export.name, alias)?,
+                None => writeln!(code, "export {{ {} }};", export.name)?,
+            }
+        }
+        
+        Ok(code)
+    }
+    
+    /// `function f<A>(x: A): A`, naming the parameters after the function's
+    /// own when the inferred type takes as many arguments; asynchronous
+    /// functions return a `Promise`
+    fn declare_function(
+        &self,
+        function: &IRFunction,
+        asynchronous: bool,
+        scheme: Option<&TypeScheme>,
+        records: &HashMap<Vec<Symbol>, Symbol>,
+    ) -> String {
+        let name = utils::sanitize_identifier(function.name, "typescript");
+        let Some(scheme) = scheme else {
+            let params: Vec<String> = function.parameters.iter()
+                .map(|p| format!("{}: unknown", utils::sanitize_identifier(p.name, "typescript")))
+                .collect();
+            return format!("function {name}({}): unknown", params.join(", "));
+        };
+        let declared = DeclaredType::new(scheme, records);
+        let (params, return_type) = match strip_forall(&scheme.body) {
+            CheckerType::Fun { params, return_type, .. } if params.len() == function.parameters.len() => {
+                (params.iter().collect::<Vec<_>>(), return_type.as_ref())
+            }
+            _ => return format!("const {name}: {}", declared.render(&scheme.body)),
+        };
+        let params: Vec<String> = function.parameters.iter().zip(params)
+            .map(|(p, typ)| format!("{}: {}", utils::sanitize_identifier(p.name, "typescript"), declared.render(typ)))
+            .collect();
+        let mut return_type = declared.render(return_type);
+        if asynchronous {
+            return_type = format!("Promise<{return_type}>");
+        }
+        format!("function {name}{}({}): {return_type}", declared.generics(), params.join(", "))
+    }
+    
+    /// Runtime names of the functions installing handlers and performing
+    /// operations
+    fn handler_functions(&self) -> (&'static str, &'static str) {
+        match self.effect_lowering {
+            TypeScriptEffectLowering::Handlers => ("handle", "perform"),
+            TypeScriptEffectLowering::AsyncAwait => ("handleAsync", "performAsync"),
+        }
+    }
+    
+    /// The handler stack with `handle` and `perform`, or their `async`
+    /// counterparts
+    fn generate_handler_runtime(&self) -> String {
+        let export = self.export_keyword();
+        let (handle, perform) = self.handler_functions();
+        let asynchronous = self.effect_lowering == TypeScriptEffectLowering::AsyncAwait;
+        let (async_, await_) = if asynchronous { ("async ", "await ") } else { ("", "") };
+        let typed = |annotation: &str| if self.emit_types { annotation.to_string() } else { String::new() };
+        let any = typed(": any");
+        let returns = typed(if asynchronous { ": Promise<any>" } else { ": any" });
+        let stack = typed(": { clauses: Record<string, Function>; shallow: boolean; spent: boolean }[]");
+        let clauses = typed(": Record<string, Function>");
+        let body = typed(if asynchronous { ": () => Promise<any>" } else { ": () => any" });
+        let text = typed(": string");
+        let args = typed(": any[]");
+        let fields = typed("  frame: any;\n  value: any;\n\n");
+        let task = typed(": (unit: undefined) => any");
+        let promise = typed(": Promise<any>");
+        format!(
+            r#"// Effect handlers, innermost last
+const handlerStack{stack} = [];
+
+// Thrown by an operation whose clause finished without resuming
+class Abort {{
+{fields}  constructor(frame{any}, value{any}) {{
+    this.frame = frame;
+    this.value = value;
+  }}
+}}
+
+// A shallow handler handles one operation; what it resumes runs without it
+{export}{async_}function {handle}(body{body}, clauses{clauses}, onReturn = {async_}(value{any}) => value, shallow = false){returns} {{
+  const frame = {{ clauses, shallow, spent: false }};
+  const depth = handlerStack.length;
+  handlerStack.push(frame);
+  let value;
+  try {{
+    value = {await_}body();
+  }} catch (error) {{
+    if (error instanceof Abort && error.frame === frame) {{
+      return error.value;
+    }}
+    throw error;
+  }} finally {{
+    handlerStack.length = depth;
+  }}
+  return frame.spent ? value : onReturn(value);
+}}
+
+{export}{async_}function {perform}(effect{text}, operation{text}, ...args{args}){returns} {{
+  const key = `${{effect}}.${{operation}}`;
+  for (let i = handlerStack.length - 1; i >= 0; i--) {{
+    const frame = handlerStack[i];
+    const clause = frame.spent ? undefined : frame.clauses[key];
+    if (!clause) {{
+      continue;
+    }}
+    // The clause runs outside the handler it belongs to
+    const inner = handlerStack.splice(i);
+    frame.spent = frame.shallow;
+    let resumed = false;
+    let resumedWith;
+    let result;
+    try {{
+      result = {await_}clause(...args, (value{any}) => {{
+        resumed = true;
+        resumedWith = value;
+        return value;
+      }});
+    }} finally {{
+      handlerStack.push(...inner);
+    }}
+    if (resumed) {{
+      return resumedWith;
+    }}
+    throw new Abort(frame, result);
+  }}
+  throw new Error(`Unhandled effect: ${{key}}`);
+}}
+
+// Async.spawn: the task starts once the spawning code next waits
+{export}function spawn(task{task}){promise} {{
+  return Promise.resolve().then(() => task(undefined));
+}}
+"#
+        )
+    }
+    
+    /// The stack of x frames, and `trace` giving errors a stack of them
+    fn generate_frame_runtime(&self) -> String {
+        let export = self.export_keyword();
+        let typed = |annotation: &str| if self.emit_types { annotation.to_string() } else { String::new() };
+        let interface = typed("\nexport interface Frame {\n  module: string;\n  definition: string;\n  location?: string;\n}\n");
+        let frame = typed(": Frame");
+        let frames = typed(": Frame[]");
+        let set = typed(": WeakSet<Error>");
+        let depth = typed(": number");
+        let error = typed(": unknown");
+        format!(
+            r#"{interface}
+// Frames of the x functions running, innermost last
+const frameStack{frames} = [];
+// Errors whose stack lists x frames already
+const traced{set} = new WeakSet();
+
+{export}function enter(frame{frame}){depth} {{
+  frameStack.push(frame);
+  return frameStack.length - 1;
+}}
+
+{export}function leave(depth{depth}) {{
+  frameStack.length = depth;
+}}
+
+// Called as an error leaves each function; the innermost one replaces the
+// error's stack with the frames running when it was thrown
+{export}function trace(error{error}) {{
+  if (error instanceof Error && !traced.has(error)) {{
+    traced.add(error);
+    const lines = frameStack.map(({{ module, definition, location }}) =>
+      `    at ${{module}}.${{definition}}${{location ? ` (${{location}})` : ""}}`);
+    error.stack = [`${{error.name}}: ${{error.message}}`, ...lines.reverse()].join("\n");
+  }}
+  return error;
+}}
+"#
+        )
+    }
+    
     fn needs_runtime(&self, _ir: &IR) -> bool {
         // For now, always include runtime
         true
     }
 }
 
+/// Normalize `module` and verify the result, timing both
+fn normalize(module: &mut IRModule, passes: &mut Vec<PassTiming>) -> Result<()> {
+    let start = std::time::Instant::now();
+    anf::normalize(module);
+    passes.push(PassTiming { name: "normalize", duration: start.elapsed() });
+    let start = std::time::Instant::now();
+    let errors = verify::verify_normalized(module);
+    passes.push(PassTiming { name: "verify", duration: start.elapsed() });
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(CompilerError::InvalidIR { module: module.name.to_string(), errors })
+    }
+}
+
+/// Where the value computed by generated statements goes
+#[derive(Debug, Clone, Copy)]
+enum Destination<'a> {
+    Return,
+    /// Assigned to a variable declared before the statements
+    Assign(&'a str),
+    Discard,
+}
+
+/// Name of the constant holding the frame of function `name`
+fn frame_name(name: Symbol) -> String {
+    format!("$frame_{}", utils::sanitize_identifier(name, "typescript"))
+}
+
+/// Whether a lambda in the body of `function` captures one of its parameters
+fn captures_parameters(function: &IRFunction) -> bool {
+    let mut captures = false;
+    function.body.walk(&mut |expr| {
+        if let IRExpression::Lambda { .. } = expr {
+            captures |= expr.free_variables().iter()
+                .any(|name| function.parameters.iter().any(|parameter| parameter.name == *name));
+        }
+        !captures
+    });
+    captures
+}
+
+/// Whether `expr` is emitted as statements: lets, and the conditionals and
+/// blocks containing them
+fn needs_statements(expr: &IRExpression) -> bool {
+    match expr {
+        IRExpression::Let { .. } => true,
+        IRExpression::If { then_branch, else_branch, .. } => needs_statements(then_branch) || needs_statements(else_branch),
+        IRExpression::Block(expressions) => expressions.len() > 1 || expressions.iter().any(needs_statements),
+        _ => false,
+    }
+}
+
+/// The code reading the part of `subject` at `access`; constructor fields
+/// are `_0`, `_1`, ...
+fn access_code(access: &Access, subject: &str) -> String {
+    match access {
+        Access::Root => subject.to_string(),
+        Access::Field { parent, index, .. } => format!("{}._{index}", access_code(parent, subject)),
+        Access::Element { parent, index } => format!("{}[{index}]", access_code(parent, subject)),
+        Access::RecordField { parent, field } => {
+            format!("{}.{}", access_code(parent, subject), utils::sanitize_identifier(*field, "typescript"))
+        }
+    }
+}
+
+/// Whether the expression awaits, outside any lambda it contains: it waits
+/// for `Async`, calls one of `async_functions`, uses a lazy module among
+/// them, or performs or handles an effect lowered to async/await
+fn awaits(expr: &IRExpression, async_functions: &HashSet<Symbol>, lowering: TypeScriptEffectLowering) -> bool {
+    let asynchronous = lowering == TypeScriptEffectLowering::AsyncAwait;
+    let mut found = false;
+    expr.walk(&mut |expr| {
+        match expr {
+            IRExpression::Effect { effect, operation, .. } if *effect == symbols::ASYNC() => {
+                found |= asynchronous || operation.as_str() == "await";
+            }
+            IRExpression::Effect { .. } | IRExpression::Handle { .. } => found |= asynchronous,
+            IRExpression::Call { function, .. } => {
+                found |= matches!(function.as_ref(), IRExpression::Variable(name) if async_functions.contains(name));
+            }
+            IRExpression::Field { record, .. } => {
+                found |= matches!(record.as_ref(), IRExpression::Variable(name) if async_functions.contains(name));
+            }
+            _ => {}
+        }
+        !found && !matches!(expr, IRExpression::Lambda { .. })
+    });
+    found
+}
+
+/// Whether the expression performs or handles effects anywhere
+fn uses_effects(expr: &IRExpression) -> bool {
+    let mut found = false;
+    expr.walk(&mut |expr| {
+        found |= matches!(expr, IRExpression::Effect { .. } | IRExpression::Handle { .. });
+        !found
+    });
+    found
+}
+
+/// Renders checker types in TypeScript, naming the scheme's quantified
+/// variables `A`, `B`, ...
+struct DeclaredType<'a> {
+    variables: HashMap<x_checker::TypeVar, String>,
+    /// Record aliases by their sorted field names, to name inferred record types
+    records: &'a HashMap<Vec<Symbol>, Symbol>,
+}
+
+impl<'a> DeclaredType<'a> {
+    fn new(scheme: &TypeScheme, records: &'a HashMap<Vec<Symbol>, Symbol>) -> Self {
+        let mut quantified = scheme.type_vars.clone();
+        if let CheckerType::Forall { type_vars, .. } = &scheme.body {
+            quantified.extend(type_vars);
+        }
+        // Named in order of first occurrence, so `A` is the first parameter's
+        let mut type_vars = Vec::new();
+        collect_type_vars(&scheme.body, &mut type_vars);
+        type_vars.retain(|var| quantified.contains(var));
+        let variables = type_vars.into_iter().enumerate()
+            .map(|(i, var)| {
+                let name = match u8::try_from(i) {
+                    Ok(i) if i < 26 => char::from(b'A' + i).to_string(),
+                    _ => format!("T{i}"),
+                };
+                (var, name)
+            })
+            .collect();
+        DeclaredType { variables, records }
+    }
+    
+    /// `<A, B>`, or nothing for a monomorphic type
+    fn generics(&self) -> String {
+        if self.variables.is_empty() {
+            return String::new();
+        }
+        let mut names: Vec<&String> = self.variables.values().collect();
+        names.sort_by_key(|name| (name.len(), name.as_str()));
+        format!("<{}>", names.iter().map(|name| name.as_str()).collect::<Vec<_>>().join(", "))
+    }
+    
+    fn render(&self, typ: &CheckerType) -> String {
+        match typ {
+            CheckerType::Var(var) => self.variables.get(var).cloned().unwrap_or_else(|| "unknown".to_string()),
+            CheckerType::Con(name) => declared_constructor(name.as_str()),
+            CheckerType::App(con, args) => {
+                let args: Vec<String> = args.iter().map(|arg| self.render(arg)).collect();
+                match (con.as_ref(), args.as_slice()) {
+                    (CheckerType::Con(name), [element]) if name.as_str() == "List" => format!("{element}[]"),
+                    _ => format!("{}<{}>", self.render(con), args.join(", ")),
+                }
+            }
+            CheckerType::Fun { params, return_type, .. } => {
+                let params: Vec<String> = params.iter().enumerate()
+                    .map(|(i, param)| format!("arg{i}: {}", self.render(param)))
+                    .collect();
+                format!("({}) => {}", params.join(", "), self.render(return_type))
+            }
+            CheckerType::Forall { body, .. } => self.render(body),
+            CheckerType::Record(fields) => {
+                let mut names: Vec<Symbol> = fields.iter().map(|(field, _)| *field).collect();
+                names.sort_by_key(|field| field.as_str());
+                match self.records.get(&names) {
+                    Some(alias) => utils::sanitize_identifier(*alias, "typescript"),
+                    None => self.render(&CheckerType::Row { fields: fields.clone(), rest: x_checker::TypeVar(0) }),
+                }
+            }
+            CheckerType::Row { fields, .. } => {
+                let fields: Vec<String> = fields.iter()
+                    .map(|(name, typ)| format!("{name}: {}", self.render(typ)))
+                    .collect();
+                format!("{{ {} }}", fields.join("; "))
+            }
+            CheckerType::Variant(constructors) => {
+                let cases: Vec<String> = constructors.iter()
+                    .map(|(name, fields)| tagged_case(*name, fields.iter().map(|field| self.render(field))))
+                    .collect();
+                cases.join(" | ")
+            }
+            CheckerType::Tuple(types) => {
+                format!("[{}]", types.iter().map(|typ| self.render(typ)).collect::<Vec<_>>().join(", "))
+            }
+            _ => "unknown".to_string(),
+        }
+    }
+}
+
+fn collect_type_vars(typ: &CheckerType, vars: &mut Vec<x_checker::TypeVar>) {
+    match typ {
+        CheckerType::Var(var) if !vars.contains(var) => vars.push(*var),
+        CheckerType::App(con, args) => {
+            collect_type_vars(con, vars);
+            args.iter().for_each(|arg| collect_type_vars(arg, vars));
+        }
+        CheckerType::Fun { params, return_type, .. } => {
+            params.iter().for_each(|param| collect_type_vars(param, vars));
+            collect_type_vars(return_type, vars);
+        }
+        CheckerType::Forall { body, .. } => collect_type_vars(body, vars),
+        CheckerType::Record(fields) | CheckerType::Row { fields, .. } => {
+            fields.iter().for_each(|(_, typ)| collect_type_vars(typ, vars));
+        }
+        CheckerType::Variant(constructors) => {
+            constructors.iter().flat_map(|(_, fields)| fields).for_each(|field| collect_type_vars(field, vars));
+        }
+        CheckerType::Tuple(types) => types.iter().for_each(|typ| collect_type_vars(typ, vars)),
+        _ => {}
+    }
+}
+
+fn strip_forall(typ: &CheckerType) -> &CheckerType {
+    match typ {
+        CheckerType::Forall { body, .. } => strip_forall(body),
+        typ => typ,
+    }
+}
+
+/// The JavaScript operator computing the built-in binary operator `name`
+fn infix_operator(name: &str) -> Option<&'static str> {
+    let infix = match name {
+        "+" | "+." | "^" => "+",
+        "-" | "-." => "-",
+        "*" | "*." => "*",
+        "/" | "/." => "/",
+        "mod" => "%",
+        "=" | "==" => "===",
+        "<>" | "!=" => "!==",
+        "<" => "<",
+        ">" => ">",
+        "<=" => "<=",
+        ">=" => ">=",
+        "&&" => "&&",
+        "||" => "||",
+        _ => return None,
+    };
+    Some(infix)
+}
+
+/// TypeScript name of a type constructor
+fn declared_constructor(name: &str) -> String {
+    match name {
+        "Int" | "Float" => "number".to_string(),
+        "String" | "Char" => "string".to_string(),
+        "Bool" => "boolean".to_string(),
+        "Unit" => "void".to_string(),
+        name => utils::sanitize_identifier(Symbol::intern(name), "typescript"),
+    }
+}
+
+/// Object type of one constructor of a discriminated union
+fn tagged_case(name: Symbol, fields: impl Iterator<Item = String>) -> String {
+    let fields: String = fields.enumerate()
+        .map(|(i, field)| format!("; _{i}: {field}"))
+        .collect();
+    format!("{{ tag: \"{name}\"{fields} }}")
+}
+
+/// Declaration of a written type definition
+fn declare_type_definition(type_def: &TypeDef) -> String {
+    let name = utils::sanitize_identifier(type_def.name, "typescript");
+    let generics = if type_def.type_params.is_empty() {
+        String::new()
+    } else {
+        let params: Vec<&str> = type_def.type_params.iter().map(|param| param.name.as_str()).collect();
+        format!("<{}>", params.join(", "))
+    };
+    match &type_def.kind {
+        TypeDefKind::Data(constructors) => {
+            let cases: Vec<String> = constructors.iter()
+                .map(|constructor| tagged_case(constructor.name, constructor.fields.iter().map(declared_type)))
+                .collect();
+            format!("export type {name}{generics} = {};", cases.join(" | "))
+        }
+        TypeDefKind::Alias(Type::Record { fields, .. }) => {
+            let mut fields: Vec<_> = fields.iter().collect();
+            fields.sort_by_key(|(field, _)| field.as_str());
+            let fields: String = fields.into_iter()
+                .map(|(field, typ)| format!(" {field}: {};", declared_type(typ)))
+                .collect();
+            format!("export interface {name}{generics} {{{fields} }}")
+        }
+        TypeDefKind::Alias(typ) => format!("export type {name}{generics} = {};", declared_type(typ)),
+        // Only the defining module can make values of an abstract type
+        TypeDefKind::Abstract => format!("export type {name}{generics} = {{ readonly __brand: \"{name}\" }};"),
+    }
+}
+
+/// TypeScript form of a written type
+fn declared_type(typ: &Type) -> String {
+    match typ {
+        Type::Var(name, _) => name.as_str().to_string(),
+        Type::Con(name, _) => declared_constructor(name.as_str()),
+        Type::App(con, args, _) => {
+            let args: Vec<String> = args.iter().map(declared_type).collect();
+            match (con.as_ref(), args.as_slice()) {
+                (Type::Con(name, _), [element]) if name.as_str() == "List" => format!("{element}[]"),
+                _ => format!("{}<{}>", declared_type(con), args.join(", ")),
+            }
+        }
+        Type::Fun { params, return_type, .. } => {
+            let params: Vec<String> = params.iter().enumerate()
+                .map(|(i, param)| format!("arg{i}: {}", declared_type(param)))
+                .collect();
+            format!("({}) => {}", params.join(", "), declared_type(return_type))
+        }
+        Type::Forall { body, .. } => declared_type(body),
+        Type::Record { fields, .. } => {
+            let mut fields: Vec<_> = fields.iter().collect();
+            fields.sort_by_key(|(field, _)| field.as_str());
+            let fields: Vec<String> = fields.into_iter()
+                .map(|(field, typ)| format!("{field}: {}", declared_type(typ)))
+                .collect();
+            format!("{{ {} }}", fields.join("; "))
+        }
+        Type::Tuple { types, .. } => {
+            format!("[{}]", types.iter().map(declared_type).collect::<Vec<_>>().join(", "))
+        }
+        _ => "unknown".to_string(),
+    }
+}
+
 impl Default for TypeScriptBackend {
     fn default() -> Self {
         Self::new()
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+    use x_parser::{parse_source, CancellationToken, FileId, SyntaxStyle};
+
+    const SOURCE: &str = "module Test\npub let pick = fun x -> fun y -> x\nlet answer = 42";
+
+    /// Write the generated files to `dir`, returning them by file name
+    fn generate(backend: TypeScriptBackend, source: &str, dir: &std::path::Path) -> HashMap<String, String> {
+        let cu = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        generate_unit(backend, &cu, dir)
+    }
+
+    fn generate_unit(mut backend: TypeScriptBackend, cu: &CompilationUnit, dir: &std::path::Path) -> HashMap<String, String> {
+        let options = CodegenOptions {
+            target: backend.target_info(),
+            output_dir: dir.to_path_buf(),
+            source_maps: false,
+            debug_info: false,
+            optimization_level: 0,
+            emit_types: false,
+            naming: Default::default(),
+            cancellation: Default::default(),
+            passes: Default::default(),
+            inlining: Default::default(),
+            sources: Default::default(),
+        };
+        let result = backend.generate_code(cu, &HashMap::new(), &options).unwrap();
+        result.files.into_iter()
+            .map(|(path, code)| {
+                std::fs::write(&path, &code).unwrap();
+                (path.file_name().unwrap().to_string_lossy().into_owned(), code)
+            })
+            .collect()
+    }
+
+    /// Run `script` with node, or `None` if node is not installed
+    fn node(args: &[&str], script: &str) -> Option<String> {
+        let output = Command::new("node").args(args).arg("-e").arg(script).output().ok()?;
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    #[test]
+    fn test_javascript_es_modules() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let files = generate(TypeScriptBackend::javascript(), SOURCE, dir.path());
+
+        let mut names: Vec<&str> = files.keys().map(String::as_str).collect();
+        names.sort();
+        assert_eq!(names, ["Test.mjs", "runtime.mjs"]);
+        let module = &files["Test.mjs"];
+        assert!(module.contains("import * as $rt from \"./runtime.mjs\";"));
+        assert!(module.contains("export function pick(x) {\n  return (y) => x;\n}"));
+        assert!(module.contains("export const answer = 42;"));
+        assert!(files["runtime.mjs"].contains("export class EffectContext {"));
+
+        let script = format!(
+            "import {{ pick, answer }} from {:?}; console.log(pick(answer)(0));",
+            dir.path().join("Test.mjs").display().to_string(),
+        );
+        match node(&["--input-type=module"], &script) {
+            Some(output) => assert_eq!(output, "42"),
+            None => eprintln!("node is not installed; skipping"),
+        }
+    }
+
+    #[test]
+    fn test_matches_compile_to_switches() {
+        let source = "module Test\n\
+            data Shape = Dot | Circle Int | Square Int\n\
+            pub let size = fun s -> match s with | Circle r | Square r => r | Dot => 0\n\
+            pub let small = fun n -> match n with | 0 | 1 | 2 => \"small\" | 7 => \"seven\" | _ => \"large\"\n\
+            pub let first = fun p -> match p with | (Some x, _) => x | (None, Some y) => y\n\
+            pub let guarded = fun p -> match p with | (Some x, _) if x => 1 | (_, z) => 2";
+        let dir = tempfile::TempDir::new().unwrap();
+        let files = generate(TypeScriptBackend::javascript(), source, dir.path());
+        let module = &files["Test.mjs"];
+        assert!(module.contains("switch ($match0.tag) {\n      case \"Circle\": {\n        const r = $match0._0;\n        return r;"));
+        assert!(module.contains("case \"Dot\": default: {"));
+        assert!(module.contains("if ($match1 >= 0 && $match1 <= 2) {"));
+        // Only the match that can fail throws
+        assert_eq!(module.matches("throw new Error(\"Match failure\")").count(), 1);
+        // A failed guard goes on to the next case; its bindings are scoped
+        assert!(module.contains("{\n          const x = $match3[0]._0;\n          if (x) return 1;\n        }\n        const z = $match3[1];\n        return 2;"));
+
+        let script = format!(
+            "import {{ size, small, first, guarded }} from {:?};\n\
+             const some = (value) => ({{ tag: \"Some\", _0: value }});\n\
+             console.log([size({{ tag: \"Square\", _0: 4 }}), size({{ tag: \"Dot\" }}), small(1), small(7), small(9),\n\
+                          first([{{ tag: \"None\" }}, some(3)]), guarded([some(false), 0]), guarded([some(true), 0])].join());\n\
+             try {{ first([{{ tag: \"None\" }}, {{ tag: \"None\" }}]); }} catch (error) {{ console.log(error.message); }}",
+            dir.path().join("Test.mjs").display().to_string(),
+        );
+        match node(&["--input-type=module"], &script) {
+            Some(output) => assert_eq!(output, "4,0,small,seven,large,3,2,1\nMatch failure"),
+            None => eprintln!("node is not installed; skipping"),
+        }
+    }
+
+    #[test]
+    fn test_constructors_build_tagged_objects() {
+        let source = "module Test\n\
+            data Shape = Dot | Rect Int Int\n\
+            pub let height = fun s -> match s with | Rect w h => h | Dot => 0\n\
+            pub let rect = Rect 1 2\n\
+            pub let dot = Dot\n\
+            pub let four = height (Rect 3 4)";
+        let dir = tempfile::TempDir::new().unwrap();
+        let files = generate(TypeScriptBackend::javascript(), source, dir.path());
+        let module = &files["Test.mjs"];
+        assert!(module.contains("const Dot = { tag: \"Dot\" };\nconst Rect = (_0) => (_1) => ({ tag: \"Rect\", _0, _1 });"));
+        assert!(!module.contains("TODO"));
+
+        let typed = generate(TypeScriptBackend::new(), source, dir.path());
+        assert!(typed["Test.ts"].contains("const Rect = (_0: any) => (_1: any) => ({ tag: \"Rect\", _0, _1 });"));
+
+        let script = format!(
+            "import {{ height, rect, dot, four }} from {:?};\n\
+             console.log(JSON.stringify(rect), JSON.stringify(dot), height(rect), height(dot), four);",
+            dir.path().join("Test.mjs").display().to_string(),
+        );
+        match node(&["--input-type=module"], &script) {
+            Some(output) => assert_eq!(output, "{\"tag\":\"Rect\",\"_0\":1,\"_1\":2} {\"tag\":\"Dot\"} 2 0 4"),
+            None => eprintln!("node is not installed; skipping"),
+        }
+    }
+
+    #[test]
+    fn test_operators_compile_to_infix() {
+        let source = "module Test\n\
+            pub let big = fun x -> x + 1 > 2\n\
+            pub let half = fun x -> x / 2\n\
+            pub let add = (_ + _)";
+        let dir = tempfile::TempDir::new().unwrap();
+        let files = generate(TypeScriptBackend::javascript(), source, dir.path());
+        let module = &files["Test.mjs"];
+        assert!(module.contains("(x + 1)"), "{module}");
+        assert!(module.contains(" > 2)"), "{module}");
+        assert!(module.contains("Math.trunc(x / 2)"), "{module}");
+        assert!(!module.contains("_("), "{module}");
+
+        let script = format!(
+            "import {{ big, half, add }} from {:?}; console.log(big(1), big(2), half(7), add(3)(4));",
+            dir.path().join("Test.mjs").display().to_string(),
+        );
+        match node(&["--input-type=module"], &script) {
+            Some(output) => assert_eq!(output, "false true 3 7"),
+            None => eprintln!("node is not installed; skipping"),
+        }
+    }
+
+    #[test]
+    fn test_lets_compile_to_statements() {
+        let source = "module Test\n\
+            import Core.String\n\
+            let twice = fun s -> String.concat s s\n\
+            pub let shout = fun s -> (let s = String.concat (twice s) \"!\" in (let t = twice s in t))\n\
+            pub let label = fun n -> match n with | 0 => (let z = twice \"zero\" in z) | _ => \"some\"";
+        let dir = tempfile::TempDir::new().unwrap();
+        let files = generate(TypeScriptBackend::javascript(), source, dir.path());
+        let module = &files["Test.mjs"];
+        // Nested calls are named, and the inner `s` no longer shadows the parameter
+        assert!(module.contains("const $t0 = twice(s);\n  const s$1 = ($t0 + \"!\");\n  const t = twice(s$1);\n  return t;"), "{module}");
+        assert!(module.contains("const z = twice(\"zero\");\n      return z;"), "{module}");
+
+        let script = format!(
+            "import {{ shout, label }} from {:?}; console.log([shout(\"a\"), label(0), label(1)].join());",
+            dir.path().join("Test.mjs").display().to_string(),
+        );
+        match node(&["--input-type=module"], &script) {
+            Some(output) => assert_eq!(output, "aa!aa!,zerozero,some"),
+            None => eprintln!("node is not installed; skipping"),
+        }
+    }
+
+    #[test]
+    fn test_tail_recursion_compiles_to_loops() {
+        let source = "module Test
+            import Core.String
+            data Chain = Link String Chain | End
+            pub let walk = fun xs acc -> match xs with | Link x rest => walk rest (String.concat acc x) | End => acc
+            pub let thunks = fun xs acc -> match xs with | Link _ rest => thunks rest ((fun u -> xs), acc) | End => acc";
+        let dir = tempfile::TempDir::new().unwrap();
+        let files = generate(TypeScriptBackend::javascript(), source, dir.path());
+        let module = &files["Test.mjs"];
+        assert!(module.contains("export function walk(xs, acc) {\n  while (true) {\n    const $match0 = xs;"), "{module}");
+        assert!(module.contains("const $t0 = (acc + x);\n        [xs, acc] = [rest, $t0];\n        continue;"), "{module}");
+        // Closures made in an iteration keep that iteration's parameters
+        assert!(module.contains("export function thunks(xs$, acc$) {\n  while (true) {\n    const xs = xs$;\n    const acc = acc$;"), "{module}");
+
+        let script = format!(
+            "import {{ walk, thunks }} from {:?};\n\
+             let chain = {{ tag: \"End\" }};\n\
+             for (let i = 0; i < 100000; i++) chain = {{ tag: \"Link\", _0: \"a\", _1: chain }};\n\
+             const links = thunks(chain, null);\n\
+             console.log([walk(chain, \"\").length, links[0](0)._1.tag, links[1][0](0)._1 === links[0](0)].join());",
+            dir.path().join("Test.mjs").display().to_string(),
+        );
+        match node(&["--input-type=module"], &script) {
+            Some(output) => assert_eq!(output, "100000,End,true"),
+            None => eprintln!("node is not installed; skipping"),
+        }
+    }
+
+    #[test]
+    fn test_javascript_commonjs() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let backend = TypeScriptBackend::javascript().with_module_system(TypeScriptModuleSystem::CommonJS);
+        assert_eq!(backend.target_info().file_extension, "cjs");
+        let files = generate(backend, SOURCE, dir.path());
+
+        let module = &files["Test.cjs"];
+        assert!(module.contains("const $rt = require(\"./runtime.cjs\");"));
+        assert!(module.contains("\nfunction pick(x) {"));
+        assert!(module.ends_with("module.exports = { answer, pick };\n"));
+        assert!(files["runtime.cjs"].ends_with("module.exports = { EffectContext, curry, MatchError, handle, perform };\n"));
+
+        let script = format!(
+            "const {{ pick, answer }} = require({:?}); console.log(pick(answer)(0));",
+            dir.path().join("Test.cjs").display().to_string(),
+        );
+        match node(&[], &script) {
+            Some(output) => assert_eq!(output, "42"),
+            None => eprintln!("node is not installed; skipping"),
+        }
+    }
+
+    #[test]
+    fn test_packed_modules_become_objects() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let source = "module Test\nimport Plugins.Math as M\n\
+            module type Plugin { type t  make : Int -> t  show : t -> Int }\n\
+            pub let plugin = module M : Plugin";
+        let files = generate(TypeScriptBackend::javascript(), source, dir.path());
+        let module = &files["Test.mjs"];
+        assert!(module.contains("import * as M from \"Plugins.Math\";"), "{module}");
+        assert!(module.contains("export const plugin = { make: M.make, show: M.show };"), "{module}");
+
+        let backend = TypeScriptBackend::javascript().with_module_system(TypeScriptModuleSystem::CommonJS);
+        let files = generate(backend, source, dir.path());
+        assert!(files["Test.cjs"].contains("const M = require(\"Plugins.Math\");"));
+    }
+
+    #[test]
+    fn test_lazy_imports_load_on_first_use() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let source = "module Test\nlazy import Reports.Pdf as Pdf\n\
+            pub let render = fun doc -> Pdf.render doc\n\
+            pub let report = fun doc -> render doc";
+        let files = generate(TypeScriptBackend::javascript(), source, dir.path());
+        let module = &files["Test.mjs"];
+        assert!(module.contains("const $load_Pdf = () => ($lazy_Pdf ??= import(\"Reports.Pdf\"));"), "{module}");
+        assert!(!module.contains("import * as Pdf"), "{module}");
+        assert!(module.contains("export async function render(doc) {\n  return (await $load_Pdf()).render(doc);"), "{module}");
+        assert!(module.contains("export async function report(doc) {\n  return (await render(doc));"), "{module}");
+    }
+
+    #[test]
+    fn test_library_calls_use_intrinsics() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let source = "module Test\nimport Core.String\npub let greet = fun name -> String.concat \"hi \" name";
+        let files = generate(TypeScriptBackend::javascript(), source, dir.path());
+
+        let module = &files["Test.mjs"];
+        assert!(!module.contains("Core.String"));
+        assert!(module.contains("return (\"hi \" + name);"), "{module}");
+    }
+
+    #[test]
+    fn test_declarations() {
+        let source = "module Shapes
+data Shape = Circle Float | Rect Float Float
+data Box[a] = Empty | Full a
+type Point = {x: Float, y: Float}
+pub let origin = {x = 0.0, y = 0.0}
+pub let pick = fun x -> fun y -> x
+let helper = fun n -> n";
+        let dir = tempfile::TempDir::new().unwrap();
+        let files = generate(TypeScriptBackend::new().with_declarations(true), source, dir.path());
+
+        let declarations = &files["Shapes.d.ts"];
+        assert!(declarations.contains(
+            "export type Shape = { tag: \"Circle\"; _0: number } | { tag: \"Rect\"; _0: number; _1: number };"
+        ));
+        assert!(declarations.contains("export type Box<a> = { tag: \"Empty\" } | { tag: \"Full\"; _0: a };"));
+        assert!(declarations.contains("export interface Point { x: number; y: number; }"));
+        assert!(declarations.contains("export declare function pick<A, B>(x: A): (arg0: B) => A;"));
+        assert!(declarations.contains("export declare const origin: Point;"));
+        assert!(!declarations.contains("helper"));
+    }
+
+    #[test]
+    fn test_cancelled_generation() {
+        let cu = parse_source("module Main\nlet one = 1\nlet two = fun x -> x", FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let mut backend = TypeScriptBackend::new();
+        let options = CodegenOptions {
+            target: backend.target_info(),
+            output_dir: std::path::PathBuf::from("out"),
+            source_maps: false,
+            debug_info: false,
+            optimization_level: 0,
+            emit_types: false,
+            naming: Default::default(),
+            cancellation: CancellationToken::new(),
+            passes: Default::default(),
+            inlining: Default::default(),
+            sources: Default::default(),
+        };
+        options.cancellation.cancel();
+        let error = backend.generate_code(&cu, &HashMap::new(), &options).unwrap_err();
+        assert!(matches!(error, crate::CompilerError::Cancelled { stage: crate::PipelineStage::CodeGen }), "{error}");
+    }
+
+    #[test]
+    fn test_javascript_declaration_extensions() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let files = generate(TypeScriptBackend::javascript().with_declarations(true), SOURCE, dir.path());
+        assert!(files["Test.d.mts"].contains("export declare const answer: number;"));
+
+        let backend = TypeScriptBackend::javascript()
+            .with_module_system(TypeScriptModuleSystem::CommonJS)
+            .with_declarations(true);
+        let files = generate(backend, SOURCE, dir.path());
+        assert!(files["Test.d.cts"].contains("export declare function pick<A, B>(x: A): (arg0: B) => A;"));
+    }
+
+    /// `get` performs `State.get`; `run` resumes it with 41 and `abort`
+    /// answers 7 without resuming. `layered` gets twice under a shallow
+    /// handler answering 41 inside a deep one answering 1, and `deeper` the
+    /// same with two deep handlers. There is no surface syntax for effects
+    /// yet, so the bodies are built directly.
+    fn effectful_unit() -> CompilationUnit {
+        use x_parser::span::CharOffset;
+        use x_parser::{EffectHandler, EffectRef, Expr, Literal};
+        let span = x_parser::Span::new(FileId::new(0), CharOffset(0), CharOffset(0));
+        let source = "module Test\npub let get = fun u -> 0\npub let run = fun u -> 0\npub let abort = fun u -> 0\n\
+            pub let layered = fun u -> 0\npub let deeper = fun u -> 0";
+        let mut cu = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let get = || Expr::App(
+            Box::new(Expr::Var(Symbol::intern("get"), span)),
+            vec![Expr::Var(Symbol::intern("u"), span)],
+            span,
+        );
+        let resume = |value: i64| Expr::Resume { value: Box::new(Expr::Literal(Literal::int(value), span)), span };
+        let handle_with = |mode: HandlerMode, expr: Expr, clause: Expr| Expr::Handle {
+            expr: Box::new(expr),
+            mode,
+            handlers: vec![EffectHandler {
+                effect: EffectRef { name: Symbol::intern("State"), args: vec![], span },
+                operation: Symbol::intern("get"),
+                parameters: vec![],
+                continuation: Some(Symbol::intern("k")),
+                body: clause,
+                span,
+            }],
+            return_clause: None,
+            span,
+        };
+        let handle = |clause: Expr| handle_with(HandlerMode::Deep, get(), clause);
+        let twice = || Expr::Tuple { elements: vec![get(), get()], span };
+        let bodies = [
+            Expr::Perform { effect: Symbol::intern("State"), operation: Symbol::intern("get"), args: vec![], span },
+            handle(resume(41)),
+            handle(Expr::Literal(Literal::int(7), span)),
+            handle_with(HandlerMode::Deep, handle_with(HandlerMode::Shallow, twice(), resume(41)), resume(1)),
+            handle_with(HandlerMode::Deep, handle_with(HandlerMode::Deep, twice(), resume(41)), resume(1)),
+        ];
+        for (item, new_body) in cu.module.items.iter_mut().zip(bodies) {
+            if let Item::ValueDef(value_def) = item {
+                if let Expr::Lambda { body, .. } = &mut value_def.body {
+                    **body = new_body;
+                }
+            }
+        }
+        cu
+    }
+
+    #[test]
+    fn test_effects_lowered_to_handlers() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let files = generate_unit(TypeScriptBackend::javascript(), &effectful_unit(), dir.path());
+
+        let module = &files["Test.mjs"];
+        assert!(module.contains("// Effects are lowered to handler objects"));
+        assert!(module.contains("export function get(u) {\n  return $rt.perform(\"State\", \"get\");\n}"));
+        assert!(module.contains("$rt.handle(() => get(u), { \"State.get\": (k) => k(41) })"));
+        assert!(module.contains("\"State.get\": (k) => k(41) }, undefined, true)"));
+
+        let script = format!(
+            "import {{ run, abort, layered, deeper }} from {:?}; console.log(run(0), abort(0), layered(0).join(), deeper(0).join());",
+            dir.path().join("Test.mjs").display().to_string(),
+        );
+        match node(&["--input-type=module"], &script) {
+            Some(output) => assert_eq!(output, "41 7 41,1 41,41"),
+            None => eprintln!("node is not installed; skipping"),
+        }
+    }
+
+    #[test]
+    fn test_effects_lowered_to_async_await() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let backend = TypeScriptBackend::javascript().with_effect_lowering(TypeScriptEffectLowering::AsyncAwait);
+        let files = generate_unit(backend, &effectful_unit(), dir.path());
+
+        let module = &files["Test.mjs"];
+        assert!(module.contains("// Effects are lowered to async/await"));
+        assert!(module.contains("export async function get(u) {\n  return (await $rt.performAsync(\"State\", \"get\"));\n}"));
+        assert!(module.contains("export async function run(u) {"));
+        assert!(module.contains("(await $rt.handleAsync(async () => (await get(u)), { \"State.get\": async (k) => k(41) }))"));
+        assert!(files["runtime.mjs"].contains("export async function performAsync(effect, operation, ...args) {"));
+
+        let script = format!(
+            "import {{ run, abort }} from {:?}; console.log(await run(0), await abort(0));",
+            dir.path().join("Test.mjs").display().to_string(),
+        );
+        match node(&["--input-type=module"], &script) {
+            Some(output) => assert_eq!(output, "41 7"),
+            None => eprintln!("node is not installed; skipping"),
+        }
+    }
+
+    #[test]
+    fn test_stack_traces_name_x_frames() {
+        let source = "module Test\n\
+            let pick = fun n -> match n with | 0 => 1\n\
+            pub let outer = fun n -> pick n + 1";
+        let mut sources = x_parser::SourceMap::new();
+        let file = sources.add_file("Test.x", source);
+        let cu = parse_source(source, file, SyntaxStyle::SExpression).unwrap();
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut backend = TypeScriptBackend::javascript().with_stack_traces(true);
+        let options = CodegenOptions {
+            target: backend.target_info(),
+            output_dir: dir.path().to_path_buf(),
+            source_maps: false,
+            debug_info: false,
+            optimization_level: 0,
+            emit_types: false,
+            naming: Default::default(),
+            cancellation: Default::default(),
+            passes: Default::default(),
+            inlining: Default::default(),
+            sources: std::sync::Arc::new(sources),
+        };
+        let result = backend.generate_code(&cu, &HashMap::new(), &options).unwrap();
+        for (path, code) in &result.files {
+            std::fs::write(path, code).unwrap();
+        }
+        let module = &result.files[&dir.path().join("Test.mjs")];
+        assert!(module.contains("const $frame_outer = { module: \"Test\", definition: \"outer\", location: \"Test.x:3:5\" };"), "{module}");
+        assert!(module.contains("export function outer(n) {\n  const $depth = $rt.enter($frame_outer);\n  try {\n    const $t0 = pick(n);\n"), "{module}");
+
+        // Unhandled operations throw from the function performing them
+        let effects = tempfile::TempDir::new().unwrap();
+        let files = generate_unit(TypeScriptBackend::javascript().with_stack_traces(true), &effectful_unit(), effects.path());
+        assert!(files["Test.mjs"].contains("location: undefined"));
+
+        let script = format!(
+            "import {{ outer }} from {:?}; import {{ get }} from {:?};\n\
+             try {{ outer(5); }} catch (error) {{ console.log(error.stack); }}\n\
+             try {{ get(0); }} catch (error) {{ console.log(error.stack); }}",
+            dir.path().join("Test.mjs").display().to_string(),
+            effects.path().join("Test.mjs").display().to_string(),
+        );
+        match node(&["--input-type=module"], &script) {
+            Some(output) => {
+                let lines: Vec<&str> = output.lines().collect();
+                assert_eq!(&lines[1..3], ["    at Test.pick (Test.x:2:1)", "    at Test.outer (Test.x:3:5)"], "{output}");
+                assert_eq!(&lines[3..], ["Error: Unhandled effect: State.get", "    at Test.get"]);
+            }
+            None => eprintln!("node is not installed; skipping"),
+        }
+
+        let plain = generate(TypeScriptBackend::javascript(), source, dir.path());
+        assert!(!plain["Test.mjs"].contains("$rt.enter"));
+        assert!(!plain["runtime.mjs"].contains("frameStack"));
+    }
+
+    #[test]
+    fn test_async_lowered_to_promises() {
+        use x_parser::span::CharOffset;
+        use x_parser::{EffectHandler, EffectRef, Expr};
+        let span = x_parser::Span::new(FileId::new(0), CharOffset(0), CharOffset(0));
+        let source = "module Test\npub let task = fun u -> 20\npub let main = fun u -> 0\npub let handled = fun u -> 0";
+        let mut cu = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let perform = |operation: &str, argument: Expr| Expr::Perform {
+            effect: symbols::ASYNC(),
+            operation: Symbol::intern(operation),
+            args: vec![argument],
+            span,
+        };
+        let call_main = Expr::App(
+            Box::new(Expr::Var(Symbol::intern("main"), span)),
+            vec![Expr::Var(Symbol::intern("u"), span)],
+            span,
+        );
+        let bodies = [
+            perform("await", perform("spawn", Expr::Var(Symbol::intern("task"), span))),
+            Expr::Handle {
+                expr: Box::new(call_main),
+                mode: HandlerMode::Deep,
+                handlers: vec![EffectHandler {
+                    effect: EffectRef { name: Symbol::intern("State"), args: vec![], span },
+                    operation: Symbol::intern("get"),
+                    parameters: vec![],
+                    continuation: Some(Symbol::intern("k")),
+                    body: Expr::Resume { value: Box::new(Expr::Literal(x_parser::Literal::int(0), span)), span },
+                    span,
+                }],
+                return_clause: None,
+                span,
+            },
+        ];
+        for (item, new_body) in cu.module.items.iter_mut().skip(1).zip(bodies) {
+            if let Item::ValueDef(value_def) = item {
+                if let Expr::Lambda { body, .. } = &mut value_def.body {
+                    **body = new_body;
+                }
+            }
+        }
+
+        // Synchronous handlers cannot wait for the task
+        let dir = tempfile::TempDir::new().unwrap();
+        let options = CodegenOptions {
+            target: TypeScriptBackend::javascript().target_info(),
+            output_dir: dir.path().to_path_buf(),
+            source_maps: false,
+            debug_info: false,
+            optimization_level: 0,
+            emit_types: false,
+            naming: Default::default(),
+            cancellation: Default::default(),
+            passes: Default::default(),
+            inlining: Default::default(),
+            sources: Default::default(),
+        };
+        let result = TypeScriptBackend::javascript().generate_code(&cu, &HashMap::new(), &options).unwrap();
+        let codes: Vec<&str> = result.diagnostics.iter().map(|diagnostic| diagnostic.code).collect();
+        assert_eq!(codes, ["E0207"]);
+
+        let backend = TypeScriptBackend::javascript().with_effect_lowering(TypeScriptEffectLowering::AsyncAwait);
+        let files = generate_unit(backend, &cu, dir.path());
+        let module = &files["Test.mjs"];
+        assert!(module.contains("export function task(u) {"));
+        assert!(module.contains("export async function main(u) {\n  const $t0 = $rt.spawn(task);\n  return (await $t0);\n}"));
+        assert!(files["runtime.mjs"].contains("export function spawn(task) {"));
+
+        let script = format!(
+            "import {{ main }} from {:?}; console.log(await main(0));",
+            dir.path().join("Test.mjs").display().to_string(),
+        );
+        match node(&["--input-type=module"], &script) {
+            Some(output) => assert_eq!(output, "20"),
+            None => eprintln!("node is not installed; skipping"),
+        }
+    }
+}
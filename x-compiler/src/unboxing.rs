@@ -0,0 +1,154 @@
+//! Unboxing of scalar locals
+//!
+//! [`unbox_locals`] gives each `let` binding whose value is always an `Int`,
+//! a `Float` or a `Bool` that type as its hint. The [scalar] a value holds is
+//! known when it is
+//!
+//! - a literal of the type;
+//! - a local already known to hold one;
+//! - an arithmetic or boolean operator, such as `+`, `-.` or `&&`, whose
+//!   operands and result have a fixed type, or a comparison, which gives a
+//!   `Bool`.
+//!
+//! Bindings written with a type keep it. The WebAssembly GC backend holds
+//! these locals [unboxed](crate::runtime::Unboxed); other backends ignore
+//! the hints. An operator the module defines itself is not a scalar one.
+
+use crate::anf::module_names;
+use crate::ir::{IRExpression, IRModule};
+use crate::runtime::{self, Unboxed};
+use std::collections::{HashMap, HashSet};
+use x_parser::Symbol;
+
+/// Give the scalar locals of every function and constant of `module` their
+/// type
+pub fn unbox_locals(module: &mut IRModule) {
+    let defined = module_names(module);
+    for function in &mut module.functions {
+        annotate(&mut function.body, &HashMap::new(), &defined);
+    }
+    for constant in &mut module.constants {
+        annotate(&mut constant.value, &HashMap::new(), &defined);
+    }
+}
+
+/// The scalar `expr` always evaluates to, knowing those of the `locals`;
+/// operators named in `defined` are the module's own
+pub fn scalar(expr: &IRExpression, locals: &HashMap<Symbol, Unboxed>, defined: &HashSet<Symbol>) -> Option<Unboxed> {
+    match expr {
+        IRExpression::Literal(literal) => Unboxed::of_literal(literal),
+        IRExpression::Variable(name) => locals.get(name).copied(),
+        IRExpression::Call { function, arguments } if arguments.len() == 2 => match function.as_ref() {
+            IRExpression::Variable(operator) if !defined.contains(operator) && !locals.contains_key(operator) => {
+                let operator = operator.as_str();
+                match runtime::operator(operator) {
+                    Some(operator) => Some(operator.scalar),
+                    None => runtime::is_comparison(operator).then_some(Unboxed::Bool),
+                }
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Give the scalar bindings of `expr` their type; `locals` holds the
+/// scalar locals in scope
+fn annotate(expr: &mut IRExpression, locals: &HashMap<Symbol, Unboxed>, defined: &HashSet<Symbol>) {
+    match expr {
+        IRExpression::Let { bindings, body } => {
+            let mut locals = locals.clone();
+            for binding in bindings {
+                annotate(&mut binding.value, &locals, defined);
+                let scalar = match &binding.type_hint {
+                    Some(typ) => Unboxed::of_type(typ),
+                    None => scalar(&binding.value, &locals, defined),
+                };
+                match scalar {
+                    Some(scalar) => {
+                        binding.type_hint.get_or_insert_with(|| scalar.ir_type());
+                        locals.insert(binding.name, scalar);
+                    }
+                    None => {
+                        locals.remove(&binding.name);
+                    }
+                }
+            }
+            annotate(body, &locals, defined);
+        }
+        IRExpression::Lambda { parameters, body, .. } => {
+            let inner = shadowed(locals, parameters.iter().map(|parameter| parameter.name));
+            annotate(body, &inner, defined);
+        }
+        IRExpression::Match { value, cases } => {
+            annotate(value, locals, defined);
+            for case in cases {
+                let mut names = Vec::new();
+                case.pattern.collect_variables(&mut names);
+                let inner = shadowed(locals, names);
+                if let Some(guard) = &mut case.guard {
+                    annotate(guard, &inner, defined);
+                }
+                annotate(&mut case.body, &inner, defined);
+            }
+        }
+        IRExpression::Handle { expression, handlers, return_handler, .. } => {
+            annotate(expression, locals, defined);
+            for handler in handlers {
+                let names = handler.parameters.iter().copied().chain([handler.continuation]);
+                annotate(&mut handler.body, &shadowed(locals, names), defined);
+            }
+            if let Some(handler) = return_handler {
+                annotate(handler, locals, defined);
+            }
+        }
+        IRExpression::Cell { name, initial, body } => {
+            annotate(initial, locals, defined);
+            annotate(body, &shadowed(locals, [*name]), defined);
+        }
+        _ => expr.for_each_child_mut(&mut |child| annotate(child, locals, defined)),
+    }
+}
+
+/// `locals` without the `names` an inner scope binds again
+fn shadowed(locals: &HashMap<Symbol, Unboxed>, names: impl IntoIterator<Item = Symbol>) -> HashMap<Symbol, Unboxed> {
+    let mut inner = locals.clone();
+    for name in names {
+        inner.remove(&name);
+    }
+    inner
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::IRBuilder;
+    use crate::ir_print::print_expression;
+    use x_parser::{parse_source, FileId, SyntaxStyle};
+
+    fn unboxed(source: &str) -> Vec<String> {
+        let unit = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let mut module = IRBuilder::new().build_ir(&unit).unwrap().modules.remove(0);
+        unbox_locals(&mut module);
+        module.functions.iter().map(|function| print_expression(&function.body)).collect()
+    }
+
+    #[test]
+    fn test_scalar_locals_get_their_type() {
+        let bodies = unboxed("module Test\n\
+            let f = fun x -> (let a = x * 2 in (let b = a in (let c = b < x in (let d = 1.5 in (let e = f x in e)))))\n\
+            let g = fun a -> (let a = 1 in (fun a -> (let b = a in b)))");
+        assert_eq!(
+            bodies[0],
+            "(let\n  ((a Int (call * x 2)))\n  (let ((b Int a)) (let ((c Bool (call < b x))) (let ((d Float 1.5)) (let ((e (call f x))) e)))))",
+        );
+        // A parameter shadowing a scalar local is not one
+        assert!(bodies[1].contains("(let ((b a)) b)"), "{}", bodies[1]);
+    }
+
+    #[test]
+    fn test_operators_the_module_defines_are_not_scalar() {
+        let bodies = unboxed("module Test\nlet (+) = fun a b -> a\nlet f = fun x -> (let y = x + 1 in y)");
+        assert_eq!(bodies[1], "(let ((y (call + x 1))) y)");
+    }
+}
@@ -0,0 +1,531 @@
+//! Verifier for the IR
+//!
+//! Passes and backends assume more of the IR than its types enforce.
+//! [`verify_module`] checks those assumptions for every function and
+//! constant of a module:
+//!
+//! - well-formedness: lets bind something, matches have cases, the
+//!   alternatives of an or-pattern bind the same variables, and no name is
+//!   defined twice at the top level or bound twice by one parameter list;
+//! - scoping: locals are used only inside the expressions binding them,
+//!   lambdas capture exactly the enclosing locals they use, and `resume`
+//!   and cell operations refer to an enclosing handler clause or cell.
+//!   Other free names refer to the module, its imports or what the target
+//!   provides, which the type checker has resolved;
+//! - type agreement, as far as the IR shows types: conditions are not
+//!   non-boolean literals, only functions are called, tuple indices and
+//!   record fields exist, constructors get no more arguments than they have
+//!   fields and patterns as many, and a function's effect set lists every
+//!   effect its body performs.
+//!
+//! [`verify_normalized`] also checks the invariants of
+//! [A-normal form](crate::anf): operands are values, locals are bound once,
+//! and calls of the module's functions are saturated.
+
+use crate::anf::{self, is_value};
+use crate::ir::{IREffectSet, IRExpression, IRLiteral, IRModule, IRParameter, IRPattern, IRTypeDefinitionKind};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use x_parser::Symbol;
+
+/// A way in which the IR of a module is malformed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyError {
+    /// The function or constant the problem is in
+    pub definition: Symbol,
+    pub message: String,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.definition, self.message)
+    }
+}
+
+/// Check the well-formedness, scoping and types of `module`
+pub fn verify_module(module: &IRModule) -> Vec<VerifyError> {
+    Verifier::new(module, false).run(module)
+}
+
+/// Check `module` as [`verify_module`] does, and that it is in the form
+/// [`anf::normalize`] gives
+pub fn verify_normalized(module: &IRModule) -> Vec<VerifyError> {
+    Verifier::new(module, true).run(module)
+}
+
+/// What the verifier knows of a local
+#[derive(Debug, Clone, PartialEq)]
+enum Shape {
+    Unknown,
+    /// A literal of the named primitive type
+    Scalar(&'static str),
+    Tuple(usize),
+    Record(Vec<Symbol>),
+    Continuation,
+    Cell,
+}
+
+impl Shape {
+    fn of(expr: &IRExpression, verifier: &Verifier) -> Shape {
+        match expr {
+            IRExpression::Literal(IRLiteral::Integer(_)) => Shape::Scalar("Int"),
+            IRExpression::Literal(IRLiteral::Float(_)) => Shape::Scalar("Float"),
+            IRExpression::Literal(IRLiteral::String(_)) => Shape::Scalar("String"),
+            IRExpression::Literal(IRLiteral::Boolean(_)) => Shape::Scalar("Bool"),
+            IRExpression::Literal(IRLiteral::Unit) => Shape::Scalar("Unit"),
+            IRExpression::Literal(IRLiteral::Record(fields)) => Shape::Record(fields.iter().map(|(name, _)| *name).collect()),
+            IRExpression::Tuple(elements) => Shape::Tuple(elements.len()),
+            IRExpression::Variable(name) => match verifier.local(*name) {
+                Some(Shape::Continuation | Shape::Cell) | None => Shape::Unknown,
+                Some(shape) => shape.clone(),
+            },
+            _ => Shape::Unknown,
+        }
+    }
+
+    /// What a value of this shape is, if it is known not to be a function
+    fn describe(&self) -> Option<String> {
+        match self {
+            Shape::Scalar(name) => Some(format!("{} {name}", if *name == "Int" { "an" } else { "a" })),
+            Shape::Tuple(arity) => Some(format!("a tuple of {arity}")),
+            Shape::Record(_) => Some("a record".to_string()),
+            Shape::Unknown | Shape::Continuation | Shape::Cell => None,
+        }
+    }
+}
+
+struct Verifier {
+    normalized: bool,
+    /// Names the module defines or imports
+    globals: HashSet<Symbol>,
+    arities: HashMap<Symbol, usize>,
+    /// Field counts of the module's constructors
+    constructors: HashMap<Symbol, usize>,
+    definition: Symbol,
+    /// Locals in scope, innermost last
+    scope: Vec<(Symbol, Shape)>,
+    /// Every name the definition binds somewhere
+    binders: HashSet<Symbol>,
+    /// Every local of the definition, for normalized IR
+    declared: HashSet<Symbol>,
+    errors: Vec<VerifyError>,
+}
+
+impl Verifier {
+    fn new(module: &IRModule, normalized: bool) -> Self {
+        Verifier {
+            normalized,
+            globals: anf::module_names(module),
+            arities: anf::function_arities(module),
+            constructors: module.types.iter()
+                .filter_map(|type_def| match &type_def.definition {
+                    IRTypeDefinitionKind::Variant(constructors) => Some(constructors),
+                    _ => None,
+                })
+                .flatten()
+                .map(|(name, fields)| (*name, fields.len()))
+                .collect(),
+            definition: module.name,
+            scope: Vec::new(),
+            binders: HashSet::new(),
+            declared: HashSet::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    fn run(mut self, module: &IRModule) -> Vec<VerifyError> {
+        let mut defined = HashSet::new();
+        let definitions = module.functions.iter().map(|function| function.name)
+            .chain(module.constants.iter().map(|constant| constant.name));
+        for name in definitions {
+            if !defined.insert(name) {
+                self.definition = name;
+                self.error(format!("`{name}` is defined more than once"));
+            }
+        }
+
+        for function in &module.functions {
+            self.enter(function.name, &function.body);
+            // A definition's own parameters may shadow the module's names
+            self.bind_parameters(&function.parameters, false);
+            self.expression(&function.body);
+            let listed: Vec<Symbol> = match &function.effects {
+                IREffectSet::Empty => Vec::new(),
+                IREffectSet::Effects(effects) => effects.iter().map(|effect| effect.name).collect(),
+                // Polymorphic in its effects: anything may be performed
+                IREffectSet::Variable(_) => continue,
+            };
+            for effect in function.body.performed_effects() {
+                if !listed.contains(&effect) {
+                    self.error(format!("performs {effect}, which its effect set does not list"));
+                }
+            }
+        }
+        for constant in &module.constants {
+            self.enter(constant.name, &constant.value);
+            self.expression(&constant.value);
+        }
+        self.errors
+    }
+
+    fn enter(&mut self, definition: Symbol, body: &IRExpression) {
+        self.definition = definition;
+        self.scope.clear();
+        self.declared.clear();
+        self.binders = binders(body);
+    }
+
+    fn error(&mut self, message: String) {
+        self.errors.push(VerifyError { definition: self.definition, message });
+    }
+
+    fn local(&self, name: Symbol) -> Option<&Shape> {
+        self.scope.iter().rev().find(|(local, _)| *local == name).map(|(_, shape)| shape)
+    }
+
+    fn bind(&mut self, name: Symbol, shape: Shape) {
+        if self.normalized && !self.declared.insert(name) {
+            self.error(format!("`{name}` is bound more than once"));
+        }
+        self.scope.push((name, shape));
+    }
+
+    /// Bind a local that normalized IR may not let shadow the module's names
+    fn bind_local(&mut self, name: Symbol, shape: Shape) {
+        if self.normalized && self.globals.contains(&name) {
+            self.error(format!("`{name}` shadows a name of the module"));
+        }
+        self.bind(name, shape);
+    }
+
+    fn bind_parameters(&mut self, parameters: &[IRParameter], local: bool) {
+        let mut seen = HashSet::new();
+        for parameter in parameters {
+            if parameter.name.as_str() != "_" && !seen.insert(parameter.name) && !self.normalized {
+                self.error(format!("parameter `{}` is listed more than once", parameter.name));
+            }
+            if local {
+                self.bind_local(parameter.name, Shape::Unknown);
+            } else {
+                self.bind(parameter.name, Shape::Unknown);
+            }
+        }
+    }
+
+    /// Check a use of `name` as a variable
+    fn variable(&mut self, name: Symbol) {
+        match self.local(name) {
+            Some(Shape::Cell) => self.error(format!("cell `{name}` is used as a variable")),
+            Some(_) => {}
+            None if self.binders.contains(&name) && !self.globals.contains(&name) => {
+                self.error(format!("`{name}` is used outside the expression binding it"));
+            }
+            None => {}
+        }
+    }
+
+    /// Check an operand, which normalized IR requires to be a value
+    fn operand(&mut self, expr: &IRExpression, role: &str) {
+        if self.normalized && !is_value(expr) {
+            self.error(format!("{role} is not a value"));
+        }
+        self.expression(expr);
+    }
+
+    fn expression(&mut self, expr: &IRExpression) {
+        match expr {
+            IRExpression::Literal(IRLiteral::Array(elements)) => {
+                elements.iter().for_each(|element| self.operand(element, "an array element"));
+            }
+            IRExpression::Literal(IRLiteral::Record(fields)) => {
+                fields.iter().for_each(|(_, value)| self.operand(value, "a record field"));
+            }
+            IRExpression::Literal(_) => {}
+            IRExpression::Variable(name) => self.variable(*name),
+            IRExpression::Call { function, arguments } => self.call(function, arguments),
+            IRExpression::Lambda { parameters, body, closure } => {
+                for name in closure {
+                    if self.local(*name).is_none() {
+                        self.error(format!("a lambda captures `{name}`, which is not a local in scope"));
+                    }
+                }
+                for name in expr.free_variables() {
+                    if self.local(name).is_some() && !closure.contains(&name) {
+                        self.error(format!("a lambda uses `{name}` without capturing it"));
+                    }
+                }
+                let outer = self.scope.len();
+                self.bind_parameters(parameters, true);
+                self.expression(body);
+                self.scope.truncate(outer);
+            }
+            IRExpression::Let { bindings, body } => {
+                if bindings.is_empty() {
+                    self.error("a let binds nothing".to_string());
+                }
+                let outer = self.scope.len();
+                for binding in bindings {
+                    self.expression(&binding.value);
+                    let shape = Shape::of(&binding.value, self);
+                    self.bind_local(binding.name, shape);
+                }
+                self.expression(body);
+                self.scope.truncate(outer);
+            }
+            IRExpression::If { condition, then_branch, else_branch } => {
+                self.operand(condition, "a condition");
+                match Shape::of(condition, self) {
+                    Shape::Scalar("Bool") | Shape::Unknown => {}
+                    shape => {
+                        let what = shape.describe().unwrap_or_default();
+                        self.error(format!("a condition is {what}, not a Bool"));
+                    }
+                }
+                self.expression(then_branch);
+                self.expression(else_branch);
+            }
+            IRExpression::Match { value, cases } => {
+                self.operand(value, "a matched value");
+                if cases.is_empty() {
+                    self.error("a match has no cases".to_string());
+                }
+                for case in cases {
+                    let outer = self.scope.len();
+                    self.pattern(&case.pattern);
+                    let mut names = Vec::new();
+                    case.pattern.collect_variables(&mut names);
+                    for name in names.into_iter().filter(|name| name.as_str() != "_") {
+                        self.bind_local(name, Shape::Unknown);
+                    }
+                    if let Some(guard) = &case.guard {
+                        self.expression(guard);
+                    }
+                    self.expression(&case.body);
+                    self.scope.truncate(outer);
+                }
+            }
+            IRExpression::Block(expressions) => expressions.iter().for_each(|expr| self.expression(expr)),
+            IRExpression::Effect { arguments, .. } => {
+                arguments.iter().for_each(|argument| self.operand(argument, "an operation argument"));
+            }
+            IRExpression::Handle { expression, handlers, return_handler, .. } => {
+                self.expression(expression);
+                for handler in handlers {
+                    let outer = self.scope.len();
+                    for parameter in &handler.parameters {
+                        self.bind_local(*parameter, Shape::Unknown);
+                    }
+                    self.bind_local(handler.continuation, Shape::Continuation);
+                    self.expression(&handler.body);
+                    self.scope.truncate(outer);
+                }
+                if let Some(handler) = return_handler {
+                    self.expression(handler);
+                }
+            }
+            IRExpression::Resume { value, continuation } => {
+                self.operand(value, "a resumed value");
+                if self.local(*continuation) != Some(&Shape::Continuation) {
+                    self.error(format!("resumes `{continuation}`, which is not the continuation of an enclosing handler clause"));
+                }
+            }
+            IRExpression::Field { record, field } => {
+                self.operand(record, "a projected record");
+                match Shape::of(record, self) {
+                    Shape::Record(fields) if !fields.contains(field) => {
+                        self.error(format!("a record without a field `{field}` is projected on it"));
+                    }
+                    Shape::Record(_) => {}
+                    shape => {
+                        if let Some(what) = shape.describe() {
+                            self.error(format!("field `{field}` is selected from {what}"));
+                        }
+                    }
+                }
+            }
+            IRExpression::RecordUpdate { record, fields } => {
+                self.operand(record, "an updated record");
+                fields.iter().for_each(|(_, value)| self.operand(value, "a record field"));
+            }
+            IRExpression::Tuple(elements) => {
+                elements.iter().for_each(|element| self.operand(element, "a tuple element"));
+            }
+            IRExpression::TupleGet { tuple, index } => {
+                self.operand(tuple, "a projected tuple");
+                match Shape::of(tuple, self) {
+                    Shape::Tuple(arity) if *index >= arity => {
+                        self.error(format!("element {index} is selected from a tuple of {arity}"));
+                    }
+                    Shape::Tuple(_) => {}
+                    shape => {
+                        if let Some(what) = shape.describe() {
+                            self.error(format!("element {index} is selected from {what}"));
+                        }
+                    }
+                }
+            }
+            IRExpression::Cell { name, initial, body } => {
+                self.operand(initial, "a cell's initial value");
+                let outer = self.scope.len();
+                self.bind_local(*name, Shape::Cell);
+                self.expression(body);
+                self.scope.truncate(outer);
+            }
+            IRExpression::CellGet(name) => self.cell(*name),
+            IRExpression::CellSet { name, value } => {
+                self.operand(value, "a cell's new value");
+                self.cell(*name);
+            }
+        }
+    }
+
+    fn cell(&mut self, name: Symbol) {
+        if self.local(name) != Some(&Shape::Cell) {
+            self.error(format!("`{name}` is not a cell in scope"));
+        }
+    }
+
+    fn call(&mut self, function: &IRExpression, arguments: &[IRExpression]) {
+        // The head of a spine may be a call; any other callee is an operand
+        match function {
+            IRExpression::Call { function, arguments } => self.call(function, arguments),
+            function => self.operand(function, "a callee"),
+        }
+        arguments.iter().for_each(|argument| self.operand(argument, "an argument"));
+
+        if let Some(what) = Shape::of(function, self).describe() {
+            self.error(format!("{what} is called"));
+        }
+        let IRExpression::Variable(name) = function else { return };
+        if self.local(*name).is_some() {
+            return;
+        }
+        if let Some(fields) = self.constructors.get(name) {
+            if arguments.len() > *fields {
+                self.error(format!("constructor `{name}` takes {fields} arguments, given {}", arguments.len()));
+            }
+        } else if let Some(arity) = self.arities.get(name).filter(|_| self.normalized) {
+            if arguments.len() != *arity {
+                self.error(format!("`{name}` takes {arity} arguments, given {}", arguments.len()));
+            }
+        }
+    }
+
+    fn pattern(&mut self, pattern: &IRPattern) {
+        match pattern {
+            IRPattern::Constructor { name, arguments } => {
+                if let Some(fields) = self.constructors.get(name) {
+                    if arguments.len() != *fields {
+                        self.error(format!("pattern `{name}` has {} arguments, but the constructor has {fields} fields", arguments.len()));
+                    }
+                }
+                arguments.iter().for_each(|argument| self.pattern(argument));
+            }
+            IRPattern::Tuple(patterns) => patterns.iter().for_each(|pattern| self.pattern(pattern)),
+            IRPattern::Record(fields) => fields.iter().for_each(|(_, pattern)| self.pattern(pattern)),
+            IRPattern::Or(alternatives) => {
+                let bound = |pattern: &IRPattern| {
+                    let mut names = Vec::new();
+                    pattern.collect_variables(&mut names);
+                    names.sort();
+                    names
+                };
+                if let Some((first, rest)) = alternatives.split_first() {
+                    if rest.iter().any(|alternative| bound(alternative) != bound(first)) {
+                        self.error("the alternatives of an or-pattern bind different variables".to_string());
+                    }
+                }
+                alternatives.iter().for_each(|alternative| self.pattern(alternative));
+            }
+            IRPattern::Wildcard | IRPattern::Variable(_) | IRPattern::Literal(_) => {}
+        }
+    }
+}
+
+/// The names `expr` binds anywhere inside it
+fn binders(expr: &IRExpression) -> HashSet<Symbol> {
+    let mut names = Vec::new();
+    expr.walk(&mut |expr| {
+        match expr {
+            IRExpression::Lambda { parameters, .. } => names.extend(parameters.iter().map(|parameter| parameter.name)),
+            IRExpression::Let { bindings, .. } => names.extend(bindings.iter().map(|binding| binding.name)),
+            IRExpression::Match { cases, .. } => cases.iter().for_each(|case| case.pattern.collect_variables(&mut names)),
+            IRExpression::Handle { handlers, .. } => {
+                for handler in handlers {
+                    names.extend(handler.parameters.iter().copied());
+                    names.push(handler.continuation);
+                }
+            }
+            IRExpression::Cell { name, .. } => names.push(*name),
+            _ => {}
+        }
+        true
+    });
+    names.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::{IRBuilder, IREffect, IRParameter, IRPrimitiveType, IRType};
+    use x_parser::{parse_source, FileId, SyntaxStyle};
+
+    fn build(source: &str) -> IRModule {
+        let cu = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        IRBuilder::new().build_ir(&cu).unwrap().modules.remove(0)
+    }
+
+    fn messages(errors: Vec<VerifyError>) -> Vec<String> {
+        errors.iter().map(ToString::to_string).collect()
+    }
+
+    const SOURCE: &str = "module Test\n\
+        data Shape = Dot | Circle Int\n\
+        let inc = fun x -> x\n\
+        let size = fun s -> match s with | Circle r => inc (inc r) | Dot => 0\n\
+        let adder = fun n -> (let m = inc n in fun k -> inc m)";
+
+    #[test]
+    fn test_lowered_and_normalized_modules_verify() {
+        let mut module = build(SOURCE);
+        assert_eq!(messages(verify_module(&module)), Vec::<String>::new());
+        // Nested calls are fine in the tree IR, but not in normal form
+        assert_eq!(messages(verify_normalized(&module)), ["size: an argument is not a value"]);
+        anf::normalize(&mut module);
+        assert_eq!(messages(verify_normalized(&module)), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_malformed_ir_is_reported() {
+        let mut module = build(SOURCE);
+        let var = |name: &str| IRExpression::Variable(Symbol::intern(name));
+        // `adder` forgets to capture `m`, and `inc` performs an effect it does not list
+        let IRExpression::Let { body, .. } = &mut module.functions[2].body else { panic!() };
+        let IRExpression::Lambda { closure, .. } = body.as_mut() else { panic!() };
+        closure.clear();
+        module.functions[0].body = IRExpression::Effect {
+            effect: Symbol::intern("Log"),
+            operation: Symbol::intern("log"),
+            arguments: vec![IRExpression::TupleGet { tuple: Box::new(IRExpression::Tuple(vec![var("x")])), index: 1 }],
+        };
+        module.functions[0].effects = IREffectSet::Effects(vec![IREffect { name: Symbol::intern("IO"), operations: Vec::new() }]);
+        // `size` matches `Circle` without its field and resumes outside a handler clause
+        let IRExpression::Match { cases, .. } = &mut module.functions[1].body else { panic!() };
+        cases[0].pattern = IRPattern::Constructor { name: Symbol::intern("Circle"), arguments: Vec::new() };
+        cases[1].body = IRExpression::Resume { value: Box::new(var("r")), continuation: Symbol::intern("k") };
+        module.functions.push(module.functions[0].clone());
+        module.functions[3].parameters.push(IRParameter { name: Symbol::intern("x"), type_hint: IRType::Primitive(IRPrimitiveType::Unit) });
+
+        assert_eq!(messages(verify_module(&module)), [
+            "inc: `inc` is defined more than once",
+            "inc: element 1 is selected from a tuple of 1",
+            "inc: performs Log, which its effect set does not list",
+            "size: pattern `Circle` has 0 arguments, but the constructor has 1 fields",
+            "size: resumes `k`, which is not the continuation of an enclosing handler clause",
+            "adder: a lambda uses `m` without capturing it",
+            "inc: parameter `x` is listed more than once",
+            "inc: element 1 is selected from a tuple of 1",
+            "inc: performs Log, which its effect set does not list",
+        ]);
+    }
+}
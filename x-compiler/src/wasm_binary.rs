@@ -0,0 +1,1013 @@
+//! Binary encoding of core WebAssembly modules and components
+//!
+//! Only the parts of the two formats the component backend emits are
+//! covered: one memory, mutable globals, functions with multi-value
+//! results, active data segments, and for components a single embedded
+//! core module whose exports are lifted into component functions.
+//!
+//! A core module given [debug info](CoreModule::debug_info) also carries a
+//! `name` section naming its functions and locals, and DWARF sections
+//! mapping its instructions to the lines of the source file. DWARF
+//! addresses of WebAssembly code are offsets into the code section's
+//! contents, as engines and debuggers expect.
+
+/// Core value types
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ValType {
+    I32,
+    I64,
+    F64,
+}
+
+impl ValType {
+    fn byte(self) -> u8 {
+        match self {
+            ValType::I32 => 0x7f,
+            ValType::I64 => 0x7e,
+            ValType::F64 => 0x7c,
+        }
+    }
+}
+
+/// Append `value` as unsigned LEB128
+pub fn write_u32(out: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Append `value` as signed LEB128
+pub fn write_i64(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Append a length-prefixed UTF-8 name
+pub fn write_name(out: &mut Vec<u8>, name: &str) {
+    write_u32(out, name.len() as u32);
+    out.extend_from_slice(name.as_bytes());
+}
+
+/// Append a section with its size prefix
+fn write_section(out: &mut Vec<u8>, id: u8, contents: &[u8]) {
+    out.push(id);
+    write_u32(out, contents.len() as u32);
+    out.extend_from_slice(contents);
+}
+
+/// Append a custom section called `name`
+fn write_custom_section(out: &mut Vec<u8>, name: &str, contents: &[u8]) {
+    let mut section = Vec::new();
+    write_name(&mut section, name);
+    section.extend_from_slice(contents);
+    write_section(out, 0, &section);
+}
+
+/// Append a vector section: item count followed by the encoded items
+fn write_vec_section(out: &mut Vec<u8>, id: u8, items: &[Vec<u8>]) {
+    if items.is_empty() {
+        return;
+    }
+    let mut contents = Vec::new();
+    write_u32(&mut contents, items.len() as u32);
+    for item in items {
+        contents.extend_from_slice(item);
+    }
+    write_section(out, id, &contents);
+}
+
+/// Instruction sequence of a function body
+#[derive(Debug, Default, Clone)]
+pub struct Instructions {
+    bytes: Vec<u8>,
+    /// Source lines and columns of the instructions from each offset on
+    lines: Vec<LineRow>,
+}
+
+/// The source position of the instructions starting at `offset`, 1-based
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LineRow {
+    offset: u32,
+    line: u32,
+    column: u32,
+}
+
+/// Block type of `block` and `if`
+#[derive(Debug, Clone, Copy)]
+pub enum BlockType {
+    Empty,
+    Value(ValType),
+    /// Index of a function type, for blocks with several results
+    Type(u32),
+}
+
+impl Instructions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn i32_const(&mut self, value: i32) -> &mut Self {
+        self.bytes.push(0x41);
+        write_i64(&mut self.bytes, value.into());
+        self
+    }
+
+    pub fn f64_const(&mut self, value: f64) -> &mut Self {
+        self.bytes.push(0x44);
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn local_get(&mut self, index: u32) -> &mut Self {
+        self.op_index(0x20, index)
+    }
+
+    pub fn local_set(&mut self, index: u32) -> &mut Self {
+        self.op_index(0x21, index)
+    }
+
+    pub fn local_tee(&mut self, index: u32) -> &mut Self {
+        self.op_index(0x22, index)
+    }
+
+    pub fn global_get(&mut self, index: u32) -> &mut Self {
+        self.op_index(0x23, index)
+    }
+
+    pub fn global_set(&mut self, index: u32) -> &mut Self {
+        self.op_index(0x24, index)
+    }
+
+    pub fn call(&mut self, function: u32) -> &mut Self {
+        self.op_index(0x10, function)
+    }
+
+    /// Start an `if`; close it with [`end`](Self::end)
+    pub fn if_(&mut self, block_type: BlockType) -> &mut Self {
+        self.bytes.push(0x04);
+        self.block_type(block_type);
+        self
+    }
+
+    pub fn else_(&mut self) -> &mut Self {
+        self.op(0x05)
+    }
+
+    pub fn end(&mut self) -> &mut Self {
+        self.op(0x0b)
+    }
+
+    /// Store the value on top of the stack at the address below it plus `offset`
+    pub fn store(&mut self, typ: ValType, offset: u32) -> &mut Self {
+        let (opcode, align) = match typ {
+            ValType::I32 => (0x36, 2),
+            ValType::I64 => (0x37, 3),
+            ValType::F64 => (0x39, 3),
+        };
+        self.memarg(opcode, align, offset)
+    }
+
+    /// Store the low byte of an `i32`
+    pub fn i32_store8(&mut self, offset: u32) -> &mut Self {
+        self.memarg(0x3a, 0, offset)
+    }
+
+    pub fn memory_size(&mut self) -> &mut Self {
+        self.bytes.extend_from_slice(&[0x3f, 0x00]);
+        self
+    }
+
+    pub fn memory_grow(&mut self) -> &mut Self {
+        self.bytes.extend_from_slice(&[0x40, 0x00]);
+        self
+    }
+
+    /// `memory.copy` from the bulk memory proposal, part of WebAssembly 2.0
+    pub fn memory_copy(&mut self) -> &mut Self {
+        self.bytes.push(0xfc);
+        write_u32(&mut self.bytes, 10);
+        self.bytes.extend_from_slice(&[0x00, 0x00]);
+        self
+    }
+
+    /// Append instructions compiled separately, e.g. the branches of an `if`
+    pub fn append(&mut self, other: &Instructions) -> &mut Self {
+        let shift = self.bytes.len() as u32;
+        for row in &other.lines {
+            self.mark_at(LineRow { offset: row.offset + shift, ..*row });
+        }
+        self.bytes.extend_from_slice(&other.bytes);
+        self
+    }
+
+    /// Attribute the instructions emitted next to a 1-based source line and
+    /// column, for the module's line table
+    pub fn mark(&mut self, line: u32, column: u32) -> &mut Self {
+        self.mark_at(LineRow { offset: self.bytes.len() as u32, line, column });
+        self
+    }
+
+    fn mark_at(&mut self, row: LineRow) {
+        match self.lines.last_mut() {
+            Some(last) if (last.line, last.column) == (row.line, row.column) => {}
+            // No instruction was emitted at the earlier position
+            Some(last) if last.offset == row.offset => *last = row,
+            _ => self.lines.push(row),
+        }
+    }
+
+    /// Emit a plain opcode, see the `op` module for the ones in use
+    pub fn op(&mut self, opcode: u8) -> &mut Self {
+        self.bytes.push(opcode);
+        self
+    }
+
+    fn op_index(&mut self, opcode: u8, index: u32) -> &mut Self {
+        self.bytes.push(opcode);
+        write_u32(&mut self.bytes, index);
+        self
+    }
+
+    fn memarg(&mut self, opcode: u8, align: u32, offset: u32) -> &mut Self {
+        self.bytes.push(opcode);
+        write_u32(&mut self.bytes, align);
+        write_u32(&mut self.bytes, offset);
+        self
+    }
+
+    fn block_type(&mut self, block_type: BlockType) {
+        match block_type {
+            BlockType::Empty => self.bytes.push(0x40),
+            BlockType::Value(typ) => self.bytes.push(typ.byte()),
+            BlockType::Type(index) => write_i64(&mut self.bytes, index.into()),
+        }
+    }
+}
+
+/// Opcodes without immediates
+pub mod op {
+    pub const UNREACHABLE: u8 = 0x00;
+    pub const DROP: u8 = 0x1a;
+    pub const I32_EQZ: u8 = 0x45;
+    pub const I32_EQ: u8 = 0x46;
+    pub const I32_NE: u8 = 0x47;
+    pub const I32_LT_S: u8 = 0x48;
+    pub const I32_GT_S: u8 = 0x4a;
+    pub const I32_GT_U: u8 = 0x4b;
+    pub const I32_LE_S: u8 = 0x4c;
+    pub const I32_GE_S: u8 = 0x4e;
+    pub const F64_EQ: u8 = 0x61;
+    pub const F64_NE: u8 = 0x62;
+    pub const F64_LT: u8 = 0x63;
+    pub const F64_GT: u8 = 0x64;
+    pub const F64_LE: u8 = 0x65;
+    pub const F64_GE: u8 = 0x66;
+    pub const I32_ADD: u8 = 0x6a;
+    pub const I32_SUB: u8 = 0x6b;
+    pub const I32_MUL: u8 = 0x6c;
+    pub const I32_DIV_S: u8 = 0x6d;
+    pub const I32_REM_S: u8 = 0x6f;
+    pub const I32_AND: u8 = 0x71;
+    pub const I32_SHL: u8 = 0x74;
+    pub const I32_SHR_U: u8 = 0x76;
+    pub const F64_ADD: u8 = 0xa0;
+    pub const F64_SUB: u8 = 0xa1;
+    pub const F64_MUL: u8 = 0xa2;
+    pub const F64_DIV: u8 = 0xa3;
+}
+
+/// A function defined by a core module
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub type_index: u32,
+    /// Locals beyond the parameters
+    pub locals: Vec<ValType>,
+    pub body: Instructions,
+}
+
+/// A core WebAssembly module with one memory
+#[derive(Debug, Default)]
+pub struct CoreModule {
+    types: Vec<(Vec<ValType>, Vec<ValType>)>,
+    functions: Vec<Option<Function>>,
+    globals: Vec<(ValType, i32)>,
+    exports: Vec<(String, u8, u32)>,
+    data: Vec<(u32, Vec<u8>)>,
+    memory_pages: u32,
+    /// Names for the `name` section
+    name: Option<String>,
+    function_names: Vec<(u32, String)>,
+    local_names: Vec<(u32, Vec<(u32, String)>)>,
+    /// Path of the source file the line tables refer to
+    debug_file: Option<String>,
+}
+
+impl CoreModule {
+    pub fn new() -> Self {
+        CoreModule { memory_pages: 1, ..Default::default() }
+    }
+
+    /// Index of the function type `params -> results`, adding it if new
+    pub fn func_type(&mut self, params: &[ValType], results: &[ValType]) -> u32 {
+        let signature = (params.to_vec(), results.to_vec());
+        match self.types.iter().position(|existing| *existing == signature) {
+            Some(index) => index as u32,
+            None => {
+                self.types.push(signature);
+                self.types.len() as u32 - 1
+            }
+        }
+    }
+
+    /// Reserve a function index, so bodies can call functions defined later
+    pub fn declare_function(&mut self) -> u32 {
+        self.functions.push(None);
+        self.functions.len() as u32 - 1
+    }
+
+    pub fn define_function(&mut self, index: u32, function: Function) {
+        self.functions[index as usize] = Some(function);
+    }
+
+    /// Add a mutable global with an `i32` initial value
+    pub fn global_i32(&mut self, value: i32) -> u32 {
+        self.globals.push((ValType::I32, value));
+        self.globals.len() as u32 - 1
+    }
+
+    pub fn export_function(&mut self, name: &str, index: u32) {
+        self.exports.push((name.to_string(), 0x00, index));
+    }
+
+    pub fn export_memory(&mut self, name: &str) {
+        self.exports.push((name.to_string(), 0x02, 0));
+    }
+
+    /// Place `bytes` at `offset` in memory when the module is instantiated
+    pub fn data(&mut self, offset: u32, bytes: Vec<u8>) {
+        self.data.push((offset, bytes));
+    }
+
+    /// Give the module a name in its `name` section
+    pub fn name_module(&mut self, name: &str) {
+        self.name = Some(name.to_string());
+    }
+
+    pub fn name_function(&mut self, index: u32, name: &str) {
+        self.function_names.push((index, name.to_string()));
+    }
+
+    /// Name the locals of function `index`, parameters included
+    pub fn name_locals(&mut self, function: u32, names: Vec<(u32, String)>) {
+        if !names.is_empty() {
+            self.local_names.push((function, names));
+        }
+    }
+
+    /// Emit DWARF sections mapping the instructions
+    /// [marked](Instructions::mark) with source lines to the file at `path`
+    pub fn debug_info(&mut self, path: &str) {
+        self.debug_file = Some(path.to_string());
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = b"\0asm".to_vec();
+        out.extend_from_slice(&[0x01, 0x00, 0x00, 0x00]);
+
+        let types: Vec<Vec<u8>> = self.types.iter()
+            .map(|(params, results)| {
+                let mut bytes = vec![0x60];
+                write_u32(&mut bytes, params.len() as u32);
+                bytes.extend(params.iter().map(|typ| typ.byte()));
+                write_u32(&mut bytes, results.len() as u32);
+                bytes.extend(results.iter().map(|typ| typ.byte()));
+                bytes
+            })
+            .collect();
+        write_vec_section(&mut out, 1, &types);
+
+        let functions: Vec<&Function> = self.functions.iter()
+            .map(|function| function.as_ref().expect("declared function was never defined"))
+            .collect();
+        let signatures: Vec<Vec<u8>> = functions.iter()
+            .map(|function| {
+                let mut bytes = Vec::new();
+                write_u32(&mut bytes, function.type_index);
+                bytes
+            })
+            .collect();
+        write_vec_section(&mut out, 3, &signatures);
+
+        let mut memory = vec![0x00];
+        write_u32(&mut memory, self.memory_pages);
+        write_vec_section(&mut out, 5, &[memory]);
+
+        let globals: Vec<Vec<u8>> = self.globals.iter()
+            .map(|(typ, value)| {
+                let mut bytes = vec![typ.byte(), 0x01];
+                let mut init = Instructions::new();
+                init.i32_const(*value).end();
+                bytes.extend_from_slice(&init.bytes);
+                bytes
+            })
+            .collect();
+        write_vec_section(&mut out, 6, &globals);
+
+        let exports: Vec<Vec<u8>> = self.exports.iter()
+            .map(|(name, kind, index)| {
+                let mut bytes = Vec::new();
+                write_name(&mut bytes, name);
+                bytes.push(*kind);
+                write_u32(&mut bytes, *index);
+                bytes
+            })
+            .collect();
+        write_vec_section(&mut out, 7, &exports);
+
+        // Sizes of the body size and locals preceding each function's instructions
+        let mut prefixes = Vec::with_capacity(functions.len());
+        let code: Vec<Vec<u8>> = functions.iter()
+            .map(|function| {
+                let mut body = Vec::new();
+                // Runs of locals with the same type share an entry
+                let mut runs: Vec<(u32, ValType)> = Vec::new();
+                for typ in &function.locals {
+                    match runs.last_mut() {
+                        Some((count, last)) if last == typ => *count += 1,
+                        _ => runs.push((1, *typ)),
+                    }
+                }
+                write_u32(&mut body, runs.len() as u32);
+                for (count, typ) in runs {
+                    write_u32(&mut body, count);
+                    body.push(typ.byte());
+                }
+                let locals_size = body.len();
+                body.extend_from_slice(&function.body.bytes);
+                body.push(0x0b);
+
+                let mut bytes = Vec::new();
+                write_u32(&mut bytes, body.len() as u32);
+                prefixes.push((bytes.len() as u32, locals_size as u32));
+                bytes.extend(body);
+                bytes
+            })
+            .collect();
+        write_vec_section(&mut out, 10, &code);
+
+        let data: Vec<Vec<u8>> = self.data.iter()
+            .map(|(offset, contents)| {
+                let mut bytes = vec![0x00];
+                let mut init = Instructions::new();
+                init.i32_const(*offset as i32).end();
+                bytes.extend_from_slice(&init.bytes);
+                write_u32(&mut bytes, contents.len() as u32);
+                bytes.extend_from_slice(contents);
+                bytes
+            })
+            .collect();
+        write_vec_section(&mut out, 11, &data);
+
+        if self.name.is_some() || !self.function_names.is_empty() || !self.local_names.is_empty() {
+            write_custom_section(&mut out, "name", &self.name_section());
+        }
+        if let Some(path) = &self.debug_file {
+            // Each body, from its locals on, as offsets into the code
+            // section's contents, which start with the function count
+            let mut offset = leb_size(code.len() as u32);
+            let ranges: Vec<(u32, u32)> = code.iter().zip(&prefixes)
+                .map(|(entry, (size, _))| {
+                    let range = (offset + size, offset + entry.len() as u32);
+                    offset = range.1;
+                    range
+                })
+                .collect();
+            let starts: Vec<u32> = prefixes.iter().map(|(_, locals)| *locals).collect();
+            let dwarf = Dwarf { path, functions: &functions, ranges: &ranges, starts: &starts };
+            write_custom_section(&mut out, ".debug_abbrev", &dwarf.abbreviations());
+            write_custom_section(&mut out, ".debug_info", &dwarf.info(&self.function_names));
+            write_custom_section(&mut out, ".debug_line", &dwarf.lines());
+        }
+
+        out
+    }
+
+    /// Subsections 0 to 2 of the `name` section: the module name, function
+    /// names and local names, each sorted by index
+    fn name_section(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        if let Some(name) = &self.name {
+            let mut contents = Vec::new();
+            write_name(&mut contents, name);
+            write_section(&mut out, 0, &contents);
+        }
+        if !self.function_names.is_empty() {
+            write_section(&mut out, 1, &name_map(&self.function_names));
+        }
+        if !self.local_names.is_empty() {
+            let mut functions: Vec<&(u32, Vec<(u32, String)>)> = self.local_names.iter().collect();
+            functions.sort_by_key(|(index, _)| *index);
+            let mut contents = Vec::new();
+            write_u32(&mut contents, functions.len() as u32);
+            for (index, names) in functions {
+                write_u32(&mut contents, *index);
+                contents.extend(name_map(names));
+            }
+            write_section(&mut out, 2, &contents);
+        }
+        out
+    }
+}
+
+/// A `name` section name map, sorted by index as the format requires
+fn name_map(names: &[(u32, String)]) -> Vec<u8> {
+    let mut names: Vec<&(u32, String)> = names.iter().collect();
+    names.sort_by_key(|(index, _)| *index);
+    let mut out = Vec::new();
+    write_u32(&mut out, names.len() as u32);
+    for (index, name) in names {
+        write_u32(&mut out, *index);
+        write_name(&mut out, name);
+    }
+    out
+}
+
+/// Bytes `value` takes as unsigned LEB128
+fn leb_size(value: u32) -> u32 {
+    let mut bytes = Vec::new();
+    write_u32(&mut bytes, value);
+    bytes.len() as u32
+}
+
+/// DWARF 4 sections of a module compiled from one source file: a compile
+/// unit with a subprogram per function, and a line table
+struct Dwarf<'a> {
+    path: &'a str,
+    functions: &'a [&'a Function],
+    /// Offsets of each function's body in the code section
+    ranges: &'a [(u32, u32)],
+    /// Offsets of each function's instructions in its body
+    starts: &'a [u32],
+}
+
+const DW_TAG_COMPILE_UNIT: u8 = 0x11;
+const DW_TAG_SUBPROGRAM: u8 = 0x2e;
+const DW_AT_NAME: u8 = 0x03;
+const DW_AT_STMT_LIST: u8 = 0x10;
+const DW_AT_LOW_PC: u8 = 0x11;
+const DW_AT_HIGH_PC: u8 = 0x12;
+const DW_AT_PRODUCER: u8 = 0x25;
+const DW_FORM_ADDR: u8 = 0x01;
+const DW_FORM_DATA4: u8 = 0x06;
+const DW_FORM_STRING: u8 = 0x08;
+const DW_FORM_SEC_OFFSET: u8 = 0x17;
+const DW_LNS_COPY: u8 = 0x01;
+const DW_LNS_ADVANCE_LINE: u8 = 0x03;
+const DW_LNS_SET_COLUMN: u8 = 0x05;
+const DW_LNE_END_SEQUENCE: u8 = 0x01;
+const DW_LNE_SET_ADDRESS: u8 = 0x02;
+/// Abbreviation codes of the two kinds of entries
+const COMPILE_UNIT: u32 = 1;
+const SUBPROGRAM: u32 = 2;
+
+/// An abbreviation's code, tag, whether its entries have children, and the
+/// forms of its attributes
+type Abbreviation = (u32, u8, bool, &'static [(u8, u8)]);
+
+impl Dwarf<'_> {
+    fn abbreviations(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        let abbreviations: [Abbreviation; 2] = [
+            (COMPILE_UNIT, DW_TAG_COMPILE_UNIT, true, &[
+                (DW_AT_PRODUCER, DW_FORM_STRING),
+                (DW_AT_NAME, DW_FORM_STRING),
+                (DW_AT_STMT_LIST, DW_FORM_SEC_OFFSET),
+                (DW_AT_LOW_PC, DW_FORM_ADDR),
+                (DW_AT_HIGH_PC, DW_FORM_DATA4),
+            ]),
+            (SUBPROGRAM, DW_TAG_SUBPROGRAM, false, &[
+                (DW_AT_NAME, DW_FORM_STRING),
+                (DW_AT_LOW_PC, DW_FORM_ADDR),
+                (DW_AT_HIGH_PC, DW_FORM_DATA4),
+            ]),
+        ];
+        for (code, tag, children, attributes) in abbreviations {
+            write_u32(&mut out, code);
+            write_u32(&mut out, tag.into());
+            out.push(children.into());
+            for (attribute, form) in attributes {
+                write_u32(&mut out, (*attribute).into());
+                write_u32(&mut out, (*form).into());
+            }
+            out.extend_from_slice(&[0, 0]);
+        }
+        out.push(0);
+        out
+    }
+
+    fn info(&self, names: &[(u32, String)]) -> Vec<u8> {
+        let end = self.ranges.last().map_or(0, |(_, end)| *end);
+        let mut entries = Vec::new();
+        write_u32(&mut entries, COMPILE_UNIT);
+        write_cstr(&mut entries, concat!("x compiler ", env!("CARGO_PKG_VERSION")));
+        write_cstr(&mut entries, self.path);
+        entries.extend_from_slice(&0u32.to_le_bytes());
+        entries.extend_from_slice(&0u32.to_le_bytes());
+        entries.extend_from_slice(&end.to_le_bytes());
+        for (index, (start, end)) in self.ranges.iter().enumerate() {
+            let name = names.iter()
+                .find(|(named, _)| *named as usize == index)
+                .map_or_else(|| format!("func{index}"), |(_, name)| name.clone());
+            write_u32(&mut entries, SUBPROGRAM);
+            write_cstr(&mut entries, &name);
+            entries.extend_from_slice(&start.to_le_bytes());
+            entries.extend_from_slice(&(end - start).to_le_bytes());
+        }
+        entries.push(0);
+
+        // Version 4, abbreviations at offset 0, 4-byte addresses
+        let mut unit = vec![4, 0, 0, 0, 0, 0, 4];
+        unit.extend(entries);
+        with_length(unit)
+    }
+
+    fn lines(&self) -> Vec<u8> {
+        let mut header = vec![
+            // Minimum instruction length, maximum operations per instruction, default is_stmt
+            1, 1, 1,
+            // Line base, line range, opcode base
+            (-5i8) as u8, 14, 13,
+        ];
+        // Operand counts of the standard opcodes
+        header.extend_from_slice(&[0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1]);
+        // No include directories, then the file with no directory, time or size
+        header.push(0);
+        write_cstr(&mut header, self.path);
+        header.extend_from_slice(&[0, 0, 0, 0]);
+
+        // A sequence per function with source lines, so the code between
+        // them has none
+        let mut program = Vec::new();
+        for ((function, (start, end)), instructions) in self.functions.iter().zip(self.ranges).zip(self.starts) {
+            if function.body.lines.is_empty() {
+                continue;
+            }
+            let mut line = 1;
+            for row in &function.body.lines {
+                set_address(&mut program, start + instructions + row.offset);
+                if row.line != line {
+                    program.push(DW_LNS_ADVANCE_LINE);
+                    write_i64(&mut program, i64::from(row.line) - i64::from(line));
+                    line = row.line;
+                }
+                program.push(DW_LNS_SET_COLUMN);
+                write_u32(&mut program, row.column);
+                program.push(DW_LNS_COPY);
+            }
+            set_address(&mut program, *end);
+            program.extend_from_slice(&[0, 1, DW_LNE_END_SEQUENCE]);
+        }
+
+        let mut unit = vec![4, 0];
+        unit.extend_from_slice(&(header.len() as u32).to_le_bytes());
+        unit.extend(header);
+        unit.extend(program);
+        with_length(unit)
+    }
+}
+
+/// Append a NUL-terminated string
+fn write_cstr(out: &mut Vec<u8>, value: &str) {
+    out.extend_from_slice(value.as_bytes());
+    out.push(0);
+}
+
+/// Append the extended opcode setting the line table's address
+fn set_address(out: &mut Vec<u8>, address: u32) {
+    out.extend_from_slice(&[0, 5, DW_LNE_SET_ADDRESS]);
+    out.extend_from_slice(&address.to_le_bytes());
+}
+
+/// A DWARF unit: its contents after their 32-bit length
+fn with_length(contents: Vec<u8>) -> Vec<u8> {
+    let mut out = (contents.len() as u32).to_le_bytes().to_vec();
+    out.extend(contents);
+    out
+}
+
+/// Component-level value types
+#[derive(Debug, Clone, PartialEq)]
+pub enum ComponentValType {
+    Bool,
+    S32,
+    F64,
+    String,
+    /// A type defined earlier in the component's type index space
+    Type(u32),
+}
+
+impl ComponentValType {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            ComponentValType::Bool => out.push(0x7f),
+            ComponentValType::S32 => out.push(0x7a),
+            ComponentValType::F64 => out.push(0x75),
+            ComponentValType::String => out.push(0x73),
+            ComponentValType::Type(index) => write_u32(out, *index),
+        }
+    }
+}
+
+/// Canonical ABI options of a lifted function
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CanonOptions {
+    /// Core memory the function's strings and lists live in
+    pub memory: Option<u32>,
+    /// Core function allocating memory for arguments passed to the function
+    pub realloc: Option<u32>,
+}
+
+/// A component wrapping a single core module
+///
+/// Items are appended in definition order, each in its own section, so a
+/// type can refer to any type or export defined before it.
+#[derive(Debug)]
+pub struct Component {
+    sections: Vec<u8>,
+    types: u32,
+    core_funcs: u32,
+    funcs: u32,
+}
+
+impl Default for Component {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Component {
+    pub fn new() -> Self {
+        Component { sections: Vec::new(), types: 0, core_funcs: 0, funcs: 0 }
+    }
+
+    /// Embed `module` and instantiate it without imports, as core instance 0
+    pub fn instantiate_module(&mut self, module: &CoreModule) {
+        write_section(&mut self.sections, 1, &module.encode());
+        self.vec_item(2, |bytes| bytes.extend_from_slice(&[0x00, 0x00, 0x00]));
+    }
+
+    /// Alias a function exported by the core instance
+    pub fn alias_core_func(&mut self, name: &str) -> u32 {
+        self.vec_item(6, |bytes| {
+            bytes.extend_from_slice(&[0x00, 0x00, 0x01, 0x00]);
+            write_name(bytes, name);
+        });
+        self.core_funcs += 1;
+        self.core_funcs - 1
+    }
+
+    /// Alias the memory exported by the core instance, as core memory 0
+    pub fn alias_core_memory(&mut self, name: &str) -> u32 {
+        self.vec_item(6, |bytes| {
+            bytes.extend_from_slice(&[0x00, 0x02, 0x01, 0x00]);
+            write_name(bytes, name);
+        });
+        0
+    }
+
+    /// Define `record { label: type, ... }`
+    pub fn record_type(&mut self, fields: &[(String, ComponentValType)]) -> u32 {
+        self.vec_item(7, |bytes| {
+            bytes.push(0x72);
+            write_u32(bytes, fields.len() as u32);
+            for (label, typ) in fields {
+                write_name(bytes, label);
+                typ.encode(bytes);
+            }
+        });
+        self.next_type()
+    }
+
+    /// Define `list<element>`
+    pub fn list_type(&mut self, element: &ComponentValType) -> u32 {
+        self.vec_item(7, |bytes| {
+            bytes.push(0x70);
+            element.encode(bytes);
+        });
+        self.next_type()
+    }
+
+    /// Define a function type with named parameters
+    pub fn func_type(&mut self, params: &[(String, ComponentValType)], result: Option<&ComponentValType>) -> u32 {
+        self.vec_item(7, |bytes| {
+            bytes.push(0x40);
+            write_u32(bytes, params.len() as u32);
+            for (name, typ) in params {
+                write_name(bytes, name);
+                typ.encode(bytes);
+            }
+            match result {
+                Some(typ) => {
+                    bytes.push(0x00);
+                    typ.encode(bytes);
+                }
+                None => bytes.extend_from_slice(&[0x01, 0x00]),
+            }
+        });
+        self.next_type()
+    }
+
+    /// Export a type under `name`; the export is itself a new type, the one
+    /// exported signatures must refer to
+    pub fn export_type(&mut self, name: &str, index: u32) -> u32 {
+        self.export(name, 0x03, index);
+        self.next_type()
+    }
+
+    /// Lift a core function to a component function of type `func_type`
+    pub fn lift(&mut self, core_func: u32, func_type: u32, options: CanonOptions) -> u32 {
+        self.vec_item(8, |bytes| {
+            bytes.extend_from_slice(&[0x00, 0x00]);
+            write_u32(bytes, core_func);
+            // Strings use UTF-8, the default encoding
+            let opts: Vec<(u8, u32)> = [(0x03, options.memory), (0x04, options.realloc)]
+                .into_iter()
+                .filter_map(|(opt, index)| Some((opt, index?)))
+                .collect();
+            write_u32(bytes, opts.len() as u32);
+            for (opt, index) in opts {
+                bytes.push(opt);
+                write_u32(bytes, index);
+            }
+            write_u32(bytes, func_type);
+        });
+        self.funcs += 1;
+        self.funcs - 1
+    }
+
+    /// Export a function under `name`; like a type export, the export is
+    /// itself a new function, which later lifts are numbered after
+    pub fn export_func(&mut self, name: &str, index: u32) -> u32 {
+        self.export(name, 0x01, index);
+        self.funcs += 1;
+        self.funcs - 1
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = b"\0asm".to_vec();
+        out.extend_from_slice(&[0x0d, 0x00, 0x01, 0x00]);
+        out.extend_from_slice(&self.sections);
+        out
+    }
+
+    fn export(&mut self, name: &str, sort: u8, index: u32) {
+        self.vec_item(11, |bytes| {
+            bytes.push(0x00);
+            write_name(bytes, name);
+            bytes.push(sort);
+            write_u32(bytes, index);
+            bytes.push(0x00);
+        });
+    }
+
+    fn next_type(&mut self) -> u32 {
+        self.types += 1;
+        self.types - 1
+    }
+
+    /// Append a section of `id` holding the single item written by `item`
+    fn vec_item(&mut self, id: u8, item: impl FnOnce(&mut Vec<u8>)) {
+        let mut bytes = Vec::new();
+        item(&mut bytes);
+        write_vec_section(&mut self.sections, id, &[bytes]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_leb128() {
+        let encode = |value: u32| {
+            let mut out = Vec::new();
+            write_u32(&mut out, value);
+            out
+        };
+        assert_eq!(encode(0), [0x00]);
+        assert_eq!(encode(127), [0x7f]);
+        assert_eq!(encode(624_485), [0xe5, 0x8e, 0x26]);
+
+        let encode = |value: i64| {
+            let mut out = Vec::new();
+            write_i64(&mut out, value);
+            out
+        };
+        assert_eq!(encode(-1), [0x7f]);
+        assert_eq!(encode(64), [0xc0, 0x00]);
+        assert_eq!(encode(-123_456), [0xc0, 0xbb, 0x78]);
+    }
+
+    #[test]
+    fn test_core_module_layout() {
+        let mut module = CoreModule::new();
+        let typ = module.func_type(&[ValType::I32], &[ValType::I32]);
+        assert_eq!(module.func_type(&[ValType::I32], &[ValType::I32]), typ);
+        let f = module.declare_function();
+        let mut body = Instructions::new();
+        body.local_get(0);
+        module.define_function(f, Function { type_index: typ, locals: Vec::new(), body });
+        module.export_function("id", f);
+
+        let bytes = module.encode();
+        assert_eq!(&bytes[..8], b"\0asm\x01\0\0\0");
+        // Sections appear in increasing id order: type, function, memory, export, code
+        let ids: Vec<u8> = section_ids(&bytes[8..]);
+        assert_eq!(ids, [1, 3, 5, 7, 10]);
+    }
+
+    #[test]
+    fn test_debug_sections() {
+        let mut module = CoreModule::new();
+        let typ = module.func_type(&[ValType::I32], &[ValType::I32]);
+        let f = module.declare_function();
+        let mut branch = Instructions::new();
+        branch.mark(3, 5).local_get(0);
+        let mut body = Instructions::new();
+        body.mark(2, 1).mark(2, 3).i32_const(1).append(&branch);
+        assert_eq!(body.lines, [
+            LineRow { offset: 0, line: 2, column: 3 },
+            LineRow { offset: 2, line: 3, column: 5 },
+        ]);
+        module.define_function(f, Function { type_index: typ, locals: Vec::new(), body });
+        module.name_module("Test");
+        module.name_function(f, "id");
+        module.name_locals(f, vec![(0, "x".to_string())]);
+        module.debug_info("Test.x");
+
+        let bytes = module.encode();
+        let customs: Vec<&[u8]> = sections(&bytes[8..]).into_iter()
+            .filter(|(id, _)| *id == 0)
+            .map(|(_, contents)| contents)
+            .collect();
+        let names: Vec<&str> = customs.iter()
+            .map(|contents| std::str::from_utf8(&contents[1..1 + contents[0] as usize]).unwrap())
+            .collect();
+        assert_eq!(names, ["name", ".debug_abbrev", ".debug_info", ".debug_line"]);
+        // Module, function and local names
+        assert_eq!(&customs[0][5..], b"\x00\x05\x04Test\x01\x05\x01\x00\x02id\x02\x06\x01\x00\x01\x00\x01x");
+        // The code section holds one function: its count, size and empty
+        // locals, so its instructions start at offset 3
+        let lines = customs[3];
+        for address in [3u32, 5] {
+            let set_address = [&[0, 5, DW_LNE_SET_ADDRESS][..], &address.to_le_bytes()].concat();
+            assert!(lines.windows(7).any(|window| window == set_address), "no row at {address}");
+        }
+    }
+
+    #[test]
+    fn test_component_preamble() {
+        let mut component = Component::new();
+        component.instantiate_module(&CoreModule::new());
+        let bytes = component.encode();
+        assert_eq!(&bytes[..8], b"\0asm\x0d\0\x01\0");
+        assert_eq!(section_ids(&bytes[8..]), [1, 2]);
+    }
+
+    fn section_ids(bytes: &[u8]) -> Vec<u8> {
+        sections(bytes).into_iter().map(|(id, _)| id).collect()
+    }
+
+    fn sections(mut bytes: &[u8]) -> Vec<(u8, &[u8])> {
+        let mut sections = Vec::new();
+        while let Some((&id, rest)) = bytes.split_first() {
+            let (mut size, mut shift, mut read) = (0usize, 0, 0);
+            loop {
+                let byte = rest[read];
+                size |= usize::from(byte & 0x7f) << shift;
+                shift += 7;
+                read += 1;
+                if byte & 0x80 == 0 {
+                    break;
+                }
+            }
+            sections.push((id, &rest[read..read + size]));
+            bytes = &rest[read + size..];
+        }
+        sections
+    }
+}
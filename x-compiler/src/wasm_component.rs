@@ -1,4 +1,5 @@
 use crate::backend::*;
+use crate::component_binary::compile_component;
 use crate::wit::WitGenerator;
 use x_parser::{CompilationUnit, Module, Symbol, TypeDefKind, Type, Visibility, Item, TypeDef, ValueDef};
 use x_checker::TypeScheme;
@@ -48,29 +49,42 @@ impl CodegenBackend for WasmComponentBackend {
     ) -> Result<CodegenResult> {
         let start_time = std::time::Instant::now();
         let mut files = HashMap::new();
+        let mut binary_files = HashMap::new();
         let mut diagnostics = Vec::new();
+        let file_stem = cu.module.name.segments.first()
+            .map(|s| s.as_str())
+            .unwrap_or("main");
 
         // Generate WIT file
         match self.wit_generator.generate(cu) {
             Ok(wit_content) => {
-                let mut wit_path = options.output_dir.clone();
-                wit_path.push(format!("{}.wit", 
-                    cu.module.name.segments.first()
-                        .map(|s| s.as_str())
-                        .unwrap_or("main")));
-                
-                files.insert(wit_path, wit_content);
+                files.insert(options.output_dir.join(options.naming.module_file(file_stem, "wit")), wit_content);
             }
             Err(e) => {
                 diagnostics.push(CodegenDiagnostic {
                     severity: DiagnosticSeverity::Error,
+                    code: "E0200",
                     message: format!("Failed to generate WIT: {e}"),
                     location: None,
                 });
             }
         }
 
+        // Compile the component binary
+        options.check_cancelled()?;
+        let component = compile_component(cu, options.debug_info.then_some(&*options.sources));
+        for skipped in component.skipped {
+            diagnostics.push(CodegenDiagnostic {
+                severity: DiagnosticSeverity::Warning,
+                code: "E0205",
+                message: format!("`{}` is not exported from the component: {}", skipped.name, skipped.reason),
+                location: Some(skipped.span),
+            });
+        }
+        binary_files.insert(options.output_dir.join(options.naming.module_file(file_stem, "wasm")), component.bytes);
+
         // Generate Rust source code for the component
+        options.check_cancelled()?;
         match self.generate_rust_component(cu, type_info) {
             Ok(rust_content) => {
                 let mut rust_path = options.output_dir.clone();
@@ -81,6 +95,7 @@ impl CodegenBackend for WasmComponentBackend {
             Err(e) => {
                 diagnostics.push(CodegenDiagnostic {
                     severity: DiagnosticSeverity::Error,
+                    code: "E0201",
                     message: format!("Failed to generate Rust component: {e}"),
                     location: None,
                 });
@@ -99,19 +114,26 @@ impl CodegenBackend for WasmComponentBackend {
         build_path.push("build.rs");
         files.insert(build_path, build_script);
 
+        // Without an IR, all of the time goes to emitting
+        let compilation_time = start_time.elapsed();
+
         // Calculate total size
-        let total_size = files.values().map(|content| content.len()).sum();
-        let file_count = files.len();
+        let total_size = files.values().map(|content| content.len()).sum::<usize>()
+            + binary_files.values().map(|content| content.len()).sum::<usize>();
+        let file_count = files.len() + binary_files.len();
 
         Ok(CodegenResult {
             files,
+            binary_files,
             source_maps: HashMap::new(),
             diagnostics,
             metadata: CodegenMetadata {
                 target_info: self.target_info(),
                 generated_files: file_count,
                 total_size,
-                compilation_time: start_time.elapsed(),
+                compilation_time,
+                passes: Vec::new(),
+                emit_time: compilation_time,
             },
         })
     }
@@ -528,8 +550,8 @@ mod tests {
     fn test_type_conversion() {
         let backend = WasmComponentBackend::new();
         
-        use x_parser::{Symbol, Span, FileId, span::ByteOffset};
-        let span = Span::new(FileId::new(0), ByteOffset(0), ByteOffset(0));
+        use x_parser::{Symbol, Span, FileId, span::CharOffset};
+        let span = Span::new(FileId::new(0), CharOffset(0), CharOffset(0));
         
         assert_eq!(backend.type_to_rust_type(&Type::Con(Symbol::intern("Int"), span)), "i32");
         assert_eq!(backend.type_to_rust_type(&Type::Con(Symbol::intern("String"), span)), "String");
@@ -540,4 +562,35 @@ mod tests {
             span
         )), "Vec<i32>");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_generate_code_emits_component_binary() {
+        use x_parser::{parse_source, FileId, SyntaxStyle};
+        let source = "module Test\npub let add = fun x -> fun y -> x + y\npub let same = fun x -> x";
+        let cu = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let mut backend = WasmComponentBackend::new();
+        let options = CodegenOptions {
+            target: backend.target_info(),
+            output_dir: std::path::PathBuf::from("out"),
+            source_maps: false,
+            debug_info: false,
+            optimization_level: 0,
+            emit_types: false,
+            naming: Default::default(),
+            cancellation: Default::default(),
+            passes: Default::default(),
+            inlining: Default::default(),
+            sources: Default::default(),
+        };
+
+        let result = backend.generate_code(&cu, &HashMap::new(), &options).unwrap();
+        let binary = &result.binary_files[&std::path::PathBuf::from("out/Test.wasm")];
+        assert_eq!(&binary[..8], b"\0asm\x0d\0\x01\0");
+        wasmparser::Validator::new_with_features(wasmparser::WasmFeatures::all())
+            .validate_all(binary)
+            .unwrap();
+        assert_eq!(result.diagnostics.len(), 1);
+        assert_eq!(result.diagnostics[0].code, "E0205");
+        assert_eq!(result.diagnostics[0].message, "`same` is not exported from the component: its type is polymorphic");
+    }
+}
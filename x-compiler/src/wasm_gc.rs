@@ -2,30 +2,116 @@
 //! 
 //! This backend generates WebAssembly GC code from x Language IR,
 //! leveraging the GC proposal for efficient functional programming.
+//!
+//! Values have a uniform `anyref` representation: numbers are boxed in
+//! `$int` and `$float` structs, booleans are `i31` references and unit is
+//! null. Lambdas are lifted to functions of their closure and one argument,
+//! and a closure is a `$closure` struct pairing a reference to that function
+//! with an array of the captured values. Data constructors are structs
+//! extending `$data`, whose first field is the constructor's tag.
+//!
+//! Calls to `Core` library functions with a WebAssembly GC
+//! [intrinsic](crate::stdlib::Intrinsic) call the matching prelude function.
+//!
+//! Locals the `unbox` [pass](crate::passes) types as `Int`, `Float` or
+//! `Bool` are held [unboxed](crate::runtime::Unboxed) in `i64`, `f64` and
+//! `i32` locals named after them with the type as suffix, and arithmetic and
+//! comparisons on such scalars are core instructions, so only values leaving
+//! the arithmetic are boxed.
+//!
+//! With debug info, each function's body starts with a `;;@ file:line:column`
+//! annotation giving the definition's source location, which Binaryen turns
+//! into DWARF or a source map, and a function whose identifier had to be
+//! mangled keeps its x name in a `(@name ...)` annotation for the `name`
+//! section.
+//!
+//! With the `tail_calls` target option, which needs an engine implementing
+//! the tail call proposal, a [tail call](crate::tail_calls) of one of the
+//! module's functions is a `return_call`, reusing the caller's frame.
 
 use crate::{
+    anf::module_names,
     backend::*,
     ir::*,
+    runtime::{self, Unboxed},
+    stdlib,
+    tail_calls,
+    unboxing,
     utils,
     Result,
 };
 use crate::codegen_mod::{WasmOptLevel, GCStrategy};
-use x_parser::{CompilationUnit, Module, Symbol};
+use crate::decision_tree::{self, Access, Constructors, Decision, Test};
+use x_parser::symbol::symbols;
+use x_parser::{CompilationUnit, Item, Module, Symbol};
 use x_checker::TypeScheme;
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
 
+/// Type of every value slot: parameters, locals, fields and results
+const VALUE: &str = "anyref";
+
+/// A constructor with the names of its fields
+type PreludeConstructor = (&'static str, &'static [&'static str]);
+
+/// Data types every module has
+const PRELUDE_TYPES: &[(&str, &[PreludeConstructor])] = &[
+    ("List", &[("Nil", &[]), ("Cons", &["head", "tail"])]),
+    ("Option", &[("None", &[]), ("Some", &["value"])]),
+];
+
+/// How values built by a constructor are laid out
+#[derive(Debug, Clone)]
+struct ConstructorLayout {
+    /// Struct type of the constructor, e.g. `List.Cons`
+    type_name: String,
+    tag: u32,
+    arity: usize,
+}
+
 /// WebAssembly GC code generation backend
 #[allow(dead_code)]
 pub struct WasmGCBackend {
     optimization_level: WasmOptLevel,
     debug_info: bool,
     gc_strategy: GCStrategy,
+    /// Whether tail calls of module functions are `return_call`s
+    tail_calls: bool,
     type_index: u32,
     func_index: u32,
     local_index: u32,
     generated_types: HashMap<String, u32>,
     generated_functions: HashMap<Symbol, u32>,
+    /// Field names are compiled to small integers; records carry them alongside their values
+    field_ids: HashMap<Symbol, u32>,
+    /// Constructors of the prelude's and the module's data types
+    constructors: HashMap<Symbol, ConstructorLayout>,
+    /// Parameter count of the module's functions, which are called directly
+    function_arity: HashMap<Symbol, usize>,
+    /// Module-level constants, read from globals
+    globals: HashSet<Symbol>,
+    /// Standard library modules imported by the module, by qualifier
+    library_modules: HashMap<Symbol, Symbol>,
+    /// Functions lifted out of lambdas, by name
+    lifted: Vec<(String, String)>,
+    /// Locals of the function being generated, with their types
+    locals: Vec<(String, &'static str)>,
+    /// Names bound in the function being generated
+    scope: HashSet<Symbol>,
+    /// The locals of `scope` held unboxed
+    unboxed: HashMap<Symbol, Unboxed>,
+    /// Names the module defines or imports, which are not scalar operators
+    defined: HashSet<Symbol>,
+    /// Counter for the labels and temporaries of `match` expressions
+    match_index: u32,
+    /// Constructors by type, for compiling matches
+    signatures: Constructors,
+    /// The calls in tail position of the function being generated, by
+    /// address, when tail calls are enabled
+    tail_call_sites: HashSet<*const IRExpression>,
+    /// `path:line:column` of the module's definitions, with debug info
+    locations: HashMap<Symbol, String>,
 }
 
 impl WasmGCBackend {
@@ -34,11 +120,26 @@ impl WasmGCBackend {
             optimization_level: WasmOptLevel::None,
             debug_info: false,
             gc_strategy: GCStrategy::Conservative,
+            tail_calls: false,
             type_index: 0,
             func_index: 0,
             local_index: 0,
             generated_types: HashMap::new(),
             generated_functions: HashMap::new(),
+            field_ids: HashMap::new(),
+            constructors: HashMap::new(),
+            function_arity: HashMap::new(),
+            globals: HashSet::new(),
+            library_modules: HashMap::new(),
+            lifted: Vec::new(),
+            locals: Vec::new(),
+            scope: HashSet::new(),
+            unboxed: HashMap::new(),
+            defined: HashSet::new(),
+            match_index: 0,
+            signatures: Constructors::default(),
+            tail_call_sites: HashSet::new(),
+            locations: HashMap::new(),
         }
     }
     
@@ -56,6 +157,11 @@ impl WasmGCBackend {
         self.gc_strategy = strategy;
         self
     }
+    
+    pub fn with_tail_calls(mut self, tail_calls: bool) -> Self {
+        self.tail_calls = tail_calls;
+        self
+    }
 }
 
 impl CodegenBackend for WasmGCBackend {
@@ -71,7 +177,7 @@ impl CodegenBackend for WasmGCBackend {
     
     fn supports_feature(&self, feature: &str) -> bool {
         match feature {
-            "gc" | "structs" | "arrays" | "functions" => true,
+            "gc" | "structs" | "arrays" | "functions" | "async" => true,
             "effects" => false, // Needs special encoding
             "exceptions" => true,
             "threads" => false, // Different proposal
@@ -88,17 +194,28 @@ impl CodegenBackend for WasmGCBackend {
         let start_time = std::time::Instant::now();
         
         // Convert AST to IR
-        let mut ir_builder = IRBuilder::new();
+        let mut ir_builder = IRBuilder::new()
+            .with_passes(options.passes.clone())
+            .with_inlining(options.inlining.clone());
         let ir = ir_builder.build_ir(cu)?;
+        options.check_cancelled()?;
+        let emit_start = std::time::Instant::now();
         
         // Generate WebAssembly text format
         let mut files = HashMap::new();
-        let diagnostics = Vec::new();
+        let mut diagnostics = multi_shot_diagnostics(cu);
+        self.locations = cu.module.items.iter()
+            .filter_map(|item| match item {
+                Item::ValueDef(def) => Some((def.name, options.debug_location(def.span)?)),
+                _ => None,
+            })
+            .collect();
         
         for module in &ir.modules {
+            diagnostics.extend(tail_calls::diagnostics(module, cu));
             let wat_code = self.generate_wat_module(module, type_info, options)?;
-            let filename = format!("{}.wat", module.name.as_str());
-            files.insert(options.output_dir.join(&filename), wat_code);
+            let path = options.naming.module_file(module.name.as_str(), "wat");
+            files.insert(options.output_dir.join(path), wat_code);
         }
         
         let compilation_time = start_time.elapsed();
@@ -107,6 +224,7 @@ impl CodegenBackend for WasmGCBackend {
         
         Ok(CodegenResult {
             files,
+            binary_files: HashMap::new(),
             source_maps: HashMap::new(),
             diagnostics,
             metadata: CodegenMetadata {
@@ -114,6 +232,8 @@ impl CodegenBackend for WasmGCBackend {
                 generated_files: files_len,
                 total_size,
                 compilation_time,
+                passes: ir_builder.pass_timings().to_vec(),
+                emit_time: emit_start.elapsed(),
             },
         })
     }
@@ -127,7 +247,7 @@ impl CodegenBackend for WasmGCBackend {
         let _ir_builder = IRBuilder::new();
         // This is a placeholder - we need to add this method to IRBuilder
         let ir_module = IRModule {
-            name: module.name.segments[0],
+            name: Symbol::intern(&module.name.to_string()),
             exports: Vec::new(),
             imports: Vec::new(),
             functions: Vec::new(),
@@ -151,13 +271,13 @@ impl CodegenBackend for WasmGCBackend {
         writeln!(code, "))")?;
         writeln!(code)?;
         
-        writeln!(code, "(type $closure (struct")?;
-        writeln!(code, "  (field $func (ref $func_type))")?;
-        writeln!(code, "  (field $env (ref $value))")?;
-        writeln!(code, "))")?;
-        writeln!(code)?;
-        
-        writeln!(code, "(type $func_type (func (param anyref) (result anyref)))")?;
+        writeln!(code, "(type $env (array anyref))")?;
+        writeln!(code, "(rec")?;
+        writeln!(code, "  (type $closure_func (func (param (ref $closure)) (param anyref) (result anyref)))")?;
+        writeln!(code, "  (type $closure (struct")?;
+        writeln!(code, "    (field $func (ref $closure_func))")?;
+        writeln!(code, "    (field $env (ref $env))")?;
+        writeln!(code, "  )))")?;
         writeln!(code)?;
         
         // Memory management
@@ -191,9 +311,10 @@ impl WasmGCBackend {
         &mut self,
         module: &IRModule,
         _type_info: &HashMap<Symbol, TypeScheme>,
-        _options: &CodegenOptions,
+        options: &CodegenOptions,
     ) -> Result<String> {
         let mut code = String::new();
+        self.start_module(module);
         
         // Module header
         writeln!(code, "(module")?;
@@ -222,16 +343,35 @@ impl WasmGCBackend {
         // Global constants
         writeln!(code, "  ;; Constants")?;
         for constant in &module.constants {
+            options.check_cancelled()?;
             writeln!(code, "  {}", self.generate_wasm_constant(constant)?)?;
         }
         writeln!(code)?;
         
         // Functions
         writeln!(code, "  ;; Functions")?;
+        writeln!(code, "{RECORD_RUNTIME}")?;
+        writeln!(code, "{DATA_RUNTIME}")?;
+        writeln!(code, "{ASYNC_RUNTIME}")?;
+        for (name, function) in PRELUDE_FUNCTIONS {
+            // A definition of the module's own takes the name
+            if !self.function_arity.keys().any(|defined| defined.as_str() == *name) {
+                writeln!(code, "{function}")?;
+            }
+        }
         for function in &module.functions {
+            options.check_cancelled()?;
             let func_wat = self.generate_wasm_function(function)?;
             writeln!(code, "{func_wat}")?;
         }
+        for (_, lambda) in &self.lifted {
+            writeln!(code, "{lambda}")?;
+        }
+        if !self.lifted.is_empty() {
+            // `ref.func` may only name functions declared up front
+            let names: Vec<String> = self.lifted.iter().map(|(name, _)| format!("${name}")).collect();
+            writeln!(code, "  (elem declare func {})", names.join(" "))?;
+        }
         writeln!(code)?;
         
         // Exports
@@ -245,6 +385,32 @@ impl WasmGCBackend {
         Ok(code)
     }
     
+    /// Forget the previous module and register the names of this one
+    fn start_module(&mut self, module: &IRModule) {
+        self.constructors.clear();
+        for (type_name, constructors) in PRELUDE_TYPES {
+            for (tag, (name, fields)) in constructors.iter().enumerate() {
+                self.constructors.insert(Symbol::intern(name), ConstructorLayout {
+                    type_name: format!("{type_name}.{name}"),
+                    tag: tag as u32,
+                    arity: fields.len(),
+                });
+            }
+        }
+        self.function_arity = module.functions.iter()
+            .map(|function| (function.name, function.parameters.len()))
+            .collect();
+        self.globals = module.constants.iter().map(|constant| constant.name).collect();
+        self.library_modules = module.imports.iter()
+            .filter(|import| stdlib::is_stdlib_module(import.module.as_str()))
+            .filter_map(|import| Some((import.qualifier?, import.module)))
+            .collect();
+        self.signatures = Constructors::for_module(module);
+        self.defined = module_names(module);
+        self.lifted.clear();
+        self.match_index = 0;
+    }
+    
     /// Generate built-in WebAssembly GC types
     fn generate_builtin_types(&mut self, code: &mut String) -> Result<()> {
         // Value type for boxed values
@@ -255,19 +421,69 @@ impl WasmGCBackend {
         self.generated_types.insert("value".to_string(), self.type_index);
         self.type_index += 1;
         
-        // Function type for closures
-        writeln!(code, "  (type $closure (struct")?;
-        writeln!(code, "    (field $func funcref)")?;
-        writeln!(code, "    (field $env (ref $value))")?;
-        writeln!(code, "  ))")?;
-        self.generated_types.insert("closure".to_string(), self.type_index);
-        self.type_index += 1;
+        // Boxed numbers; booleans are i31 references and unit is null
+        writeln!(code, "  (type $int (struct (field i64)))")?;
+        writeln!(code, "  (type $float (struct (field f64)))")?;
+        self.generated_types.insert("int".to_string(), self.type_index);
+        self.generated_types.insert("float".to_string(), self.type_index + 1);
+        self.type_index += 2;
+        
+        // Closures: the lifted function takes the closure itself, to reach
+        // its captured values, and the argument
+        writeln!(code, "  (type $env (array anyref))")?;
+        writeln!(code, "  (rec")?;
+        writeln!(code, "    (type $closure_func (func (param (ref $closure)) (param anyref) (result anyref)))")?;
+        writeln!(code, "    (type $closure (struct")?;
+        writeln!(code, "      (field $func (ref $closure_func))")?;
+        writeln!(code, "      (field $env (ref $env))")?;
+        writeln!(code, "    )))")?;
+        self.generated_types.insert("env".to_string(), self.type_index);
+        self.generated_types.insert("closure_func".to_string(), self.type_index + 1);
+        self.generated_types.insert("closure".to_string(), self.type_index + 2);
+        self.type_index += 3;
         
         // Array type for lists/arrays
-        writeln!(code, "  (type $array (array (mut (ref null $value))))")?;
+        writeln!(code, "  (type $array (array (mut anyref)))")?;
         self.generated_types.insert("array".to_string(), self.type_index);
         self.type_index += 1;
         
+        // Records pair their field ids with the values, so that a projection
+        // works on any record that has the field, whatever else it contains
+        writeln!(code, "  (type $fields (array i32))")?;
+        self.generated_types.insert("fields".to_string(), self.type_index);
+        self.type_index += 1;
+        writeln!(code, "  (type $record (struct")?;
+        writeln!(code, "    (field $names (ref $fields))")?;
+        writeln!(code, "    (field $values (ref $array))")?;
+        writeln!(code, "  ))")?;
+        self.generated_types.insert("record".to_string(), self.type_index);
+        self.type_index += 1;
+        
+        // Spawned tasks, queued in spawn order until something awaits
+        writeln!(code, "  (type $task (struct")?;
+        writeln!(code, "    (field $done (mut i32))")?;
+        writeln!(code, "    (field $result (mut anyref))")?;
+        writeln!(code, "    (field $thunk (mut anyref))")?;
+        writeln!(code, "    (field $next (mut (ref null $task)))")?;
+        writeln!(code, "  ))")?;
+        self.generated_types.insert("task".to_string(), self.type_index);
+        self.type_index += 1;
+        
+        // Every constructor's struct extends this one, so a match can read
+        // the tag before knowing which constructor it has
+        writeln!(code, "  (type $data (sub (struct (field $tag i32))))")?;
+        self.generated_types.insert("data".to_string(), self.type_index);
+        self.type_index += 1;
+        for (type_name, constructors) in PRELUDE_TYPES {
+            for (name, fields) in *constructors {
+                let fields: Vec<String> = fields.iter()
+                    .map(|field| format!(" (field ${field} {VALUE})"))
+                    .collect();
+                writeln!(code, "  (type ${type_name}.{name} (sub final $data (struct (field $tag i32){})))", fields.concat())?;
+                self.type_index += 1;
+            }
+        }
+        
         Ok(())
     }
     
@@ -277,8 +493,24 @@ impl WasmGCBackend {
         
         let func_name = utils::sanitize_identifier(function.name, "wasm-gc");
         
+        self.locals.clear();
+        self.scope = function.parameters.iter().map(|param| param.name).collect();
+        self.unboxed.clear();
+        self.tail_call_sites = if self.tail_calls {
+            tail_calls::tail_calls(&function.body).into_iter().map(|call| call as *const IRExpression).collect()
+        } else {
+            HashSet::new()
+        };
+        let body_code = self.generate_wasm_expression(&function.body, 2);
+        self.tail_call_sites.clear();
+        let body_code = body_code?;
+        
         // Function signature
         write!(code, "  (func ${func_name}")?;
+        let location = self.locations.get(&function.name);
+        if location.is_some() && func_name != function.name.as_str() {
+            write!(code, " (@name {:?})", function.name.as_str())?;
+        }
         
         // Parameters
         for param in &function.parameters {
@@ -295,11 +527,13 @@ impl WasmGCBackend {
         
         writeln!(code)?;
         
-        // Local variables (if needed)
-        writeln!(code, "    ;; Locals would be declared here")?;
+        // Local variables
+        self.write_locals(&mut code)?;
         
         // Function body
-        let body_code = self.generate_wasm_expression(&function.body, 2)?;
+        if let Some(location) = location {
+            writeln!(code, "    ;;@ {location}")?;
+        }
         writeln!(code, "{body_code}")?;
         
         writeln!(code, "  )")?;
@@ -311,50 +545,86 @@ impl WasmGCBackend {
         Ok(code)
     }
     
+    fn declare_local(&mut self, name: String, typ: &'static str) {
+        if !self.locals.iter().any(|(local, _)| *local == name) {
+            self.locals.push((name, typ));
+        }
+    }
+    
+    /// Declare a local for a name the program binds
+    fn bind_local(&mut self, name: Symbol) -> String {
+        let local = utils::sanitize_identifier(name, "wasm-gc");
+        self.declare_local(local.clone(), VALUE);
+        self.scope.insert(name);
+        self.unboxed.remove(&name);
+        local
+    }
+    
+    /// Declare the local holding `name` as an unboxed `scalar`
+    fn bind_scalar(&mut self, name: Symbol, scalar: Unboxed) -> String {
+        let local = scalar_local(name, scalar);
+        self.declare_local(local.clone(), scalar.wasm_type());
+        self.scope.insert(name);
+        self.unboxed.insert(name, scalar);
+        local
+    }
+    
+    fn write_locals(&self, code: &mut String) -> Result<()> {
+        for (name, typ) in &self.locals {
+            writeln!(code, "    (local ${name} {typ})")?;
+        }
+        Ok(())
+    }
+    
     /// Generate WebAssembly expression
     fn generate_wasm_expression(&mut self, expr: &IRExpression, indent: usize) -> Result<String> {
         let indent_str = "  ".repeat(indent);
         
         match expr {
+            IRExpression::Literal(IRLiteral::Record(fields)) => {
+                let mut code = String::new();
+                writeln!(code, "{indent_str}(struct.new $record")?;
+                writeln!(code, "{}", self.generate_field_ids(fields, indent + 1))?;
+                writeln!(code, "{}", self.generate_field_values(fields, indent + 1)?)?;
+                write!(code, "{indent_str})")?;
+                Ok(code)
+            }
             IRExpression::Literal(lit) => {
                 Ok(format!("{}{}", indent_str, self.generate_wasm_literal(lit)))
             }
-            IRExpression::Variable(symbol) => {
-                let var_name = utils::sanitize_identifier(*symbol, "wasm-gc");
-                Ok(format!("{indent_str}(local.get ${var_name})"))
-            }
-            IRExpression::Call { function, arguments } => {
-                let mut code = String::new();
-                
-                // Generate arguments first (stack-based)
-                for arg in arguments {
-                    let arg_code = self.generate_wasm_expression(arg, indent)?;
-                    writeln!(code, "{arg_code}")?;
-                }
-                
-                // Generate function call
-                match function.as_ref() {
-                    IRExpression::Variable(func_symbol) => {
-                        let func_name = utils::sanitize_identifier(*func_symbol, "wasm-gc");
-                        write!(code, "{indent_str}(call ${func_name})")?;
-                    }
-                    _ => {
-                        // Indirect call or more complex function expression
-                        let func_code = self.generate_wasm_expression(function, indent)?;
-                        writeln!(code, "{func_code}")?;
-                        write!(code, "{indent_str}(call_indirect)")?;
-                    }
+            IRExpression::Variable(symbol) => self.generate_variable(*symbol, indent),
+            IRExpression::Call { function, arguments } if self.scalar_operator(function, arguments).is_some() => {
+                let scalar = unboxing::scalar(expr, &self.unboxed, &self.defined).expect("scalar operators give scalars");
+                let mut code = self.generate_unboxed(expr, scalar, indent)?;
+                for instruction in scalar.wasm_box() {
+                    write!(code, "\n{indent_str}{instruction}")?;
                 }
-                
                 Ok(code)
             }
+            IRExpression::Call { .. } => {
+                // `f x y` arrives as `(f x) y`; take all the arguments at once
+                let mut arguments = Vec::new();
+                let mut function = expr;
+                while let IRExpression::Call { function: inner, arguments: args } = function {
+                    arguments.splice(0..0, args.iter());
+                    function = inner;
+                }
+                let tail = self.tail_call_sites.contains(&(expr as *const IRExpression));
+                self.generate_call(function, &arguments, tail, indent)
+            }
             IRExpression::Let { bindings, body } => {
                 let mut code = String::new();
                 
-                writeln!(code, "{indent_str}(block")?;
+                writeln!(code, "{indent_str}(block (result {VALUE})")?;
                 for binding in bindings {
-                    let var_name = utils::sanitize_identifier(binding.name, "wasm-gc");
+                    if let Some(scalar) = binding.type_hint.as_ref().and_then(Unboxed::of_type) {
+                        writeln!(code, "{}", self.generate_unboxed(&binding.value, scalar, indent + 1)?)?;
+                        let local = self.bind_scalar(binding.name, scalar);
+                        writeln!(code, "{indent_str}  (local.set ${local})")?;
+                        continue;
+                    }
                     let value_code = self.generate_wasm_expression(&binding.value, indent + 1)?;
+                    let var_name = self.bind_local(binding.name);
                     writeln!(code, "{value_code}")?;
                     writeln!(code, "{indent_str}  (local.set ${var_name})")?;
                 }
@@ -368,10 +638,9 @@ impl WasmGCBackend {
             IRExpression::If { condition, then_branch, else_branch } => {
                 let mut code = String::new();
                 
-                let cond_code = self.generate_wasm_expression(condition, indent)?;
-                writeln!(code, "{cond_code}")?;
+                writeln!(code, "{}", self.generate_unboxed(condition, Unboxed::Bool, indent)?)?;
                 
-                writeln!(code, "{indent_str}(if")?;
+                writeln!(code, "{indent_str}(if (result {VALUE})")?;
                 writeln!(code, "{indent_str}  (then")?;
                 let then_code = self.generate_wasm_expression(then_branch, indent + 2)?;
                 writeln!(code, "{then_code}")?;
@@ -384,21 +653,74 @@ impl WasmGCBackend {
                 
                 Ok(code)
             }
-            IRExpression::Lambda {   .. } => {
-                // Lambda expressions need to be converted to closures
+            IRExpression::Lambda { parameters, body, closure } => {
+                self.generate_closure(parameters, body, closure, indent)
+            }
+            IRExpression::Match { value, cases } => self.generate_match(value, cases, indent),
+            IRExpression::Field { record, field } => {
                 let mut code = String::new();
-                
-                // For now, create a simple closure structure
-                writeln!(code, "{indent_str};; Lambda expression")?;
-                writeln!(code, "{indent_str}(struct.new $closure")?;
-                
-                // Function reference (would need to be pre-generated)
-                writeln!(code, "{}  (ref.func $lambda_{})", indent_str, self.func_index)?;
-                
-                // Environment (simplified)
-                writeln!(code, "{indent_str}  (ref.null $value)")?;
+                writeln!(code, "{}", self.generate_wasm_expression(record, indent)?)?;
+                writeln!(code, "{indent_str}(ref.cast (ref $record))")?;
+                write!(code, "{}(call $record_get (i32.const {}))", indent_str, self.field_id(*field))?;
+                Ok(code)
+            }
+            IRExpression::RecordUpdate { record, fields } => {
+                let mut code = String::new();
+                writeln!(code, "{}", self.generate_wasm_expression(record, indent)?)?;
+                writeln!(code, "{indent_str}(ref.cast (ref $record))")?;
+                writeln!(code, "{}", self.generate_field_ids(fields, indent))?;
+                writeln!(code, "{}", self.generate_field_values(fields, indent)?)?;
+                write!(code, "{indent_str}(call $record_with)")?;
+                Ok(code)
+            }
+            IRExpression::Tuple(elements) => {
+                // Tuples share the boxed value array representation
+                let mut code = String::new();
+                writeln!(code, "{}(array.new_fixed $array {}", indent_str, elements.len())?;
+                for element in elements {
+                    writeln!(code, "{}", self.generate_wasm_expression(element, indent + 1)?)?;
+                }
                 write!(code, "{indent_str})")?;
-                
+                Ok(code)
+            }
+            IRExpression::TupleGet { tuple, index } => {
+                let mut code = String::new();
+                writeln!(code, "{}", self.generate_wasm_expression(tuple, indent)?)?;
+                writeln!(code, "{indent_str}(ref.cast (ref $array))")?;
+                writeln!(code, "{indent_str}(i32.const {index})")?;
+                write!(code, "{indent_str}(array.get $array)")?;
+                Ok(code)
+            }
+            IRExpression::Cell { name, initial, body } => {
+                let mut code = String::new();
+                writeln!(code, "{indent_str}(block (result {VALUE})")?;
+                writeln!(code, "{}", self.generate_wasm_expression(initial, indent + 1)?)?;
+                let local = self.bind_local(*name);
+                writeln!(code, "{indent_str}  (local.set ${local})")?;
+                writeln!(code, "{}", self.generate_wasm_expression(body, indent + 1)?)?;
+                write!(code, "{indent_str})")?;
+                Ok(code)
+            }
+            IRExpression::CellGet(name) => {
+                Ok(format!("{indent_str}(local.get ${})", utils::sanitize_identifier(*name, "wasm-gc")))
+            }
+            IRExpression::CellSet { name, value } => {
+                let mut code = String::new();
+                writeln!(code, "{}", self.generate_wasm_expression(value, indent)?)?;
+                writeln!(code, "{indent_str}(local.set ${})", utils::sanitize_identifier(*name, "wasm-gc"))?;
+                write!(code, "{indent_str}(ref.null none)")?;
+                Ok(code)
+            }
+            // The runtime's task queue handles `Async`
+            IRExpression::Effect { effect, operation, arguments } if *effect == symbols::ASYNC() && arguments.len() == 1 => {
+                let function = match operation.as_str() {
+                    "await" => "$rt.async_await",
+                    "spawn" => "$rt.async_spawn",
+                    _ => return Err(crate::CompilerError::CodeGen { message: format!("Unknown operation Async.{operation}") }),
+                };
+                let mut code = String::new();
+                writeln!(code, "{}", self.generate_wasm_expression(&arguments[0], indent)?)?;
+                write!(code, "{indent_str}(call {function})")?;
                 Ok(code)
             }
             _ => {
@@ -407,18 +729,464 @@ impl WasmGCBackend {
         }
     }
     
+    /// A name used as a value: a local, a global, a nullary constructor, or
+    /// a closure over a function or constructor
+    fn generate_variable(&mut self, symbol: Symbol, indent: usize) -> Result<String> {
+        let indent_str = "  ".repeat(indent);
+        let var_name = utils::sanitize_identifier(symbol, "wasm-gc");
+        if let Some(&scalar) = self.unboxed.get(&symbol) {
+            let mut code = format!("{indent_str}(local.get ${})", scalar_local(symbol, scalar));
+            for instruction in scalar.wasm_box() {
+                write!(code, "\n{indent_str}{instruction}")?;
+            }
+            return Ok(code);
+        }
+        if self.scope.contains(&symbol) {
+            return Ok(format!("{indent_str}(local.get ${var_name})"));
+        }
+        let arity = match self.constructors.get(&symbol) {
+            Some(layout) if layout.arity == 0 => {
+                return Ok(format!("{}(struct.new ${} (i32.const {}))", indent_str, layout.type_name, layout.tag));
+            }
+            Some(layout) => Some(layout.arity),
+            None => self.function_arity.get(&symbol).copied().filter(|&arity| arity > 0),
+        };
+        if let Some(arity) = arity {
+            // Wrap in a lambda taking the arguments one at a time
+            let parameters: Vec<IRParameter> = (0..arity)
+                .map(|index| IRParameter {
+                    name: Symbol::intern(&format!("arg.{index}")),
+                    type_hint: IRType::Primitive(IRPrimitiveType::Unit),
+                })
+                .collect();
+            let body = IRExpression::Call {
+                function: Box::new(IRExpression::Variable(symbol)),
+                arguments: parameters.iter().map(|param| IRExpression::Variable(param.name)).collect(),
+            };
+            return self.generate_closure(&parameters, &body, &[], indent);
+        }
+        if self.function_arity.contains_key(&symbol) {
+            Ok(format!("{indent_str}(call ${var_name})"))
+        } else if self.globals.contains(&symbol) {
+            Ok(format!("{indent_str}(global.get ${var_name})"))
+        } else {
+            Ok(format!("{indent_str}(local.get ${var_name})"))
+        }
+    }
+    
+    /// Code leaving the `scalar` that `expr` evaluates to unboxed on the
+    /// stack: literals, unboxed locals and scalar operators directly, and
+    /// anything else boxed first
+    fn generate_unboxed(&mut self, expr: &IRExpression, scalar: Unboxed, indent: usize) -> Result<String> {
+        let indent_str = "  ".repeat(indent);
+        match expr {
+            IRExpression::Literal(IRLiteral::Integer(n)) if scalar == Unboxed::Int => {
+                return Ok(format!("{indent_str}(i64.const {n})"));
+            }
+            IRExpression::Literal(IRLiteral::Float(f)) if scalar == Unboxed::Float => {
+                return Ok(format!("{indent_str}{}", float_const(*f)));
+            }
+            IRExpression::Literal(IRLiteral::Boolean(b)) if scalar == Unboxed::Bool => {
+                return Ok(format!("{indent_str}(i32.const {})", i32::from(*b)));
+            }
+            IRExpression::Variable(name) if self.unboxed.get(name) == Some(&scalar) => {
+                return Ok(format!("{indent_str}(local.get ${})", scalar_local(*name, scalar)));
+            }
+            IRExpression::Call { function, arguments } => {
+                if let Some((operands, instruction)) = self.scalar_operator(function, arguments)
+                    .filter(|_| unboxing::scalar(expr, &self.unboxed, &self.defined) == Some(scalar))
+                {
+                    let mut code = String::new();
+                    for argument in arguments {
+                        writeln!(code, "{}", self.generate_unboxed(argument, operands, indent)?)?;
+                    }
+                    write!(code, "{indent_str}({instruction})")?;
+                    return Ok(code);
+                }
+            }
+            _ => {}
+        }
+        let mut code = self.generate_wasm_expression(expr, indent)?;
+        for instruction in scalar.wasm_unbox() {
+            write!(code, "\n{indent_str}{instruction}")?;
+        }
+        Ok(code)
+    }
+    
+    /// The scalar type of the operands of a call of `function` on
+    /// `arguments` and the core instruction computing it, when it is a
+    /// scalar operator whose operands' type is known
+    fn scalar_operator(&self, function: &IRExpression, arguments: &[IRExpression]) -> Option<(Unboxed, &'static str)> {
+        let IRExpression::Variable(name) = function else {
+            return None;
+        };
+        if arguments.len() != 2 || self.scope.contains(name) || self.defined.contains(name) {
+            return None;
+        }
+        if let Some(operator) = runtime::operator(name.as_str()) {
+            return Some((operator.scalar, operator.instruction));
+        }
+        // Comparisons take operands of any one type
+        let operands = arguments.iter().find_map(|argument| unboxing::scalar(argument, &self.unboxed, &self.defined))?;
+        Some((operands, operands.comparison(name.as_str())?))
+    }
+    
+    /// Apply `function` to `arguments`: module functions are called and
+    /// constructors built directly when given enough arguments, and any other
+    /// function value is applied one argument at a time. A `tail` call of a
+    /// module function with exactly its arguments returns its result with
+    /// `return_call`.
+    fn generate_call(&mut self, function: &IRExpression, arguments: &[&IRExpression], tail: bool, indent: usize) -> Result<String> {
+        let indent_str = "  ".repeat(indent);
+        let mut code = String::new();
+        
+        let mut applied = 0;
+        match function {
+            IRExpression::Variable(symbol) if !self.scope.contains(symbol) => {
+                let func_name = utils::sanitize_identifier(*symbol, "wasm-gc");
+                if let Some(layout) = self.constructors.get(symbol).cloned() {
+                    if arguments.len() >= layout.arity {
+                        writeln!(code, "{}(i32.const {})", indent_str, layout.tag)?;
+                        for arg in &arguments[..layout.arity] {
+                            writeln!(code, "{}", self.generate_wasm_expression(arg, indent)?)?;
+                        }
+                        write!(code, "{}(struct.new ${})", indent_str, layout.type_name)?;
+                        applied = layout.arity;
+                    }
+                } else if let Some(&arity) = self.function_arity.get(symbol) {
+                    if arguments.len() >= arity {
+                        for arg in &arguments[..arity] {
+                            writeln!(code, "{}", self.generate_wasm_expression(arg, indent)?)?;
+                        }
+                        let instruction = if tail && arguments.len() == arity { "return_call" } else { "call" };
+                        write!(code, "{indent_str}({instruction} ${func_name})")?;
+                        applied = arity;
+                    }
+                } else if !self.globals.contains(symbol) {
+                    // Operators and prelude functions take all their arguments
+                    for arg in arguments {
+                        writeln!(code, "{}", self.generate_wasm_expression(arg, indent)?)?;
+                    }
+                    write!(code, "{indent_str}(call ${func_name})")?;
+                    applied = arguments.len();
+                }
+            }
+            IRExpression::Field { record, field } => {
+                let intrinsic = match record.as_ref() {
+                    IRExpression::Variable(qualifier) => self.library_modules.get(qualifier)
+                        .and_then(|module| stdlib::intrinsic("wasm-gc", module.as_str(), field.as_str()))
+                        .filter(|intrinsic| arguments.len() >= intrinsic.arity),
+                    _ => None,
+                };
+                if let Some(intrinsic) = intrinsic {
+                    for arg in &arguments[..intrinsic.arity] {
+                        writeln!(code, "{}", self.generate_wasm_expression(arg, indent)?)?;
+                    }
+                    write!(code, "{indent_str}(call ${})", intrinsic.code)?;
+                    applied = intrinsic.arity;
+                }
+            }
+            _ => {}
+        }
+        if code.is_empty() {
+            code = self.generate_wasm_expression(function, indent)?;
+        }
+        
+        for arg in &arguments[applied..] {
+            writeln!(code)?;
+            writeln!(code, "{}", self.generate_wasm_expression(arg, indent)?)?;
+            write!(code, "{indent_str}(call $rt.apply)")?;
+        }
+        
+        Ok(code)
+    }
+    
+    /// Lift a lambda to a function of its closure and one argument, and
+    /// build the closure; lambdas of several parameters are curried
+    fn generate_closure(
+        &mut self,
+        parameters: &[IRParameter],
+        body: &IRExpression,
+        closure: &[Symbol],
+        indent: usize,
+    ) -> Result<String> {
+        let indent_str = "  ".repeat(indent);
+        let (parameter, body) = match parameters {
+            [] => (Symbol::intern("_"), Cow::Borrowed(body)),
+            [parameter] => (parameter.name, Cow::Borrowed(body)),
+            [first, rest @ ..] => {
+                let mut inner = IRExpression::Lambda {
+                    parameters: rest.to_vec(),
+                    body: Box::new(body.clone()),
+                    closure: Vec::new(),
+                };
+                let captured: Vec<Symbol> = inner.free_variables().into_iter()
+                    .filter(|name| *name == first.name || closure.contains(name))
+                    .collect();
+                if let IRExpression::Lambda { closure, .. } = &mut inner {
+                    *closure = captured;
+                }
+                (first.name, Cow::Owned(inner))
+            }
+        };
+        
+        // Reserve the name before lifting the lambdas nested in the body
+        let name = format!("lambda_{}", self.lifted.len());
+        let index = self.lifted.len();
+        self.lifted.push((name.clone(), String::new()));
+        
+        let outer_locals = std::mem::take(&mut self.locals);
+        let outer_scope = std::mem::replace(&mut self.scope, HashSet::from([parameter]));
+        let outer_unboxed = std::mem::take(&mut self.unboxed);
+        let mut prologue = String::new();
+        if !closure.is_empty() {
+            self.declare_local("self.env".to_string(), "(ref $env)");
+            writeln!(prologue, "    (local.set $self.env (struct.get $closure $env (local.get $self.closure)))")?;
+        }
+        for (slot, captured) in closure.iter().enumerate() {
+            let local = self.bind_local(*captured);
+            writeln!(prologue, "    (local.set ${local} (array.get $env (local.get $self.env) (i32.const {slot})))")?;
+        }
+        let body_code = self.generate_wasm_expression(&body, 2);
+        let locals = std::mem::replace(&mut self.locals, outer_locals);
+        self.scope = outer_scope;
+        self.unboxed = outer_unboxed;
+        let body_code = body_code?;
+        
+        let mut lifted = String::new();
+        let param_name = utils::sanitize_identifier(parameter, "wasm-gc");
+        writeln!(
+            lifted,
+            "  (func ${name} (type $closure_func) (param $self.closure (ref $closure)) (param ${param_name} {VALUE}) (result {VALUE})"
+        )?;
+        for (local, typ) in &locals {
+            writeln!(lifted, "    (local ${local} {typ})")?;
+        }
+        write!(lifted, "{prologue}")?;
+        writeln!(lifted, "{body_code}")?;
+        write!(lifted, "  )")?;
+        self.lifted[index].1 = lifted;
+        
+        let mut code = String::new();
+        writeln!(code, "{indent_str}(struct.new $closure")?;
+        writeln!(code, "{indent_str}  (ref.func ${name})")?;
+        write!(code, "{}  (array.new_fixed $env {}", indent_str, closure.len())?;
+        for captured in closure {
+            write!(code, " {}", self.generate_variable(*captured, 0)?)?;
+        }
+        write!(code, "))")?;
+        Ok(code)
+    }
+    
+    /// Lower the match through its decision tree: each switch becomes one
+    /// branch table or chain of tests, and each case leaves the block with
+    /// its value
+    fn generate_match(&mut self, value: &IRExpression, cases: &[IRMatchCase], indent: usize) -> Result<String> {
+        let indent_str = "  ".repeat(indent);
+        let id = self.match_index;
+        self.match_index += 1;
+        let subject = format!("match.{id}");
+        self.declare_local(subject.clone(), VALUE);
+        let decision = decision_tree::compile(cases, &self.signatures);
+        
+        let mut code = String::new();
+        writeln!(code, "{indent_str}(block $match.{id} (result {VALUE})")?;
+        writeln!(code, "{}", self.generate_wasm_expression(value, indent + 1)?)?;
+        writeln!(code, "{indent_str}  (local.set ${subject})")?;
+        self.generate_decision(&decision, cases, id, indent + 1, &mut code)?;
+        write!(code, "{indent_str})")?;
+        Ok(code)
+    }
+    
+    /// Code for `decision` that never falls through: every path ends in a
+    /// branch out of the match or, where no case matches, `unreachable`
+    fn generate_decision(
+        &mut self,
+        decision: &Decision,
+        cases: &[IRMatchCase],
+        id: u32,
+        indent: usize,
+        code: &mut String,
+    ) -> Result<()> {
+        let indent_str = "  ".repeat(indent);
+        match decision {
+            Decision::Fail => writeln!(code, "{indent_str}(unreachable)")?,
+            Decision::Leaf { case, bindings, otherwise } => {
+                for (name, access) in bindings {
+                    let value = self.access(access, id)?;
+                    let local = self.bind_local(*name);
+                    writeln!(code, "{indent_str}(local.set ${local} {value})")?;
+                }
+                let case = &cases[*case];
+                if let (Some(guard), Some(otherwise)) = (&case.guard, otherwise) {
+                    let label = self.match_label("guard");
+                    writeln!(code, "{indent_str}(block {label}")?;
+                    writeln!(code, "{}", self.generate_wasm_expression(guard, indent + 1)?)?;
+                    writeln!(code, "{indent_str}  (call $rt.is_true)")?;
+                    writeln!(code, "{indent_str}  (br_if {label} (i32.eqz))")?;
+                    writeln!(code, "{}", self.generate_wasm_expression(&case.body, indent + 1)?)?;
+                    writeln!(code, "{indent_str}  (br $match.{id}))")?;
+                    self.generate_decision(otherwise, cases, id, indent, code)?;
+                } else {
+                    writeln!(code, "{}", self.generate_wasm_expression(&case.body, indent)?)?;
+                    writeln!(code, "{indent_str}(br $match.{id})")?;
+                }
+            }
+            Decision::Switch { access, branches, default } => {
+                let value = self.access(access, id)?;
+                if matches!(branches[0].0, Test::Constructor(_)) {
+                    self.generate_tag_switch(&value, branches, default.as_deref(), cases, id, indent, code)?;
+                } else {
+                    self.generate_test_chain(&value, branches, default.as_deref(), cases, id, indent, code)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    
+    /// A `br_table` on the constructor tag, into one block per branch; the
+    /// default, or the last branch of a complete switch, takes the other tags
+    #[allow(clippy::too_many_arguments)]
+    fn generate_tag_switch(
+        &mut self,
+        value: &str,
+        branches: &[(Test, Decision)],
+        default: Option<&Decision>,
+        cases: &[IRMatchCase],
+        id: u32,
+        indent: usize,
+        code: &mut String,
+    ) -> Result<()> {
+        let mut arms: Vec<&Decision> = branches.iter().map(|(_, decision)| decision).collect();
+        arms.extend(default);
+        let labels: Vec<String> = arms.iter().map(|_| self.match_label("arm")).collect();
+        
+        let mut targets = Vec::new();
+        for (arm, (test, _)) in branches.iter().enumerate() {
+            let Test::Constructor(name) = test else { unreachable!("constructor switches only test constructors") };
+            let layout = self.constructors.get(name).ok_or_else(|| crate::CompilerError::CodeGen {
+                message: format!("unknown constructor `{name}` in pattern"),
+            })?;
+            let tag = layout.tag as usize;
+            if targets.len() <= tag {
+                targets.resize(tag + 1, None);
+            }
+            targets[tag] = Some(arm);
+        }
+        let fallback = &labels[arms.len() - 1];
+        let table: Vec<&str> = targets.iter()
+            .map(|arm| arm.map_or(fallback.as_str(), |arm| labels[arm].as_str()))
+            .collect();
+        
+        // The first arm's block is innermost, so each arm's code follows the
+        // end of its block
+        for (depth, label) in labels.iter().rev().enumerate() {
+            writeln!(code, "{}(block {label}", "  ".repeat(indent + depth))?;
+        }
+        let depth = indent + arms.len();
+        writeln!(code, "{}(br_table {} {fallback} (call $rt.tag {value}))", "  ".repeat(depth), table.join(" "))?;
+        for (arm, decision) in arms.iter().enumerate() {
+            let depth = depth - arm - 1;
+            writeln!(code, "{})", "  ".repeat(depth))?;
+            self.generate_decision(decision, cases, id, depth, code)?;
+        }
+        Ok(())
+    }
+    
+    /// Literal tests one after another, each skipping a block on failure;
+    /// the default, or the last branch when there is none, needs no test
+    #[allow(clippy::too_many_arguments)]
+    fn generate_test_chain(
+        &mut self,
+        value: &str,
+        branches: &[(Test, Decision)],
+        default: Option<&Decision>,
+        cases: &[IRMatchCase],
+        id: u32,
+        indent: usize,
+        code: &mut String,
+    ) -> Result<()> {
+        let indent_str = "  ".repeat(indent);
+        let (tested, last) = match default {
+            Some(default) => (branches, default),
+            None => {
+                let (last, tested) = branches.split_last().expect("a switch has branches");
+                (tested, &last.1)
+            }
+        };
+        for (test, decision) in tested {
+            let label = self.match_label("test");
+            let condition = test_condition(test, value)?;
+            writeln!(code, "{indent_str}(block {label}")?;
+            writeln!(code, "{indent_str}  (br_if {label} (i32.eqz {condition}))")?;
+            self.generate_decision(decision, cases, id, indent + 1, code)?;
+            writeln!(code, "{indent_str})")?;
+        }
+        self.generate_decision(last, cases, id, indent, code)
+    }
+    
+    /// A fresh label for a block of the match being generated
+    fn match_label(&mut self, kind: &str) -> String {
+        let index = self.match_index;
+        self.match_index += 1;
+        format!("${kind}.{index}")
+    }
+    
+    /// The code reading the part of the subject of match `id` at `access`
+    fn access(&mut self, access: &Access, id: u32) -> Result<String> {
+        Ok(match access {
+            Access::Root => format!("(local.get $match.{id})"),
+            Access::Field { parent, constructor, index } => {
+                let layout = self.constructors.get(constructor).cloned().ok_or_else(|| crate::CompilerError::CodeGen {
+                    message: format!("unknown constructor `{constructor}` in pattern"),
+                })?;
+                let parent = self.access(parent, id)?;
+                format!("(struct.get ${ty} {} (ref.cast (ref ${ty}) {parent}))", index + 1, ty = layout.type_name)
+            }
+            Access::Element { parent, index } => {
+                format!("(array.get $array (ref.cast (ref $array) {}) (i32.const {index}))", self.access(parent, id)?)
+            }
+            Access::RecordField { parent, field } => {
+                let parent = self.access(parent, id)?;
+                format!("(call $record_get (ref.cast (ref $record) {parent}) (i32.const {}))", self.field_id(*field))
+            }
+        })
+    }
+    
+    fn field_id(&mut self, field: Symbol) -> u32 {
+        let next = self.field_ids.len() as u32;
+        *self.field_ids.entry(field).or_insert(next)
+    }
+    
+    fn generate_field_ids(&mut self, fields: &[(Symbol, IRExpression)], indent: usize) -> String {
+        let ids: Vec<String> = fields.iter()
+            .map(|(name, _)| format!("(i32.const {})", self.field_id(*name)))
+            .collect();
+        format!("{}(array.new_fixed $fields {} {})", "  ".repeat(indent), ids.len(), ids.join(" "))
+    }
+    
+    fn generate_field_values(&mut self, fields: &[(Symbol, IRExpression)], indent: usize) -> Result<String> {
+        let mut code = String::new();
+        writeln!(code, "{}(array.new_fixed $array {}", "  ".repeat(indent), fields.len())?;
+        for (_, value) in fields {
+            writeln!(code, "{}", self.generate_wasm_expression(value, indent + 1)?)?;
+        }
+        write!(code, "{})", "  ".repeat(indent))?;
+        Ok(code)
+    }
+    
     /// Generate WebAssembly literal
     fn generate_wasm_literal(&self, lit: &IRLiteral) -> String {
         match lit {
-            IRLiteral::Integer(n) => format!("(i64.const {n})"),
-            IRLiteral::Float(f) => format!("(f64.const {f})"),
-            IRLiteral::Boolean(true) => "(i32.const 1)".to_string(),
-            IRLiteral::Boolean(false) => "(i32.const 0)".to_string(),
+            IRLiteral::Integer(n) => format!("(struct.new $int (i64.const {n}))"),
+            IRLiteral::Float(f) => format!("(struct.new $float {})", float_const(*f)),
+            IRLiteral::Boolean(true) => "(ref.i31 (i32.const 1))".to_string(),
+            IRLiteral::Boolean(false) => "(ref.i31 (i32.const 0))".to_string(),
             IRLiteral::String(s) => {
                 // Strings need special handling in WebAssembly
                 format!(";; String literal: \"{s}\" (needs implementation)")
             }
-            IRLiteral::Unit => "(ref.null $value)".to_string(),
+            IRLiteral::Unit => "(ref.null none)".to_string(),
             _ => ";; Complex literal (needs implementation)".to_string(),
         }
     }
@@ -433,20 +1201,20 @@ impl WasmGCBackend {
                 if let Some(&type_idx) = self.generated_types.get(name.as_str()) {
                     format!("(ref $type_{type_idx})")
                 } else {
-                    "(ref $value)".to_string()
+                    VALUE.to_string()
                 }
             }
-            _ => "(ref $value)".to_string(), // Default to boxed value
+            _ => VALUE.to_string(),
         }
     }
     
     fn generate_wasm_primitive_type(&self, prim: &IRPrimitiveType) -> String {
         match prim {
-            IRPrimitiveType::Int => "i64".to_string(),
-            IRPrimitiveType::Float => "f64".to_string(),
-            IRPrimitiveType::Bool => "i32".to_string(),
+            IRPrimitiveType::Int => "(ref $int)".to_string(),
+            IRPrimitiveType::Float => "(ref $float)".to_string(),
+            IRPrimitiveType::Bool => "(ref i31)".to_string(),
             IRPrimitiveType::String => "(ref $string)".to_string(), // Would need string type
-            IRPrimitiveType::Unit => "(ref null $value)".to_string(),
+            IRPrimitiveType::Unit => VALUE.to_string(),
         }
     }
     
@@ -466,7 +1234,7 @@ impl WasmGCBackend {
         let const_name = utils::sanitize_identifier(constant.name, "wasm-gc");
         let value = match &constant.value {
             IRExpression::Literal(lit) => self.generate_wasm_literal(lit),
-            _ => ";; Complex constant (needs implementation)".to_string(),
+            _ => "(; Complex constant (needs implementation) ;)".to_string(),
         };
         Ok(format!("(global ${} {} {})", 
                    const_name, 
@@ -474,14 +1242,521 @@ impl WasmGCBackend {
                    value))
     }
     
-    fn generate_wasm_type_definition(&mut self, _type_def: &IRTypeDefinition) -> Result<String> {
-        // Simplified type definition generation
-        Ok(";; Type definition (needs implementation)".to_string())
+    /// A data type becomes one struct type per constructor, numbered by
+    /// their order in the definition
+    fn generate_wasm_type_definition(&mut self, type_def: &IRTypeDefinition) -> Result<String> {
+        let IRTypeDefinitionKind::Variant(constructors) = &type_def.definition else {
+            return Ok(format!(";; type {}: no representation of its own", type_def.name));
+        };
+        
+        let type_name = utils::sanitize_identifier(type_def.name, "wasm-gc");
+        if let Some((_, prelude)) = PRELUDE_TYPES.iter().find(|(name, _)| *name == type_name) {
+            // The module spells out a prelude type; it must be the same one
+            let same = prelude.len() == constructors.len()
+                && prelude.iter().zip(constructors).all(|((name, fields), (constructor, args))| {
+                    constructor.as_str() == *name && args.len() == fields.len()
+                });
+            if !same {
+                return Err(crate::CompilerError::CodeGen {
+                    message: format!("data type `{type_name}` conflicts with the prelude's `{type_name}`"),
+                });
+            }
+            return Ok(format!(";; data {type_name}: defined by the prelude"));
+        }
+        
+        let mut definitions = Vec::new();
+        for (tag, (name, fields)) in constructors.iter().enumerate() {
+            let layout = ConstructorLayout {
+                type_name: format!("{}.{}", type_name, utils::sanitize_identifier(*name, "wasm-gc")),
+                tag: tag as u32,
+                arity: fields.len(),
+            };
+            definitions.push(format!(
+                "(type ${} (sub final $data (struct (field $tag i32){})))",
+                layout.type_name,
+                format!(" (field {VALUE})").repeat(fields.len())
+            ));
+            self.constructors.insert(*name, layout);
+            self.type_index += 1;
+        }
+        Ok(definitions.join("\n  "))
     }
 }
 
+/// WAT constant for a float, which spells the special values differently
+/// The local holding `name` unboxed as a `scalar`
+fn scalar_local(name: Symbol, scalar: Unboxed) -> String {
+    format!("{}.{}", utils::sanitize_identifier(name, "wasm-gc"), scalar.wasm_type())
+}
+
+fn float_const(f: f64) -> String {
+    if f.is_nan() {
+        "(f64.const nan)".to_string()
+    } else if f.is_infinite() {
+        format!("(f64.const {}inf)", if f < 0.0 { "-" } else { "" })
+    } else {
+        format!("(f64.const {f:?})")
+    }
+}
+
+/// Field lookup and functional update over `$record` values
+const RECORD_RUNTIME: &str = r#"  (func $field_index (param $record (ref $record)) (param $field i32) (result i32)
+    (local $names (ref $fields))
+    (local $i i32)
+    (local.set $names (struct.get $record $names (local.get $record)))
+    (loop $scan
+      (if (i32.ge_u (local.get $i) (array.len (local.get $names)))
+        (then (unreachable)))
+      (if (i32.eq (array.get $fields (local.get $names) (local.get $i)) (local.get $field))
+        (then (return (local.get $i))))
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br $scan))
+    (unreachable)
+  )
+  (func $record_get (param $record (ref $record)) (param $field i32) (result anyref)
+    (array.get $array
+      (struct.get $record $values (local.get $record))
+      (call $field_index (local.get $record) (local.get $field)))
+  )
+  (func $record_with (param $record (ref $record)) (param $names (ref $fields)) (param $values (ref $array)) (result (ref $record))
+    (local $old (ref $array))
+    (local $copy (ref $array))
+    (local $j i32)
+    (local.set $old (struct.get $record $values (local.get $record)))
+    (local.set $copy (array.new_default $array (array.len (local.get $old))))
+    (array.copy $array $array
+      (local.get $copy) (i32.const 0)
+      (local.get $old) (i32.const 0)
+      (array.len (local.get $old)))
+    (block $done
+      (loop $update
+        (br_if $done (i32.ge_u (local.get $j) (array.len (local.get $names))))
+        (array.set $array
+          (local.get $copy)
+          (call $field_index (local.get $record) (array.get $fields (local.get $names) (local.get $j)))
+          (array.get $array (local.get $values) (local.get $j)))
+        (local.set $j (i32.add (local.get $j) (i32.const 1)))
+        (br $update)))
+    (struct.new $record (struct.get $record $names (local.get $record)) (local.get $copy))
+  )"#;
+
+/// Closure application, constructor tags and booleans as `i32` conditions
+const DATA_RUNTIME: &str = r#"  (func $rt.apply (param $f anyref) (param $x anyref) (result anyref)
+    (local $closure (ref $closure))
+    (local.set $closure (ref.cast (ref $closure) (local.get $f)))
+    (call_ref $closure_func
+      (local.get $closure)
+      (local.get $x)
+      (struct.get $closure $func (local.get $closure)))
+  )
+  (func $rt.tag (param $value anyref) (result i32)
+    (struct.get $data $tag (ref.cast (ref $data) (local.get $value)))
+  )
+  (func $rt.is_true (param $value anyref) (result i32)
+    (i31.get_u (ref.cast (ref i31) (local.get $value)))
+  )"#;
+
+/// `Async` as a trampoline: `spawn` queues a task, and `await` runs queued
+/// tasks in spawn order until the awaited one has its result. A task runs to
+/// completion, running the tasks it awaits before it resumes; awaiting a
+/// task that is already running traps once the queue is empty
+const ASYNC_RUNTIME: &str = r#"  (global $tasks.head (mut (ref null $task)) (ref.null $task))
+  (global $tasks.tail (mut (ref null $task)) (ref.null $task))
+  (func $rt.async_spawn (param $thunk anyref) (result anyref)
+    (local $task (ref $task))
+    (local.set $task (struct.new $task (i32.const 0) (ref.null none) (local.get $thunk) (ref.null $task)))
+    (if (ref.is_null (global.get $tasks.tail))
+      (then (global.set $tasks.head (local.get $task)))
+      (else (struct.set $task $next (ref.as_non_null (global.get $tasks.tail)) (local.get $task))))
+    (global.set $tasks.tail (local.get $task))
+    (local.get $task)
+  )
+  (func $rt.async_step
+    (local $task (ref $task))
+    (local $thunk anyref)
+    (local.set $task (ref.as_non_null (global.get $tasks.head)))
+    (global.set $tasks.head (struct.get $task $next (local.get $task)))
+    (if (ref.is_null (global.get $tasks.head))
+      (then (global.set $tasks.tail (ref.null $task))))
+    (local.set $thunk (struct.get $task $thunk (local.get $task)))
+    (struct.set $task $thunk (local.get $task) (ref.null none))
+    (struct.set $task $result (local.get $task) (call $rt.apply (local.get $thunk) (ref.null none)))
+    (struct.set $task $done (local.get $task) (i32.const 1))
+  )
+  (func $rt.async_await (param $promise anyref) (result anyref)
+    (local $task (ref $task))
+    (local.set $task (ref.cast (ref $task) (local.get $promise)))
+    (block $ready
+      (loop $run
+        (br_if $ready (struct.get $task $done (local.get $task)))
+        (call $rt.async_step)
+        (br $run)))
+    (struct.get $task $result (local.get $task))
+  )"#;
+
+/// List and option functions programs can call by name
+const PRELUDE_FUNCTIONS: &[(&str, &str)] = &[
+    ("list_length", r#"  (func $list_length (param $list anyref) (result anyref)
+    (local $length i64)
+    (block $done
+      (loop $next
+        (br_if $done (i32.eqz (call $rt.tag (local.get $list))))
+        (local.set $length (i64.add (local.get $length) (i64.const 1)))
+        (local.set $list (struct.get $List.Cons $tail (ref.cast (ref $List.Cons) (local.get $list))))
+        (br $next)))
+    (struct.new $int (local.get $length))
+  )"#),
+    ("list_map", r#"  (func $list_map (param $f anyref) (param $list anyref) (result anyref)
+    (local $cons (ref $List.Cons))
+    (if (result anyref) (i32.eqz (call $rt.tag (local.get $list)))
+      (then (local.get $list))
+      (else
+        (local.set $cons (ref.cast (ref $List.Cons) (local.get $list)))
+        (struct.new $List.Cons
+          (i32.const 1)
+          (call $rt.apply (local.get $f) (struct.get $List.Cons $head (local.get $cons)))
+          (call $list_map (local.get $f) (struct.get $List.Cons $tail (local.get $cons))))))
+  )"#),
+    ("list_fold", r#"  (func $list_fold (param $f anyref) (param $acc anyref) (param $list anyref) (result anyref)
+    (local $cons (ref $List.Cons))
+    (block $done
+      (loop $next
+        (br_if $done (i32.eqz (call $rt.tag (local.get $list))))
+        (local.set $cons (ref.cast (ref $List.Cons) (local.get $list)))
+        (local.set $acc
+          (call $rt.apply
+            (call $rt.apply (local.get $f) (local.get $acc))
+            (struct.get $List.Cons $head (local.get $cons))))
+        (local.set $list (struct.get $List.Cons $tail (local.get $cons)))
+        (br $next)))
+    (local.get $acc)
+  )"#),
+    ("list_reverse", r#"  (func $list_reverse (param $list anyref) (result anyref)
+    (local $reversed anyref)
+    (local $cons (ref $List.Cons))
+    (local.set $reversed (struct.new $List.Nil (i32.const 0)))
+    (block $done
+      (loop $next
+        (br_if $done (i32.eqz (call $rt.tag (local.get $list))))
+        (local.set $cons (ref.cast (ref $List.Cons) (local.get $list)))
+        (local.set $reversed
+          (struct.new $List.Cons
+            (i32.const 1)
+            (struct.get $List.Cons $head (local.get $cons))
+            (local.get $reversed)))
+        (local.set $list (struct.get $List.Cons $tail (local.get $cons)))
+        (br $next)))
+    (local.get $reversed)
+  )"#),
+    ("option_map", r#"  (func $option_map (param $f anyref) (param $option anyref) (result anyref)
+    (if (result anyref) (i32.eqz (call $rt.tag (local.get $option)))
+      (then (local.get $option))
+      (else
+        (struct.new $Option.Some
+          (i32.const 1)
+          (call $rt.apply
+            (local.get $f)
+            (struct.get $Option.Some $value (ref.cast (ref $Option.Some) (local.get $option)))))))
+  )"#),
+    ("option_get_or", r#"  (func $option_get_or (param $option anyref) (param $default anyref) (result anyref)
+    (if (result anyref) (i32.eqz (call $rt.tag (local.get $option)))
+      (then (local.get $default))
+      (else (struct.get $Option.Some $value (ref.cast (ref $Option.Some) (local.get $option)))))
+  )"#),
+];
+
 impl Default for WasmGCBackend {
     fn default() -> Self {
         Self::new()
     }
-}
\ No newline at end of file
+}
+/// The `i32` condition of a literal test of `value`
+fn test_condition(test: &Test, value: &str) -> Result<String> {
+    let int = format!("(struct.get $int 0 (ref.cast (ref $int) {value}))");
+    Ok(match test {
+        Test::Int(n) => format!("(i64.eq {int} (i64.const {n}))"),
+        Test::Range(low, high) => format!("(i32.and (i64.ge_s {int} (i64.const {low})) (i64.le_s {int} (i64.const {high})))"),
+        Test::Float(f) => format!("(f64.eq (struct.get $float 0 (ref.cast (ref $float) {value})) {})", float_const(*f)),
+        Test::Bool(b) => format!("(i32.eq (call $rt.is_true {value}) (i32.const {}))", i32::from(*b)),
+        Test::String(_) => {
+            return Err(crate::CompilerError::CodeGen {
+                message: "the WebAssembly GC backend cannot match on strings yet".to_string(),
+            })
+        }
+        Test::Constructor(_) => unreachable!("constructors are switched on by tag"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::passes::PassManager;
+    use x_parser::{parse_source, FileId, SyntaxStyle};
+
+    fn codegen(source: &str) -> Result<CodegenResult> {
+        codegen_unit(&parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap())
+    }
+
+    fn codegen_unit(cu: &CompilationUnit) -> Result<CodegenResult> {
+        codegen_with(WasmGCBackend::new(), cu, PassManager::default())
+    }
+
+    fn codegen_with(mut backend: WasmGCBackend, cu: &CompilationUnit, passes: PassManager) -> Result<CodegenResult> {
+        let options = CodegenOptions {
+            target: backend.target_info(),
+            output_dir: std::path::PathBuf::from("out"),
+            source_maps: false,
+            debug_info: false,
+            optimization_level: 0,
+            emit_types: false,
+            naming: Default::default(),
+            cancellation: Default::default(),
+            passes,
+            inlining: Default::default(),
+            sources: Default::default(),
+        };
+        backend.generate_code(cu, &HashMap::new(), &options)
+    }
+
+    fn generate(source: &str) -> Result<String> {
+        Ok(codegen(source)?.files.into_values().next().unwrap())
+    }
+
+    fn wat(source: &str) -> String {
+        let wat = generate(source).unwrap();
+        assert_well_formed(&wat);
+        wat
+    }
+
+    /// Assemble the text with `wat` and validate the module, with the GC,
+    /// function reference and tail call proposals the backend relies on
+    fn assert_well_formed(wat: &str) {
+        let binary = wat::parse_str(wat).unwrap_or_else(|e| panic!("{e}\n{wat}"));
+        wasmparser::Validator::new_with_features(wasmparser::WasmFeatures::all())
+            .validate_all(&binary)
+            .unwrap_or_else(|e| panic!("{e}\n{wat}"));
+    }
+
+    /// The text of the generated function `name`
+    fn function<'a>(wat: &'a str, name: &str) -> &'a str {
+        let start = &wat[wat.find(&format!("(func ${name} ")).unwrap()..];
+        &start[..start.find("\n  )").unwrap()]
+    }
+
+    #[test]
+    fn test_lambdas_become_closures() {
+        let wat = wat(
+            "module Test\n\
+             let konst = fun x -> fun y -> x\n\
+             let use = fun z -> konst\n\
+             let cons = fun u -> Cons\n\
+             let one = fun u -> konst 1 2\n\
+             let two = fun f -> f 1 2",
+        );
+
+        // The inner lambda captures `x` into the closure's environment
+        assert!(wat.contains("(ref.func $lambda_0)\n      (array.new_fixed $env 1 (local.get $x)))"));
+        assert!(wat.contains(
+            "(func $lambda_0 (type $closure_func) (param $self.closure (ref $closure)) (param $y anyref) (result anyref)"
+        ));
+        assert!(wat.contains("(local.set $x (array.get $env (local.get $self.env) (i32.const 0)))"));
+        // A function used as a value is wrapped in a closure of its own
+        assert!(wat.contains("(func $use (param $z anyref) (result anyref)\n    (struct.new $closure"));
+        // Constructors too, curried: the first argument is captured for the second
+        assert!(wat.contains("(func $lambda_3 (type $closure_func) (param $self.closure (ref $closure)) (param $arg.1 anyref)"));
+        assert!(wat.contains("(ref.func $lambda_3)\n      (array.new_fixed $env 1 (local.get $arg.0)))"));
+        assert!(wat.contains("(elem declare func $lambda_0 $lambda_1 $lambda_2 $lambda_3)"));
+
+        // `konst` takes one argument directly, the second through its result;
+        // a closure takes each argument through `$rt.apply`
+        assert!(wat.contains("(call $konst)\n    (struct.new $int (i64.const 2))\n    (call $rt.apply)"));
+        let two = &wat[wat.find("(func $two").unwrap()..];
+        assert_eq!(two[..two.find("\n  )").unwrap()].matches("(call $rt.apply)").count(), 2);
+    }
+
+    #[test]
+    fn test_constructors_lower_to_tagged_structs() {
+        let shapes = wat(
+            "module Test\n\
+             data Shape = Circle Int | Rect Int Int | Empty\n\
+             let make = fun n -> Rect n n\n\
+             let nothing = fun u -> Empty\n\
+             let area = fun s -> match s with | Circle r => r | Rect w h if true => w | Empty => 0",
+        );
+
+        assert!(shapes.contains("(type $Shape.Rect (sub final $data (struct (field $tag i32) (field anyref) (field anyref))))"));
+        assert!(shapes.contains("(i32.const 1)\n    (local.get $n)\n    (local.get $n)\n    (struct.new $Shape.Rect)"));
+        assert!(shapes.contains("(struct.new $Shape.Empty (i32.const 2))"));
+        // One branch table on the tag; fields are bound through a cast
+        assert!(shapes.contains("(br_table $arm.1 $arm.2 $arm.3 $arm.3 (call $rt.tag (local.get $match.0)))"));
+        assert!(shapes.contains("(local.set $h (struct.get $Shape.Rect 2 (ref.cast (ref $Shape.Rect) (local.get $match.0))))"));
+        // A guard that fails falls to the cases after it, of which there are
+        // none for `Rect`
+        assert!(shapes.contains("(br_if $guard.4 (i32.eqz))\n          (local.get $w)\n          (br $match.0))\n        (unreachable)"));
+        assert_eq!(function(&shapes, "area").matches("(unreachable)").count(), 1);
+
+        // A nested pattern casts the field only once the outer tag has matched
+        let nested = wat("module Test\nlet first = fun xs -> match xs with | Cons (Some x) rest => x | _ => 0");
+        assert!(nested.contains(
+            "(br_table $arm.2 $arm.1 $arm.2 (call $rt.tag (local.get $match.0)))\n        )\n        \
+             (block $arm.4\n          (block $arm.3\n            \
+             (br_table $arm.4 $arm.3 $arm.4 (call $rt.tag (struct.get $List.Cons 1 (ref.cast (ref $List.Cons) (local.get $match.0)))))"
+        ));
+        assert!(!function(&nested, "first").contains("(unreachable)"));
+    }
+
+    #[test]
+    fn test_match_literals_and_or_patterns() {
+        let sizes = wat("module Test\nlet size = fun n -> match n with | 1 | 2 | 3 => 0 | 5 => 1 | _ => 2");
+        assert!(sizes.contains(
+            "(br_if $test.1 (i32.eqz (i32.and (i64.ge_s (struct.get $int 0 (ref.cast (ref $int) (local.get $match.0))) (i64.const 1)) \
+             (i64.le_s (struct.get $int 0 (ref.cast (ref $int) (local.get $match.0))) (i64.const 3)))))"
+        ));
+        assert!(sizes.contains("(br_if $test.2 (i32.eqz (i64.eq (struct.get $int 0 (ref.cast (ref $int) (local.get $match.0))) (i64.const 5))))"));
+        assert!(!function(&sizes, "size").contains("(unreachable)"));
+
+        // Without a default, a value no case matches traps
+        let partial = wat("module Test\nlet one = fun b -> match b with | true => 1");
+        assert_eq!(function(&partial, "one").matches("(unreachable)").count(), 1);
+    }
+
+    #[test]
+    fn test_multi_shot_clauses_are_rejected() {
+        let effect = "module Test\neffect Choice { flip : Bool }\n";
+        let one_shot = codegen(&format!("{effect}handler first {{ | Choice.flip with k => k true }}")).unwrap();
+        assert!(one_shot.diagnostics.is_empty());
+
+        let source = format!("{effect}handler both {{ | Choice.flip with k => (k true, k false) }}");
+        let multi_shot = codegen(&source).unwrap();
+        assert_eq!(multi_shot.diagnostics.iter().map(|d| d.code).collect::<Vec<_>>(), ["E0206"]);
+        let span = multi_shot.diagnostics[0].location.unwrap();
+        assert!(source[span.start.0 as usize..].starts_with("Choice.flip"));
+    }
+
+    #[test]
+    fn test_tail_calls_return_with_return_call() {
+        let source = "module Test
+            let last = fun xs -> match xs with | Cons x Nil => x | Cons _ rest => last rest | Nil => 0
+            let size = fun xs -> match xs with | Cons _ rest => Some (size rest) | Nil => None";
+        let cu = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let plain = codegen_unit(&cu).unwrap().files.into_values().next().unwrap();
+        assert!(!plain.contains("return_call"));
+
+        let result = codegen_with(WasmGCBackend::new().with_tail_calls(true), &cu, PassManager::default()).unwrap();
+        let wat = result.files.into_values().next().unwrap();
+        assert_well_formed(&wat);
+        assert!(function(&wat, "last").contains("(return_call $last)"));
+        assert!(function(&wat, "size").contains("(call $size)"));
+        assert!(!function(&wat, "size").contains("return_call"));
+        assert_eq!(result.diagnostics.iter().map(|d| d.code).collect::<Vec<_>>(), ["E0208"]);
+    }
+
+    #[test]
+    fn test_scalar_locals_stay_unboxed() {
+        let source = "module Test
+            let step = fun n -> (let a = n * 2 in (let b = a + 1 in (let big = b > 10 in if big then b else a)))";
+        let cu = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let result = codegen_with(WasmGCBackend::new(), &cu, PassManager::for_level(1)).unwrap();
+        let wat = result.files.into_values().next().unwrap();
+        assert_well_formed(&wat);
+        let step = function(&wat, "step");
+        assert!(step.contains("(local $a.i64 i64)\n    (local $b.i64 i64)\n    (local $big.i32 i32)"), "{step}");
+        // The parameter is unboxed once; the locals are computed without allocating
+        assert!(step.contains("(local.get $n)\n      (ref.cast (ref $int))\n      (struct.get $int 0)\n      (i64.const 2)\n      (i64.mul)"), "{step}");
+        assert!(step.contains("(local.get $a.i64)\n        (i64.const 1)\n        (i64.add)"), "{step}");
+        assert!(step.contains("(local.get $b.i64)\n          (i64.const 10)\n          (i64.gt_s)"), "{step}");
+        // Values leaving the function are boxed where they are used
+        assert!(step.contains("(local.get $big.i32)\n          (if"), "{step}");
+        assert!(step.contains("(local.get $b.i64)\n              (struct.new $int)"), "{step}");
+        assert_eq!(step.matches("struct.new $int").count(), 2);
+
+        let plain = codegen_unit(&cu).unwrap().files.into_values().next().unwrap();
+        assert!(function(&plain, "step").contains("(local $a anyref)"));
+    }
+
+    #[test]
+    fn test_debug_info_annotates_source_locations() {
+        let source = "module Test\nlet twice' = fun x -> x\nlet twice = fun x -> twice' x";
+        let mut sources = x_parser::SourceMap::new();
+        let file = sources.add_file("Test.x", source);
+        let cu = parse_source(source, file, SyntaxStyle::SExpression).unwrap();
+        let mut backend = WasmGCBackend::new();
+        let options = CodegenOptions {
+            target: backend.target_info(),
+            output_dir: std::path::PathBuf::from("out"),
+            source_maps: false,
+            debug_info: true,
+            optimization_level: 0,
+            emit_types: false,
+            naming: Default::default(),
+            cancellation: Default::default(),
+            passes: PassManager::default(),
+            inlining: Default::default(),
+            sources: std::sync::Arc::new(sources),
+        };
+        let result = backend.generate_code(&cu, &HashMap::new(), &options).unwrap();
+        let wat = result.files.into_values().next().unwrap();
+        assert_well_formed(&wat);
+        assert!(function(&wat, "twice").contains("\n    ;;@ Test.x:3:1\n"), "{wat}");
+        assert!(wat.contains("(func $twice_ (@name \"twice'\")"), "{wat}");
+        assert!(wat.contains(";;@ Test.x:2:1"), "{wat}");
+
+        let plain = codegen_unit(&cu).unwrap().files.into_values().next().unwrap();
+        assert!(!plain.contains(";;@"));
+    }
+
+    #[test]
+    fn test_async_runs_on_the_task_queue() {
+        use x_parser::span::CharOffset;
+        use x_parser::{Expr, Item};
+        let span = x_parser::Span::new(FileId::new(0), CharOffset(0), CharOffset(0));
+        let mut cu = parse_source("module Test\nlet main = fun u -> fun v -> 0", FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let perform = |operation: &str, argument: Expr| Expr::Perform {
+            effect: symbols::ASYNC(),
+            operation: Symbol::intern(operation),
+            args: vec![argument],
+            span,
+        };
+        if let Item::ValueDef(value_def) = &mut cu.module.items[0] {
+            if let Expr::Lambda { body, .. } = &mut value_def.body {
+                **body = perform("await", perform("spawn", (**body).clone()));
+            }
+        }
+        let wat = codegen_unit(&cu).unwrap().files.into_values().next().unwrap();
+        assert_well_formed(&wat);
+
+        let main = function(&wat, "main");
+        assert!(main.contains("(struct.new $closure"));
+        assert!(main.contains("(call $rt.async_spawn)\n    (call $rt.async_await)"));
+        assert!(!main.contains("TODO"));
+        assert!(wat.contains("(func $rt.async_step"));
+    }
+
+    #[test]
+    fn test_prelude_functions_take_list_and_option_values() {
+        let wat = wat("module Test\nlet wrap = fun xs -> list_map Some xs\nlet list_length = fun xs -> 0");
+
+        assert!(wat.contains("(func $list_map (param $f anyref) (param $list anyref) (result anyref)"));
+        assert!(wat.contains("(struct.new $Option.Some)"));
+        assert!(wat.contains("(call $list_map)"));
+        // A definition of the module's own replaces the prelude's
+        assert_eq!(wat.matches("(func $list_length").count(), 1);
+    }
+
+    #[test]
+    fn test_library_calls_use_prelude_functions() {
+        let wat = wat("module Test\nimport Core.List as L\nlet count = fun xs -> L.length (L.reverse xs)");
+
+        assert!(wat.contains("(call $list_reverse)"));
+        assert!(wat.contains("(call $list_length)"));
+        assert!(!wat.contains("(call $record_get"));
+    }
+
+    #[test]
+    fn test_prelude_types_may_be_spelled_out() {
+        assert!(wat("module Test\ndata Option[a] = None | Some a").contains(";; data Option: defined by the prelude"));
+
+        let error = generate("module Test\ndata Option[a] = Nothing | Just a").unwrap_err();
+        assert!(error.to_string().contains("conflicts with the prelude's `Option`"));
+    }
+}
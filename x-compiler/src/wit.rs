@@ -1,10 +1,27 @@
-use x_parser::{CompilationUnit, Module, ModulePath, Item, TypeDef, TypeDefKind, ValueDef, Symbol, Type, Visibility, WasmType, ComponentInterface, InterfaceItem, FunctionSignature, ResourceMethod, span::{Span, FileId, ByteOffset}};
+use x_checker::{Type as CheckerType, TypeScheme};
+use x_parser::{CompilationUnit, Module, ModulePath, Item, TypeDef, TypeDefKind, ValueDef, Symbol, Type, Visibility, WasmType, ComponentInterface, InterfaceItem, FunctionSignature, ResourceMethod, EffectDef, span::{Span, FileId, CharOffset}};
+use std::collections::{BTreeSet, HashMap};
 use std::fmt::Write;
 
 /// WebAssembly Interface Types (WIT) generator
+///
+/// A module becomes one WIT package:
+///
+/// - data types go into a `types` interface, as `enum`s when every
+///   constructor is nullary and `variant`s otherwise; record aliases become
+///   `record`s and abstract types opaque `resource`s
+/// - each effect becomes an interface holding a resource of the same name
+///   whose methods are the effect's operations, imported by the world since
+///   the host provides the handler
+/// - public values are exported from the world as functions, with the
+///   signatures the type checker inferred
 pub struct WitGenerator {
     output: String,
     indent_level: usize,
+    /// Inferred types of the module's definitions
+    types: HashMap<Symbol, TypeScheme>,
+    /// Record aliases by their sorted field names, to name inferred record types
+    records: HashMap<Vec<Symbol>, Symbol>,
 }
 
 impl Default for WitGenerator {
@@ -18,24 +35,65 @@ impl WitGenerator {
         Self {
             output: String::new(),
             indent_level: 0,
+            types: HashMap::new(),
+            records: HashMap::new(),
         }
     }
 
     pub fn generate(&mut self, compilation_unit: &CompilationUnit) -> Result<String, String> {
         self.output.clear();
-        
-        // Generate package declaration from module name
-        let package_name = compilation_unit.module.name.to_string();
-        writeln!(self.output, "package {package_name};\n")
+        self.types = x_checker::type_check(compilation_unit).inferred_types;
+        let module = &compilation_unit.module;
+        self.records = module.items.iter()
+            .filter_map(|item| match item {
+                Item::TypeDef(TypeDef { name, kind: TypeDefKind::Alias(Type::Record { fields, .. }), .. }) => {
+                    let mut fields: Vec<Symbol> = fields.keys().copied().collect();
+                    fields.sort_by_key(|field| field.as_str());
+                    Some((fields, *name))
+                }
+                _ => None,
+            })
+            .collect();
+
+        writeln!(self.output, "package {};\n", package_name(&module.name))
             .map_err(|e| format!("Failed to write package declaration: {e}"))?;
 
+        let type_names = self.generate_types_interface(module)?;
+        let effects: Vec<&EffectDef> = module.items.iter()
+            .filter_map(|item| match item {
+                Item::EffectDef(effect) => Some(effect),
+                _ => None,
+            })
+            .collect();
+        for effect in &effects {
+            self.generate_effect_interface(effect)?;
+        }
+        let interfaces: Vec<&ComponentInterface> = module.items.iter()
+            .filter_map(|item| match item {
+                Item::InterfaceDef(interface) => Some(interface),
+                _ => None,
+            })
+            .collect();
+        for interface in &interfaces {
+            self.generate_interface_def(interface)?;
+        }
+
         // Generate world declaration
         writeln!(self.output, "world effect-lang {{")
             .map_err(|e| format!("Failed to write world declaration: {e}"))?;
         self.indent_level += 1;
 
-        // Process the module
-        self.generate_module(&compilation_unit.module)?;
+        for effect in &effects {
+            self.line(&format!("import {};", wit_name(effect.name.as_str())))?;
+        }
+        if !type_names.is_empty() {
+            let names: Vec<String> = type_names.iter().map(|name| wit_name(name)).collect();
+            self.line(&format!("use types.{{{}}};", names.join(", ")))?;
+        }
+        for interface in &interfaces {
+            self.line(&format!("export {};", wit_name(&interface.name)))?;
+        }
+        self.generate_module(module)?;
 
         self.indent_level -= 1;
         writeln!(self.output, "}}")
@@ -44,10 +102,59 @@ impl WitGenerator {
         Ok(self.output.clone())
     }
 
-    fn generate_module(&mut self, module: &Module) -> Result<(), String> {
-        writeln!(self.output, "{}// Module: {}", self.indent(), module.name)
-            .map_err(|e| format!("Failed to write module comment: {e}"))?;
+    /// Emit the `types` interface and return the names it defines
+    fn generate_types_interface(&mut self, module: &Module) -> Result<BTreeSet<String>, String> {
+        let type_defs: Vec<&TypeDef> = module.items.iter()
+            .filter_map(|item| match item {
+                Item::TypeDef(def) if def.type_params.is_empty() => Some(def),
+                _ => None,
+            })
+            .collect();
+        if type_defs.is_empty() {
+            return Ok(BTreeSet::new());
+        }
+
+        self.line("interface types {")?;
+        self.indent_level += 1;
+        for (i, type_def) in type_defs.iter().enumerate() {
+            if i > 0 {
+                self.line("")?;
+            }
+            self.generate_type_def(type_def)?;
+        }
+        self.indent_level -= 1;
+        self.line("}\n")?;
+
+        Ok(type_defs.iter().map(|def| def.name.as_str().to_string()).collect())
+    }
+
+    /// Emit an effect as an interface exposing a resource whose methods are its operations
+    fn generate_effect_interface(&mut self, effect: &EffectDef) -> Result<(), String> {
+        let name = wit_name(effect.name.as_str());
+        self.line(&format!("interface {name} {{"))?;
+        self.indent_level += 1;
+        self.line(&format!("resource {name} {{"))?;
+        self.indent_level += 1;
+        for operation in &effect.operations {
+            let params: Vec<String> = operation.parameters.iter()
+                .filter(|param| !is_unit(param))
+                .enumerate()
+                .map(|(i, param)| format!("p{i}: {}", self.type_to_wit(param)))
+                .collect();
+            let result = if is_unit(&operation.return_type) {
+                String::new()
+            } else {
+                format!(" -> {}", self.type_to_wit(&operation.return_type))
+            };
+            self.line(&format!("{}: func({}){result};", wit_name(operation.name.as_str()), params.join(", ")))?;
+        }
+        self.indent_level -= 1;
+        self.line("}")?;
+        self.indent_level -= 1;
+        self.line("}\n")
+    }
 
+    fn generate_module(&mut self, module: &Module) -> Result<(), String> {
         for item in &module.items {
             self.generate_item(item)?;
         }
@@ -55,15 +162,18 @@ impl WitGenerator {
         Ok(())
     }
 
+    /// World-level items; types, effects and interfaces have their own
+    /// interfaces, emitted before the world
     fn generate_item(&mut self, item: &Item) -> Result<(), String> {
         match item {
-            Item::InterfaceDef(interface) => self.generate_interface_def(interface),
-            Item::TypeDef(type_def) => self.generate_type_def(type_def),
             Item::ValueDef(value_def) => self.generate_value_def(value_def),
-            Item::EffectDef(_) => Ok(()), // Skip effect definitions for now
+            Item::InterfaceDef(_) | Item::TypeDef(_) | Item::EffectDef(_) => Ok(()),
             Item::HandlerDef(_) => Ok(()), // Skip handler definitions for now
             Item::ModuleTypeDef(_) => Ok(()), // Skip module type definitions for now
-            Item::TestDef(_) => Ok(()), // Skip test definitions for now
+            Item::TestDef(_) | Item::BenchDef(_) => Ok(()), // Tests and benchmarks are not exported
+            Item::ClassDef(_) | Item::InstanceDef(_) => Ok(()), // Classes are lowered to dictionaries
+            Item::Fixity(_) => Ok(()), // Only the parser needs fixities
+            Item::Conditional(_) => Ok(()), // Resolved for the target before code generation
         }
     }
 
@@ -228,70 +338,84 @@ impl WitGenerator {
     }
 
     fn generate_type_def(&mut self, type_def: &TypeDef) -> Result<(), String> {
-        // Generate WIT type definition for complex types
-        writeln!(self.output, "{}// Type: {}", self.indent(), type_def.name.as_str())
-            .map_err(|e| format!("Failed to write type comment: {e}"))?;
-        
-        // Convert x-lang type to WIT type
+        let name = wit_name(type_def.name.as_str());
         match &type_def.kind {
+            TypeDefKind::Data(constructors) if constructors.iter().all(|c| c.fields.is_empty()) => {
+                self.line(&format!("enum {name} {{"))?;
+                self.indent_level += 1;
+                for constructor in constructors {
+                    self.line(&format!("{},", wit_name(constructor.name.as_str())))?;
+                }
+                self.indent_level -= 1;
+                self.line("}")?;
+            }
             TypeDefKind::Data(constructors) => {
-                // Generate variant for sum types
-                writeln!(self.output, "{}variant {} {{", self.indent(), type_def.name.as_str())
-                    .map_err(|e| format!("Failed to write variant declaration: {e}"))?;
+                self.line(&format!("variant {name} {{"))?;
                 self.indent_level += 1;
-
                 for constructor in constructors {
-                    if constructor.fields.is_empty() {
-                        writeln!(self.output, "{}{},", self.indent(), constructor.name.as_str())
-                            .map_err(|e| format!("Failed to write variant constructor: {e}"))?;
-                    } else if constructor.fields.len() == 1 {
-                        writeln!(self.output, "{}{}({}),", self.indent(), constructor.name.as_str(), self.type_to_wit(&constructor.fields[0]))
-                            .map_err(|e| format!("Failed to write variant constructor: {e}"))?;
-                    } else {
+                    let case = wit_name(constructor.name.as_str());
+                    match constructor.fields.as_slice() {
+                        [] => self.line(&format!("{case},"))?,
+                        [field] => self.line(&format!("{case}({}),", self.type_to_wit(field)))?,
                         // Multiple fields become a tuple
-                        let fields_str = constructor.fields.iter()
-                            .map(|t| self.type_to_wit(t))
-                            .collect::<Vec<_>>()
-                            .join(", ");
-                        writeln!(self.output, "{}{}(tuple<{}>),", self.indent(), constructor.name.as_str(), fields_str)
-                            .map_err(|e| format!("Failed to write variant constructor: {e}"))?;
+                        fields => {
+                            let fields: Vec<String> = fields.iter().map(|t| self.type_to_wit(t)).collect();
+                            self.line(&format!("{case}(tuple<{}>),", fields.join(", ")))?;
+                        }
                     }
                 }
-
                 self.indent_level -= 1;
-                writeln!(self.output, "{}}}", self.indent())
-                    .map_err(|e| format!("Failed to close variant: {e}"))?;
+                self.line("}")?;
+            }
+            TypeDefKind::Alias(Type::Record { fields, .. }) => {
+                self.line(&format!("record {name} {{"))?;
+                self.indent_level += 1;
+                let mut fields: Vec<_> = fields.iter().collect();
+                fields.sort_by_key(|(field, _)| field.as_str());
+                for (field, typ) in fields {
+                    self.line(&format!("{}: {},", wit_name(field.as_str()), self.type_to_wit(typ)))?;
+                }
+                self.indent_level -= 1;
+                self.line("}")?;
             }
             TypeDefKind::Alias(aliased_type) => {
-                writeln!(self.output, "{}type {} = {};", self.indent(), type_def.name.as_str(), self.type_to_wit(aliased_type))
-                    .map_err(|e| format!("Failed to write type alias: {e}"))?;
+                self.line(&format!("type {name} = {};", self.type_to_wit(aliased_type)))?;
             }
             TypeDefKind::Abstract => {
-                // Abstract types can't be directly represented in WIT
-                writeln!(self.output, "{}// Abstract type: {}", self.indent(), type_def.name.as_str())
-                    .map_err(|e| format!("Failed to write abstract type comment: {e}"))?;
+                // Values of abstract types stay on the guest side behind a handle
+                self.line(&format!("resource {name};"))?;
             }
         }
 
-        writeln!(self.output)
-            .map_err(|e| format!("Failed to write newline: {e}"))?;
-
         Ok(())
     }
 
     fn generate_value_def(&mut self, value_def: &ValueDef) -> Result<(), String> {
         // Generate export for public functions
-        if self.is_public_visibility(&value_def.visibility) {
-            writeln!(self.output, "{}export {}: func() -> {};", 
-                self.indent(), 
-                value_def.name.as_str(), 
-                self.type_to_wit(value_def.type_annotation.as_ref().unwrap_or(&Type::Con(Symbol::from("any"), Span::new(FileId::INVALID, ByteOffset::INVALID, ByteOffset::INVALID)))))
-                .map_err(|e| format!("Failed to write value export: {e}"))?;
+        if !self.is_public_visibility(&value_def.visibility) {
+            return Ok(());
         }
 
-        Ok(())
+        let name = wit_name(value_def.name.as_str());
+        let signature = match self.types.get(&value_def.name) {
+            Some(scheme) => self.checker_signature_to_wit(&scheme.body),
+            None => {
+                let annotation = value_def.type_annotation.clone()
+                    .unwrap_or(Type::Con(Symbol::from("any"), Span::new(FileId::INVALID, CharOffset::INVALID, CharOffset::INVALID)));
+                format!("func() -> {}", self.type_to_wit(&annotation))
+            }
+        };
+        self.line(&format!("export {name}: {signature};"))
     }
 
+    /// Write one indented line
+    fn line(&mut self, text: &str) -> Result<(), String> {
+        if text.is_empty() {
+            return writeln!(self.output).map_err(|e| format!("Failed to write newline: {e}"));
+        }
+        writeln!(self.output, "{}{text}", self.indent())
+            .map_err(|e| format!("Failed to write WIT: {e}"))
+    }
 
     fn wasm_type_to_wit(&self, wasm_type: &WasmType) -> String {
         match wasm_type {
@@ -312,13 +436,13 @@ impl WitGenerator {
             Type::Con(name, _) => {
                 // Map x-lang type constructors to WIT types
                 match name.as_str() {
-                    "Unit" => "()".to_string(),
+                    "Unit" => "tuple<>".to_string(),
                     "Bool" => "bool".to_string(),
                     "Int" => "s32".to_string(),
                     "Float" => "f64".to_string(),
                     "String" => "string".to_string(),
                     "Char" => "char".to_string(),
-                    _ => name.as_str().to_string(),
+                    _ => wit_name(name.as_str()),
                 }
             }
             Type::App(con, args, _) => {
@@ -335,7 +459,7 @@ impl WitGenerator {
                         }
                         _ => {
                             // Generic type application
-                            format!("{}<{}>", name.as_str(), 
+                            format!("{}<{}>", wit_name(name.as_str()),
                                 args.iter().map(|t| self.type_to_wit(t)).collect::<Vec<_>>().join(", "))
                         }
                     }
@@ -343,6 +467,9 @@ impl WitGenerator {
                     "unknown".to_string()
                 }
             }
+            Type::Tuple { types, .. } => {
+                format!("tuple<{}>", types.iter().map(|t| self.type_to_wit(t)).collect::<Vec<_>>().join(", "))
+            }
             Type::Fun { params, return_type, .. } => {
                 let param_strs: Vec<String> = params.iter().map(|t| self.type_to_wit(t)).collect();
                 format!("func({}) -> {}", param_strs.join(", "), self.type_to_wit(return_type))
@@ -351,6 +478,62 @@ impl WitGenerator {
         }
     }
 
+    /// WIT function type for an inferred type; curried functions take all their
+    /// arguments at once, and non-function values become nullary functions
+    fn checker_signature_to_wit(&self, typ: &CheckerType) -> String {
+        let mut params = Vec::new();
+        let mut result = typ;
+        while let CheckerType::Fun { params: fun_params, return_type, .. } = result {
+            params.extend(fun_params.iter().filter(|param| !is_checker_unit(param)));
+            result = return_type;
+        }
+
+        let params: Vec<String> = params.iter()
+            .enumerate()
+            .map(|(i, param)| format!("p{i}: {}", self.checker_type_to_wit(param)))
+            .collect();
+        if is_checker_unit(result) {
+            format!("func({})", params.join(", "))
+        } else {
+            format!("func({}) -> {}", params.join(", "), self.checker_type_to_wit(result))
+        }
+    }
+
+    fn checker_type_to_wit(&self, typ: &CheckerType) -> String {
+        match typ {
+            CheckerType::Con(name) => match name.as_str() {
+                "Unit" => "tuple<>".to_string(),
+                "Bool" => "bool".to_string(),
+                "Int" => "s32".to_string(),
+                "Float" => "f64".to_string(),
+                "String" => "string".to_string(),
+                "Char" => "char".to_string(),
+                _ => wit_name(name.as_str()),
+            },
+            CheckerType::App(con, args) => {
+                let args: Vec<String> = args.iter().map(|t| self.checker_type_to_wit(t)).collect();
+                match (con.as_ref(), args.as_slice()) {
+                    (CheckerType::Con(name), [arg]) if name.as_str() == "List" => format!("list<{arg}>"),
+                    (CheckerType::Con(name), [arg]) if name.as_str() == "Option" => format!("option<{arg}>"),
+                    (CheckerType::Con(name), [ok, err]) if name.as_str() == "Result" => format!("result<{ok}, {err}>"),
+                    _ => format!("{}<{}>", self.checker_type_to_wit(con), args.join(", ")),
+                }
+            }
+            CheckerType::Tuple(types) => {
+                format!("tuple<{}>", types.iter().map(|t| self.checker_type_to_wit(t)).collect::<Vec<_>>().join(", "))
+            }
+            CheckerType::Record(fields) => {
+                let mut names: Vec<Symbol> = fields.iter().map(|(field, _)| *field).collect();
+                names.sort_by_key(|field| field.as_str());
+                // WIT has no anonymous records
+                self.records.get(&names)
+                    .map_or_else(|| "unknown".to_string(), |name| wit_name(name.as_str()))
+            }
+            CheckerType::Forall { body, .. } => self.checker_type_to_wit(body),
+            _ => "unknown".to_string(),
+        }
+    }
+
     #[allow(dead_code)]
     fn visibility_to_wit_export(&self, visibility: &Visibility) -> Option<String> {
         match visibility {
@@ -373,6 +556,56 @@ impl WitGenerator {
     }
 }
 
+/// WIT package names need a namespace; modules without one are placed under `x:`
+fn package_name(path: &ModulePath) -> String {
+    let name = path.to_string().replace('.', "-");
+    match name.split_once(':') {
+        Some((namespace, package)) => format!("{}:{}", wit_name(namespace), wit_name(package)),
+        None => format!("x:{}", wit_name(&name)),
+    }
+}
+
+/// WIT words that must be escaped with `%` when used as names
+const WIT_KEYWORDS: &[&str] = &[
+    "as", "bool", "borrow", "char", "constructor", "enum", "export", "f32", "f64", "flags", "from",
+    "func", "import", "include", "interface", "list", "option", "own", "package", "record",
+    "resource", "result", "s8", "s16", "s32", "s64", "static", "string", "tuple", "type", "u8",
+    "u16", "u32", "u64", "use", "variant", "with", "world",
+];
+
+/// Convert an x identifier (`areaOf`, `area_of`, `Shape`) to a kebab-case WIT name
+pub(crate) fn wit_name(name: &str) -> String {
+    let mut kebab = String::new();
+    let mut previous_lower = false;
+    for c in name.chars() {
+        if c == '_' || c == '-' {
+            if !kebab.is_empty() && !kebab.ends_with('-') {
+                kebab.push('-');
+            }
+            previous_lower = false;
+            continue;
+        }
+        if c.is_uppercase() && previous_lower {
+            kebab.push('-');
+        }
+        previous_lower = c.is_lowercase() || c.is_ascii_digit();
+        kebab.extend(c.to_lowercase());
+    }
+    let kebab = kebab.trim_end_matches('-').to_string();
+    if WIT_KEYWORDS.contains(&kebab.as_str()) {
+        format!("%{kebab}")
+    } else {
+        kebab
+    }
+}
+
+fn is_unit(typ: &Type) -> bool {
+    matches!(typ, Type::Con(name, _) if name.as_str() == "Unit")
+}
+
+fn is_checker_unit(typ: &CheckerType) -> bool {
+    matches!(typ, CheckerType::Con(name) if name.as_str() == "Unit")
+}
 
 #[cfg(test)]
 mod tests {
@@ -382,7 +615,7 @@ mod tests {
     #[test]
     fn test_basic_wit_generation() {
         let mut generator = WitGenerator::new();
-        let span = Span::new(FileId::new(0), ByteOffset(0), ByteOffset(0));
+        let span = Span::new(FileId::new(0), CharOffset(0), CharOffset(0));
         let compilation_unit = CompilationUnit {
             module: Module {
                 documentation: None,
@@ -396,10 +629,85 @@ mod tests {
         };
 
         let result = generator.generate(&compilation_unit).unwrap();
-        assert!(result.contains("package test:package;"));
+        assert_well_formed(&result);
+        assert!(result.contains("package test:%package;"));
         assert!(result.contains("world effect-lang {"));
     }
 
+    const SHAPES: &str = "module Shapes
+data Shape = Circle Float | Rect Float Float
+data Color = Red | Green
+type Point = {x: Float, y: Float}
+effect Counter {
+  tick : Unit -> Int
+  add : Int -> Unit
+}
+pub let origin = {x = 0.0, y = 0.0}
+pub let negate = fun b -> if b then false else true
+let hidden = 1";
+
+    fn parse(source: &str) -> CompilationUnit {
+        x_parser::parse_source(source, FileId::new(0), x_parser::SyntaxStyle::SExpression).unwrap()
+    }
+
+    fn generate(unit: &CompilationUnit) -> String {
+        let wit = WitGenerator::new().generate(unit).unwrap();
+        assert_well_formed(&wit);
+        wit
+    }
+
+    /// Parse and resolve the package with `wit-parser`, which rejects
+    /// syntax errors, undefined interfaces and types, and invalid names
+    fn assert_well_formed(wit: &str) {
+        wit_parser::Resolve::default()
+            .push_str("generated.wit", wit)
+            .unwrap_or_else(|e| panic!("{e:?}\n{wit}"));
+    }
+
+    #[test]
+    fn test_data_types_map_to_wit_types() {
+        let mut unit = parse(SHAPES);
+        unit.module.items.push(Item::TypeDef(TypeDef {
+            name: Symbol::intern("Handle"),
+            documentation: None,
+            type_params: vec![],
+            kind: TypeDefKind::Abstract,
+            visibility: Visibility::Public,
+            span: unit.span,
+        }));
+
+        let wit = generate(&unit);
+        assert!(wit.starts_with("package x:shapes;\n"));
+        assert!(wit.contains("  variant shape {\n    circle(f64),\n    rect(tuple<f64, f64>),\n  }\n"));
+        assert!(wit.contains("  enum color {\n    red,\n    green,\n  }\n"));
+        assert!(wit.contains("  record point {\n    x: f64,\n    y: f64,\n  }\n"));
+        assert!(wit.contains("  resource handle;\n"));
+    }
+
+    #[test]
+    fn test_effects_become_imported_resources() {
+        let wit = generate(&parse(SHAPES));
+        assert!(wit.contains("interface counter {\n  resource counter {\n    tick: func() -> s32;\n    add: func(p0: s32);\n  }\n}\n"));
+        assert!(wit.contains("world effect-lang {\n  import counter;\n"));
+    }
+
+    #[test]
+    fn test_world_exports_public_values() {
+        let wit = generate(&parse(SHAPES));
+        assert!(wit.contains("  use types.{color, point, shape};\n"));
+        assert!(wit.contains("  export origin: func() -> point;\n"));
+        assert!(wit.contains("  export negate: func(p0: bool) -> bool;\n"));
+        assert!(!wit.contains("hidden"));
+    }
+
+    #[test]
+    fn test_wit_names() {
+        assert_eq!(wit_name("isRed"), "is-red");
+        assert_eq!(wit_name("area_of"), "area-of");
+        assert_eq!(wit_name("Shape"), "shape");
+        assert_eq!(wit_name("type"), "%type");
+    }
+
     #[test]
     fn test_wasm_type_conversion() {
         let generator = WitGenerator::new();
@@ -62,15 +62,13 @@ impl CodegenBackend for WitBackend {
         // Generate WIT file
         match self.generator.generate(cu) {
             Ok(wit_content) => {
-                let mut output_path = options.output_dir.clone();
-                output_path.push(format!("{}.wit", 
-                    cu.module.name));
-                
-                files.insert(output_path, wit_content);
+                let path = options.naming.module_file(&cu.module.name.to_string(), "wit");
+                files.insert(options.output_dir.join(path), wit_content);
             }
             Err(e) => {
                 diagnostics.push(CodegenDiagnostic {
                     severity: DiagnosticSeverity::Error,
+                    code: "E0200",
                     message: format!("Failed to generate WIT: {e}"),
                     location: None,
                 });
@@ -78,24 +76,31 @@ impl CodegenBackend for WitBackend {
         }
 
         // Generate cargo.toml for component
+        options.check_cancelled()?;
         let cargo_toml = self.generate_cargo_toml(cu)?;
         let mut cargo_path = options.output_dir.clone();
         cargo_path.push("Cargo.toml");
         files.insert(cargo_path, cargo_toml);
 
+        // Without an IR, all of the time goes to emitting
+        let compilation_time = start_time.elapsed();
+
         // Calculate total size
         let total_size = files.values().map(|content| content.len()).sum();
         let file_count = files.len();
 
         Ok(CodegenResult {
             files,
+            binary_files: HashMap::new(),
             source_maps: HashMap::new(),
             diagnostics,
             metadata: CodegenMetadata {
                 target_info: self.target_info(),
                 generated_files: file_count,
                 total_size,
-                compilation_time: start_time.elapsed(),
+                compilation_time,
+                passes: Vec::new(),
+                emit_time: compilation_time,
             },
         })
     }
@@ -128,6 +133,7 @@ impl CodegenBackend for WitBackend {
         if !code.contains("world") && !code.contains("interface") {
             diagnostics.push(CodegenDiagnostic {
                 severity: DiagnosticSeverity::Warning,
+                code: "E0202",
                 message: "WIT file doesn't contain world or interface definitions".to_string(),
                 location: None,
             });
@@ -137,6 +143,7 @@ impl CodegenBackend for WitBackend {
         if code.contains("record {") && !code.contains("}") {
             diagnostics.push(CodegenDiagnostic {
                 severity: DiagnosticSeverity::Error,
+                code: "E0203",
                 message: "Unclosed record definition".to_string(),
                 location: None,
             });
@@ -145,6 +152,7 @@ impl CodegenBackend for WitBackend {
         if code.contains("interface ") && !code.contains("func") {
             diagnostics.push(CodegenDiagnostic {
                 severity: DiagnosticSeverity::Warning,
+                code: "E0204",
                 message: "Interface contains no function definitions".to_string(),
                 location: None,
             });
@@ -221,8 +229,8 @@ mod tests {
     #[test]
     fn test_cargo_toml_generation() {
         let backend = WitBackend::new();
-        use x_parser::{Module, ModulePath, Symbol, Span, FileId, span::ByteOffset};
-        let span = Span::new(FileId::new(0), ByteOffset(0), ByteOffset(0));
+        use x_parser::{Module, ModulePath, Symbol, Span, FileId, span::CharOffset};
+        let span = Span::new(FileId::new(0), CharOffset(0), CharOffset(0));
         let cu = CompilationUnit {
             module: Module {
                 documentation: None,
@@ -0,0 +1,10 @@
+module Core.Exn
+
+import Core.Result { Ok, Error }
+
+-- | Run a computation performing `Exn`, giving `Ok` its value or `Error` what
+-- | it raised
+pub handler catch {
+  | Exn.raise e with k => Error e
+  | return x => Ok x
+}
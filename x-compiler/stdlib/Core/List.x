@@ -0,0 +1,53 @@
+module Core.List
+
+import Core.Option { Some, None }
+
+-- | A singly linked list
+pub data List[a] = Nil | Cons a (List[a])
+
+pub let length = fun list ->
+  match list with
+  | Nil => 0
+  | Cons _ rest => 1 + length rest
+
+pub let is_empty = fun list ->
+  match list with
+  | Nil => true
+  | Cons _ _ => false
+
+pub let head = fun list ->
+  match list with
+  | Nil => None
+  | Cons x _ => Some x
+
+pub let tail = fun list ->
+  match list with
+  | Nil => None
+  | Cons _ rest => Some rest
+
+pub let map = fun f -> fun list ->
+  match list with
+  | Nil => Nil
+  | Cons x rest => Cons (f x) (map f rest)
+
+pub let filter = fun keep -> fun list ->
+  match list with
+  | Nil => Nil
+  | Cons x rest => if keep x then Cons x (filter keep rest) else filter keep rest
+
+pub let fold = fun f -> fun acc -> fun list ->
+  match list with
+  | Nil => acc
+  | Cons x rest => fold f (f acc x) rest
+
+pub let append = fun left -> fun right ->
+  match left with
+  | Nil => right
+  | Cons x rest => Cons x (append rest right)
+
+pub let reverse = fun list -> fold (fun acc -> fun x -> Cons x acc) Nil list
+
+pub let find = fun matches -> fun list ->
+  match list with
+  | Nil => None
+  | Cons x rest => if matches x then Some x else find matches rest
@@ -0,0 +1,30 @@
+module Core.Map
+
+import Core.Option { Some, None }
+
+-- | A map from keys to values, kept as an association list
+pub data Map[k, v] = Empty | Entry k v (Map[k, v])
+
+pub let empty = Empty
+
+pub let get = fun key -> fun map ->
+  match map with
+  | Empty => None
+  | Entry k v rest => if k == key then Some v else get key rest
+
+pub let remove = fun key -> fun map ->
+  match map with
+  | Empty => Empty
+  | Entry k v rest => if k == key then remove key rest else Entry k v (remove key rest)
+
+pub let insert = fun key -> fun value -> fun map -> Entry key value (remove key map)
+
+pub let contains = fun key -> fun map ->
+  match map with
+  | Empty => false
+  | Entry k _ rest => if k == key then true else contains key rest
+
+pub let size = fun map ->
+  match map with
+  | Empty => 0
+  | Entry _ _ rest => 1 + size rest
@@ -0,0 +1,29 @@
+module Core.Option
+
+-- | An optional value
+pub data Option[a] = None | Some a
+
+pub let map = fun f -> fun option ->
+  match option with
+  | None => None
+  | Some x => Some (f x)
+
+pub let and_then = fun f -> fun option ->
+  match option with
+  | None => None
+  | Some x => f x
+
+pub let get_or = fun default -> fun option ->
+  match option with
+  | None => default
+  | Some x => x
+
+pub let is_some = fun option ->
+  match option with
+  | None => false
+  | Some _ => true
+
+pub let is_none = fun option ->
+  match option with
+  | None => true
+  | Some _ => false
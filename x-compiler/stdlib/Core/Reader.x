@@ -0,0 +1,8 @@
+module Core.Reader
+
+-- | Run a computation performing `Reader`: the handled computation becomes a
+-- | function of the value `ask` gives
+pub handler run {
+  | Reader.ask with k => fun r -> (k r) r
+  | return x => fun _ -> x
+}
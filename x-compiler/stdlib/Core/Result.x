@@ -0,0 +1,36 @@
+module Core.Result
+
+import Core.Option { Some, None }
+
+-- | Either a value or the error that prevented computing it
+pub data Result[a, e] = Ok a | Error e
+
+pub let map = fun f -> fun res ->
+  match res with
+  | Ok x => Ok (f x)
+  | Error e => Error e
+
+pub let map_error = fun f -> fun res ->
+  match res with
+  | Ok x => Ok x
+  | Error e => Error (f e)
+
+pub let and_then = fun f -> fun res ->
+  match res with
+  | Ok x => f x
+  | Error e => Error e
+
+pub let get_or = fun default -> fun res ->
+  match res with
+  | Ok x => x
+  | Error _ => default
+
+pub let to_option = fun res ->
+  match res with
+  | Ok x => Some x
+  | Error _ => None
+
+pub let is_ok = fun res ->
+  match res with
+  | Ok _ => true
+  | Error _ => false
@@ -0,0 +1,16 @@
+module Core.State
+
+-- | Run a computation performing `State`: the handled computation becomes a
+-- | function of the initial state, giving the computation's value
+pub handler run {
+  | State.get with k => fun s -> (k s) s
+  | State.put s with k => fun _ -> (k ()) s
+  | return x => fun _ -> x
+}
+
+-- | Like `run`, giving the final state alongside the value
+pub handler run_with_state {
+  | State.get with k => fun s -> (k s) s
+  | State.put s with k => fun _ -> (k ()) s
+  | return x => fun s -> (x, s)
+}
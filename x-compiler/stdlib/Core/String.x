@@ -0,0 +1,19 @@
+module Core.String
+
+import Core.List { Nil, Cons }
+
+pub let concat = fun left -> fun right -> left ^ right
+
+pub let from_int = fun n -> string_of_int n
+
+pub let to_int = fun s -> int_of_string s
+
+pub let join = fun separator -> fun parts ->
+  match parts with
+  | Nil => ""
+  | Cons first rest => match rest with
+    | Nil => first
+    | Cons _ _ => first ^ separator ^ join separator rest
+
+pub let repeat = fun count -> fun s ->
+  if count < 1 then "" else s ^ repeat (count - 1) s
@@ -0,0 +1,321 @@
+//! Call graph and definition-use analysis
+//!
+//! [`call_graph`] links every value, test, handler and effect definition of a
+//! module to the definitions of the same module it uses: a value or test to
+//! the names its body refers to and the effects it performs, a handler to the
+//! effects it handles and the names its clauses refer to. Names that are not
+//! defined in the module (builtins, imports, local bindings) are left out.
+//!
+//! Exported definitions, tests and `main` are the entry points; whatever they
+//! cannot reach is dead code. The callers of a definition, followed
+//! transitively, are what an edit to it can affect.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use x_parser::dependency::DependencyManager;
+use x_parser::{CompilationUnit, Expr, Item, Span, Symbol, Visibility};
+
+/// What a definition defines
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DefinitionKind {
+    Value,
+    Test,
+    Handler,
+    Effect,
+}
+
+impl fmt::Display for DefinitionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DefinitionKind::Value => "value",
+            DefinitionKind::Test => "test",
+            DefinitionKind::Handler => "handler",
+            DefinitionKind::Effect => "effect",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A node of the call graph
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    pub name: Symbol,
+    pub kind: DefinitionKind,
+    /// Public, or named in the module's export list
+    pub exported: bool,
+    pub span: Span,
+}
+
+impl Definition {
+    /// Whether analysis starts from this definition
+    pub fn is_entry_point(&self) -> bool {
+        self.exported || self.kind == DefinitionKind::Test || self.name.as_str() == "main"
+    }
+}
+
+/// Definitions of one module and the uses between them
+#[derive(Debug, Clone, Default)]
+pub struct CallGraph {
+    module: String,
+    /// In source order; a name defined twice keeps its first definition
+    definitions: Vec<Definition>,
+    index: HashMap<Symbol, usize>,
+    /// Indices of the definitions each definition uses, in source order
+    callees: Vec<Vec<usize>>,
+    callers: Vec<Vec<usize>>,
+}
+
+/// Build the call graph of `cu`
+pub fn call_graph(cu: &CompilationUnit) -> CallGraph {
+    let exports: HashSet<Symbol> = cu.module.exports.iter()
+        .flat_map(|exports| exports.items.iter().map(|item| item.name))
+        .collect();
+
+    let mut graph = CallGraph { module: cu.module.name.to_string(), ..CallGraph::default() };
+    let mut uses = Vec::new();
+    for item in &cu.module.items {
+        let (name, kind, visibility, span, used) = match item {
+            Item::ValueDef(def) => (
+                def.name, DefinitionKind::Value, &def.visibility, def.span,
+                DependencyManager::extract_dependencies_from_def(def),
+            ),
+            Item::TestDef(test) => {
+                let exprs: Vec<&Expr> = test.setup.iter().map(|e| &**e)
+                    .chain(std::iter::once(&test.body))
+                    .chain(test.teardown.iter().map(|e| &**e))
+                    .collect();
+                (test.name, DefinitionKind::Test, &test.visibility, test.span, dependencies_of(&exprs))
+            }
+            Item::HandlerDef(handler) => {
+                let exprs: Vec<&Expr> = handler.handlers.iter().map(|op| &op.body)
+                    .chain(handler.return_clause.iter().map(|ret| &*ret.body))
+                    .collect();
+                let mut used = dependencies_of(&exprs);
+                used.extend(handler.handled_effects.iter().map(|effect| effect.name));
+                used.extend(handler.handlers.iter().map(|op| op.effect.name));
+                (handler.name, DefinitionKind::Handler, &handler.visibility, handler.span, used)
+            }
+            Item::EffectDef(effect) => (
+                effect.name, DefinitionKind::Effect, &effect.visibility, effect.span, HashSet::new(),
+            ),
+            _ => continue,
+        };
+        if graph.index.contains_key(&name) {
+            continue;
+        }
+        graph.index.insert(name, graph.definitions.len());
+        graph.definitions.push(Definition {
+            name,
+            kind,
+            exported: *visibility != Visibility::Private || exports.contains(&name),
+            span,
+        });
+        uses.push(used);
+    }
+
+    graph.callers = vec![Vec::new(); graph.definitions.len()];
+    for (caller, used) in uses.into_iter().enumerate() {
+        let mut callees: Vec<usize> = used.iter()
+            .filter_map(|name| graph.index.get(name).copied())
+            .filter(|&callee| callee != caller)
+            .collect();
+        callees.sort_unstable();
+        for &callee in &callees {
+            graph.callers[callee].push(caller);
+        }
+        graph.callees.push(callees);
+    }
+    graph
+}
+
+fn dependencies_of(exprs: &[&Expr]) -> HashSet<Symbol> {
+    exprs.iter().flat_map(|expr| DependencyManager::extract_dependencies(expr)).collect()
+}
+
+impl CallGraph {
+    /// Every definition, in source order
+    pub fn definitions(&self) -> &[Definition] {
+        &self.definitions
+    }
+
+    pub fn definition(&self, name: &str) -> Option<&Definition> {
+        self.lookup(name).map(|index| &self.definitions[index])
+    }
+
+    /// Every use of one definition by another, as (user, used)
+    pub fn edges(&self) -> impl Iterator<Item = (&Definition, &Definition)> {
+        self.callees.iter().enumerate().flat_map(move |(caller, callees)| {
+            callees.iter().map(move |&callee| (&self.definitions[caller], &self.definitions[callee]))
+        })
+    }
+
+    /// Definitions `name` uses directly
+    pub fn callees(&self, name: &str) -> Vec<&Definition> {
+        self.neighbours(name, &self.callees)
+    }
+
+    /// Definitions using `name` directly
+    pub fn callers(&self, name: &str) -> Vec<&Definition> {
+        self.neighbours(name, &self.callers)
+    }
+
+    /// Definitions reachable from `roots`, the roots included, in source order
+    pub fn reachable_from(&self, roots: &[&str]) -> Vec<&Definition> {
+        let roots: Vec<usize> = roots.iter().filter_map(|name| self.lookup(name)).collect();
+        self.collect(self.closure(roots, &self.callees))
+    }
+
+    /// Definitions no entry point reaches
+    pub fn dead_code(&self) -> Vec<&Definition> {
+        let live = self.live();
+        self.definitions.iter().enumerate()
+            .filter(|(index, _)| !live.contains(index))
+            .map(|(_, definition)| definition)
+            .collect()
+    }
+
+    /// Definitions an edit to `name` can affect: everything that uses it,
+    /// directly or through other definitions
+    pub fn impact_of(&self, name: &str) -> Vec<&Definition> {
+        let Some(start) = self.lookup(name) else { return Vec::new() };
+        let mut affected = self.closure(self.callers[start].clone(), &self.callers);
+        affected.remove(&start);
+        self.collect(affected)
+    }
+
+    /// The graph in Graphviz DOT, dead definitions dashed and entry points bold
+    pub fn to_dot(&self) -> String {
+        let live = self.live();
+        let mut dot = format!("digraph {:?} {{\n", self.module);
+        for (index, definition) in self.definitions.iter().enumerate() {
+            let shape = match definition.kind {
+                DefinitionKind::Value => "ellipse",
+                DefinitionKind::Test => "note",
+                DefinitionKind::Handler => "box",
+                DefinitionKind::Effect => "diamond",
+            };
+            let style = if !live.contains(&index) {
+                ", style=dashed"
+            } else if definition.is_entry_point() {
+                ", style=bold"
+            } else {
+                ""
+            };
+            dot.push_str(&format!("    {:?} [shape={shape}{style}];\n", definition.name.as_str()));
+        }
+        for (caller, callee) in self.edges() {
+            dot.push_str(&format!("    {:?} -> {:?};\n", caller.name.as_str(), callee.name.as_str()));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// The graph as JSON, with the dead definitions listed
+    pub fn to_json(&self) -> serde_json::Value {
+        let live = self.live();
+        let definitions: Vec<_> = self.definitions.iter().enumerate()
+            .map(|(index, definition)| serde_json::json!({
+                "name": definition.name.as_str(),
+                "kind": definition.kind.to_string(),
+                "exported": definition.exported,
+                "entry_point": definition.is_entry_point(),
+                "dead": !live.contains(&index),
+                "calls": self.callees[index].iter()
+                    .map(|&callee| self.definitions[callee].name.as_str())
+                    .collect::<Vec<_>>(),
+            }))
+            .collect();
+        let dead: Vec<_> = self.dead_code().iter().map(|definition| definition.name.as_str()).collect();
+        serde_json::json!({
+            "module": self.module,
+            "definitions": definitions,
+            "dead_code": dead,
+        })
+    }
+
+    fn lookup(&self, name: &str) -> Option<usize> {
+        self.index.get(&Symbol::intern(name)).copied()
+    }
+
+    fn neighbours(&self, name: &str, adjacency: &[Vec<usize>]) -> Vec<&Definition> {
+        self.lookup(name)
+            .map(|index| adjacency[index].iter().map(|&other| &self.definitions[other]).collect())
+            .unwrap_or_default()
+    }
+
+    /// Indices reachable from the entry points
+    fn live(&self) -> HashSet<usize> {
+        let roots = self.definitions.iter().enumerate()
+            .filter(|(_, definition)| definition.is_entry_point())
+            .map(|(index, _)| index)
+            .collect();
+        self.closure(roots, &self.callees)
+    }
+
+    fn closure(&self, mut pending: Vec<usize>, adjacency: &[Vec<usize>]) -> HashSet<usize> {
+        let mut seen = HashSet::new();
+        while let Some(index) = pending.pop() {
+            if seen.insert(index) {
+                pending.extend(&adjacency[index]);
+            }
+        }
+        seen
+    }
+
+    fn collect(&self, indices: HashSet<usize>) -> Vec<&Definition> {
+        let mut indices: Vec<usize> = indices.into_iter().collect();
+        indices.sort_unstable();
+        indices.into_iter().map(|index| &self.definitions[index]).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x_parser::{parse_source, FileId, SyntaxStyle};
+
+    fn graph(source: &str) -> CallGraph {
+        call_graph(&parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap())
+    }
+
+    fn names(definitions: Vec<&Definition>) -> Vec<&'static str> {
+        definitions.into_iter().map(|definition| definition.name.as_str()).collect()
+    }
+
+    const SOURCE: &str = "module Demo\n\
+        let square = fun x -> x * x\n\
+        let sum_squares = fun a b -> square a + square b\n\
+        let unused = fun x -> square x\n\
+        let main = fun u -> sum_squares 1 2";
+
+    #[test]
+    fn test_callers_and_callees() {
+        let graph = graph(SOURCE);
+        assert_eq!(names(graph.callees("sum_squares")), vec!["square"]);
+        assert_eq!(names(graph.callers("square")), vec!["sum_squares", "unused"]);
+        assert_eq!(names(graph.impact_of("square")), vec!["sum_squares", "unused", "main"]);
+        assert_eq!(names(graph.reachable_from(&["sum_squares"])), vec!["square", "sum_squares"]);
+        assert!(graph.callees("missing").is_empty());
+    }
+
+    #[test]
+    fn test_dead_code_from_entry_points() {
+        assert_eq!(names(graph(SOURCE).dead_code()), vec!["unused"]);
+
+        let exported = graph(&SOURCE.replace("let unused", "pub let unused"));
+        assert!(exported.dead_code().is_empty());
+    }
+
+    #[test]
+    fn test_dot_and_json_output() {
+        let graph = graph(SOURCE);
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph \"Demo\" {"), "{dot}");
+        assert!(dot.contains("\"sum_squares\" -> \"square\";"), "{dot}");
+        assert!(dot.contains("\"unused\" [shape=ellipse, style=dashed];"), "{dot}");
+
+        let json = graph.to_json();
+        assert_eq!(json["dead_code"], serde_json::json!(["unused"]));
+        assert_eq!(json["definitions"][3]["calls"], serde_json::json!(["sum_squares"]));
+    }
+}
@@ -170,6 +170,11 @@ impl TypeAnnotator {
             Item::ModuleTypeDef(_) => panic!("Module type definitions not yet supported in annotated AST"),
             Item::InterfaceDef(_) => panic!("Interface definitions not yet supported in annotated AST"),
             Item::TestDef(_) => panic!("Test definitions not yet supported in annotated AST"),
+            Item::BenchDef(_) => panic!("Benchmark definitions not yet supported in annotated AST"),
+            Item::ClassDef(_) => panic!("Class definitions not yet supported in annotated AST"),
+            Item::InstanceDef(_) => panic!("Instance definitions not yet supported in annotated AST"),
+            Item::Fixity(_) => panic!("Fixity declarations not yet supported in annotated AST"),
+            Item::Conditional(conditional) => self.annotate_item(&conditional.item),
         }
     }
     
@@ -244,8 +249,8 @@ impl TypeAnnotator {
     /// Get type for literal
     fn literal_type(&self, lit: &Literal) -> InferredType {
         match lit {
-            Literal::Integer(_) => InferredType::Con(Symbol::intern("Int")),
-            Literal::Float(_) => InferredType::Con(Symbol::intern("Float")),
+            Literal::Integer(..) => InferredType::Con(Symbol::intern("Int")),
+            Literal::Float(..) => InferredType::Con(Symbol::intern("Float")),
             Literal::String(_) => InferredType::Con(Symbol::intern("String")),
             Literal::Bool(_) => InferredType::Con(Symbol::intern("Bool")),
             Literal::Unit => InferredType::Con(Symbol::intern("Unit")),
@@ -412,7 +417,7 @@ impl AnnotatedValueDef {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use x_parser::{FileId, span::ByteOffset};
+    use x_parser::{FileId, span::CharOffset};
     
     #[test]
     fn test_type_environment() {
@@ -426,8 +431,8 @@ mod tests {
     
     #[test]
     fn test_annotated_expr() {
-        let span = Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(1));
-        let expr = Expr::Literal(Literal::Integer(42), span);
+        let span = Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(1));
+        let expr = Expr::Literal(Literal::int(42), span);
         let inferred = InferredType::Con(Symbol::intern("Int"));
         
         let annotated = AnnotatedExpr {
@@ -1,12 +1,12 @@
 //! Direct AST editing operations without text representation
 
-use crate::operations::{EditOperation, InsertOperation, DeleteOperation, ReplaceOperation, MoveOperation, EditableNode};
+use crate::operations::{EditOperation, InsertOperation, DeleteOperation, ReplaceOperation, MoveOperation, EditableNode, NodeRef};
 use crate::query::{AstQuery, QueryResult};
 use crate::validation::ValidationResult;
 use x_parser::{CompilationUnit, Module, Item, Expr, Literal};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use uuid::Uuid;
+use x_parser::persistent_ast::NodeId;
 
 /// AST Editor for direct manipulation of syntax trees
 #[derive(Debug)]
@@ -52,22 +52,39 @@ impl AstEditor {
         Ok(result)
     }
 
+    /// Compute the operation that reverts an applied edit
+    pub fn inverse_operation(&self, result: &EditResult) -> EditOperation {
+        match result {
+            EditResult::Inserted { path, .. } => EditOperation::delete(path.clone()),
+            EditResult::Deleted { path, removed_node } => {
+                EditOperation::insert(path.clone(), removed_node.clone())
+            }
+            EditResult::Replaced { path, old_node, .. } => {
+                EditOperation::replace(path.clone(), old_node.clone())
+            }
+            EditResult::Moved { source_path, dest_path } => {
+                EditOperation::move_node(dest_path.clone(), source_path.clone())
+            }
+        }
+    }
+
     /// Apply insert operation
     fn apply_insert(
         &mut self,
         ast: &mut CompilationUnit,
         operation: &InsertOperation,
     ) -> Result<EditResult, EditError> {
-        let target = self.navigate_to_path_mut(ast, &operation.path)?;
+        let path = target_path(&operation.target)?;
+        let target = self.navigate_to_path_mut(ast, path)?;
         
         match target {
             AstTarget::ModuleItems(items) => {
-                let index = operation.path.last().copied().unwrap_or(items.len());
+                let index = path.last().copied().unwrap_or(items.len());
                 if let EditableNode::Item(item) = operation.node.clone() {
                     items.insert(index, item);
                     Ok(EditResult::Inserted { 
-                        path: operation.path.clone(),
-                        node_id: self.generate_node_id(),
+                        path: path.to_vec(),
+                        node_id: None,
                     })
                 } else {
                     Err(EditError::InvalidNodeType {
@@ -77,12 +94,12 @@ impl AstEditor {
                 }
             }
             AstTarget::Expressions(expressions) => {
-                let index = operation.path.last().copied().unwrap_or(expressions.len());
+                let index = path.last().copied().unwrap_or(expressions.len());
                 if let EditableNode::Expr(expr) = operation.node.clone() {
                     expressions.insert(index, expr);
                     Ok(EditResult::Inserted { 
-                        path: operation.path.clone(),
-                        node_id: self.generate_node_id(),
+                        path: path.to_vec(),
+                        node_id: None,
                     })
                 } else {
                     Err(EditError::InvalidNodeType {
@@ -92,7 +109,7 @@ impl AstEditor {
                 }
             }
             _ => Err(EditError::InvalidInsertTarget {
-                path: operation.path.clone(),
+                path: path.to_vec(),
             }),
         }
     }
@@ -103,39 +120,40 @@ impl AstEditor {
         ast: &mut CompilationUnit,
         operation: &DeleteOperation,
     ) -> Result<EditResult, EditError> {
-        let target = self.navigate_to_path_mut(ast, &operation.path)?;
+        let path = target_path(&operation.target)?;
+        let target = self.navigate_to_path_mut(ast, path)?;
         
         match target {
             AstTarget::ModuleItems(items) => {
-                let index = operation.path.last().copied().unwrap_or(0);
+                let index = path.last().copied().unwrap_or(0);
                 if index < items.len() {
                     let removed = items.remove(index);
                     Ok(EditResult::Deleted { 
-                        path: operation.path.clone(),
+                        path: path.to_vec(),
                         removed_node: EditableNode::Item(removed),
                     })
                 } else {
                     Err(EditError::PathNotFound {
-                        path: operation.path.clone(),
+                        path: path.to_vec(),
                     })
                 }
             }
             AstTarget::Expressions(expressions) => {
-                let index = operation.path.last().copied().unwrap_or(0);
+                let index = path.last().copied().unwrap_or(0);
                 if index < expressions.len() {
                     let removed = expressions.remove(index);
                     Ok(EditResult::Deleted { 
-                        path: operation.path.clone(),
+                        path: path.to_vec(),
                         removed_node: EditableNode::Expr(removed),
                     })
                 } else {
                     Err(EditError::PathNotFound {
-                        path: operation.path.clone(),
+                        path: path.to_vec(),
                     })
                 }
             }
             _ => Err(EditError::InvalidDeleteTarget {
-                path: operation.path.clone(),
+                path: path.to_vec(),
             }),
         }
     }
@@ -146,16 +164,17 @@ impl AstEditor {
         ast: &mut CompilationUnit,
         operation: &ReplaceOperation,
     ) -> Result<EditResult, EditError> {
-        let target = self.navigate_to_path_mut(ast, &operation.path)?;
+        let path = target_path(&operation.target)?;
+        let target = self.navigate_to_path_mut(ast, path)?;
         
         match target {
             AstTarget::ModuleItems(items) => {
-                let index = operation.path.last().copied().unwrap_or(0);
+                let index = path.last().copied().unwrap_or(0);
                 if index < items.len() {
                     if let EditableNode::Item(new_item) = operation.new_node.clone() {
                         let old_item = std::mem::replace(&mut items[index], new_item);
                         Ok(EditResult::Replaced { 
-                            path: operation.path.clone(),
+                            path: path.to_vec(),
                             old_node: EditableNode::Item(old_item),
                             new_node: operation.new_node.clone(),
                         })
@@ -167,17 +186,17 @@ impl AstEditor {
                     }
                 } else {
                     Err(EditError::PathNotFound {
-                        path: operation.path.clone(),
+                        path: path.to_vec(),
                     })
                 }
             }
             AstTarget::Expressions(expressions) => {
-                let index = operation.path.last().copied().unwrap_or(0);
+                let index = path.last().copied().unwrap_or(0);
                 if index < expressions.len() {
                     if let EditableNode::Expr(new_expr) = operation.new_node.clone() {
                         let old_expr = std::mem::replace(&mut expressions[index], new_expr);
                         Ok(EditResult::Replaced { 
-                            path: operation.path.clone(),
+                            path: path.to_vec(),
                             old_node: EditableNode::Expr(old_expr),
                             new_node: operation.new_node.clone(),
                         })
@@ -189,12 +208,12 @@ impl AstEditor {
                     }
                 } else {
                     Err(EditError::PathNotFound {
-                        path: operation.path.clone(),
+                        path: path.to_vec(),
                     })
                 }
             }
             _ => Err(EditError::InvalidReplaceTarget {
-                path: operation.path.clone(),
+                path: path.to_vec(),
             }),
         }
     }
@@ -205,39 +224,42 @@ impl AstEditor {
         ast: &mut CompilationUnit,
         operation: &MoveOperation,
     ) -> Result<EditResult, EditError> {
+        let source_path = target_path(&operation.source)?;
+        let dest_path = target_path(&operation.dest)?;
+
         // First, extract the node from source
-        let source_target = self.navigate_to_path_mut(ast, &operation.source_path)?;
+        let source_target = self.navigate_to_path_mut(ast, source_path)?;
         let node_to_move = match source_target {
             AstTarget::ModuleItems(items) => {
-                let index = operation.source_path.last().copied().unwrap_or(0);
+                let index = source_path.last().copied().unwrap_or(0);
                 if index < items.len() {
                     EditableNode::Item(items.remove(index))
                 } else {
                     return Err(EditError::PathNotFound {
-                        path: operation.source_path.clone(),
+                        path: source_path.to_vec(),
                     });
                 }
             }
             AstTarget::Expressions(expressions) => {
-                let index = operation.source_path.last().copied().unwrap_or(0);
+                let index = source_path.last().copied().unwrap_or(0);
                 if index < expressions.len() {
                     EditableNode::Expr(expressions.remove(index))
                 } else {
                     return Err(EditError::PathNotFound {
-                        path: operation.source_path.clone(),
+                        path: source_path.to_vec(),
                     });
                 }
             }
             _ => return Err(EditError::InvalidMoveSource {
-                path: operation.source_path.clone(),
+                path: source_path.to_vec(),
             }),
         };
 
         // Then, insert at destination
-        let dest_target = self.navigate_to_path_mut(ast, &operation.dest_path)?;
+        let dest_target = self.navigate_to_path_mut(ast, dest_path)?;
         match dest_target {
             AstTarget::ModuleItems(items) => {
-                let index = operation.dest_path.last().copied().unwrap_or(items.len());
+                let index = dest_path.last().copied().unwrap_or(items.len());
                 if let EditableNode::Item(item) = node_to_move {
                     items.insert(index, item);
                 } else {
@@ -248,7 +270,7 @@ impl AstEditor {
                 }
             }
             AstTarget::Expressions(expressions) => {
-                let index = operation.dest_path.last().copied().unwrap_or(expressions.len());
+                let index = dest_path.last().copied().unwrap_or(expressions.len());
                 if let EditableNode::Expr(expr) = node_to_move {
                     expressions.insert(index, expr);
                 } else {
@@ -259,13 +281,13 @@ impl AstEditor {
                 }
             }
             _ => return Err(EditError::InvalidMoveDestination {
-                path: operation.dest_path.clone(),
+                path: dest_path.to_vec(),
             }),
         }
 
         Ok(EditResult::Moved {
-            source_path: operation.source_path.clone(),
-            dest_path: operation.dest_path.clone(),
+            source_path: source_path.to_vec(),
+            dest_path: dest_path.to_vec(),
         })
     }
 
@@ -313,7 +335,7 @@ impl AstEditor {
 
         // Always allow delete if the node exists
         operations.push(EditOperation::Delete(DeleteOperation {
-            path: path.to_vec(),
+            target: NodeRef::Path(path.to_vec()),
         }));
 
         // Add replace operations based on node type
@@ -321,14 +343,14 @@ impl AstEditor {
             AstTarget::ModuleItems(_) => {
                 // Can replace with any item
                 operations.push(EditOperation::Replace(ReplaceOperation {
-                    path: path.to_vec(),
+                    target: NodeRef::Path(path.to_vec()),
                     new_node: EditableNode::Item(self.create_placeholder_item()),
                 }));
             }
             AstTarget::Expressions(_) => {
                 // Can replace with any expression
                 operations.push(EditOperation::Replace(ReplaceOperation {
-                    path: path.to_vec(),
+                    target: NodeRef::Path(path.to_vec()),
                     new_node: EditableNode::Expr(self.create_placeholder_expression()),
                 }));
             }
@@ -374,12 +396,10 @@ impl AstEditor {
             return Ok(AstTarget::CompilationUnit(ast));
         }
 
-        // For mutable access, we need to handle this differently
-        if path.len() == 1 {
-            let index = path[0];
-            if index == 0 {
-                return Ok(AstTarget::ModuleItems(&mut ast.module.items));
-            }
+        // For mutable access, we need to handle this differently; `[0, i]`
+        // addresses module item `i`, with the index taken from the path's end
+        if path[0] == 0 && path.len() <= 2 {
+            return Ok(AstTarget::ModuleItems(&mut ast.module.items));
         }
 
         // For now, return an error for complex paths
@@ -439,11 +459,6 @@ impl AstEditor {
         Ok(())
     }
 
-    /// Generate a unique node ID
-    fn generate_node_id(&self) -> String {
-        Uuid::new_v4().to_string()
-    }
-
     /// Create a placeholder item for template operations
     fn create_placeholder_item(&self) -> Item {
         Item::ValueDef(x_parser::ValueDef {
@@ -451,17 +466,26 @@ impl AstEditor {
             documentation: None,
             type_annotation: None,
             parameters: vec![],
-            body: Expr::Literal(Literal::Integer(0), x_parser::Span::single(x_parser::FileId::new(0), x_parser::span::ByteOffset::new(0))),
+            body: Expr::Literal(Literal::int(0), x_parser::Span::single(x_parser::FileId::new(0), x_parser::span::CharOffset::new(0))),
             visibility: x_parser::Visibility::Private,
             purity: x_parser::Purity::Pure,
             imports: Vec::new(),
-            span: x_parser::Span::single(x_parser::FileId::new(0), x_parser::span::ByteOffset::new(0)),
+            span: x_parser::Span::single(x_parser::FileId::new(0), x_parser::span::CharOffset::new(0)),
         })
     }
 
     /// Create a placeholder expression for template operations
     fn create_placeholder_expression(&self) -> Expr {
-        Expr::Literal(Literal::Integer(0), x_parser::Span::single(x_parser::FileId::new(0), x_parser::span::ByteOffset::new(0)))
+        Expr::Literal(Literal::int(0), x_parser::Span::single(x_parser::FileId::new(0), x_parser::span::CharOffset::new(0)))
+    }
+}
+
+/// The path of an operation's target; ids only mean something to the
+/// session that assigned them and must be resolved there first
+fn target_path(target: &NodeRef) -> Result<&[usize], EditError> {
+    match target {
+        NodeRef::Path(path) => Ok(path),
+        NodeRef::Id(node_id) => Err(EditError::NodeNotFound { node_id: *node_id }),
     }
 }
 
@@ -488,7 +512,8 @@ enum AstTarget<'a> {
 pub enum EditResult {
     Inserted {
         path: Vec<usize>,
-        node_id: String,
+        /// Id of the new node, when the edit went through a session
+        node_id: Option<NodeId>,
     },
     Deleted {
         path: Vec<usize>,
@@ -511,6 +536,9 @@ pub enum EditError {
     #[error("Path not found: {path:?}")]
     PathNotFound { path: Vec<usize> },
 
+    #[error("No node with id {node_id:?}")]
+    NodeNotFound { node_id: NodeId },
+
     #[error("Invalid node type: expected {expected}, found {found}")]
     InvalidNodeType { expected: String, found: String },
 
@@ -540,6 +568,36 @@ pub enum EditError {
 
     #[error("Validation error: {message}")]
     Validation { message: String },
+
+    #[error("No renameable symbol at offset {offset}")]
+    NoSymbolAtPosition { offset: u32 },
+
+    #[error("Invalid identifier: {name}")]
+    InvalidIdentifier { name: String },
+
+    #[error("Renaming to '{name}' would change what existing references resolve to")]
+    RenameConflict { name: String },
+
+    #[error("Operation {index} in the session history has no recorded inverse")]
+    Irreversible { index: usize },
+
+    #[error("Checkpoint {position} is no longer in the session history")]
+    InvalidCheckpoint { position: usize },
+
+    #[error(transparent)]
+    Query(#[from] crate::query_language::QueryParseError),
+
+    #[error("Batch rolled back: {0}")]
+    BatchFailed(Box<crate::batch::BatchFailure>),
+
+    #[error("'{name}' is already defined")]
+    NameInUse { name: String },
+
+    #[error("No expression within selection {start}..{end}")]
+    NoExpressionInSelection { start: u32, end: u32 },
+
+    #[error("Invalid rewrite rule: {message}")]
+    InvalidRewriteRule { message: String },
 }
 
 #[cfg(test)]
@@ -560,17 +618,17 @@ mod tests {
         let mut ast = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
 
         let operation = EditOperation::Insert(InsertOperation {
-            path: vec![0],
+            target: NodeRef::Path(vec![0]),
             node: EditableNode::Item(Item::ValueDef(x_parser::ValueDef {
                 name: x_parser::Symbol::intern("y"),
                 documentation: None,
                 type_annotation: None,
                 parameters: vec![],
-                body: Expr::Literal(Literal::Bool(true), x_parser::Span::single(x_parser::FileId::new(0), x_parser::span::ByteOffset::new(0))),
+                body: Expr::Literal(Literal::Bool(true), x_parser::Span::single(x_parser::FileId::new(0), x_parser::span::CharOffset::new(0))),
                 visibility: x_parser::Visibility::Private,
                 purity: x_parser::Purity::Pure,
                 imports: Vec::new(),
-                span: x_parser::Span::single(x_parser::FileId::new(0), x_parser::span::ByteOffset::new(0)),
+                span: x_parser::Span::single(x_parser::FileId::new(0), x_parser::span::CharOffset::new(0)),
             })),
         });
 
@@ -0,0 +1,54 @@
+//! Atomic application of several edit operations
+//!
+//! A batch is applied to a session one operation at a time. After each step
+//! the tree is validated and type checked; the first operation that fails,
+//! or that introduces new errors, aborts the batch and the session is rolled
+//! back to where it was before the batch started.
+
+use crate::operations::EditOperation;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Stage at which a batched operation was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BatchStage {
+    /// The operation itself could not be applied
+    Apply,
+    /// The resulting tree failed structural validation
+    Validation,
+    /// The resulting tree introduced new type errors
+    TypeCheck,
+}
+
+impl fmt::Display for BatchStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BatchStage::Apply => write!(f, "apply"),
+            BatchStage::Validation => write!(f, "validation"),
+            BatchStage::TypeCheck => write!(f, "type checking"),
+        }
+    }
+}
+
+/// Report describing why a batch was rolled back
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchFailure {
+    /// Position of the failing operation within the batch
+    pub index: usize,
+    /// The failing operation
+    pub operation: EditOperation,
+    /// Where the operation was rejected
+    pub stage: BatchStage,
+    /// Errors reported for the operation
+    pub messages: Vec<String>,
+}
+
+impl fmt::Display for BatchFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "operation {} failed during {}", self.index, self.stage)?;
+        if !self.messages.is_empty() {
+            write!(f, ": {}", self.messages.join("; "))?;
+        }
+        Ok(())
+    }
+}
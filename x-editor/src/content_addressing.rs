@@ -28,6 +28,11 @@ impl ContentHash {
     pub fn short(&self) -> &str {
         &self.0[..8]
     }
+
+    /// Whether this is a SHA-256 digest in lowercase hex, as `new` makes
+    pub fn is_valid(&self) -> bool {
+        self.0.len() == 64 && self.0.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+    }
 }
 
 /// Semantic fingerprint for similarity matching
@@ -124,6 +129,21 @@ pub struct ContentEntry {
     pub content: ContentItem,
 }
 
+impl ContentEntry {
+    /// Hash of the entry's content, computed as the repository computes it
+    /// when the entry is added
+    pub fn compute_hash(&self) -> Result<ContentHash> {
+        let data = match &self.content {
+            ContentItem::Function { annotated_ast: Some(annotated), .. } => serde_json::to_vec(annotated)?,
+            ContentItem::Function { ast, .. } => format!("{ast:?}").into_bytes(),
+            ContentItem::Type { ast, .. } => format!("{ast:?}").into_bytes(),
+            ContentItem::Effect { ast, .. } => format!("{ast:?}").into_bytes(),
+            ContentItem::AnnotatedModule { ast } => serde_json::to_vec(ast)?,
+        };
+        Ok(ContentHash::new(&data))
+    }
+}
+
 /// Actual content item
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ContentItem {
@@ -522,6 +542,9 @@ impl ContentRepository {
             Expr::Lambda { parameters, body, .. } => {
                 format!("λ{}.{}", parameters.len(), self.normalize_expr(body))
             }
+            // Normalized by value, not by how the number was written
+            Expr::Literal(Literal::Integer(n, _), _) => format!("Integer({n})"),
+            Expr::Literal(Literal::Float(f, _), _) => format!("Float({f:?})"),
             Expr::Literal(lit, _) => format!("{lit:?}"),
             _ => "expr".to_string(),
         }
@@ -824,7 +847,7 @@ pub struct SimilarityDetails {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use x_parser::{FileId, Span, span::ByteOffset};
+    use x_parser::{FileId, Span, span::CharOffset};
     
     #[test]
     fn test_content_hash() {
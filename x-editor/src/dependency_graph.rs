@@ -0,0 +1,248 @@
+//! Module and package dependency graphs
+//!
+//! A [`DependencyGraph`] holds two layers: packages with the version
+//! requirements they place on each other, and modules with the imports they
+//! make, each module tagged with the package providing it. Tools walk the
+//! module layer to show what a program pulls in and the package layer to find
+//! dependencies that have newer versions available.
+
+use std::collections::{BTreeMap, HashSet};
+use x_parser::versioning::{Version, VersionSpec};
+use x_parser::{CompilationUnit, ImportKind};
+
+/// A package and the requirements it places on its direct dependencies
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackageNode {
+    pub name: String,
+    pub version: Version,
+    pub dependencies: BTreeMap<String, Option<String>>,
+}
+
+/// A module and the modules it imports
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModuleNode {
+    pub name: String,
+    /// Package providing the module, if known
+    pub package: Option<String>,
+    pub imports: Vec<ImportEdge>,
+}
+
+/// One import, with the version requirement written in it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportEdge {
+    pub module: String,
+    pub requirement: Option<String>,
+}
+
+impl ModuleNode {
+    /// The module defined by `unit`
+    pub fn from_unit(unit: &CompilationUnit, package: Option<String>) -> Self {
+        let imports = unit.module.imports.iter()
+            .map(|import| {
+                let item_requirement = match &import.kind {
+                    ImportKind::Selective(items) => items.iter().find_map(|item| item.version_spec.clone()),
+                    _ => None,
+                };
+                ImportEdge {
+                    module: import.module_path.to_string(),
+                    requirement: import.version_spec.clone().or(item_requirement),
+                }
+            })
+            .collect();
+        Self { name: unit.module.name.to_string(), package, imports }
+    }
+}
+
+/// The transitive imports of a module
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportTree {
+    pub module: String,
+    /// Requirement of the import leading here; `None` at the root
+    pub requirement: Option<String>,
+    pub package: Option<String>,
+    pub version: Option<Version>,
+    pub children: Vec<ImportTree>,
+    /// Already shown earlier in the tree, so not expanded again
+    pub repeated: bool,
+}
+
+/// A dependency with a newer version available
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutdatedPackage {
+    pub name: String,
+    /// Package requiring it
+    pub dependent: String,
+    pub requirement: Option<String>,
+    pub current: Version,
+    /// Newest available version the requirement accepts, if newer than the current one
+    pub compatible: Option<Version>,
+    /// Newest available version
+    pub latest: Version,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DependencyGraph {
+    packages: BTreeMap<String, PackageNode>,
+    modules: BTreeMap<String, ModuleNode>,
+}
+
+impl DependencyGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_package(&mut self, package: PackageNode) {
+        self.packages.insert(package.name.clone(), package);
+    }
+
+    pub fn add_module(&mut self, module: ModuleNode) {
+        self.modules.insert(module.name.clone(), module);
+    }
+
+    pub fn package(&self, name: &str) -> Option<&PackageNode> {
+        self.packages.get(name)
+    }
+
+    pub fn module(&self, name: &str) -> Option<&ModuleNode> {
+        self.modules.get(name)
+    }
+
+    pub fn packages(&self) -> impl Iterator<Item = &PackageNode> {
+        self.packages.values()
+    }
+
+    pub fn modules(&self) -> impl Iterator<Item = &ModuleNode> {
+        self.modules.values()
+    }
+
+    /// Imports of `root`, expanding every module once; modules unknown to the
+    /// graph appear as leaves
+    pub fn import_tree(&self, root: &str) -> ImportTree {
+        let mut expanded = HashSet::new();
+        self.subtree(root, None, &mut expanded)
+    }
+
+    fn subtree(&self, module: &str, requirement: Option<String>, expanded: &mut HashSet<String>) -> ImportTree {
+        let node = self.modules.get(module);
+        let package = node.and_then(|node| node.package.clone());
+        let version = package.as_deref()
+            .and_then(|package| self.packages.get(package))
+            .map(|package| package.version.clone());
+        let repeated = !expanded.insert(module.to_string());
+        let children = match node {
+            Some(node) if !repeated => node.imports.iter()
+                .map(|import| self.subtree(&import.module, import.requirement.clone(), expanded))
+                .collect(),
+            _ => Vec::new(),
+        };
+        ImportTree { module: module.to_string(), requirement, package, version, children, repeated }
+    }
+
+    /// Dependencies of `root` and of the packages it depends on, directly or
+    /// not, with a newer version among `available`
+    pub fn outdated(&self, root: &str, available: &BTreeMap<String, Vec<Version>>) -> Vec<OutdatedPackage> {
+        let mut outdated = Vec::new();
+        let mut visited = HashSet::new();
+        let mut pending = vec![root];
+        while let Some(name) = pending.pop() {
+            let Some(package) = self.packages.get(name) else { continue };
+            if !visited.insert(name) {
+                continue;
+            }
+            for (dependency, requirement) in &package.dependencies {
+                pending.push(dependency);
+                let Some(current) = self.packages.get(dependency).map(|node| &node.version) else {
+                    continue;
+                };
+                let versions = available.get(dependency).map(Vec::as_slice).unwrap_or_default();
+                let Some(latest) = versions.iter().max().filter(|latest| *latest > current) else {
+                    continue;
+                };
+                let spec = requirement.as_deref().and_then(VersionSpec::parse);
+                let compatible = versions.iter()
+                    .filter(|version| *version > current)
+                    .filter(|version| spec.as_ref().is_none_or(|spec| spec.matches(version)))
+                    .max()
+                    .cloned();
+                outdated.push(OutdatedPackage {
+                    name: dependency.clone(),
+                    dependent: name.to_string(),
+                    requirement: requirement.clone(),
+                    current: current.clone(),
+                    compatible,
+                    latest: latest.clone(),
+                });
+            }
+        }
+        outdated.sort_by(|a, b| (&a.name, &a.dependent).cmp(&(&b.name, &b.dependent)));
+        outdated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x_parser::{parse_source, FileId, SyntaxStyle};
+
+    fn package(name: &str, version: &str, dependencies: &[(&str, &str)]) -> PackageNode {
+        PackageNode {
+            name: name.to_string(),
+            version: Version::parse(version).unwrap(),
+            dependencies: dependencies.iter()
+                .map(|(name, requirement)| (name.to_string(), Some(requirement.to_string())))
+                .collect(),
+        }
+    }
+
+    fn module(source: &str, package: &str) -> ModuleNode {
+        let unit = parse_source(source, FileId::new(0), SyntaxStyle::default()).unwrap();
+        ModuleNode::from_unit(&unit, Some(package.to_string()))
+    }
+
+    #[test]
+    fn test_import_tree() {
+        let mut graph = DependencyGraph::new();
+        graph.add_package(package("app", "0.1.0", &[("utils", "^1.0")]));
+        graph.add_package(package("utils", "1.2.0", &[]));
+        graph.add_module(module("module Main\nimport Utils@^1.1.0\nimport Text\nimport Core.List\nlet main = 1", "app"));
+        graph.add_module(module("module Utils\nimport Text\npub let x = 1", "utils"));
+        graph.add_module(module("module Text\npub let y = 1", "utils"));
+
+        let tree = graph.import_tree("Main");
+        let utils = &tree.children[0];
+        assert_eq!((utils.module.as_str(), utils.requirement.as_deref()), ("Utils", Some("^1.1.0")));
+        assert_eq!(utils.version, Version::parse("1.2.0"));
+        assert_eq!(utils.children[0].module, "Text");
+        assert!(!utils.children[0].repeated);
+
+        // Shown under Utils already
+        assert!(tree.children[1].repeated);
+        let list = &tree.children[2];
+        assert_eq!((list.package.as_deref(), list.children.len(), list.repeated), (None, 0, false));
+    }
+
+    #[test]
+    fn test_outdated_packages() {
+        let mut graph = DependencyGraph::new();
+        graph.add_package(package("app", "0.1.0", &[("utils", "^1.0"), ("text", "^0.2")]));
+        graph.add_package(package("utils", "1.2.0", &[("base", "=0.1.0")]));
+        graph.add_package(package("text", "0.2.0", &[]));
+        graph.add_package(package("base", "0.1.0", &[]));
+
+        let versions = |versions: &[&str]| versions.iter().map(|v| Version::parse(v).unwrap()).collect();
+        let available = BTreeMap::from([
+            ("utils".to_string(), versions(&["1.2.0", "1.4.1", "2.0.0"])),
+            ("text".to_string(), versions(&["0.1.0", "0.2.0"])),
+            ("base".to_string(), versions(&["0.2.0"])),
+        ]);
+
+        let outdated = graph.outdated("app", &available);
+        let summary: Vec<_> = outdated.iter()
+            .map(|p| (p.name.as_str(), p.dependent.as_str(), p.compatible.as_ref().map(Version::to_string), p.latest.to_string()))
+            .collect();
+        assert_eq!(summary, [
+            ("base", "utils", None, "0.2.0".to_string()),
+            ("utils", "app", Some("1.4.1".to_string()), "2.0.0".to_string()),
+        ]);
+    }
+}
@@ -0,0 +1,145 @@
+//! Dependency-aware definition hashes
+//!
+//! Every value, test and handler definition in a module gets a body hash,
+//! which ignores spans and layout, and a closure hash that also covers the
+//! body hash of every definition it transitively refers to. A cached result
+//! keyed by the closure hash stays valid until the definition or something it
+//! depends on changes.
+
+use crate::content_addressing::ContentHash;
+use std::collections::{BTreeMap, HashMap};
+use x_parser::content_hash::{hash_expr, hash_value_def};
+use x_parser::dependency::DependencyManager;
+use x_parser::{CompilationUnit, Expr, Item, Symbol};
+
+/// Body and closure hashes for the definitions of one module
+#[derive(Debug, Clone, Default)]
+pub struct DefinitionHashes {
+    bodies: HashMap<Symbol, ContentHash>,
+    dependencies: HashMap<Symbol, BTreeMap<String, ContentHash>>,
+}
+
+impl DefinitionHashes {
+    /// Hash every definition in the module
+    pub fn compute(cu: &CompilationUnit) -> Self {
+        let mut bodies = HashMap::new();
+        let mut manager = DependencyManager::new();
+
+        for item in &cu.module.items {
+            let (name, body, deps) = match item {
+                Item::ValueDef(def) => (
+                    def.name,
+                    ContentHash(hash_value_def(def)),
+                    DependencyManager::extract_dependencies_from_def(def),
+                ),
+                Item::TestDef(test) => {
+                    let exprs: Vec<&Expr> = test.setup.iter().map(|e| &**e)
+                        .chain(std::iter::once(&test.body))
+                        .chain(test.teardown.iter().map(|e| &**e))
+                        .collect();
+                    (test.name, combined_hash(&exprs), dependencies_of(&exprs))
+                }
+                Item::BenchDef(bench) => {
+                    let exprs = [&bench.body];
+                    (bench.name, combined_hash(&exprs), dependencies_of(&exprs))
+                }
+                Item::HandlerDef(handler) => {
+                    let exprs: Vec<&Expr> = handler.handlers.iter().map(|op| &op.body)
+                        .chain(handler.return_clause.iter().map(|ret| &*ret.body))
+                        .collect();
+                    (handler.name, combined_hash(&exprs), dependencies_of(&exprs))
+                }
+                _ => continue,
+            };
+            bodies.insert(name, body);
+            manager.add_definition(name, deps);
+        }
+        manager.compute_transitive_deps();
+
+        // Only names defined in this module take part; builtins, effects and
+        // imports are outside the closure
+        let dependencies = bodies.keys()
+            .map(|name| {
+                let deps = manager.get_all_dependencies(name)
+                    .into_iter()
+                    .flatten()
+                    .filter(|dep| *dep != name)
+                    .filter_map(|dep| Some((dep.as_str().to_string(), bodies.get(dep)?.clone())))
+                    .collect();
+                (*name, deps)
+            })
+            .collect();
+
+        Self { bodies, dependencies }
+    }
+
+    /// Hash of the definition itself
+    pub fn body_hash(&self, name: &Symbol) -> Option<&ContentHash> {
+        self.bodies.get(name)
+    }
+
+    /// Body hashes of every definition `name` transitively refers to
+    pub fn dependencies(&self, name: &Symbol) -> HashMap<String, ContentHash> {
+        self.dependencies.get(name)
+            .map(|deps| deps.iter().map(|(dep, hash)| (dep.clone(), hash.clone())).collect())
+            .unwrap_or_default()
+    }
+
+    /// Hash of the definition together with its dependency closure
+    pub fn closure_hash(&self, name: &Symbol) -> Option<ContentHash> {
+        let body = self.bodies.get(name)?;
+        let mut data = body.0.clone();
+        for (dep, hash) in self.dependencies.get(name).into_iter().flatten() {
+            data.push('\0');
+            data.push_str(dep);
+            data.push('=');
+            data.push_str(&hash.0);
+        }
+        Some(ContentHash::new(data.as_bytes()))
+    }
+}
+
+fn combined_hash(exprs: &[&Expr]) -> ContentHash {
+    let hashes: Vec<String> = exprs.iter().map(|expr| hash_expr(expr)).collect();
+    ContentHash::new(hashes.join("\0").as_bytes())
+}
+
+fn dependencies_of(exprs: &[&Expr]) -> std::collections::HashSet<Symbol> {
+    exprs.iter().flat_map(|expr| DependencyManager::extract_dependencies(expr)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x_parser::{parse_source, FileId, SyntaxStyle};
+
+    fn hashes(source: &str) -> DefinitionHashes {
+        let cu = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        DefinitionHashes::compute(&cu)
+    }
+
+    #[test]
+    fn test_closure_hash_tracks_transitive_dependencies() {
+        let name = Symbol::intern("test_double");
+        let before = hashes("module Test\nlet base = 1\nlet double = fun x -> base\nlet test_double = double 2");
+        let after = hashes("module Test\nlet base = 2\nlet double = fun x -> base\nlet test_double = double 2");
+
+        assert_eq!(before.body_hash(&name), after.body_hash(&name));
+        assert_ne!(before.closure_hash(&name), after.closure_hash(&name));
+
+        let deps = before.dependencies(&name);
+        assert_eq!(deps.len(), 2);
+        assert_ne!(deps["base"], after.dependencies(&name)["base"]);
+    }
+
+    #[test]
+    fn test_closure_hash_ignores_layout_and_unrelated_changes() {
+        let name = Symbol::intern("test_one");
+        let before = hashes("module Test\nlet one = 1\nlet other = 1\nlet test_one = one");
+        let after = hashes("module Test\n\nlet one   = 1\nlet other = 2\nlet test_one =\n  one");
+
+        assert_eq!(before.closure_hash(&name), after.closure_hash(&name));
+        assert!(before.dependencies(&name).contains_key("one"));
+        assert!(!before.dependencies(&name).contains_key("other"));
+    }
+}
@@ -0,0 +1,316 @@
+//! Extract-method refactoring
+//!
+//! The outermost expression inside a selection is lifted into a new top-level
+//! function. Locally bound names the expression refers to become the
+//! function's parameters, the effect row is taken from the type checker, and
+//! the selection is replaced by a call. The tree is updated through ordinary
+//! `AstEditor` operations so the change is recorded like any other edit.
+
+use crate::ast_editor::{AstEditor, EditError};
+use crate::operations::{EditOperation, EditableNode, InsertOperation, NodeRef, ReplaceOperation};
+use crate::rename::{is_valid_identifier, BindingKind, SymbolIndex};
+use crate::semantic_tokens::function_effects;
+use x_checker::EffectSet;
+use x_parser::span::CharOffset;
+use x_parser::{
+    CompilationUnit, DoStatement, Expr, Item, Literal, Pattern, Purity, Span, Symbol, ValueDef,
+    Visibility,
+};
+
+/// Outcome of a successful extraction
+#[derive(Debug, Clone)]
+pub struct Extraction {
+    /// Name of the new function
+    pub name: Symbol,
+    /// Free variables of the extracted expression, in order of first use
+    pub parameters: Vec<Symbol>,
+    /// Effects the new function performs, as inferred by the checker
+    pub effects: Vec<Symbol>,
+    /// Source span of the extracted expression
+    pub extracted: Span,
+    /// Start of the definition the expression was taken from; the new
+    /// function is inserted right before it
+    pub insert_at: CharOffset,
+}
+
+impl Extraction {
+    /// Mirror the extraction on the original source text
+    pub fn apply_to_source(&self, source: &str) -> String {
+        let chars: Vec<char> = source.chars().collect();
+        let (start, end) = (self.extracted.start.as_u32() as usize, self.extracted.end.as_u32() as usize);
+        let insert_at = self.insert_at.as_u32() as usize;
+        let body: String = chars[start..end].iter().collect();
+
+        let mut lambda = String::new();
+        if self.parameters.is_empty() {
+            lambda.push_str("fun _ -> ");
+        }
+        for param in &self.parameters {
+            lambda.push_str(&format!("fun {param} -> "));
+        }
+        let definition = format!("let {} = {}{}\n", self.name, lambda, body);
+
+        let args: Vec<String> = if self.parameters.is_empty() {
+            vec!["()".to_string()]
+        } else {
+            self.parameters.iter().map(|param| param.to_string()).collect()
+        };
+        let call = format!("({} {})", self.name, args.join(" "));
+
+        let mut result: String = chars[..insert_at].iter().collect();
+        result.push_str(&definition);
+        result.extend(&chars[insert_at..start]);
+        result.push_str(&call);
+        result.extend(&chars[end..]);
+        result
+    }
+}
+
+/// Extract the outermost expression inside `selection` into a function named `name`
+pub fn extract_function(
+    editor: &mut AstEditor,
+    ast: &mut CompilationUnit,
+    source: &str,
+    selection: Span,
+    name: &str,
+) -> Result<Extraction, EditError> {
+    if !is_valid_identifier(name) {
+        return Err(EditError::InvalidIdentifier { name: name.to_string() });
+    }
+    let name = Symbol::intern(name);
+    let taken = ast.module.items.iter()
+        .any(|item| matches!(item, Item::ValueDef(def) if def.name == name));
+    if taken {
+        return Err(EditError::NameInUse { name: name.to_string() });
+    }
+
+    let no_expression = || EditError::NoExpressionInSelection {
+        start: selection.start.as_u32(),
+        end: selection.end.as_u32(),
+    };
+    if selection.is_empty() {
+        return Err(no_expression());
+    }
+
+    // Cut the expression out of a copy of its definition, leaving a placeholder
+    // that is filled in once the parameters are known
+    let placeholder = Expr::Literal(Literal::Unit, selection);
+    let (item_index, mut item, expr) = ast.module.items.iter()
+        .enumerate()
+        .find_map(|(index, item)| {
+            let Item::ValueDef(def) = item else {
+                return None;
+            };
+            let mut def = def.clone();
+            let expr = take_selected(&mut def.body, selection, &placeholder)?;
+            Some((index, def, expr))
+        })
+        .ok_or_else(no_expression)?;
+    let extracted = expr.span();
+
+    let parameters = free_variables(&SymbolIndex::build(ast, source), extracted);
+    let call = Expr::App(
+        Box::new(Expr::Var(name, extracted)),
+        if parameters.is_empty() {
+            vec![Expr::Literal(Literal::Unit, extracted)]
+        } else {
+            parameters.iter().map(|param| Expr::Var(*param, extracted)).collect()
+        },
+        extracted,
+    );
+    take_selected(&mut item.body, selection, &call);
+
+    let mut function = ValueDef {
+        name,
+        documentation: None,
+        type_annotation: None,
+        parameters: Vec::new(),
+        body: curried_lambda(&parameters, expr, extracted),
+        visibility: Visibility::Private,
+        purity: Purity::Inferred,
+        imports: Vec::new(),
+        span: extracted,
+    };
+
+    // Infer the effect row on a scratch copy before touching the real tree
+    let mut scratch = ast.clone();
+    scratch.module.items[item_index] = Item::ValueDef(item.clone());
+    scratch.module.items.insert(item_index, Item::ValueDef(function.clone()));
+    let check = x_checker::type_check(&scratch);
+    let effects = check.type_env.vars.get(&name)
+        .and_then(|scheme| function_effects(&scheme.body))
+        .map(effect_names);
+    if let Some(effects) = &effects {
+        function.purity = if effects.is_empty() { Purity::Pure } else { Purity::Impure };
+    }
+
+    let insert_at = item.span.start;
+    editor.apply_operation(ast, EditOperation::Replace(ReplaceOperation {
+        target: NodeRef::Path(vec![0, item_index]),
+        new_node: EditableNode::Item(Item::ValueDef(item)),
+    }))?;
+    editor.apply_operation(ast, EditOperation::Insert(InsertOperation {
+        target: NodeRef::Path(vec![0, item_index]),
+        node: EditableNode::Item(Item::ValueDef(function)),
+    }))?;
+
+    Ok(Extraction {
+        name,
+        parameters,
+        effects: effects.unwrap_or_default(),
+        extracted,
+        insert_at,
+    })
+}
+
+/// Locally bound names referenced inside `span` but bound outside of it
+fn free_variables(index: &SymbolIndex, span: Span) -> Vec<Symbol> {
+    let mut free = Vec::new();
+    for occ in index.occurrences() {
+        if occ.is_definition || !span.contains_position(occ.span) || free.contains(&occ.name) {
+            continue;
+        }
+        if !matches!(index.binding_kind(occ), Some(BindingKind::Parameter | BindingKind::Local)) {
+            continue;
+        }
+        let bound_inside = index.related(occ).iter()
+            .any(|def| def.is_definition && span.contains_position(def.span));
+        if !bound_inside {
+            free.push(occ.name);
+        }
+    }
+    free
+}
+
+/// `fun a -> fun b -> body`, or `fun _ -> body` without parameters
+fn curried_lambda(parameters: &[Symbol], body: Expr, span: Span) -> Expr {
+    if parameters.is_empty() {
+        return Expr::Lambda {
+            parameters: vec![Pattern::Wildcard(span)],
+            body: Box::new(body),
+            span,
+        };
+    }
+
+    parameters.iter().rev().fold(body, |body, param| Expr::Lambda {
+        parameters: vec![Pattern::Variable(*param, span)],
+        body: Box::new(body),
+        span,
+    })
+}
+
+/// Names of the concrete effects in a row
+fn effect_names(effects: &EffectSet) -> Vec<Symbol> {
+    match effects {
+        EffectSet::Empty | EffectSet::Var(_) => Vec::new(),
+        EffectSet::Row { effects, tail } => {
+            let mut names: Vec<Symbol> = effects.iter().map(|effect| effect.name).collect();
+            if let Some(tail) = tail {
+                names.extend(effect_names(tail));
+            }
+            names
+        }
+    }
+}
+
+/// Replace the outermost expression lying inside `selection` with
+/// `replacement`, returning the expression that was removed
+fn take_selected(expr: &mut Expr, selection: Span, replacement: &Expr) -> Option<Expr> {
+    if selection.contains_position(expr.span()) {
+        return Some(std::mem::replace(expr, replacement.clone()));
+    }
+
+    let take = |expr: &mut Expr| take_selected(expr, selection, replacement);
+    match expr {
+        Expr::Literal(..) | Expr::Var(..) | Expr::Pack { .. } | Expr::Error(_) => None,
+        Expr::App(func, args, _) => take(func).or_else(|| args.iter_mut().find_map(take)),
+        Expr::Lambda { body, .. } => take(body),
+        Expr::Let { value, body, .. } => take(value).or_else(|| take(body)),
+        Expr::If { condition, then_branch, else_branch, .. } => {
+            take(condition).or_else(|| take(then_branch)).or_else(|| take(else_branch))
+        }
+        Expr::Match { scrutinee, arms, .. } => take(scrutinee).or_else(|| {
+            arms.iter_mut().find_map(|arm| {
+                arm.guard.as_mut().and_then(|guard| take(guard)).or_else(|| take(&mut arm.body))
+            })
+        }),
+        Expr::Do { statements, .. } => statements.iter_mut().find_map(|statement| match statement {
+            DoStatement::Let { expr, .. } | DoStatement::Bind { expr, .. } | DoStatement::Expr(expr) => take(expr),
+        }),
+        Expr::Handle { expr, handlers, return_clause, .. } => take(expr)
+            .or_else(|| handlers.iter_mut().find_map(|handler| take(&mut handler.body)))
+            .or_else(|| return_clause.as_mut().and_then(|ret| take(&mut ret.body))),
+        Expr::Resume { value, .. } => take(value),
+        Expr::Perform { args, .. } => args.iter_mut().find_map(take),
+        Expr::Ann { expr, .. } | Expr::Project { record: expr, .. } => take(expr),
+        Expr::Record { fields, .. } => fields.iter_mut().find_map(|(_, value)| take(value)),
+        Expr::Tuple { elements, .. } => elements.iter_mut().find_map(take),
+        Expr::Unpack { value, body, .. } => take(value).or_else(|| take(body)),
+        Expr::RecordUpdate { record, fields, .. } => {
+            take(record).or_else(|| fields.iter_mut().find_map(|(_, value)| take(value)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x_parser::{parse_source, FileId, SyntaxStyle};
+
+    fn selection_of(source: &str, needle: &str) -> Span {
+        let pos = source.find(needle).unwrap();
+        let start = source[..pos].chars().count() as u32;
+        let len = needle.chars().count() as u32;
+        Span::new(FileId::new(0), CharOffset::new(start), CharOffset::new(start + len))
+    }
+
+    fn extract(source: &str, needle: &str, name: &str) -> Result<(CompilationUnit, Extraction), EditError> {
+        let mut ast = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let mut editor = AstEditor::new();
+        let extraction = extract_function(&mut editor, &mut ast, source, selection_of(source, needle), name)?;
+        Ok((ast, extraction))
+    }
+
+    #[test]
+    fn test_extract_with_free_variables() {
+        let source = "module Test\nlet scale = 2\nlet f = fun a -> fun b -> a * scale + b";
+        let (ast, extraction) = extract(source, "a * scale", "scaled").unwrap();
+
+        // Module-level names stay references; local ones become parameters
+        assert_eq!(extraction.parameters, vec![Symbol::intern("a")]);
+        assert_eq!(
+            extraction.apply_to_source(source),
+            "module Test\nlet scale = 2\nlet scaled = fun a -> a * scale\nlet f = fun a -> fun b -> (scaled a) + b",
+        );
+
+        let names: Vec<Symbol> = ast.module.items.iter()
+            .filter_map(|item| match item {
+                Item::ValueDef(def) => Some(def.name),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(names, ["scale", "scaled", "f"].map(Symbol::intern));
+    }
+
+    #[test]
+    fn test_extracted_source_reparses() {
+        let source = "module Test\nlet g = fun x -> x + 1";
+        let (_, extraction) = extract(source, "x + 1", "inc").unwrap();
+        let rewritten = extraction.apply_to_source(source);
+
+        let reparsed = parse_source(&rewritten, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        assert_eq!(reparsed.module.items.len(), 2);
+        assert!(extraction.effects.is_empty());
+    }
+
+    #[test]
+    fn test_extract_rejects_bad_requests() {
+        let source = "module Test\nlet y = 1\nlet g = fun x -> x + y";
+
+        let err = extract(source, "x + y", "y").unwrap_err();
+        assert!(matches!(err, EditError::NameInUse { .. }));
+
+        let err = extract(source, "module", "h").unwrap_err();
+        assert!(matches!(err, EditError::NoExpressionInSelection { .. }));
+    }
+}
@@ -1,6 +1,8 @@
 //! Incremental analysis and compilation support
 
-use x_parser::CompilationUnit;
+use crate::quick_fix::TextEdit;
+use x_parser::{parse_items_at, CompilationUnit, FileId, Item, ParseError, Parser, Span, VisitSpans};
+use x_parser::span::CharOffset;
 use x_checker::CheckResult;
 use dashmap::DashMap;
 use std::sync::Arc;
@@ -272,10 +274,139 @@ pub struct CacheStats {
     pub hit_rate: f64,
 }
 
+/// What applying an edit to an `IncrementalDocument` reparsed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reparse {
+    /// Items parsed again from the edited text
+    pub reparsed_items: usize,
+    /// Items kept from the previous tree
+    pub reused_items: usize,
+}
+
+/// A module's text and syntax tree, kept in sync edit by edit
+///
+/// An edit only reparses the items it touches, plus the item before it,
+/// whose expression the edit may extend, and the item after it, which must
+/// come out unchanged to show the parse is back in step with the old tree.
+/// Items further on are kept and have their spans moved by the length the
+/// edit added or removed. Edits to the module header, or that fall out of
+/// step, reparse the whole module.
+#[derive(Debug, Clone)]
+pub struct IncrementalDocument {
+    file_id: FileId,
+    text: String,
+    unit: Option<CompilationUnit>,
+    /// Source covered by each item of `unit`, doc comments included
+    extents: Vec<Span>,
+}
+
+impl IncrementalDocument {
+    /// Parse `text` as the document's initial contents
+    pub fn new(text: impl Into<String>, file_id: FileId) -> Self {
+        let mut document = IncrementalDocument { file_id, text: text.into(), unit: None, extents: Vec::new() };
+        let _ = document.parse_all();
+        document
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The syntax tree of the current text, if it parses
+    pub fn ast(&self) -> Option<&CompilationUnit> {
+        self.unit.as_ref()
+    }
+
+    /// Replace the text covered by `edit.span`, whose offsets count
+    /// characters like every other span, and bring the tree up to date
+    pub fn apply_edit(&mut self, edit: &TextEdit) -> Result<Reparse, ParseError> {
+        let (start, end) = (edit.span.start.as_u32() as usize, edit.span.end.as_u32() as usize);
+        let (Some(start_byte), Some(end_byte)) = (edit.span.start.byte_index(&self.text), edit.span.end.byte_index(&self.text)) else {
+            return Err(ParseError::parse(format!("edit {start}..{end} is outside the document")));
+        };
+        if start > end {
+            return Err(ParseError::parse(format!("edit {start}..{end} ends before it starts")));
+        }
+        self.text.replace_range(start_byte..end_byte, &edit.new_text);
+        let delta = edit.new_text.chars().count() as i64 - (end - start) as i64;
+
+        if let Some(reparse) = self.reparse_items(start, end, delta) {
+            return Ok(reparse);
+        }
+        self.parse_all()
+    }
+
+    fn parse_all(&mut self) -> Result<Reparse, ParseError> {
+        self.unit = None;
+        self.extents.clear();
+        let mut parser = Parser::new(&self.text, self.file_id)?;
+        let unit = parser.parse()?;
+        self.extents = parser.take_trivia().item_extents;
+        let reparse = Reparse { reparsed_items: unit.module.items.len(), reused_items: 0 };
+        self.unit = Some(unit);
+        Ok(reparse)
+    }
+
+    /// Reparse the items around an edit of the old text's `start..end`,
+    /// or `None` when the whole module has to be parsed again
+    fn reparse_items(&mut self, start: usize, end: usize, delta: i64) -> Option<Reparse> {
+        let mut unit = self.unit.take()?;
+        let extents = &mut self.extents;
+        let count = extents.len();
+        // Edits at or before the first item's start may touch the header
+        if count == 0 || start <= extents[0].start.as_u32() as usize {
+            return None;
+        }
+        let offset = |span: &Span| span.start.as_u32() as usize;
+        let first = extents.partition_point(|extent| (extent.end.as_u32() as usize) < start).saturating_sub(1);
+        let lookahead = extents.partition_point(|extent| offset(extent) <= end);
+        let window_end = (lookahead + 1).min(count);
+
+        let moved = CharOffset::new(end as u32);
+        unit.module.items[lookahead.min(count)..].shift_spans(moved, delta);
+        extents[lookahead.min(count)..].shift_spans(moved, delta);
+
+        // The window runs to the start of the item after the lookahead, and
+        // its text on past that to the end of the item, whose first token
+        // ends some spans of the lookahead
+        let from = offset(&extents[first]);
+        let (stop, to) = match extents.get(window_end) {
+            Some(next) => (next.start, next.end.byte_index(&self.text)?),
+            None => (CharOffset::new(u32::MAX), self.text.len()),
+        };
+        let fragment = &self.text[CharOffset::new(from as u32).byte_index(&self.text)?..to];
+        // A fixity declaration changes how items anywhere in the module parse
+        let is_fixity = |item: &Item| matches!(item, Item::Fixity(_));
+        if unit.module.items[first..window_end].iter().any(is_fixity) {
+            return None;
+        }
+        let fixities: Vec<_> = unit.module.fixities().collect();
+        let (items, item_extents) =
+            parse_items_at(fragment, self.file_id, CharOffset::new(from as u32), stop, fixities).ok()?;
+        if items.iter().any(is_fixity) {
+            return None;
+        }
+        if lookahead < count
+            && (items.last() != Some(&unit.module.items[lookahead]) || item_extents.last() != Some(&extents[lookahead]))
+        {
+            return None;
+        }
+
+        let reparse = Reparse { reparsed_items: items.len(), reused_items: count - (window_end - first) };
+        unit.module.items.splice(first..window_end, items);
+        extents.splice(first..window_end, item_extents);
+        // Both end at the end of the text
+        unit.span.shift_spans(moved, delta);
+        unit.module.span.shift_spans(moved, delta);
+        self.unit = Some(unit);
+        Some(reparse)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use x_parser::{parse_source, FileId, SyntaxStyle};
+    use x_parser::{parse_source, SyntaxStyle};
 
     #[test]
     fn test_incremental_analyzer_creation() {
@@ -319,4 +450,95 @@ mod tests {
         assert_eq!(stats.cache_misses, 0);
         assert_eq!(stats.hit_rate, 0.0);
     }
-}
\ No newline at end of file
+
+    const SOURCE: &str = "module Edits\n\nlet one = 1\n\n-- helper\nlet add = fun x y -> x + y\n\n```\nDoubles\n```\nlet double = fun x -> add x x\n\ndata Shape = Circle Int | Square Int\n\nlet last = double one\n";
+
+    fn edit(text: &str, pattern: &str, new_text: &str) -> TextEdit {
+        let byte = text.find(pattern).unwrap();
+        let start = text[..byte].chars().count() as u32;
+        let end = start + pattern.chars().count() as u32;
+        TextEdit {
+            span: Span::new(FileId::new(0), CharOffset::new(start), CharOffset::new(end)),
+            new_text: new_text.to_string(),
+        }
+    }
+
+    /// Apply `edit` and check the tree matches a parse of the whole text
+    fn apply(document: &mut IncrementalDocument, edit: TextEdit) -> Result<Reparse, ParseError> {
+        let reparse = document.apply_edit(&edit);
+        match parse_source(document.text(), FileId::new(0), SyntaxStyle::SExpression) {
+            Ok(expected) => assert_eq!(document.ast(), Some(&expected), "after {edit:?}"),
+            Err(_) => assert!(reparse.is_err() && document.ast().is_none(), "after {edit:?}"),
+        }
+        reparse
+    }
+
+    #[test]
+    fn test_edit_reparses_touched_items() {
+        let mut document = IncrementalDocument::new(SOURCE, FileId::new(0));
+        assert_eq!(document.ast().unwrap().module.items.len(), 5);
+
+        // The item before and after the edited one are parsed again
+        let reparse = apply(&mut document, edit(SOURCE, "x + y", "x + y + 100")).unwrap();
+        assert_eq!(reparse, Reparse { reparsed_items: 3, reused_items: 2 });
+
+        let text = document.text().to_string();
+        let reparse = apply(&mut document, edit(&text, "double one", "double (add one 2)")).unwrap();
+        assert_eq!(reparse, Reparse { reparsed_items: 2, reused_items: 3 });
+
+        let text = document.text().to_string();
+        apply(&mut document, edit(&text, "```\nDoubles\n```\n", "")).unwrap();
+        let text = document.text().to_string();
+        apply(&mut document, edit(&text, "-- helper\n", "let inserted = \"ünïcode\"\n")).unwrap();
+        let text = document.text().to_string();
+        let reparse = apply(&mut document, edit(&text, "Square Int", "Square Int | Point")).unwrap();
+        assert!(reparse.reused_items > 0);
+        assert_eq!(document.ast().unwrap().module.items.len(), 6);
+    }
+
+    #[test]
+    fn test_edits_at_every_offset() {
+        let length = SOURCE.chars().count() as u32;
+        for offset in 0..length {
+            for (removed, inserted) in [(0, " 7"), (0, "\n"), (1, ""), (1, "z")] {
+                if offset + removed > length {
+                    continue;
+                }
+                let mut document = IncrementalDocument::new(SOURCE, FileId::new(0));
+                let span = Span::new(FileId::new(0), CharOffset::new(offset), CharOffset::new(offset + removed));
+                let _ = apply(&mut document, TextEdit { span, new_text: inserted.to_string() });
+            }
+        }
+    }
+
+    #[test]
+    fn test_edits_that_need_a_full_parse() {
+        let mut document = IncrementalDocument::new(SOURCE, FileId::new(0));
+
+        let reparse = apply(&mut document, edit(SOURCE, "Edits", "Renamed")).unwrap();
+        assert_eq!(reparse.reused_items, 0);
+
+        // A broken edit leaves no tree until the text parses again
+        let text = document.text().to_string();
+        assert!(apply(&mut document, edit(&text, "fun x y ->", "fun x y")).is_err());
+        let text = document.text().to_string();
+        apply(&mut document, edit(&text, "fun x y", "fun x y ->")).unwrap();
+        assert!(document.ast().is_some());
+
+        // Joining two items into one falls out of step with the old tree
+        let text = document.text().to_string();
+        apply(&mut document, edit(&text, "let one = 1\n", "let one = 1 +\n")).ok();
+
+        // Items parse with the module's fixities, and changing one parses everything again
+        let source = "module Ops\nlet a = 1 <+> 2\nlet b = 4\nlet c = 5\nlet d = 6\ninfixr 6 <+>\n";
+        let mut document = IncrementalDocument::new(source, FileId::new(0));
+        let reparse = apply(&mut document, edit(source, "4", "4 <+> 5 <+> 6")).unwrap();
+        assert!(reparse.reused_items > 0);
+        let text = document.text().to_string();
+        let reparse = apply(&mut document, edit(&text, "infixr", "infixl")).unwrap();
+        assert_eq!(reparse.reused_items, 0);
+
+        let out_of_range = TextEdit { span: Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(10_000)), new_text: String::new() };
+        assert!(document.apply_edit(&out_of_range).is_err());
+    }
+}
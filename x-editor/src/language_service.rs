@@ -1,5 +1,9 @@
 //! Language service functionality
 
+use crate::incremental::{IncrementalDocument, Reparse};
+use crate::query_language::{parse_query, run_query, QueryMatch};
+use crate::quick_fix::TextEdit;
+use crate::references::{Reference, SourceFile};
 use crate::validation::{ValidationResult, ValidationError};
 use x_parser::{CompilationUnit, ParseError, SyntaxStyle, parse_source, FileId};
 use x_checker::{type_check, CheckResult};
@@ -54,6 +58,16 @@ impl LanguageService {
         parse_source(source, file_id, syntax)
     }
 
+    /// Open `source` as a document that later edits reparse incrementally
+    pub fn open_document(&self, source: &str, file_id: FileId) -> IncrementalDocument {
+        IncrementalDocument::new(source, file_id)
+    }
+
+    /// Apply `edit` to `document`, reparsing only the items it touches
+    pub fn apply_text_edit(&self, document: &mut IncrementalDocument, edit: &TextEdit) -> Result<Reparse, ParseError> {
+        document.apply_edit(edit)
+    }
+
     /// Type check an AST
     pub fn type_check(&self, ast: &CompilationUnit) -> Result<CheckResult, crate::ast_editor::EditError> {
         Ok(type_check(ast))
@@ -82,6 +96,18 @@ impl LanguageService {
         })
     }
 
+    /// Run a textual query (see `query_language`) against an AST
+    pub fn query_str(&self, ast: &CompilationUnit, query: &str) -> Result<Vec<QueryMatch>, crate::ast_editor::EditError> {
+        let pattern = parse_query(query)?;
+        Ok(run_query(ast, &pattern))
+    }
+
+    /// Every reference to the top-level value `symbol` (`name` or
+    /// `Module.name`) in the files of `scope`, definitions included
+    pub fn find_references(&self, symbol: &str, scope: &[SourceFile]) -> Vec<Reference> {
+        crate::references::find_references(symbol, scope)
+    }
+
     /// Get configuration
     pub fn config(&self) -> &LanguageServiceConfig {
         &self.config
@@ -125,4 +151,30 @@ mod tests {
         
         assert!(validation.is_valid);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_query_str() {
+        let service = LanguageService::new(LanguageServiceConfig::default());
+        let ast = service.parse("module Test\nlet main = fun x -> print x").unwrap();
+
+        let matches = service.query_str(&ast, r#"fn > app[name="print"]"#).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert!(service.query_str(&ast, "fn >").is_err());
+    }
+
+    #[test]
+    fn test_apply_text_edit() {
+        let service = LanguageService::new(LanguageServiceConfig::default());
+        let source = "module Test\nlet a = 1\nlet b = 2\nlet c = 3\nlet d = 4\n";
+        let mut document = service.open_document(source, FileId::new(0));
+
+        let start = source.find('3').unwrap() as u32;
+        let edit = TextEdit {
+            span: x_parser::Span::new(FileId::new(0), x_parser::span::CharOffset::new(start), x_parser::span::CharOffset::new(start + 1)),
+            new_text: "30 + a".to_string(),
+        };
+        let reparse = service.apply_text_edit(&mut document, &edit).unwrap();
+        assert_eq!(reparse.reused_items, 1);
+        assert_eq!(document.ast(), Some(&service.parse(document.text()).unwrap()));
+    }
+}
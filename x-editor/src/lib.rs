@@ -6,29 +6,62 @@
 pub mod ast_editor;
 pub mod language_service;
 pub mod operations;
+pub mod node_ids;
 pub mod query;
+pub mod query_language;
 pub mod session;
 pub mod incremental;
 pub mod validation;
 pub mod index_system;
 pub mod content_addressing;
+pub mod dependency_hash;
+pub mod dependency_graph;
 pub mod tree_similarity;
+pub mod merge;
+pub mod analysis;
 pub mod annotated_ast;
 pub mod namespace;
 pub mod namespace_storage;
+pub mod namespace_sync;
+pub mod namespace_semver;
 pub mod namespace_resolver;
+pub mod rename;
+pub mod references;
+pub mod rewrite;
+pub mod extract;
+pub mod batch;
+pub mod semantic_tokens;
+pub mod quick_fix;
+pub mod lint;
+pub mod metrics;
 
 // Re-export main types
 pub use ast_editor::{AstEditor, EditResult, EditError};
 pub use language_service::{LanguageService, LanguageServiceConfig};
 pub use operations::{
-    EditOperation, InsertOperation, DeleteOperation, ReplaceOperation, MoveOperation,
+    EditOperation, InsertOperation, DeleteOperation, ReplaceOperation, MoveOperation, NodeRef,
     StructuralTransformation, TransformationResult,
 };
+pub use node_ids::NodeIds;
 pub use query::{AstQuery, QueryResult, QueryPattern, NodeSelector};
-pub use session::{EditSession, SessionId, SessionState};
-pub use incremental::{IncrementalAnalyzer, AnalysisResult};
+pub use query_language::{parse_query, run_query, syntax_tree, QueryMatch, QueryNode, QueryParseError};
+pub use session::{Checkpoint, EditSession, SessionId, SessionState};
+pub use incremental::{IncrementalAnalyzer, AnalysisResult, IncrementalDocument, Reparse};
 pub use validation::{ValidationResult, ValidationError};
+pub use batch::{BatchFailure, BatchStage};
+pub use dependency_hash::DefinitionHashes;
+pub use dependency_graph::{DependencyGraph, ImportEdge, ImportTree, ModuleNode, OutdatedPackage, PackageNode};
+pub use extract::{extract_function, Extraction};
+pub use rename::{rename_symbol, replace_references, BindingKind, RenameResult, SymbolIndex};
+pub use references::{find_references, references_at, Reference, SourceFile};
+pub use rewrite::{MetaBinding, RewriteRule, RewriteSite};
+pub use tree_similarity::diff_compilation_units;
+pub use merge::{merge, MergeConflict, MergeResult};
+pub use analysis::{call_graph, CallGraph, Definition, DefinitionKind};
+pub use semantic_tokens::{semantic_tokens, SemanticToken, SemanticTokenKind};
+pub use quick_fix::{apply_text_edits, suggest_fix, QuickFix, TextEdit};
+pub use lint::{Lint, LintContext, LintDiagnostic, LintRegistry, Severity};
+pub use metrics::{module_metrics, module_metrics_with, DefinitionMetrics, ModuleMetrics};
 
 use x_parser::CompilationUnit;
 use x_checker::CheckResult;
@@ -80,7 +113,88 @@ impl XLanguageEditor {
         let session = self.sessions.get_mut(&session_id)
             .ok_or(EditError::SessionNotFound { session_id })?;
         
-        self.ast_editor.apply_operation(&mut session.ast, operation)
+        session.apply(&mut self.ast_editor, operation)
+    }
+
+    /// Apply several operations to a session as a single unit
+    ///
+    /// Each operation must apply cleanly and leave the tree valid without
+    /// introducing type errors that were not present before the batch. If any
+    /// operation fails, or checking the tree does, every operation of the
+    /// batch is rolled back and dropped from the history; a rejected
+    /// operation is reported by `EditError::BatchFailed`.
+    pub fn apply_batch(
+        &mut self,
+        session_id: SessionId,
+        operations: Vec<EditOperation>,
+    ) -> Result<Vec<EditResult>, EditError> {
+        let session = self.sessions.get_mut(&session_id)
+            .ok_or(EditError::SessionNotFound { session_id })?;
+
+        let checkpoint = session.checkpoint();
+        let baseline: Vec<String> = self.language_service.type_check(&session.ast)?.errors.iter()
+            .map(|error| error.to_string())
+            .collect();
+
+        let mut results = Vec::with_capacity(operations.len());
+        for (index, operation) in operations.into_iter().enumerate() {
+            let rejected = match session.apply(&mut self.ast_editor, operation.clone()) {
+                Err(err) => Ok(Some((BatchStage::Apply, vec![err.to_string()]))),
+                Ok(result) => {
+                    results.push(result);
+                    check_batch_step(&self.language_service, &session.ast, &baseline)
+                }
+            };
+
+            let error = match rejected {
+                Ok(None) => continue,
+                Ok(Some((stage, messages))) => {
+                    EditError::BatchFailed(Box::new(BatchFailure { index, operation, stage, messages }))
+                }
+                Err(error) => error,
+            };
+            session.rollback_to(checkpoint)?;
+            // Once an operation was recorded, everything past the checkpoint
+            // belongs to the batch; otherwise any redo history predates it
+            if !results.is_empty() {
+                session.discard_redo();
+            }
+            return Err(error);
+        }
+
+        Ok(results)
+    }
+
+    /// Revert the last operation applied to a session
+    pub fn undo(&mut self, session_id: SessionId) -> Result<Option<EditResult>, EditError> {
+        let session = self.sessions.get_mut(&session_id)
+            .ok_or(EditError::SessionNotFound { session_id })?;
+
+        session.undo(&mut self.ast_editor)
+    }
+
+    /// Re-apply the last undone operation of a session
+    pub fn redo(&mut self, session_id: SessionId) -> Result<Option<EditResult>, EditError> {
+        let session = self.sessions.get_mut(&session_id)
+            .ok_or(EditError::SessionNotFound { session_id })?;
+
+        session.redo(&mut self.ast_editor)
+    }
+
+    /// Mark the current point in a session's history
+    pub fn checkpoint(&self, session_id: SessionId) -> Result<Checkpoint, EditError> {
+        let session = self.get_session(session_id)
+            .ok_or(EditError::SessionNotFound { session_id })?;
+
+        Ok(session.checkpoint())
+    }
+
+    /// Return a session to a previously taken checkpoint
+    pub fn rollback_to(&mut self, session_id: SessionId, checkpoint: Checkpoint) -> Result<(), EditError> {
+        let session = self.sessions.get_mut(&session_id)
+            .ok_or(EditError::SessionNotFound { session_id })?;
+
+        session.rollback_to(checkpoint)
     }
 
     /// Query AST in a session
@@ -92,7 +206,7 @@ impl XLanguageEditor {
         let session = self.get_session(session_id)
             .ok_or(EditError::SessionNotFound { session_id })?;
         
-        self.ast_editor.query(&session.ast, query)
+        session.query(&self.ast_editor, query)
     }
 
     /// Type check a session
@@ -186,6 +300,34 @@ impl Default for XLanguageEditor {
 }
 
 /// Convenience functions for common operations
+/// Why the tree after a batched operation is rejected, if it is: the
+/// stage that failed and its errors, where type errors already in
+/// `baseline` do not count
+fn check_batch_step(
+    service: &LanguageService,
+    ast: &CompilationUnit,
+    baseline: &[String],
+) -> Result<Option<(BatchStage, Vec<String>)>, EditError> {
+    let validation = service.validate(ast)?;
+    if !validation.is_valid {
+        let messages = validation.errors.iter().map(|e| e.to_string()).collect();
+        return Ok(Some((BatchStage::Validation, messages)));
+    }
+
+    let mut known = baseline.to_vec();
+    let new_errors: Vec<String> = service.type_check(ast)?.errors.iter()
+        .map(|error| error.to_string())
+        .filter(|message| match known.iter().position(|known| known == message) {
+            Some(position) => {
+                known.swap_remove(position);
+                false
+            }
+            None => true,
+        })
+        .collect();
+    Ok((!new_errors.is_empty()).then_some((BatchStage::TypeCheck, new_errors)))
+}
+
 pub mod convenience {
     use super::*;
     
@@ -255,11 +397,94 @@ mod tests {
     fn test_convenience_functions() {
         let source = "let x = 42";
         let operation = EditOperation::Insert(InsertOperation {
-            path: vec![0],
-            node: crate::operations::EditableNode::Expr(x_parser::Expr::Literal(x_parser::Literal::Integer(100), x_parser::Span::single(x_parser::FileId::new(0), x_parser::span::ByteOffset::new(0)))),
+            target: crate::operations::NodeRef::Path(vec![0]),
+            node: crate::operations::EditableNode::Expr(x_parser::Expr::Literal(x_parser::Literal::int(100), x_parser::Span::single(x_parser::FileId::new(0), x_parser::span::CharOffset::new(0)))),
         });
         
         let result = convenience::parse_and_edit(source, operation);
         assert!(result.is_ok());
     }
-}
\ No newline at end of file
+    
+    fn value_def(name: &str, body: &str) -> crate::operations::EditableNode {
+        let source = format!("module Scratch\nlet {name} = {body}");
+        let ast = x_parser::parse_source(&source, x_parser::FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        crate::operations::EditableNode::Item(ast.module.items[0].clone())
+    }
+    
+    #[test]
+    fn test_apply_batch_commits_all_operations() {
+        let mut editor = XLanguageEditor::default();
+        let session_id = editor.start_session("module Test\nlet x = 42").unwrap();
+        
+        let results = editor.apply_batch(session_id, vec![
+            EditOperation::insert(vec![0, 1], value_def("y", "1")),
+            EditOperation::insert(vec![0, 2], value_def("z", "true")),
+        ]).unwrap();
+        
+        assert_eq!(results.len(), 2);
+        assert_eq!(editor.get_session(session_id).unwrap().ast.module.items.len(), 3);
+    }
+    
+    #[test]
+    fn test_apply_batch_rolls_back_on_failure() {
+        let mut editor = XLanguageEditor::default();
+        let session_id = editor.start_session("module Test\nlet x = 42").unwrap();
+        let before = editor.get_session(session_id).unwrap().ast.clone();
+        
+        let err = editor.apply_batch(session_id, vec![
+            EditOperation::insert(vec![0, 1], value_def("y", "1")),
+            EditOperation::delete(vec![0, 5]),
+        ]).unwrap_err();
+        
+        match err {
+            EditError::BatchFailed(failure) => {
+                assert_eq!(failure.index, 1);
+                assert_eq!(failure.stage, BatchStage::Apply);
+            }
+            other => panic!("unexpected error: {other:?}"),
+        }
+        assert_eq!(editor.get_session(session_id).unwrap().ast, before);
+        assert!(!editor.get_session(session_id).unwrap().can_redo());
+    }
+    
+    #[test]
+    fn test_apply_batch_rejects_new_type_errors() {
+        let mut editor = XLanguageEditor::default();
+        let session_id = editor.start_session("module Test\nlet x = 42").unwrap();
+        
+        let err = editor.apply_batch(session_id, vec![
+            EditOperation::replace(vec![0, 0], value_def("x", "1 + true")),
+        ]).unwrap_err();
+        
+        assert!(matches!(err, EditError::BatchFailed(ref failure) if failure.stage == BatchStage::TypeCheck));
+        let session = editor.get_session(session_id).unwrap();
+        assert!(session.operations.is_empty());
+        assert!(!session.can_redo());
+    }
+    
+    #[test]
+    fn test_apply_batch_compares_type_errors_not_counts() {
+        let mut editor = XLanguageEditor::default();
+        let session_id = editor.start_session("module Test\nlet x = 1 + true").unwrap();
+        let before = editor.get_session(session_id).unwrap().ast.clone();
+        
+        // Fixing the existing error does not pay for a new one
+        let err = editor.apply_batch(session_id, vec![
+            EditOperation::replace(vec![0, 0], value_def("x", "\"a\" + 1")),
+        ]).unwrap_err();
+        
+        match err {
+            EditError::BatchFailed(failure) => {
+                assert_eq!(failure.stage, BatchStage::TypeCheck);
+                assert_eq!(failure.messages.len(), 1);
+            }
+            other => panic!("unexpected error: {other:?}"),
+        }
+        assert_eq!(editor.get_session(session_id).unwrap().ast, before);
+        
+        // Keeping the existing error is fine
+        editor.apply_batch(session_id, vec![
+            EditOperation::insert(vec![0, 1], value_def("y", "1")),
+        ]).unwrap();
+    }
+}
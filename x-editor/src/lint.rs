@@ -0,0 +1,558 @@
+//! Lints over the type-checked AST
+//!
+//! A [`Lint`] inspects one compilation unit together with its type check
+//! result and scope resolution and reports findings. The [`LintRegistry`]
+//! holds the rules that run, each at a severity that can be configured by
+//! rule name or code; a rule set to [`Severity::Allow`] does not run at all.
+
+use crate::references::{item_exprs, visit_exprs};
+use crate::rename::{BindingKind, SymbolIndex};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use x_checker::{type_check, CheckResult};
+use x_parser::{CompilationUnit, ExportKind, Expr, ImportKind, Item, Span, Symbol, Visibility};
+
+/// How a lint finding is treated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The rule does not run
+    Allow,
+    Warn,
+    /// Findings fail the lint run
+    Deny,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Allow => "allow",
+            Severity::Warn => "warning",
+            Severity::Deny => "error",
+        })
+    }
+}
+
+/// What a lint rule sees of a compilation unit
+pub struct LintContext<'a> {
+    pub unit: &'a CompilationUnit,
+    pub source: &'a str,
+    pub check: &'a CheckResult,
+    pub symbols: &'a SymbolIndex,
+}
+
+/// Something a rule found, before the registry attaches the rule to it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub span: Span,
+    pub message: String,
+}
+
+/// A lint rule
+pub trait Lint {
+    /// Stable code, e.g. `W0001`
+    fn code(&self) -> &'static str;
+
+    /// Name the rule is configured by, e.g. `unused-variable`
+    fn name(&self) -> &'static str;
+
+    fn default_severity(&self) -> Severity {
+        Severity::Warn
+    }
+
+    fn check(&self, cx: &LintContext<'_>) -> Vec<Finding>;
+}
+
+/// A finding of a rule, at the severity the rule is configured with
+#[derive(Debug, Clone, PartialEq)]
+pub struct LintDiagnostic {
+    pub code: &'static str,
+    pub rule: &'static str,
+    pub severity: Severity,
+    pub span: Span,
+    pub message: String,
+}
+
+/// A configured rule name that no registered lint has
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("unknown lint `{0}`")]
+pub struct UnknownLint(pub String);
+
+/// The lint rules to run and their severities
+pub struct LintRegistry {
+    lints: Vec<Box<dyn Lint>>,
+    severities: HashMap<&'static str, Severity>,
+}
+
+impl LintRegistry {
+    /// A registry without any rules
+    pub fn new() -> Self {
+        Self { lints: Vec::new(), severities: HashMap::new() }
+    }
+
+    /// A registry with every built-in rule at its default severity
+    pub fn builtin() -> Self {
+        let mut registry = Self::new();
+        registry.register(UnusedVariable);
+        registry.register(UnusedImport);
+        registry.register(ShadowedBinding);
+        registry.register(UnhandledEffect);
+        registry.register(OverlyGeneralAnnotation);
+        registry.register(DocAttributes);
+        registry
+    }
+
+    pub fn register(&mut self, lint: impl Lint + 'static) {
+        self.severities.insert(lint.name(), lint.default_severity());
+        self.lints.push(Box::new(lint));
+    }
+
+    /// Registered rules, in registration order
+    pub fn lints(&self) -> impl Iterator<Item = &dyn Lint> {
+        self.lints.iter().map(|lint| lint.as_ref())
+    }
+
+    /// Severity of the rule with the given name
+    pub fn severity(&self, name: &str) -> Option<Severity> {
+        self.severities.get(name).copied()
+    }
+
+    /// Set the severity of a rule, named by its name or its code
+    pub fn set_severity(&mut self, rule: &str, severity: Severity) -> Result<(), UnknownLint> {
+        let lint = self.lints.iter()
+            .find(|lint| lint.name() == rule || lint.code() == rule)
+            .ok_or_else(|| UnknownLint(rule.to_string()))?;
+        self.severities.insert(lint.name(), severity);
+        Ok(())
+    }
+
+    /// Apply a configuration table of rule severities
+    pub fn configure(&mut self, severities: &BTreeMap<String, Severity>) -> Result<(), UnknownLint> {
+        severities.iter().try_for_each(|(rule, severity)| self.set_severity(rule, *severity))
+    }
+
+    /// Run every rule that is not allowed over `unit`, type checking it first
+    pub fn run(&self, unit: &CompilationUnit, source: &str) -> Vec<LintDiagnostic> {
+        let check = type_check(unit);
+        let symbols = SymbolIndex::build(unit, source);
+        self.run_with(&LintContext { unit, source, check: &check, symbols: &symbols })
+    }
+
+    /// Run every rule that is not allowed in an already prepared context
+    pub fn run_with(&self, cx: &LintContext<'_>) -> Vec<LintDiagnostic> {
+        let mut diagnostics = Vec::new();
+        for lint in &self.lints {
+            let severity = self.severities[lint.name()];
+            if severity == Severity::Allow {
+                continue;
+            }
+            diagnostics.extend(lint.check(cx).into_iter().map(|finding| LintDiagnostic {
+                code: lint.code(),
+                rule: lint.name(),
+                severity,
+                span: finding.span,
+                message: finding.message,
+            }));
+        }
+        diagnostics.sort_by_key(|diagnostic| (diagnostic.span.file_id, diagnostic.span.start));
+        diagnostics
+    }
+}
+
+impl Default for LintRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl fmt::Debug for LintRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.lints.iter().map(|lint| (lint.code(), self.severities[lint.name()])))
+            .finish()
+    }
+}
+
+/// Parameters and local bindings that are never read
+pub struct UnusedVariable;
+
+impl Lint for UnusedVariable {
+    fn code(&self) -> &'static str {
+        "W0001"
+    }
+
+    fn name(&self) -> &'static str {
+        "unused-variable"
+    }
+
+    fn check(&self, cx: &LintContext<'_>) -> Vec<Finding> {
+        cx.symbols.occurrences().iter()
+            .filter(|occ| occ.is_definition && !occ.name.as_str().starts_with('_'))
+            .filter(|occ| matches!(cx.symbols.binding_kind(occ), Some(BindingKind::Parameter | BindingKind::Local)))
+            .filter(|occ| cx.symbols.related(occ).iter().all(|related| related.is_definition))
+            .map(|occ| Finding { span: occ.span, message: format!("unused variable `{}`", occ.name) })
+            .collect()
+    }
+}
+
+/// Imports nothing refers to
+///
+/// A selective import is unused when none of its values are; a qualified
+/// one when its qualifier never appears. Wildcard and other imports are
+/// not checked.
+pub struct UnusedImport;
+
+impl Lint for UnusedImport {
+    fn code(&self) -> &'static str {
+        "W0002"
+    }
+
+    fn name(&self) -> &'static str {
+        "unused-import"
+    }
+
+    fn check(&self, cx: &LintContext<'_>) -> Vec<Finding> {
+        let free: HashSet<Symbol> = cx.symbols.occurrences().iter()
+            .filter(|occ| !occ.is_definition && cx.symbols.binding_kind(occ).is_none())
+            .map(|occ| occ.name)
+            .collect();
+
+        let mut findings = Vec::new();
+        for import in &cx.unit.module.imports {
+            match &import.kind {
+                ImportKind::Selective(items) => {
+                    for item in items.iter().filter(|item| item.kind == ExportKind::Value) {
+                        if !free.contains(&item.alias.unwrap_or(item.name)) {
+                            findings.push(Finding {
+                                span: cx.symbols.name_span(item.span.file_id, item.span.start, item.name),
+                                message: format!("unused import `{}.{}`", import.module_path, item.name),
+                            });
+                        }
+                    }
+                }
+                ImportKind::Qualified | ImportKind::Lazy => {
+                    let qualifier = import.alias.or_else(|| import.module_path.segments.first().copied());
+                    if qualifier.is_some_and(|qualifier| !free.contains(&qualifier)) {
+                        findings.push(Finding {
+                            span: import.module_path.span,
+                            message: format!("unused import `{}`", import.module_path),
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+        findings
+    }
+}
+
+/// Local bindings hiding an outer binding of the same name
+pub struct ShadowedBinding;
+
+impl Lint for ShadowedBinding {
+    fn code(&self) -> &'static str {
+        "W0003"
+    }
+
+    fn name(&self) -> &'static str {
+        "shadowed-binding"
+    }
+
+    fn check(&self, cx: &LintContext<'_>) -> Vec<Finding> {
+        cx.symbols.occurrences().iter()
+            .filter(|occ| occ.is_definition && cx.symbols.shadows(occ))
+            .map(|occ| Finding { span: occ.span, message: format!("`{}` shadows an earlier binding", occ.name) })
+            .collect()
+    }
+}
+
+/// Operations of an effect no handler in the program can handle
+///
+/// Only effects private to the module are considered: no other module can
+/// handle them, so when the module has no handler for one either, performing
+/// it fails at run time.
+pub struct UnhandledEffect;
+
+impl Lint for UnhandledEffect {
+    fn code(&self) -> &'static str {
+        "W0004"
+    }
+
+    fn name(&self) -> &'static str {
+        "unhandled-effect"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Deny
+    }
+
+    fn check(&self, cx: &LintContext<'_>) -> Vec<Finding> {
+        let module = &cx.unit.module;
+        let exported: HashSet<Symbol> = module.exports.iter()
+            .flat_map(|exports| exports.items.iter().map(|item| item.name))
+            .collect();
+        let private: HashSet<Symbol> = module.items.iter()
+            .filter_map(|item| match item {
+                Item::EffectDef(effect) if effect.visibility == Visibility::Private && !exported.contains(&effect.name) => {
+                    Some(effect.name)
+                }
+                _ => None,
+            })
+            .collect();
+
+        let mut handled = HashSet::new();
+        let mut performed = Vec::new();
+        for item in &module.items {
+            if let Item::HandlerDef(handler) = item {
+                handled.extend(handler.handled_effects.iter().map(|effect| effect.name));
+                handled.extend(handler.handlers.iter().map(|op| op.effect.name));
+            }
+            for expr in item_exprs(item) {
+                visit_exprs(expr, &mut |expr| match expr {
+                    Expr::Handle { handlers, .. } => handled.extend(handlers.iter().map(|op| op.effect.name)),
+                    Expr::Perform { effect, operation, span, .. } => performed.push((*effect, *operation, *span)),
+                    _ => {}
+                });
+            }
+        }
+
+        performed.into_iter()
+            .filter(|(effect, ..)| private.contains(effect) && !handled.contains(effect))
+            .map(|(effect, operation, span)| Finding {
+                span,
+                message: format!("`{effect}.{operation}` is performed, but nothing handles `{effect}`"),
+            })
+            .collect()
+    }
+}
+
+/// Type annotations more general than the definition they annotate:
+/// quantified type parameters the type never mentions, and effects the
+/// body never performs
+pub struct OverlyGeneralAnnotation;
+
+impl Lint for OverlyGeneralAnnotation {
+    fn code(&self) -> &'static str {
+        "W0005"
+    }
+
+    fn name(&self) -> &'static str {
+        "overly-general-annotation"
+    }
+
+    fn check(&self, cx: &LintContext<'_>) -> Vec<Finding> {
+        let annotated: Vec<_> = cx.unit.module.items.iter()
+            .filter_map(|item| match item {
+                Item::ValueDef(def) => def.type_annotation.as_ref().map(|annotation| (def.name, annotation)),
+                _ => None,
+            })
+            .collect();
+        if annotated.is_empty() {
+            return Vec::new();
+        }
+
+        // What the definitions would be inferred as without their annotations
+        let mut unannotated = cx.unit.clone();
+        for item in &mut unannotated.module.items {
+            if let Item::ValueDef(def) = item {
+                def.type_annotation = None;
+            }
+        }
+        let inferred = type_check(&unannotated).inferred_types;
+
+        let mut findings = Vec::new();
+        for (name, annotation) in annotated {
+            let mut annotation = annotation;
+            if let x_parser::Type::Forall { type_params, body, .. } = annotation {
+                for param in type_params.iter().filter(|param| !mentions(body, param.name)) {
+                    findings.push(Finding {
+                        span: param.span,
+                        message: format!("type parameter `{}` in the annotation of `{name}` is never used", param.name),
+                    });
+                }
+                annotation = body;
+            }
+
+            let Some(scheme) = inferred.get(&name) else { continue };
+            let mut inferred_type = &scheme.body;
+            while let (
+                x_parser::Type::Fun { effects, return_type, .. },
+                x_checker::Type::Fun { effects: inferred_effects, return_type: inferred_return, .. },
+            ) = (annotation, inferred_type) {
+                if is_closed(inferred_effects) {
+                    for effect in effects.effects.iter().filter(|effect| !inferred_effects.contains_effect(effect.name)) {
+                        findings.push(Finding {
+                            span: effect.span,
+                            message: format!("`{name}` is annotated with effect `{}` but never performs it", effect.name),
+                        });
+                    }
+                }
+                annotation = return_type;
+                inferred_type = inferred_return;
+            }
+        }
+        findings
+    }
+}
+
+/// Doc comment attributes the schema doesn't allow, and `@param`s naming
+/// no parameter of the function they document
+pub struct DocAttributes;
+
+impl Lint for DocAttributes {
+    fn code(&self) -> &'static str {
+        "W0006"
+    }
+
+    fn name(&self) -> &'static str {
+        "doc-attributes"
+    }
+
+    fn check(&self, cx: &LintContext<'_>) -> Vec<Finding> {
+        let module = &cx.unit.module;
+        let mut findings: Vec<Finding> = module.documentation.iter()
+            .flat_map(|documentation| documentation.doc_comment.attribute_warnings())
+            .map(|warning| Finding { span: warning.span, message: warning.message })
+            .collect();
+        for item in &module.items {
+            let Some(documentation) = item.documentation() else { continue };
+            let comment = &documentation.doc_comment;
+            findings.extend(comment.attribute_warnings().into_iter()
+                .map(|warning| Finding { span: warning.span, message: warning.message }));
+
+            let Item::ValueDef(def) = item else { continue };
+            let parameters = parameter_names(&def.body);
+            let mut documented: Vec<&str> = comment.attributes.keys()
+                .filter_map(|key| key.strip_prefix("param "))
+                .filter(|param| !parameters.iter().any(|name| name.as_str() == *param))
+                .collect();
+            documented.sort();
+            findings.extend(documented.into_iter().map(|param| Finding {
+                span: comment.span,
+                message: format!("`@param {param}` documents no parameter of `{}`", def.name),
+            }));
+        }
+        findings
+    }
+}
+
+/// Names the curried parameters of a function bind
+fn parameter_names(body: &Expr) -> Vec<Symbol> {
+    let mut names = Vec::new();
+    let mut body = body;
+    while let Expr::Lambda { parameters, body: inner, .. } = body {
+        names.extend(parameters.iter().filter_map(|parameter| match parameter {
+            x_parser::Pattern::Variable(name, _) => Some(*name),
+            _ => None,
+        }));
+        body = inner;
+    }
+    names
+}
+
+/// Whether an inferred effect set is fully known
+fn is_closed(effects: &x_checker::EffectSet) -> bool {
+    match effects {
+        x_checker::EffectSet::Empty => true,
+        x_checker::EffectSet::Var(_) => false,
+        x_checker::EffectSet::Row { tail, .. } => tail.as_deref().is_none_or(is_closed),
+    }
+}
+
+/// Whether `ty` refers to the type named `name`
+fn mentions(ty: &x_parser::Type, name: Symbol) -> bool {
+    use x_parser::Type;
+    match ty {
+        Type::Var(var, _) | Type::Con(var, _) => *var == name,
+        Type::App(head, args, _) => mentions(head, name) || args.iter().any(|arg| mentions(arg, name)),
+        Type::Fun { params, return_type, .. } => {
+            params.iter().any(|param| mentions(param, name)) || mentions(return_type, name)
+        }
+        Type::Forall { type_params, body, .. } | Type::Exists { type_params, body, .. } => {
+            !type_params.iter().any(|param| param.name == name) && mentions(body, name)
+        }
+        Type::Record { fields, rest, .. } | Type::Variant { variants: fields, rest, .. } | Type::Row { fields, rest, .. } => {
+            fields.values().any(|field| mentions(field, name)) || rest.as_deref().is_some_and(|rest| mentions(rest, name))
+        }
+        Type::Tuple { types, .. } => types.iter().any(|ty| mentions(ty, name)),
+        Type::Effects(..) | Type::Hole(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x_parser::{parse_source, FileId, SyntaxStyle};
+
+    fn lint(source: &str) -> Vec<(&'static str, String)> {
+        let unit = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        LintRegistry::builtin().run(&unit, source).into_iter()
+            .map(|diagnostic| (diagnostic.rule, diagnostic.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_unused_and_shadowed_bindings() {
+        let found = lint("module Demo\nimport Math { square, cube }\nimport Text\n\
+            let f = fun x -> fun y -> fun _z -> fun x -> cube x\nlet main = fun u -> f 1 2 3");
+        assert_eq!(found, vec![
+            ("unused-import", "unused import `Math.square`".to_string()),
+            ("unused-import", "unused import `Text`".to_string()),
+            ("unused-variable", "unused variable `x`".to_string()),
+            ("unused-variable", "unused variable `y`".to_string()),
+            ("shadowed-binding", "`x` shadows an earlier binding".to_string()),
+            ("unused-variable", "unused variable `u`".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_severities_are_configurable() {
+        let source = "module Demo\nlet f = fun x -> 1";
+        let unit = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let mut registry = LintRegistry::builtin();
+        assert_eq!(registry.run(&unit, source)[0].severity, Severity::Warn);
+
+        registry.configure(&BTreeMap::from([("W0001".to_string(), Severity::Deny)])).unwrap();
+        assert_eq!(registry.run(&unit, source)[0].severity, Severity::Deny);
+        registry.set_severity("unused-variable", Severity::Allow).unwrap();
+        assert!(registry.run(&unit, source).is_empty());
+        assert_eq!(registry.set_severity("no-such-rule", Severity::Deny), Err(UnknownLint("no-such-rule".to_string())));
+    }
+
+    #[test]
+    fn test_unused_type_parameter_in_annotation() {
+        assert_eq!(lint("module Demo\nlet zero : forall [a, b]. List[a] = Nil\nlet main = zero"), vec![(
+            "overly-general-annotation",
+            "type parameter `b` in the annotation of `zero` is never used".to_string(),
+        )]);
+    }
+
+    #[test]
+    fn test_doc_attributes() {
+        let found = lint("--! ---\n--! @version 2\n--! ---\nmodule Demo\n\
+            ```\n---\n@param {x: Int} the number\n@param y missing\n@since soon\n---\nDoubles.\n```\n\
+            let double = fun x -> x + x\nlet main = double 1");
+        assert_eq!(found, vec![
+            ("doc-attributes", "unknown doc attribute `version`".to_string()),
+            ("doc-attributes", "`since` takes a version, such as `1.2.0`".to_string()),
+            ("doc-attributes", "`@param y` documents no parameter of `double`".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_unhandled_private_effect() {
+        let source = "module Demo\neffect Log { log : String -> Unit }\nlet main = fun u -> u";
+        let mut unit = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let Item::ValueDef(main) = &mut unit.module.items[1] else { panic!("expected main") };
+        let Expr::Lambda { body, .. } = &mut main.body else { panic!("expected a lambda") };
+        let span = body.span();
+        **body = Expr::Perform { effect: Symbol::intern("Log"), operation: Symbol::intern("log"), args: vec![], span };
+
+        let diagnostics = LintRegistry::builtin().run(&unit, source);
+        let unhandled: Vec<_> = diagnostics.iter().filter(|d| d.rule == "unhandled-effect").collect();
+        assert_eq!(unhandled.len(), 1);
+        assert_eq!(unhandled[0].severity, Severity::Deny);
+        assert_eq!(unhandled[0].message, "`Log.log` is performed, but nothing handles `Log`");
+    }
+}
@@ -0,0 +1,247 @@
+//! Three-way structural merge of compilation units
+//!
+//! Where a textual merge sees lines, [`merge`] sees the module header, the
+//! imports and the items. Each of them is matched across the three versions
+//! by identity (an item's kind and name, an import's module path) and
+//! compared by content, ignoring spans and layout. A node changed on one
+//! side only takes that side's version; a node changed on both sides in
+//! different ways is a conflict, for which the merged tree keeps our version.
+//!
+//! Imports and items keep our order; those only the other side added are
+//! placed after the node they follow there.
+
+use crate::tree_similarity::{item_identity, item_key};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use x_parser::{CompilationUnit, Import, Item};
+
+/// Outcome of a three-way merge
+#[derive(Debug, Clone)]
+pub struct MergeResult {
+    /// The merged tree, with our version wherever the sides conflict
+    pub merged: CompilationUnit,
+    /// Nodes both sides changed in different ways
+    pub conflicts: Vec<MergeConflict>,
+}
+
+impl MergeResult {
+    /// Whether the merge went through without conflicts
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+/// A node both sides changed in different ways; `None` means the node is
+/// absent from that version
+#[derive(Debug, Clone, PartialEq)]
+pub enum MergeConflict {
+    /// The module name, documentation or export list
+    Header,
+    Import {
+        path: String,
+        base: Option<Box<Import>>,
+        ours: Option<Box<Import>>,
+        theirs: Option<Box<Import>>,
+    },
+    Item {
+        name: String,
+        base: Option<Box<Item>>,
+        ours: Option<Box<Item>>,
+        theirs: Option<Box<Item>>,
+    },
+}
+
+impl fmt::Display for MergeConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MergeConflict::Header => write!(f, "both sides changed the module header"),
+            MergeConflict::Import { path, .. } => write!(f, "both sides changed the import of {path}"),
+            MergeConflict::Item { name, .. } => write!(f, "both sides changed {name}"),
+        }
+    }
+}
+
+/// Merge the changes `ours` and `theirs` made to `base`
+pub fn merge(base: &CompilationUnit, ours: &CompilationUnit, theirs: &CompilationUnit) -> MergeResult {
+    let mut merged = ours.clone();
+    let mut conflicts = Vec::new();
+
+    let header = |unit: &CompilationUnit| content(&(&unit.module.name, &unit.module.documentation, &unit.module.exports));
+    match pick(Some(header(base)), Some(header(ours)), Some(header(theirs))) {
+        Pick::Ours => {}
+        Pick::Theirs => {
+            merged.module.name = theirs.module.name.clone();
+            merged.module.documentation = theirs.module.documentation.clone();
+            merged.module.exports = theirs.module.exports.clone();
+        }
+        Pick::Conflict => conflicts.push(MergeConflict::Header),
+    }
+
+    let imports = |unit: &CompilationUnit| keyed(&unit.module.imports, |import| import.module_path.to_string(), content);
+    let (merged_imports, import_conflicts) = merge_keyed(imports(base), imports(ours), imports(theirs));
+    merged.module.imports = merged_imports;
+    conflicts.extend(import_conflicts.into_iter().map(|(path, base, ours, theirs)| {
+        MergeConflict::Import { path, base: base.map(Box::new), ours: ours.map(Box::new), theirs: theirs.map(Box::new) }
+    }));
+
+    let items = |unit: &CompilationUnit| keyed(&unit.module.items, item_name, item_key);
+    let (merged_items, item_conflicts) = merge_keyed(items(base), items(ours), items(theirs));
+    merged.module.items = merged_items;
+    conflicts.extend(item_conflicts.into_iter().map(|(name, base, ours, theirs)| {
+        MergeConflict::Item { name, base: base.map(Box::new), ours: ours.map(Box::new), theirs: theirs.map(Box::new) }
+    }));
+
+    MergeResult { merged, conflicts }
+}
+
+/// Which version of a node the merge takes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pick {
+    Ours,
+    Theirs,
+    Conflict,
+}
+
+fn pick<T: PartialEq>(base: Option<T>, ours: Option<T>, theirs: Option<T>) -> Pick {
+    if ours == theirs || theirs == base {
+        Pick::Ours
+    } else if ours == base {
+        Pick::Theirs
+    } else {
+        Pick::Conflict
+    }
+}
+
+/// A node with the key that matches it across versions and the content it
+/// is compared by
+struct Keyed<T> {
+    key: String,
+    content: String,
+    node: T,
+}
+
+/// Key and content of every node; a key that repeats within one version is
+/// numbered by occurrence
+fn keyed<T: Clone>(nodes: &[T], key: impl Fn(&T) -> String, content: impl Fn(&T) -> String) -> Vec<Keyed<T>> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    nodes.iter()
+        .map(|node| {
+            let mut key = key(node);
+            let occurrence = seen.entry(key.clone()).or_default();
+            if *occurrence > 0 {
+                key = format!("{key} #{occurrence}");
+            }
+            *occurrence += 1;
+            Keyed { key, content: content(node), node: node.clone() }
+        })
+        .collect()
+}
+
+type Conflict<T> = (String, Option<T>, Option<T>, Option<T>);
+
+/// Three-way merge of two keyed sequences against their common base
+fn merge_keyed<T: Clone>(base: Vec<Keyed<T>>, ours: Vec<Keyed<T>>, theirs: Vec<Keyed<T>>) -> (Vec<T>, Vec<Conflict<T>>) {
+    let find = |nodes: &[Keyed<T>], key: &str| nodes.iter().position(|node| node.key == key);
+    let mut conflicts = Vec::new();
+    let mut decide = |key: &str| -> Option<T> {
+        let [b, o, t] = [&base, &ours, &theirs].map(|nodes| find(nodes, key).map(|index| &nodes[index]));
+        let content = |node: Option<&Keyed<T>>| node.map(|node| node.content.clone());
+        let node = |node: Option<&Keyed<T>>| node.map(|node| node.node.clone());
+        match pick(content(b), content(o), content(t)) {
+            Pick::Ours => node(o),
+            Pick::Theirs => node(t),
+            Pick::Conflict => {
+                conflicts.push((key.to_string(), node(b), node(o), node(t)));
+                node(o).or(node(t))
+            }
+        }
+    };
+
+    let mut merged: Vec<(String, T)> = Vec::new();
+    for node in &ours {
+        if let Some(value) = decide(&node.key) {
+            merged.push((node.key.clone(), value));
+        }
+    }
+    for (index, node) in theirs.iter().enumerate() {
+        if find(&ours, &node.key).is_some() {
+            continue;
+        }
+        let Some(value) = decide(&node.key) else { continue };
+        let at = theirs[..index].iter().rev()
+            .find_map(|previous| merged.iter().position(|(key, _)| *key == previous.key))
+            .map_or(0, |position| position + 1);
+        merged.insert(at, (node.key.clone(), value));
+    }
+
+    (merged.into_iter().map(|(_, value)| value).collect(), conflicts)
+}
+
+/// Kind and name of an item, or its text for items without a name
+fn item_name(item: &Item) -> String {
+    match item_identity(item) {
+        Some((kind, name)) => format!("{kind} {name}"),
+        None => item_key(item),
+    }
+}
+
+/// Serialized form of a node without its spans
+fn content<T: Serialize>(node: &T) -> String {
+    fn strip_spans(value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::Object(fields) => {
+                fields.remove("span");
+                fields.values_mut().for_each(strip_spans);
+            }
+            serde_json::Value::Array(values) => values.iter_mut().for_each(strip_spans),
+            _ => {}
+        }
+    }
+    let mut value = serde_json::to_value(node).unwrap_or_default();
+    strip_spans(&mut value);
+    value.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x_parser::{parse_source, FileId, SyntaxStyle};
+
+    fn parse(source: &str) -> CompilationUnit {
+        parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap()
+    }
+
+    fn items(unit: &CompilationUnit) -> Vec<String> {
+        unit.module.items.iter().map(item_key).collect()
+    }
+
+    #[test]
+    fn test_merge_combines_independent_changes() {
+        let base = parse("module M\nlet a = 1\nlet b = 2\nlet c = 3");
+        let ours = parse("module M\nimport Core.List\nlet a = 10\nlet b = 2\nlet c = 3");
+        let theirs = parse("module M\nlet a = 1\nlet b = 2\nlet d = 4\nlet c = 30");
+
+        let result = merge(&base, &ours, &theirs);
+        assert!(result.is_clean(), "{:?}", result.conflicts);
+        assert_eq!(result.merged.module.imports.len(), 1);
+        assert_eq!(items(&result.merged), items(&parse("module M\nlet a = 10\nlet b = 2\nlet d = 4\nlet c = 30")));
+    }
+
+    #[test]
+    fn test_merge_reports_conflicting_items() {
+        let base = parse("module M\nlet a = 1\nlet b = 2");
+        let ours = parse("module M\nlet a = 10\nlet b = 2");
+        let theirs = parse("module M\nlet a = 20");
+
+        let result = merge(&base, &ours, &theirs);
+        assert_eq!(result.conflicts.len(), 1);
+        let MergeConflict::Item { name, ours, theirs, .. } = &result.conflicts[0] else {
+            panic!("expected an item conflict");
+        };
+        assert_eq!(name, "value a");
+        assert!(ours.is_some() && theirs.is_some());
+        assert_eq!(items(&result.merged), items(&parse("module M\nlet a = 10")), "b was removed on one side only");
+        assert_eq!(result.conflicts[0].to_string(), "both sides changed value a");
+    }
+}
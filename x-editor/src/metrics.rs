@@ -0,0 +1,233 @@
+//! Code metrics for the definitions of a module
+//!
+//! Every value, test and handler definition is measured on its own:
+//!
+//! - cyclomatic complexity, one plus the decision points in its body: each
+//!   `if`, each match arm after the first, each guard, each `&&` and `||`,
+//!   and each clause of a handler after the first;
+//! - the deepest nesting of expressions;
+//! - how many effect operations it performs and how many `handle`
+//!   expressions it contains.
+//!
+//! A value definition is effectful when it is declared impure, performs an
+//! operation itself, or the type checker infers an effect for it; otherwise
+//! it is pure. The purity ratio of a module counts value definitions only.
+
+use crate::analysis::DefinitionKind;
+use crate::references::{item_exprs, sub_exprs, visit_exprs};
+use x_checker::{type_check, CheckResult, EffectSet};
+use x_parser::{CompilationUnit, Expr, Item, Purity, Span, Symbol};
+
+/// Measurements of one definition
+#[derive(Debug, Clone, PartialEq)]
+pub struct DefinitionMetrics {
+    pub name: Symbol,
+    pub kind: DefinitionKind,
+    pub span: Span,
+    pub complexity: usize,
+    pub max_depth: usize,
+    pub perform_sites: usize,
+    pub handle_sites: usize,
+    pub effectful: bool,
+}
+
+/// Measurements of every definition of one module, in source order
+#[derive(Debug, Clone, Default)]
+pub struct ModuleMetrics {
+    pub module: String,
+    pub definitions: Vec<DefinitionMetrics>,
+}
+
+/// Measure the definitions of `cu`, type checking it to find their effects
+pub fn module_metrics(cu: &CompilationUnit) -> ModuleMetrics {
+    module_metrics_with(cu, &type_check(cu))
+}
+
+/// Measure the definitions of `cu` with the results of an earlier check
+pub fn module_metrics_with(cu: &CompilationUnit, check: &CheckResult) -> ModuleMetrics {
+    let mut definitions = Vec::new();
+    for item in &cu.module.items {
+        let (name, kind, span, clauses) = match item {
+            Item::ValueDef(def) => (def.name, DefinitionKind::Value, def.span, 1),
+            Item::TestDef(test) => (test.name, DefinitionKind::Test, test.span, 1),
+            Item::HandlerDef(handler) => (
+                handler.name,
+                DefinitionKind::Handler,
+                handler.span,
+                handler.handlers.len() + usize::from(handler.return_clause.is_some()),
+            ),
+            _ => continue,
+        };
+        let exprs = item_exprs(item);
+
+        let mut metrics = DefinitionMetrics {
+            name,
+            kind,
+            span,
+            complexity: clauses.max(1),
+            max_depth: exprs.iter().map(|expr| depth(expr)).max().unwrap_or(0),
+            perform_sites: 0,
+            handle_sites: 0,
+            effectful: false,
+        };
+        for expr in exprs {
+            visit_exprs(expr, &mut |expr| {
+                metrics.complexity += decision_points(expr);
+                match expr {
+                    Expr::Perform { .. } => metrics.perform_sites += 1,
+                    Expr::Handle { .. } => metrics.handle_sites += 1,
+                    _ => {}
+                }
+            });
+        }
+
+        metrics.effectful = metrics.perform_sites > 0 || match item {
+            Item::ValueDef(def) => {
+                def.purity == Purity::Impure
+                    || check.inferred_effects.get(&name).is_some_and(has_effects)
+                    || check.inferred_types.get(&name).is_some_and(|scheme| latent_effects(&scheme.body))
+            }
+            _ => false,
+        };
+        definitions.push(metrics);
+    }
+    ModuleMetrics { module: cu.module.name.to_string(), definitions }
+}
+
+impl ModuleMetrics {
+    /// Value definitions without effects
+    pub fn pure_count(&self) -> usize {
+        self.values().filter(|metrics| !metrics.effectful).count()
+    }
+
+    /// Value definitions with effects
+    pub fn effectful_count(&self) -> usize {
+        self.values().filter(|metrics| metrics.effectful).count()
+    }
+
+    /// The share of value definitions that are pure, if there are any
+    pub fn purity_ratio(&self) -> Option<f64> {
+        purity_ratio(self.pure_count(), self.effectful_count())
+    }
+
+    pub fn perform_sites(&self) -> usize {
+        self.definitions.iter().map(|metrics| metrics.perform_sites).sum()
+    }
+
+    pub fn handle_sites(&self) -> usize {
+        self.definitions.iter().map(|metrics| metrics.handle_sites).sum()
+    }
+
+    pub fn max_complexity(&self) -> usize {
+        self.definitions.iter().map(|metrics| metrics.complexity).max().unwrap_or(0)
+    }
+
+    /// The metrics as JSON, per definition and in total
+    pub fn to_json(&self) -> serde_json::Value {
+        let definitions: Vec<_> = self.definitions.iter()
+            .map(|metrics| serde_json::json!({
+                "name": metrics.name.as_str(),
+                "kind": metrics.kind.to_string(),
+                "complexity": metrics.complexity,
+                "max_depth": metrics.max_depth,
+                "perform_sites": metrics.perform_sites,
+                "handle_sites": metrics.handle_sites,
+                "effectful": metrics.effectful,
+            }))
+            .collect();
+        serde_json::json!({
+            "module": self.module,
+            "definitions": definitions,
+            "pure": self.pure_count(),
+            "effectful": self.effectful_count(),
+            "purity_ratio": self.purity_ratio(),
+            "perform_sites": self.perform_sites(),
+            "handle_sites": self.handle_sites(),
+            "max_complexity": self.max_complexity(),
+        })
+    }
+
+    fn values(&self) -> impl Iterator<Item = &DefinitionMetrics> {
+        self.definitions.iter().filter(|metrics| metrics.kind == DefinitionKind::Value)
+    }
+}
+
+/// The share of `pure` among `pure + effectful` definitions
+pub fn purity_ratio(pure: usize, effectful: usize) -> Option<f64> {
+    let total = pure + effectful;
+    (total > 0).then(|| pure as f64 / total as f64)
+}
+
+/// Decision points `expr` adds by itself, not counting its subexpressions
+fn decision_points(expr: &Expr) -> usize {
+    match expr {
+        Expr::If { .. } => 1,
+        Expr::Match { arms, .. } => {
+            arms.len().saturating_sub(1) + arms.iter().filter(|arm| arm.guard.is_some()).count()
+        }
+        Expr::Handle { handlers, .. } => handlers.len().saturating_sub(1),
+        Expr::App(func, ..) => match &**func {
+            Expr::Var(name, _) if matches!(name.as_str(), "&&" | "||") => 1,
+            _ => 0,
+        },
+        _ => 0,
+    }
+}
+
+/// Nesting depth of `expr`, a leaf counting as one
+fn depth(expr: &Expr) -> usize {
+    1 + sub_exprs(expr).into_iter().map(depth).max().unwrap_or(0)
+}
+
+fn has_effects(effects: &EffectSet) -> bool {
+    match effects {
+        EffectSet::Empty | EffectSet::Var(_) => false,
+        EffectSet::Row { effects, tail } => !effects.is_empty() || tail.as_deref().is_some_and(has_effects),
+    }
+}
+
+/// Whether calling a value of type `ty` performs effects
+fn latent_effects(ty: &x_checker::Type) -> bool {
+    match ty {
+        x_checker::Type::Fun { effects, return_type, .. } => has_effects(effects) || latent_effects(return_type),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x_parser::{parse_source, FileId, SyntaxStyle};
+
+    fn metrics(source: &str) -> ModuleMetrics {
+        module_metrics(&parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap())
+    }
+
+    #[test]
+    fn test_complexity_and_depth() {
+        let metrics = metrics("module Demo\n\
+            let id = fun x -> x\n\
+            let sign = fun n -> if n < 0 then 0 - 1 else if n > 0 && n < 100 then 1 else 0\n\
+            let describe = fun n -> match n with | 0 => 1 | 1 => 2 | _ => 3");
+        let complexity: Vec<_> = metrics.definitions.iter().map(|metrics| metrics.complexity).collect();
+        assert_eq!(complexity, vec![1, 4, 3]);
+        assert_eq!(metrics.definitions[0].max_depth, 2);
+        assert!(metrics.definitions[1].max_depth > metrics.definitions[0].max_depth);
+        assert_eq!(metrics.max_complexity(), 4);
+    }
+
+    #[test]
+    fn test_purity_ratio() {
+        let mut metrics = metrics("module Demo\nlet a = fun x -> x\nlet b = fun x -> x + 1");
+        assert_eq!((metrics.pure_count(), metrics.effectful_count()), (2, 0));
+        assert_eq!(metrics.purity_ratio(), Some(1.0));
+
+        metrics.definitions[1].effectful = true;
+        assert_eq!(metrics.purity_ratio(), Some(0.5));
+        assert_eq!(ModuleMetrics::default().purity_ratio(), None);
+
+        let json = metrics.to_json();
+        assert_eq!(json["definitions"][1]["effectful"], serde_json::json!(true));
+        assert_eq!(json["purity_ratio"], serde_json::json!(0.5));
+    }
+}
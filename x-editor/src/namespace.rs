@@ -48,6 +48,12 @@ impl NamespacePath {
     pub fn is_root(&self) -> bool {
         self.segments.is_empty()
     }
+
+    /// Whether every segment is an identifier, so the path maps to files
+    /// inside a store and nowhere else
+    pub fn is_valid(&self) -> bool {
+        self.segments.iter().all(|segment| crate::rename::is_identifier_like(segment.as_str()))
+    }
     
     pub fn parent(&self) -> Option<Self> {
         if self.segments.is_empty() {
@@ -666,6 +672,11 @@ mod tests {
         
         let parent = path.parent().unwrap();
         assert_eq!(parent.to_string(), "Data");
+
+        assert!(path.is_valid() && NamespacePath::root().is_valid());
+        assert!(!NamespacePath::from_str("Data..List").is_valid());
+        assert!(!NamespacePath::new(vec![Symbol::intern("..")]).is_valid());
+        assert!(!NamespacePath::new(vec![Symbol::intern("a/b")]).is_valid());
     }
     
     #[test]
@@ -0,0 +1,236 @@
+//! Semantic versioning of namespaces
+//!
+//! Two versions of a namespace are compared by their exported definitions.
+//! Removing an export, or changing its type so some caller no longer
+//! checks, is a major change. Adding an export, or generalizing a value's
+//! type, is a minor change. A new definition behind an unchanged type is a
+//! patch. Type compatibility is decided by the checker: a value's new type
+//! is compatible when it is at least as general as the old one.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use serde::{Serialize, Deserialize};
+use x_checker::inference::InferenceContext;
+use x_checker::types::TypeScheme;
+use x_parser::versioning::Version;
+
+use crate::namespace::{NameBinding, Namespace, TypeKind, Visibility};
+
+/// How far a version number must move for a change
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum VersionBump {
+    None,
+    Patch,
+    Minor,
+    Major,
+}
+
+impl VersionBump {
+    /// `version` moved by this bump
+    pub fn apply(self, version: &Version) -> Version {
+        match self {
+            VersionBump::None => version.clone(),
+            VersionBump::Patch => Version::new(version.major, version.minor, version.patch + 1),
+            VersionBump::Minor => Version::new(version.major, version.minor + 1, 0),
+            VersionBump::Major => Version::new(version.major + 1, 0, 0),
+        }
+    }
+}
+
+impl fmt::Display for VersionBump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            VersionBump::None => "none",
+            VersionBump::Patch => "patch",
+            VersionBump::Minor => "minor",
+            VersionBump::Major => "major",
+        })
+    }
+}
+
+/// A changed export and the bump it calls for
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportChange {
+    /// Name relative to the compared namespace, e.g. `List.map`
+    pub name: String,
+    pub bump: VersionBump,
+    pub reason: String,
+}
+
+/// Changes between two versions of a namespace
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SemverDiff {
+    /// The largest bump any change calls for
+    pub bump: VersionBump,
+    /// Changed exports, sorted by name
+    pub changes: Vec<ExportChange>,
+}
+
+/// Classify the changes from `old` to `new`
+pub fn diff(old: &Namespace, new: &Namespace) -> SemverDiff {
+    let mut changes = Vec::new();
+    collect_changes(old, new, "", &mut InferenceContext::new(), &mut changes);
+    changes.sort_by(|a, b| a.name.cmp(&b.name));
+    let bump = changes.iter().map(|change| change.bump).max().unwrap_or(VersionBump::None);
+    SemverDiff { bump, changes }
+}
+
+fn collect_changes(
+    old: &Namespace,
+    new: &Namespace,
+    prefix: &str,
+    context: &mut InferenceContext,
+    out: &mut Vec<ExportChange>,
+) {
+    let old_exports = exports(old);
+    let new_exports = exports(new);
+    for (name, old_binding) in &old_exports {
+        let qualified = format!("{prefix}{name}");
+        let change = |bump, reason: String| ExportChange { name: qualified.clone(), bump, reason };
+        let Some(new_binding) = new_exports.get(name) else {
+            out.push(change(VersionBump::Major, "no longer exported".to_string()));
+            continue;
+        };
+        match (old_binding, new_binding) {
+            (NameBinding::Namespace { namespace: old }, NameBinding::Namespace { namespace: new }) => {
+                collect_changes(old, new, &format!("{qualified}."), context, out);
+            }
+            (
+                NameBinding::Value { hash: old_hash, type_scheme: old_type, .. },
+                NameBinding::Value { hash: new_hash, type_scheme: new_type, .. },
+            ) => {
+                if old_hash == new_hash {
+                    continue;
+                }
+                let (bump, reason) = compare_types(old_type.as_ref(), new_type.as_ref(), context);
+                out.push(change(bump, reason));
+            }
+            (
+                NameBinding::Type { hash: old_hash, kind: old_kind, .. },
+                NameBinding::Type { hash: new_hash, kind: new_kind, .. },
+            ) => {
+                if old_hash == new_hash {
+                    continue;
+                }
+                // An abstract type's representation is hidden from callers
+                if let (TypeKind::Abstract, TypeKind::Abstract) = (old_kind, new_kind) {
+                    out.push(change(VersionBump::Patch, "representation changed".to_string()));
+                } else {
+                    out.push(change(VersionBump::Major, "definition changed".to_string()));
+                }
+            }
+            (NameBinding::Effect { hash: old_hash, .. }, NameBinding::Effect { hash: new_hash, .. }) => {
+                if old_hash != new_hash {
+                    out.push(change(VersionBump::Major, "operations changed".to_string()));
+                }
+            }
+            (NameBinding::Alias { target: old }, NameBinding::Alias { target: new }) => {
+                if old != new {
+                    out.push(change(VersionBump::Major, format!("now refers to {}", new.to_string())));
+                }
+            }
+            _ => out.push(change(VersionBump::Major, "now names a different kind of definition".to_string())),
+        }
+    }
+    for name in new_exports.keys().filter(|name| !old_exports.contains_key(*name)) {
+        out.push(ExportChange {
+            name: format!("{prefix}{name}"),
+            bump: VersionBump::Minor,
+            reason: "newly exported".to_string(),
+        });
+    }
+}
+
+/// The bump a value's definition change calls for, judged by its types
+fn compare_types(old: Option<&TypeScheme>, new: Option<&TypeScheme>, context: &mut InferenceContext) -> (VersionBump, String) {
+    let (Some(old), Some(new)) = (old, new) else {
+        return (VersionBump::Major, "definition changed and its type is not recorded".to_string());
+    };
+    if !context.is_at_least_as_general(new, old) {
+        (VersionBump::Major, format!("type changed from {} to {}", old.body, new.body))
+    } else if !context.is_at_least_as_general(old, new) {
+        (VersionBump::Minor, format!("type generalized from {} to {}", old.body, new.body))
+    } else {
+        (VersionBump::Patch, "definition changed".to_string())
+    }
+}
+
+/// Exported bindings by name: those the export list names, or else the
+/// public ones
+fn exports(namespace: &Namespace) -> BTreeMap<String, &NameBinding> {
+    let listed = namespace.exports.as_ref().map(|_| namespace.exported_names());
+    namespace.bindings.iter()
+        .filter(|(name, binding)| match &listed {
+            Some(listed) => listed.contains(*name),
+            None => is_public(binding),
+        })
+        .map(|(name, binding)| (name.to_string(), binding))
+        .collect()
+}
+
+fn is_public(binding: &NameBinding) -> bool {
+    match binding {
+        NameBinding::Value { visibility, .. }
+        | NameBinding::Type { visibility, .. }
+        | NameBinding::Effect { visibility, .. } => *visibility == Visibility::Public,
+        NameBinding::Namespace { .. } | NameBinding::Alias { .. } => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::content_addressing::ContentHash;
+    use crate::namespace::NamespacePath;
+    use x_checker::checker::TypeCheck;
+    use x_parser::{parse_source, FileId, Symbol, SyntaxStyle};
+
+    /// An `App` namespace binding each name to its definition and inferred type
+    fn namespace(definitions: &[(&str, &str)]) -> Namespace {
+        let source: String = definitions.iter().map(|(name, body)| format!("\nlet {name} = {body}")).collect();
+        let unit = parse_source(&format!("module App{source}"), FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let result = unit.type_check();
+        let mut namespace = Namespace::new(NamespacePath::from_str("App"));
+        for (name, body) in definitions {
+            let name = Symbol::intern(name);
+            let scheme = result.inferred_types.get(&name).cloned();
+            namespace.add_value(name, ContentHash::new(body.as_bytes()), scheme, Visibility::Public);
+        }
+        namespace
+    }
+
+    fn bumps(diff: &SemverDiff) -> Vec<(&str, VersionBump)> {
+        diff.changes.iter().map(|change| (change.name.as_str(), change.bump)).collect()
+    }
+
+    #[test]
+    fn test_changes_are_classified_by_type_compatibility() {
+        let old = namespace(&[("inc", "fun n -> n + 1"), ("keep", "fun n -> n + 1"), ("same", "1")]);
+
+        let patched = namespace(&[("inc", "fun n -> 1 + n"), ("keep", "fun n -> n + 1"), ("same", "1")]);
+        assert_eq!(bumps(&diff(&old, &patched)), [("inc", VersionBump::Patch)]);
+
+        let extended = namespace(&[("inc", "fun n -> n"), ("keep", "fun n -> n + 1"), ("same", "1"), ("two", "2")]);
+        let minor = diff(&old, &extended);
+        assert_eq!(bumps(&minor), [("inc", VersionBump::Minor), ("two", VersionBump::Minor)]);
+        assert_eq!(minor.bump.apply(&Version::new(1, 2, 3)), Version::new(1, 3, 0));
+
+        let broken = namespace(&[("inc", "fun n -> n == 1"), ("same", "1")]);
+        let major = diff(&old, &broken);
+        assert_eq!(bumps(&major), [("inc", VersionBump::Major), ("keep", VersionBump::Major)]);
+        assert_eq!(major.changes[1].reason, "no longer exported");
+        assert_eq!(diff(&old, &old).bump, VersionBump::None);
+    }
+
+    #[test]
+    fn test_private_and_nested_bindings() {
+        let mut old = namespace(&[("one", "1")]);
+        let mut new = old.clone();
+        new.add_value(Symbol::intern("hidden"), ContentHash::new(b"2"), None, Visibility::Private);
+        assert_eq!(diff(&old, &new).bump, VersionBump::None);
+
+        old.add_namespace(Symbol::intern("List"), namespace(&[("map", "fun f -> f")]));
+        new.add_namespace(Symbol::intern("List"), namespace(&[]));
+        assert_eq!(bumps(&diff(&old, &new)), [("List.map", VersionBump::Major)]);
+    }
+}
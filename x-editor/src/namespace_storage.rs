@@ -13,7 +13,7 @@ use crate::namespace::{
     Namespace, NamespacePath, NameBinding,
     NamespaceResolver,
 };
-use crate::content_addressing::{ContentRepository, ContentHash};
+use crate::content_addressing::{ContentEntry, ContentRepository, ContentHash};
 use x_parser::Symbol;
 use crate::namespace::Visibility;
 
@@ -89,9 +89,10 @@ impl NamespaceStorage {
         let hash = ContentHash::new(&data);
         
         // Save to file system
-        let path = self.namespace_path(&namespace.path);
+        let path = self.namespace_path(&namespace.path)?;
         fs::create_dir_all(path.parent().unwrap())?;
         fs::write(&path, &data)?;
+        fs::write(self.namespace_version_path(&namespace.path, &hash)?, &data)?;
         
         // Update index
         self.namespace_index.namespaces.insert(namespace.path.to_string());
@@ -139,7 +140,7 @@ impl NamespaceStorage {
         }
         
         // Load from file
-        let file_path = self.namespace_path(path);
+        let file_path = self.namespace_path(path)?;
         if !file_path.exists() {
             return Err(anyhow!("Namespace '{}' not found", path.to_string()));
         }
@@ -167,7 +168,7 @@ impl NamespaceStorage {
         }
         
         // Remove from file system
-        let file_path = self.namespace_path(path);
+        let file_path = self.namespace_path(path)?;
         if file_path.exists() {
             fs::remove_file(&file_path)?;
         }
@@ -192,6 +193,52 @@ impl NamespaceStorage {
         Ok(())
     }
     
+    /// Check whether a namespace is stored
+    pub fn has_namespace(&self, path: &NamespacePath) -> bool {
+        self.namespace_index.namespaces.contains(&path.to_string())
+    }
+    
+    /// Get a stored definition by hash, from the repository or the content directory
+    pub fn content(&self, hash: &ContentHash) -> Result<Option<ContentEntry>> {
+        if let Some(entry) = self.content_repo.get(hash) {
+            return Ok(Some(entry.clone()));
+        }
+        let path = self.content_path(hash)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        let data = fs::read_to_string(&path)?;
+        Ok(Some(serde_json::from_str(&data)?))
+    }
+    
+    /// Check whether a definition is stored
+    pub fn has_content(&self, hash: &ContentHash) -> bool {
+        self.content_repo.get(hash).is_some() || self.content_path(hash).is_ok_and(|path| path.exists())
+    }
+    
+    /// Store a definition under its hash, so other stores can fetch it
+    ///
+    /// The hash is recomputed from the content; an entry stored under any
+    /// other hash is rejected.
+    pub fn store_content(&mut self, entry: &ContentEntry) -> Result<()> {
+        let path = self.content_path(&entry.hash)?;
+        let actual = entry.compute_hash()?;
+        if actual != entry.hash {
+            return Err(anyhow!("Definition '{}' claims hash {} but its content hashes to {}", entry.name, entry.hash.0, actual.0));
+        }
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, serde_json::to_vec(entry)?)?;
+        self.content_repo.entries.insert(entry.hash.clone(), entry.clone());
+        Ok(())
+    }
+    
+    /// Hashes of the definitions bound in a namespace and its subnamespaces
+    pub fn content_hashes(&self, namespace: &Namespace) -> HashSet<ContentHash> {
+        let mut hashes = HashSet::new();
+        self.collect_content_hashes(namespace, &mut hashes);
+        hashes
+    }
+    
     /// List all namespaces
     pub fn list_namespaces(&self) -> Vec<NamespacePath> {
         self.namespace_index.namespaces.iter()
@@ -220,7 +267,7 @@ impl NamespaceStorage {
                 version, path.to_string()))?;
         
         // Load from versioned file
-        let file_path = self.namespace_version_path(path, &version_info.hash);
+        let file_path = self.namespace_version_path(path, &version_info.hash)?;
         if !file_path.exists() {
             return Err(anyhow!("Version file not found"));
         }
@@ -258,15 +305,11 @@ impl NamespaceStorage {
     pub fn export_namespace(&mut self, path: &NamespacePath) -> Result<NamespaceExport> {
         let namespace = self.load_namespace(path)?;
         
-        // Collect all content hashes referenced by this namespace
-        let mut content_hashes = HashSet::new();
-        self.collect_content_hashes(&namespace, &mut content_hashes);
-        
-        // Export content items
+        // Export content items referenced by this namespace
         let mut content_items = HashMap::new();
-        for hash in &content_hashes {
-            if let Some(entry) = self.content_repo.get(hash) {
-                content_items.insert(hash.clone(), entry.clone());
+        for hash in self.content_hashes(&namespace) {
+            if let Some(entry) = self.content(&hash)? {
+                content_items.insert(hash, entry);
             }
         }
         
@@ -277,23 +320,35 @@ impl NamespaceStorage {
         })
     }
     
-    /// Path to namespace file
-    fn namespace_path(&self, path: &NamespacePath) -> PathBuf {
+    /// Path to namespace file; paths whose segments are not identifiers
+    /// could name files outside the store and are rejected
+    fn namespace_path(&self, path: &NamespacePath) -> Result<PathBuf> {
+        if !path.is_valid() {
+            return Err(anyhow!("Invalid namespace path '{}'", path.to_string()));
+        }
         if path.is_root() {
-            self.root_dir.join("root.namespace.json")
+            Ok(self.root_dir.join("root.namespace.json"))
         } else {
             let mut file_path = self.root_dir.clone();
             for segment in &path.segments {
                 file_path = file_path.join(segment.as_str());
             }
-            file_path.with_extension("namespace.json")
+            Ok(file_path.with_extension("namespace.json"))
+        }
+    }
+    
+    /// Path to a stored definition; only well-formed hashes name a file
+    fn content_path(&self, hash: &ContentHash) -> Result<PathBuf> {
+        if !hash.is_valid() {
+            return Err(anyhow!("Invalid content hash '{}'", hash.0));
         }
+        Ok(self.root_dir.join("content").join(format!("{}.json", hash.0)))
     }
     
     /// Path to versioned namespace file
-    fn namespace_version_path(&self, path: &NamespacePath, hash: &ContentHash) -> PathBuf {
-        let base = self.namespace_path(path);
-        base.with_extension(format!("{}.json", hash.short()))
+    fn namespace_version_path(&self, path: &NamespacePath, hash: &ContentHash) -> Result<PathBuf> {
+        let base = self.namespace_path(path)?;
+        Ok(base.with_extension(format!("{}.json", hash.short())))
     }
     
     /// Save namespace index
@@ -420,4 +475,36 @@ mod tests {
         assert_eq!(loaded.path, ns_path);
         assert!(loaded.bindings.contains_key(&Symbol::intern("test_fn")));
     }
+
+    #[test]
+    fn test_paths_and_hashes_stay_inside_the_store() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut storage = NamespaceStorage::new(temp_dir.path().join("store"), ContentRepository::new()).unwrap();
+
+        let escaping = Namespace::new(NamespacePath::new(vec![Symbol::intern(".."), Symbol::intern("outside")]));
+        assert!(storage.save_namespace(&escaping).is_err());
+        assert!(!temp_dir.path().join("outside.namespace.json").exists());
+
+        let traversal = ContentHash("../../outside".to_string());
+        assert!(!storage.has_content(&traversal));
+        assert!(storage.content(&traversal).is_err());
+    }
+
+    #[test]
+    fn test_store_content_checks_the_hash() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut storage = NamespaceStorage::new(temp_dir.path().to_path_buf(), ContentRepository::new()).unwrap();
+        let unit = x_parser::parse_source("module App\nlet one = 1", x_parser::FileId::new(0), x_parser::SyntaxStyle::SExpression).unwrap();
+        let x_parser::Item::ValueDef(def) = &unit.module.items[0] else { unreachable!() };
+        let mut repo = ContentRepository::new();
+        let hash = repo.add_function("one", def, None, None, HashSet::new()).unwrap();
+        let entry = repo.get(&hash).unwrap().clone();
+
+        storage.store_content(&entry).unwrap();
+        assert!(storage.has_content(&hash));
+
+        let forged = ContentEntry { hash: ContentHash::new(b"something else"), ..entry };
+        assert!(storage.store_content(&forged).is_err());
+        assert!(!storage.has_content(&forged.hash));
+    }
 }
\ No newline at end of file
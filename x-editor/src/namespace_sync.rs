@@ -0,0 +1,303 @@
+//! Synchronization of namespaces between stores
+//!
+//! Stores exchange a namespace and the definitions it binds. Definitions
+//! are content addressed, so only those the receiving store lacks are sent.
+//! A name bound to different hashes in the two stores is a conflict: it is
+//! reported, and nothing is written until it is resolved on one side.
+
+use std::collections::HashSet;
+use anyhow::{Result, anyhow};
+use serde::{Serialize, Deserialize};
+
+use crate::content_addressing::{ContentEntry, ContentHash};
+use crate::namespace::{NameBinding, Namespace, NamespacePath};
+use crate::namespace_storage::NamespaceStorage;
+
+/// A store namespaces are pushed to and pulled from
+pub trait NamespaceRemote {
+    /// The namespace at `path`, if the store has it
+    fn fetch_namespace(&mut self, path: &NamespacePath) -> Result<Option<Namespace>>;
+
+    /// Store a namespace, replacing the one at its path
+    fn store_namespace(&mut self, namespace: &Namespace) -> Result<()>;
+
+    /// The hashes among `hashes` the store has no definition for
+    fn missing_content(&mut self, hashes: &[ContentHash]) -> Result<Vec<ContentHash>>;
+
+    /// The definitions with the given hashes
+    fn fetch_content(&mut self, hashes: &[ContentHash]) -> Result<Vec<ContentEntry>>;
+
+    /// Store definitions under their hashes
+    fn store_content(&mut self, entries: &[ContentEntry]) -> Result<()>;
+}
+
+impl NamespaceRemote for NamespaceStorage {
+    fn fetch_namespace(&mut self, path: &NamespacePath) -> Result<Option<Namespace>> {
+        if self.has_namespace(path) {
+            self.load_namespace(path).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn store_namespace(&mut self, namespace: &Namespace) -> Result<()> {
+        self.save_namespace(namespace).map(|_| ())
+    }
+
+    fn missing_content(&mut self, hashes: &[ContentHash]) -> Result<Vec<ContentHash>> {
+        Ok(hashes.iter().filter(|hash| !self.has_content(hash)).cloned().collect())
+    }
+
+    fn fetch_content(&mut self, hashes: &[ContentHash]) -> Result<Vec<ContentEntry>> {
+        hashes.iter()
+            .map(|hash| self.content(hash)?.ok_or_else(|| anyhow!("No definition with hash {}", hash.0)))
+            .collect()
+    }
+
+    fn store_content(&mut self, entries: &[ContentEntry]) -> Result<()> {
+        entries.iter().try_for_each(|entry| NamespaceStorage::store_content(self, entry))
+    }
+}
+
+/// A name bound to different definitions locally and remotely
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Conflict {
+    /// Name relative to the synchronized namespace, e.g. `List.map`
+    pub name: String,
+    pub local: ContentHash,
+    pub remote: ContentHash,
+}
+
+/// Outcome of a push or pull
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncReport {
+    /// Definitions sent to or received from the remote
+    pub transferred: usize,
+    /// Definitions the receiving store already had
+    pub deduplicated: usize,
+    /// Names bound differently on each side; nothing was written if any
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Push the namespace at `path` from `local` to `remote`
+pub fn push(local: &mut NamespaceStorage, remote: &mut dyn NamespaceRemote, path: &NamespacePath) -> Result<SyncReport> {
+    let ours = local.load_namespace(path)?;
+    let theirs = remote.fetch_namespace(path)?;
+    let conflicts = theirs.as_ref().map_or_else(Vec::new, |theirs| conflicts(&ours, theirs));
+    if !conflicts.is_empty() {
+        return Ok(SyncReport { conflicts, ..SyncReport::default() });
+    }
+
+    let hashes: Vec<ContentHash> = local.content_hashes(&ours).into_iter().collect();
+    let missing = remote.missing_content(&hashes)?;
+    let entries = local.fetch_content(&missing)?;
+    remote.store_content(&entries)?;
+    let merged = match theirs {
+        Some(theirs) => merge(&theirs, &ours),
+        None => ours,
+    };
+    remote.store_namespace(&merged)?;
+
+    Ok(SyncReport { transferred: missing.len(), deduplicated: hashes.len() - missing.len(), conflicts })
+}
+
+/// Pull the namespace at `path` from `remote` into `local`
+pub fn pull(local: &mut NamespaceStorage, remote: &mut dyn NamespaceRemote, path: &NamespacePath) -> Result<SyncReport> {
+    let theirs = remote.fetch_namespace(path)?
+        .ok_or_else(|| anyhow!("Namespace '{}' not found on the remote", path.to_string()))?;
+    if theirs.path != *path {
+        return Err(anyhow!("The remote sent namespace '{}' for '{}'", theirs.path.to_string(), path.to_string()));
+    }
+    let ours = local.fetch_namespace(path)?;
+    let conflicts = ours.as_ref().map_or_else(Vec::new, |ours| self::conflicts(ours, &theirs));
+    if !conflicts.is_empty() {
+        return Ok(SyncReport { conflicts, ..SyncReport::default() });
+    }
+
+    let hashes: Vec<ContentHash> = local.content_hashes(&theirs).into_iter().collect();
+    let missing = local.missing_content(&hashes)?;
+    let entries = remote.fetch_content(&missing)?;
+    if let Some(entry) = entries.iter().find(|entry| !missing.contains(&entry.hash)) {
+        return Err(anyhow!("The remote sent an unrequested definition {}", entry.hash.0));
+    }
+    // Check every definition before storing any, so a bad one leaves the
+    // local store untouched
+    for entry in &entries {
+        let actual = entry.compute_hash()?;
+        if actual != entry.hash {
+            return Err(anyhow!("The remote sent definition {} whose content hashes to {}", entry.hash.0, actual.0));
+        }
+    }
+    NamespaceRemote::store_content(local, &entries)?;
+    let merged = match ours {
+        Some(ours) => merge(&ours, &theirs),
+        None => theirs,
+    };
+    local.save_namespace(&merged)?;
+
+    Ok(SyncReport { transferred: missing.len(), deduplicated: hashes.len() - missing.len(), conflicts })
+}
+
+/// Names bound to different definitions in `local` and `remote`, sorted by name
+pub fn conflicts(local: &Namespace, remote: &Namespace) -> Vec<Conflict> {
+    let mut conflicts = Vec::new();
+    collect_conflicts(local, remote, "", &mut conflicts);
+    conflicts.sort_by(|a, b| a.name.cmp(&b.name));
+    conflicts
+}
+
+fn collect_conflicts(local: &Namespace, remote: &Namespace, prefix: &str, out: &mut Vec<Conflict>) {
+    for (name, ours) in &local.bindings {
+        let Some(theirs) = remote.bindings.get(name) else { continue };
+        let qualified = format!("{prefix}{name}");
+        match (ours, theirs) {
+            (NameBinding::Namespace { namespace: ours }, NameBinding::Namespace { namespace: theirs }) => {
+                collect_conflicts(ours, theirs, &format!("{qualified}."), out);
+            }
+            _ => {
+                if let (Some(local), Some(remote)) = (binding_hash(ours), binding_hash(theirs)) {
+                    if local != remote {
+                        out.push(Conflict { name: qualified, local: local.clone(), remote: remote.clone() });
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn binding_hash(binding: &NameBinding) -> Option<&ContentHash> {
+    match binding {
+        NameBinding::Value { hash, .. } | NameBinding::Type { hash, .. } | NameBinding::Effect { hash, .. } => Some(hash),
+        NameBinding::Namespace { .. } | NameBinding::Alias { .. } => None,
+    }
+}
+
+/// `base` with the bindings of `incoming` added; the two must not conflict
+fn merge(base: &Namespace, incoming: &Namespace) -> Namespace {
+    let mut merged = base.clone();
+    for (name, binding) in &incoming.bindings {
+        let combined = match (merged.bindings.get(name), binding) {
+            (Some(NameBinding::Namespace { namespace: ours }), NameBinding::Namespace { namespace: theirs }) => {
+                NameBinding::Namespace { namespace: Box::new(merge(ours, theirs)) }
+            }
+            _ => binding.clone(),
+        };
+        merged.bindings.insert(*name, combined);
+    }
+    let known: HashSet<String> = merged.imports.iter().map(|import| import.source.to_string()).collect();
+    merged.imports.extend(incoming.imports.iter().filter(|import| !known.contains(&import.source.to_string())).cloned());
+    merged.metadata.modified_at = base.metadata.modified_at.max(incoming.metadata.modified_at);
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::content_addressing::ContentRepository;
+    use crate::namespace::Visibility;
+    use tempfile::TempDir;
+    use x_parser::{parse_source, FileId, Item, Symbol, SyntaxStyle};
+
+    /// A store whose `App` namespace binds each name to its stored definition
+    fn store(dir: &TempDir, definitions: &[(&str, &str)]) -> NamespaceStorage {
+        let mut repo = ContentRepository::new();
+        let mut namespace = Namespace::new(NamespacePath::from_str("App"));
+        let mut entries = Vec::new();
+        for (name, body) in definitions {
+            let unit = parse_source(&format!("module App\nlet {name} = {body}"), FileId::new(0), SyntaxStyle::SExpression).unwrap();
+            let Item::ValueDef(def) = &unit.module.items[0] else { unreachable!() };
+            let hash = repo.add_function(name, def, None, None, HashSet::new()).unwrap();
+            entries.push(repo.get(&hash).unwrap().clone());
+            namespace.add_value(Symbol::intern(name), hash, None, Visibility::Public);
+        }
+        let mut storage = NamespaceStorage::new(dir.path().to_path_buf(), ContentRepository::new()).unwrap();
+        for entry in &entries {
+            storage.store_content(entry).unwrap();
+        }
+        storage.save_namespace(&namespace).unwrap();
+        storage
+    }
+
+    #[test]
+    fn test_push_sends_only_missing_definitions() {
+        let (local_dir, remote_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let mut local = store(&local_dir, &[("one", "1"), ("two", "2")]);
+        let mut remote = store(&remote_dir, &[("one", "1"), ("three", "3")]);
+        let path = NamespacePath::from_str("App");
+
+        let report = push(&mut local, &mut remote, &path).unwrap();
+        assert_eq!((report.transferred, report.deduplicated), (1, 1));
+        assert!(report.conflicts.is_empty());
+
+        let merged = remote.load_namespace(&path).unwrap();
+        assert_eq!(merged.bindings.len(), 3);
+        assert!(remote.content_hashes(&merged).iter().all(|hash| remote.has_content(hash)));
+
+        // Everything is there now
+        let report = pull(&mut local, &mut remote, &path).unwrap();
+        assert_eq!((report.transferred, report.deduplicated), (1, 2));
+        assert_eq!(local.load_namespace(&path).unwrap().bindings.len(), 3);
+    }
+
+    /// A remote that swaps the content of the definitions it sends
+    struct Tampering(NamespaceStorage);
+
+    impl NamespaceRemote for Tampering {
+        fn fetch_namespace(&mut self, path: &NamespacePath) -> Result<Option<Namespace>> {
+            self.0.fetch_namespace(path)
+        }
+
+        fn store_namespace(&mut self, namespace: &Namespace) -> Result<()> {
+            NamespaceRemote::store_namespace(&mut self.0, namespace)
+        }
+
+        fn missing_content(&mut self, hashes: &[ContentHash]) -> Result<Vec<ContentHash>> {
+            self.0.missing_content(hashes)
+        }
+
+        fn fetch_content(&mut self, hashes: &[ContentHash]) -> Result<Vec<ContentEntry>> {
+            let mut entries = self.0.fetch_content(hashes)?;
+            let first = entries[0].content.clone();
+            let last = entries.len() - 1;
+            entries[0].content = entries[last].content.clone();
+            entries[last].content = first;
+            Ok(entries)
+        }
+
+        fn store_content(&mut self, entries: &[ContentEntry]) -> Result<()> {
+            NamespaceRemote::store_content(&mut self.0, entries)
+        }
+    }
+
+    #[test]
+    fn test_pull_verifies_definition_hashes() {
+        let (local_dir, remote_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let mut local = NamespaceStorage::new(local_dir.path().to_path_buf(), ContentRepository::new()).unwrap();
+        let mut remote = Tampering(store(&remote_dir, &[("one", "1"), ("two", "2")]));
+        let path = NamespacePath::from_str("App");
+
+        let error = pull(&mut local, &mut remote, &path).unwrap_err();
+        assert!(error.to_string().contains("whose content hashes to"), "{error}");
+        assert!(!local.has_namespace(&path));
+        let served = remote.0.load_namespace(&path).unwrap();
+        assert!(remote.0.content_hashes(&served).iter().all(|hash| !local.has_content(hash)));
+    }
+
+    #[test]
+    fn test_conflicting_names_are_reported_and_not_written() {
+        let (local_dir, remote_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let mut local = store(&local_dir, &[("answer", "42"), ("two", "2")]);
+        let mut remote = store(&remote_dir, &[("answer", "41")]);
+        let path = NamespacePath::from_str("App");
+
+        let report = pull(&mut local, &mut remote, &path).unwrap();
+        assert_eq!(report.conflicts.len(), 1);
+        assert_eq!(report.conflicts[0].name, "answer");
+        assert_ne!(report.conflicts[0].local, report.conflicts[0].remote);
+        assert_eq!(report.transferred, 0);
+
+        let report = push(&mut local, &mut remote, &path).unwrap();
+        assert_eq!(report.conflicts.len(), 1);
+        assert_eq!(remote.load_namespace(&path).unwrap().bindings.len(), 1);
+    }
+}
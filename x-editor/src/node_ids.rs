@@ -0,0 +1,173 @@
+//! Stable identities for module items
+//!
+//! Paths like `[0, 2]` name whatever item currently sits at a position and go
+//! stale as soon as an edit inserts or removes an item before it. A session
+//! numbers the items once, when its source has been parsed, and keeps each
+//! number attached to its item through every edit: a replaced item keeps its
+//! id, a moved one takes it along, and only inserted items get new ones.
+
+use crate::ast_editor::{EditError, EditResult};
+use crate::operations::{EditOperation, NodeRef};
+use im::Vector;
+use x_parser::persistent_ast::NodeId;
+use x_parser::CompilationUnit;
+
+/// The id of every module item, in item order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeIds {
+    ids: Vector<NodeId>,
+    /// Last id handed out; ids are never reused
+    last: u64,
+}
+
+impl NodeIds {
+    /// Number the items of `unit` from 1
+    pub fn assign(unit: &CompilationUnit) -> Self {
+        let count = unit.module.items.len() as u64;
+        NodeIds {
+            ids: (1..=count).map(NodeId::new).collect(),
+            last: count,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Id of the `index`th item
+    pub fn get(&self, index: usize) -> Option<NodeId> {
+        self.ids.get(index).copied()
+    }
+
+    /// Position of the item with id `node_id`
+    pub fn index_of(&self, node_id: NodeId) -> Option<usize> {
+        self.ids.index_of(&node_id)
+    }
+
+    /// Id of the node at `path`; only module items have ids
+    pub fn at_path(&self, path: &[usize]) -> Option<NodeId> {
+        match path {
+            [0, index] => self.get(*index),
+            _ => None,
+        }
+    }
+
+    /// Current path of the item with id `node_id`
+    pub fn path_of(&self, node_id: NodeId) -> Option<Vec<usize>> {
+        self.index_of(node_id).map(|index| vec![0, index])
+    }
+
+    /// `target` as a path into the current tree
+    pub fn resolve(&self, target: &NodeRef) -> Result<Vec<usize>, EditError> {
+        match target {
+            NodeRef::Path(path) => Ok(path.clone()),
+            NodeRef::Id(node_id) => self.path_of(*node_id)
+                .ok_or(EditError::NodeNotFound { node_id: *node_id }),
+        }
+    }
+
+    /// `operation` with every target resolved to a path
+    ///
+    /// A node moved in front of another by id lands in front of it: the
+    /// destination path is taken after the node has been removed from its
+    /// source, so it shifts when the source comes first.
+    pub fn resolve_operation(&self, operation: &EditOperation) -> Result<EditOperation, EditError> {
+        let resolved = match operation {
+            EditOperation::Insert(op) => EditOperation::insert(self.resolve(&op.target)?, op.node.clone()),
+            EditOperation::Delete(op) => EditOperation::delete(self.resolve(&op.target)?),
+            EditOperation::Replace(op) => EditOperation::replace(self.resolve(&op.target)?, op.new_node.clone()),
+            EditOperation::Move(op) => {
+                let source = self.resolve(&op.source)?;
+                let mut dest = self.resolve(&op.dest)?;
+                if let (NodeRef::Id(_), [0, from], [0, to]) = (&op.dest, source.as_slice(), dest.as_mut_slice()) {
+                    if *from < *to {
+                        *to -= 1;
+                    }
+                }
+                EditOperation::move_node(source, dest)
+            }
+        };
+        Ok(resolved)
+    }
+
+    /// Follow an applied edit, giving an inserted item a fresh id
+    pub fn record(&mut self, result: &mut EditResult) {
+        match &*result {
+            EditResult::Inserted { path, .. } => {
+                if let Some(&index) = path.last().filter(|&&index| index <= self.ids.len()) {
+                    self.last += 1;
+                    self.ids.insert(index, NodeId::new(self.last));
+                }
+            }
+            EditResult::Deleted { path, .. } => {
+                if let Some(&index) = path.last().filter(|&&index| index < self.ids.len()) {
+                    self.ids.remove(index);
+                }
+            }
+            EditResult::Moved { source_path, dest_path } => {
+                let (Some(&from), Some(&to)) = (source_path.last(), dest_path.last()) else { return };
+                if from < self.ids.len() {
+                    let node_id = self.ids.remove(from);
+                    self.ids.insert(to.min(self.ids.len()), node_id);
+                }
+            }
+            EditResult::Replaced { .. } => {}
+        }
+        self.label(result);
+    }
+
+    /// Fill in the id of an inserted item
+    pub fn label(&self, result: &mut EditResult) {
+        if let EditResult::Inserted { path, node_id } = result {
+            *node_id = path.last().and_then(|&index| self.get(index));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operations::EditableNode;
+    use x_parser::{parse_source, FileId, SyntaxStyle};
+
+    fn ids() -> NodeIds {
+        let unit = parse_source("module Test\nlet a = 1\nlet b = 2\nlet c = 3", FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        NodeIds::assign(&unit)
+    }
+
+    #[test]
+    fn test_ids_follow_their_items() {
+        let mut ids = ids();
+        let b = ids.get(1).unwrap();
+
+        let mut inserted = EditResult::Inserted { path: vec![0, 0], node_id: None };
+        ids.record(&mut inserted);
+        let EditResult::Inserted { node_id: Some(new), .. } = inserted else { panic!("expected an id") };
+        assert_eq!(new, NodeId::new(4));
+        assert_eq!(ids.path_of(b), Some(vec![0, 2]));
+
+        ids.record(&mut EditResult::Moved { source_path: vec![0, 2], dest_path: vec![0, 0] });
+        assert_eq!(ids.path_of(b), Some(vec![0, 0]));
+        assert_eq!(ids.path_of(new), Some(vec![0, 1]));
+    }
+
+    #[test]
+    fn test_move_in_front_of_a_node() {
+        let ids = ids();
+        let (a, c) = (ids.get(0).unwrap(), ids.get(2).unwrap());
+        let EditOperation::Move(op) = ids.resolve_operation(&EditOperation::move_node(a, c)).unwrap() else {
+            panic!("expected a move");
+        };
+        assert_eq!(op.dest, NodeRef::Path(vec![0, 1]));
+
+        let missing = EditOperation::replace(NodeId::new(9), EditableNode::Expr(x_parser::Expr::Literal(
+            x_parser::Literal::int(0),
+            x_parser::Span::single(FileId::new(0), x_parser::span::CharOffset::new(0)),
+        )));
+        assert!(matches!(ids.resolve_operation(&missing), Err(EditError::NodeNotFound { .. })));
+    }
+}
@@ -1,6 +1,7 @@
 //! Edit operations for AST manipulation
 
 use x_parser::{Item, Expr, Pattern, Type};
+use x_parser::persistent_ast::NodeId;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -13,38 +14,82 @@ pub enum EditOperation {
     Move(MoveOperation),
 }
 
-/// Insert a new node at a specific path
+/// The node an operation applies to
+///
+/// A path names whatever sits at a position and goes stale once an edit
+/// shifts its siblings; an id keeps naming the same node across edits but
+/// can only be resolved by a session, which tracks the ids.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NodeRef {
+    Path(Vec<usize>),
+    Id(NodeId),
+}
+
+impl NodeRef {
+    /// The path, if this reference is one
+    pub fn as_path(&self) -> Option<&[usize]> {
+        match self {
+            NodeRef::Path(path) => Some(path),
+            NodeRef::Id(_) => None,
+        }
+    }
+
+    /// Whether the two references may name the same node or one inside the
+    /// other; a path and an id cannot be compared without the tree, so they
+    /// are assumed to
+    pub fn overlaps(&self, other: &NodeRef) -> bool {
+        match (self, other) {
+            (NodeRef::Path(a), NodeRef::Path(b)) => paths_overlap(a, b),
+            (NodeRef::Id(a), NodeRef::Id(b)) => a == b,
+            _ => true,
+        }
+    }
+}
+
+impl From<Vec<usize>> for NodeRef {
+    fn from(path: Vec<usize>) -> Self {
+        NodeRef::Path(path)
+    }
+}
+
+impl From<NodeId> for NodeRef {
+    fn from(id: NodeId) -> Self {
+        NodeRef::Id(id)
+    }
+}
+
+/// Insert a new node at a specific position
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InsertOperation {
-    /// Path to the parent node where the new node will be inserted
-    pub path: Vec<usize>,
+    /// Path where the new node will be inserted, or the node it is inserted before
+    pub target: NodeRef,
     /// The node to insert
     pub node: EditableNode,
 }
 
-/// Delete a node at a specific path
+/// Delete a node
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DeleteOperation {
-    /// Path to the node to delete
-    pub path: Vec<usize>,
+    /// The node to delete
+    pub target: NodeRef,
 }
 
-/// Replace a node at a specific path
+/// Replace a node, keeping its id
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReplaceOperation {
-    /// Path to the node to replace
-    pub path: Vec<usize>,
+    /// The node to replace
+    pub target: NodeRef,
     /// The new node to replace it with
     pub new_node: EditableNode,
 }
 
-/// Move a node from one path to another
+/// Move a node from one position to another
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MoveOperation {
-    /// Path to the source node
-    pub source_path: Vec<usize>,
-    /// Path to the destination
-    pub dest_path: Vec<usize>,
+    /// The node to move
+    pub source: NodeRef,
+    /// Path to the destination, or the node to move it in front of
+    pub dest: NodeRef,
 }
 
 /// Structural transformation operations
@@ -95,53 +140,53 @@ pub enum EditableNode {
 
 impl EditOperation {
     /// Create a new insert operation
-    pub fn insert(path: Vec<usize>, node: EditableNode) -> Self {
-        Self::Insert(InsertOperation { path, node })
+    pub fn insert(target: impl Into<NodeRef>, node: EditableNode) -> Self {
+        Self::Insert(InsertOperation { target: target.into(), node })
     }
 
     /// Create a new delete operation
-    pub fn delete(path: Vec<usize>) -> Self {
-        Self::Delete(DeleteOperation { path })
+    pub fn delete(target: impl Into<NodeRef>) -> Self {
+        Self::Delete(DeleteOperation { target: target.into() })
     }
 
     /// Create a new replace operation
-    pub fn replace(path: Vec<usize>, new_node: EditableNode) -> Self {
-        Self::Replace(ReplaceOperation { path, new_node })
+    pub fn replace(target: impl Into<NodeRef>, new_node: EditableNode) -> Self {
+        Self::Replace(ReplaceOperation { target: target.into(), new_node })
     }
 
     /// Create a new move operation
-    pub fn move_node(source_path: Vec<usize>, dest_path: Vec<usize>) -> Self {
-        Self::Move(MoveOperation { source_path, dest_path })
+    pub fn move_node(source: impl Into<NodeRef>, dest: impl Into<NodeRef>) -> Self {
+        Self::Move(MoveOperation { source: source.into(), dest: dest.into() })
     }
 
-    /// Get the primary path affected by this operation
-    pub fn primary_path(&self) -> &[usize] {
+    /// Get the primary node affected by this operation
+    pub fn primary_target(&self) -> &NodeRef {
         match self {
-            EditOperation::Insert(op) => &op.path,
-            EditOperation::Delete(op) => &op.path,
-            EditOperation::Replace(op) => &op.path,
-            EditOperation::Move(op) => &op.source_path,
+            EditOperation::Insert(op) => &op.target,
+            EditOperation::Delete(op) => &op.target,
+            EditOperation::Replace(op) => &op.target,
+            EditOperation::Move(op) => &op.source,
         }
     }
 
-    /// Get all paths affected by this operation
-    pub fn affected_paths(&self) -> Vec<&[usize]> {
+    /// Get all nodes affected by this operation
+    pub fn affected_targets(&self) -> Vec<&NodeRef> {
         match self {
-            EditOperation::Insert(op) => vec![&op.path],
-            EditOperation::Delete(op) => vec![&op.path],
-            EditOperation::Replace(op) => vec![&op.path],
-            EditOperation::Move(op) => vec![&op.source_path, &op.dest_path],
+            EditOperation::Insert(op) => vec![&op.target],
+            EditOperation::Delete(op) => vec![&op.target],
+            EditOperation::Replace(op) => vec![&op.target],
+            EditOperation::Move(op) => vec![&op.source, &op.dest],
         }
     }
 
     /// Check if this operation conflicts with another operation
     pub fn conflicts_with(&self, other: &EditOperation) -> bool {
-        let self_paths = self.affected_paths();
-        let other_paths = other.affected_paths();
+        let self_targets = self.affected_targets();
+        let other_targets = other.affected_targets();
         
-        for self_path in self_paths {
-            for other_path in &other_paths {
-                if paths_overlap(self_path, other_path) {
+        for self_target in self_targets {
+            for other_target in &other_targets {
+                if self_target.overlaps(other_target) {
                     return true;
                 }
             }
@@ -174,26 +219,26 @@ impl EditOperationBuilder {
     }
 
     /// Add an insert operation
-    pub fn insert(mut self, path: Vec<usize>, node: EditableNode) -> Self {
-        self.operations.push(EditOperation::insert(path, node));
+    pub fn insert(mut self, target: impl Into<NodeRef>, node: EditableNode) -> Self {
+        self.operations.push(EditOperation::insert(target, node));
         self
     }
 
     /// Add a delete operation
-    pub fn delete(mut self, path: Vec<usize>) -> Self {
-        self.operations.push(EditOperation::delete(path));
+    pub fn delete(mut self, target: impl Into<NodeRef>) -> Self {
+        self.operations.push(EditOperation::delete(target));
         self
     }
 
     /// Add a replace operation
-    pub fn replace(mut self, path: Vec<usize>, new_node: EditableNode) -> Self {
-        self.operations.push(EditOperation::replace(path, new_node));
+    pub fn replace(mut self, target: impl Into<NodeRef>, new_node: EditableNode) -> Self {
+        self.operations.push(EditOperation::replace(target, new_node));
         self
     }
 
     /// Add a move operation
-    pub fn move_node(mut self, source_path: Vec<usize>, dest_path: Vec<usize>) -> Self {
-        self.operations.push(EditOperation::move_node(source_path, dest_path));
+    pub fn move_node(mut self, source: impl Into<NodeRef>, dest: impl Into<NodeRef>) -> Self {
+        self.operations.push(EditOperation::move_node(source, dest));
         self
     }
 
@@ -219,16 +264,16 @@ impl EditOperationBuilder {
 mod tests {
     use super::*;
     use x_parser::{Literal, Expr};
-    use x_parser::span::{Span, FileId, ByteOffset};
+    use x_parser::span::{Span, FileId, CharOffset};
 
     #[test]
     fn test_edit_operation_creation() {
-        let node = EditableNode::Expr(Expr::Literal(Literal::Integer(42), Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(2))));
+        let node = EditableNode::Expr(Expr::Literal(Literal::int(42), Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(2))));
         let op = EditOperation::insert(vec![0, 1], node);
         
         match op {
             EditOperation::Insert(insert_op) => {
-                assert_eq!(insert_op.path, vec![0, 1]);
+                assert_eq!(insert_op.target, NodeRef::Path(vec![0, 1]));
             }
             _ => panic!("Expected insert operation"),
         }
@@ -236,8 +281,8 @@ mod tests {
 
     #[test]
     fn test_operation_conflicts() {
-        let node1 = EditableNode::Expr(Expr::Literal(Literal::Integer(42), Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(2))));
-        let node2 = EditableNode::Expr(Expr::Literal(Literal::Bool(true), Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(4))));
+        let node1 = EditableNode::Expr(Expr::Literal(Literal::int(42), Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(2))));
+        let node2 = EditableNode::Expr(Expr::Literal(Literal::Bool(true), Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(4))));
         
         let op1 = EditOperation::insert(vec![0, 1], node1);
         let op2 = EditOperation::delete(vec![0, 1, 2]);
@@ -247,7 +292,7 @@ mod tests {
 
     #[test]
     fn test_operation_builder() {
-        let node = EditableNode::Expr(Expr::Literal(Literal::Integer(42), Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(2))));
+        let node = EditableNode::Expr(Expr::Literal(Literal::int(42), Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(2))));
         
         let operations = EditOperationBuilder::new()
             .insert(vec![0], node)
@@ -264,4 +309,12 @@ mod tests {
         assert!(!paths_overlap(&[0, 1], &[0, 2]));
         assert!(!paths_overlap(&[1], &[2]));
     }
+
+    #[test]
+    fn test_id_targets_conflict_only_with_the_same_node() {
+        let delete = |target: NodeRef| EditOperation::delete(target);
+        assert!(delete(NodeId::new(1).into()).conflicts_with(&delete(NodeId::new(1).into())));
+        assert!(!delete(NodeId::new(1).into()).conflicts_with(&delete(NodeId::new(2).into())));
+        assert!(delete(NodeId::new(1).into()).conflicts_with(&delete(vec![0, 3].into())));
+    }
 }
\ No newline at end of file
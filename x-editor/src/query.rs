@@ -14,6 +14,7 @@ pub enum AstQuery {
     // Basic structural queries
     FindByType { node_type: String },
     FindByPath { path: Vec<usize> },
+    ById { node_id: NodeId },
     FindByPattern { pattern: QueryPattern },
     GetChildren { node_id: NodeId },
     GetParent { node_id: NodeId },
@@ -60,6 +61,10 @@ pub enum QueryPattern {
     Value(String),
     /// Match nodes with specific symbol name
     Symbol(Symbol),
+    /// Match nodes whose name matches a glob (`*` and `?` wildcards)
+    NameMatches(String),
+    /// Match a pattern and record the matched node under a name
+    Capture { name: String, pattern: Box<QueryPattern> },
     /// Match nodes that are children of another pattern
     Child(Box<QueryPattern>),
     /// Match nodes that are descendants of another pattern
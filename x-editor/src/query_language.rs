@@ -0,0 +1,535 @@
+//! Textual AST query language
+//!
+//! Queries select nodes by kind, optionally constrained by name, and relate
+//! them to their ancestors with CSS-like combinators:
+//!
+//! ```text
+//! fn[name~"parse*"] > let > match     match directly inside a let directly inside parse* functions
+//! handler perform                     perform anywhere below a handler
+//! fn@f app[name="print"]@call         print calls, capturing the call and its function
+//! var[name="x"], lit                  alternatives
+//! ```
+//!
+//! Node kinds are `module`, `fn`, `value`, `type`, `effect`, `handler`, `test`,
+//! `lambda`, `let`, `app`, `var`, `lit`, `if`, `match`, `do`, `handle`,
+//! `resume`, `perform`, `ann`, and `*` for any node. Names are compared with
+//! `=` (exact) or `~` (glob with `*` and `?`). A query is parsed into a
+//! `QueryPattern` and can be evaluated against any compilation unit.
+
+use crate::query::QueryPattern;
+use x_parser::{CompilationUnit, DoStatement, Expr, Item, Span, Symbol};
+use thiserror::Error;
+
+/// Syntax error in a textual query
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("Invalid query at column {column}: {message}")]
+pub struct QueryParseError {
+    /// 1-based column of the offending character
+    pub column: usize,
+    pub message: String,
+}
+
+/// A node visited by the query engine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueryNode {
+    /// Node kind as spelled in queries
+    pub kind: &'static str,
+    /// Name of the node, for definitions, variables, calls and operations
+    pub name: Option<Symbol>,
+    pub span: Span,
+}
+
+/// A node selected by a query together with its named captures
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryMatch {
+    pub node: QueryNode,
+    /// Captured nodes, outermost first
+    pub captures: Vec<(String, QueryNode)>,
+}
+
+/// Parse a textual query into a `QueryPattern`
+pub fn parse_query(text: &str) -> Result<QueryPattern, QueryParseError> {
+    let mut parser = QueryParser {
+        chars: text.chars().collect(),
+        pos: 0,
+    };
+    let pattern = parser.alternatives()?;
+    parser.skip_whitespace();
+    if parser.pos < parser.chars.len() {
+        return Err(parser.error("unexpected character"));
+    }
+    Ok(pattern)
+}
+
+/// The nodes queries range over, in pre-order, each with the index of its parent
+pub fn syntax_tree(cu: &CompilationUnit) -> Vec<(QueryNode, Option<usize>)> {
+    let mut nodes = Vec::new();
+    collect_unit(cu, &mut nodes);
+    nodes
+}
+
+/// Evaluate a query against a compilation unit, returning matches in tree order
+pub fn run_query(cu: &CompilationUnit, pattern: &QueryPattern) -> Vec<QueryMatch> {
+    let nodes = syntax_tree(cu);
+
+    let mut matches = Vec::new();
+    for index in 0..nodes.len() {
+        let mut ancestors = Vec::new();
+        let mut parent = nodes[index].1;
+        while let Some(p) = parent {
+            ancestors.push(nodes[p].0);
+            parent = nodes[p].1;
+        }
+        ancestors.reverse();
+
+        let mut captures = Vec::new();
+        if matches_node(pattern, &nodes[index].0, &ancestors, &mut captures) {
+            captures.reverse();
+            matches.push(QueryMatch { node: nodes[index].0, captures });
+        }
+    }
+    matches
+}
+
+fn matches_node(
+    pattern: &QueryPattern,
+    node: &QueryNode,
+    ancestors: &[QueryNode],
+    captures: &mut Vec<(String, QueryNode)>,
+) -> bool {
+    let mark = captures.len();
+    let matched = match pattern {
+        QueryPattern::Any => true,
+        QueryPattern::Type(kind) => kind == node.kind,
+        QueryPattern::Symbol(name) => node.name == Some(*name),
+        QueryPattern::NameMatches(glob) => node.name.is_some_and(|name| glob_match(glob, name.as_str())),
+        QueryPattern::AtDepth { depth } => ancestors.len() == *depth,
+        QueryPattern::Child(parent) => match ancestors.split_last() {
+            Some((last, rest)) => matches_node(parent, last, rest, captures),
+            None => false,
+        },
+        QueryPattern::Descendant(ancestor) => (0..ancestors.len()).rev()
+            .any(|i| matches_node(ancestor, &ancestors[i], &ancestors[..i], captures)),
+        QueryPattern::And(patterns) => patterns.iter()
+            .all(|p| matches_node(p, node, ancestors, captures)),
+        QueryPattern::Or(patterns) => patterns.iter()
+            .any(|p| matches_node(p, node, ancestors, captures)),
+        QueryPattern::Not(inner) => {
+            let matched = matches_node(inner, node, ancestors, captures);
+            captures.truncate(mark);
+            !matched
+        }
+        QueryPattern::Capture { name, pattern } => {
+            let matched = matches_node(pattern, node, ancestors, captures);
+            if matched {
+                captures.push((name.clone(), *node));
+            }
+            matched
+        }
+        // Remaining patterns need semantic information the syntax tree lacks
+        QueryPattern::Value(_)
+        | QueryPattern::HasAnnotation { .. }
+        | QueryPattern::HasType { .. }
+        | QueryPattern::HasEffect { .. }
+        | QueryPattern::InRange { .. } => false,
+    };
+
+    if !matched {
+        captures.truncate(mark);
+    }
+    matched
+}
+
+/// Match `text` against a glob where `*` is any run and `?` any single character
+fn glob_match(glob: &str, text: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    // Classic backtracking over the most recent `*`
+    let (mut g, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if g < glob.len() && (glob[g] == '?' || glob[g] == text[t]) {
+            g += 1;
+            t += 1;
+        } else if g < glob.len() && glob[g] == '*' {
+            star = Some((g, t));
+            g += 1;
+        } else if let Some((sg, st)) = star {
+            g = sg + 1;
+            t = st + 1;
+            star = Some((sg, st + 1));
+        } else {
+            return false;
+        }
+    }
+    glob[g..].iter().all(|c| *c == '*')
+}
+
+struct QueryParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl QueryParser {
+    fn error(&self, message: &str) -> QueryParseError {
+        QueryParseError {
+            column: self.pos + 1,
+            message: message.to_string(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) -> bool {
+        let start = self.pos;
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+        self.pos > start
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), QueryParseError> {
+        if self.peek() == Some(expected) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{expected}'")))
+        }
+    }
+
+    /// `chain (',' chain)*`
+    fn alternatives(&mut self) -> Result<QueryPattern, QueryParseError> {
+        let mut alternatives = vec![self.chain()?];
+        while self.peek() == Some(',') {
+            self.pos += 1;
+            alternatives.push(self.chain()?);
+        }
+        Ok(if alternatives.len() == 1 {
+            alternatives.remove(0)
+        } else {
+            QueryPattern::Or(alternatives)
+        })
+    }
+
+    /// `step (('>' | whitespace) step)*`
+    fn chain(&mut self) -> Result<QueryPattern, QueryParseError> {
+        self.skip_whitespace();
+        let mut pattern = self.step()?;
+        loop {
+            let spaced = self.skip_whitespace();
+            let child = self.peek() == Some('>');
+            if child {
+                self.pos += 1;
+                self.skip_whitespace();
+            } else if !spaced || matches!(self.peek(), None | Some(',')) {
+                return Ok(pattern);
+            }
+
+            let step = self.step()?;
+            let relation = if child {
+                QueryPattern::Child(Box::new(pattern))
+            } else {
+                QueryPattern::Descendant(Box::new(pattern))
+            };
+            pattern = QueryPattern::And(vec![step, relation]);
+        }
+    }
+
+    /// `kind ('[' 'name' ('=' | '~') string ']')* ('@' ident)?`
+    fn step(&mut self) -> Result<QueryPattern, QueryParseError> {
+        let mut parts = Vec::new();
+        if self.peek() == Some('*') {
+            self.pos += 1;
+        } else {
+            let kind = self.ident().ok_or_else(|| self.error("expected a node kind or '*'"))?;
+            if !NODE_KINDS.contains(&kind.as_str()) {
+                self.pos -= kind.chars().count();
+                return Err(self.error(&format!("unknown node kind '{kind}'")));
+            }
+            parts.push(QueryPattern::Type(kind));
+        }
+
+        while self.peek() == Some('[') {
+            self.pos += 1;
+            self.skip_whitespace();
+            match self.ident().as_deref() {
+                Some("name") => {}
+                _ => return Err(self.error("expected attribute 'name'")),
+            }
+            self.skip_whitespace();
+            let op = self.peek();
+            if !matches!(op, Some('=' | '~')) {
+                return Err(self.error("expected '=' or '~'"));
+            }
+            self.pos += 1;
+            self.skip_whitespace();
+            let value = self.string()?;
+            self.skip_whitespace();
+            self.expect(']')?;
+
+            parts.push(if op == Some('=') {
+                QueryPattern::Symbol(Symbol::intern(&value))
+            } else {
+                QueryPattern::NameMatches(value)
+            });
+        }
+
+        let mut pattern = match parts.len() {
+            0 => QueryPattern::Any,
+            1 => parts.remove(0),
+            _ => QueryPattern::And(parts),
+        };
+
+        if self.peek() == Some('@') {
+            self.pos += 1;
+            let name = self.ident().ok_or_else(|| self.error("expected a capture name"))?;
+            pattern = QueryPattern::Capture { name, pattern: Box::new(pattern) };
+        }
+        Ok(pattern)
+    }
+
+    fn ident(&mut self) -> Option<String> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+            self.pos += 1;
+        }
+        (self.pos > start).then(|| self.chars[start..self.pos].iter().collect())
+    }
+
+    fn string(&mut self) -> Result<String, QueryParseError> {
+        self.expect('"')?;
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c != '"') {
+            self.pos += 1;
+        }
+        let value = self.chars[start..self.pos].iter().collect();
+        self.expect('"')?;
+        Ok(value)
+    }
+}
+
+const NODE_KINDS: &[&str] = &[
+    "module", "fn", "value", "type", "effect", "handler", "test", "lambda", "let", "app", "var",
+    "lit", "if", "match", "do", "handle", "resume", "perform", "ann",
+];
+
+/// Flatten the tree into `(node, parent)` pairs in pre-order
+fn collect_unit(cu: &CompilationUnit, nodes: &mut Vec<(QueryNode, Option<usize>)>) {
+    let module = &cu.module;
+    let root = push(nodes, None, "module", Some(Symbol::intern(&module.name.to_string())), module.span);
+
+    for item in &module.items {
+        match item.unconditional() {
+            Item::ValueDef(def) => {
+                let is_function = !def.parameters.is_empty() || matches!(def.body, Expr::Lambda { .. });
+                let kind = if is_function { "fn" } else { "value" };
+                let index = push(nodes, Some(root), kind, Some(def.name), def.span);
+
+                // The lambdas spelling out a function's parameters belong to the function
+                let mut body = &def.body;
+                while let Expr::Lambda { body: inner, .. } = body {
+                    body = inner;
+                }
+                collect_expr(body, index, nodes);
+            }
+            Item::TypeDef(def) => {
+                push(nodes, Some(root), "type", Some(def.name), def.span);
+            }
+            Item::EffectDef(def) => {
+                push(nodes, Some(root), "effect", Some(def.name), def.span);
+            }
+            Item::HandlerDef(def) => {
+                let index = push(nodes, Some(root), "handler", Some(def.name), def.span);
+                for handler in &def.handlers {
+                    collect_expr(&handler.body, index, nodes);
+                }
+                if let Some(ret) = &def.return_clause {
+                    collect_expr(&ret.body, index, nodes);
+                }
+            }
+            Item::TestDef(def) => {
+                let index = push(nodes, Some(root), "test", Some(def.name), def.span);
+                for expr in def.setup.iter().chain(def.teardown.iter()) {
+                    collect_expr(expr, index, nodes);
+                }
+                collect_expr(&def.body, index, nodes);
+            }
+            Item::BenchDef(def) => {
+                let index = push(nodes, Some(root), "bench", Some(def.name), def.span);
+                collect_expr(&def.body, index, nodes);
+            }
+            Item::ClassDef(def) => {
+                push(nodes, Some(root), "class", Some(def.name), def.span);
+            }
+            Item::InstanceDef(def) => {
+                let index = push(nodes, Some(root), "instance", Some(def.class), def.span);
+                for method in &def.methods {
+                    collect_expr(&method.body, index, nodes);
+                }
+            }
+            Item::ModuleTypeDef(_) | Item::InterfaceDef(_) | Item::Fixity(_) | Item::Conditional(_) => {}
+        }
+    }
+}
+
+fn collect_expr(expr: &Expr, parent: usize, nodes: &mut Vec<(QueryNode, Option<usize>)>) {
+    let (kind, name) = match expr {
+        Expr::Literal(..) => ("lit", None),
+        Expr::Error(_) => ("error", None),
+        Expr::Var(name, _) => ("var", Some(*name)),
+        Expr::App(func, _, _) => match func.as_ref() {
+            Expr::Var(name, _) => ("app", Some(*name)),
+            _ => ("app", None),
+        },
+        Expr::Lambda { .. } => ("lambda", None),
+        Expr::Let { .. } => ("let", None),
+        Expr::If { .. } => ("if", None),
+        Expr::Match { .. } => ("match", None),
+        Expr::Do { .. } => ("do", None),
+        Expr::Handle { .. } => ("handle", None),
+        Expr::Resume { .. } => ("resume", None),
+        Expr::Perform { operation, .. } => ("perform", Some(*operation)),
+        Expr::Ann { .. } => ("ann", None),
+        Expr::Record { .. } => ("record", None),
+        Expr::Project { field, .. } => ("project", Some(*field)),
+        Expr::RecordUpdate { .. } => ("update", None),
+        Expr::Tuple { .. } => ("tuple", None),
+        Expr::Pack { module, .. } => ("pack", Some(*module)),
+        Expr::Unpack { name, .. } => ("unpack", Some(*name)),
+    };
+    let index = push(nodes, Some(parent), kind, name, expr.span());
+
+    let mut child = |expr: &Expr| collect_expr(expr, index, nodes);
+    match expr {
+        Expr::Literal(..) | Expr::Var(..) | Expr::Pack { .. } | Expr::Error(_) => {}
+        Expr::App(func, args, _) => {
+            child(func);
+            args.iter().for_each(child);
+        }
+        Expr::Lambda { body, .. } => child(body),
+        Expr::Let { value, body, .. } => {
+            child(value);
+            child(body);
+        }
+        Expr::If { condition, then_branch, else_branch, .. } => {
+            child(condition);
+            child(then_branch);
+            child(else_branch);
+        }
+        Expr::Match { scrutinee, arms, .. } => {
+            child(scrutinee);
+            for arm in arms {
+                if let Some(guard) = &arm.guard {
+                    child(guard);
+                }
+                child(&arm.body);
+            }
+        }
+        Expr::Do { statements, .. } => {
+            for statement in statements {
+                match statement {
+                    DoStatement::Let { expr, .. } | DoStatement::Bind { expr, .. } | DoStatement::Expr(expr) => child(expr),
+                }
+            }
+        }
+        Expr::Handle { expr, handlers, return_clause, .. } => {
+            child(expr);
+            for handler in handlers {
+                child(&handler.body);
+            }
+            if let Some(ret) = return_clause {
+                child(&ret.body);
+            }
+        }
+        Expr::Resume { value, .. } => child(value),
+        Expr::Perform { args, .. } => args.iter().for_each(child),
+        Expr::Ann { expr, .. } | Expr::Project { record: expr, .. } => child(expr),
+        Expr::Record { fields, .. } => fields.iter().for_each(|(_, value)| child(value)),
+        Expr::Tuple { elements, .. } => elements.iter().for_each(child),
+        Expr::Unpack { value, body, .. } => {
+            child(value);
+            child(body);
+        }
+        Expr::RecordUpdate { record, fields, .. } => {
+            child(record);
+            fields.iter().for_each(|(_, value)| child(value));
+        }
+    }
+}
+
+fn push(
+    nodes: &mut Vec<(QueryNode, Option<usize>)>,
+    parent: Option<usize>,
+    kind: &'static str,
+    name: Option<Symbol>,
+    span: Span,
+) -> usize {
+    nodes.push((QueryNode { kind, name, span }, parent));
+    nodes.len() - 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x_parser::{parse_source, FileId, SyntaxStyle};
+
+    fn query(source: &str, text: &str) -> Vec<QueryMatch> {
+        let cu = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        run_query(&cu, &parse_query(text).unwrap())
+    }
+
+    fn names(matches: &[QueryMatch]) -> Vec<String> {
+        matches.iter()
+            .map(|m| m.node.name.map(|n| n.to_string()).unwrap_or_default())
+            .collect()
+    }
+
+    #[test]
+    fn test_child_and_descendant_combinators() {
+        let source = "module Test\nlet parse_a = fun x -> f (g x)\nlet other = fun y -> f y";
+
+        let direct = query(source, r#"fn[name~"parse*"] > app"#);
+        assert_eq!(names(&direct), ["f"]);
+
+        let nested = query(source, r#"fn[name~"parse*"] app"#);
+        assert_eq!(names(&nested), ["f", "g"]);
+
+        let all = query(source, r#"app[name="f"]"#);
+        assert_eq!(all.len(), 2);
+    }
+
+    #[test]
+    fn test_captures_and_alternatives() {
+        let source = "module Test\nlet main = fun x -> print x\nlet answer = 42";
+
+        let matches = query(source, r#"fn@f > app[name="print"]@call"#);
+        assert_eq!(matches.len(), 1);
+        let captured: Vec<(&str, &str)> = matches[0].captures.iter()
+            .map(|(name, node)| (name.as_str(), node.kind))
+            .collect();
+        assert_eq!(captured, [("f", "fn"), ("call", "app")]);
+
+        let either = query(source, "value, fn");
+        assert_eq!(names(&either), ["main", "answer"]);
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("parse*", "parse_expr"));
+        assert!(glob_match("*_expr", "parse_expr"));
+        assert!(glob_match("p?rse", "parse"));
+        assert!(!glob_match("parse*", "unparse"));
+    }
+
+    #[test]
+    fn test_query_syntax_errors() {
+        let err = parse_query("fn > ").unwrap_err();
+        assert_eq!(err.column, 6);
+        assert!(parse_query("function").is_err());
+        assert!(parse_query(r#"fn[name~parse]"#).is_err());
+        assert!(parse_query(r#"fn[name="x""#).is_err());
+    }
+}
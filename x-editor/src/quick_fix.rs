@@ -0,0 +1,313 @@
+//! Suggested fixes for type errors
+//!
+//! A fix is described twice: as edit operations on the AST, for clients that
+//! work on the tree, and as text edits that mirror them on the source, for
+//! the LSP server and `x check --fix`. Some fixes have no surface syntax to
+//! mirror (a `handle` block, for instance) and carry AST operations only.
+
+use crate::operations::{EditOperation, EditableNode};
+use crate::rename::{apply_rename, BindingKind, SymbolIndex};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use x_checker::{Type as CheckerType, TypeEnv, TypeError};
+use x_parser::{
+    CompilationUnit, EffectHandler, EffectRef, Expr, HandlerMode, Item, Literal, Pattern, Span, Symbol, Type,
+};
+
+/// A suggested change that resolves a diagnostic
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuickFix {
+    /// Short imperative description, e.g. "add missing handler for State effect"
+    pub title: String,
+    /// The fix as edits of the AST
+    pub operations: Vec<EditOperation>,
+    /// The same fix on the source text; empty when it cannot be written in source
+    pub text_edits: Vec<TextEdit>,
+}
+
+/// Replacement of the source text covered by `span`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TextEdit {
+    pub span: Span,
+    pub new_text: String,
+}
+
+impl QuickFix {
+    /// Whether the fix can be applied to source text
+    pub fn is_textual(&self) -> bool {
+        !self.text_edits.is_empty()
+    }
+}
+
+/// Suggest a fix for `error`, reported against `cu` parsed from `source`
+pub fn suggest_fix(cu: &CompilationUnit, source: &str, error: &TypeError) -> Option<QuickFix> {
+    match error {
+        TypeError::UnboundVariable { name, span } => replace_unbound(cu, source, *name, *span),
+        TypeError::AnnotationMismatch { found, annotation, .. } => {
+            fix_annotation(cu, found, *annotation)
+        }
+        TypeError::MissingHandler { effect, operation: Some(operation), span } => {
+            add_handler(cu, *effect, *operation, *span)
+        }
+        _ => None,
+    }
+}
+
+/// Apply the text edits of several fixes to `source`
+///
+/// Edits overlapping one already applied are skipped; the number of edits
+/// applied is returned with the new text.
+pub fn apply_text_edits<'a>(source: &str, edits: impl IntoIterator<Item = &'a TextEdit>) -> (String, usize) {
+    let mut edits: Vec<&TextEdit> = edits.into_iter().collect();
+    edits.sort_by_key(|edit| std::cmp::Reverse(edit.span.start));
+
+    let mut chars: Vec<char> = source.chars().collect();
+    let mut applied = 0;
+    let mut limit = chars.len();
+    for edit in edits {
+        let (start, end) = (edit.span.start.as_u32() as usize, edit.span.end.as_u32() as usize);
+        if end > limit || start > end {
+            continue;
+        }
+        chars.splice(start..end, edit.new_text.chars());
+        limit = start;
+        applied += 1;
+    }
+    (chars.into_iter().collect(), applied)
+}
+
+/// Replace an unbound name with the closest name in scope
+fn replace_unbound(cu: &CompilationUnit, source: &str, name: Symbol, span: Span) -> Option<QuickFix> {
+    let (index, item) = enclosing_item(cu, span)?;
+
+    // Module-level names, and names bound earlier in the same definition
+    let symbols = SymbolIndex::build(cu, source);
+    let candidate = symbols.occurrences().iter()
+        .filter(|occ| occ.is_definition && occ.name != name)
+        .filter(|occ| {
+            symbols.binding_kind(occ) == Some(BindingKind::Module)
+                || (item.span().contains(occ.span.start) && occ.span.start < span.start)
+        })
+        .map(|occ| (edit_distance(name.as_str(), occ.name.as_str()), occ.name))
+        .filter(|(distance, _)| *distance <= max_distance(name.as_str()))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)?;
+
+    let chars: Vec<char> = source.chars().collect();
+    let mut renamed = cu.clone();
+    apply_rename(&mut renamed, &chars, &HashSet::from([span.start]), name, candidate);
+
+    Some(QuickFix {
+        title: format!("replace `{name}` with `{candidate}`"),
+        operations: vec![replace_item(index, renamed.module.items.swap_remove(index))],
+        text_edits: vec![TextEdit { span, new_text: candidate.to_string() }],
+    })
+}
+
+/// Change a definition's annotation to the type its body has
+fn fix_annotation(cu: &CompilationUnit, found: &CheckerType, annotation: Span) -> Option<QuickFix> {
+    let (index, item) = enclosing_item(cu, annotation)?;
+    let Item::ValueDef(def) = item else {
+        return None;
+    };
+    let typ = surface_type(found, annotation)?;
+
+    let mut def = def.clone();
+    def.type_annotation = Some(typ);
+    Some(QuickFix {
+        title: format!("change the annotation to `{found}`"),
+        operations: vec![replace_item(index, Item::ValueDef(def))],
+        text_edits: vec![TextEdit { span: annotation, new_text: found.to_string() }],
+    })
+}
+
+/// Wrap the body of the definition performing `operation` in a handler stub
+/// that resumes with `()`
+fn add_handler(cu: &CompilationUnit, effect: Symbol, operation: Symbol, span: Span) -> Option<QuickFix> {
+    let (index, item) = enclosing_item(cu, span)?;
+    let Item::ValueDef(def) = item else {
+        return None;
+    };
+
+    let body_span = def.body.span();
+    let parameters = (0..operation_arity(cu, effect, operation))
+        .map(|_| Pattern::Wildcard(body_span))
+        .collect();
+    let clause = EffectHandler {
+        effect: EffectRef { name: effect, args: Vec::new(), span: body_span },
+        operation,
+        parameters,
+        continuation: Some(Symbol::intern("k")),
+        body: Expr::Resume {
+            value: Box::new(Expr::Literal(Literal::Unit, body_span)),
+            span: body_span,
+        },
+        span: body_span,
+    };
+
+    let mut def = def.clone();
+    def.body = Expr::Handle {
+        expr: Box::new(def.body),
+        mode: HandlerMode::Deep,
+        handlers: vec![clause],
+        return_clause: None,
+        span: body_span,
+    };
+    Some(QuickFix {
+        title: format!("add missing handler for {effect} effect"),
+        operations: vec![replace_item(index, Item::ValueDef(def))],
+        text_edits: Vec::new(),
+    })
+}
+
+/// The top-level item containing `span`, with its index
+fn enclosing_item(cu: &CompilationUnit, span: Span) -> Option<(usize, &Item)> {
+    cu.module.items.iter()
+        .enumerate()
+        .find(|(_, item)| item.span().contains_position(span))
+}
+
+/// Replace module item `index`; `[0, i]` addresses the module's items
+fn replace_item(index: usize, item: Item) -> EditOperation {
+    EditOperation::replace(vec![0, index], EditableNode::Item(item))
+}
+
+/// Number of arguments `effect.operation` takes, from the module's effect
+/// definitions or the built-in effects
+fn operation_arity(cu: &CompilationUnit, effect: Symbol, operation: Symbol) -> usize {
+    let declared = cu.module.items.iter()
+        .filter_map(|item| match item {
+            Item::EffectDef(def) if def.name == effect => Some(def),
+            _ => None,
+        })
+        .flat_map(|def| &def.operations)
+        .find(|op| op.name == operation)
+        .map(|op| op.parameters.len());
+
+    declared.or_else(|| {
+        TypeEnv::new().effects.get(&effect)?
+            .operations.iter()
+            .find(|op| op.name == operation)
+            .map(|op| op.params.len())
+    })
+    .unwrap_or(0)
+}
+
+/// Written form of a checker type built from constructors only
+fn surface_type(typ: &CheckerType, span: Span) -> Option<Type> {
+    match typ {
+        CheckerType::Con(name) => Some(Type::Con(*name, span)),
+        CheckerType::App(con, args) => Some(Type::App(
+            Box::new(surface_type(con, span)?),
+            args.iter().map(|arg| surface_type(arg, span)).collect::<Option<_>>()?,
+            span,
+        )),
+        _ => None,
+    }
+}
+
+/// Largest edit distance at which a name is still offered as a replacement
+fn max_distance(name: &str) -> usize {
+    if name.chars().count() <= 3 { 1 } else { 2 }
+}
+
+/// Levenshtein distance between two names
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operations::NodeRef;
+    use x_parser::span::{CharOffset, FileId};
+    use x_parser::{parse_source, SyntaxStyle};
+
+    fn fixes(source: &str) -> Vec<QuickFix> {
+        let cu = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        x_checker::type_check(&cu).errors.iter()
+            .filter_map(|error| suggest_fix(&cu, source, error))
+            .collect()
+    }
+
+    #[test]
+    fn test_unbound_variable_suggests_closest_name() {
+        let source = "module Test\nlet count = 1\nlet next = fun x -> cuont + x";
+        let fixes = fixes(source);
+
+        assert_eq!(fixes.len(), 1);
+        assert_eq!(fixes[0].title, "replace `cuont` with `count`");
+        let (fixed, applied) = apply_text_edits(source, &fixes[0].text_edits);
+        assert_eq!(applied, 1);
+        assert_eq!(fixed, "module Test\nlet count = 1\nlet next = fun x -> count + x");
+
+        let EditOperation::Replace(op) = &fixes[0].operations[0] else {
+            panic!("expected a replace operation");
+        };
+        assert_eq!(op.target, NodeRef::Path(vec![0, 1]));
+    }
+
+    #[test]
+    fn test_annotation_mismatch_rewrites_annotation() {
+        let source = "module Test\nlet x : Int = true";
+        let fixes = fixes(source);
+
+        assert_eq!(fixes[0].title, "change the annotation to `Bool`");
+        let (fixed, _) = apply_text_edits(source, fixes.iter().flat_map(|fix| &fix.text_edits));
+        assert_eq!(fixed, "module Test\nlet x : Bool = true");
+    }
+
+    #[test]
+    fn test_missing_handler_wraps_body() {
+        let mut cu = parse_source("module Test\nlet x = 1", FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let span = Span::new(FileId::new(0), CharOffset(20), CharOffset(21));
+        if let Item::ValueDef(def) = &mut cu.module.items[0] {
+            def.body = Expr::Perform {
+                effect: x_parser::symbol::symbols::STATE(),
+                operation: x_parser::symbol::symbols::PUT(),
+                args: vec![Expr::Literal(Literal::int(1), span)],
+                span,
+            };
+        }
+        let error = TypeError::MissingHandler {
+            effect: x_parser::symbol::symbols::STATE(),
+            operation: Some(x_parser::symbol::symbols::PUT()),
+            span,
+        };
+
+        let fix = suggest_fix(&cu, "", &error).unwrap();
+        assert_eq!(fix.title, "add missing handler for State effect");
+        assert!(!fix.is_textual());
+        let EditOperation::Replace(op) = &fix.operations[0] else {
+            panic!("expected a replace operation");
+        };
+        let EditableNode::Item(Item::ValueDef(def)) = &op.new_node else {
+            panic!("expected a value definition");
+        };
+        let Expr::Handle { handlers, .. } = &def.body else {
+            panic!("expected the body to be handled");
+        };
+        assert_eq!(handlers[0].parameters.len(), 1);
+    }
+
+    #[test]
+    fn test_overlapping_edits_are_skipped() {
+        let span = |start, end| Span::new(FileId::new(0), CharOffset(start), CharOffset(end));
+        let edits = [
+            TextEdit { span: span(0, 3), new_text: "one".to_string() },
+            TextEdit { span: span(2, 5), new_text: "two".to_string() },
+        ];
+        let (text, applied) = apply_text_edits("abcdef", &edits);
+        assert_eq!((text.as_str(), applied), ("abtwof", 1));
+    }
+}
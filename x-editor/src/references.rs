@@ -0,0 +1,270 @@
+//! Find all references to a definition across a project
+//!
+//! A top-level value is referred to by name inside its own module, resolved
+//! with the same scope rules as renaming so that shadowing bindings are not
+//! mistaken for it, and from other modules through their imports: by its
+//! plain or aliased name when imported selectively or by wildcard, and as
+//! `Module.name` (or `Alias.name`) when the module is imported qualified.
+//! Local bindings are only ever referred to within their own file.
+
+use crate::rename::{BindingKind, SymbolIndex};
+use x_parser::span::CharOffset;
+use x_parser::{CompilationUnit, DoStatement, Expr, ExportKind, Import, ImportKind, Item, Span, Symbol};
+
+/// A parsed source file taking part in a search
+#[derive(Debug, Clone, Copy)]
+pub struct SourceFile<'a> {
+    pub unit: &'a CompilationUnit,
+    pub source: &'a str,
+}
+
+/// A use of the symbol, or its definition
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reference {
+    /// Span covering exactly the name; its file id tells the files apart
+    pub span: Span,
+    pub is_definition: bool,
+}
+
+/// Every reference to the top-level value `symbol`, written either as a
+/// plain name or qualified with the name of its module (`Math.square`),
+/// in the files of `scope`
+pub fn find_references(symbol: &str, scope: &[SourceFile]) -> Vec<Reference> {
+    let (module, name) = match symbol.rsplit_once('.') {
+        Some((module, name)) => (Some(module), Symbol::intern(name)),
+        None => (None, Symbol::intern(symbol)),
+    };
+
+    let indices: Vec<SymbolIndex> = scope.iter().map(|file| SymbolIndex::build(file.unit, file.source)).collect();
+    let mut references = Vec::new();
+    for (file, index) in scope.iter().zip(&indices) {
+        let module_name = file.unit.module.name.to_string();
+        if module.is_some_and(|module| module != module_name) || !defines(file.unit, name) {
+            continue;
+        }
+        references.extend(local_references(index, name));
+        for (other, other_index) in scope.iter().zip(&indices) {
+            if other.unit.module.name.to_string() == module_name {
+                continue;
+            }
+            for import in other.unit.module.imports.iter().filter(|import| import.module_path.to_string() == module_name) {
+                references.extend(imported_references(other.unit, other_index, import, name));
+            }
+        }
+    }
+
+    references.sort_by_key(|reference| (reference.span.file_id, reference.span.start));
+    references.dedup();
+    references
+}
+
+/// Every reference to whatever the name at `offset` in `scope[file]` refers to
+pub fn references_at(scope: &[SourceFile], file: usize, offset: CharOffset) -> Vec<Reference> {
+    let Some(unit) = scope.get(file).map(|source| source.unit) else { return Vec::new() };
+    let index = SymbolIndex::build(unit, scope[file].source);
+    let Some(occurrence) = index.occurrence_at(offset) else { return Vec::new() };
+
+    match index.binding_kind(occurrence) {
+        Some(BindingKind::Module) => {
+            find_references(&format!("{}.{}", unit.module.name, occurrence.name), scope)
+        }
+        Some(_) => index.related(occurrence).iter()
+            .map(|occ| Reference { span: occ.span, is_definition: occ.is_definition })
+            .collect(),
+        // A free name may have been imported selectively
+        None => unit.module.imports.iter()
+            .find_map(|import| match &import.kind {
+                ImportKind::Selective(items) => items.iter()
+                    .find(|item| item.alias.unwrap_or(item.name) == occurrence.name)
+                    .map(|item| format!("{}.{}", import.module_path, item.name)),
+                _ => None,
+            })
+            .map(|symbol| find_references(&symbol, scope))
+            .unwrap_or_default(),
+    }
+}
+
+fn defines(unit: &CompilationUnit, name: Symbol) -> bool {
+    unit.module.items.iter().any(|item| matches!(item, Item::ValueDef(def) if def.name == name))
+}
+
+/// Occurrences of the module-level binding of `name` within its own module
+fn local_references(index: &SymbolIndex, name: Symbol) -> Vec<Reference> {
+    index.occurrences().iter()
+        .find(|occ| occ.name == name && index.binding_kind(occ) == Some(BindingKind::Module))
+        .map(|occ| index.related(occ))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|occ| Reference { span: occ.span, is_definition: occ.is_definition })
+        .collect()
+}
+
+/// References to `name` that `unit` makes through `import`
+fn imported_references(unit: &CompilationUnit, index: &SymbolIndex, import: &Import, name: Symbol) -> Vec<Reference> {
+    let mut references = Vec::new();
+    let free_uses = |local: Symbol, references: &mut Vec<Reference>| {
+        references.extend(index.occurrences().iter()
+            .filter(|occ| occ.name == local && !occ.is_definition && index.binding_kind(occ).is_none())
+            .map(|occ| Reference { span: occ.span, is_definition: false }));
+    };
+
+    match &import.kind {
+        ImportKind::Selective(items) => {
+            for item in items.iter().filter(|item| item.kind == ExportKind::Value && item.name == name) {
+                references.push(Reference {
+                    span: index.name_span(item.span.file_id, item.span.start, name),
+                    is_definition: false,
+                });
+                free_uses(item.alias.unwrap_or(name), &mut references);
+            }
+        }
+        ImportKind::Wildcard => free_uses(name, &mut references),
+        ImportKind::Interface { .. } | ImportKind::Core { .. } => {}
+        _ => {
+            let qualifier = import.alias.map_or_else(|| import.module_path.to_string(), |alias| alias.to_string());
+            let mut visit = |expr: &Expr| {
+                let Expr::Project { record, field, span } = expr else { return };
+                if *field != name || qualified_name(record).as_deref() != Some(qualifier.as_str()) {
+                    return;
+                }
+                // The qualifier must not be shadowed by a local binding
+                let shadowed = index.occurrence_at(span.start).is_some_and(|occ| index.binding_kind(occ).is_some());
+                if !shadowed {
+                    references.push(Reference { span: index.name_span_ending_at(span.file_id, span.end, name), is_definition: false });
+                }
+            };
+            for item in &unit.module.items {
+                for expr in item_exprs(item) {
+                    visit_exprs(expr, &mut visit);
+                }
+            }
+        }
+    }
+    references
+}
+
+/// `A.B.C` for a chain of projections from a variable
+fn qualified_name(expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::Var(name, _) => Some(name.to_string()),
+        Expr::Project { record, field, .. } => Some(format!("{}.{field}", qualified_name(record)?)),
+        _ => None,
+    }
+}
+
+/// The expressions making up the body of an item
+pub fn item_exprs(item: &Item) -> Vec<&Expr> {
+    match item {
+        Item::ValueDef(def) => vec![&def.body],
+        Item::TestDef(test) => test.setup.iter().map(|e| &**e)
+            .chain(std::iter::once(&test.body))
+            .chain(test.teardown.iter().map(|e| &**e))
+            .collect(),
+        Item::BenchDef(bench) => vec![&bench.body],
+        Item::HandlerDef(handler) => handler.handlers.iter().map(|op| &op.body)
+            .chain(handler.return_clause.iter().map(|ret| &*ret.body))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Call `visit` on `expr` and every expression inside it
+pub fn visit_exprs<'a>(expr: &'a Expr, visit: &mut impl FnMut(&'a Expr)) {
+    visit(expr);
+    for sub_expr in sub_exprs(expr) {
+        visit_exprs(sub_expr, visit);
+    }
+}
+
+/// The expressions directly inside `expr`, in source order
+pub fn sub_exprs(expr: &Expr) -> Vec<&Expr> {
+    match expr {
+        Expr::Literal(..) | Expr::Var(..) | Expr::Pack { .. } | Expr::Error(_) => Vec::new(),
+        Expr::App(func, args, _) => std::iter::once(&**func).chain(args).collect(),
+        Expr::Lambda { body, .. } => vec![body],
+        Expr::Let { value, body, .. } => vec![value, body],
+        Expr::If { condition, then_branch, else_branch, .. } => vec![condition, then_branch, else_branch],
+        Expr::Match { scrutinee, arms, .. } => std::iter::once(&**scrutinee)
+            .chain(arms.iter().flat_map(|arm| arm.guard.iter().map(|guard| &**guard).chain(std::iter::once(&arm.body))))
+            .collect(),
+        Expr::Do { statements, .. } => statements.iter()
+            .map(|statement| match statement {
+                DoStatement::Let { expr, .. } | DoStatement::Bind { expr, .. } | DoStatement::Expr(expr) => expr,
+            })
+            .collect(),
+        Expr::Handle { expr, handlers, return_clause, .. } => std::iter::once(&**expr)
+            .chain(handlers.iter().map(|handler| &handler.body))
+            .chain(return_clause.iter().map(|ret| &*ret.body))
+            .collect(),
+        Expr::Resume { value, .. } => vec![value],
+        Expr::Perform { args, .. } => args.iter().collect(),
+        Expr::Ann { expr, .. } | Expr::Project { record: expr, .. } => vec![expr],
+        Expr::Record { fields, .. } => fields.iter().map(|(_, value)| value).collect(),
+        Expr::Tuple { elements, .. } => elements.iter().collect(),
+        Expr::Unpack { value, body, .. } => vec![value, body],
+        Expr::RecordUpdate { record, fields, .. } => std::iter::once(&**record)
+            .chain(fields.iter().map(|(_, value)| value))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x_parser::{parse_source, FileId, SyntaxStyle};
+
+    const MATH: &str = "module Math\nlet square = fun x -> x * x\nlet cube = fun x -> x * square x";
+    const APP: &str = "module App\nimport Math { square as sq }\nimport Math\n\
+        let a = sq 2\nlet b = Math.square 3\nlet c = fun sq -> sq 4\nlet d = fun square -> square 5";
+
+    fn parse(source: &str, file: u32) -> CompilationUnit {
+        parse_source(source, FileId::new(file), SyntaxStyle::SExpression).unwrap()
+    }
+
+    /// (file, text at the span) of every reference
+    fn located(references: &[Reference], sources: &[&str]) -> Vec<(u32, String)> {
+        references.iter()
+            .map(|reference| {
+                let source = sources[reference.span.file_id.as_u32() as usize];
+                let (start, end) = (reference.span.start.as_u32() as usize, reference.span.end.as_u32() as usize);
+                let line = source[..start].matches('\n').count();
+                (reference.span.file_id.as_u32(), format!("{}@{line}", &source[start..end]))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_references_across_modules() {
+        let (math, app) = (parse(MATH, 0), parse(APP, 1));
+        let scope = [SourceFile { unit: &math, source: MATH }, SourceFile { unit: &app, source: APP }];
+
+        let references = find_references("Math.square", &scope);
+        assert_eq!(located(&references, &[MATH, APP]), vec![
+            (0, "square@1".to_string()),
+            (0, "square@2".to_string()),
+            (1, "square@1".to_string()),
+            (1, "sq@3".to_string()),
+            (1, "square@4".to_string()),
+        ]);
+        assert!(references[0].is_definition && !references[1].is_definition);
+        assert_eq!(find_references("square", &scope), references);
+        assert!(find_references("Other.square", &scope).is_empty());
+    }
+
+    #[test]
+    fn test_references_at_position() {
+        let (math, app) = (parse(MATH, 0), parse(APP, 1));
+        let scope = [SourceFile { unit: &math, source: MATH }, SourceFile { unit: &app, source: APP }];
+
+        // On the imported alias in `let a = sq 2`
+        let offset = CharOffset::new(APP.find("sq 2").unwrap() as u32);
+        assert_eq!(references_at(&scope, 1, offset).len(), 5);
+
+        // On the lambda parameter shadowing the import
+        let offset = CharOffset::new(APP.find("sq 4").unwrap() as u32);
+        assert_eq!(located(&references_at(&scope, 1, offset), &[MATH, APP]), vec![
+            (1, "sq@5".to_string()),
+            (1, "sq@5".to_string()),
+        ]);
+    }
+}
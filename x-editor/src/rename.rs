@@ -0,0 +1,804 @@
+//! Scope-aware symbol renaming
+//!
+//! Names are resolved against the AST rather than matched textually: each
+//! occurrence is linked to the binding it refers to, honouring shadowing by
+//! `let`, lambda, match and handler bindings as well as module-level scope.
+//! Renaming rewrites the AST and reports the source spans that changed so
+//! that text-based clients (e.g. the LSP server) can mirror the edit.
+
+use crate::ast_editor::EditError;
+use x_parser::span::CharOffset;
+use x_parser::token::keyword_to_token;
+use x_parser::{
+    CompilationUnit, DoStatement, ExportKind, Expr, FileId, Item, Lexer, Pattern, Span, Symbol, TokenKind,
+};
+use std::collections::{HashMap, HashSet};
+
+/// Identifier of a binding site discovered during resolution
+type BindingId = usize;
+
+/// A single name occurrence in the source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Occurrence {
+    /// Span covering exactly the identifier
+    pub span: Span,
+    /// The name as written
+    pub name: Symbol,
+    /// Whether this occurrence introduces the binding
+    pub is_definition: bool,
+    binding: Option<BindingId>,
+}
+
+/// Outcome of a successful rename
+#[derive(Debug, Clone)]
+pub struct RenameResult {
+    pub old_name: Symbol,
+    pub new_name: Symbol,
+    /// Source spans of every renamed occurrence, in source order
+    pub locations: Vec<Span>,
+}
+
+/// All name occurrences of a compilation unit linked to their bindings
+#[derive(Debug, Default)]
+pub struct SymbolIndex {
+    occurrences: Vec<Occurrence>,
+    /// Bindings whose defining name has no source location (e.g. handler continuations)
+    unlocated: HashSet<BindingId>,
+    /// Kind of each binding, indexed by `BindingId`
+    kinds: Vec<BindingKind>,
+    /// Local bindings whose name was already bound in an enclosing scope
+    shadowing: HashSet<BindingId>,
+    /// Spans of the identifier tokens of the source, in order
+    names: Vec<Span>,
+}
+
+/// What introduced a binding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindingKind {
+    /// Top-level value definition
+    Module,
+    /// Function, lambda or handler parameter
+    Parameter,
+    /// Local `let`, `match` or `do` binding
+    Local,
+}
+
+impl SymbolIndex {
+    /// Resolve every name in `cu`; `source` is needed to locate definition names
+    pub fn build(cu: &CompilationUnit, source: &str) -> Self {
+        let names = Lexer::new(source, cu.span.file_id).tokenize()
+            .map(|tokens| tokens.into_iter()
+                .filter(|token| matches!(token.kind, TokenKind::Ident(_)))
+                .map(|token| token.span)
+                .collect())
+            .unwrap_or_default();
+        let mut resolver = Resolver {
+            chars: source.chars().collect(),
+            index: SymbolIndex { names, ..SymbolIndex::default() },
+            scopes: Vec::new(),
+            next_binding: 0,
+        };
+        resolver.resolve_unit(cu);
+        resolver.index.occurrences.sort_by_key(|occ| occ.span.start);
+        resolver.index
+    }
+
+    /// Every occurrence, in source order
+    pub fn occurrences(&self) -> &[Occurrence] {
+        &self.occurrences
+    }
+
+    /// The occurrence whose identifier contains `offset`
+    pub fn occurrence_at(&self, offset: CharOffset) -> Option<&Occurrence> {
+        self.occurrences.iter()
+            .find(|occ| occ.span.start <= offset && offset <= occ.span.end)
+    }
+
+    /// Kind of the binding `occurrence` resolves to, if any
+    pub fn binding_kind(&self, occurrence: &Occurrence) -> Option<BindingKind> {
+        occurrence.binding.map(|binding| self.kinds[binding])
+    }
+
+    /// Whether `occurrence` resolves to a local binding that hides an
+    /// outer one of the same name
+    pub fn shadows(&self, occurrence: &Occurrence) -> bool {
+        occurrence.binding.is_some_and(|binding| self.shadowing.contains(&binding))
+    }
+
+    /// Span of the identifier starting at `start`
+    ///
+    /// Names are interned in NFC, so the length of `name` may differ from
+    /// what the source spells; it is only used when no identifier token
+    /// starts there.
+    pub(crate) fn name_span(&self, file_id: FileId, start: CharOffset, name: Symbol) -> Span {
+        let end = match self.names.binary_search_by_key(&start, |span| span.start) {
+            Ok(i) => self.names[i].end,
+            Err(_) => start.advance(name.as_str().chars().count() as u32),
+        };
+        Span::new(file_id, start, end)
+    }
+
+    /// Span of the identifier ending at `end`, as `name_span` does for starts
+    pub(crate) fn name_span_ending_at(&self, file_id: FileId, end: CharOffset, name: Symbol) -> Span {
+        let start = match self.names.binary_search_by_key(&end, |span| span.end) {
+            Ok(i) => self.names[i].start,
+            Err(_) => CharOffset::new(end.as_u32() - name.as_str().chars().count() as u32),
+        };
+        Span::new(file_id, start, end)
+    }
+
+    /// All occurrences referring to the same binding as `occurrence`
+    pub fn related(&self, occurrence: &Occurrence) -> Vec<Occurrence> {
+        match occurrence.binding {
+            Some(binding) => self.occurrences.iter()
+                .filter(|occ| occ.binding == Some(binding))
+                .copied()
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Rename the symbol at `offset`, updating `ast` in place
+pub fn rename_symbol(
+    ast: &mut CompilationUnit,
+    source: &str,
+    offset: CharOffset,
+    new_name: &str,
+) -> Result<RenameResult, EditError> {
+    if !is_valid_identifier(new_name) {
+        return Err(EditError::InvalidIdentifier { name: new_name.to_string() });
+    }
+
+    let index = SymbolIndex::build(ast, source);
+    let target = index.occurrence_at(offset)
+        .filter(|occ| occ.binding.is_some_and(|b| !index.unlocated.contains(&b)))
+        .ok_or(EditError::NoSymbolAtPosition { offset: offset.as_u32() })?;
+
+    let old_name = target.name;
+    let new_name = Symbol::intern(new_name);
+    let locations: Vec<Span> = index.related(target).iter().map(|occ| occ.span).collect();
+
+    if old_name == new_name {
+        return Ok(RenameResult { old_name, new_name, locations });
+    }
+
+    // Rename a copy first and make sure every occurrence still resolves to the
+    // same binding; otherwise the new name would capture or be captured.
+    let starts: HashSet<CharOffset> = locations.iter().map(|span| span.start).collect();
+    let chars: Vec<char> = source.chars().collect();
+    let mut renamed = ast.clone();
+    apply_rename(&mut renamed, &chars, &starts, old_name, new_name);
+
+    let after = SymbolIndex::build(&renamed, source);
+    if !same_bindings(&index, &after) {
+        return Err(EditError::RenameConflict { name: new_name.to_string() });
+    }
+
+    *ast = renamed;
+    Ok(RenameResult { old_name, new_name, locations })
+}
+
+/// Point the references to the module-level value `old` at `new` instead,
+/// leaving its definition, its export and any local binding of `old` alone
+///
+/// References to a name the module does not define, such as an imported
+/// one, are replaced as well. Returns the source spans of the replaced
+/// references, and fails if a local binding would capture `new` at one.
+pub fn replace_references(
+    ast: &mut CompilationUnit,
+    source: &str,
+    old: Symbol,
+    new: &str,
+) -> Result<Vec<Span>, EditError> {
+    if !is_valid_identifier(new) {
+        return Err(EditError::InvalidIdentifier { name: new.to_string() });
+    }
+
+    let exports: HashSet<CharOffset> = ast.module.exports.iter()
+        .flat_map(|exports| exports.items.iter().map(|export| export.span.start))
+        .collect();
+    let index = SymbolIndex::build(ast, source);
+    let locations: Vec<Span> = index.occurrences.iter()
+        .filter(|occ| occ.name == old && !occ.is_definition && !exports.contains(&occ.span.start))
+        .filter(|occ| matches!(index.binding_kind(occ), None | Some(BindingKind::Module)))
+        .map(|occ| occ.span)
+        .collect();
+
+    let new = Symbol::intern(new);
+    if locations.is_empty() || old == new {
+        return Ok(locations);
+    }
+
+    let starts: HashSet<CharOffset> = locations.iter().map(|span| span.start).collect();
+    let chars: Vec<char> = source.chars().collect();
+    let mut replaced = ast.clone();
+    apply_rename(&mut replaced, &chars, &starts, old, new);
+
+    let after = SymbolIndex::build(&replaced, source);
+    let captured = after.occurrences.iter()
+        .filter(|occ| starts.contains(&occ.span.start))
+        .any(|occ| matches!(after.binding_kind(occ), Some(BindingKind::Parameter | BindingKind::Local)));
+    if captured {
+        return Err(EditError::RenameConflict { name: new.to_string() });
+    }
+
+    *ast = replaced;
+    Ok(locations)
+}
+
+/// Check whether `name` lexes as a plain identifier
+pub fn is_valid_identifier(name: &str) -> bool {
+    is_identifier_like(name) && name != "_" && keyword_to_token(name).is_none()
+}
+
+pub(crate) fn is_identifier_like(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '\'')
+}
+
+/// Compare the binding structure of two indices over the same tree shape
+fn same_bindings(before: &SymbolIndex, after: &SymbolIndex) -> bool {
+    let mut mapping: HashMap<Option<BindingId>, Option<BindingId>> = HashMap::new();
+    before.occurrences.len() == after.occurrences.len()
+        && before.occurrences.iter().zip(&after.occurrences).all(|(b, a)| {
+            *mapping.entry(b.binding).or_insert(a.binding) == a.binding
+                && (b.binding.is_some() == a.binding.is_some())
+        })
+        && {
+            // The mapping must be injective as well, so no two bindings merge
+            let targets: HashSet<_> = mapping.values().collect();
+            targets.len() == mapping.len()
+        }
+}
+
+/// Locate the name of a `let` definition: the identifier following the keyword
+///
+/// Only the layout is checked, not the name itself, so the original source
+/// can still be used to locate names in a tree that has already been renamed.
+pub(crate) fn definition_name_start(chars: &[char], def_span: Span) -> Option<CharOffset> {
+    let mut pos = def_span.start.as_u32() as usize;
+    if chars.get(pos..pos + 3)? != ['l', 'e', 't'] {
+        return None;
+    }
+    pos += 3;
+    while chars.get(pos).is_some_and(|c| c.is_whitespace()) {
+        pos += 1;
+    }
+
+    chars.get(pos)
+        .is_some_and(|c| c.is_alphabetic() || *c == '_')
+        .then(|| CharOffset::new(pos as u32))
+}
+
+struct Resolver {
+    chars: Vec<char>,
+    index: SymbolIndex,
+    scopes: Vec<HashMap<Symbol, BindingId>>,
+    next_binding: BindingId,
+}
+
+impl Resolver {
+    fn resolve_unit(&mut self, cu: &CompilationUnit) {
+        let module = &cu.module;
+        self.scopes.push(HashMap::new());
+
+        // Module-level values are visible throughout the module, including
+        // before their definition and in the export list.
+        for item in &module.items {
+            if let Item::ValueDef(def) = item {
+                let binding = self.bind(def.name, BindingKind::Module);
+                if let Some(start) = definition_name_start(&self.chars, def.span) {
+                    self.record(self.index.name_span(def.span.file_id, start, def.name), def.name, true, Some(binding));
+                } else {
+                    self.index.unlocated.insert(binding);
+                }
+            }
+        }
+
+        if let Some(exports) = &module.exports {
+            for export in &exports.items {
+                if export.kind == ExportKind::Value {
+                    let span = self.index.name_span(export.span.file_id, export.span.start, export.name);
+                    self.reference(export.name, span);
+                }
+            }
+        }
+
+        for item in &module.items {
+            match item {
+                Item::ValueDef(def) => {
+                    self.scopes.push(HashMap::new());
+                    for param in &def.parameters {
+                        self.bind_pattern(param, BindingKind::Parameter);
+                    }
+                    self.resolve_expr(&def.body);
+                    self.scopes.pop();
+                }
+                Item::TestDef(test) => {
+                    for expr in test.setup.iter().chain(test.teardown.iter()) {
+                        self.resolve_expr(expr);
+                    }
+                    self.resolve_expr(&test.body);
+                }
+                Item::BenchDef(bench) => self.resolve_expr(&bench.body),
+                Item::HandlerDef(handler) => {
+                    for op in &handler.handlers {
+                        self.resolve_handler(&op.parameters, op.continuation, &op.body);
+                    }
+                    if let Some(ret) = &handler.return_clause {
+                        self.resolve_handler(std::slice::from_ref(&ret.parameter), None, &ret.body);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        self.scopes.pop();
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal(..) | Expr::Pack { .. } | Expr::Error(_) => {}
+            // Operators are desugared to variables spanning the whole expression
+            Expr::Var(name, span) if is_identifier_like(name.as_str()) => {
+                let span = self.index.name_span(span.file_id, span.start, *name);
+                self.reference(*name, span);
+            }
+            Expr::Var(..) => {}
+            Expr::App(func, args, _) => {
+                self.resolve_expr(func);
+                for arg in args {
+                    self.resolve_expr(arg);
+                }
+            }
+            Expr::Lambda { parameters, body, .. } => {
+                self.scopes.push(HashMap::new());
+                for param in parameters {
+                    self.bind_pattern(param, BindingKind::Parameter);
+                }
+                self.resolve_expr(body);
+                self.scopes.pop();
+            }
+            Expr::Let { pattern, value, body, .. } => {
+                self.resolve_expr(value);
+                self.scopes.push(HashMap::new());
+                self.bind_pattern(pattern, BindingKind::Local);
+                self.resolve_expr(body);
+                self.scopes.pop();
+            }
+            Expr::If { condition, then_branch, else_branch, .. } => {
+                self.resolve_expr(condition);
+                self.resolve_expr(then_branch);
+                self.resolve_expr(else_branch);
+            }
+            Expr::Match { scrutinee, arms, .. } => {
+                self.resolve_expr(scrutinee);
+                for arm in arms {
+                    self.scopes.push(HashMap::new());
+                    self.bind_pattern(&arm.pattern, BindingKind::Local);
+                    if let Some(guard) = &arm.guard {
+                        self.resolve_expr(guard);
+                    }
+                    self.resolve_expr(&arm.body);
+                    self.scopes.pop();
+                }
+            }
+            Expr::Do { statements, .. } => {
+                // Each statement's bindings scope over the statements after it
+                self.scopes.push(HashMap::new());
+                for statement in statements {
+                    match statement {
+                        DoStatement::Let { pattern, expr, .. } | DoStatement::Bind { pattern, expr, .. } => {
+                            self.resolve_expr(expr);
+                            self.scopes.push(HashMap::new());
+                            self.bind_pattern(pattern, BindingKind::Local);
+                        }
+                        DoStatement::Expr(expr) => self.resolve_expr(expr),
+                    }
+                }
+                let depth = 1 + statements.iter()
+                    .filter(|s| !matches!(s, DoStatement::Expr(_)))
+                    .count();
+                for _ in 0..depth {
+                    self.scopes.pop();
+                }
+            }
+            Expr::Handle { expr, handlers, return_clause, .. } => {
+                self.resolve_expr(expr);
+                for handler in handlers {
+                    self.resolve_handler(&handler.parameters, handler.continuation, &handler.body);
+                }
+                if let Some(ret) = return_clause {
+                    self.resolve_handler(std::slice::from_ref(&ret.parameter), None, &ret.body);
+                }
+            }
+            Expr::Resume { value, .. } => self.resolve_expr(value),
+            Expr::Perform { args, .. } => {
+                for arg in args {
+                    self.resolve_expr(arg);
+                }
+            }
+            Expr::Ann { expr, .. } | Expr::Project { record: expr, .. } => self.resolve_expr(expr),
+            Expr::Record { fields, .. } => {
+                for (_, value) in fields {
+                    self.resolve_expr(value);
+                }
+            }
+            Expr::Tuple { elements, .. } => {
+                for element in elements {
+                    self.resolve_expr(element);
+                }
+            }
+            // The opened module's name qualifies values rather than binding one
+            Expr::Unpack { value, body, .. } => {
+                self.resolve_expr(value);
+                self.resolve_expr(body);
+            }
+            Expr::RecordUpdate { record, fields, .. } => {
+                self.resolve_expr(record);
+                for (_, value) in fields {
+                    self.resolve_expr(value);
+                }
+            }
+        }
+    }
+
+    fn resolve_handler(&mut self, parameters: &[Pattern], continuation: Option<Symbol>, body: &Expr) {
+        self.scopes.push(HashMap::new());
+        for param in parameters {
+            self.bind_pattern(param, BindingKind::Parameter);
+        }
+        if let Some(k) = continuation {
+            let binding = self.bind(k, BindingKind::Parameter);
+            self.index.unlocated.insert(binding);
+        }
+        self.resolve_expr(body);
+        self.scopes.pop();
+    }
+
+    fn bind_pattern(&mut self, pattern: &Pattern, kind: BindingKind) {
+        match pattern {
+            Pattern::Wildcard(_) | Pattern::Literal(..) => {}
+            Pattern::Variable(name, span) => {
+                let binding = self.bind(*name, kind);
+                self.record(self.index.name_span(span.file_id, span.start, *name), *name, true, Some(binding));
+            }
+            Pattern::Constructor { args, .. } => {
+                for arg in args {
+                    self.bind_pattern(arg, kind);
+                }
+            }
+            Pattern::Record { fields, rest, .. } => {
+                for field in fields.values() {
+                    self.bind_pattern(field, kind);
+                }
+                if let Some(rest) = rest {
+                    self.bind_pattern(rest, kind);
+                }
+            }
+            Pattern::Tuple { patterns, .. } => {
+                for p in patterns {
+                    self.bind_pattern(p, kind);
+                }
+            }
+            // Both alternatives bind the same names; the right side reuses the left's bindings
+            Pattern::Or { left, right, .. } => {
+                self.bind_pattern(left, kind);
+                self.reference_pattern(right);
+            }
+            Pattern::As { pattern, name, .. } => {
+                self.bind_pattern(pattern, kind);
+                let binding = self.bind(*name, kind);
+                self.index.unlocated.insert(binding);
+            }
+            Pattern::Ann { pattern, .. } => self.bind_pattern(pattern, kind),
+        }
+    }
+
+    /// Link variables of an or-pattern alternative to already-bound names
+    fn reference_pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Variable(name, span) => {
+                let span = self.index.name_span(span.file_id, span.start, *name);
+                self.reference(*name, span);
+            }
+            Pattern::Constructor { args, .. } | Pattern::Tuple { patterns: args, .. } => {
+                for arg in args {
+                    self.reference_pattern(arg);
+                }
+            }
+            Pattern::Record { fields, .. } => {
+                for field in fields.values() {
+                    self.reference_pattern(field);
+                }
+            }
+            Pattern::Or { left, right, .. } => {
+                self.reference_pattern(left);
+                self.reference_pattern(right);
+            }
+            Pattern::As { pattern, .. } | Pattern::Ann { pattern, .. } => self.reference_pattern(pattern),
+            Pattern::Wildcard(_) | Pattern::Literal(..) => {}
+        }
+    }
+
+    fn bind(&mut self, name: Symbol, kind: BindingKind) -> BindingId {
+        let binding = self.next_binding;
+        self.next_binding += 1;
+        self.index.kinds.push(kind);
+        if kind != BindingKind::Module && self.scopes.iter().any(|scope| scope.contains_key(&name)) {
+            self.index.shadowing.insert(binding);
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name, binding);
+        }
+        binding
+    }
+
+    fn reference(&mut self, name: Symbol, span: Span) {
+        let binding = self.scopes.iter().rev().find_map(|scope| scope.get(&name).copied());
+        self.record(span, name, false, binding);
+    }
+
+    fn record(&mut self, span: Span, name: Symbol, is_definition: bool, binding: Option<BindingId>) {
+        self.index.occurrences.push(Occurrence { span, name, is_definition, binding });
+    }
+}
+
+/// Rename the occurrences of `old` starting at `starts` within the AST
+pub(crate) fn apply_rename(
+    cu: &mut CompilationUnit,
+    chars: &[char],
+    starts: &HashSet<CharOffset>,
+    old: Symbol,
+    new: Symbol,
+) {
+    let module = &mut cu.module;
+    if let Some(exports) = &mut module.exports {
+        for export in &mut exports.items {
+            if export.name == old && starts.contains(&export.span.start) {
+                export.name = new;
+            }
+        }
+    }
+
+    for item in &mut module.items {
+        match item {
+            Item::ValueDef(def) => {
+                let name_start = definition_name_start(chars, def.span);
+                if def.name == old && name_start.is_some_and(|start| starts.contains(&start)) {
+                    def.name = new;
+                }
+                for param in &mut def.parameters {
+                    rename_pattern(param, starts, old, new);
+                }
+                rename_expr(&mut def.body, starts, old, new);
+            }
+            Item::TestDef(test) => {
+                for expr in test.setup.iter_mut().chain(test.teardown.iter_mut()) {
+                    rename_expr(expr, starts, old, new);
+                }
+                rename_expr(&mut test.body, starts, old, new);
+            }
+            Item::BenchDef(bench) => rename_expr(&mut bench.body, starts, old, new),
+            Item::HandlerDef(handler) => {
+                for op in &mut handler.handlers {
+                    op.parameters.iter_mut().for_each(|p| rename_pattern(p, starts, old, new));
+                    rename_expr(&mut op.body, starts, old, new);
+                }
+                if let Some(ret) = &mut handler.return_clause {
+                    rename_pattern(&mut ret.parameter, starts, old, new);
+                    rename_expr(&mut ret.body, starts, old, new);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn rename_expr(expr: &mut Expr, starts: &HashSet<CharOffset>, old: Symbol, new: Symbol) {
+    match expr {
+        Expr::Literal(..) | Expr::Pack { .. } | Expr::Error(_) => {}
+        Expr::Var(name, span) => {
+            if *name == old && starts.contains(&span.start) {
+                *name = new;
+            }
+        }
+        Expr::App(func, args, _) => {
+            rename_expr(func, starts, old, new);
+            args.iter_mut().for_each(|arg| rename_expr(arg, starts, old, new));
+        }
+        Expr::Lambda { parameters, body, .. } => {
+            parameters.iter_mut().for_each(|p| rename_pattern(p, starts, old, new));
+            rename_expr(body, starts, old, new);
+        }
+        Expr::Let { pattern, value, body, .. } => {
+            rename_pattern(pattern, starts, old, new);
+            rename_expr(value, starts, old, new);
+            rename_expr(body, starts, old, new);
+        }
+        Expr::If { condition, then_branch, else_branch, .. } => {
+            rename_expr(condition, starts, old, new);
+            rename_expr(then_branch, starts, old, new);
+            rename_expr(else_branch, starts, old, new);
+        }
+        Expr::Match { scrutinee, arms, .. } => {
+            rename_expr(scrutinee, starts, old, new);
+            for arm in arms {
+                rename_pattern(&mut arm.pattern, starts, old, new);
+                if let Some(guard) = &mut arm.guard {
+                    rename_expr(guard, starts, old, new);
+                }
+                rename_expr(&mut arm.body, starts, old, new);
+            }
+        }
+        Expr::Do { statements, .. } => {
+            for statement in statements {
+                match statement {
+                    DoStatement::Let { pattern, expr, .. } | DoStatement::Bind { pattern, expr, .. } => {
+                        rename_pattern(pattern, starts, old, new);
+                        rename_expr(expr, starts, old, new);
+                    }
+                    DoStatement::Expr(expr) => rename_expr(expr, starts, old, new),
+                }
+            }
+        }
+        Expr::Handle { expr, handlers, return_clause, .. } => {
+            rename_expr(expr, starts, old, new);
+            for handler in handlers {
+                handler.parameters.iter_mut().for_each(|p| rename_pattern(p, starts, old, new));
+                rename_expr(&mut handler.body, starts, old, new);
+            }
+            if let Some(ret) = return_clause {
+                rename_pattern(&mut ret.parameter, starts, old, new);
+                rename_expr(&mut ret.body, starts, old, new);
+            }
+        }
+        Expr::Resume { value, .. } => rename_expr(value, starts, old, new),
+        Expr::Perform { args, .. } => {
+            args.iter_mut().for_each(|arg| rename_expr(arg, starts, old, new));
+        }
+        Expr::Ann { expr, .. } | Expr::Project { record: expr, .. } => rename_expr(expr, starts, old, new),
+        Expr::Record { fields, .. } => {
+            fields.iter_mut().for_each(|(_, value)| rename_expr(value, starts, old, new));
+        }
+        Expr::Tuple { elements, .. } => {
+            elements.iter_mut().for_each(|element| rename_expr(element, starts, old, new));
+        }
+        Expr::Unpack { value, body, .. } => {
+            rename_expr(value, starts, old, new);
+            rename_expr(body, starts, old, new);
+        }
+        Expr::RecordUpdate { record, fields, .. } => {
+            rename_expr(record, starts, old, new);
+            fields.iter_mut().for_each(|(_, value)| rename_expr(value, starts, old, new));
+        }
+    }
+}
+
+fn rename_pattern(pattern: &mut Pattern, starts: &HashSet<CharOffset>, old: Symbol, new: Symbol) {
+    match pattern {
+        Pattern::Variable(name, span) => {
+            if *name == old && starts.contains(&span.start) {
+                *name = new;
+            }
+        }
+        Pattern::Constructor { args, .. } | Pattern::Tuple { patterns: args, .. } => {
+            args.iter_mut().for_each(|arg| rename_pattern(arg, starts, old, new));
+        }
+        Pattern::Record { fields, rest, .. } => {
+            fields.values_mut().for_each(|field| rename_pattern(field, starts, old, new));
+            if let Some(rest) = rest {
+                rename_pattern(rest, starts, old, new);
+            }
+        }
+        Pattern::Or { left, right, .. } => {
+            rename_pattern(left, starts, old, new);
+            rename_pattern(right, starts, old, new);
+        }
+        Pattern::As { pattern, .. } | Pattern::Ann { pattern, .. } => rename_pattern(pattern, starts, old, new),
+        Pattern::Wildcard(_) | Pattern::Literal(..) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x_parser::{parse_source, FileId, SyntaxStyle};
+
+    fn parse(source: &str) -> CompilationUnit {
+        parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap()
+    }
+
+    fn rewrite_source(source: &str, locations: &[Span], new_name: &str) -> String {
+        let mut chars: Vec<char> = source.chars().collect();
+        for span in locations.iter().rev() {
+            chars.splice(span.start.as_u32() as usize..span.end.as_u32() as usize, new_name.chars());
+        }
+        chars.into_iter().collect()
+    }
+
+    fn offset_of(source: &str, needle: &str, nth: usize) -> CharOffset {
+        let pos = source.match_indices(needle).nth(nth).unwrap().0;
+        CharOffset::new(source[..pos].chars().count() as u32)
+    }
+
+    #[test]
+    fn test_rename_top_level_value() {
+        let source = "module Test\nlet count = 1\nlet double = count + count";
+        let mut ast = parse(source);
+
+        let result = rename_symbol(&mut ast, source, offset_of(source, "count", 1), "total").unwrap();
+        assert_eq!(result.old_name, Symbol::intern("count"));
+        assert_eq!(result.locations.len(), 3);
+
+        let renamed = rewrite_source(source, &result.locations, "total");
+        assert_eq!(renamed, "module Test\nlet total = 1\nlet double = total + total");
+
+        match &ast.module.items[0] {
+            Item::ValueDef(def) => assert_eq!(def.name, Symbol::intern("total")),
+            other => panic!("unexpected item: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_rename_decomposed_name() {
+        // `cafe\u{301}` is interned as the one-character-shorter `café`
+        let source = "module Test\nlet cafe\u{301} = 1\nlet double = cafe\u{301} + cafe\u{301}";
+        let mut ast = parse(source);
+
+        let result = rename_symbol(&mut ast, source, offset_of(source, "cafe", 1), "total").unwrap();
+        assert_eq!(result.old_name, Symbol::intern("café"));
+        let renamed = rewrite_source(source, &result.locations, "total");
+        assert_eq!(renamed, "module Test\nlet total = 1\nlet double = total + total");
+    }
+
+    #[test]
+    fn test_rename_respects_shadowing() {
+        let source = "module Test\nlet x = 1\nlet y = fun x -> x\nlet z = x";
+        let mut ast = parse(source);
+
+        // Renaming the lambda's `x` leaves the module-level one untouched
+        let result = rename_symbol(&mut ast, source, offset_of(source, "x", 2), "inner").unwrap();
+        let renamed = rewrite_source(source, &result.locations, "inner");
+        assert_eq!(renamed, "module Test\nlet x = 1\nlet y = fun inner -> inner\nlet z = x");
+    }
+
+    #[test]
+    fn test_rename_rejects_capture() {
+        let source = "module Test\nlet x = 1\nlet y = fun a -> a + x";
+        let mut ast = parse(source);
+
+        let err = rename_symbol(&mut ast, source, offset_of(source, "a", 1), "x").unwrap_err();
+        assert!(matches!(err, EditError::RenameConflict { .. }));
+    }
+
+    #[test]
+    fn test_rename_rejects_unresolved_and_invalid() {
+        let source = "module Test\nlet y = print 1";
+        let mut ast = parse(source);
+
+        let err = rename_symbol(&mut ast, source, offset_of(source, "print", 0), "show").unwrap_err();
+        assert!(matches!(err, EditError::NoSymbolAtPosition { .. }));
+
+        let err = rename_symbol(&mut ast, source, offset_of(source, "y", 0), "let").unwrap_err();
+        assert!(matches!(err, EditError::InvalidIdentifier { .. }));
+    }
+
+    #[test]
+    fn test_replace_references_skips_definition_and_locals() {
+        let source = "module Test\nlet old = 1\nlet a = old + old\nlet b = fun old -> old\nlet c = fun fresh -> old";
+        let mut ast = parse(source);
+
+        let err = replace_references(&mut ast, source, Symbol::intern("old"), "fresh").unwrap_err();
+        assert!(matches!(err, EditError::RenameConflict { .. }));
+
+        let locations = replace_references(&mut ast, source, Symbol::intern("old"), "new_value").unwrap();
+        let replaced = rewrite_source(source, &locations, "new_value");
+        assert_eq!(
+            replaced,
+            "module Test\nlet old = 1\nlet a = new_value + new_value\nlet b = fun old -> old\nlet c = fun fresh -> new_value",
+        );
+    }
+}
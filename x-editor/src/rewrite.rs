@@ -0,0 +1,908 @@
+//! Structural search-and-replace with AST templates
+//!
+//! A rule pairs a pattern template with a replacement template, both written
+//! in ordinary expression syntax. Identifiers prefixed with `$` are
+//! metavariables: in the pattern they match any expression (or, in pattern
+//! position, any pattern), and in the replacement they are substituted with
+//! whatever they matched.
+//!
+//! ```text
+//! match $x with | Some $y => $y | None => $d   ==>   unwrap_or $x $d
+//! ```
+//!
+//! Matching ignores spans and layout. `RewriteRule::search` is the dry run:
+//! it reports every candidate site without touching the tree.
+
+use crate::ast_editor::EditError;
+use x_parser::span::CharOffset;
+use x_parser::{
+    parse_source, CompilationUnit, DoStatement, EffectHandler, Expr, FileId, Item, Literal, Pattern,
+    ReturnClause, Span, Symbol, SyntaxStyle, Type, VisitSpans,
+};
+use std::collections::HashMap;
+
+/// Prefix metavariables are rewritten to before the template is parsed
+const META_PREFIX: &str = "__meta_";
+
+/// What a metavariable was bound to
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetaBinding {
+    Expr(Expr),
+    Pattern(Pattern),
+}
+
+impl MetaBinding {
+    /// Source span of the bound node
+    pub fn span(&self) -> Span {
+        match self {
+            MetaBinding::Expr(expr) => expr.span(),
+            MetaBinding::Pattern(pattern) => pattern.span(),
+        }
+    }
+}
+
+/// A place where a rule's pattern matched
+#[derive(Debug, Clone, PartialEq)]
+pub struct RewriteSite {
+    /// Top-level definition containing the match
+    pub item: Symbol,
+    /// Span of the matched expression
+    pub span: Span,
+    /// Metavariable bindings, by name without the `$`
+    pub bindings: HashMap<String, MetaBinding>,
+    /// The expression the match is (or would be) replaced with
+    pub replacement: Expr,
+}
+
+/// A structural rewrite rule
+#[derive(Debug, Clone)]
+pub struct RewriteRule {
+    pattern: Expr,
+    replacement: Expr,
+}
+
+impl RewriteRule {
+    /// Parse a rule from pattern and replacement templates
+    pub fn parse(pattern: &str, replacement: &str) -> Result<Self, EditError> {
+        let pattern = parse_template(pattern)?;
+        let replacement = parse_template(replacement)?;
+
+        if let Expr::Var(name, _) = &pattern {
+            if meta_name(*name).is_some() {
+                return Err(EditError::InvalidRewriteRule {
+                    message: "a bare metavariable would match every expression".to_string(),
+                });
+            }
+        }
+
+        let mut bound = Vec::new();
+        collect_metavars_expr(&pattern, &mut bound);
+        let mut used = Vec::new();
+        collect_metavars_expr(&replacement, &mut used);
+        if let Some(unbound) = used.iter().find(|name| !bound.contains(name)) {
+            return Err(EditError::InvalidRewriteRule {
+                message: format!("${unbound} is used in the replacement but not bound by the pattern"),
+            });
+        }
+
+        Ok(Self { pattern, replacement })
+    }
+
+    /// Report every site the rule would rewrite, leaving the tree untouched
+    pub fn search(&self, cu: &CompilationUnit) -> Vec<RewriteSite> {
+        let mut scratch = cu.clone();
+        self.apply(&mut scratch)
+    }
+
+    /// Rewrite every match in the module, returning the rewritten sites
+    ///
+    /// Matches do not overlap: once an expression is rewritten, neither it nor
+    /// its replacement is searched again.
+    pub fn apply(&self, cu: &mut CompilationUnit) -> Vec<RewriteSite> {
+        let mut sites = Vec::new();
+        for item in &mut cu.module.items {
+            match item {
+                Item::ValueDef(def) => self.rewrite_expr(&mut def.body, def.name, &mut sites),
+                Item::TestDef(test) => {
+                    for expr in test.setup.iter_mut().chain(test.teardown.iter_mut()) {
+                        self.rewrite_expr(expr, test.name, &mut sites);
+                    }
+                    self.rewrite_expr(&mut test.body, test.name, &mut sites);
+                }
+                Item::BenchDef(bench) => self.rewrite_expr(&mut bench.body, bench.name, &mut sites),
+                Item::HandlerDef(handler) => {
+                    for op in &mut handler.handlers {
+                        self.rewrite_expr(&mut op.body, handler.name, &mut sites);
+                    }
+                    if let Some(ret) = &mut handler.return_clause {
+                        self.rewrite_expr(&mut ret.body, handler.name, &mut sites);
+                    }
+                }
+                _ => {}
+            }
+        }
+        sites
+    }
+
+    fn rewrite_expr(&self, expr: &mut Expr, item: Symbol, sites: &mut Vec<RewriteSite>) {
+        let mut bindings = HashMap::new();
+        if match_expr(&self.pattern, expr, &mut bindings) {
+            let span = expr.span();
+            let replacement = instantiate_expr(&self.replacement, &bindings, span);
+            *expr = replacement.clone();
+            sites.push(RewriteSite {
+                item,
+                span,
+                bindings: bindings.into_iter()
+                    .map(|(name, binding)| (name.as_str()[META_PREFIX.len()..].to_string(), binding))
+                    .collect(),
+                replacement,
+            });
+            return;
+        }
+
+        let mut visit = |expr: &mut Expr| self.rewrite_expr(expr, item, sites);
+        match expr {
+            Expr::Literal(..) | Expr::Var(..) | Expr::Pack { .. } | Expr::Error(_) => {}
+            Expr::App(func, args, _) => {
+                visit(func);
+                args.iter_mut().for_each(visit);
+            }
+            Expr::Lambda { body, .. } => visit(body),
+            Expr::Let { value, body, .. } => {
+                visit(value);
+                visit(body);
+            }
+            Expr::If { condition, then_branch, else_branch, .. } => {
+                visit(condition);
+                visit(then_branch);
+                visit(else_branch);
+            }
+            Expr::Match { scrutinee, arms, .. } => {
+                visit(scrutinee);
+                for arm in arms {
+                    if let Some(guard) = &mut arm.guard {
+                        visit(guard);
+                    }
+                    visit(&mut arm.body);
+                }
+            }
+            Expr::Do { statements, .. } => {
+                for statement in statements {
+                    match statement {
+                        DoStatement::Let { expr, .. } | DoStatement::Bind { expr, .. } | DoStatement::Expr(expr) => visit(expr),
+                    }
+                }
+            }
+            Expr::Handle { expr, handlers, return_clause, .. } => {
+                visit(expr);
+                for handler in handlers {
+                    visit(&mut handler.body);
+                }
+                if let Some(ret) = return_clause {
+                    visit(&mut ret.body);
+                }
+            }
+            Expr::Resume { value, .. } => visit(value),
+            Expr::Perform { args, .. } => args.iter_mut().for_each(visit),
+            Expr::Ann { expr, .. } | Expr::Project { record: expr, .. } => visit(expr),
+            Expr::Record { fields, .. } => fields.iter_mut().for_each(|(_, value)| visit(value)),
+            Expr::Tuple { elements, .. } => elements.iter_mut().for_each(visit),
+            Expr::Unpack { value, body, .. } => {
+                visit(value);
+                visit(body);
+            }
+            Expr::RecordUpdate { record, fields, .. } => {
+                visit(record);
+                fields.iter_mut().for_each(|(_, value)| visit(value));
+            }
+        }
+    }
+}
+
+/// Parse a template expression, turning `$name` into reserved identifiers
+fn parse_template(template: &str) -> Result<Expr, EditError> {
+    let mut text = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '$' {
+            if !chars.peek().is_some_and(|c| c.is_alphabetic() || *c == '_') {
+                return Err(EditError::InvalidRewriteRule {
+                    message: "'$' must be followed by a metavariable name".to_string(),
+                });
+            }
+            text.push_str(META_PREFIX);
+        } else {
+            text.push(c);
+        }
+    }
+
+    // Templates are parsed as the body of a throwaway definition
+    let source = format!("module Template\nlet template = {text}");
+    let cu = parse_source(&source, FileId::new(0), SyntaxStyle::SExpression)
+        .map_err(|err| EditError::InvalidRewriteRule { message: format!("cannot parse template: {err}") })?;
+    let mut items = cu.module.items.into_iter();
+    match (items.next(), items.next()) {
+        (Some(Item::ValueDef(def)), None) => Ok(def.body),
+        _ => Err(EditError::InvalidRewriteRule {
+            message: format!("template must be a single expression: {template}"),
+        }),
+    }
+}
+
+fn meta_name(name: Symbol) -> Option<Symbol> {
+    name.as_str().starts_with(META_PREFIX).then_some(name)
+}
+
+fn collect_metavars_expr(expr: &Expr, out: &mut Vec<Symbol>) {
+    let mut push = |name: Symbol| {
+        if meta_name(name).is_some() && !out.contains(&name) {
+            out.push(name);
+        }
+    };
+    match expr {
+        Expr::Var(name, _) | Expr::Pack { module: name, .. } => push(*name),
+        Expr::Literal(..) | Expr::Error(_) => {}
+        Expr::App(func, args, _) => {
+            collect_metavars_expr(func, out);
+            args.iter().for_each(|arg| collect_metavars_expr(arg, out));
+        }
+        Expr::Lambda { parameters, body, .. } => {
+            parameters.iter().for_each(|p| collect_metavars_pattern(p, out));
+            collect_metavars_expr(body, out);
+        }
+        Expr::Let { pattern, value, body, .. } => {
+            collect_metavars_pattern(pattern, out);
+            collect_metavars_expr(value, out);
+            collect_metavars_expr(body, out);
+        }
+        Expr::If { condition, then_branch, else_branch, .. } => {
+            collect_metavars_expr(condition, out);
+            collect_metavars_expr(then_branch, out);
+            collect_metavars_expr(else_branch, out);
+        }
+        Expr::Match { scrutinee, arms, .. } => {
+            collect_metavars_expr(scrutinee, out);
+            for arm in arms {
+                collect_metavars_pattern(&arm.pattern, out);
+                if let Some(guard) = &arm.guard {
+                    collect_metavars_expr(guard, out);
+                }
+                collect_metavars_expr(&arm.body, out);
+            }
+        }
+        Expr::Resume { value, .. } => collect_metavars_expr(value, out),
+        Expr::Perform { args, .. } => args.iter().for_each(|arg| collect_metavars_expr(arg, out)),
+        Expr::Ann { expr, .. } | Expr::Project { record: expr, .. } => collect_metavars_expr(expr, out),
+        Expr::Record { fields, .. } => fields.iter().for_each(|(_, value)| collect_metavars_expr(value, out)),
+        Expr::Tuple { elements, .. } => elements.iter().for_each(|element| collect_metavars_expr(element, out)),
+        Expr::Unpack { name, value, body, .. } => {
+            push(*name);
+            collect_metavars_expr(value, out);
+            collect_metavars_expr(body, out);
+        }
+        Expr::RecordUpdate { record, fields, .. } => {
+            collect_metavars_expr(record, out);
+            fields.iter().for_each(|(_, value)| collect_metavars_expr(value, out));
+        }
+        Expr::Do { statements, .. } => {
+            for statement in statements {
+                match statement {
+                    DoStatement::Let { pattern, expr, .. } | DoStatement::Bind { pattern, expr, .. } => {
+                        collect_metavars_pattern(pattern, out);
+                        collect_metavars_expr(expr, out);
+                    }
+                    DoStatement::Expr(expr) => collect_metavars_expr(expr, out),
+                }
+            }
+        }
+        Expr::Handle { expr, handlers, return_clause, .. } => {
+            collect_metavars_expr(expr, out);
+            for handler in handlers {
+                handler.parameters.iter().for_each(|p| collect_metavars_pattern(p, out));
+                collect_metavars_expr(&handler.body, out);
+            }
+            if let Some(ret) = return_clause {
+                collect_metavars_pattern(&ret.parameter, out);
+                collect_metavars_expr(&ret.body, out);
+            }
+        }
+    }
+}
+
+fn collect_metavars_pattern(pattern: &Pattern, out: &mut Vec<Symbol>) {
+    match pattern {
+        Pattern::Variable(name, _) => {
+            if meta_name(*name).is_some() && !out.contains(name) {
+                out.push(*name);
+            }
+        }
+        Pattern::Constructor { args, .. } => args.iter().for_each(|p| collect_metavars_pattern(p, out)),
+        Pattern::Tuple { patterns, .. } => patterns.iter().for_each(|p| collect_metavars_pattern(p, out)),
+        Pattern::Or { left, right, .. } => {
+            collect_metavars_pattern(left, out);
+            collect_metavars_pattern(right, out);
+        }
+        Pattern::As { pattern, .. } | Pattern::Ann { pattern, .. } => collect_metavars_pattern(pattern, out),
+        Pattern::Record { fields, .. } => fields.values().for_each(|p| collect_metavars_pattern(p, out)),
+        Pattern::Wildcard(_) | Pattern::Literal(..) => {}
+    }
+}
+
+type Bindings = HashMap<Symbol, MetaBinding>;
+
+/// Match a template expression against an actual one, ignoring spans
+fn match_expr(template: &Expr, actual: &Expr, bindings: &mut Bindings) -> bool {
+    if let Expr::Var(name, _) = template {
+        if meta_name(*name).is_some() {
+            return match bindings.get(name) {
+                None => {
+                    bindings.insert(*name, MetaBinding::Expr(actual.clone()));
+                    true
+                }
+                Some(MetaBinding::Expr(bound)) => {
+                    let bound = bound.clone();
+                    match_expr(&bound, actual, &mut HashMap::new())
+                }
+                // A pattern-bound metavariable used as an expression refers to the bound name
+                Some(MetaBinding::Pattern(Pattern::Variable(bound, _))) => {
+                    matches!(actual, Expr::Var(name, _) if name == bound)
+                }
+                Some(MetaBinding::Pattern(_)) => false,
+            };
+        }
+    }
+
+    match (template, actual) {
+        (Expr::Literal(a, _), Expr::Literal(b, _)) => same_literal(a, b),
+        (Expr::Var(a, _), Expr::Var(b, _)) => a == b,
+        (Expr::App(tf, targs, _), Expr::App(af, aargs, _)) => {
+            targs.len() == aargs.len()
+                && match_expr(tf, af, bindings)
+                && targs.iter().zip(aargs).all(|(t, a)| match_expr(t, a, bindings))
+        }
+        (
+            Expr::Lambda { parameters: tp, body: tb, .. },
+            Expr::Lambda { parameters: ap, body: ab, .. },
+        ) => {
+            tp.len() == ap.len()
+                && tp.iter().zip(ap).all(|(t, a)| match_pattern(t, a, bindings))
+                && match_expr(tb, ab, bindings)
+        }
+        (
+            Expr::Let { pattern: tp, value: tv, body: tb, .. },
+            Expr::Let { pattern: ap, value: av, body: ab, .. },
+        ) => match_pattern(tp, ap, bindings) && match_expr(tv, av, bindings) && match_expr(tb, ab, bindings),
+        (
+            Expr::If { condition: tc, then_branch: tt, else_branch: te, .. },
+            Expr::If { condition: ac, then_branch: at, else_branch: ae, .. },
+        ) => match_expr(tc, ac, bindings) && match_expr(tt, at, bindings) && match_expr(te, ae, bindings),
+        (
+            Expr::Match { scrutinee: ts, arms: tarms, .. },
+            Expr::Match { scrutinee: as_, arms: aarms, .. },
+        ) => {
+            tarms.len() == aarms.len()
+                && match_expr(ts, as_, bindings)
+                && tarms.iter().zip(aarms).all(|(t, a)| {
+                    match_pattern(&t.pattern, &a.pattern, bindings)
+                        && match (&t.guard, &a.guard) {
+                            (None, None) => true,
+                            (Some(t), Some(a)) => match_expr(t, a, bindings),
+                            _ => false,
+                        }
+                        && match_expr(&t.body, &a.body, bindings)
+                })
+        }
+        (Expr::Resume { value: t, .. }, Expr::Resume { value: a, .. }) => match_expr(t, a, bindings),
+        (
+            Expr::Perform { effect: te, operation: to, args: targs, .. },
+            Expr::Perform { effect: ae, operation: ao, args: aargs, .. },
+        ) => {
+            te == ae && to == ao && targs.len() == aargs.len()
+                && targs.iter().zip(aargs).all(|(t, a)| match_expr(t, a, bindings))
+        }
+        (Expr::Record { fields: t, .. }, Expr::Record { fields: a, .. }) => match_fields(t, a, bindings),
+        (Expr::Tuple { elements: t, .. }, Expr::Tuple { elements: a, .. }) => {
+            t.len() == a.len() && t.iter().zip(a).all(|(t, a)| match_expr(t, a, bindings))
+        }
+        (
+            Expr::Project { record: tr, field: tf, .. },
+            Expr::Project { record: ar, field: af, .. },
+        ) => tf == af && match_expr(tr, ar, bindings),
+        (
+            Expr::RecordUpdate { record: tr, fields: tfs, .. },
+            Expr::RecordUpdate { record: ar, fields: afs, .. },
+        ) => match_expr(tr, ar, bindings) && match_fields(tfs, afs, bindings),
+        (
+            Expr::Ann { expr: te, type_annotation: tt, .. },
+            Expr::Ann { expr: ae, type_annotation: at, .. },
+        ) => same_type(tt, at) && match_expr(te, ae, bindings),
+        (Expr::Do { statements: t, .. }, Expr::Do { statements: a, .. }) => {
+            t.len() == a.len() && t.iter().zip(a).all(|(t, a)| match (t, a) {
+                (DoStatement::Let { pattern: tp, expr: te, .. }, DoStatement::Let { pattern: ap, expr: ae, .. })
+                | (DoStatement::Bind { pattern: tp, expr: te, .. }, DoStatement::Bind { pattern: ap, expr: ae, .. }) => {
+                    match_pattern(tp, ap, bindings) && match_expr(te, ae, bindings)
+                }
+                (DoStatement::Expr(t), DoStatement::Expr(a)) => match_expr(t, a, bindings),
+                _ => false,
+            })
+        }
+        (
+            Expr::Handle { expr: te, mode: tm, handlers: ths, return_clause: tr, .. },
+            Expr::Handle { expr: ae, mode: am, handlers: ahs, return_clause: ar, .. },
+        ) => {
+            tm == am && ths.len() == ahs.len()
+                && match_expr(te, ae, bindings)
+                && ths.iter().zip(ahs).all(|(t, a)| {
+                    t.effect.name == a.effect.name
+                        && t.effect.args.len() == a.effect.args.len()
+                        && t.effect.args.iter().zip(&a.effect.args).all(|(t, a)| same_type(t, a))
+                        && t.operation == a.operation
+                        && t.continuation == a.continuation
+                        && t.parameters.len() == a.parameters.len()
+                        && t.parameters.iter().zip(&a.parameters).all(|(t, a)| match_pattern(t, a, bindings))
+                        && match_expr(&t.body, &a.body, bindings)
+                })
+                && match (tr, ar) {
+                    (None, None) => true,
+                    (Some(t), Some(a)) => match_pattern(&t.parameter, &a.parameter, bindings) && match_expr(&t.body, &a.body, bindings),
+                    _ => false,
+                }
+        }
+        (
+            Expr::Pack { module: tm, signature: ts, span },
+            Expr::Pack { module: am, signature: as_, .. },
+        ) => ts == as_ && match_name(*tm, *am, *span, bindings),
+        (
+            Expr::Unpack { name: tn, signature: ts, value: tv, body: tb, span },
+            Expr::Unpack { name: an, signature: as_, value: av, body: ab, .. },
+        ) => {
+            ts == as_ && match_name(*tn, *an, *span, bindings)
+                && match_expr(tv, av, bindings)
+                && match_expr(tb, ab, bindings)
+        }
+        _ => false,
+    }
+}
+
+/// Match a name in a position that binds no pattern, such as a packed
+/// module's, where a metavariable stands for the name itself
+fn match_name(template: Symbol, actual: Symbol, span: Span, bindings: &mut Bindings) -> bool {
+    if meta_name(template).is_none() {
+        return template == actual;
+    }
+    match bindings.get(&template) {
+        None => {
+            bindings.insert(template, MetaBinding::Pattern(Pattern::Variable(actual, span)));
+            true
+        }
+        Some(MetaBinding::Pattern(Pattern::Variable(bound, _))) => *bound == actual,
+        Some(_) => false,
+    }
+}
+
+/// Whether two types are the same, ignoring spans
+fn same_type(a: &Type, b: &Type) -> bool {
+    let span = Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(0));
+    at_span(a.clone(), span) == at_span(b.clone(), span)
+}
+
+/// `node` with every span in it moved to `span`
+fn at_span<T: VisitSpans>(mut node: T, span: Span) -> T {
+    node.visit_spans(&mut |s| *s = span);
+    node
+}
+
+fn match_fields(template: &[(Symbol, Expr)], actual: &[(Symbol, Expr)], bindings: &mut Bindings) -> bool {
+    template.len() == actual.len()
+        && template.iter().zip(actual).all(|((tn, t), (an, a))| tn == an && match_expr(t, a, bindings))
+}
+
+fn match_pattern(template: &Pattern, actual: &Pattern, bindings: &mut Bindings) -> bool {
+    if let Pattern::Variable(name, _) = template {
+        if meta_name(*name).is_some() {
+            return match bindings.get(name) {
+                None => {
+                    bindings.insert(*name, MetaBinding::Pattern(actual.clone()));
+                    true
+                }
+                Some(MetaBinding::Pattern(bound)) => {
+                    let bound = bound.clone();
+                    match_pattern(&bound, actual, &mut HashMap::new())
+                }
+                Some(MetaBinding::Expr(_)) => false,
+            };
+        }
+    }
+
+    match (template, actual) {
+        (Pattern::Wildcard(_), Pattern::Wildcard(_)) => true,
+        (Pattern::Variable(a, _), Pattern::Variable(b, _)) => a == b,
+        (Pattern::Literal(a, _), Pattern::Literal(b, _)) => same_literal(a, b),
+        (
+            Pattern::Constructor { name: tn, args: targs, .. },
+            Pattern::Constructor { name: an, args: aargs, .. },
+        ) => {
+            tn == an && targs.len() == aargs.len()
+                && targs.iter().zip(aargs).all(|(t, a)| match_pattern(t, a, bindings))
+        }
+        (Pattern::Tuple { patterns: t, .. }, Pattern::Tuple { patterns: a, .. }) => {
+            t.len() == a.len() && t.iter().zip(a).all(|(t, a)| match_pattern(t, a, bindings))
+        }
+        (Pattern::Or { left: tl, right: tr, .. }, Pattern::Or { left: al, right: ar, .. }) => {
+            match_pattern(tl, al, bindings) && match_pattern(tr, ar, bindings)
+        }
+        _ => false,
+    }
+}
+
+fn same_literal(a: &Literal, b: &Literal) -> bool {
+    match (a, b) {
+        (Literal::Float(a, _), Literal::Float(b, _)) => a.to_bits() == b.to_bits(),
+        _ => a == b,
+    }
+}
+
+/// Build the replacement, placing every new node at the matched site's span
+fn instantiate_expr(template: &Expr, bindings: &Bindings, span: Span) -> Expr {
+    let inst = |expr: &Expr| instantiate_expr(expr, bindings, span);
+    let inst_pattern = |pattern: &Pattern| instantiate_pattern(pattern, bindings, span);
+    match template {
+        Expr::Var(name, _) => match bindings.get(name) {
+            Some(MetaBinding::Expr(bound)) => bound.clone(),
+            Some(MetaBinding::Pattern(Pattern::Variable(bound, bound_span))) => Expr::Var(*bound, *bound_span),
+            _ => Expr::Var(*name, span),
+        },
+        Expr::Literal(literal, _) => Expr::Literal(literal.clone(), span),
+        Expr::Error(_) => Expr::Error(span),
+        Expr::App(func, args, _) => Expr::App(Box::new(inst(func)), args.iter().map(inst).collect(), span),
+        Expr::Lambda { parameters, body, .. } => Expr::Lambda {
+            parameters: parameters.iter().map(inst_pattern).collect(),
+            body: Box::new(inst(body)),
+            span,
+        },
+        Expr::Let { pattern, type_annotation, value, body, .. } => Expr::Let {
+            pattern: inst_pattern(pattern),
+            type_annotation: type_annotation.clone().map(|typ| at_span(typ, span)),
+            value: Box::new(inst(value)),
+            body: Box::new(inst(body)),
+            span,
+        },
+        Expr::If { condition, then_branch, else_branch, .. } => Expr::If {
+            condition: Box::new(inst(condition)),
+            then_branch: Box::new(inst(then_branch)),
+            else_branch: Box::new(inst(else_branch)),
+            span,
+        },
+        Expr::Match { scrutinee, arms, .. } => Expr::Match {
+            scrutinee: Box::new(inst(scrutinee)),
+            arms: arms.iter()
+                .map(|arm| x_parser::MatchArm {
+                    pattern: inst_pattern(&arm.pattern),
+                    guard: arm.guard.as_ref().map(|guard| Box::new(inst(guard))),
+                    body: inst(&arm.body),
+                    span,
+                })
+                .collect(),
+            span,
+        },
+        Expr::Resume { value, .. } => Expr::Resume { value: Box::new(inst(value)), span },
+        Expr::Perform { effect, operation, args, .. } => Expr::Perform {
+            effect: *effect,
+            operation: *operation,
+            args: args.iter().map(inst).collect(),
+            span,
+        },
+        Expr::Ann { expr, type_annotation, .. } => Expr::Ann {
+            expr: Box::new(inst(expr)),
+            type_annotation: at_span(type_annotation.clone(), span),
+            span,
+        },
+        Expr::Record { fields, .. } => Expr::Record {
+            fields: fields.iter().map(|(name, value)| (*name, inst(value))).collect(),
+            span,
+        },
+        Expr::Project { record, field, .. } => Expr::Project {
+            record: Box::new(inst(record)),
+            field: *field,
+            span,
+        },
+        Expr::RecordUpdate { record, fields, .. } => Expr::RecordUpdate {
+            record: Box::new(inst(record)),
+            fields: fields.iter().map(|(name, value)| (*name, inst(value))).collect(),
+            span,
+        },
+        Expr::Tuple { elements, .. } => Expr::Tuple {
+            elements: elements.iter().map(inst).collect(),
+            span,
+        },
+        Expr::Do { statements, .. } => Expr::Do {
+            statements: statements.iter()
+                .map(|statement| match statement {
+                    DoStatement::Let { pattern, expr, .. } => DoStatement::Let {
+                        pattern: inst_pattern(pattern),
+                        expr: inst(expr),
+                        span,
+                    },
+                    DoStatement::Bind { pattern, expr, .. } => DoStatement::Bind {
+                        pattern: inst_pattern(pattern),
+                        expr: inst(expr),
+                        span,
+                    },
+                    DoStatement::Expr(expr) => DoStatement::Expr(inst(expr)),
+                })
+                .collect(),
+            span,
+        },
+        Expr::Handle { expr, mode, handlers, return_clause, .. } => Expr::Handle {
+            expr: Box::new(inst(expr)),
+            mode: *mode,
+            handlers: handlers.iter()
+                .map(|handler| EffectHandler {
+                    effect: at_span(handler.effect.clone(), span),
+                    operation: handler.operation,
+                    parameters: handler.parameters.iter().map(inst_pattern).collect(),
+                    continuation: handler.continuation,
+                    body: inst(&handler.body),
+                    span,
+                })
+                .collect(),
+            return_clause: return_clause.as_ref().map(|ret| Box::new(ReturnClause {
+                parameter: inst_pattern(&ret.parameter),
+                body: Box::new(inst(&ret.body)),
+                span,
+            })),
+            span,
+        },
+        Expr::Pack { module, signature, .. } => Expr::Pack {
+            module: instantiate_name(*module, bindings),
+            signature: *signature,
+            span,
+        },
+        Expr::Unpack { name, signature, value, body, .. } => Expr::Unpack {
+            name: instantiate_name(*name, bindings),
+            signature: *signature,
+            value: Box::new(inst(value)),
+            body: Box::new(inst(body)),
+            span,
+        },
+    }
+}
+
+/// The name a metavariable in a name position was bound to
+fn instantiate_name(name: Symbol, bindings: &Bindings) -> Symbol {
+    match bindings.get(&name) {
+        Some(MetaBinding::Pattern(Pattern::Variable(bound, _))) => *bound,
+        _ => name,
+    }
+}
+
+fn instantiate_pattern(template: &Pattern, bindings: &Bindings, span: Span) -> Pattern {
+    let inst = |pattern: &Pattern| instantiate_pattern(pattern, bindings, span);
+    match template {
+        Pattern::Variable(name, _) => match bindings.get(name) {
+            Some(MetaBinding::Pattern(bound)) => bound.clone(),
+            _ => Pattern::Variable(*name, span),
+        },
+        Pattern::Wildcard(_) => Pattern::Wildcard(span),
+        Pattern::Literal(literal, _) => Pattern::Literal(literal.clone(), span),
+        Pattern::Constructor { name, args, .. } => Pattern::Constructor {
+            name: *name,
+            args: args.iter().map(inst).collect(),
+            span,
+        },
+        Pattern::Tuple { patterns, .. } => Pattern::Tuple { patterns: patterns.iter().map(inst).collect(), span },
+        Pattern::Or { left, right, .. } => Pattern::Or {
+            left: Box::new(inst(left)),
+            right: Box::new(inst(right)),
+            span,
+        },
+        Pattern::Record { fields, rest, .. } => Pattern::Record {
+            fields: fields.iter().map(|(name, field)| (*name, inst(field))).collect(),
+            rest: rest.as_ref().map(|rest| Box::new(inst(rest))),
+            span,
+        },
+        Pattern::As { pattern, name, .. } => Pattern::As {
+            pattern: Box::new(inst(pattern)),
+            name: instantiate_name(*name, bindings),
+            span,
+        },
+        Pattern::Ann { pattern, type_annotation, .. } => Pattern::Ann {
+            pattern: Box::new(inst(pattern)),
+            type_annotation: at_span(type_annotation.clone(), span),
+            span,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x_parser::{EffectRef, HandlerMode};
+
+    fn parse(source: &str) -> CompilationUnit {
+        parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap()
+    }
+
+    fn body(cu: &CompilationUnit, index: usize) -> &Expr {
+        match &cu.module.items[index] {
+            Item::ValueDef(def) => &def.body,
+            other => panic!("unexpected item: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_match_to_unwrap_or() {
+        let rule = RewriteRule::parse(
+            "match $x with | Some $y => $y | None => $d",
+            "unwrap_or $x $d",
+        ).unwrap();
+        let mut cu = parse(
+            "module Test\nlet f = fun opt -> match opt with | Some v => v | None => 0",
+        );
+
+        let sites = rule.search(&cu);
+        assert_eq!(sites.len(), 1);
+        assert_eq!(sites[0].item, Symbol::intern("f"));
+        assert!(sites[0].bindings.contains_key("d"));
+
+        let applied = rule.apply(&mut cu);
+        assert_eq!(applied, sites);
+        let expected = parse("module Test\nlet f = fun opt -> unwrap_or opt 0");
+        assert!(match_expr(body(&expected, 0), body(&cu, 0), &mut HashMap::new()));
+    }
+
+    #[test]
+    fn test_repeated_metavariable_must_agree() {
+        let rule = RewriteRule::parse("$a + $a", "2 * $a").unwrap();
+        let mut cu = parse("module Test\nlet f = fun x -> fun y -> (x + x) * (x + y)");
+
+        let sites = rule.apply(&mut cu);
+        // Only `x + x` matches; `x + y` binds `$a` inconsistently
+        assert_eq!(sites.len(), 1);
+        assert!(matches!(&sites[0].bindings["a"], MetaBinding::Expr(Expr::Var(name, _)) if name.as_str() == "x"));
+    }
+
+    #[test]
+    fn test_search_leaves_tree_untouched() {
+        let rule = RewriteRule::parse("not (not $b)", "$b").unwrap();
+        let cu = parse("module Test\nlet f = fun b -> not (not b)\nlet g = not (not true)");
+
+        let before = cu.clone();
+        assert_eq!(rule.search(&cu).len(), 2);
+        assert_eq!(cu, before);
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        assert!(matches!(
+            RewriteRule::parse("f $x", "g $y"),
+            Err(EditError::InvalidRewriteRule { .. })
+        ));
+        assert!(RewriteRule::parse("$x", "$x").is_err());
+        assert!(RewriteRule::parse("f $", "f").is_err());
+    }
+
+    fn span(start: u32) -> Span {
+        Span::new(FileId::new(0), CharOffset::new(start), CharOffset::new(start + 1))
+    }
+
+    fn var(name: &str, at: u32) -> Expr {
+        Expr::Var(Symbol::intern(name), span(at))
+    }
+
+    fn meta(name: &str) -> Expr {
+        var(&format!("{META_PREFIX}{name}"), 0)
+    }
+
+    /// Apply `rule` to a definition whose body is `expr`
+    fn rewrite(rule: &RewriteRule, expr: Expr) -> (Vec<RewriteSite>, Expr) {
+        let mut cu = parse("module Test\nlet g = 0");
+        if let Item::ValueDef(def) = &mut cu.module.items[0] {
+            def.body = expr;
+        }
+        let sites = rule.apply(&mut cu);
+        (sites, body(&cu, 0).clone())
+    }
+
+    /// Every span in `expr` that is not `site`
+    fn other_spans(mut expr: Expr, site: Span) -> Vec<Span> {
+        let mut spans = Vec::new();
+        expr.visit_spans(&mut |span| if *span != site { spans.push(*span) });
+        spans
+    }
+
+    #[test]
+    fn test_pack_and_unpack() {
+        let rule = RewriteRule::parse(
+            "(let module $p : Plugin = $v in $b)",
+            "(let module $p : Plugin = upgrade $v in $b)",
+        ).unwrap();
+        let mut cu = parse("module Test\nlet f = fun m -> (let module P : Plugin = m in run 1)\nlet g = module M : Plugin");
+
+        let sites = rule.apply(&mut cu);
+        assert_eq!(sites.len(), 1);
+        let Expr::Lambda { body: lambda_body, .. } = body(&cu, 0) else { panic!("expected a lambda") };
+        let Expr::Unpack { name, value, span, .. } = lambda_body.as_ref() else { panic!("expected an unpack") };
+        assert_eq!(name.as_str(), "P");
+        assert_eq!(*span, sites[0].span);
+        assert!(matches!(value.as_ref(), Expr::App(func, _, app_span) if **func == Expr::Var(Symbol::intern("upgrade"), *span) && app_span == span));
+
+        let rule = RewriteRule::parse("module $m : Plugin", "module $m : Extended").unwrap();
+        let sites = rule.apply(&mut cu);
+        assert_eq!(sites.len(), 1);
+        assert!(matches!(
+            body(&cu, 1),
+            Expr::Pack { module, signature, span } if module.as_str() == "M" && signature.as_str() == "Extended" && *span == sites[0].span
+        ));
+    }
+
+    #[test]
+    fn test_do_and_handle() {
+        let state = EffectRef { name: Symbol::intern("State"), args: Vec::new(), span: span(0) };
+        let do_block = |pattern: Pattern, value: Expr, last: Expr, at: u32| Expr::Do {
+            statements: vec![
+                DoStatement::Bind { pattern, expr: value, span: span(at) },
+                DoStatement::Expr(last),
+            ],
+            span: span(at),
+        };
+        let x = Pattern::Variable(Symbol::intern(&format!("{META_PREFIX}x")), span(0));
+        let pattern = do_block(x.clone(), meta("e"), Expr::App(Box::new(var("log", 0)), vec![meta("x")], span(0)), 0);
+        let replacement = Expr::Handle {
+            expr: Box::new(do_block(x, meta("e"), meta("x"), 0)),
+            mode: HandlerMode::Deep,
+            handlers: vec![EffectHandler {
+                effect: state,
+                operation: Symbol::intern("get"),
+                parameters: Vec::new(),
+                continuation: Some(Symbol::intern("k")),
+                body: Expr::App(Box::new(var("k", 0)), vec![meta("e")], span(0)),
+                span: span(0),
+            }],
+            return_clause: None,
+            span: span(0),
+        };
+        let rule = RewriteRule { pattern, replacement };
+
+        let y = Pattern::Variable(Symbol::intern("y"), span(30));
+        let actual = do_block(y, var("read", 20), Expr::App(Box::new(var("log", 40)), vec![var("y", 44)], span(40)), 10);
+        let (sites, rewritten) = rewrite(&rule, actual);
+        assert_eq!(sites.len(), 1);
+        assert_eq!(sites[0].span, span(10));
+
+        // `$e` and `$x` were substituted throughout, the rest sits at the site
+        let Expr::Handle { expr, handlers, .. } = &rewritten else { panic!("expected a handler: {rewritten:?}") };
+        let Expr::Do { statements, .. } = expr.as_ref() else { panic!("expected a do block") };
+        assert!(matches!(&statements[0], DoStatement::Bind { pattern: Pattern::Variable(name, _), expr, .. } if name.as_str() == "y" && *expr == var("read", 20)));
+        assert!(matches!(&statements[1], DoStatement::Expr(Expr::Var(name, _)) if name.as_str() == "y"));
+        assert!(matches!(&handlers[0].body, Expr::App(_, args, _) if args[0] == var("read", 20)));
+        assert_eq!(other_spans(rewritten, span(10)), [span(30), span(20), span(30), span(20)]);
+
+        // A block of a different shape does not match
+        let (sites, _) = rewrite(&rule, do_block(Pattern::Wildcard(span(5)), var("read", 6), var("done", 7), 5));
+        assert!(sites.is_empty());
+    }
+
+    #[test]
+    fn test_type_annotation() {
+        let int = |at| Type::Con(Symbol::intern("Int"), span(at));
+        let float = |at| Type::Con(Symbol::intern("Float"), span(at));
+        let ann = |expr: Expr, typ: Type, at| Expr::Ann { expr: Box::new(expr), type_annotation: typ, span: span(at) };
+        let rule = RewriteRule {
+            pattern: ann(meta("e"), int(0), 0),
+            replacement: ann(Expr::App(Box::new(var("to_float", 0)), vec![meta("e")], span(0)), float(0), 0),
+        };
+
+        // Types match regardless of where they were written
+        let (sites, rewritten) = rewrite(&rule, ann(var("n", 11), int(15), 10));
+        assert_eq!(sites.len(), 1);
+        let Expr::Ann { expr, type_annotation, .. } = &rewritten else { panic!("expected an annotation") };
+        assert_eq!(*type_annotation, float(10));
+        assert!(matches!(expr.as_ref(), Expr::App(_, args, _) if args[0] == var("n", 11)));
+        assert_eq!(other_spans(rewritten, span(10)), [span(11)]);
+
+        let (sites, _) = rewrite(&rule, ann(var("n", 11), float(15), 10));
+        assert!(sites.is_empty());
+    }
+}
@@ -0,0 +1,295 @@
+//! Semantic classification of identifiers for editor highlighting
+//!
+//! Every identifier token is classified using the resolved AST together with
+//! the checker's results, so that effectful functions can be told apart from
+//! pure ones and effect-related names get their own colours.
+
+use crate::rename::{BindingKind, SymbolIndex};
+use x_checker::{CheckResult, EffectSet, Type};
+use x_parser::{CompilationUnit, FileId, Item, Lexer, Span, Symbol, TokenKind, TypeDefKind};
+use std::collections::HashSet;
+
+/// Kind of a highlighted identifier
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SemanticTokenKind {
+    /// Effect name, e.g. `State`
+    Effect,
+    /// Handler name
+    Handler,
+    /// Type or type constructor, e.g. `List`
+    Type,
+    /// Data constructor, e.g. `Some`
+    Constructor,
+    /// Effect operation, e.g. `get`
+    Operation,
+    /// Value whose type is a function
+    Function,
+    /// Any other value
+    Variable,
+    /// Function, lambda or handler parameter
+    Parameter,
+}
+
+impl SemanticTokenKind {
+    /// Every kind, in legend order
+    pub const ALL: [SemanticTokenKind; 8] = [
+        SemanticTokenKind::Effect,
+        SemanticTokenKind::Handler,
+        SemanticTokenKind::Type,
+        SemanticTokenKind::Constructor,
+        SemanticTokenKind::Operation,
+        SemanticTokenKind::Function,
+        SemanticTokenKind::Variable,
+        SemanticTokenKind::Parameter,
+    ];
+
+    /// Position of this kind in `ALL`
+    pub fn index(self) -> u32 {
+        Self::ALL.iter().position(|kind| *kind == self).unwrap_or(0) as u32
+    }
+}
+
+/// A classified identifier
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SemanticToken {
+    pub span: Span,
+    pub kind: SemanticTokenKind,
+    /// The token introduces the name
+    pub declaration: bool,
+    /// The function performs effects according to the checker
+    pub effectful: bool,
+    /// The function is known to perform no effects
+    pub pure: bool,
+}
+
+/// Classify every identifier of `source`, in source order
+pub fn semantic_tokens(cu: &CompilationUnit, source: &str, check: &CheckResult) -> Vec<SemanticToken> {
+    let Ok(tokens) = Lexer::new(source, FileId::new(0)).tokenize() else {
+        return Vec::new();
+    };
+
+    let names = DeclaredNames::collect(cu);
+    let index = SymbolIndex::build(cu, source);
+
+    tokens.iter()
+        .filter_map(|token| {
+            let TokenKind::Ident(text) = &token.kind else {
+                return None;
+            };
+            let name = Symbol::intern(text);
+            let span = Span::new(cu.span.file_id, token.span.start, token.span.end);
+
+            // Names resolved by scope take precedence over global name tables
+            let occurrence = index.occurrence_at(token.span.start)
+                .filter(|occ| occ.span.start == token.span.start && occ.name == name);
+            if let Some(occ) = occurrence {
+                if let Some(kind) = index.binding_kind(occ) {
+                    return Some(classify_binding(span, name, kind, occ.is_definition, check));
+                }
+            }
+
+            names.classify(name).map(|kind| SemanticToken {
+                span,
+                kind,
+                declaration: false,
+                effectful: false,
+                pure: false,
+            })
+        })
+        .collect()
+}
+
+fn classify_binding(
+    span: Span,
+    name: Symbol,
+    kind: BindingKind,
+    declaration: bool,
+    check: &CheckResult,
+) -> SemanticToken {
+    let mut token = SemanticToken {
+        span,
+        kind: SemanticTokenKind::Variable,
+        declaration,
+        effectful: false,
+        pure: false,
+    };
+
+    match kind {
+        BindingKind::Parameter => token.kind = SemanticTokenKind::Parameter,
+        BindingKind::Local => {}
+        BindingKind::Module => {
+            let scheme = check.type_env.vars.get(&name);
+            if let Some(effects) = scheme.and_then(|scheme| function_effects(&scheme.body)) {
+                token.kind = SemanticTokenKind::Function;
+                token.effectful = performs_effects(effects);
+                token.pure = scheme.is_some_and(|scheme| is_pure(&scheme.body));
+            }
+        }
+    }
+
+    token
+}
+
+/// Effect row of a (possibly quantified) function type
+pub(crate) fn function_effects(typ: &Type) -> Option<&EffectSet> {
+    match typ {
+        Type::Fun { effects, .. } => Some(effects),
+        Type::Forall { body, .. } => function_effects(body),
+        _ => None,
+    }
+}
+
+/// Whether an effect row contains at least one concrete effect
+fn performs_effects(effects: &EffectSet) -> bool {
+    match effects {
+        EffectSet::Empty | EffectSet::Var(_) => false,
+        EffectSet::Row { effects, tail } => {
+            !effects.is_empty() || tail.as_deref().is_some_and(performs_effects)
+        }
+    }
+}
+
+/// Whether a function type is known to perform no effects: its row names
+/// no effect, and a row variable it ends in can be instantiated to the empty
+/// row. A row variable shared with a parameter or the result makes the
+/// function effect-polymorphic, performing whatever effects its caller
+/// passes in, so such a function is neither effectful nor pure.
+fn is_pure(typ: &Type) -> bool {
+    match typ {
+        Type::Forall { body, .. } => is_pure(body),
+        Type::Fun { params, return_type, effects } => {
+            !performs_effects(effects) && effects.row_tail().is_none_or(|tail| {
+                !params.iter()
+                    .chain(std::iter::once(&**return_type))
+                    .any(|typ| typ.free_effect_vars().contains(&tail))
+            })
+        }
+        _ => false,
+    }
+}
+
+/// Names declared by type, effect and handler definitions
+#[derive(Debug, Default)]
+struct DeclaredNames {
+    effects: HashSet<Symbol>,
+    handlers: HashSet<Symbol>,
+    types: HashSet<Symbol>,
+    constructors: HashSet<Symbol>,
+    operations: HashSet<Symbol>,
+}
+
+impl DeclaredNames {
+    fn collect(cu: &CompilationUnit) -> Self {
+        let mut names = DeclaredNames::default();
+        for builtin in ["Int", "Float", "String", "Bool", "Unit", "List", "Option", "Result"] {
+            names.types.insert(Symbol::intern(builtin));
+        }
+
+        for item in &cu.module.items {
+            match item {
+                Item::TypeDef(def) => {
+                    names.types.insert(def.name);
+                    if let TypeDefKind::Data(constructors) = &def.kind {
+                        names.constructors.extend(constructors.iter().map(|c| c.name));
+                    }
+                }
+                Item::EffectDef(def) => {
+                    names.effects.insert(def.name);
+                    names.operations.extend(def.operations.iter().map(|op| op.name));
+                }
+                Item::HandlerDef(def) => {
+                    names.handlers.insert(def.name);
+                }
+                _ => {}
+            }
+        }
+
+        names
+    }
+
+    fn classify(&self, name: Symbol) -> Option<SemanticTokenKind> {
+        if self.effects.contains(&name) {
+            Some(SemanticTokenKind::Effect)
+        } else if self.handlers.contains(&name) {
+            Some(SemanticTokenKind::Handler)
+        } else if self.constructors.contains(&name) {
+            Some(SemanticTokenKind::Constructor)
+        } else if self.types.contains(&name) {
+            Some(SemanticTokenKind::Type)
+        } else if self.operations.contains(&name) {
+            Some(SemanticTokenKind::Operation)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x_checker::types::EffectVar;
+    use x_parser::{parse_source, SyntaxStyle};
+
+    fn tokens_for(source: &str) -> Vec<(String, SemanticTokenKind)> {
+        let cu = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let check = x_checker::type_check(&cu);
+        let chars: Vec<char> = source.chars().collect();
+        semantic_tokens(&cu, source, &check)
+            .into_iter()
+            .map(|token| {
+                let text = chars[token.span.start.as_u32() as usize..token.span.end.as_u32() as usize]
+                    .iter()
+                    .collect();
+                (text, token.kind)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_classifies_declared_names() {
+        let source = "module Test\nlet area = fun s -> s\ndata Shape = Circle Int | Square Int";
+        let tokens = tokens_for(source);
+
+        assert!(tokens.contains(&("Shape".to_string(), SemanticTokenKind::Type)));
+        assert!(tokens.contains(&("Circle".to_string(), SemanticTokenKind::Constructor)));
+        assert!(tokens.contains(&("Int".to_string(), SemanticTokenKind::Type)));
+        assert!(tokens.contains(&("s".to_string(), SemanticTokenKind::Parameter)));
+    }
+
+    #[test]
+    fn test_pure_function_modifier() {
+        let source = "module Test\nlet id = fun x -> x";
+        let cu = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let check = x_checker::type_check(&cu);
+
+        let id = semantic_tokens(&cu, source, &check)
+            .into_iter()
+            .find(|token| token.declaration && token.kind == SemanticTokenKind::Function)
+            .expect("`id` should be classified as a function");
+        assert!(id.pure);
+        assert!(!id.effectful);
+    }
+
+    #[test]
+    fn test_effect_polymorphic_function_is_not_pure() {
+        let var = |n| Type::Var(x_checker::types::TypeVar(n));
+        let row = EffectSet::Var(EffectVar(0));
+        let callback = Type::Fun { params: vec![var(0)], return_type: Box::new(var(1)), effects: row.clone() };
+        let apply = Type::Fun { params: vec![callback, var(0)], return_type: Box::new(var(1)), effects: row.clone() };
+        assert!(!is_pure(&apply));
+
+        let id = Type::Fun { params: vec![var(0)], return_type: Box::new(var(0)), effects: row };
+        assert!(is_pure(&id));
+    }
+
+    #[test]
+    fn test_performs_effects() {
+        let io = x_checker::Effect {
+            name: Symbol::intern("IO"),
+            operations: Vec::new(),
+        };
+        assert!(!performs_effects(&EffectSet::Empty));
+        assert!(performs_effects(&EffectSet::Row { effects: vec![io], tail: None }));
+        assert!(!performs_effects(&EffectSet::Var(EffectVar(0))));
+    }
+}
@@ -1,7 +1,11 @@
 //! Edit session management
 
+use crate::ast_editor::{AstEditor, EditError, EditResult};
+use crate::node_ids::NodeIds;
+use crate::query::{AstQuery, QueryResult};
 use crate::operations::{EditOperation, InsertOperation, EditableNode};
-use x_parser::{CompilationUnit, Expr, Literal, Span, FileId, span::ByteOffset};
+use x_parser::{CompilationUnit, Expr, Literal, Span, FileId, span::CharOffset};
+use x_parser::persistent_ast::{NodeId, PersistentUnit};
 use serde::{Deserialize, Serialize};
 use std::time::SystemTime;
 use uuid::Uuid;
@@ -47,6 +51,21 @@ pub enum SessionState {
     Closed,
 }
 
+/// Position in a session's history that can be rolled back to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Checkpoint {
+    position: usize,
+    /// Version of the history entry at `position` when the checkpoint was taken
+    version: u64,
+}
+
+impl Checkpoint {
+    /// Number of operations applied when the checkpoint was taken
+    pub fn position(self) -> usize {
+        self.position
+    }
+}
+
 /// Edit session containing AST and operation history
 #[derive(Debug)]
 pub struct EditSession {
@@ -64,12 +83,28 @@ pub struct EditSession {
     pub last_modified: SystemTime,
     /// Undo/redo position in operation history
     pub history_position: usize,
+    /// Inverse of each operation in `operations`, if it was applied through the session
+    inverses: Vec<Option<EditOperation>>,
+    /// The AST after each prefix of `operations`: `history[i]` is the state
+    /// after the first `i`; neighbouring versions share unchanged items
+    history: Vec<PersistentUnit>,
+    /// Stable ids of the current module items
+    node_ids: NodeIds,
+    /// The ids after each prefix of `operations`, alongside `history`
+    node_id_history: Vec<NodeIds>,
+    /// Version of each entry in `history`; an entry replaced by a new edit
+    /// gets a fresh one, so checkpoints on the abandoned branch stop matching
+    versions: Vec<u64>,
+    /// Version the next history entry gets
+    next_version: u64,
 }
 
 impl EditSession {
     /// Create a new edit session
     pub fn new(id: SessionId, ast: CompilationUnit) -> Self {
         let now = SystemTime::now();
+        let history = vec![PersistentUnit::from(&ast)];
+        let node_ids = NodeIds::assign(&ast);
         Self {
             id,
             ast,
@@ -78,20 +113,70 @@ impl EditSession {
             created_at: now,
             last_modified: now,
             history_position: 0,
+            inverses: Vec::new(),
+            history,
+            node_id_history: vec![node_ids.clone()],
+            node_ids,
+            versions: vec![0],
+            next_version: 1,
         }
     }
 
     /// Add an operation to the session history
+    ///
+    /// The operation is only recorded; it cannot be undone since its inverse
+    /// is unknown. Use `apply` to edit the AST and journal the inverse.
     pub fn add_operation(&mut self, operation: EditOperation) {
-        // Truncate history if we're not at the end (for redo)
-        self.operations.truncate(self.history_position);
+        self.record(operation, None);
+    }
+
+    /// Apply an operation to the session's AST and journal its inverse
+    ///
+    /// Targets given by id are resolved against the session's current ids;
+    /// the operation is journaled as given, so a redo finds its nodes again.
+    pub fn apply(
+        &mut self,
+        editor: &mut AstEditor,
+        operation: EditOperation,
+    ) -> Result<EditResult, EditError> {
+        let resolved = self.node_ids.resolve_operation(&operation)?;
+        let mut result = editor.apply_operation(&mut self.ast, resolved)?;
+        self.node_ids.record(&mut result);
+        let inverse = editor.inverse_operation(&result);
+        self.record(operation, Some(inverse));
+        Ok(result)
+    }
+
+    fn record(&mut self, operation: EditOperation, inverse: Option<EditOperation>) {
+        self.discard_redo();
         
         // Add the new operation
         self.operations.push(operation);
+        self.inverses.push(inverse);
+        self.history.push(self.history[self.history_position].update(&self.ast));
+        self.node_id_history.push(self.node_ids.clone());
+        let version = self.fresh_version();
+        self.versions.push(version);
         self.history_position = self.operations.len();
         self.last_modified = SystemTime::now();
     }
 
+    /// Drop the operations that could be redone, making the current
+    /// position the end of the history
+    pub fn discard_redo(&mut self) {
+        let position = self.history_position;
+        self.operations.truncate(position);
+        self.inverses.truncate(position);
+        self.history.truncate(position + 1);
+        self.node_id_history.truncate(position + 1);
+        self.versions.truncate(position + 1);
+    }
+
+    fn fresh_version(&mut self) -> u64 {
+        self.next_version += 1;
+        self.next_version - 1
+    }
+
     /// Check if undo is possible
     pub fn can_undo(&self) -> bool {
         self.history_position > 0
@@ -120,26 +205,112 @@ impl EditSession {
         }
     }
 
-    /// Move undo position back
-    pub fn undo(&mut self) -> Option<&EditOperation> {
-        if self.can_undo() {
-            self.history_position -= 1;
-            self.last_modified = SystemTime::now();
-            self.operations.get(self.history_position)
-        } else {
-            None
+    /// Revert the most recently applied operation
+    ///
+    /// Returns `Ok(None)` when there is nothing to undo.
+    pub fn undo(&mut self, editor: &mut AstEditor) -> Result<Option<EditResult>, EditError> {
+        if !self.can_undo() {
+            return Ok(None);
         }
+
+        let index = self.history_position - 1;
+        let inverse = self.inverses[index].clone()
+            .ok_or(EditError::Irreversible { index })?;
+        let result = editor.apply_operation(&mut self.ast, inverse)?;
+
+        self.node_ids = self.node_id_history[index].clone();
+        self.history_position = index;
+        self.last_modified = SystemTime::now();
+        Ok(Some(result))
     }
 
-    /// Move redo position forward
-    pub fn redo(&mut self) -> Option<&EditOperation> {
-        if self.can_redo() {
-            let operation = self.operations.get(self.history_position);
-            self.history_position += 1;
-            self.last_modified = SystemTime::now();
-            operation
-        } else {
-            None
+    /// Re-apply the most recently undone operation
+    ///
+    /// Returns `Ok(None)` when there is nothing to redo.
+    pub fn redo(&mut self, editor: &mut AstEditor) -> Result<Option<EditResult>, EditError> {
+        if !self.can_redo() {
+            return Ok(None);
+        }
+
+        let operation = self.node_ids.resolve_operation(&self.operations[self.history_position])?;
+        let mut result = editor.apply_operation(&mut self.ast, operation)?;
+
+        // Items inserted again get back the ids they had before the undo
+        self.node_ids = self.node_id_history[self.history_position + 1].clone();
+        self.node_ids.label(&mut result);
+
+        // Re-derive the inverse; it may differ from the original one (e.g. new node ids)
+        self.inverses[self.history_position] = Some(editor.inverse_operation(&result));
+        self.history[self.history_position + 1] = self.history[self.history_position].update(&self.ast);
+        self.history_position += 1;
+        self.last_modified = SystemTime::now();
+        Ok(Some(result))
+    }
+
+    /// Mark the current point in the history
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            position: self.history_position,
+            version: self.versions[self.history_position],
+        }
+    }
+
+    /// Move the history back (or forward) to `checkpoint`, restoring the AST
+    /// from its snapshot instead of replaying operations
+    ///
+    /// A checkpoint taken on a branch of the history that was undone and then
+    /// overwritten by new edits, or before the history was cleared, can no
+    /// longer be reached and is rejected, leaving the session as it was.
+    pub fn rollback_to(&mut self, checkpoint: Checkpoint) -> Result<(), EditError> {
+        let snapshot = self.snapshot_at(checkpoint)
+            .ok_or(EditError::InvalidCheckpoint { position: checkpoint.position })?;
+
+        self.ast = snapshot.to_compilation_unit();
+        self.node_ids = self.node_id_history[checkpoint.position].clone();
+        self.history_position = checkpoint.position;
+        self.last_modified = SystemTime::now();
+        Ok(())
+    }
+
+    /// The current AST as a persistent snapshot, cheap to keep and safe to
+    /// read from other threads while the session goes on
+    pub fn snapshot(&self) -> PersistentUnit {
+        self.history[self.history_position].update(&self.ast)
+    }
+
+    /// The AST as it was at `checkpoint`, if the history still reaches it
+    pub fn snapshot_at(&self, checkpoint: Checkpoint) -> Option<&PersistentUnit> {
+        (self.versions.get(checkpoint.position) == Some(&checkpoint.version))
+            .then(|| &self.history[checkpoint.position])
+    }
+
+    /// Stable ids of the current module items
+    pub fn node_ids(&self) -> &NodeIds {
+        &self.node_ids
+    }
+
+    /// Id of the node at `path`
+    pub fn node_id(&self, path: &[usize]) -> Option<NodeId> {
+        self.node_ids.at_path(path)
+    }
+
+    /// Current path of the node with id `node_id`
+    pub fn path_of(&self, node_id: NodeId) -> Option<Vec<usize>> {
+        self.node_ids.path_of(node_id)
+    }
+
+    /// Query the session's AST, answering queries about ids from the
+    /// session's own table
+    pub fn query(&self, editor: &AstEditor, query: AstQuery) -> Result<QueryResult, EditError> {
+        match query {
+            AstQuery::ById { node_id } => {
+                Ok(QueryResult::new(self.node_ids.index_of(node_id).map(|_| node_id).into_iter().collect()))
+            }
+            AstQuery::FindByPath { path } => match self.node_ids.at_path(&path) {
+                Some(node_id) => Ok(QueryResult::new(vec![node_id])),
+                None => editor.query(&self.ast, AstQuery::FindByPath { path }),
+            },
+            query => editor.query(&self.ast, query),
         }
     }
 
@@ -167,7 +338,11 @@ impl EditSession {
 
     /// Clear operation history
     pub fn clear_history(&mut self) {
+        self.history = vec![self.snapshot()];
+        self.node_id_history = vec![self.node_ids.clone()];
+        self.versions = vec![self.fresh_version()];
         self.operations.clear();
+        self.inverses.clear();
         self.history_position = 0;
         self.last_modified = SystemTime::now();
     }
@@ -192,7 +367,7 @@ impl EditSession {
 mod tests {
     use super::*;
     use x_parser::{parse_source, FileId, SyntaxStyle};
-    use crate::operations::{EditOperation, InsertOperation};
+    use crate::operations::{EditOperation, InsertOperation, NodeRef};
     use x_parser::{Expr, Literal};
 
     #[test]
@@ -222,8 +397,8 @@ mod tests {
         let mut session = EditSession::new(id, ast);
         
         let operation = EditOperation::Insert(InsertOperation {
-            path: vec![0],
-            node: EditableNode::Expr(Expr::Literal(Literal::Integer(100), Span::new(FileId::new(0), ByteOffset(0), ByteOffset(3)))),
+            target: NodeRef::Path(vec![0]),
+            node: EditableNode::Expr(Expr::Literal(Literal::int(100), Span::new(FileId::new(0), CharOffset(0), CharOffset(3)))),
         });
         
         session.add_operation(operation);
@@ -232,34 +407,167 @@ mod tests {
         assert!(!session.can_redo());
     }
 
+    fn value_def(name: &str, value: i64) -> EditableNode {
+        let span = Span::new(FileId::new(0), CharOffset(0), CharOffset(0));
+        EditableNode::Item(x_parser::Item::ValueDef(x_parser::ValueDef {
+            name: x_parser::Symbol::intern(name),
+            documentation: None,
+            type_annotation: None,
+            parameters: vec![],
+            body: Expr::Literal(Literal::int(value), span),
+            visibility: x_parser::Visibility::Private,
+            purity: x_parser::Purity::Pure,
+            imports: Vec::new(),
+            span,
+        }))
+    }
+
+    fn item_names(session: &EditSession) -> Vec<String> {
+        session.ast.module.items.iter()
+            .filter_map(|item| match item {
+                x_parser::Item::ValueDef(def) => Some(def.name.to_string()),
+                _ => None,
+            })
+            .collect()
+    }
+
     #[test]
     fn test_undo_redo() {
-        let source = "let x = 42";
+        let source = "module Test\nlet x = 42";
         let ast = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
-        let id = SessionId::new();
-        let mut session = EditSession::new(id, ast);
-        
-        let operation = EditOperation::Insert(InsertOperation {
-            path: vec![0],
-            node: EditableNode::Expr(Expr::Literal(Literal::Integer(100), Span::new(FileId::new(0), ByteOffset(0), ByteOffset(3)))),
-        });
-        
-        session.add_operation(operation);
+        let mut editor = AstEditor::new();
+        let mut session = EditSession::new(SessionId::new(), ast);
+
+        session.apply(&mut editor, EditOperation::insert(vec![0, 1], value_def("y", 1))).unwrap();
+        assert_eq!(item_names(&session), ["x", "y"]);
         
         // Test undo
         assert!(session.can_undo());
-        let undone = session.undo();
+        let undone = session.undo(&mut editor).unwrap();
         assert!(undone.is_some());
+        assert_eq!(item_names(&session), ["x"]);
         assert!(!session.can_undo());
         assert!(session.can_redo());
         
         // Test redo
-        let redone = session.redo();
+        let redone = session.redo(&mut editor).unwrap();
         assert!(redone.is_some());
+        assert_eq!(item_names(&session), ["x", "y"]);
         assert!(session.can_undo());
         assert!(!session.can_redo());
     }
 
+    #[test]
+    fn test_rollback_to_checkpoint() {
+        let source = "module Test\nlet x = 42";
+        let ast = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let mut editor = AstEditor::new();
+        let mut session = EditSession::new(SessionId::new(), ast);
+
+        let checkpoint = session.checkpoint();
+        session.apply(&mut editor, EditOperation::replace(vec![0, 0], value_def("x", 7))).unwrap();
+        session.apply(&mut editor, EditOperation::insert(vec![0, 0], value_def("w", 0))).unwrap();
+        session.apply(&mut editor, EditOperation::move_node(vec![0, 0], vec![0, 1])).unwrap();
+        session.apply(&mut editor, EditOperation::delete(vec![0, 0])).unwrap();
+        assert_eq!(item_names(&session), ["w"]);
+
+        session.rollback_to(checkpoint).unwrap();
+        assert_eq!(session.ast, parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap());
+        assert_eq!(session.history_position, 0);
+    }
+
+    #[test]
+    fn test_rollback_rejects_abandoned_branch() {
+        let source = "module Test\nlet x = 42";
+        let ast = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let mut editor = AstEditor::new();
+        let mut session = EditSession::new(SessionId::new(), ast);
+
+        session.apply(&mut editor, EditOperation::replace(vec![0, 0], value_def("x", 7))).unwrap();
+        let abandoned = session.checkpoint();
+        session.undo(&mut editor).unwrap();
+        session.apply(&mut editor, EditOperation::replace(vec![0, 0], value_def("y", 1))).unwrap();
+        assert_eq!(session.checkpoint().position(), abandoned.position());
+
+        // The position exists again, but on a different branch
+        let err = session.rollback_to(abandoned).unwrap_err();
+        assert!(matches!(err, EditError::InvalidCheckpoint { position: 1 }));
+        assert_eq!(item_names(&session), ["y"]);
+        assert_eq!(session.snapshot_at(abandoned), None);
+
+        let current = session.checkpoint();
+        session.clear_history();
+        assert!(session.rollback_to(current).is_err());
+        assert!(session.rollback_to(session.checkpoint()).is_ok());
+    }
+
+    #[test]
+    fn test_recorded_operations_are_irreversible() {
+        let source = "module Test\nlet x = 42";
+        let ast = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let mut editor = AstEditor::new();
+        let mut session = EditSession::new(SessionId::new(), ast);
+
+        session.add_operation(EditOperation::delete(vec![0, 0]));
+        let err = session.undo(&mut editor).unwrap_err();
+        assert!(matches!(err, EditError::Irreversible { index: 0 }));
+        assert!(session.rollback_to(Checkpoint { position: 5, version: 0 }).is_err());
+    }
+
+    #[test]
+    fn test_snapshots_share_unchanged_items() {
+        let source = "module Test\nlet x = 42\nlet y = 1";
+        let ast = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let mut editor = AstEditor::new();
+        let mut session = EditSession::new(SessionId::new(), ast);
+
+        let before = session.snapshot();
+        let checkpoint = session.checkpoint();
+        session.apply(&mut editor, EditOperation::replace(vec![0, 1], value_def("y", 2))).unwrap();
+        let after = session.snapshot();
+        assert!(std::sync::Arc::ptr_eq(before.item(0).unwrap(), after.item(0).unwrap()));
+        assert!(!std::sync::Arc::ptr_eq(before.item(1).unwrap(), after.item(1).unwrap()));
+
+        // A reader on another thread keeps its version while the session moves on
+        let edited = session.ast.clone();
+        let reader = std::thread::spawn(move || after.to_compilation_unit());
+        session.rollback_to(checkpoint).unwrap();
+        assert_eq!(session.snapshot_at(checkpoint), Some(&before));
+        assert_eq!(session.ast, before.to_compilation_unit());
+        assert_eq!(reader.join().unwrap(), edited);
+    }
+
+    #[test]
+    fn test_node_ids_survive_edits_and_undo() {
+        let source = "module Test\nlet x = 42\nlet y = 1";
+        let ast = parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let mut editor = AstEditor::new();
+        let mut session = EditSession::new(SessionId::new(), ast);
+        let y = session.node_id(&[0, 1]).unwrap();
+
+        let result = session.apply(&mut editor, EditOperation::insert(vec![0, 0], value_def("w", 0))).unwrap();
+        let EditResult::Inserted { node_id: Some(w), .. } = result else { panic!("expected an id") };
+        assert_eq!(session.path_of(y), Some(vec![0, 2]));
+
+        session.apply(&mut editor, EditOperation::replace(y, value_def("y", 2))).unwrap();
+        session.apply(&mut editor, EditOperation::move_node(y, w)).unwrap();
+        assert_eq!(item_names(&session), ["y", "w", "x"]);
+        assert_eq!(session.query(&editor, AstQuery::ById { node_id: y }).unwrap().first(), Some(y));
+
+        session.undo(&mut editor).unwrap();
+        session.undo(&mut editor).unwrap();
+        session.undo(&mut editor).unwrap();
+        session.redo(&mut editor).unwrap();
+        assert_eq!(session.node_id(&[0, 0]), Some(w), "a redone insert keeps its id");
+
+        session.apply(&mut editor, EditOperation::delete(w)).unwrap();
+        assert!(session.query(&editor, AstQuery::ById { node_id: w }).unwrap().is_empty());
+        assert!(matches!(
+            session.apply(&mut editor, EditOperation::delete(w)),
+            Err(EditError::NodeNotFound { .. })
+        ));
+    }
+
     #[test]
     fn test_session_state() {
         let source = "let x = 42";
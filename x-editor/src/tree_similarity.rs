@@ -1,9 +1,12 @@
 //! Tree similarity algorithms for AST comparison
 //! 
 //! Implements APTED (All Path Tree Edit Distance) and TSED (Tree Structure Edit Distance)
-//! for structural similarity computation.
+//! for structural similarity computation, and [`diff_compilation_units`],
+//! which turns the difference between two modules into an edit script.
 
+use crate::operations::{EditOperation, EditableNode};
 use x_parser::ast::*;
+use x_parser::syntax::{ocaml::OCamlPrinter, SyntaxConfig};
 
 /// Tree node abstraction for similarity algorithms
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -29,8 +32,8 @@ impl TreeNode {
         match expr {
             Expr::Literal(lit, _) => {
                 let label = match lit {
-                    Literal::Integer(n) => format!("Int:{n}"),
-                    Literal::Float(f) => format!("Float:{f}"),
+                    Literal::Integer(n, _) => format!("Int:{n}"),
+                    Literal::Float(f, _) => format!("Float:{f}"),
                     Literal::String(s) => format!("String:{s}"),
                     Literal::Bool(b) => format!("Bool:{b}"),
                     Literal::Unit => "Unit".to_string(),
@@ -104,8 +107,8 @@ impl TreeNode {
             }
             Pattern::Literal(lit, _) => {
                 let label = match lit {
-                    Literal::Integer(n) => format!("PatInt:{n}"),
-                    Literal::Float(f) => format!("PatFloat:{f}"),
+                    Literal::Integer(n, _) => format!("PatInt:{n}"),
+                    Literal::Float(f, _) => format!("PatFloat:{f}"),
                     Literal::String(s) => format!("PatString:{s}"),
                     Literal::Bool(b) => format!("PatBool:{b}"),
                     Literal::Unit => "PatUnit".to_string(),
@@ -396,13 +399,173 @@ pub struct SimilarityReport {
     pub tree2_depth: usize,
 }
 
+/// Edit script that turns `old` into `new` when applied in order
+///
+/// Module items are compared by their canonical printed form, so spans,
+/// layout and comments do not count as changes. The items are aligned with
+/// the fewest inserts, deletes and replaces, preferring to replace an item by
+/// one of the same kind and name; an item deleted in one place and inserted
+/// unchanged in another becomes a single move.
+pub fn diff_compilation_units(old: &CompilationUnit, new: &CompilationUnit) -> Vec<EditOperation> {
+    let old_items = &old.module.items;
+    let new_items = &new.module.items;
+    let old_keys: Vec<String> = old_items.iter().map(item_key).collect();
+    let new_keys: Vec<String> = new_items.iter().map(item_key).collect();
+    let same_identity = |i: usize, j: usize| {
+        item_identity(&old_items[i]).is_some_and(|identity| Some(identity) == item_identity(&new_items[j]))
+    };
+    let steps = align(&old_keys, &new_keys, same_identity);
+
+    // Pair deletes and inserts of identical items into moves
+    let mut moved_to = vec![None; old_items.len()];
+    let mut moved_from = vec![None; new_items.len()];
+    for step in &steps {
+        if let DiffStep::Delete(i) = *step {
+            let partner = steps.iter().find_map(|step| match *step {
+                DiffStep::Insert(j) if moved_from[j].is_none() && old_keys[i] == new_keys[j] => Some(j),
+                _ => None,
+            });
+            if let Some(j) = partner {
+                moved_to[i] = Some(j);
+                moved_from[j] = Some(i);
+            }
+        }
+    }
+
+    // Replay the steps on a list of item tokens, so every path is taken
+    // against the tree as the earlier operations left it; old item `i` is
+    // token `i` and new item `j` token `old_items.len() + j`
+    let mut tokens: Vec<usize> = (0..old_items.len()).collect();
+    let position = |tokens: &[usize], token: usize| tokens.iter().position(|&t| t == token).unwrap_or(0);
+    let mut last_placed: Option<usize> = None;
+    let mut operations = Vec::new();
+    for step in steps {
+        let insert_at = |tokens: &[usize], last_placed: Option<usize>| {
+            last_placed.map_or(0, |token| position(tokens, token) + 1)
+        };
+        match step {
+            DiffStep::Keep(i, _) => last_placed = Some(i),
+            DiffStep::Replace(i, j) => {
+                operations.push(EditOperation::replace(vec![0, position(&tokens, i)], EditableNode::Item(new_items[j].clone())));
+                last_placed = Some(i);
+            }
+            DiffStep::Delete(i) if moved_to[i].is_some() => {}
+            DiffStep::Delete(i) => {
+                let at = position(&tokens, i);
+                tokens.remove(at);
+                operations.push(EditOperation::delete(vec![0, at]));
+            }
+            DiffStep::Insert(j) => match moved_from[j] {
+                Some(i) => {
+                    let from = position(&tokens, i);
+                    tokens.remove(from);
+                    let to = insert_at(&tokens, last_placed);
+                    tokens.insert(to, i);
+                    operations.push(EditOperation::move_node(vec![0, from], vec![0, to]));
+                    last_placed = Some(i);
+                }
+                None => {
+                    let token = old_items.len() + j;
+                    let to = insert_at(&tokens, last_placed);
+                    tokens.insert(to, token);
+                    operations.push(EditOperation::insert(vec![0, to], EditableNode::Item(new_items[j].clone())));
+                    last_placed = Some(token);
+                }
+            },
+        }
+    }
+    operations
+}
+
+/// One column of the alignment between the old and the new items
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DiffStep {
+    Keep(usize, usize),
+    Replace(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// Cheapest alignment of `old` with `new`, every step but a keep costing one
+fn align(old: &[String], new: &[String], same_identity: impl Fn(usize, usize) -> bool) -> Vec<DiffStep> {
+    let (n, m) = (old.len(), new.len());
+    // cost[i][j]: edits needed to turn old[i..] into new[j..]
+    let mut cost = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..=n).rev() {
+        for j in (0..=m).rev() {
+            cost[i][j] = if i == n {
+                m - j
+            } else if j == m {
+                n - i
+            } else if old[i] == new[j] {
+                cost[i + 1][j + 1]
+            } else {
+                1 + cost[i + 1][j + 1].min(cost[i + 1][j]).min(cost[i][j + 1])
+            };
+        }
+    }
+
+    let mut steps = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        let here = cost[i][j];
+        let step = if i < n && j < m && old[i] == new[j] {
+            DiffStep::Keep(i, j)
+        } else if i < n && j < m && same_identity(i, j) && here == cost[i + 1][j + 1] + 1 {
+            DiffStep::Replace(i, j)
+        } else if i < n && here == cost[i + 1][j] + 1 {
+            DiffStep::Delete(i)
+        } else if j < m && here == cost[i][j + 1] + 1 {
+            DiffStep::Insert(j)
+        } else {
+            DiffStep::Replace(i, j)
+        };
+        match step {
+            DiffStep::Keep(..) | DiffStep::Replace(..) => (i, j) = (i + 1, j + 1),
+            DiffStep::Delete(_) => i += 1,
+            DiffStep::Insert(_) => j += 1,
+        }
+        steps.push(step);
+    }
+    steps
+}
+
+/// What an item is compared by: its canonical text, or its debug form when
+/// the printer cannot print it
+pub(crate) fn item_key(item: &Item) -> String {
+    OCamlPrinter::new().print_item(item, &SyntaxConfig::default())
+        .unwrap_or_else(|_| format!("{item:?}"))
+}
+
+/// Kind and name of an item, which a changed version of it keeps
+pub(crate) fn item_identity(item: &Item) -> Option<(&'static str, String)> {
+    match item {
+        Item::TypeDef(def) => Some(("type", def.name.to_string())),
+        Item::ValueDef(def) => Some(("value", def.name.to_string())),
+        Item::EffectDef(def) => Some(("effect", def.name.to_string())),
+        Item::HandlerDef(def) => Some(("handler", def.name.to_string())),
+        Item::ModuleTypeDef(def) => Some(("module type", def.name.to_string())),
+        Item::InterfaceDef(def) => Some(("interface", def.name.clone())),
+        Item::TestDef(def) => Some(("test", def.name.to_string())),
+        Item::BenchDef(def) => Some(("bench", def.name.to_string())),
+        Item::ClassDef(def) => Some(("class", def.name.to_string())),
+        Item::InstanceDef(_) => None,
+        Item::Fixity(decl) => {
+            let operators: Vec<_> = decl.operators.iter().map(|operator| operator.as_str()).collect();
+            Some(("fixity", operators.join(" ")))
+        }
+        Item::Conditional(conditional) => item_identity(&conditional.item),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use x_parser::{FileId, Span, span::ByteOffset, Symbol};
+    use crate::ast_editor::AstEditor;
+    use x_parser::{FileId, Span, span::CharOffset, Symbol};
     
     fn make_span() -> Span {
-        Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(1))
+        Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(1))
     }
     
     #[test]
@@ -410,8 +573,8 @@ mod tests {
         let expr = Expr::App(
             Box::new(Expr::Var(Symbol::intern("+"), make_span())),
             vec![
-                Expr::Literal(Literal::Integer(1), make_span()),
-                Expr::Literal(Literal::Integer(2), make_span()),
+                Expr::Literal(Literal::int(1), make_span()),
+                Expr::Literal(Literal::int(2), make_span()),
             ],
             make_span(),
         );
@@ -452,4 +615,36 @@ mod tests {
         // Should have high similarity because structure is same
         assert!(tsed.similarity(&tree1, &tree2) > 0.8);
     }
-}
\ No newline at end of file
+
+    fn parse(source: &str) -> CompilationUnit {
+        x_parser::parse_source(source, FileId::new(0), x_parser::SyntaxStyle::SExpression).unwrap()
+    }
+
+    fn keys(unit: &CompilationUnit) -> Vec<String> {
+        unit.module.items.iter().map(item_key).collect()
+    }
+
+    fn assert_script_turns(old: &str, new: &str, expected_len: usize) {
+        let (mut old, new) = (parse(old), parse(new));
+        let script = diff_compilation_units(&old, &new);
+        assert_eq!(script.len(), expected_len, "{script:#?}");
+        let mut editor = AstEditor::new();
+        for operation in script {
+            editor.apply_operation(&mut old, operation).unwrap();
+        }
+        assert_eq!(keys(&old), keys(&new));
+    }
+
+    #[test]
+    fn test_diff_replaces_changed_items_and_ignores_layout() {
+        assert_script_turns("module M\nlet a = 1\nlet b = 2", "module M\n\nlet a   =  1\nlet b = 3", 1);
+        assert_script_turns("module M\nlet a = 1\nlet b = 2", "module M\nlet z = 0\nlet a = 1\nlet c = 4", 2);
+    }
+
+    #[test]
+    fn test_diff_moves_unchanged_items() {
+        assert_script_turns("module M\nlet a = 1\nlet b = 2\nlet c = 3", "module M\nlet b = 2\nlet c = 3\nlet a = 1", 1);
+        assert_script_turns("module M\nlet a = 1\nlet b = 2\nlet c = 3", "module M\nlet c = 3\nlet b = 2\nlet a = 1", 2);
+        assert_script_turns("module M\nlet a = 1\nlet b = 2", "module M", 2);
+    }
+}
@@ -0,0 +1,221 @@
+//! Parse storage reused across parses
+//!
+//! The AST is owned: nodes are `Box`es and `Vec`s that callers keep, clone
+//! and drop like any other value, each node freeing its own allocation.
+//! Allocating them from a bump region would need either allocator-aware
+//! boxes, which stable Rust lacks, or AST types borrowing from the region,
+//! which would put a lifetime on every tree and change every caller. So
+//! `AstArena` pools nodes instead of carving them from a region.
+//!
+//! Every parse leaves the text of its identifier and literal tokens in a
+//! pool shared by all files, and trees given back with `recycle` leave the
+//! boxes and argument lists of their expressions there too. The first
+//! parse of a file thus lexes into the strings and builds its expressions
+//! in the nodes of trees parsed from other files. Each file also keeps the
+//! character and token buffers of its last parse for its next one. An
+//! editor reparsing a large file on every change, or opening one file after
+//! another, then allocates little beyond what grew.
+//!
+//! ```ignore
+//! let mut arena = AstArena::with_limit(64 << 20);
+//! let unit = arena.parse(&source, file_id)?;
+//! // ... the source changes ...
+//! arena.recycle(unit);
+//! let unit = arena.parse(&source, file_id)?;
+//! ```
+//!
+//! The memory limit bounds the characters, tokens and expression nodes of
+//! a single parse, so a huge generated file fails with
+//! `ParseError::MemoryLimit` instead of exhausting memory.
+
+use std::collections::HashMap;
+use std::mem::size_of;
+
+use crate::ast::{CompilationUnit, Expr, Item};
+use crate::error::Result;
+use crate::parser::{Parser, RecoveredParse};
+use crate::span::{CharOffset, FileId, Span};
+use crate::token::Token;
+
+/// Buffers and nodes one parse leaves for the next
+#[derive(Debug, Default)]
+pub(crate) struct ParseStorage {
+    pub(crate) chars: Vec<char>,
+    pub(crate) tokens: Vec<Token>,
+    /// Emptied strings for token text
+    pub(crate) text: Vec<String>,
+    /// Boxes holding `Expr::Error`, to overwrite with new nodes; kept
+    /// boxed because the allocations are what is reused
+    #[allow(clippy::vec_box)]
+    pub(crate) boxes: Vec<Box<Expr>>,
+    /// Emptied argument lists
+    pub(crate) lists: Vec<Vec<Expr>>,
+}
+
+impl ParseStorage {
+    fn bytes(&self) -> usize {
+        self.chars.capacity() * size_of::<char>()
+            + self.tokens.capacity() * size_of::<Token>()
+            + self.text.iter().map(String::capacity).sum::<usize>()
+            + self.boxes.len() * size_of::<Expr>()
+            + self.lists.iter().map(Vec::capacity).sum::<usize>() * size_of::<Expr>()
+    }
+
+    /// Take apart `expr`, keeping its boxes and lists
+    fn reclaim(&mut self, expr: Expr) {
+        match expr {
+            Expr::App(function, args, _) => {
+                self.reclaim_box(function);
+                self.reclaim_list(args);
+            }
+            Expr::Lambda { body, .. } | Expr::Resume { value: body, .. } => self.reclaim_box(body),
+            Expr::Ann { expr, .. } | Expr::Project { record: expr, .. } => self.reclaim_box(expr),
+            Expr::Handle { expr, .. } => self.reclaim_box(expr),
+            Expr::Let { value, body, .. } | Expr::Unpack { value, body, .. } => {
+                self.reclaim_box(value);
+                self.reclaim_box(body);
+            }
+            Expr::If { condition, then_branch, else_branch, .. } => {
+                self.reclaim_box(condition);
+                self.reclaim_box(then_branch);
+                self.reclaim_box(else_branch);
+            }
+            Expr::Match { scrutinee, arms, .. } => {
+                self.reclaim_box(scrutinee);
+                for arm in arms {
+                    if let Some(guard) = arm.guard {
+                        self.reclaim_box(guard);
+                    }
+                    self.reclaim(arm.body);
+                }
+            }
+            Expr::Perform { args, .. } | Expr::Tuple { elements: args, .. } => self.reclaim_list(args),
+            Expr::Record { fields, .. } => fields.into_iter().for_each(|(_, value)| self.reclaim(value)),
+            Expr::RecordUpdate { record, fields, .. } => {
+                self.reclaim_box(record);
+                fields.into_iter().for_each(|(_, value)| self.reclaim(value));
+            }
+            Expr::Literal(..) | Expr::Var(..) | Expr::Do { .. } | Expr::Pack { .. } | Expr::Error(_) => {}
+        }
+    }
+
+    fn reclaim_box(&mut self, mut node: Box<Expr>) {
+        let placeholder = Expr::Error(Span::single(FileId::new(0), CharOffset::new(0)));
+        let expr = std::mem::replace(&mut *node, placeholder);
+        self.reclaim(expr);
+        self.boxes.push(node);
+    }
+
+    fn reclaim_list(&mut self, mut list: Vec<Expr>) {
+        for expr in list.drain(..) {
+            self.reclaim(expr);
+        }
+        self.lists.push(list);
+    }
+}
+
+/// Parse buffers for each file and a pool of text and nodes shared between
+/// files, with an optional memory limit per parse
+#[derive(Debug, Default)]
+pub struct AstArena {
+    /// Character and token buffers of each file's last parse
+    files: HashMap<FileId, ParseStorage>,
+    /// Token text of every parse and the boxes and argument lists of
+    /// recycled trees, for any file
+    shared: ParseStorage,
+    limit: Option<usize>,
+}
+
+impl AstArena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An arena whose parses fail past `limit` bytes of characters, tokens
+    /// and expression nodes
+    pub fn with_limit(limit: usize) -> Self {
+        AstArena { limit: Some(limit), ..Self::default() }
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    /// Parse `source`, reusing the buffers of the last parse of `file_id`
+    /// and the pooled text and nodes
+    pub fn parse(&mut self, source: &str, file_id: FileId) -> Result<CompilationUnit> {
+        self.with_parser(source, file_id, Parser::parse)
+    }
+
+    /// Parse `source` reporting every syntax error, reusing storage as
+    /// `parse` does
+    pub fn parse_recovering(&mut self, source: &str, file_id: FileId) -> Result<RecoveredParse> {
+        self.with_parser(source, file_id, Parser::parse_recovering)
+    }
+
+    fn with_parser<T>(&mut self, source: &str, file_id: FileId, parse: fn(&mut Parser) -> Result<T>) -> Result<T> {
+        let mut storage = self.files.remove(&file_id).unwrap_or_default();
+        storage.text = std::mem::take(&mut self.shared.text);
+        let mut parser = Parser::with_storage(source, file_id, 0, storage, self.limit)?;
+        parser.swap_nodes(&mut self.shared);
+        let result = parse(&mut parser);
+        parser.swap_nodes(&mut self.shared);
+        let mut storage = parser.into_storage();
+        self.shared.text = std::mem::take(&mut storage.text);
+        self.files.insert(file_id, storage);
+        result
+    }
+
+    /// Give back a tree that is no longer needed, so that later parses of
+    /// any file build their expressions in its nodes
+    pub fn recycle(&mut self, unit: CompilationUnit) {
+        for item in unit.module.items {
+            match item {
+                Item::ValueDef(def) => self.shared.reclaim(def.body),
+                Item::InstanceDef(instance) => {
+                    instance.methods.into_iter().for_each(|method| self.shared.reclaim(method.body));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Drop the buffers kept for `file_id`, as when it is closed
+    pub fn release(&mut self, file_id: FileId) {
+        self.files.remove(&file_id);
+    }
+
+    /// Bytes kept for later parses across all files
+    pub fn retained_bytes(&self) -> usize {
+        self.files.values().chain([&self.shared]).map(ParseStorage::bytes).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ParseError;
+
+    fn generated(lines: usize) -> String {
+        let mut source = String::from("module Generated\n");
+        for i in 0..lines {
+            source.push_str(&format!("let f{i} = fun x y -> (x + y) * {i}\n"));
+        }
+        source
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let source = generated(50);
+        let file_id = FileId::new(0);
+
+        let mut arena = AstArena::with_limit(16 * 1024);
+        assert_eq!(arena.limit(), Some(16 * 1024));
+        assert!(matches!(arena.parse(&source, file_id), Err(ParseError::MemoryLimit { limit: 16384 })));
+        assert!(matches!(arena.parse_recovering(&source, file_id), Err(ParseError::MemoryLimit { limit: 16384 })));
+
+        let mut arena = AstArena::with_limit(16 << 20);
+        assert!(arena.parse(&source, file_id).is_ok());
+        assert_eq!(AstArena::new().limit(), None);
+    }
+}
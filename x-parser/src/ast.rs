@@ -26,6 +26,18 @@ pub struct Module {
     pub span: Span,
 }
 
+impl Module {
+    /// Operators the module declares a fixity for
+    pub fn fixities(&self) -> impl Iterator<Item = (Symbol, Fixity)> + '_ {
+        self.items.iter()
+            .filter_map(|item| match item {
+                Item::Fixity(decl) => Some(decl),
+                _ => None,
+            })
+            .flat_map(|decl| decl.operators.iter().map(move |operator| (*operator, decl.fixity)))
+    }
+}
+
 /// Module path (e.g., Core.Types.User)
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct ModulePath {
@@ -126,7 +138,10 @@ pub enum ImportKind {
     Wildcard,
     /// `lazy import Module` - Lazy import
     Lazy,
-    /// `import Module when condition` - Conditional import
+    /// `import Module : Signature` - Qualified import seen through a module type
+    Signature(Symbol),
+    /// `import Module when condition` - Conditional import, kept or dropped
+    /// by the compiler for each target
     Conditional(Box<Expr>),
     /// WebAssembly Component Model style imports
     /// `import interface "wasi:io/poll@0.2.0" { poll-one, poll-list }`
@@ -195,6 +210,16 @@ pub enum Item {
     InterfaceDef(ComponentInterface),
     /// Test definition
     TestDef(TestDef),
+    /// Benchmark definition
+    BenchDef(BenchDef),
+    /// Type class declaration
+    ClassDef(ClassDef),
+    /// Type class instance
+    InstanceDef(InstanceDef),
+    /// Fixity declaration of infix operators
+    Fixity(FixityDecl),
+    /// Item compiled only for the targets its `#[cfg(...)]` selects
+    Conditional(ConditionalItem),
 }
 
 impl Item {
@@ -207,6 +232,32 @@ impl Item {
             Item::ModuleTypeDef(def) => def.span,
             Item::InterfaceDef(def) => def.span,
             Item::TestDef(def) => def.span,
+            Item::BenchDef(def) => def.span,
+            Item::ClassDef(def) => def.span,
+            Item::InstanceDef(def) => def.span,
+            Item::Fixity(decl) => decl.span,
+            Item::Conditional(conditional) => conditional.span,
+        }
+    }
+    
+    /// The item under any `#[cfg(...)]` attributes
+    pub fn unconditional(&self) -> &Item {
+        match self {
+            Item::Conditional(conditional) => conditional.item.unconditional(),
+            item => item,
+        }
+    }
+    
+    /// The item's doc comment, if it has one
+    pub fn documentation(&self) -> Option<&Documentation> {
+        match self.unconditional() {
+            Item::TypeDef(def) => def.documentation.as_ref(),
+            Item::ValueDef(def) => def.documentation.as_ref(),
+            Item::EffectDef(def) => def.documentation.as_ref(),
+            Item::TestDef(def) => def.documentation.as_ref(),
+            Item::BenchDef(def) => def.documentation.as_ref(),
+            Item::ClassDef(def) => def.documentation.as_ref(),
+            _ => None,
         }
     }
 }
@@ -236,6 +287,8 @@ pub enum TypeDefKind {
 pub struct Constructor {
     pub name: Symbol,
     pub fields: Vec<Type>,
+    /// Refined result type of a GADT constructor: `IntLit Int : Expr[Int]`
+    pub result: Option<Type>,
     pub span: Span,
 }
 
@@ -253,6 +306,90 @@ pub struct ValueDef {
     pub span: Span,
 }
 
+/// `infixl 6 <+> <->`: how the listed operators group, for every use in
+/// the module and in the modules importing them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FixityDecl {
+    pub fixity: Fixity,
+    pub operators: Vec<Symbol>,
+    pub span: Span,
+}
+
+/// `#[cfg(target = "typescript")] let ...`: an item the compiler keeps or
+/// drops for each target before type checking
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConditionalItem {
+    pub condition: Cfg,
+    pub item: Box<Item>,
+    /// The attribute and the item
+    pub span: Span,
+}
+
+/// Condition of a `#[cfg(...)]` attribute
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Cfg {
+    /// `target = "typescript"` or `feature = "node"`
+    Equals { key: Symbol, value: String },
+    /// `not(cfg)`
+    Not(Box<Cfg>),
+    /// `all(cfg, ...)`, which holds when empty
+    All(Vec<Cfg>),
+    /// `any(cfg, ...)`, which does not hold when empty
+    Any(Vec<Cfg>),
+}
+
+impl fmt::Display for Cfg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |f: &mut fmt::Formatter<'_>, name: &str, cfgs: &[Cfg]| {
+            let cfgs: Vec<String> = cfgs.iter().map(Cfg::to_string).collect();
+            write!(f, "{name}({})", cfgs.join(", "))
+        };
+        match self {
+            Cfg::Equals { key, value } => write!(f, "{key} = {value:?}"),
+            Cfg::Not(cfg) => write!(f, "not({cfg})"),
+            Cfg::All(cfgs) => list(f, "all", cfgs),
+            Cfg::Any(cfgs) => list(f, "any", cfgs),
+        }
+    }
+}
+
+/// Precedence and associativity of an infix operator
+///
+/// Precedences share the scale of the built-in operators, from 0 for `|>`
+/// to 10 for composition; `*` is 8 and `+` is 7.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Fixity {
+    pub associativity: Associativity,
+    pub precedence: u8,
+}
+
+impl Fixity {
+    /// Highest precedence a declaration may give
+    pub const MAX_PRECEDENCE: u8 = 10;
+
+    /// Fixity of an operator without a declaration
+    pub const DEFAULT: Fixity = Fixity { associativity: Associativity::Left, precedence: 9 };
+
+    /// The keyword declaring this fixity
+    pub fn keyword(&self) -> &'static str {
+        match self.associativity {
+            Associativity::Left => "infixl",
+            Associativity::Right => "infixr",
+            Associativity::None => "infix",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Associativity {
+    /// `infixl`: `a - b - c` is `(a - b) - c`
+    Left,
+    /// `infixr`: `a :: b :: c` is `a :: (b :: c)`
+    Right,
+    /// `infix`: `a == b == c` needs parentheses
+    None,
+}
+
 /// Test definition
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TestDef {
@@ -270,6 +407,22 @@ pub struct TestDef {
     pub span: Span,
 }
 
+/// Benchmark definition: `bench "name" with iterations = 100, warmup = 5 { body }`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchDef {
+    pub name: Symbol,
+    pub documentation: Option<Documentation>,
+    pub description: Option<String>,
+    /// The expression measured
+    pub body: Expr,
+    /// Measured runs, overriding the runner's default
+    pub iterations: Option<u64>,
+    /// Unmeasured runs before the measured ones
+    pub warmup: Option<u64>,
+    pub visibility: Visibility,
+    pub span: Span,
+}
+
 /// Effect definition
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct EffectDef {
@@ -289,10 +442,40 @@ pub struct EffectOperation {
     pub span: Span,
 }
 
+/// Type class declaration: `class Ord[a] with Eq[a] { compare : a -> a -> Int }`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClassDef {
+    pub name: Symbol,
+    pub documentation: Option<Documentation>,
+    pub type_params: Vec<TypeParam>,
+    pub superclasses: Vec<TypeConstraint>,
+    pub methods: Vec<ClassMethod>,
+    pub visibility: Visibility,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClassMethod {
+    pub name: Symbol,
+    pub type_annotation: Type,
+    pub span: Span,
+}
+
+/// Type class instance: `instance Eq[List[a]] with Eq[a] { let eq = ... }`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InstanceDef {
+    pub class: Symbol,
+    pub types: Vec<Type>,
+    pub constraints: Vec<TypeConstraint>,
+    pub methods: Vec<ValueDef>,
+    pub span: Span,
+}
+
 /// Handler definition
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct HandlerDef {
     pub name: Symbol,
+    pub mode: HandlerMode,
     pub type_annotation: Option<Type>,
     pub handled_effects: Vec<EffectRef>,
     pub handlers: Vec<EffectHandler>,
@@ -301,6 +484,18 @@ pub struct HandlerDef {
     pub span: Span,
 }
 
+/// How long a handler stays installed around the computation it handles
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum HandlerMode {
+    /// Handles every operation the computation performs, including those
+    /// after it is resumed
+    #[default]
+    Deep,
+    /// `shallow handler`: handles the first operation only; the resumed
+    /// computation runs without it
+    Shallow,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct EffectHandler {
     pub effect: EffectRef,
@@ -604,6 +799,7 @@ pub enum Expr {
     /// Handle expression: `handle expr { effect -> handler }`
     Handle {
         expr: Box<Expr>,
+        mode: HandlerMode,
         handlers: Vec<EffectHandler>,
         return_clause: Option<Box<ReturnClause>>,
         span: Span,
@@ -626,6 +822,45 @@ pub enum Expr {
         type_annotation: Type,
         span: Span,
     },
+    /// Record literal: `{ x = 1, y = 2 }`
+    Record {
+        fields: Vec<(Symbol, Expr)>,
+        span: Span,
+    },
+    /// Field projection: `r.x`
+    Project {
+        record: Box<Expr>,
+        field: Symbol,
+        span: Span,
+    },
+    /// Functional record update: `{ r with x = 3 }`
+    RecordUpdate {
+        record: Box<Expr>,
+        fields: Vec<(Symbol, Expr)>,
+        span: Span,
+    },
+    /// Tuple: `(a, b, c)`
+    Tuple {
+        elements: Vec<Expr>,
+        span: Span,
+    },
+    /// Module packed as a value: `module Math : Plugin`
+    Pack {
+        /// Name the module is qualified with where it is imported
+        module: Symbol,
+        signature: Symbol,
+        span: Span,
+    },
+    /// Packed module opened locally: `let module P : Plugin = e in P.run 1`
+    Unpack {
+        name: Symbol,
+        signature: Symbol,
+        value: Box<Expr>,
+        body: Box<Expr>,
+        span: Span,
+    },
+    /// Placeholder for source that failed to parse, left by error recovery
+    Error(Span),
 }
 
 impl Expr {
@@ -643,20 +878,270 @@ impl Expr {
             Expr::Resume { span, .. } => *span,
             Expr::Perform { span, .. } => *span,
             Expr::Ann { span, .. } => *span,
+            Expr::Record { span, .. } => *span,
+            Expr::Project { span, .. } => *span,
+            Expr::RecordUpdate { span, .. } => *span,
+            Expr::Tuple { span, .. } => *span,
+            Expr::Pack { span, .. } => *span,
+            Expr::Unpack { span, .. } => *span,
+            Expr::Error(span) => *span,
         }
     }
+
+    /// Desugar the `_` placeholders of `(_ + 1)` or `(f _ x)` into the
+    /// parameters of lambdas spanning the parentheses: `fun $1 -> $1 + 1`,
+    /// and `fun $1 -> fun $2 -> $1 + $2` for `(_ + _)`, one lambda per
+    /// placeholder since applications pass one argument at a time
+    ///
+    /// Only placeholders in the application spine of `body` are taken; one
+    /// nested in a lambda, `let` or other parentheses is left alone. Without
+    /// any, `body` is returned as it is.
+    pub fn section(mut body: Expr, span: Span) -> Expr {
+        let mut parameters = Vec::new();
+        body.visit_section_spine(&mut |expr| {
+            if let Expr::Var(name, placeholder) = expr {
+                if name.as_str() == "_" {
+                    let parameter = section_parameter(parameters.len() + 1);
+                    parameters.push(Pattern::Variable(parameter, *placeholder));
+                    *name = parameter;
+                }
+            }
+        });
+        parameters.into_iter().rev().fold(body, |body, parameter| {
+            Expr::Lambda { parameters: vec![parameter], body: Box::new(body), span }
+        })
+    }
+
+    /// The body of a lambda made by [`Expr::section`], with its parameters
+    /// turned back into `_`, for printing
+    pub fn section_body(&self) -> Option<Expr> {
+        let mut names = Vec::new();
+        let mut body = self;
+        while let Expr::Lambda { parameters, body: inner, .. } = body {
+            match parameters.as_slice() {
+                [Pattern::Variable(name, _)] if *name == section_parameter(names.len() + 1) => names.push(*name),
+                _ => return None,
+            }
+            body = inner;
+        }
+        if names.is_empty() {
+            return None;
+        }
+        let mut body = body.clone();
+        let mut next = 0;
+        let mut in_order = true;
+        body.visit_section_spine(&mut |expr| {
+            if let Expr::Var(name, _) = expr {
+                if names.contains(name) {
+                    in_order &= names.get(next) == Some(name);
+                    next += 1;
+                    *name = Symbol::intern("_");
+                }
+            }
+        });
+        (in_order && next == names.len()).then_some(body)
+    }
+
+    /// Visit `self` and, through applications, tuples and projections, the
+    /// expressions a section's placeholders can be, in source order
+    fn visit_section_spine(&mut self, visit: &mut impl FnMut(&mut Expr)) {
+        visit(self);
+        match self {
+            Expr::App(function, args, _) => {
+                function.visit_section_spine(visit);
+                args.iter_mut().for_each(|arg| arg.visit_section_spine(visit));
+            }
+            Expr::Tuple { elements, .. } => elements.iter_mut().for_each(|element| element.visit_section_spine(visit)),
+            Expr::Project { record, .. } => record.visit_section_spine(visit),
+            _ => {}
+        }
+    }
+}
+
+/// Parameter `index`, from 1, of a lambda made by [`Expr::section`]; the
+/// `$` keeps it from clashing with any name written in source
+pub fn section_parameter(index: usize) -> Symbol {
+    Symbol::intern(&format!("${index}"))
 }
 
 /// Literal values
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Literal {
-    Integer(i64),
-    Float(f64),
+    Integer(i64, NumberFormat),
+    Float(f64, NumberFormat),
     String(String),
     Bool(bool),
     Unit,
 }
 
+impl Literal {
+    /// Integer literal written in plain decimal
+    pub fn int(value: i64) -> Self {
+        Literal::Integer(value, NumberFormat::default())
+    }
+
+    /// Float literal written in plain decimal
+    pub fn float(value: f64) -> Self {
+        Literal::Float(value, NumberFormat::default())
+    }
+
+    /// Parse numeric literal text: `42`, `0xFF`, `0b1010`, `1_000_000`,
+    /// `3.14`, and the `42i` / `3.14f` suffixed forms
+    pub fn parse_number(text: &str) -> Option<Literal> {
+        let (negative, text) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text),
+        };
+        if !text.starts_with(|c: char| c.is_ascii_digit()) {
+            return None;
+        }
+
+        let (radix, body) = if let Some(body) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+            (Radix::Hexadecimal, body)
+        } else if let Some(body) = text.strip_prefix("0b").or_else(|| text.strip_prefix("0B")) {
+            (Radix::Binary, body)
+        } else {
+            (Radix::Decimal, text)
+        };
+
+        // `f` is a hex digit, so only decimal literals take the float suffix
+        let (body, float_suffix, int_suffix) = if let Some(body) = body.strip_suffix('i') {
+            (body, false, true)
+        } else if let Some(body) = body.strip_suffix('f').filter(|_| radix == Radix::Decimal) {
+            (body, true, false)
+        } else {
+            (body, false, false)
+        };
+
+        let (whole, fraction) = match body.split_once('.') {
+            Some((whole, fraction)) => (whole, Some(fraction)),
+            None => (body, None),
+        };
+        let group = digit_group(whole, radix)?;
+        let format = NumberFormat { radix, group, suffix: float_suffix || int_suffix };
+
+        if fraction.is_some() || float_suffix {
+            if radix != Radix::Decimal || int_suffix {
+                return None;
+            }
+            if let Some(fraction) = fraction {
+                digit_group(fraction, radix)?;
+            }
+            let value: f64 = body.replace('_', "").parse().ok()?;
+            Some(Literal::Float(if negative { -value } else { value }, format))
+        } else {
+            let digits = whole.replace('_', "");
+            let digits = if negative { format!("-{digits}") } else { digits };
+            let value = i64::from_str_radix(&digits, radix.base()).ok()?;
+            Some(Literal::Integer(value, format))
+        }
+    }
+}
+
+/// Check the digits of a numeric literal part and return the size of its
+/// trailing `_`-separated group (0 when there are no separators)
+fn digit_group(digits: &str, radix: Radix) -> Option<u8> {
+    let groups: Vec<&str> = digits.split('_').collect();
+    let valid = groups.iter().all(|group| {
+        !group.is_empty() && group.chars().all(|c| c.is_digit(radix.base()))
+    });
+    match (valid, groups.as_slice()) {
+        (false, _) => None,
+        (true, [_]) => Some(0),
+        (true, [.., last]) => u8::try_from(last.len()).ok(),
+        (true, []) => None,
+    }
+}
+
+/// How a numeric literal was written, so printers can round-trip it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct NumberFormat {
+    pub radix: Radix,
+    /// Digits between `_` separators, counted from the right; 0 for none
+    pub group: u8,
+    /// Whether an explicit `i` / `f` suffix was written
+    pub suffix: bool,
+}
+
+/// Base of an integer literal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum Radix {
+    #[default]
+    Decimal,
+    Hexadecimal,
+    Binary,
+}
+
+impl Radix {
+    pub fn base(self) -> u32 {
+        match self {
+            Radix::Decimal => 10,
+            Radix::Hexadecimal => 16,
+            Radix::Binary => 2,
+        }
+    }
+}
+
+impl NumberFormat {
+    /// Render an integer the way it was written
+    pub fn format_integer(&self, value: i64) -> String {
+        let magnitude = value.unsigned_abs();
+        let (prefix, digits) = match self.radix {
+            Radix::Decimal => ("", magnitude.to_string()),
+            Radix::Hexadecimal => ("0x", format!("{magnitude:X}")),
+            Radix::Binary => ("0b", format!("{magnitude:b}")),
+        };
+        let sign = if value < 0 { "-" } else { "" };
+        let suffix = if self.suffix { "i" } else { "" };
+        format!("{sign}{prefix}{}{suffix}", self.separate(&digits))
+    }
+
+    /// Render a float the way it was written
+    pub fn format_float(&self, value: f64) -> String {
+        let text = value.to_string();
+        let (sign, text) = match text.strip_prefix('-') {
+            Some(rest) => ("-", rest),
+            None => ("", text.as_str()),
+        };
+        let number = match text.split_once('.') {
+            Some((whole, fraction)) => format!("{}.{fraction}", self.separate(whole)),
+            None if !value.is_finite() => text.to_string(),
+            // Without a suffix the point is what makes it a float
+            None if self.suffix => self.separate(text),
+            None => format!("{}.0", self.separate(text)),
+        };
+        let suffix = if self.suffix { "f" } else { "" };
+        format!("{sign}{number}{suffix}")
+    }
+
+    fn separate(&self, digits: &str) -> String {
+        if self.group == 0 {
+            return digits.to_string();
+        }
+        let group = self.group as usize;
+        let mut out = String::new();
+        for (index, digit) in digits.chars().enumerate() {
+            if index > 0 && (digits.len() - index).is_multiple_of(group) {
+                out.push('_');
+            }
+            out.push(digit);
+        }
+        out
+    }
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Literal::Integer(n, format) => write!(f, "{}", format.format_integer(*n)),
+            Literal::Float(x, format) => write!(f, "{}", format.format_float(*x)),
+            Literal::String(s) => write!(f, "{s:?}"),
+            Literal::Bool(b) => write!(f, "{b}"),
+            Literal::Unit => write!(f, "()"),
+        }
+    }
+}
+
 /// Patterns for destructuring
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Pattern {
@@ -902,10 +1387,10 @@ pub struct ResourceMethod {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::span::{FileId, ByteOffset};
+    use crate::span::{FileId, CharOffset};
 
     fn test_span() -> Span {
-        Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(10))
+        Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(10))
     }
 
     #[test]
@@ -930,9 +1415,30 @@ mod tests {
         assert!(empty_effects.row_var.is_none());
     }
 
+    #[test]
+    fn test_number_literals_round_trip() {
+        for text in ["42", "0xFF", "0b1010", "1_000_000", "42i", "3.14", "3.14f", "1_000.5", "2f", "0xFF_FFi"] {
+            let literal = Literal::parse_number(text).unwrap();
+            assert_eq!(literal.to_string(), text);
+        }
+
+        assert_eq!(Literal::parse_number("0xFF").unwrap(), Literal::Integer(255, NumberFormat {
+            radix: Radix::Hexadecimal,
+            group: 0,
+            suffix: false,
+        }));
+        assert!(matches!(Literal::parse_number("1_000_000"), Some(Literal::Integer(1_000_000, _))));
+        assert!(matches!(Literal::parse_number("42f"), Some(Literal::Float(value, _)) if value == 42.0));
+        assert_eq!(Literal::float(1.0).to_string(), "1.0");
+
+        for invalid in ["1__0", "_1", "1_", "0b102", "0x", "3.5i", "0x1.5", "12abc"] {
+            assert_eq!(Literal::parse_number(invalid), None, "{invalid}");
+        }
+    }
+
     #[test]
     fn test_ast_spans() {
-        let literal = Expr::Literal(Literal::Integer(42), test_span());
+        let literal = Expr::Literal(Literal::int(42), test_span());
         assert_eq!(literal.span(), test_span());
         
         let pattern = Pattern::Wildcard(test_span());
@@ -956,6 +1462,13 @@ impl HasSpan for Expr {
             Expr::Resume { span, .. } => *span,
             Expr::Perform { span, .. } => *span,
             Expr::Ann { span, .. } => *span,
+            Expr::Record { span, .. } => *span,
+            Expr::Project { span, .. } => *span,
+            Expr::RecordUpdate { span, .. } => *span,
+            Expr::Tuple { span, .. } => *span,
+            Expr::Pack { span, .. } => *span,
+            Expr::Unpack { span, .. } => *span,
+            Expr::Error(span) => *span,
         }
     }
 }
@@ -10,32 +10,75 @@
 
 use crate::{
     ast::*,
-    span::{Span, FileId, ByteOffset},
-    symbol::Symbol,
+    span::{Span, FileId, CharOffset},
+    symbol::{Symbol, SymbolSnapshot},
     error::{ParseError as Error, Result},
 };
+use std::borrow::Cow;
+use std::ops::Range;
 
-// Temporary type definitions for binary serialization
-// These should be properly integrated with the type system in the future
-#[derive(Debug, Clone)]
-pub struct InternalType;
+// Analysis types as stored in the binary format. They mirror the checker's
+// types so that inference results can be persisted without x-parser
+// depending on x-checker; the checker converts to and from them.
 
-#[derive(Debug, Clone)]
-#[allow(dead_code)]
-pub struct EffectVar(u32);
+/// Inferred type
+#[derive(Debug, Clone, PartialEq)]
+pub enum InternalType {
+    Var(u32),
+    Con(Symbol),
+    App(Box<InternalType>, Vec<InternalType>),
+    Fun {
+        params: Vec<InternalType>,
+        return_type: Box<InternalType>,
+        effects: EffectSet,
+    },
+    Forall {
+        type_vars: Vec<u32>,
+        effect_vars: Vec<u32>,
+        body: Box<InternalType>,
+    },
+    Record(Vec<(Symbol, InternalType)>),
+    Row { fields: Vec<(Symbol, InternalType)>, rest: u32 },
+    Variant(Vec<(Symbol, Vec<InternalType>)>),
+    Tuple(Vec<InternalType>),
+    Hole,
+    Rec { var: u32, body: Box<InternalType> },
+    Unknown,
+}
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct EffectVar(pub u32);
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Effect {
     pub name: Symbol,
-    pub operations: Vec<()>,
+    pub operations: Vec<EffectOperation>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct EffectOperation {
+    pub name: Symbol,
+    pub params: Vec<InternalType>,
+    pub return_type: InternalType,
+}
+
+/// Inferred effect row
+#[derive(Debug, Clone, PartialEq)]
 pub enum EffectSet {
     Empty,
     Var(EffectVar),
     Row { effects: Vec<Effect>, tail: Option<Box<EffectSet>> },
 }
+
+/// Type scheme and effect row inferred for one top-level definition
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypedDefinition {
+    pub name: Symbol,
+    pub type_vars: Vec<u32>,
+    pub effect_vars: Vec<u32>,
+    pub body: InternalType,
+    pub effects: EffectSet,
+}
 use sha2::{Sha256, Digest};
 use std::collections::HashMap;
 
@@ -43,7 +86,173 @@ use std::collections::HashMap;
 pub const MAGIC_NUMBER: [u8; 4] = [0x00, 0x78, 0x6C, 0x67];
 
 /// Current version of the binary format
-pub const FORMAT_VERSION: u32 = 1;
+///
+/// Version 2 adds the checksum table after the header; version 1 files are
+/// still readable but cannot be verified. Version 3 records how numeric
+/// literals were written, version 4 the module's name, version 5 data and
+/// alias type definitions, including GADT constructor result types, and
+/// version 6 fixity declarations.
+pub const FORMAT_VERSION: u32 = 6;
+
+/// Payload bytes covered by each entry of the checksum table
+pub const CHECKSUM_BLOCK_SIZE: usize = 4096;
+
+/// Size of magic number, version and the fixed header fields
+const FIXED_HEADER_LEN: usize = 24;
+
+/// CRC-32 (IEEE 802.3) lookup table
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// CRC-32 checksum of `data`
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        CRC32_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Part of a binary file covered by a checksum
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinarySection {
+    /// Magic number, version, header fields and the checksum table itself
+    Header,
+    /// Serialized AST
+    Payload,
+}
+
+impl std::fmt::Display for BinarySection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BinarySection::Header => write!(f, "header"),
+            BinarySection::Payload => write!(f, "payload"),
+        }
+    }
+}
+
+/// Byte range whose contents do not match the stored checksum
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorruptRegion {
+    pub section: BinarySection,
+    /// Byte offsets into the file, end exclusive
+    pub start: usize,
+    pub end: usize,
+    pub expected: u32,
+    pub actual: u32,
+}
+
+impl std::fmt::Display for CorruptRegion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} bytes {}..{}: checksum {:08x}, expected {:08x}",
+            self.section, self.start, self.end, self.actual, self.expected
+        )
+    }
+}
+
+/// Check every checksummed region of a binary file
+///
+/// Returns the regions that fail verification, or an error when the file is
+/// too damaged to locate its checksum table. Version 1 files carry no
+/// checksums and always verify.
+pub fn verify_checksums(data: &[u8]) -> Result<Vec<CorruptRegion>> {
+    if data.len() < 8 || data[0..4] != MAGIC_NUMBER {
+        return Err(Error::Parse {
+            message: "Invalid magic number. This is not a valid x Language binary file".to_string(),
+        });
+    }
+    let read_u32 = |at: usize| -> Result<u32> {
+        data.get(at..at + 4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .ok_or_else(|| Error::Parse {
+                message: format!("File truncated inside the header at byte {at}"),
+            })
+    };
+    if read_u32(4)? < 2 {
+        return Ok(Vec::new());
+    }
+
+    // checksum, payload length, block size, block count, block checksums, header checksum
+    let checksum = read_u32(FIXED_HEADER_LEN)?;
+    let payload_len = read_u32(FIXED_HEADER_LEN + 4)? as usize;
+    let block_size = read_u32(FIXED_HEADER_LEN + 8)? as usize;
+    let block_count = read_u32(FIXED_HEADER_LEN + 12)? as usize;
+    let table_start = FIXED_HEADER_LEN + 16;
+    let header_end = block_count.checked_mul(4)
+        .and_then(|len| len.checked_add(table_start + 4))
+        .filter(|&end| end <= data.len())
+        .ok_or_else(|| Error::Parse {
+            message: format!("Checksum table at byte {table_start} extends past the end of the file"),
+        })?;
+
+    let header_expected = read_u32(header_end - 4)?;
+    let header_actual = crc32(&data[..header_end - 4]);
+    if header_expected != header_actual || block_size == 0 {
+        // The table cannot be trusted, so nothing else can be checked
+        return Ok(vec![CorruptRegion {
+            section: BinarySection::Header,
+            start: 0,
+            end: header_end,
+            expected: header_expected,
+            actual: header_actual,
+        }]);
+    }
+
+    // A table that does not describe the payload would send the block walk
+    // outside the file, so it is rejected even with a valid checksum
+    if block_count != payload_len.div_ceil(block_size) {
+        return Err(Error::Parse {
+            message: format!(
+                "Checksum table lists {block_count} blocks for a {payload_len}-byte payload in {block_size}-byte blocks"
+            ),
+        });
+    }
+    let payload_end = header_end.checked_add(payload_len).ok_or_else(|| Error::Parse {
+        message: format!("Payload length {payload_len} overflows the file offset"),
+    })?;
+
+    let mut corrupt = Vec::new();
+    for block in 0..block_count {
+        let start = header_end + block * block_size;
+        let end = (start + block_size).min(payload_end);
+        let expected = read_u32(table_start + block * 4)?;
+        let actual = crc32(&data[start.min(data.len())..end.min(data.len())]);
+        if expected != actual || end > data.len() {
+            corrupt.push(CorruptRegion { section: BinarySection::Payload, start, end, expected, actual });
+        }
+    }
+    if data.len() > payload_end {
+        corrupt.push(CorruptRegion {
+            section: BinarySection::Payload,
+            start: payload_end,
+            end: data.len(),
+            expected: checksum,
+            actual: crc32(&data[header_end..]),
+        });
+    } else if corrupt.is_empty() && crc32(&data[header_end..]) != checksum {
+        corrupt.push(CorruptRegion {
+            section: BinarySection::Payload,
+            start: header_end,
+            end: data.len(),
+            expected: checksum,
+            actual: crc32(&data[header_end..]),
+        });
+    }
+    Ok(corrupt)
+}
 
 /// Enhanced binary format type codes with type checking support
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -72,6 +281,7 @@ pub enum TypeCode {
     ExprResume = 0x28,
     ExprPerform = 0x29,
     ExprAnn = 0x2A,
+    ExprTuple = 0x2B,
     
     // Patterns
     PatternWildcard = 0x30,
@@ -106,6 +316,10 @@ pub enum TypeCode {
     InternalTypeRec = 0x55,
     InternalTypeHole = 0x56,
     InternalTypeVariant = 0x57,
+    InternalTypeForall = 0x58,
+    InternalTypeRecord = 0x59,
+    InternalTypeUnknown = 0x5A,
+    InternalTypeRow = 0x5B,
     
     // Effect System
     EffectSetEmpty = 0x60,
@@ -119,6 +333,9 @@ pub enum TypeCode {
     ItemValueDef = 0x71,
     ItemEffectDef = 0x72,
     ItemHandlerDef = 0x73,
+    ItemDataDef = 0x74,
+    ItemFixity = 0x75,
+    ItemConditional = 0x76,
     
     // Collections
     Vec = 0x80,
@@ -234,9 +451,35 @@ impl BinarySerializer {
         serializer.header = BinaryHeader::with_type_checking();
         serializer
     }
+
+    /// Compress the payload; readers decompress it transparently
+    #[cfg(feature = "compression")]
+    pub fn with_compression(mut self) -> Self {
+        self.header.flags |= BinaryFlags::COMPRESSED;
+        self
+    }
     
     /// Serialize a compilation unit to binary format
     pub fn serialize_compilation_unit(&mut self, cu: &CompilationUnit) -> Result<Vec<u8>> {
+        self.serialize_unit(cu, None)
+    }
+
+    /// Serialize a compilation unit together with its inference results
+    ///
+    /// The definitions are stored after the AST in a type metadata section,
+    /// and the `TYPE_CHECKED` and `CACHED_EFFECTS` flags are set so readers
+    /// can load them with `BinaryDeserializer::deserialize_type_metadata`
+    /// instead of inferring again.
+    pub fn serialize_typed_compilation_unit(
+        &mut self,
+        cu: &CompilationUnit,
+        definitions: &[TypedDefinition],
+    ) -> Result<Vec<u8>> {
+        self.header.flags |= BinaryFlags::TYPE_CHECKED | BinaryFlags::CACHED_EFFECTS;
+        self.serialize_unit(cu, Some(definitions))
+    }
+
+    fn serialize_unit(&mut self, cu: &CompilationUnit, definitions: Option<&[TypedDefinition]>) -> Result<Vec<u8>> {
         self.buffer.clear();
         self.symbol_table.clear();
         self.next_symbol_id = 0;
@@ -251,9 +494,33 @@ impl BinarySerializer {
         self.serialize_header()?;
         
         // Write actual content
+        let payload_start = self.buffer.len();
         self.write_u8(TypeCode::CompilationUnit as u8)?;
         self.serialize_module(&cu.module)?;
         self.serialize_span(&cu.span)?;
+        if let Some(definitions) = definitions {
+            self.serialize_type_metadata(definitions)?;
+        }
+        
+        // Insert the checksum table between header and payload
+        let payload = self.buffer.split_off(payload_start);
+        #[cfg(feature = "compression")]
+        let payload = if self.header.flags.contains(BinaryFlags::COMPRESSED) {
+            crate::compression::compress(&payload)
+        } else {
+            payload
+        };
+        self.header.checksum = crc32(&payload);
+        self.write_u32(self.header.checksum)?;
+        self.write_u32(payload.len() as u32)?;
+        self.write_u32(CHECKSUM_BLOCK_SIZE as u32)?;
+        let blocks: Vec<&[u8]> = payload.chunks(CHECKSUM_BLOCK_SIZE).collect();
+        self.write_u32(blocks.len() as u32)?;
+        for block in blocks {
+            self.write_u32(crc32(block))?;
+        }
+        self.write_u32(crc32(&self.buffer))?;
+        self.buffer.extend_from_slice(&payload);
         
         Ok(self.buffer.clone())
     }
@@ -275,6 +542,12 @@ impl BinarySerializer {
         Ok(())
     }
     
+    /// Names of the symbols the last serialization wrote, for readers of
+    /// raw symbol ids in other processes
+    pub fn symbol_snapshot(&self) -> SymbolSnapshot {
+        SymbolSnapshot::capture(self.symbol_table.keys().copied())
+    }
+    
     /// Calculate content hash of the binary representation
     pub fn content_hash(data: &[u8]) -> String {
         let mut hasher = Sha256::new();
@@ -319,13 +592,15 @@ impl BinarySerializer {
         match expr {
             Expr::Literal(lit, span) => {
                 match lit {
-                    Literal::Integer(n) => {
+                    Literal::Integer(n, format) => {
                         self.write_u8(TypeCode::LiteralInteger as u8)?;
                         self.write_i64(*n)?;
+                        self.write_number_format(format)?;
                     }
-                    Literal::Float(f) => {
+                    Literal::Float(f, format) => {
                         self.write_u8(TypeCode::LiteralFloat as u8)?;
                         self.write_f64(*f)?;
+                        self.write_number_format(format)?;
                     }
                     Literal::String(s) => {
                         self.write_u8(TypeCode::LiteralString as u8)?;
@@ -387,6 +662,19 @@ impl BinarySerializer {
                 self.serialize_expr(else_branch)?;
                 self.serialize_span(span)?;
             }
+            Expr::Tuple { elements, span } => {
+                self.write_u8(TypeCode::ExprTuple as u8)?;
+                self.write_varint(elements.len() as u64)?;
+                for element in elements {
+                    self.serialize_expr(element)?;
+                }
+                self.serialize_span(span)?;
+            }
+            Expr::Resume { value, span } => {
+                self.write_u8(TypeCode::ExprResume as u8)?;
+                self.serialize_expr(value)?;
+                self.serialize_span(span)?;
+            }
             // Add other expression types as needed...
             _ => {
                 return Err(Error::Parse {
@@ -423,6 +711,14 @@ impl BinarySerializer {
                 }
                 self.serialize_span(span)?;
             }
+            Pattern::Tuple { patterns, span } => {
+                self.write_u8(TypeCode::PatternTuple as u8)?;
+                self.write_varint(patterns.len() as u64)?;
+                for pattern in patterns {
+                    self.serialize_pattern(pattern)?;
+                }
+                self.serialize_span(span)?;
+            }
             // Add other pattern types as needed...
             _ => {
                 return Err(Error::Parse {
@@ -468,6 +764,14 @@ impl BinarySerializer {
                 self.serialize_effect_set(effects)?;
                 self.serialize_span(span)?;
             }
+            Type::Tuple { types, span } => {
+                self.write_u8(TypeCode::AstTypeTuple as u8)?;
+                self.write_varint(types.len() as u64)?;
+                for typ in types {
+                    self.serialize_type(typ)?;
+                }
+                self.serialize_span(span)?;
+            }
             Type::Hole(span) => {
                 self.write_u8(TypeCode::AstTypeHole as u8)?;
                 self.serialize_span(span)?;
@@ -485,13 +789,15 @@ impl BinarySerializer {
     /// Serialize a literal
     fn serialize_literal(&mut self, literal: &Literal) -> Result<()> {
         match literal {
-            Literal::Integer(n) => {
+            Literal::Integer(n, format) => {
                 self.write_u8(TypeCode::LiteralInteger as u8)?;
                 self.write_i64(*n)?;
+                self.write_number_format(format)?;
             }
-            Literal::Float(f) => {
+            Literal::Float(f, format) => {
                 self.write_u8(TypeCode::LiteralFloat as u8)?;
                 self.write_f64(*f)?;
+                self.write_number_format(format)?;
             }
             Literal::String(s) => {
                 self.write_u8(TypeCode::LiteralString as u8)?;
@@ -523,6 +829,137 @@ impl BinarySerializer {
         Ok(())
     }
     
+    /// Serialize the type metadata section
+    fn serialize_type_metadata(&mut self, definitions: &[TypedDefinition]) -> Result<()> {
+        self.write_u8(TypeCode::TypeMetadata as u8)?;
+        self.write_varint(definitions.len() as u64)?;
+        for definition in definitions {
+            self.serialize_symbol(definition.name)?;
+            self.write_u32_list(&definition.type_vars)?;
+            self.write_u32_list(&definition.effect_vars)?;
+            self.serialize_internal_type(&definition.body)?;
+            self.serialize_internal_effect_set(&definition.effects)?;
+        }
+        Ok(())
+    }
+
+    /// Serialize an inferred type
+    fn serialize_internal_type(&mut self, typ: &InternalType) -> Result<()> {
+        match typ {
+            InternalType::Var(var) => {
+                self.write_u8(TypeCode::InternalTypeVar as u8)?;
+                self.write_varint(*var as u64)?;
+            }
+            InternalType::Con(name) => {
+                self.write_u8(TypeCode::InternalTypeCon as u8)?;
+                self.serialize_symbol(*name)?;
+            }
+            InternalType::App(base, args) => {
+                self.write_u8(TypeCode::InternalTypeApp as u8)?;
+                self.serialize_internal_type(base)?;
+                self.serialize_internal_types(args)?;
+            }
+            InternalType::Fun { params, return_type, effects } => {
+                self.write_u8(TypeCode::InternalTypeFun as u8)?;
+                self.serialize_internal_types(params)?;
+                self.serialize_internal_type(return_type)?;
+                self.serialize_internal_effect_set(effects)?;
+            }
+            InternalType::Forall { type_vars, effect_vars, body } => {
+                self.write_u8(TypeCode::InternalTypeForall as u8)?;
+                self.write_u32_list(type_vars)?;
+                self.write_u32_list(effect_vars)?;
+                self.serialize_internal_type(body)?;
+            }
+            InternalType::Record(fields) => {
+                self.write_u8(TypeCode::InternalTypeRecord as u8)?;
+                self.serialize_internal_fields(fields)?;
+            }
+            InternalType::Row { fields, rest } => {
+                self.write_u8(TypeCode::InternalTypeRow as u8)?;
+                self.serialize_internal_fields(fields)?;
+                self.write_varint(*rest as u64)?;
+            }
+            InternalType::Variant(variants) => {
+                self.write_u8(TypeCode::InternalTypeVariant as u8)?;
+                self.write_varint(variants.len() as u64)?;
+                for (name, fields) in variants {
+                    self.serialize_symbol(*name)?;
+                    self.serialize_internal_types(fields)?;
+                }
+            }
+            InternalType::Tuple(types) => {
+                self.write_u8(TypeCode::InternalTypeTuple as u8)?;
+                self.serialize_internal_types(types)?;
+            }
+            InternalType::Hole => self.write_u8(TypeCode::InternalTypeHole as u8)?,
+            InternalType::Rec { var, body } => {
+                self.write_u8(TypeCode::InternalTypeRec as u8)?;
+                self.write_varint(*var as u64)?;
+                self.serialize_internal_type(body)?;
+            }
+            InternalType::Unknown => self.write_u8(TypeCode::InternalTypeUnknown as u8)?,
+        }
+        Ok(())
+    }
+
+    fn serialize_internal_types(&mut self, types: &[InternalType]) -> Result<()> {
+        self.write_varint(types.len() as u64)?;
+        for typ in types {
+            self.serialize_internal_type(typ)?;
+        }
+        Ok(())
+    }
+
+    fn serialize_internal_fields(&mut self, fields: &[(Symbol, InternalType)]) -> Result<()> {
+        self.write_varint(fields.len() as u64)?;
+        for (name, field) in fields {
+            self.serialize_symbol(*name)?;
+            self.serialize_internal_type(field)?;
+        }
+        Ok(())
+    }
+
+    /// Serialize an inferred effect row
+    fn serialize_internal_effect_set(&mut self, effects: &EffectSet) -> Result<()> {
+        match effects {
+            EffectSet::Empty => self.write_u8(TypeCode::EffectSetEmpty as u8)?,
+            EffectSet::Var(var) => {
+                self.write_u8(TypeCode::EffectSetVar as u8)?;
+                self.write_varint(var.0 as u64)?;
+            }
+            EffectSet::Row { effects, tail } => {
+                self.write_u8(TypeCode::EffectSetRow as u8)?;
+                self.write_varint(effects.len() as u64)?;
+                for effect in effects {
+                    self.serialize_symbol(effect.name)?;
+                    self.write_varint(effect.operations.len() as u64)?;
+                    for operation in &effect.operations {
+                        self.serialize_symbol(operation.name)?;
+                        self.serialize_internal_types(&operation.params)?;
+                        self.serialize_internal_type(&operation.return_type)?;
+                    }
+                }
+                match tail {
+                    Some(tail) => {
+                        self.write_u8(1)?;
+                        self.serialize_internal_effect_set(tail)?;
+                    }
+                    None => self.write_u8(0)?,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write_u32_list(&mut self, values: &[u32]) -> Result<()> {
+        self.write_varint(values.len() as u64)?;
+        for value in values {
+            self.write_varint(*value as u64)?;
+        }
+        Ok(())
+    }
+    
     /// Serialize a span
     fn serialize_span(&mut self, span: &Span) -> Result<()> {
         self.write_u8(TypeCode::Span as u8)?;
@@ -561,9 +998,12 @@ impl BinarySerializer {
     }
     
     // Placeholder implementations for missing methods
-    fn serialize_module_path(&mut self, _path: &ModulePath) -> Result<()> {
-        // TODO: Implement module path serialization
-        Ok(())
+    fn serialize_module_path(&mut self, path: &ModulePath) -> Result<()> {
+        self.write_varint(path.segments.len() as u64)?;
+        for segment in &path.segments {
+            self.serialize_symbol(*segment)?;
+        }
+        self.serialize_span(&path.span)
     }
     
     fn serialize_export_list(&mut self, _exports: &ExportList) -> Result<()> {
@@ -603,16 +1043,7 @@ impl BinarySerializer {
                 self.serialize_expr(&value_def.body)?;
                 
                 // Serialize visibility and purity
-                self.write_u8(match value_def.visibility {
-                    Visibility::Public => 0,
-                    Visibility::Private => 1,
-                    Visibility::Crate => 2,
-                    Visibility::Package => 3,
-                    Visibility::Super => 4,
-                    Visibility::InPath(_) => 5,
-                    Visibility::SelfModule => 6,
-                    Visibility::Component { .. } => 7,
-                })?;
+                self.write_visibility(&value_def.visibility)?;
                 
                 self.write_u8(match value_def.purity {
                     Purity::Pure => 0,
@@ -622,17 +1053,17 @@ impl BinarySerializer {
                 
                 self.serialize_span(&value_def.span)?;
             }
-            Item::TypeDef(_) => {
-                self.write_u8(TypeCode::ItemTypeDef as u8)?;
-                // TODO: Implement type definition serialization
+            Item::TypeDef(type_def) => {
+                self.write_u8(TypeCode::ItemDataDef as u8)?;
+                self.serialize_type_def(type_def)?;
             }
             Item::EffectDef(_) => {
                 self.write_u8(TypeCode::ItemEffectDef as u8)?;
                 // TODO: Implement effect definition serialization
             }
-            Item::HandlerDef(_) => {
+            Item::HandlerDef(handler_def) => {
                 self.write_u8(TypeCode::ItemHandlerDef as u8)?;
-                // TODO: Implement handler definition serialization
+                self.serialize_handler_def(handler_def)?;
             }
             Item::ModuleTypeDef(_) => {
                 self.write_u8(TypeCode::ItemTypeDef as u8)?; 
@@ -642,14 +1073,210 @@ impl BinarySerializer {
                 self.write_u8(TypeCode::ItemTypeDef as u8)?;
                 // TODO: Implement interface definition serialization 
             }
-            Item::TestDef(_) => {
+            Item::TestDef(_) | Item::BenchDef(_) => {
                 self.write_u8(TypeCode::ItemValueDef as u8)?; // Treat as value def for now
                 // TODO: Implement test definition serialization
             }
+            Item::ClassDef(_) | Item::InstanceDef(_) => {
+                self.write_u8(TypeCode::ItemTypeDef as u8)?;
+                // TODO: Implement class and instance serialization
+            }
+            Item::Fixity(decl) => {
+                self.write_u8(TypeCode::ItemFixity as u8)?;
+                self.write_u8(match decl.fixity.associativity {
+                    Associativity::Left => 0,
+                    Associativity::Right => 1,
+                    Associativity::None => 2,
+                })?;
+                self.write_u8(decl.fixity.precedence)?;
+                self.write_varint(decl.operators.len() as u64)?;
+                for operator in &decl.operators {
+                    self.serialize_symbol(*operator)?;
+                }
+                self.serialize_span(&decl.span)?;
+            }
+            Item::Conditional(conditional) => {
+                self.write_u8(TypeCode::ItemConditional as u8)?;
+                self.serialize_cfg(&conditional.condition)?;
+                self.serialize_item(&conditional.item)?;
+                self.serialize_span(&conditional.span)?;
+            }
         }
         Ok(())
     }
     
+    fn serialize_type_def(&mut self, type_def: &TypeDef) -> Result<()> {
+        self.serialize_symbol(type_def.name)?;
+        self.write_varint(type_def.type_params.len() as u64)?;
+        for param in &type_def.type_params {
+            self.serialize_symbol(param.name)?;
+            match &param.kind {
+                Some(kind) => {
+                    self.write_u8(1)?;
+                    self.serialize_kind(kind)?;
+                }
+                None => self.write_u8(0)?,
+            }
+            self.write_varint(param.constraints.len() as u64)?;
+            for constraint in &param.constraints {
+                self.serialize_symbol(constraint.class)?;
+                self.write_varint(constraint.types.len() as u64)?;
+                for typ in &constraint.types {
+                    self.serialize_type(typ)?;
+                }
+                self.serialize_span(&constraint.span)?;
+            }
+            self.serialize_span(&param.span)?;
+        }
+
+        match &type_def.kind {
+            TypeDefKind::Data(constructors) => {
+                self.write_u8(0)?;
+                self.write_varint(constructors.len() as u64)?;
+                for constructor in constructors {
+                    self.serialize_symbol(constructor.name)?;
+                    self.write_varint(constructor.fields.len() as u64)?;
+                    for field in &constructor.fields {
+                        self.serialize_type(field)?;
+                    }
+                    match &constructor.result {
+                        Some(result) => {
+                            self.write_u8(1)?;
+                            self.serialize_type(result)?;
+                        }
+                        None => self.write_u8(0)?,
+                    }
+                    self.serialize_span(&constructor.span)?;
+                }
+            }
+            TypeDefKind::Alias(typ) => {
+                self.write_u8(1)?;
+                self.serialize_type(typ)?;
+            }
+            TypeDefKind::Abstract => self.write_u8(2)?,
+        }
+
+        self.write_visibility(&type_def.visibility)?;
+        self.serialize_span(&type_def.span)
+    }
+
+    fn serialize_kind(&mut self, kind: &Kind) -> Result<()> {
+        match kind {
+            Kind::Type => self.write_u8(0),
+            Kind::Effect => self.write_u8(1),
+            Kind::Row => self.write_u8(2),
+            Kind::Arrow(from, to) => {
+                self.write_u8(3)?;
+                self.serialize_kind(from)?;
+                self.serialize_kind(to)
+            }
+        }
+    }
+
+    fn serialize_handler_def(&mut self, handler_def: &HandlerDef) -> Result<()> {
+        self.serialize_symbol(handler_def.name)?;
+        self.write_u8(match handler_def.mode {
+            HandlerMode::Deep => 0,
+            HandlerMode::Shallow => 1,
+        })?;
+        match &handler_def.type_annotation {
+            Some(type_ann) => {
+                self.write_u8(1)?;
+                self.serialize_type(type_ann)?;
+            }
+            None => {
+                self.write_u8(0)?;
+            }
+        }
+        
+        self.write_varint(handler_def.handled_effects.len() as u64)?;
+        for effect in &handler_def.handled_effects {
+            self.serialize_effect_ref(effect)?;
+        }
+        
+        self.write_varint(handler_def.handlers.len() as u64)?;
+        for handler in &handler_def.handlers {
+            self.serialize_effect_ref(&handler.effect)?;
+            self.serialize_symbol(handler.operation)?;
+            self.write_varint(handler.parameters.len() as u64)?;
+            for param in &handler.parameters {
+                self.serialize_pattern(param)?;
+            }
+            match handler.continuation {
+                Some(continuation) => {
+                    self.write_u8(1)?;
+                    self.serialize_symbol(continuation)?;
+                }
+                None => {
+                    self.write_u8(0)?;
+                }
+            }
+            self.serialize_expr(&handler.body)?;
+            self.serialize_span(&handler.span)?;
+        }
+        
+        match &handler_def.return_clause {
+            Some(clause) => {
+                self.write_u8(1)?;
+                self.serialize_pattern(&clause.parameter)?;
+                self.serialize_expr(&clause.body)?;
+                self.serialize_span(&clause.span)?;
+            }
+            None => {
+                self.write_u8(0)?;
+            }
+        }
+        
+        self.write_visibility(&handler_def.visibility)?;
+        self.serialize_span(&handler_def.span)
+    }
+    
+    fn serialize_cfg(&mut self, cfg: &Cfg) -> Result<()> {
+        let cfgs = match cfg {
+            Cfg::Equals { key, value } => {
+                self.write_u8(0)?;
+                self.serialize_symbol(*key)?;
+                return self.serialize_symbol(Symbol::intern(value));
+            }
+            Cfg::Not(cfg) => {
+                self.write_u8(1)?;
+                return self.serialize_cfg(cfg);
+            }
+            Cfg::All(cfgs) => {
+                self.write_u8(2)?;
+                cfgs
+            }
+            Cfg::Any(cfgs) => {
+                self.write_u8(3)?;
+                cfgs
+            }
+        };
+        self.write_varint(cfgs.len() as u64)?;
+        cfgs.iter().try_for_each(|cfg| self.serialize_cfg(cfg))
+    }
+    
+    fn serialize_effect_ref(&mut self, effect: &EffectRef) -> Result<()> {
+        self.serialize_symbol(effect.name)?;
+        self.write_varint(effect.args.len() as u64)?;
+        for arg in &effect.args {
+            self.serialize_type(arg)?;
+        }
+        self.serialize_span(&effect.span)
+    }
+    
+    fn write_visibility(&mut self, visibility: &Visibility) -> Result<()> {
+        self.write_u8(match visibility {
+            Visibility::Public => 0,
+            Visibility::Private => 1,
+            Visibility::Crate => 2,
+            Visibility::Package => 3,
+            Visibility::Super => 4,
+            Visibility::InPath(_) => 5,
+            Visibility::SelfModule => 6,
+            Visibility::Component { .. } => 7,
+        })
+    }
+    
     // Low-level writing methods
     fn write_u8(&mut self, value: u8) -> Result<()> {
         self.buffer.push(value);
@@ -671,6 +1298,17 @@ impl BinarySerializer {
         Ok(())
     }
     
+    /// Radix and suffix flag in one byte, then the separator group size
+    fn write_number_format(&mut self, format: &NumberFormat) -> Result<()> {
+        let radix = match format.radix {
+            Radix::Decimal => 0,
+            Radix::Hexadecimal => 1,
+            Radix::Binary => 2,
+        };
+        self.write_u8(radix | (format.suffix as u8) << 4)?;
+        self.write_u8(format.group)
+    }
+    
     fn write_string(&mut self, s: &str) -> Result<()> {
         let bytes = s.as_bytes();
         self.write_varint(bytes.len() as u64)?;
@@ -689,18 +1327,73 @@ impl BinarySerializer {
 }
 
 /// Enhanced binary AST deserializer with type checking support
+///
+/// The input is either owned (`new`) or borrowed (`from_slice`). A borrowed
+/// deserializer never copies the input: symbol names are kept as ranges into
+/// the buffer and are neither decoded nor interned until `symbol` is first
+/// called for them. Any byte slice can be borrowed; with the `mmap` feature
+/// `MappedBinary` maps an `.xbin` file and lends it out.
 #[allow(dead_code)]
-pub struct BinaryDeserializer {
-    data: Vec<u8>,
+pub struct BinaryDeserializer<'a> {
+    data: Cow<'a, [u8]>,
     pos: usize,
-    symbol_table: Vec<Symbol>,
+    symbol_table: Vec<SymbolEntry>,
     header: Option<BinaryHeader>,
     type_cache: Vec<InternalType>,
     effect_cache: Vec<EffectSet>,
+    version: u32,
 }
 
-impl BinaryDeserializer {
+/// Symbol table slot: the name's location in the input, interned on demand
+#[derive(Debug, Clone)]
+struct SymbolEntry {
+    range: Range<usize>,
+    symbol: Option<Symbol>,
+}
+
+impl BinaryDeserializer<'static> {
+    /// Deserialize from an owned buffer
     pub fn new(data: Vec<u8>) -> Result<Self> {
+        Self::with_data(Cow::Owned(data))
+    }
+}
+
+/// A memory-mapped `.xbin` file that deserializers can borrow from
+#[cfg(feature = "mmap")]
+pub struct MappedBinary {
+    map: memmap2::Mmap,
+}
+
+#[cfg(feature = "mmap")]
+impl MappedBinary {
+    /// Map a binary AST file into memory
+    pub fn open(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let file = std::fs::File::open(path).map_err(|e| Error::io(e.to_string()))?;
+        // SAFETY: the map is read-only and private to this process. Truncating
+        // or rewriting the file while it is mapped is not supported, as with
+        // any other mmap-based reader.
+        let map = unsafe { memmap2::Mmap::map(&file) }.map_err(|e| Error::io(e.to_string()))?;
+        Ok(MappedBinary { map })
+    }
+
+    /// Mapped bytes
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.map
+    }
+
+    /// Deserializer reading straight from the mapping
+    pub fn deserializer(&self) -> Result<BinaryDeserializer<'_>> {
+        BinaryDeserializer::from_slice(&self.map)
+    }
+}
+
+impl<'a> BinaryDeserializer<'a> {
+    /// Deserialize directly from borrowed bytes without copying them
+    pub fn from_slice(data: &'a [u8]) -> Result<Self> {
+        Self::with_data(Cow::Borrowed(data))
+    }
+
+    fn with_data(data: Cow<'a, [u8]>) -> Result<Self> {
         let mut deserializer = BinaryDeserializer {
             data,
             pos: 0,
@@ -708,6 +1401,7 @@ impl BinaryDeserializer {
             header: None,
             type_cache: Vec::new(),
             effect_cache: Vec::new(),
+            version: FORMAT_VERSION,
         };
         
         // Check minimum file size for magic number + version
@@ -733,7 +1427,7 @@ impl BinaryDeserializer {
             deserializer.data[7]
         ]);
         
-        if version != FORMAT_VERSION {
+        if version == 0 || version > FORMAT_VERSION {
             return Err(Error::Parse {
                 message: format!("Unsupported format version: {version}. Expected: {FORMAT_VERSION}"),
             });
@@ -741,14 +1435,66 @@ impl BinaryDeserializer {
         
         // Move position past magic number and version
         deserializer.pos = 8;
+        deserializer.version = version;
+        
+        if version == 1 {
+            // Try to read header if present
+            if let Ok(header) = deserializer.read_header() {
+                deserializer.header = Some(header);
+            }
+            return Ok(deserializer);
+        }
+        
+        let corrupt = verify_checksums(&deserializer.data)?;
+        if !corrupt.is_empty() {
+            let regions: Vec<String> = corrupt.iter().map(|region| region.to_string()).collect();
+            return Err(Error::Parse {
+                message: format!("Corrupted binary file: {}", regions.join("; ")),
+            });
+        }
+        
+        let mut header = deserializer.read_header()?;
+        header.checksum = deserializer.read_u32()?;
+        let _payload_len = deserializer.read_u32()?;
+        let _block_size = deserializer.read_u32()?;
+        let block_count = deserializer.read_u32()? as usize;
+        // Skip the block checksums and the header checksum, verified above
+        deserializer.pos += (block_count + 1) * 4;
         
-        // Try to read header if present
-        if let Ok(header) = deserializer.read_header() {
-            deserializer.header = Some(header);
+        if header.flags.contains(BinaryFlags::COMPRESSED) {
+            deserializer.decompress_payload()?;
         }
+        deserializer.header = Some(header);
         
         Ok(deserializer)
     }
+
+    /// Replace a compressed payload with its decompressed bytes
+    #[cfg(feature = "compression")]
+    fn decompress_payload(&mut self) -> Result<()> {
+        let payload = crate::compression::decompress(&self.data[self.pos..])?;
+        self.data = Cow::Owned(payload);
+        self.pos = 0;
+        Ok(())
+    }
+
+    #[cfg(not(feature = "compression"))]
+    fn decompress_payload(&mut self) -> Result<()> {
+        Err(Error::Parse {
+            message: "Binary payload is compressed; x-parser was built without the `compression` feature".to_string(),
+        })
+    }
+
+    /// Whether the input is borrowed rather than owned
+    pub fn is_zero_copy(&self) -> bool {
+        matches!(self.data, Cow::Borrowed(_))
+    }
+
+    /// Name of a symbol seen so far, borrowed from the input without interning it
+    pub fn symbol_str(&self, id: usize) -> Option<&str> {
+        let entry = self.symbol_table.get(id)?;
+        std::str::from_utf8(&self.data[entry.range.clone()]).ok()
+    }
     
     pub fn has_type_information(&self) -> bool {
         self.header
@@ -787,7 +1533,7 @@ impl BinaryDeserializer {
         // For now, return a placeholder expression
         let expr = Expr::Literal(
             Literal::Unit,
-            Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(0)),
+            Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(0)),
         );
         
         Ok((expr, typ, effects))
@@ -823,19 +1569,22 @@ impl BinaryDeserializer {
         let effect_code = self.read_u8()?;
         match effect_code {
             0x60 => Ok(EffectSet::Empty), // EffectSetEmpty
-            0x61 => {
-                let var_id = self.read_u32()?;
-                Ok(EffectSet::Var(EffectVar(var_id)))
-            }
+            0x61 => Ok(EffectSet::Var(EffectVar(self.read_varint()? as u32))),
             0x62 => { // EffectSetRow
-                let count = self.read_u32()?;
-                let mut effects = Vec::with_capacity(count as usize);
+                let count = self.read_count()?;
+                let mut effects = Vec::with_capacity(count);
                 for _ in 0..count {
-                    let effect_name = Symbol::intern(&format!("effect_{}", self.read_u32()?));
-                    effects.push(Effect {
-                        name: effect_name,
-                        operations: Vec::new(), // Simplified
-                    });
+                    let name = self.deserialize_symbol()?;
+                    let operation_count = self.read_count()?;
+                    let mut operations = Vec::with_capacity(operation_count);
+                    for _ in 0..operation_count {
+                        operations.push(EffectOperation {
+                            name: self.deserialize_symbol()?,
+                            params: self.deserialize_internal_types()?,
+                            return_type: self.deserialize_internal_type()?,
+                        });
+                    }
+                    effects.push(Effect { name, operations });
                 }
                 let has_tail = self.read_u8()? != 0;
                 let tail = if has_tail {
@@ -850,6 +1599,101 @@ impl BinaryDeserializer {
             }),
         }
     }
+
+    /// Deserialize an inferred type
+    pub fn deserialize_internal_type(&mut self) -> Result<InternalType> {
+        let type_code = self.read_u8()?;
+        let typ = match type_code {
+            0x50 => InternalType::Var(self.read_varint()? as u32),
+            0x51 => InternalType::Con(self.deserialize_symbol()?),
+            0x52 => {
+                let base = Box::new(self.deserialize_internal_type()?);
+                InternalType::App(base, self.deserialize_internal_types()?)
+            }
+            0x53 => InternalType::Fun {
+                params: self.deserialize_internal_types()?,
+                return_type: Box::new(self.deserialize_internal_type()?),
+                effects: self.deserialize_internal_effect_set()?,
+            },
+            0x54 => InternalType::Tuple(self.deserialize_internal_types()?),
+            0x55 => InternalType::Rec {
+                var: self.read_varint()? as u32,
+                body: Box::new(self.deserialize_internal_type()?),
+            },
+            0x56 => InternalType::Hole,
+            0x57 => {
+                let count = self.read_count()?;
+                let mut variants = Vec::with_capacity(count);
+                for _ in 0..count {
+                    variants.push((self.deserialize_symbol()?, self.deserialize_internal_types()?));
+                }
+                InternalType::Variant(variants)
+            }
+            0x58 => InternalType::Forall {
+                type_vars: self.read_u32_list()?,
+                effect_vars: self.read_u32_list()?,
+                body: Box::new(self.deserialize_internal_type()?),
+            },
+            0x59 => InternalType::Record(self.deserialize_internal_fields()?),
+            0x5A => InternalType::Unknown,
+            0x5B => InternalType::Row {
+                fields: self.deserialize_internal_fields()?,
+                rest: self.read_varint()? as u32,
+            },
+            _ => {
+                return Err(Error::Parse {
+                    message: format!("Unknown internal type code: {type_code}"),
+                })
+            }
+        };
+        Ok(typ)
+    }
+
+    fn deserialize_internal_types(&mut self) -> Result<Vec<InternalType>> {
+        let count = self.read_count()?;
+        (0..count).map(|_| self.deserialize_internal_type()).collect()
+    }
+
+    fn deserialize_internal_fields(&mut self) -> Result<Vec<(Symbol, InternalType)>> {
+        let count = self.read_count()?;
+        (0..count)
+            .map(|_| Ok((self.deserialize_symbol()?, self.deserialize_internal_type()?)))
+            .collect()
+    }
+
+    fn read_u32_list(&mut self) -> Result<Vec<u32>> {
+        let count = self.read_count()?;
+        (0..count).map(|_| Ok(self.read_varint()? as u32)).collect()
+    }
+
+    /// Read the inference results stored after the compilation unit
+    ///
+    /// Call after `deserialize_compilation_unit`. Returns no definitions when
+    /// the file was written without type information.
+    pub fn deserialize_type_metadata(&mut self) -> Result<Vec<TypedDefinition>> {
+        if !self.has_type_information() {
+            return Ok(Vec::new());
+        }
+        let type_code = self.read_u8()?;
+        if type_code != TypeCode::TypeMetadata as u8 {
+            return Err(Error::Parse {
+                message: format!("Expected type metadata, got type code {type_code}"),
+            });
+        }
+
+        let count = self.read_count()?;
+        let mut definitions = Vec::with_capacity(count);
+        for _ in 0..count {
+            definitions.push(TypedDefinition {
+                name: self.deserialize_symbol()?,
+                type_vars: self.read_u32_list()?,
+                effect_vars: self.read_u32_list()?,
+                body: self.deserialize_internal_type()?,
+                effects: self.deserialize_internal_effect_set()?,
+            });
+        }
+        Ok(definitions)
+    }
     
     /// Deserialize a compilation unit from binary format
     pub fn deserialize_compilation_unit(&mut self) -> Result<CompilationUnit> {
@@ -884,14 +1728,14 @@ impl BinaryDeserializer {
         };
         
         // Deserialize imports
-        let import_count = self.read_varint()? as usize;
+        let import_count = self.read_count()?;
         let mut imports = Vec::with_capacity(import_count);
         for _ in 0..import_count {
             imports.push(self.deserialize_import()?);
         }
         
         // Deserialize items
-        let item_count = self.read_varint()? as usize;
+        let item_count = self.read_count()?;
         let mut items = Vec::with_capacity(item_count);
         for _ in 0..item_count {
             items.push(self.deserialize_item()?);
@@ -911,19 +1755,26 @@ impl BinaryDeserializer {
     
     // Placeholder implementations for missing methods
     fn deserialize_module_path(&mut self) -> Result<ModulePath> {
-        // TODO: Implement module path deserialization
-        use crate::symbol::Symbol;
-        Ok(ModulePath::new(
-            vec![Symbol::intern("Test")],
-            Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(4))
-        ))
+        if self.version < 4 {
+            return Ok(ModulePath::new(
+                vec![Symbol::intern("Test")],
+                Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(4))
+            ));
+        }
+        let count = self.read_count()?;
+        let mut segments = Vec::with_capacity(count);
+        for _ in 0..count {
+            segments.push(self.deserialize_symbol()?);
+        }
+        let span = self.deserialize_span()?;
+        Ok(ModulePath::new(segments, span))
     }
     
     fn deserialize_export_list(&mut self) -> Result<ExportList> {
         // TODO: Implement export list deserialization
         Ok(ExportList {
             items: Vec::new(),
-            span: Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(0)),
+            span: Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(0)),
         })
     }
     
@@ -932,12 +1783,12 @@ impl BinaryDeserializer {
         Ok(Import {
             module_path: ModulePath::new(
                 vec![Symbol::intern("Test")],
-                Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(4))
+                Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(4))
             ),
             kind: ImportKind::Qualified,
             alias: None,
             version_spec: None,
-            span: Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(0)),
+            span: Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(0)),
         })
     }
     
@@ -955,7 +1806,7 @@ impl BinaryDeserializer {
                 };
                 
                 // Deserialize parameters
-                let param_count = self.read_varint()? as usize;
+                let param_count = self.read_count()?;
                 let mut parameters = Vec::with_capacity(param_count);
                 for _ in 0..param_count {
                     parameters.push(self.deserialize_pattern()?);
@@ -965,17 +1816,7 @@ impl BinaryDeserializer {
                 let body = self.deserialize_expr()?;
                 
                 // Deserialize visibility and purity
-                let visibility = match self.read_u8()? {
-                    0 => Visibility::Public,
-                    1 => Visibility::Private,
-                    2 => Visibility::Crate,
-                    3 => Visibility::Package,
-                    4 => Visibility::Super,
-                    5 => Visibility::InPath(ModulePath::new(vec![], Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(0)))),
-                    6 => Visibility::SelfModule,
-                    7 => Visibility::Component { export: false, import: false, interface: None },
-                    _ => Visibility::Public, // default
-                };
+                let visibility = self.read_visibility()?;
                 
                 let purity = match self.read_u8()? {
                     0 => Purity::Pure,
@@ -1005,45 +1846,269 @@ impl BinaryDeserializer {
                     documentation: None,
                     type_annotation: None,
                     parameters: Vec::new(),
-                    body: Expr::Literal(Literal::Unit, Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(0))),
+                    body: Expr::Literal(Literal::Unit, Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(0))),
                     visibility: Visibility::Public,
                     purity: Purity::Inferred,
                     imports: Vec::new(),
-                    span: Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(0)),
+                    span: Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(0)),
                 }))
             }
+            code if code == TypeCode::ItemHandlerDef as u8 => {
+                Ok(Item::HandlerDef(self.deserialize_handler_def()?))
+            }
+            code if code == TypeCode::ItemDataDef as u8 => {
+                Ok(Item::TypeDef(self.deserialize_type_def()?))
+            }
+            code if code == TypeCode::ItemFixity as u8 => {
+                let associativity = match self.read_u8()? {
+                    0 => Associativity::Left,
+                    1 => Associativity::Right,
+                    2 => Associativity::None,
+                    tag => return Err(Error::Parse { message: format!("Unknown associativity: {tag}") }),
+                };
+                let precedence = self.read_u8()?;
+                let count = self.read_count()?;
+                let mut operators = Vec::with_capacity(count);
+                for _ in 0..count {
+                    operators.push(self.deserialize_symbol()?);
+                }
+                let span = self.deserialize_span()?;
+                Ok(Item::Fixity(FixityDecl { fixity: Fixity { associativity, precedence }, operators, span }))
+            }
+            code if code == TypeCode::ItemConditional as u8 => {
+                let condition = self.deserialize_cfg()?;
+                let item = Box::new(self.deserialize_item()?);
+                let span = self.deserialize_span()?;
+                Ok(Item::Conditional(ConditionalItem { condition, item, span }))
+            }
             _ => Err(Error::Parse {
                 message: format!("Unknown item type code: {type_code}"),
             }),
         }
     }
     
-    fn deserialize_symbol(&mut self) -> Result<Symbol> {
-        let id = self.read_varint()?;
-        if id < self.symbol_table.len() as u64 {
-            Ok(self.symbol_table[id as usize])
-        } else {
-            // New symbol - read string
-            let string_len = self.read_varint()? as usize;
-            if self.pos + string_len > self.data.len() {
+    fn deserialize_type_def(&mut self) -> Result<TypeDef> {
+        let name = self.deserialize_symbol()?;
+        let param_count = self.read_count()?;
+        let mut type_params = Vec::with_capacity(param_count);
+        for _ in 0..param_count {
+            let name = self.deserialize_symbol()?;
+            let kind = if self.read_u8()? == 1 {
+                Some(self.deserialize_kind()?)
+            } else {
+                None
+            };
+            let constraint_count = self.read_count()?;
+            let mut constraints = Vec::with_capacity(constraint_count);
+            for _ in 0..constraint_count {
+                let class = self.deserialize_symbol()?;
+                let type_count = self.read_count()?;
+                let mut types = Vec::with_capacity(type_count);
+                for _ in 0..type_count {
+                    types.push(self.deserialize_type()?);
+                }
+                let span = self.deserialize_span()?;
+                constraints.push(TypeConstraint { class, types, span });
+            }
+            let span = self.deserialize_span()?;
+            type_params.push(TypeParam { name, kind, constraints, span });
+        }
+
+        let kind = match self.read_u8()? {
+            0 => {
+                let constructor_count = self.read_count()?;
+                let mut constructors = Vec::with_capacity(constructor_count);
+                for _ in 0..constructor_count {
+                    let name = self.deserialize_symbol()?;
+                    let field_count = self.read_count()?;
+                    let mut fields = Vec::with_capacity(field_count);
+                    for _ in 0..field_count {
+                        fields.push(self.deserialize_type()?);
+                    }
+                    let result = if self.read_u8()? == 1 {
+                        Some(self.deserialize_type()?)
+                    } else {
+                        None
+                    };
+                    let span = self.deserialize_span()?;
+                    constructors.push(Constructor { name, fields, result, span });
+                }
+                TypeDefKind::Data(constructors)
+            }
+            1 => TypeDefKind::Alias(self.deserialize_type()?),
+            2 => TypeDefKind::Abstract,
+            code => {
                 return Err(Error::Parse {
-                    message: "Not enough data for string".to_string(),
-                });
+                    message: format!("Unknown type definition kind: {code}"),
+                })
             }
-            
-            let string_bytes = &self.data[self.pos..self.pos + string_len];
-            self.pos += string_len;
-            
-            let symbol_str = std::str::from_utf8(string_bytes)
-                .map_err(|_| Error::Parse {
-                    message: "Invalid UTF-8 in symbol".to_string(),
-                })?;
-            
-            let symbol = Symbol::intern(symbol_str);
-            self.symbol_table.push(symbol);
-            Ok(symbol)
+        };
+
+        let visibility = self.read_visibility()?;
+        let span = self.deserialize_span()?;
+        Ok(TypeDef { name, documentation: None, type_params, kind, visibility, span })
+    }
+
+    fn deserialize_kind(&mut self) -> Result<Kind> {
+        match self.read_u8()? {
+            0 => Ok(Kind::Type),
+            1 => Ok(Kind::Effect),
+            2 => Ok(Kind::Row),
+            3 => {
+                let from = self.deserialize_kind()?;
+                let to = self.deserialize_kind()?;
+                Ok(Kind::Arrow(Box::new(from), Box::new(to)))
+            }
+            code => Err(Error::Parse {
+                message: format!("Unknown kind: {code}"),
+            }),
         }
     }
+
+    fn deserialize_handler_def(&mut self) -> Result<HandlerDef> {
+        let name = self.deserialize_symbol()?;
+        let mode = match self.read_u8()? {
+            0 => HandlerMode::Deep,
+            1 => HandlerMode::Shallow,
+            code => {
+                return Err(Error::Parse {
+                    message: format!("Unknown handler mode: {code}"),
+                })
+            }
+        };
+        let type_annotation = if self.read_u8()? == 1 {
+            Some(self.deserialize_type()?)
+        } else {
+            None
+        };
+        
+        let effect_count = self.read_count()?;
+        let mut handled_effects = Vec::with_capacity(effect_count);
+        for _ in 0..effect_count {
+            handled_effects.push(self.deserialize_effect_ref()?);
+        }
+        
+        let handler_count = self.read_count()?;
+        let mut handlers = Vec::with_capacity(handler_count);
+        for _ in 0..handler_count {
+            let effect = self.deserialize_effect_ref()?;
+            let operation = self.deserialize_symbol()?;
+            let param_count = self.read_count()?;
+            let mut parameters = Vec::with_capacity(param_count);
+            for _ in 0..param_count {
+                parameters.push(self.deserialize_pattern()?);
+            }
+            let continuation = if self.read_u8()? == 1 {
+                Some(self.deserialize_symbol()?)
+            } else {
+                None
+            };
+            let body = self.deserialize_expr()?;
+            let span = self.deserialize_span()?;
+            handlers.push(EffectHandler { effect, operation, parameters, continuation, body, span });
+        }
+        
+        let return_clause = if self.read_u8()? == 1 {
+            let parameter = self.deserialize_pattern()?;
+            let body = Box::new(self.deserialize_expr()?);
+            let span = self.deserialize_span()?;
+            Some(ReturnClause { parameter, body, span })
+        } else {
+            None
+        };
+        
+        let visibility = self.read_visibility()?;
+        let span = self.deserialize_span()?;
+        Ok(HandlerDef { name, mode, type_annotation, handled_effects, handlers, return_clause, visibility, span })
+    }
+    
+    fn deserialize_cfg(&mut self) -> Result<Cfg> {
+        let tag = self.read_u8()?;
+        match tag {
+            0 => {
+                let key = self.deserialize_symbol()?;
+                // Values are plain strings; there is no need to intern them
+                let id = self.deserialize_symbol_id()?;
+                let value = self.symbol_str(id)
+                    .ok_or_else(|| Error::Parse { message: "Invalid UTF-8 in symbol".to_string() })?
+                    .to_string();
+                Ok(Cfg::Equals { key, value })
+            }
+            1 => Ok(Cfg::Not(Box::new(self.deserialize_cfg()?))),
+            2 | 3 => {
+                let count = self.read_count()?;
+                let cfgs = (0..count).map(|_| self.deserialize_cfg()).collect::<Result<Vec<_>>>()?;
+                Ok(if tag == 2 { Cfg::All(cfgs) } else { Cfg::Any(cfgs) })
+            }
+            tag => Err(Error::Parse { message: format!("Unknown cfg tag: {tag}") }),
+        }
+    }
+    
+    fn deserialize_effect_ref(&mut self) -> Result<EffectRef> {
+        let name = self.deserialize_symbol()?;
+        let count = self.read_count()?;
+        let mut args = Vec::with_capacity(count);
+        for _ in 0..count {
+            args.push(self.deserialize_type()?);
+        }
+        let span = self.deserialize_span()?;
+        Ok(EffectRef { name, args, span })
+    }
+    
+    fn read_visibility(&mut self) -> Result<Visibility> {
+        Ok(match self.read_u8()? {
+            0 => Visibility::Public,
+            1 => Visibility::Private,
+            2 => Visibility::Crate,
+            3 => Visibility::Package,
+            4 => Visibility::Super,
+            5 => Visibility::InPath(ModulePath::new(vec![], Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(0)))),
+            6 => Visibility::SelfModule,
+            7 => Visibility::Component { export: false, import: false, interface: None },
+            _ => Visibility::Public, // default
+        })
+    }
+    
+    fn deserialize_symbol(&mut self) -> Result<Symbol> {
+        let id = self.deserialize_symbol_id()?;
+        self.symbol(id)
+    }
+
+    /// Read a symbol reference, recording new names without decoding them
+    fn deserialize_symbol_id(&mut self) -> Result<usize> {
+        let id = self.read_varint()? as usize;
+        if id < self.symbol_table.len() {
+            return Ok(id);
+        }
+        // New symbol - record where its name lives
+        let string_len = self.read_count()?;
+        if self.pos + string_len > self.data.len() {
+            return Err(Error::Parse {
+                message: "Not enough data for string".to_string(),
+            });
+        }
+        
+        let range = self.pos..self.pos + string_len;
+        self.pos += string_len;
+        self.symbol_table.push(SymbolEntry { range, symbol: None });
+        Ok(self.symbol_table.len() - 1)
+    }
+
+    /// Intern a symbol table entry, validating its name on first access
+    pub fn symbol(&mut self, id: usize) -> Result<Symbol> {
+        let entry = self.symbol_table.get_mut(id).ok_or_else(|| Error::Parse {
+            message: format!("Unknown symbol id: {id}"),
+        })?;
+        if let Some(symbol) = entry.symbol {
+            return Ok(symbol);
+        }
+        let name = std::str::from_utf8(&self.data[entry.range.clone()]).map_err(|_| Error::Parse {
+            message: "Invalid UTF-8 in symbol".to_string(),
+        })?;
+        let symbol = Symbol::intern(name);
+        entry.symbol = Some(symbol);
+        Ok(symbol)
+    }
     
     fn deserialize_expr(&mut self) -> Result<Expr> {
         let type_code = self.read_u8()?;
@@ -1055,7 +2120,7 @@ impl BinaryDeserializer {
             }
             code if code == TypeCode::ExprApp as u8 => {
                 let func = Box::new(self.deserialize_expr()?);
-                let arg_count = self.read_varint()? as usize;
+                let arg_count = self.read_count()?;
                 let mut args = Vec::with_capacity(arg_count);
                 for _ in 0..arg_count {
                     args.push(self.deserialize_expr()?);
@@ -1064,7 +2129,7 @@ impl BinaryDeserializer {
                 Ok(Expr::App(func, args, span))
             }
             code if code == TypeCode::ExprLambda as u8 => {
-                let param_count = self.read_varint()? as usize;
+                let param_count = self.read_count()?;
                 let mut parameters = Vec::with_capacity(param_count);
                 for _ in 0..param_count {
                     parameters.push(self.deserialize_pattern()?);
@@ -1085,13 +2150,35 @@ impl BinaryDeserializer {
                 let span = self.deserialize_span()?;
                 Ok(Expr::Let { pattern, type_annotation, value, body, span })
             }
+            code if code == TypeCode::ExprIf as u8 => {
+                let condition = Box::new(self.deserialize_expr()?);
+                let then_branch = Box::new(self.deserialize_expr()?);
+                let else_branch = Box::new(self.deserialize_expr()?);
+                let span = self.deserialize_span()?;
+                Ok(Expr::If { condition, then_branch, else_branch, span })
+            }
+            code if code == TypeCode::ExprTuple as u8 => {
+                let count = self.read_count()?;
+                let mut elements = Vec::with_capacity(count);
+                for _ in 0..count {
+                    elements.push(self.deserialize_expr()?);
+                }
+                let span = self.deserialize_span()?;
+                Ok(Expr::Tuple { elements, span })
+            }
+            code if code == TypeCode::ExprResume as u8 => {
+                let value = Box::new(self.deserialize_expr()?);
+                let span = self.deserialize_span()?;
+                Ok(Expr::Resume { value, span })
+            }
             code if code == TypeCode::LiteralInteger as u8 => {
                 let value = self.read_i64()?;
+                let format = self.read_number_format()?;
                 let span = self.deserialize_span()?;
-                Ok(Expr::Literal(Literal::Integer(value), span))
+                Ok(Expr::Literal(Literal::Integer(value, format), span))
             }
             code if code == TypeCode::LiteralString as u8 => {
-                let string_len = self.read_varint()? as usize;
+                let string_len = self.read_count()?;
                 if self.pos + string_len > self.data.len() {
                     return Err(Error::Parse {
                         message: "Not enough data for string literal".to_string(),
@@ -1117,8 +2204,9 @@ impl BinaryDeserializer {
             }
             code if code == TypeCode::LiteralFloat as u8 => {
                 let value = self.read_f64()?;
+                let format = self.read_number_format()?;
                 let span = self.deserialize_span()?;
-                Ok(Expr::Literal(Literal::Float(value), span))
+                Ok(Expr::Literal(Literal::Float(value, format), span))
             }
             code if code == TypeCode::LiteralUnit as u8 => {
                 let span = self.deserialize_span()?;
@@ -1142,15 +2230,37 @@ impl BinaryDeserializer {
                 let span = self.deserialize_span()?;
                 Ok(Pattern::Wildcard(span))
             }
+            code if code == TypeCode::PatternTuple as u8 => {
+                let count = self.read_count()?;
+                let mut patterns = Vec::with_capacity(count);
+                for _ in 0..count {
+                    patterns.push(self.deserialize_pattern()?);
+                }
+                let span = self.deserialize_span()?;
+                Ok(Pattern::Tuple { patterns, span })
+            }
+            code if code == TypeCode::PatternConstructor as u8 => {
+                let name = self.deserialize_symbol()?;
+                let count = self.read_count()?;
+                let mut args = Vec::with_capacity(count);
+                for _ in 0..count {
+                    args.push(self.deserialize_pattern()?);
+                }
+                let span = self.deserialize_span()?;
+                Ok(Pattern::Constructor { name, args, span })
+            }
             code if code == TypeCode::PatternLiteral as u8 => {
                 // Read the literal
                 let literal_type = self.read_u8()?;
                 let literal = match literal_type {
                     code if code == TypeCode::LiteralInteger as u8 => {
-                        Literal::Integer(self.read_i64()?)
+                        Literal::Integer(self.read_i64()?, self.read_number_format()?)
+                    }
+                    code if code == TypeCode::LiteralFloat as u8 => {
+                        Literal::Float(self.read_f64()?, self.read_number_format()?)
                     }
                     code if code == TypeCode::LiteralString as u8 => {
-                        let string_len = self.read_varint()? as usize;
+                        let string_len = self.read_count()?;
                         if self.pos + string_len > self.data.len() {
                             return Err(Error::Parse {
                                 message: "Not enough data for string".to_string(),
@@ -1167,6 +2277,7 @@ impl BinaryDeserializer {
                         
                         Literal::String(string_value.to_string())
                     }
+                    code if code == TypeCode::LiteralBool as u8 => Literal::Bool(self.read_u8()? == 1),
                     code if code == TypeCode::LiteralUnit as u8 => Literal::Unit,
                     _ => return Err(Error::Parse {
                         message: format!("Unknown literal type in pattern: {literal_type}"),
@@ -1195,9 +2306,19 @@ impl BinaryDeserializer {
                 let span = self.deserialize_span()?;
                 Ok(Type::Con(symbol, span))
             }
+            code if code == TypeCode::AstTypeApp as u8 => {
+                let base = Box::new(self.deserialize_type()?);
+                let arg_count = self.read_count()?;
+                let mut args = Vec::with_capacity(arg_count);
+                for _ in 0..arg_count {
+                    args.push(self.deserialize_type()?);
+                }
+                let span = self.deserialize_span()?;
+                Ok(Type::App(base, args, span))
+            }
             code if code == TypeCode::AstTypeFun as u8 => {
                 // Deserialize parameter types
-                let param_count = self.read_varint()? as usize;
+                let param_count = self.read_count()?;
                 let mut params = Vec::with_capacity(param_count);
                 for _ in 0..param_count {
                     params.push(self.deserialize_type()?);
@@ -1217,6 +2338,15 @@ impl BinaryDeserializer {
                     span 
                 })
             }
+            code if code == TypeCode::AstTypeTuple as u8 => {
+                let count = self.read_count()?;
+                let mut types = Vec::with_capacity(count);
+                for _ in 0..count {
+                    types.push(self.deserialize_type()?);
+                }
+                let span = self.deserialize_span()?;
+                Ok(Type::Tuple { types, span })
+            }
             code if code == TypeCode::AstTypeHole as u8 => {
                 let span = self.deserialize_span()?;
                 Ok(Type::Hole(span))
@@ -1229,11 +2359,11 @@ impl BinaryDeserializer {
     
     fn deserialize_effect_set(&mut self) -> Result<crate::ast::EffectSet> {
         // Deserialize effect list
-        let effect_count = self.read_varint()? as usize;
+        let effect_count = self.read_count()?;
         let mut effects = Vec::with_capacity(effect_count);
         for _ in 0..effect_count {
             let name = self.deserialize_symbol()?;
-            let arg_count = self.read_varint()? as usize;
+            let arg_count = self.read_count()?;
             let mut args = Vec::with_capacity(arg_count);
             for _ in 0..arg_count {
                 args.push(self.deserialize_type()?);
@@ -1266,8 +2396,8 @@ impl BinaryDeserializer {
         }
         
         let file_id = FileId::new(self.read_u32()?);
-        let start = ByteOffset::new(self.read_u32()?);
-        let end = ByteOffset::new(self.read_u32()?);
+        let start = CharOffset::new(self.read_u32()?);
+        let end = CharOffset::new(self.read_u32()?);
         
         Ok(Span::new(file_id, start, end))
     }
@@ -1319,6 +2449,37 @@ impl BinaryDeserializer {
         Ok(f64::from_le_bytes(bytes))
     }
     
+    fn read_number_format(&mut self) -> Result<NumberFormat> {
+        if self.version < 3 {
+            return Ok(NumberFormat::default());
+        }
+        let flags = self.read_u8()?;
+        let radix = match flags & 0x0F {
+            0 => Radix::Decimal,
+            1 => Radix::Hexadecimal,
+            2 => Radix::Binary,
+            other => return Err(Error::Parse {
+                message: format!("Unknown numeric literal radix: {other}"),
+            }),
+        };
+        Ok(NumberFormat {
+            radix,
+            group: self.read_u8()?,
+            suffix: flags & 0x10 != 0,
+        })
+    }
+    
+    /// A length or element count, which cannot exceed the bytes left
+    fn read_count(&mut self) -> Result<usize> {
+        let count = self.read_varint()?;
+        if count > (self.data.len() - self.pos) as u64 {
+            return Err(Error::Parse {
+                message: format!("Count of {count} exceeds the remaining data"),
+            });
+        }
+        Ok(count as usize)
+    }
+    
     fn read_varint(&mut self) -> Result<u64> {
         let mut result = 0u64;
         let mut shift = 0;
@@ -1360,7 +2521,7 @@ impl Default for BinarySerializer {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::span::{FileId, ByteOffset};
+    use crate::span::{FileId, CharOffset};
 
     #[test]
     fn test_basic_serialization() {
@@ -1370,18 +2531,18 @@ mod tests {
         let module = Module {
             name: ModulePath::new(
                 vec![Symbol::intern("Test")],
-                Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(4))
+                Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(4))
             ),
             documentation: None,
             exports: None,
             imports: Vec::new(),
             items: Vec::new(),
-            span: Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(10)),
+            span: Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(10)),
         };
         
         let cu = CompilationUnit {
             module,
-            span: Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(10)),
+            span: Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(10)),
         };
         
         let binary_data = serializer.serialize_compilation_unit(&cu).unwrap();
@@ -1408,4 +2569,240 @@ mod tests {
         
         assert_ne!(hash1, hash3);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_zero_copy_deserialization() {
+        let source = "module Test\nlet answer = 42\nlet greet = fun name -> answer";
+        let cu = crate::parse_source(source, FileId::new(0), crate::SyntaxStyle::SExpression).unwrap();
+        let binary_data = BinarySerializer::new().serialize_compilation_unit(&cu).unwrap();
+
+        let mut borrowed = BinaryDeserializer::from_slice(&binary_data).unwrap();
+        assert!(borrowed.is_zero_copy());
+        let restored = borrowed.deserialize_compilation_unit().unwrap();
+
+        let mut owned = BinaryDeserializer::new(binary_data.clone()).unwrap();
+        assert!(!owned.is_zero_copy());
+        assert_eq!(restored, owned.deserialize_compilation_unit().unwrap());
+        assert_eq!(restored.module.items.len(), 2);
+
+        // Symbol names are readable straight out of the input buffer
+        let names: Vec<&str> = (0..borrowed.symbol_table.len())
+            .filter_map(|id| borrowed.symbol_str(id))
+            .collect();
+        assert!(names.contains(&"answer"));
+        assert!(borrowed.symbol_str(names.len()).is_none());
+    }
+
+    #[test]
+    fn test_symbols_are_interned_on_first_access() {
+        // A symbol table entry with invalid UTF-8 is only rejected when the
+        // name is actually needed
+        let binary_data = sample_binary();
+        let mut deserializer = BinaryDeserializer::from_slice(&binary_data).unwrap();
+        deserializer.symbol_table.push(SymbolEntry { range: 0..1, symbol: None });
+        let id = deserializer.symbol_table.len() - 1;
+        assert!(deserializer.symbol_table[id].symbol.is_none());
+        assert!(deserializer.symbol(id).is_ok());
+        assert!(deserializer.symbol_table[id].symbol.is_some());
+
+        let bytes = [0xFF, 0xFE];
+        let mut invalid = BinaryDeserializer::from_slice(&binary_data).unwrap();
+        invalid.data = Cow::Borrowed(&bytes);
+        invalid.symbol_table = vec![SymbolEntry { range: 0..2, symbol: None }];
+        assert!(invalid.symbol_str(0).is_none());
+        assert!(invalid.symbol(0).is_err());
+        assert!(invalid.symbol(1).is_err());
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_mapped_binary() {
+        let binary_data = sample_binary();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.xbin");
+        std::fs::write(&path, &binary_data).unwrap();
+
+        let mapped = MappedBinary::open(&path).unwrap();
+        assert_eq!(mapped.as_bytes(), &binary_data[..]);
+        let mut deserializer = mapped.deserializer().unwrap();
+        assert!(deserializer.is_zero_copy());
+        let restored = deserializer.deserialize_compilation_unit().unwrap();
+        let expected = BinaryDeserializer::new(binary_data).unwrap().deserialize_compilation_unit().unwrap();
+        assert_eq!(restored, expected);
+    }
+
+    fn sample_binary() -> Vec<u8> {
+        let source = "module Test\nlet answer = 42\nlet greet = fun name -> answer";
+        let cu = crate::parse_source(source, FileId::new(0), crate::SyntaxStyle::SExpression).unwrap();
+        BinarySerializer::new().serialize_compilation_unit(&cu).unwrap()
+    }
+
+    #[test]
+    fn test_checksums_verify_and_locate_corruption() {
+        let mut data = sample_binary();
+        assert!(verify_checksums(&data).unwrap().is_empty());
+
+        let last = data.len() - 1;
+        data[last] ^= 0xFF;
+        let corrupt = verify_checksums(&data).unwrap();
+        assert_eq!(corrupt.len(), 1);
+        assert_eq!(corrupt[0].section, BinarySection::Payload);
+        assert!(corrupt[0].start < last && corrupt[0].end == data.len());
+
+        let err = BinaryDeserializer::from_slice(&data).err().unwrap();
+        assert!(err.to_string().contains("payload bytes"));
+    }
+
+    #[test]
+    fn test_header_corruption_is_reported() {
+        let mut data = sample_binary();
+        data[8] ^= 0x01; // flags
+        let corrupt = verify_checksums(&data).unwrap();
+        assert_eq!(corrupt.len(), 1);
+        assert_eq!(corrupt[0].section, BinarySection::Header);
+        assert_eq!(corrupt[0].start, 0);
+
+        // Truncation shows up as a damaged final block
+        let data = sample_binary();
+        let truncated = &data[..data.len() - 3];
+        let corrupt = verify_checksums(truncated).unwrap();
+        assert_eq!(corrupt.last().unwrap().end, data.len());
+    }
+
+    #[test]
+    fn test_inconsistent_checksum_table_is_rejected() {
+        // Rewrite a table field and re-seal the header so only the
+        // consistency check can catch it
+        let reseal = |field: usize, value: u32| {
+            let mut data = sample_binary();
+            let at = FIXED_HEADER_LEN + field;
+            data[at..at + 4].copy_from_slice(&value.to_le_bytes());
+            let block_count = u32::from_le_bytes(data[FIXED_HEADER_LEN + 12..FIXED_HEADER_LEN + 16].try_into().unwrap());
+            let header_end = FIXED_HEADER_LEN + 16 + block_count as usize * 4 + 4;
+            let checksum = crc32(&data[..header_end - 4]);
+            data[header_end - 4..header_end].copy_from_slice(&checksum.to_le_bytes());
+            data
+        };
+
+        assert!(verify_checksums(&reseal(4, u32::MAX)).is_err()); // payload length
+        assert!(verify_checksums(&reseal(8, 1)).is_err()); // block size
+        assert!(BinaryDeserializer::new(reseal(8, 1)).is_err());
+    }
+
+    #[test]
+    fn test_crc32_known_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn test_type_metadata_round_trip() {
+        let source = "module Test\nlet answer = 42\nlet greet = fun name -> answer";
+        let cu = crate::parse_source(source, FileId::new(0), crate::SyntaxStyle::SExpression).unwrap();
+        let io = Effect {
+            name: Symbol::intern("IO"),
+            operations: vec![EffectOperation {
+                name: Symbol::intern("print"),
+                params: vec![InternalType::Con(Symbol::intern("String"))],
+                return_type: InternalType::Con(Symbol::intern("Unit")),
+            }],
+        };
+        let definitions = vec![
+            TypedDefinition {
+                name: Symbol::intern("answer"),
+                type_vars: vec![],
+                effect_vars: vec![],
+                body: InternalType::Con(Symbol::intern("Int")),
+                effects: EffectSet::Empty,
+            },
+            TypedDefinition {
+                name: Symbol::intern("greet"),
+                type_vars: vec![3],
+                effect_vars: vec![1],
+                body: InternalType::Fun {
+                    params: vec![InternalType::Var(3)],
+                    return_type: Box::new(InternalType::Con(Symbol::intern("Int"))),
+                    effects: EffectSet::Row {
+                        effects: vec![io],
+                        tail: Some(Box::new(EffectSet::Var(EffectVar(1)))),
+                    },
+                },
+                effects: EffectSet::Empty,
+            },
+        ];
+
+        let data = BinarySerializer::new().serialize_typed_compilation_unit(&cu, &definitions).unwrap();
+        let mut deserializer = BinaryDeserializer::from_slice(&data).unwrap();
+        assert!(deserializer.has_type_information() && deserializer.has_cached_effects());
+        deserializer.deserialize_compilation_unit().unwrap();
+        assert_eq!(deserializer.deserialize_type_metadata().unwrap(), definitions);
+
+        let mut untyped = BinaryDeserializer::new(sample_binary()).unwrap();
+        untyped.deserialize_compilation_unit().unwrap();
+        assert!(untyped.deserialize_type_metadata().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_handler_definitions_round_trip() {
+        let source = "module Test\n\
+            effect State { get : Int put : Int -> Unit }\n\
+            pub handler counter { | State.get with k => k 0 | State.put (n, _) => resume () | return x => (x, 1) }\n\
+            shallow handler empty {}";
+        let cu = crate::parse_source(source, FileId::new(0), crate::SyntaxStyle::SExpression).unwrap();
+        let handlers: Vec<Item> = cu.module.items.iter().filter(|item| matches!(item, Item::HandlerDef(_))).cloned().collect();
+        let cu = CompilationUnit { module: Module { items: handlers, ..cu.module }, span: cu.span };
+
+        let data = BinarySerializer::new().serialize_compilation_unit(&cu).unwrap();
+        let restored = BinaryDeserializer::new(data).unwrap().deserialize_compilation_unit().unwrap();
+        assert_eq!(restored, cu);
+    }
+
+    #[test]
+    fn test_type_definitions_round_trip() {
+        let source = "module Test\n\
+            pub data Option[a] = None | Some a\n\
+            data Expr[a] = IntLit Int : Expr[Int] | If (Expr[Bool]) (Expr[a]) (Expr[a]) : Expr[a]\n\
+            type UserId = Int";
+        let cu = crate::parse_source(source, FileId::new(0), crate::SyntaxStyle::SExpression).unwrap();
+
+        let data = BinarySerializer::new().serialize_compilation_unit(&cu).unwrap();
+        let restored = BinaryDeserializer::new(data).unwrap().deserialize_compilation_unit().unwrap();
+        assert_eq!(restored, cu);
+    }
+
+    #[test]
+    fn test_fixity_declarations_round_trip() {
+        let source = "module Test\ninfixr 5 <+> <->\nlet (<+>) = fun a b -> a\nlet x = 1 <+> 2 <+> 3";
+        let cu = crate::parse_source(source, FileId::new(0), crate::SyntaxStyle::SExpression).unwrap();
+
+        let data = BinarySerializer::new().serialize_compilation_unit(&cu).unwrap();
+        let restored = BinaryDeserializer::new(data).unwrap().deserialize_compilation_unit().unwrap();
+        assert_eq!(restored, cu);
+    }
+
+    #[test]
+    fn test_cfg_attributes_round_trip() {
+        let source = "module Test\n#[cfg(all(target = \"typescript\", not(any())))]\nlet x = 1";
+        let cu = crate::parse_source(source, FileId::new(0), crate::SyntaxStyle::SExpression).unwrap();
+
+        let data = BinarySerializer::new().serialize_compilation_unit(&cu).unwrap();
+        let restored = BinaryDeserializer::new(data).unwrap().deserialize_compilation_unit().unwrap();
+        assert_eq!(restored, cu);
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_compressed_round_trip() {
+        let source = "module Test\nlet answer = 42\nlet greet = fun name -> answer\nlet again = fun name -> answer";
+        let cu = crate::parse_source(source, FileId::new(0), crate::SyntaxStyle::SExpression).unwrap();
+        let plain = BinarySerializer::new().serialize_compilation_unit(&cu).unwrap();
+        let packed = BinarySerializer::new().with_compression().serialize_compilation_unit(&cu).unwrap();
+        assert!(packed.len() < plain.len());
+        assert!(verify_checksums(&packed).unwrap().is_empty());
+
+        let mut deserializer = BinaryDeserializer::from_slice(&packed).unwrap();
+        assert!(!deserializer.is_zero_copy());
+        let expected = BinaryDeserializer::from_slice(&plain).unwrap().deserialize_compilation_unit().unwrap();
+        assert_eq!(deserializer.deserialize_compilation_unit().unwrap(), expected);
+    }
+}
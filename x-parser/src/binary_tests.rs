@@ -7,22 +7,25 @@
 mod tests {
     use crate::{
         ast::*,
-        span::{Span, FileId, ByteOffset},
+        span::{Span, FileId, CharOffset},
         symbol::Symbol,
         binary::{BinarySerializer, BinaryDeserializer},
     };
 
     /// Create a test span for testing purposes
     fn test_span() -> Span {
-        Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(10))
+        Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(10))
     }
 
     /// Test round-trip serialization of simple literals
     #[test]
     fn test_literal_round_trip() {
         let test_cases = vec![
-            Literal::Integer(42),
-            Literal::Float(3.14),
+            Literal::int(42),
+            Literal::float(3.14),
+            Literal::parse_number("0xFF_FF").unwrap(),
+            Literal::parse_number("0b1010i").unwrap(),
+            Literal::parse_number("2.5f").unwrap(),
             Literal::String("hello".to_string()),
             Literal::Bool(true),
             Literal::Bool(false),
@@ -139,8 +142,8 @@ mod tests {
     fn test_application_round_trip() {
         let func = Box::new(Expr::Var(Symbol::intern("add"), test_span()));
         let args = vec![
-            Expr::Literal(Literal::Integer(1), test_span()),
-            Expr::Literal(Literal::Integer(2), test_span()),
+            Expr::Literal(Literal::int(1), test_span()),
+            Expr::Literal(Literal::int(2), test_span()),
         ];
         let expr = Expr::App(func, args, test_span());
         
@@ -190,13 +193,13 @@ mod tests {
                 
                 assert_eq!(restored_args.len(), 2, "Argument count not preserved");
                 
-                if let Expr::Literal(Literal::Integer(n1), _) = &restored_args[0] {
+                if let Expr::Literal(Literal::Integer(n1, _), _) = &restored_args[0] {
                     assert_eq!(*n1, 1, "First argument not preserved");
                 } else {
                     panic!("Expected integer literal for first argument");
                 }
                 
-                if let Expr::Literal(Literal::Integer(n2), _) = &restored_args[1] {
+                if let Expr::Literal(Literal::Integer(n2, _), _) = &restored_args[1] {
                     assert_eq!(*n2, 2, "Second argument not preserved");
                 } else {
                     panic!("Expected integer literal for second argument");
@@ -283,7 +286,7 @@ mod tests {
     #[test]
     fn test_let_round_trip() {
         let pattern = Pattern::Variable(Symbol::intern("y"), test_span());
-        let value = Box::new(Expr::Literal(Literal::Integer(42), test_span()));
+        let value = Box::new(Expr::Literal(Literal::int(42), test_span()));
         let body = Box::new(Expr::Var(Symbol::intern("y"), test_span()));
         
         let expr = Expr::Let {
@@ -338,7 +341,7 @@ mod tests {
                     panic!("Expected variable pattern in let");
                 }
                 
-                if let Expr::Literal(Literal::Integer(n), _) = value.as_ref() {
+                if let Expr::Literal(Literal::Integer(n, _), _) = value.as_ref() {
                     assert_eq!(*n, 42, "Let value not preserved");
                 } else {
                     panic!("Expected integer literal in let value");
@@ -458,8 +461,8 @@ mod tests {
         let use_add = Box::new(Expr::App(
             Box::new(Expr::Var(Symbol::intern("add"), test_span())),
             vec![
-                Expr::Literal(Literal::Integer(1), test_span()),
-                Expr::Literal(Literal::Integer(2), test_span()),
+                Expr::Literal(Literal::int(1), test_span()),
+                Expr::Literal(Literal::int(2), test_span()),
             ],
             test_span(),
         ));
@@ -558,8 +561,8 @@ mod tests {
     /// Test round-trip serialization preserves spans
     #[test]
     fn test_span_preservation() {
-        let custom_span = Span::new(FileId::new(42), ByteOffset::new(100), ByteOffset::new(200));
-        let expr = Expr::Literal(Literal::Integer(123), custom_span);
+        let custom_span = Span::new(FileId::new(42), CharOffset::new(100), CharOffset::new(200));
+        let expr = Expr::Literal(Literal::int(123), custom_span);
         
         let module = Module {
             name: ModulePath::single(Symbol::intern("test"), test_span()),
@@ -609,4 +612,17 @@ mod tests {
             panic!("Expected value definition");
         }
     }
+    /// The serializer reports the names of the symbols it wrote
+    #[test]
+    fn test_symbol_snapshot_of_serialization() {
+        let unit = crate::parse_source("module Snap\nlet snapshot_name = 1 + 2", FileId::new(0), crate::SyntaxStyle::SExpression)
+            .expect("Failed to parse");
+        let mut serializer = BinarySerializer::new();
+        serializer.serialize_compilation_unit(&unit).expect("Failed to serialize compilation unit");
+
+        let name = Symbol::intern("snapshot_name");
+        let map = serializer.symbol_snapshot().restore();
+        assert_eq!(map.get(name.as_u32()), Some(name));
+        assert_eq!(map.get(crate::symbol::symbols::PLUS().as_u32()), Some(crate::symbol::symbols::PLUS()));
+    }
 }
\ No newline at end of file
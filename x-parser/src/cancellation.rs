@@ -0,0 +1,107 @@
+//! Cooperative cancellation for long-running analyses
+//!
+//! A `CancellationToken` is shared between the caller (e.g. an LSP request
+//! handler) and the parser, checker, and compilation pipeline. Each stage polls
+//! the token at item and expression boundaries so that superseded work stops
+//! promptly instead of running to completion. A token with a deadline also
+//! counts as cancelled once the deadline has passed, which bounds how long
+//! the work it guards may run.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// Marker error returned when an operation observes a cancelled token
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[error("Operation was cancelled")]
+pub struct Cancelled;
+
+/// Shared, cloneable cancellation flag
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    flag: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl CancellationToken {
+    /// Create a new token that has not been cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// This token, also cancelled once `timeout` has passed from now
+    ///
+    /// The deadline belongs to the returned token and its clones; the
+    /// tokens it was cloned from keep theirs.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        self.with_deadline(Instant::now() + timeout)
+    }
+
+    /// This token, also cancelled from `deadline` on
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(self.deadline.map_or(deadline, |earlier| earlier.min(deadline)));
+        self
+    }
+
+    /// Request cancellation of every operation observing this token
+    pub fn cancel(&self) {
+        self.flag.store(true, Ordering::Release);
+    }
+
+    /// Check whether cancellation has been requested or the deadline passed
+    pub fn is_cancelled(&self) -> bool {
+        self.flag.load(Ordering::Acquire) || self.is_timed_out()
+    }
+
+    /// Check whether the token's deadline has passed
+    pub fn is_timed_out(&self) -> bool {
+        self.deadline.is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// Return `Err(Cancelled)` if cancellation has been requested
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.is_cancelled() {
+            Err(Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_starts_uncancelled() {
+        let token = CancellationToken::new();
+        assert!(!token.is_cancelled());
+        assert_eq!(token.check(), Ok(()));
+    }
+
+    #[test]
+    fn test_cancel_is_shared_between_clones() {
+        let token = CancellationToken::new();
+        let observer = token.clone();
+
+        token.cancel();
+        assert!(observer.is_cancelled());
+        assert_eq!(observer.check(), Err(Cancelled));
+    }
+
+    #[test]
+    fn test_deadline_cancels() {
+        let token = CancellationToken::new();
+        let bounded = token.clone().with_timeout(Duration::from_secs(60));
+        assert!(!bounded.is_cancelled());
+
+        let expired = bounded.clone().with_deadline(Instant::now());
+        assert!(expired.is_timed_out() && expired.is_cancelled());
+        assert!(!bounded.is_cancelled() && !token.is_cancelled());
+
+        // Cancelling still reaches every clone, deadline or not
+        token.cancel();
+        assert!(bounded.is_cancelled() && !bounded.is_timed_out());
+    }
+}
@@ -0,0 +1,100 @@
+//! Compression for binary AST payloads
+//!
+//! Payloads are DEFLATE streams (RFC 1951) produced by `flate2`. Serialized
+//! ASTs repeat span markers, type codes and symbol ids heavily, which deflate
+//! catches well.
+//!
+//! Layout: uncompressed length as `u32` LE, then the raw deflate stream. The
+//! length is untrusted input, so decompression caps it at what the stream
+//! could possibly expand to and never writes past it.
+
+use crate::error::{ParseError as Error, Result};
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use std::io::{Read, Write};
+
+/// Largest expansion a deflate stream can encode: 258-byte matches packed
+/// into a little over two bits each
+const MAX_RATIO: usize = 1032;
+
+/// Compress `input`
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len() / 2 + 8);
+    out.extend_from_slice(&(input.len() as u32).to_le_bytes());
+    let mut encoder = DeflateEncoder::new(out, Compression::default());
+    encoder.write_all(input).expect("writing to a Vec cannot fail");
+    encoder.finish().expect("writing to a Vec cannot fail")
+}
+
+/// Decompress data produced by [`compress`]
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    let corrupt = |what: &str| Error::Parse {
+        message: format!("Corrupted compressed payload: {what}"),
+    };
+
+    let expected = data.get(0..4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+        .ok_or_else(|| corrupt("missing length"))?;
+    let stream = &data[4..];
+    if expected > stream.len().saturating_mul(MAX_RATIO) {
+        return Err(corrupt(&format!(
+            "length {expected} is more than {} bytes of input can expand to",
+            stream.len()
+        )));
+    }
+
+    // One byte of slack tells an over-long stream apart from an exact one
+    let mut out = Vec::with_capacity(expected);
+    DeflateDecoder::new(stream)
+        .take(expected as u64 + 1)
+        .read_to_end(&mut out)
+        .map_err(|e| corrupt(&e.to_string()))?;
+
+    if out.len() != expected {
+        return Err(corrupt(&format!("expected {expected} bytes, got at least {}", out.len())));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let inputs: [&[u8]; 4] = [
+            b"",
+            b"abc",
+            b"abcabcabcabcabcabcabcabcabcabc with a tail that does not repeat",
+            &[7u8; 1000],
+        ];
+        for input in inputs {
+            assert_eq!(decompress(&compress(input)).unwrap(), input);
+        }
+
+        let long: Vec<u8> = (0..70_000u32).map(|i| (i % 251) as u8 ^ (i / 300) as u8).collect();
+        let packed = compress(&long);
+        assert!(packed.len() < long.len());
+        assert_eq!(decompress(&packed).unwrap(), long);
+    }
+
+    #[test]
+    fn test_rejects_corrupt_input() {
+        let mut packed = compress(b"hello hello hello hello hello");
+        packed[0] = packed[0].wrapping_add(1);
+        assert!(decompress(&packed).is_err());
+        assert!(decompress(&[1, 0]).is_err());
+    }
+
+    #[test]
+    fn test_rejects_oversized_length() {
+        // The header claims 4 GiB; nothing may be allocated for it
+        let mut packed = compress(b"tiny");
+        packed[0..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(decompress(&packed).is_err());
+
+        // A stream longer than its header says stops at the stated length
+        let mut packed = compress(&[0u8; 10_000]);
+        packed[0..4].copy_from_slice(&100u32.to_le_bytes());
+        assert!(decompress(&packed).is_err());
+    }
+}
@@ -175,6 +175,50 @@ impl ContentHasher {
                 self.hash_expr(expr);
                 self.hash_type(type_annotation);
             }
+            Expr::Record { fields, .. } => {
+                self.write_u8(b'{');
+                self.hash_record_fields(fields);
+            }
+            Expr::Project { record, field, .. } => {
+                self.write_u8(b'.');
+                self.hash_expr(record);
+                self.write_symbol(field);
+            }
+            Expr::RecordUpdate { record, fields, .. } => {
+                self.write_u8(b'W');
+                self.hash_expr(record);
+                self.hash_record_fields(fields);
+            }
+            Expr::Tuple { elements, .. } => {
+                self.write_u8(b',');
+                self.write_u8(elements.len() as u8);
+                for element in elements {
+                    self.hash_expr(element);
+                }
+            }
+            Expr::Pack { module, signature, .. } => {
+                self.write_u8(b'K');
+                self.write_symbol(module);
+                self.write_symbol(signature);
+            }
+            Expr::Unpack { name, signature, value, body, .. } => {
+                self.write_u8(b'U');
+                self.write_symbol(name);
+                self.write_symbol(signature);
+                self.hash_expr(value);
+                self.hash_expr(body);
+            }
+            Expr::Error(_) => {
+                self.write_u8(b'!');
+            }
+        }
+    }
+
+    fn hash_record_fields(&mut self, fields: &[(Symbol, Expr)]) {
+        self.write_u8(fields.len() as u8);
+        for (name, value) in fields {
+            self.write_symbol(name);
+            self.hash_expr(value);
         }
     }
 
@@ -243,11 +287,12 @@ impl ContentHasher {
 
     fn hash_literal(&mut self, lit: &Literal) {
         match lit {
-            Literal::Integer(n) => {
+            // How a number was written does not change what it means
+            Literal::Integer(n, _) => {
                 self.write_u8(b'i');
                 self.write_string(&n.to_string());
             }
-            Literal::Float(f) => {
+            Literal::Float(f, _) => {
                 self.write_u8(b'f');
                 // Use a canonical representation for floats
                 self.write_string(&format!("{f:?}"));
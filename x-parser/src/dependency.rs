@@ -244,9 +244,33 @@ impl DependencyManager {
                     Self::collect_expr_deps(arg, deps, bound_vars);
                 }
             }
-            Expr::Ann { expr, .. } => {
+            Expr::Ann { expr, .. } | Expr::Project { record: expr, .. } => {
                 Self::collect_expr_deps(expr, deps, bound_vars);
             }
+            Expr::Record { fields, .. } => {
+                for (_, value) in fields {
+                    Self::collect_expr_deps(value, deps, bound_vars);
+                }
+            }
+            Expr::Tuple { elements, .. } => {
+                for element in elements {
+                    Self::collect_expr_deps(element, deps, bound_vars);
+                }
+            }
+            Expr::Pack { .. } => {}
+            Expr::Unpack { name, value, body, .. } => {
+                Self::collect_expr_deps(value, deps, bound_vars);
+                let mut new_bound = bound_vars.clone();
+                new_bound.insert(*name);
+                Self::collect_expr_deps(body, deps, &mut new_bound);
+            }
+            Expr::Error(_) => {}
+            Expr::RecordUpdate { record, fields, .. } => {
+                Self::collect_expr_deps(record, deps, bound_vars);
+                for (_, value) in fields {
+                    Self::collect_expr_deps(value, deps, bound_vars);
+                }
+            }
             Expr::Literal(_, _) => {
                 // No dependencies
             }
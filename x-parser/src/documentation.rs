@@ -0,0 +1,302 @@
+//! Doc comment attributes and the schema they are checked against
+//!
+//! A doc comment may open with front matter between `---` lines, one
+//! attribute per line, written `key: value` or JSDoc-style `@key value`:
+//!
+//! ```text
+//! ---
+//! @param {n: Int} how many copies to make
+//! @returns {List[a]} the copies
+//! @example replicate 3 'x'
+//! @deprecated use `List.repeat`
+//! @since 1.2.0
+//! @tailrec
+//! ---
+//! ```
+//!
+//! | Attribute    | Value                                                  |
+//! |--------------|--------------------------------------------------------|
+//! | `param NAME` | a description, optionally after `{NAME: Type}`         |
+//! | `returns`    | a description, optionally after `{Type}`               |
+//! | `example`    | an expression using the item                           |
+//! | `deprecated` | `true`, or what to use instead                         |
+//! | `since`      | the version the item appeared in, such as `1.2.0`      |
+//! | `tailrec`    | nothing, or `true` or `false`                          |
+//! | `inline`     | nothing, or `true` or `false`                          |
+//! | `noinline`   | nothing, or `true` or `false`                          |
+//!
+//! Anything else is kept in the AST, but reported by
+//! [`DocComment::attribute_warnings`], which `x doc` and the
+//! `doc-attributes` lint show. The checker warns at each use of a
+//! `deprecated` item, with the attribute's message as the hint, and the compiler
+//! reports a `tailrec` function whose recursive calls are not all tail
+//! calls. `inline` and `noinline` steer which functions the optimizer
+//! inlines into other modules.
+
+use crate::ast::{DocAttributeValue, DocComment};
+use crate::span::Span;
+use crate::unicode::{is_identifier_continue, is_identifier_start};
+use std::fmt;
+
+/// Attributes the schema knows, by the name their key starts with
+pub const DOC_ATTRIBUTES: &[&str] = &["param", "returns", "example", "deprecated", "since", "tailrec", "inline", "noinline"];
+
+/// A doc comment attribute the schema does not allow
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocAttributeWarning {
+    /// The attribute's key, e.g. `param x`
+    pub attribute: String,
+    pub message: String,
+    /// The doc comment the attribute is in
+    pub span: Span,
+}
+
+/// What a `@deprecated` attribute says about its item
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deprecation {
+    /// What to use instead, when the attribute gives a message
+    pub note: Option<String>,
+}
+
+impl fmt::Display for DocAttributeWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl DocComment {
+    /// Attributes that are unknown or whose value does not fit the schema,
+    /// in key order
+    pub fn attribute_warnings(&self) -> Vec<DocAttributeWarning> {
+        let mut attributes: Vec<_> = self.attributes.iter().collect();
+        attributes.sort_by(|a, b| a.0.cmp(b.0));
+        attributes.into_iter()
+            .filter_map(|(key, value)| {
+                attribute_problem(key, value).map(|message| DocAttributeWarning {
+                    attribute: key.clone(),
+                    message,
+                    span: self.span,
+                })
+            })
+            .collect()
+    }
+
+    /// The item's deprecation, unless it has no `@deprecated` attribute or
+    /// the attribute is `false`
+    pub fn deprecation(&self) -> Option<Deprecation> {
+        match self.attributes.get("deprecated")? {
+            DocAttributeValue::Boolean(false) => None,
+            DocAttributeValue::String(note) if !note.is_empty() => Some(Deprecation { note: Some(note.clone()) }),
+            _ => Some(Deprecation { note: None }),
+        }
+    }
+
+    /// Whether the item asserts, with `@tailrec`, that it only calls itself
+    /// in tail position
+    pub fn tailrec(&self) -> bool {
+        self.flag("tailrec")
+    }
+
+    /// Whether the item should be inlined, as `@inline` asks, or kept a
+    /// call, as `@noinline` does, when it says either
+    pub fn inline(&self) -> Option<bool> {
+        if self.flag("noinline") {
+            Some(false)
+        } else {
+            self.flag("inline").then_some(true)
+        }
+    }
+
+    /// Whether the attribute `key` is present without a value or `true`
+    fn flag(&self, key: &str) -> bool {
+        match self.attributes.get(key) {
+            Some(DocAttributeValue::Boolean(value)) => *value,
+            Some(DocAttributeValue::String(value)) => value.is_empty(),
+            _ => false,
+        }
+    }
+}
+
+/// What is wrong with the attribute `key`, if anything
+fn attribute_problem(key: &str, value: &DocAttributeValue) -> Option<String> {
+    use DocAttributeValue::*;
+    let (name, argument) = match key.split_once(' ') {
+        Some((name, argument)) => (name, Some(argument)),
+        None => (key, None),
+    };
+    let fits = match (name, argument) {
+        ("param", Some(param)) if is_identifier(param) => matches!(value, String(_) | TypedParam { .. }),
+        ("param", _) => return Some("`param` needs the parameter's name, as in `@param {x: Int} description`".to_string()),
+        (name, Some(_)) if DOC_ATTRIBUTES.contains(&name) => return Some(format!("`{name}` takes no name")),
+        ("returns", None) => matches!(value, String(_) | TypedParam { .. }),
+        ("example", None) => matches!(value, String(_) | Number(_)),
+        ("deprecated", None) => matches!(value, String(_) | Boolean(_)),
+        ("tailrec" | "inline" | "noinline", None) => matches!(value, Boolean(_)) || matches!(value, String(value) if value.is_empty()),
+        ("since", None) => match value {
+            String(version) => is_version(version),
+            Number(_) => true,
+            _ => false,
+        },
+        _ => return Some(format!("unknown doc attribute `{key}`")),
+    };
+    let expected = match name {
+        "param" | "returns" => "a description",
+        "example" => "an expression",
+        "deprecated" => "`true` or a message",
+        "tailrec" | "inline" | "noinline" => "no value, or a boolean",
+        _ => "a version, such as `1.2.0`",
+    };
+    (!fits).then(|| format!("`{name}` takes {expected}"))
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(is_identifier_start) && chars.all(is_identifier_continue)
+}
+
+fn is_version(version: &str) -> bool {
+    version.split('.').all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Parse a front matter line into its key and value
+///
+/// `@param` keys take the parameter's name, from `{name: Type}` or the
+/// first word, so that each parameter has its own key.
+pub(crate) fn parse_attribute(line: &str) -> Option<(String, DocAttributeValue)> {
+    let (key, value) = match line.trim().strip_prefix('@') {
+        // `@key value`, or `@key: value`
+        Some(tagged) => {
+            let (key, value) = tagged.split_once(char::is_whitespace).unwrap_or((tagged, ""));
+            (key.trim_end_matches(':'), value.trim())
+        }
+        None => {
+            let (key, value) = line.split_once(':')?;
+            (key.trim(), value.trim())
+        }
+    };
+    if key != "param" {
+        return Some((key.to_string(), parse_value(value)));
+    }
+
+    if let Some((binding, description)) = value.strip_prefix('{').and_then(|rest| rest.split_once('}')) {
+        let (name, type_info) = binding.split_once(':').unwrap_or((binding, ""));
+        let value = DocAttributeValue::TypedParam {
+            type_info: type_info.trim().to_string(),
+            description: description.trim().to_string(),
+        };
+        return Some((format!("param {}", name.trim()), value));
+    }
+    let (name, description) = value.split_once(char::is_whitespace).unwrap_or((value, ""));
+    let key = if name.is_empty() { "param".to_string() } else { format!("param {name}") };
+    Some((key, DocAttributeValue::String(description.trim().to_string())))
+}
+
+fn parse_value(value: &str) -> DocAttributeValue {
+    if let Ok(num) = value.parse::<f64>() {
+        DocAttributeValue::Number(num)
+    } else if value == "true" || value == "false" {
+        DocAttributeValue::Boolean(value == "true")
+    } else if value.starts_with('[') && value.ends_with(']') {
+        // Simple list parsing
+        let items: Vec<String> = value[1..value.len()-1]
+            .split(',')
+            .map(|s| s.trim().trim_matches('"').to_string())
+            .collect();
+        DocAttributeValue::List(items)
+    } else if let Some((type_part, desc_part)) = value.strip_prefix('{').and_then(|rest| rest.split_once('}')) {
+        // Typed values like {Int} description
+        DocAttributeValue::TypedParam {
+            type_info: type_part.to_string(),
+            description: desc_part.trim().to_string(),
+        }
+    } else {
+        // A quoted message, such as `@deprecated "use bar instead"`
+        let unquoted = value.strip_prefix('"').and_then(|rest| rest.strip_suffix('"')).unwrap_or(value);
+        DocAttributeValue::String(unquoted.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::span::FileId;
+    use std::collections::HashMap;
+
+    fn comment(lines: &[&str]) -> DocComment {
+        DocComment {
+            content: String::new(),
+            attributes: lines.iter().filter_map(|line| parse_attribute(line)).collect::<HashMap<_, _>>(),
+            code_blocks: Vec::new(),
+            span: Span::single(FileId::new(0), crate::span::CharOffset::new(0)),
+        }
+    }
+
+    #[test]
+    fn test_parse_attribute_lines() {
+        let typed = parse_attribute("@param {n: Int} how many");
+        assert_eq!(typed, Some(("param n".to_string(), DocAttributeValue::TypedParam {
+            type_info: "Int".to_string(),
+            description: "how many".to_string(),
+        })));
+        assert_eq!(parse_attribute("@param xs the list"), Some(("param xs".to_string(), DocAttributeValue::String("the list".to_string()))));
+        assert_eq!(parse_attribute("@since: 1.2.0"), Some(("since".to_string(), DocAttributeValue::String("1.2.0".to_string()))));
+        assert_eq!(parse_attribute("deprecated: true"), Some(("deprecated".to_string(), DocAttributeValue::Boolean(true))));
+        assert_eq!(parse_attribute("no separator"), None);
+        assert_eq!(
+            parse_attribute("@deprecated \"use bar instead\""),
+            Some(("deprecated".to_string(), DocAttributeValue::String("use bar instead".to_string())))
+        );
+    }
+
+    #[test]
+    fn test_deprecation() {
+        let note = Some("use bar instead".to_string());
+        assert_eq!(comment(&["@deprecated \"use bar instead\""]).deprecation(), Some(Deprecation { note }));
+        assert_eq!(comment(&["@deprecated true"]).deprecation(), Some(Deprecation { note: None }));
+        assert_eq!(comment(&["@deprecated false"]).deprecation(), None);
+        assert_eq!(comment(&["@since 1.0"]).deprecation(), None);
+    }
+
+    #[test]
+    fn test_tailrec() {
+        assert!(comment(&["@tailrec"]).tailrec());
+        assert!(comment(&["tailrec: true"]).tailrec());
+        assert!(!comment(&["@tailrec false"]).tailrec());
+        assert!(!comment(&["@since 1.0"]).tailrec());
+        assert_eq!(comment(&["@tailrec"]).attribute_warnings(), Vec::new());
+        let messages: Vec<_> = comment(&["@tailrec always"]).attribute_warnings().iter().map(|warning| warning.to_string()).collect();
+        assert_eq!(messages, ["`tailrec` takes no value, or a boolean"]);
+    }
+
+    #[test]
+    fn test_inline() {
+        assert_eq!(comment(&["@inline"]).inline(), Some(true));
+        assert_eq!(comment(&["@noinline"]).inline(), Some(false));
+        assert_eq!(comment(&["@inline", "@noinline true"]).inline(), Some(false));
+        assert_eq!(comment(&["@inline false"]).inline(), None);
+        assert_eq!(comment(&["@since 1.0"]).inline(), None);
+    }
+
+    #[test]
+    fn test_attribute_warnings() {
+        let valid = comment(&[
+            "@param {n: Int} how many",
+            "@param xs the list",
+            "@returns {List[a]} the copies",
+            "@example replicate 3 x",
+            "@deprecated use `repeat`",
+            "@since 1.2.0",
+        ]);
+        assert_eq!(valid.attribute_warnings(), Vec::new());
+
+        let invalid = comment(&["@param", "@since soon", "@deprecated [a, b]", "@author me", "returns x: y"]);
+        let messages: Vec<_> = invalid.attribute_warnings().iter().map(|warning| warning.to_string()).collect();
+        assert_eq!(messages, [
+            "unknown doc attribute `author`",
+            "`deprecated` takes `true` or a message",
+            "`param` needs the parameter's name, as in `@param {x: Int} description`",
+            "`returns` takes no name",
+            "`since` takes a version, such as `1.2.0`",
+        ]);
+    }
+}
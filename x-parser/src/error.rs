@@ -1,5 +1,6 @@
 //! Parser error types and utilities
 
+use crate::cancellation::Cancelled;
 use crate::span::Span;
 use thiserror::Error;
 
@@ -34,6 +35,12 @@ pub enum ParseError {
 
     #[error("I/O error: {message}")]
     Io { message: String },
+
+    #[error("Parsing was cancelled")]
+    Cancelled,
+
+    #[error("Parsing needed more than its memory limit of {limit} bytes")]
+    MemoryLimit { limit: usize },
 }
 
 impl ParseError {
@@ -96,13 +103,41 @@ impl ParseError {
         }
     }
 
+    /// Stable diagnostic code; parser errors use the `E00xx` range
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Parse { .. } => "E0001",
+            Self::Lexer { .. } => "E0002",
+            Self::Syntax { .. } => "E0003",
+            Self::UnexpectedToken { .. } => "E0004",
+            Self::UnexpectedEof { .. } => "E0005",
+            Self::InvalidSyntaxStyle { .. } => "E0006",
+            Self::BinaryFormat { .. } => "E0007",
+            Self::Io { .. } => "E0008",
+            Self::Cancelled => "E0009",
+            Self::MemoryLimit { .. } => "E0010",
+        }
+    }
+
     /// Check if this is a fatal error that should stop parsing
     pub fn is_fatal(&self) -> bool {
         matches!(
             self,
-            Self::UnexpectedEof { .. } | Self::BinaryFormat { .. } | Self::Io { .. }
+            Self::UnexpectedEof { .. } | Self::BinaryFormat { .. } | Self::Io { .. } | Self::Cancelled
+                | Self::MemoryLimit { .. }
         )
     }
+
+    /// Check if parsing stopped because its cancellation token was triggered
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self, Self::Cancelled)
+    }
+}
+
+impl From<Cancelled> for ParseError {
+    fn from(_: Cancelled) -> Self {
+        Self::Cancelled
+    }
 }
 
 /// Error recovery strategies for parsing
@@ -208,14 +243,14 @@ impl ErrorReporter {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::span::{FileId, ByteOffset};
+    use crate::span::{FileId, CharOffset};
 
     #[test]
     fn test_error_creation() {
         let span = Span::new(
             FileId::new(0),
-            ByteOffset::new(0),
-            ByteOffset::new(5),
+            CharOffset::new(0),
+            CharOffset::new(5),
         );
 
         let error = ParseError::syntax("test error", span);
@@ -229,8 +264,8 @@ mod tests {
         
         let span = Span::new(
             FileId::new(0),
-            ByteOffset::new(0),
-            ByteOffset::new(5),
+            CharOffset::new(0),
+            CharOffset::new(5),
         );
 
         reporter.report_error(ParseError::syntax("test error", span));
@@ -249,8 +284,8 @@ mod tests {
         
         let span = Span::new(
             FileId::new(0),
-            ByteOffset::new(8),
-            ByteOffset::new(15),
+            CharOffset::new(8),
+            CharOffset::new(15),
         );
 
         reporter.report_error(ParseError::syntax("invalid syntax", span));
@@ -0,0 +1,317 @@
+//! Fuzzing support
+//!
+//! Entry points for fuzzers such as cargo-fuzz or proptest, none of which may
+//! panic whatever the input:
+//!
+//! - [`parse_arbitrary`] feeds raw bytes to the text parser;
+//! - [`deserialize_arbitrary`] feeds raw bytes to the binary deserializer;
+//! - [`roundtrip_arbitrary`] builds an AST from the bytes with [`Arbitrary`],
+//!   serializes it and checks that deserializing gives it back.
+//!
+//! [`Arbitrary`] is implemented for the parts of the AST the binary format
+//! covers, always producing trees the printer can write and the parser can
+//! read. [`corpus`] uses it to write seed sources that lean on edge cases:
+//! extreme numbers, escapes and non-ASCII text in strings, long names and
+//! deep nesting.
+
+use crate::ast::*;
+use crate::binary::{BinaryDeserializer, BinarySerializer};
+use crate::error::{ParseError, Result};
+use crate::span::{CharOffset, FileId, Span};
+use crate::symbol::Symbol;
+use crate::syntax::ocaml::OCamlPrinter;
+use crate::syntax::{SyntaxConfig, SyntaxPrinter, SyntaxStyle};
+
+/// How deep generated expressions and patterns nest at most
+const MAX_DEPTH: usize = 6;
+
+/// Parse `data`, read as UTF-8 with invalid sequences replaced, as source text
+pub fn parse_arbitrary(data: &[u8]) -> Result<CompilationUnit> {
+    let source = String::from_utf8_lossy(data);
+    crate::parse_source(&source, FileId::new(0), crate::SyntaxStyle::SExpression)
+}
+
+/// Deserialize `data` as a binary AST
+pub fn deserialize_arbitrary(data: &[u8]) -> Result<CompilationUnit> {
+    BinaryDeserializer::from_slice(data)?.deserialize_compilation_unit()
+}
+
+/// Build a compilation unit from `data` and round-trip it through the binary
+/// format, failing if it does not come back unchanged
+pub fn roundtrip_arbitrary(data: &[u8]) -> Result<CompilationUnit> {
+    let unit = CompilationUnit::arbitrary(&mut Unstructured::new(data));
+    let bytes = BinarySerializer::new().serialize_compilation_unit(&unit)?;
+    let restored = BinaryDeserializer::new(bytes)?.deserialize_compilation_unit()?;
+    if restored != unit {
+        return Err(ParseError::BinaryFormat {
+            message: format!("round trip changed the AST:\n{unit:#?}\nbecame\n{restored:#?}"),
+        });
+    }
+    Ok(unit)
+}
+
+/// `count` seed sources generated from `seed`, each a module that parses
+pub fn corpus(seed: u64, count: usize) -> Vec<String> {
+    let mut state = seed | 1;
+    let config = SyntaxConfig { style: SyntaxStyle::OCaml, ..SyntaxConfig::default() };
+    (0..count)
+        .map(|_| {
+            let data: Vec<u8> = (0..512).map(|_| xorshift(&mut state) as u8).collect();
+            let unit = CompilationUnit::arbitrary(&mut Unstructured::new(&data));
+            OCamlPrinter::new().print(&unit, &config).expect("generated ASTs are printable")
+        })
+        .collect()
+}
+
+fn xorshift(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
+
+/// Raw fuzzer input, consumed a choice at a time
+///
+/// Once the bytes run out every choice takes its smallest option, so any
+/// input, including an empty one, builds a finite value.
+pub struct Unstructured<'a> {
+    data: &'a [u8],
+    depth: usize,
+    offset: u32,
+}
+
+impl<'a> Unstructured<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, depth: 0, offset: 0 }
+    }
+
+    /// Whether the input is used up
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn byte(&mut self) -> u8 {
+        match self.data.split_first() {
+            Some((&byte, rest)) => {
+                self.data = rest;
+                byte
+            }
+            None => 0,
+        }
+    }
+
+    /// A number in `0..bound`, or 0 if `bound` is 0
+    pub fn below(&mut self, bound: usize) -> usize {
+        if bound == 0 {
+            return 0;
+        }
+        let value = u16::from_le_bytes([self.byte(), self.byte()]);
+        value as usize % bound
+    }
+
+    pub fn choose<'b, T>(&mut self, options: &'b [T]) -> &'b T {
+        &options[self.below(options.len())]
+    }
+
+    pub fn u64(&mut self) -> u64 {
+        let mut bytes = [0; 8];
+        bytes.iter_mut().for_each(|byte| *byte = self.byte());
+        u64::from_le_bytes(bytes)
+    }
+
+    /// A fresh span, after every span handed out before
+    fn span(&mut self) -> Span {
+        let start = self.offset;
+        self.offset += 1 + self.below(16) as u32;
+        Span::new(FileId::new(0), CharOffset::new(start), CharOffset::new(self.offset))
+    }
+
+    /// Whether there is budget left to nest another level
+    fn can_nest(&self) -> bool {
+        self.depth < MAX_DEPTH && !self.data.is_empty()
+    }
+
+    fn nested<T>(&mut self, build: impl FnOnce(&mut Self) -> T) -> T {
+        self.depth += 1;
+        let value = build(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn many<T>(&mut self, min: usize, max: usize, build: impl Fn(&mut Self) -> T) -> Vec<T> {
+        let count = min + self.below(max - min + 1);
+        (0..count).map(|_| build(self)).collect()
+    }
+}
+
+/// Types that can be built from fuzzer input
+pub trait Arbitrary: Sized {
+    fn arbitrary(u: &mut Unstructured) -> Self;
+}
+
+const NAMES: &[&str] = &["x", "y", "acc", "value_1", "f'", "_unused", "a_very_long_identifier_name_that_keeps_going_and_going"];
+const CONSTRUCTORS: &[&str] = &["None", "Some", "Cons", "Nil", "Pair", "A"];
+const OPERATORS: &[&str] = &["+", "-", "*", "/", "%", "^", "::", "==", "!=", "<", "<=", ">", ">=", "&&", "||", "|>", ">>", "<<"];
+const STRINGS: &[&str] = &["", " ", "\"quoted\"", "back\\slash", "tab\tand\nnewline", "日本語", "emoji 🦀", "\u{0}"];
+
+fn name(u: &mut Unstructured) -> Symbol {
+    let base = *u.choose(NAMES);
+    match u.below(3) {
+        0 => Symbol::intern(&format!("{base}{}", u.below(1000))),
+        _ => Symbol::intern(base),
+    }
+}
+
+impl Arbitrary for Literal {
+    fn arbitrary(u: &mut Unstructured) -> Self {
+        match u.below(6) {
+            0 => Literal::int(*u.choose(&[0, 1, 42, i64::MAX, 1 << 53])),
+            1 => Literal::int((u.u64() >> 1) as i64),
+            2 => Literal::float(*u.choose(&[0.0, 0.5, 1e-300, 1e300, f64::MAX, f64::MIN_POSITIVE, 3.25])),
+            3 => Literal::String(u.choose(STRINGS).to_string()),
+            4 => Literal::Bool(u.below(2) == 0),
+            _ => Literal::Unit,
+        }
+    }
+}
+
+impl Arbitrary for Pattern {
+    fn arbitrary(u: &mut Unstructured) -> Self {
+        let choice = if u.can_nest() { u.below(5) } else { u.below(2) };
+        match choice {
+            0 => Pattern::Variable(name(u), u.span()),
+            1 => Pattern::Wildcard(u.span()),
+            2 => Pattern::Literal(Literal::arbitrary(u), u.span()),
+            3 => {
+                let constructor = *u.choose(CONSTRUCTORS);
+                let name = Symbol::intern(constructor);
+                let args = u.nested(|u| u.many(0, 3, simple_pattern));
+                Pattern::Constructor { name, args, span: u.span() }
+            }
+            _ => {
+                let patterns = u.nested(|u| u.many(2, 4, Pattern::arbitrary));
+                Pattern::Tuple { patterns, span: u.span() }
+            }
+        }
+    }
+}
+
+/// A pattern that needs no parentheses as a constructor argument
+fn simple_pattern(u: &mut Unstructured) -> Pattern {
+    match u.below(3) {
+        0 => Pattern::Wildcard(u.span()),
+        1 => Pattern::Variable(name(u), u.span()),
+        _ => {
+            let patterns = u.nested(|u| u.many(2, 3, simple_pattern));
+            Pattern::Tuple { patterns, span: u.span() }
+        }
+    }
+}
+
+impl Arbitrary for Expr {
+    fn arbitrary(u: &mut Unstructured) -> Self {
+        let choice = if u.can_nest() { u.below(8) } else { u.below(2) };
+        match choice {
+            0 => Expr::Literal(Literal::arbitrary(u), u.span()),
+            1 => Expr::Var(name(u), u.span()),
+            2 => {
+                let operator = *u.choose(OPERATORS);
+                let operator = Expr::Var(Symbol::intern(operator), u.span());
+                let operands = u.nested(|u| vec![Expr::arbitrary(u), Expr::arbitrary(u)]);
+                Expr::App(Box::new(operator), operands, u.span())
+            }
+            3 => {
+                let function = Expr::Var(name(u), u.span());
+                let args = u.nested(|u| u.many(1, 3, Expr::arbitrary));
+                Expr::App(Box::new(function), args, u.span())
+            }
+            4 => {
+                let parameters = vec![u.nested(Pattern::arbitrary)];
+                let body = u.nested(Expr::arbitrary);
+                Expr::Lambda { parameters, body: Box::new(body), span: u.span() }
+            }
+            5 => {
+                let pattern = Pattern::Variable(name(u), u.span());
+                let (value, body) = u.nested(|u| (Expr::arbitrary(u), Expr::arbitrary(u)));
+                Expr::Let { pattern, type_annotation: None, value: Box::new(value), body: Box::new(body), span: u.span() }
+            }
+            6 => {
+                let (condition, then_branch, else_branch) = u.nested(|u| (Expr::arbitrary(u), Expr::arbitrary(u), Expr::arbitrary(u)));
+                Expr::If {
+                    condition: Box::new(condition),
+                    then_branch: Box::new(then_branch),
+                    else_branch: Box::new(else_branch),
+                    span: u.span(),
+                }
+            }
+            _ => {
+                let elements = u.nested(|u| u.many(2, 4, Expr::arbitrary));
+                Expr::Tuple { elements, span: u.span() }
+            }
+        }
+    }
+}
+
+impl Arbitrary for CompilationUnit {
+    fn arbitrary(u: &mut Unstructured) -> Self {
+        let items = u.many(1, 6, |u| {
+            let name = Symbol::intern(&format!("{}_{}", u.choose(NAMES).trim_matches('_'), u.below(100)));
+            let body = Expr::arbitrary(u);
+            Item::ValueDef(ValueDef {
+                name,
+                documentation: None,
+                type_annotation: None,
+                parameters: Vec::new(),
+                body,
+                visibility: if u.below(2) == 0 { Visibility::Public } else { Visibility::Private },
+                purity: Purity::Inferred,
+                imports: Vec::new(),
+                span: u.span(),
+            })
+        });
+        let span = u.span();
+        CompilationUnit {
+            module: Module {
+                name: ModulePath::single(Symbol::intern("Fuzz"), span),
+                documentation: None,
+                exports: None,
+                imports: Vec::new(),
+                items,
+                span,
+            },
+            span,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::binary::MAGIC_NUMBER;
+
+    #[test]
+    fn test_arbitrary_inputs_round_trip() {
+        let mut state = 7;
+        for size in [0, 1, 3, 16, 64, 256, 1024] {
+            for _ in 0..20 {
+                let data: Vec<u8> = (0..size).map(|_| xorshift(&mut state) as u8).collect();
+                roundtrip_arbitrary(&data).unwrap();
+                let _ = parse_arbitrary(&data);
+                let _ = deserialize_arbitrary(&data);
+                // Past the magic number, so the payload readers see the noise
+                let unverified = [&MAGIC_NUMBER[..], &1u32.to_le_bytes(), &data].concat();
+                let _ = deserialize_arbitrary(&unverified);
+            }
+        }
+    }
+
+    #[test]
+    fn test_corpus_parses() {
+        let corpus = corpus(42, 100);
+        assert_eq!(corpus.len(), 100);
+        assert_eq!(corpus, super::corpus(42, 100), "the corpus depends on the seed only");
+        for source in &corpus {
+            parse_arbitrary(source.as_bytes()).unwrap_or_else(|error| panic!("{error}\n{source}"));
+        }
+    }
+}
@@ -3,47 +3,99 @@
 //! Tokenizes source code into a stream of tokens for parsing
 
 use crate::{
-    span::{FileId, ByteOffset, Span},
+    span::{FileId, CharOffset, Span},
     token::{Token, TokenKind, keyword_to_token},
+    trivia::{Comment, Trivia},
+    unicode::{is_identifier_continue, is_identifier_start, is_operator_char, nfc},
     error::{ParseError as Error, Result},
 };
 
 /// Lexical analyzer
 #[allow(dead_code)]
 pub struct Lexer {
-    input: String,
     chars: Vec<char>,
     position: usize,
     file_id: FileId,
+    /// Whether the last token was `@`, which a version requirement may follow
+    after_at: bool,
+    /// End of the last token read
+    last_token_end: Option<usize>,
+    /// Comments and blank lines skipped so far
+    trivia: Trivia,
+    /// Emptied strings to build token text in, left by an earlier parse
+    spare_text: Vec<String>,
+    /// Offset of the input in the source it was cut from
+    base: usize,
 }
 
 impl Lexer {
     /// Create a new lexer for the given input
     pub fn new(input: &str, file_id: FileId) -> Self {
-        let chars: Vec<char> = input.chars().collect();
+        Self::with_storage(input, file_id, Vec::new(), Vec::new())
+    }
+    
+    /// Create a lexer reusing the character buffer and token text of an
+    /// earlier parse
+    pub(crate) fn with_storage(input: &str, file_id: FileId, mut chars: Vec<char>, spare_text: Vec<String>) -> Self {
+        chars.clear();
+        chars.extend(input.chars());
         Lexer {
-            input: input.to_string(),
             chars,
             position: 0,
             file_id,
+            after_at: false,
+            last_token_end: None,
+            trivia: Trivia::new(),
+            spare_text,
+            base: 0,
         }
     }
     
+    /// Lex `input` as the part of a larger source starting at `base`, so
+    /// that spans are offsets in that source
+    pub(crate) fn at_offset(mut self, base: usize) -> Self {
+        self.base = base;
+        self
+    }
+    
+    /// The character buffer and unused token text, for a later parse
+    pub(crate) fn into_storage(self) -> (Vec<char>, Vec<String>) {
+        (self.chars, self.spare_text)
+    }
+    
+    /// Comments and blank lines skipped so far
+    pub fn trivia(&self) -> &Trivia {
+        &self.trivia
+    }
+    
+    /// Take the comments and blank lines skipped so far
+    pub fn take_trivia(&mut self) -> Trivia {
+        std::mem::take(&mut self.trivia)
+    }
+    
     /// Tokenize the entire input
     pub fn tokenize(&mut self) -> Result<Vec<Token>> {
         let mut tokens = Vec::new();
-        
+        self.tokenize_into(&mut tokens)?;
+        Ok(tokens)
+    }
+    
+    /// Tokenize the entire input, appending to `tokens`
+    pub(crate) fn tokenize_into(&mut self, tokens: &mut Vec<Token>) -> Result<()> {
         loop {
             let token = self.next_token()?;
             let is_eof = matches!(token.kind, TokenKind::Eof);
             tokens.push(token);
             
             if is_eof {
-                break;
+                return Ok(());
             }
         }
-        
-        Ok(tokens)
+    }
+    
+    /// An empty string for token text
+    fn text_buffer(&mut self) -> String {
+        self.spare_text.pop().unwrap_or_default()
     }
     
     /// Get current character
@@ -65,10 +117,34 @@ impl Lexer {
     
     /// Get the next token
     pub fn next_token(&mut self) -> Result<Token> {
+        let token = self.read_token()?;
+        self.last_token_end = Some(token.span.end.0 as usize - self.base);
+        Ok(token)
+    }
+    
+    fn read_token(&mut self) -> Result<Token> {
         self.skip_whitespace_and_comments();
         
         let start_pos = self.position;
         
+        // `@^1.0.0`: the requirement is one identifier-like token
+        if std::mem::take(&mut self.after_at)
+            && self.current_char().is_some_and(|c| c.is_ascii_digit() || "^~=<>".contains(c))
+        {
+            while self.current_char().is_some_and(|c| c.is_ascii_alphanumeric() || "^~=<>.*+-".contains(c)) {
+                self.advance();
+            }
+            let requirement: String = self.chars[start_pos..self.position].iter().collect();
+            return Ok(Token::new(TokenKind::Ident(requirement), self.make_span(start_pos, self.position)));
+        }
+        
+        if let Some(length) = self.user_operator_length() {
+            let operator: String = self.chars[start_pos..start_pos + length].iter().collect();
+            let operator = nfc(&operator).into_owned();
+            self.position += length;
+            return Ok(Token::new(TokenKind::Operator(operator), self.make_span(start_pos, self.position)));
+        }
+        
         match self.current_char() {
             None => Ok(Token::new(TokenKind::Eof, self.make_span(start_pos, self.position))),
             
@@ -133,8 +209,13 @@ impl Lexer {
                 self.advance();
                 Ok(Token::new(TokenKind::Question, self.make_span(start_pos, self.position)))
             }
+            Some('#') => {
+                self.advance();
+                Ok(Token::new(TokenKind::Hash, self.make_span(start_pos, self.position)))
+            }
             Some('@') => {
                 self.advance();
+                self.after_at = true;
                 Ok(Token::new(TokenKind::At, self.make_span(start_pos, self.position)))
             }
             
@@ -145,7 +226,7 @@ impl Lexer {
             Some(ch) if ch.is_ascii_digit() => self.read_number(),
             
             // Identifiers and keywords
-            Some(ch) if ch.is_alphabetic() || ch == '_' => self.read_identifier(),
+            Some(ch) if is_identifier_start(ch) => self.read_identifier(),
             
             // Operators
             Some('+') => {
@@ -199,6 +280,9 @@ impl Lexer {
                 if self.current_char() == Some('=') {
                     self.advance();
                     Ok(Token::new(TokenKind::LessEqual, self.make_span(start_pos, self.position)))
+                } else if self.current_char() == Some('<') {
+                    self.advance();
+                    Ok(Token::new(TokenKind::ComposeLeft, self.make_span(start_pos, self.position)))
                 } else {
                     Ok(Token::new(TokenKind::Less, self.make_span(start_pos, self.position)))
                 }
@@ -208,6 +292,9 @@ impl Lexer {
                 if self.current_char() == Some('=') {
                     self.advance();
                     Ok(Token::new(TokenKind::GreaterEqual, self.make_span(start_pos, self.position)))
+                } else if self.current_char() == Some('>') {
+                    self.advance();
+                    Ok(Token::new(TokenKind::ComposeRight, self.make_span(start_pos, self.position)))
                 } else {
                     Ok(Token::new(TokenKind::Greater, self.make_span(start_pos, self.position)))
                 }
@@ -255,6 +342,13 @@ impl Lexer {
     fn skip_whitespace_and_comments(&mut self) {
         loop {
             match self.current_char() {
+                Some('\n') => {
+                    let line_start = self.line_start();
+                    if self.chars[line_start..self.position].iter().all(|c| c.is_whitespace()) {
+                        self.trivia.blank_lines.push(CharOffset::new((self.base + line_start) as u32));
+                    }
+                    self.advance();
+                }
                 Some(ch) if ch.is_whitespace() => {
                     self.advance();
                 }
@@ -267,6 +361,7 @@ impl Lexer {
     }
     
     fn skip_line_comment(&mut self) {
+        let start = self.position;
         // Skip '--'
         self.advance();
         self.advance();
@@ -278,13 +373,30 @@ impl Lexer {
             }
             self.advance();
         }
+        
+        let text: String = self.chars[start + 2..self.position].iter().collect();
+        let after_code = self.last_token_end.filter(|&end| end >= self.line_start_of(start));
+        self.trivia.comments.push(Comment {
+            text: text.trim_end().to_string(),
+            span: self.make_span(start, self.position),
+            after_code: after_code.map(|end| CharOffset::new((self.base + end) as u32)),
+        });
+    }
+    
+    /// Start of the line the current character is on
+    fn line_start(&self) -> usize {
+        self.line_start_of(self.position)
+    }
+    
+    fn line_start_of(&self, position: usize) -> usize {
+        self.chars[..position].iter().rposition(|&c| c == '\n').map_or(0, |newline| newline + 1)
     }
     
     fn read_string(&mut self) -> Result<Token> {
         let start_pos = self.position;
         self.advance(); // Skip opening quote
         
-        let mut value = String::new();
+        let mut value = self.text_buffer();
         
         while let Some(ch) = self.current_char() {
             if ch == '"' {
@@ -318,10 +430,13 @@ impl Lexer {
     
     fn read_number(&mut self) -> Result<Token> {
         let start_pos = self.position;
-        let mut value = String::new();
+        let mut value = self.text_buffer();
         
+        // Radix prefixes, `_` separators and `i`/`f` suffixes are all taken
+        // here and validated when the literal is parsed
         while let Some(ch) = self.current_char() {
-            if ch.is_ascii_digit() || ch == '.' {
+            let fraction = ch == '.' && self.peek_ahead(1).is_some_and(|next| next.is_ascii_digit());
+            if ch.is_ascii_alphanumeric() || ch == '_' || fraction {
                 value.push(ch);
                 self.advance();
             } else {
@@ -337,10 +452,10 @@ impl Lexer {
     
     fn read_identifier(&mut self) -> Result<Token> {
         let start_pos = self.position;
-        let mut value = String::new();
+        let mut value = self.text_buffer();
         
         while let Some(ch) = self.current_char() {
-            if ch.is_alphanumeric() || ch == '_' || ch == '\'' {
+            if is_identifier_continue(ch) {
                 value.push(ch);
                 self.advance();
             } else {
@@ -349,9 +464,14 @@ impl Lexer {
         }
         
         let span = self.make_span(start_pos, self.position);
+        if let std::borrow::Cow::Owned(normalized) = nfc(&value) {
+            value = normalized;
+        }
         
         // Check if it's a keyword
         if let Some(keyword_token) = keyword_to_token(&value) {
+            value.clear();
+            self.spare_text.push(value);
             Ok(Token::new(keyword_token, span))
         } else {
             Ok(Token::new(TokenKind::Ident(value), span))
@@ -361,8 +481,8 @@ impl Lexer {
     fn make_span(&self, start: usize, end: usize) -> Span {
         Span::new(
             self.file_id,
-            ByteOffset::new(start as u32),
-            ByteOffset::new(end as u32),
+            CharOffset::new((self.base + start) as u32),
+            CharOffset::new((self.base + end) as u32),
         )
     }
     
@@ -371,6 +491,25 @@ impl Lexer {
         self.chars.get(self.position + n).copied()
     }
     
+    /// Length of the run of operator characters starting here, unless the
+    /// run is one of the fixed operators or the reserved `<-`; `--` ends a
+    /// run, as it starts a comment
+    fn user_operator_length(&self) -> Option<usize> {
+        const FIXED: &[&str] = &[
+            "+", "-", "->", "*", "/", "%", "=", "==", "=>", "!", "!=", "<", "<=", "<<", "<-",
+            ">", ">=", ">>", "&", "&&", "^", "|", "||", "|>",
+        ];
+        let mut length = 0;
+        while let Some(c) = self.peek_ahead(length) {
+            if !is_operator_char(c) || (c == '-' && self.peek_ahead(length + 1) == Some('-')) {
+                break;
+            }
+            length += 1;
+        }
+        let run: String = self.chars[self.position..self.position + length].iter().collect();
+        (length > 0 && !FIXED.contains(&run.as_str())).then_some(length)
+    }
+    
     /// Read a documentation comment
     fn read_doc_comment(&mut self) -> Result<Token> {
         let start_pos = self.position;
@@ -381,12 +520,24 @@ impl Lexer {
         self.advance(); // `
         
         let mut content = String::new();
+        // Inside a fenced code block opened with a language tag (```x)
+        let mut in_code_block = false;
         
         // Read until closing ```
         while self.position + 2 < self.chars.len() {
-            if self.current_char() == Some('`') 
+            let fence = self.current_char() == Some('`') 
                 && self.peek_char() == Some('`') 
-                && self.peek_ahead(2) == Some('`') {
+                && self.peek_ahead(2) == Some('`');
+            let tagged = self.peek_ahead(3).is_some_and(|c| c.is_alphanumeric());
+            if fence && (in_code_block || tagged) {
+                in_code_block = !in_code_block;
+                content.push_str("```");
+                self.advance();
+                self.advance();
+                self.advance();
+                continue;
+            }
+            if fence {
                 // Found closing ```
                 self.advance(); // `
                 self.advance(); // `
@@ -479,4 +630,82 @@ mod tests {
             TokenKind::Eof,
         ]);
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_composition_operators() {
+        let tokens = lex_string("f >> g << h > >= <");
+        assert_eq!(tokens, vec![
+            TokenKind::Ident("f".to_string()),
+            TokenKind::ComposeRight,
+            TokenKind::Ident("g".to_string()),
+            TokenKind::ComposeLeft,
+            TokenKind::Ident("h".to_string()),
+            TokenKind::Greater,
+            TokenKind::GreaterEqual,
+            TokenKind::Less,
+            TokenKind::Eof,
+        ]);
+    }
+    
+    #[test]
+    fn test_user_defined_operators() {
+        let tokens = lex_string("a <+> b >>= c <$> d ~ e +-- f");
+        assert_eq!(tokens, vec![
+            TokenKind::Ident("a".to_string()),
+            TokenKind::Operator("<+>".to_string()),
+            TokenKind::Ident("b".to_string()),
+            TokenKind::Operator(">>=".to_string()),
+            TokenKind::Ident("c".to_string()),
+            TokenKind::Operator("<$>".to_string()),
+            TokenKind::Ident("d".to_string()),
+            TokenKind::Operator("~".to_string()),
+            TokenKind::Ident("e".to_string()),
+            TokenKind::Plus,
+            TokenKind::Eof,
+        ]);
+    }
+    
+    #[test]
+    fn test_unicode_identifiers_and_operators() {
+        let tokens = lex_string("λx' ∘ 名前 ⊕= cafe\u{301}");
+        assert_eq!(tokens, vec![
+            TokenKind::Ident("λx'".to_string()),
+            TokenKind::Operator("∘".to_string()),
+            TokenKind::Ident("名前".to_string()),
+            TokenKind::Operator("⊕=".to_string()),
+            TokenKind::Ident("caf\u{e9}".to_string()),
+            TokenKind::Eof,
+        ]);
+        assert_eq!(crate::Symbol::intern("cafe\u{301}"), crate::Symbol::intern("caf\u{e9}"));
+
+        // Spans count characters, not UTF-8 bytes
+        let mut lexer = Lexer::new("λ ∘ x", FileId::new(0));
+        let starts: Vec<u32> = lexer.tokenize().unwrap().iter().map(|token| token.span.start.as_u32()).collect();
+        assert_eq!(starts, vec![0, 2, 4, 5]);
+    }
+    
+    #[test]
+    fn test_version_requirement() {
+        let tokens = lex_string("Foo@^1.0.0 { a }");
+        assert_eq!(tokens, vec![
+            TokenKind::Ident("Foo".to_string()),
+            TokenKind::At,
+            TokenKind::Ident("^1.0.0".to_string()),
+            TokenKind::LeftBrace,
+            TokenKind::Ident("a".to_string()),
+            TokenKind::RightBrace,
+            TokenKind::Eof,
+        ]);
+    }
+    
+    #[test]
+    fn test_comments_are_kept_as_trivia() {
+        let source = "-- header\n\nlet x = 1 -- one\n  \n-- two\nx";
+        let mut lexer = Lexer::new(source, FileId::new(0));
+        lexer.tokenize().unwrap();
+        let trivia = lexer.take_trivia();
+        let comments: Vec<_> = trivia.comments.iter().map(|c| (c.text.as_str(), c.after_code.map(|end| end.0))).collect();
+        assert_eq!(comments, [(" header", None), (" one", Some(20)), (" two", None)]);
+        assert_eq!(trivia.blank_lines, [CharOffset(10), CharOffset(28)]);
+    }
+}
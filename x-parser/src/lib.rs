@@ -4,15 +4,23 @@
 //! It supports multiple syntax styles (Haskell, S-expression)
 //! and handles conversion from text to AST representation.
 
+pub mod arena;
 pub mod ast;
+pub mod cancellation;
 pub mod persistent_ast;
 pub mod lexer;
 pub mod parser;
 pub mod syntax;
 pub mod span;
+pub mod source_map;
 pub mod symbol;
 pub mod token;
+pub mod documentation;
+pub mod unicode;
+pub mod trivia;
 pub mod binary;
+#[cfg(feature = "compression")]
+pub mod compression;
 pub mod error;
 pub mod dependency;
 pub mod metadata;
@@ -21,18 +29,26 @@ pub mod versioning;
 pub mod signature;
 pub mod minimal_ast;
 pub mod semantic_ast;
+pub mod fuzz;
+pub mod visit_spans;
 
 #[cfg(test)]
 mod binary_tests;
 
 // Re-export core types
+pub use arena::AstArena;
 pub use ast::*;
 pub use lexer::Lexer;
-pub use parser::Parser;
+pub use parser::{parse_items_at, Parser, RecoveredParse};
 pub use crate::span::{Span, FileId};
+pub use crate::source_map::{SourceFile, SourceMap};
 pub use crate::symbol::Symbol;
 pub use token::{Token, TokenKind};
+pub use trivia::{Comment, Trivia};
 pub use error::{ParseError, Result};
+pub use cancellation::{CancellationToken, Cancelled};
+pub use visit_spans::VisitSpans;
+pub use documentation::{Deprecation, DocAttributeWarning, DOC_ATTRIBUTES};
 
 /// Parse source code in the specified syntax style
 pub fn parse_source(source: &str, file_id: FileId, _syntax_style: SyntaxStyle) -> Result<CompilationUnit> {
@@ -41,6 +57,21 @@ pub fn parse_source(source: &str, file_id: FileId, _syntax_style: SyntaxStyle) -
     parser.parse()
 }
 
+/// Parse source code, reporting every syntax error alongside a partial AST
+pub fn parse_source_recovering(source: &str, file_id: FileId, _syntax_style: SyntaxStyle) -> Result<RecoveredParse> {
+    Parser::new(source, file_id)?.parse_recovering()
+}
+
+/// Parse source code, aborting with `ParseError::Cancelled` once `token` is cancelled
+pub fn parse_source_cancellable(
+    source: &str,
+    file_id: FileId,
+    _syntax_style: SyntaxStyle,
+    token: &CancellationToken,
+) -> Result<CompilationUnit> {
+    parser::parse_cancellable(source, file_id, token)
+}
+
 /// Syntax styles supported by the parser
 #[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 #[derive(Default)]
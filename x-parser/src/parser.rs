@@ -4,12 +4,18 @@
 
 use crate::{
     ast::*,
-    span::{Span, FileId},
+    span::{CharOffset, Span, FileId},
     token::{Token, TokenKind},
-    symbol::Symbol,
+    symbol::{symbols, Symbol},
     lexer::Lexer,
+    trivia::Trivia,
     error::{ParseError as Error, Result},
+    cancellation::CancellationToken,
+    arena::ParseStorage,
+    documentation::parse_attribute,
 };
+use std::collections::HashMap;
+use std::mem::size_of;
 
 /// Parser state
 #[allow(dead_code)]
@@ -17,19 +23,114 @@ pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
     file_id: FileId,
+    cancellation: CancellationToken,
+    /// Whether syntax errors are collected instead of aborting the parse
+    recovering: bool,
+    errors: Vec<Error>,
+    /// Comments and blank lines from the lexer, plus the item extents
+    trivia: Trivia,
+    /// Storage of an earlier parse, reused before allocating
+    storage: ParseStorage,
+    /// Bytes of characters, tokens and expression nodes the parse may take
+    memory_limit: Option<usize>,
+    memory_used: usize,
+    /// Fixities of the operators declared in the input, found before
+    /// parsing so uses may come first, and of those imported
+    fixities: HashMap<Symbol, Fixity>,
+}
+
+/// A partial AST together with every syntax error found on the way
+#[derive(Debug)]
+pub struct RecoveredParse {
+    pub ast: CompilationUnit,
+    pub errors: Vec<Error>,
+}
+
+impl RecoveredParse {
+    pub fn has_errors(&self) -> bool {
+        !self.errors.is_empty()
+    }
 }
 
 impl Parser {
     /// Create a new parser from source code
     pub fn new(input: &str, file_id: FileId) -> Result<Self> {
-        let mut lexer = Lexer::new(input, file_id);
-        let tokens = lexer.tokenize()?;
-        
-        Ok(Parser {
+        Self::with_storage(input, file_id, 0, ParseStorage::default(), None)
+    }
+    
+    /// Create a parser taking its buffers and nodes from `storage`, and
+    /// failing with `ParseError::MemoryLimit` past `memory_limit` bytes
+    pub(crate) fn with_storage(
+        input: &str,
+        file_id: FileId,
+        base: usize,
+        mut storage: ParseStorage,
+        memory_limit: Option<usize>,
+    ) -> Result<Self> {
+        let mut lexer = Lexer::with_storage(
+            input,
+            file_id,
+            std::mem::take(&mut storage.chars),
+            std::mem::take(&mut storage.text),
+        ).at_offset(base);
+        let mut tokens = std::mem::take(&mut storage.tokens);
+        tokens.clear();
+        lexer.tokenize_into(&mut tokens)?;
+        let trivia = lexer.take_trivia();
+        (storage.chars, storage.text) = lexer.into_storage();
+        let fixities = declared_fixities(&tokens);
+        
+        let mut parser = Parser {
             tokens,
             current: 0,
             file_id,
-        })
+            cancellation: CancellationToken::new(),
+            recovering: false,
+            errors: Vec::new(),
+            trivia,
+            storage,
+            memory_limit,
+            memory_used: 0,
+            fixities,
+        };
+        parser.charge(parser.storage.chars.len() * size_of::<char>() + parser.tokens.len() * size_of::<Token>())?;
+        Ok(parser)
+    }
+    
+    /// Swap the parser's spare boxes and lists with those of `nodes`
+    pub(crate) fn swap_nodes(&mut self, nodes: &mut ParseStorage) {
+        std::mem::swap(&mut self.storage.boxes, &mut nodes.boxes);
+        std::mem::swap(&mut self.storage.lists, &mut nodes.lists);
+    }
+    
+    /// Give back the parse's buffers, with the text of its tokens emptied
+    /// for reuse
+    pub(crate) fn into_storage(mut self) -> ParseStorage {
+        for token in self.tokens.drain(..) {
+            if let TokenKind::Ident(mut text) | TokenKind::String(mut text) | TokenKind::Number(mut text)
+                | TokenKind::Operator(mut text) = token.kind
+            {
+                text.clear();
+                self.storage.text.push(text);
+            }
+        }
+        self.storage.tokens = self.tokens;
+        self.storage
+    }
+    
+    /// Attach a cancellation token that is polled between items and expressions
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = token;
+        self
+    }
+    
+    /// Parse uses of operators declared elsewhere, such as in imported
+    /// modules, with the given fixities; declarations in the input win
+    pub fn with_fixities(mut self, fixities: impl IntoIterator<Item = (Symbol, Fixity)>) -> Self {
+        for (operator, fixity) in fixities {
+            self.fixities.entry(operator).or_insert(fixity);
+        }
+        self
     }
     
     /// Parse a complete compilation unit
@@ -44,6 +145,32 @@ impl Parser {
         })
     }
     
+    /// Parse a compilation unit, collecting syntax errors instead of
+    /// stopping at the first one
+    ///
+    /// A broken item is skipped up to the next item keyword, and a definition
+    /// whose body fails to parse keeps an `Expr::Error` body. Only a bad module
+    /// header and fatal errors such as cancellation abort the parse.
+    pub fn parse_recovering(&mut self) -> Result<RecoveredParse> {
+        self.recovering = true;
+        let ast = self.parse()?;
+        Ok(RecoveredParse {
+            ast,
+            errors: std::mem::take(&mut self.errors),
+        })
+    }
+    
+    /// Comments, blank lines and item extents of the source; the extents are
+    /// complete once `parse` has run
+    pub fn trivia(&self) -> &Trivia {
+        &self.trivia
+    }
+    
+    /// Take the source's trivia, leaving an empty table behind
+    pub fn take_trivia(&mut self) -> Trivia {
+        std::mem::take(&mut self.trivia)
+    }
+    
     /// Parse a single expression (public for testing)
     pub fn parse_expression_public(&mut self) -> Result<Expr> {
         self.parse_expression()
@@ -66,27 +193,17 @@ impl Parser {
         
         // Parse imports
         let mut imports = Vec::new();
-        while self.check(&TokenKind::Import) {
+        while self.check(&TokenKind::Import) || self.at_lazy_import() {
             imports.push(self.parse_import()?);
         }
         
-        // Parse module items
-        let mut items = Vec::new();
-        while !self.is_at_end() {
-            // Skip standalone doc comments at module level
-            if matches!(self.current_token().kind, TokenKind::DocComment(_)) {
-                self.advance();
-                continue;
-            }
-            
-            items.push(self.parse_item()?);
-        }
-        
+        let documentation = self.module_documentation(self.current_span().start);
+        let items = self.parse_items(CharOffset::new(u32::MAX))?;
         let end_span = self.current_span();
         
         Ok(Module {
             name: module_path,
-            documentation: None, // TODO: Parse module doc comments
+            documentation,
             exports,
             imports,
             items,
@@ -94,6 +211,56 @@ impl Parser {
         })
     }
     
+    /// Module documentation, written in `--!` comments on lines of their
+    /// own before `before`, the first item
+    fn module_documentation(&self, before: CharOffset) -> Option<Documentation> {
+        let lines: Vec<_> = self.trivia.comments.iter()
+            .take_while(|comment| comment.span.start < before)
+            .filter(|comment| !comment.is_trailing() && comment.text.starts_with('!'))
+            .collect();
+        let span = lines.first()?.span.merge(lines.last()?.span);
+        let content: Vec<&str> = lines.iter()
+            .map(|comment| comment.text[1..].strip_prefix(' ').unwrap_or(&comment.text[1..]))
+            .collect();
+        Some(Documentation {
+            doc_comment: self.parse_doc_comment_content(&content.join("\n"), span),
+            inline_comments: Vec::new(),
+            is_module_doc: true,
+        })
+    }
+    
+    /// Parse module items up to the end of the input or the first token at
+    /// or after `stop`, recording their extents in the trivia
+    fn parse_items(&mut self, stop: CharOffset) -> Result<Vec<Item>> {
+        let mut items = Vec::new();
+        let mut documentation_start = None;
+        while !self.is_at_end() && self.current_span().start < stop {
+            // Skip standalone doc comments at module level
+            if matches!(self.current_token().kind, TokenKind::DocComment(_)) {
+                documentation_start.get_or_insert(self.current_span());
+                self.advance();
+                continue;
+            }
+            
+            let item_start = self.current;
+            let first_span = documentation_start.take().unwrap_or_else(|| self.current_span());
+            match self.parse_item() {
+                Ok(item) => {
+                    self.trivia.item_extents.push(first_span.merge(self.previous().span));
+                    items.push(item);
+                }
+                Err(error) => {
+                    self.recover(error)?;
+                    if self.current == item_start {
+                        self.advance();
+                    }
+                    self.synchronize();
+                }
+            }
+        }
+        Ok(items)
+    }
+    
     /// Parse module path (e.g., Core.Types.User)
     fn parse_module_path(&mut self) -> Result<ModulePath> {
         let start_span = self.current_span();
@@ -149,7 +316,7 @@ impl Parser {
             ExportKind::Value
         };
         
-        let name = self.parse_identifier()?;
+        let name = self.parse_value_name()?;
         let alias = if self.match_token(&TokenKind::LeftParen) {
             let alias = self.parse_identifier()?;
             self.expect(TokenKind::RightParen)?;
@@ -219,6 +386,9 @@ impl Parser {
             ImportKind::Selective(items)
         } else if self.match_token(&TokenKind::Dot) && self.match_token(&TokenKind::Star) {
             ImportKind::Wildcard
+        } else if self.match_token(&TokenKind::Colon) {
+            // Seen only through a module type: `import Plugins.Math : Plugin`
+            ImportKind::Signature(self.parse_identifier()?)
         } else {
             ImportKind::Qualified
         };
@@ -229,6 +399,13 @@ impl Parser {
             None
         };
         
+        // Only for some targets or features: `import Node.Fs as Fs when target == "typescript"`
+        let kind = if kind == ImportKind::Qualified && self.match_ident("when") {
+            ImportKind::Conditional(Box::new(self.parse_expression()?))
+        } else {
+            kind
+        };
+        
         let end_span = self.current_span();
         
         Ok(Import {
@@ -252,7 +429,7 @@ impl Parser {
             ExportKind::Value
         };
         
-        let name = self.parse_identifier()?;
+        let name = self.parse_value_name()?;
         
         // Parse optional version specification (e.g., @^1.0.0)
         let version_spec = if self.match_token(&TokenKind::At) {
@@ -272,7 +449,7 @@ impl Parser {
         };
         
         let alias = if self.match_ident("as") {
-            Some(self.parse_identifier()?)
+            Some(self.parse_value_name()?)
         } else {
             None
         };
@@ -290,12 +467,23 @@ impl Parser {
     
     /// Parse top-level item
     fn parse_item(&mut self) -> Result<Item> {
+        self.cancellation.check()?;
+        
+        if self.check(&TokenKind::Hash) {
+            return self.parse_conditional_item();
+        }
+        if self.at_fixity_decl() {
+            return Ok(Item::Fixity(self.parse_fixity_decl()?));
+        }
+        
         // Parse visibility modifier first
         let visibility = self.parse_visibility()?;
         
         
         if self.check(&TokenKind::Test) {
             Ok(Item::TestDef(self.parse_test_def_with_visibility(visibility)?))
+        } else if self.check(&TokenKind::Bench) {
+            Ok(Item::BenchDef(self.parse_bench_def(visibility)?))
         } else if self.check(&TokenKind::Interface) {
             Ok(Item::InterfaceDef(self.parse_interface_def(visibility)?))
         } else if self.check(&TokenKind::Data) {
@@ -304,17 +492,119 @@ impl Parser {
             Ok(Item::TypeDef(self.parse_type_alias_with_visibility(visibility)?))
         } else if self.check(&TokenKind::Effect) {
             Ok(Item::EffectDef(self.parse_effect_def_with_visibility(visibility)?))
-        } else if self.check(&TokenKind::Handler) {
+        } else if self.check(&TokenKind::Handler) || self.at_shallow_handler() {
             Ok(Item::HandlerDef(self.parse_handler_def_with_visibility(visibility)?))
-        } else if self.check(&TokenKind::Let) {
+        } else if self.check(&TokenKind::Class) {
+            Ok(Item::ClassDef(self.parse_class_def_with_visibility(visibility)?))
+        } else if self.check(&TokenKind::Instance) {
+            Ok(Item::InstanceDef(self.parse_instance_def()?))
+        } else if self.check(&TokenKind::Let) || self.at_pure_let() {
             Ok(Item::ValueDef(self.parse_value_def_with_visibility(visibility)?))
+        } else if self.at_module_type() {
+            Ok(Item::ModuleTypeDef(self.parse_module_type_def_with_visibility(visibility)?))
         } else {
             return Err(Error::Parse {
-                message: "Expected item declaration (let, data, type, effect, handler, interface, or test)".to_string(),
+                message: "Expected item declaration (let, data, type, effect, handler, class, instance, interface, module type, test, bench, or fixity)".to_string(),
             });
         }
     }
     
+    /// Parse `#[cfg(...)]` and the item it applies to; the item's doc
+    /// comment may come before or after the attribute
+    fn parse_conditional_item(&mut self) -> Result<Item> {
+        let start_span = self.current_span();
+        self.expect(TokenKind::Hash)?;
+        self.expect(TokenKind::LeftBracket)?;
+        if !matches!(self.current(), TokenKind::Ident(name) if name == "cfg") {
+            return self.error("Expected `cfg`, the only item attribute");
+        }
+        self.advance();
+        self.expect(TokenKind::LeftParen)?;
+        let condition = self.parse_cfg()?;
+        self.expect(TokenKind::RightParen)?;
+        self.expect(TokenKind::RightBracket)?;
+        while matches!(self.current(), TokenKind::DocComment(_)) {
+            self.advance();
+        }
+        
+        let item = self.parse_item()?;
+        let span = start_span.merge(item.span());
+        Ok(Item::Conditional(ConditionalItem { condition, item: Box::new(item), span }))
+    }
+    
+    /// Parse a `cfg` condition: `key = "value"`, or `all`, `any` or `not`
+    /// applied to conditions
+    fn parse_cfg(&mut self) -> Result<Cfg> {
+        let key = self.parse_identifier()?;
+        if self.check(&TokenKind::Equal) {
+            self.advance();
+            let TokenKind::String(value) = self.current().clone() else {
+                return self.error(&format!("Expected a string after `{key} =`"));
+            };
+            self.advance();
+            return Ok(Cfg::Equals { key, value });
+        }
+        
+        self.expect(TokenKind::LeftParen)?;
+        let mut cfgs = Vec::new();
+        while !self.check(&TokenKind::RightParen) {
+            cfgs.push(self.parse_cfg()?);
+            if !self.check(&TokenKind::RightParen) {
+                self.expect(TokenKind::Comma)?;
+            }
+        }
+        self.expect(TokenKind::RightParen)?;
+        match key.as_str() {
+            "all" => Ok(Cfg::All(cfgs)),
+            "any" => Ok(Cfg::Any(cfgs)),
+            "not" => match <[Cfg; 1]>::try_from(cfgs) {
+                Ok([cfg]) => Ok(Cfg::Not(Box::new(cfg))),
+                Err(_) => self.error("`not` takes one condition"),
+            },
+            _ => self.error(&format!("Unknown cfg predicate `{key}`; expected `all`, `any` or `not`")),
+        }
+    }
+    
+    /// Parse a fixity declaration, `infixl 6 <+> <->`
+    fn parse_fixity_decl(&mut self) -> Result<FixityDecl> {
+        let start_span = self.current_span();
+        let associativity = match self.current() {
+            TokenKind::Ident(keyword) => fixity_associativity(keyword),
+            _ => None,
+        };
+        let Some(associativity) = associativity else {
+            return self.error("Expected infixl, infixr or infix");
+        };
+        self.advance();
+        
+        let precedence = match self.current() {
+            TokenKind::Number(digits) => digits.parse::<u8>().ok().filter(|&precedence| precedence <= Fixity::MAX_PRECEDENCE),
+            _ => None,
+        };
+        let Some(precedence) = precedence else {
+            return self.error(&format!("Expected a precedence from 0 to {}", Fixity::MAX_PRECEDENCE));
+        };
+        self.advance();
+        
+        let mut operators = Vec::new();
+        while let Some(kind) = self.current().is_operator().then(|| self.current().clone()) {
+            let TokenKind::Operator(operator) = kind else {
+                return self.error(&format!("`{kind}` is a built-in operator with a fixed precedence"));
+            };
+            operators.push(Symbol::intern(&operator));
+            self.advance();
+        }
+        if operators.is_empty() {
+            return self.error("Expected an operator to declare the fixity of");
+        }
+        
+        Ok(FixityDecl {
+            fixity: Fixity { associativity, precedence },
+            operators,
+            span: start_span.merge(self.previous().span),
+        })
+    }
+    
     /// Parse visibility modifier
     fn parse_visibility(&mut self) -> Result<Visibility> {
         if !self.check(&TokenKind::Pub) {
@@ -333,9 +623,8 @@ impl Parser {
                 Visibility::Super
             } else if self.match_token(&TokenKind::Self_) {
                 Visibility::SelfModule
-            } else if self.check(&TokenKind::Ident(String::new())) {
+            } else if self.match_token(&TokenKind::In) {
                 // pub(in path)
-                self.expect(TokenKind::Ident("in".to_string()))?;
                 let path = self.parse_module_path()?;
                 Visibility::InPath(path)
             } else {
@@ -424,15 +713,22 @@ impl Parser {
         
         let mut fields = Vec::new();
         while !self.check(&TokenKind::Pipe) && !self.is_at_end() && 
-              !self.check(&TokenKind::RightBrace) && !self.check(&TokenKind::Eof) {
+              !self.check(&TokenKind::RightBrace) && !self.check(&TokenKind::Eof) &&
+              !self.check(&TokenKind::Colon) && !self.at_item_start() {
             fields.push(self.parse_type()?);
         }
+        let result = if self.match_token(&TokenKind::Colon) {
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
         
         let end_span = self.current_span();
         
         Ok(Constructor {
             name,
             fields,
+            result,
             span: start_span.merge(end_span),
         })
     }
@@ -472,6 +768,169 @@ impl Parser {
         self.parse_effect_def_with_visibility(Visibility::Private)
     }
     
+    /// Parse type class declaration: `class Ord[a] with Eq[a] { compare : a -> a -> Int }`
+    fn parse_class_def_with_visibility(&mut self, visibility: Visibility) -> Result<ClassDef> {
+        let documentation = self.collect_doc_comments();
+        let start_span = self.current_span();
+        self.expect(TokenKind::Class)?;
+        
+        let name = self.parse_identifier()?;
+        let type_params = self.parse_type_params()?;
+        let superclasses = self.parse_class_context()?;
+        
+        self.expect(TokenKind::LeftBrace)?;
+        
+        let mut methods = Vec::new();
+        while !self.check(&TokenKind::RightBrace) {
+            let method_start = self.current_span();
+            let method_name = self.parse_identifier()?;
+            self.expect(TokenKind::Colon)?;
+            let type_annotation = self.parse_signature_type()?;
+            methods.push(ClassMethod {
+                name: method_name,
+                type_annotation,
+                span: method_start.merge(self.current_span()),
+            });
+        }
+        
+        self.expect(TokenKind::RightBrace)?;
+        let end_span = self.current_span();
+        
+        Ok(ClassDef {
+            name,
+            documentation,
+            type_params,
+            superclasses,
+            methods,
+            visibility,
+            span: start_span.merge(end_span),
+        })
+    }
+    
+    /// Parse module type declaration: `module type Plugin { type t  make : Int -> t }`
+    fn parse_module_type_def_with_visibility(&mut self, visibility: Visibility) -> Result<ModuleTypeDef> {
+        let start_span = self.current_span();
+        self.expect(TokenKind::Module)?;
+        self.expect(TokenKind::Type)?;
+        
+        let name = self.parse_identifier()?;
+        
+        let signature_start = self.current_span();
+        self.expect(TokenKind::LeftBrace)?;
+        
+        let mut items = Vec::new();
+        while !self.check(&TokenKind::RightBrace) && !self.is_at_end() {
+            let item_start = self.current_span();
+            if self.match_token(&TokenKind::Type) {
+                let type_name = self.parse_identifier()?;
+                let type_params = self.parse_type_params()?;
+                items.push(SignatureItem::TypeSig {
+                    name: type_name,
+                    type_params,
+                    kind: None,
+                    span: item_start.merge(self.current_span()),
+                });
+            } else {
+                let value_name = self.parse_identifier()?;
+                self.expect(TokenKind::Colon)?;
+                let type_annotation = self.parse_signature_type()?;
+                items.push(SignatureItem::ValueSig {
+                    name: value_name,
+                    type_annotation,
+                    span: item_start.merge(self.current_span()),
+                });
+            }
+        }
+        
+        self.expect(TokenKind::RightBrace)?;
+        let end_span = self.current_span();
+        
+        Ok(ModuleTypeDef {
+            name,
+            signature: ModuleSignature { items, span: signature_start.merge(end_span) },
+            visibility,
+            span: start_span.merge(end_span),
+        })
+    }
+    
+    /// Parse instance declaration: `instance Eq[List[a]] with Eq[a] { let eq = ... }`
+    fn parse_instance_def(&mut self) -> Result<InstanceDef> {
+        let start_span = self.current_span();
+        self.expect(TokenKind::Instance)?;
+        
+        let head = self.parse_class_constraint()?;
+        let constraints = self.parse_class_context()?;
+        
+        self.expect(TokenKind::LeftBrace)?;
+        
+        let mut methods = Vec::new();
+        while !self.check(&TokenKind::RightBrace) {
+            methods.push(self.parse_value_def_with_visibility(Visibility::Public)?);
+        }
+        
+        self.expect(TokenKind::RightBrace)?;
+        let end_span = self.current_span();
+        
+        Ok(InstanceDef {
+            class: head.class,
+            types: head.types,
+            constraints,
+            methods,
+            span: start_span.merge(end_span),
+        })
+    }
+    
+    /// Parse an optional `with C1[a], C2[b]` context
+    fn parse_class_context(&mut self) -> Result<Vec<TypeConstraint>> {
+        let mut constraints = Vec::new();
+        if self.match_token(&TokenKind::With) {
+            loop {
+                constraints.push(self.parse_class_constraint()?);
+                if !self.match_token(&TokenKind::Comma) {
+                    break;
+                }
+            }
+        }
+        Ok(constraints)
+    }
+    
+    /// Parse a class constraint: `Eq[a]`
+    fn parse_class_constraint(&mut self) -> Result<TypeConstraint> {
+        let start_span = self.current_span();
+        let class = self.parse_identifier()?;
+        
+        self.expect(TokenKind::LeftBracket)?;
+        let mut types = vec![self.parse_type()?];
+        while self.match_token(&TokenKind::Comma) {
+            types.push(self.parse_type()?);
+        }
+        self.expect(TokenKind::RightBracket)?;
+        
+        Ok(TypeConstraint {
+            class,
+            types,
+            span: start_span.merge(self.current_span()),
+        })
+    }
+    
+    /// Parse a curried signature `t1 -> t2 -> ret` into nested function types
+    fn parse_signature_type(&mut self) -> Result<Type> {
+        let start_span = self.current_span();
+        let param = self.parse_type()?;
+        if !self.match_token(&TokenKind::Arrow) {
+            return Ok(param);
+        }
+        
+        let return_type = self.parse_signature_type()?;
+        let span = start_span.merge(self.current_span());
+        Ok(Type::Fun {
+            params: vec![param],
+            return_type: Box::new(return_type),
+            effects: EffectSet { effects: Vec::new(), row_var: None, span },
+            span,
+        })
+    }
+    
     /// Parse effect operation
     fn parse_effect_operation(&mut self) -> Result<EffectOperation> {
         let start_span = self.current_span();
@@ -521,31 +980,105 @@ impl Parser {
         }
     }
     
-    /// Parse handler definition with visibility
+    /// Parse handler definition with visibility:
+    /// `handler name { | State.get with k => k 0 | return x => x }`, deep
+    /// unless it starts with `shallow`
     fn parse_handler_def_with_visibility(&mut self, visibility: Visibility) -> Result<HandlerDef> {
         let start_span = self.current_span();
+        let mode = if self.match_ident("shallow") { HandlerMode::Shallow } else { HandlerMode::Deep };
         self.expect(TokenKind::Handler)?;
         
         let name = self.parse_identifier()?;
+        self.expect(TokenKind::LeftBrace)?;
         
-        // Simplified handler parsing - just skip to end for now
-        while !self.is_at_end() && !self.check(&TokenKind::Let) && !self.check(&TokenKind::Data) && !self.check(&TokenKind::Pub) {
-            self.advance();
+        let mut handlers: Vec<EffectHandler> = Vec::new();
+        let mut return_clause = None;
+        while !self.is_at_end() && !self.check(&TokenKind::RightBrace) {
+            // Skip optional leading pipe
+            self.match_token(&TokenKind::Pipe);
+            
+            let clause_start = self.current_span();
+            if self.match_token(&TokenKind::Return) {
+                if return_clause.is_some() {
+                    return Err(Error::Parse {
+                        message: format!("Handler {name} has more than one return clause"),
+                    });
+                }
+                let parameter = self.parse_or_pattern()?;
+                self.expect(TokenKind::FatArrow)?;
+                let body = self.parse_expression()?;
+                let span = clause_start.merge(body.span());
+                return_clause = Some(ReturnClause { parameter, body: Box::new(body), span });
+            } else {
+                handlers.push(self.parse_operation_clause()?);
+            }
         }
         
+        self.expect(TokenKind::RightBrace)?;
         let end_span = self.current_span();
         
+        // The effects handled, in the order their first clause appears
+        let mut handled_effects: Vec<EffectRef> = Vec::new();
+        for handler in &handlers {
+            if !handled_effects.iter().any(|effect| effect.name == handler.effect.name) {
+                handled_effects.push(handler.effect.clone());
+            }
+        }
+        
         Ok(HandlerDef {
             name,
+            mode,
             type_annotation: None,
-            handled_effects: Vec::new(),
-            handlers: Vec::new(),
-            return_clause: None,
+            handled_effects,
+            handlers,
+            return_clause,
             visibility,
             span: start_span.merge(end_span),
         })
     }
     
+    /// Parse an operation clause of a handler: `State.put s with k => body`
+    ///
+    /// Without a `with` binder the clause has no continuation of its own and
+    /// resumes through `resume`.
+    fn parse_operation_clause(&mut self) -> Result<EffectHandler> {
+        let start_span = self.current_span();
+        let effect = self.parse_identifier()?;
+        self.expect(TokenKind::Dot)?;
+        let operation = self.parse_identifier()?;
+        
+        let mut parameters = Vec::new();
+        while self.can_start_pattern() {
+            if let TokenKind::Ident(name) = self.current() {
+                let name = Symbol::intern(name);
+                let span = self.current_span();
+                self.advance();
+                parameters.push(Pattern::Variable(name, span));
+            } else {
+                parameters.push(self.parse_pattern()?);
+            }
+        }
+        
+        let continuation = if self.match_token(&TokenKind::With) {
+            Some(self.parse_identifier()?)
+        } else {
+            None
+        };
+        
+        self.expect(TokenKind::FatArrow)?;
+        let body = self.parse_expression()?;
+        let span = start_span.merge(body.span());
+        
+        Ok(EffectHandler {
+            effect: EffectRef { name: effect, args: Vec::new(), span: start_span },
+            operation,
+            parameters,
+            continuation,
+            body,
+            span,
+        })
+    }
+    
     /// Parse handler definition (backward compatibility)
     #[allow(dead_code)]
     fn parse_handler_def(&mut self) -> Result<HandlerDef> {
@@ -556,19 +1089,28 @@ impl Parser {
     fn parse_value_def_with_visibility(&mut self, visibility: Visibility) -> Result<ValueDef> {
         let documentation = self.collect_doc_comments();
         let start_span = self.current_span();
+        let purity = if self.match_token(&TokenKind::Pure) { Purity::Pure } else { Purity::Inferred };
         self.expect(TokenKind::Let)?;
         
-        let name = self.parse_identifier()?;
+        let name = self.parse_value_name()?;
         
         // Parse optional type annotation
         let type_annotation = if self.match_token(&TokenKind::Colon) {
-            Some(self.parse_type()?)
+            Some(self.parse_signature_type()?)
         } else {
             None
         };
         
         self.expect(TokenKind::Equal)?;
-        let body = self.parse_expression()?;
+        let body_start = self.current_span();
+        let body = match self.parse_expression() {
+            Ok(body) => body,
+            Err(error) => {
+                self.recover(error)?;
+                self.synchronize();
+                Expr::Error(body_start.merge(self.previous().span))
+            }
+        };
         
         let end_span = self.current_span();
         
@@ -579,7 +1121,7 @@ impl Parser {
             parameters: Vec::new(), // Simplified for now
             body,
             visibility,
-            purity: Purity::Inferred,
+            purity,
             imports: Vec::new(),
             span: start_span.merge(end_span),
         })
@@ -719,26 +1261,92 @@ impl Parser {
         })
     }
     
-    /// Parse component interface definition
-    fn parse_interface_def(&mut self, _visibility: Visibility) -> Result<ComponentInterface> {
+    /// Parse benchmark definition: `bench "name" with iterations = N, warmup = N { body }`
+    fn parse_bench_def(&mut self, visibility: Visibility) -> Result<BenchDef> {
+        let documentation = self.collect_doc_comments();
         let start_span = self.current_span();
-        self.expect(TokenKind::Interface)?;
+        self.expect(TokenKind::Bench)?;
         
-        // Parse interface name (e.g., "wasi:io/poll@0.2.0")
-        let name = match &self.current_token().kind {
+        let (name, description) = match &self.current_token().kind {
             TokenKind::String(s) => {
-                let name = s.clone();
+                let desc = s.clone();
                 self.advance();
-                name
+                (Symbol::intern(&desc.replace(' ', "_")), Some(desc))
+            }
+            TokenKind::Ident(s) => {
+                let name = Symbol::intern(s);
+                self.advance();
+                (name, None)
             }
             _ => return Err(Error::Parse {
-                message: "Expected interface name string".to_string(),
+                message: "Expected benchmark name (string or identifier)".to_string(),
             }),
         };
         
-        // Parse optional version
-        let version = None; // TODO: implement version parsing
-        
+        let mut iterations = None;
+        let mut warmup = None;
+        if self.match_token(&TokenKind::With) {
+            loop {
+                let count = if self.match_ident("iterations") {
+                    &mut iterations
+                } else if self.match_ident("warmup") {
+                    &mut warmup
+                } else {
+                    break;
+                };
+                self.expect(TokenKind::Equal)?;
+                match &self.current_token().kind {
+                    TokenKind::Number(n) if n.parse::<u64>().is_ok() => {
+                        *count = n.parse().ok();
+                        self.advance();
+                    }
+                    _ => return Err(Error::Parse {
+                        message: "Expected a non-negative count".to_string(),
+                    }),
+                }
+                if !self.match_token(&TokenKind::Comma) {
+                    break;
+                }
+            }
+        }
+        
+        self.expect(TokenKind::LeftBrace)?;
+        let body = self.parse_expression()?;
+        self.expect(TokenKind::RightBrace)?;
+        let end_span = self.current_span();
+        
+        Ok(BenchDef {
+            name,
+            documentation,
+            description,
+            body,
+            iterations,
+            warmup,
+            visibility,
+            span: start_span.merge(end_span),
+        })
+    }
+    
+    /// Parse component interface definition
+    fn parse_interface_def(&mut self, _visibility: Visibility) -> Result<ComponentInterface> {
+        let start_span = self.current_span();
+        self.expect(TokenKind::Interface)?;
+        
+        // Parse interface name (e.g., "wasi:io/poll@0.2.0")
+        let name = match &self.current_token().kind {
+            TokenKind::String(s) => {
+                let name = s.clone();
+                self.advance();
+                name
+            }
+            _ => return Err(Error::Parse {
+                message: "Expected interface name string".to_string(),
+            }),
+        };
+        
+        // Parse optional version
+        let version = None; // TODO: implement version parsing
+        
         // Parse interface items
         self.expect(TokenKind::LeftBrace)?;
         let mut items = Vec::new();
@@ -912,8 +1520,21 @@ impl Parser {
                 Ok(Type::Con(Symbol::intern("Unit"), start_span))
             } else {
                 let inner_type = self.parse_type()?;
+                if !self.check(&TokenKind::Comma) {
+                    self.expect(TokenKind::RightParen)?;
+                    return Ok(inner_type);
+                }
+                
+                let mut types = vec![inner_type];
+                while self.match_token(&TokenKind::Comma) {
+                    types.push(self.parse_type()?);
+                }
+                let end_span = self.current_span();
                 self.expect(TokenKind::RightParen)?;
-                Ok(inner_type)
+                Ok(Type::Tuple {
+                    types,
+                    span: start_span.merge(end_span),
+                })
             }
         } else if self.match_token(&TokenKind::Forall) {
             // Forall type
@@ -930,6 +1551,29 @@ impl Parser {
         } else if self.match_token(&TokenKind::Question) {
             // Type hole
             Ok(Type::Hole(start_span))
+        } else if self.match_token(&TokenKind::LeftBrace) {
+            // Record type, open when it ends in `| r`
+            let mut fields = HashMap::new();
+            let mut rest = None;
+            while !self.check(&TokenKind::RightBrace) && !self.is_at_end() {
+                if self.match_token(&TokenKind::Pipe) {
+                    rest = Some(Box::new(self.parse_type()?));
+                    break;
+                }
+                let name = self.parse_identifier()?;
+                self.expect(TokenKind::Colon)?;
+                fields.insert(name, self.parse_type()?);
+                if !self.match_token(&TokenKind::Comma) && !self.check(&TokenKind::Pipe) {
+                    break;
+                }
+            }
+            let end_span = self.current_span();
+            self.expect(TokenKind::RightBrace)?;
+            Ok(Type::Record {
+                fields,
+                rest,
+                span: start_span.merge(end_span),
+            })
         } else {
             // Type constructor or variable
             let name = self.parse_identifier()?;
@@ -995,37 +1639,47 @@ impl Parser {
     
     /// Parse expression with precedence climbing
     fn parse_expression(&mut self) -> Result<Expr> {
+        self.cancellation.check()?;
         self.parse_binary_expression(0)
     }
     
     /// Parse binary expressions with precedence climbing
     fn parse_binary_expression(&mut self, min_precedence: u8) -> Result<Expr> {
         let mut left = self.parse_application()?;
+        // Precedence of a non-associative operator just applied, which no
+        // operator of the same precedence may follow
+        let mut non_associative = None;
         
         while !self.is_at_end() {
             let token_kind = self.current_token().kind.clone();
             
             // Check if current token is a binary operator
-            if let Some(precedence) = token_kind.precedence() {
+            if let Some(fixity) = self.fixity_of(&token_kind) {
+                let precedence = fixity.precedence;
                 if precedence < min_precedence {
                     break;
                 }
+                if non_associative == Some(precedence) {
+                    return self.error(&format!(
+                        "`{token_kind}` cannot follow a non-associative operator of the same precedence; add parentheses"
+                    ));
+                }
                 
                 let operator = token_kind.clone();
                 self.advance(); // consume operator
                 
-                let right_precedence = if token_kind.is_left_associative() {
-                    precedence + 1
-                } else {
-                    precedence
+                let right_precedence = match fixity.associativity {
+                    Associativity::Right => precedence,
+                    Associativity::Left | Associativity::None => precedence + 1,
                 };
                 
                 let right = self.parse_binary_expression(right_precedence)?;
+                non_associative = (fixity.associativity == Associativity::None).then_some(precedence);
                 
                 // For all operators, create function application
                 let span = left.span().merge(right.span());
-                let op_var = Expr::Var(self.operator_to_symbol(&operator), span);
-                left = Expr::App(Box::new(op_var), vec![left, right], span);
+                let op_var = self.boxed(Expr::Var(self.operator_to_symbol(&operator), span))?;
+                left = Expr::App(op_var, self.list([left, right])?, span);
             } else {
                 break;
             }
@@ -1034,23 +1688,38 @@ impl Parser {
         Ok(left)
     }
     
+    /// Fixity of `operator` if it is a binary operator: declared for user
+    /// operators, defaulting to `Fixity::DEFAULT`, and fixed for the rest
+    fn fixity_of(&self, operator: &TokenKind) -> Option<Fixity> {
+        if let TokenKind::Operator(operator) = operator {
+            return Some(self.fixities.get(&Symbol::intern(operator)).copied().unwrap_or(Fixity::DEFAULT));
+        }
+        let associativity = if operator.is_left_associative() { Associativity::Left } else { Associativity::Right };
+        operator.precedence().map(|precedence| Fixity { associativity, precedence })
+    }
+    
     /// Convert operator token to symbol
     fn operator_to_symbol(&self, operator: &TokenKind) -> Symbol {
         match operator {
-            TokenKind::Plus => Symbol::intern("+"),
-            TokenKind::Minus => Symbol::intern("-"),
-            TokenKind::Star => Symbol::intern("*"),
-            TokenKind::Slash => Symbol::intern("/"),
-            TokenKind::EqualEqual => Symbol::intern("=="),
-            TokenKind::NotEqual => Symbol::intern("!="),
-            TokenKind::Less => Symbol::intern("<"),
-            TokenKind::LessEqual => Symbol::intern("<="),
-            TokenKind::Greater => Symbol::intern(">"),
-            TokenKind::GreaterEqual => Symbol::intern(">="),
-            TokenKind::AndAnd | TokenKind::And => Symbol::intern("&&"),
-            TokenKind::OrOr | TokenKind::Or => Symbol::intern("||"),
-            TokenKind::Cons => Symbol::intern("::"),
-            TokenKind::Caret => Symbol::intern("^"),
+            TokenKind::Plus => symbols::PLUS(),
+            TokenKind::Minus => symbols::MINUS(),
+            TokenKind::Star => symbols::MULTIPLY(),
+            TokenKind::Slash => symbols::DIVIDE(),
+            TokenKind::Percent => symbols::MODULO(),
+            TokenKind::EqualEqual => symbols::EQUAL_EQUAL(),
+            TokenKind::NotEqual => symbols::NOT_EQUAL(),
+            TokenKind::Less => symbols::LESS(),
+            TokenKind::LessEqual => symbols::LESS_EQUAL(),
+            TokenKind::Greater => symbols::GREATER(),
+            TokenKind::GreaterEqual => symbols::GREATER_EQUAL(),
+            TokenKind::AndAnd | TokenKind::And => symbols::AND(),
+            TokenKind::OrOr | TokenKind::Or => symbols::OR(),
+            TokenKind::Cons => symbols::CONS(),
+            TokenKind::Caret => symbols::CARET(),
+            TokenKind::PipeForward => symbols::PIPE_FORWARD(),
+            TokenKind::ComposeRight => symbols::COMPOSE_RIGHT(),
+            TokenKind::ComposeLeft => symbols::COMPOSE_LEFT(),
+            TokenKind::Operator(operator) => Symbol::intern(operator),
             _ => Symbol::intern("unknown_op"),
         }
     }
@@ -1063,7 +1732,7 @@ impl Parser {
         while !self.is_at_end() && self.can_start_atom() {
             let arg = self.parse_atom()?;
             let span = expr.span().merge(arg.span());
-            expr = Expr::App(Box::new(expr), vec![arg], span);
+            expr = Expr::App(self.boxed(expr)?, self.list([arg])?, span);
         }
         
         Ok(expr)
@@ -1071,7 +1740,7 @@ impl Parser {
     
     /// Parse atomic expressions
     fn parse_atom(&mut self) -> Result<Expr> {
-        if self.check(&TokenKind::LeftParen) {
+        let mut expr = if self.check(&TokenKind::LeftParen) {
             self.parse_parenthesized()
         } else if self.check(&TokenKind::If) {
             self.parse_if()
@@ -1079,9 +1748,65 @@ impl Parser {
             self.parse_lambda()
         } else if self.check(&TokenKind::Match) {
             self.parse_match()
+        } else if self.check(&TokenKind::LeftBrace) {
+            self.parse_record()
+        } else if self.check(&TokenKind::Resume) {
+            self.parse_resume()
+        } else if self.check(&TokenKind::Module) {
+            self.parse_pack()
         } else {
             self.parse_primary()
+        }?;
+        
+        // Field projection binds tighter than application: `f r.x`
+        while self.check(&TokenKind::Dot) && matches!(self.peek(), Some(TokenKind::Ident(_))) {
+            self.advance();
+            let end_span = self.current_span();
+            let field = self.parse_identifier()?;
+            let span = expr.span().merge(end_span);
+            expr = Expr::Project {
+                record: Box::new(expr),
+                field,
+                span,
+            };
+        }
+        
+        Ok(expr)
+    }
+    
+    /// Parse a record literal `{ x = 1, y = 2 }` or update `{ r with x = 3 }`
+    fn parse_record(&mut self) -> Result<Expr> {
+        let start_span = self.current_span();
+        self.expect(TokenKind::LeftBrace)?;
+        
+        let is_literal = self.check(&TokenKind::RightBrace)
+            || (matches!(self.current(), TokenKind::Ident(_)) && matches!(self.peek(), Some(TokenKind::Equal)));
+        let record = if is_literal {
+            None
+        } else {
+            let record = self.parse_expression()?;
+            self.expect(TokenKind::With)?;
+            Some(Box::new(record))
+        };
+        
+        let mut fields = Vec::new();
+        while !self.check(&TokenKind::RightBrace) && !self.is_at_end() {
+            let name = self.parse_identifier()?;
+            self.expect(TokenKind::Equal)?;
+            fields.push((name, self.parse_expression()?));
+            if !self.match_token(&TokenKind::Comma) {
+                break;
+            }
         }
+        
+        let end_span = self.current_span();
+        self.expect(TokenKind::RightBrace)?;
+        let span = start_span.merge(end_span);
+        
+        Ok(match record {
+            Some(record) => Expr::RecordUpdate { record, fields, span },
+            None => Expr::Record { fields, span },
+        })
     }
     
     /// Check if current token can start an atomic expression
@@ -1090,9 +1815,19 @@ impl Parser {
             TokenKind::LeftParen | TokenKind::Integer(_) | TokenKind::Float(_) |
             TokenKind::String(_) | TokenKind::Bool(_) | TokenKind::Ident(_) |
             TokenKind::Number(_) | TokenKind::If | TokenKind::Fun | TokenKind::Fn |
-            TokenKind::Match | TokenKind::Do | TokenKind::LeftBracket
+            TokenKind::Match | TokenKind::Do | TokenKind::LeftBracket | TokenKind::LeftBrace |
+            TokenKind::Underscore
             // Note: Removed Let - let expressions should be handled carefully to avoid confusion with top-level let definitions
-        )
+        ) && !self.at_fixity_decl()
+    }
+    
+    /// Parse `resume value`, continuing the computation an operation clause handles
+    fn parse_resume(&mut self) -> Result<Expr> {
+        let start_span = self.current_span();
+        self.expect(TokenKind::Resume)?;
+        let value = self.parse_atom()?;
+        let span = start_span.merge(value.span());
+        Ok(Expr::Resume { value: Box::new(value), span })
     }
     
     /// Parse parenthesized expressions
@@ -1103,6 +1838,13 @@ impl Parser {
         if self.match_token(&TokenKind::RightParen) {
             // Unit literal
             Ok(Expr::Literal(Literal::Unit, start_span))
+        } else if self.at_operator_name() {
+            // `(<+>)`, an operator used as a function
+            let operator = self.operator_to_symbol(self.current());
+            self.advance();
+            let end_span = self.current_span();
+            self.expect(TokenKind::RightParen)?;
+            Ok(Expr::Var(operator, start_span.merge(end_span)))
         } else {
             // Inside parentheses, we can have let expressions
             let expr = if self.check(&TokenKind::Let) {
@@ -1110,8 +1852,20 @@ impl Parser {
             } else {
                 self.parse_expression()
             }?;
+            if !self.check(&TokenKind::Comma) {
+                let end_span = self.current_span();
+                self.expect(TokenKind::RightParen)?;
+                return Ok(Expr::section(expr, start_span.merge(end_span)));
+            }
+            
+            let mut elements = vec![expr];
+            while self.match_token(&TokenKind::Comma) {
+                elements.push(self.parse_expression()?);
+            }
+            let end_span = self.current_span();
             self.expect(TokenKind::RightParen)?;
-            Ok(expr)
+            let span = start_span.merge(end_span);
+            Ok(Expr::section(Expr::Tuple { elements, span }, span))
         }
     }
     
@@ -1120,11 +1874,11 @@ impl Parser {
         let start_span = self.current_span();
         self.expect(TokenKind::If)?;
         
-        let condition = Box::new(self.parse_expression()?);
+        let condition = self.parse_boxed_expression()?;
         self.expect(TokenKind::Then)?;
-        let then_branch = Box::new(self.parse_expression()?);
+        let then_branch = self.parse_boxed_expression()?;
         self.expect(TokenKind::Else)?;
-        let else_branch = Box::new(self.parse_expression()?);
+        let else_branch = self.parse_boxed_expression()?;
         
         let end_span = self.current_span();
         
@@ -1141,6 +1895,10 @@ impl Parser {
         let start_span = self.current_span();
         self.expect(TokenKind::Let)?;
         
+        if self.match_token(&TokenKind::Module) {
+            return self.parse_unpack(start_span);
+        }
+        
         let pattern = self.parse_pattern()?;
         
         let type_annotation = if self.match_token(&TokenKind::Colon) {
@@ -1150,9 +1908,9 @@ impl Parser {
         };
         
         self.expect(TokenKind::Equal)?;
-        let value = Box::new(self.parse_expression()?);
+        let value = self.parse_boxed_expression()?;
         self.expect(TokenKind::In)?;
-        let body = Box::new(self.parse_expression()?);
+        let body = self.parse_boxed_expression()?;
         
         let end_span = self.current_span();
         
@@ -1165,6 +1923,40 @@ impl Parser {
         })
     }
     
+    /// Parse the rest of `let module P : Plugin = e in body`, after `module`
+    fn parse_unpack(&mut self, start_span: Span) -> Result<Expr> {
+        let name = self.parse_identifier()?;
+        self.expect(TokenKind::Colon)?;
+        let signature = self.parse_identifier()?;
+        self.expect(TokenKind::Equal)?;
+        let value = self.parse_boxed_expression()?;
+        self.expect(TokenKind::In)?;
+        let body = self.parse_boxed_expression()?;
+        
+        Ok(Expr::Unpack {
+            name,
+            signature,
+            value,
+            body,
+            span: start_span.merge(self.current_span()),
+        })
+    }
+    
+    /// Parse a module packed as a value: `module Math : Plugin`
+    fn parse_pack(&mut self) -> Result<Expr> {
+        let start_span = self.current_span();
+        self.expect(TokenKind::Module)?;
+        let module = self.parse_identifier()?;
+        self.expect(TokenKind::Colon)?;
+        let signature = self.parse_identifier()?;
+        
+        Ok(Expr::Pack {
+            module,
+            signature,
+            span: start_span.merge(self.current_span()),
+        })
+    }
+    
     /// Parse lambda expressions
     fn parse_lambda(&mut self) -> Result<Expr> {
         let start_span = self.current_span();
@@ -1177,16 +1969,30 @@ impl Parser {
         
         let mut parameters = Vec::new();
         
-        // Parse parameters
+        // Each parameter is one pattern: `fun x y` takes two variables, and
+        // a constructor with arguments needs parentheses, `fun (Some x) ->`
         while !self.check(&TokenKind::Arrow) && !self.is_at_end() {
-            parameters.push(self.parse_pattern()?);
+            if let TokenKind::Ident(name) = &self.current_token().kind {
+                parameters.push(Pattern::Variable(Symbol::intern(name), self.current_span()));
+                self.advance();
+            } else {
+                parameters.push(self.parse_pattern()?);
+            }
         }
         
         self.expect(TokenKind::Arrow)?;
-        let body = Box::new(self.parse_expression()?);
+        let mut body = self.parse_boxed_expression()?;
         
         let end_span = self.current_span();
         
+        // Applications pass one argument at a time, so `fun p q -> body` is
+        // `fun p -> fun q -> body`; the inner lambdas start at their parameter
+        while parameters.len() > 1 {
+            let parameter = parameters.pop().expect("more than one parameter");
+            let span = parameter.span().merge(end_span);
+            body = self.boxed(Expr::Lambda { parameters: vec![parameter], body, span })?;
+        }
+        
         Ok(Expr::Lambda {
             parameters,
             body,
@@ -1199,7 +2005,7 @@ impl Parser {
         let start_span = self.current_span();
         self.expect(TokenKind::Match)?;
         
-        let scrutinee = Box::new(self.parse_expression()?);
+        let scrutinee = self.parse_boxed_expression()?;
         self.expect(TokenKind::With)?;
         
         let mut arms = Vec::new();
@@ -1208,10 +2014,10 @@ impl Parser {
             // Skip optional leading pipe
             self.match_token(&TokenKind::Pipe);
             
-            let pattern = self.parse_pattern()?;
+            let pattern = self.parse_or_pattern()?;
             
             let guard = if self.match_token(&TokenKind::If) {
-                Some(Box::new(self.parse_expression()?))
+                Some(self.parse_boxed_expression()?)
             } else {
                 None
             };
@@ -1242,6 +2048,16 @@ impl Parser {
         })
     }
     
+    /// Parse a pattern with alternatives, `p1 | p2 | ...`
+    fn parse_or_pattern(&mut self) -> Result<Pattern> {
+        let mut pattern = self.parse_pattern()?;
+        while self.match_token(&TokenKind::Pipe) {
+            let right = self.parse_pattern()?;
+            let span = pattern.span().merge(right.span());
+            pattern = Pattern::Or { left: Box::new(pattern), right: Box::new(right), span };
+        }
+        Ok(pattern)
+    }
     
     /// Parse patterns
     fn parse_pattern(&mut self) -> Result<Pattern> {
@@ -1255,12 +2071,22 @@ impl Parser {
             TokenKind::Integer(n) => {
                 let n = *n;
                 self.advance();
-                Ok(Pattern::Literal(Literal::Integer(n), start_span))
+                Ok(Pattern::Literal(Literal::int(n), start_span))
             }
             TokenKind::Float(f) => {
                 let f = *f;
                 self.advance();
-                Ok(Pattern::Literal(Literal::Float(f), start_span))
+                Ok(Pattern::Literal(Literal::float(f), start_span))
+            }
+            TokenKind::Number(s) => {
+                let s = s.clone();
+                self.advance();
+                match Literal::parse_number(&s) {
+                    Some(literal) => Ok(Pattern::Literal(literal, start_span)),
+                    None => Err(Error::Parse {
+                        message: format!("Invalid numeric literal: {s}"),
+                    }),
+                }
             }
             TokenKind::String(s) => {
                 let s = s.clone();
@@ -1280,7 +2106,14 @@ impl Parser {
                 if self.can_start_pattern() {
                     let mut args = Vec::new();
                     while self.can_start_pattern() {
-                        args.push(self.parse_pattern()?);
+                        // `Rect w h` has two arguments; a constructor argument
+                        // with arguments of its own needs parentheses
+                        if let TokenKind::Ident(name) = &self.current_token().kind {
+                            args.push(Pattern::Variable(Symbol::intern(name), self.current_span()));
+                            self.advance();
+                        } else {
+                            args.push(self.parse_pattern()?);
+                        }
                     }
                     let end_span = self.current_span();
                     Ok(Pattern::Constructor {
@@ -1297,9 +2130,22 @@ impl Parser {
                 if self.match_token(&TokenKind::RightParen) {
                     Ok(Pattern::Literal(Literal::Unit, start_span))
                 } else {
-                    let pattern = self.parse_pattern()?;
+                    let pattern = self.parse_or_pattern()?;
+                    if !self.check(&TokenKind::Comma) {
+                        self.expect(TokenKind::RightParen)?;
+                        return Ok(pattern);
+                    }
+                    
+                    let mut patterns = vec![pattern];
+                    while self.match_token(&TokenKind::Comma) {
+                        patterns.push(self.parse_or_pattern()?);
+                    }
+                    let end_span = self.current_span();
                     self.expect(TokenKind::RightParen)?;
-                    Ok(pattern)
+                    Ok(Pattern::Tuple {
+                        patterns,
+                        span: start_span.merge(end_span),
+                    })
                 }
             }
             TokenKind::LeftBracket => {
@@ -1322,7 +2168,7 @@ impl Parser {
     /// Check if current token can start a pattern
     fn can_start_pattern(&self) -> bool {
         matches!(self.current_token().kind,
-            TokenKind::Underscore | TokenKind::Integer(_) | TokenKind::Float(_) |
+            TokenKind::Underscore | TokenKind::Integer(_) | TokenKind::Float(_) | TokenKind::Number(_) |
             TokenKind::String(_) | TokenKind::Bool(_) | TokenKind::Ident(_) |
             TokenKind::LeftParen | TokenKind::LeftBracket
         )
@@ -1336,12 +2182,12 @@ impl Parser {
             TokenKind::Integer(n) => {
                 let n = *n;
                 self.advance();
-                Ok(Expr::Literal(Literal::Integer(n), start_span))
+                Ok(Expr::Literal(Literal::int(n), start_span))
             }
             TokenKind::Float(f) => {
                 let f = *f;
                 self.advance();
-                Ok(Expr::Literal(Literal::Float(f), start_span))
+                Ok(Expr::Literal(Literal::float(f), start_span))
             }
             TokenKind::String(s) => {
                 let s = s.clone();
@@ -1356,21 +2202,11 @@ impl Parser {
             TokenKind::Number(s) => {
                 let s = s.clone();
                 self.advance();
-                // Parse as integer or float
-                if s.contains('.') {
-                    if let Ok(f) = s.parse::<f64>() {
-                        Ok(Expr::Literal(Literal::Float(f), start_span))
-                    } else {
-                        Err(Error::Parse {
-                            message: format!("Invalid float literal: {s}"),
-                        })
-                    }
-                } else if let Ok(i) = s.parse::<i64>() {
-                    Ok(Expr::Literal(Literal::Integer(i), start_span))
-                } else {
-                    Err(Error::Parse {
-                        message: format!("Invalid integer literal: {s}"),
-                    })
+                match Literal::parse_number(&s) {
+                    Some(literal) => Ok(Expr::Literal(literal, start_span)),
+                    None => Err(Error::Parse {
+                        message: format!("Invalid numeric literal: {s}"),
+                    }),
                 }
             }
             TokenKind::Ident(name) => {
@@ -1378,16 +2214,12 @@ impl Parser {
                 self.advance();
                 Ok(Expr::Var(name, start_span))
             }
-            TokenKind::LeftParen => {
+            // A placeholder, turned into a parameter by the enclosing parentheses
+            TokenKind::Underscore => {
                 self.advance();
-                if self.match_token(&TokenKind::RightParen) {
-                    Ok(Expr::Literal(Literal::Unit, start_span))
-                } else {
-                    let expr = self.parse_expression()?;
-                    self.expect(TokenKind::RightParen)?;
-                    Ok(expr)
-                }
+                Ok(Expr::Var(Symbol::intern("_"), start_span))
             }
+            TokenKind::LeftParen => self.parse_parenthesized(),
             TokenKind::LeftBracket => {
                 self.advance();
                 
@@ -1421,13 +2253,13 @@ impl Parser {
                 let end_span = self.current_span();
                 
                 // Build list using :: and []
-                let mut list_expr = Expr::Var(Symbol::intern("[]"), end_span);
+                let mut list_expr = Expr::Var(symbols::NIL(), end_span);
                 
                 // Build from right to left: [1; 2; 3] becomes 1 :: 2 :: 3 :: []
                 for elem in elements.into_iter().rev() {
                     let cons_span = elem.span().merge(list_expr.span());
-                    let cons_op = Expr::Var(Symbol::intern("::"), cons_span);
-                    list_expr = Expr::App(Box::new(cons_op), vec![elem, list_expr], cons_span);
+                    let cons_op = Expr::Var(symbols::CONS(), cons_span);
+                    list_expr = Expr::App(self.boxed(cons_op)?, self.list([elem, list_expr])?, cons_span);
                 }
                 
                 Ok(list_expr)
@@ -1454,6 +2286,60 @@ impl Parser {
     
     // Helper methods
     
+    /// Parse the name of a value, which is `(<+>)` for an operator
+    fn parse_value_name(&mut self) -> Result<Symbol> {
+        if !self.check(&TokenKind::LeftParen) {
+            return self.parse_identifier();
+        }
+        self.advance();
+        if !self.at_operator_name() {
+            return self.error("Expected an identifier or a parenthesized operator");
+        }
+        let operator = self.operator_to_symbol(self.current());
+        self.advance();
+        self.expect(TokenKind::RightParen)?;
+        Ok(operator)
+    }
+    
+    /// Whether the current tokens are an operator and the `)` closing it
+    fn at_operator_name(&self) -> bool {
+        self.fixity_of(self.current()).is_some() && matches!(self.peek(), Some(TokenKind::RightParen))
+    }
+    
+    fn parse_boxed_expression(&mut self) -> Result<Box<Expr>> {
+        let expr = self.parse_expression()?;
+        self.boxed(expr)
+    }
+    
+    /// Box `expr`, in a node of an earlier parse if one is left
+    fn boxed(&mut self, expr: Expr) -> Result<Box<Expr>> {
+        self.charge(size_of::<Expr>())?;
+        Ok(match self.storage.boxes.pop() {
+            Some(mut node) => {
+                *node = expr;
+                node
+            }
+            None => Box::new(expr),
+        })
+    }
+    
+    /// Collect `exprs`, in a list of an earlier parse if one is left
+    fn list<const N: usize>(&mut self, exprs: [Expr; N]) -> Result<Vec<Expr>> {
+        self.charge(N * size_of::<Expr>())?;
+        let mut list = self.storage.lists.pop().unwrap_or_default();
+        list.extend(exprs);
+        Ok(list)
+    }
+    
+    /// Count `bytes` against the memory limit
+    fn charge(&mut self, bytes: usize) -> Result<()> {
+        self.memory_used += bytes;
+        match self.memory_limit {
+            Some(limit) if self.memory_used > limit => Err(Error::MemoryLimit { limit }),
+            _ => Ok(()),
+        }
+    }
+    
     fn current_token(&self) -> &Token {
         if self.current < self.tokens.len() {
             &self.tokens[self.current]
@@ -1461,8 +2347,8 @@ impl Parser {
             // Return a default EOF token
             static EOF_TOKEN: std::sync::OnceLock<Token> = std::sync::OnceLock::new();
             EOF_TOKEN.get_or_init(|| {
-                use crate::span::{FileId, ByteOffset, Span};
-                Token::eof(Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(0)))
+                use crate::span::{FileId, CharOffset, Span};
+                Token::eof(Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(0)))
             })
         }
     }
@@ -1475,6 +2361,11 @@ impl Parser {
         &self.current_token().kind
     }
     
+    /// The token after the current one
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.current + 1).map(|token| &token.kind)
+    }
+    
     fn advance(&mut self) -> &Token {
         if !self.is_at_end() {
             self.current += 1;
@@ -1521,7 +2412,7 @@ impl Parser {
     /// Collect documentation comments before the current position
     fn collect_doc_comments(&mut self) -> Option<Documentation> {
         let mut doc_tokens = Vec::new();
-        let mut current = self.current;
+        let mut current = self.visibility_start();
         
         // Look backwards for doc comments
         while current > 0 {
@@ -1569,6 +2460,48 @@ impl Parser {
         })
     }
     
+    /// Index of the `pub` or `pub(...)` modifier just before the current
+    /// token, and of the `#[...]` attributes before that, so doc comments
+    /// written above them are found
+    fn visibility_start(&self) -> usize {
+        let mut start = self.current;
+        if start > 0 && self.tokens[start - 1].kind == TokenKind::RightParen {
+            if let Some(open) = self.tokens[..start - 1].iter().rposition(|token| token.kind == TokenKind::LeftParen) {
+                if open > 0 && self.tokens[open - 1].kind == TokenKind::Pub {
+                    start = open;
+                }
+            }
+        }
+        if start > 0 && self.tokens[start - 1].kind == TokenKind::Pub {
+            start -= 1;
+        }
+        while let Some(attribute) = self.attribute_before(start) {
+            start = attribute;
+        }
+        start
+    }
+    
+    /// Index of the `#` of a `#[...]` attribute ending just before token `end`
+    fn attribute_before(&self, end: usize) -> Option<usize> {
+        if end == 0 || self.tokens[end - 1].kind != TokenKind::RightBracket {
+            return None;
+        }
+        let mut depth = 0usize;
+        for index in (0..end).rev() {
+            match self.tokens[index].kind {
+                TokenKind::RightBracket => depth += 1,
+                TokenKind::LeftBracket => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return (index > 0 && self.tokens[index - 1].kind == TokenKind::Hash).then(|| index - 1);
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+    
     /// Parse documentation comment content
     fn parse_doc_comment_content(&self, content: &str, span: Span) -> DocComment {
         use std::collections::HashMap;
@@ -1585,44 +2518,9 @@ impl Parser {
         if lines.first() == Some(&"---") {
             i = 1;
             while i < lines.len() && lines[i] != "---" {
-                if let Some((key, value)) = lines[i].split_once(':') {
-                    let key = key.trim();
-                    let value = value.trim();
-                    
-                    // Handle JSDoc-style attributes (e.g., @param, @returns)
-                    let key = if key.starts_with('@') {
-                        key.trim_start_matches('@')
-                    } else {
-                        key
-                    };
-                    
-                    // Parse attribute value
-                    if let Ok(num) = value.parse::<f64>() {
-                        attributes.insert(key.to_string(), DocAttributeValue::Number(num));
-                    } else if value == "true" || value == "false" {
-                        attributes.insert(key.to_string(), DocAttributeValue::Boolean(value == "true"));
-                    } else if value.starts_with('[') && value.ends_with(']') {
-                        // Simple list parsing
-                        let items: Vec<String> = value[1..value.len()-1]
-                            .split(',')
-                            .map(|s| s.trim().trim_matches('"').to_string())
-                            .collect();
-                        attributes.insert(key.to_string(), DocAttributeValue::List(items));
-                    } else if value.starts_with('{') && value.contains('}') {
-                        // Parse typed parameters like {a: Int} or {Type}
-                        if let Some(end) = value.find('}') {
-                            let type_part = &value[1..end];
-                            let desc_part = value[end+1..].trim();
-                            attributes.insert(key.to_string(), DocAttributeValue::TypedParam {
-                                type_info: type_part.to_string(),
-                                description: desc_part.to_string(),
-                            });
-                        } else {
-                            attributes.insert(key.to_string(), DocAttributeValue::String(value.to_string()));
-                        }
-                    } else {
-                        attributes.insert(key.to_string(), DocAttributeValue::String(value.to_string()));
-                    }
+                // Handles JSDoc-style attributes (e.g., @param, @returns)
+                if let Some((key, value)) = parse_attribute(lines[i]) {
+                    attributes.insert(key, value);
                 }
                 i += 1;
             }
@@ -1633,7 +2531,12 @@ impl Parser {
         while i < lines.len() {
             if lines[i].starts_with("```") {
                 // Code block
-                let lang = lines[i][3..].trim();
+                // ```x no_run: the language, then free-form metadata
+                let info = lines[i][3..].trim();
+                let (lang, metadata) = match info.split_once(char::is_whitespace) {
+                    Some((lang, metadata)) => (lang, Some(metadata.trim().to_string())),
+                    None => (info, None),
+                };
                 let lang = if lang.is_empty() { None } else { Some(lang.to_string()) };
                 let mut code_content = String::new();
                 i += 1;
@@ -1649,7 +2552,7 @@ impl Parser {
                 code_blocks.push(CodeBlock {
                     language: lang,
                     content: code_content,
-                    metadata: None,
+                    metadata,
                     span,
                 });
                 i += 1; // Skip closing ```
@@ -1690,6 +2593,103 @@ impl Parser {
         })
     }
     
+    /// Record a syntax error when recovering, or hand it back otherwise
+    fn recover(&mut self, error: Error) -> Result<()> {
+        if !self.recovering || error.is_fatal() {
+            return Err(error);
+        }
+        // Errors raised without a location point at the offending token
+        let error = match error {
+            Error::Parse { message } => Error::syntax(message, self.current_span()),
+            error => error,
+        };
+        self.errors.push(error);
+        Ok(())
+    }
+    
+    /// Skip tokens up to the next item keyword outside any brackets
+    fn synchronize(&mut self) {
+        let mut depth = 0usize;
+        while !self.is_at_end() {
+            match self.current() {
+                TokenKind::LeftParen | TokenKind::LeftBracket | TokenKind::LeftBrace => depth += 1,
+                TokenKind::RightParen | TokenKind::RightBracket | TokenKind::RightBrace => {
+                    depth = depth.saturating_sub(1);
+                }
+                _ if depth == 0 && self.at_item_start() => return,
+                _ => {}
+            }
+            self.advance();
+        }
+    }
+
+    /// Whether the current token can only begin a new top-level item
+    fn at_item_start(&self) -> bool {
+        matches!(
+            self.current(),
+            TokenKind::Let | TokenKind::Data | TokenKind::Type | TokenKind::Effect
+                | TokenKind::Handler | TokenKind::Class | TokenKind::Instance
+                | TokenKind::Interface | TokenKind::Test | TokenKind::Bench | TokenKind::Pub
+                | TokenKind::DocComment(_) | TokenKind::Hash
+        ) || self.at_shallow_handler() || self.at_pure_let() || self.at_module_type() || self.at_fixity_decl()
+    }
+    
+    /// Whether the current tokens are `infixl`, `infixr` or `infix` and a
+    /// precedence; the words are not keywords anywhere else
+    fn at_fixity_decl(&self) -> bool {
+        matches!(self.current(), TokenKind::Ident(keyword) if fixity_associativity(keyword).is_some())
+            && matches!(self.peek(), Some(TokenKind::Number(_)))
+    }
+
+    /// Whether the current tokens are `shallow handler`; `shallow` is not a
+    /// keyword anywhere else
+    fn at_shallow_handler(&self) -> bool {
+        matches!(self.current(), TokenKind::Ident(name) if name == "shallow")
+            && matches!(self.peek(), Some(TokenKind::Handler))
+    }
+    
+    /// Whether the next tokens are `module type`, a module type declaration
+    fn at_module_type(&self) -> bool {
+        self.check(&TokenKind::Module) && matches!(self.peek(), Some(TokenKind::Type))
+    }
+    
+    /// Whether the next tokens are `pure let`, a definition declared pure
+    fn at_pure_let(&self) -> bool {
+        self.check(&TokenKind::Pure) && matches!(self.peek(), Some(TokenKind::Let))
+    }
+
+    fn at_lazy_import(&self) -> bool {
+        self.check(&TokenKind::Ident("lazy".to_string())) && matches!(self.peek(), Some(TokenKind::Import))
+    }
+    
+}
+
+/// The associativity `keyword` declares, if it is a fixity keyword
+fn fixity_associativity(keyword: &str) -> Option<Associativity> {
+    match keyword {
+        "infixl" => Some(Associativity::Left),
+        "infixr" => Some(Associativity::Right),
+        "infix" => Some(Associativity::None),
+        _ => None,
+    }
+}
+
+/// Fixities the declarations among `tokens` give, wherever they are
+///
+/// Malformed declarations are left for `parse_fixity_decl` to report.
+fn declared_fixities(tokens: &[Token]) -> HashMap<Symbol, Fixity> {
+    let mut fixities = HashMap::new();
+    for (i, token) in tokens.iter().enumerate() {
+        let TokenKind::Ident(keyword) = &token.kind else { continue };
+        let Some(associativity) = fixity_associativity(keyword) else { continue };
+        let Some(TokenKind::Number(digits)) = tokens.get(i + 1).map(|token| &token.kind) else { continue };
+        let Ok(precedence) = digits.parse::<u8>() else { continue };
+        for token in &tokens[i + 2..] {
+            let TokenKind::Operator(operator) = &token.kind else { break };
+            fixities.insert(Symbol::intern(operator), Fixity { associativity, precedence });
+        }
+    }
+    fixities
 }
 
 /// Convenience function to parse source code
@@ -1698,6 +2698,35 @@ pub fn parse(input: &str, file_id: FileId) -> Result<CompilationUnit> {
     parser.parse()
 }
 
+/// Parse the module items of `fragment`, a run of whole items cut from a
+/// module's source at offset `base`, up to the item starting at `stop`,
+/// returning them with their extents
+///
+/// Spans are offsets in the whole source, as if it had been parsed at once.
+/// Nodes ending an item can reach over the first token of the next, so the
+/// fragment should run on past `stop` to the end of that token. Operators
+/// are parsed with `fixities`, those of the rest of the module, unless the
+/// fragment declares them itself.
+pub fn parse_items_at(
+    fragment: &str,
+    file_id: FileId,
+    base: CharOffset,
+    stop: CharOffset,
+    fixities: impl IntoIterator<Item = (Symbol, Fixity)>,
+) -> Result<(Vec<Item>, Vec<Span>)> {
+    let mut parser = Parser::with_storage(fragment, file_id, base.as_u32() as usize, ParseStorage::default(), None)?
+        .with_fixities(fixities);
+    let items = parser.parse_items(stop)?;
+    Ok((items, std::mem::take(&mut parser.trivia.item_extents)))
+}
+
+/// Parse source code, stopping early with `ParseError::Cancelled` once `token` is cancelled
+pub fn parse_cancellable(input: &str, file_id: FileId, token: &CancellationToken) -> Result<CompilationUnit> {
+    token.check()?;
+    let mut parser = Parser::new(input, file_id)?.with_cancellation(token.clone());
+    parser.parse()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1744,6 +2773,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_data_constructors_end_at_next_item() {
+        let cu = parse("module Test\ndata Shape = Rect Float Float\ndata Color = Red | Green", FileId::new(0)).unwrap();
+        assert_eq!(cu.module.items.len(), 2);
+
+        let Item::TypeDef(TypeDef { kind: TypeDefKind::Data(constructors), .. }) = &cu.module.items[0] else {
+            panic!("expected a data type");
+        };
+        assert_eq!(constructors[0].fields.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_gadt_constructors() {
+        let cu = parse("module Test\ndata Expr[a] = IntLit Int : Expr[Int] | Pair (Expr[a]) (Expr[a]) : Expr[a] | Unit", FileId::new(0)).unwrap();
+        let Item::TypeDef(TypeDef { kind: TypeDefKind::Data(constructors), .. }) = &cu.module.items[0] else {
+            panic!("expected a data type");
+        };
+        assert_eq!(constructors[0].fields.len(), 1);
+        assert!(matches!(&constructors[0].result, Some(Type::App(_, args, _)) if matches!(args[0], Type::Con(name, _) if name.as_str() == "Int")));
+        assert_eq!(constructors[1].fields.len(), 2);
+        assert!(constructors[1].result.is_some());
+        assert!(constructors[2].result.is_none());
+    }
+
     #[test]
     fn test_parse_effect() {
         let input = r#"
@@ -1870,6 +2923,22 @@ let mixed2 = fn x -> fun y -> x + y"#;
     //     assert_eq!(cu.module.items.len(), 1);
     // }
     
+    #[test]
+    fn test_parse_cancelled() {
+        let input = r#"
+            module Test
+            
+            let x = 42
+        "#;
+        
+        let token = CancellationToken::new();
+        assert!(parse_cancellable(input, FileId::new(0), &token).is_ok());
+        
+        token.cancel();
+        let result = parse_cancellable(input, FileId::new(0), &token);
+        assert!(matches!(result, Err(Error::Cancelled)));
+    }
+    
     #[test]
     fn test_parse_function_application() {
         let input = r#"
@@ -1893,6 +2962,525 @@ let mixed2 = fn x -> fun y -> x + y"#;
         }
     }
     
+    #[test]
+    fn test_parse_class_and_instance() {
+        let input = r#"
+            module Test
+            
+            class Ord[a] with Eq[a] {
+              compare : a -> a -> Int
+            }
+            
+            instance Ord[List[a]] with Ord[a] {
+              let compare = fun x -> fun y -> 0
+            }
+        "#;
+        
+        let cu = parse(input, FileId::new(0)).unwrap();
+        assert_eq!(cu.module.items.len(), 2);
+        
+        let Item::ClassDef(class_def) = &cu.module.items[0] else {
+            panic!("expected class definition");
+        };
+        assert_eq!(class_def.name.as_str(), "Ord");
+        assert_eq!(class_def.superclasses[0].class.as_str(), "Eq");
+        // Signatures are curried like application
+        assert!(matches!(
+            &class_def.methods[0].type_annotation,
+            Type::Fun { params, return_type, .. } if params.len() == 1 && matches!(**return_type, Type::Fun { .. })
+        ));
+        
+        let Item::InstanceDef(instance_def) = &cu.module.items[1] else {
+            panic!("expected instance definition");
+        };
+        assert_eq!(instance_def.class.as_str(), "Ord");
+        assert!(matches!(instance_def.types[0], Type::App(..)));
+        assert_eq!(instance_def.constraints.len(), 1);
+        assert_eq!(instance_def.methods[0].name.as_str(), "compare");
+    }
+    
+    #[test]
+    fn test_parse_record_expressions() {
+        let input = r#"
+            module Test
+            
+            let origin = { x = 0, y = 0 }
+            let moved = { origin with x = 1 }
+            let size = length origin.x
+            let open : {x: Int | r} = origin
+        "#;
+        
+        let cu = parse(input, FileId::new(0)).unwrap();
+        let bodies: Vec<&Expr> = cu.module.items.iter()
+            .map(|item| match item {
+                Item::ValueDef(def) => &def.body,
+                _ => panic!("expected value definition"),
+            })
+            .collect();
+        
+        assert!(matches!(bodies[0], Expr::Record { fields, .. } if fields.len() == 2));
+        assert!(matches!(bodies[1], Expr::RecordUpdate { fields, .. } if fields[0].0.as_str() == "x"));
+        // Projection binds tighter than application
+        assert!(matches!(
+            bodies[2],
+            Expr::App(_, args, _) if matches!(&args[0], Expr::Project { field, .. } if field.as_str() == "x")
+        ));
+        
+        let Item::ValueDef(open) = &cu.module.items[3] else { unreachable!() };
+        assert!(matches!(&open.type_annotation, Some(Type::Record { rest: Some(_), .. })));
+    }
+    
+    #[test]
+    fn test_parse_numeric_literals() {
+        let input = r#"
+            module Test
+            
+            let mask = 0xFF
+            let flags = 0b1010
+            let million = 1_000_000
+            let answer = 42i
+            let pi = 3.14f
+            let zero = match million with 0x0 => true | _ => false
+        "#;
+        
+        let cu = parse(input, FileId::new(0)).unwrap();
+        let bodies: Vec<&Expr> = cu.module.items.iter()
+            .map(|item| match item {
+                Item::ValueDef(def) => &def.body,
+                _ => panic!("expected value definition"),
+            })
+            .collect();
+        
+        assert!(matches!(bodies[0], Expr::Literal(Literal::Integer(255, format), _) if format.radix == Radix::Hexadecimal));
+        assert!(matches!(bodies[1], Expr::Literal(Literal::Integer(10, format), _) if format.radix == Radix::Binary));
+        assert!(matches!(bodies[2], Expr::Literal(Literal::Integer(1_000_000, format), _) if format.group == 3));
+        assert!(matches!(bodies[3], Expr::Literal(Literal::Integer(42, format), _) if format.suffix));
+        assert!(matches!(bodies[4], Expr::Literal(Literal::Float(_, format), _) if format.suffix));
+        assert!(matches!(
+            bodies[5],
+            Expr::Match { arms, .. } if matches!(&arms[0].pattern, Pattern::Literal(Literal::Integer(0, _), _))
+        ));
+        
+        assert!(parse("module Test
+let bad = 0b12", FileId::new(0)).is_err());
+    }
+    
+    #[test]
+    fn test_parse_recovers_from_errors() {
+        let input = r#"
+            module Test
+            
+            let ok = 1
+            let broken = (1, )
+            ) garbage
+            let also_ok = 2
+            let bad_literal = 0b12
+            let last = 3
+        "#;
+        
+        assert!(parse(input, FileId::new(0)).is_err());
+        
+        let recovered = Parser::new(input, FileId::new(0)).unwrap().parse_recovering().unwrap();
+        assert_eq!(recovered.errors.len(), 2, "{:?}", recovered.errors);
+        assert!(recovered.errors.iter().all(|error| error.span().is_some()));
+        
+        let defs: Vec<(&str, &Expr)> = recovered.ast.module.items.iter()
+            .map(|item| match item {
+                Item::ValueDef(def) => (def.name.as_str(), &def.body),
+                _ => panic!("expected value definition"),
+            })
+            .collect();
+        let names: Vec<&str> = defs.iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, ["ok", "broken", "also_ok", "bad_literal", "last"]);
+        assert!(matches!(defs[1].1, Expr::Error(_)));
+        assert!(matches!(defs[3].1, Expr::Error(_)));
+        assert!(matches!(defs[4].1, Expr::Literal(Literal::Integer(3, _), _)));
+    }
+    
+    #[test]
+    fn test_parse_tuple_expressions() {
+        let input = r#"
+            module Test
+            
+            let pair = (1, "one")
+            let unit = ()
+            let grouped = (pair)
+            let swap = fun (a, b) -> (b, a)
+            let pair2 : (Int, String) = pair
+        "#;
+        
+        let cu = parse(input, FileId::new(0)).unwrap();
+        let bodies: Vec<&Expr> = cu.module.items.iter()
+            .map(|item| match item {
+                Item::ValueDef(def) => &def.body,
+                _ => panic!("expected value definition"),
+            })
+            .collect();
+        
+        assert!(matches!(bodies[0], Expr::Tuple { elements, .. } if elements.len() == 2));
+        assert!(matches!(bodies[1], Expr::Literal(Literal::Unit, _)));
+        assert!(matches!(bodies[2], Expr::Var(..)));
+        assert!(matches!(
+            bodies[3],
+            Expr::Lambda { parameters, body, .. }
+                if matches!(&parameters[0], Pattern::Tuple { patterns, .. } if patterns.len() == 2)
+                    && matches!(body.as_ref(), Expr::Tuple { .. })
+        ));
+        
+        let Item::ValueDef(annotated) = &cu.module.items[4] else { unreachable!() };
+        assert!(matches!(&annotated.type_annotation, Some(Type::Tuple { types, .. }) if types.len() == 2));
+    }
+    
+    #[test]
+    fn test_parse_constructor_pattern_arguments() {
+        let input = "module Test\nlet f = fun s -> match s with | Rect w h => w | Cons (Some x) rest => x";
+        let cu = parse(input, FileId::new(0)).unwrap();
+        let Item::ValueDef(def) = &cu.module.items[0] else { unreachable!() };
+        let Expr::Lambda { body, .. } = &def.body else { panic!("expected a lambda") };
+        let Expr::Match { arms, .. } = body.as_ref() else { panic!("expected a match") };
+        
+        assert!(matches!(
+            &arms[0].pattern,
+            Pattern::Constructor { args, .. } if matches!(args.as_slice(), [Pattern::Variable(..), Pattern::Variable(..)])
+        ));
+        assert!(matches!(
+            &arms[1].pattern,
+            Pattern::Constructor { args, .. } if matches!(&args[0], Pattern::Constructor { args, .. } if args.len() == 1)
+        ));
+    }
+
+    #[test]
+    fn test_parse_doc_comment_code_blocks() {
+        let input = "module Test\n```\nDoubles a number.\n\n```x no_run\ndouble 2 == 4\n```\n```\nlet double = fun x -> x + x";
+        let cu = parse(input, FileId::new(0)).unwrap();
+        let Item::ValueDef(def) = &cu.module.items[0] else { unreachable!() };
+        let doc = &def.documentation.as_ref().expect("documented").doc_comment;
+
+        assert_eq!(doc.content, "Doubles a number.");
+        assert_eq!(doc.code_blocks.len(), 1);
+        assert_eq!(doc.code_blocks[0].language.as_deref(), Some("x"));
+        assert_eq!(doc.code_blocks[0].metadata.as_deref(), Some("no_run"));
+        assert_eq!(doc.code_blocks[0].content, "double 2 == 4");
+    }
+
+    #[test]
+    fn test_doc_comment_above_visibility() {
+        let input = "module Test\n```\nPublic.\n```\npub let a = 1\n```\nCrate.\n```\npub(crate) data T = A\nlet b = f (c)\nlet d = 2";
+        let cu = parse(input, FileId::new(0)).unwrap();
+        let docs: Vec<_> = cu.module.items.iter()
+            .map(|item| match item {
+                Item::ValueDef(def) => def.documentation.as_ref(),
+                Item::TypeDef(def) => def.documentation.as_ref(),
+                _ => unreachable!(),
+            })
+            .map(|doc| doc.map(|doc| doc.doc_comment.content.as_str()))
+            .collect();
+        assert_eq!(docs, [Some("Public."), Some("Crate."), None, None]);
+    }
+
+    #[test]
+    fn test_parse_module_documentation() {
+        let input = "--! ---\n--! @since 1.2.0\n--! ---\n--! Lists and the functions on them.\nmodule List\n--! More prose.\nimport Core\n-- not documentation\nlet a = 1 --! trailing\n--! after the first item";
+        let cu = parse(input, FileId::new(0)).unwrap();
+        let documentation = cu.module.documentation.unwrap();
+        assert!(documentation.is_module_doc);
+        let comment = documentation.doc_comment;
+        assert_eq!(comment.content, "Lists and the functions on them.\nMore prose.");
+        assert_eq!(comment.attributes["since"], DocAttributeValue::String("1.2.0".to_string()));
+        assert_eq!((comment.span.start.as_u32(), comment.span.end.as_u32()), (0, 97));
+
+        assert_eq!(parse("module Test\nlet a = 1", FileId::new(0)).unwrap().module.documentation, None);
+    }
+
+    #[test]
+    fn test_parse_bench_def() {
+        let input = "module Test\nbench \"sum list\" with iterations = 50, warmup = 2 { sum [1, 2, 3] }\nbench quick { 1 + 1 }";
+        let cu = parse(input, FileId::new(0)).unwrap();
+        let benches: Vec<_> = cu.module.items.iter()
+            .map(|item| match item {
+                Item::BenchDef(bench) => (bench.name.as_str(), bench.description.as_deref(), bench.iterations, bench.warmup),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(benches, [("sum_list", Some("sum list"), Some(50), Some(2)), ("quick", None, None, None)]);
+
+        assert!(parse("module Test\nbench b with iterations = x { 1 }", FileId::new(0)).is_err());
+    }
+
+    #[test]
+    fn test_parse_pipeline_and_composition() {
+        let input = "module Test\nlet p = xs |> map f >> g |> length\nlet q = f << g << h";
+        let cu = parse(input, FileId::new(0)).unwrap();
+        let bodies: Vec<_> = cu.module.items.iter()
+            .map(|item| match item {
+                Item::ValueDef(def) => &def.body,
+                _ => unreachable!(),
+            })
+            .collect();
+
+        fn operator(expr: &Expr) -> (&str, &[Expr]) {
+            match expr {
+                Expr::App(function, args, _) => match function.as_ref() {
+                    Expr::Var(name, _) => (name.as_str(), args.as_slice()),
+                    _ => ("", args.as_slice()),
+                },
+                _ => ("", &[]),
+            }
+        }
+        // `(xs |> ((map f) >> g)) |> length`
+        let (outer, args) = operator(bodies[0]);
+        assert_eq!(outer, "|>");
+        let (inner, args) = operator(&args[0]);
+        assert_eq!(inner, "|>");
+        assert_eq!(operator(&args[1]).0, ">>");
+
+        // `f << (g << h)`
+        let (compose, args) = operator(bodies[1]);
+        assert_eq!(compose, "<<");
+        assert_eq!(operator(&args[1]).0, "<<");
+    }
+
+    #[test]
+    fn test_parse_fixity_declarations() {
+        // Uses may come before the declarations
+        let input = "module Test\n\
+            let x = a <+> b <*> c <+> d\n\
+            let y = a <^> b <^> c\n\
+            let z = (<+>) a (b <~> c <~> d)\n\
+            infixl 6 <+>\n\
+            infixl 7 <*>\n\
+            infixr 5 <^>\n\
+            let (<+>) = fun a b -> a + b";
+        let cu = parse(input, FileId::new(0)).unwrap();
+
+        fn grouping(expr: &Expr) -> String {
+            match expr {
+                Expr::Var(name, _) => name.to_string(),
+                Expr::App(function, args, _) => match (function.as_ref(), args.as_slice()) {
+                    (Expr::Var(op, _), [left, right]) if op.as_str().starts_with(['<', '∘']) => {
+                        format!("({} {op} {})", grouping(left), grouping(right))
+                    }
+                    _ => format!("[{} {}]", grouping(function), args.iter().map(grouping).collect::<Vec<_>>().join(" ")),
+                },
+                _ => "?".to_string(),
+            }
+        }
+        let items: Vec<_> = cu.module.items.iter()
+            .map(|item| match item {
+                Item::ValueDef(def) => format!("{} = {}", def.name, grouping(&def.body)),
+                Item::Fixity(decl) => format!("{} {} {}", decl.fixity.keyword(), decl.fixity.precedence, decl.operators[0]),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(items[..3], [
+            "x = ((a <+> (b <*> c)) <+> d)",
+            "y = (a <^> (b <^> c))",
+            // Undeclared operators are left-associative at precedence 9
+            "z = [[<+> a] ((b <~> c) <~> d)]",
+        ]);
+        assert_eq!(items[3], "infixl 6 <+>");
+        assert!(items[6].starts_with("<+> = "));
+
+        let imported = Parser::new("module Test\nlet x = a <+> b * c", FileId::new(0)).unwrap()
+            .with_fixities([(Symbol::intern("<+>"), Fixity { associativity: Associativity::Left, precedence: 9 })])
+            .parse()
+            .unwrap();
+        let Item::ValueDef(def) = &imported.module.items[0] else { unreachable!() };
+        assert_eq!(grouping(&def.body), "[* (a <+> b) c]");
+
+        // Unicode math symbols are operators too
+        let unicode = parse("module Test\ninfixr 9 ∘\nlet h = f ∘ g ∘ k", FileId::new(0)).unwrap();
+        let Item::ValueDef(def) = &unicode.module.items[1] else { unreachable!() };
+        assert_eq!(grouping(&def.body), "(f ∘ (g ∘ k))");
+    }
+
+    #[test]
+    fn test_fixity_declaration_errors() {
+        for input in [
+            "module Test\ninfix 4 ===\nlet x = a === b === c",
+            "module Test\ninfixl 6 +",
+            "module Test\ninfixl 11 <+>",
+        ] {
+            assert!(parse(input, FileId::new(0)).is_err(), "{input}");
+        }
+        assert!(parse("module Test\ninfix 4 ===\nlet x = (a === b) === c", FileId::new(0)).is_ok());
+    }
+
+    #[test]
+    fn test_parse_cfg_attributes() {
+        let input = "module Test\n\
+            ```\nReads a file.\n```\n\
+            #[cfg(target = \"typescript\")]\n\
+            pub let read = fun path -> path\n\
+            #[cfg(all(not(target = \"typescript\"), any(feature = \"wasi\", feature = \"browser\")))]\n\
+            ```\nReads nothing.\n```\n\
+            let read = fun path -> \"\"";
+        let cu = parse(input, FileId::new(0)).unwrap();
+        let conditions: Vec<_> = cu.module.items.iter()
+            .map(|item| {
+                let Item::Conditional(conditional) = item else { panic!("expected a conditional item") };
+                let Item::ValueDef(def) = conditional.item.as_ref() else { panic!("expected a definition") };
+                assert_eq!(def.name.as_str(), "read");
+                (conditional.condition.to_string(), item.documentation().unwrap().doc_comment.content.trim().to_string())
+            })
+            .collect();
+        assert_eq!(conditions, [
+            ("target = \"typescript\"".to_string(), "Reads a file.".to_string()),
+            (
+                "all(not(target = \"typescript\"), any(feature = \"wasi\", feature = \"browser\"))".to_string(),
+                "Reads nothing.".to_string(),
+            ),
+        ]);
+
+        for input in [
+            "module Test\n#[inline]\nlet x = 1",
+            "module Test\n#[cfg(target)]\nlet x = 1",
+            "module Test\n#[cfg(not(feature = \"a\", feature = \"b\"))]\nlet x = 1",
+            "module Test\n#[cfg(some(feature = \"a\"))]\nlet x = 1",
+        ] {
+            assert!(parse(input, FileId::new(0)).is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn test_parse_lambda_patterns_and_sections() {
+        let input = "module Test\nlet f = fun (Some x) (a, b) y -> x\nlet g = map (_ + 1) xs";
+        let cu = parse(input, FileId::new(0)).unwrap();
+        let bodies: Vec<_> = cu.module.items.iter()
+            .map(|item| match item {
+                Item::ValueDef(def) => &def.body,
+                _ => unreachable!(),
+            })
+            .collect();
+
+        // One lambda per parameter: `fun (Some x) -> fun (a, b) -> fun y -> x`
+        let mut parameters = Vec::new();
+        let mut lambda = bodies[0];
+        while let Expr::Lambda { parameters: own, body, .. } = lambda {
+            assert_eq!(own.len(), 1);
+            parameters.push(&own[0]);
+            lambda = body;
+        }
+        assert!(matches!(parameters[0], Pattern::Constructor { args, .. } if args.len() == 1));
+        assert!(matches!(parameters[1], Pattern::Tuple { patterns, .. } if patterns.len() == 2));
+        assert!(matches!(parameters[2], Pattern::Variable(name, _) if name.as_str() == "y"));
+
+        // `(_ + 1)` becomes `fun $1 -> $1 + 1`, spanning the parentheses
+        let Expr::App(function, _, _) = bodies[1] else { panic!("expected an application") };
+        let Expr::App(_, args, _) = function.as_ref() else { panic!("expected an application") };
+        let Expr::Lambda { parameters, body, span } = &args[0] else { panic!("expected a section") };
+        let section = input.find("(_").unwrap() as u32;
+        assert_eq!((span.start.as_u32(), span.end.as_u32()), (section, section + "(_ + 1)".len() as u32));
+        let Pattern::Variable(parameter, placeholder) = &parameters[0] else { panic!("expected a variable") };
+        assert_eq!((parameter.as_str(), placeholder.start.as_u32()), ("$1", section + 1));
+        assert!(matches!(body.as_ref(), Expr::App(_, operands, _) if operands[0] == Expr::Var(*parameter, *placeholder)));
+
+        // `(_ + _)` takes its operands one at a time
+        let cu = parse("module Test\nlet add = (_ + _)", FileId::new(0)).unwrap();
+        let Item::ValueDef(def) = &cu.module.items[0] else { unreachable!() };
+        let Expr::Lambda { parameters, body, .. } = &def.body else { panic!("expected a section") };
+        assert_eq!(parameters.len(), 1);
+        assert!(matches!(body.as_ref(), Expr::Lambda { parameters, .. } if parameters.len() == 1));
+        assert!(def.body.section_body().is_some());
+    }
+
+    #[test]
+    fn test_parse_or_patterns() {
+        let input = "module Test\nlet f = fun n -> match n with | 0 | 1 => a | (Some 2 | None, _) if ok => b | _ => c";
+        let cu = parse(input, FileId::new(0)).unwrap();
+        let Item::ValueDef(def) = &cu.module.items[0] else { panic!("expected a definition") };
+        let Expr::Lambda { body, .. } = &def.body else { panic!("expected a lambda") };
+        let Expr::Match { arms, .. } = body.as_ref() else { panic!("expected a match") };
+        assert_eq!(arms.len(), 3);
+        let Pattern::Or { left, right, .. } = &arms[0].pattern else { panic!("expected an or-pattern") };
+        assert!(matches!((left.as_ref(), right.as_ref()), (Pattern::Literal(..), Pattern::Literal(..))));
+        let Pattern::Tuple { patterns, .. } = &arms[1].pattern else { panic!("expected a tuple") };
+        assert!(matches!(&patterns[0], Pattern::Or { .. }));
+        assert!(arms[1].guard.is_some());
+    }
+
+    #[test]
+    fn test_parse_handler_definition() {
+        let input = "module Test\n\
+            effect State { get : Int put : Int -> Unit }\n\
+            handler counter {\n\
+              | State.get with k => k 0\n\
+              | State.put (n) => resume ()\n\
+              | return x => (x, 1)\n\
+            }\n\
+            let after = 1";
+        let cu = parse(input, FileId::new(0)).unwrap();
+        assert_eq!(cu.module.items.len(), 3);
+        let Item::HandlerDef(def) = &cu.module.items[1] else { panic!("expected a handler") };
+        assert_eq!(def.name.as_str(), "counter");
+        assert_eq!(def.handled_effects.iter().map(|effect| effect.name.as_str()).collect::<Vec<_>>(), ["State"]);
+        assert_eq!(def.handlers.len(), 2);
+        assert_eq!(def.handlers[0].continuation.map(|k| k.to_string()), Some("k".to_string()));
+        assert!(def.handlers[0].parameters.is_empty());
+        assert_eq!(def.handlers[1].operation.as_str(), "put");
+        assert_eq!(def.handlers[1].parameters.len(), 1);
+        assert!(matches!(def.handlers[1].body, Expr::Resume { .. }));
+        assert!(matches!(def.return_clause.as_ref().map(|clause| &clause.parameter), Some(Pattern::Variable(..))));
+        assert_eq!(def.mode, HandlerMode::Deep);
+
+        let cu = parse("module Test\npub shallow handler once { | State.get => resume 1 }\nlet shallow = 1", FileId::new(0)).unwrap();
+        let Item::HandlerDef(def) = &cu.module.items[0] else { panic!("expected a handler") };
+        assert_eq!((def.mode, def.visibility.clone()), (HandlerMode::Shallow, Visibility::Public));
+        assert!(matches!(&cu.module.items[1], Item::ValueDef(value) if value.name.as_str() == "shallow"));
+    }
+
+    #[test]
+    fn test_parse_pure_value_definition() {
+        let input = "module Test\n\
+            ```Doubles its argument```\n\
+            pub pure let double = fun x -> x\n\
+            let other = 1";
+        let cu = parse(input, FileId::new(0)).unwrap();
+        let Item::ValueDef(def) = &cu.module.items[0] else { panic!("expected a value") };
+        assert_eq!((def.name.as_str(), def.purity.clone(), def.visibility.clone()), ("double", Purity::Pure, Visibility::Public));
+        assert!(def.documentation.is_some());
+        let Item::ValueDef(other) = &cu.module.items[1] else { panic!("expected a value") };
+        assert_eq!(other.purity, Purity::Inferred);
+    }
+
+    #[test]
+    fn test_parse_lazy_imports() {
+        let input = "module Test\nimport Core\nlazy import Reports.Pdf as Pdf\nlet lazy = 1";
+        let cu = parse(input, FileId::new(0)).unwrap();
+        let kinds: Vec<_> = cu.module.imports.iter().map(|import| &import.kind).collect();
+        assert_eq!(kinds, [&ImportKind::Qualified, &ImportKind::Lazy]);
+        assert_eq!(cu.module.imports[1].alias, Some(Symbol::intern("Pdf")));
+        assert_eq!(cu.module.items.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_first_class_modules() {
+        let input = "module Test\n\
+            import Plugins.Math : Plugin as M\n\
+            module type Plugin { type t  make : Int -> t  show : t -> Int }\n\
+            let plugin = module M : Plugin\n\
+            let run = fun p -> (let module P : Plugin = p in P.show (P.make 1))";
+        let cu = parse(input, FileId::new(0)).unwrap();
+        assert_eq!(cu.module.imports[0].kind, ImportKind::Signature(Symbol::intern("Plugin")));
+        assert_eq!(cu.module.imports[0].alias, Some(Symbol::intern("M")));
+
+        let Item::ModuleTypeDef(def) = &cu.module.items[0] else { panic!("expected a module type") };
+        let names: Vec<_> = def.signature.items.iter()
+            .map(|item| match item {
+                SignatureItem::TypeSig { name, .. } => format!("type {name}"),
+                SignatureItem::ValueSig { name, .. } => name.to_string(),
+                SignatureItem::EffectSig { name, .. } => format!("effect {name}"),
+            })
+            .collect();
+        assert_eq!(names, ["type t", "make", "show"]);
+
+        let Item::ValueDef(plugin) = &cu.module.items[1] else { panic!("expected a value") };
+        assert!(matches!(&plugin.body, Expr::Pack { module, signature, .. } if module.as_str() == "M" && signature.as_str() == "Plugin"));
+        let Item::ValueDef(run) = &cu.module.items[2] else { panic!("expected a value") };
+        let Expr::Lambda { body, .. } = &run.body else { panic!("expected a lambda") };
+        assert!(matches!(body.as_ref(), Expr::Unpack { name, .. } if name.as_str() == "P"));
+    }
+
     // match式も中置記法の一種なので、S式構文では無効化
     // #[test]
     // fn test_parse_match_expression() {
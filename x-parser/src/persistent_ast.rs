@@ -2,11 +2,15 @@
 //! 
 //! This module provides immutable, persistent AST nodes that support
 //! efficient structural sharing and O(log n) operations.
+//!
+//! [`PersistentUnit`] is the persistent form of a whole `CompilationUnit`:
+//! its items are reference counted and held in an `im::Vector`, so versions
+//! derived from one another share every item that did not change.
 
-use crate::{span::{Span, FileId, ByteOffset}, symbol::Symbol};
+use crate::{ast, span::{Span, FileId, CharOffset}, symbol::Symbol};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-// use im::Vector;
+use std::sync::Arc;
 
 /// Unique identifier for AST nodes
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
@@ -422,13 +426,13 @@ impl PersistentAstNode {
             "ValueDef" => AstNodeKind::ValueDef { 
                 name: Symbol::intern(""), 
                 type_annotation: None, 
-                body: Box::new(PersistentAstNode::new(NodeId::new(0), Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(0)), AstNodeKind::Literal { value: LiteralValue::Unit })), 
+                body: Box::new(PersistentAstNode::new(NodeId::new(0), Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(0)), AstNodeKind::Literal { value: LiteralValue::Unit })), 
                 visibility: Visibility::Private, 
                 purity: Purity::Inferred 
             },
             "Variable" => AstNodeKind::Variable { name: Symbol::intern("") },
             "Application" => AstNodeKind::Application { 
-                function: Box::new(PersistentAstNode::new(NodeId::new(0), Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(0)), AstNodeKind::Literal { value: LiteralValue::Unit })), 
+                function: Box::new(PersistentAstNode::new(NodeId::new(0), Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(0)), AstNodeKind::Literal { value: LiteralValue::Unit })), 
                 arguments: Vec::new() 
             },
             _ => return false,
@@ -472,4 +476,179 @@ impl Default for NodeBuilder {
     fn default() -> Self {
         Self::new()
     }
-}
\ No newline at end of file
+}
+
+/// Immutable version of a `CompilationUnit` sharing structure with the
+/// versions it was derived from
+///
+/// Cloning is O(1), and a clone can be read from other threads while the
+/// editor works on the next version.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PersistentUnit {
+    header: Arc<ModuleHeader>,
+    items: im::Vector<Arc<ast::Item>>,
+    span: Span,
+}
+
+/// Everything of a module but its items
+#[derive(Debug, Clone, PartialEq)]
+struct ModuleHeader {
+    name: ast::ModulePath,
+    documentation: Option<ast::Documentation>,
+    exports: Option<ast::ExportList>,
+    imports: Vec<ast::Import>,
+    span: Span,
+}
+
+impl ModuleHeader {
+    fn of(module: &ast::Module) -> Self {
+        ModuleHeader {
+            name: module.name.clone(),
+            documentation: module.documentation.clone(),
+            exports: module.exports.clone(),
+            imports: module.imports.clone(),
+            span: module.span,
+        }
+    }
+}
+
+impl PersistentUnit {
+    pub fn module_name(&self) -> &ast::ModulePath {
+        &self.header.name
+    }
+
+    pub fn imports(&self) -> &[ast::Import] {
+        &self.header.imports
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn item(&self, index: usize) -> Option<&Arc<ast::Item>> {
+        self.items.get(index)
+    }
+
+    pub fn items(&self) -> impl Iterator<Item = &Arc<ast::Item>> {
+        self.items.iter()
+    }
+
+    /// This version with the item at `index` replaced
+    pub fn with_item(&self, index: usize, item: ast::Item) -> Self {
+        let mut next = self.clone();
+        next.items.set(index, Arc::new(item));
+        next
+    }
+
+    /// This version with `item` inserted at `index`
+    pub fn with_inserted(&self, index: usize, item: ast::Item) -> Self {
+        let mut next = self.clone();
+        next.items.insert(index, Arc::new(item));
+        next
+    }
+
+    /// This version without the item at `index`
+    pub fn without_item(&self, index: usize) -> Self {
+        let mut next = self.clone();
+        next.items.remove(index);
+        next
+    }
+
+    /// The version holding `unit`, sharing the header and every item that is
+    /// unchanged from this one
+    ///
+    /// Items are matched by position from both ends, which finds everything
+    /// an insertion, deletion or replacement in between leaves untouched.
+    pub fn update(&self, unit: &ast::CompilationUnit) -> Self {
+        let module = &unit.module;
+        let header = ModuleHeader::of(module);
+        let header = if *self.header == header { self.header.clone() } else { Arc::new(header) };
+
+        let new_items = &module.items;
+        let prefix = self.items.iter().zip(new_items).take_while(|(old, new)| ***old == **new).count();
+        let suffix = self.items.iter().rev().zip(new_items.iter().rev())
+            .take(self.items.len().min(new_items.len()) - prefix)
+            .take_while(|(old, new)| ***old == **new)
+            .count();
+
+        let mut items = self.items.clone();
+        let tail = items.split_off(self.items.len() - suffix);
+        items.truncate(prefix);
+        items.extend(new_items[prefix..new_items.len() - suffix].iter().cloned().map(Arc::new));
+        items.append(tail);
+        PersistentUnit { header, items, span: unit.span }
+    }
+
+    /// The mutable AST of this version
+    pub fn to_compilation_unit(&self) -> ast::CompilationUnit {
+        let header = &*self.header;
+        ast::CompilationUnit {
+            module: ast::Module {
+                name: header.name.clone(),
+                documentation: header.documentation.clone(),
+                exports: header.exports.clone(),
+                imports: header.imports.clone(),
+                items: self.items.iter().map(|item| (**item).clone()).collect(),
+                span: header.span,
+            },
+            span: self.span,
+        }
+    }
+}
+
+impl From<&ast::CompilationUnit> for PersistentUnit {
+    fn from(unit: &ast::CompilationUnit) -> Self {
+        PersistentUnit {
+            header: Arc::new(ModuleHeader::of(&unit.module)),
+            items: unit.module.items.iter().cloned().map(Arc::new).collect(),
+            span: unit.span,
+        }
+    }
+}
+
+impl From<&PersistentUnit> for ast::CompilationUnit {
+    fn from(unit: &PersistentUnit) -> Self {
+        unit.to_compilation_unit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_source, SyntaxStyle};
+
+    fn unit(source: &str) -> ast::CompilationUnit {
+        parse_source(source, FileId::new(0), SyntaxStyle::default()).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let original = unit("module Demo\nimport Core.List\nlet a = 1\nlet b = fun x -> x + a");
+        let persistent = PersistentUnit::from(&original);
+        assert_eq!(persistent.len(), 2);
+        assert_eq!(persistent.to_compilation_unit(), original);
+    }
+
+    #[test]
+    fn test_versions_share_unchanged_items() {
+        let original = unit("module Demo\nlet a = 1\nlet b = 2\nlet c = 3");
+        let first = PersistentUnit::from(&original);
+        let mut edited = original.clone();
+        edited.module.items.insert(1, unit("module Demo\nlet inserted = 0").module.items.remove(0));
+        let second = first.update(&edited);
+
+        assert_eq!(second.to_compilation_unit().module.items, edited.module.items);
+        assert!(Arc::ptr_eq(&first.header, &second.header));
+        assert!(Arc::ptr_eq(first.item(0).unwrap(), second.item(0).unwrap()));
+        assert!(Arc::ptr_eq(first.item(1).unwrap(), second.item(2).unwrap()));
+        assert!(Arc::ptr_eq(first.item(2).unwrap(), second.item(3).unwrap()));
+
+        let third = second.without_item(1);
+        assert!(Arc::ptr_eq(first.item(1).unwrap(), third.item(1).unwrap()));
+        assert_eq!(second.len(), 4, "older versions are unaffected");
+    }
+}
@@ -322,8 +322,8 @@ impl Span {
     /// Dummy span for examples
     pub fn dummy() -> Self {
         // Use a proper dummy span instead of unimplemented
-        use crate::span::{FileId, ByteOffset};
-        Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(0))
+        use crate::span::{FileId, CharOffset};
+        Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(0))
     }
 }
 
@@ -0,0 +1,167 @@
+//! Registry of the source files a compilation reads
+//!
+//! Spans only carry a `FileId` and offsets; a `SourceMap` keeps the path
+//! and text behind each id, so diagnostics, the language server and
+//! commands taking `line:column` arguments can turn spans into positions
+//! and snippets and back.
+
+use crate::span::{CharOffset, FileId, LineMap, Position, Span};
+use std::path::{Path, PathBuf};
+
+/// One file in a `SourceMap`
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    id: FileId,
+    path: PathBuf,
+    text: String,
+    lines: LineMap,
+}
+
+impl SourceFile {
+    pub fn id(&self) -> FileId {
+        self.id
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn lines(&self) -> &LineMap {
+        &self.lines
+    }
+
+    /// Line and column of `offset`, if it lies within the file
+    pub fn position(&self, offset: CharOffset) -> Option<Position> {
+        self.lines.byte_index(&self.text, offset).is_some().then(|| self.lines.offset_to_position(offset))
+    }
+
+    /// Offset of a line and column, if the line has that column; a
+    /// position just past the end of a line is on it
+    pub fn offset(&self, position: Position) -> Option<CharOffset> {
+        let offset = self.lines.position_to_offset(position)?;
+        let line = self.lines.line_text(&self.text, position.line).unwrap_or("");
+        (position.column.as_u32() as usize <= line.chars().count()).then_some(offset)
+    }
+
+    /// The text `span` covers
+    pub fn snippet(&self, span: Span) -> Option<&str> {
+        span.text(&self.text)
+    }
+
+    /// `path:line:column` of `offset`, 1-based
+    pub fn location(&self, offset: CharOffset) -> Option<String> {
+        let position = self.position(offset)?;
+        Some(format!("{}:{}:{}", self.path.display(), position.line.to_display(), position.column.to_display()))
+    }
+}
+
+/// The source files of a compilation, by the `FileId` their spans carry
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file, returning the id to parse it with
+    pub fn add_file(&mut self, path: impl Into<PathBuf>, text: impl Into<String>) -> FileId {
+        let id = FileId::new(self.files.len() as u32);
+        let text = text.into();
+        self.files.push(SourceFile { id, path: path.into(), lines: LineMap::new(&text), text });
+        id
+    }
+
+    /// Replace the text of a file after an edit
+    pub fn set_text(&mut self, id: FileId, text: impl Into<String>) -> Option<()> {
+        let file = self.files.get_mut(id.as_u32() as usize)?;
+        file.text = text.into();
+        file.lines = LineMap::new(&file.text);
+        Some(())
+    }
+
+    pub fn file(&self, id: FileId) -> Option<&SourceFile> {
+        self.files.get(id.as_u32() as usize)
+    }
+
+    /// The file added with `path`
+    pub fn file_by_path(&self, path: &Path) -> Option<&SourceFile> {
+        self.files.iter().find(|file| file.path == path)
+    }
+
+    pub fn path(&self, id: FileId) -> Option<&Path> {
+        self.file(id).map(SourceFile::path)
+    }
+
+    pub fn text(&self, id: FileId) -> Option<&str> {
+        self.file(id).map(SourceFile::text)
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = &SourceFile> {
+        self.files.iter()
+    }
+
+    /// Start and end positions of `span` in its file
+    pub fn range(&self, span: Span) -> Option<(Position, Position)> {
+        let file = self.file(span.file_id)?;
+        Some((file.position(span.start)?, file.position(span.end)?))
+    }
+
+    /// `span` as an LSP range in its file
+    pub fn lsp_range(&self, span: Span) -> Option<lsp_types::Range> {
+        let (start, end) = self.range(span)?;
+        Some(lsp_types::Range { start: start.into(), end: end.into() })
+    }
+
+    /// The text `span` covers in its file
+    pub fn snippet(&self, span: Span) -> Option<&str> {
+        self.file(span.file_id)?.snippet(span)
+    }
+
+    /// `path:line:column` of the start of `span`
+    pub fn location(&self, span: Span) -> Option<String> {
+        self.file(span.file_id)?.location(span.start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_positions_in_several_files() {
+        let mut map = SourceMap::new();
+        let main = map.add_file("src/main.x", "module Main\nlet x = 1\n");
+        let lib = map.add_file("src/lib.x", "module Lib\nlet λ = \"é\"\n");
+        assert_ne!(main, lib);
+        assert_eq!(map.file_by_path(Path::new("src/lib.x")).map(SourceFile::id), Some(lib));
+        assert_eq!(map.path(main), Some(Path::new("src/main.x")));
+
+        let span = Span::new(lib, CharOffset::new(15), CharOffset::new(22));
+        assert_eq!(map.snippet(span), Some("λ = \"é\""));
+        assert_eq!(map.range(span), Some((Position::new(1, 4), Position::new(1, 11))));
+        assert_eq!(map.location(span).as_deref(), Some("src/lib.x:2:5"));
+
+        let file = map.file(lib).unwrap();
+        assert_eq!(file.offset(Position::new(1, 4)), Some(CharOffset::new(15)));
+        assert_eq!(file.offset(Position::new(1, 11)), Some(CharOffset::new(22)));
+        assert_eq!(file.offset(Position::new(1, 12)), None);
+        assert_eq!(file.offset(Position::new(5, 0)), None);
+        assert_eq!(map.snippet(Span::new(FileId::new(7), CharOffset::new(0), CharOffset::new(1))), None);
+    }
+
+    #[test]
+    fn test_set_text_updates_lines() {
+        let mut map = SourceMap::new();
+        let file = map.add_file("a.x", "module A\n");
+        map.set_text(file, "module A\n\nlet x = 1\n").unwrap();
+        let offset = map.file(file).unwrap().offset(Position::new(2, 4)).unwrap();
+        assert_eq!(map.snippet(Span::new(file, offset, offset.advance(1))), Some("x"));
+    }
+}
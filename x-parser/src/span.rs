@@ -25,15 +25,19 @@ impl fmt::Display for FileId {
     }
 }
 
-/// Byte offset in a source file (0-based)
+/// Offset in a source file (0-based), counted in characters
+///
+/// Counting characters rather than UTF-8 bytes keeps offsets independent of
+/// how the source is encoded; `byte_index` finds the bytes of an offset in a
+/// `str`.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
-pub struct ByteOffset(pub u32);
+pub struct CharOffset(pub u32);
 
-impl ByteOffset {
-    pub const INVALID: ByteOffset = ByteOffset(u32::MAX);
+impl CharOffset {
+    pub const INVALID: CharOffset = CharOffset(u32::MAX);
     
     pub fn new(offset: u32) -> Self {
-        ByteOffset(offset)
+        CharOffset(offset)
     }
     
     pub fn as_u32(self) -> u32 {
@@ -41,7 +45,20 @@ impl ByteOffset {
     }
     
     pub fn advance(self, by: u32) -> Self {
-        ByteOffset(self.0 + by)
+        CharOffset(self.0 + by)
+    }
+    
+    /// Index in `text` of the character at this offset, `text.len()` at
+    /// its end, or `None` past that
+    ///
+    /// Non-ASCII text is walked from its start; `LineMap::byte_index` only
+    /// walks the line of the offset.
+    pub fn byte_index(self, text: &str) -> Option<usize> {
+        let offset = self.0 as usize;
+        if text.is_ascii() {
+            return (offset <= text.len()).then_some(offset);
+        }
+        text.char_indices().map(|(index, _)| index).chain(std::iter::once(text.len())).nth(offset)
     }
 }
 
@@ -132,16 +149,16 @@ impl From<lsp_types::Position> for Position {
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Span {
     pub file_id: FileId,
-    pub start: ByteOffset,
-    pub end: ByteOffset,
+    pub start: CharOffset,
+    pub end: CharOffset,
 }
 
 impl Span {
-    pub fn new(file_id: FileId, start: ByteOffset, end: ByteOffset) -> Self {
+    pub fn new(file_id: FileId, start: CharOffset, end: CharOffset) -> Self {
         Span { file_id, start, end }
     }
     
-    pub fn single(file_id: FileId, offset: ByteOffset) -> Self {
+    pub fn single(file_id: FileId, offset: CharOffset) -> Self {
         Span {
             file_id,
             start: offset,
@@ -162,13 +179,13 @@ impl Span {
         assert_eq!(self.file_id, other.file_id);
         Span {
             file_id: self.file_id,
-            start: ByteOffset(self.start.0.min(other.start.0)),
-            end: ByteOffset(self.end.0.max(other.end.0)),
+            start: CharOffset(self.start.0.min(other.start.0)),
+            end: CharOffset(self.end.0.max(other.end.0)),
         }
     }
     
     /// Check if this span contains the given offset
-    pub fn contains(self, offset: ByteOffset) -> bool {
+    pub fn contains(self, offset: CharOffset) -> bool {
         self.start <= offset && offset < self.end
     }
     
@@ -179,6 +196,11 @@ impl Span {
         other.end <= self.end
     }
     
+    /// The text of `source` this span covers, if it lies within it
+    pub fn text(self, source: &str) -> Option<&str> {
+        source.get(self.start.byte_index(source)?..self.end.byte_index(source)?)
+    }
+    
     /// Check if this span overlaps with another span
     pub fn overlaps(self, other: Span) -> bool {
         self.file_id == other.file_id &&
@@ -206,31 +228,57 @@ impl Span {
     }
 }
 
-/// Mapping between byte offsets and line/column positions
+/// Mapping between offsets and line/column positions, both counting
+/// characters
 #[derive(Debug, Clone)]
 pub struct LineMap {
-    /// Byte offsets of the start of each line
-    line_starts: Vec<ByteOffset>,
+    /// Offsets of the start of each line
+    line_starts: Vec<CharOffset>,
+    /// Indices in the source of the same line starts
+    byte_starts: Vec<usize>,
 }
 
 impl LineMap {
     pub fn new(source: &str) -> Self {
-        let mut line_starts = vec![ByteOffset(0)];
+        let mut line_starts = vec![CharOffset(0)];
+        let mut byte_starts = vec![0];
         
-        for (idx, byte) in source.bytes().enumerate() {
-            if byte == b'\n' {
-                line_starts.push(ByteOffset(idx as u32 + 1));
+        for (idx, (byte, ch)) in source.char_indices().enumerate() {
+            if ch == '\n' {
+                line_starts.push(CharOffset(idx as u32 + 1));
+                byte_starts.push(byte + 1);
             }
         }
         
-        LineMap { line_starts }
+        LineMap { line_starts, byte_starts }
+    }
+    
+    /// Index in `source`, the text the map was built from, of the character
+    /// at `offset`, `source.len()` at its end, or `None` past that
+    pub fn byte_index(&self, source: &str, offset: CharOffset) -> Option<usize> {
+        let line = self.line_starts.partition_point(|start| *start <= offset) - 1;
+        let line_start = self.byte_starts[line];
+        let column = (offset.0 - self.line_starts[line].0) as usize;
+        let rest = source.get(line_start..)?;
+        rest.char_indices().map(|(index, _)| index).chain(std::iter::once(rest.len())).nth(column)
+            .map(|index| line_start + index)
+    }
+    
+    /// The text of `line` in `source`, the text the map was built from,
+    /// without its line break
+    pub fn line_text<'a>(&self, source: &'a str, line: Line) -> Option<&'a str> {
+        let line = line.as_u32() as usize;
+        let start = *self.byte_starts.get(line)?;
+        let end = self.byte_starts.get(line + 1).map_or(source.len(), |next| next - 1);
+        let text = source.get(start..end)?;
+        Some(text.strip_suffix('\r').unwrap_or(text))
     }
     
     pub fn line_count(&self) -> u32 {
         self.line_starts.len() as u32
     }
     
-    pub fn offset_to_position(&self, offset: ByteOffset) -> Position {
+    pub fn offset_to_position(&self, offset: CharOffset) -> Position {
         // Binary search for the line containing the offset
         match self.line_starts.binary_search(&offset) {
             Ok(line) => Position {
@@ -248,7 +296,7 @@ impl LineMap {
         }
     }
     
-    pub fn position_to_offset(&self, position: Position) -> Option<ByteOffset> {
+    pub fn position_to_offset(&self, position: Position) -> Option<CharOffset> {
         let line_idx = position.line.as_u32() as usize;
         
         if line_idx >= self.line_starts.len() {
@@ -269,7 +317,7 @@ impl LineMap {
         let start = self.line_starts[line_idx];
         let end = self.line_starts.get(line_idx + 1)
             .copied()
-            .unwrap_or(ByteOffset(u32::MAX));
+            .unwrap_or(CharOffset(u32::MAX));
         
         Some(Span::new(FileId::INVALID, start, end))
     }
@@ -332,48 +380,69 @@ mod tests {
         
         // Test offset to position conversion
         assert_eq!(
-            line_map.offset_to_position(ByteOffset(0)),
+            line_map.offset_to_position(CharOffset(0)),
             Position::new(0, 0)
         );
         assert_eq!(
-            line_map.offset_to_position(ByteOffset(6)),
+            line_map.offset_to_position(CharOffset(6)),
             Position::new(1, 0)
         );
         assert_eq!(
-            line_map.offset_to_position(ByteOffset(12)),
+            line_map.offset_to_position(CharOffset(12)),
             Position::new(2, 0)
         );
         
         // Test position to offset conversion
         assert_eq!(
             line_map.position_to_offset(Position::new(0, 0)),
-            Some(ByteOffset(0))
+            Some(CharOffset(0))
         );
         assert_eq!(
             line_map.position_to_offset(Position::new(1, 0)),
-            Some(ByteOffset(6))
+            Some(CharOffset(6))
         );
     }
 
+    #[test]
+    fn test_offsets_count_characters() {
+        let source = "let λ = \"é\"\nλ";
+        let line_map = LineMap::new(source);
+        assert_eq!(line_map.offset_to_position(CharOffset(12)), Position::new(1, 0));
+        assert_eq!(line_map.offset_to_position(CharOffset(9)), Position::new(0, 9));
+        
+        let span = Span::new(FileId::new(0), CharOffset(4), CharOffset(11));
+        assert_eq!(span.text(source), Some("λ = \"é\""));
+        assert_eq!(CharOffset(13).byte_index(source), Some(source.len()));
+        assert_eq!(CharOffset(14).byte_index(source), None);
+        
+        assert_eq!(line_map.byte_index(source, CharOffset(9)), Some(10));
+        assert_eq!(line_map.byte_index(source, CharOffset(12)), Some(14));
+        assert_eq!(line_map.byte_index(source, CharOffset(13)), Some(source.len()));
+        assert_eq!(line_map.byte_index(source, CharOffset(14)), None);
+        assert_eq!(line_map.line_text(source, Line(0)), Some("let λ = \"é\""));
+        assert_eq!(line_map.line_text(source, Line(1)), Some("λ"));
+        assert_eq!(line_map.line_text(source, Line(2)), None);
+    }
+
     #[test]
     fn test_span_operations() {
         let file_id = FileId::new(1);
-        let span1 = Span::new(file_id, ByteOffset(0), ByteOffset(5));
-        let span2 = Span::new(file_id, ByteOffset(3), ByteOffset(8));
+        let span1 = Span::new(file_id, CharOffset(0), CharOffset(5));
+        let span2 = Span::new(file_id, CharOffset(3), CharOffset(8));
         
         // Test merge
         let merged = span1.merge(span2);
-        assert_eq!(merged.start, ByteOffset(0));
-        assert_eq!(merged.end, ByteOffset(8));
+        assert_eq!(merged.start, CharOffset(0));
+        assert_eq!(merged.end, CharOffset(8));
         
         // Test contains
-        assert!(span1.contains(ByteOffset(2)));
-        assert!(!span1.contains(ByteOffset(5)));
+        assert!(span1.contains(CharOffset(2)));
+        assert!(!span1.contains(CharOffset(5)));
         
         // Test overlaps
         assert!(span1.overlaps(span2));
         
-        let span3 = Span::new(file_id, ByteOffset(10), ByteOffset(15));
+        let span3 = Span::new(file_id, CharOffset(10), CharOffset(15));
         assert!(!span1.overlaps(span3));
     }
 }
\ No newline at end of file
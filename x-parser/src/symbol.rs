@@ -2,12 +2,18 @@
 //! 
 //! This module provides a global symbol table for interning strings,
 //! which is essential for performance when dealing with many identifiers.
+//! The table is sharded so parallel parses rarely contend, and the common
+//! keywords and operators in `symbols` have fixed ids, the same in every
+//! process. Other ids depend on the order names were interned in; a
+//! `SymbolSnapshot` carries their names to another process.
 
 #![allow(non_snake_case)]
 
+use crate::unicode::nfc;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::sync::{Mutex, OnceLock};
+use std::hash::{Hash, Hasher};
+use std::sync::{OnceLock, RwLock};
 use std::fmt;
 
 /// Interned string symbol
@@ -15,21 +21,31 @@ use std::fmt;
 pub struct Symbol(u32);
 
 impl Symbol {
-    /// Intern a string and return its symbol
+    /// Intern a string, in NFC, and return its symbol
     pub fn intern(s: &str) -> Self {
-        GlobalInterner::get().with_mut(|interner| interner.intern(s))
+        interner().intern(&nfc(s))
     }
     
     /// Get the string representation of this symbol
     pub fn as_str(self) -> &'static str {
-        GlobalInterner::get().with(|interner| interner.resolve(self))
+        interner().resolve(self).expect("symbols are only made by interning")
     }
     
     /// Get the raw symbol ID
-    pub fn as_u32(self) -> u32 {
+    pub const fn as_u32(self) -> u32 {
         self.0
     }
     
+    /// The symbol with raw ID `id`, if one has been interned
+    pub fn try_from_u32(id: u32) -> Option<Self> {
+        interner().resolve(Symbol(id)).map(|_| Symbol(id))
+    }
+    
+    /// Whether this is one of `symbols`, whose ID is fixed
+    pub const fn is_predefined(self) -> bool {
+        (self.0 as usize) < symbols::NAMES.len()
+    }
+    
     /// Create a symbol from a raw ID (unsafe - only for deserialization)
     /// 
     /// # Safety
@@ -59,85 +75,147 @@ impl From<String> for Symbol {
     }
 }
 
-/// Thread-safe symbol interner
+/// Number of interner shards; a power of two
+const SHARD_BITS: u32 = 4;
+const SHARDS: usize = 1 << SHARD_BITS;
+
+/// One shard of the interner: names whose hash selects it
+#[derive(Default)]
+struct Shard {
+    /// Names by their index in the shard
+    names: Vec<&'static str>,
+    ids: HashMap<&'static str, u32>,
+}
+
+/// Symbol interner split into independently locked shards, so threads
+/// parsing in parallel rarely wait for each other
+///
+/// The predefined symbols take the ids below `symbols::NAMES.len()`; any
+/// other name gets `predefined + (index in shard << SHARD_BITS | shard)`.
+/// Names are leaked, which is what lets `as_str` return `&'static str`.
 struct SymbolInterner {
-    symbols: Vec<String>,
-    indices: HashMap<String, u32>,
+    shards: [RwLock<Shard>; SHARDS],
 }
 
 impl SymbolInterner {
     fn new() -> Self {
-        SymbolInterner {
-            symbols: Vec::new(),
-            indices: HashMap::new(),
+        let interner = SymbolInterner { shards: std::array::from_fn(|_| RwLock::default()) };
+        for (id, name) in symbols::NAMES.iter().enumerate() {
+            interner.shards[Self::shard_of(name)].write().unwrap().ids.insert(name, id as u32);
         }
+        interner
     }
-    
-    fn intern(&mut self, s: &str) -> Symbol {
-        if let Some(&index) = self.indices.get(s) {
-            Symbol(index)
-        } else {
-            let index = self.symbols.len() as u32;
-            self.symbols.push(s.to_string());
-            self.indices.insert(s.to_string(), index);
-            Symbol(index)
-        }
+
+    fn shard_of(s: &str) -> usize {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        s.hash(&mut hasher);
+        hasher.finish() as usize & (SHARDS - 1)
     }
-    
-    fn resolve(&self, symbol: Symbol) -> &'static str {
-        // SAFETY: This is a memory leak, but it's intentional for symbols
-        // In a real implementation, you'd use Arena allocation or Arc<str>
-        unsafe {
-            let s = &self.symbols[symbol.0 as usize];
-            std::mem::transmute::<&str, &'static str>(s.as_str())
+
+    fn intern(&self, s: &str) -> Symbol {
+        let shard_index = Self::shard_of(s);
+        let shard = &self.shards[shard_index];
+        if let Some(&id) = shard.read().unwrap().ids.get(s) {
+            return Symbol(id);
         }
+        let mut shard = shard.write().unwrap();
+        // Another thread may have interned it since the read
+        if let Some(&id) = shard.ids.get(s) {
+            return Symbol(id);
+        }
+        let name: &'static str = Box::leak(s.into());
+        let id = symbols::NAMES.len() as u32 + ((shard.names.len() as u32) << SHARD_BITS | shard_index as u32);
+        shard.names.push(name);
+        shard.ids.insert(name, id);
+        Symbol(id)
     }
-    
+
+    fn resolve(&self, symbol: Symbol) -> Option<&'static str> {
+        let Some(id) = symbol.0.checked_sub(symbols::NAMES.len() as u32) else {
+            return Some(symbols::NAMES[symbol.0 as usize]);
+        };
+        let shard = self.shards[(id as usize) & (SHARDS - 1)].read().unwrap();
+        shard.names.get((id >> SHARD_BITS) as usize).copied()
+    }
+
     fn len(&self) -> usize {
-        self.symbols.len()
+        symbols::NAMES.len() + self.shards.iter().map(|shard| shard.read().unwrap().names.len()).sum::<usize>()
     }
 }
 
-/// Global symbol interner with thread-safe access
-struct GlobalInterner {
-    inner: Mutex<SymbolInterner>,
+static INTERNER: OnceLock<SymbolInterner> = OnceLock::new();
+
+fn interner() -> &'static SymbolInterner {
+    INTERNER.get_or_init(SymbolInterner::new)
 }
 
-impl GlobalInterner {
-    fn new() -> Self {
-        GlobalInterner {
-            inner: Mutex::new(SymbolInterner::new()),
-        }
+/// The names of some symbols by raw id, so that raw ids written by one
+/// process, as serde does, can be mapped to symbols in another
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SymbolSnapshot {
+    /// `(raw id, name)` sorted by id; predefined symbols are left out, as
+    /// their ids are the same in every process
+    entries: Vec<(u32, String)>,
+}
+
+impl SymbolSnapshot {
+    pub fn capture(symbols: impl IntoIterator<Item = Symbol>) -> Self {
+        let mut entries: Vec<(u32, String)> = symbols.into_iter()
+            .filter(|symbol| !symbol.is_predefined())
+            .map(|symbol| (symbol.0, symbol.as_str().to_string()))
+            .collect();
+        entries.sort();
+        entries.dedup();
+        SymbolSnapshot { entries }
     }
-    
-    fn with_mut<R>(&self, f: impl FnOnce(&mut SymbolInterner) -> R) -> R {
-        f(&mut self.inner.lock().unwrap())
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
     }
-    
-    fn with<R>(&self, f: impl FnOnce(&SymbolInterner) -> R) -> R {
-        f(&self.inner.lock().unwrap())
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Intern the snapshot's names in this process
+    pub fn restore(&self) -> SymbolMap {
+        SymbolMap {
+            symbols: self.entries.iter().map(|(id, name)| (*id, Symbol::intern(name))).collect(),
+        }
     }
 }
 
-static INTERNER: OnceLock<GlobalInterner> = OnceLock::new();
+/// Raw ids of a `SymbolSnapshot` mapped to this process's symbols
+#[derive(Debug, Clone, Default)]
+pub struct SymbolMap {
+    symbols: HashMap<u32, Symbol>,
+}
 
-impl GlobalInterner {
-    fn get() -> &'static GlobalInterner {
-        INTERNER.get_or_init(GlobalInterner::new)
+impl SymbolMap {
+    /// The symbol a raw id of the snapshot's process stands for
+    pub fn get(&self, id: u32) -> Option<Symbol> {
+        if (id as usize) < symbols::NAMES.len() {
+            return Some(Symbol(id));
+        }
+        self.symbols.get(&id).copied()
     }
 }
 
-/// Commonly used symbols (pre-interned for efficiency)
+/// Commonly used symbols, pre-interned with fixed ids
 pub mod symbols {
     use super::Symbol;
-    use std::sync::OnceLock;
-    
+
     macro_rules! define_symbols {
         ($($name:ident = $value:literal),* $(,)?) => {
+            #[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+            enum Predefined { $($name),* }
+
+            /// Names of the predefined symbols, indexed by id
+            pub(super) const NAMES: &[&str] = &[$($value),*];
+
             $(
-                pub fn $name() -> Symbol {
-                    static SYMBOL: OnceLock<Symbol> = OnceLock::new();
-                    *SYMBOL.get_or_init(|| Symbol::intern($value))
+                pub const fn $name() -> Symbol {
+                    Symbol(Predefined::$name as u32)
                 }
             )*
         };
@@ -147,6 +225,7 @@ pub mod symbols {
         // Special symbols
         UNDERSCORE = "_",
         UNIT = "()",
+        NIL = "[]",
         
         // Built-in types
         INT = "Int",
@@ -189,6 +268,7 @@ pub mod symbols {
         MINUS = "-",
         MULTIPLY = "*",
         DIVIDE = "/",
+        MODULO = "%",
         EQUAL = "=",
         EQUAL_EQUAL = "==",
         NOT_EQUAL = "!=",
@@ -203,6 +283,10 @@ pub mod symbols {
         FAT_ARROW = "=>",
         PIPE = "|",
         CONS = "::",
+        CARET = "^",
+        PIPE_FORWARD = "|>",
+        COMPOSE_RIGHT = ">>",
+        COMPOSE_LEFT = "<<",
         
         // Common function names
         MAP = "map",
@@ -233,17 +317,7 @@ pub mod symbols {
     
     /// Get all predefined symbols for initialization
     pub fn all_predefined() -> Vec<Symbol> {
-        vec![
-            UNDERSCORE(), UNIT(), INT(), FLOAT(), STRING(), BOOL(), UNIT_TYPE(),
-            IO(), STATE(), EXCEPT(), ASYNC(), LET(), FUN(), IN(), IF(), THEN(),
-            ELSE(), MATCH(), WITH(), DO(), HANDLE(), RESUME(), RETURN(), EFFECT(),
-            HANDLER(), TYPE(), DATA(), MODULE(), IMPORT(), EXPORT(), PURE(), FORALL(),
-            PLUS(), MINUS(), MULTIPLY(), DIVIDE(), EQUAL(), EQUAL_EQUAL(), NOT_EQUAL(),
-            LESS(), LESS_EQUAL(), GREATER(), GREATER_EQUAL(), AND(), OR(), NOT(),
-            ARROW(), FAT_ARROW(), PIPE(), CONS(), MAP(), FILTER(), FOLD(), PRINT(),
-            READ(), WRITE(), GET(), PUT(), THROW(), CATCH(), PRELUDE(), LIST(),
-            OPTION(), RESULT(), CORE(), STD(), MAIN(), INIT(), UPDATE(), VIEW(),
-        ]
+        (0..NAMES.len() as u32).map(Symbol).collect()
     }
 }
 
@@ -375,11 +449,9 @@ pub fn init_symbols() {
 
 /// Get statistics about the symbol interner
 pub fn interner_stats() -> InternerStats {
-    GlobalInterner::get().with(|interner| {
-        InternerStats {
-            symbol_count: interner.len(),
-        }
-    })
+    InternerStats {
+        symbol_count: interner().len(),
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -390,7 +462,7 @@ pub struct InternerStats {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::span::{FileId, ByteOffset, Span};
+    use crate::span::{FileId, CharOffset, Span};
 
     #[test]
     fn test_symbol_interning() {
@@ -417,7 +489,7 @@ mod tests {
     #[test]
     fn test_symbol_table() {
         let mut table = SymbolTable::new();
-        let span = Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(5));
+        let span = Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(5));
         
         let var_info = SymbolInfo {
             symbol: Symbol::intern("x"),
@@ -442,6 +514,44 @@ mod tests {
         assert!(table.lookup(Symbol::intern("x")).is_some());
     }
 
+    #[test]
+    fn test_parallel_interning_agrees() {
+        let names: Vec<String> = (0..500).map(|i| format!("parallel_{i}")).collect();
+        let results: Vec<Vec<Symbol>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..8)
+                .map(|_| scope.spawn(|| names.iter().map(|name| Symbol::intern(name)).collect()))
+                .collect();
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+        });
+        assert!(results.iter().all(|symbols| *symbols == results[0]));
+        for (name, symbol) in names.iter().zip(&results[0]) {
+            assert_eq!(symbol.as_str(), name);
+            assert_eq!(Symbol::try_from_u32(symbol.as_u32()), Some(*symbol));
+        }
+    }
+
+    #[test]
+    fn test_predefined_ids_are_fixed() {
+        assert_eq!(symbols::UNDERSCORE().as_u32(), 0);
+        assert_eq!(Symbol::intern("+"), symbols::PLUS());
+        assert_eq!(symbols::CONS().as_str(), "::");
+        assert!(symbols::all_predefined().iter().all(|symbol| symbol.is_predefined()));
+        assert!(!Symbol::intern("not_predefined").is_predefined());
+        assert_eq!(Symbol::try_from_u32(u32::MAX), None);
+    }
+
+    #[test]
+    fn test_snapshot_restores_names() {
+        let local = Symbol::intern("snapshot_local");
+        let snapshot = SymbolSnapshot::capture([local, symbols::INT(), local]);
+        assert_eq!(snapshot.len(), 1);
+        let json = serde_json::to_string(&snapshot).unwrap();
+        let map = serde_json::from_str::<SymbolSnapshot>(&json).unwrap().restore();
+        assert_eq!(map.get(local.as_u32()), Some(local));
+        assert_eq!(map.get(symbols::INT().as_u32()), Some(symbols::INT()));
+        assert_eq!(map.get(u32::MAX), None);
+    }
+
     #[test]
     fn test_symbol_conversion() {
         let sym1: Symbol = "test".into();
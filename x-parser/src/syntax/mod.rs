@@ -5,10 +5,11 @@
 //! the same underlying semantic structure.
 
 pub mod sexp;
+pub mod ocaml;
 pub mod printer;
 pub mod converter;
 
-use crate::{ast::*, span::FileId};
+use crate::{ast::*, span::FileId, trivia::Trivia};
 use crate::error::{ParseError as Error, Result};
 use std::fmt;
 
@@ -17,12 +18,15 @@ use std::fmt;
 pub enum SyntaxStyle {
     /// S-expression syntax (Lisp-like)
     SExp,
+    /// OCaml-like syntax of textual `.x` sources
+    OCaml,
 }
 
 impl fmt::Display for SyntaxStyle {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             SyntaxStyle::SExp => write!(f, "sexp"),
+            SyntaxStyle::OCaml => write!(f, "ocaml"),
         }
     }
 }
@@ -33,6 +37,7 @@ impl std::str::FromStr for SyntaxStyle {
     fn from_str(s: &str) -> Result<Self> {
         match s.to_lowercase().as_str() {
             "sexp" | "sexpr" | "lisp" => Ok(SyntaxStyle::SExp),
+            "ocaml" | "ml" => Ok(SyntaxStyle::OCaml),
             _ => Err(Error::Parse {
                 message: format!("Unknown syntax style: {s}"),
             }),
@@ -67,6 +72,12 @@ pub trait SyntaxParser {
     /// Parse source code into AST
     fn parse(&mut self, input: &str, file_id: FileId) -> Result<CompilationUnit>;
     
+    /// Parse source code into AST, keeping the comments and blank lines the
+    /// AST has no place for
+    fn parse_with_trivia(&mut self, input: &str, file_id: FileId) -> Result<(CompilationUnit, Trivia)> {
+        Ok((self.parse(input, file_id)?, Trivia::new()))
+    }
+    
     /// Parse expression from string (for REPL/testing)
     fn parse_expression(&mut self, input: &str, file_id: FileId) -> Result<Expr>;
     
@@ -79,6 +90,12 @@ pub trait SyntaxPrinter {
     /// Print AST to source code
     fn print(&self, ast: &CompilationUnit, config: &SyntaxConfig) -> Result<String>;
     
+    /// Print AST to source code, re-emitting the comments of the source it
+    /// was parsed from when `config.preserve_comments` is set
+    fn print_with_trivia(&self, ast: &CompilationUnit, _trivia: &Trivia, config: &SyntaxConfig) -> Result<String> {
+        self.print(ast, config)
+    }
+    
     /// Print expression to string (for REPL/testing)
     fn print_expression(&self, expr: &Expr, config: &SyntaxConfig) -> Result<String>;
     
@@ -155,17 +172,35 @@ impl MultiSyntax {
         }
     }
     
-    /// Convert code from one syntax style to another
+    /// Parse code in the specified syntax style, keeping its comments
+    pub fn parse_with_trivia(&mut self, input: &str, style: SyntaxStyle, file_id: FileId) -> Result<(CompilationUnit, Trivia)> {
+        match self.parsers.get_mut(&style) {
+            Some(parser) => parser.parse_with_trivia(input, file_id),
+            None => Err(Error::Parse {
+                message: format!("No parser registered for syntax style: {style}"),
+            }),
+        }
+    }
+    
+    /// Print AST in the specified syntax style, re-emitting the comments of
+    /// the source it was parsed from
+    pub fn print_with_trivia(&self, ast: &CompilationUnit, trivia: &Trivia, config: &SyntaxConfig) -> Result<String> {
+        match self.printers.get(&config.style) {
+            Some(printer) => printer.print_with_trivia(ast, trivia, config),
+            None => Err(Error::Parse {
+                message: format!("No printer registered for syntax style: {}", config.style),
+            }),
+        }
+    }
+    
+    /// Convert code from one syntax style to another, keeping its comments
     pub fn convert(&mut self, input: &str, from: SyntaxStyle, to: SyntaxStyle, file_id: FileId) -> Result<String> {
-        // Parse with source syntax
-        let ast = self.parse(input, from, file_id)?;
-        
-        // Print with target syntax
+        let (ast, trivia) = self.parse_with_trivia(input, from, file_id)?;
         let config = SyntaxConfig {
             style: to,
             ..Default::default()
         };
-        self.print(&ast, &config)
+        self.print_with_trivia(&ast, &trivia, &config)
     }
     
     /// Get list of supported syntax styles
@@ -181,6 +216,8 @@ impl Default for MultiSyntax {
         // Register all parsers and printers
         multi.register_parser(Box::new(sexp::SExpParser::new()));
         multi.register_printer(Box::new(sexp::SExpPrinter::new()));
+        multi.register_parser(Box::new(ocaml::OCamlParser::new()));
+        multi.register_printer(Box::new(ocaml::OCamlPrinter::new()));
         
         multi
     }
@@ -194,6 +231,7 @@ mod tests {
     #[test]
     fn test_syntax_style_parsing() {
         assert_eq!("sexp".parse::<SyntaxStyle>().unwrap(), SyntaxStyle::SExp);
+        assert_eq!("ocaml".parse::<SyntaxStyle>().unwrap(), SyntaxStyle::OCaml);
         
         assert!("unknown".parse::<SyntaxStyle>().is_err());
     }
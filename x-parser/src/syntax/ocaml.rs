@@ -0,0 +1,1395 @@
+//! OCaml-like syntax parser and printer
+//!
+//! This is the syntax textual `.x` sources are written in:
+//!
+//! ```text
+//! pub let length = fun list ->
+//!   match list with
+//!   | Nil => 0
+//!   | Cons _ rest => 1 + length rest
+//! ```
+//!
+//! The printer produces one canonical layout. A construct stays on one line
+//! when it fits in `max_line_length` and is broken over several otherwise,
+//! `match` arms always go one per line, and parentheses are exactly the ones
+//! the parser needs. Printing a parse of the printer's own output therefore
+//! gives the same text back.
+//!
+//! Given the source's [`Trivia`], the printer keeps its `--` comments: a
+//! comment on a line of its own goes above the next import, item or match arm,
+//! one after code stays at the end of that item's or arm's last line, and
+//! comments elsewhere inside an item are moved above it.
+
+use super::{SyntaxParser, SyntaxPrinter, SyntaxStyle, SyntaxConfig};
+use crate::{ast::*, parser::Parser, span::{CharOffset, FileId}, symbol::Symbol, token::is_operator_name};
+use crate::error::{ParseError as Error, Result};
+use crate::trivia::{Comment, CommentCursor, Trivia};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// OCaml-like syntax parser
+pub struct OCamlParser;
+
+impl Default for OCamlParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OCamlParser {
+    pub fn new() -> Self {
+        OCamlParser
+    }
+}
+
+impl SyntaxParser for OCamlParser {
+    fn parse(&mut self, input: &str, file_id: FileId) -> Result<CompilationUnit> {
+        Parser::new(input, file_id)?.parse()
+    }
+
+    fn parse_with_trivia(&mut self, input: &str, file_id: FileId) -> Result<(CompilationUnit, Trivia)> {
+        let mut parser = Parser::new(input, file_id)?;
+        let unit = parser.parse()?;
+        Ok((unit, parser.take_trivia()))
+    }
+
+    fn parse_expression(&mut self, input: &str, file_id: FileId) -> Result<Expr> {
+        Parser::new(input, file_id)?.parse_expression_public()
+    }
+
+    fn syntax_style(&self) -> SyntaxStyle {
+        SyntaxStyle::OCaml
+    }
+}
+
+/// OCaml-like syntax printer
+#[derive(Default)]
+pub struct OCamlPrinter {
+    /// Text the printed AST was parsed from, so doc comments are kept verbatim
+    source: Option<String>,
+    /// Fixities of operators declared outside the printed tree
+    fixities: HashMap<Symbol, Fixity>,
+}
+
+impl OCamlPrinter {
+    pub fn new() -> Self {
+        OCamlPrinter::default()
+    }
+
+    /// Printer copying doc comments from `source` instead of rebuilding them
+    /// from their parsed form
+    pub fn with_source(source: impl Into<String>) -> Self {
+        OCamlPrinter { source: Some(source.into()), ..OCamlPrinter::default() }
+    }
+
+    /// Parenthesize uses of operators declared elsewhere, such as those a
+    /// printed item's module declares or imports, by the given fixities
+    pub fn with_fixities(mut self, fixities: impl IntoIterator<Item = (Symbol, Fixity)>) -> Self {
+        self.fixities.extend(fixities);
+        self
+    }
+
+    /// Print a single module item, as it appears in a printed module
+    pub fn print_item(&self, item: &Item, config: &SyntaxConfig) -> Result<String> {
+        Layout::new(config, self.source.as_deref(), &Trivia::new(), self.fixities.clone()).item(item)
+    }
+}
+
+impl SyntaxPrinter for OCamlPrinter {
+    fn print(&self, ast: &CompilationUnit, config: &SyntaxConfig) -> Result<String> {
+        self.print_with_trivia(ast, &Trivia::new(), config)
+    }
+
+    fn print_with_trivia(&self, ast: &CompilationUnit, trivia: &Trivia, config: &SyntaxConfig) -> Result<String> {
+        let fixities = self.fixities.clone().into_iter().chain(ast.module.fixities()).collect();
+        Layout::new(config, self.source.as_deref(), trivia, fixities).unit(ast)
+    }
+
+    fn print_expression(&self, expr: &Expr, config: &SyntaxConfig) -> Result<String> {
+        Layout::new(config, self.source.as_deref(), &Trivia::new(), self.fixities.clone()).expr(expr, Position::Top, 0, 0)
+    }
+
+    fn print_type(&self, typ: &Type, config: &SyntaxConfig) -> Result<String> {
+        Layout::new(config, self.source.as_deref(), &Trivia::new(), self.fixities.clone()).typ(typ)
+    }
+
+    fn syntax_style(&self) -> SyntaxStyle {
+        SyntaxStyle::OCaml
+    }
+}
+
+/// Where an expression is printed, which decides whether it needs parentheses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Position {
+    /// Delimited on both sides: a definition body, a branch, a tuple element
+    Top,
+    /// Body of a match arm; more arms follow unless `last`
+    Arm { last: bool },
+    /// Left operand of an operator with the given fixity, if it is known
+    Left(Option<Fixity>),
+    /// Right operand of an operator with the given fixity, if it is known
+    Right(Option<Fixity>),
+    /// Function, argument or projected record of an application
+    Argument,
+}
+
+/// How an expression binds, as far as parenthesisation is concerned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Shape {
+    Atom,
+    Application,
+    Binary(Option<Fixity>),
+    /// `fun`, `if` and `match` extend as far right as they can
+    Open,
+}
+
+/// Fixity of the binary operator `name`: fixed for the built-in operators,
+/// mirroring `TokenKind::precedence`, and as declared in `fixities` for the
+/// rest; `None` for an operator declared elsewhere, such as in a module the
+/// printed one imports
+fn operator(name: &str, fixities: &HashMap<Symbol, Fixity>) -> Option<Fixity> {
+    let (precedence, associativity) = match name {
+        "|>" => (0, Associativity::Left),
+        "||" => (1, Associativity::Left),
+        "&&" => (2, Associativity::Left),
+        "==" | "!=" => (3, Associativity::Left),
+        "<" | "<=" | ">" | ">=" => (4, Associativity::Left),
+        "::" => (5, Associativity::Right),
+        "^" => (6, Associativity::Left),
+        "+" | "-" => (7, Associativity::Left),
+        "*" | "/" | "%" => (8, Associativity::Left),
+        ">>" => (10, Associativity::Left),
+        "<<" => (10, Associativity::Right),
+        _ => return fixities.get(&Symbol::intern(name)).copied(),
+    };
+    Some(Fixity { associativity, precedence })
+}
+
+/// Whether `name` is written between its operands
+fn is_binary_operator(name: &str) -> bool {
+    name == "::" || is_operator_name(name)
+}
+
+/// A value's name as written outside an application, `(<+>)` for an operator
+fn value_name(name: Symbol) -> String {
+    if is_binary_operator(name.as_str()) {
+        format!("({name})")
+    } else {
+        name.to_string()
+    }
+}
+
+/// `l op r`, which the parser represents as `App(Var(op), [l, r])`
+fn binary(expr: &Expr) -> Option<(&str, &Expr, &Expr)> {
+    match expr {
+        Expr::App(function, args, _) if args.len() == 2 => match function.as_ref() {
+            Expr::Var(name, _) if is_binary_operator(name.as_str()) => Some((name.as_str(), &args[0], &args[1])),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Elements of a `::` chain ending in `[]`, which is how list literals parse
+fn list_elements(expr: &Expr) -> Option<Vec<&Expr>> {
+    let mut elements = Vec::new();
+    let mut rest = expr;
+    while let Some(("::", head, tail)) = binary(rest) {
+        elements.push(head);
+        rest = tail;
+    }
+    match rest {
+        Expr::Var(name, _) if name.as_str() == "[]" && !elements.is_empty() => Some(elements),
+        _ => None,
+    }
+}
+
+/// A function and all its arguments, with curried applications flattened
+fn spine(expr: &Expr) -> (&Expr, Vec<&Expr>) {
+    match expr {
+        Expr::App(function, args, _) => {
+            let (head, mut all) = spine(function);
+            all.extend(args);
+            (head, all)
+        }
+        _ => (expr, Vec::new()),
+    }
+}
+
+fn shape(expr: &Expr, fixities: &HashMap<Symbol, Fixity>) -> Shape {
+    match expr {
+        Expr::App(..) => match binary(expr) {
+            _ if list_elements(expr).is_some() => Shape::Atom,
+            Some((op, _, _)) => Shape::Binary(operator(op, fixities)),
+            None => Shape::Application,
+        },
+        Expr::Resume { .. } | Expr::Pack { .. } => Shape::Application,
+        // A section prints in its own parentheses
+        Expr::Lambda { .. } if expr.section_body().is_some() => Shape::Atom,
+        Expr::Lambda { .. } | Expr::If { .. } | Expr::Match { .. } => Shape::Open,
+        _ => Shape::Atom,
+    }
+}
+
+/// Whether a `match` at the right end of `expr` would take over the arms
+/// following it
+fn ends_in_match(expr: &Expr) -> bool {
+    match expr {
+        Expr::Match { .. } => true,
+        Expr::Lambda { body, .. } => ends_in_match(body),
+        Expr::If { else_branch, .. } => ends_in_match(else_branch),
+        _ => false,
+    }
+}
+
+fn needs_parens(expr: &Expr, position: Position, fixities: &HashMap<Symbol, Fixity>) -> bool {
+    match (shape(expr, fixities), position) {
+        (Shape::Atom, _) | (_, Position::Top) => false,
+        (Shape::Open, Position::Arm { last }) => !last && ends_in_match(expr),
+        (_, Position::Arm { .. }) => false,
+        (Shape::Application, position) => position == Position::Argument,
+        // Operators of unknown fixity keep their operands parenthesized, so
+        // the output means the same whatever the fixity is
+        (Shape::Binary(Some(fixity)), Position::Left(Some(parent))) => {
+            fixity.precedence < parent.precedence
+                || (fixity.precedence == parent.precedence && parent.associativity != Associativity::Left)
+        }
+        (Shape::Binary(Some(fixity)), Position::Right(Some(parent))) => {
+            fixity.precedence < parent.precedence
+                || (fixity.precedence == parent.precedence && parent.associativity != Associativity::Right)
+        }
+        (Shape::Binary(_), Position::Left(_) | Position::Right(_)) => true,
+        (Shape::Binary(..), Position::Argument) | (Shape::Open, _) => true,
+    }
+}
+
+fn unsupported(what: &str) -> Error {
+    Error::Parse {
+        message: format!("{what} cannot be written in OCaml syntax"),
+    }
+}
+
+fn string_literal(value: &str) -> String {
+    let mut out = String::from("\"");
+    for ch in value.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn literal(value: &Literal) -> String {
+    match value {
+        // The lexer reads `-` as an operator, so negative numbers are written as a subtraction
+        Literal::Integer(n, format) if *n < 0 => {
+            format!("(0 - {})", format.format_integer(n.unsigned_abs() as i64))
+        }
+        Literal::Float(x, format) if x.is_sign_negative() => format!("(0.0 - {})", format.format_float(-x)),
+        Literal::String(s) => string_literal(s),
+        other => other.to_string(),
+    }
+}
+
+/// A version requirement as the lexer reads it after `@`, quoted otherwise
+fn version_spec(spec: &str) -> String {
+    let bare_requirement = spec.starts_with(|c: char| c.is_ascii_digit() || "^~=<>".contains(c))
+        && spec.chars().all(|c| c.is_ascii_alphanumeric() || "^~=<>.*+-".contains(c));
+    let identifier = spec.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        && spec.chars().all(|c| c.is_alphanumeric() || c == '_');
+    if bare_requirement || identifier {
+        spec.to_string()
+    } else {
+        string_literal(spec)
+    }
+}
+
+fn visibility(visibility: &Visibility) -> Result<String> {
+    Ok(match visibility {
+        Visibility::Private => String::new(),
+        Visibility::Public => "pub ".to_string(),
+        Visibility::Crate => "pub(crate) ".to_string(),
+        Visibility::Package => "pub(package) ".to_string(),
+        Visibility::Super => "pub(super) ".to_string(),
+        Visibility::SelfModule => "pub(self) ".to_string(),
+        Visibility::InPath(path) => format!("pub(in {path}) "),
+        Visibility::Component { .. } => return Err(unsupported("component visibility")),
+    })
+}
+
+fn type_params(params: &[TypeParam]) -> String {
+    if params.is_empty() {
+        return String::new();
+    }
+    let names: Vec<&str> = params.iter().map(|param| param.name.as_str()).collect();
+    format!("[{}]", names.join(", "))
+}
+
+fn wasm_type(typ: &WasmType) -> String {
+    match typ {
+        WasmType::I32 => "i32".to_string(),
+        WasmType::I64 => "i64".to_string(),
+        WasmType::F32 => "f32".to_string(),
+        WasmType::F64 => "f64".to_string(),
+        WasmType::V128 => "v128".to_string(),
+        WasmType::FuncRef => "funcref".to_string(),
+        WasmType::ExternRef => "externref".to_string(),
+        WasmType::Named(name) => name.to_string(),
+    }
+}
+
+fn function_signature(signature: &FunctionSignature) -> String {
+    let types = |types: &[WasmType]| types.iter().map(|t| format!(" {}", wasm_type(t))).collect::<String>();
+    let mut out = String::new();
+    // Results are the second group, so a signature with results always has a params group
+    if !signature.params.is_empty() || !signature.results.is_empty() {
+        out.push_str(&format!(" (param{})", types(&signature.params)));
+    }
+    if !signature.results.is_empty() {
+        out.push_str(&format!(" (result{})", types(&signature.results)));
+    }
+    out
+}
+
+fn attribute_value(value: &DocAttributeValue) -> Option<String> {
+    Some(match value {
+        DocAttributeValue::String(s) => s.clone(),
+        DocAttributeValue::Number(n) => n.to_string(),
+        DocAttributeValue::Boolean(b) => b.to_string(),
+        DocAttributeValue::List(items) => format!("[{}]", items.join(", ")),
+        DocAttributeValue::TypedParam { type_info, description } => format!("{{{type_info}}} {description}"),
+        DocAttributeValue::Object(_) => return None,
+    })
+}
+
+struct Layout<'a> {
+    config: &'a SyntaxConfig,
+    source: Option<&'a str>,
+    trivia: &'a Trivia,
+    /// Comments not printed yet
+    comments: RefCell<CommentCursor<'a>>,
+    /// Fixities of the operators the printed code may use
+    fixities: HashMap<Symbol, Fixity>,
+}
+
+impl<'a> Layout<'a> {
+    fn new(config: &'a SyntaxConfig, source: Option<&'a str>, trivia: &'a Trivia, fixities: HashMap<Symbol, Fixity>) -> Self {
+        let comments = if config.preserve_comments { trivia.comments.as_slice() } else { &[] };
+        Layout { config, source, trivia, comments: RefCell::new(CommentCursor::new(comments)), fixities }
+    }
+
+    fn indent(&self, level: usize) -> String {
+        if self.config.use_tabs {
+            "\t".repeat(level)
+        } else {
+            " ".repeat(level * self.config.indent_size)
+        }
+    }
+
+    fn indent_width(&self, level: usize) -> usize {
+        level * self.config.indent_size
+    }
+
+    /// Column after printing `text` from `column`
+    fn end_column(&self, column: usize, text: &str) -> usize {
+        let width = |line: &str| line.chars().map(|c| if c == '\t' { self.config.indent_size } else { 1 }).sum::<usize>();
+        match text.rsplit_once('\n') {
+            Some((_, last)) => width(last),
+            None => column + width(text),
+        }
+    }
+
+    fn fits(&self, column: usize, text: &str) -> bool {
+        !text.contains('\n') && self.end_column(column, text) <= self.config.max_line_length
+    }
+
+    /// Comments not printed yet that start before `offset`, one per line at
+    /// `level`
+    fn comments_before(&self, offset: CharOffset, level: usize) -> String {
+        let comments = self.comments.borrow_mut().before(offset);
+        self.comment_lines(comments, offset, level)
+    }
+
+    /// `comments` one per line, keeping the blank lines between them and the
+    /// one before `next`
+    fn comment_lines(&self, comments: &[Comment], next: CharOffset, level: usize) -> String {
+        let indent = self.indent(level);
+        let mut out = String::new();
+        for (i, comment) in comments.iter().enumerate() {
+            out.push_str(&format!("{indent}--{}\n", comment.text));
+            let following = comments.get(i + 1).map_or(next, |comment| comment.span.start);
+            if self.trivia.blank_line_between(comment.span.end, following) {
+                out.push('\n');
+            }
+        }
+        out
+    }
+
+    /// The comment ending the last line of the construct starting at `from`,
+    /// if there is one before `until`
+    fn trailing_comment(&self, from: CharOffset, until: CharOffset) -> String {
+        self.comments.borrow_mut().trailing(from, until)
+            .map(|comment| format!(" --{}", comment.text))
+            .unwrap_or_default()
+    }
+
+    fn unit(&self, unit: &CompilationUnit) -> Result<String> {
+        let module = &unit.module;
+        // Module documentation is `--!` comments, printed with the others
+        let mut header = self.comments_before(module.span.start, 0);
+        header.push_str(&format!("module {}", module.name));
+        if let Some(exports) = &module.exports {
+            let items = exports.items.iter().map(|item| {
+                let kind = match item.kind {
+                    ExportKind::Value => "",
+                    ExportKind::Type => "type ",
+                    ExportKind::Effect => "effect ",
+                    ExportKind::Module => "module ",
+                    _ => return Err(unsupported("component exports")),
+                };
+                let alias = item.alias.map(|alias| format!("({alias})")).unwrap_or_default();
+                Ok(format!("{kind}{}{alias}", value_name(item.name)))
+            }).collect::<Result<Vec<_>>>()?;
+            header.push_str(&format!(" export {{ {} }}", items.join(", ")));
+        }
+
+        let extents: Vec<_> = module.items.iter().enumerate()
+            .map(|(i, item)| self.trivia.item_extent(i, item.span()))
+            .collect();
+        let item_start = |i: usize| extents.get(i).map_or(CharOffset::INVALID, |extent| extent.0);
+
+        let mut sections = vec![header];
+        if !module.imports.is_empty() {
+            let mut imports = Vec::new();
+            for (i, import) in module.imports.iter().enumerate() {
+                let comments = self.comments_before(import.span.start, 0);
+                let line = self.import(import)?;
+                let next = module.imports.get(i + 1).map_or(item_start(0), |import| import.span.start);
+                imports.push(format!("{comments}{line}{}", self.trailing_comment(import.span.start, next)));
+            }
+            sections.push(imports.join("\n"));
+        }
+        for (i, item) in module.items.iter().enumerate() {
+            let (start, end) = extents[i];
+            let comments = self.comments_before(start, 0);
+            let text = self.item(item)?;
+            // Comments inside the item that no match arm took go above it
+            let inner = self.comments.borrow_mut().before(end);
+            let inner = self.comment_lines(inner, start, 0);
+            let trailing = self.trailing_comment(start, item_start(i + 1));
+            sections.push(format!("{comments}{inner}{text}{trailing}"));
+        }
+        let rest = self.comments.borrow_mut().rest();
+        if !rest.is_empty() {
+            sections.push(self.comment_lines(rest, CharOffset::INVALID, 0).trim_end().to_string());
+        }
+        Ok(format!("{}\n", sections.join("\n\n")))
+    }
+
+    fn import(&self, import: &Import) -> Result<String> {
+        let mut out = match import.kind {
+            ImportKind::Lazy => "lazy import ".to_string(),
+            _ => "import ".to_string(),
+        };
+        out.push_str(&import.module_path.to_string());
+        if let Some(spec) = &import.version_spec {
+            out.push_str(&format!("@{}", version_spec(spec)));
+        }
+        match &import.kind {
+            ImportKind::Qualified | ImportKind::Lazy | ImportKind::Conditional(_) => {}
+            ImportKind::Wildcard => out.push_str(".*"),
+            ImportKind::Signature(signature) => out.push_str(&format!(" : {signature}")),
+            ImportKind::Selective(items) => {
+                let items = items.iter().map(|item| {
+                    let kind = match item.kind {
+                        ExportKind::Value => "",
+                        ExportKind::Type => "type ",
+                        ExportKind::Effect => "effect ",
+                        _ => return Err(unsupported("component imports")),
+                    };
+                    let version = item.version_spec.as_deref().map(|spec| format!("@{}", version_spec(spec))).unwrap_or_default();
+                    let alias = item.alias.map(|alias| format!(" as {}", value_name(alias))).unwrap_or_default();
+                    Ok(format!("{kind}{}{version}{alias}", value_name(item.name)))
+                }).collect::<Result<Vec<_>>>()?;
+                out.push_str(&format!(" {{ {} }}", items.join(", ")));
+            }
+            _ => return Err(unsupported("component imports")),
+        }
+        if let Some(alias) = import.alias {
+            out.push_str(&format!(" as {alias}"));
+        }
+        if let ImportKind::Conditional(condition) = &import.kind {
+            out.push_str(&format!(" when {}", self.expr(condition, Position::Top, 1, self.indent_width(1))?));
+        }
+        Ok(out)
+    }
+
+    /// A doc comment, copied from the source when there is one; it always
+    /// starts at column 0 since the lexer only reads it there
+    fn documentation(&self, documentation: &Option<Documentation>) -> String {
+        let Some(documentation) = documentation else { return String::new() };
+        let comment = &documentation.doc_comment;
+        let original = self.source
+            .and_then(|source| comment.span.text(source))
+            .filter(|text| text.starts_with("```") && text.ends_with("```"));
+        if let Some(text) = original {
+            return format!("{text}\n");
+        }
+
+        let mut lines = Vec::new();
+        if !comment.attributes.is_empty() {
+            let mut attributes: Vec<_> = comment.attributes.iter()
+                .filter_map(|(key, value)| attribute_value(value).map(|value| format!("{key}: {value}")))
+                .collect();
+            attributes.sort();
+            lines.push("---".to_string());
+            lines.extend(attributes);
+            lines.push("---".to_string());
+        }
+        if !comment.content.is_empty() {
+            lines.push(comment.content.clone());
+        }
+        for block in &comment.code_blocks {
+            let info = [block.language.as_deref(), block.metadata.as_deref()];
+            let info: Vec<&str> = info.into_iter().flatten().collect();
+            lines.push(format!("```{}", info.join(" ")));
+            lines.push(block.content.clone());
+            lines.push("```".to_string());
+        }
+        format!("```\n{}\n```\n", lines.join("\n"))
+    }
+
+    fn item(&self, item: &Item) -> Result<String> {
+        match item {
+            Item::ValueDef(def) => {
+                Ok(format!("{}{}", self.documentation(&def.documentation), self.value_def(def, &def.visibility, 0)?))
+            }
+            Item::TypeDef(def) => {
+                Ok(format!("{}{}", self.documentation(&def.documentation), self.type_def(def)?))
+            }
+            Item::EffectDef(def) => {
+                let operations = def.operations.iter()
+                    .map(|operation| self.effect_operation(operation))
+                    .collect::<Result<Vec<_>>>()?;
+                let header = format!("{}effect {}{}", visibility(&def.visibility)?, def.name, type_params(&def.type_params));
+                Ok(format!("{}{}", self.documentation(&def.documentation), self.block(&header, &operations)))
+            }
+            Item::ClassDef(def) => {
+                let methods = def.methods.iter()
+                    .map(|method| Ok(format!("{} : {}", method.name, self.typ(&method.type_annotation)?)))
+                    .collect::<Result<Vec<_>>>()?;
+                let header = format!(
+                    "{}class {}{}{}",
+                    visibility(&def.visibility)?,
+                    def.name,
+                    type_params(&def.type_params),
+                    self.class_context(&def.superclasses)?,
+                );
+                Ok(format!("{}{}", self.documentation(&def.documentation), self.block(&header, &methods)))
+            }
+            Item::InstanceDef(def) => {
+                let types = def.types.iter().map(|typ| self.typ(typ)).collect::<Result<Vec<_>>>()?;
+                let methods = def.methods.iter()
+                    .map(|method| {
+                        let doc = self.documentation(&method.documentation);
+                        Ok(format!("{doc}{}{}", self.indent(1), self.value_def(method, &Visibility::Private, 1)?))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let header = format!("instance {}[{}]{}", def.class, types.join(", "), self.class_context(&def.constraints)?);
+                if methods.is_empty() {
+                    return Ok(format!("{header} {{}}"));
+                }
+                Ok(format!("{header} {{\n{}\n}}", methods.join("\n")))
+            }
+            Item::TestDef(def) => {
+                Ok(format!("{}{}", self.documentation(&def.documentation), self.test_def(def)?))
+            }
+            Item::BenchDef(def) => {
+                Ok(format!("{}{}", self.documentation(&def.documentation), self.bench_def(def)?))
+            }
+            Item::InterfaceDef(def) => self.interface_def(def),
+            Item::HandlerDef(def) => self.handler_def(def),
+            Item::ModuleTypeDef(def) => {
+                let items = def.signature.items.iter()
+                    .map(|item| match item {
+                        SignatureItem::TypeSig { name, type_params: params, .. } => Ok(format!("type {name}{}", type_params(params))),
+                        SignatureItem::ValueSig { name, type_annotation, .. } => Ok(format!("{name} : {}", self.typ(type_annotation)?)),
+                        SignatureItem::EffectSig { name, .. } => Err(unsupported(&format!("effect `{name}` in a module type"))),
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let header = format!("{}module type {}", visibility(&def.visibility)?, def.name);
+                Ok(self.block(&header, &items))
+            }
+            Item::Fixity(decl) => {
+                let operators: Vec<_> = decl.operators.iter().map(|operator| operator.as_str()).collect();
+                Ok(format!("{} {} {}", decl.fixity.keyword(), decl.fixity.precedence, operators.join(" ")))
+            }
+            // The item's doc comment comes after the attribute, which the parser accepts
+            Item::Conditional(conditional) => Ok(format!("#[cfg({})]\n{}", conditional.condition, self.item(&conditional.item)?)),
+        }
+    }
+
+    /// `header { ... }` with one line per entry
+    fn block(&self, header: &str, lines: &[String]) -> String {
+        if lines.is_empty() {
+            return format!("{header} {{}}");
+        }
+        let indent = self.indent(1);
+        let body: Vec<String> = lines.iter().map(|line| format!("{indent}{line}")).collect();
+        format!("{header} {{\n{}\n}}", body.join("\n"))
+    }
+
+    fn class_context(&self, constraints: &[TypeConstraint]) -> Result<String> {
+        if constraints.is_empty() {
+            return Ok(String::new());
+        }
+        let constraints = constraints.iter()
+            .map(|constraint| {
+                let types = constraint.types.iter().map(|typ| self.typ(typ)).collect::<Result<Vec<_>>>()?;
+                Ok(format!("{}[{}]", constraint.class, types.join(", ")))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(format!(" with {}", constraints.join(", ")))
+    }
+
+    fn effect_operation(&self, operation: &EffectOperation) -> Result<String> {
+        let mut types = operation.parameters.iter().map(|param| self.type_operand(param)).collect::<Result<Vec<_>>>()?;
+        types.push(self.typ(&operation.return_type)?);
+        Ok(format!("{} : {}", operation.name, types.join(" -> ")))
+    }
+
+    /// `let name = body`, its continuation lines indented from `level`
+    fn value_def(&self, def: &ValueDef, vis: &Visibility, level: usize) -> Result<String> {
+        let annotation = match &def.type_annotation {
+            Some(typ) => format!(" : {}", self.typ(typ)?),
+            None => String::new(),
+        };
+        let purity = if def.purity == Purity::Pure { "pure " } else { "" };
+        let header = format!("{}{purity}let {}{annotation} =", visibility(vis)?, value_name(def.name));
+
+        // Parameters have no syntax of their own; they are the same as a lambda
+        let lambda;
+        let body = if def.parameters.is_empty() {
+            &def.body
+        } else {
+            lambda = Expr::Lambda { parameters: def.parameters.clone(), body: Box::new(def.body.clone()), span: def.span };
+            &lambda
+        };
+
+        let column = self.end_column(self.indent_width(level), &header) + 1;
+        if let Some(flat) = self.flat(body, Position::Top)? {
+            if self.fits(column, &flat) {
+                return Ok(format!("{header} {flat}"));
+            }
+        }
+        if matches!(body, Expr::Lambda { .. }) {
+            return Ok(format!("{header} {}", self.expr(body, Position::Top, level, column)?));
+        }
+        let body = self.expr(body, Position::Top, level + 1, self.indent_width(level + 1))?;
+        Ok(format!("{header}\n{}{body}", self.indent(level + 1)))
+    }
+
+    fn type_def(&self, def: &TypeDef) -> Result<String> {
+        let vis = visibility(&def.visibility)?;
+        let params = type_params(&def.type_params);
+        match &def.kind {
+            TypeDefKind::Alias(typ) => Ok(format!("{vis}type {}{params} = {}", def.name, self.typ(typ)?)),
+            TypeDefKind::Data(constructors) => {
+                let constructors = constructors.iter()
+                    .map(|constructor| {
+                        let fields = constructor.fields.iter()
+                            .map(|field| self.type_operand(field).map(|field| format!(" {field}")))
+                            .collect::<Result<String>>()?;
+                        let result = match &constructor.result {
+                            Some(result) => format!(" : {}", self.typ(result)?),
+                            None => String::new(),
+                        };
+                        Ok(format!("{}{fields}{result}", constructor.name))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let header = format!("{vis}data {}{params} =", def.name);
+                let flat = format!("{header} {}", constructors.join(" | "));
+                if self.fits(0, &flat) {
+                    return Ok(flat);
+                }
+                let indent = self.indent(1);
+                let lines: Vec<String> = constructors.iter().enumerate()
+                    .map(|(i, constructor)| if i == 0 { format!("{indent}{constructor}") } else { format!("{indent}| {constructor}") })
+                    .collect();
+                Ok(format!("{header}\n{}", lines.join("\n")))
+            }
+            TypeDefKind::Abstract => Err(unsupported(&format!("abstract type `{}`", def.name))),
+        }
+    }
+
+    /// `handler name { ... }` with one clause per line, the return clause last
+    fn handler_def(&self, def: &HandlerDef) -> Result<String> {
+        let mut clauses = Vec::new();
+        for handler in &def.handlers {
+            let mut head = format!("| {}.{}", handler.effect.name, handler.operation);
+            for parameter in &handler.parameters {
+                head.push_str(&format!(" {}", self.pattern(parameter, true)?));
+            }
+            if let Some(continuation) = handler.continuation {
+                head.push_str(&format!(" with {continuation}"));
+            }
+            clauses.push((head, &handler.body));
+        }
+        if let Some(clause) = &def.return_clause {
+            clauses.push((format!("| return {}", self.pattern(&clause.parameter, false)?), clause.body.as_ref()));
+        }
+
+        let mode = match def.mode {
+            HandlerMode::Deep => "",
+            HandlerMode::Shallow => "shallow ",
+        };
+        let header = format!("{}{mode}handler {}", visibility(&def.visibility)?, def.name);
+        if clauses.is_empty() {
+            return Ok(format!("{header} {{}}"));
+        }
+        let indent = self.indent(1);
+        let mut lines = Vec::new();
+        for (i, (head, body)) in clauses.iter().enumerate() {
+            let position = Position::Arm { last: i + 1 == clauses.len() };
+            let body_column = self.end_column(self.indent_width(1), head) + 4;
+            let body = match self.flat(body, position)? {
+                Some(flat) if self.fits(body_column, &flat) => format!(" {flat}"),
+                _ => format!("\n{}{}", self.indent(2), self.expr(body, position, 2, self.indent_width(2))?),
+            };
+            lines.push(format!("{indent}{head} =>{body}"));
+        }
+        Ok(format!("{header} {{\n{}\n}}", lines.join("\n")))
+    }
+
+    fn test_def(&self, def: &TestDef) -> Result<String> {
+        let name = match &def.description {
+            Some(description) => string_literal(description),
+            None => def.name.to_string(),
+        };
+        let mut attributes = Vec::new();
+        if !def.tags.is_empty() {
+            let tags: Vec<String> = def.tags.iter().map(|tag| string_literal(tag)).collect();
+            attributes.push(format!("tags [{}]", tags.join(", ")));
+        }
+        if let Some(timeout) = def.timeout {
+            attributes.push(format!("timeout = {timeout}"));
+        }
+        if def.expected_failure {
+            attributes.push("expected_failure = true".to_string());
+        }
+        let attributes = if attributes.is_empty() { String::new() } else { format!(" with {}", attributes.join(", ")) };
+        let header = format!("{}test {name}{attributes} {{", visibility(&def.visibility)?);
+
+        if def.setup.is_none() && def.teardown.is_none() {
+            let body = self.expr(&def.body, Position::Top, 1, self.indent_width(1))?;
+            return Ok(format!("{header}\n{}{body}\n}}", self.indent(1)));
+        }
+        let sections = [("setup", def.setup.as_deref()), ("body", Some(&def.body)), ("teardown", def.teardown.as_deref())];
+        let mut lines = Vec::new();
+        for (label, expr) in sections {
+            let Some(expr) = expr else { continue };
+            let column = self.indent_width(1) + label.len() + 3;
+            let flat = self.flat(expr, Position::Top)?.filter(|flat| self.fits(column, &format!("{flat} }}")));
+            let section = match flat {
+                Some(flat) => format!("{label} {{ {flat} }}"),
+                None => {
+                    let body = self.expr(expr, Position::Top, 2, self.indent_width(2))?;
+                    format!("{label} {{\n{}{body}\n{}}}", self.indent(2), self.indent(1))
+                }
+            };
+            lines.push(format!("{}{section}", self.indent(1)));
+        }
+        Ok(format!("{header}\n{}\n}}", lines.join("\n")))
+    }
+
+    fn bench_def(&self, def: &BenchDef) -> Result<String> {
+        let name = match &def.description {
+            Some(description) => string_literal(description),
+            None => def.name.to_string(),
+        };
+        let attributes: Vec<String> = [("iterations", def.iterations), ("warmup", def.warmup)].into_iter()
+            .filter_map(|(label, count)| count.map(|count| format!("{label} = {count}")))
+            .collect();
+        let attributes = if attributes.is_empty() { String::new() } else { format!(" with {}", attributes.join(", ")) };
+        let header = format!("{}bench {name}{attributes} {{", visibility(&def.visibility)?);
+        let body = self.expr(&def.body, Position::Top, 1, self.indent_width(1))?;
+        Ok(format!("{header}\n{}{body}\n}}", self.indent(1)))
+    }
+
+    fn interface_def(&self, def: &ComponentInterface) -> Result<String> {
+        let mut lines = Vec::new();
+        for item in &def.items {
+            match item {
+                InterfaceItem::Func { name, signature, .. } => {
+                    lines.push(format!("func {name}{}", function_signature(signature)));
+                }
+                InterfaceItem::Type { name, definition, .. } => match definition {
+                    Some(typ) => lines.push(format!("type {name} = {}", self.typ(typ)?)),
+                    None => lines.push(format!("type {name}")),
+                },
+                InterfaceItem::Resource { name, methods, .. } => {
+                    let methods: Vec<String> = methods.iter()
+                        .map(|method| {
+                            let constructor = if method.is_constructor { "constructor " } else { "" };
+                            let is_static = if method.is_static { "static " } else { "" };
+                            format!("{constructor}{is_static}{}{}", method.name, function_signature(&method.signature))
+                        })
+                        .collect();
+                    let resource = self.block(&format!("resource {name}"), &methods);
+                    lines.extend(resource.lines().map(str::to_string));
+                }
+            }
+        }
+        Ok(self.block(&format!("interface {}", string_literal(&def.name)), &lines))
+    }
+
+    fn typ(&self, typ: &Type) -> Result<String> {
+        Ok(match typ {
+            Type::Var(name, _) | Type::Con(name, _) => name.to_string(),
+            Type::App(constructor, args, _) => {
+                let args = args.iter().map(|arg| self.typ(arg)).collect::<Result<Vec<_>>>()?;
+                format!("{}[{}]", self.typ(constructor)?, args.join(", "))
+            }
+            Type::Fun { params, return_type, effects, .. } => {
+                if !effects.effects.is_empty() || effects.row_var.is_some() {
+                    return Err(unsupported("effectful function types"));
+                }
+                let mut types = params.iter().map(|param| self.type_operand(param)).collect::<Result<Vec<_>>>()?;
+                if types.is_empty() {
+                    types.push("Unit".to_string());
+                }
+                types.push(self.typ(return_type)?);
+                types.join(" -> ")
+            }
+            Type::Forall { type_params: params, body, .. } => format!("forall{}. {}", type_params(params), self.typ(body)?),
+            Type::Record { fields, rest, .. } | Type::Row { fields, rest, .. } => {
+                // Fields are kept unordered, so they are printed by name
+                let mut fields: Vec<_> = fields.iter().collect();
+                fields.sort_by_key(|(name, _)| name.as_str());
+                let mut parts = fields.into_iter()
+                    .map(|(name, typ)| Ok(format!("{name}: {}", self.typ(typ)?)))
+                    .collect::<Result<Vec<_>>>()?
+                    .join(", ");
+                if let Some(rest) = rest {
+                    parts.push_str(&format!(" | {}", self.typ(rest)?));
+                }
+                format!("{{{}}}", parts.trim_start())
+            }
+            Type::Tuple { types, .. } => {
+                let types = types.iter().map(|typ| self.typ(typ)).collect::<Result<Vec<_>>>()?;
+                format!("({})", types.join(", "))
+            }
+            Type::Hole(_) => "?".to_string(),
+            Type::Effects(..) => return Err(unsupported("effect row types")),
+            Type::Exists { .. } => return Err(unsupported("existential types")),
+            Type::Variant { .. } => return Err(unsupported("variant types")),
+        })
+    }
+
+    /// A type as a constructor field or function parameter
+    fn type_operand(&self, typ: &Type) -> Result<String> {
+        let text = self.typ(typ)?;
+        Ok(match typ {
+            Type::App(..) | Type::Fun { .. } | Type::Forall { .. } => format!("({text})"),
+            _ => text,
+        })
+    }
+
+    fn pattern(&self, pattern: &Pattern, nested: bool) -> Result<String> {
+        Ok(match pattern {
+            Pattern::Wildcard(_) => "_".to_string(),
+            Pattern::Variable(name, _) => name.to_string(),
+            Pattern::Literal(value, _) => match value {
+                Literal::Integer(n, _) if *n < 0 => return Err(unsupported("negative literal patterns")),
+                Literal::Float(x, _) if x.is_sign_negative() => return Err(unsupported("negative literal patterns")),
+                value => literal(value),
+            },
+            Pattern::Constructor { name, args, .. } => {
+                if args.is_empty() {
+                    return Ok(name.to_string());
+                }
+                let args = args.iter().map(|arg| self.pattern(arg, true)).collect::<Result<Vec<_>>>()?;
+                let text = format!("{name} {}", args.join(" "));
+                if nested { format!("({text})") } else { text }
+            }
+            Pattern::Tuple { patterns, .. } => {
+                let patterns = patterns.iter().map(|p| self.pattern(p, false)).collect::<Result<Vec<_>>>()?;
+                format!("({})", patterns.join(", "))
+            }
+            Pattern::Record { .. } => return Err(unsupported("record patterns")),
+            Pattern::Or { left, right, .. } => {
+                let text = format!("{} | {}", self.pattern(left, false)?, self.pattern(right, false)?);
+                if nested { format!("({text})") } else { text }
+            }
+            Pattern::As { .. } => return Err(unsupported("as-patterns")),
+            Pattern::Ann { .. } => return Err(unsupported("annotated patterns")),
+        })
+    }
+
+    /// `fun a b -> fun c -> ` for a chain of lambdas, and the innermost body
+    ///
+    /// A lambda starting at its parameter is one the parser split off
+    /// `fun a b`, and joins the parameters of the lambda around it.
+    fn lambda_head<'e>(&self, mut expr: &'e Expr) -> Result<(String, &'e Expr)> {
+        let mut head = String::new();
+        while let Expr::Lambda { parameters, body, span } = expr {
+            if expr.section_body().is_some() {
+                break;
+            }
+            let continues = !head.is_empty() && parameters.first().is_some_and(|p| p.span().start == span.start);
+            let parameters = parameters.iter().map(|p| self.pattern(p, true)).collect::<Result<Vec<_>>>()?;
+            if continues {
+                head.truncate(head.len() - "-> ".len());
+            } else {
+                head.push_str("fun ");
+            }
+            for parameter in parameters {
+                head.push_str(&parameter);
+                head.push(' ');
+            }
+            head.push_str("-> ");
+            expr = body;
+        }
+        Ok((head, expr))
+    }
+
+    /// Wrap the text of a section's body in its parentheses, unless it is a
+    /// tuple that brings its own
+    fn section(body: &Expr, text: String) -> String {
+        match body {
+            Expr::Tuple { .. } => text,
+            _ => format!("({text})"),
+        }
+    }
+
+    /// `expr` on one line, or `None` if it has to be broken
+    fn flat(&self, expr: &Expr, position: Position) -> Result<Option<String>> {
+        if needs_parens(expr, position, &self.fixities) {
+            return Ok(self.flat(expr, Position::Top)?.map(|text| format!("({text})")));
+        }
+        macro_rules! flat {
+            ($expr:expr, $position:expr) => {
+                match self.flat($expr, $position)? {
+                    Some(text) => text,
+                    None => return Ok(None),
+                }
+            };
+        }
+        let text = match expr {
+            Expr::Literal(value, _) => literal(value),
+            // What the parser makes of an operator it has no name for
+            Expr::Var(name, _) if name.as_str() == "unknown_op" => return Err(unsupported("unknown operators")),
+            Expr::Var(name, _) => value_name(*name),
+            Expr::App(..) => {
+                if let Some(elements) = list_elements(expr) {
+                    let mut parts = Vec::new();
+                    for element in elements {
+                        parts.push(flat!(element, Position::Top));
+                    }
+                    format!("[{}]", parts.join(", "))
+                } else if let Some((op, left, right)) = binary(expr) {
+                    let fixity = operator(op, &self.fixities);
+                    let left = flat!(left, Position::Left(fixity));
+                    let right = flat!(right, Position::Right(fixity));
+                    format!("{left} {op} {right}")
+                } else {
+                    let (function, args) = spine(expr);
+                    let mut parts = vec![flat!(function, Position::Argument)];
+                    for arg in args {
+                        parts.push(flat!(arg, Position::Argument));
+                    }
+                    parts.join(" ")
+                }
+            }
+            Expr::Lambda { .. } => match expr.section_body() {
+                Some(body) => Self::section(&body, flat!(&body, Position::Top)),
+                None => {
+                    let (head, body) = self.lambda_head(expr)?;
+                    format!("{head}{}", flat!(body, Position::Top))
+                }
+            },
+            Expr::Let { pattern, type_annotation, value, body, .. } => {
+                let annotation = match type_annotation {
+                    Some(typ) => format!(" : {}", self.typ(typ)?),
+                    None => String::new(),
+                };
+                let value = flat!(value, Position::Top);
+                let body = flat!(body, Position::Top);
+                format!("(let {}{annotation} = {value} in {body})", self.pattern(pattern, false)?)
+            }
+            Expr::Unpack { name, signature, value, body, .. } => {
+                let value = flat!(value, Position::Top);
+                let body = flat!(body, Position::Top);
+                format!("(let module {name} : {signature} = {value} in {body})")
+            }
+            Expr::Pack { module, signature, .. } => format!("module {module} : {signature}"),
+            Expr::If { condition, then_branch, else_branch, .. } => {
+                let condition = flat!(condition, Position::Top);
+                let then_branch = flat!(then_branch, Position::Top);
+                let else_branch = flat!(else_branch, Position::Top);
+                format!("if {condition} then {then_branch} else {else_branch}")
+            }
+            Expr::Match { .. } => return Ok(None),
+            Expr::Record { fields, .. } => {
+                if fields.is_empty() {
+                    return Ok(Some("{}".to_string()));
+                }
+                let mut parts = Vec::new();
+                for (name, value) in fields {
+                    parts.push(format!("{name} = {}", flat!(value, Position::Top)));
+                }
+                format!("{{ {} }}", parts.join(", "))
+            }
+            Expr::RecordUpdate { record, fields, .. } => {
+                let record = flat!(record, Position::Top);
+                let mut parts = Vec::new();
+                for (name, value) in fields {
+                    parts.push(format!("{name} = {}", flat!(value, Position::Top)));
+                }
+                format!("{{ {record} with {} }}", parts.join(", "))
+            }
+            Expr::Project { record, field, .. } => format!("{}.{field}", flat!(record, Position::Argument)),
+            Expr::Resume { value, .. } => format!("resume {}", flat!(value, Position::Argument)),
+            Expr::Tuple { elements, .. } => {
+                let mut parts = Vec::new();
+                for element in elements {
+                    parts.push(flat!(element, Position::Top));
+                }
+                format!("({})", parts.join(", "))
+            }
+            other => return Err(self.unsupported_expr(other)),
+        };
+        Ok(Some(text))
+    }
+
+    fn unsupported_expr(&self, expr: &Expr) -> Error {
+        unsupported(match expr {
+            Expr::Do { .. } => "do blocks",
+            Expr::Handle { .. } => "handle expressions",
+            Expr::Perform { .. } => "perform expressions",
+            Expr::Ann { .. } => "expression type annotations",
+            Expr::Error(_) => "unparsed source",
+            _ => "this expression",
+        })
+    }
+
+    /// `expr` starting at `column`, its continuation lines indented from `level`
+    fn expr(&self, expr: &Expr, position: Position, level: usize, column: usize) -> Result<String> {
+        if needs_parens(expr, position, &self.fixities) {
+            let inner = self.expr(expr, Position::Top, level, column + 1)?;
+            return Ok(format!("({inner})"));
+        }
+        if let Some(flat) = self.flat(expr, position)? {
+            if self.fits(column, &flat) {
+                return Ok(flat);
+            }
+        }
+
+        let next = self.indent(level + 1);
+        let next_column = self.indent_width(level + 1);
+        match expr {
+            Expr::App(..) => {
+                if let Some(elements) = list_elements(expr) {
+                    let parts = elements.into_iter()
+                        .map(|element| Ok(format!("{next}{}", self.expr(element, Position::Top, level + 1, next_column)?)))
+                        .collect::<Result<Vec<_>>>()?;
+                    return Ok(format!("[\n{}\n{}]", parts.join(",\n"), self.indent(level)));
+                }
+                if let Some((op, left, right)) = binary(expr) {
+                    let fixity = operator(op, &self.fixities);
+                    let left = self.expr(left, Position::Left(fixity), level, column)?;
+                    let right_column = next_column + op.len() + 1;
+                    let right = self.expr(right, Position::Right(fixity), level + 1, right_column)?;
+                    return Ok(format!("{left}\n{next}{op} {right}"));
+                }
+                // Arguments fill the line, and the rest go on continuation lines
+                let (function, args) = spine(expr);
+                let mut out = self.expr(function, Position::Argument, level, column)?;
+                for arg in args {
+                    let current = self.end_column(column, &out);
+                    match self.flat(arg, Position::Argument)? {
+                        Some(flat) if self.fits(current + 1, &flat) => {
+                            out.push(' ');
+                            out.push_str(&flat);
+                        }
+                        _ => {
+                            let arg = self.expr(arg, Position::Argument, level + 1, next_column)?;
+                            out.push_str(&format!("\n{next}{arg}"));
+                        }
+                    }
+                }
+                Ok(out)
+            }
+            Expr::Lambda { .. } => {
+                if let Some(body) = expr.section_body() {
+                    let text = self.expr(&body, Position::Top, level, column + 1)?;
+                    return Ok(Self::section(&body, text));
+                }
+                let (head, body) = self.lambda_head(expr)?;
+                let body_column = self.end_column(column, &head);
+                if let Some(flat) = self.flat(body, Position::Top)? {
+                    if self.fits(body_column, &flat) {
+                        return Ok(format!("{head}{flat}"));
+                    }
+                }
+                let body = self.expr(body, Position::Top, level + 1, next_column)?;
+                Ok(format!("{}\n{next}{body}", head.trim_end()))
+            }
+            Expr::Let { pattern, type_annotation, value, body, .. } => {
+                let annotation = match type_annotation {
+                    Some(typ) => format!(" : {}", self.typ(typ)?),
+                    None => String::new(),
+                };
+                let head = format!("(let {}{annotation} = ", self.pattern(pattern, false)?);
+                let value = self.expr(value, Position::Top, level + 1, self.end_column(column, &head))?;
+                let indent = self.indent(level);
+                let body = self.expr(body, Position::Top, level, self.indent_width(level))?;
+                Ok(format!("{head}{value} in\n{indent}{body})"))
+            }
+            Expr::Unpack { name, signature, value, body, .. } => {
+                let head = format!("(let module {name} : {signature} = ");
+                let value = self.expr(value, Position::Top, level + 1, self.end_column(column, &head))?;
+                let indent = self.indent(level);
+                let body = self.expr(body, Position::Top, level, self.indent_width(level))?;
+                Ok(format!("{head}{value} in\n{indent}{body})"))
+            }
+            Expr::If { condition, then_branch, else_branch, .. } => {
+                let condition = self.expr(condition, Position::Top, level + 1, column + 3)?;
+                let then_branch = self.expr(then_branch, Position::Top, level + 1, next_column)?;
+                let indent = self.indent(level);
+                let else_branch = match else_branch.as_ref() {
+                    Expr::If { .. } => format!(" {}", self.expr(else_branch, Position::Top, level, self.indent_width(level) + 5)?),
+                    other => format!("\n{next}{}", self.expr(other, Position::Top, level + 1, next_column)?),
+                };
+                Ok(format!("if {condition} then\n{next}{then_branch}\n{indent}else{else_branch}"))
+            }
+            Expr::Match { scrutinee, arms, .. } => {
+                let mut out = format!("match {} with", self.expr(scrutinee, Position::Top, level + 1, column + 6)?);
+                let indent = self.indent(level);
+                for (i, arm) in arms.iter().enumerate() {
+                    let comments = self.comments_before(arm.span.start, level);
+                    let mut head = format!("| {}", self.pattern(&arm.pattern, false)?);
+                    if let Some(guard) = &arm.guard {
+                        let guard_column = self.indent_width(level) + head.len() + 4;
+                        head.push_str(&format!(" if {}", self.expr(guard, Position::Top, level + 1, guard_column)?));
+                    }
+                    head.push_str(" =>");
+                    let position = Position::Arm { last: i + 1 == arms.len() };
+                    let body_column = self.end_column(self.indent_width(level), &head) + 1;
+                    let body = match self.flat(&arm.body, position)? {
+                        Some(flat) if self.fits(body_column, &flat) => format!(" {flat}"),
+                        _ => format!("\n{next}{}", self.expr(&arm.body, position, level + 1, next_column)?),
+                    };
+                    // The last arm may be followed by a closing parenthesis, so
+                    // its comment is left to the enclosing item
+                    let trailing = match arms.get(i + 1) {
+                        Some(next) => self.trailing_comment(arm.span.start, next.span.start),
+                        None => String::new(),
+                    };
+                    out.push_str(&format!("\n{comments}{indent}{head}{body}{trailing}"));
+                }
+                Ok(out)
+            }
+            Expr::Record { fields, .. } => {
+                let fields = self.fields(fields, level)?;
+                Ok(format!("{{\n{fields}\n{}}}", self.indent(level)))
+            }
+            Expr::RecordUpdate { record, fields, .. } => {
+                let record = self.expr(record, Position::Top, level + 1, column + 2)?;
+                let fields = self.fields(fields, level)?;
+                Ok(format!("{{ {record} with\n{fields}\n{}}}", self.indent(level)))
+            }
+            Expr::Project { record, field, .. } => {
+                Ok(format!("{}.{field}", self.expr(record, Position::Argument, level, column)?))
+            }
+            Expr::Resume { value, .. } => {
+                Ok(format!("resume {}", self.expr(value, Position::Argument, level, column + 7)?))
+            }
+            Expr::Tuple { elements, .. } => {
+                let parts = elements.iter()
+                    .map(|element| Ok(format!("{next}{}", self.expr(element, Position::Top, level + 1, next_column)?)))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(format!("(\n{}\n{})", parts.join(",\n"), self.indent(level)))
+            }
+            // Literals and variables never break
+            other => self.flat(other, Position::Top)?.ok_or_else(|| self.unsupported_expr(other)),
+        }
+    }
+
+    /// Record fields one per line, one level in from `level`
+    fn fields(&self, fields: &[(crate::symbol::Symbol, Expr)], level: usize) -> Result<String> {
+        let next = self.indent(level + 1);
+        let parts = fields.iter()
+            .map(|(name, value)| {
+                let head = format!("{next}{name} = ");
+                let value = self.expr(value, Position::Top, level + 2, self.end_column(0, &head))?;
+                Ok(format!("{head}{value}"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(parts.join(",\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(source: &str, config: &SyntaxConfig) -> String {
+        let (unit, trivia) = OCamlParser::new().parse_with_trivia(source, FileId::new(0)).unwrap();
+        OCamlPrinter::with_source(source).print_with_trivia(&unit, &trivia, config).unwrap()
+    }
+
+    #[test]
+    fn test_print_canonical_layout() {
+        let source = "module Demo\nimport Core.List\n\
+            pub data Shape = Circle Int | Rect Int (List[Int])\n\
+            pub let area = fun s -> match s with | Circle r => r * r | Rect w h => w * (h - 1)\n\
+            let nested = fun x -> (let y = x + 1 in [y, (2 + 3) * y, f (g x)])\n\
+            let cond = fun a -> if a && (b || c) then \"yes\\n\" else (fun z -> z) 1";
+        let expected = "module Demo\n\n\
+            import Core.List\n\n\
+            pub data Shape = Circle Int | Rect Int (List[Int])\n\n\
+            pub let area = fun s ->\n  match s with\n  | Circle r => r * r\n  | Rect w h => w * (h - 1)\n\n\
+            let nested = fun x -> (let y = x + 1 in [y, (2 + 3) * y, f (g x)])\n\n\
+            let cond = fun a -> if a && (b || c) then \"yes\\n\" else (fun z -> z) 1\n";
+        assert_eq!(format(source, &SyntaxConfig::default()), expected);
+    }
+
+    #[test]
+    fn test_print_gadt_constructors() {
+        let source = "module Demo\ndata Expr[a] = IntLit Int : Expr[Int] | If (Expr[Bool]) (Expr[a]) (Expr[a]) : Expr[a]";
+        let expected = "module Demo\n\ndata Expr[a] = IntLit Int : Expr[Int] | If (Expr[Bool]) (Expr[a]) (Expr[a]) : Expr[a]\n";
+        assert_eq!(format(source, &SyntaxConfig::default()), expected);
+    }
+
+    #[test]
+    fn test_print_respects_declared_fixities() {
+        let source = "module Demo export { (<+>), x }\n\
+            infixr 6 <+>\n\
+            infix 4 ===\n\
+            let (<+>) = fun a b -> a\n\
+            let x = ((a <+> b) <+> c) <+> (d <+> e) * f\n\
+            let y = (a === b) === (c <+> d <~> e) <~> g (<+>)\n\
+            let z = a <~> b <~> c";
+        // `<~>` is declared nowhere, so its operands keep their parentheses
+        let expected = "module Demo export { (<+>), x }\n\n\
+            infixr 6 <+>\n\n\
+            infix 4 ===\n\n\
+            let (<+>) = fun a b -> a\n\n\
+            let x = ((a <+> b) <+> c) <+> (d <+> e) * f\n\n\
+            let y = (a === b) === ((c <+> (d <~> e)) <~> g (<+>))\n\n\
+            let z = (a <~> b) <~> c\n";
+        assert_eq!(format(source, &SyntaxConfig::default()), expected);
+        assert_eq!(format(expected, &SyntaxConfig::default()), expected);
+    }
+
+    #[test]
+    fn test_print_cfg_attributes() {
+        let source = "module Demo\n#[cfg(any(target = \"typescript\", not(feature = \"wasi\")))]\nlet now = 0";
+        let expected = "module Demo\n\n#[cfg(any(target = \"typescript\", not(feature = \"wasi\")))]\nlet now = 0\n";
+        assert_eq!(format(source, &SyntaxConfig::default()), expected);
+        assert_eq!(format(expected, &SyntaxConfig::default()), expected);
+    }
+
+    #[test]
+    fn test_print_pattern_parameters_and_sections() {
+        let source = "module Demo\n\
+            let first = fun (Some x) (a, _) y -> fun z -> x\n\
+            let incremented = map (_ + 1) xs (f _ (_, 2)) (pair _ _)";
+        let expected = "module Demo\n\n\
+            let first = fun (Some x) (a, _) y -> fun z -> x\n\n\
+            let incremented = map (_ + 1) xs (f _ (_, 2)) (pair _ _)\n";
+        assert_eq!(format(source, &SyntaxConfig::default()), expected);
+    }
+
+    #[test]
+    fn test_print_breaks_long_lines() {
+        let source = "module Demo\n\
+            let pick = fun flag -> if flag then some_long_function_name 1 2 else other_long_function_name 3 4\n\
+            let inner = fun x -> match x with | Some y => (match y with | 0 => 1 | _ => 2) | None => 0";
+        let config = SyntaxConfig { max_line_length: 40, indent_size: 4, ..SyntaxConfig::default() };
+        let expected = "module Demo\n\n\
+            let pick = fun flag ->\n    if flag then\n        some_long_function_name 1 2\n    else\n        other_long_function_name 3 4\n\n\
+            let inner = fun x ->\n    match x with\n    | Some y =>\n        (match y with\n        | 0 => 1\n        | _ => 2)\n    | None => 0\n";
+        assert_eq!(format(source, &config), expected);
+    }
+
+    #[test]
+    fn test_print_first_class_modules() {
+        let source = "module Demo\nimport Plugins.Math : Plugin\n\
+            pub module type Plugin { type t  make : Int -> t  show : t -> Int }\n\
+            let run = fun p -> (let module P : Plugin = p in P.show (P.make 1))\n\
+            let answer = run (module Math : Plugin)";
+        let expected = "module Demo\n\n\
+            import Plugins.Math : Plugin\n\n\
+            pub module type Plugin {\n  type t\n  make : Int -> t\n  show : t -> Int\n}\n\n\
+            let run = fun p -> (let module P : Plugin = p in P.show (P.make 1))\n\n\
+            let answer = run (module Math : Plugin)\n";
+        assert_eq!(format(source, &SyntaxConfig::default()), expected);
+        assert_eq!(format(expected, &SyntaxConfig::default()), expected);
+    }
+
+    #[test]
+    fn test_print_conditional_imports() {
+        let source = "module Demo\nimport Node.Fs as Fs when target == \"typescript\" && not (feature \"browser\")\nlet x = 1";
+        let expected = "module Demo\n\nimport Node.Fs as Fs when target == \"typescript\" && not (feature \"browser\")\n\nlet x = 1\n";
+        assert_eq!(format(source, &SyntaxConfig::default()), expected);
+        assert_eq!(format(expected, &SyntaxConfig::default()), expected);
+    }
+
+    #[test]
+    fn test_print_is_idempotent() {
+        let sources = [
+            include_str!("../../../x-compiler/stdlib/Core/List.x"),
+            include_str!("../../../x-compiler/stdlib/Core/Option.x"),
+            "module Demo\n```\nDoubles.\n\n```x\ndouble 2 == 4\n```\n```\nlet double = fun x -> x + x\n\
+             test \"doubling\" with tags [\"math\"] { double 2 == 4 }\n\
+             bench \"doubling\" with iterations = 50, warmup = 2 { double 21 }\n\
+             class Show[a] { show : a -> String }\n\
+             instance Show[Int] { let show = fun x -> string_of_int x }\n\
+             let xs = fun f -> f { x = 1, y = [1; 2] } ({ r with x = 2 }).x (1, \"a\")\n\
+             let pipeline = fun xs -> xs |> map (f >> g) |> filter (h << k << (_ > 0))\n\
+             let sections = fun (Some x) (a, b) -> map (_ + x) [a, b] (f _ 2, _) (g (_ * 2))\n\
+             let small = fun n -> match n with | 0 | 1 => true | (Some (2 | 3) | None, _) if n > 0 => false | _ => n < 0\n\
+             effect State { get : Int put : Int -> Unit }\n\
+             handler counter { | State.get with k => k 0 | State.put (Some n) => resume (match n with | 0 => () | _ => ()) | return x => (x, 1) }\n\
+             shallow handler once { | State.get => resume 1 }",
+        ];
+        for width in [20, 40, 100] {
+            let config = SyntaxConfig { max_line_length: width, ..SyntaxConfig::default() };
+            for source in sources {
+                let once = format(source, &config);
+                let twice = format(&once, &config);
+                assert_eq!(once, twice, "not idempotent at width {width}:\n{once}");
+                let original = OCamlParser::new().parse(source, FileId::new(0)).unwrap();
+                let reparsed = OCamlParser::new().parse(&once, FileId::new(0)).unwrap();
+                assert_eq!(original.module.items.len(), reparsed.module.items.len());
+            }
+        }
+    }
+
+    #[test]
+    fn test_print_keeps_comments() {
+        let source = "-- Header\n\nmodule Demo\nimport Core.List -- lists\n\
+            -- Sizes\n\n-- of shapes\npub let size = fun s -> match s with\n\
+            -- small ones\n| 0 => 1 -- one\n| _ => 2 -- two\n\
+            let double = fun x ->\n  -- twice\n  x + x\n-- the end";
+        let expected = "-- Header\n\nmodule Demo\n\n\
+            import Core.List -- lists\n\n\
+            -- Sizes\n\n-- of shapes\npub let size = fun s ->\n  match s with\n  -- small ones\n  | 0 => 1 -- one\n  | _ => 2 -- two\n\n\
+            -- twice\nlet double = fun x -> x + x\n\n\
+            -- the end\n";
+        let config = SyntaxConfig::default();
+        assert_eq!(format(source, &config), expected);
+        assert_eq!(format(expected, &config), expected);
+
+        let config = SyntaxConfig { preserve_comments: false, ..SyntaxConfig::default() };
+        assert!(!format(source, &config).contains("--"));
+    }
+}
@@ -2,10 +2,14 @@
 //! 
 //! This provides a Lisp-like syntax for x Language that can be useful for
 //! meta-programming, code generation, and data exchange.
+//!
+//! Given the source's [`Trivia`], the printer keeps comments as `;` lines
+//! between the module's imports and items.
 
 use super::{SyntaxParser, SyntaxPrinter, SyntaxStyle, SyntaxConfig};
-use crate::{ast::*, span::{FileId, Span, ByteOffset}, symbol::Symbol};
+use crate::{ast::*, span::{FileId, Span, CharOffset}, symbol::Symbol};
 use crate::error::{ParseError as Error, Result};
+use crate::trivia::{Comment, CommentCursor, Trivia};
 
 /// S-expression parser
 pub struct SExpParser;
@@ -21,22 +25,27 @@ impl SExpParser {
         SExpParser
     }
     
-    fn parse_sexp(&mut self, input: &str, file_id: FileId) -> Result<SExp> {
+    fn parse_sexp(&mut self, input: &str, file_id: FileId) -> Result<(SExp, Trivia)> {
         let mut lexer = SExpLexer::new(input, file_id);
         let tokens = lexer.tokenize()?;
         let mut parser = SExpTokenParser::new(tokens, file_id);
-        parser.parse_sexp()
+        Ok((parser.parse_sexp()?, lexer.trivia))
     }
 }
 
 impl SyntaxParser for SExpParser {
     fn parse(&mut self, input: &str, file_id: FileId) -> Result<CompilationUnit> {
-        let sexp = self.parse_sexp(input, file_id)?;
+        let (sexp, _) = self.parse_sexp(input, file_id)?;
         sexp_to_ast(&sexp)
     }
     
+    fn parse_with_trivia(&mut self, input: &str, file_id: FileId) -> Result<(CompilationUnit, Trivia)> {
+        let (sexp, trivia) = self.parse_sexp(input, file_id)?;
+        Ok((sexp_to_ast(&sexp)?, trivia))
+    }
+    
     fn parse_expression(&mut self, input: &str, file_id: FileId) -> Result<Expr> {
-        let sexp = self.parse_sexp(input, file_id)?;
+        let (sexp, _) = self.parse_sexp(input, file_id)?;
         sexp_to_expr(&sexp)
     }
     
@@ -70,8 +79,18 @@ impl SExpPrinter {
 
 impl SyntaxPrinter for SExpPrinter {
     fn print(&self, ast: &CompilationUnit, config: &SyntaxConfig) -> Result<String> {
-        let sexp = ast_to_sexp(ast);
-        Ok(self.print_sexp(&sexp, config, 0))
+        self.print_with_trivia(ast, &Trivia::new(), config)
+    }
+    
+    fn print_with_trivia(&self, ast: &CompilationUnit, trivia: &Trivia, config: &SyntaxConfig) -> Result<String> {
+        let comments = if config.preserve_comments { trivia.comments.as_slice() } else { &[] };
+        let mut comments = CommentCursor::new(comments);
+        // Comments above the module header stay above the whole unit
+        let header: String = comments.before(ast.module.span.start).iter()
+            .map(|comment| format!(";{}\n", comment.text))
+            .collect();
+        let sexp = ast_to_sexp(ast, trivia, &mut comments);
+        Ok(format!("{header}{}", self.print_sexp(&sexp, config, 0)))
     }
     
     fn print_expression(&self, expr: &Expr, config: &SyntaxConfig) -> Result<String> {
@@ -93,6 +112,7 @@ impl SExpPrinter {
     fn print_sexp(&self, sexp: &SExp, config: &SyntaxConfig, level: usize) -> String {
         match sexp {
             SExp::Atom(atom) => atom.clone(),
+            SExp::Comment(comment) => format!(";{comment}"),
             SExp::List(list) => {
                 if list.is_empty() {
                     "()".to_string()
@@ -106,17 +126,23 @@ impl SExpPrinter {
                     // First element (usually the operator/function)
                     output.push_str(&self.print_sexp(&list[0], config, level));
                     
-                    // Remaining elements
+                    // Remaining elements; a comment runs to the end of its line
+                    let mut after_comment = false;
                     for item in &list[1..] {
-                        if self.should_break_line(&list[0], item, config) {
+                        if after_comment || self.should_break_line(&list[0], item, config) {
                             output.push('\n');
                             output.push_str(&self.indent(level + 1, config));
                         } else {
                             output.push(' ');
                         }
                         output.push_str(&self.print_sexp(item, config, level + 1));
+                        after_comment = matches!(item, SExp::Comment(_));
                     }
                     
+                    if after_comment {
+                        output.push('\n');
+                        output.push_str(&self.indent(level, config));
+                    }
                     output.push(')');
                     output
                 }
@@ -129,7 +155,7 @@ impl SExpPrinter {
         if let SExp::Atom(atom) = first {
             match atom.as_str() {
                 "module" | "let" | "type" | "effect" | "handler" | "match" | "if" | "lambda" => {
-                    matches!(item, SExp::List(_))
+                    matches!(item, SExp::List(_) | SExp::Comment(_))
                 }
                 _ => false,
             }
@@ -144,6 +170,8 @@ impl SExpPrinter {
 enum SExp {
     Atom(String),
     List(Vec<SExp>),
+    /// A `;` comment, only ever printed
+    Comment(String),
 }
 
 /// S-expression token
@@ -162,6 +190,9 @@ struct SExpLexer {
     chars: Vec<char>,
     position: usize,
     file_id: FileId,
+    /// End of the last token read
+    last_token_end: Option<usize>,
+    trivia: Trivia,
 }
 
 impl SExpLexer {
@@ -171,6 +202,8 @@ impl SExpLexer {
             chars: input.chars().collect(),
             position: 0,
             file_id,
+            last_token_end: None,
+            trivia: Trivia::new(),
         }
     }
     
@@ -185,6 +218,7 @@ impl SExpLexer {
             }
             
             let token = self.next_token()?;
+            self.last_token_end = Some(self.position);
             tokens.push(token);
         }
         
@@ -264,22 +298,37 @@ impl SExpLexer {
     
     fn skip_whitespace_and_comments(&mut self) {
         while let Some(ch) = self.current_char() {
-            if ch.is_whitespace() {
+            if ch == '\n' {
+                let line_start = self.line_start(self.position);
+                if self.chars[line_start..self.position].iter().all(|c| c.is_whitespace()) {
+                    self.trivia.blank_lines.push(CharOffset::new(line_start as u32));
+                }
+                self.advance();
+            } else if ch.is_whitespace() {
                 self.advance();
             } else if ch == ';' {
-                // Skip comment until end of line
-                while let Some(ch) = self.current_char() {
+                // Skip comment until end of line, keeping it as trivia
+                let start = self.position;
+                while self.current_char().is_some_and(|ch| ch != '\n') {
                     self.advance();
-                    if ch == '\n' {
-                        break;
-                    }
                 }
+                let text: String = self.chars[start + 1..self.position].iter().collect();
+                let after_code = self.last_token_end.filter(|&end| end >= self.line_start(start));
+                self.trivia.comments.push(Comment {
+                    text: text.trim_end().to_string(),
+                    span: Span::new(self.file_id, CharOffset::new(start as u32), CharOffset::new(self.position as u32)),
+                    after_code: after_code.map(|end| CharOffset::new(end as u32)),
+                });
             } else {
                 break;
             }
         }
     }
     
+    fn line_start(&self, position: usize) -> usize {
+        self.chars[..position].iter().rposition(|&c| c == '\n').map_or(0, |newline| newline + 1)
+    }
+    
     fn current_char(&self) -> Option<char> {
         self.chars.get(self.position).copied()
     }
@@ -360,14 +409,19 @@ impl SExpTokenParser {
 
 // AST to S-expression conversion functions
 
-fn ast_to_sexp(ast: &CompilationUnit) -> SExp {
+fn ast_to_sexp(ast: &CompilationUnit, trivia: &Trivia, comments: &mut CommentCursor) -> SExp {
     SExp::List(vec![
         SExp::Atom("compilation-unit".to_string()),
-        module_to_sexp(&ast.module),
+        module_to_sexp(&ast.module, trivia, comments),
     ])
 }
 
-fn module_to_sexp(module: &Module) -> SExp {
+/// Comments as elements of the list they are printed in
+fn comments_to_sexp(comments: &[Comment]) -> impl Iterator<Item = SExp> + '_ {
+    comments.iter().map(|comment| SExp::Comment(comment.text.clone()))
+}
+
+fn module_to_sexp(module: &Module, trivia: &Trivia, comments: &mut CommentCursor) -> SExp {
     let mut elements = vec![
         SExp::Atom("module".to_string()),
         SExp::Atom(module.name.to_string()),
@@ -384,14 +438,22 @@ fn module_to_sexp(module: &Module) -> SExp {
     }
     
     // Imports
-    for import in &module.imports {
+    let item_start = |i: usize| module.items.get(i).map_or(CharOffset::INVALID, |item| trivia.item_extent(i, item.span()).0);
+    for (i, import) in module.imports.iter().enumerate() {
+        elements.extend(comments_to_sexp(comments.before(import.span.start)));
         elements.push(import_to_sexp(import));
+        let next = module.imports.get(i + 1).map_or(item_start(0), |import| import.span.start);
+        elements.extend(comments.trailing(import.span.start, next).map(|comment| SExp::Comment(comment.text.clone())));
     }
     
-    // Items
-    for item in &module.items {
+    // Items, with the comments inside them moved above them
+    for (i, item) in module.items.iter().enumerate() {
+        let (start, end) = trivia.item_extent(i, item.span());
+        elements.extend(comments_to_sexp(comments.before(end)));
         elements.push(item_to_sexp(item));
+        elements.extend(comments.trailing(start, item_start(i + 1)).map(|comment| SExp::Comment(comment.text.clone())));
     }
+    elements.extend(comments_to_sexp(comments.rest()));
     
     SExp::List(elements)
 }
@@ -418,8 +480,11 @@ fn import_to_sexp(import: &Import) -> SExp {
         ImportKind::Lazy => {
             elements.push(SExp::Atom("lazy".to_string()));
         }
-        ImportKind::Conditional(_) => {
-            elements.push(SExp::List(vec![SExp::Atom("when".to_string()), SExp::Atom("condition".to_string())]));
+        ImportKind::Signature(signature) => {
+            elements.push(SExp::List(vec![SExp::Atom("signature".to_string()), SExp::Atom(signature.to_string())]));
+        }
+        ImportKind::Conditional(condition) => {
+            elements.push(SExp::List(vec![SExp::Atom("when".to_string()), expr_to_sexp(condition)]));
         }
         ImportKind::Interface { interface, items } => {
             elements.push(SExp::List(vec![SExp::Atom("interface".to_string()), SExp::Atom(interface.clone())]));
@@ -472,9 +537,89 @@ fn item_to_sexp(item: &Item) -> SExp {
             SExp::Atom(def.name.as_str().to_string()),
             SExp::List(vec![SExp::Atom("body".to_string()), expr_to_sexp(&def.body)]),
         ]),
+        Item::BenchDef(def) => SExp::List(vec![
+            SExp::Atom("bench-def".to_string()),
+            SExp::Atom(def.name.as_str().to_string()),
+            SExp::List(vec![SExp::Atom("body".to_string()), expr_to_sexp(&def.body)]),
+        ]),
+        Item::ClassDef(def) => class_def_to_sexp(def),
+        Item::InstanceDef(def) => instance_def_to_sexp(def),
+        Item::Fixity(decl) => {
+            let mut elements = vec![
+                SExp::Atom(decl.fixity.keyword().to_string()),
+                SExp::Atom(decl.fixity.precedence.to_string()),
+            ];
+            elements.extend(decl.operators.iter().map(|operator| SExp::Atom(operator.as_str().to_string())));
+            SExp::List(elements)
+        }
+        Item::Conditional(conditional) => SExp::List(vec![
+            SExp::Atom("cfg".to_string()),
+            cfg_to_sexp(&conditional.condition),
+            item_to_sexp(&conditional.item),
+        ]),
     }
 }
 
+fn cfg_to_sexp(cfg: &Cfg) -> SExp {
+    let (name, cfgs) = match cfg {
+        Cfg::Equals { key, value } => {
+            return SExp::List(vec![SExp::Atom("=".to_string()), SExp::Atom(key.to_string()), SExp::Atom(format!("{value:?}"))]);
+        }
+        Cfg::Not(cfg) => return SExp::List(vec![SExp::Atom("not".to_string()), cfg_to_sexp(cfg)]),
+        Cfg::All(cfgs) => ("all", cfgs),
+        Cfg::Any(cfgs) => ("any", cfgs),
+    };
+    SExp::List(std::iter::once(SExp::Atom(name.to_string())).chain(cfgs.iter().map(cfg_to_sexp)).collect())
+}
+
+fn constraint_to_sexp(constraint: &TypeConstraint) -> SExp {
+    let mut elements = vec![SExp::Atom(constraint.class.as_str().to_string())];
+    elements.extend(constraint.types.iter().map(type_to_sexp));
+    SExp::List(elements)
+}
+
+fn class_def_to_sexp(def: &ClassDef) -> SExp {
+    let mut elements = vec![
+        SExp::Atom("class".to_string()),
+        SExp::Atom(def.name.as_str().to_string()),
+        SExp::List(
+            def.type_params.iter()
+                .map(|param| SExp::Atom(param.name.as_str().to_string()))
+                .collect()
+        ),
+    ];
+    
+    if !def.superclasses.is_empty() {
+        let mut context = vec![SExp::Atom("with".to_string())];
+        context.extend(def.superclasses.iter().map(constraint_to_sexp));
+        elements.push(SExp::List(context));
+    }
+    
+    for method in &def.methods {
+        elements.push(SExp::List(vec![
+            SExp::Atom(method.name.as_str().to_string()),
+            type_to_sexp(&method.type_annotation),
+        ]));
+    }
+    
+    SExp::List(elements)
+}
+
+fn instance_def_to_sexp(def: &InstanceDef) -> SExp {
+    let mut head = vec![SExp::Atom(def.class.as_str().to_string())];
+    head.extend(def.types.iter().map(type_to_sexp));
+    let mut elements = vec![SExp::Atom("instance".to_string()), SExp::List(head)];
+    
+    if !def.constraints.is_empty() {
+        let mut context = vec![SExp::Atom("with".to_string())];
+        context.extend(def.constraints.iter().map(constraint_to_sexp));
+        elements.push(SExp::List(context));
+    }
+    
+    elements.extend(def.methods.iter().map(value_def_to_sexp));
+    SExp::List(elements)
+}
+
 fn value_def_to_sexp(def: &ValueDef) -> SExp {
     let mut elements = vec![
         SExp::Atom("let".to_string()),
@@ -526,6 +671,10 @@ fn type_def_to_sexp(def: &TypeDef) -> SExp {
                         for field in &ctor.fields {
                             ctor_elements.push(type_to_sexp(field));
                         }
+                        if let Some(result) = &ctor.result {
+                            ctor_elements.push(SExp::Atom(":".to_string()));
+                            ctor_elements.push(type_to_sexp(result));
+                        }
                         SExp::List(ctor_elements)
                     })
                     .collect()
@@ -559,8 +708,12 @@ fn effect_def_to_sexp(def: &EffectDef) -> SExp {
 }
 
 fn handler_def_to_sexp(def: &HandlerDef) -> SExp {
+    let keyword = match def.mode {
+        HandlerMode::Deep => "handler",
+        HandlerMode::Shallow => "shallow-handler",
+    };
     let mut elements = vec![
-        SExp::Atom("handler".to_string()),
+        SExp::Atom(keyword.to_string()),
         SExp::Atom(def.name.as_str().to_string()),
     ];
     
@@ -587,11 +740,26 @@ fn handler_def_to_sexp(def: &HandlerDef) -> SExp {
 }
 
 fn module_type_def_to_sexp(def: &ModuleTypeDef) -> SExp {
-    SExp::List(vec![
+    let mut elements = vec![
         SExp::Atom("module-type".to_string()),
         SExp::Atom(def.name.as_str().to_string()),
-        SExp::Atom("signature".to_string()), // Simplified
-    ])
+    ];
+    
+    for item in &def.signature.items {
+        elements.push(match item {
+            SignatureItem::TypeSig { name, .. } => {
+                SExp::List(vec![SExp::Atom("type".to_string()), SExp::Atom(name.to_string())])
+            }
+            SignatureItem::ValueSig { name, type_annotation, .. } => {
+                SExp::List(vec![SExp::Atom(name.to_string()), type_to_sexp(type_annotation)])
+            }
+            SignatureItem::EffectSig { name, .. } => {
+                SExp::List(vec![SExp::Atom("effect".to_string()), SExp::Atom(name.to_string())])
+            }
+        });
+    }
+    
+    SExp::List(elements)
 }
 
 fn interface_def_to_sexp(def: &ComponentInterface) -> SExp {
@@ -703,6 +871,9 @@ fn expr_to_sexp(expr: &Expr) -> SExp {
             SExp::List(elements)
         }
         Expr::Lambda { parameters, body, span: _ } => {
+            if let Some(body) = expr.section_body() {
+                return SExp::List(vec![SExp::Atom("section".to_string()), expr_to_sexp(&body)]);
+            }
             let mut elements = vec![SExp::Atom("lambda".to_string())];
             let params = SExp::List(
                 parameters.iter().map(pattern_to_sexp).collect()
@@ -753,9 +924,13 @@ fn expr_to_sexp(expr: &Expr) -> SExp {
             }
             SExp::List(elements)
         }
-        Expr::Handle { expr, handlers, return_clause, span: _ } => {
+        Expr::Handle { expr, mode, handlers, return_clause, span: _ } => {
+            let keyword = match mode {
+                HandlerMode::Deep => "handle",
+                HandlerMode::Shallow => "shallow-handle",
+            };
             let mut elements = vec![
-                SExp::Atom("handle".to_string()),
+                SExp::Atom(keyword.to_string()),
                 expr_to_sexp(expr),
             ];
             for handler in handlers {
@@ -793,9 +968,53 @@ fn expr_to_sexp(expr: &Expr) -> SExp {
                 type_to_sexp(type_annotation),
             ])
         }
+        Expr::Record { fields, span: _ } => {
+            let mut elements = vec![SExp::Atom("record".to_string())];
+            elements.extend(record_fields_to_sexp(fields));
+            SExp::List(elements)
+        }
+        Expr::Project { record, field, span: _ } => {
+            SExp::List(vec![
+                SExp::Atom("project".to_string()),
+                expr_to_sexp(record),
+                SExp::Atom(field.as_str().to_string()),
+            ])
+        }
+        Expr::RecordUpdate { record, fields, span: _ } => {
+            let mut elements = vec![
+                SExp::Atom("update".to_string()),
+                expr_to_sexp(record),
+            ];
+            elements.extend(record_fields_to_sexp(fields));
+            SExp::List(elements)
+        }
+        Expr::Tuple { elements: items, span: _ } => {
+            let mut elements = vec![SExp::Atom("tuple".to_string())];
+            elements.extend(items.iter().map(expr_to_sexp));
+            SExp::List(elements)
+        }
+        Expr::Pack { module, signature, span: _ } => SExp::List(vec![
+            SExp::Atom("pack".to_string()),
+            SExp::Atom(module.to_string()),
+            SExp::Atom(signature.to_string()),
+        ]),
+        Expr::Unpack { name, signature, value, body, span: _ } => SExp::List(vec![
+            SExp::Atom("unpack".to_string()),
+            SExp::Atom(name.to_string()),
+            SExp::Atom(signature.to_string()),
+            expr_to_sexp(value),
+            expr_to_sexp(body),
+        ]),
+        Expr::Error(_) => SExp::List(vec![SExp::Atom("error".to_string())]),
     }
 }
 
+fn record_fields_to_sexp(fields: &[(Symbol, Expr)]) -> impl Iterator<Item = SExp> + '_ {
+    fields.iter().map(|(name, value)| {
+        SExp::List(vec![SExp::Atom(name.as_str().to_string()), expr_to_sexp(value)])
+    })
+}
+
 fn do_statement_to_sexp(statement: &DoStatement) -> SExp {
     match statement {
         DoStatement::Let { pattern, expr, span: _ } => {
@@ -973,8 +1192,8 @@ fn type_to_sexp(typ: &Type) -> SExp {
 
 fn literal_to_sexp(literal: &Literal) -> SExp {
     match literal {
-        Literal::Integer(n) => SExp::Atom(n.to_string()),
-        Literal::Float(f) => SExp::Atom(f.to_string()),
+        Literal::Integer(n, format) => SExp::Atom(format.format_integer(*n)),
+        Literal::Float(f, format) => SExp::Atom(format.format_float(*f)),
         Literal::String(s) => SExp::Atom(format!("\"{s}\"")),
         Literal::Bool(b) => SExp::Atom(b.to_string()),
         Literal::Unit => SExp::Atom("()".to_string()),
@@ -1033,11 +1252,8 @@ fn sexp_to_expr(sexp: &SExp) -> Result<Expr> {
     match sexp {
         SExp::Atom(atom) => {
             // Try to parse as number
-            if let Ok(n) = atom.parse::<i64>() {
-                return Ok(Expr::Literal(Literal::Integer(n), dummy_span()));
-            }
-            if let Ok(f) = atom.parse::<f64>() {
-                return Ok(Expr::Literal(Literal::Float(f), dummy_span()));
+            if let Some(literal) = Literal::parse_number(atom) {
+                return Ok(Expr::Literal(literal, dummy_span()));
             }
             if atom == "true" {
                 return Ok(Expr::Literal(Literal::Bool(true), dummy_span()));
@@ -1072,6 +1288,9 @@ fn sexp_to_expr(sexp: &SExp) -> Result<Expr> {
                                 span: dummy_span(),
                             })
                         }
+                        "section" if list.len() == 2 => {
+                            Ok(Expr::section(sexp_to_expr(&list[1])?, dummy_span()))
+                        }
                         _ => {
                             // Function application
                             let func = Box::new(sexp_to_expr(&list[0])?);
@@ -1097,11 +1316,14 @@ fn sexp_to_expr(sexp: &SExp) -> Result<Expr> {
         SExp::List(_) => {
             Ok(Expr::Literal(Literal::Unit, dummy_span()))
         }
+        SExp::Comment(_) => Err(Error::Parse {
+            message: "A comment is not an expression".to_string(),
+        }),
     }
 }
 
 fn dummy_span() -> Span {
-    Span::new(FileId::new(0), ByteOffset::new(0), ByteOffset::new(0))
+    Span::new(FileId::new(0), CharOffset::new(0), CharOffset::new(0))
 }
 
 #[cfg(test)]
@@ -1142,7 +1364,7 @@ mod tests {
 
     #[test]
     fn test_expression_to_sexp() {
-        let expr = Expr::Literal(Literal::Integer(42), dummy_span());
+        let expr = Expr::Literal(Literal::int(42), dummy_span());
         let sexp = expr_to_sexp(&expr);
         assert_eq!(sexp, SExp::Atom("42".to_string()));
     }
@@ -1150,7 +1372,7 @@ mod tests {
     #[test]
     fn test_function_application_to_sexp() {
         let func = Expr::Var(Symbol::intern("f"), dummy_span());
-        let arg = Expr::Literal(Literal::Integer(42), dummy_span());
+        let arg = Expr::Literal(Literal::int(42), dummy_span());
         let app = Expr::App(Box::new(func), vec![arg], dummy_span());
         
         let sexp = expr_to_sexp(&app);
@@ -1160,6 +1382,19 @@ mod tests {
         ]));
     }
 
+    #[test]
+    fn test_section_round_trip() {
+        let source = "module Demo\nlet inc = (_ + 1)";
+        let unit = crate::syntax::ocaml::OCamlParser::new().parse(source, FileId::new(0)).unwrap();
+        let Item::ValueDef(def) = &unit.module.items[0] else { panic!("expected a definition") };
+
+        let sexp = expr_to_sexp(&def.body);
+        let printed = SExpPrinter::new().print_sexp(&sexp, &SyntaxConfig::default(), 0);
+        assert_eq!(printed, "(section (+ _ 1))");
+        let restored = sexp_to_expr(&sexp).unwrap();
+        assert!(restored.section_body().is_some());
+    }
+
     #[test]
     fn test_sexp_printing() {
         let printer = SExpPrinter::new();
@@ -1173,4 +1408,19 @@ mod tests {
         let result = printer.print_sexp(&sexp, &config, 0);
         assert_eq!(result, "(f 42)");
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_comments_survive_printing() {
+        let source = "-- Demo\nmodule Demo\n-- doubles\nlet double = fun x -> x + x -- twice\n-- end";
+        let (unit, trivia) = crate::syntax::ocaml::OCamlParser::new().parse_with_trivia(source, FileId::new(0)).unwrap();
+        let printed = SExpPrinter::new().print_with_trivia(&unit, &trivia, &SyntaxConfig::default()).unwrap();
+        assert!(printed.starts_with("; Demo\n(compilation-unit"), "{printed}");
+        let comments: Vec<&str> = printed.lines().map(str::trim).filter(|line| line.starts_with(';')).collect();
+        assert_eq!(comments, ["; Demo", "; doubles", "; twice", "; end"]);
+        
+        // A comment runs to the end of its line, so the closing parentheses go on the next
+        assert!(printed.ends_with("; end\n  ))"), "{printed}");
+        let (_, trivia) = SExpParser::new().parse_with_trivia(&printed, FileId::new(0)).unwrap();
+        assert_eq!(trivia.comments.len(), 4);
+    }
+}
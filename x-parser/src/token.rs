@@ -1,6 +1,6 @@
 //! Token definitions for lexical analysis
 
-use crate::span::Span;
+use crate::{span::Span, unicode::is_operator_char};
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
@@ -36,6 +36,9 @@ pub enum TokenKind {
     Pure,
     Forall,
     Test,
+    Bench,
+    Class,
+    Instance,
     
     // Module keywords
     Module,
@@ -88,8 +91,13 @@ pub enum TokenKind {
     FatArrow,      // =>
     Pipe,          // |
     PipeForward,   // |>
+    ComposeRight,  // >>
+    ComposeLeft,   // <<
     Cons,          // ::
     Caret,         // ^
+    /// Any other run of operator characters, such as `<+>`, whose fixity
+    /// comes from an `infixl`, `infixr` or `infix` declaration
+    Operator(String),
     
     // Delimiters
     LeftParen,     // (
@@ -111,6 +119,7 @@ pub enum TokenKind {
     Underscore,    // _
     Question,      // ?
     At,            // @
+    Hash,          // #
     
     // Special
     Newline,
@@ -143,7 +152,7 @@ impl TokenKind {
             TokenKind::Then | TokenKind::Else | TokenKind::Match | TokenKind::With |
             TokenKind::Data | TokenKind::Type | TokenKind::Effect | TokenKind::Handler |
             TokenKind::Handle | TokenKind::Do | TokenKind::Pure | TokenKind::Forall |
-            TokenKind::Test | TokenKind::Module | TokenKind::Import | TokenKind::Export | 
+            TokenKind::Test | TokenKind::Bench | TokenKind::Class | TokenKind::Instance | TokenKind::Module | TokenKind::Import | TokenKind::Export | 
             TokenKind::Pub | TokenKind::Crate | TokenKind::Package | TokenKind::Super | 
             TokenKind::Self_ | TokenKind::Interface | TokenKind::Component | TokenKind::Core | 
             TokenKind::Func | TokenKind::Param | TokenKind::Result | TokenKind::Resource |
@@ -159,7 +168,8 @@ impl TokenKind {
             TokenKind::NotEqual | TokenKind::Less | TokenKind::LessEqual |
             TokenKind::Greater | TokenKind::GreaterEqual | TokenKind::And |
             TokenKind::Or | TokenKind::Not | TokenKind::Arrow | TokenKind::FatArrow |
-            TokenKind::Pipe | TokenKind::PipeForward | TokenKind::Cons | TokenKind::Caret
+            TokenKind::Pipe | TokenKind::PipeForward | TokenKind::ComposeRight |
+            TokenKind::ComposeLeft | TokenKind::Cons | TokenKind::Caret | TokenKind::Operator(_)
         )
     }
     
@@ -185,6 +195,8 @@ impl TokenKind {
             TokenKind::Plus | TokenKind::Minus => Some(7),
             TokenKind::Star | TokenKind::Slash | TokenKind::Percent => Some(8),
             TokenKind::Not => Some(9),
+            // Composition binds tighter than any other binary operator
+            TokenKind::ComposeRight | TokenKind::ComposeLeft => Some(10),
             _ => None,
         }
     }
@@ -195,6 +207,7 @@ impl TokenKind {
             TokenKind::Arrow => false, // Right-associative
             TokenKind::Cons => false, // Right-associative
             TokenKind::PipeForward => true, // Left-associative
+            TokenKind::ComposeLeft => false, // Right-associative, `f << g << h` is `f << (g << h)`
             _ if self.is_operator() => true,
             _ => false,
         }
@@ -230,6 +243,9 @@ impl fmt::Display for TokenKind {
             TokenKind::Pure => write!(f, "pure"),
             TokenKind::Forall => write!(f, "forall"),
             TokenKind::Test => write!(f, "test"),
+            TokenKind::Bench => write!(f, "bench"),
+            TokenKind::Class => write!(f, "class"),
+            TokenKind::Instance => write!(f, "instance"),
             TokenKind::Module => write!(f, "module"),
             TokenKind::Import => write!(f, "import"),
             TokenKind::Export => write!(f, "export"),
@@ -274,8 +290,11 @@ impl fmt::Display for TokenKind {
             TokenKind::FatArrow => write!(f, "=>"),
             TokenKind::Pipe => write!(f, "|"),
             TokenKind::PipeForward => write!(f, "|>"),
+            TokenKind::ComposeRight => write!(f, ">>"),
+            TokenKind::ComposeLeft => write!(f, "<<"),
             TokenKind::Cons => write!(f, "::"),
             TokenKind::Caret => write!(f, "^"),
+            TokenKind::Operator(op) => write!(f, "{op}"),
             
             // Delimiters
             TokenKind::LeftParen => write!(f, "("),
@@ -297,6 +316,7 @@ impl fmt::Display for TokenKind {
             TokenKind::Underscore => write!(f, "_"),
             TokenKind::Question => write!(f, "?"),
             TokenKind::At => write!(f, "@"),
+            TokenKind::Hash => write!(f, "#"),
             
             // Special
             TokenKind::Newline => write!(f, "\\n"),
@@ -309,6 +329,11 @@ impl fmt::Display for TokenKind {
     }
 }
 
+/// Whether `name` is an operator, written infix and referred to as `(name)`
+pub fn is_operator_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(is_operator_char)
+}
+
 /// A token with its source span
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Token {
@@ -362,6 +387,9 @@ pub fn keyword_to_token(s: &str) -> Option<TokenKind> {
         "pure" => Some(TokenKind::Pure),
         "forall" => Some(TokenKind::Forall),
         "test" => Some(TokenKind::Test),
+        "bench" => Some(TokenKind::Bench),
+        "class" => Some(TokenKind::Class),
+        "instance" => Some(TokenKind::Instance),
         "module" => Some(TokenKind::Module),
         "import" => Some(TokenKind::Import),
         "export" => Some(TokenKind::Export),
@@ -0,0 +1,141 @@
+//! Comments and blank lines, kept beside the AST
+//!
+//! The AST has no place for comments, so the lexers record them in a
+//! [`Trivia`] table keyed by source offsets instead, together with the blank
+//! lines between them. A printer walking the AST in source order hands the
+//! offsets of the nodes it prints to a [`CommentCursor`] and gets back the
+//! comments that belong before or after them.
+
+use crate::span::{CharOffset, Span};
+
+/// A line comment, without its marker (`--` or `;`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comment {
+    pub text: String,
+    pub span: Span,
+    /// End of the code before the comment on its line; `None` for a comment
+    /// on a line of its own
+    pub after_code: Option<CharOffset>,
+}
+
+impl Comment {
+    pub fn is_trailing(&self) -> bool {
+        self.after_code.is_some()
+    }
+}
+
+/// Everything in a source file the parser skips, in source order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trivia {
+    pub comments: Vec<Comment>,
+    /// Start of every line with nothing but whitespace on it
+    pub blank_lines: Vec<CharOffset>,
+    /// Extent of each module item, from its first token (a doc comment or
+    /// `pub` included) to its last; recorded by parsers whose item spans
+    /// are not exact
+    pub item_extents: Vec<Span>,
+}
+
+impl Trivia {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.comments.is_empty()
+    }
+
+    /// Whether a blank line lies between `from` and `to`
+    pub fn blank_line_between(&self, from: CharOffset, to: CharOffset) -> bool {
+        let first = self.blank_lines.partition_point(|&line| line < from);
+        self.blank_lines.get(first).is_some_and(|&line| line < to)
+    }
+
+    /// Start and end of the `index`th module item, falling back to `span`
+    /// when the parser recorded no extents
+    pub fn item_extent(&self, index: usize, span: Span) -> (CharOffset, CharOffset) {
+        match self.item_extents.get(index) {
+            Some(extent) => (extent.start, extent.end),
+            None => (span.start, span.start),
+        }
+    }
+
+    pub fn cursor(&self) -> CommentCursor<'_> {
+        CommentCursor::new(&self.comments)
+    }
+}
+
+/// Hands out comments to a printer in source order, each exactly once
+#[derive(Debug, Clone)]
+pub struct CommentCursor<'a> {
+    comments: &'a [Comment],
+    next: usize,
+}
+
+impl<'a> CommentCursor<'a> {
+    pub fn new(comments: &'a [Comment]) -> Self {
+        CommentCursor { comments, next: 0 }
+    }
+
+    /// Comments not handed out yet that start before `offset`
+    pub fn before(&mut self, offset: CharOffset) -> &'a [Comment] {
+        let pending = &self.comments[self.next..];
+        let count = pending.iter().take_while(|comment| comment.span.start < offset).count();
+        self.next += count;
+        &pending[..count]
+    }
+
+    /// The next comment, when it ends a line of code starting at or after
+    /// `from` and comes before `until`
+    pub fn trailing(&mut self, from: CharOffset, until: CharOffset) -> Option<&'a Comment> {
+        let comment = self.comments.get(self.next)?;
+        let after_code = comment.after_code?;
+        if after_code < from || comment.span.start >= until {
+            return None;
+        }
+        self.next += 1;
+        Some(comment)
+    }
+
+    /// Comments not handed out yet
+    pub fn rest(&mut self) -> &'a [Comment] {
+        let rest = &self.comments[self.next..];
+        self.next = self.comments.len();
+        rest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::span::FileId;
+
+    fn comment(text: &str, start: u32, after_code: Option<u32>) -> Comment {
+        let span = Span::new(FileId::new(0), CharOffset(start), CharOffset(start + text.len() as u32 + 2));
+        Comment { text: text.to_string(), span, after_code: after_code.map(CharOffset) }
+    }
+
+    #[test]
+    fn test_cursor_hands_out_each_comment_once() {
+        let trivia = Trivia {
+            comments: vec![comment(" header", 0, None), comment(" one", 20, Some(15)), comment(" end", 40, None)],
+            ..Trivia::default()
+        };
+        let mut cursor = trivia.cursor();
+        assert_eq!(cursor.before(CharOffset(10)).len(), 1);
+        assert!(cursor.before(CharOffset(10)).is_empty());
+        assert!(cursor.trailing(CharOffset(16), CharOffset(30)).is_none(), "the code before it starts earlier");
+        assert_eq!(cursor.trailing(CharOffset(12), CharOffset(30)).unwrap().text, " one");
+        assert!(cursor.trailing(CharOffset(30), CharOffset(50)).is_none(), "own-line comments never trail");
+        assert_eq!(cursor.rest().len(), 1);
+        assert!(cursor.rest().is_empty());
+    }
+
+    #[test]
+    fn test_blank_line_between() {
+        let trivia = Trivia { blank_lines: vec![CharOffset(5), CharOffset(30)], ..Trivia::default() };
+        assert!(trivia.blank_line_between(CharOffset(0), CharOffset(10)));
+        assert!(!trivia.blank_line_between(CharOffset(6), CharOffset(30)));
+        assert!(trivia.blank_line_between(CharOffset(6), CharOffset(31)));
+    }
+}
@@ -0,0 +1,75 @@
+//! Unicode character classes and normalization for names
+//!
+//! Identifiers follow UAX #31: they start with an `XID_Start` character
+//! or `_` and continue with `XID_Continue` characters or `'`. Operators
+//! are runs of the ASCII operator characters and of Unicode math symbols
+//! (general category `Sm`), such as `∘` or `⊕`. Names are put in NFC
+//! before they are interned, so that the same name typed with composed
+//! or decomposed characters is one symbol with one hash.
+
+use icu_normalizer::ComposingNormalizerBorrowed;
+use icu_properties::{
+    props::{GeneralCategory, XidContinue, XidStart},
+    CodePointMapData, CodePointSetData,
+};
+use std::borrow::Cow;
+
+/// ASCII characters user-defined operators are made of
+pub const OPERATOR_CHARS: &str = "+-*/%=!<>&^|~$";
+
+/// Whether an identifier can start with `c`
+pub fn is_identifier_start(c: char) -> bool {
+    if c.is_ascii() {
+        c.is_ascii_alphabetic() || c == '_'
+    } else {
+        CodePointSetData::new::<XidStart>().contains(c)
+    }
+}
+
+/// Whether an identifier can continue with `c`
+pub fn is_identifier_continue(c: char) -> bool {
+    if c.is_ascii() {
+        c.is_ascii_alphanumeric() || c == '_' || c == '\''
+    } else {
+        CodePointSetData::new::<XidContinue>().contains(c)
+    }
+}
+
+/// Whether `c` can be part of a user-defined operator
+pub fn is_operator_char(c: char) -> bool {
+    if c.is_ascii() {
+        OPERATOR_CHARS.contains(c)
+    } else {
+        CodePointMapData::<GeneralCategory>::new().get(c) == GeneralCategory::MathSymbol
+    }
+}
+
+/// `name` in Normalization Form C, borrowed when it already is
+pub fn nfc(name: &str) -> Cow<'_, str> {
+    if name.is_ascii() {
+        Cow::Borrowed(name)
+    } else {
+        ComposingNormalizerBorrowed::new_nfc().normalize(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_character_classes() {
+        assert!(is_identifier_start('λ') && is_identifier_start('名') && is_identifier_start('_'));
+        assert!(!is_identifier_start('1') && !is_identifier_start('∘') && !is_identifier_start('\''));
+        assert!(is_identifier_continue('1') && is_identifier_continue('\'') && is_identifier_continue('\u{301}'));
+        assert!(is_operator_char('∘') && is_operator_char('⊕') && is_operator_char('≤') && is_operator_char('-'));
+        assert!(!is_operator_char('λ') && !is_operator_char(':') && !is_operator_char('…'));
+    }
+
+    #[test]
+    fn test_names_are_normalized() {
+        assert_eq!(nfc("cafe\u{301}"), "caf\u{e9}");
+        assert!(matches!(nfc("caf\u{e9}"), Cow::Borrowed(_)));
+        assert!(matches!(nfc("plain"), Cow::Borrowed(_)));
+    }
+}
@@ -8,6 +8,7 @@ use crate::metadata::ContentHash;
 use crate::ast::Type;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
+use std::fmt;
 
 /// A versioned reference to a function
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -65,6 +66,75 @@ impl Version {
         (self.minor > other.minor || 
          (self.minor == other.minor && self.patch >= other.patch))
     }
+
+    /// Parse `1.2.3`, with an optional `-pre` suffix; missing minor and
+    /// patch numbers are zero
+    pub fn parse(s: &str) -> Option<Self> {
+        let (numbers, pre_release) = match s.split_once('-') {
+            Some((numbers, pre)) => (numbers, Some(pre.to_string())),
+            None => (s, None),
+        };
+        let mut parts = numbers.split('.').map(|part| part.parse::<u32>());
+        let major = parts.next()?.ok()?;
+        let minor = parts.next().transpose().ok()?.unwrap_or(0);
+        let patch = parts.next().transpose().ok()?.unwrap_or(0);
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Self { major, minor, patch, pre_release })
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if let Some(pre) = &self.pre_release {
+            write!(f, "-{pre}")?;
+        }
+        Ok(())
+    }
+}
+
+impl VersionSpec {
+    /// Parse a requirement as written after `@` in an import or in a manifest
+    ///
+    /// `^1.2` and a bare `1.2` accept compatible versions, `~1.2.3` the same
+    /// minor version, `=1.2.3` only that version, `>=1.0` and `<=2.0` one-sided
+    /// ranges, and `*` or `latest` anything.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        if s == "*" || s == "latest" {
+            return Some(VersionSpec::Latest);
+        }
+        if let Some(version) = s.strip_prefix(">=") {
+            return Some(VersionSpec::Range { min: Some(Version::parse(version)?), max: None });
+        }
+        if let Some(version) = s.strip_prefix("<=") {
+            return Some(VersionSpec::Range { min: None, max: Some(Version::parse(version)?) });
+        }
+        if let Some(version) = s.strip_prefix('=') {
+            return Some(VersionSpec::Exact(Version::parse(version)?));
+        }
+        if let Some(version) = s.strip_prefix('~') {
+            let min = Version::parse(version)?;
+            let max = Version::new(min.major, min.minor, u32::MAX);
+            return Some(VersionSpec::Range { min: Some(min), max: Some(max) });
+        }
+        Some(VersionSpec::Compatible(Version::parse(s.strip_prefix('^').unwrap_or(s))?))
+    }
+
+    /// Whether `version` satisfies the requirement; a content hash names no version
+    pub fn matches(&self, version: &Version) -> bool {
+        match self {
+            VersionSpec::Exact(exact) => version == exact,
+            VersionSpec::Compatible(base) => version.is_compatible_with(base),
+            VersionSpec::Range { min, max } => {
+                min.as_ref().is_none_or(|min| version >= min) && max.as_ref().is_none_or(|max| version <= max)
+            }
+            VersionSpec::Latest => true,
+            VersionSpec::Hash(_) => false,
+        }
+    }
 }
 
 /// Function signature for compatibility checking
@@ -401,4 +471,23 @@ mod tests {
         assert!(v2.is_compatible_with(&v1));
         assert!(!v3.is_compatible_with(&v1));
     }
+
+    #[test]
+    fn test_requirement_parsing() {
+        let v = |s| Version::parse(s).unwrap();
+        assert_eq!(v("1.2"), Version::new(1, 2, 0));
+        assert_eq!(v("1.2.3-beta").to_string(), "1.2.3-beta");
+        assert_eq!(Version::parse("1.x"), None);
+
+        let spec = |s| VersionSpec::parse(s).unwrap();
+        assert!(spec("^1.0.0").matches(&v("1.4.2")));
+        assert!(!spec("^1.2.0").matches(&v("2.0.0")));
+        assert!(spec("1.2").matches(&v("1.3.0")));
+        assert!(spec("~1.2.3").matches(&v("1.2.9")));
+        assert!(!spec("~1.2.3").matches(&v("1.3.0")));
+        assert!(!spec("=1.2.3").matches(&v("1.2.4")));
+        assert!(spec(">=1.0").matches(&v("3.0.0")));
+        assert!(spec("latest").matches(&v("0.1.0")));
+        assert_eq!(VersionSpec::parse("^one"), None);
+    }
 }
\ No newline at end of file
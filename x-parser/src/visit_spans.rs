@@ -0,0 +1,370 @@
+//! Mutable access to every span of a tree
+//!
+//! Incremental reparsing keeps the items after an edit and moves them by
+//! the length the edit added or removed, which means touching every span
+//! they contain. `VisitSpans` walks them all; the AST types only list the
+//! fields that can hold a span.
+
+use std::collections::HashMap;
+
+use crate::ast::*;
+use crate::span::{CharOffset, Span};
+use crate::symbol::Symbol;
+
+pub trait VisitSpans {
+    /// Call `f` on every span in `self`
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span));
+
+    /// Move every offset at or after `from` by `delta`
+    fn shift_spans(&mut self, from: CharOffset, delta: i64) {
+        let shift = |offset: &mut CharOffset| {
+            if *offset >= from {
+                *offset = CharOffset::new((offset.as_u32() as i64 + delta) as u32);
+            }
+        };
+        self.visit_spans(&mut |span| {
+            shift(&mut span.start);
+            shift(&mut span.end);
+        });
+    }
+}
+
+impl VisitSpans for Span {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        f(self)
+    }
+}
+
+impl<T: VisitSpans> VisitSpans for [T] {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        self.iter_mut().for_each(|value| value.visit_spans(f))
+    }
+}
+
+impl<T: VisitSpans> VisitSpans for Vec<T> {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        self.as_mut_slice().visit_spans(f)
+    }
+}
+
+impl<T: VisitSpans> VisitSpans for Option<T> {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        if let Some(value) = self {
+            value.visit_spans(f)
+        }
+    }
+}
+
+impl<T: VisitSpans> VisitSpans for Box<T> {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        (**self).visit_spans(f)
+    }
+}
+
+impl<T: VisitSpans> VisitSpans for HashMap<Symbol, T> {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        self.values_mut().for_each(|value| value.visit_spans(f))
+    }
+}
+
+impl<T: VisitSpans> VisitSpans for (Symbol, T) {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        self.1.visit_spans(f)
+    }
+}
+
+/// Structs, with the fields that can hold spans
+macro_rules! visit_fields {
+    ($($name:ident { $($field:ident),* })*) => {
+        $(
+            impl VisitSpans for $name {
+                fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+                    $(self.$field.visit_spans(f);)*
+                }
+            }
+        )*
+    };
+}
+
+visit_fields! {
+    CompilationUnit { module, span }
+    Module { name, documentation, exports, imports, items, span }
+    ModulePath { span }
+    ExportList { items, span }
+    ExportItem { span }
+    Import { module_path, kind, span }
+    FunctionImport { span }
+    ImportItem { span }
+    TypeDef { documentation, type_params, kind, visibility, span }
+    Constructor { fields, result, span }
+    ValueDef { documentation, type_annotation, parameters, body, visibility, imports, span }
+    TestDef { documentation, setup, teardown, body, visibility, imports, span }
+    BenchDef { documentation, body, visibility, span }
+    EffectDef { documentation, type_params, operations, visibility, span }
+    EffectOperation { parameters, return_type, span }
+    ClassDef { documentation, type_params, superclasses, methods, visibility, span }
+    ClassMethod { type_annotation, span }
+    InstanceDef { types, constraints, methods, span }
+    FixityDecl { span }
+    ConditionalItem { item, span }
+    HandlerDef { type_annotation, handled_effects, handlers, return_clause, visibility, span }
+    EffectHandler { effect, parameters, body, span }
+    ReturnClause { parameter, body, span }
+    MatchArm { pattern, guard, body, span }
+    ModuleTypeDef { signature, visibility, span }
+    ModuleSignature { items, span }
+    TypeParam { constraints, span }
+    TypeConstraint { types, span }
+    EffectRef { args, span }
+    EffectSet { effects, span }
+    FunctionSignature { span }
+    ComponentInterface { items, span }
+    ResourceMethod { signature, span }
+    Documentation { doc_comment }
+    DocComment { code_blocks, span }
+    CodeBlock { span }
+}
+
+impl VisitSpans for ImportKind {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        match self {
+            ImportKind::Selective(items)
+            | ImportKind::Interface { items, .. }
+            | ImportKind::Core { items, .. } => items.visit_spans(f),
+            ImportKind::Conditional(condition) => condition.visit_spans(f),
+            ImportKind::Func { signature, .. } => signature.visit_spans(f),
+            ImportKind::Qualified | ImportKind::Wildcard | ImportKind::Lazy | ImportKind::Signature(_) => {}
+        }
+    }
+}
+
+impl VisitSpans for Item {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        match self {
+            Item::TypeDef(def) => def.visit_spans(f),
+            Item::ValueDef(def) => def.visit_spans(f),
+            Item::EffectDef(def) => def.visit_spans(f),
+            Item::HandlerDef(def) => def.visit_spans(f),
+            Item::ModuleTypeDef(def) => def.visit_spans(f),
+            Item::InterfaceDef(def) => def.visit_spans(f),
+            Item::TestDef(def) => def.visit_spans(f),
+            Item::BenchDef(def) => def.visit_spans(f),
+            Item::ClassDef(def) => def.visit_spans(f),
+            Item::InstanceDef(def) => def.visit_spans(f),
+            Item::Fixity(decl) => decl.visit_spans(f),
+            Item::Conditional(conditional) => conditional.visit_spans(f),
+        }
+    }
+}
+
+impl VisitSpans for TypeDefKind {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        match self {
+            TypeDefKind::Data(constructors) => constructors.visit_spans(f),
+            TypeDefKind::Alias(alias) => alias.visit_spans(f),
+            TypeDefKind::Abstract => {}
+        }
+    }
+}
+
+impl VisitSpans for Visibility {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        if let Visibility::InPath(path) = self {
+            path.visit_spans(f)
+        }
+    }
+}
+
+impl VisitSpans for DoStatement {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        match self {
+            DoStatement::Let { pattern, expr, span } | DoStatement::Bind { pattern, expr, span } => {
+                pattern.visit_spans(f);
+                expr.visit_spans(f);
+                f(span);
+            }
+            DoStatement::Expr(expr) => expr.visit_spans(f),
+        }
+    }
+}
+
+impl VisitSpans for SignatureItem {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        match self {
+            SignatureItem::TypeSig { type_params, span, .. } => {
+                type_params.visit_spans(f);
+                f(span);
+            }
+            SignatureItem::ValueSig { type_annotation, span, .. } => {
+                type_annotation.visit_spans(f);
+                f(span);
+            }
+            SignatureItem::EffectSig { operations, span, .. } => {
+                operations.visit_spans(f);
+                f(span);
+            }
+        }
+    }
+}
+
+impl VisitSpans for InterfaceItem {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        match self {
+            InterfaceItem::Func { signature, span, .. } => {
+                signature.visit_spans(f);
+                f(span);
+            }
+            InterfaceItem::Type { definition, span, .. } => {
+                definition.visit_spans(f);
+                f(span);
+            }
+            InterfaceItem::Resource { methods, span, .. } => {
+                methods.visit_spans(f);
+                f(span);
+            }
+        }
+    }
+}
+
+impl VisitSpans for Type {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        match self {
+            Type::Var(_, span) | Type::Con(_, span) | Type::Hole(span) => f(span),
+            Type::App(base, args, span) => {
+                base.visit_spans(f);
+                args.visit_spans(f);
+                f(span);
+            }
+            Type::Fun { params, return_type, effects, span } => {
+                params.visit_spans(f);
+                return_type.visit_spans(f);
+                effects.visit_spans(f);
+                f(span);
+            }
+            Type::Forall { type_params, body, span } | Type::Exists { type_params, body, span } => {
+                type_params.visit_spans(f);
+                body.visit_spans(f);
+                f(span);
+            }
+            Type::Effects(effects, span) => {
+                effects.visit_spans(f);
+                f(span);
+            }
+            Type::Record { fields, rest, span }
+            | Type::Variant { variants: fields, rest, span }
+            | Type::Row { fields, rest, span } => {
+                fields.visit_spans(f);
+                rest.visit_spans(f);
+                f(span);
+            }
+            Type::Tuple { types, span } => {
+                types.visit_spans(f);
+                f(span);
+            }
+        }
+    }
+}
+
+impl VisitSpans for Expr {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        match self {
+            Expr::Literal(_, span) | Expr::Var(_, span) | Expr::Pack { span, .. } | Expr::Error(span) => f(span),
+            Expr::App(function, args, span) => {
+                function.visit_spans(f);
+                args.visit_spans(f);
+                f(span);
+            }
+            Expr::Lambda { parameters, body, span } => {
+                parameters.visit_spans(f);
+                body.visit_spans(f);
+                f(span);
+            }
+            Expr::Let { pattern, type_annotation, value, body, span } => {
+                pattern.visit_spans(f);
+                type_annotation.visit_spans(f);
+                value.visit_spans(f);
+                body.visit_spans(f);
+                f(span);
+            }
+            Expr::If { condition, then_branch, else_branch, span } => {
+                condition.visit_spans(f);
+                then_branch.visit_spans(f);
+                else_branch.visit_spans(f);
+                f(span);
+            }
+            Expr::Match { scrutinee, arms, span } => {
+                scrutinee.visit_spans(f);
+                arms.visit_spans(f);
+                f(span);
+            }
+            Expr::Do { statements, span } => {
+                statements.visit_spans(f);
+                f(span);
+            }
+            Expr::Handle { expr, handlers, return_clause, span, .. } => {
+                expr.visit_spans(f);
+                handlers.visit_spans(f);
+                return_clause.visit_spans(f);
+                f(span);
+            }
+            Expr::Resume { value, span } | Expr::Project { record: value, span, .. } => {
+                value.visit_spans(f);
+                f(span);
+            }
+            Expr::Perform { args, span, .. } | Expr::Tuple { elements: args, span } => {
+                args.visit_spans(f);
+                f(span);
+            }
+            Expr::Ann { expr, type_annotation, span } => {
+                expr.visit_spans(f);
+                type_annotation.visit_spans(f);
+                f(span);
+            }
+            Expr::Record { fields, span } => {
+                fields.visit_spans(f);
+                f(span);
+            }
+            Expr::RecordUpdate { record, fields, span } => {
+                record.visit_spans(f);
+                fields.visit_spans(f);
+                f(span);
+            }
+            Expr::Unpack { value, body, span, .. } => {
+                value.visit_spans(f);
+                body.visit_spans(f);
+                f(span);
+            }
+        }
+    }
+}
+
+impl VisitSpans for Pattern {
+    fn visit_spans(&mut self, f: &mut dyn FnMut(&mut Span)) {
+        match self {
+            Pattern::Wildcard(span) | Pattern::Variable(_, span) | Pattern::Literal(_, span) => f(span),
+            Pattern::Constructor { args, span, .. } | Pattern::Tuple { patterns: args, span } => {
+                args.visit_spans(f);
+                f(span);
+            }
+            Pattern::Record { fields, rest, span } => {
+                fields.visit_spans(f);
+                rest.visit_spans(f);
+                f(span);
+            }
+            Pattern::Or { left, right, span } => {
+                left.visit_spans(f);
+                right.visit_spans(f);
+                f(span);
+            }
+            Pattern::As { pattern, span, .. } => {
+                pattern.visit_spans(f);
+                f(span);
+            }
+            Pattern::Ann { pattern, type_annotation, span } => {
+                pattern.visit_spans(f);
+                type_annotation.visit_spans(f);
+                f(span);
+            }
+        }
+    }
+}
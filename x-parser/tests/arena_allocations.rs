@@ -0,0 +1,90 @@
+//! Allocation counts of `AstArena` parses
+//!
+//! Counting needs a `#[global_allocator]`, which would apply to every test
+//! of the library, so this test is a binary of its own.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use x_parser::{parse_source, AstArena, FileId, SyntaxStyle};
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Counts the allocations of the current thread, so tests running in
+/// parallel do not disturb each other
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, pointer: *mut u8, layout: Layout) {
+        System.dealloc(pointer, layout)
+    }
+
+    unsafe fn realloc(&self, pointer: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.realloc(pointer, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATIONS.with(Cell::get);
+    let value = f();
+    (value, ALLOCATIONS.with(Cell::get) - before)
+}
+
+fn generated(lines: usize) -> String {
+    let mut source = String::from("module Generated\n");
+    for i in 0..lines {
+        source.push_str(&format!("let f{i} = fun x y -> if x > {i} then (x + y) * {i} else f{i} (x - 1) [y; {i}]\n"));
+    }
+    source
+}
+
+#[test]
+fn test_reparse_reuses_storage() {
+    let source = generated(500);
+    let file_id = FileId::new(3);
+    let (fresh, fresh_allocations) = allocations(|| parse_source(&source, file_id, SyntaxStyle::SExpression).unwrap());
+
+    let mut arena = AstArena::new();
+    let first = arena.parse(&source, file_id).unwrap();
+    assert_eq!(first, fresh);
+    arena.recycle(first);
+    assert!(arena.retained_bytes() > 0);
+
+    let (second, reused_allocations) = allocations(|| arena.parse(&source, file_id).unwrap());
+    assert_eq!(second, fresh);
+    assert!(
+        reused_allocations * 2 < fresh_allocations,
+        "{reused_allocations} allocations reparsing, {fresh_allocations} parsing afresh",
+    );
+
+    let retained = arena.retained_bytes();
+    arena.release(file_id);
+    assert!(arena.retained_bytes() < retained);
+}
+
+#[test]
+fn test_first_parse_reuses_nodes_of_other_files() {
+    let source = generated(500);
+    let (fresh, fresh_allocations) = allocations(|| parse_source(&source, FileId::new(5), SyntaxStyle::SExpression).unwrap());
+
+    let mut arena = AstArena::new();
+    let other = arena.parse(&generated(500), FileId::new(4)).unwrap();
+    arena.recycle(other);
+
+    let (first, first_allocations) = allocations(|| arena.parse(&source, FileId::new(5)).unwrap());
+    assert_eq!(first, fresh);
+    assert!(
+        first_allocations * 2 < fresh_allocations,
+        "{first_allocations} allocations parsing a new file, {fresh_allocations} parsing afresh",
+    );
+}
@@ -0,0 +1,232 @@
+//! Python bindings for the x Language AST editor
+//!
+//! Built with `maturin develop` (or `maturin build`) from this directory,
+//! the `x_lang` module wraps `XLanguageEditor`:
+//!
+//! ```python
+//! import x_lang
+//!
+//! editor = x_lang.Editor()
+//! session = editor.start_session("module Main\nlet one = 1")
+//! item = x_lang.parse_items("module M\nlet one = 10")[0]
+//! editor.apply_operation(session, {"Replace": {"target": {"Path": [0, 0]}, "new_node": {"Item": item}}})
+//! editor.type_check(session)["types"]  # {'one': 'Int'}
+//! ```
+//!
+//! Operations, items and edit results are the editor's serde types as plain
+//! dicts and lists, in the same shape as their JSON. Editor failures raise
+//! `x_lang.EditorError`; malformed operations raise `ValueError`.
+
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+use serde_json::Value;
+use x_checker::TypeError;
+use x_editor::{parse_query, run_query, EditError, EditOperation, LanguageServiceConfig, QueryNode, SessionId, XLanguageEditor};
+use x_parser::syntax::{ocaml::OCamlPrinter, SyntaxConfig, SyntaxPrinter};
+use x_parser::{parse_source, FileId, SyntaxStyle};
+
+create_exception!(x_lang, EditorError, PyException);
+
+fn editor_error(error: impl std::fmt::Display) -> PyErr {
+    EditorError::new_err(error.to_string())
+}
+
+/// A Python object converted through its JSON form
+fn from_python<T: serde::de::DeserializeOwned>(value: &Bound<'_, PyAny>) -> PyResult<T> {
+    let json: String = value.py().import("json")?.call_method1("dumps", (value,))?.extract()?;
+    serde_json::from_str(&json).map_err(|error| PyValueError::new_err(error.to_string()))
+}
+
+fn to_python(py: Python<'_>, value: &impl serde::Serialize) -> PyResult<PyObject> {
+    let json = serde_json::to_string(value).map_err(editor_error)?;
+    Ok(py.import("json")?.call_method1("loads", (json,))?.unbind())
+}
+
+fn session_id(id: &str) -> PyResult<SessionId> {
+    serde_json::from_value(Value::String(id.to_string()))
+        .map_err(|_| PyValueError::new_err(format!("not a session id: {id}")))
+}
+
+fn node<'py>(py: Python<'py>, node: &QueryNode) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    dict.set_item("kind", node.kind)?;
+    dict.set_item("name", node.name.map(|name| name.to_string()))?;
+    dict.set_item("start", node.span.start.as_u32())?;
+    dict.set_item("end", node.span.end.as_u32())?;
+    Ok(dict)
+}
+
+fn diagnostic<'py>(py: Python<'py>, error: &TypeError) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    dict.set_item("code", error.code())?;
+    dict.set_item("message", error.to_string())?;
+    dict.set_item("start", error.span().start.as_u32())?;
+    dict.set_item("end", error.span().end.as_u32())?;
+    Ok(dict)
+}
+
+/// Editing sessions over x Language modules
+#[pyclass(name = "Editor", unsendable)]
+pub struct Editor {
+    editor: XLanguageEditor,
+}
+
+#[pymethods]
+impl Editor {
+    #[new]
+    fn new() -> Self {
+        Editor { editor: XLanguageEditor::new(LanguageServiceConfig::default()) }
+    }
+
+    /// Parse `source` into a new session, returning its id
+    fn start_session(&mut self, source: &str) -> PyResult<String> {
+        Ok(self.editor.start_session(source).map_err(editor_error)?.to_string())
+    }
+
+    fn close_session(&mut self, session: &str) -> PyResult<()> {
+        self.editor.close_session(session_id(session)?).map_err(editor_error)
+    }
+
+    /// Apply an operation given as a dict, e.g. `{"Delete": {"target": {"Path": [0, 1]}}}`
+    fn apply_operation(&mut self, py: Python<'_>, session: &str, operation: &Bound<'_, PyAny>) -> PyResult<PyObject> {
+        let operation: EditOperation = from_python(operation)?;
+        let result = self.editor.apply_operation(session_id(session)?, operation).map_err(editor_error)?;
+        to_python(py, &result)
+    }
+
+    /// Apply operations all together, or none of them
+    fn apply_batch(&mut self, py: Python<'_>, session: &str, operations: &Bound<'_, PyAny>) -> PyResult<PyObject> {
+        let operations: Vec<EditOperation> = from_python(operations)?;
+        let result = self.editor.apply_batch(session_id(session)?, operations).map_err(editor_error)?;
+        to_python(py, &result)
+    }
+
+    fn undo(&mut self, py: Python<'_>, session: &str) -> PyResult<PyObject> {
+        to_python(py, &self.editor.undo(session_id(session)?).map_err(editor_error)?)
+    }
+
+    fn redo(&mut self, py: Python<'_>, session: &str) -> PyResult<PyObject> {
+        to_python(py, &self.editor.redo(session_id(session)?).map_err(editor_error)?)
+    }
+
+    /// Nodes matching a textual query such as `call[name="print"]`, each
+    /// with `kind`, `name`, character offsets `start` and `end`, and `captures`
+    fn query<'py>(&self, py: Python<'py>, session: &str, query: &str) -> PyResult<Bound<'py, PyList>> {
+        let pattern = parse_query(query).map_err(|error| PyValueError::new_err(error.to_string()))?;
+        let session_id = session_id(session)?;
+        let session = self.editor.get_session(session_id)
+            .ok_or_else(|| editor_error(EditError::SessionNotFound { session_id }))?;
+        let matches = PyList::empty(py);
+        for found in run_query(&session.ast, &pattern) {
+            let dict = node(py, &found.node)?;
+            let captures = PyDict::new(py);
+            for (name, captured) in &found.captures {
+                captures.set_item(name, node(py, captured)?)?;
+            }
+            dict.set_item("captures", captures)?;
+            matches.append(dict)?;
+        }
+        Ok(matches)
+    }
+
+    /// `errors` and `warnings` of the session's module, and the inferred
+    /// type of each definition under `types`
+    fn type_check<'py>(&self, py: Python<'py>, session: &str) -> PyResult<Bound<'py, PyDict>> {
+        let result = self.editor.type_check_session(session_id(session)?).map_err(editor_error)?;
+        let errors = result.errors.iter().map(|error| diagnostic(py, error)).collect::<PyResult<Vec<_>>>()?;
+        let warnings = result.warnings.iter().map(|warning| diagnostic(py, warning)).collect::<PyResult<Vec<_>>>()?;
+        let types = PyDict::new(py);
+        for (name, scheme) in &result.inferred_types {
+            types.set_item(name.to_string(), scheme.body.to_string())?;
+        }
+        let dict = PyDict::new(py);
+        dict.set_item("errors", errors)?;
+        dict.set_item("warnings", warnings)?;
+        dict.set_item("types", types)?;
+        Ok(dict)
+    }
+
+    /// The session's module as source
+    fn render(&self, session: &str) -> PyResult<String> {
+        let session_id = session_id(session)?;
+        let session = self.editor.get_session(session_id)
+            .ok_or_else(|| editor_error(EditError::SessionNotFound { session_id }))?;
+        OCamlPrinter::new().print(&session.ast, &SyntaxConfig::default()).map_err(editor_error)
+    }
+}
+
+/// The items of a module, as dicts to build operations from
+#[pyfunction]
+fn parse_items(py: Python<'_>, source: &str) -> PyResult<PyObject> {
+    let unit = parse_source(source, FileId::new(0), SyntaxStyle::SExpression)
+        .map_err(|error| PyValueError::new_err(error.to_string()))?;
+    to_python(py, &unit.module.items)
+}
+
+#[pymodule]
+fn x_lang(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Editor>()?;
+    m.add_function(wrap_pyfunction!(parse_items, m)?)?;
+    m.add("EditorError", m.py().get_type::<EditorError>())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(script: &str) -> PyResult<()> {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let module = PyModule::new(py, "x_lang")?;
+            x_lang(&module)?;
+            let globals = PyDict::new(py);
+            globals.set_item("x_lang", module)?;
+            py.run(&std::ffi::CString::new(script).unwrap(), Some(&globals), None)
+        })
+    }
+
+    #[test]
+    fn test_editing_from_python() {
+        run(r#"
+editor = x_lang.Editor()
+session = editor.start_session("module Main\nlet one = 1\nlet two = one + 1")
+item = x_lang.parse_items("module M\nlet one = 10")[0]
+editor.apply_operation(session, {"Replace": {"target": {"Path": [0, 0]}, "new_node": {"Item": item}}})
+assert "let one = 10" in editor.render(session)
+
+checked = editor.type_check(session)
+assert checked["types"]["two"] == "Int" and checked["errors"] == [], checked
+found = editor.query(session, 'var[name="one"]')
+assert [(m["kind"], m["name"]) for m in found] == [("var", "one")], found
+assert editor.undo(session) is not None
+assert "let one = 1" in editor.render(session)
+"#).unwrap();
+    }
+
+    #[test]
+    fn test_errors_raise_python_exceptions() {
+        run(r#"
+editor = x_lang.Editor()
+session = editor.start_session("module Main\nlet one = 1\nlet two = one + 1")
+try:
+    editor.apply_batch(session, [{"Delete": {"target": {"Path": [0, 0]}}}])
+    raise AssertionError("the batch should have been rolled back")
+except x_lang.EditorError as error:
+    assert "TypeCheck" in str(error) or "type" in str(error).lower(), error
+try:
+    editor.apply_operation(session, {"Frobnicate": {}})
+    raise AssertionError("unknown operations are invalid")
+except ValueError:
+    pass
+editor.close_session(session)
+try:
+    editor.render(session)
+    raise AssertionError("the session was closed")
+except x_lang.EditorError:
+    pass
+"#).unwrap();
+    }
+}
@@ -0,0 +1,286 @@
+//! Benchmarks
+//!
+//! A `bench "name" { body }` item is measured by evaluating its body on the
+//! [`Interpreter`]: first a number of warmup runs whose times are thrown
+//! away, then the measured iterations, summarized as [`BenchStats`]. Each
+//! run gets the full step budget of the [`Limits`].
+//!
+//! Results are kept in a [`BenchBaseline`] keyed by the closure hash of the
+//! benchmark, its body and everything it depends on. A benchmark whose hash
+//! matches the baseline is skipped; a changed one is measured again and
+//! compared with the stats it replaces.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use x_editor::content_addressing::ContentHash;
+use x_editor::dependency_hash::DefinitionHashes;
+use x_parser::{BenchDef, CompilationUnit, Expr, Item, Symbol};
+use crate::interpreter::{Interpreter, Limits};
+
+/// Where `x bench` keeps the baseline, relative to the project
+pub const BASELINE_FILE: &str = ".x-bench/baseline.json";
+
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    /// Unmeasured runs, unless the benchmark sets its own
+    pub warmup: u64,
+    /// Measured runs, unless the benchmark sets its own
+    pub iterations: u64,
+    /// Measure benchmarks even when the baseline is up to date
+    pub force: bool,
+    pub limits: Limits,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self { warmup: 3, iterations: 100, force: false, limits: Limits::default() }
+    }
+}
+
+/// Summary of the measured runs of one benchmark, in nanoseconds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchStats {
+    pub iterations: u64,
+    pub mean_ns: f64,
+    pub median_ns: f64,
+    pub min_ns: u64,
+    pub max_ns: u64,
+    pub std_dev_ns: f64,
+    /// Evaluation steps of one run
+    pub steps: u64,
+}
+
+impl BenchStats {
+    /// Summarize the times of the measured runs; `None` without any
+    pub fn from_samples(samples: &[u64], steps: u64) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let mut sorted = samples.to_vec();
+        sorted.sort_unstable();
+        let count = sorted.len();
+        let mean = sorted.iter().sum::<u64>() as f64 / count as f64;
+        let median = if count.is_multiple_of(2) {
+            (sorted[count / 2 - 1] + sorted[count / 2]) as f64 / 2.0
+        } else {
+            sorted[count / 2] as f64
+        };
+        let variance = sorted.iter().map(|&sample| (sample as f64 - mean).powi(2)).sum::<f64>() / count as f64;
+        Some(Self {
+            iterations: count as u64,
+            mean_ns: mean,
+            median_ns: median,
+            min_ns: sorted[0],
+            max_ns: sorted[count - 1],
+            std_dev_ns: variance.sqrt(),
+            steps,
+        })
+    }
+
+    /// Relative change of the mean from `baseline`, `0.1` being 10% slower
+    pub fn change_from(&self, baseline: &BenchStats) -> Option<f64> {
+        (baseline.mean_ns > 0.0).then(|| (self.mean_ns - baseline.mean_ns) / baseline.mean_ns)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BaselineEntry {
+    pub hash: ContentHash,
+    pub stats: BenchStats,
+}
+
+/// Stats of earlier runs, by `Module.benchmark`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BenchBaseline {
+    pub benchmarks: BTreeMap<String, BaselineEntry>,
+}
+
+impl BenchBaseline {
+    /// The baseline stored at `path`, or an empty one if there is none yet
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let data = fs::read(path)
+            .with_context(|| format!("Failed to read benchmark baseline {}", path.display()))?;
+        serde_json::from_slice(&data)
+            .with_context(|| format!("Failed to parse benchmark baseline {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context("Failed to create benchmark directory")?;
+        }
+        fs::write(path, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("Failed to write benchmark baseline {}", path.display()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BenchOutcome {
+    /// Measured now, with the stats of the previous version if there were any
+    Measured { stats: BenchStats, previous: Option<BenchStats> },
+    /// Skipped, as nothing it depends on changed since the baseline
+    Unchanged { stats: BenchStats },
+    /// The body failed to evaluate
+    Failed { error: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchResult {
+    pub name: Symbol,
+    pub description: Option<String>,
+    pub outcome: BenchOutcome,
+}
+
+/// Runs the benchmarks of modules against a baseline
+pub struct BenchRunner {
+    config: BenchConfig,
+    baseline: BenchBaseline,
+    filter: Option<String>,
+}
+
+impl BenchRunner {
+    pub fn new(config: BenchConfig) -> Self {
+        Self { config, baseline: BenchBaseline::default(), filter: None }
+    }
+
+    pub fn with_baseline(mut self, baseline: BenchBaseline) -> Self {
+        self.baseline = baseline;
+        self
+    }
+
+    /// Only run benchmarks whose name contains `filter`
+    pub fn with_filter(mut self, filter: Option<String>) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// The baseline, updated with every benchmark measured so far
+    pub fn baseline(&self) -> &BenchBaseline {
+        &self.baseline
+    }
+
+    /// Run the benchmarks of `unit`, in source order
+    pub fn run(&mut self, unit: &CompilationUnit) -> Vec<BenchResult> {
+        let module = unit.module.name.to_string();
+        let hashes = DefinitionHashes::compute(unit);
+        let mut interpreter = Interpreter::new().with_limits(self.config.limits);
+        interpreter.load(unit);
+
+        let mut results = Vec::new();
+        for item in &unit.module.items {
+            let Item::BenchDef(bench) = item else { continue };
+            if self.filter.as_deref().is_some_and(|filter| !bench.name.as_str().contains(filter)) {
+                continue;
+            }
+            let key = format!("{module}.{}", bench.name);
+            let hash = hashes.closure_hash(&bench.name);
+            let previous = self.baseline.benchmarks.get(&key);
+
+            let outcome = match (previous, &hash) {
+                (Some(entry), Some(hash)) if entry.hash == *hash && !self.config.force => {
+                    BenchOutcome::Unchanged { stats: entry.stats.clone() }
+                }
+                _ => match self.measure(&mut interpreter, &module, bench) {
+                    Ok(stats) => {
+                        let previous = previous.map(|entry| entry.stats.clone());
+                        if let Some(hash) = hash {
+                            self.baseline.benchmarks.insert(key, BaselineEntry { hash, stats: stats.clone() });
+                        }
+                        BenchOutcome::Measured { stats, previous }
+                    }
+                    Err(error) => BenchOutcome::Failed { error },
+                },
+            };
+            results.push(BenchResult { name: bench.name, description: bench.description.clone(), outcome });
+        }
+        results
+    }
+
+    fn measure(&self, interpreter: &mut Interpreter, module: &str, bench: &BenchDef) -> Result<BenchStats, String> {
+        // The body as a function of no arguments, so every run evaluates it anew
+        let thunk = Expr::Lambda { parameters: Vec::new(), body: Box::new(bench.body.clone()), span: bench.span };
+        let thunk = interpreter.eval_in(module, &thunk).map_err(|error| error.to_string())?;
+
+        let warmup = bench.warmup.unwrap_or(self.config.warmup);
+        let iterations = bench.iterations.unwrap_or(self.config.iterations).max(1);
+        let mut samples = Vec::with_capacity(iterations as usize);
+        let mut steps = 0;
+        for run in 0..warmup + iterations {
+            interpreter.reset_steps();
+            let start = Instant::now();
+            interpreter.apply(thunk.clone(), Vec::new(), bench.span)
+                .map_err(|error| format!("runtime error: {error}"))?;
+            let elapsed = start.elapsed().as_nanos() as u64;
+            if run >= warmup {
+                samples.push(elapsed);
+                steps = interpreter.steps();
+            }
+        }
+        BenchStats::from_samples(&samples, steps).ok_or_else(|| "no iterations measured".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x_parser::{parse_source, FileId, SyntaxStyle};
+
+    fn parse(source: &str) -> CompilationUnit {
+        parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap()
+    }
+
+    #[test]
+    fn test_stats_from_samples() {
+        let stats = BenchStats::from_samples(&[40, 10, 30, 20], 7).unwrap();
+        assert_eq!((stats.iterations, stats.min_ns, stats.max_ns, stats.steps), (4, 10, 40, 7));
+        assert_eq!((stats.mean_ns, stats.median_ns), (25.0, 25.0));
+        assert!((stats.std_dev_ns - 125f64.sqrt()).abs() < 1e-9);
+        assert_eq!(BenchStats::from_samples(&[], 0), None);
+
+        let slower = BenchStats { mean_ns: 30.0, ..stats.clone() };
+        assert_eq!(slower.change_from(&stats), Some(0.2));
+    }
+
+    #[test]
+    fn test_unchanged_benchmarks_are_skipped() {
+        let source = "module Demo\n\
+            let double = fun x -> x + x\n\
+            let triple = fun x -> x * 3\n\
+            bench \"doubling\" with iterations = 5, warmup = 1 { double 21 }\n\
+            bench tripling with iterations = 3 { triple 1 }";
+        let mut runner = BenchRunner::new(BenchConfig::default());
+        let first = runner.run(&parse(source));
+        assert!(first.iter().all(|result| matches!(result.outcome, BenchOutcome::Measured { previous: None, .. })));
+        let BenchOutcome::Measured { stats, .. } = &first[0].outcome else { unreachable!() };
+        assert_eq!(stats.iterations, 5);
+        assert!(stats.steps > 0);
+
+        // Only the benchmark depending on the changed definition runs again
+        let changed = source.replace("x * 3", "x + x + x");
+        let mut runner = BenchRunner::new(BenchConfig::default()).with_baseline(runner.baseline().clone());
+        let second = runner.run(&parse(&changed));
+        assert!(matches!(second[0].outcome, BenchOutcome::Unchanged { .. }));
+        assert!(matches!(second[1].outcome, BenchOutcome::Measured { previous: Some(_), .. }));
+    }
+
+    #[test]
+    fn test_failing_benchmark_and_baseline_file() {
+        let unit = parse("module Demo\nbench broken { missing 1 }\nbench fine with iterations = 2 { 1 + 1 }");
+        let mut runner = BenchRunner::new(BenchConfig::default());
+        let results = runner.run(&unit);
+        assert!(matches!(results[0].outcome, BenchOutcome::Failed { .. }));
+        assert_eq!(runner.baseline().benchmarks.keys().collect::<Vec<_>>(), ["Demo.fine"]);
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join(BASELINE_FILE);
+        assert_eq!(BenchBaseline::load(&path).unwrap(), BenchBaseline::default());
+        runner.baseline().save(&path).unwrap();
+        assert_eq!(&BenchBaseline::load(&path).unwrap(), runner.baseline());
+    }
+}
@@ -0,0 +1,234 @@
+//! Test coverage
+//!
+//! An [`Interpreter`] made with [`Interpreter::with_coverage`] counts how
+//! often each expression is evaluated, by span, and which way each `if` and
+//! `match` goes. [`ModuleCoverage`] maps those counts back onto the
+//! definitions of a module: a line is covered when an expression starting on
+//! it ran, a function when its body ran, and every `if` (then, else) and
+//! `match` (one branch per arm) contributes branches. Test definitions
+//! themselves are left out. The result can be written as LCOV for editors.
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::Instant;
+use x_editor::references::visit_exprs;
+use x_parser::span::LineMap;
+use x_parser::{CompilationUnit, Expr, Item, Span, Symbol};
+use crate::interpreter::{Interpreter, Limits};
+use crate::test_discovery::is_test_name;
+use crate::test_runner::{test_result, TestResult};
+
+/// Evaluation counts recorded by an interpreter
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Coverage {
+    expressions: HashMap<Span, u64>,
+    /// By the span of the `if` or `match` and the branch taken: 0 for
+    /// `then` and 1 for `else`, or the index of the arm
+    branches: HashMap<(Span, usize), u64>,
+}
+
+impl Coverage {
+    pub(crate) fn record(&mut self, span: Span) {
+        *self.expressions.entry(span).or_default() += 1;
+    }
+
+    pub(crate) fn record_branch(&mut self, span: Span, branch: usize) {
+        *self.branches.entry((span, branch)).or_default() += 1;
+    }
+
+    /// How often the expression at `span` was evaluated
+    pub fn hits(&self, span: Span) -> u64 {
+        self.expressions.get(&span).copied().unwrap_or(0)
+    }
+
+    /// How often the `if` or `match` at `span` took `branch`
+    pub fn branch_hits(&self, span: Span, branch: usize) -> u64 {
+        self.branches.get(&(span, branch)).copied().unwrap_or(0)
+    }
+
+    /// Add the counts of another run
+    pub fn merge(&mut self, other: &Coverage) {
+        for (span, hits) in &other.expressions {
+            *self.expressions.entry(*span).or_default() += hits;
+        }
+        for (branch, hits) in &other.branches {
+            *self.branches.entry(*branch).or_default() += hits;
+        }
+    }
+}
+
+/// Run every test of `unit` on one interpreter recording coverage
+pub fn run_tests_with_coverage(unit: &CompilationUnit, limits: Limits) -> (Vec<(Symbol, TestResult)>, Coverage) {
+    let module = unit.module.name.to_string();
+    let mut interpreter = Interpreter::new().with_limits(limits).with_coverage();
+    interpreter.load(unit);
+
+    let results = unit.module.items.iter()
+        .filter_map(|item| match item {
+            Item::ValueDef(def) if is_test_name(def.name.as_str()) => Some(def.name),
+            _ => None,
+        })
+        .map(|test| {
+            let start = Instant::now();
+            let outcome = interpreter.evaluate(&module, test.as_str());
+            (test, test_result(outcome, start))
+        })
+        .collect();
+    let coverage = interpreter.coverage().cloned().unwrap_or_default();
+    (results, coverage)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionCoverage {
+    pub name: Symbol,
+    /// Line of the definition, from 1
+    pub line: u32,
+    /// How often the body was evaluated
+    pub hits: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BranchCoverage {
+    /// Line of the `if` or `match`, from 1
+    pub line: u32,
+    /// Which `if` or `match` of the module, in source order
+    pub block: usize,
+    pub branch: usize,
+    /// `None` when the `if` or `match` itself never ran
+    pub hits: Option<u64>,
+}
+
+/// Coverage of the definitions of one module
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModuleCoverage {
+    pub module: String,
+    pub functions: Vec<FunctionCoverage>,
+    /// Hits of every line an expression starts on, by line from 1
+    pub lines: BTreeMap<u32, u64>,
+    pub branches: Vec<BranchCoverage>,
+}
+
+impl ModuleCoverage {
+    /// Map `coverage` onto the non-test value definitions of `unit`
+    pub fn compute(unit: &CompilationUnit, source: &str, coverage: &Coverage) -> Self {
+        let line_map = LineMap::new(source);
+        let line = |span: Span| line_map.offset_to_position(span.start).line.to_display();
+        let mut report = ModuleCoverage { module: unit.module.name.to_string(), ..ModuleCoverage::default() };
+
+        for item in &unit.module.items {
+            let Item::ValueDef(def) = item else { continue };
+            if is_test_name(def.name.as_str()) {
+                continue;
+            }
+            let mut body = &def.body;
+            while let Expr::Lambda { body: inner, .. } = body {
+                body = inner;
+            }
+            report.functions.push(FunctionCoverage { name: def.name, line: line(def.span), hits: coverage.hits(body.span()) });
+
+            visit_exprs(&def.body, &mut |expr| {
+                let span = expr.span();
+                let hits = coverage.hits(span);
+                let entry = report.lines.entry(line(span)).or_default();
+                *entry = (*entry).max(hits);
+
+                let branches = match expr {
+                    Expr::If { .. } => 2,
+                    Expr::Match { arms, .. } => arms.len(),
+                    _ => return,
+                };
+                let block = report.branches.last().map_or(0, |last| last.block + 1);
+                for branch in 0..branches {
+                    report.branches.push(BranchCoverage {
+                        line: line(span),
+                        block,
+                        branch,
+                        hits: (hits > 0).then(|| coverage.branch_hits(span, branch)),
+                    });
+                }
+            });
+        }
+        report
+    }
+
+    /// (covered, total) lines
+    pub fn line_counts(&self) -> (usize, usize) {
+        (self.lines.values().filter(|hits| **hits > 0).count(), self.lines.len())
+    }
+
+    /// (taken, total) branches
+    pub fn branch_counts(&self) -> (usize, usize) {
+        (self.branches.iter().filter(|branch| branch.hits.is_some_and(|hits| hits > 0)).count(), self.branches.len())
+    }
+
+    /// (called, total) functions
+    pub fn function_counts(&self) -> (usize, usize) {
+        (self.functions.iter().filter(|function| function.hits > 0).count(), self.functions.len())
+    }
+
+    /// Lines with an expression that never ran
+    pub fn uncovered_lines(&self) -> Vec<u32> {
+        self.lines.iter().filter(|(_, hits)| **hits == 0).map(|(line, _)| *line).collect()
+    }
+
+    /// The coverage as an LCOV record for the file at `path`
+    pub fn to_lcov(&self, path: &str) -> String {
+        let mut lcov = format!("TN:\nSF:{path}\n");
+        for function in &self.functions {
+            lcov.push_str(&format!("FN:{},{}\n", function.line, function.name));
+        }
+        for function in &self.functions {
+            lcov.push_str(&format!("FNDA:{},{}\n", function.hits, function.name));
+        }
+        let (called, functions) = self.function_counts();
+        lcov.push_str(&format!("FNF:{functions}\nFNH:{called}\n"));
+        for branch in &self.branches {
+            let taken = branch.hits.map_or_else(|| "-".to_string(), |hits| hits.to_string());
+            lcov.push_str(&format!("BRDA:{},{},{},{taken}\n", branch.line, branch.block, branch.branch));
+        }
+        let (taken, branches) = self.branch_counts();
+        lcov.push_str(&format!("BRF:{branches}\nBRH:{taken}\n"));
+        for (line, hits) in &self.lines {
+            lcov.push_str(&format!("DA:{line},{hits}\n"));
+        }
+        let (covered, lines) = self.line_counts();
+        lcov.push_str(&format!("LF:{lines}\nLH:{covered}\nend_of_record\n"));
+        lcov
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x_parser::{parse_source, FileId, SyntaxStyle};
+
+    const SOURCE: &str = "module Demo\n\
+        let sign = fun n ->\n  if n < 0 then\n    0 - 1\n  else\n    1\n\
+        let unused = fun x -> x\n\
+        let test_negative = sign (0 - 3) == 0 - 1";
+
+    fn coverage() -> ModuleCoverage {
+        let unit = parse_source(SOURCE, FileId::new(0), SyntaxStyle::SExpression).unwrap();
+        let (results, coverage) = run_tests_with_coverage(&unit, Limits::default());
+        assert!(results.iter().all(|(_, result)| result.is_pass()), "{results:?}");
+        ModuleCoverage::compute(&unit, SOURCE, &coverage)
+    }
+
+    #[test]
+    fn test_lines_branches_and_functions() {
+        let report = coverage();
+        assert_eq!(report.function_counts(), (1, 2));
+        assert_eq!(report.uncovered_lines(), vec![6, 7]);
+        assert_eq!(report.branch_counts(), (1, 2));
+        assert_eq!(report.branches[1].hits, Some(0));
+    }
+
+    #[test]
+    fn test_lcov_export() {
+        let lcov = coverage().to_lcov("src/demo.x");
+        assert!(lcov.starts_with("TN:\nSF:src/demo.x\nFN:2,sign\nFN:7,unused\n"), "{lcov}");
+        assert!(lcov.contains("FNDA:0,unused\n"), "{lcov}");
+        assert!(lcov.contains("BRDA:3,0,0,1\nBRDA:3,0,1,0\n"), "{lcov}");
+        assert!(lcov.contains("DA:2,1\n") && lcov.contains("DA:6,0\n"), "{lcov}");
+        assert!(lcov.ends_with("LF:5\nLH:3\nend_of_record\n"), "{lcov}");
+    }
+}
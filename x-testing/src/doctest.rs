@@ -0,0 +1,270 @@
+//! Doctests: examples in documentation comments
+//!
+//! Every ```` ```x ```` block in a doc comment is an expression. It is type
+//! checked as a definition added to the module the comment belongs to, then
+//! evaluated with the [`Interpreter`], and passes when it evaluates to `true`
+//! or `()`. The info string controls what happens to a block:
+//!
+//! ```text
+//! ```x            checked and run
+//! ```x no_run     only checked
+//! ```x ignore     skipped
+//! ```
+//!
+//! Blocks in other languages are left alone.
+
+use std::time::Instant;
+use x_parser::span::LineMap;
+use x_parser::{parse_source, CompilationUnit, Documentation, FileId, Item, Span, SyntaxStyle};
+use crate::interpreter::{with_large_stack, Interpreter, Limits, Value};
+use crate::test_runner::TestResult;
+
+/// Name of the definition a doctest is checked and run as
+const DOCTEST_NAME: &str = "__doctest";
+
+/// File id of doctest code, so its type errors are told apart from the module's
+const DOCTEST_FILE: FileId = FileId(u32::MAX / 2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DoctestMode {
+    Run,
+    NoRun,
+    Ignore,
+}
+
+/// One example from a doc comment
+#[derive(Debug, Clone, PartialEq)]
+pub struct Doctest {
+    /// The documented item, or the module for its own documentation
+    pub item: String,
+    /// Position among the item's examples, from 1
+    pub index: usize,
+    /// Line of the example's opening fence, from 1
+    pub line: usize,
+    pub code: String,
+    pub mode: DoctestMode,
+}
+
+impl Doctest {
+    /// Where the example is, for reports: `src/list.x:12 (length, example 1)`
+    pub fn location(&self, file: &str) -> String {
+        format!("{file}:{} ({}, example {})", self.line, self.item, self.index)
+    }
+}
+
+/// The examples in `unit`'s doc comments, in source order
+pub fn extract_doctests(unit: &CompilationUnit, source: &str) -> Vec<Doctest> {
+    let lines = LineMap::new(source);
+    let module = &unit.module;
+    let module_documentation = module.documentation.as_ref().map(|doc| (module.name.to_string(), doc));
+    let documented = module_documentation.into_iter().chain(module.items.iter().filter_map(documented));
+
+    let mut doctests = Vec::new();
+    for (item, documentation) in documented {
+        let comment = &documentation.doc_comment;
+        let fences = fence_lines(source, comment.span, &lines);
+        let mut index = 0;
+        for (position, block) in comment.code_blocks.iter().enumerate() {
+            if block.language.as_deref() != Some("x") {
+                continue;
+            }
+            index += 1;
+            let mode = match block.metadata.as_deref().map(str::trim) {
+                Some("ignore") => DoctestMode::Ignore,
+                Some("no_run") => DoctestMode::NoRun,
+                _ => DoctestMode::Run,
+            };
+            let line = fences.get(position).copied().unwrap_or_else(|| line_of(&lines, comment.span));
+            doctests.push(Doctest { item: item.clone(), index, line, code: block.content.clone(), mode });
+        }
+    }
+    doctests
+}
+
+/// Check and run one example against the module it documents
+pub fn run_doctest(unit: &CompilationUnit, doctest: &Doctest) -> TestResult {
+    if doctest.mode == DoctestMode::Ignore {
+        return TestResult::Skipped { reason: "marked ignore".to_string() };
+    }
+    let start = Instant::now();
+    let fail = |error: String| TestResult::Fail {
+        duration_ms: start.elapsed().as_millis() as u64,
+        error,
+        output: None,
+    };
+
+    let unit = match with_doctest(unit, &doctest.code) {
+        Ok(unit) => unit,
+        Err(error) => return fail(error),
+    };
+    let result = x_compiler::stdlib::checker().check_compilation_unit(&unit);
+    let errors: Vec<String> = result.errors.iter()
+        .filter(|error| error.span().file_id == DOCTEST_FILE)
+        .map(|error| error.to_string())
+        .collect();
+    if !errors.is_empty() {
+        return fail(format!("does not compile: {}", errors.join("; ")));
+    }
+    if doctest.mode == DoctestMode::NoRun {
+        return TestResult::Pass { duration_ms: start.elapsed().as_millis() as u64, output: None };
+    }
+
+    let module = unit.module.name.to_string();
+    let outcome = with_large_stack(|| {
+        let mut interpreter = Interpreter::new().with_limits(Limits::deep());
+        interpreter.load(&unit);
+        let value = interpreter.evaluate(&module, DOCTEST_NAME);
+        let passed = value.as_ref().map(|value| matches!(value, Value::Bool(true) | Value::Unit));
+        (passed.map_err(|error| error.to_string()), value.map(|v| v.to_string()).unwrap_or_default(), interpreter.output().to_string())
+    });
+    let duration_ms = start.elapsed().as_millis() as u64;
+    let output = (!outcome.2.is_empty()).then_some(outcome.2);
+    match outcome.0 {
+        Ok(true) => TestResult::Pass { duration_ms, output },
+        Ok(false) => TestResult::Fail { duration_ms, error: format!("evaluated to {}", outcome.1), output },
+        Err(error) => TestResult::Fail { duration_ms, error: format!("runtime error: {error}"), output },
+    }
+}
+
+/// Every example in `unit`, with its result
+pub fn run_doctests(unit: &CompilationUnit, source: &str) -> Vec<(Doctest, TestResult)> {
+    extract_doctests(unit, source).into_iter()
+        .map(|doctest| {
+            let result = run_doctest(unit, &doctest);
+            (doctest, result)
+        })
+        .collect()
+}
+
+/// `unit` with the example added as a definition
+fn with_doctest(unit: &CompilationUnit, code: &str) -> Result<CompilationUnit, String> {
+    let indented: Vec<String> = code.lines().map(|line| format!("  {line}")).collect();
+    let source = format!("module {}\nlet {DOCTEST_NAME} =\n{}", unit.module.name, indented.join("\n"));
+    let parsed = parse_source(&source, DOCTEST_FILE, SyntaxStyle::default())
+        .map_err(|error| format!("does not parse: {error}"))?;
+    let mut unit = unit.clone();
+    unit.module.items.extend(parsed.module.items);
+    Ok(unit)
+}
+
+/// Line of every tagged fence opening a code block inside the comment at `span`
+fn fence_lines(source: &str, span: Span, lines: &LineMap) -> Vec<usize> {
+    let Some(text) = span.text(source) else { return Vec::new() };
+    let mut fences = Vec::new();
+    let mut opening = true;
+    let mut offset = 0;
+    // A block comment's own delimiters are skipped; `--!` module docs have none
+    let (skipped, body) = match text.strip_prefix("```") {
+        Some(rest) => (3, rest.strip_suffix("```").unwrap_or(rest)),
+        None => (0, text),
+    };
+    while let Some(found) = body[offset..].find("```") {
+        let at = offset + found;
+        if opening {
+            // Spans count characters
+            let chars = skipped + body[..at].chars().count() as u32;
+            let position = lines.offset_to_position(span.start.advance(chars));
+            fences.push(position.line.as_u32() as usize + 1);
+        }
+        opening = !opening;
+        offset = at + 3;
+    }
+    fences
+}
+
+fn line_of(lines: &LineMap, span: Span) -> usize {
+    lines.offset_to_position(span.start).line.as_u32() as usize + 1
+}
+
+/// An item's name and documentation, if it has any
+fn documented(item: &Item) -> Option<(String, &Documentation)> {
+    let (name, documentation) = match item {
+        Item::TypeDef(def) => (def.name, &def.documentation),
+        Item::ValueDef(def) => (def.name, &def.documentation),
+        Item::EffectDef(def) => (def.name, &def.documentation),
+        Item::TestDef(def) => (def.name, &def.documentation),
+        _ => return None,
+    };
+    documentation.as_ref().map(|documentation| (name.to_string(), documentation))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "module Math\n\
+        import Core.List\n\
+        ```\n\
+        Doubles a number.\n\
+        \n\
+        ```x\n\
+        double 2 == 4\n\
+        ```\n\
+        \n\
+        ```x\n\
+        double (List.length (List.Cons 1 List.Nil)) == 3\n\
+        ```\n\
+        ```\n\
+        let double = fun x -> x + x\n\
+        ```\n\
+        Divides.\n\
+        \n\
+        ```x no_run\n\
+        divide 1 0\n\
+        ```\n\
+        ```x ignore\n\
+        divide \"a\"\n\
+        ```\n\
+        ```x\n\
+        divide 1 0 == 0\n\
+        ```\n\
+        ```x\n\
+        divide true\n\
+        ```\n\
+        ```\n\
+        let divide = fun a -> fun b -> a / b";
+
+    fn unit() -> CompilationUnit {
+        parse_source(SOURCE, FileId::new(0), SyntaxStyle::default()).unwrap()
+    }
+
+    #[test]
+    fn test_extract_doctests() {
+        let doctests = extract_doctests(&unit(), SOURCE);
+        let summary: Vec<_> = doctests.iter().map(|d| (d.item.as_str(), d.index, d.line, d.mode)).collect();
+        assert_eq!(summary, [
+            ("double", 1, 6, DoctestMode::Run),
+            ("double", 2, 10, DoctestMode::Run),
+            ("divide", 1, 18, DoctestMode::NoRun),
+            ("divide", 2, 21, DoctestMode::Ignore),
+            ("divide", 3, 24, DoctestMode::Run),
+            ("divide", 4, 27, DoctestMode::Run),
+        ]);
+        assert_eq!(doctests[0].location("math.x"), "math.x:6 (double, example 1)");
+    }
+
+    #[test]
+    fn test_module_documentation_examples() {
+        let source = "--! Arithmetic, with ünïcödé.\n--!\n--! ```x\n--! 1 + 1 == 2\n--! ```\nmodule Sums\nlet one = 1";
+        let unit = parse_source(source, FileId::new(0), SyntaxStyle::default()).unwrap();
+        let doctests = extract_doctests(&unit, source);
+        let summary: Vec<_> = doctests.iter().map(|d| (d.item.as_str(), d.line, d.code.as_str())).collect();
+        assert_eq!(summary, [("Sums", 3, "1 + 1 == 2")]);
+    }
+
+    #[test]
+    fn test_run_doctests() {
+        let results = run_doctests(&unit(), SOURCE);
+        let error = |index: usize| match &results[index].1 {
+            TestResult::Fail { error, .. } => error.clone(),
+            other => panic!("example {index} did not fail: {other:?}"),
+        };
+
+        assert!(results[0].1.is_pass());
+        assert_eq!(error(1), "evaluated to false");
+        assert!(results[2].1.is_pass(), "no_run examples are only checked");
+        assert!(matches!(results[3].1, TestResult::Skipped { .. }));
+        assert_eq!(error(4), "runtime error: division by zero");
+        assert!(error(5).starts_with("does not compile"), "{}", error(5));
+    }
+}
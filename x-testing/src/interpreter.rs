@@ -0,0 +1,1278 @@
+//! Tree-walking interpreter for type checked modules
+//!
+//! Runs definitions directly from the AST, so tests and documentation
+//! examples can be executed without going through a backend. Modules are
+//! loaded with [`Interpreter::load`]; the `Core` standard library is always
+//! available.
+//!
+//! Effects are handled as the TypeScript runtime handles them. An operation
+//! runs the clause of the innermost handler for it, outside that handler;
+//! resuming returns to the operation, and a clause that finishes without
+//! resuming gives the value of the whole `handle`. A deep handler stays
+//! installed for everything the computation does after it is resumed, while
+//! a shallow one handles a single operation, after which its return clause
+//! no longer applies either.
+//!
+//! A clause that may resume more than once, or keeps computing after it
+//! resumes, instead gets a copy of the handled computation: the computation
+//! is left at the operation, and resuming runs it again from the start,
+//! replaying the results of the operations it performed so far. Output
+//! written during a replay is dropped, as it has been written already.
+//!
+//! Evaluation is bounded by [`Limits`]: a program that does not terminate, or
+//! recurses too deeply, stops with an error instead of hanging the runner.
+//! Each nested call takes several kilobytes of native stack, so the default
+//! depth suits any thread; deeper programs run inside [`with_large_stack`]
+//! with [`Limits::deep`].
+//!
+//! An interpreter made [`Interpreter::with_coverage`] records every
+//! expression it evaluates and the branch each `if` and `match` takes.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use thiserror::Error;
+use crate::coverage::Coverage;
+use x_checker::continuations::{self, Resumption};
+use x_parser::{CompilationUnit, EffectHandler, Expr, HandlerMode, ImportKind, Item, Literal, Pattern, SignatureItem, Span, Symbol, TypeDefKind};
+
+/// Error raised while evaluating
+#[derive(Debug, Clone, Error, PartialEq)]
+#[error("{message}")]
+pub struct RuntimeError {
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl RuntimeError {
+    fn new(message: impl Into<String>, span: Span) -> Self {
+        Self { message: message.into(), span: Some(span) }
+    }
+}
+
+type EvalResult<T = Value> = Result<T, RuntimeError>;
+
+/// How far evaluation may go before giving up
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// Expressions evaluated
+    pub steps: u64,
+    /// Nested function calls
+    pub depth: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self { steps: 1_000_000, depth: 150 }
+    }
+}
+
+impl Limits {
+    /// Limits for an interpreter running inside [`with_large_stack`]
+    pub fn deep() -> Self {
+        Self { steps: 10_000_000, depth: 20_000 }
+    }
+}
+
+/// Run `f` on a thread with a stack large enough for [`Limits::deep`]
+pub fn with_large_stack<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    std::thread::scope(|scope| {
+        std::thread::Builder::new()
+            .stack_size(512 << 20)
+            .spawn_scoped(scope, f)
+            .expect("failed to spawn the interpreter thread")
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+/// A runtime value
+#[derive(Debug, Clone)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    String(String),
+    Bool(bool),
+    Unit,
+    Tuple(Vec<Value>),
+    Record(Vec<(Symbol, Value)>),
+    /// Data constructor applied to all its fields
+    Data(Symbol, Vec<Value>),
+    /// Function together with the arguments applied so far
+    Function(Rc<Function>, Vec<Value>),
+}
+
+#[derive(Debug)]
+pub enum Function {
+    Closure {
+        /// The `Expr::Lambda` evaluated
+        lambda: Rc<Expr>,
+        env: Env,
+        module: Rc<str>,
+        /// Name the closure is bound to inside its own body
+        recursive: Option<Symbol>,
+    },
+    Constructor(Symbol, usize),
+    Builtin(Builtin),
+    /// What an operation clause resumes, holding the value it was resumed
+    /// with once it has been
+    Continuation(Rc<RefCell<Option<Value>>>),
+    /// What a clause that copies its continuation resumes
+    Copied(Captured),
+}
+
+/// A handled computation left at an operation
+#[derive(Debug)]
+pub struct Captured {
+    /// The `Expr::Handle` the computation ran in
+    handle: Rc<Expr>,
+    env: Env,
+    module: Rc<str>,
+    /// Results of the operations it performed before the one it was left at
+    log: Vec<Value>,
+}
+
+impl Function {
+    fn arity(&self) -> usize {
+        match self {
+            Function::Closure { lambda, .. } => match lambda.as_ref() {
+                Expr::Lambda { parameters, .. } => parameters.len(),
+                _ => 0,
+            },
+            Function::Constructor(_, arity) => *arity,
+            Function::Builtin(builtin) => builtin.arity(),
+            Function::Continuation(_) | Function::Copied(_) => 1,
+        }
+    }
+}
+
+/// Operators and functions provided by the runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Builtin {
+    Add, Sub, Mul, Div, Mod,
+    FAdd, FSub, FMul, FDiv,
+    Eq, Ne, Lt, Le, Gt, Ge,
+    Not, Concat, Cons,
+    Pipe, ComposeRight, ComposeLeft,
+    PrintEndline, PrintString, StringOfInt, IntOfString,
+}
+
+impl Builtin {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "+" => Builtin::Add,
+            "-" => Builtin::Sub,
+            "*" => Builtin::Mul,
+            "/" => Builtin::Div,
+            "mod" => Builtin::Mod,
+            "+." => Builtin::FAdd,
+            "-." => Builtin::FSub,
+            "*." => Builtin::FMul,
+            "/." => Builtin::FDiv,
+            "==" | "=" => Builtin::Eq,
+            "!=" | "<>" => Builtin::Ne,
+            "<" => Builtin::Lt,
+            "<=" => Builtin::Le,
+            ">" => Builtin::Gt,
+            ">=" => Builtin::Ge,
+            "not" => Builtin::Not,
+            "^" => Builtin::Concat,
+            "::" => Builtin::Cons,
+            "|>" => Builtin::Pipe,
+            ">>" => Builtin::ComposeRight,
+            "<<" => Builtin::ComposeLeft,
+            "print_endline" => Builtin::PrintEndline,
+            "print_string" => Builtin::PrintString,
+            "string_of_int" => Builtin::StringOfInt,
+            "int_of_string" => Builtin::IntOfString,
+            _ => return None,
+        })
+    }
+
+    fn arity(self) -> usize {
+        match self {
+            Builtin::Not | Builtin::PrintEndline | Builtin::PrintString
+            | Builtin::StringOfInt | Builtin::IntOfString => 1,
+            // The two functions and the argument of the composition
+            Builtin::ComposeRight | Builtin::ComposeLeft => 3,
+            _ => 2,
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Unit, Value::Unit) => true,
+            (Value::Tuple(a), Value::Tuple(b)) => a == b,
+            (Value::Record(a), Value::Record(b)) => {
+                a.len() == b.len() && a.iter().all(|(name, value)| {
+                    b.iter().any(|(other, v)| other == name && v == value)
+                })
+            }
+            (Value::Data(a, xs), Value::Data(b, ys)) => a == b && xs == ys,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(n) => write!(f, "{n}"),
+            Value::Float(x) => write!(f, "{x:?}"),
+            Value::String(s) => write!(f, "{s:?}"),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Unit => write!(f, "()"),
+            Value::Tuple(values) => {
+                let values: Vec<String> = values.iter().map(Value::to_string).collect();
+                write!(f, "({})", values.join(", "))
+            }
+            Value::Record(fields) => {
+                let fields: Vec<String> = fields.iter().map(|(name, value)| format!("{name} = {value}")).collect();
+                write!(f, "{{ {} }}", fields.join(", "))
+            }
+            Value::Data(name, fields) => {
+                write!(f, "{name}")?;
+                for field in fields {
+                    match field {
+                        Value::Data(_, inner) if !inner.is_empty() => write!(f, " ({field})")?,
+                        _ => write!(f, " {field}")?,
+                    }
+                }
+                Ok(())
+            }
+            Value::Function(..) => write!(f, "<function>"),
+        }
+    }
+}
+
+/// Local bindings, innermost first
+#[derive(Debug, Clone, Default)]
+pub struct Env(Option<Rc<Binding>>);
+
+#[derive(Debug)]
+struct Binding {
+    name: Symbol,
+    value: Value,
+    parent: Env,
+}
+
+impl Env {
+    fn bind(&self, name: Symbol, value: Value) -> Env {
+        Env(Some(Rc::new(Binding { name, value, parent: self.clone() })))
+    }
+
+    fn lookup(&self, name: Symbol) -> Option<&Value> {
+        let mut current = self.0.as_ref();
+        while let Some(binding) = current {
+            if binding.name == name {
+                return Some(&binding.value);
+            }
+            current = binding.parent.0.as_ref();
+        }
+        None
+    }
+}
+
+/// Names a loaded module defines and imports
+#[derive(Debug, Default)]
+struct ModuleScope {
+    definitions: HashMap<Symbol, Rc<Expr>>,
+    constructors: HashMap<Symbol, usize>,
+    /// Unqualified names brought in by selective imports
+    imported: HashMap<Symbol, (String, Symbol)>,
+    /// Modules imported with `.*`
+    wildcards: Vec<String>,
+    /// Module paths by the qualifier used for them, `List` for `Core.List`
+    qualifiers: HashMap<Symbol, String>,
+    /// Values listed by each `module type`, which a packed module carries
+    signatures: HashMap<Symbol, Vec<Symbol>>,
+}
+
+/// A handler installed by `handle`
+struct HandlerFrame {
+    id: usize,
+    /// The `Expr::Handle` that installed it, whose clauses it runs
+    handle: Rc<Expr>,
+    env: Env,
+    module: Rc<str>,
+    mode: HandlerMode,
+    /// Whether it is a shallow handler that has handled its operation
+    spent: bool,
+    /// Results of the operations that reached it, in order
+    log: Vec<Value>,
+    /// How many of `log` the computation has performed; fewer while a copy
+    /// of it replays them
+    performed: usize,
+}
+
+/// Why a handled computation is being left
+enum Unwind {
+    /// A clause finished without resuming, giving the `handle` this value
+    Abort(Value),
+    /// The clause at this index handles an operation by copying the computation
+    Suspend { clause: usize, arguments: Vec<Value> },
+}
+
+/// What a name refers to
+enum Resolved {
+    Definition(Rc<str>, Symbol, Rc<Expr>),
+    Constructor(Symbol, usize),
+}
+
+pub struct Interpreter {
+    modules: HashMap<String, Rc<ModuleScope>>,
+    /// Shared copies of lambdas and handlers by address, so closures and
+    /// handler frames do not copy their bodies each time they are created
+    lambdas: HashMap<*const Expr, Rc<Expr>>,
+    /// Every expression evaluated from, kept alive so the addresses above
+    /// are never reused
+    retained: Vec<Rc<Expr>>,
+    /// Values of top-level definitions evaluated so far
+    globals: HashMap<(String, Symbol), Value>,
+    output: String,
+    limits: Limits,
+    steps: u64,
+    depth: usize,
+    /// What has been evaluated, when recording
+    coverage: Option<Coverage>,
+    /// Installed handlers, innermost last
+    handlers: Vec<HandlerFrame>,
+    next_handler: usize,
+    /// Continuations of the operation clauses running, innermost last
+    resumptions: Vec<Value>,
+    /// The handler whose computation is being left, and why; the error
+    /// carrying it is caught by that handler
+    unwinding: Option<(usize, Unwind)>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    /// An interpreter with the standard library loaded
+    pub fn new() -> Self {
+        let mut interpreter = Self {
+            modules: HashMap::new(),
+            lambdas: HashMap::new(),
+            retained: Vec::new(),
+            globals: HashMap::new(),
+            output: String::new(),
+            limits: Limits::default(),
+            steps: 0,
+            depth: 0,
+            coverage: None,
+            handlers: Vec::new(),
+            next_handler: 0,
+            resumptions: Vec::new(),
+            unwinding: None,
+        };
+        for (path, _) in x_compiler::stdlib::MODULES {
+            if let Some(unit) = x_compiler::stdlib::parse(path) {
+                interpreter.load(&unit);
+            }
+        }
+        interpreter
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Record which expressions and branches are evaluated from now on
+    pub fn with_coverage(mut self) -> Self {
+        self.coverage = Some(Coverage::default());
+        self
+    }
+
+    /// What has been evaluated so far, if recording
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Make a module's definitions available, replacing any module of the same name
+    pub fn load(&mut self, unit: &CompilationUnit) {
+        let path = unit.module.name.to_string();
+        let mut scope = ModuleScope::default();
+
+        for import in &unit.module.imports {
+            let imported = import.module_path.to_string();
+            let qualifier = import.alias.or_else(|| import.module_path.segments.last().copied());
+            if let Some(qualifier) = qualifier {
+                scope.qualifiers.insert(qualifier, imported.clone());
+            }
+            match &import.kind {
+                ImportKind::Selective(items) => {
+                    for item in items {
+                        scope.imported.insert(item.alias.unwrap_or(item.name), (imported.clone(), item.name));
+                    }
+                }
+                ImportKind::Wildcard => scope.wildcards.push(imported),
+                _ => {}
+            }
+        }
+
+        for item in &unit.module.items {
+            match item {
+                Item::ValueDef(def) => {
+                    let body = if def.parameters.is_empty() {
+                        def.body.clone()
+                    } else {
+                        Expr::Lambda { parameters: def.parameters.clone(), body: Box::new(def.body.clone()), span: def.span }
+                    };
+                    let body = Rc::new(body);
+                    self.retained.push(body.clone());
+                    scope.definitions.insert(def.name, body);
+                }
+                Item::ModuleTypeDef(def) => {
+                    let values = def.signature.items.iter()
+                        .filter_map(|item| match item {
+                            SignatureItem::ValueSig { name, .. } => Some(*name),
+                            _ => None,
+                        })
+                        .collect();
+                    scope.signatures.insert(def.name, values);
+                }
+                Item::TypeDef(def) => {
+                    if let TypeDefKind::Data(constructors) = &def.kind {
+                        for constructor in constructors {
+                            scope.constructors.insert(constructor.name, constructor.fields.len());
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        self.globals.retain(|(module, _), _| *module != path);
+        self.modules.insert(path, Rc::new(scope));
+    }
+
+    /// Value of the top-level definition `name` of `module`
+    pub fn evaluate(&mut self, module: &str, name: &str) -> EvalResult {
+        let name = Symbol::intern(name);
+        let module: Rc<str> = Rc::from(module);
+        let scope = self.scope(&module, None)?;
+        let body = scope.definitions.get(&name).cloned().ok_or_else(|| RuntimeError {
+            message: format!("`{module}` has no definition `{name}`"),
+            span: None,
+        })?;
+        self.global(module, name, &body)
+    }
+
+    /// Evaluate `expr` in the scope of `module`
+    pub fn eval_in(&mut self, module: &str, expr: &Expr) -> EvalResult {
+        let expr = Rc::new(expr.clone());
+        self.retained.push(expr.clone());
+        self.eval(&expr, &Env::default(), &Rc::from(module))
+    }
+
+    /// Evaluation steps taken since the interpreter was made or last reset
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Count steps against the limit afresh, so each run gets the full budget
+    pub fn reset_steps(&mut self) {
+        self.steps = 0;
+    }
+
+    /// Text printed by the program so far
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+
+    /// Apply a function value to arguments
+    pub fn apply(&mut self, function: Value, arguments: Vec<Value>, span: Span) -> EvalResult {
+        let Value::Function(function, mut applied) = function else {
+            return Err(RuntimeError::new(format!("{function} is not a function"), span));
+        };
+        applied.extend(arguments);
+        let arity = function.arity();
+        if applied.len() < arity {
+            return Ok(Value::Function(function, applied));
+        }
+        let rest = applied.split_off(arity);
+        let result = self.call(function, applied, span)?;
+        if rest.is_empty() {
+            Ok(result)
+        } else {
+            self.apply(result, rest, span)
+        }
+    }
+
+    fn call(&mut self, function: Rc<Function>, arguments: Vec<Value>, span: Span) -> EvalResult {
+        match function.as_ref() {
+            Function::Closure { lambda, env, module, recursive } => {
+                let Expr::Lambda { parameters, body, .. } = lambda.as_ref() else {
+                    return Err(RuntimeError::new("closure without a lambda", span));
+                };
+                let mut env = match recursive {
+                    Some(name) => env.bind(*name, Value::Function(function.clone(), Vec::new())),
+                    None => env.clone(),
+                };
+                for (pattern, argument) in parameters.iter().zip(arguments) {
+                    env = self.bind_pattern(pattern, argument, &env, module)?
+                        .ok_or_else(|| RuntimeError::new("argument does not match the parameter pattern", pattern.span()))?;
+                }
+                self.nested(span, |interpreter| interpreter.eval(body, &env, module))
+            }
+            Function::Constructor(name, _) => Ok(Value::Data(*name, arguments)),
+            Function::Builtin(builtin) => self.call_builtin(*builtin, arguments, span),
+            Function::Continuation(resumed) => {
+                let value = arguments.into_iter().next().unwrap_or(Value::Unit);
+                resume(resumed, value, span)
+            }
+            Function::Copied(captured) => {
+                let mut log = captured.log.clone();
+                log.extend(arguments);
+                let handle = captured.handle.clone();
+                self.nested(span, |interpreter| interpreter.handle(handle, &captured.env, &captured.module, log))
+            }
+        }
+    }
+
+    fn global(&mut self, module: Rc<str>, name: Symbol, body: &Expr) -> EvalResult {
+        let key = (module.to_string(), name);
+        if let Some(value) = self.globals.get(&key) {
+            return Ok(value.clone());
+        }
+        // A definition referring to itself outside a function recurses here
+        let value = self.nested(body.span(), |interpreter| interpreter.eval(body, &Env::default(), &module))?;
+        self.globals.insert(key, value.clone());
+        Ok(value)
+    }
+
+    fn nested(&mut self, span: Span, eval: impl FnOnce(&mut Self) -> EvalResult) -> EvalResult {
+        if self.depth >= self.limits.depth {
+            return Err(RuntimeError::new(format!("call depth limit of {} exceeded", self.limits.depth), span));
+        }
+        self.depth += 1;
+        let result = eval(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// A shared copy of `expr`, made the first time it is asked for
+    fn shared(&mut self, expr: &Expr) -> Rc<Expr> {
+        self.lambdas.entry(expr as *const Expr)
+            .or_insert_with(|| Rc::new(expr.clone()))
+            .clone()
+    }
+
+    fn closure(&mut self, lambda: &Expr, env: &Env, module: &Rc<str>, recursive: Option<Symbol>) -> Value {
+        let lambda = self.shared(lambda);
+        let function = Function::Closure { lambda, env: env.clone(), module: module.clone(), recursive };
+        Value::Function(Rc::new(function), Vec::new())
+    }
+
+    fn scope(&self, module: &str, span: Option<Span>) -> EvalResult<Rc<ModuleScope>> {
+        self.modules.get(module).cloned().ok_or_else(|| RuntimeError {
+            message: format!("module `{module}` is not loaded"),
+            span,
+        })
+    }
+
+    /// Resolve a name that is not a local in `module`
+    fn resolve(&self, module: &str, name: Symbol) -> Option<(Rc<str>, Resolved)> {
+        let scope = self.modules.get(module)?;
+        if let Some(body) = scope.definitions.get(&name) {
+            return Some((Rc::from(module), Resolved::Definition(Rc::from(module), name, body.clone())));
+        }
+        if let Some(arity) = scope.constructors.get(&name) {
+            return Some((Rc::from(module), Resolved::Constructor(name, *arity)));
+        }
+        if let Some((imported, original)) = scope.imported.get(&name) {
+            return self.resolve_exported(imported, *original);
+        }
+        scope.wildcards.iter().find_map(|imported| self.resolve_exported(imported, name))
+    }
+
+    /// `name` as defined by `module` itself
+    fn resolve_exported(&self, module: &str, name: Symbol) -> Option<(Rc<str>, Resolved)> {
+        let scope = self.modules.get(module)?;
+        let module: Rc<str> = Rc::from(module);
+        if let Some(body) = scope.definitions.get(&name) {
+            return Some((module.clone(), Resolved::Definition(module, name, body.clone())));
+        }
+        scope.constructors.get(&name).map(|arity| (module, Resolved::Constructor(name, *arity)))
+    }
+
+    fn value_of(&mut self, resolved: Resolved) -> EvalResult {
+        match resolved {
+            Resolved::Definition(module, name, body) => self.global(module, name, &body),
+            Resolved::Constructor(name, 0) => Ok(Value::Data(name, Vec::new())),
+            Resolved::Constructor(name, arity) => Ok(Value::Function(Rc::new(Function::Constructor(name, arity)), Vec::new())),
+        }
+    }
+
+    /// Module path named by a `Var`/`Project` chain such as `List` or `Core.List`
+    fn qualifier(&self, expr: &Expr, env: &Env, module: &str) -> Option<String> {
+        let mut segments = Vec::new();
+        let mut current = expr;
+        loop {
+            match current {
+                Expr::Project { record, field, .. } => {
+                    segments.push(*field);
+                    current = record;
+                }
+                Expr::Var(name, _) => {
+                    if env.lookup(*name).is_some() || self.resolve(module, *name).is_some() {
+                        return None;
+                    }
+                    segments.push(*name);
+                    break;
+                }
+                _ => return None,
+            }
+        }
+        segments.reverse();
+        if let [single] = segments.as_slice() {
+            if let Some(path) = self.modules.get(module).and_then(|scope| scope.qualifiers.get(single)) {
+                return Some(path.clone());
+            }
+        }
+        let path = segments.iter().map(|segment| segment.as_str()).collect::<Vec<_>>().join(".");
+        self.modules.contains_key(&path).then_some(path)
+    }
+
+    fn tick(&mut self, span: Span) -> EvalResult<()> {
+        self.steps += 1;
+        if self.steps > self.limits.steps {
+            return Err(RuntimeError::new(format!("evaluation step limit of {} exceeded", self.limits.steps), span));
+        }
+        Ok(())
+    }
+
+    fn eval(&mut self, expr: &Expr, env: &Env, module: &Rc<str>) -> EvalResult {
+        self.tick(expr.span())?;
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record(expr.span());
+        }
+        match expr {
+            Expr::Literal(literal, _) => Ok(literal_value(literal)),
+            Expr::Var(name, span) => {
+                if let Some(value) = env.lookup(*name) {
+                    return Ok(value.clone());
+                }
+                if let Some((_, resolved)) = self.resolve(module, *name) {
+                    return self.value_of(resolved);
+                }
+                match Builtin::from_name(name.as_str()) {
+                    Some(builtin) => Ok(Value::Function(Rc::new(Function::Builtin(builtin)), Vec::new())),
+                    None => Err(RuntimeError::new(format!("unbound variable `{name}`"), *span)),
+                }
+            }
+            Expr::App(function, arguments, span) => {
+                // `&&` and `||` only evaluate their right operand when needed
+                if let (Expr::Var(op, _), [left, right]) = (function.as_ref(), arguments.as_slice()) {
+                    if matches!(op.as_str(), "&&" | "||") && env.lookup(*op).is_none() {
+                        let left = self.eval_bool(left, env, module)?;
+                        return match (op.as_str(), left) {
+                            ("&&", false) => Ok(Value::Bool(false)),
+                            ("||", true) => Ok(Value::Bool(true)),
+                            _ => self.eval_bool(right, env, module).map(Value::Bool),
+                        };
+                    }
+                }
+                let function = self.eval(function, env, module)?;
+                let arguments = arguments.iter()
+                    .map(|argument| self.eval(argument, env, module))
+                    .collect::<EvalResult<Vec<_>>>()?;
+                self.apply(function, arguments, *span)
+            }
+            Expr::Lambda { .. } => Ok(self.closure(expr, env, module, None)),
+            Expr::Let { pattern, value, body, span, .. } => {
+                // `let f = fun ...` may refer to itself
+                let value = match (pattern, value.as_ref()) {
+                    (Pattern::Variable(name, _), Expr::Lambda { .. }) => self.closure(value, env, module, Some(*name)),
+                    _ => self.eval(value, env, module)?,
+                };
+                let env = self.bind_pattern(pattern, value, env, module)?
+                    .ok_or_else(|| RuntimeError::new("value does not match the let pattern", *span))?;
+                self.eval(body, &env, module)
+            }
+            Expr::If { condition, then_branch, else_branch, span } => {
+                let taken = self.eval_bool(condition, env, module)?;
+                if let Some(coverage) = &mut self.coverage {
+                    coverage.record_branch(*span, usize::from(!taken));
+                }
+                if taken {
+                    self.eval(then_branch, env, module)
+                } else {
+                    self.eval(else_branch, env, module)
+                }
+            }
+            Expr::Match { scrutinee, arms, span } => {
+                let value = self.eval(scrutinee, env, module)?;
+                for (index, arm) in arms.iter().enumerate() {
+                    let Some(arm_env) = self.bind_pattern(&arm.pattern, value.clone(), env, module)? else {
+                        continue;
+                    };
+                    if let Some(guard) = &arm.guard {
+                        if !self.eval_bool(guard, &arm_env, module)? {
+                            continue;
+                        }
+                    }
+                    if let Some(coverage) = &mut self.coverage {
+                        coverage.record_branch(*span, index);
+                    }
+                    return self.eval(&arm.body, &arm_env, module);
+                }
+                Err(RuntimeError::new(format!("no match arm accepts {value}"), *span))
+            }
+            Expr::Ann { expr, .. } => self.eval(expr, env, module),
+            Expr::Tuple { elements, .. } => elements.iter()
+                .map(|element| self.eval(element, env, module))
+                .collect::<EvalResult<Vec<_>>>()
+                .map(Value::Tuple),
+            Expr::Record { fields, .. } => fields.iter()
+                .map(|(name, value)| Ok((*name, self.eval(value, env, module)?)))
+                .collect::<EvalResult<Vec<_>>>()
+                .map(Value::Record),
+            Expr::Project { record, field, span } => {
+                if let Some(path) = self.qualifier(record, env, module) {
+                    let (_, resolved) = self.resolve_exported(&path, *field)
+                        .ok_or_else(|| RuntimeError::new(format!("`{path}` has no definition `{field}`"), *span))?;
+                    return self.value_of(resolved);
+                }
+                match self.eval(record, env, module)? {
+                    Value::Record(fields) => fields.into_iter()
+                        .find(|(name, _)| name == field)
+                        .map(|(_, value)| value)
+                        .ok_or_else(|| RuntimeError::new(format!("record has no field `{field}`"), *span)),
+                    other => Err(RuntimeError::new(format!("{other} is not a record"), *span)),
+                }
+            }
+            // A packed module is a record of the values its signature lists
+            Expr::Pack { module: packed, signature, span } => {
+                let scope = self.scope(module, Some(*span))?;
+                let values = scope.signatures.get(signature).cloned()
+                    .ok_or_else(|| RuntimeError::new(format!("unknown module type `{signature}`"), *span))?;
+                let path = scope.qualifiers.get(packed).cloned().unwrap_or_else(|| packed.to_string());
+                values.into_iter()
+                    .map(|name| {
+                        let (_, resolved) = self.resolve_exported(&path, name)
+                            .ok_or_else(|| RuntimeError::new(format!("`{path}` has no definition `{name}`"), *span))?;
+                        Ok((name, self.value_of(resolved)?))
+                    })
+                    .collect::<EvalResult<Vec<_>>>()
+                    .map(Value::Record)
+            }
+            Expr::Unpack { name, value, body, .. } => {
+                let value = self.eval(value, env, module)?;
+                self.eval(body, &env.bind(*name, value), module)
+            }
+            Expr::RecordUpdate { record, fields, span } => {
+                let Value::Record(mut current) = self.eval(record, env, module)? else {
+                    return Err(RuntimeError::new("only records can be updated", *span));
+                };
+                for (name, value) in fields {
+                    let value = self.eval(value, env, module)?;
+                    match current.iter_mut().find(|(field, _)| field == name) {
+                        Some(field) => field.1 = value,
+                        None => current.push((*name, value)),
+                    }
+                }
+                Ok(Value::Record(current))
+            }
+            Expr::Handle { .. } => {
+                let handle = self.shared(expr);
+                self.handle(handle, env, module, Vec::new())
+            }
+            Expr::Perform { effect, operation, args, span } => {
+                let arguments = args.iter()
+                    .map(|argument| self.eval(argument, env, module))
+                    .collect::<EvalResult<Vec<_>>>()?;
+                self.perform(*effect, *operation, arguments, *span)
+            }
+            Expr::Resume { value, span } => {
+                let value = self.eval(value, env, module)?;
+                let continuation = self.resumptions.last().cloned()
+                    .ok_or_else(|| RuntimeError::new("resume outside of an operation clause", *span))?;
+                self.apply(continuation, vec![value], *span)
+            }
+            Expr::Do { span, .. } => Err(RuntimeError::new("do blocks are not supported by the interpreter", *span)),
+            Expr::Error(span) => Err(RuntimeError::new("cannot evaluate code that failed to parse", *span)),
+        }
+    }
+
+    /// Run a handled computation, replaying `log` as the results of its first operations
+    fn handle(&mut self, handle: Rc<Expr>, env: &Env, module: &Rc<str>, log: Vec<Value>) -> EvalResult {
+        let Expr::Handle { expr: body, mode, handlers, return_clause, .. } = handle.as_ref() else {
+            return Err(RuntimeError::new("expected a handle expression", handle.span()));
+        };
+        let id = self.next_handler;
+        self.next_handler += 1;
+        let depth = self.handlers.len();
+        self.handlers.push(HandlerFrame {
+            id,
+            handle: handle.clone(),
+            env: env.clone(),
+            module: module.clone(),
+            mode: *mode,
+            spent: false,
+            log,
+            performed: 0,
+        });
+        let result = self.eval(body, env, module);
+        self.handlers.truncate(depth + 1);
+        let frame = self.handlers.pop().expect("a handle removes only its own frame");
+        let value = match result {
+            Ok(value) => value,
+            Err(error) => {
+                return match self.unwinding.take_if(|(handler, _)| *handler == id) {
+                    Some((_, Unwind::Abort(value))) => Ok(value),
+                    Some((_, Unwind::Suspend { clause, arguments })) => {
+                        let captured = Captured { handle: handle.clone(), env: env.clone(), module: module.clone(), log: frame.log };
+                        let continuation = Value::Function(Rc::new(Function::Copied(captured)), Vec::new());
+                        self.run_clause(&handlers[clause], arguments, env, module, continuation)
+                    }
+                    None => Err(error),
+                };
+            }
+        };
+        match return_clause {
+            Some(clause) if !frame.spent => {
+                let env = self.bind_pattern(&clause.parameter, value, env, module)?
+                    .ok_or_else(|| RuntimeError::new("value does not match the return clause", clause.span))?;
+                self.eval(&clause.body, &env, module)
+            }
+            _ => Ok(value),
+        }
+    }
+
+    /// Run the clause of the innermost handler for `effect.operation`
+    fn perform(&mut self, effect: Symbol, operation: Symbol, arguments: Vec<Value>, span: Span) -> EvalResult {
+        let mut found = None;
+        for index in (0..self.handlers.len()).rev() {
+            let frame = &mut self.handlers[index];
+            // A copy of a computation gets the results its operations had
+            if frame.performed < frame.log.len() {
+                let value = frame.log[frame.performed].clone();
+                frame.performed += 1;
+                // The last result is of the operation a shallow handler handled
+                if frame.performed == frame.log.len() && frame.mode == HandlerMode::Shallow {
+                    frame.spent = true;
+                }
+                self.record(index + 1, &value);
+                return Ok(value);
+            }
+            if frame.spent {
+                continue;
+            }
+            let Expr::Handle { handlers, .. } = frame.handle.as_ref() else { continue };
+            if let Some(clause) = handlers.iter().position(|clause| clause.effect.name == effect && clause.operation == operation) {
+                found = Some((index, clause));
+                break;
+            }
+        }
+        let Some((index, clause)) = found else {
+            return Err(RuntimeError::new(format!("unhandled effect {effect}.{operation}"), span));
+        };
+
+        let frame = &self.handlers[index];
+        let Expr::Handle { handlers, .. } = frame.handle.as_ref() else { unreachable!("handler frames hold handle expressions") };
+        if !matches!(continuations::classify(&handlers[clause]), Resumption::Never | Resumption::Tail) {
+            // Leave the computation, so the clause can resume copies of it
+            self.unwinding = Some((frame.id, Unwind::Suspend { clause, arguments }));
+            return Err(RuntimeError::new(format!("{effect}.{operation} was not resumed"), span));
+        }
+
+        // The clause runs outside the handler it belongs to
+        let mut inner = self.handlers.split_off(index);
+        let frame = &mut inner[0];
+        frame.spent = frame.mode == HandlerMode::Shallow;
+        let (id, handle, env, module) = (frame.id, frame.handle.clone(), frame.env.clone(), frame.module.clone());
+        let Expr::Handle { handlers, .. } = handle.as_ref() else { unreachable!("handler frames hold handle expressions") };
+
+        let resumed = Rc::new(RefCell::new(None));
+        let continuation = Value::Function(Rc::new(Function::Continuation(resumed.clone())), Vec::new());
+        let result = self.run_clause(&handlers[clause], arguments, &env, &module, continuation);
+        self.handlers.extend(inner);
+
+        let value = result?;
+        match resumed.take() {
+            Some(resumed_with) => {
+                self.record(index, &resumed_with);
+                Ok(resumed_with)
+            }
+            None => {
+                self.unwinding = Some((id, Unwind::Abort(value)));
+                Err(RuntimeError::new(format!("{effect}.{operation} was not resumed"), span))
+            }
+        }
+    }
+
+    /// Log the result of an operation in the handlers from `depth` inwards
+    /// that it reached, for copies of their computations to replay
+    fn record(&mut self, depth: usize, value: &Value) {
+        for frame in &mut self.handlers[depth..] {
+            frame.log.push(value.clone());
+            frame.performed += 1;
+        }
+    }
+
+    /// Whether a copied computation is rerunning what it has done before
+    fn replaying(&self) -> bool {
+        self.handlers.iter().any(|frame| frame.performed < frame.log.len())
+    }
+
+    fn run_clause(
+        &mut self,
+        clause: &EffectHandler,
+        arguments: Vec<Value>,
+        env: &Env,
+        module: &Rc<str>,
+        continuation: Value,
+    ) -> EvalResult {
+        if clause.parameters.len() != arguments.len() {
+            return Err(RuntimeError::new(
+                format!("{}.{} takes {} arguments, got {}", clause.effect.name, clause.operation, clause.parameters.len(), arguments.len()),
+                clause.span,
+            ));
+        }
+        let mut env = env.clone();
+        for (pattern, argument) in clause.parameters.iter().zip(arguments) {
+            env = self.bind_pattern(pattern, argument, &env, module)?
+                .ok_or_else(|| RuntimeError::new("argument does not match the parameter pattern", pattern.span()))?;
+        }
+        if let Some(name) = clause.continuation {
+            env = env.bind(name, continuation.clone());
+        }
+        self.resumptions.push(continuation);
+        let result = self.eval(&clause.body, &env, module);
+        self.resumptions.pop();
+        result
+    }
+
+    fn eval_bool(&mut self, expr: &Expr, env: &Env, module: &Rc<str>) -> EvalResult<bool> {
+        match self.eval(expr, env, module)? {
+            Value::Bool(b) => Ok(b),
+            other => Err(RuntimeError::new(format!("expected a Bool, found {other}"), expr.span())),
+        }
+    }
+
+    /// Bindings made by matching `value` against `pattern`, or `None` if it does not match
+    fn bind_pattern(&mut self, pattern: &Pattern, value: Value, env: &Env, module: &Rc<str>) -> EvalResult<Option<Env>> {
+        Ok(match pattern {
+            Pattern::Wildcard(_) => Some(env.clone()),
+            Pattern::Variable(name, _) => match self.resolve(module, *name) {
+                // A nullary constructor, not a new binding
+                Some((_, Resolved::Constructor(constructor, 0))) => {
+                    matches!(&value, Value::Data(tag, fields) if *tag == constructor && fields.is_empty()).then(|| env.clone())
+                }
+                _ => Some(env.bind(*name, value)),
+            },
+            Pattern::Literal(literal, _) => (literal_value(literal) == value).then(|| env.clone()),
+            Pattern::Constructor { name, args, .. } => {
+                let tag = name.as_str().rsplit('.').next().map(Symbol::intern).unwrap_or(*name);
+                match value {
+                    Value::Data(value_tag, fields) if value_tag == tag && fields.len() == args.len() => {
+                        self.bind_all(args, fields, env, module)?
+                    }
+                    _ => None,
+                }
+            }
+            Pattern::Tuple { patterns, .. } => match value {
+                Value::Tuple(values) if values.len() == patterns.len() => self.bind_all(patterns, values, env, module)?,
+                _ => None,
+            },
+            Pattern::Record { fields, rest, .. } => {
+                let Value::Record(values) = value else { return Ok(None) };
+                let mut env = env.clone();
+                for (name, pattern) in fields {
+                    let Some((_, field)) = values.iter().find(|(field, _)| field == name) else { return Ok(None) };
+                    let Some(bound) = self.bind_pattern(pattern, field.clone(), &env, module)? else { return Ok(None) };
+                    env = bound;
+                }
+                match rest {
+                    Some(rest) => {
+                        let remaining = values.into_iter().filter(|(name, _)| !fields.contains_key(name)).collect();
+                        self.bind_pattern(rest, Value::Record(remaining), &env, module)?
+                    }
+                    None => Some(env),
+                }
+            }
+            Pattern::Or { left, right, .. } => match self.bind_pattern(left, value.clone(), env, module)? {
+                Some(env) => Some(env),
+                None => self.bind_pattern(right, value, env, module)?,
+            },
+            Pattern::As { pattern, name, .. } => self.bind_pattern(pattern, value.clone(), env, module)?
+                .map(|env| env.bind(*name, value)),
+            Pattern::Ann { pattern, .. } => self.bind_pattern(pattern, value, env, module)?,
+        })
+    }
+
+    fn bind_all(&mut self, patterns: &[Pattern], values: Vec<Value>, env: &Env, module: &Rc<str>) -> EvalResult<Option<Env>> {
+        let mut env = env.clone();
+        for (pattern, value) in patterns.iter().zip(values) {
+            match self.bind_pattern(pattern, value, &env, module)? {
+                Some(bound) => env = bound,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(env))
+    }
+
+    fn call_builtin(&mut self, builtin: Builtin, arguments: Vec<Value>, span: Span) -> EvalResult {
+        use Value::*;
+        let type_error = |arguments: &[Value]| {
+            let arguments: Vec<std::string::String> = arguments.iter().map(Value::to_string).collect();
+            RuntimeError::new(format!("{builtin:?} cannot be applied to {}", arguments.join(", ")), span)
+        };
+        Ok(match (builtin, arguments.as_slice()) {
+            (Builtin::Add, [Int(a), Int(b)]) => Int(a.wrapping_add(*b)),
+            (Builtin::Sub, [Int(a), Int(b)]) => Int(a.wrapping_sub(*b)),
+            (Builtin::Mul, [Int(a), Int(b)]) => Int(a.wrapping_mul(*b)),
+            (Builtin::Div | Builtin::Mod, [Int(_), Int(0)]) => return Err(RuntimeError::new("division by zero", span)),
+            (Builtin::Div, [Int(a), Int(b)]) => Int(a.wrapping_div(*b)),
+            (Builtin::Mod, [Int(a), Int(b)]) => Int(a.wrapping_rem(*b)),
+            (Builtin::Add | Builtin::FAdd, [Float(a), Float(b)]) => Float(a + b),
+            (Builtin::Sub | Builtin::FSub, [Float(a), Float(b)]) => Float(a - b),
+            (Builtin::Mul | Builtin::FMul, [Float(a), Float(b)]) => Float(a * b),
+            (Builtin::Div | Builtin::FDiv, [Float(a), Float(b)]) => Float(a / b),
+            (Builtin::Eq, [a, b]) => Bool(a == b),
+            (Builtin::Ne, [a, b]) => Bool(a != b),
+            (Builtin::Lt | Builtin::Le | Builtin::Gt | Builtin::Ge, [a, b]) => {
+                let ordering = match (a, b) {
+                    (Int(a), Int(b)) => a.partial_cmp(b),
+                    (Float(a), Float(b)) => a.partial_cmp(b),
+                    (String(a), String(b)) => a.partial_cmp(b),
+                    (Bool(a), Bool(b)) => a.partial_cmp(b),
+                    _ => None,
+                };
+                let ordering = ordering.ok_or_else(|| type_error(&arguments))?;
+                Bool(match builtin {
+                    Builtin::Lt => ordering.is_lt(),
+                    Builtin::Le => ordering.is_le(),
+                    Builtin::Gt => ordering.is_gt(),
+                    _ => ordering.is_ge(),
+                })
+            }
+            (Builtin::Not, [Bool(b)]) => Bool(!b),
+            (Builtin::Concat, [String(a), String(b)]) => String(format!("{a}{b}")),
+            (Builtin::Cons, [head, tail]) => Data(Symbol::intern("Cons"), vec![head.clone(), tail.clone()]),
+            // A replay has written its output already
+            (Builtin::PrintEndline | Builtin::PrintString, [_]) if self.replaying() => Unit,
+            (Builtin::PrintEndline, [String(s)]) => {
+                self.output.push_str(s);
+                self.output.push('\n');
+                Unit
+            }
+            (Builtin::PrintString, [String(s)]) => {
+                self.output.push_str(s);
+                Unit
+            }
+            (Builtin::Pipe, [argument, function]) => self.apply(function.clone(), vec![argument.clone()], span)?,
+            (Builtin::ComposeRight, [first, second, argument]) => {
+                let intermediate = self.apply(first.clone(), vec![argument.clone()], span)?;
+                self.apply(second.clone(), vec![intermediate], span)?
+            }
+            (Builtin::ComposeLeft, [second, first, argument]) => {
+                let intermediate = self.apply(first.clone(), vec![argument.clone()], span)?;
+                self.apply(second.clone(), vec![intermediate], span)?
+            }
+            (Builtin::StringOfInt, [Int(n)]) => String(n.to_string()),
+            (Builtin::IntOfString, [String(s)]) => Int(s.trim().parse()
+                .map_err(|_| RuntimeError::new(format!("{s:?} is not an integer"), span))?),
+            _ => return Err(type_error(&arguments)),
+        })
+    }
+}
+
+/// Resume a continuation with `value`, which the operation then returns
+fn resume(resumed: &RefCell<Option<Value>>, value: Value, span: Span) -> EvalResult {
+    if resumed.borrow().is_some() {
+        return Err(RuntimeError::new("a continuation can only be resumed once", span));
+    }
+    *resumed.borrow_mut() = Some(value.clone());
+    Ok(value)
+}
+
+fn literal_value(literal: &Literal) -> Value {
+    match literal {
+        Literal::Integer(n, _) => Value::Int(*n),
+        Literal::Float(x, _) => Value::Float(*x),
+        Literal::String(s) => Value::String(s.clone()),
+        Literal::Bool(b) => Value::Bool(*b),
+        Literal::Unit => Value::Unit,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x_parser::{parse_source, FileId, SyntaxStyle};
+
+    fn run(source: &str, name: &str) -> EvalResult {
+        let unit = parse_source(source, FileId::new(0), SyntaxStyle::default()).unwrap();
+        let mut interpreter = Interpreter::new();
+        interpreter.load(&unit);
+        interpreter.evaluate(&unit.module.name.to_string(), name)
+    }
+
+    #[test]
+    fn test_functions_and_data() {
+        let source = "module Main\n\
+            data Shape = Circle Int | Rect Int Int\n\
+            let area = fun s -> match s with | Circle r => 3 * r * r | Rect w h => w * h\n\
+            let fact = fun n -> if n <= 1 then 1 else n * fact (n - 1)\n\
+            let add = fun a -> fun b -> a + b\n\
+            let total = area (Circle 2) + area (Rect 2 3) + fact 5 + (add 1) 2\n\
+            let pair = (1, { x = \"a\" }.x)\n\
+            let pairs = (fun (a, b) (Circle c) -> a - b + c) (5, 2) (Circle 4)";
+        assert_eq!(run(source, "total").unwrap(), Value::Int(12 + 6 + 120 + 3));
+        assert_eq!(run(source, "pairs").unwrap(), Value::Int(7));
+        assert_eq!(run(source, "pair").unwrap().to_string(), "(1, \"a\")");
+    }
+
+    #[test]
+    fn test_standard_library_calls() {
+        let source = "module Main\n\
+            import Core.List\n\
+            import Core.Option { Some, None }\n\
+            let xs = List.Cons 1 (List.Cons 2 (List.Cons 3 List.Nil))\n\
+            let total = List.fold (fun acc -> fun x -> acc + x) 0 xs\n\
+            let first = match List.head xs with | Some x => x | None => 0\n\
+            let doubled = List.map (fun x -> x * 2) xs\n\
+            let differences = List.map (10 - _) (List.map (_ * 3) xs)\n\
+            let piped = xs |> List.map ((_ + 1) >> string_of_int << (_ * 2))";
+        assert_eq!(run(source, "total").unwrap(), Value::Int(6));
+        assert_eq!(run(source, "first").unwrap(), Value::Int(1));
+        assert_eq!(run(source, "doubled").unwrap().to_string(), "Cons 2 (Cons 4 (Cons 6 Nil))");
+        assert_eq!(run(source, "differences").unwrap().to_string(), "Cons 7 (Cons 4 (Cons 1 Nil))");
+        assert_eq!(run(source, "piped").unwrap().to_string(), "Cons \"3\" (Cons \"5\" (Cons \"7\" Nil))");
+    }
+
+    #[test]
+    fn test_packed_modules() {
+        let library = parse_source("module Plugins.Math\nlet make = fun n -> n * 2\nlet show = fun n -> n + 1", FileId::new(1), SyntaxStyle::default()).unwrap();
+        let main = parse_source(
+            "module Main\n\
+             import Plugins.Math as M\n\
+             module type Plugin { type t  make : Int -> t  show : t -> Int }\n\
+             let run = fun p -> (let module P : Plugin = p in P.show (P.make 20))\n\
+             let answer = run (module M : Plugin)",
+            FileId::new(0),
+            SyntaxStyle::default(),
+        ).unwrap();
+        let mut interpreter = Interpreter::new();
+        interpreter.load(&library);
+        interpreter.load(&main);
+        assert_eq!(interpreter.evaluate("Main", "answer").unwrap(), Value::Int(41));
+    }
+
+    /// `handle body` with one `State.get` clause, built directly as effects
+    /// have no surface syntax yet
+    fn handle(mode: HandlerMode, body: Expr, clause: Expr, return_clause: Option<Expr>) -> Expr {
+        use x_parser::{EffectHandler, EffectRef, ReturnClause};
+        let span = body.span();
+        Expr::Handle {
+            expr: Box::new(body),
+            mode,
+            handlers: vec![EffectHandler {
+                effect: EffectRef { name: Symbol::intern("State"), args: vec![], span },
+                operation: Symbol::intern("get"),
+                parameters: vec![],
+                continuation: Some(Symbol::intern("k")),
+                body: clause,
+                span,
+            }],
+            return_clause: return_clause.map(|body| Box::new(ReturnClause {
+                parameter: Pattern::Variable(Symbol::intern("x"), span),
+                body: Box::new(body),
+                span,
+            })),
+            span,
+        }
+    }
+
+    #[test]
+    fn test_deep_and_shallow_handlers() {
+        let expr = |source: &str| {
+            let unit = parse_source(&format!("module Main\nlet e = {source}"), FileId::new(0), SyntaxStyle::default()).unwrap();
+            let Item::ValueDef(def) = &unit.module.items[0] else { unreachable!() };
+            def.body.clone()
+        };
+        let span = expr("0").span();
+        let get = || Expr::Perform { effect: Symbol::intern("State"), operation: Symbol::intern("get"), args: vec![], span };
+        let twice = || Expr::Tuple { elements: vec![get(), get()], span };
+        let eval = |expr: Expr| Interpreter::new().eval_in("Core.List", &expr).map(|value| value.to_string());
+
+        // The inner handler answers the first `get` only when it is shallow
+        let layered = |mode| handle(HandlerMode::Deep, handle(mode, twice(), expr("k 41"), None), expr("k 1"), None);
+        assert_eq!(eval(layered(HandlerMode::Deep)).unwrap(), "(41, 41)");
+        assert_eq!(eval(layered(HandlerMode::Shallow)).unwrap(), "(41, 1)");
+
+        // The return clause sees the value unless a shallow handler has handled an operation
+        let returning = |mode| handle(HandlerMode::Deep, handle(mode, twice(), expr("k 41"), Some(expr("0"))), expr("k 1"), None);
+        assert_eq!(eval(returning(HandlerMode::Deep)).unwrap(), "0");
+        assert_eq!(eval(returning(HandlerMode::Shallow)).unwrap(), "(41, 1)");
+
+        // A clause that does not resume answers for the whole handler
+        assert_eq!(eval(handle(HandlerMode::Deep, twice(), expr("7"), Some(expr("0")))).unwrap(), "7");
+        assert_eq!(eval(handle(HandlerMode::Deep, twice(), Expr::Resume { value: Box::new(expr("2")), span }, None)).unwrap(), "(2, 2)");
+        assert_eq!(eval(get()).unwrap_err().message, "unhandled effect State.get");
+    }
+
+    #[test]
+    fn test_multi_shot_continuations() {
+        let expr = |source: &str| {
+            let unit = parse_source(&format!("module Main\nlet e = {source}"), FileId::new(0), SyntaxStyle::default()).unwrap();
+            let Item::ValueDef(def) = &unit.module.items[0] else { unreachable!() };
+            def.body.clone()
+        };
+        let span = expr("0").span();
+        let get = || Expr::Perform { effect: Symbol::intern("State"), operation: Symbol::intern("get"), args: vec![], span };
+        let twice = || Expr::Tuple { elements: vec![get(), get()], span };
+        let eval = |expr: Expr| Interpreter::new().eval_in("Core.List", &expr).map(|value| value.to_string());
+
+        // Each resumption runs the rest of the computation, handler included
+        let both = handle(HandlerMode::Deep, twice(), expr("(k 1, k 2)"), None);
+        assert_eq!(eval(both).unwrap(), "(((1, 1), (1, 2)), ((2, 1), (2, 2)))");
+        let after = handle(HandlerMode::Deep, get(), expr("(let x = k 1 in (x, 0))"), Some(expr("(x, 9)")));
+        assert_eq!(eval(after).unwrap(), "((1, 9), 0)");
+
+        // A copy of a shallow handler's computation no longer handles anything
+        let shallow = handle(HandlerMode::Shallow, twice(), expr("(k 1, k 2)"), Some(expr("0")));
+        assert_eq!(eval(handle(HandlerMode::Deep, shallow, expr("k 5"), None)).unwrap(), "((1, 5), (2, 5))");
+
+        // Output written before the operation is not written again by the copies
+        let print = Expr::App(Box::new(expr("print_endline")), vec![expr("\"before\"")], span);
+        let body = Expr::Tuple { elements: vec![print, get()], span };
+        let mut interpreter = Interpreter::new();
+        let value = interpreter.eval_in("Core.List", &handle(HandlerMode::Deep, body, expr("(k 1, k 2)"), None)).unwrap();
+        assert_eq!(value.to_string(), "(((), 1), ((), 2))");
+        assert_eq!(interpreter.output(), "before\n");
+    }
+
+    #[test]
+    fn test_runtime_errors() {
+        let error = run("module Main\nlet n = 1 / 0", "n").unwrap_err();
+        assert_eq!(error.message, "division by zero");
+
+        let error = run("module Main\nlet loop = fun n -> loop (n + 1)\nlet n = loop 0", "n").unwrap_err();
+        assert!(error.message.contains("limit"), "{}", error.message);
+
+        // A thousand nested calls need the large stack
+        let count = "module Main\nlet count = fun n -> if n == 0 then 0 else 1 + count (n - 1)\nlet n = count 1000";
+        let unit = parse_source(count, FileId::new(0), SyntaxStyle::default()).unwrap();
+        let value = with_large_stack(|| {
+            let mut interpreter = Interpreter::new().with_limits(Limits::deep());
+            interpreter.load(&unit);
+            interpreter.evaluate("Main", "n").map(|value| value.to_string())
+        });
+        assert_eq!(value.unwrap(), "1000");
+    }
+}
@@ -7,8 +7,17 @@ pub mod test_runner;
 pub mod test_cache;
 pub mod test_discovery;
 pub mod test_report;
+pub mod interpreter;
+pub mod doctest;
+pub mod mutation;
+pub mod coverage;
+pub mod bench;
 
 pub use test_runner::{TestRunner, TestRunnerConfig, TestResult};
 pub use test_cache::{TestCache, CachedTestResult};
 pub use test_discovery::{TestDiscovery, TestCase, TestSuite};
-pub use test_report::{TestReport, TestReporter, ConsoleReporter};
\ No newline at end of file
+pub use test_report::{TestReport, TestReporter, ConsoleReporter};pub use interpreter::{Interpreter, Limits, RuntimeError, Value};
+pub use doctest::{extract_doctests, run_doctest, run_doctests, Doctest, DoctestMode};
+pub use mutation::{apply_mutation, mutations, MutantResult, MutantStatus, Mutation, MutationOperator, MutationReport, MutationTester};
+pub use coverage::{run_tests_with_coverage, Coverage, ModuleCoverage};
+pub use bench::{BenchBaseline, BenchConfig, BenchOutcome, BenchResult, BenchRunner, BenchStats};
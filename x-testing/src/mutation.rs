@@ -0,0 +1,481 @@
+//! Mutation testing
+//!
+//! A mutant is the module with one small change to one definition: a binary
+//! operator swapped for its opposite, the branches of an `if` swapped (which
+//! negates its condition), or one arm of a `match` dropped. Each change is
+//! applied as an [`EditOperation`] replacing the definition, and the module's
+//! tests (definitions named `test_*`) are run against the mutant with the
+//! [`Interpreter`]. A mutant that makes some test fail is killed; one that
+//! every test still passes against survives, and points at behaviour the
+//! tests do not pin down.
+//!
+//! Tests are keyed by the closure hash of their definition, as in the test
+//! cache, so a test that does not depend on the mutated definition keeps its
+//! result from the original module and is not run again. Results can also be
+//! kept in a [`TestCache`] to carry them over between runs.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Instant;
+use x_editor::content_addressing::ContentHash;
+use x_editor::dependency_hash::DefinitionHashes;
+use x_editor::{AstEditor, EditError, EditOperation};
+use x_editor::operations::EditableNode;
+use x_parser::{CompilationUnit, DoStatement, Expr, Item, Span, Symbol};
+use crate::interpreter::{Interpreter, Limits, Value};
+use crate::test_cache::{CachedTestResult, TestCache};
+use crate::test_discovery::is_test_name;
+use crate::test_runner::TestResult;
+
+/// The kinds of change made to a definition
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MutationOperator {
+    /// `a + b` to `a - b`, `a < b` to `a >= b`, `a && b` to `a || b`, ...
+    SwapOperator,
+    /// `if c then a else b` to `if c then b else a`
+    NegateCondition,
+    /// A `match` without one of its arms
+    DropMatchArm,
+}
+
+impl fmt::Display for MutationOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            MutationOperator::SwapOperator => "swap-operator",
+            MutationOperator::NegateCondition => "negate-condition",
+            MutationOperator::DropMatchArm => "drop-match-arm",
+        };
+        f.write_str(name)
+    }
+}
+
+/// One change that can be made to a module
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mutation {
+    /// The definition changed
+    pub definition: Symbol,
+    /// Index of the definition among the module's items
+    pub item: usize,
+    /// Position of the changed expression in a pre-order walk of the body
+    pub site: usize,
+    /// For [`MutationOperator::DropMatchArm`], the arm dropped
+    pub arm: usize,
+    pub operator: MutationOperator,
+    /// The expression changed
+    pub span: Span,
+    pub description: String,
+}
+
+/// What running the tests against a mutant showed
+#[derive(Debug, Clone, PartialEq)]
+pub enum MutantStatus {
+    /// The first test found failing
+    Killed { test: Symbol },
+    Survived,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MutantResult {
+    pub mutation: Mutation,
+    pub status: MutantStatus,
+    /// Tests run against the mutant
+    pub tests_run: usize,
+    /// Tests not depending on the mutated definition, and not run again
+    pub tests_skipped: usize,
+}
+
+/// Mutation testing results for one module
+#[derive(Debug, Clone, Default)]
+pub struct MutationReport {
+    pub module: String,
+    /// Tests passing on the unchanged module; only these can kill mutants
+    pub tests: Vec<Symbol>,
+    /// Tests already failing on the unchanged module, left out
+    pub failing_tests: Vec<Symbol>,
+    pub mutants: Vec<MutantResult>,
+}
+
+impl MutationReport {
+    pub fn killed(&self) -> usize {
+        self.mutants.iter().filter(|mutant| mutant.status != MutantStatus::Survived).count()
+    }
+
+    /// Mutants no test caught, in source order
+    pub fn survivors(&self) -> Vec<&MutantResult> {
+        self.mutants.iter().filter(|mutant| mutant.status == MutantStatus::Survived).collect()
+    }
+
+    /// The share of mutants killed, if there are any
+    pub fn score(&self) -> Option<f64> {
+        (!self.mutants.is_empty()).then(|| self.killed() as f64 / self.mutants.len() as f64)
+    }
+}
+
+/// Every mutation of the non-test value definitions of `unit`, in source order
+pub fn mutations(unit: &CompilationUnit) -> Vec<Mutation> {
+    let mut mutations = Vec::new();
+    for (item, definition) in unit.module.items.iter().enumerate() {
+        let Item::ValueDef(def) = definition else { continue };
+        if is_test_name(def.name.as_str()) {
+            continue;
+        }
+        let mut site = 0;
+        let mut body = def.body.clone();
+        walk_mut(&mut body, &mut |expr| {
+            let span = expr.span();
+            let mut push = |operator, arm, description| mutations.push(Mutation {
+                definition: def.name,
+                item,
+                site,
+                arm,
+                operator,
+                span,
+                description,
+            });
+            match expr {
+                Expr::App(func, args, _) if args.len() == 2 => {
+                    if let Expr::Var(op, _) = &**func {
+                        if let Some(swapped) = swapped_operator(op.as_str()) {
+                            push(MutationOperator::SwapOperator, 0, format!("replace `{op}` with `{swapped}`"));
+                        }
+                    }
+                }
+                Expr::If { .. } => {
+                    push(MutationOperator::NegateCondition, 0, "negate the `if` condition".to_string());
+                }
+                Expr::Match { arms, .. } if arms.len() > 1 => {
+                    for arm in 0..arms.len() {
+                        push(MutationOperator::DropMatchArm, arm, format!("drop match arm {}", arm + 1));
+                    }
+                }
+                _ => {}
+            }
+            site += 1;
+        });
+    }
+    mutations
+}
+
+/// `unit` with `mutation` made, through an edit replacing its definition
+pub fn apply_mutation(unit: &CompilationUnit, mutation: &Mutation) -> Result<CompilationUnit, EditError> {
+    let path = vec![0, mutation.item];
+    let Some(Item::ValueDef(def)) = unit.module.items.get(mutation.item) else {
+        return Err(EditError::PathNotFound { path });
+    };
+
+    let mut def = def.clone();
+    let mut site = 0;
+    walk_mut(&mut def.body, &mut |expr| {
+        if site == mutation.site {
+            mutate(expr, mutation);
+        }
+        site += 1;
+    });
+
+    let mut mutant = unit.clone();
+    AstEditor::new().apply_operation(&mut mutant, EditOperation::replace(path, EditableNode::Item(Item::ValueDef(def))))?;
+    Ok(mutant)
+}
+
+fn mutate(expr: &mut Expr, mutation: &Mutation) {
+    match (mutation.operator, expr) {
+        (MutationOperator::SwapOperator, Expr::App(func, ..)) => {
+            if let Expr::Var(op, _) = &mut **func {
+                if let Some(swapped) = swapped_operator(op.as_str()) {
+                    *op = Symbol::intern(swapped);
+                }
+            }
+        }
+        (MutationOperator::NegateCondition, Expr::If { then_branch, else_branch, .. }) => {
+            std::mem::swap(then_branch, else_branch);
+        }
+        (MutationOperator::DropMatchArm, Expr::Match { arms, .. }) if mutation.arm < arms.len() => {
+            arms.remove(mutation.arm);
+        }
+        _ => {}
+    }
+}
+
+/// Runs a module's tests against each of its mutants
+pub struct MutationTester {
+    limits: Limits,
+    cache: Option<TestCache>,
+    /// Only definitions whose name contains this are mutated
+    filter: Option<String>,
+    /// Whether a test passed, by the closure hash of the test in the module run
+    results: HashMap<ContentHash, bool>,
+}
+
+impl Default for MutationTester {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MutationTester {
+    pub fn new() -> Self {
+        Self { limits: Limits::default(), cache: None, filter: None, results: HashMap::new() }
+    }
+
+    /// Bound the evaluation of each test; mutants easily loop forever
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Keep test results in `cache` as well, across runs
+    pub fn with_cache(mut self, cache: TestCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Only mutate definitions whose name contains `pattern`
+    pub fn with_filter(mut self, pattern: &str) -> Self {
+        self.filter = Some(pattern.to_string());
+        self
+    }
+
+    /// Test every mutant of `unit`
+    pub fn run(&mut self, unit: &CompilationUnit) -> MutationReport {
+        let tests: Vec<Symbol> = unit.module.items.iter()
+            .filter_map(|item| match item {
+                Item::ValueDef(def) if is_test_name(def.name.as_str()) => Some(def.name),
+                _ => None,
+            })
+            .collect();
+
+        let original = DefinitionHashes::compute(unit);
+        let mut report = MutationReport { module: unit.module.name.to_string(), ..MutationReport::default() };
+        let baseline = self.run_tests(unit, &original, &tests);
+        for (test, passed) in tests.iter().zip(baseline) {
+            if passed {
+                report.tests.push(*test);
+            } else {
+                report.failing_tests.push(*test);
+            }
+        }
+
+        let filter = self.filter.clone();
+        let selected = mutations(unit).into_iter()
+            .filter(|mutation| filter.as_ref().is_none_or(|filter| mutation.definition.as_str().contains(filter.as_str())));
+        for mutation in selected {
+            let Ok(mutant) = apply_mutation(unit, &mutation) else { continue };
+            let hashes = DefinitionHashes::compute(&mutant);
+            let affected: Vec<Symbol> = report.tests.iter()
+                .copied()
+                .filter(|test| hashes.closure_hash(test) != original.closure_hash(test))
+                .collect();
+
+            let killer = affected.iter()
+                .zip(self.run_tests(&mutant, &hashes, &affected))
+                .find(|(_, passed)| !passed)
+                .map(|(test, _)| *test);
+            report.mutants.push(MutantResult {
+                mutation,
+                status: killer.map_or(MutantStatus::Survived, |test| MutantStatus::Killed { test }),
+                tests_run: affected.len(),
+                tests_skipped: report.tests.len() - affected.len(),
+            });
+        }
+        report
+    }
+
+    /// Whether each of `tests` passes on `unit`, reusing earlier results of
+    /// tests with the same closure hash
+    fn run_tests(&mut self, unit: &CompilationUnit, hashes: &DefinitionHashes, tests: &[Symbol]) -> Vec<bool> {
+        let module = unit.module.name.to_string();
+        let mut interpreter = None;
+        tests.iter()
+            .map(|test| {
+                let hash = hashes.closure_hash(test);
+                if let Some(passed) = hash.as_ref().and_then(|hash| self.cached(hash)) {
+                    return passed;
+                }
+
+                let start = Instant::now();
+                let interpreter = interpreter.get_or_insert_with(|| {
+                    let mut interpreter = Interpreter::new().with_limits(self.limits);
+                    interpreter.load(unit);
+                    interpreter
+                });
+                let outcome = interpreter.evaluate(&module, test.as_str());
+                let passed = matches!(outcome, Ok(Value::Bool(true) | Value::Unit));
+                if let Some(hash) = hash {
+                    self.store(hash, passed, start, hashes.dependencies(test));
+                }
+                passed
+            })
+            .collect()
+    }
+
+    fn cached(&self, hash: &ContentHash) -> Option<bool> {
+        if let Some(passed) = self.results.get(hash) {
+            return Some(*passed);
+        }
+        let cached = self.cache.as_ref()?.get(hash).ok()??;
+        Some(cached.result.is_pass())
+    }
+
+    fn store(&mut self, hash: ContentHash, passed: bool, start: Instant, dependencies: HashMap<String, ContentHash>) {
+        if let Some(cache) = &self.cache {
+            let duration_ms = start.elapsed().as_millis() as u64;
+            let result = if passed {
+                TestResult::Pass { duration_ms, output: None }
+            } else {
+                TestResult::Fail { duration_ms, error: "failed under mutation testing".to_string(), output: None }
+            };
+            let cached = CachedTestResult {
+                test_hash: hash.clone(),
+                result,
+                dependencies,
+                executed_at: chrono::Utc::now(),
+                x_version: env!("CARGO_PKG_VERSION").to_string(),
+            };
+            // The cache only saves time; a failed write is not an error
+            let _ = cache.put(&hash, &cached);
+        }
+        self.results.insert(hash, passed);
+    }
+}
+
+fn swapped_operator(op: &str) -> Option<&'static str> {
+    Some(match op {
+        "+" => "-",
+        "-" => "+",
+        "*" => "/",
+        "/" => "*",
+        "+." => "-.",
+        "-." => "+.",
+        "*." => "/.",
+        "/." => "*.",
+        "<" => ">=",
+        ">=" => "<",
+        ">" => "<=",
+        "<=" => ">",
+        "==" | "=" => "!=",
+        "!=" | "<>" => "==",
+        "&&" => "||",
+        "||" => "&&",
+        _ => return None,
+    })
+}
+
+/// Call `visit` on `expr` and every expression inside it, in pre-order
+fn walk_mut(expr: &mut Expr, visit: &mut impl FnMut(&mut Expr)) {
+    visit(expr);
+    match expr {
+        Expr::Literal(..) | Expr::Var(..) | Expr::Pack { .. } | Expr::Error(_) => {}
+        Expr::App(func, args, _) => {
+            walk_mut(func, visit);
+            args.iter_mut().for_each(|arg| walk_mut(arg, visit));
+        }
+        Expr::Lambda { body, .. } => walk_mut(body, visit),
+        Expr::Let { value, body, .. } | Expr::Unpack { value, body, .. } => {
+            walk_mut(value, visit);
+            walk_mut(body, visit);
+        }
+        Expr::If { condition, then_branch, else_branch, .. } => {
+            walk_mut(condition, visit);
+            walk_mut(then_branch, visit);
+            walk_mut(else_branch, visit);
+        }
+        Expr::Match { scrutinee, arms, .. } => {
+            walk_mut(scrutinee, visit);
+            for arm in arms {
+                if let Some(guard) = &mut arm.guard {
+                    walk_mut(guard, visit);
+                }
+                walk_mut(&mut arm.body, visit);
+            }
+        }
+        Expr::Do { statements, .. } => {
+            for statement in statements {
+                match statement {
+                    DoStatement::Let { expr, .. } | DoStatement::Bind { expr, .. } | DoStatement::Expr(expr) => {
+                        walk_mut(expr, visit)
+                    }
+                }
+            }
+        }
+        Expr::Handle { expr, handlers, return_clause, .. } => {
+            walk_mut(expr, visit);
+            handlers.iter_mut().for_each(|handler| walk_mut(&mut handler.body, visit));
+            if let Some(ret) = return_clause {
+                walk_mut(&mut ret.body, visit);
+            }
+        }
+        Expr::Resume { value, .. } => walk_mut(value, visit),
+        Expr::Perform { args, .. } => args.iter_mut().for_each(|arg| walk_mut(arg, visit)),
+        Expr::Ann { expr, .. } | Expr::Project { record: expr, .. } => walk_mut(expr, visit),
+        Expr::Record { fields, .. } => fields.iter_mut().for_each(|(_, value)| walk_mut(value, visit)),
+        Expr::Tuple { elements, .. } => elements.iter_mut().for_each(|element| walk_mut(element, visit)),
+        Expr::RecordUpdate { record, fields, .. } => {
+            walk_mut(record, visit);
+            fields.iter_mut().for_each(|(_, value)| walk_mut(value, visit));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    use x_parser::{parse_source, FileId, SyntaxStyle};
+
+    const SOURCE: &str = "module Demo\n\
+        let clamp = fun n -> if n < 0 then 0 else n\n\
+        let add = fun a -> fun b -> a + b\n\
+        let test_clamp = clamp (0 - 5) == 0\n\
+        let test_add_zero = add 0 0 == 0";
+
+    fn parse(source: &str) -> CompilationUnit {
+        parse_source(source, FileId::new(0), SyntaxStyle::SExpression).unwrap()
+    }
+
+    #[test]
+    fn test_mutations_are_found_and_applied() {
+        let unit = parse(SOURCE);
+        let found = mutations(&unit);
+        let described: Vec<_> = found.iter().map(|m| (m.definition.as_str(), m.description.as_str())).collect();
+        assert_eq!(described, vec![
+            ("clamp", "negate the `if` condition"),
+            ("clamp", "replace `<` with `>=`"),
+            ("add", "replace `+` with `-`"),
+        ]);
+
+        let unit = parse(&format!("{SOURCE}\nlet probe = add 5 3"));
+        let mutant = apply_mutation(&unit, &found[2]).unwrap();
+        let mut interpreter = Interpreter::new();
+        interpreter.load(&mutant);
+        assert_eq!(interpreter.evaluate("Demo", "probe").unwrap(), Value::Int(2));
+    }
+
+    #[test]
+    fn test_surviving_mutants_are_reported() {
+        let report = MutationTester::new().run(&parse(SOURCE));
+        assert_eq!(report.tests.len(), 2);
+        assert_eq!(report.mutants.len(), 3);
+
+        // `add 0 0` is 0 whether it adds or subtracts
+        let survivors = report.survivors();
+        assert_eq!(survivors.len(), 1);
+        assert_eq!(survivors[0].mutation.definition.as_str(), "add");
+        // Only the test using `add` runs against its mutant
+        assert_eq!((survivors[0].tests_run, survivors[0].tests_skipped), (1, 1));
+        assert_eq!(report.score(), Some(2.0 / 3.0));
+    }
+
+    #[test]
+    fn test_results_are_cached_by_closure_hash() {
+        let temp_dir = TempDir::new().unwrap();
+        let unit = parse(SOURCE);
+        let first = MutationTester::new().with_cache(TestCache::new(temp_dir.path()).unwrap()).run(&unit);
+        let hash = DefinitionHashes::compute(&unit).closure_hash(&Symbol::intern("test_clamp")).unwrap();
+        let cached = TestCache::new(temp_dir.path()).unwrap().get(&hash).unwrap().unwrap();
+        assert!(cached.result.is_pass());
+
+        let second = MutationTester::new().with_cache(TestCache::new(temp_dir.path()).unwrap()).run(&unit);
+        assert_eq!(first.killed(), second.killed());
+        assert_eq!(first.survivors().len(), second.survivors().len());
+    }
+}
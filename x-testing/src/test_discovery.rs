@@ -30,6 +30,9 @@ pub struct TestCase {
     /// Content hash of the test function
     pub hash: ContentHash,
 
+    /// Hashes of the definitions the test transitively depends on
+    pub dependencies: HashMap<String, ContentHash>,
+
     /// Test attributes (e.g., tags, skip conditions)
     pub attributes: TestAttributes,
 }
@@ -69,6 +72,11 @@ pub struct TestSuite {
     pub by_tag: HashMap<String, Vec<TestCase>>,
 }
 
+/// Whether a definition is a test by its name: `test_parse`, `testParse`
+pub(crate) fn is_test_name(name: &str) -> bool {
+    name.starts_with("test_") || name.starts_with("test") && name.len() > 4
+}
+
 /// Test discovery
 pub struct TestDiscovery {
     content_repo: ContentRepository,
@@ -223,6 +231,7 @@ impl TestDiscovery {
                     namespace: namespace_path.clone(),
                     function,
                     hash: hash.clone(),
+                    dependencies: HashMap::new(),
                     attributes,
                 }));
             }
@@ -232,8 +241,7 @@ impl TestDiscovery {
     }
 
     fn is_test_function_by_name(&self, name: &Symbol) -> bool {
-        let name_str = name.as_str();
-        name_str.starts_with("test_") || name_str.starts_with("test") && name_str.len() > 4
+        is_test_name(name.as_str())
     }
 
     fn create_dummy_test_function(
@@ -242,7 +250,7 @@ impl TestDiscovery {
         type_scheme: Option<TypeScheme>,
     ) -> AnnotatedValueDef {
         use x_parser::ast::Purity;
-        use x_parser::span::ByteOffset;
+        use x_parser::span::CharOffset;
         use x_parser::{FileId, Span};
 
         AnnotatedValueDef {
@@ -253,14 +261,14 @@ impl TestDiscovery {
             body: AnnotatedExpr {
                 expr: Expr::Literal(
                     Literal::Bool(true),
-                    Span::new(FileId(0), ByteOffset(0), ByteOffset(1)),
+                    Span::new(FileId(0), CharOffset(0), CharOffset(1)),
                 ),
                 inferred_type: None,
-                span: Span::new(FileId(0), ByteOffset(0), ByteOffset(1)),
+                span: Span::new(FileId(0), CharOffset(0), CharOffset(1)),
             },
             visibility: Visibility::Public,
             purity: Purity::Pure,
-            span: Span::new(FileId(0), ByteOffset(0), ByteOffset(1)),
+            span: Span::new(FileId(0), CharOffset(0), CharOffset(1)),
         }
     }
 
@@ -329,7 +337,7 @@ mod tests {
     use super::*;
     use x_parser::ast::Visibility;
     use x_parser::Purity;
-    use x_parser::{span::ByteOffset, FileId, Span};
+    use x_parser::{span::CharOffset, FileId, Span};
 
     #[test]
     fn test_discovery_basic() {
@@ -348,14 +356,14 @@ mod tests {
             body: AnnotatedExpr {
                 expr: Expr::Literal(
                     Literal::Bool(true),
-                    Span::new(FileId(0), ByteOffset(0), ByteOffset(1)),
+                    Span::new(FileId(0), CharOffset(0), CharOffset(1)),
                 ),
                 inferred_type: None,
-                span: Span::new(FileId(0), ByteOffset(0), ByteOffset(1)),
+                span: Span::new(FileId(0), CharOffset(0), CharOffset(1)),
             },
             visibility: Visibility::Public,
             purity: Purity::Pure,
-            span: Span::new(FileId(0), ByteOffset(0), ByteOffset(1)),
+            span: Span::new(FileId(0), CharOffset(0), CharOffset(1)),
         };
 
         let test_hash = ContentHash::new(b"test_addition_hash");
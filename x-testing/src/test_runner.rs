@@ -2,10 +2,14 @@
 //!
 //! This module implements the core test runner that caches test results
 //! based on the content hash of the test function and its dependencies.
+//! Tests that are not cached are evaluated by the [`Interpreter`] in the
+//! module they were discovered in, which has to be [loaded](TestRunner::load)
+//! first.
 
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use std::collections::HashMap;
+use std::time::Instant;
 use anyhow::{Result, anyhow};
 use x_parser::{Symbol, ast::*};
 use x_editor::content_addressing::{ContentHash, ContentRepository};
@@ -14,6 +18,7 @@ use x_compiler::{
     pipeline::CompilationPipeline,
     config::CompilerConfig,
 };
+use crate::interpreter::{Interpreter, RuntimeError, Value};
 use crate::test_cache::{TestCache, CachedTestResult};
 use crate::test_discovery::{TestCase, TestSuite};
 use crate::test_report::{TestReport, TestReporter};
@@ -106,6 +111,14 @@ pub struct TestRunner {
     content_repo: Arc<ContentRepository>,
     compiler: CompilationPipeline,
     results: Arc<Mutex<HashMap<ContentHash, TestResult>>>,
+    /// The module of each namespace tests are run in
+    modules: HashMap<NamespacePath, LoadedModule>,
+}
+
+/// A module loaded into an interpreter of its own
+struct LoadedModule {
+    unit: CompilationUnit,
+    interpreter: Interpreter,
 }
 
 impl TestRunner {
@@ -121,9 +134,18 @@ impl TestRunner {
             content_repo,
             compiler,
             results: Arc::new(Mutex::new(HashMap::new())),
+            modules: HashMap::new(),
         })
     }
     
+    /// Load the module whose tests are discovered in `namespace`, replacing
+    /// any module loaded for it before
+    pub fn load(&mut self, namespace: NamespacePath, unit: &CompilationUnit) {
+        let mut interpreter = Interpreter::new();
+        interpreter.load(unit);
+        self.modules.insert(namespace, LoadedModule { unit: unit.clone(), interpreter });
+    }
+    
     /// Run all tests in a test suite
     pub fn run_suite(&mut self, suite: &TestSuite, reporter: &dyn TestReporter) -> Result<TestReport> {
         reporter.on_suite_start(suite);
@@ -226,135 +248,39 @@ impl TestRunner {
     }
     
     fn execute_test(&mut self, test: &TestCase) -> Result<TestResult> {
-        // For now, we'll simulate test execution
-        // In a real implementation, we'd compile and run the test
-        let _compiled = self.simulate_test_execution(test)?;
-        
-        // Set up test environment
-        let _test_env = self.create_test_environment(test)?;
-        
-        // Execute test (simulated)
-        let output = self.simulate_test_result(test);
-        
-        // Check test assertion
-        if self.check_test_assertion(&output)? {
-            Ok(TestResult::Pass {
-                duration_ms: 0, // Will be set by caller
-                output: Some(format!("{:?}", output)),
-            })
-        } else {
-            Ok(TestResult::Fail {
-                duration_ms: 0,
-                error: "Assertion failed".to_string(),
-                output: Some(format!("{:?}", output)),
-            })
-        }
-    }
-    
-    fn verify_dependencies(&self, _test: &TestCase, cached: &CachedTestResult) -> Result<bool> {
-        // Check if any dependencies have changed
-        for (dep_name, dep_hash) in &cached.dependencies {
-            let current_hash = self.get_current_dependency_hash(dep_name)?;
-            if current_hash != *dep_hash {
-                return Ok(false);
-            }
+        if test.attributes.skip {
+            let reason = test.attributes.skip_reason.clone().unwrap_or_else(|| "marked skip".to_string());
+            return Ok(TestResult::Skipped { reason });
         }
-        
-        Ok(true)
-    }
-    
-    fn collect_dependencies(&self, _test: &TestCase) -> Result<HashMap<String, ContentHash>> {
-        let deps = HashMap::new();
-        
-        // For now, we'll skip dependency collection
-        // In a real implementation, we'd analyze the annotated AST
-        
-        Ok(deps)
-    }
-    
-    fn collect_expr_dependencies(
-        &self,
-        expr: &Expr,
-        deps: &mut HashMap<String, ContentHash>,
-    ) -> Result<()> {
-        match expr {
-            Expr::Var(name, _) => {
-                // In a real implementation, we'd look up the function hash
-                // For now, we'll create a dummy hash
-                let hash = ContentHash::new(name.as_str().as_bytes());
-                deps.insert(name.as_str().to_string(), hash);
-            }
-            Expr::App(func, args, _) => {
-                self.collect_expr_dependencies(func, deps)?;
-                for arg in args {
-                    self.collect_expr_dependencies(arg, deps)?;
-                }
-            }
-            Expr::Lambda { body, .. } => {
-                self.collect_expr_dependencies(body, deps)?;
-            }
-            Expr::Let { value, body, .. } => {
-                self.collect_expr_dependencies(value, deps)?;
-                self.collect_expr_dependencies(body, deps)?;
-            }
-            Expr::If { condition, then_branch, else_branch, .. } => {
-                self.collect_expr_dependencies(condition, deps)?;
-                self.collect_expr_dependencies(then_branch, deps)?;
-                self.collect_expr_dependencies(else_branch, deps)?;
-            }
-            Expr::Match { scrutinee, arms, .. } => {
-                self.collect_expr_dependencies(scrutinee, deps)?;
-                for arm in arms {
-                    self.collect_expr_dependencies(&arm.body, deps)?;
-                }
-            }
-            _ => {}
+        let module = self.modules.get_mut(&test.namespace)
+            .ok_or_else(|| anyhow!("the module of test `{}` is not loaded", test.full_path))?;
+        let name = module.unit.module.name.to_string();
+        let start = Instant::now();
+        let outcome = match test_definition(&module.unit, test.name) {
+            Some(def) => module.interpreter.eval_in(&name, &def.body),
+            None => module.interpreter.evaluate(&name, test.name.as_str()),
+        };
+        let result = test_result(outcome, start);
+        if !test.attributes.should_fail {
+            return Ok(result);
         }
-        
-        Ok(())
-    }
-    
-    fn get_current_dependency_hash(&self, name: &str) -> Result<ContentHash> {
-        // In a real implementation, we'd look up the actual hash
-        // For now, return a dummy hash
-        Ok(ContentHash::new(name.as_bytes()))
+        let duration_ms = start.elapsed().as_millis() as u64;
+        Ok(match result {
+            TestResult::Fail { .. } => TestResult::Pass { duration_ms, output: None },
+            _ => TestResult::Fail { duration_ms, error: "passed but is expected to fail".to_string(), output: None },
+        })
     }
     
-    fn create_test_environment(&self, test: &TestCase) -> Result<TestEnvironment> {
-        Ok(TestEnvironment {
-            test_name: test.name.clone(),
-            namespace: test.namespace.clone(),
-            timeout: self.config.timeout_seconds,
-        })
+    fn verify_dependencies(&self, test: &TestCase, cached: &CachedTestResult) -> Result<bool> {
+        // Any dependency added, removed or changed since the cached run invalidates it
+        Ok(cached.dependencies == test.dependencies)
     }
     
-    fn check_test_assertion(&self, output: &TestOutput) -> Result<bool> {
-        match output {
-            TestOutput::Bool(b) => Ok(*b),
-            TestOutput::Unit => Ok(true),
-            TestOutput::Exception(_) => Ok(false),
-            _ => Err(anyhow!("Invalid test output type")),
-        }
+    fn collect_dependencies(&self, test: &TestCase) -> Result<HashMap<String, ContentHash>> {
+        Ok(test.dependencies.clone())
     }
 }
 
-/// Test environment
-#[derive(Debug)]
-struct TestEnvironment {
-    test_name: Symbol,
-    namespace: NamespacePath,
-    timeout: u64,
-}
-
-/// Test output
-#[derive(Debug)]
-enum TestOutput {
-    Bool(bool),
-    Unit,
-    Exception(String),
-    Value(String),
-}
-
 // Placeholder for missing dependencies
 mod num_cpus {
     pub fn get() -> usize {
@@ -364,29 +290,22 @@ mod num_cpus {
     }
 }
 
-impl TestRunner {
-    fn simulate_test_execution(&self, test: &TestCase) -> Result<CompiledTest> {
-        Ok(CompiledTest { test_name: test.name.clone() })
-    }
-    
-    fn simulate_test_result(&self, test: &TestCase) -> TestOutput {
-        // Simulate test results based on test name
-        if test.name.as_str().contains("fail") {
-            TestOutput::Bool(false)
-        } else if test.name.as_str().contains("error") {
-            TestOutput::Exception("Simulated error".to_string())
-        } else {
-            TestOutput::Bool(true)
-        }
-    }
+/// The `test` definition named `name`, which the interpreter does not load
+/// as a value
+fn test_definition(unit: &CompilationUnit, name: Symbol) -> Option<&TestDef> {
+    unit.module.items.iter().find_map(|item| match item {
+        Item::TestDef(def) if def.name == name => Some(def),
+        _ => None,
+    })
 }
 
-struct CompiledTest {
-    test_name: Symbol,
-}
-
-impl CompiledTest {
-    fn execute(&self, _env: &TestEnvironment) -> TestOutput {
-        TestOutput::Bool(true)
+/// The result of a test that evaluated to `outcome`: it passes when it is
+/// `true` or `()`
+pub(crate) fn test_result(outcome: std::result::Result<Value, RuntimeError>, start: Instant) -> TestResult {
+    let duration_ms = start.elapsed().as_millis() as u64;
+    match outcome {
+        Ok(Value::Bool(true) | Value::Unit) => TestResult::Pass { duration_ms, output: None },
+        Ok(value) => TestResult::Fail { duration_ms, error: format!("evaluated to {value}"), output: None },
+        Err(error) => TestResult::Fail { duration_ms, error: format!("runtime error: {error}"), output: None },
     }
-}
\ No newline at end of file
+}